@@ -86,9 +86,16 @@ if [[ $RUSTC_MINOR_VERSION -gt 67 && "$HOST_PLATFORM" != *windows* ]]; then
 	cargo test --verbose --color always --features esplora-async
 	cargo build --verbose --color always --features esplora-async-https
 	cargo test --verbose --color always --features esplora-async-https
+	cargo build --verbose --color always --features electrum
+	cargo test --verbose --color always --features electrum
 	popd
 fi
 
+echo -e "\n\nBuilding and testing the VSS client"
+pushd lightning-persister
+cargo test --verbose --color always --features vss
+popd
+
 echo -e "\n\nTest futures builds"
 pushd lightning-background-processor
 cargo test --verbose --color always --features futures
@@ -106,4 +113,6 @@ pushd lightning
 RUSTFLAGS="$RUSTFLAGS --cfg=anchors" cargo test --verbose --color always -p lightning
 echo -e "\n\nTest Taproot builds"
 RUSTFLAGS="$RUSTFLAGS --cfg=anchors --cfg=taproot" cargo test --verbose --color always -p lightning
+echo -e "\n\nTest experimental PTLC builds"
+RUSTFLAGS="$RUSTFLAGS --cfg=ptlc" cargo test --verbose --color always -p lightning
 popd
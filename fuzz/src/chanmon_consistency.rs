@@ -45,7 +45,7 @@ use lightning::ln::msgs::{self, CommitmentUpdate, ChannelMessageHandler, DecodeE
 use lightning::ln::script::ShutdownScript;
 use lightning::ln::functional_test_utils::*;
 use lightning::util::enforcing_trait_impls::{EnforcingSigner, EnforcementState};
-use lightning::util::errors::APIError;
+use lightning::util::errors::{APIError, SendHtlcError};
 use lightning::util::logger::Logger;
 use lightning::util::config::UserConfig;
 use lightning::util::ser::{Readable, ReadableArgs, Writeable, Writer};
@@ -77,8 +77,9 @@ impl FeeEstimator for FuzzEstimator {
 		// always return a HighPriority feerate here which is >= the maximum Normal feerate and a
 		// Background feerate which is <= the minimum Normal feerate.
 		match conf_target {
-			ConfirmationTarget::HighPriority => MAX_FEE,
-			ConfirmationTarget::Background => 253,
+			ConfirmationTarget::OnChainSweep | ConfirmationTarget::AnchorChannelFee |
+				ConfirmationTarget::HighPriority => MAX_FEE,
+			ConfirmationTarget::ChannelCloseMinimum | ConfirmationTarget::Background => 253,
 			ConfirmationTarget::Normal => cmp::min(self.ret_val.load(atomic::Ordering::Acquire), MAX_FEE),
 		}
 	}
@@ -216,6 +217,10 @@ impl NodeSigner for KeyProvider {
 		let secp_ctx = Secp256k1::signing_only();
 		Ok(secp_ctx.sign_ecdsa(&msg_hash, &self.node_secret))
 	}
+
+	fn sign_message(&self, _msg: &[u8]) -> Result<String, ()> {
+		unreachable!()
+	}
 }
 
 impl SignerProvider for KeyProvider {
@@ -290,14 +295,15 @@ fn check_api_err(api_err: APIError, sendable_bounds_violated: bool) {
 		APIError::APIMisuseError { .. } => panic!("We can't misuse the API"),
 		APIError::FeeRateTooHigh { .. } => panic!("We can't send too much fee?"),
 		APIError::InvalidRoute { .. } => panic!("Our routes should work"),
-		APIError::ChannelUnavailable { err } => {
+		APIError::ChannelUnavailable { err } => panic!("{}", err),
+		APIError::ChannelCannotSendHtlc { err } => {
 			// Test the error against a list of errors we can hit, and reject
 			// all others. If you hit this panic, the list of acceptable errors
-			// is probably just stale and you should add new messages here.
-			match err.as_str() {
-				"Peer for first hop currently disconnected" => {},
-				_ if err.starts_with("Cannot send less than our next-HTLC minimum - ") => {},
-				_ if err.starts_with("Cannot send more than our next-HTLC maximum - ") => {},
+			// is probably just stale and you should add new variants here.
+			match err {
+				SendHtlcError::PeerDisconnected { .. } => {},
+				SendHtlcError::AmountBelowHtlcMinimum { .. } => {},
+				SendHtlcError::AmountAboveHtlcLimit { .. } => {},
 				_ => panic!("{}", err),
 			}
 			assert!(sendable_bounds_violated);
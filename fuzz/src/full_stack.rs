@@ -321,6 +321,10 @@ impl NodeSigner for KeyProvider {
 		let secp_ctx = Secp256k1::signing_only();
 		Ok(secp_ctx.sign_ecdsa(&msg_hash, &self.node_secret))
 	}
+
+	fn sign_message(&self, _msg: &[u8]) -> Result<String, ()> {
+		unreachable!()
+	}
 }
 
 impl SignerProvider for KeyProvider {
@@ -156,6 +156,10 @@ impl NodeSigner for KeyProvider {
 	fn sign_gossip_message(&self, _msg: lightning::ln::msgs::UnsignedGossipMessage) -> Result<bitcoin::secp256k1::ecdsa::Signature, ()> {
 		unreachable!()
 	}
+
+	fn sign_message(&self, _msg: &[u8]) -> Result<String, ()> {
+		unreachable!()
+	}
 }
 
 impl SignerProvider for KeyProvider {
@@ -248,6 +248,7 @@ pub fn do_test<Out: test_logger::Output>(data: &[u8], out: Out) {
 									forwarding_info: None,
 									outbound_htlc_minimum_msat: None,
 									outbound_htlc_maximum_msat: None,
+									latest_channel_update: None,
 								},
 								funding_txo: Some(OutPoint { txid: bitcoin::Txid::from_slice(&[0; 32]).unwrap(), index: 0 }),
 								channel_type: None,
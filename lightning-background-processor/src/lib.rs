@@ -117,6 +117,10 @@ const FIRST_NETWORK_PRUNE_TIMER: u64 = 60;
 #[cfg(test)]
 const FIRST_NETWORK_PRUNE_TIMER: u64 = 1;
 
+/// Persist the [`ChannelManager`] in full hourly, persisting only the changes since the last full
+/// persistence on any other update.
+const MANAGER_SNAPSHOT_TIMER: u64 = 60 * 60;
+
 #[cfg(not(test))]
 const REBROADCAST_TIMER: u64 = 30;
 #[cfg(test)]
@@ -243,13 +247,27 @@ fn update_scorer<'a, S: 'static + Deref<Target = SC> + Send + Sync, SC: 'a + Wri
 ) -> bool {
 	let mut score = scorer.lock();
 	match event {
-		Event::PaymentPathFailed { ref path, short_channel_id: Some(scid), .. } => {
+		Event::PaymentPathFailed { ref path, short_channel_id: Some(scid), ref hold_times, .. } => {
 			score.payment_path_failed(path, *scid);
+			if !hold_times.is_empty() {
+				score.payment_path_hold_times(path, hold_times);
+			}
 		},
-		Event::PaymentPathFailed { ref path, payment_failed_permanently: true, .. } => {
+		Event::PaymentPathFailed { ref path, payment_failed_permanently: true, ref hold_times, .. } => {
 			// Reached if the destination explicitly failed it back. We treat this as a successful probe
 			// because the payment made it all the way to the destination with sufficient liquidity.
 			score.probe_successful(path);
+			if !hold_times.is_empty() {
+				score.payment_path_hold_times(path, hold_times);
+			}
+		},
+		Event::PaymentPathFailed { ref path, ref hold_times, .. } => {
+			// Hold times alone don't warrant persisting the scorer right away, as scorers may
+			// ignore them. They are picked up by the next periodic persistence otherwise.
+			if !hold_times.is_empty() {
+				score.payment_path_hold_times(path, hold_times);
+			}
+			return false;
 		},
 		Event::PaymentPathSuccessful { path, .. } => {
 			score.payment_path_successful(path);
@@ -282,7 +300,9 @@ macro_rules! define_run_body {
 		let mut last_prune_call = $get_timer(FIRST_NETWORK_PRUNE_TIMER);
 		let mut last_scorer_persist_call = $get_timer(SCORER_PERSIST_TIMER);
 		let mut last_rebroadcast_call = $get_timer(REBROADCAST_TIMER);
+		let mut last_manager_snapshot_call = $get_timer(MANAGER_SNAPSHOT_TIMER);
 		let mut have_pruned = false;
+		let mut last_best_block_height = None;
 
 		loop {
 			$process_channel_manager_events;
@@ -321,8 +341,14 @@ macro_rules! define_run_body {
 			}
 
 			if updates_available {
-				log_trace!($logger, "Persisting ChannelManager...");
-				$persister.persist_manager(&*$channel_manager)?;
+				if $timer_elapsed(&mut last_manager_snapshot_call, MANAGER_SNAPSHOT_TIMER) {
+					log_trace!($logger, "Persisting ChannelManager...");
+					$persister.persist_manager(&*$channel_manager)?;
+					last_manager_snapshot_call = $get_timer(MANAGER_SNAPSHOT_TIMER);
+				} else {
+					log_trace!($logger, "Persisting ChannelManager delta...");
+					$persister.persist_manager_delta(&*$channel_manager)?;
+				}
 				log_trace!($logger, "Done persisting ChannelManager.");
 			}
 			if $timer_elapsed(&mut last_freshness_call, FRESHNESS_TIMER) {
@@ -330,6 +356,18 @@ macro_rules! define_run_body {
 				$channel_manager.timer_tick_occurred();
 				last_freshness_call = $get_timer(FRESHNESS_TIMER);
 			}
+			// Keep the network graph and scorer, neither of which are informed of blocks directly,
+			// up to date with the chain tip, e.g., to age channels and decay what was learned.
+			let best_block_height = $channel_manager.current_best_block().height();
+			if last_best_block_height != Some(best_block_height) {
+				if let Some(network_graph) = $gossip_sync.network_graph() {
+					network_graph.best_block_updated(best_block_height);
+				}
+				if let Some(ref scorer) = $scorer {
+					scorer.lock().best_block_updated(best_block_height);
+				}
+				last_best_block_height = Some(best_block_height);
+			}
 			if await_slow {
 				// On various platforms, we may be starved of CPU cycles for several reasons.
 				// E.g. on iOS, if we've been in the background, we will be entirely paused.
@@ -685,7 +723,12 @@ impl BackgroundProcessor {
 	/// [`Persister::persist_manager`] is responsible for writing out the [`ChannelManager`] to disk, and/or
 	/// uploading to one or more backup services. See [`ChannelManager::write`] for writing out a
 	/// [`ChannelManager`]. See the `lightning-persister` crate for LDK's
-	/// provided implementation.
+	/// provided implementation. The [`ChannelManager`] is written out via
+	/// [`Persister::persist_manager`] hourly and via [`Persister::persist_manager_delta`] on any
+	/// other update. The latter persists it in full by default, only writing out the changes since
+	/// the last full persistence if the [`Persister`] opts in, e.g., via
+	/// [`KVStorePersister::persists_manager_deltas`], in which case it must be read back via
+	/// [`read_channel_manager`].
 	///
 	/// [`Persister::persist_graph`] is responsible for writing out the [`NetworkGraph`] to disk, if
 	/// [`GossipSync`] is supplied. See [`NetworkGraph::write`] for writing out a [`NetworkGraph`].
@@ -714,6 +757,9 @@ impl BackgroundProcessor {
 	/// [`ChannelManager`]: lightning::ln::channelmanager::ChannelManager
 	/// [`ChannelManager::write`]: lightning::ln::channelmanager::ChannelManager#impl-Writeable
 	/// [`Persister::persist_manager`]: lightning::util::persist::Persister::persist_manager
+	/// [`Persister::persist_manager_delta`]: lightning::util::persist::Persister::persist_manager_delta
+	/// [`KVStorePersister::persists_manager_deltas`]: lightning::util::persist::KVStorePersister::persists_manager_deltas
+	/// [`read_channel_manager`]: lightning::util::persist::read_channel_manager
 	/// [`Persister::persist_graph`]: lightning::util::persist::Persister::persist_graph
 	/// [`NetworkGraph`]: lightning::routing::gossip::NetworkGraph
 	/// [`NetworkGraph::write`]: lightning::routing::gossip::NetworkGraph#impl-Writeable
@@ -1650,6 +1696,10 @@ mod tests {
 				failure: PathFailure::OnPath { network_update: None },
 				path: path.clone(),
 				short_channel_id: Some(scored_scid),
+				hold_times: Vec::new(),
+				failure_reason: None,
+				failing_node_id: None,
+				attempts: None,
 			});
 			let event = $receive.expect("PaymentPathFailed not handled within deadline");
 			match event {
@@ -1667,6 +1717,10 @@ mod tests {
 				failure: PathFailure::OnPath { network_update: None },
 				path: path.clone(),
 				short_channel_id: None,
+				hold_times: Vec::new(),
+				failure_reason: None,
+				failing_node_id: None,
+				attempts: None,
 			});
 			let event = $receive.expect("PaymentPathFailed not handled within deadline");
 			match event {
@@ -0,0 +1,289 @@
+//! Utilities for syncing the chain using BIP 157/158 compact block filters.
+//!
+//! Rather than downloading every block, a [`CompactFilterBlockSource`] first fetches the compact
+//! filter for a block and matches it against the scripts registered via [`chain::Filter`]. Only
+//! blocks which may contain relevant transactions are downloaded in full, with all other blocks
+//! returned as [`BlockData::HeaderOnly`]. As filters are computed over all scripts in a block,
+//! this does not reveal to the filter source which transactions we are interested in.
+//!
+//! Each filter is checked against its BIP 157 filter header before being used, which commits to
+//! the filters of all previous blocks as well. Thus, a filter source serving filters which omit
+//! our scripts is detected as long as the filter headers are trusted, e.g. by cross-checking them
+//! against several independent sources via [`CompactFilterBlockSource::with_filter_header_source`].
+//!
+//! Filters may be fetched from any [`BlockFilterSource`], such as a Bitcoin Core node via its REST
+//! or RPC interface, a btcd node via `rpc::BtcdFilterClient`, or a BIP 157 peer, independently of
+//! the [`BlockSource`] blocks are fetched from.
+//!
+//! Note that a compact filter may produce false positives, in which case a full block will be
+//! downloaded needlessly, but never false negatives.
+
+use crate::{AsyncBlockSourceResult, BlockData, BlockSource, BlockSourceError, BlockSourceResult, BlockHeaderData};
+
+use bitcoin::blockdata::script::Script;
+use bitcoin::hash_types::{BlockHash, FilterHeader, Txid};
+use bitcoin::hashes::Hash;
+use bitcoin::util::bip158::BlockFilter;
+
+use lightning::chain;
+use lightning::chain::WatchedOutput;
+
+use std::collections::{HashSet, VecDeque};
+use std::ops::Deref;
+use std::sync::Mutex;
+
+/// The number of verified filter headers we keep, such that the filter of the next block can be
+/// verified without refetching the filter header of its predecessor, even across short reorgs.
+const VERIFIED_FILTER_HEADERS_CACHE_SIZE: usize = 6;
+
+/// A source of BIP 158 basic block filters and their BIP 157 filter headers.
+pub trait BlockFilterSource : Sync + Send {
+	/// Returns the BIP 158 basic block filter for the block with the given hash.
+	fn get_block_filter<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, BlockFilter>;
+
+	/// Returns the BIP 157 filter header of the basic block filter for the block with the given
+	/// hash.
+	fn get_filter_header<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, FilterHeader>;
+}
+
+/// A [`BlockSource`] which only downloads blocks whose compact filter matches the scripts we've
+/// been asked to watch.
+///
+/// Blocks and headers are fetched from the given [`BlockSource`], and filters from the given
+/// [`BlockFilterSource`]. Filters are only used once they match their filter header, which all
+/// filter header sources must agree on.
+///
+/// Should be provided as the [`chain::Filter`] to any interested parties, e.g. as the chain source
+/// of a [`ChainMonitor`], so that it learns of all outputs which must be watched. Blocks which do
+/// not match any registered script are returned as [`BlockData::HeaderOnly`], which
+/// [`chain::Listen`] implementations receive as a block with no transaction data via
+/// [`chain::Listen::filtered_block_connected`].
+///
+/// [`ChainMonitor`]: lightning::chain::chainmonitor::ChainMonitor
+pub struct CompactFilterBlockSource<B: BlockSource, F: Deref> where F::Target: BlockFilterSource {
+	block_source: B,
+	filter_source: F,
+	filter_header_sources: Vec<F>,
+	verified_filter_headers: Mutex<VecDeque<(BlockHash, FilterHeader)>>,
+	watched_scripts: Mutex<HashSet<Script>>,
+}
+
+impl<B: BlockSource, F: Deref> CompactFilterBlockSource<B, F> where F::Target: BlockFilterSource {
+	/// Creates a new filtering block source which fetches headers and blocks from `block_source`,
+	/// and filters and their filter headers from `filter_source`.
+	pub fn new(block_source: B, filter_source: F) -> Self {
+		Self {
+			block_source,
+			filter_source,
+			filter_header_sources: Vec::new(),
+			verified_filter_headers: Mutex::new(VecDeque::new()),
+			watched_scripts: Mutex::new(HashSet::new()),
+		}
+	}
+
+	/// Cross-checks the filter headers of our filter source against the given source, failing to
+	/// fetch any block for which they disagree.
+	///
+	/// May be called several times to require the agreement of several independent sources.
+	pub fn with_filter_header_source(mut self, filter_header_source: F) -> Self {
+		self.filter_header_sources.push(filter_header_source);
+		self
+	}
+
+	/// Returns the underlying block source.
+	pub fn source(&self) -> &B {
+		&self.block_source
+	}
+
+	/// Returns the filter header for the block with the given hash, once all our filter header
+	/// sources agree on it.
+	async fn get_filter_header(&self, header_hash: &BlockHash) -> BlockSourceResult<FilterHeader> {
+		if *header_hash == BlockHash::all_zeros() {
+			// The genesis block's filter header commits to an all-zero previous filter header.
+			return Ok(FilterHeader::all_zeros());
+		}
+		let cached_filter_header = self.verified_filter_headers.lock().unwrap().iter()
+			.find(|(block_hash, _)| block_hash == header_hash)
+			.map(|(_, filter_header)| *filter_header);
+		if let Some(filter_header) = cached_filter_header {
+			return Ok(filter_header);
+		}
+
+		let filter_header = self.filter_source.get_filter_header(header_hash).await?;
+		for filter_header_source in self.filter_header_sources.iter() {
+			if filter_header_source.get_filter_header(header_hash).await? != filter_header {
+				return Err(BlockSourceError::persistent("filter header sources disagree"));
+			}
+		}
+		Ok(filter_header)
+	}
+
+	/// Fetches the filter for the block with the given header, checking it against its filter
+	/// header.
+	async fn get_verified_filter(&self, header_hash: &BlockHash, header_data: &BlockHeaderData) -> BlockSourceResult<BlockFilter> {
+		let filter = self.filter_source.get_block_filter(header_hash).await?;
+		let previous_filter_header = self.get_filter_header(&header_data.header.prev_blockhash).await?;
+		let filter_header = self.get_filter_header(header_hash).await?;
+		if filter.filter_header(&previous_filter_header) != filter_header {
+			return Err(BlockSourceError::persistent("block filter does not match its filter header"));
+		}
+
+		let mut verified_filter_headers = self.verified_filter_headers.lock().unwrap();
+		if !verified_filter_headers.iter().any(|(block_hash, _)| block_hash == header_hash) {
+			if verified_filter_headers.len() >= VERIFIED_FILTER_HEADERS_CACHE_SIZE {
+				verified_filter_headers.pop_front();
+			}
+			verified_filter_headers.push_back((*header_hash, filter_header));
+		}
+		Ok(filter)
+	}
+
+	/// Returns whether the given filter for the block with the given hash matches any watched
+	/// script.
+	fn filter_matches(&self, block_hash: &BlockHash, filter: &BlockFilter) -> BlockSourceResult<bool> {
+		let watched_scripts = self.watched_scripts.lock().unwrap();
+		if watched_scripts.is_empty() {
+			return Ok(false);
+		}
+		filter.match_any(block_hash, &mut watched_scripts.iter().map(|script| script.as_bytes()))
+			.map_err(|e| BlockSourceError::persistent(e))
+	}
+}
+
+impl<B: BlockSource, F: Deref + Sync + Send> BlockSource for CompactFilterBlockSource<B, F> where F::Target: BlockFilterSource {
+	fn get_header<'a>(&'a self, header_hash: &'a BlockHash, height_hint: Option<u32>) -> AsyncBlockSourceResult<'a, BlockHeaderData> {
+		self.block_source.get_header(header_hash, height_hint)
+	}
+
+	fn get_block<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, BlockData> {
+		Box::pin(async move {
+			let header_data = self.block_source.get_header(header_hash, None).await?;
+			let filter = self.get_verified_filter(header_hash, &header_data).await?;
+			if self.filter_matches(header_hash, &filter)? {
+				self.block_source.get_block(header_hash).await
+			} else {
+				Ok(BlockData::HeaderOnly(header_data.header))
+			}
+		})
+	}
+
+	fn get_best_block<'a>(&'a self) -> AsyncBlockSourceResult<(BlockHash, Option<u32>)> {
+		self.block_source.get_best_block()
+	}
+}
+
+impl<B: BlockSource, F: Deref> chain::Filter for CompactFilterBlockSource<B, F> where F::Target: BlockFilterSource {
+	fn register_tx(&self, _txid: &Txid, script_pubkey: &Script) {
+		self.watched_scripts.lock().unwrap().insert(script_pubkey.clone());
+	}
+
+	fn register_output(&self, output: WatchedOutput) {
+		// BIP 158 basic filters include the previous output script of every input, thus watching
+		// the output script also matches any block containing a spend of the output.
+		self.watched_scripts.lock().unwrap().insert(output.script_pubkey);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_utils::Blockchain;
+
+	use bitcoin::blockdata::transaction::TxOut;
+
+	use lightning::chain::Filter;
+
+	use std::sync::Arc;
+
+	/// Serves empty filters along with the correct filter headers of the wrapped chain.
+	struct EmptyFilterSource(Blockchain);
+
+	impl BlockFilterSource for EmptyFilterSource {
+		fn get_block_filter<'a>(&'a self, _header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, BlockFilter> {
+			Box::pin(async move { Ok(BlockFilter::new(&[0])) })
+		}
+
+		fn get_filter_header<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, FilterHeader> {
+			self.0.get_filter_header(header_hash)
+		}
+	}
+
+	/// Serves all-zero filter headers, disagreeing with any real filter header source.
+	struct ZeroFilterHeaderSource;
+
+	impl BlockFilterSource for ZeroFilterHeaderSource {
+		fn get_block_filter<'a>(&'a self, _header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, BlockFilter> {
+			Box::pin(async move { Ok(BlockFilter::new(&[0])) })
+		}
+
+		fn get_filter_header<'a>(&'a self, _header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, FilterHeader> {
+			Box::pin(async move { Ok(FilterHeader::all_zeros()) })
+		}
+	}
+
+	fn chain_with_script(script: &Script) -> Blockchain {
+		let mut chain = Blockchain::default().with_height(2);
+		chain.blocks[1].txdata[0].output.push(TxOut { value: 1000, script_pubkey: script.clone() });
+		chain
+	}
+
+	#[tokio::test]
+	async fn only_fetches_matching_blocks() {
+		let script = Script::from(vec![0x51]);
+		let chain = chain_with_script(&script);
+		let txid = chain.blocks[1].txdata[0].txid();
+		let matching_hash = chain.blocks[1].block_hash();
+		let other_hash = chain.blocks[2].block_hash();
+
+		let source = CompactFilterBlockSource::new(chain_with_script(&script), Arc::new(chain));
+		match source.get_block(&matching_hash).await {
+			Ok(BlockData::HeaderOnly(header)) => assert_eq!(header.block_hash(), matching_hash),
+			_ => panic!("Expected header-only block with no registered scripts"),
+		}
+
+		source.register_tx(&txid, &script);
+		match source.get_block(&matching_hash).await {
+			Ok(BlockData::FullBlock(block)) => assert_eq!(block.block_hash(), matching_hash),
+			_ => panic!("Expected full block"),
+		}
+		match source.get_block(&other_hash).await {
+			Ok(BlockData::HeaderOnly(header)) => assert_eq!(header.block_hash(), other_hash),
+			_ => panic!("Expected header-only block"),
+		}
+	}
+
+	#[tokio::test]
+	async fn rejects_filters_not_matching_filter_headers() {
+		let script = Script::from(vec![0x51]);
+		let chain = chain_with_script(&script);
+		let txid = chain.blocks[1].txdata[0].txid();
+		let matching_hash = chain.blocks[1].block_hash();
+
+		// A filter source omitting our script from its filters is detected via its filter headers.
+		let source = CompactFilterBlockSource::new(
+			chain_with_script(&script), Arc::new(EmptyFilterSource(chain_with_script(&script))));
+		source.register_tx(&txid, &script);
+		match source.get_block(&matching_hash).await {
+			Err(e) => assert_eq!(e.kind(), crate::BlockSourceErrorKind::Persistent),
+			Ok(_) => panic!("Expected filter verification to fail"),
+		}
+
+		// As are filter headers which don't match those of an independent source.
+		let filter_source: Arc<dyn BlockFilterSource> = Arc::new(chain_with_script(&script));
+		let source = CompactFilterBlockSource::new(chain_with_script(&script), filter_source)
+			.with_filter_header_source(Arc::new(ZeroFilterHeaderSource) as Arc<dyn BlockFilterSource>);
+		match source.get_block(&matching_hash).await {
+			Err(e) => assert_eq!(e.kind(), crate::BlockSourceErrorKind::Persistent),
+			Ok(_) => panic!("Expected filter header sources to disagree"),
+		}
+
+		// While agreeing filter header sources are accepted.
+		let source = CompactFilterBlockSource::new(chain_with_script(&script), Arc::new(chain))
+			.with_filter_header_source(Arc::new(chain_with_script(&script)));
+		source.register_tx(&txid, &script);
+		match source.get_block(&matching_hash).await {
+			Ok(BlockData::FullBlock(block)) => assert_eq!(block.block_hash(), matching_hash),
+			_ => panic!("Expected full block"),
+		}
+	}
+}
@@ -4,8 +4,9 @@ use crate::{BlockHeaderData, BlockSourceError};
 
 use bitcoin::blockdata::block::{Block, BlockHeader};
 use bitcoin::consensus::encode;
-use bitcoin::hash_types::{BlockHash, TxMerkleNode, Txid};
+use bitcoin::hash_types::{BlockHash, FilterHeader, TxMerkleNode, Txid};
 use bitcoin::hashes::hex::FromHex;
+use bitcoin::util::bip158::BlockFilter;
 use bitcoin::Transaction;
 
 use serde_json;
@@ -138,6 +139,56 @@ impl TryInto<(BlockHash, Option<u32>)> for JsonResponse {
 	}
 }
 
+/// Converts a JSON value into a BIP 158 block filter. Assumes the filter is hex-encoded either in
+/// the `filter` field of a JSON object, as returned by both Bitcoin Core's `getblockfilter` RPC and
+/// its `blockfilter` REST endpoint, or as a JSON string, as returned by btcd's `getcfilter` RPC.
+impl TryInto<BlockFilter> for JsonResponse {
+	type Error = std::io::Error;
+
+	fn try_into(self) -> std::io::Result<BlockFilter> {
+		let hex_data = match &self.0 {
+			serde_json::Value::Object(_) => match &self.0["filter"] {
+				serde_json::Value::String(hex_data) => hex_data,
+				_ => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected JSON string")),
+			},
+			serde_json::Value::String(hex_data) => hex_data,
+			_ => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected JSON object or string")),
+		};
+
+		match Vec::<u8>::from_hex(hex_data) {
+			Err(_) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid hex data")),
+			Ok(filter_data) => Ok(BlockFilter::new(&filter_data)),
+		}
+	}
+}
+
+/// Converts a JSON value into a BIP 157 filter header. Assumes the filter header is hex-encoded
+/// either in the `header` field of a JSON object, as returned by Bitcoin Core's `getblockfilter`
+/// RPC, as the first element of a JSON array, as returned by its `blockfilterheaders` REST
+/// endpoint, or as a JSON string, as returned by btcd's `getcfilterheader` RPC.
+impl TryInto<FilterHeader> for JsonResponse {
+	type Error = std::io::Error;
+
+	fn try_into(self) -> std::io::Result<FilterHeader> {
+		let hex_data = match &self.0 {
+			serde_json::Value::Object(_) => &self.0["header"],
+			serde_json::Value::Array(headers) => match headers.first() {
+				Some(hex_data) => hex_data,
+				None => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "empty JSON array")),
+			},
+			hex_data => hex_data,
+		};
+
+		match hex_data {
+			serde_json::Value::String(hex_data) => match FilterHeader::from_hex(hex_data) {
+				Err(_) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid hex data")),
+				Ok(filter_header) => Ok(filter_header),
+			},
+			_ => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected JSON string")),
+		}
+	}
+}
+
 impl TryInto<Txid> for JsonResponse {
 	type Error = std::io::Error;
 	fn try_into(self) -> std::io::Result<Txid> {
@@ -543,6 +594,121 @@ pub(crate) mod tests {
 		}
 	}
 
+	#[test]
+	fn into_block_filter_from_json_response_with_unexpected_type() {
+		let response = JsonResponse(serde_json::json!(42));
+		match TryInto::<BlockFilter>::try_into(response) {
+			Err(e) => {
+				assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+				assert_eq!(e.get_ref().unwrap().to_string(), "expected JSON object or string");
+			},
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+
+	#[test]
+	fn into_block_filter_from_json_response_with_invalid_hex_data() {
+		let response = JsonResponse(serde_json::json!({ "filter": "foobar" }));
+		match TryInto::<BlockFilter>::try_into(response) {
+			Err(e) => {
+				assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+				assert_eq!(e.get_ref().unwrap().to_string(), "invalid hex data");
+			},
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+
+	#[test]
+	fn into_block_filter_from_json_response_with_valid_filter() {
+		let response = JsonResponse(serde_json::json!({ "filter": "019dfca8", "header": "00" }));
+		match TryInto::<BlockFilter>::try_into(response) {
+			Err(e) => panic!("Unexpected error: {:?}", e),
+			Ok(filter) => assert_eq!(filter.content, vec![0x01, 0x9d, 0xfc, 0xa8]),
+		}
+	}
+
+	#[test]
+	fn into_block_filter_from_json_response_with_valid_filter_string() {
+		let response = JsonResponse(serde_json::json!("019dfca8"));
+		match TryInto::<BlockFilter>::try_into(response) {
+			Err(e) => panic!("Unexpected error: {:?}", e),
+			Ok(filter) => assert_eq!(filter.content, vec![0x01, 0x9d, 0xfc, 0xa8]),
+		}
+	}
+
+	#[test]
+	fn into_filter_header_from_json_response_with_unexpected_type() {
+		let response = JsonResponse(serde_json::json!({ "header": 42 }));
+		match TryInto::<FilterHeader>::try_into(response) {
+			Err(e) => {
+				assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+				assert_eq!(e.get_ref().unwrap().to_string(), "expected JSON string");
+			},
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+
+	#[test]
+	fn into_filter_header_from_json_response_with_empty_array() {
+		let response = JsonResponse(serde_json::json!([]));
+		match TryInto::<FilterHeader>::try_into(response) {
+			Err(e) => {
+				assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+				assert_eq!(e.get_ref().unwrap().to_string(), "empty JSON array");
+			},
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+
+	#[test]
+	fn into_filter_header_from_json_response_with_valid_header() {
+		let header = "9f3c30f0c37fb977cf3e1a3173c631e8ff119ad3088b6f5b2bced0802139c202";
+		let expected = FilterHeader::from_hex(header).unwrap();
+		for response in vec![
+			serde_json::json!({ "filter": "00", "header": header }),
+			serde_json::json!([header]),
+			serde_json::json!(header),
+		] {
+			match TryInto::<FilterHeader>::try_into(JsonResponse(response)) {
+				Err(e) => panic!("Unexpected error: {:?}", e),
+				Ok(filter_header) => assert_eq!(filter_header, expected),
+			}
+		}
+	}
+
+	#[cfg(feature = "rpc-client")]
+	#[test]
+	fn into_fee_rate_estimate_from_json_response_with_unexpected_feerate_type() {
+		let response = JsonResponse(serde_json::json!({ "feerate": "foo", "blocks": 6 }));
+		match TryInto::<FeeRateEstimate>::try_into(response) {
+			Err(e) => {
+				assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+				assert_eq!(e.get_ref().unwrap().to_string(), "expected JSON number");
+			},
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+
+	#[cfg(feature = "rpc-client")]
+	#[test]
+	fn into_fee_rate_estimate_from_json_response_without_feerate() {
+		let response = JsonResponse(serde_json::json!({ "errors": ["Insufficient data"], "blocks": 6 }));
+		match TryInto::<FeeRateEstimate>::try_into(response) {
+			Err(e) => panic!("Unexpected error: {:?}", e),
+			Ok(FeeRateEstimate(estimate)) => assert_eq!(estimate, None),
+		}
+	}
+
+	#[cfg(feature = "rpc-client")]
+	#[test]
+	fn into_fee_rate_estimate_from_json_response_with_feerate() {
+		let response = JsonResponse(serde_json::json!({ "feerate": 0.00012345, "blocks": 6 }));
+		match TryInto::<FeeRateEstimate>::try_into(response) {
+			Err(e) => panic!("Unexpected error: {:?}", e),
+			Ok(FeeRateEstimate(estimate)) => assert_eq!(estimate, Some(3086)),
+		}
+	}
+
 	#[test]
 	fn into_txid_from_json_response_with_unexpected_type() {
 		let response = JsonResponse(serde_json::json!({ "result": "foo" }));
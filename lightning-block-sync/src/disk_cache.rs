@@ -0,0 +1,200 @@
+//! A [`Cache`] implementation which persists block headers to disk.
+
+use crate::{BlockHeaderData, Cache, UnboundedCache};
+use crate::poll::{Validate, ValidatedBlockHeader};
+
+use bitcoin::consensus::encode;
+use bitcoin::hash_types::BlockHash;
+use bitcoin::hashes::hex::{FromHex, ToHex};
+use bitcoin::util::uint::Uint256;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The length of a serialized header entry: an 80-byte block header, a 4-byte height, and 32 bytes
+/// of chainwork.
+const HEADER_ENTRY_LEN: usize = 80 + 4 + 32;
+
+/// A block header cache which keeps headers in memory and writes them through to a directory on
+/// disk, one file per header, such that they are available again after a restart.
+///
+/// Useful when catching up after being offline for some time, as headers needed to find the fork
+/// point with the best chain need not be re-fetched from a block source.
+///
+/// Headers more than `max_depth` blocks below the most recently connected header are evicted both
+/// from memory and from disk. Errors writing to or removing from disk are ignored, as the cache is
+/// merely an optimization; any missing headers will be fetched from a block source as needed.
+pub struct DiskHeaderCache {
+	path: PathBuf,
+	headers: UnboundedCache,
+	max_depth: u32,
+}
+
+impl DiskHeaderCache {
+	/// Creates a cache persisting headers in the directory at `path`, loading any headers
+	/// previously persisted there. The directory is created if it doesn't exist.
+	///
+	/// Any entries which fail to deserialize or validate are removed.
+	pub fn new<P: Into<PathBuf>>(path: P, max_depth: u32) -> io::Result<Self> {
+		let path = path.into();
+		fs::create_dir_all(&path)?;
+
+		let mut headers = UnboundedCache::new();
+		for entry in fs::read_dir(&path)? {
+			let entry_path = entry?.path();
+			let block_hash = match entry_path.file_name().and_then(|name| name.to_str())
+				.and_then(|name| BlockHash::from_hex(name).ok())
+			{
+				Some(block_hash) => block_hash,
+				None => continue,
+			};
+			match read_header(&entry_path, block_hash) {
+				Some(header) => { headers.insert(block_hash, header); },
+				None => { let _ = fs::remove_file(&entry_path); },
+			}
+		}
+
+		Ok(Self { path, headers, max_depth })
+	}
+
+	fn header_path(&self, block_hash: &BlockHash) -> PathBuf {
+		self.path.join(block_hash.to_hex())
+	}
+
+	fn write_header(&self, block_hash: &BlockHash, header: &ValidatedBlockHeader) -> io::Result<()> {
+		let mut bytes = Vec::with_capacity(HEADER_ENTRY_LEN);
+		bytes.extend_from_slice(&encode::serialize(&header.header));
+		bytes.extend_from_slice(&header.height.to_be_bytes());
+		bytes.extend_from_slice(&header.chainwork.to_be_bytes());
+
+		// Write to a temporary file first so that a crash never leaves a partially written entry.
+		let header_path = self.header_path(block_hash);
+		let tmp_path = header_path.with_extension("tmp");
+		fs::write(&tmp_path, &bytes)?;
+		fs::rename(&tmp_path, &header_path)
+	}
+
+	fn remove_header(&mut self, block_hash: &BlockHash) -> Option<ValidatedBlockHeader> {
+		let _ = fs::remove_file(self.header_path(block_hash));
+		self.headers.remove(block_hash)
+	}
+}
+
+fn read_header(path: &Path, block_hash: BlockHash) -> Option<ValidatedBlockHeader> {
+	let bytes = fs::read(path).ok()?;
+	if bytes.len() != HEADER_ENTRY_LEN {
+		return None;
+	}
+
+	let header = encode::deserialize(&bytes[..80]).ok()?;
+	let mut height_bytes = [0; 4];
+	height_bytes.copy_from_slice(&bytes[80..84]);
+	let mut chainwork_bytes = [0; 32];
+	chainwork_bytes.copy_from_slice(&bytes[84..]);
+	let header_data = BlockHeaderData {
+		header,
+		height: u32::from_be_bytes(height_bytes),
+		chainwork: Uint256::from_be_bytes(chainwork_bytes),
+	};
+	header_data.validate(block_hash).ok()
+}
+
+impl Cache for DiskHeaderCache {
+	fn look_up(&self, block_hash: &BlockHash) -> Option<&ValidatedBlockHeader> {
+		self.headers.get(block_hash)
+	}
+
+	fn block_connected(&mut self, block_hash: BlockHash, block_header: ValidatedBlockHeader) {
+		let _ = self.write_header(&block_hash, &block_header);
+		self.headers.insert(block_hash, block_header);
+
+		if let Some(min_height) = block_header.height.checked_sub(self.max_depth) {
+			let evicted_hashes: Vec<BlockHash> = self.headers.iter()
+				.filter(|(_, header)| header.height < min_height)
+				.map(|(block_hash, _)| *block_hash)
+				.collect();
+			for block_hash in evicted_hashes {
+				self.remove_header(&block_hash);
+			}
+		}
+	}
+
+	fn block_disconnected(&mut self, block_hash: &BlockHash) -> Option<ValidatedBlockHeader> {
+		self.remove_header(block_hash)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_utils::Blockchain;
+
+	fn test_cache_path(test_name: &str) -> PathBuf {
+		let path = std::env::temp_dir().join(format!("lightning-block-sync-{}", test_name));
+		let _ = fs::remove_dir_all(&path);
+		path
+	}
+
+	#[test]
+	fn persists_connected_headers() {
+		let chain = Blockchain::default().with_height(3);
+		let path = test_cache_path("persists_connected_headers");
+
+		let mut cache = DiskHeaderCache::new(&path, 10).unwrap();
+		for height in 0..=3 {
+			let header = chain.at_height(height);
+			cache.block_connected(header.block_hash, header);
+		}
+		let disconnected_tip = chain.tip();
+		assert_eq!(cache.block_disconnected(&disconnected_tip.block_hash), Some(disconnected_tip));
+
+		let cache = DiskHeaderCache::new(&path, 10).unwrap();
+		assert_eq!(cache.headers.len(), 3);
+		for height in 0..=2 {
+			let header = chain.at_height(height);
+			assert_eq!(cache.look_up(&header.block_hash), Some(&header));
+		}
+		assert_eq!(cache.look_up(&disconnected_tip.block_hash), None);
+
+		fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[test]
+	fn evicts_deep_headers() {
+		let chain = Blockchain::default().with_height(3);
+		let path = test_cache_path("evicts_deep_headers");
+
+		let mut cache = DiskHeaderCache::new(&path, 1).unwrap();
+		for height in 0..=3 {
+			let header = chain.at_height(height);
+			cache.block_connected(header.block_hash, header);
+		}
+		assert_eq!(cache.headers.len(), 2);
+		assert_eq!(cache.look_up(&chain.at_height(1).block_hash), None);
+
+		let cache = DiskHeaderCache::new(&path, 1).unwrap();
+		assert_eq!(cache.headers.len(), 2);
+		assert!(cache.look_up(&chain.at_height(2).block_hash).is_some());
+		assert!(cache.look_up(&chain.at_height(3).block_hash).is_some());
+
+		fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[test]
+	fn removes_corrupted_entries() {
+		let chain = Blockchain::default().with_height(1);
+		let path = test_cache_path("removes_corrupted_entries");
+
+		let mut cache = DiskHeaderCache::new(&path, 10).unwrap();
+		let header = chain.tip();
+		cache.block_connected(header.block_hash, header);
+		fs::write(cache.header_path(&header.block_hash), &[0u8; HEADER_ENTRY_LEN][..]).unwrap();
+
+		let cache = DiskHeaderCache::new(&path, 10).unwrap();
+		assert_eq!(cache.headers.len(), 0);
+		assert!(!cache.header_path(&header.block_hash).exists());
+
+		fs::remove_dir_all(&path).unwrap();
+	}
+}
@@ -132,10 +132,29 @@ BlockSourceResult<ValidatedBlockHeader> where B::Target: BlockSource {
 /// [`ChannelManager`]: lightning::ln::channelmanager::ChannelManager
 /// [`ChannelMonitor`]: lightning::chain::channelmonitor::ChannelMonitor
 pub async fn synchronize_listeners<B: Deref + Sized + Send + Sync, C: Cache, L: chain::Listen + ?Sized>(
+	block_source: B,
+	network: Network,
+	header_cache: &mut C,
+	chain_listeners: Vec<(BlockHash, &L)>,
+) -> BlockSourceResult<ValidatedBlockHeader> where B::Target: BlockSource {
+	synchronize_listeners_with_block_fetch_concurrency(
+		block_source, network, header_cache, chain_listeners, 1).await
+}
+
+/// Performs the same one-time sync as [`synchronize_listeners`], but fetches up to
+/// `block_fetch_concurrency` blocks from `block_source` concurrently when connecting blocks.
+///
+/// Useful when listeners may be many blocks behind the best chain tip, e.g., after being offline
+/// for an extended period. Blocks are still connected to the listeners in order. A value of `0` is
+/// treated as `1`.
+pub async fn synchronize_listeners_with_block_fetch_concurrency<
+	B: Deref + Sized + Send + Sync, C: Cache, L: chain::Listen + ?Sized
+>(
 	block_source: B,
 	network: Network,
 	header_cache: &mut C,
 	mut chain_listeners: Vec<(BlockHash, &L)>,
+	block_fetch_concurrency: usize,
 ) -> BlockSourceResult<ValidatedBlockHeader> where B::Target: BlockSource {
 	let best_header = validate_best_block_header(&*block_source).await?;
 
@@ -161,7 +180,7 @@ pub async fn synchronize_listeners<B: Deref + Sized + Send + Sync, C: Cache, L:
 		let header_cache = &mut ReadOnlyCache(header_cache);
 		let (common_ancestor, connected_blocks) = {
 			let chain_listener = &DynamicChainListener(chain_listener);
-			let mut chain_notifier = ChainNotifier { header_cache, chain_listener };
+			let mut chain_notifier = ChainNotifier { header_cache, chain_listener, block_fetch_concurrency };
 			let difference =
 				chain_notifier.find_difference(best_header, &old_header, &mut chain_poller).await?;
 			chain_notifier.disconnect_blocks(difference.disconnected_blocks);
@@ -179,7 +198,7 @@ pub async fn synchronize_listeners<B: Deref + Sized + Send + Sync, C: Cache, L:
 	// Connect new blocks for all listeners at once to avoid re-fetching blocks.
 	if let Some(common_ancestor) = most_common_ancestor {
 		let chain_listener = &ChainListenerSet(chain_listeners_at_height);
-		let mut chain_notifier = ChainNotifier { header_cache, chain_listener };
+		let mut chain_notifier = ChainNotifier { header_cache, chain_listener, block_fetch_concurrency };
 		chain_notifier.connect_blocks(common_ancestor, most_connected_blocks, &mut chain_poller)
 			.await.map_err(|(e, _)| e)?;
 	}
@@ -280,6 +299,33 @@ mod tests {
 		}
 	}
 
+	#[tokio::test]
+	async fn sync_from_same_chain_with_block_fetch_concurrency() {
+		let chain = Blockchain::default().with_height(6);
+
+		let listener_1 = MockChainListener::new()
+			.expect_block_connected(*chain.at_height(2))
+			.expect_block_connected(*chain.at_height(3))
+			.expect_block_connected(*chain.at_height(4))
+			.expect_block_connected(*chain.at_height(5))
+			.expect_block_connected(*chain.at_height(6));
+		let listener_2 = MockChainListener::new()
+			.expect_block_connected(*chain.at_height(5))
+			.expect_block_connected(*chain.at_height(6));
+
+		let listeners = vec![
+			(chain.at_height(1).block_hash, &listener_1 as &dyn chain::Listen),
+			(chain.at_height(4).block_hash, &listener_2 as &dyn chain::Listen),
+		];
+		let mut cache = chain.header_cache(0..=6);
+		match synchronize_listeners_with_block_fetch_concurrency(
+			&chain, Network::Bitcoin, &mut cache, listeners, 2).await
+		{
+			Ok(header) => assert_eq!(header, chain.tip()),
+			Err(e) => panic!("Unexpected error: {:?}", e),
+		}
+	}
+
 	#[tokio::test]
 	async fn sync_from_different_chains() {
 		let main_chain = Blockchain::default().with_height(4);
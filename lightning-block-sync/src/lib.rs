@@ -12,6 +12,18 @@
 //!
 //! Both features support either blocking I/O using `std::net::TcpStream` or, with feature `tokio`,
 //! non-blocking I/O using `tokio::net::TcpStream` from inside a Tokio runtime.
+//!
+//! Defines a [`CompactFilterBlockSource`] utility for only fetching blocks relevant to the
+//! scripts registered via [`chain::Filter`], using BIP 157/158 compact block filters.
+//!
+//! Defines a [`DiskHeaderCache`] implementation of the [`Cache`] trait which persists block headers
+//! on the best chain to disk, avoiding re-fetching them after a restart.
+//!
+//! With feature `tokio`, the `zmq` module allows polling as soon as Bitcoin Core publishes a new
+//! block over ZMQ, rather than only on a fixed interval.
+//!
+//! [`CompactFilterBlockSource`]: crate::compact_filters::CompactFilterBlockSource
+//! [`DiskHeaderCache`]: crate::disk_cache::DiskHeaderCache
 
 // Prefix these with `rustdoc::` when we update our MSRV to be >= 1.52 to remove warnings.
 #![deny(broken_intra_doc_links)]
@@ -25,6 +37,8 @@
 #[cfg(any(feature = "rest-client", feature = "rpc-client"))]
 pub mod http;
 
+pub mod compact_filters;
+pub mod disk_cache;
 pub mod init;
 pub mod poll;
 
@@ -34,6 +48,9 @@ pub mod rest;
 #[cfg(feature = "rpc-client")]
 pub mod rpc;
 
+#[cfg(feature = "tokio")]
+pub mod zmq;
+
 #[cfg(any(feature = "rest-client", feature = "rpc-client"))]
 mod convert;
 
@@ -50,11 +67,13 @@ use bitcoin::hash_types::BlockHash;
 use bitcoin::util::uint::Uint256;
 
 use lightning::chain;
-use lightning::chain::Listen;
+use lightning::chain::{Listen, SyncProgress};
 
+use std::cmp;
 use std::future::Future;
 use std::ops::Deref;
 use std::pin::Pin;
+use std::task;
 
 /// Abstract type for retrieving block headers and data.
 pub trait BlockSource : Sync + Send {
@@ -179,9 +198,14 @@ pub enum BlockData {
 /// custom cache eviction policy. This offers flexibility to those sensitive to resource usage.
 /// Hence, there is a trade-off between a lower memory footprint and potentially increased network
 /// I/O as headers are re-fetched during fork detection.
+///
+/// By default, blocks are fetched one at a time. When catching up on many blocks, fetching several
+/// blocks concurrently may be enabled via [`SpvClient::with_block_fetch_concurrency`]. Blocks are
+/// always connected to the listener in order regardless.
 pub struct SpvClient<'a, P: Poll, C: Cache, L: Deref>
 where L::Target: chain::Listen {
 	chain_tip: ValidatedBlockHeader,
+	best_polled_tip_height: u32,
 	chain_poller: P,
 	chain_notifier: ChainNotifier<'a, C, L>,
 }
@@ -243,8 +267,15 @@ impl<'a, P: Poll, C: Cache, L: Deref> SpvClient<'a, P, C, L> where L::Target: ch
 		header_cache: &'a mut C,
 		chain_listener: L,
 	) -> Self {
-		let chain_notifier = ChainNotifier { header_cache, chain_listener };
-		Self { chain_tip, chain_poller, chain_notifier }
+		let chain_notifier = ChainNotifier { header_cache, chain_listener, block_fetch_concurrency: 1 };
+		Self { chain_tip, best_polled_tip_height: chain_tip.height, chain_poller, chain_notifier }
+	}
+
+	/// Sets the maximum number of blocks fetched concurrently from the chain poller when connecting
+	/// blocks. A value of `0` is treated as `1`.
+	pub fn with_block_fetch_concurrency(mut self, block_fetch_concurrency: usize) -> Self {
+		self.chain_notifier.block_fetch_concurrency = block_fetch_concurrency;
+		self
 	}
 
 	/// Polls for the best tip and updates the chain listener with any connected or disconnected
@@ -259,6 +290,7 @@ impl<'a, P: Poll, C: Cache, L: Deref> SpvClient<'a, P, C, L> where L::Target: ch
 			ChainTip::Better(chain_tip) => {
 				debug_assert_ne!(chain_tip.block_hash, self.chain_tip.block_hash);
 				debug_assert!(chain_tip.chainwork > self.chain_tip.chainwork);
+				self.best_polled_tip_height = chain_tip.height;
 				self.update_chain_tip(chain_tip).await
 			},
 			ChainTip::Worse(chain_tip) => {
@@ -270,6 +302,20 @@ impl<'a, P: Poll, C: Cache, L: Deref> SpvClient<'a, P, C, L> where L::Target: ch
 		Ok((chain_tip, blocks_connected))
 	}
 
+	/// Returns how far the chain listener has been synced towards the best chain tip polled so far,
+	/// e.g., to pass [`SyncProgress::tip_height`] to [`ChannelManager::set_chain_tip_height`].
+	///
+	/// As full blocks are connected, no scripts are ever pending a scan.
+	///
+	/// [`ChannelManager::set_chain_tip_height`]: lightning::ln::channelmanager::ChannelManager::set_chain_tip_height
+	pub fn sync_progress(&self) -> SyncProgress {
+		SyncProgress {
+			synced_height: self.chain_tip.height,
+			tip_height: cmp::max(self.best_polled_tip_height, self.chain_tip.height),
+			pending_scripts: 0,
+		}
+	}
+
 	/// Updates the chain tip, syncing the chain listener with any connected or disconnected
 	/// blocks. Returns whether there were any such blocks.
 	async fn update_chain_tip(&mut self, best_chain_tip: ValidatedBlockHeader) -> bool {
@@ -298,6 +344,9 @@ pub struct ChainNotifier<'a, C: Cache, L: Deref> where L::Target: chain::Listen
 
 	/// Listener that will be notified of connected or disconnected blocks.
 	chain_listener: L,
+
+	/// Maximum number of blocks to fetch concurrently when connecting blocks.
+	block_fetch_concurrency: usize,
 }
 
 /// Changes made to the chain between subsequent polls that transformed it from having one chain tip
@@ -404,36 +453,86 @@ impl<'a, C: Cache, L: Deref> ChainNotifier<'a, C, L> where L::Target: chain::Lis
 	}
 
 	/// Notifies the chain listeners of connected blocks.
+	///
+	/// Fetches up to `block_fetch_concurrency` blocks at a time, connecting each batch in order once
+	/// fetched. If fetching a block fails, any preceding blocks in the batch are still connected.
 	async fn connect_blocks<P: Poll>(
 		&mut self,
 		mut new_tip: ValidatedBlockHeader,
 		mut connected_blocks: Vec<ValidatedBlockHeader>,
 		chain_poller: &mut P,
 	) -> Result<(), (BlockSourceError, Option<ValidatedBlockHeader>)> {
-		for header in connected_blocks.drain(..).rev() {
-			let height = header.height;
-			let block_data = chain_poller
-				.fetch_block(&header).await
-				.map_err(|e| (e, Some(new_tip)))?;
-			debug_assert_eq!(block_data.block_hash, header.block_hash);
-
-			match block_data.deref() {
-				BlockData::FullBlock(block) => {
-					self.chain_listener.block_connected(block, height);
-				},
-				BlockData::HeaderOnly(header) => {
-					self.chain_listener.filtered_block_connected(header, &[], height);
-				},
+		connected_blocks.reverse();
+		let batch_size = std::cmp::max(self.block_fetch_concurrency, 1);
+		for headers in connected_blocks.chunks(batch_size) {
+			let chain_poller = &*chain_poller;
+			let block_fetches = headers.iter().map(|header| chain_poller.fetch_block(header)).collect();
+			let fetched_blocks = JoinAll::new(block_fetches).await;
+
+			for (header, block_data) in headers.iter().zip(fetched_blocks) {
+				let height = header.height;
+				let block_data = block_data.map_err(|e| (e, Some(new_tip)))?;
+				debug_assert_eq!(block_data.block_hash, header.block_hash);
+
+				match block_data.deref() {
+					BlockData::FullBlock(block) => {
+						self.chain_listener.block_connected(block, height);
+					},
+					BlockData::HeaderOnly(header) => {
+						self.chain_listener.filtered_block_connected(header, &[], height);
+					},
+				}
+
+				self.header_cache.block_connected(header.block_hash, *header);
+				new_tip = *header;
 			}
-
-			self.header_cache.block_connected(header.block_hash, header);
-			new_tip = header;
 		}
 
 		Ok(())
 	}
 }
 
+/// A future which drives a set of block source requests concurrently, resolving to their results
+/// in the original order once all have completed.
+struct JoinAll<'a, T> {
+	futures: Vec<Option<AsyncBlockSourceResult<'a, T>>>,
+	results: Vec<Option<BlockSourceResult<T>>>,
+}
+
+impl<'a, T> JoinAll<'a, T> {
+	fn new(futures: Vec<AsyncBlockSourceResult<'a, T>>) -> Self {
+		let results = futures.iter().map(|_| None).collect();
+		let futures = futures.into_iter().map(Some).collect();
+		Self { futures, results }
+	}
+}
+
+impl<'a, T: Unpin> Future for JoinAll<'a, T> {
+	type Output = Vec<BlockSourceResult<T>>;
+
+	fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> task::Poll<Self::Output> {
+		let this = self.get_mut();
+		let mut pending = false;
+		for (future, result) in this.futures.iter_mut().zip(this.results.iter_mut()) {
+			if let Some(fut) = future {
+				match fut.as_mut().poll(cx) {
+					task::Poll::Ready(res) => {
+						*result = Some(res);
+						*future = None;
+					},
+					task::Poll::Pending => pending = true,
+				}
+			}
+		}
+
+		if pending {
+			task::Poll::Pending
+		} else {
+			task::Poll::Ready(this.results.drain(..).map(|res| res.unwrap()).collect())
+		}
+	}
+}
+
 #[cfg(test)]
 mod spv_client_tests {
 	use crate::test_utils::{Blockchain, NullChainListener};
@@ -539,6 +638,25 @@ mod spv_client_tests {
 		assert_eq!(client.chain_tip, chain.at_height(2));
 	}
 
+	#[tokio::test]
+	async fn reports_sync_progress() {
+		let mut chain = Blockchain::default().with_height(3).without_blocks(3..);
+		let old_tip = chain.at_height(1);
+
+		let poller = poll::ChainPoller::new(&mut chain, Network::Testnet);
+		let mut cache = UnboundedCache::new();
+		let mut listener = NullChainListener {};
+		let mut client = SpvClient::new(old_tip, poller, &mut cache, &mut listener);
+		assert_eq!(client.sync_progress(), SyncProgress { synced_height: 1, tip_height: 1, pending_scripts: 0 });
+
+		// Block 3 is unavailable, so we only get to sync up to block 2 of the chain tip at block 3.
+		client.poll_best_tip().await.unwrap();
+		let progress = client.sync_progress();
+		assert_eq!(progress, SyncProgress { synced_height: 2, tip_height: 3, pending_scripts: 0 });
+		assert_eq!(progress.blocks_behind(), 1);
+		assert!(!progress.is_synced());
+	}
+
 	#[tokio::test]
 	async fn poll_from_chain_with_worse_tip() {
 		let mut chain = Blockchain::default().with_height(3);
@@ -580,6 +698,30 @@ mod chain_notifier_tests {
 		let mut notifier = ChainNotifier {
 			header_cache: &mut chain.header_cache(0..=1),
 			chain_listener,
+			block_fetch_concurrency: 1,
+		};
+		let mut poller = poll::ChainPoller::new(&mut chain, Network::Testnet);
+		match notifier.synchronize_listener(new_tip, &old_tip, &mut poller).await {
+			Err((e, _)) => panic!("Unexpected error: {:?}", e),
+			Ok(_) => {},
+		}
+	}
+
+	#[tokio::test]
+	async fn sync_from_same_chain_with_block_fetch_concurrency() {
+		let mut chain = Blockchain::default().with_height(5);
+
+		let new_tip = chain.tip();
+		let old_tip = chain.at_height(1);
+		let chain_listener = &MockChainListener::new()
+			.expect_block_connected(*chain.at_height(2))
+			.expect_block_connected(*chain.at_height(3))
+			.expect_block_connected(*chain.at_height(4))
+			.expect_block_connected(*new_tip);
+		let mut notifier = ChainNotifier {
+			header_cache: &mut chain.header_cache(0..=1),
+			chain_listener,
+			block_fetch_concurrency: 3,
 		};
 		let mut poller = poll::ChainPoller::new(&mut chain, Network::Testnet);
 		match notifier.synchronize_listener(new_tip, &old_tip, &mut poller).await {
@@ -588,6 +730,32 @@ mod chain_notifier_tests {
 		}
 	}
 
+	#[tokio::test]
+	async fn sync_from_chain_without_some_blocks_with_block_fetch_concurrency() {
+		let mut chain = Blockchain::default().with_height(5).without_blocks(4..);
+
+		let new_tip = chain.tip();
+		let old_tip = chain.at_height(1);
+		let last_connected_tip = chain.at_height(3);
+		let chain_listener = &MockChainListener::new()
+			.expect_block_connected(*chain.at_height(2))
+			.expect_block_connected(*last_connected_tip);
+		let mut notifier = ChainNotifier {
+			header_cache: &mut chain.header_cache(0..=1),
+			chain_listener,
+			block_fetch_concurrency: 3,
+		};
+		let mut poller = poll::ChainPoller::new(&mut chain, Network::Testnet);
+		match notifier.synchronize_listener(new_tip, &old_tip, &mut poller).await {
+			Err((e, tip)) => {
+				assert_eq!(e.kind(), BlockSourceErrorKind::Persistent);
+				assert_eq!(e.into_inner().as_ref().to_string(), "block not found");
+				assert_eq!(tip, Some(last_connected_tip));
+			},
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+
 	#[tokio::test]
 	async fn sync_from_different_chains() {
 		let mut test_chain = Blockchain::with_network(Network::Testnet).with_height(1);
@@ -599,6 +767,7 @@ mod chain_notifier_tests {
 		let mut notifier = ChainNotifier {
 			header_cache: &mut main_chain.header_cache(0..=1),
 			chain_listener,
+			block_fetch_concurrency: 1,
 		};
 		let mut poller = poll::ChainPoller::new(&mut test_chain, Network::Testnet);
 		match notifier.synchronize_listener(new_tip, &old_tip, &mut poller).await {
@@ -623,6 +792,7 @@ mod chain_notifier_tests {
 		let mut notifier = ChainNotifier {
 			header_cache: &mut main_chain.header_cache(0..=2),
 			chain_listener,
+			block_fetch_concurrency: 1,
 		};
 		let mut poller = poll::ChainPoller::new(&mut fork_chain, Network::Testnet);
 		match notifier.synchronize_listener(new_tip, &old_tip, &mut poller).await {
@@ -646,6 +816,7 @@ mod chain_notifier_tests {
 		let mut notifier = ChainNotifier {
 			header_cache: &mut main_chain.header_cache(0..=3),
 			chain_listener,
+			block_fetch_concurrency: 1,
 		};
 		let mut poller = poll::ChainPoller::new(&mut fork_chain, Network::Testnet);
 		match notifier.synchronize_listener(new_tip, &old_tip, &mut poller).await {
@@ -669,6 +840,7 @@ mod chain_notifier_tests {
 		let mut notifier = ChainNotifier {
 			header_cache: &mut main_chain.header_cache(0..=2),
 			chain_listener,
+			block_fetch_concurrency: 1,
 		};
 		let mut poller = poll::ChainPoller::new(&mut fork_chain, Network::Testnet);
 		match notifier.synchronize_listener(new_tip, &old_tip, &mut poller).await {
@@ -687,6 +859,7 @@ mod chain_notifier_tests {
 		let mut notifier = ChainNotifier {
 			header_cache: &mut chain.header_cache(0..=1),
 			chain_listener,
+			block_fetch_concurrency: 1,
 		};
 		let mut poller = poll::ChainPoller::new(&mut chain, Network::Testnet);
 		match notifier.synchronize_listener(new_tip, &old_tip, &mut poller).await {
@@ -705,6 +878,7 @@ mod chain_notifier_tests {
 		let mut notifier = ChainNotifier {
 			header_cache: &mut chain.header_cache(0..=3),
 			chain_listener,
+			block_fetch_concurrency: 1,
 		};
 		let mut poller = poll::ChainPoller::new(&mut chain, Network::Testnet);
 		match notifier.synchronize_listener(new_tip, &old_tip, &mut poller).await {
@@ -724,6 +898,7 @@ mod chain_notifier_tests {
 		let mut notifier = ChainNotifier {
 			header_cache: &mut chain.header_cache(0..=3),
 			chain_listener,
+			block_fetch_concurrency: 1,
 		};
 		let mut poller = poll::ChainPoller::new(&mut chain, Network::Testnet);
 		match notifier.synchronize_listener(new_tip, &old_tip, &mut poller).await {
@@ -744,6 +919,7 @@ mod chain_notifier_tests {
 		let mut notifier = ChainNotifier {
 			header_cache: &mut chain.header_cache(0..=1),
 			chain_listener,
+			block_fetch_concurrency: 1,
 		};
 		let mut poller = poll::ChainPoller::new(&mut chain, Network::Testnet);
 		match notifier.synchronize_listener(new_tip, &old_tip, &mut poller).await {
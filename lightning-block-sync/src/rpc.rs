@@ -1,12 +1,24 @@
 //! Simple RPC client implementation which implements [`BlockSource`] against a Bitcoin Core RPC
 //! endpoint.
+//!
+//! Also provides an [`RpcFeeEstimator`] which implements [`FeeEstimator`] using Bitcoin Core's
+//! `estimatesmartfee` RPC, which does not require the node to have a wallet.
 
 use crate::{BlockData, BlockHeaderData, BlockSource, AsyncBlockSourceResult};
+use crate::compact_filters::BlockFilterSource;
+use crate::convert::FeeRateEstimate;
 use crate::http::{HttpClient, HttpEndpoint, HttpError, JsonResponse};
+use crate::utils::base64_encode;
 
-use bitcoin::hash_types::BlockHash;
+use bitcoin::hash_types::{BlockHash, FilterHeader};
 use bitcoin::hashes::hex::ToHex;
+use bitcoin::util::bip158::BlockFilter;
 
+use lightning::chain::chaininterface::{ConfirmationTarget, FeeEstimator, FEERATE_FLOOR_SATS_PER_KW};
+
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::path::Path;
 use std::sync::Mutex;
 
 use serde_json;
@@ -58,26 +70,87 @@ impl RpcClient {
 		})
 	}
 
+	/// Creates a new RPC client connected to the given endpoint using the credentials in the cookie
+	/// file written by Bitcoin Core, typically found at `.cookie` in its data directory.
+	///
+	/// Note that Bitcoin Core generates a new cookie each time it starts, so the client must be
+	/// re-created whenever the node is restarted.
+	pub fn from_cookie_file<P: AsRef<Path>>(path: P, endpoint: HttpEndpoint) -> std::io::Result<Self> {
+		let cookie = std::fs::read_to_string(path)?;
+		let cookie = cookie.trim_end();
+		if !cookie.contains(':') {
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid cookie file"));
+		}
+		Self::new(&base64_encode(cookie.as_bytes()), endpoint)
+	}
+
 	/// Calls a method with the response encoded in JSON format and interpreted as type `T`.
 	///
 	/// When an `Err` is returned, [`std::io::Error::into_inner`] may contain an [`RpcError`] if
 	/// [`std::io::Error::kind`] is [`std::io::ErrorKind::Other`].
 	pub async fn call_method<T>(&self, method: &str, params: &[serde_json::Value]) -> std::io::Result<T>
 	where JsonResponse: TryFrom<Vec<u8>, Error = std::io::Error> + TryInto<T, Error = std::io::Error> {
-		let host = format!("{}:{}", self.endpoint.host(), self.endpoint.port());
-		let uri = self.endpoint.path();
 		let content = serde_json::json!({
 			"method": method,
 			"params": params,
 			"id": &self.id.fetch_add(1, Ordering::AcqRel).to_string()
 		});
 
+		let response = self.post(content).await?;
+		Self::parse_response(response)
+	}
+
+	/// Calls several methods using a single JSON-RPC batch request, with each response encoded in
+	/// JSON format and interpreted as type `T`.
+	///
+	/// Returns the result of each call in the order the calls were given. The outer `Err` is
+	/// returned if the batch request as a whole failed, whereas an inner `Err` is returned for each
+	/// failed call and may contain an [`RpcError`] as described in [`RpcClient::call_method`].
+	pub async fn call_method_batch<T>(&self, calls: &[(&str, &[serde_json::Value])]) -> std::io::Result<Vec<std::io::Result<T>>>
+	where JsonResponse: TryFrom<Vec<u8>, Error = std::io::Error> + TryInto<T, Error = std::io::Error> {
+		let first_id = self.id.fetch_add(calls.len(), Ordering::AcqRel);
+		let content = serde_json::Value::Array(calls.iter().enumerate().map(|(i, (method, params))| {
+			serde_json::json!({
+				"method": method,
+				"params": params,
+				"id": &first_id.wrapping_add(i).to_string()
+			})
+		}).collect());
+
+		let responses = match self.post(content).await? {
+			serde_json::Value::Array(responses) => responses,
+			_ => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected JSON array")),
+		};
+
+		let mut results: Vec<Option<std::io::Result<T>>> = calls.iter().map(|_| None).collect();
+		for response in responses {
+			let index = response["id"].as_str()
+				.and_then(|id| id.parse::<usize>().ok())
+				.map(|id| id.wrapping_sub(first_id))
+				.filter(|index| *index < calls.len());
+			match index {
+				Some(index) => results[index] = Some(Self::parse_response(response)),
+				None => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "unexpected JSON-RPC id")),
+			}
+		}
+
+		Ok(results.into_iter().map(|result| result.unwrap_or_else(|| {
+			Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "missing JSON-RPC response"))
+		})).collect())
+	}
+
+	/// Posts the given JSON-RPC request, returning the JSON response even if the server responded
+	/// with an HTTP error, as Bitcoin Core does for RPC errors.
+	async fn post(&self, content: serde_json::Value) -> std::io::Result<serde_json::Value> {
+		let host = format!("{}:{}", self.endpoint.host(), self.endpoint.port());
+		let uri = self.endpoint.path();
+
 		let mut client = if let Some(client) = self.client.lock().unwrap().take() { client }
 			else { HttpClient::connect(&self.endpoint)? };
 		let http_response = client.post::<JsonResponse>(&uri, &host, &self.basic_auth, content).await;
 		*self.client.lock().unwrap() = Some(client);
 
-		let mut response = match http_response {
+		let response = match http_response {
 			Ok(JsonResponse(response)) => response,
 			Err(e) if e.kind() == std::io::ErrorKind::Other => {
 				match e.get_ref().unwrap().downcast_ref::<HttpError>() {
@@ -91,6 +164,12 @@ impl RpcClient {
 			Err(e) => Err(e)?,
 		};
 
+		Ok(response)
+	}
+
+	/// Interprets a single JSON-RPC response object, returning its result as type `T`.
+	fn parse_response<T>(mut response: serde_json::Value) -> std::io::Result<T>
+	where JsonResponse: TryInto<T, Error = std::io::Error> {
 		if !response.is_object() {
 			return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected JSON object"));
 		}
@@ -137,6 +216,136 @@ impl BlockSource for RpcClient {
 	}
 }
 
+/// A [`FeeEstimator`] backed by Bitcoin Core's `estimatesmartfee` RPC.
+///
+/// As [`FeeEstimator`] is a synchronous interface, estimates are cached per [`ConfirmationTarget`]
+/// and must be refreshed periodically by calling [`RpcFeeEstimator::update_fee_estimates`], e.g.,
+/// whenever a new block is connected. Until an estimate is available for a target, a conservative
+/// fallback feerate is returned.
+pub struct RpcFeeEstimator<R: Deref<Target = RpcClient>> {
+	client: R,
+	fee_rates: Mutex<HashMap<ConfirmationTarget, u32>>,
+}
+
+impl<R: Deref<Target = RpcClient>> RpcFeeEstimator<R> {
+	/// Creates a fee estimator querying the given RPC client. No estimates are fetched until
+	/// [`RpcFeeEstimator::update_fee_estimates`] is called.
+	pub fn new(client: R) -> Self {
+		Self { client, fee_rates: Mutex::new(HashMap::new()) }
+	}
+
+	/// Fetches new estimates for all [`ConfirmationTarget`]s using a single batch request.
+	///
+	/// Targets for which Bitcoin Core has insufficient data to produce an estimate retain their
+	/// previously cached estimate, if any.
+	pub async fn update_fee_estimates(&self) -> std::io::Result<()> {
+		let targets = [
+			ConfirmationTarget::Background, ConfirmationTarget::Normal, ConfirmationTarget::HighPriority,
+			ConfirmationTarget::OnChainSweep, ConfirmationTarget::AnchorChannelFee,
+			ConfirmationTarget::ChannelCloseMinimum,
+		];
+		let params: Vec<[serde_json::Value; 2]> = targets.iter().map(|target| {
+			let (conf_target, estimate_mode) = Self::estimate_params(*target);
+			[serde_json::json!(conf_target), serde_json::json!(estimate_mode)]
+		}).collect();
+		let calls: Vec<(&str, &[serde_json::Value])> =
+			params.iter().map(|params| ("estimatesmartfee", &params[..])).collect();
+
+		let estimates = self.client.call_method_batch::<FeeRateEstimate>(&calls).await?;
+		let mut fee_rates = self.fee_rates.lock().unwrap();
+		for (target, estimate) in targets.iter().zip(estimates) {
+			if let FeeRateEstimate(Some(sat_per_kw)) = estimate? {
+				fee_rates.insert(*target, std::cmp::max(sat_per_kw, FEERATE_FLOOR_SATS_PER_KW));
+			}
+		}
+		Ok(())
+	}
+
+	/// Returns the `estimatesmartfee` confirmation target in blocks and estimate mode to use for the
+	/// given target.
+	fn estimate_params(target: ConfirmationTarget) -> (u16, &'static str) {
+		match target {
+			ConfirmationTarget::Background => (144, "ECONOMICAL"),
+			ConfirmationTarget::Normal => (18, "ECONOMICAL"),
+			ConfirmationTarget::HighPriority => (6, "CONSERVATIVE"),
+			ConfirmationTarget::OnChainSweep => (3, "CONSERVATIVE"),
+			ConfirmationTarget::AnchorChannelFee => (18, "ECONOMICAL"),
+			ConfirmationTarget::ChannelCloseMinimum => (1008, "ECONOMICAL"),
+		}
+	}
+
+	/// Returns the feerate to use for the given target when no estimate is available.
+	fn fallback_fee_rate(target: ConfirmationTarget) -> u32 {
+		match target {
+			ConfirmationTarget::Background => FEERATE_FLOOR_SATS_PER_KW,
+			ConfirmationTarget::Normal => 2000,
+			ConfirmationTarget::HighPriority => 5000,
+			ConfirmationTarget::OnChainSweep => 5000,
+			ConfirmationTarget::AnchorChannelFee => 2000,
+			ConfirmationTarget::ChannelCloseMinimum => FEERATE_FLOOR_SATS_PER_KW,
+		}
+	}
+}
+
+impl<R: Deref<Target = RpcClient>> FeeEstimator for RpcFeeEstimator<R> {
+	fn get_est_sat_per_1000_weight(&self, confirmation_target: ConfirmationTarget) -> u32 {
+		self.fee_rates.lock().unwrap().get(&confirmation_target).copied()
+			.unwrap_or_else(|| Self::fallback_fee_rate(confirmation_target))
+	}
+}
+
+/// Requires the Bitcoin Core node to be run with `-blockfilterindex`.
+impl BlockFilterSource for RpcClient {
+	fn get_block_filter<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, BlockFilter> {
+		Box::pin(async move {
+			let header_hash = serde_json::json!(header_hash.to_hex());
+			let filter_type = serde_json::json!("basic");
+			Ok(self.call_method("getblockfilter", &[header_hash, filter_type]).await?)
+		})
+	}
+
+	fn get_filter_header<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, FilterHeader> {
+		Box::pin(async move {
+			let header_hash = serde_json::json!(header_hash.to_hex());
+			let filter_type = serde_json::json!("basic");
+			Ok(self.call_method("getblockfilter", &[header_hash, filter_type]).await?)
+		})
+	}
+}
+
+/// A [`BlockFilterSource`] backed by a btcd node, which serves BIP 157/158 filters via its
+/// `getcfilter` and `getcfilterheader` RPCs rather than Bitcoin Core's `getblockfilter`.
+///
+/// Requires the btcd node not to be run with `--nocfilters`.
+pub struct BtcdFilterClient {
+	client: RpcClient,
+}
+
+impl BtcdFilterClient {
+	/// Creates a new filter source calling the given btcd RPC client.
+	pub fn new(client: RpcClient) -> Self {
+		Self { client }
+	}
+}
+
+impl BlockFilterSource for BtcdFilterClient {
+	fn get_block_filter<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, BlockFilter> {
+		Box::pin(async move {
+			let header_hash = serde_json::json!(header_hash.to_hex());
+			let filter_type = serde_json::json!(0);
+			Ok(self.client.call_method("getcfilter", &[header_hash, filter_type]).await?)
+		})
+	}
+
+	fn get_filter_header<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, FilterHeader> {
+		Box::pin(async move {
+			let header_hash = serde_json::json!(header_hash.to_hex());
+			let filter_type = serde_json::json!(0);
+			Ok(self.client.call_method("getcfilterheader", &[header_hash, filter_type]).await?)
+		})
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -244,4 +453,89 @@ mod tests {
 			Ok(count) => assert_eq!(count, 654470),
 		}
 	}
+
+	#[test]
+	fn from_cookie_file_with_valid_cookie() {
+		let path = std::env::temp_dir().join("lightning-block-sync-rpc-valid-cookie");
+		std::fs::write(&path, "user:password\n").unwrap();
+		let endpoint = HttpEndpoint::for_host("localhost".to_string());
+		let client = RpcClient::from_cookie_file(&path, endpoint).unwrap();
+		assert_eq!(client.basic_auth, "Basic ".to_string() + CREDENTIALS);
+		std::fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn from_cookie_file_with_invalid_cookie() {
+		let path = std::env::temp_dir().join("lightning-block-sync-rpc-invalid-cookie");
+		std::fs::write(&path, "password").unwrap();
+		let endpoint = HttpEndpoint::for_host("localhost".to_string());
+		match RpcClient::from_cookie_file(&path, endpoint) {
+			Err(e) => {
+				assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+				assert_eq!(e.get_ref().unwrap().to_string(), "invalid cookie file");
+			},
+			Ok(_) => panic!("Expected error"),
+		}
+		std::fs::remove_file(&path).unwrap();
+	}
+
+	#[tokio::test]
+	async fn call_method_batch_returning_malformed_response() {
+		let response = serde_json::json!({ "result": 654470 });
+		let server = HttpServer::responding_with_ok(MessageBody::Content(response));
+		let client = RpcClient::new(CREDENTIALS, server.endpoint()).unwrap();
+
+		match client.call_method_batch::<u64>(&[("getblockcount", &[])]).await {
+			Err(e) => {
+				assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+				assert_eq!(e.get_ref().unwrap().to_string(), "expected JSON array");
+			},
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+
+	#[tokio::test]
+	async fn call_method_batch_returning_results_out_of_order() {
+		let response = serde_json::json!([
+			{ "id": "2", "result": null, "error": { "code": -8, "message": "invalid parameter" } },
+			{ "id": "1", "result": "foo" },
+			{ "id": "0", "result": 654470 },
+		]);
+		let server = HttpServer::responding_with_ok(MessageBody::Content(response));
+		let client = RpcClient::new(CREDENTIALS, server.endpoint()).unwrap();
+
+		let calls: &[(&str, &[serde_json::Value])] =
+			&[("getblockcount", &[]), ("getblockcount", &[]), ("getblockcount", &[]), ("getblockcount", &[])];
+		let mut results = client.call_method_batch::<u64>(calls).await.unwrap().into_iter();
+		assert_eq!(results.next().unwrap().unwrap(), 654470);
+		assert_eq!(results.next().unwrap().unwrap_err().get_ref().unwrap().to_string(), "not a number");
+		let rpc_error: Box<RpcError> = results.next().unwrap().unwrap_err().into_inner().unwrap().downcast().unwrap();
+		assert_eq!(rpc_error.code, -8);
+		assert_eq!(results.next().unwrap().unwrap_err().get_ref().unwrap().to_string(), "missing JSON-RPC response");
+		assert!(results.next().is_none());
+	}
+
+	#[tokio::test]
+	async fn fee_estimator_caches_estimates() {
+		let response = serde_json::json!([
+			{ "id": "0", "result": { "feerate": 0.00001, "blocks": 144 } },
+			{ "id": "1", "result": { "errors": ["Insufficient data or no feerate found"], "blocks": 18 } },
+			{ "id": "2", "result": { "feerate": 0.0005, "blocks": 6 } },
+			{ "id": "3", "result": { "feerate": 0.001, "blocks": 3 } },
+			{ "id": "4", "result": { "errors": ["Insufficient data or no feerate found"], "blocks": 18 } },
+			{ "id": "5", "result": { "feerate": 0.00001, "blocks": 1008 } },
+		]);
+		let server = HttpServer::responding_with_ok(MessageBody::Content(response));
+		let client = RpcClient::new(CREDENTIALS, server.endpoint()).unwrap();
+		let fee_estimator = RpcFeeEstimator::new(&client);
+
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::HighPriority), 5000);
+		fee_estimator.update_fee_estimates().await.unwrap();
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::Background), 253);
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::Normal), 2000);
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::HighPriority), 12500);
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::OnChainSweep), 25000);
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::AnchorChannelFee), 2000);
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::ChannelCloseMinimum), 253);
+	}
 }
@@ -1,11 +1,14 @@
 use crate::{AsyncBlockSourceResult, BlockData, BlockHeaderData, BlockSource, BlockSourceError, UnboundedCache};
+use crate::compact_filters::BlockFilterSource;
 use crate::poll::{Validate, ValidatedBlockHeader};
 
 use bitcoin::blockdata::block::{Block, BlockHeader};
 use bitcoin::blockdata::constants::genesis_block;
-use bitcoin::hash_types::BlockHash;
+use bitcoin::hash_types::{BlockHash, FilterHeader};
+use bitcoin::hashes::Hash;
 use bitcoin::network::constants::Network;
 use bitcoin::util::uint::Uint256;
+use bitcoin::util::bip158::{self, BlockFilter};
 use bitcoin::util::hash::bitcoin_merkle_root;
 use bitcoin::{PackedLockTime, Transaction};
 
@@ -185,6 +188,35 @@ impl BlockSource for Blockchain {
 	}
 }
 
+impl BlockFilterSource for Blockchain {
+	fn get_block_filter<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, BlockFilter> {
+		Box::pin(async move {
+			for block in self.blocks.iter() {
+				if block.header.block_hash() == *header_hash {
+					return BlockFilter::new_script_filter(block, |outpoint| Err(bip158::Error::UtxoMissing(*outpoint)))
+						.map_err(|e| BlockSourceError::persistent(e));
+				}
+			}
+			Err(BlockSourceError::transient("block not found"))
+		})
+	}
+
+	fn get_filter_header<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, FilterHeader> {
+		Box::pin(async move {
+			let mut filter_header = FilterHeader::all_zeros();
+			for block in self.blocks.iter() {
+				let filter = BlockFilter::new_script_filter(block, |outpoint| Err(bip158::Error::UtxoMissing(*outpoint)))
+					.map_err(|e| BlockSourceError::persistent(e))?;
+				filter_header = filter.filter_header(&filter_header);
+				if block.header.block_hash() == *header_hash {
+					return Ok(filter_header);
+				}
+			}
+			Err(BlockSourceError::transient("block not found"))
+		})
+	}
+}
+
 pub struct NullChainListener;
 
 impl chain::Listen for NullChainListener {
@@ -6,11 +6,39 @@ pub fn hex_to_uint256(hex: &str) -> Result<Uint256, bitcoin::hashes::hex::Error>
 	Ok(Uint256::from_be_bytes(bytes))
 }
 
+#[cfg(feature = "rpc-client")]
+pub fn base64_encode(data: &[u8]) -> String {
+	const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+	let mut encoded = String::with_capacity((data.len() + 2) / 3 * 4);
+	for chunk in data.chunks(3) {
+		let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+		let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+		for i in 0..4 {
+			if i <= chunk.len() {
+				encoded.push(ALPHABET[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+			} else {
+				encoded.push('=');
+			}
+		}
+	}
+	encoded
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
 	use bitcoin::util::uint::Uint256;
 
+	#[cfg(feature = "rpc-client")]
+	#[test]
+	fn base64_encode_with_padding() {
+		assert_eq!(base64_encode(b""), "");
+		assert_eq!(base64_encode(b"f"), "Zg==");
+		assert_eq!(base64_encode(b"fo"), "Zm8=");
+		assert_eq!(base64_encode(b"foo"), "Zm9v");
+		assert_eq!(base64_encode(b"user:password"), "dXNlcjpwYXNzd29yZA==");
+	}
+
 	#[test]
 	fn hex_to_uint256_empty_str() {
 		assert!(hex_to_uint256("").is_err());
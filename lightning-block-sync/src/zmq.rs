@@ -0,0 +1,507 @@
+//! Real-time block and transaction notifications using Bitcoin Core's ZMQ interface.
+//!
+//! Bitcoin Core may be configured to publish each new block and mempool transaction over ZMQ using
+//! the `-zmqpubrawblock` and `-zmqpubrawtx` options. A [`ZmqChainNotifier`] subscribes to such an
+//! endpoint, allowing an [`SpvClient`] to be polled as soon as a new block arrives rather than
+//! only on a fixed interval. Received blocks are cached by a [`ZmqBlockSource`] so that they need
+//! not be fetched again from the underlying [`BlockSource`], while mempool transactions are handed
+//! to a [`MempoolListener`].
+//!
+//! ZMQ notifications are not reliable: messages may be dropped by Bitcoin Core under load or while
+//! disconnected. Thus, [`ZmqChainNotifier::wait_for_block`] always returns after the configured
+//! poll interval, such that polling the [`SpvClient`] as a fallback still keeps listeners in sync.
+//! For example:
+//!
+//! ```
+//! use lightning::chain;
+//! use lightning_block_sync::{Cache, SpvClient};
+//! use lightning_block_sync::poll::Poll;
+//! use lightning::chain::MempoolListener;
+//! use lightning_block_sync::zmq::ZmqChainNotifier;
+//!
+//! use std::ops::Deref;
+//!
+//! async fn sync_on_notification<P: Poll, C: Cache, L: Deref, M: Deref>(
+//! 	mut spv_client: SpvClient<'_, P, C, L>,
+//! 	mut notifier: ZmqChainNotifier<M>,
+//! ) where L::Target: chain::Listen, M::Target: MempoolListener {
+//! 	loop {
+//! 		notifier.wait_for_block().await;
+//! 		let _ = spv_client.poll_best_tip().await;
+//! 	}
+//! }
+//! ```
+//!
+//! Only the NULL security mechanism of ZMTP 3.0 is supported, which matches Bitcoin Core's ZMQ
+//! configuration.
+//!
+//! [`SpvClient`]: crate::SpvClient
+
+use crate::{AsyncBlockSourceResult, BlockData, BlockHeaderData, BlockSource};
+
+use bitcoin::blockdata::block::Block;
+use bitcoin::blockdata::transaction::Transaction;
+use bitcoin::consensus::encode;
+use bitcoin::hash_types::BlockHash;
+
+use lightning::chain::MempoolListener;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Flag set on all frames of a multi-part message except the last.
+const FRAME_FLAG_MORE: u8 = 0x01;
+
+/// Flag set on frames whose size is encoded in eight rather than one byte.
+const FRAME_FLAG_LONG: u8 = 0x02;
+
+/// Flag set on command frames, as opposed to message frames.
+const FRAME_FLAG_COMMAND: u8 = 0x04;
+
+/// Maximum frame size in bytes. Enough for a serialized block.
+const MAX_FRAME_SIZE: u64 = 4_000_000 + 32_000;
+
+/// Number of recently received blocks kept by a [`ZmqBlockSource`].
+const BLOCK_CACHE_SIZE: usize = 16;
+
+/// Timeout for connecting to and completing the handshake with a ZMQ publisher.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Minimum number of bytes to make room for when reading from the stream.
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// A notification published by Bitcoin Core over ZMQ.
+pub enum ZmqNotification {
+	/// A block newly connected to Bitcoin Core's best chain, published on the `rawblock` topic.
+	Block(Block),
+	/// A transaction newly accepted to Bitcoin Core's mempool or included in a connected block,
+	/// published on the `rawtx` topic.
+	Transaction(Transaction),
+}
+
+/// A minimal ZMQ `SUB` socket subscribed to Bitcoin Core's `rawblock` and `rawtx` topics.
+pub struct ZmqSubscriber {
+	stream: TcpStream,
+	/// Bytes read from the stream but not yet parsed into a complete frame.
+	read_buffer: Vec<u8>,
+	/// Frames of a multi-part message whose last frame has yet to be read.
+	partial_message: Vec<Vec<u8>>,
+}
+
+impl ZmqSubscriber {
+	/// Connects to the ZMQ publisher at the given address, e.g., as configured by
+	/// `-zmqpubrawblock=tcp://127.0.0.1:28332`, and subscribes to block and transaction
+	/// notifications.
+	pub async fn connect(address: SocketAddr) -> std::io::Result<Self> {
+		let stream = TcpStream::connect(address);
+		let stream = match tokio::time::timeout(HANDSHAKE_TIMEOUT, stream).await {
+			Ok(stream) => stream?,
+			Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "connect timed out")),
+		};
+		let mut subscriber = Self::from_stream(stream);
+		match tokio::time::timeout(HANDSHAKE_TIMEOUT, subscriber.handshake()).await {
+			Ok(result) => result?,
+			Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "handshake timed out")),
+		}
+		Ok(subscriber)
+	}
+
+	fn from_stream(stream: TcpStream) -> Self {
+		Self { stream, read_buffer: Vec::new(), partial_message: Vec::new() }
+	}
+
+	/// Exchanges ZMTP greetings and `READY` commands, then subscribes to the relevant topics.
+	async fn handshake(&mut self) -> std::io::Result<()> {
+		let mut greeting = [0; 64];
+		greeting[0] = 0xff;
+		greeting[9] = 0x7f;
+		greeting[10] = 3;
+		greeting[11] = 0;
+		greeting[12..16].copy_from_slice(b"NULL");
+		self.stream.write_all(&greeting).await?;
+
+		let mut peer_greeting = [0; 64];
+		self.stream.read_exact(&mut peer_greeting).await?;
+		if peer_greeting[0] != 0xff || peer_greeting[9] != 0x7f || peer_greeting[10] < 3 {
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "unsupported ZMTP version"));
+		}
+		if peer_greeting[12..32] != greeting[12..32] {
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "unsupported security mechanism"));
+		}
+
+		let mut ready = Vec::new();
+		ready.push(5);
+		ready.extend_from_slice(b"READY");
+		ready.push(11);
+		ready.extend_from_slice(b"Socket-Type");
+		ready.extend_from_slice(&3u32.to_be_bytes());
+		ready.extend_from_slice(b"SUB");
+		self.write_frame(FRAME_FLAG_COMMAND, &ready).await?;
+
+		let (flags, command) = self.read_frame().await?;
+		if flags & FRAME_FLAG_COMMAND == 0 || command.get(1..6) != Some(&b"READY"[..]) {
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected READY command"));
+		}
+
+		for topic in [&b"rawblock"[..], &b"rawtx"[..]].iter() {
+			let mut subscription = vec![1];
+			subscription.extend_from_slice(topic);
+			self.write_frame(0, &subscription).await?;
+		}
+		self.stream.flush().await
+	}
+
+	/// Waits for the next block or transaction notification, skipping any other topics.
+	///
+	/// Once an `Err` is returned, the connection should be considered broken and a new subscriber
+	/// created.
+	///
+	/// This method is cancel-safe: partially received messages are kept by the subscriber, so it
+	/// may be used with `tokio::time::timeout` or in `tokio::select!` without losing data.
+	pub async fn recv(&mut self) -> std::io::Result<ZmqNotification> {
+		loop {
+			let message = self.read_message().await?;
+			if message.len() < 2 {
+				continue;
+			}
+			match &message[0][..] {
+				b"rawblock" => return Ok(ZmqNotification::Block(Self::deserialize(&message[1])?)),
+				b"rawtx" => return Ok(ZmqNotification::Transaction(Self::deserialize(&message[1])?)),
+				_ => continue,
+			}
+		}
+	}
+
+	fn deserialize<T: encode::Decodable>(data: &[u8]) -> std::io::Result<T> {
+		encode::deserialize(data)
+			.map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid notification"))
+	}
+
+	/// Reads all frames of the next message, ignoring any interleaved commands.
+	///
+	/// Cancel-safe, as frames read so far are kept in `partial_message`.
+	async fn read_message(&mut self) -> std::io::Result<Vec<Vec<u8>>> {
+		loop {
+			let (flags, body) = self.read_frame().await?;
+			if flags & FRAME_FLAG_COMMAND != 0 {
+				continue;
+			}
+			self.partial_message.push(body);
+			if flags & FRAME_FLAG_MORE == 0 {
+				return Ok(core::mem::take(&mut self.partial_message));
+			}
+		}
+	}
+
+	/// Reads the next frame.
+	///
+	/// Cancel-safe, as bytes are only ever read into `read_buffer`, which is consumed once a
+	/// complete frame is available.
+	async fn read_frame(&mut self) -> std::io::Result<(u8, Vec<u8>)> {
+		loop {
+			if let Some(frame) = self.parse_frame()? {
+				return Ok(frame);
+			}
+
+			self.read_buffer.reserve(READ_CHUNK_SIZE);
+			if self.stream.read_buf(&mut self.read_buffer).await? == 0 {
+				return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "connection closed"));
+			}
+		}
+	}
+
+	/// Removes a complete frame from the front of `read_buffer`, if any.
+	fn parse_frame(&mut self) -> std::io::Result<Option<(u8, Vec<u8>)>> {
+		let flags = match self.read_buffer.get(0) {
+			Some(flags) => *flags,
+			None => return Ok(None),
+		};
+		let (header_len, size) = if flags & FRAME_FLAG_LONG != 0 {
+			match self.read_buffer.get(1..9) {
+				Some(size) => {
+					let mut size_bytes = [0; 8];
+					size_bytes.copy_from_slice(size);
+					(9, u64::from_be_bytes(size_bytes))
+				},
+				None => return Ok(None),
+			}
+		} else {
+			match self.read_buffer.get(1) {
+				Some(size) => (2, *size as u64),
+				None => return Ok(None),
+			}
+		};
+		if size > MAX_FRAME_SIZE {
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "frame too large"));
+		}
+
+		let frame_len = header_len + size as usize;
+		if self.read_buffer.len() < frame_len {
+			return Ok(None);
+		}
+		let body = self.read_buffer[header_len..frame_len].to_vec();
+		self.read_buffer.drain(..frame_len);
+		Ok(Some((flags, body)))
+	}
+
+	async fn write_frame(&mut self, flags: u8, body: &[u8]) -> std::io::Result<()> {
+		if body.len() > u8::max_value() as usize {
+			self.stream.write_u8(flags | FRAME_FLAG_LONG).await?;
+			self.stream.write_u64(body.len() as u64).await?;
+		} else {
+			self.stream.write_u8(flags).await?;
+			self.stream.write_u8(body.len() as u8).await?;
+		}
+		self.stream.write_all(body).await
+	}
+}
+
+/// A [`BlockSource`] which serves recently received ZMQ blocks from memory, delegating to an
+/// underlying source otherwise.
+///
+/// Headers and the best block are always fetched from the underlying source, as ZMQ notifications
+/// lack the block height and chainwork.
+pub struct ZmqBlockSource<B: BlockSource> {
+	source: B,
+	blocks: Arc<Mutex<VecDeque<Block>>>,
+}
+
+impl<B: BlockSource> ZmqBlockSource<B> {
+	/// Creates a block source which serves blocks received by `notifier` before delegating to
+	/// `source`.
+	pub fn new<M: Deref>(source: B, notifier: &ZmqChainNotifier<M>) -> Self
+	where M::Target: MempoolListener {
+		Self { source, blocks: Arc::clone(&notifier.blocks) }
+	}
+}
+
+impl<B: BlockSource> BlockSource for ZmqBlockSource<B> {
+	fn get_header<'a>(&'a self, header_hash: &'a BlockHash, height_hint: Option<u32>) -> AsyncBlockSourceResult<'a, BlockHeaderData> {
+		self.source.get_header(header_hash, height_hint)
+	}
+
+	fn get_block<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, BlockData> {
+		let cached_block = self.blocks.lock().unwrap().iter()
+			.find(|block| block.block_hash() == *header_hash)
+			.cloned();
+		match cached_block {
+			Some(block) => Box::pin(async move { Ok(BlockData::FullBlock(block)) }),
+			None => self.source.get_block(header_hash),
+		}
+	}
+
+	fn get_best_block<'a>(&'a self) -> AsyncBlockSourceResult<(BlockHash, Option<u32>)> {
+		self.source.get_best_block()
+	}
+}
+
+/// Waits on ZMQ notifications from Bitcoin Core, signaling when the chain should be polled.
+///
+/// Reconnects to the publisher as needed, falling back to polling on the configured interval while
+/// disconnected.
+pub struct ZmqChainNotifier<M: Deref> where M::Target: MempoolListener {
+	address: SocketAddr,
+	subscriber: Option<ZmqSubscriber>,
+	mempool_listener: M,
+	poll_interval: Duration,
+	blocks: Arc<Mutex<VecDeque<Block>>>,
+}
+
+impl<M: Deref> ZmqChainNotifier<M> where M::Target: MempoolListener {
+	/// Creates a notifier for the ZMQ publisher at `address`, which should publish on both the
+	/// `rawblock` and `rawtx` topics. No connection is made until
+	/// [`ZmqChainNotifier::wait_for_block`] is called.
+	///
+	/// `poll_interval` is the longest time to wait for a block notification before the chain
+	/// should be polled regardless.
+	pub fn new(address: SocketAddr, mempool_listener: M, poll_interval: Duration) -> Self {
+		Self {
+			address,
+			subscriber: None,
+			mempool_listener,
+			poll_interval,
+			blocks: Arc::new(Mutex::new(VecDeque::new())),
+		}
+	}
+
+	/// Waits until either a block notification is received or the poll interval elapses, passing
+	/// any transactions received in the meantime to the [`MempoolListener`].
+	///
+	/// Either way, the chain should be polled upon return, e.g., using [`SpvClient::poll_best_tip`]
+	/// with a chain poller built on a [`ZmqBlockSource`] for this notifier.
+	///
+	/// [`SpvClient::poll_best_tip`]: crate::SpvClient::poll_best_tip
+	pub async fn wait_for_block(&mut self) {
+		let deadline = tokio::time::Instant::now() + self.poll_interval;
+		let _ = tokio::time::timeout_at(deadline, self.recv_until_block()).await;
+	}
+
+	async fn recv_until_block(&mut self) {
+		loop {
+			if self.subscriber.is_none() {
+				match ZmqSubscriber::connect(self.address).await {
+					Ok(subscriber) => self.subscriber = Some(subscriber),
+					Err(_) => {
+						// Wait out the remaining poll interval, which is enforced by the caller.
+						tokio::time::sleep(self.poll_interval).await;
+						return;
+					},
+				}
+			}
+
+			let notification = match self.subscriber.as_mut() {
+				Some(subscriber) => subscriber.recv().await,
+				None => return,
+			};
+			match notification {
+				Ok(ZmqNotification::Block(block)) => {
+					let mut blocks = self.blocks.lock().unwrap();
+					if blocks.len() == BLOCK_CACHE_SIZE {
+						blocks.pop_front();
+					}
+					blocks.push_back(block);
+					return;
+				},
+				Ok(ZmqNotification::Transaction(tx)) => {
+					self.mempool_listener.transaction_seen_in_mempool(&tx);
+				},
+				Err(_) => {
+					self.subscriber = None;
+					// Poll immediately since a block may have been missed while disconnected.
+					return;
+				},
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_utils::Blockchain;
+
+	use bitcoin::blockdata::locktime::PackedLockTime;
+
+	use tokio::net::TcpListener;
+
+	use std::cell::RefCell;
+
+	/// Accepts a single subscriber, returning once it has subscribed to both topics.
+	async fn accept(listener: TcpListener) -> ZmqSubscriber {
+		let (stream, _) = listener.accept().await.unwrap();
+		let mut publisher = ZmqSubscriber::from_stream(stream);
+
+		let mut greeting = [0; 64];
+		publisher.stream.read_exact(&mut greeting).await.unwrap();
+		publisher.stream.write_all(&greeting).await.unwrap();
+
+		let (flags, _) = publisher.read_frame().await.unwrap();
+		assert_ne!(flags & FRAME_FLAG_COMMAND, 0);
+		let mut ready = vec![5];
+		ready.extend_from_slice(b"READY");
+		publisher.write_frame(FRAME_FLAG_COMMAND, &ready).await.unwrap();
+
+		assert_eq!(publisher.read_message().await.unwrap(), vec![b"\x01rawblock".to_vec()]);
+		assert_eq!(publisher.read_message().await.unwrap(), vec![b"\x01rawtx".to_vec()]);
+		publisher
+	}
+
+	/// Accepts a single subscriber, publishing the given messages once it has subscribed to both
+	/// topics.
+	async fn publish(listener: TcpListener, messages: Vec<Vec<Vec<u8>>>) {
+		let mut publisher = accept(listener).await;
+		for message in messages {
+			let (last_frame, frames) = message.split_last().unwrap();
+			for frame in frames {
+				publisher.write_frame(FRAME_FLAG_MORE, frame).await.unwrap();
+			}
+			publisher.write_frame(0, last_frame).await.unwrap();
+		}
+		publisher.stream.flush().await.unwrap();
+	}
+
+	struct TestMempoolListener(RefCell<Vec<Transaction>>);
+
+	impl MempoolListener for TestMempoolListener {
+		fn transaction_seen_in_mempool(&self, tx: &Transaction) {
+			self.0.borrow_mut().push(tx.clone());
+		}
+	}
+
+	#[tokio::test]
+	async fn receive_notifications() {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let address = listener.local_addr().unwrap();
+
+		let chain = Blockchain::default().with_height(1);
+		let block = chain.blocks[1].clone();
+		let tx = Transaction { version: 2, lock_time: PackedLockTime::ZERO, input: vec![], output: vec![] };
+		let messages = vec![
+			vec![b"hashblock".to_vec(), vec![0; 32], vec![0; 4]],
+			vec![b"rawtx".to_vec(), encode::serialize(&tx), vec![0; 4]],
+			vec![b"rawblock".to_vec(), encode::serialize(&block), vec![0; 4]],
+		];
+		tokio::spawn(publish(listener, messages));
+
+		let mempool_listener = TestMempoolListener(RefCell::new(Vec::new()));
+		let mut notifier = ZmqChainNotifier::new(address, &mempool_listener, Duration::from_secs(5));
+		let block_source = ZmqBlockSource::new(Blockchain::default(), &notifier);
+		notifier.wait_for_block().await;
+		assert_eq!(*mempool_listener.0.borrow(), vec![tx]);
+
+		// The block is served from the cache despite the underlying source lacking it.
+		match block_source.get_block(&block.block_hash()).await {
+			Ok(BlockData::FullBlock(cached_block)) => assert_eq!(cached_block, block),
+			_ => panic!("Expected cached block"),
+		}
+	}
+
+	#[tokio::test]
+	async fn recv_is_cancel_safe() {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let address = listener.local_addr().unwrap();
+		let publisher = tokio::spawn(accept(listener));
+		let mut subscriber = ZmqSubscriber::connect(address).await.unwrap();
+		let mut publisher = publisher.await.unwrap();
+
+		let chain = Blockchain::default().with_height(1);
+		let block = chain.blocks[1].clone();
+		let serialized_block = encode::serialize(&block);
+		let (first_half, second_half) = serialized_block.split_at(serialized_block.len() / 2);
+
+		// Publish the block frame only partially, such that receiving times out mid-frame.
+		publisher.write_frame(FRAME_FLAG_MORE, b"rawblock").await.unwrap();
+		publisher.stream.write_u8(FRAME_FLAG_MORE | FRAME_FLAG_LONG).await.unwrap();
+		publisher.stream.write_u64(serialized_block.len() as u64).await.unwrap();
+		publisher.stream.write_all(first_half).await.unwrap();
+		publisher.stream.flush().await.unwrap();
+		let result = tokio::time::timeout(Duration::from_millis(10), subscriber.recv()).await;
+		assert!(result.is_err());
+
+		publisher.stream.write_all(second_half).await.unwrap();
+		publisher.write_frame(0, &[0; 4]).await.unwrap();
+		publisher.stream.flush().await.unwrap();
+		match subscriber.recv().await {
+			Ok(ZmqNotification::Block(received_block)) => assert_eq!(received_block, block),
+			_ => panic!("Expected block notification"),
+		}
+	}
+
+	#[tokio::test]
+	async fn wait_for_block_without_publisher() {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let address = listener.local_addr().unwrap();
+		drop(listener);
+
+		let mempool_listener = TestMempoolListener(RefCell::new(Vec::new()));
+		let mut notifier = ZmqChainNotifier::new(address, &mempool_listener, Duration::from_millis(10));
+		notifier.wait_for_block().await;
+		assert!(notifier.subscriber.is_none());
+	}
+}
@@ -21,13 +21,17 @@
 //!   * For parsing use `str::parse::<Invoice>(&self)` (see [`Invoice::from_str`])
 //!   * For constructing invoices use the [`InvoiceBuilder`]
 //!   * For serializing invoices use the [`Display`]/[`ToString`] traits
+//!   * For parsing a string which may be any of a BOLT11 invoice, a BOLT12 offer or refund, or a
+//!     BIP21 URI use [`PaymentInstruction`]
 //!
 //! [`Invoice::from_str`]: crate::Invoice#impl-FromStr
+//! [`PaymentInstruction`]: crate::payment_instruction::PaymentInstruction
 
 #[cfg(not(any(feature = "std", feature = "no-std")))]
 compile_error!("at least one of the `std` or `no-std` features must be enabled");
 
 pub mod payment;
+pub mod payment_instruction;
 pub mod utils;
 
 pub(crate) mod time_utils;
@@ -55,6 +59,7 @@ use lightning::ln::features::InvoiceFeatures;
 #[cfg(any(doc, test))]
 use lightning::routing::gossip::RoutingFees;
 use lightning::routing::router::RouteHint;
+use lightning::util::clock::TimeProvider;
 use lightning::util::invoice::construct_invoice_preimage;
 
 use secp256k1::PublicKey;
@@ -1381,6 +1386,17 @@ impl Invoice {
 			.unwrap_or(Duration::from_nanos(0))
 	}
 
+	/// Returns whether the invoice has expired, according to the given [`TimeProvider`].
+	pub fn is_expired_with_time<T: Deref>(&self, time_provider: T) -> bool where T::Target: TimeProvider {
+		self.would_expire(time_provider.duration_since_epoch())
+	}
+
+	/// Returns the Duration remaining until the invoice expires, according to the given
+	/// [`TimeProvider`].
+	pub fn duration_until_expiry_with_time<T: Deref>(&self, time_provider: T) -> Duration where T::Target: TimeProvider {
+		self.expiration_remaining_from_epoch(time_provider.duration_since_epoch())
+	}
+
 	/// Returns the Duration remaining until the invoice expires given the current time.
 	/// `time` is the timestamp as a duration since the Unix epoch.
 	pub fn expiration_remaining_from_epoch(&self, time: Duration) -> Duration {
@@ -1632,6 +1648,11 @@ pub enum CreationError {
 	///
 	/// [`MIN_FINAL_CLTV_EXPIRY_DELTA`]: lightning::ln::channelmanager::MIN_FINAL_CLTV_EXPIRY_DELTA
 	MinFinalCltvExpiryDeltaTooShort,
+
+	/// The provided `invoice_expiry_delta_secs` was less than [`MIN_INVOICE_EXPIRY_DELTA_SECS`].
+	///
+	/// [`MIN_INVOICE_EXPIRY_DELTA_SECS`]: crate::utils::MIN_INVOICE_EXPIRY_DELTA_SECS
+	ExpiryTooShort,
 }
 
 impl Display for CreationError {
@@ -1644,6 +1665,8 @@ impl Display for CreationError {
 			CreationError::MissingRouteHints => f.write_str("The invoice required route hints and they weren't provided"),
 			CreationError::MinFinalCltvExpiryDeltaTooShort => f.write_str(
 				"The supplied final CLTV expiry delta was less than LDK's `MIN_FINAL_CLTV_EXPIRY_DELTA`"),
+			CreationError::ExpiryTooShort => f.write_str(
+				"The supplied invoice expiry delta was less than `MIN_INVOICE_EXPIRY_DELTA_SECS`"),
 		}
 	}
 }
@@ -2205,6 +2228,16 @@ mod test {
 		let invoice = Invoice::from_signed(signed_invoice).unwrap();
 
 		assert!(invoice.would_expire(Duration::from_secs(1234567 + DEFAULT_EXPIRY_TIME + 1)));
+
+		struct FixedTime(Duration);
+		impl TimeProvider for FixedTime {
+			fn duration_since_epoch(&self) -> Duration { self.0 }
+		}
+		let expiry = Duration::from_secs(1234567 + DEFAULT_EXPIRY_TIME);
+		assert!(!invoice.is_expired_with_time(&FixedTime(expiry)));
+		assert!(invoice.is_expired_with_time(&FixedTime(expiry + Duration::from_secs(1))));
+		assert_eq!(invoice.duration_until_expiry_with_time(&FixedTime(expiry - Duration::from_secs(10))),
+			Duration::from_secs(10));
 	}
 
 	#[cfg(feature = "serde")]
@@ -10,16 +10,23 @@
 //! Convenient utilities for paying Lightning invoices and sending spontaneous payments.
 
 use crate::Invoice;
+use crate::prelude::*;
+use crate::sync::Mutex;
 
-use bitcoin_hashes::Hash;
+use bitcoin_hashes::{Hash, HashEngine};
+use bitcoin_hashes::sha256::Hash as Sha256;
 
 use lightning::chain;
 use lightning::chain::chaininterface::{BroadcasterInterface, FeeEstimator};
+use lightning::io;
 use lightning::sign::{NodeSigner, SignerProvider, EntropySource};
 use lightning::ln::PaymentHash;
-use lightning::ln::channelmanager::{ChannelManager, PaymentId, Retry, RetryableSendFailure, RecipientOnionFields};
+use lightning::ln::msgs::DecodeError;
+use lightning::ln::channelmanager::{BatchPayment, BatchPaymentError, BatchPaymentId, ChannelManager, PaymentId, Retry, RetryableSendFailure, RecipientOnionFields};
 use lightning::routing::router::{PaymentParameters, RouteParameters, Router};
 use lightning::util::logger::Logger;
+use lightning::util::persist::{KVStorePersister, KVStoreReader};
+use lightning::util::ser::{Readable, Writeable, WithoutLength, Writer};
 
 use core::fmt::Debug;
 use core::ops::Deref;
@@ -140,10 +147,172 @@ where
 	}
 }
 
+/// Pays the given [`Invoice`] at most once per caller-supplied `idempotency_key`, retrying if
+/// needed based on [`Retry`].
+///
+/// The [`PaymentId`] is derived from the `idempotency_key` and the invoice's payment hash, and the
+/// first call for a given key persists its mapping via `idempotency_keys` before sending the
+/// payment. Any later call with the same key, including after a restart, returns the same
+/// [`PaymentId`] without sending anything, so that retrying an application-level request after a
+/// crash can't pay the invoice twice. Use [`ChannelManager::list_recent_payments`] or
+/// [`ChannelManager::resolved_payment_status`] to learn what became of the payment.
+///
+/// If the payment could not be sent at all, the key is forgotten, allowing the request to be
+/// retried. Fails with [`PaymentError::Invoice`] if the key was used for a different invoice, or
+/// with [`IdempotentPaymentError::Persistence`] if the key could not be persisted, in which case
+/// nothing was sent.
+pub fn pay_invoice_with_idempotency_key<M: Deref, T: Deref, ES: Deref, NS: Deref, SP: Deref, F: Deref, R: Deref, L: Deref, K: Deref>(
+	invoice: &Invoice, idempotency_key: &[u8], retry_strategy: Retry,
+	channelmanager: &ChannelManager<M, T, ES, NS, SP, F, R, L>,
+	idempotency_keys: &PaymentIdempotencyKeys<K>
+) -> Result<PaymentId, IdempotentPaymentError>
+where
+		M::Target: chain::Watch<<SP::Target as SignerProvider>::Signer>,
+		T::Target: BroadcasterInterface,
+		ES::Target: EntropySource,
+		NS::Target: NodeSigner,
+		SP::Target: SignerProvider,
+		F::Target: FeeEstimator,
+		R::Target: Router,
+		L::Target: Logger,
+		K::Target: KVStorePersister,
+{
+	let amt_msat = invoice.amount_milli_satoshis().ok_or(PaymentError::Invoice("amount missing"))
+		.map_err(IdempotentPaymentError::Payment)?;
+	pay_invoice_using_idempotency_key(invoice, amt_msat, idempotency_key, retry_strategy,
+		channelmanager, idempotency_keys)
+}
+
+/// Pays the given zero-value [`Invoice`] using the given amount at most once per caller-supplied
+/// `idempotency_key`, retrying if needed based on [`Retry`].
+///
+/// See [`pay_invoice_with_idempotency_key`] for the idempotency guarantees provided.
+pub fn pay_zero_value_invoice_with_idempotency_key<M: Deref, T: Deref, ES: Deref, NS: Deref, SP: Deref, F: Deref, R: Deref, L: Deref, K: Deref>(
+	invoice: &Invoice, amount_msats: u64, idempotency_key: &[u8], retry_strategy: Retry,
+	channelmanager: &ChannelManager<M, T, ES, NS, SP, F, R, L>,
+	idempotency_keys: &PaymentIdempotencyKeys<K>
+) -> Result<PaymentId, IdempotentPaymentError>
+where
+		M::Target: chain::Watch<<SP::Target as SignerProvider>::Signer>,
+		T::Target: BroadcasterInterface,
+		ES::Target: EntropySource,
+		NS::Target: NodeSigner,
+		SP::Target: SignerProvider,
+		F::Target: FeeEstimator,
+		R::Target: Router,
+		L::Target: Logger,
+		K::Target: KVStorePersister,
+{
+	if invoice.amount_milli_satoshis().is_some() {
+		Err(IdempotentPaymentError::Payment(PaymentError::Invoice("amount unexpected")))
+	} else {
+		pay_invoice_using_idempotency_key(invoice, amount_msats, idempotency_key, retry_strategy,
+			channelmanager, idempotency_keys)
+	}
+}
+
+/// Pays all of the given [`Invoice`]s as a single batch with a shared routing fee budget, retrying
+/// each payment if needed based on [`Retry`].
+///
+/// Each invoice is given along with the amount to pay it, which must be set if and only if the
+/// invoice is zero-value. Routes for all invoices are found together, splitting payments over
+/// multiple paths where needed, and none are paid if their combined amount exceeds our outbound
+/// capacity or if the routes found would pay more than `max_total_routing_fee_msat` in fees, see
+/// [`ChannelManager::send_batch_payments_to_hashes`].
+///
+/// Otherwise, returns the id of the batch along with the result of sending each payment, in the
+/// order given. The outcome of the batch as a whole, i.e., which invoices were paid, is reported
+/// via an [`Event::BatchPaymentCompleted`].
+///
+/// [`Event::BatchPaymentCompleted`]: lightning::events::Event::BatchPaymentCompleted
+pub fn pay_invoices<M: Deref, T: Deref, ES: Deref, NS: Deref, SP: Deref, F: Deref, R: Deref, L: Deref>(
+	invoices: &[(&Invoice, Option<u64>)], max_total_routing_fee_msat: Option<u64>,
+	retry_strategy: Retry, channelmanager: &ChannelManager<M, T, ES, NS, SP, F, R, L>
+) -> Result<(BatchPaymentId, Vec<Result<PaymentId, RetryableSendFailure>>), PaymentError>
+where
+		M::Target: chain::Watch<<SP::Target as SignerProvider>::Signer>,
+		T::Target: BroadcasterInterface,
+		ES::Target: EntropySource,
+		NS::Target: NodeSigner,
+		SP::Target: SignerProvider,
+		F::Target: FeeEstimator,
+		R::Target: Router,
+		L::Target: Logger,
+{
+	pay_invoices_using_amounts(invoices, max_total_routing_fee_msat, retry_strategy, channelmanager)
+}
+
+fn pay_invoices_using_amounts<P: Deref>(
+	invoices: &[(&Invoice, Option<u64>)], max_total_routing_fee_msat: Option<u64>,
+	retry_strategy: Retry, payer: P
+) -> Result<(BatchPaymentId, Vec<Result<PaymentId, RetryableSendFailure>>), PaymentError>
+where P::Target: Payer {
+	let mut payments = Vec::with_capacity(invoices.len());
+	for (invoice, amount_msats) in invoices.iter() {
+		let amount_msats = match (invoice.amount_milli_satoshis(), amount_msats) {
+			(Some(amount_msats), None) => amount_msats,
+			(None, Some(amount_msats)) => *amount_msats,
+			(None, None) => return Err(PaymentError::Invoice("amount missing")),
+			(Some(_), Some(_)) => return Err(PaymentError::Invoice("amount unexpected")),
+		};
+		let (payment_hash, recipient_onion, route_params) = payment_for_invoice(invoice, amount_msats);
+		payments.push(BatchPayment { payment_hash, recipient_onion, route_params });
+	}
+
+	payer.send_batch_payments(payments, max_total_routing_fee_msat, retry_strategy)
+}
+
+fn pay_invoice_using_idempotency_key<P: Deref, K: Deref>(
+	invoice: &Invoice, amount_msats: u64, idempotency_key: &[u8], retry_strategy: Retry,
+	payer: P, idempotency_keys: &PaymentIdempotencyKeys<K>
+) -> Result<PaymentId, IdempotentPaymentError> where P::Target: Payer, K::Target: KVStorePersister {
+	let payment_hash = PaymentHash((*invoice.payment_hash()).into_inner());
+	let payment_id = {
+		let mut payments = idempotency_keys.payments.lock().unwrap();
+		if let Some((payment_id, paid_hash)) = payments.get(idempotency_key) {
+			if *paid_hash != payment_hash {
+				return Err(IdempotentPaymentError::Payment(
+					PaymentError::Invoice("idempotency key used for a different invoice")));
+			}
+			return Ok(*payment_id);
+		}
+		// Even if the mapping is lost, e.g., because the key was removed while the payment was still
+		// pending, the same key and invoice always result in the same PaymentId, which the
+		// ChannelManager refuses to pay twice while it is pending.
+		let mut engine = Sha256::engine();
+		engine.input(idempotency_key);
+		engine.input(&payment_hash.0);
+		let payment_id = PaymentId(Sha256::from_engine(engine).into_inner());
+		let entry = IdempotencyKeyEntry {
+			idempotency_key: idempotency_key.to_vec(), payment_id, payment_hash,
+		};
+		idempotency_keys.persister.persist(&store_key(idempotency_key), &entry)
+			.map_err(|e| IdempotentPaymentError::Persistence(e.kind()))?;
+		payments.insert(idempotency_key.to_vec(), (payment_id, payment_hash));
+		payment_id
+	};
+
+	match pay_invoice_using_amount(invoice, amount_msats, payment_id, retry_strategy, payer) {
+		Ok(()) | Err(PaymentError::Sending(RetryableSendFailure::DuplicatePayment)) => Ok(payment_id),
+		Err(e) => {
+			// Nothing was sent, so there's nothing to be idempotent about.
+			let _ = idempotency_keys.remove_key(idempotency_key);
+			Err(IdempotentPaymentError::Payment(e))
+		},
+	}
+}
+
 fn pay_invoice_using_amount<P: Deref>(
 	invoice: &Invoice, amount_msats: u64, payment_id: PaymentId, retry_strategy: Retry,
 	payer: P
 ) -> Result<(), PaymentError> where P::Target: Payer {
+	let (payment_hash, recipient_onion, route_params) = payment_for_invoice(invoice, amount_msats);
+	payer.send_payment(payment_hash, recipient_onion, payment_id, route_params, retry_strategy)
+}
+
+fn payment_for_invoice(
+	invoice: &Invoice, amount_msats: u64
+) -> (PaymentHash, RecipientOnionFields, RouteParameters) {
 	let payment_hash = PaymentHash((*invoice.payment_hash()).into_inner());
 	let recipient_onion = RecipientOnionFields {
 		payment_secret: Some(*invoice.payment_secret()),
@@ -160,8 +329,7 @@ fn pay_invoice_using_amount<P: Deref>(
 		payment_params,
 		final_value_msat: amount_msats,
 	};
-
-	payer.send_payment(payment_hash, recipient_onion, payment_id, route_params, retry_strategy)
+	(payment_hash, recipient_onion, route_params)
 }
 
 fn expiry_time_from_unix_epoch(invoice: &Invoice) -> Duration {
@@ -175,6 +343,116 @@ pub enum PaymentError {
 	Invoice(&'static str),
 	/// An error occurring when sending a payment.
 	Sending(RetryableSendFailure),
+	/// An error occurring when sending a batch of payments via [`pay_invoices`], in which case none
+	/// of them were sent.
+	Batch(BatchPaymentError),
+}
+
+/// An error that may occur when making a payment via [`pay_invoice_with_idempotency_key`] or
+/// [`pay_zero_value_invoice_with_idempotency_key`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IdempotentPaymentError {
+	/// An error occurring when making the payment, see [`PaymentError`].
+	Payment(PaymentError),
+	/// An error occurring when persisting the idempotency key, in which case no payment was sent.
+	Persistence(io::ErrorKind),
+}
+
+/// The prefix of the keys at which [`PaymentIdempotencyKeys`] are persisted via a
+/// [`KVStorePersister`], one per idempotency key.
+pub const PAYMENT_IDEMPOTENCY_KEYS_PREFIX: &str = "payment_idempotency_keys/";
+
+/// Returns the store key for the given idempotency key, which is hashed such that arbitrary keys
+/// result in store keys of a fixed length and only made of hex characters.
+fn store_key(idempotency_key: &[u8]) -> String {
+	let mut key = PAYMENT_IDEMPOTENCY_KEYS_PREFIX.to_string();
+	for byte in Sha256::hash(idempotency_key).into_inner().iter() {
+		key.push_str(&format!("{:02x}", byte));
+	}
+	key
+}
+
+/// A mapping of caller-supplied idempotency keys to the [`PaymentId`]s of the invoice payments
+/// they were used for, each persisted at its own key starting with
+/// [`PAYMENT_IDEMPOTENCY_KEYS_PREFIX`] when it is added or removed.
+///
+/// Unlike a [`PaymentId`], whose idempotency the [`ChannelManager`] only ensures as long as the
+/// payment is pending or recently resolved, a key is remembered until it is removed via
+/// [`PaymentIdempotencyKeys::remove_key`].
+///
+/// See [`pay_invoice_with_idempotency_key`] for how keys are used.
+pub struct PaymentIdempotencyKeys<K: Deref> where K::Target: KVStorePersister {
+	persister: K,
+	payments: Mutex<HashMap<Vec<u8>, (PaymentId, PaymentHash)>>,
+}
+
+impl<K: Deref> PaymentIdempotencyKeys<K> where K::Target: KVStorePersister {
+	/// Creates an empty mapping, persisted via the given `persister`.
+	pub fn new(persister: K) -> Self {
+		Self { persister, payments: Mutex::new(HashMap::new()) }
+	}
+
+	/// Returns the [`PaymentId`] the given `idempotency_key` was used for, if any.
+	pub fn payment_id(&self, idempotency_key: &[u8]) -> Option<PaymentId> {
+		self.payments.lock().unwrap().get(idempotency_key).map(|(payment_id, _)| *payment_id)
+	}
+
+	/// Forgets the given `idempotency_key`, e.g. once the application request it was used for has
+	/// been completed, such that it may be reused for a new payment.
+	///
+	/// As a [`KVStorePersister`] can't remove objects, an empty object is persisted in its place.
+	pub fn remove_key(&self, idempotency_key: &[u8]) -> Result<(), io::Error> {
+		let mut payments = self.payments.lock().unwrap();
+		if payments.contains_key(idempotency_key) {
+			self.persister.persist(&store_key(idempotency_key), &WithoutLength(&Vec::<u8>::new()))?;
+			payments.remove(idempotency_key);
+		}
+		Ok(())
+	}
+}
+
+impl<K: Deref> PaymentIdempotencyKeys<K> where K::Target: KVStorePersister + KVStoreReader {
+	/// Reads the mapping stored at the keys starting with [`PAYMENT_IDEMPOTENCY_KEYS_PREFIX`] in the
+	/// given `store`, which it is then persisted to, returning an empty mapping if none was stored.
+	pub fn read_from_store(store: K) -> Result<Self, io::Error> {
+		let mut payments = HashMap::new();
+		for key in store.list(PAYMENT_IDEMPOTENCY_KEYS_PREFIX)? {
+			let bytes = match store.read(&key)? {
+				Some(bytes) => bytes,
+				None => continue,
+			};
+			// Removed keys are persisted as empty objects.
+			if bytes.is_empty() { continue; }
+			let entry: IdempotencyKeyEntry = Readable::read(&mut &bytes[..])
+				.map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid payment idempotency key"))?;
+			payments.insert(entry.idempotency_key, (entry.payment_id, entry.payment_hash));
+		}
+		Ok(Self { persister: store, payments: Mutex::new(payments) })
+	}
+}
+
+struct IdempotencyKeyEntry {
+	idempotency_key: Vec<u8>,
+	payment_id: PaymentId,
+	payment_hash: PaymentHash,
+}
+
+impl Writeable for IdempotencyKeyEntry {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		self.idempotency_key.write(w)?;
+		self.payment_id.write(w)?;
+		self.payment_hash.write(w)
+	}
+}
+
+impl Readable for IdempotencyKeyEntry {
+	fn read<R: io::Read>(r: &mut R) -> Result<Self, DecodeError> {
+		Ok(Self {
+			idempotency_key: Readable::read(r)?,
+			payment_id: Readable::read(r)?,
+			payment_hash: Readable::read(r)?,
+		})
+	}
 }
 
 /// A trait defining behavior of an [`Invoice`] payer.
@@ -188,6 +466,12 @@ trait Payer {
 		&self, payment_hash: PaymentHash, recipient_onion: RecipientOnionFields,
 		payment_id: PaymentId, route_params: RouteParameters, retry_strategy: Retry
 	) -> Result<(), PaymentError>;
+
+	/// Sends the given payments as a batch, with a shared routing fee budget.
+	fn send_batch_payments(
+		&self, payments: Vec<BatchPayment>, max_total_routing_fee_msat: Option<u64>,
+		retry_strategy: Retry
+	) -> Result<(BatchPaymentId, Vec<Result<PaymentId, RetryableSendFailure>>), PaymentError>;
 }
 
 impl<M: Deref, T: Deref, ES: Deref, NS: Deref, SP: Deref, F: Deref, R: Deref, L: Deref> Payer for ChannelManager<M, T, ES, NS, SP, F, R, L>
@@ -208,6 +492,14 @@ where
 		self.send_payment(payment_hash, recipient_onion, payment_id, route_params, retry_strategy)
 			.map_err(PaymentError::Sending)
 	}
+
+	fn send_batch_payments(
+		&self, payments: Vec<BatchPayment>, max_total_routing_fee_msat: Option<u64>,
+		retry_strategy: Retry
+	) -> Result<(BatchPaymentId, Vec<Result<PaymentId, RetryableSendFailure>>), PaymentError> {
+		self.send_batch_payments_to_hashes(payments, max_total_routing_fee_msat, retry_strategy)
+			.map_err(PaymentError::Batch)
+	}
 }
 
 #[cfg(test)]
@@ -260,6 +552,18 @@ mod tests {
 			self.check_value_msats(Amount(route_params.final_value_msat));
 			Ok(())
 		}
+
+		fn send_batch_payments(
+			&self, payments: Vec<BatchPayment>, _max_total_routing_fee_msat: Option<u64>,
+			_retry_strategy: Retry
+		) -> Result<(BatchPaymentId, Vec<Result<PaymentId, RetryableSendFailure>>), PaymentError> {
+			let batch_id = BatchPaymentId([42; 32]);
+			let results = payments.iter().enumerate().map(|(idx, payment)| {
+				self.check_value_msats(Amount(payment.route_params.final_value_msat));
+				Ok(batch_id.payment_id(idx))
+			}).collect();
+			Ok((batch_id, results))
+		}
 	}
 
 	impl Drop for TestPayer {
@@ -338,6 +642,89 @@ mod tests {
 		pay_invoice_using_amount(&invoice, amt_msat, payment_id, Retry::Attempts(0), &payer).unwrap();
 	}
 
+	#[test]
+	fn pays_invoices_as_batch() {
+		let invoice = invoice(PaymentPreimage([1; 32]));
+		let zero_value_invoice = zero_value_invoice(PaymentPreimage([2; 32]));
+		let final_value_msat = invoice.amount_milli_satoshis().unwrap();
+		let amt_msat = 10_000;
+
+		let payer = TestPayer::new()
+			.expect_send(Amount(final_value_msat))
+			.expect_send(Amount(amt_msat));
+		let (batch_id, results) = pay_invoices_using_amounts(
+			&[(&invoice, None), (&zero_value_invoice, Some(amt_msat))], None, Retry::Attempts(0),
+			&payer).unwrap();
+		assert_eq!(results, vec![Ok(batch_id.payment_id(0)), Ok(batch_id.payment_id(1))]);
+
+		// Nothing is sent unless every invoice's amount is valid.
+		let payer = TestPayer::new();
+		assert_eq!(pay_invoices_using_amounts(&[(&invoice, None), (&zero_value_invoice, None)],
+			None, Retry::Attempts(0), &payer), Err(PaymentError::Invoice("amount missing")));
+		assert_eq!(pay_invoices_using_amounts(&[(&invoice, Some(amt_msat))], None,
+			Retry::Attempts(0), &payer), Err(PaymentError::Invoice("amount unexpected")));
+	}
+
+	struct TestStore {
+		entries: std::sync::Mutex<HashMap<String, Vec<u8>>>,
+	}
+
+	impl KVStorePersister for TestStore {
+		fn persist<W: Writeable>(&self, key: &str, object: &W) -> io::Result<()> {
+			self.entries.lock().unwrap().insert(key.to_string(), object.encode());
+			Ok(())
+		}
+	}
+
+	impl KVStoreReader for TestStore {
+		fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+			Ok(self.entries.lock().unwrap().get(key).cloned())
+		}
+
+		fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+			Ok(self.entries.lock().unwrap().keys().filter(|key| key.starts_with(prefix)).cloned().collect())
+		}
+	}
+
+	#[test]
+	fn pays_invoice_once_per_idempotency_key() {
+		let store = TestStore { entries: std::sync::Mutex::new(HashMap::new()) };
+		let invoice = invoice(PaymentPreimage([1; 32]));
+		let final_value_msat = invoice.amount_milli_satoshis().unwrap();
+
+		let idempotency_keys = PaymentIdempotencyKeys::new(&store);
+		let payer = TestPayer::new().expect_send(Amount(final_value_msat));
+		let payment_id = pay_invoice_using_idempotency_key(&invoice, final_value_msat, b"request",
+			Retry::Attempts(0), &payer, &idempotency_keys).unwrap();
+		assert_eq!(pay_invoice_using_idempotency_key(&invoice, final_value_msat, b"request",
+			Retry::Attempts(0), &payer, &idempotency_keys), Ok(payment_id));
+
+		// Each key is persisted on its own and results in its own PaymentId.
+		let payer = TestPayer::new().expect_send(Amount(final_value_msat));
+		let other_payment_id = pay_invoice_using_idempotency_key(&invoice, final_value_msat,
+			b"other request", Retry::Attempts(0), &payer, &idempotency_keys).unwrap();
+		assert_ne!(other_payment_id, payment_id);
+		assert_eq!(store.list(PAYMENT_IDEMPOTENCY_KEYS_PREFIX).unwrap().len(), 2);
+
+		// The key is remembered across restarts, so the invoice still isn't paid again.
+		let idempotency_keys = PaymentIdempotencyKeys::read_from_store(&store).unwrap();
+		assert_eq!(idempotency_keys.payment_id(b"request"), Some(payment_id));
+		assert_eq!(pay_invoice_using_idempotency_key(&invoice, final_value_msat, b"request",
+			Retry::Attempts(0), &payer, &idempotency_keys), Ok(payment_id));
+
+		let other_invoice = zero_value_invoice(PaymentPreimage([2; 32]));
+		assert_eq!(pay_invoice_using_idempotency_key(&other_invoice, final_value_msat, b"request",
+			Retry::Attempts(0), &payer, &idempotency_keys),
+			Err(IdempotentPaymentError::Payment(PaymentError::Invoice("idempotency key used for a different invoice"))));
+
+		// Once the key is removed it may be used for another payment.
+		idempotency_keys.remove_key(b"request").unwrap();
+		assert!(PaymentIdempotencyKeys::read_from_store(&store).unwrap().payment_id(b"request").is_none());
+		let payer = TestPayer::new().expect_send(Amount(10_000));
+		pay_invoice_using_idempotency_key(&other_invoice, 10_000, b"request", Retry::Attempts(0),
+			&payer, &idempotency_keys).unwrap();
+	}
+
 	#[test]
 	fn fails_paying_zero_value_invoice_with_amount() {
 		let chanmon_cfgs = create_chanmon_cfgs(1);
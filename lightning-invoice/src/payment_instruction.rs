@@ -0,0 +1,467 @@
+//! Utilities to classify and parse the strings a wallet may be asked to pay, e.g. after scanning a
+//! QR code, into a unified [`PaymentInstruction`].
+//!
+//! A [`PaymentInstruction`] may be a [BOLT 11] invoice, a [BOLT 12] offer or refund, or a
+//! [BIP 21] URI, which may itself embed a BOLT 11 invoice or BOLT 12 offer. Regardless of the
+//! underlying format, its amount, description and expiry may be read in a normalized form.
+//!
+//! [BOLT 11]: https://github.com/lightning/bolts/blob/master/11-payment-encoding.md
+//! [BOLT 12]: https://github.com/lightning/bolts/blob/master/12-offer-encoding.md
+//! [BIP 21]: https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki
+
+use crate::{Invoice, InvoiceDescription, ParseOrSemanticError};
+use crate::prelude::*;
+
+use bitcoin::Address;
+use lightning::offers::offer::{Amount, Offer};
+use lightning::offers::parse::ParseError as Bolt12ParseError;
+use lightning::offers::refund::Refund;
+
+use core::fmt::{self, Display, Formatter};
+use core::str::FromStr;
+use core::time::Duration;
+
+/// The URI scheme which may prefix a BOLT 11 invoice or BOLT 12 offer or refund.
+const LIGHTNING_URI_SCHEME: &str = "lightning:";
+
+/// The URI scheme of a BIP 21 URI.
+const BITCOIN_URI_SCHEME: &str = "bitcoin:";
+
+/// The BIP 21 parameter used to embed a BOLT 11 invoice.
+const BIP21_LIGHTNING_PARAM: &str = "lightning";
+
+/// The BIP 21 parameter used to embed a BOLT 12 offer.
+const BIP21_OFFER_PARAM: &str = "lno";
+
+/// The number of millisatoshis in a bitcoin.
+const MSATS_PER_BTC: u64 = 100_000_000_000;
+
+/// The number of decimal places of a bitcoin amount expressed in millisatoshis.
+const MSAT_DECIMAL_PLACES: usize = 11;
+
+/// Something a wallet may be asked to pay, parsed via [`str::parse`].
+///
+/// This is not exported to bindings users as it wraps types which are not exported either.
+#[derive(Clone, Debug)]
+pub enum PaymentInstruction {
+	/// A BOLT 11 invoice.
+	Bolt11(Invoice),
+	/// A BOLT 12 offer, from which an invoice must be requested.
+	Bolt12Offer(Offer),
+	/// A BOLT 12 refund, which is paid to us by responding with an invoice.
+	Bolt12Refund(Refund),
+	/// A BIP 21 URI, which may embed a BOLT 11 invoice or BOLT 12 offer.
+	Bip21(Bip21Uri),
+}
+
+impl PaymentInstruction {
+	/// The amount requested, if any.
+	///
+	/// For a [`PaymentInstruction::Bip21`] URI, the amount of the URI itself takes precedence over
+	/// that of any invoice or offer it embeds.
+	pub fn amount(&self) -> Option<Amount> {
+		match self {
+			PaymentInstruction::Bolt11(invoice) => invoice.amount_milli_satoshis()
+				.map(|amount_msats| Amount::Bitcoin { amount_msats }),
+			PaymentInstruction::Bolt12Offer(offer) => offer.amount().cloned(),
+			PaymentInstruction::Bolt12Refund(refund) =>
+				Some(Amount::Bitcoin { amount_msats: refund.amount_msats() }),
+			PaymentInstruction::Bip21(uri) => uri.amount_msats
+				.map(|amount_msats| Amount::Bitcoin { amount_msats })
+				.or_else(|| uri.lightning.as_ref().and_then(|invoice| invoice.amount_milli_satoshis())
+					.map(|amount_msats| Amount::Bitcoin { amount_msats }))
+				.or_else(|| uri.offer.as_ref().and_then(|offer| offer.amount().cloned())),
+		}
+	}
+
+	/// The human-readable description of what is being paid for, if any.
+	///
+	/// This is `None` for a BOLT 11 invoice which only commits to a hash of its description. For a
+	/// [`PaymentInstruction::Bip21`] URI, its message or label takes precedence over the description
+	/// of any invoice or offer it embeds.
+	///
+	/// Note that the description is provided by the payee and should be treated as untrusted.
+	pub fn description(&self) -> Option<String> {
+		match self {
+			PaymentInstruction::Bolt11(invoice) => bolt11_description(invoice),
+			PaymentInstruction::Bolt12Offer(offer) => Some(offer.description().to_string()),
+			PaymentInstruction::Bolt12Refund(refund) => Some(refund.description().to_string()),
+			PaymentInstruction::Bip21(uri) => uri.message.clone()
+				.or_else(|| uri.label.clone())
+				.or_else(|| uri.lightning.as_ref().and_then(bolt11_description))
+				.or_else(|| uri.offer.as_ref().map(|offer| offer.description().to_string())),
+		}
+	}
+
+	/// The time, as a [`Duration`] since the Unix epoch, after which the payment instruction may no
+	/// longer be paid, if any.
+	///
+	/// For a [`PaymentInstruction::Bip21`] URI, this is the expiry of the invoice or offer it
+	/// embeds, if any.
+	pub fn expires_at(&self) -> Option<Duration> {
+		match self {
+			PaymentInstruction::Bolt11(invoice) => invoice.expires_at(),
+			PaymentInstruction::Bolt12Offer(offer) => offer.absolute_expiry(),
+			PaymentInstruction::Bolt12Refund(refund) => refund.absolute_expiry(),
+			PaymentInstruction::Bip21(uri) => uri.lightning.as_ref().and_then(|invoice| invoice.expires_at())
+				.or_else(|| uri.offer.as_ref().and_then(|offer| offer.absolute_expiry())),
+		}
+	}
+}
+
+fn bolt11_description(invoice: &Invoice) -> Option<String> {
+	match invoice.description() {
+		InvoiceDescription::Direct(description) => Some(String::from(&**description)),
+		InvoiceDescription::Hash(_) => None,
+	}
+}
+
+/// A parsed [BIP 21] URI, optionally embedding a BOLT 11 invoice in its `lightning` parameter or a
+/// BOLT 12 offer in its `lno` parameter.
+///
+/// [BIP 21]: https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki
+#[derive(Clone, Debug)]
+pub struct Bip21Uri {
+	/// The on-chain address to pay, which may be omitted if the URI embeds an invoice or offer.
+	pub address: Option<Address>,
+	/// The amount to pay, in millisatoshis.
+	pub amount_msats: Option<u64>,
+	/// A label for the address, e.g. the payee's name.
+	pub label: Option<String>,
+	/// A message describing the payment.
+	pub message: Option<String>,
+	/// The BOLT 11 invoice which may be paid instead of the on-chain address.
+	pub lightning: Option<Invoice>,
+	/// The BOLT 12 offer which may be paid instead of the on-chain address.
+	pub offer: Option<Offer>,
+}
+
+/// An error when parsing a [`PaymentInstruction`].
+#[derive(Debug, PartialEq)]
+pub enum PaymentInstructionParseError {
+	/// The string was neither a BOLT 11 invoice, a BOLT 12 offer or refund, nor a BIP 21 URI.
+	UnknownFormat,
+	/// The BOLT 11 invoice, possibly embedded in a BIP 21 URI, couldn't be parsed.
+	Bolt11(ParseOrSemanticError),
+	/// The BOLT 12 offer or refund, possibly embedded in a BIP 21 URI, couldn't be parsed.
+	Bolt12(Bolt12ParseError),
+	/// The BIP 21 URI was malformed, e.g. because of an invalid address or amount, or had a
+	/// required parameter we don't understand.
+	InvalidBip21Uri,
+}
+
+impl Display for PaymentInstructionParseError {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		match self {
+			PaymentInstructionParseError::UnknownFormat => f.write_str("Unknown payment instruction format"),
+			PaymentInstructionParseError::Bolt11(e) => write!(f, "Invalid BOLT 11 invoice: {}", e),
+			PaymentInstructionParseError::Bolt12(e) => write!(f, "Invalid BOLT 12 message: {:?}", e),
+			PaymentInstructionParseError::InvalidBip21Uri => f.write_str("Invalid BIP 21 URI"),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PaymentInstructionParseError {}
+
+impl From<ParseOrSemanticError> for PaymentInstructionParseError {
+	fn from(e: ParseOrSemanticError) -> Self {
+		PaymentInstructionParseError::Bolt11(e)
+	}
+}
+
+impl From<Bolt12ParseError> for PaymentInstructionParseError {
+	fn from(e: Bolt12ParseError) -> Self {
+		PaymentInstructionParseError::Bolt12(e)
+	}
+}
+
+/// Returns the part of `s` following the given URI scheme, which is matched case-insensitively.
+fn strip_scheme<'a>(s: &'a str, scheme: &str) -> Option<&'a str> {
+	if s.len() >= scheme.len() && s.is_char_boundary(scheme.len()) &&
+		s[..scheme.len()].eq_ignore_ascii_case(scheme)
+	{
+		Some(&s[scheme.len()..])
+	} else {
+		None
+	}
+}
+
+impl FromStr for PaymentInstruction {
+	type Err = PaymentInstructionParseError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let s = s.trim();
+		if let Some(uri) = strip_scheme(s, BITCOIN_URI_SCHEME) {
+			return Ok(PaymentInstruction::Bip21(Bip21Uri::from_uri_body(uri)?));
+		}
+
+		let s = strip_scheme(s, LIGHTNING_URI_SCHEME).unwrap_or(s);
+		if strip_scheme(s, "lno1").is_some() {
+			Ok(PaymentInstruction::Bolt12Offer(Offer::from_str(s)?))
+		} else if strip_scheme(s, "lnr1").is_some() {
+			Ok(PaymentInstruction::Bolt12Refund(Refund::from_str(s)?))
+		} else if strip_scheme(s, "ln").is_some() {
+			Ok(PaymentInstruction::Bolt11(Invoice::from_str(s)?))
+		} else {
+			Err(PaymentInstructionParseError::UnknownFormat)
+		}
+	}
+}
+
+impl Display for PaymentInstruction {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		match self {
+			PaymentInstruction::Bolt11(invoice) => Display::fmt(invoice, f),
+			PaymentInstruction::Bolt12Offer(offer) => Display::fmt(offer, f),
+			PaymentInstruction::Bolt12Refund(refund) => Display::fmt(refund, f),
+			PaymentInstruction::Bip21(uri) => Display::fmt(uri, f),
+		}
+	}
+}
+
+impl Bip21Uri {
+	/// Parses the part of a BIP 21 URI following its `bitcoin:` scheme.
+	fn from_uri_body(body: &str) -> Result<Self, PaymentInstructionParseError> {
+		let (address, query) = match body.find('?') {
+			Some(idx) => (&body[..idx], Some(&body[idx + 1..])),
+			None => (body, None),
+		};
+		let address = if address.is_empty() {
+			None
+		} else {
+			Some(Address::from_str(address).map_err(|_| PaymentInstructionParseError::InvalidBip21Uri)?)
+		};
+
+		let mut uri = Bip21Uri {
+			address, amount_msats: None, label: None, message: None, lightning: None, offer: None,
+		};
+		for param in query.into_iter().flat_map(|query| query.split('&')).filter(|param| !param.is_empty()) {
+			let (key, value) = match param.find('=') {
+				Some(idx) => (&param[..idx], percent_decode(&param[idx + 1..])?),
+				None => (param, String::new()),
+			};
+			let key = key.to_ascii_lowercase();
+			match key.as_str() {
+				"amount" => uri.amount_msats = Some(parse_btc_amount_msats(&value)?),
+				"label" => uri.label = Some(value),
+				"message" => uri.message = Some(value),
+				BIP21_LIGHTNING_PARAM => {
+					let invoice = strip_scheme(&value, LIGHTNING_URI_SCHEME).unwrap_or(&value);
+					uri.lightning = Some(Invoice::from_str(invoice)?);
+				},
+				BIP21_OFFER_PARAM => uri.offer = Some(Offer::from_str(&value)?),
+				// Per BIP 21, we must fail if a parameter we don't understand is required.
+				_ if key.starts_with("req-") => return Err(PaymentInstructionParseError::InvalidBip21Uri),
+				_ => {},
+			}
+		}
+
+		if uri.address.is_none() && uri.lightning.is_none() && uri.offer.is_none() {
+			return Err(PaymentInstructionParseError::InvalidBip21Uri);
+		}
+		Ok(uri)
+	}
+}
+
+impl Display for Bip21Uri {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		f.write_str(BITCOIN_URI_SCHEME)?;
+		if let Some(address) = &self.address {
+			write!(f, "{}", address)?;
+		}
+		let mut separator = '?';
+		let mut write_param = |f: &mut Formatter, key: &str, value: &str| -> fmt::Result {
+			write!(f, "{}{}={}", separator, key, value)?;
+			separator = '&';
+			Ok(())
+		};
+		if let Some(amount_msats) = self.amount_msats {
+			write_param(f, "amount", &format_btc_amount(amount_msats))?;
+		}
+		if let Some(label) = &self.label {
+			write_param(f, "label", &percent_encode(label))?;
+		}
+		if let Some(message) = &self.message {
+			write_param(f, "message", &percent_encode(message))?;
+		}
+		if let Some(invoice) = &self.lightning {
+			write_param(f, BIP21_LIGHTNING_PARAM, &invoice.to_string())?;
+		}
+		if let Some(offer) = &self.offer {
+			write_param(f, BIP21_OFFER_PARAM, &offer.to_string())?;
+		}
+		Ok(())
+	}
+}
+
+/// Parses a decimal amount of bitcoin, as used in BIP 21 URIs, into millisatoshis.
+fn parse_btc_amount_msats(amount: &str) -> Result<u64, PaymentInstructionParseError> {
+	let err = PaymentInstructionParseError::InvalidBip21Uri;
+	let (whole, fraction) = match amount.find('.') {
+		Some(idx) => (&amount[..idx], &amount[idx + 1..]),
+		None => (amount, ""),
+	};
+	if whole.is_empty() && fraction.is_empty() { return Err(err); }
+	if fraction.len() > MSAT_DECIMAL_PLACES { return Err(err); }
+	if !whole.bytes().chain(fraction.bytes()).all(|b| b.is_ascii_digit()) { return Err(err); }
+
+	let whole_msats = if whole.is_empty() { 0 } else {
+		whole.parse::<u64>().ok().and_then(|btc| btc.checked_mul(MSATS_PER_BTC)).ok_or(err)?
+	};
+	let mut fraction_msats = 0u64;
+	for (idx, digit) in fraction.bytes().enumerate() {
+		let place = 10u64.pow((MSAT_DECIMAL_PLACES - 1 - idx) as u32);
+		fraction_msats += (digit - b'0') as u64 * place;
+	}
+	whole_msats.checked_add(fraction_msats).ok_or(PaymentInstructionParseError::InvalidBip21Uri)
+}
+
+/// Formats an amount in millisatoshis as a decimal amount of bitcoin, as used in BIP 21 URIs.
+fn format_btc_amount(amount_msats: u64) -> String {
+	let whole = amount_msats / MSATS_PER_BTC;
+	let fraction = amount_msats % MSATS_PER_BTC;
+	if fraction == 0 {
+		return whole.to_string();
+	}
+	let fraction = format!("{:011}", fraction);
+	format!("{}.{}", whole, fraction.trim_end_matches('0'))
+}
+
+/// Decodes the percent-encoded value of a URI query parameter.
+fn percent_decode(value: &str) -> Result<String, PaymentInstructionParseError> {
+	let bytes = value.as_bytes();
+	let mut decoded = Vec::with_capacity(bytes.len());
+	let mut idx = 0;
+	while idx < bytes.len() {
+		if bytes[idx] == b'%' {
+			let hex = value.get(idx + 1..idx + 3).ok_or(PaymentInstructionParseError::InvalidBip21Uri)?;
+			let byte = u8::from_str_radix(hex, 16).map_err(|_| PaymentInstructionParseError::InvalidBip21Uri)?;
+			decoded.push(byte);
+			idx += 3;
+		} else {
+			decoded.push(bytes[idx]);
+			idx += 1;
+		}
+	}
+	String::from_utf8(decoded).map_err(|_| PaymentInstructionParseError::InvalidBip21Uri)
+}
+
+/// Percent-encodes a value for use as a URI query parameter.
+fn percent_encode(value: &str) -> String {
+	let mut encoded = String::with_capacity(value.len());
+	for byte in value.bytes() {
+		match byte {
+			b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => encoded.push(byte as char),
+			_ => encoded.push_str(&format!("%{:02X}", byte)),
+		}
+	}
+	encoded
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{PaymentInstruction, PaymentInstructionParseError};
+	use secp256k1::{PublicKey, Secp256k1, SecretKey};
+	use core::time::Duration;
+	use lightning::offers::offer::{Amount, OfferBuilder};
+	use lightning::offers::refund::RefundBuilder;
+
+	const INVOICE: &str = "lnbc2500u1pvjluezsp5zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygspp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqdq5xysxxatsyp3k7enxv4jsxqzpu9qrsgquk0rl77nj30yxdy8j9vdx85fkpmdla2087ne0xh8nhedh8w27kyke0lp53ut353s06fv3qfegext0eh0ymjpf39tuven09sam30g4vgpfna3rh";
+
+	fn pubkey() -> PublicKey {
+		PublicKey::from_secret_key(&Secp256k1::new(), &SecretKey::from_slice(&[42; 32]).unwrap())
+	}
+
+	#[test]
+	fn parses_bolt11_invoice() {
+		for s in [INVOICE.to_owned(), INVOICE.to_uppercase(), format!("lightning:{}", INVOICE)].iter() {
+			let instruction: PaymentInstruction = s.parse().unwrap();
+			match instruction {
+				PaymentInstruction::Bolt11(_) => {},
+				_ => panic!("Unexpected payment instruction"),
+			}
+			assert_eq!(instruction.amount(), Some(Amount::Bitcoin { amount_msats: 250_000_000 }));
+			assert_eq!(instruction.description(), Some("1 cup coffee".to_owned()));
+			assert_eq!(instruction.expires_at(), Some(Duration::from_secs(1496314658 + 60)));
+			assert_eq!(instruction.to_string(), INVOICE);
+		}
+	}
+
+	#[test]
+	fn parses_bolt12_offer_and_refund() {
+		let offer = OfferBuilder::new("foo".into(), pubkey())
+			.amount_msats(1000)
+			.absolute_expiry(Duration::from_secs(1_000_000))
+			.build().unwrap();
+		let instruction: PaymentInstruction = offer.to_string().parse().unwrap();
+		match instruction {
+			PaymentInstruction::Bolt12Offer(_) => {},
+			_ => panic!("Unexpected payment instruction"),
+		}
+		assert_eq!(instruction.amount(), Some(Amount::Bitcoin { amount_msats: 1000 }));
+		assert_eq!(instruction.description(), Some("foo".to_owned()));
+		assert_eq!(instruction.expires_at(), Some(Duration::from_secs(1_000_000)));
+		assert_eq!(instruction.to_string(), offer.to_string());
+
+		let refund = RefundBuilder::new("bar".into(), vec![1; 32], pubkey(), 2000).unwrap()
+			.build().unwrap();
+		let instruction: PaymentInstruction = refund.to_string().parse().unwrap();
+		match instruction {
+			PaymentInstruction::Bolt12Refund(_) => {},
+			_ => panic!("Unexpected payment instruction"),
+		}
+		assert_eq!(instruction.amount(), Some(Amount::Bitcoin { amount_msats: 2000 }));
+		assert_eq!(instruction.description(), Some("bar".to_owned()));
+		assert_eq!(instruction.expires_at(), None);
+	}
+
+	#[test]
+	fn parses_bip21_uri() {
+		let uri = format!("BITCOIN:bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq?amount=0.0025&label=Caf%C3%A9&message=1%20cup%20coffee&lightning={}", INVOICE);
+		let instruction: PaymentInstruction = uri.parse().unwrap();
+		match &instruction {
+			PaymentInstruction::Bip21(bip21) => {
+				assert!(bip21.address.is_some());
+				assert_eq!(bip21.amount_msats, Some(250_000_000));
+				assert_eq!(bip21.label, Some("Café".to_owned()));
+				assert!(bip21.lightning.is_some());
+			},
+			_ => panic!("Unexpected payment instruction"),
+		}
+		assert_eq!(instruction.amount(), Some(Amount::Bitcoin { amount_msats: 250_000_000 }));
+		assert_eq!(instruction.description(), Some("1 cup coffee".to_owned()));
+		assert_eq!(instruction.expires_at(), Some(Duration::from_secs(1496314658 + 60)));
+
+		// Serializing and re-parsing the URI results in the same payment instruction.
+		let reparsed: PaymentInstruction = instruction.to_string().parse().unwrap();
+		assert_eq!(reparsed.to_string(), instruction.to_string());
+
+		// A URI may omit the address if it embeds an invoice.
+		let instruction: PaymentInstruction = format!("bitcoin:?lightning={}", INVOICE).parse().unwrap();
+		assert_eq!(instruction.amount(), Some(Amount::Bitcoin { amount_msats: 250_000_000 }));
+	}
+
+	#[test]
+	fn fails_to_parse_invalid_payment_instructions() {
+		assert_eq!("foo".parse::<PaymentInstruction>().unwrap_err(), PaymentInstructionParseError::UnknownFormat);
+		match "lnbc1invalid".parse::<PaymentInstruction>() {
+			Err(PaymentInstructionParseError::Bolt11(_)) => {},
+			_ => panic!("Expected a BOLT 11 error"),
+		}
+		match "lno1invalid".parse::<PaymentInstruction>() {
+			Err(PaymentInstructionParseError::Bolt12(_)) => {},
+			_ => panic!("Expected a BOLT 12 error"),
+		}
+		for uri in [
+			"bitcoin:",
+			"bitcoin:notanaddress",
+			"bitcoin:bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq?amount=0.000000000001",
+			"bitcoin:bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq?amount=1e3",
+			"bitcoin:bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq?req-somethingyoudontunderstand=50",
+			"bitcoin:bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq?label=%ZZ",
+		].iter() {
+			assert_eq!(uri.parse::<PaymentInstruction>().unwrap_err(), PaymentInstructionParseError::InvalidBip21Uri);
+		}
+	}
+}
@@ -1,17 +1,19 @@
 //! Convenient utilities to create an invoice.
 
-use crate::{CreationError, Currency, Invoice, InvoiceBuilder, SignOrCreationError};
+use crate::{CreationError, Currency, Invoice, InvoiceBuilder, RawInvoice, SignOrCreationError};
 
 use crate::{prelude::*, Description, InvoiceDescription, Sha256};
 use bech32::ToBase32;
 use bitcoin_hashes::Hash;
 use lightning::chain;
 use lightning::chain::chaininterface::{BroadcasterInterface, FeeEstimator};
-use lightning::sign::{Recipient, NodeSigner, SignerProvider, EntropySource};
+use lightning::sign::{Recipient, NodeSigner, NodeSignerStatus, SignerProvider, EntropySource};
 use lightning::ln::{PaymentHash, PaymentSecret};
 use lightning::ln::channelmanager::{ChannelDetails, ChannelManager, MIN_FINAL_CLTV_EXPIRY_DELTA};
 use lightning::ln::channelmanager::{PhantomRouteHints, MIN_CLTV_EXPIRY_DELTA};
 use lightning::ln::inbound_payment::{create, create_from_hash, ExpandedKey};
+use lightning::offers::invoice_request::InvoiceRequest;
+use lightning::offers::parse::{ParseError, SemanticError as OfferSemanticError};
 use lightning::routing::gossip::RoutingFees;
 use lightning::routing::router::{RouteHint, RouteHintHop, Router};
 use lightning::util::logger::Logger;
@@ -20,6 +22,27 @@ use core::ops::Deref;
 use core::time::Duration;
 use core::iter::Iterator;
 
+/// The minimum `invoice_expiry_delta_secs` accepted by the utilities in this module.
+///
+/// Senders need some time to find a route and pay an invoice, and clocks may be off by some
+/// seconds, thus invoices expiring sooner are likely to expire before they could be paid. Short
+/// but reasonable expiries, e.g. for point-of-sale invoices, are well above this.
+pub const MIN_INVOICE_EXPIRY_DELTA_SECS: u32 = 60;
+
+/// Checks the per-invoice `invoice_expiry_delta_secs` and `min_final_cltv_expiry_delta` against
+/// [`MIN_INVOICE_EXPIRY_DELTA_SECS`] and [`MIN_FINAL_CLTV_EXPIRY_DELTA`], respectively.
+fn check_invoice_parameters(
+	invoice_expiry_delta_secs: u32, min_final_cltv_expiry_delta: Option<u16>,
+) -> Result<(), SignOrCreationError<()>> {
+	if invoice_expiry_delta_secs < MIN_INVOICE_EXPIRY_DELTA_SECS {
+		return Err(SignOrCreationError::CreationError(CreationError::ExpiryTooShort));
+	}
+	if min_final_cltv_expiry_delta.is_some() && min_final_cltv_expiry_delta.unwrap().saturating_add(3) < MIN_FINAL_CLTV_EXPIRY_DELTA {
+		return Err(SignOrCreationError::CreationError(CreationError::MinFinalCltvExpiryDeltaTooShort));
+	}
+	Ok(())
+}
+
 /// Utility to create an invoice that can be paid to one of multiple nodes, or a "phantom invoice."
 /// See [`PhantomKeysManager`] for more information on phantom node payments.
 ///
@@ -38,7 +61,7 @@ use core::iter::Iterator;
 /// If `None` is provided for `payment_hash`, then one will be created.
 ///
 /// `invoice_expiry_delta_secs` describes the number of seconds that the invoice is valid for
-/// in excess of the current time.
+/// in excess of the current time. It must be at least [`MIN_INVOICE_EXPIRY_DELTA_SECS`].
 ///
 /// `duration_since_epoch` is the current time since epoch in seconds.
 ///
@@ -75,7 +98,8 @@ where
 	_create_phantom_invoice::<ES, NS, L>(
 		amt_msat, payment_hash, description, invoice_expiry_delta_secs, phantom_route_hints,
 		entropy_source, node_signer, logger, network, min_final_cltv_expiry_delta, duration_since_epoch,
-	)
+		false,
+	).map(InvoiceSigningStatus::expect_signed)
 }
 
 /// Utility to create an invoice that can be paid to one of multiple nodes, or a "phantom invoice."
@@ -100,7 +124,7 @@ where
 /// If `None` is provided for `payment_hash`, then one will be created.
 ///
 /// `invoice_expiry_delta_secs` describes the number of seconds that the invoice is valid for
-/// in excess of the current time.
+/// in excess of the current time. It must be at least [`MIN_INVOICE_EXPIRY_DELTA_SECS`].
 ///
 /// `duration_since_epoch` is the current time since epoch in seconds.
 ///
@@ -129,7 +153,31 @@ where
 	_create_phantom_invoice::<ES, NS, L>(
 		amt_msat, payment_hash, InvoiceDescription::Hash(&description_hash),
 		invoice_expiry_delta_secs, phantom_route_hints, entropy_source, node_signer, logger, network,
-		min_final_cltv_expiry_delta, duration_since_epoch,
+		min_final_cltv_expiry_delta, duration_since_epoch, false,
+	).map(InvoiceSigningStatus::expect_signed)
+}
+
+/// Utility to create a phantom invoice as in [`create_phantom_invoice`] or
+/// [`create_phantom_invoice_with_description_hash`], signing it via
+/// [`NodeSigner::sign_invoice_async`].
+///
+/// If our [`NodeSigner`] is still signing the invoice, it is returned unsigned in an
+/// [`InvoiceSigningStatus::Pending`], to be signed via [`sign_pending_invoice`] once the signer
+/// completes.
+pub fn create_phantom_invoice_async<ES: Deref, NS: Deref, L: Deref>(
+	amt_msat: Option<u64>, payment_hash: Option<PaymentHash>, description: InvoiceDescription,
+	invoice_expiry_delta_secs: u32, phantom_route_hints: Vec<PhantomRouteHints>, entropy_source: ES,
+	node_signer: NS, logger: L, network: Currency, min_final_cltv_expiry_delta: Option<u16>, duration_since_epoch: Duration,
+) -> Result<InvoiceSigningStatus, SignOrCreationError<()>>
+where
+	ES::Target: EntropySource,
+	NS::Target: NodeSigner,
+	L::Target: Logger,
+{
+	_create_phantom_invoice::<ES, NS, L>(
+		amt_msat, payment_hash, description, invoice_expiry_delta_secs, phantom_route_hints,
+		entropy_source, node_signer, logger, network, min_final_cltv_expiry_delta, duration_since_epoch,
+		true,
 	)
 }
 
@@ -139,7 +187,8 @@ fn _create_phantom_invoice<ES: Deref, NS: Deref, L: Deref>(
 	amt_msat: Option<u64>, payment_hash: Option<PaymentHash>, description: InvoiceDescription,
 	invoice_expiry_delta_secs: u32, phantom_route_hints: Vec<PhantomRouteHints>, entropy_source: ES,
 	node_signer: NS, logger: L, network: Currency, min_final_cltv_expiry_delta: Option<u16>, duration_since_epoch: Duration,
-) -> Result<Invoice, SignOrCreationError<()>>
+	sign_async: bool,
+) -> Result<InvoiceSigningStatus, SignOrCreationError<()>>
 where
 	ES::Target: EntropySource,
 	NS::Target: NodeSigner,
@@ -152,9 +201,7 @@ where
 		));
 	}
 
-	if min_final_cltv_expiry_delta.is_some() && min_final_cltv_expiry_delta.unwrap().saturating_add(3) < MIN_FINAL_CLTV_EXPIRY_DELTA {
-		return Err(SignOrCreationError::CreationError(CreationError::MinFinalCltvExpiryDeltaTooShort));
-	}
+	check_invoice_parameters(invoice_expiry_delta_secs, min_final_cltv_expiry_delta)?;
 
 	let invoice = match description {
 		InvoiceDescription::Direct(description) => {
@@ -214,14 +261,7 @@ where
 		Ok(inv) => inv,
 		Err(e) => return Err(SignOrCreationError::CreationError(e))
 	};
-	let hrp_str = raw_invoice.hrp.to_string();
-	let hrp_bytes = hrp_str.as_bytes();
-	let data_without_signature = raw_invoice.data.to_base32();
-	let signed_raw_invoice = raw_invoice.sign(|_| node_signer.sign_invoice(hrp_bytes, &data_without_signature, Recipient::PhantomNode));
-	match signed_raw_invoice {
-		Ok(inv) => Ok(Invoice::from_signed(inv).unwrap()),
-		Err(e) => Err(SignOrCreationError::SignError(e))
-	}
+	sign_raw_invoice(raw_invoice, node_signer, Recipient::PhantomNode, sign_async)
 }
 
 /// Utility to select route hints for phantom invoices.
@@ -321,7 +361,9 @@ fn rotate_through_iterators<T, I: Iterator<Item = T>>(mut vecs: Vec<I>) -> impl
 /// that the payment secret is valid when the invoice is paid.
 ///
 /// `invoice_expiry_delta_secs` describes the number of seconds that the invoice is valid for
-/// in excess of the current time.
+/// in excess of the current time. It must be at least [`MIN_INVOICE_EXPIRY_DELTA_SECS`], but may
+/// otherwise be chosen per invoice, e.g., short for point-of-sale invoices and long for
+/// subscription top-ups.
 ///
 /// You can specify a custom `min_final_cltv_expiry_delta`, or let LDK default it to
 /// [`MIN_FINAL_CLTV_EXPIRY_DELTA`]. The provided expiry must be at least [`MIN_FINAL_CLTV_EXPIRY_DELTA`].
@@ -362,7 +404,7 @@ where
 /// Use this variant if you want to pass the `description_hash` to the invoice.
 ///
 /// `invoice_expiry_delta_secs` describes the number of seconds that the invoice is valid for
-/// in excess of the current time.
+/// in excess of the current time. It must be at least [`MIN_INVOICE_EXPIRY_DELTA_SECS`].
 ///
 /// You can specify a custom `min_final_cltv_expiry_delta`, or let LDK default it to
 /// [`MIN_FINAL_CLTV_EXPIRY_DELTA`]. The provided expiry must be at least [`MIN_FINAL_CLTV_EXPIRY_DELTA`].
@@ -418,7 +460,7 @@ pub fn create_invoice_from_channelmanager_with_description_hash_and_duration_sin
 	_create_invoice_from_channelmanager_and_duration_since_epoch(
 		channelmanager, node_signer, logger, network, amt_msat,
 		InvoiceDescription::Hash(&description_hash),
-		duration_since_epoch, invoice_expiry_delta_secs, min_final_cltv_expiry_delta,
+		duration_since_epoch, invoice_expiry_delta_secs, min_final_cltv_expiry_delta, None,
 	)
 }
 
@@ -445,14 +487,115 @@ pub fn create_invoice_from_channelmanager_and_duration_since_epoch<M: Deref, T:
 		InvoiceDescription::Direct(
 			&Description::new(description).map_err(SignOrCreationError::CreationError)?,
 		),
-		duration_since_epoch, invoice_expiry_delta_secs, min_final_cltv_expiry_delta,
+		duration_since_epoch, invoice_expiry_delta_secs, min_final_cltv_expiry_delta, None,
+	)
+}
+
+/// The per-invoice fields supplied by the callback passed to
+/// [`create_invoice_from_channelmanager_with_template`].
+///
+/// This allows invoice factories, such as lightning-address servers, which need to fill in
+/// these fields differently for each request to still rely on the `ChannelManager` utilities.
+pub struct InvoiceTemplate {
+	/// The description of the invoice, included either directly or by its hash.
+	pub description: InvoiceTemplateDescription,
+	/// Payment metadata to include in the invoice, if any. It will be provided back to us by the
+	/// sender in [`RecipientOnionFields::payment_metadata`] if the sender supports it.
+	///
+	/// [`RecipientOnionFields::payment_metadata`]: lightning::ln::channelmanager::RecipientOnionFields::payment_metadata
+	pub payment_metadata: Option<Vec<u8>>,
+	/// The number of seconds that the invoice is valid for in excess of the current time, which
+	/// must be at least [`MIN_INVOICE_EXPIRY_DELTA_SECS`].
+	pub invoice_expiry_delta_secs: u32,
+	/// A custom `min_final_cltv_expiry_delta`, or `None` to let LDK default it to
+	/// [`MIN_FINAL_CLTV_EXPIRY_DELTA`]. See [`create_invoice_from_channelmanager`] for the
+	/// requirements on this value.
+	pub min_final_cltv_expiry_delta: Option<u16>,
+}
+
+/// The description of an invoice created from an [`InvoiceTemplate`].
+pub enum InvoiceTemplateDescription {
+	/// The description is included directly in the invoice.
+	Direct(String),
+	/// Only the hash of the description is included in the invoice.
+	Hash(Sha256),
+}
+
+#[cfg(feature = "std")]
+/// Utility to construct an invoice like [`create_invoice_from_channelmanager`], but with the
+/// description, payment metadata, expiry and `min_final_cltv_expiry_delta` filled in by the given
+/// `template` callback, which is called with the requested `amt_msat`.
+pub fn create_invoice_from_channelmanager_with_template<M: Deref, T: Deref, ES: Deref, NS: Deref, SP: Deref, F: Deref, R: Deref, L: Deref, TF>(
+	channelmanager: &ChannelManager<M, T, ES, NS, SP, F, R, L>, node_signer: NS, logger: L,
+	network: Currency, amt_msat: Option<u64>, template: TF,
+) -> Result<Invoice, SignOrCreationError<()>>
+where
+	M::Target: chain::Watch<<SP::Target as SignerProvider>::Signer>,
+	T::Target: BroadcasterInterface,
+	ES::Target: EntropySource,
+	NS::Target: NodeSigner,
+	SP::Target: SignerProvider,
+	F::Target: FeeEstimator,
+	R::Target: Router,
+	L::Target: Logger,
+	TF: FnOnce(Option<u64>) -> InvoiceTemplate,
+{
+	use std::time::SystemTime;
+	let duration = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)
+		.expect("for the foreseeable future this shouldn't happen");
+	create_invoice_from_channelmanager_with_template_and_duration_since_epoch(
+		channelmanager, node_signer, logger, network, amt_msat, duration, template,
 	)
 }
 
+/// See [`create_invoice_from_channelmanager_with_template`]
+/// This version can be used in a `no_std` environment, where [`std::time::SystemTime`] is not
+/// available and the current time is supplied by the caller.
+pub fn create_invoice_from_channelmanager_with_template_and_duration_since_epoch<M: Deref, T: Deref, ES: Deref, NS: Deref, SP: Deref, F: Deref, R: Deref, L: Deref, TF>(
+	channelmanager: &ChannelManager<M, T, ES, NS, SP, F, R, L>, node_signer: NS, logger: L,
+	network: Currency, amt_msat: Option<u64>, duration_since_epoch: Duration, template: TF,
+) -> Result<Invoice, SignOrCreationError<()>>
+		where
+			M::Target: chain::Watch<<SP::Target as SignerProvider>::Signer>,
+			T::Target: BroadcasterInterface,
+			ES::Target: EntropySource,
+			NS::Target: NodeSigner,
+			SP::Target: SignerProvider,
+			F::Target: FeeEstimator,
+			R::Target: Router,
+			L::Target: Logger,
+			TF: FnOnce(Option<u64>) -> InvoiceTemplate,
+{
+	let InvoiceTemplate {
+		description, payment_metadata, invoice_expiry_delta_secs, min_final_cltv_expiry_delta
+	} = template(amt_msat);
+	match description {
+		InvoiceTemplateDescription::Direct(description) => {
+			_create_invoice_from_channelmanager_and_duration_since_epoch(
+				channelmanager, node_signer, logger, network, amt_msat,
+				InvoiceDescription::Direct(
+					&Description::new(description).map_err(SignOrCreationError::CreationError)?,
+				),
+				duration_since_epoch, invoice_expiry_delta_secs, min_final_cltv_expiry_delta,
+				payment_metadata,
+			)
+		},
+		InvoiceTemplateDescription::Hash(description_hash) => {
+			_create_invoice_from_channelmanager_and_duration_since_epoch(
+				channelmanager, node_signer, logger, network, amt_msat,
+				InvoiceDescription::Hash(&description_hash),
+				duration_since_epoch, invoice_expiry_delta_secs, min_final_cltv_expiry_delta,
+				payment_metadata,
+			)
+		},
+	}
+}
+
 fn _create_invoice_from_channelmanager_and_duration_since_epoch<M: Deref, T: Deref, ES: Deref, NS: Deref, SP: Deref, F: Deref, R: Deref, L: Deref>(
 	channelmanager: &ChannelManager<M, T, ES, NS, SP, F, R, L>, node_signer: NS, logger: L,
 	network: Currency, amt_msat: Option<u64>, description: InvoiceDescription,
 	duration_since_epoch: Duration, invoice_expiry_delta_secs: u32, min_final_cltv_expiry_delta: Option<u16>,
+	payment_metadata: Option<Vec<u8>>,
 ) -> Result<Invoice, SignOrCreationError<()>>
 		where
 			M::Target: chain::Watch<<SP::Target as SignerProvider>::Signer>,
@@ -464,9 +607,7 @@ fn _create_invoice_from_channelmanager_and_duration_since_epoch<M: Deref, T: Der
 			R::Target: Router,
 			L::Target: Logger,
 {
-	if min_final_cltv_expiry_delta.is_some() && min_final_cltv_expiry_delta.unwrap().saturating_add(3) < MIN_FINAL_CLTV_EXPIRY_DELTA {
-		return Err(SignOrCreationError::CreationError(CreationError::MinFinalCltvExpiryDeltaTooShort));
-	}
+	check_invoice_parameters(invoice_expiry_delta_secs, min_final_cltv_expiry_delta)?;
 
 	// `create_inbound_payment` only returns an error if the amount is greater than the total bitcoin
 	// supply.
@@ -475,7 +616,8 @@ fn _create_invoice_from_channelmanager_and_duration_since_epoch<M: Deref, T: Der
 		.map_err(|()| SignOrCreationError::CreationError(CreationError::InvalidAmount))?;
 	_create_invoice_from_channelmanager_and_duration_since_epoch_with_payment_hash(
 		channelmanager, node_signer, logger, network, amt_msat, description, duration_since_epoch,
-		invoice_expiry_delta_secs, payment_hash, payment_secret, min_final_cltv_expiry_delta)
+		invoice_expiry_delta_secs, payment_hash, payment_secret, min_final_cltv_expiry_delta,
+		payment_metadata, false).map(InvoiceSigningStatus::expect_signed)
 }
 
 /// See [`create_invoice_from_channelmanager_and_duration_since_epoch`]
@@ -497,6 +639,8 @@ pub fn create_invoice_from_channelmanager_and_duration_since_epoch_with_payment_
 		R::Target: Router,
 		L::Target: Logger,
 {
+	check_invoice_parameters(invoice_expiry_delta_secs, min_final_cltv_expiry_delta)?;
+
 	let payment_secret = channelmanager
 		.create_inbound_payment_for_hash(payment_hash, amt_msat, invoice_expiry_delta_secs,
 			min_final_cltv_expiry_delta)
@@ -507,16 +651,177 @@ pub fn create_invoice_from_channelmanager_and_duration_since_epoch_with_payment_
 			&Description::new(description).map_err(SignOrCreationError::CreationError)?,
 		),
 		duration_since_epoch, invoice_expiry_delta_secs, payment_hash, payment_secret,
-		min_final_cltv_expiry_delta,
+		min_final_cltv_expiry_delta, None, false,
+	).map(InvoiceSigningStatus::expect_signed)
+}
+
+/// Utility to create an invoice as in [`create_invoice_from_channelmanager_and_duration_since_epoch`]
+/// or [`create_invoice_from_channelmanager_with_description_hash_and_duration_since_epoch`],
+/// signing it via [`NodeSigner::sign_invoice_async`].
+///
+/// If our [`NodeSigner`] is still signing the invoice, it is returned unsigned in an
+/// [`InvoiceSigningStatus::Pending`], to be signed via [`sign_pending_invoice`] once the signer
+/// completes.
+pub fn create_invoice_from_channelmanager_and_duration_since_epoch_async<M: Deref, T: Deref, ES: Deref, NS: Deref, SP: Deref, F: Deref, R: Deref, L: Deref>(
+	channelmanager: &ChannelManager<M, T, ES, NS, SP, F, R, L>, node_signer: NS, logger: L,
+	network: Currency, amt_msat: Option<u64>, description: InvoiceDescription,
+	duration_since_epoch: Duration, invoice_expiry_delta_secs: u32, min_final_cltv_expiry_delta: Option<u16>,
+) -> Result<InvoiceSigningStatus, SignOrCreationError<()>>
+	where
+		M::Target: chain::Watch<<SP::Target as SignerProvider>::Signer>,
+		T::Target: BroadcasterInterface,
+		ES::Target: EntropySource,
+		NS::Target: NodeSigner,
+		SP::Target: SignerProvider,
+		F::Target: FeeEstimator,
+		R::Target: Router,
+		L::Target: Logger,
+{
+	check_invoice_parameters(invoice_expiry_delta_secs, min_final_cltv_expiry_delta)?;
+
+	let (payment_hash, payment_secret) = channelmanager
+		.create_inbound_payment(amt_msat, invoice_expiry_delta_secs, min_final_cltv_expiry_delta)
+		.map_err(|()| SignOrCreationError::CreationError(CreationError::InvalidAmount))?;
+	_create_invoice_from_channelmanager_and_duration_since_epoch_with_payment_hash(
+		channelmanager, node_signer, logger, network, amt_msat, description, duration_since_epoch,
+		invoice_expiry_delta_secs, payment_hash, payment_secret, min_final_cltv_expiry_delta, None,
+		true)
+}
+
+/// An error answering an [`InvoiceRequest`] with a BOLT 11 [`Invoice`].
+#[derive(Debug, PartialEq)]
+pub enum InvoiceRequestResponseError {
+	/// The invoice request could not be parsed from its bech32 encoding, or its signature was
+	/// invalid.
+	InvalidEncoding(ParseError),
+	/// The invoice request was not for one of our offers or had an invalid amount or quantity.
+	InvalidInvoiceRequest(OfferSemanticError),
+	/// The invoice answering the request could not be created or signed.
+	Invoice(SignOrCreationError<()>),
+}
+
+impl From<SignOrCreationError<()>> for InvoiceRequestResponseError {
+	fn from(e: SignOrCreationError<()>) -> Self {
+		InvoiceRequestResponseError::Invoice(e)
+	}
+}
+
+#[cfg(feature = "std")]
+/// Utility to construct a BOLT 11 invoice answering a bech32-encoded [`InvoiceRequest`] for one
+/// of our offers, for payers which can't complete the BOLT 12 flow, e.g. as they lack onion
+/// message support.
+///
+/// Such payers hand the request's `lnr` string to us out-of-band, e.g. in an HTTPS request,
+/// rather than in an onion message, and are answered with the string encoding of the returned
+/// [`Invoice`], which they pay as any BOLT 11 invoice. See [`create_invoice_for_invoice_request`]
+/// for details.
+///
+/// Errors with [`InvoiceRequestResponseError::InvalidEncoding`] if `encoded_invoice_request`
+/// isn't a validly signed invoice request.
+pub fn create_invoice_for_encoded_invoice_request<M: Deref, T: Deref, ES: Deref, NS: Deref, SP: Deref, F: Deref, R: Deref, L: Deref>(
+	channelmanager: &ChannelManager<M, T, ES, NS, SP, F, R, L>, node_signer: NS, logger: L,
+	network: Currency, encoded_invoice_request: &str, payment_hash: Option<PaymentHash>,
+	invoice_expiry_delta_secs: u32, min_final_cltv_expiry_delta: Option<u16>,
+) -> Result<Invoice, InvoiceRequestResponseError>
+where
+	M::Target: chain::Watch<<SP::Target as SignerProvider>::Signer>,
+	T::Target: BroadcasterInterface,
+	ES::Target: EntropySource,
+	NS::Target: NodeSigner,
+	SP::Target: SignerProvider,
+	F::Target: FeeEstimator,
+	R::Target: Router,
+	L::Target: Logger,
+{
+	let invoice_request = encoded_invoice_request.parse::<InvoiceRequest>()
+		.map_err(InvoiceRequestResponseError::InvalidEncoding)?;
+	create_invoice_for_invoice_request(
+		channelmanager, node_signer, logger, network, &invoice_request, payment_hash,
+		invoice_expiry_delta_secs, min_final_cltv_expiry_delta,
+	)
+}
+
+#[cfg(feature = "std")]
+/// Utility to construct a BOLT 11 invoice answering an [`InvoiceRequest`] for one of our offers,
+/// for payers which can't complete the BOLT 12 flow, e.g. as they lack onion message support.
+///
+/// The invoice is for the amount checked against the offer and carries the offer's description,
+/// or its hash if too long. See [`ChannelManager::create_inbound_payment_for_invoice_request`],
+/// which registers the payment such that it is received just like any other BOLT 11 payment,
+/// including using an externally stored preimage for the given `payment_hash`, if any.
+///
+/// Errors with [`InvoiceRequestResponseError::InvalidInvoiceRequest`] if the request isn't for one
+/// of our offers or has an invalid amount or quantity.
+pub fn create_invoice_for_invoice_request<M: Deref, T: Deref, ES: Deref, NS: Deref, SP: Deref, F: Deref, R: Deref, L: Deref>(
+	channelmanager: &ChannelManager<M, T, ES, NS, SP, F, R, L>, node_signer: NS, logger: L,
+	network: Currency, invoice_request: &InvoiceRequest, payment_hash: Option<PaymentHash>,
+	invoice_expiry_delta_secs: u32, min_final_cltv_expiry_delta: Option<u16>,
+) -> Result<Invoice, InvoiceRequestResponseError>
+where
+	M::Target: chain::Watch<<SP::Target as SignerProvider>::Signer>,
+	T::Target: BroadcasterInterface,
+	ES::Target: EntropySource,
+	NS::Target: NodeSigner,
+	SP::Target: SignerProvider,
+	F::Target: FeeEstimator,
+	R::Target: Router,
+	L::Target: Logger,
+{
+	use std::time::SystemTime;
+	let duration = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)
+		.expect("for the foreseeable future this shouldn't happen");
+	create_invoice_for_invoice_request_and_duration_since_epoch(
+		channelmanager, node_signer, logger, network, invoice_request, payment_hash, duration,
+		invoice_expiry_delta_secs, min_final_cltv_expiry_delta,
 	)
 }
 
+/// See [`create_invoice_for_invoice_request`]
+/// This version can be used in a `no_std` environment, where [`std::time::SystemTime`] is not
+/// available and the current time is supplied by the caller.
+pub fn create_invoice_for_invoice_request_and_duration_since_epoch<M: Deref, T: Deref, ES: Deref, NS: Deref, SP: Deref, F: Deref, R: Deref, L: Deref>(
+	channelmanager: &ChannelManager<M, T, ES, NS, SP, F, R, L>, node_signer: NS, logger: L,
+	network: Currency, invoice_request: &InvoiceRequest, payment_hash: Option<PaymentHash>,
+	duration_since_epoch: Duration, invoice_expiry_delta_secs: u32,
+	min_final_cltv_expiry_delta: Option<u16>,
+) -> Result<Invoice, InvoiceRequestResponseError>
+	where
+		M::Target: chain::Watch<<SP::Target as SignerProvider>::Signer>,
+		T::Target: BroadcasterInterface,
+		ES::Target: EntropySource,
+		NS::Target: NodeSigner,
+		SP::Target: SignerProvider,
+		F::Target: FeeEstimator,
+		R::Target: Router,
+		L::Target: Logger,
+{
+	check_invoice_parameters(invoice_expiry_delta_secs, min_final_cltv_expiry_delta)?;
+
+	let (amt_msat, payment_hash, payment_secret) = channelmanager
+		.create_inbound_payment_for_invoice_request(invoice_request, payment_hash,
+			invoice_expiry_delta_secs, min_final_cltv_expiry_delta)
+		.map_err(InvoiceRequestResponseError::InvalidInvoiceRequest)?;
+
+	let offer_description = invoice_request.description().to_string();
+	let description_hash = Sha256(Hash::hash(offer_description.as_bytes()));
+	let description = Description::new(offer_description);
+	let description = match description {
+		Ok(ref description) => InvoiceDescription::Direct(description),
+		Err(_) => InvoiceDescription::Hash(&description_hash),
+	};
+	_create_invoice_from_channelmanager_and_duration_since_epoch_with_payment_hash(
+		channelmanager, node_signer, logger, network, Some(amt_msat), description,
+		duration_since_epoch, invoice_expiry_delta_secs, payment_hash, payment_secret,
+		min_final_cltv_expiry_delta, None, false,
+	).map(InvoiceSigningStatus::expect_signed).map_err(InvoiceRequestResponseError::from)
+}
+
 fn _create_invoice_from_channelmanager_and_duration_since_epoch_with_payment_hash<M: Deref, T: Deref, ES: Deref, NS: Deref, SP: Deref, F: Deref, R: Deref, L: Deref>(
 	channelmanager: &ChannelManager<M, T, ES, NS, SP, F, R, L>, node_signer: NS, logger: L,
 	network: Currency, amt_msat: Option<u64>, description: InvoiceDescription, duration_since_epoch: Duration,
 	invoice_expiry_delta_secs: u32, payment_hash: PaymentHash, payment_secret: PaymentSecret,
-	min_final_cltv_expiry_delta: Option<u16>,
-) -> Result<Invoice, SignOrCreationError<()>>
+	min_final_cltv_expiry_delta: Option<u16>, payment_metadata: Option<Vec<u8>>, sign_async: bool,
+) -> Result<InvoiceSigningStatus, SignOrCreationError<()>>
 	where
 		M::Target: chain::Watch<<SP::Target as SignerProvider>::Signer>,
 		T::Target: BroadcasterInterface,
@@ -530,9 +835,7 @@ fn _create_invoice_from_channelmanager_and_duration_since_epoch_with_payment_has
 	let our_node_pubkey = channelmanager.get_our_node_id();
 	let channels = channelmanager.list_channels();
 
-	if min_final_cltv_expiry_delta.is_some() && min_final_cltv_expiry_delta.unwrap().saturating_add(3) < MIN_FINAL_CLTV_EXPIRY_DELTA {
-		return Err(SignOrCreationError::CreationError(CreationError::MinFinalCltvExpiryDeltaTooShort));
-	}
+	check_invoice_parameters(invoice_expiry_delta_secs, min_final_cltv_expiry_delta)?;
 
 	log_trace!(logger, "Creating invoice with payment hash {}", log_bytes!(payment_hash.0));
 
@@ -562,16 +865,68 @@ fn _create_invoice_from_channelmanager_and_duration_since_epoch_with_payment_has
 		invoice = invoice.private_route(hint);
 	}
 
-	let raw_invoice = match invoice.build_raw() {
+	let raw_invoice = match payment_metadata {
+		Some(payment_metadata) => invoice.payment_metadata(payment_metadata).build_raw(),
+		None => invoice.build_raw(),
+	};
+	let raw_invoice = match raw_invoice {
 		Ok(inv) => inv,
 		Err(e) => return Err(SignOrCreationError::CreationError(e))
 	};
+	sign_raw_invoice(raw_invoice, node_signer, Recipient::Node, sign_async)
+}
+
+/// An invoice created by one of the `*_async` utilities in this module.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InvoiceSigningStatus {
+	/// The invoice was signed by our [`NodeSigner`].
+	Signed(Invoice),
+	/// Our [`NodeSigner`] is still signing the invoice, see [`NodeSignerStatus::InProgress`].
+	///
+	/// Once it completes, the invoice may be signed via [`sign_pending_invoice`].
+	Pending(RawInvoice),
+}
+
+impl InvoiceSigningStatus {
+	fn expect_signed(self) -> Invoice {
+		match self {
+			InvoiceSigningStatus::Signed(invoice) => invoice,
+			InvoiceSigningStatus::Pending(_) => unreachable!(),
+		}
+	}
+}
+
+/// Signs an invoice returned in an [`InvoiceSigningStatus::Pending`] by the `*_async` utilities in
+/// this module via [`NodeSigner::sign_invoice_async`], once our signer completed signing it.
+///
+/// The `recipient` must be the one the invoice was created for, i.e. [`Recipient::PhantomNode`]
+/// for phantom invoices and [`Recipient::Node`] otherwise. If our signer is still signing the
+/// invoice, it is returned in an [`InvoiceSigningStatus::Pending`] again.
+pub fn sign_pending_invoice<NS: Deref>(
+	raw_invoice: RawInvoice, node_signer: NS, recipient: Recipient
+) -> Result<InvoiceSigningStatus, SignOrCreationError<()>> where NS::Target: NodeSigner {
+	sign_raw_invoice(raw_invoice, node_signer, recipient, true)
+}
+
+/// Signs an invoice via [`NodeSigner::sign_invoice`], or via [`NodeSigner::sign_invoice_async`] if
+/// `sign_async` is set.
+fn sign_raw_invoice<NS: Deref>(
+	raw_invoice: RawInvoice, node_signer: NS, recipient: Recipient, sign_async: bool
+) -> Result<InvoiceSigningStatus, SignOrCreationError<()>> where NS::Target: NodeSigner {
 	let hrp_str = raw_invoice.hrp.to_string();
 	let hrp_bytes = hrp_str.as_bytes();
 	let data_without_signature = raw_invoice.data.to_base32();
-	let signed_raw_invoice = raw_invoice.sign(|_| node_signer.sign_invoice(hrp_bytes, &data_without_signature, Recipient::Node));
+	let signature = if sign_async {
+		match node_signer.sign_invoice_async(hrp_bytes, &data_without_signature, recipient) {
+			NodeSignerStatus::Complete(signature) => signature,
+			NodeSignerStatus::InProgress => return Ok(InvoiceSigningStatus::Pending(raw_invoice)),
+		}
+	} else {
+		node_signer.sign_invoice(hrp_bytes, &data_without_signature, recipient)
+	};
+	let signed_raw_invoice = raw_invoice.sign(|_| signature);
 	match signed_raw_invoice {
-		Ok(inv) => Ok(Invoice::from_signed(inv).unwrap()),
+		Ok(inv) => Ok(InvoiceSigningStatus::Signed(Invoice::from_signed(inv).unwrap())),
 		Err(e) => Err(SignOrCreationError::SignError(e))
 	}
 }
@@ -805,7 +1160,12 @@ mod test {
 	use lightning::routing::router::{PaymentParameters, RouteParameters};
 	use lightning::util::test_utils;
 	use lightning::util::config::UserConfig;
-	use crate::utils::{create_invoice_from_channelmanager_and_duration_since_epoch, rotate_through_iterators};
+	use lightning::ln::inbound_payment::ExpandedKey;
+	use lightning::offers::offer::OfferBuilder;
+	use lightning::sign::{NodeSigner, Recipient};
+	use crate::utils::{create_invoice_from_channelmanager_and_duration_since_epoch, create_invoice_from_channelmanager_and_duration_since_epoch_async, rotate_through_iterators, sign_pending_invoice, InvoiceSigningStatus};
+	use secp256k1::{KeyPair, Secp256k1, SecretKey};
+	use core::convert::Infallible;
 	use std::collections::HashSet;
 
 	#[test]
@@ -896,6 +1256,84 @@ mod test {
 		assert_eq!(events.len(), 2);
 	}
 
+	#[test]
+	fn test_from_channelmanager_async() {
+		// Tests that an invoice created while our NodeSigner is still signing it is returned unsigned,
+		// and can be signed once the signer completed.
+		let chanmon_cfgs = create_chanmon_cfgs(2);
+		let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+		let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+		create_unannounced_chan_between_nodes_with_value(&nodes, 0, 1, 100000, 10001);
+
+		*nodes[1].keys_manager.sign_invoice_in_progress.lock().unwrap() = true;
+		let description = Description("test".to_string());
+		let status = create_invoice_from_channelmanager_and_duration_since_epoch_async(
+			nodes[1].node, nodes[1].keys_manager, nodes[1].logger, Currency::BitcoinTestnet,
+			Some(10_000), InvoiceDescription::Direct(&description), Duration::from_secs(1234567),
+			3600, None).unwrap();
+		let raw_invoice = match status {
+			InvoiceSigningStatus::Pending(raw_invoice) => raw_invoice,
+			InvoiceSigningStatus::Signed(_) => panic!("Expected the invoice to be pending"),
+		};
+
+		// While our signer is still signing, the invoice remains pending.
+		let status = sign_pending_invoice(raw_invoice.clone(), nodes[1].keys_manager, Recipient::Node).unwrap();
+		assert_eq!(status, InvoiceSigningStatus::Pending(raw_invoice.clone()));
+
+		*nodes[1].keys_manager.sign_invoice_in_progress.lock().unwrap() = false;
+		let invoice = match sign_pending_invoice(raw_invoice, nodes[1].keys_manager, Recipient::Node).unwrap() {
+			InvoiceSigningStatus::Signed(invoice) => invoice,
+			InvoiceSigningStatus::Pending(_) => panic!("Expected the invoice to be signed"),
+		};
+		assert_eq!(invoice.amount_pico_btc(), Some(100_000));
+		assert_eq!(invoice.description(), InvoiceDescription::Direct(&description));
+		assert_eq!(invoice.recover_payee_pub_key(), nodes[1].node.get_our_node_id());
+		assert!(invoice.check_signature().is_ok());
+	}
+
+	#[test]
+	fn test_create_invoice_from_channelmanager_with_template() {
+		let chanmon_cfgs = create_chanmon_cfgs(2);
+		let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+		let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+		create_unannounced_chan_between_nodes_with_value(&nodes, 0, 1, 100000, 10001);
+
+		let description_hash = crate::Sha256(Hash::hash("lnurl metadata".as_bytes()));
+		let invoice = crate::utils::create_invoice_from_channelmanager_with_template_and_duration_since_epoch(
+			nodes[1].node, nodes[1].keys_manager, nodes[1].logger, Currency::BitcoinTestnet,
+			Some(10_000), Duration::from_secs(1234567), |amt_msat| {
+				assert_eq!(amt_msat, Some(10_000));
+				crate::utils::InvoiceTemplate {
+					description: crate::utils::InvoiceTemplateDescription::Hash(description_hash),
+					payment_metadata: Some(vec![42; 8]),
+					invoice_expiry_delta_secs: 600,
+					min_final_cltv_expiry_delta: Some(MIN_FINAL_CLTV_EXPIRY_DELTA + 10),
+				}
+			}).unwrap();
+		assert_eq!(invoice.amount_pico_btc(), Some(100_000));
+		assert_eq!(invoice.description(), InvoiceDescription::Hash(&description_hash));
+		assert_eq!(invoice.payment_metadata(), Some(&vec![42; 8]));
+		assert!(invoice.features().unwrap().supports_payment_metadata());
+		assert_eq!(invoice.expiry_time(), Duration::from_secs(600));
+		assert_eq!(invoice.min_final_cltv_expiry_delta(), (MIN_FINAL_CLTV_EXPIRY_DELTA + 13) as u64);
+
+		// An invalid template is rejected just like invalid fixed arguments.
+		let res = crate::utils::create_invoice_from_channelmanager_with_template_and_duration_since_epoch(
+			nodes[1].node, nodes[1].keys_manager, nodes[1].logger, Currency::BitcoinTestnet,
+			None, Duration::from_secs(1234567), |_| crate::utils::InvoiceTemplate {
+				description: crate::utils::InvoiceTemplateDescription::Direct("test".to_string()),
+				payment_metadata: None,
+				invoice_expiry_delta_secs: 600,
+				min_final_cltv_expiry_delta: Some(MIN_FINAL_CLTV_EXPIRY_DELTA - 4),
+			});
+		match res {
+			Err(SignOrCreationError::CreationError(CreationError::MinFinalCltvExpiryDeltaTooShort)) => {},
+			_ => panic!(),
+		}
+	}
+
 	fn do_create_invoice_min_final_cltv_delta(with_custom_delta: bool) {
 		let chanmon_cfgs = create_chanmon_cfgs(2);
 		let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
@@ -968,6 +1406,126 @@ mod test {
 		assert_eq!(invoice.payment_hash(), &sha256::Hash::from_slice(&payment_hash.0[..]).unwrap());
 	}
 
+	#[test]
+	fn test_create_invoice_for_invoice_request() {
+		let chanmon_cfgs = create_chanmon_cfgs(2);
+		let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+		let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+		create_unannounced_chan_between_nodes_with_value(&nodes, 0, 1, 100000, 10001);
+
+		let secp_ctx = Secp256k1::new();
+		let payer_keys = KeyPair::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[42; 32]).unwrap());
+		let expanded_key = ExpandedKey::new(&nodes[1].keys_manager.get_inbound_payment_key_material());
+		let offer = OfferBuilder::deriving_signing_pubkey(
+			"coffee".into(), nodes[1].node.get_our_node_id(), &expanded_key, nodes[1].keys_manager, &secp_ctx
+		)
+			.amount_msats(10_000)
+			.build().unwrap();
+		let invoice_request = offer.request_invoice(vec![1; 32], payer_keys.public_key()).unwrap()
+			.build().unwrap()
+			.sign::<_, Infallible>(|digest| Ok(secp_ctx.sign_schnorr_no_aux_rand(digest, &payer_keys)))
+			.unwrap();
+
+		// Only the node which created the offer can answer the request.
+		match crate::utils::create_invoice_for_invoice_request_and_duration_since_epoch(
+			nodes[0].node, nodes[0].keys_manager, nodes[0].logger, Currency::BitcoinTestnet,
+			&invoice_request, None, Duration::from_secs(1234567), 3600, None,
+		) {
+			Err(crate::utils::InvoiceRequestResponseError::InvalidInvoiceRequest(_)) => {},
+			_ => panic!(),
+		}
+
+		// The payment hash of an existing invoice pipeline is settled like any other BOLT 11 payment.
+		let payment_preimage = PaymentPreimage([1; 32]);
+		let payment_hash = PaymentHash(Sha256::hash(&payment_preimage.0).into_inner());
+		let invoice = crate::utils::create_invoice_for_invoice_request_and_duration_since_epoch(
+			nodes[1].node, nodes[1].keys_manager, nodes[1].logger, Currency::BitcoinTestnet,
+			&invoice_request, Some(payment_hash), Duration::from_secs(1234567), 3600, None,
+		).unwrap();
+		assert_eq!(invoice.amount_pico_btc(), Some(100_000));
+		assert_eq!(invoice.description(), InvoiceDescription::Direct(&Description("coffee".to_string())));
+		assert_eq!(invoice.payment_hash(), &sha256::Hash::from_slice(&payment_hash.0[..]).unwrap());
+
+		let payment_params = PaymentParameters::from_node_id(invoice.recover_payee_pub_key(),
+				invoice.min_final_cltv_expiry_delta() as u32)
+			.with_bolt11_features(invoice.features().unwrap().clone()).unwrap()
+			.with_route_hints(invoice.route_hints()).unwrap();
+		let route_params = RouteParameters {
+			payment_params,
+			final_value_msat: invoice.amount_milli_satoshis().unwrap(),
+		};
+		nodes[0].node.send_payment(payment_hash,
+			RecipientOnionFields::secret_only(*invoice.payment_secret()),
+			PaymentId(payment_hash.0), route_params, Retry::Attempts(0)).unwrap();
+		{
+			let mut added_monitors = nodes[0].chain_monitor.added_monitors.lock().unwrap();
+			assert_eq!(added_monitors.len(), 1);
+			added_monitors.clear();
+		}
+		pass_along_route(&nodes[0], &[&[&nodes[1]]], 10_000, payment_hash, *invoice.payment_secret());
+		claim_payment(&nodes[0], &[&nodes[1]], payment_preimage);
+	}
+
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_create_invoice_for_encoded_invoice_request() {
+		// Payers without onion message support send the request out-of-band as an `lnr` string and
+		// are answered with the string of a BOLT 11 invoice.
+		let chanmon_cfgs = create_chanmon_cfgs(2);
+		let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+		let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+		create_unannounced_chan_between_nodes_with_value(&nodes, 0, 1, 100000, 10001);
+
+		let secp_ctx = Secp256k1::new();
+		let payer_keys = KeyPair::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[42; 32]).unwrap());
+		let expanded_key = ExpandedKey::new(&nodes[1].keys_manager.get_inbound_payment_key_material());
+		let offer = OfferBuilder::deriving_signing_pubkey(
+			"coffee".into(), nodes[1].node.get_our_node_id(), &expanded_key, nodes[1].keys_manager, &secp_ctx
+		)
+			.amount_msats(10_000)
+			.build().unwrap();
+		let encoded_invoice_request = offer.request_invoice(vec![1; 32], payer_keys.public_key()).unwrap()
+			.build().unwrap()
+			.sign::<_, Infallible>(|digest| Ok(secp_ctx.sign_schnorr_no_aux_rand(digest, &payer_keys)))
+			.unwrap()
+			.to_string();
+
+		match crate::utils::create_invoice_for_encoded_invoice_request(
+			nodes[1].node, nodes[1].keys_manager, nodes[1].logger, Currency::BitcoinTestnet,
+			&encoded_invoice_request[..encoded_invoice_request.len() - 1], None, 3600, None,
+		) {
+			Err(crate::utils::InvoiceRequestResponseError::InvalidEncoding(_)) => {},
+			_ => panic!(),
+		}
+
+		let encoded_invoice = crate::utils::create_invoice_for_encoded_invoice_request(
+			nodes[1].node, nodes[1].keys_manager, nodes[1].logger, Currency::BitcoinTestnet,
+			&encoded_invoice_request, None, 3600, None,
+		).unwrap().to_string();
+		let invoice = encoded_invoice.parse::<crate::Invoice>().unwrap();
+		assert_eq!(invoice.amount_milli_satoshis(), Some(10_000));
+		assert_eq!(invoice.description(), InvoiceDescription::Direct(&Description("coffee".to_string())));
+
+		let payment_hash = PaymentHash(invoice.payment_hash().into_inner());
+		let payment_params = PaymentParameters::from_node_id(invoice.recover_payee_pub_key(),
+				invoice.min_final_cltv_expiry_delta() as u32)
+			.with_bolt11_features(invoice.features().unwrap().clone()).unwrap()
+			.with_route_hints(invoice.route_hints()).unwrap();
+		let route_params = RouteParameters {
+			payment_params,
+			final_value_msat: invoice.amount_milli_satoshis().unwrap(),
+		};
+		nodes[0].node.send_payment(payment_hash,
+			RecipientOnionFields::secret_only(*invoice.payment_secret()),
+			PaymentId(payment_hash.0), route_params, Retry::Attempts(0)).unwrap();
+		check_added_monitors(&nodes[0], 1);
+		pass_along_route(&nodes[0], &[&[&nodes[1]]], 10_000, payment_hash, *invoice.payment_secret());
+		let payment_preimage = nodes[1].node.get_payment_preimage(payment_hash, *invoice.payment_secret()).unwrap();
+		claim_payment(&nodes[0], &[&nodes[1]], payment_preimage);
+	}
+
 	#[test]
 	fn test_hints_has_only_public_confd_channels() {
 		let chanmon_cfgs = create_chanmon_cfgs(2);
@@ -1915,6 +2473,40 @@ mod test {
 		}
 	}
 
+	#[test]
+	fn test_create_invoice_fails_with_too_short_expiry() {
+		let chanmon_cfgs = create_chanmon_cfgs(2);
+		let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+		let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+		let result = crate::utils::create_invoice_from_channelmanager_and_duration_since_epoch(
+			nodes[1].node, nodes[1].keys_manager, nodes[1].logger, Currency::BitcoinTestnet,
+			Some(10_000), "Some description".into(), Duration::from_secs(1234567),
+			crate::utils::MIN_INVOICE_EXPIRY_DELTA_SECS - 1, None,
+		);
+		match result {
+			Err(SignOrCreationError::CreationError(CreationError::ExpiryTooShort)) => {},
+			_ => panic!(),
+		}
+
+		let result = crate::utils::create_invoice_from_channelmanager_and_duration_since_epoch_with_payment_hash(
+			nodes[1].node, nodes[1].keys_manager, nodes[1].logger, Currency::BitcoinTestnet,
+			Some(10_000), "Some description".into(), Duration::from_secs(1234567),
+			crate::utils::MIN_INVOICE_EXPIRY_DELTA_SECS - 1, PaymentHash([42; 32]), None,
+		);
+		match result {
+			Err(SignOrCreationError::CreationError(CreationError::ExpiryTooShort)) => {},
+			_ => panic!(),
+		}
+
+		let invoice = crate::utils::create_invoice_from_channelmanager_and_duration_since_epoch(
+			nodes[1].node, nodes[1].keys_manager, nodes[1].logger, Currency::BitcoinTestnet,
+			Some(10_000), "Some description".into(), Duration::from_secs(1234567),
+			crate::utils::MIN_INVOICE_EXPIRY_DELTA_SECS, None,
+		).unwrap();
+		assert_eq!(invoice.expiry_time(), Duration::from_secs(crate::utils::MIN_INVOICE_EXPIRY_DELTA_SECS.into()));
+	}
+
 	#[test]
 	fn test_rotate_through_iterators() {
 		// two nested vectors
@@ -17,8 +17,10 @@
 //! (e.g. an [`Arc`]) and must use the [`SocketDescriptor`] provided here as the [`PeerManager`]'s
 //! `SocketDescriptor` implementation.
 //!
-//! Three methods are exposed to register a new connection for handling in [`tokio::spawn`] calls;
-//! see their individual docs for details.
+//! Four methods are exposed to register a new connection for handling in [`tokio::spawn`] calls;
+//! see their individual docs for details. Additionally, [`connect_outbound_with_config`] connects
+//! with a configurable timeout and returns a [`ConnectionHandle`] which can be used to disconnect
+//! the peer and wait for the connection to close.
 //!
 //! [`PeerManager`]: lightning::ln::peer_handler::PeerManager
 
@@ -40,18 +42,27 @@ use lightning::ln::peer_handler;
 use lightning::ln::peer_handler::SocketDescriptor as LnSocketTrait;
 use lightning::ln::peer_handler::APeerManager;
 use lightning::ln::msgs::NetAddress;
+use lightning::routing::gossip::{NetworkGraph, PeerAddressResolver};
+use lightning::util::logger::Logger;
 
+use std::future::Future;
 use std::ops::Deref;
+use std::pin::Pin;
 use std::task;
 use std::net::SocketAddr;
 use std::net::TcpStream as StdTcpStream;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, Weak};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 use std::hash::Hash;
 
 static ID_COUNTER: AtomicU64 = AtomicU64::new(0);
 
+// How long we wait after being woken before calling `PeerManager::process_events`. Any further
+// wakes which arrive in the meantime are coalesced into the same pass, so that a burst of
+// messages across many connections doesn't result in a `process_events` call for each one.
+const EVENT_BATCHING_WINDOW: Duration = Duration::from_millis(1);
+
 /// Connection contains all our internal state for a connection - we hold a reference to the
 /// Connection object (in an Arc<Mutex<>>) in each SocketDescriptor we create as well as in the
 /// read future (which is returned by schedule_read).
@@ -75,6 +86,9 @@ struct Connection {
 	read_waker: mpsc::Sender<()>,
 	read_paused: bool,
 	rl_requested_disconnect: bool,
+	// Set when the user asked us to disconnect via a ConnectionHandle, in which case the read task
+	// is woken via read_waker and closes the connection.
+	user_requested_disconnect: bool,
 	id: u64,
 }
 impl Connection {
@@ -86,6 +100,7 @@ impl Connection {
 			if event_receiver.recv().await.is_none() {
 				return;
 			}
+			time::sleep(EVENT_BATCHING_WINDOW).await;
 			peer_manager.as_ref().process_events();
 		}
 	}
@@ -117,7 +132,11 @@ impl Connection {
 			// closed.
 			// In this case, we do need to call peer_manager.socket_disconnected() to inform
 			// Rust-Lightning that the socket is gone.
-			PeerDisconnected
+			PeerDisconnected,
+			// The user told us to disconnect via ConnectionHandle::disconnect.
+			// As Rust-Lightning doesn't know about it, we need to call
+			// peer_manager.socket_disconnected() here too.
+			UserRequested,
 		}
 		let disconnect_type = loop {
 			let read_paused = {
@@ -125,6 +144,9 @@ impl Connection {
 				if us_lock.rl_requested_disconnect {
 					break Disconnect::CloseConnection;
 				}
+				if us_lock.user_requested_disconnect {
+					break Disconnect::UserRequested;
+				}
 				us_lock.read_paused
 			};
 			tokio::select! {
@@ -166,9 +188,12 @@ impl Connection {
 			// If the socket is already closed, shutdown() will fail, so just ignore it.
 			let _ = writer.shutdown().await;
 		}
-		if let Disconnect::PeerDisconnected = disconnect_type {
-			peer_manager.as_ref().socket_disconnected(&our_descriptor);
-			peer_manager.as_ref().process_events();
+		match disconnect_type {
+			Disconnect::CloseConnection => {},
+			Disconnect::PeerDisconnected|Disconnect::UserRequested => {
+				peer_manager.as_ref().socket_disconnected(&our_descriptor);
+				peer_manager.as_ref().process_events();
+			},
 		}
 	}
 
@@ -189,7 +214,7 @@ impl Connection {
 		(reader, write_receiver, read_receiver,
 		Arc::new(Mutex::new(Self {
 			writer: Some(writer), write_avail, read_waker, read_paused: false,
-			rl_requested_disconnect: false,
+			rl_requested_disconnect: false, user_requested_disconnect: false,
 			id: ID_COUNTER.fetch_add(1, Ordering::AcqRel)
 		})))
 	}
@@ -262,9 +287,19 @@ pub fn setup_outbound<PM: Deref + 'static + Send + Sync + Clone>(
 	their_node_id: PublicKey,
 	stream: StdTcpStream,
 ) -> impl std::future::Future<Output=()>
+where PM::Target: APeerManager<Descriptor = SocketDescriptor> {
+	do_setup_outbound(peer_manager, their_node_id, stream).1
+}
+
+fn do_setup_outbound<PM: Deref + 'static + Send + Sync + Clone>(
+	peer_manager: PM,
+	their_node_id: PublicKey,
+	stream: StdTcpStream,
+) -> (Weak<Mutex<Connection>>, impl std::future::Future<Output=()>)
 where PM::Target: APeerManager<Descriptor = SocketDescriptor> {
 	let remote_addr = get_addr_from_stream(&stream);
 	let (reader, mut write_receiver, read_receiver, us) = Connection::new(stream);
+	let weak_us = Arc::downgrade(&us);
 	#[cfg(test)]
 	let last_us = Arc::clone(&us);
 	let handle_opt = if let Ok(initial_send) = peer_manager.as_ref().new_outbound_connection(their_node_id, SocketDescriptor::new(us.clone()), remote_addr) {
@@ -301,7 +336,7 @@ where PM::Target: APeerManager<Descriptor = SocketDescriptor> {
 		None
 	};
 
-	async move {
+	(weak_us, async move {
 		if let Some(handle) = handle_opt {
 			if let Err(e) = handle.await {
 				assert!(e.is_cancelled());
@@ -315,7 +350,7 @@ where PM::Target: APeerManager<Descriptor = SocketDescriptor> {
 				debug_assert!(Arc::try_unwrap(last_us).is_ok());
 			}
 		}
-	}
+	})
 }
 
 /// Process incoming messages and feed outgoing messages on a new connection made to the given
@@ -340,6 +375,195 @@ where PM::Target: APeerManager<Descriptor = SocketDescriptor> {
 	} else { None }
 }
 
+/// Process incoming messages and feed outgoing messages on a new connection made to the given
+/// [`NetAddress`], e.g. as found in a peer's node_announcement, which is expected to be accepted by
+/// a peer with the given public key (by scheduling futures with tokio::spawn).
+///
+/// IP addresses are connected to directly. Hostnames are resolved via DNS, trying each resolved
+/// address in turn. Tor v3 onion addresses are connected to via the SOCKS5 proxy at `tor_proxy`,
+/// e.g. a local Tor daemon listening on `127.0.0.1:9050`, and are skipped if no proxy is given.
+/// Deprecated Tor v2 onion addresses are not supported.
+///
+/// See [`connect_outbound`] for details on the returned future.
+pub async fn connect_outbound_net_address<PM: Deref + 'static + Send + Sync + Clone>(
+	peer_manager: PM,
+	their_node_id: PublicKey,
+	addr: NetAddress,
+	tor_proxy: Option<SocketAddr>,
+) -> Option<impl std::future::Future<Output=()>>
+where PM::Target: APeerManager<Descriptor = SocketDescriptor> {
+	if let Ok(Ok(stream)) = time::timeout(Duration::from_secs(10), async { connect_stream(&addr, tor_proxy).await.map(|s| s.into_std().unwrap()) }).await {
+		Some(setup_outbound(peer_manager, their_node_id, stream))
+	} else { None }
+}
+
+/// Settings for connections made via [`connect_outbound_with_config`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConnectConfig {
+	/// How long we wait for the connection to be established, including any DNS resolution and
+	/// SOCKS5 proxy negotiation, before giving up.
+	///
+	/// Default value: 10 seconds
+	pub timeout: Duration,
+	/// The SOCKS5 proxy via which Tor v3 onion addresses are connected to, e.g. a local Tor daemon
+	/// listening on `127.0.0.1:9050`. If `None`, connecting to onion addresses fails.
+	///
+	/// Default value: `None`
+	pub tor_proxy: Option<SocketAddr>,
+}
+
+impl Default for ConnectConfig {
+	fn default() -> Self {
+		ConnectConfig { timeout: Duration::from_secs(10), tor_proxy: None }
+	}
+}
+
+/// A handle to a connection made via [`connect_outbound_with_config`].
+///
+/// The handle is a future which completes when the peer is disconnected and associated handling
+/// futures are freed, though, because all processing is spawned with tokio::spawn, you do not
+/// need to poll it in order to make progress. Dropping the handle does not disconnect the peer.
+pub struct ConnectionHandle {
+	connection: Weak<Mutex<Connection>>,
+	closed: Pin<Box<dyn Future<Output=()> + Send>>,
+}
+
+impl ConnectionHandle {
+	/// Disconnects the peer, informing the [`PeerManager`] as if the socket had been closed.
+	///
+	/// Await the handle afterwards to wait for the connection to have been torn down. Does
+	/// nothing if the connection has already closed.
+	///
+	/// [`PeerManager`]: lightning::ln::peer_handler::PeerManager
+	pub fn disconnect(&self) {
+		if let Some(connection) = self.connection.upgrade() {
+			let mut us = connection.lock().unwrap();
+			us.user_requested_disconnect = true;
+			let _ = us.read_waker.try_send(());
+		}
+	}
+}
+
+impl Future for ConnectionHandle {
+	type Output = ();
+	fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> task::Poll<()> {
+		self.closed.as_mut().poll(cx)
+	}
+}
+
+/// Process incoming messages and feed outgoing messages on a new connection made to the given
+/// [`NetAddress`], which is expected to be accepted by a peer with the given public key (by
+/// scheduling futures with tokio::spawn).
+///
+/// Addresses are connected to as described in [`connect_outbound_net_address`], with hostnames
+/// resolved asynchronously, but giving up after [`ConnectConfig::timeout`]. Unlike the other
+/// connect functions, the reason the connection failed is returned, with
+/// [`io::ErrorKind::TimedOut`] indicating a timeout.
+///
+/// On success, returns a [`ConnectionHandle`] which can be used to disconnect the peer and to
+/// wait for the connection to close.
+pub async fn connect_outbound_with_config<PM: Deref + 'static + Send + Sync + Clone>(
+	peer_manager: PM,
+	their_node_id: PublicKey,
+	addr: NetAddress,
+	config: ConnectConfig,
+) -> Result<ConnectionHandle, io::Error>
+where PM::Target: APeerManager<Descriptor = SocketDescriptor> {
+	let stream = match time::timeout(config.timeout, connect_stream(&addr, config.tor_proxy)).await {
+		Ok(res) => res?.into_std()?,
+		Err(_) => return Err(io::Error::new(io::ErrorKind::TimedOut, "Timed out connecting to peer")),
+	};
+	let (connection, closed) = do_setup_outbound(peer_manager, their_node_id, stream);
+	Ok(ConnectionHandle { connection, closed: Box::pin(closed) })
+}
+
+/// Connects to the peer with the given public key as described in
+/// [`connect_outbound_with_config`], trying each address returned by
+/// [`NetworkGraph::peer_addresses`] in turn until one succeeds.
+///
+/// This allows reconnecting to peers which have not announced any addresses, e.g. unannounced
+/// clients of an LSP, at the addresses given by the [`PeerAddressResolver`].
+///
+/// Returns the error of the last address tried, or an [`io::ErrorKind::NotFound`] error if no
+/// addresses are known for the peer.
+pub async fn connect_outbound_to_peer<PM: Deref + 'static + Send + Sync + Clone, L: Deref, R: Deref>(
+	peer_manager: PM,
+	their_node_id: PublicKey,
+	network_graph: &NetworkGraph<L>,
+	peer_address_resolver: &R,
+	config: ConnectConfig,
+) -> Result<ConnectionHandle, io::Error>
+where PM::Target: APeerManager<Descriptor = SocketDescriptor>, L::Target: Logger, R::Target: PeerAddressResolver {
+	let addresses = network_graph.peer_addresses(&their_node_id, peer_address_resolver);
+	let mut last_err = io::Error::new(io::ErrorKind::NotFound, "No known addresses for peer");
+	for addr in addresses {
+		match connect_outbound_with_config(peer_manager.clone(), their_node_id, addr, config).await {
+			Ok(handle) => return Ok(handle),
+			Err(e) => last_err = e,
+		}
+	}
+	Err(last_err)
+}
+
+/// Opens a TCP connection to the given [`NetAddress`], see [`connect_outbound_net_address`].
+async fn connect_stream(addr: &NetAddress, tor_proxy: Option<SocketAddr>) -> io::Result<TcpStream> {
+	match *addr {
+		NetAddress::IPv4 { addr, port } => TcpStream::connect((std::net::Ipv4Addr::from(addr), port)).await,
+		NetAddress::IPv6 { addr, port } => TcpStream::connect((std::net::Ipv6Addr::from(addr), port)).await,
+		NetAddress::Hostname { ref hostname, port } => {
+			// Connecting to a hostname tries each DNS result until one succeeds.
+			TcpStream::connect((hostname.as_str(), port)).await
+		},
+		NetAddress::OnionV3 { port, .. } => {
+			let proxy = tor_proxy.ok_or_else(|| io::Error::new(io::ErrorKind::Other, "No Tor proxy configured"))?;
+			let addr_string = addr.to_string();
+			let onion_host = addr_string.rsplitn(2, ':').nth(1).unwrap();
+			connect_via_socks5_proxy(proxy, onion_host, port).await
+		},
+		NetAddress::OnionV2(_) => Err(io::Error::new(io::ErrorKind::Other, "Tor v2 onion addresses are unsupported")),
+	}
+}
+
+/// Opens a connection to `host:port` via the SOCKS5 proxy at `proxy` using the unauthenticated
+/// `CONNECT` command of RFC 1928, letting the proxy resolve the host.
+async fn connect_via_socks5_proxy(proxy: SocketAddr, host: &str, port: u16) -> io::Result<TcpStream> {
+	let socks_error = |msg: &'static str| io::Error::new(io::ErrorKind::Other, msg);
+	if host.len() > 255 {
+		return Err(socks_error("Hostname too long for SOCKS5"));
+	}
+
+	let mut stream = TcpStream::connect(proxy).await?;
+	// Version 5, offering only the "no authentication" method.
+	stream.write_all(&[5, 1, 0]).await?;
+	let mut method_selection = [0; 2];
+	stream.read_exact(&mut method_selection).await?;
+	if method_selection != [5, 0] {
+		return Err(socks_error("SOCKS5 proxy requires authentication"));
+	}
+
+	// Version 5, CONNECT, reserved, and a domain name address.
+	let mut request = vec![5, 1, 0, 3, host.len() as u8];
+	request.extend_from_slice(host.as_bytes());
+	request.extend_from_slice(&port.to_be_bytes());
+	stream.write_all(&request).await?;
+
+	let mut reply = [0; 4];
+	stream.read_exact(&mut reply).await?;
+	if reply[0] != 5 || reply[1] != 0 {
+		return Err(socks_error("SOCKS5 proxy failed to connect"));
+	}
+	// Skip the address the proxy bound to, followed by its 2-byte port.
+	let bound_addr_len = match reply[3] {
+		1 => 4,
+		4 => 16,
+		3 => stream.read_u8().await? as usize,
+		_ => return Err(socks_error("SOCKS5 proxy replied with an unknown address type")),
+	};
+	let mut bound_addr = vec![0; bound_addr_len + 2];
+	stream.read_exact(&mut bound_addr).await?;
+	Ok(stream)
+}
+
 const SOCK_WAKER_VTABLE: task::RawWakerVTable =
 	task::RawWakerVTable::new(clone_socket_waker, wake_socket_waker, wake_socket_waker_by_ref, drop_socket_waker);
 
@@ -471,7 +695,7 @@ mod tests {
 	use lightning::ln::msgs::*;
 	use lightning::ln::peer_handler::{MessageHandler, PeerManager};
 	use lightning::ln::features::NodeFeatures;
-	use lightning::routing::gossip::NodeId;
+	use lightning::routing::gossip::{NetworkGraph, NodeId};
 	use lightning::events::*;
 	use lightning::util::test_utils::TestNodeSigner;
 	use bitcoin::Network;
@@ -502,6 +726,8 @@ mod tests {
 	impl RoutingMessageHandler for MsgHandler {
 		fn handle_node_announcement(&self, _msg: &NodeAnnouncement) -> Result<bool, LightningError> { Ok(false) }
 		fn handle_channel_announcement(&self, _msg: &ChannelAnnouncement) -> Result<bool, LightningError> { Ok(false) }
+		#[cfg(taproot)]
+		fn handle_channel_announcement_2(&self, _msg: &lightning::ln::msgs::ChannelAnnouncement2) -> Result<bool, LightningError> { Ok(false) }
 		fn handle_channel_update(&self, _msg: &ChannelUpdate) -> Result<bool, LightningError> { Ok(false) }
 		fn get_next_channel_announcement(&self, _starting_point: u64) -> Option<(ChannelAnnouncement, Option<ChannelUpdate>, Option<ChannelUpdate>)> { None }
 		fn get_next_node_announcement(&self, _starting_point: Option<&NodeId>) -> Option<NodeAnnouncement> { None }
@@ -522,6 +748,8 @@ mod tests {
 		fn handle_channel_ready(&self, _their_node_id: &PublicKey, _msg: &ChannelReady) {}
 		fn handle_shutdown(&self, _their_node_id: &PublicKey, _msg: &Shutdown) {}
 		fn handle_closing_signed(&self, _their_node_id: &PublicKey, _msg: &ClosingSigned) {}
+		fn handle_closing_complete(&self, _their_node_id: &PublicKey, _msg: &ClosingComplete) {}
+		fn handle_closing_sig(&self, _their_node_id: &PublicKey, _msg: &ClosingSig) {}
 		fn handle_update_add_htlc(&self, _their_node_id: &PublicKey, _msg: &UpdateAddHTLC) {}
 		fn handle_update_fulfill_htlc(&self, _their_node_id: &PublicKey, _msg: &UpdateFulfillHTLC) {}
 		fn handle_update_fail_htlc(&self, _their_node_id: &PublicKey, _msg: &UpdateFailHTLC) {}
@@ -542,6 +770,8 @@ mod tests {
 		fn handle_tx_init_rbf(&self, _their_node_id: &PublicKey, _msg: &TxInitRbf) {}
 		fn handle_tx_ack_rbf(&self, _their_node_id: &PublicKey, _msg: &TxAckRbf) {}
 		fn handle_tx_abort(&self, _their_node_id: &PublicKey, _msg: &TxAbort) {}
+		fn handle_peer_storage(&self, _their_node_id: &PublicKey, _msg: &PeerStorage) {}
+		fn handle_your_peer_storage(&self, _their_node_id: &PublicKey, _msg: &YourPeerStorage) {}
 		fn peer_disconnected(&self, their_node_id: &PublicKey) {
 			if *their_node_id == self.expected_pubkey {
 				self.disconnected_flag.store(true, Ordering::SeqCst);
@@ -708,4 +938,118 @@ mod tests {
 	async fn unthreaded_race_disconnect_accept() {
 		race_disconnect_accept().await;
 	}
+
+	async fn do_connection_handle_test() {
+		let secp_ctx = Secp256k1::new();
+		let a_key = SecretKey::from_slice(&[1; 32]).unwrap();
+		let b_key = SecretKey::from_slice(&[2; 32]).unwrap();
+		let a_pub = PublicKey::from_secret_key(&secp_ctx, &a_key);
+		let b_pub = PublicKey::from_secret_key(&secp_ctx, &b_key);
+
+		let (a_connected_sender, mut a_connected) = mpsc::channel(1);
+		let (a_disconnected_sender, mut a_disconnected) = mpsc::channel(1);
+		let a_handler = Arc::new(MsgHandler {
+			expected_pubkey: b_pub,
+			pubkey_connected: a_connected_sender,
+			pubkey_disconnected: a_disconnected_sender,
+			disconnected_flag: AtomicBool::new(false),
+			msg_events: Mutex::new(Vec::new()),
+		});
+		let a_manager = Arc::new(PeerManager::new(MessageHandler {
+			chan_handler: Arc::clone(&a_handler),
+			route_handler: Arc::clone(&a_handler),
+			onion_message_handler: Arc::new(lightning::ln::peer_handler::IgnoringMessageHandler{}),
+			custom_message_handler: Arc::new(lightning::ln::peer_handler::IgnoringMessageHandler{}),
+		}, 0, &[1; 32], Arc::new(TestLogger()), Arc::new(TestNodeSigner::new(a_key))));
+
+		let (b_connected_sender, mut b_connected) = mpsc::channel(1);
+		let (b_disconnected_sender, mut b_disconnected) = mpsc::channel(1);
+		let b_handler = Arc::new(MsgHandler {
+			expected_pubkey: a_pub,
+			pubkey_connected: b_connected_sender,
+			pubkey_disconnected: b_disconnected_sender,
+			disconnected_flag: AtomicBool::new(false),
+			msg_events: Mutex::new(Vec::new()),
+		});
+		let b_manager = Arc::new(PeerManager::new(MessageHandler {
+			chan_handler: Arc::clone(&b_handler),
+			route_handler: Arc::clone(&b_handler),
+			onion_message_handler: Arc::new(lightning::ln::peer_handler::IgnoringMessageHandler{}),
+			custom_message_handler: Arc::new(lightning::ln::peer_handler::IgnoringMessageHandler{}),
+		}, 0, &[2; 32], Arc::new(TestLogger()), Arc::new(TestNodeSigner::new(b_key))));
+
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let port = listener.local_addr().unwrap().port();
+		let accept = tokio::spawn(async move {
+			let (conn, _) = listener.accept().await.unwrap();
+			super::setup_inbound(b_manager, conn.into_std().unwrap()).await;
+		});
+
+		// Connect by hostname, resolving it asynchronously.
+		let addr = NetAddress::Hostname {
+			hostname: std::convert::TryFrom::try_from("localhost".to_owned()).unwrap(), port,
+		};
+		let config = super::ConnectConfig { timeout: Duration::from_secs(10), tor_proxy: None };
+		let handle = super::connect_outbound_with_config(Arc::clone(&a_manager), b_pub, addr, config).await.unwrap();
+
+		tokio::time::timeout(Duration::from_secs(10), a_connected.recv()).await.unwrap();
+		tokio::time::timeout(Duration::from_secs(1), b_connected.recv()).await.unwrap();
+
+		// Disconnecting via the handle informs both sides and completes the handle.
+		handle.disconnect();
+		tokio::time::timeout(Duration::from_secs(10), handle).await.unwrap();
+		tokio::time::timeout(Duration::from_secs(1), a_disconnected.recv()).await.unwrap();
+		tokio::time::timeout(Duration::from_secs(10), b_disconnected.recv()).await.unwrap();
+		accept.await.unwrap();
+
+		// Peers without any known addresses can't be connected to.
+		let network_graph = NetworkGraph::new(Network::Testnet, Arc::new(TestLogger()));
+		let err = super::connect_outbound_to_peer(Arc::clone(&a_manager), b_pub, &network_graph,
+			&lightning::ln::peer_handler::IgnoringMessageHandler{}, config).await;
+		assert_eq!(err.err().unwrap().kind(), std::io::ErrorKind::NotFound);
+
+		// Unsupported addresses fail immediately and tell us why.
+		let err = super::connect_outbound_with_config(a_manager, b_pub, NetAddress::OnionV2([0; 12]), config).await;
+		assert_eq!(err.err().unwrap().kind(), std::io::ErrorKind::Other);
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn threaded_connection_handle_test() {
+		do_connection_handle_test().await;
+	}
+
+	#[tokio::test]
+	async fn unthreaded_connection_handle_test() {
+		do_connection_handle_test().await;
+	}
+
+	#[tokio::test]
+	async fn socks5_proxy_connect() {
+		use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let proxy_addr = listener.local_addr().unwrap();
+		let onion_host = "pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd.onion";
+		let proxy = tokio::spawn(async move {
+			let (mut conn, _) = listener.accept().await.unwrap();
+			let mut method_selection = [0; 3];
+			conn.read_exact(&mut method_selection).await.unwrap();
+			assert_eq!(method_selection, [5, 1, 0]);
+			conn.write_all(&[5, 0]).await.unwrap();
+
+			let mut request = vec![0; 5 + onion_host.len() + 2];
+			conn.read_exact(&mut request).await.unwrap();
+			assert_eq!(request[..5], [5, 1, 0, 3, onion_host.len() as u8]);
+			assert_eq!(&request[5..5 + onion_host.len()], onion_host.as_bytes());
+			assert_eq!(request[5 + onion_host.len()..], 9735u16.to_be_bytes());
+			conn.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0]).await.unwrap();
+			conn.write_all(b"hello").await.unwrap();
+		});
+
+		let mut stream = super::connect_via_socks5_proxy(proxy_addr, onion_host, 9735).await.unwrap();
+		let mut data = [0; 5];
+		stream.read_exact(&mut data).await.unwrap();
+		assert_eq!(&data, b"hello");
+		proxy.await.unwrap();
+	}
 }
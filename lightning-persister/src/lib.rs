@@ -11,6 +11,9 @@
 #[cfg(ldk_bench)] extern crate criterion;
 
 mod util;
+pub mod wal;
+#[cfg(feature = "vss")]
+pub mod vss;
 
 extern crate lightning;
 extern crate bitcoin;
@@ -21,7 +24,7 @@ use bitcoin::hashes::hex::FromHex;
 use lightning::chain::channelmonitor::ChannelMonitor;
 use lightning::sign::{EntropySource, SignerProvider};
 use lightning::util::ser::{ReadableArgs, Writeable};
-use lightning::util::persist::KVStorePersister;
+use lightning::util::persist::{KVStorePersister, KVStoreReader, MigrationRegistry};
 use std::fs;
 use std::io::Cursor;
 use std::ops::Deref;
@@ -58,6 +61,9 @@ impl FilesystemPersister {
 	}
 
 	/// Read `ChannelMonitor`s from disk.
+	///
+	/// As this is the first object read on startup, any pending migrations of the persisted data
+	/// to the current schema version are run first, see [`MigrationRegistry`].
 	pub fn read_channelmonitors<ES: Deref, SP: Deref> (
 		&self, entropy_source: ES, signer_provider: SP
 	) -> std::io::Result<Vec<(BlockHash, ChannelMonitor<<SP::Target as SignerProvider>::Signer>)>>
@@ -65,6 +71,8 @@ impl FilesystemPersister {
 			ES::Target: EntropySource + Sized,
 			SP::Target: SignerProvider + Sized
 	{
+		MigrationRegistry::new().run(self)?;
+
 		let mut path = PathBuf::from(&self.path_to_channel_data);
 		path.push("monitors");
 		if !Path::new(&path).exists() {
@@ -130,6 +138,42 @@ impl KVStorePersister for FilesystemPersister {
 	}
 }
 
+impl KVStoreReader for FilesystemPersister {
+	fn read(&self, key: &str) -> std::io::Result<Option<Vec<u8>>> {
+		let mut path = PathBuf::from(&self.path_to_channel_data);
+		path.push(key);
+		match fs::read(path) {
+			Ok(contents) => Ok(Some(contents)),
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+			Err(e) => Err(e),
+		}
+	}
+
+	fn list(&self, prefix: &str) -> std::io::Result<Vec<String>> {
+		// Keys are only ever nested one directory deep, e.g. "monitors/{funding_txo}".
+		let (dir, file_prefix) = match prefix.rfind('/') {
+			Some(idx) => prefix.split_at(idx + 1),
+			None => ("", prefix),
+		};
+		let mut path = PathBuf::from(&self.path_to_channel_data);
+		path.push(dir);
+		if !Path::new(&path).exists() {
+			return Ok(Vec::new());
+		}
+		let mut res = Vec::new();
+		for file_option in fs::read_dir(path)? {
+			let file = file_option?;
+			if let Some(filename) = file.file_name().to_str() {
+				// Skip any temporary files left behind by an interrupted write.
+				if filename.starts_with(file_prefix) && !filename.ends_with(".tmp") {
+					res.push(format!("{}{}", dir, filename));
+				}
+			}
+		}
+		Ok(res)
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	extern crate lightning;
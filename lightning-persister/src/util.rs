@@ -3,7 +3,7 @@ extern crate winapi;
 
 use std::fs;
 use std::path::PathBuf;
-use std::io::BufWriter;
+use std::io::{BufWriter, Write};
 
 #[cfg(not(target_os = "windows"))]
 use std::os::unix::io::AsRawFd;
@@ -77,6 +77,33 @@ pub(crate) fn write_to_file<W: Writeable>(dest_file: PathBuf, data: &W) -> std::
 	Ok(())
 }
 
+/// Appends `data` to the end of `dest_file`, creating it if it does not exist yet, and fsyncs it
+/// before returning.
+///
+/// Unlike [`write_to_file`] this does not rewrite the file, so the cost of the write is only
+/// proportional to the size of `data`. It is, however, not atomic - a crash may leave a partial
+/// write of `data` at the end of the file, which callers must detect when reading it back.
+pub(crate) fn append_to_file(dest_file: PathBuf, data: &[u8]) -> std::io::Result<()> {
+	let parent_directory = dest_file.parent().unwrap();
+	fs::create_dir_all(parent_directory)?;
+	let created = !dest_file.exists();
+	let mut file = fs::OpenOptions::new().create(true).append(true).open(&dest_file)?;
+	file.write_all(data)?;
+	file.sync_data()?;
+	// If we just created the file, fsync the parent directory on Unix to make sure the file
+	// itself survives a power loss.
+	#[cfg(not(target_os = "windows"))]
+	{
+		if created {
+			let dir_file = fs::OpenOptions::new().read(true).open(parent_directory)?;
+			unsafe { libc::fsync(dir_file.as_raw_fd()); }
+		}
+	}
+	#[cfg(target_os = "windows")]
+	let _ = created;
+	Ok(())
+}
+
 #[cfg(test)]
 mod tests {
 	use lightning::util::ser::{Writer, Writeable};
@@ -0,0 +1,440 @@
+//! A [`KVStorePersister`] backed by a remote [Versioned Storage Service] (VSS) server.
+//!
+//! [`VssStore`] talks the VSS protocol, i.e., protobuf-encoded requests `POST`ed to the server's
+//! `getObject`, `putObjects` and `listKeyVersions` endpoints. Writes are conditional on the
+//! version of the object last seen, such that writes of an instance which missed another
+//! instance's write are rejected by the server rather than overwriting it.
+//!
+//! [`VssStore`] does not encrypt anything itself. As the server is generally untrusted, it should
+//! be wrapped in an [`EncryptedStore`], which encrypts all objects and obfuscates all keys
+//! client-side, such that the server learns neither the stored data nor which kind of object is
+//! stored under which key.
+//!
+//! Requests are sent via a [`VssTransport`], which applications provide by wrapping an HTTPS
+//! client.
+//!
+//! [Versioned Storage Service]: https://github.com/lightningdevkit/vss-server
+//! [`EncryptedStore`]: lightning::util::persist::EncryptedStore
+
+use lightning::util::persist::{KVStorePersister, KVStoreReader};
+use lightning::util::ser::Writeable;
+use std::collections::HashMap;
+use std::io;
+use std::sync::Mutex;
+
+/// The response to a request sent via a [`VssTransport`].
+pub struct VssHttpResponse {
+	/// The HTTP status code of the response.
+	pub status: u16,
+	/// The body of the response.
+	pub body: Vec<u8>,
+}
+
+/// A way of sending requests to a VSS server.
+pub trait VssTransport {
+	/// Sends `body` as an HTTP `POST` request with content type `application/octet-stream` to the
+	/// given endpoint of the VSS server, e.g., `getObject`, returning the server's response.
+	///
+	/// Requests must be sent over TLS, authenticating the server, as any credentials and the
+	/// versions of all objects would otherwise be exposed to, and could be tampered with by, the
+	/// network. Any authentication required by the server is the responsibility of the transport.
+	fn post(&self, endpoint: &str, body: &[u8]) -> io::Result<VssHttpResponse>;
+}
+
+/// A minimal encoder and decoder for the protobuf messages of the VSS protocol.
+mod proto {
+	use std::io;
+
+	const WIRE_TYPE_VARINT: u8 = 0;
+	const WIRE_TYPE_FIXED64: u8 = 1;
+	const WIRE_TYPE_LENGTH_DELIMITED: u8 = 2;
+	const WIRE_TYPE_FIXED32: u8 = 5;
+
+	pub(super) enum FieldValue<'a> {
+		Varint(u64),
+		LengthDelimited(&'a [u8]),
+		Fixed,
+	}
+
+	fn invalid_message() -> io::Error {
+		io::Error::new(io::ErrorKind::InvalidData, "Invalid protobuf message")
+	}
+
+	fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+		while value >= 0x80 {
+			buf.push((value as u8) | 0x80);
+			value >>= 7;
+		}
+		buf.push(value as u8);
+	}
+
+	fn read_varint(buf: &mut &[u8]) -> io::Result<u64> {
+		let mut value = 0u64;
+		for shift in (0..64).step_by(7) {
+			let (byte, rest) = buf.split_first().ok_or_else(invalid_message)?;
+			*buf = rest;
+			value |= ((byte & 0x7f) as u64) << shift;
+			if byte & 0x80 == 0 {
+				return Ok(value);
+			}
+		}
+		Err(invalid_message())
+	}
+
+	pub(super) fn write_varint_field(buf: &mut Vec<u8>, field: u32, value: u64) {
+		write_varint(buf, ((field as u64) << 3) | WIRE_TYPE_VARINT as u64);
+		write_varint(buf, value);
+	}
+
+	pub(super) fn write_bytes_field(buf: &mut Vec<u8>, field: u32, value: &[u8]) {
+		write_varint(buf, ((field as u64) << 3) | WIRE_TYPE_LENGTH_DELIMITED as u64);
+		write_varint(buf, value.len() as u64);
+		buf.extend_from_slice(value);
+	}
+
+	/// Splits a message into its fields, in the order they appear.
+	pub(super) fn read_fields(mut buf: &[u8]) -> io::Result<Vec<(u32, FieldValue)>> {
+		let mut fields = Vec::new();
+		while !buf.is_empty() {
+			let key = read_varint(&mut buf)?;
+			let field = (key >> 3) as u32;
+			let value = match (key & 0x7) as u8 {
+				WIRE_TYPE_VARINT => FieldValue::Varint(read_varint(&mut buf)?),
+				WIRE_TYPE_LENGTH_DELIMITED => {
+					let len = read_varint(&mut buf)? as usize;
+					if buf.len() < len {
+						return Err(invalid_message());
+					}
+					let (value, rest) = buf.split_at(len);
+					buf = rest;
+					FieldValue::LengthDelimited(value)
+				},
+				WIRE_TYPE_FIXED64 if buf.len() >= 8 => { buf = &buf[8..]; FieldValue::Fixed },
+				WIRE_TYPE_FIXED32 if buf.len() >= 4 => { buf = &buf[4..]; FieldValue::Fixed },
+				_ => return Err(invalid_message()),
+			};
+			fields.push((field, value));
+		}
+		Ok(fields)
+	}
+
+	pub(super) fn read_string(value: &[u8]) -> io::Result<String> {
+		String::from_utf8(value.to_vec()).map_err(|_| invalid_message())
+	}
+}
+
+/// The `KeyValue` message of the VSS protocol.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct KeyValue {
+	key: String,
+	version: i64,
+	value: Vec<u8>,
+}
+
+impl KeyValue {
+	fn encode(&self) -> Vec<u8> {
+		let mut buf = Vec::new();
+		proto::write_bytes_field(&mut buf, 1, self.key.as_bytes());
+		proto::write_varint_field(&mut buf, 2, self.version as u64);
+		proto::write_bytes_field(&mut buf, 3, &self.value);
+		buf
+	}
+
+	fn decode(buf: &[u8]) -> io::Result<Self> {
+		let mut res = KeyValue { key: String::new(), version: 0, value: Vec::new() };
+		for (field, value) in proto::read_fields(buf)? {
+			match (field, value) {
+				(1, proto::FieldValue::LengthDelimited(key)) => res.key = proto::read_string(key)?,
+				(2, proto::FieldValue::Varint(version)) => res.version = version as i64,
+				(3, proto::FieldValue::LengthDelimited(value)) => res.value = value.to_vec(),
+				_ => {},
+			}
+		}
+		Ok(res)
+	}
+}
+
+/// The `ErrorCode` of an `ErrorResponse` indicating a conditional write failed.
+const CONFLICT_EXCEPTION: u64 = 1;
+/// The `ErrorCode` of an `ErrorResponse` indicating the requested key does not exist.
+const NO_SUCH_KEY_EXCEPTION: u64 = 4;
+
+/// The number of keys requested at once when listing keys.
+const LIST_PAGE_SIZE: u64 = 100;
+
+/// A [`KVStorePersister`] and [`KVStoreReader`] storing all objects with a remote VSS server.
+///
+/// Objects are stored as given, so this should generally be wrapped in an [`EncryptedStore`].
+///
+/// Each write is conditional on the version of the object last read or written by this store, and
+/// fails if another client wrote the object in the meantime. Such a failure will repeat on any
+/// later write of the same object, as it indicates another instance of the node is writing to the
+/// same store, in which case this instance should shut down.
+///
+/// [`EncryptedStore`]: lightning::util::persist::EncryptedStore
+pub struct VssStore<T: VssTransport> {
+	transport: T,
+	store_id: String,
+	versions: Mutex<HashMap<String, i64>>,
+}
+
+impl<T: VssTransport> VssStore<T> {
+	/// Constructs a new store keeping its objects under `store_id` on the VSS server reached via
+	/// `transport`.
+	pub fn new(transport: T, store_id: String) -> Self {
+		Self { transport, store_id, versions: Mutex::new(HashMap::new()) }
+	}
+
+	/// Sends a request to the given endpoint, returning the response body on success or the
+	/// `ErrorCode` of the server's `ErrorResponse` otherwise.
+	fn request(&self, endpoint: &str, body: &[u8]) -> io::Result<Result<Vec<u8>, u64>> {
+		let response = self.transport.post(endpoint, body)?;
+		if response.status == 200 {
+			return Ok(Ok(response.body));
+		}
+		let mut error_code = None;
+		let mut message = String::new();
+		if let Ok(fields) = proto::read_fields(&response.body) {
+			for (field, value) in fields {
+				match (field, value) {
+					(1, proto::FieldValue::Varint(code)) => error_code = Some(code),
+					(2, proto::FieldValue::LengthDelimited(msg)) => message = String::from_utf8_lossy(msg).into_owned(),
+					_ => {},
+				}
+			}
+		}
+		match error_code {
+			Some(code) if code == CONFLICT_EXCEPTION || code == NO_SUCH_KEY_EXCEPTION => Ok(Err(code)),
+			_ => Err(io::Error::new(io::ErrorKind::Other,
+				format!("VSS request failed with status {}: {}", response.status, message))),
+		}
+	}
+
+	fn get_object(&self, key: &str) -> io::Result<Option<KeyValue>> {
+		let mut request = Vec::new();
+		proto::write_bytes_field(&mut request, 1, self.store_id.as_bytes());
+		proto::write_bytes_field(&mut request, 2, key.as_bytes());
+		let response = match self.request("getObject", &request)? {
+			Ok(response) => response,
+			Err(_) => return Ok(None),
+		};
+		for (field, value) in proto::read_fields(&response)? {
+			if let (2, proto::FieldValue::LengthDelimited(value)) = (field, value) {
+				return Ok(Some(KeyValue::decode(value)?));
+			}
+		}
+		Ok(None)
+	}
+}
+
+impl<T: VssTransport> KVStorePersister for VssStore<T> {
+	fn persist<W: Writeable>(&self, key: &str, object: &W) -> io::Result<()> {
+		let known_version = self.versions.lock().unwrap().get(key).cloned();
+		let version = match known_version {
+			Some(version) => version,
+			None => self.get_object(key)?.map_or(0, |object| object.version),
+		};
+
+		let mut request = Vec::new();
+		proto::write_bytes_field(&mut request, 1, self.store_id.as_bytes());
+		let item = KeyValue { key: key.to_owned(), version, value: object.encode() };
+		proto::write_bytes_field(&mut request, 3, &item.encode());
+		match self.request("putObjects", &request)? {
+			Ok(_) => {
+				self.versions.lock().unwrap().insert(key.to_owned(), version + 1);
+				Ok(())
+			},
+			Err(_) => {
+				self.versions.lock().unwrap().insert(key.to_owned(), version);
+				Err(io::Error::new(io::ErrorKind::Other, "Object was written by another client"))
+			},
+		}
+	}
+}
+
+impl<T: VssTransport> KVStoreReader for VssStore<T> {
+	fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+		let object = match self.get_object(key)? {
+			Some(object) => object,
+			None => return Ok(None),
+		};
+		self.versions.lock().unwrap().insert(key.to_owned(), object.version);
+		Ok(Some(object.value))
+	}
+
+	fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+		let mut keys = Vec::new();
+		let mut page_token: Option<String> = None;
+		loop {
+			let mut request = Vec::new();
+			proto::write_bytes_field(&mut request, 1, self.store_id.as_bytes());
+			if !prefix.is_empty() {
+				proto::write_bytes_field(&mut request, 2, prefix.as_bytes());
+			}
+			proto::write_varint_field(&mut request, 3, LIST_PAGE_SIZE);
+			if let Some(token) = &page_token {
+				proto::write_bytes_field(&mut request, 4, token.as_bytes());
+			}
+			let response = self.request("listKeyVersions", &request)?
+				.map_err(|_| io::Error::new(io::ErrorKind::Other, "Failed to list VSS keys"))?;
+
+			let mut page_len = 0;
+			page_token = None;
+			for (field, value) in proto::read_fields(&response)? {
+				match (field, value) {
+					(1, proto::FieldValue::LengthDelimited(key_version)) => {
+						page_len += 1;
+						let key_version = KeyValue::decode(key_version)?;
+						if key_version.key.starts_with(prefix) {
+							self.versions.lock().unwrap().insert(key_version.key.clone(), key_version.version);
+							keys.push(key_version.key);
+						}
+					},
+					(2, proto::FieldValue::LengthDelimited(token)) if !token.is_empty() => {
+						page_token = Some(proto::read_string(token)?);
+					},
+					_ => {},
+				}
+			}
+			if page_len == 0 || page_token.is_none() {
+				break;
+			}
+		}
+		Ok(keys)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{KeyValue, VssHttpResponse, VssStore, VssTransport, proto};
+	use lightning::sign::KeysManager;
+	use lightning::util::persist::{EncryptedStore, KVStorePersister, KVStoreReader};
+	use lightning::util::ser::Writeable;
+	use std::collections::HashMap;
+	use std::io;
+	use std::sync::Mutex;
+
+	/// An in-memory VSS server, storing the objects of all stores by key.
+	struct TestVssServer {
+		objects: Mutex<HashMap<String, (i64, Vec<u8>)>>,
+	}
+
+	impl TestVssServer {
+		fn error(code: u64) -> io::Result<VssHttpResponse> {
+			let mut body = Vec::new();
+			proto::write_varint_field(&mut body, 1, code);
+			Ok(VssHttpResponse { status: if code == 4 { 404 } else { 409 }, body })
+		}
+	}
+
+	impl<'a> VssTransport for &'a TestVssServer {
+		fn post(&self, endpoint: &str, body: &[u8]) -> io::Result<VssHttpResponse> {
+			let fields = proto::read_fields(body).unwrap();
+			let mut objects = self.objects.lock().unwrap();
+			let mut response = Vec::new();
+			match endpoint {
+				"getObject" => {
+					let key = fields.iter().find_map(|(field, value)| match (field, value) {
+						(2, proto::FieldValue::LengthDelimited(key)) => Some(proto::read_string(key).unwrap()),
+						_ => None,
+					}).unwrap();
+					match objects.get(&key) {
+						Some((version, value)) => {
+							let object = KeyValue { key, version: *version, value: value.clone() };
+							proto::write_bytes_field(&mut response, 2, &object.encode());
+						},
+						None => return TestVssServer::error(4),
+					}
+				},
+				"putObjects" => {
+					for (field, value) in fields {
+						if let (3, proto::FieldValue::LengthDelimited(item)) = (field, value) {
+							let item = KeyValue::decode(item).unwrap();
+							let current_version = objects.get(&item.key).map_or(0, |(version, _)| *version);
+							if item.version != current_version {
+								return TestVssServer::error(1);
+							}
+							objects.insert(item.key, (current_version + 1, item.value));
+						}
+					}
+				},
+				"listKeyVersions" => {
+					// Return one key per page to exercise pagination.
+					let page_token = fields.iter().find_map(|(field, value)| match (field, value) {
+						(4, proto::FieldValue::LengthDelimited(token)) => Some(proto::read_string(token).unwrap()),
+						_ => None,
+					});
+					let key_prefix = fields.iter().find_map(|(field, value)| match (field, value) {
+						(2, proto::FieldValue::LengthDelimited(prefix)) => Some(proto::read_string(prefix).unwrap()),
+						_ => None,
+					}).unwrap_or_default();
+					let mut keys: Vec<_> = objects.keys().filter(|key| key.starts_with(&key_prefix)).cloned().collect();
+					keys.sort();
+					if let Some(key) = keys.into_iter().find(|key| page_token.as_ref().map_or(true, |token| key > token)) {
+						let key_version = KeyValue { key: key.clone(), version: objects[&key].0, value: Vec::new() };
+						proto::write_bytes_field(&mut response, 1, &key_version.encode());
+						proto::write_bytes_field(&mut response, 2, key.as_bytes());
+					}
+				},
+				_ => panic!("Unexpected endpoint"),
+			}
+			Ok(VssHttpResponse { status: 200, body: response })
+		}
+	}
+
+	#[test]
+	fn persists_encrypted_objects() {
+		let server = TestVssServer { objects: Mutex::new(HashMap::new()) };
+		let keys_manager = KeysManager::new(&[0; 32], 42, 42);
+		let vss_store = VssStore::new(&server, "node".to_owned());
+		let store = EncryptedStore::new(&vss_store, [1; 32], &keys_manager);
+
+		assert_eq!(store.read("manager").unwrap(), None);
+		store.persist("manager", &vec![1u8; 10]).unwrap();
+		store.persist("manager", &vec![2u8; 10]).unwrap();
+		store.persist("monitors/a_0", &vec![3u8; 10]).unwrap();
+		store.persist("monitors/b_1", &vec![4u8; 10]).unwrap();
+		assert_eq!(store.read("manager").unwrap(), Some(vec![2u8; 10].encode()));
+
+		// Neither keys nor values are visible to the server.
+		for (key, (_, value)) in server.objects.lock().unwrap().iter() {
+			assert!(!key.contains("manager") && !key.contains("monitors"));
+			assert!(!value.windows(10).any(|window| window.iter().all(|byte| *byte == window[0])));
+		}
+
+		let mut monitors = store.list("monitors/").unwrap();
+		monitors.sort();
+		assert_eq!(monitors, vec!["monitors/a_0".to_owned(), "monitors/b_1".to_owned()]);
+	}
+
+	#[test]
+	fn lists_keys_by_prefix() {
+		let server = TestVssServer { objects: Mutex::new(HashMap::new()) };
+		let store = VssStore::new(&server, "node".to_owned());
+		store.persist("manager", &vec![1u8]).unwrap();
+		store.persist("monitors/a_0", &vec![2u8]).unwrap();
+		store.persist("monitors/b_1", &vec![3u8]).unwrap();
+
+		let mut monitors = store.list("monitors/").unwrap();
+		monitors.sort();
+		assert_eq!(monitors, vec!["monitors/a_0".to_owned(), "monitors/b_1".to_owned()]);
+		assert_eq!(store.list("").unwrap().len(), 3);
+	}
+
+	#[test]
+	fn rejects_writes_of_stale_instances() {
+		let server = TestVssServer { objects: Mutex::new(HashMap::new()) };
+		let store = VssStore::new(&server, "node".to_owned());
+		store.persist("manager", &vec![1u8]).unwrap();
+
+		// Another instance picks up the latest version of the object and writes it.
+		let other_store = VssStore::new(&server, "node".to_owned());
+		assert_eq!(other_store.read("manager").unwrap(), Some(vec![1u8].encode()));
+		other_store.persist("manager", &vec![2u8]).unwrap();
+
+		// The first instance missed that write and may no longer write the object.
+		assert!(store.persist("manager", &vec![3u8]).is_err());
+		assert!(store.persist("manager", &vec![3u8]).is_err());
+		assert_eq!(other_store.read("manager").unwrap(), Some(vec![2u8].encode()));
+	}
+}
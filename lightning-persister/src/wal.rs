@@ -0,0 +1,323 @@
+//! A [`ChannelMonitor`] persister which bounds the latency of persisting [`ChannelMonitorUpdate`]s.
+//!
+//! [`FilesystemPersister`] rewrites the full [`ChannelMonitor`] on every update, which for
+//! channels with a long history can mean writing (and fsync'ing) hundreds of KB while a payment
+//! is in flight. On mobile devices with slow storage this can stall channel operation for a
+//! noticeable amount of time.
+//!
+//! [`WalFilesystemPersister`] instead appends each serialized [`ChannelMonitorUpdate`] to a
+//! per-channel write-ahead log (WAL), which only requires writing a few KB, and compacts the log
+//! into a full [`ChannelMonitor`] write opportunistically - when the monitor is persisted due to
+//! chain sync, which is not on the critical path of channel operation, or when the log grows past
+//! a configurable number of updates. On startup, the logged updates are replayed onto the
+//! [`ChannelMonitor`]s read from disk via [`WalFilesystemPersister::read_channelmonitors`].
+//!
+//! Each WAL entry is stored as the big-endian length of the serialized update, followed by the
+//! first four bytes of its SHA256 and the update itself. An entry which was only partially written
+//! when we crashed can thus be detected and is discarded on read - as we never returned to the
+//! caller, the update was never irrevocably committed to.
+
+use bitcoin::hash_types::BlockHash;
+use bitcoin::hashes::Hash;
+use bitcoin::hashes::hex::ToHex;
+use bitcoin::hashes::sha256::Hash as Sha256;
+use lightning::chain;
+use lightning::chain::chaininterface::{BroadcasterInterface, FeeEstimator};
+use lightning::chain::chainmonitor::{MonitorUpdateId, Persist};
+use lightning::chain::channelmonitor::{ChannelMonitor, ChannelMonitorUpdate, CLOSED_CHANNEL_UPDATE_ID};
+use lightning::chain::transaction::OutPoint;
+use lightning::sign::{EntropySource, SignerProvider, WriteableEcdsaChannelSigner};
+use lightning::util::logger::Logger;
+use lightning::util::ser::{Readable, Writeable};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Cursor;
+use std::ops::Deref;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::FilesystemPersister;
+use crate::util;
+
+/// The default number of [`ChannelMonitorUpdate`]s which a channel's write-ahead log may hold
+/// before it is compacted into a full [`ChannelMonitor`] write.
+pub const DEFAULT_MAX_PENDING_UPDATES: usize = 64;
+
+const WAL_ENTRY_HEADER_LEN: usize = 8;
+
+/// A [`Persist`] implementation which writes [`ChannelMonitorUpdate`]s to a per-channel
+/// write-ahead log rather than rewriting the full [`ChannelMonitor`], see the [module-level
+/// documentation] for details.
+///
+/// Full [`ChannelMonitor`]s are stored in the same location and format as with the wrapped
+/// [`FilesystemPersister`], with the logs stored in a `monitor_updates` directory next to them.
+/// The wrapped [`FilesystemPersister`], available via [`Self::filesystem_persister`], should still
+/// be used to persist the `ChannelManager`, network graph and scorer.
+///
+/// Note that [`FilesystemPersister::read_channelmonitors`] does not replay the logged updates, so
+/// [`Self::read_channelmonitors`] must be used to read the [`ChannelMonitor`]s on startup instead.
+///
+/// [module-level documentation]: crate::wal
+pub struct WalFilesystemPersister {
+	persister: FilesystemPersister,
+	max_pending_updates: usize,
+	/// The number of entries in each channel's write-ahead log. Also serializes all writes, such
+	/// that a compaction never races with an append of an update it does not include.
+	pending_updates: Mutex<HashMap<OutPoint, usize>>,
+}
+
+impl WalFilesystemPersister {
+	/// Initialize a new WalFilesystemPersister writing to the same location as the given
+	/// [`FilesystemPersister`], compacting a channel's log once it holds `max_pending_updates`
+	/// updates.
+	pub fn new(persister: FilesystemPersister, max_pending_updates: usize) -> Self {
+		Self {
+			persister,
+			max_pending_updates,
+			pending_updates: Mutex::new(HashMap::new()),
+		}
+	}
+
+	/// Get the wrapped [`FilesystemPersister`].
+	pub fn filesystem_persister(&self) -> &FilesystemPersister {
+		&self.persister
+	}
+
+	fn monitor_path(&self, funding_txo: OutPoint) -> PathBuf {
+		let mut path = PathBuf::from(self.persister.get_data_dir());
+		path.push("monitors");
+		path.push(format!("{}_{}", funding_txo.txid.to_hex(), funding_txo.index));
+		path
+	}
+
+	fn wal_path(&self, funding_txo: OutPoint) -> PathBuf {
+		let mut path = PathBuf::from(self.persister.get_data_dir());
+		path.push("monitor_updates");
+		path.push(format!("{}_{}", funding_txo.txid.to_hex(), funding_txo.index));
+		path
+	}
+
+	/// Read `ChannelMonitor`s from disk, replaying any updates in their write-ahead logs.
+	///
+	/// Any partially-written entry at the end of a log is discarded, and the log truncated such
+	/// that later updates are appended after the last complete entry.
+	pub fn read_channelmonitors<ES: Deref, SP: Deref, B: Deref, F: Deref, L: Deref>(
+		&self, entropy_source: ES, signer_provider: SP, broadcaster: B, fee_estimator: F, logger: L,
+	) -> std::io::Result<Vec<(BlockHash, ChannelMonitor<<SP::Target as SignerProvider>::Signer>)>>
+		where
+			ES::Target: EntropySource + Sized,
+			SP::Target: SignerProvider + Sized,
+			B::Target: BroadcasterInterface,
+			F::Target: FeeEstimator,
+			L::Target: Logger,
+	{
+		let monitors = self.persister.read_channelmonitors(entropy_source, signer_provider)?;
+		let mut pending_updates = self.pending_updates.lock().unwrap();
+		for (_, monitor) in monitors.iter() {
+			let funding_txo = monitor.get_funding_txo().0;
+			let updates = self.read_wal(funding_txo)?;
+			pending_updates.insert(funding_txo, updates.len());
+			for update in updates {
+				// Updates already included in the full monitor were logged before a compaction
+				// which we crashed in the middle of, and can be skipped.
+				if update.update_id <= monitor.get_latest_update_id() {
+					continue;
+				}
+				monitor.update_monitor(&update, &broadcaster, &*fee_estimator, &logger)
+					.map_err(|()| std::io::Error::new(std::io::ErrorKind::InvalidData,
+						"Failed to replay ChannelMonitorUpdate"))?;
+			}
+		}
+		Ok(monitors)
+	}
+
+	fn read_wal(&self, funding_txo: OutPoint) -> std::io::Result<Vec<ChannelMonitorUpdate>> {
+		let path = self.wal_path(funding_txo);
+		let contents = match fs::read(&path) {
+			Ok(contents) => contents,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+			Err(e) => return Err(e),
+		};
+		let mut updates = Vec::new();
+		let mut pos = 0;
+		while pos < contents.len() {
+			let entry_start = pos + WAL_ENTRY_HEADER_LEN;
+			if entry_start > contents.len() { break; }
+			let mut len_bytes = [0; 4];
+			len_bytes.copy_from_slice(&contents[pos..pos + 4]);
+			let entry_end = entry_start + u32::from_be_bytes(len_bytes) as usize;
+			if entry_end > contents.len() { break; }
+			let entry = &contents[entry_start..entry_end];
+			if Sha256::hash(entry)[..4] != contents[pos + 4..entry_start] {
+				if entry_end == contents.len() { break; }
+				return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+					"Corrupted ChannelMonitorUpdate log entry"));
+			}
+			let update = <ChannelMonitorUpdate as Readable>::read(&mut Cursor::new(entry))
+				.map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData,
+					format!("Failed to deserialize ChannelMonitorUpdate: {}", e)))?;
+			updates.push(update);
+			pos = entry_end;
+		}
+		if pos < contents.len() {
+			// We crashed while appending the last entry, drop it.
+			let file = fs::OpenOptions::new().write(true).open(&path)?;
+			file.set_len(pos as u64)?;
+			file.sync_all()?;
+		}
+		Ok(updates)
+	}
+
+	fn append_update(&self, funding_txo: OutPoint, update: &ChannelMonitorUpdate) -> std::io::Result<()> {
+		let serialized = update.encode();
+		let mut entry = Vec::with_capacity(WAL_ENTRY_HEADER_LEN + serialized.len());
+		entry.extend_from_slice(&(serialized.len() as u32).to_be_bytes());
+		entry.extend_from_slice(&Sha256::hash(&serialized)[..4]);
+		entry.extend_from_slice(&serialized);
+		util::append_to_file(self.wal_path(funding_txo), &entry)
+	}
+
+	fn write_monitor<ChannelSigner: WriteableEcdsaChannelSigner>(
+		&self, funding_txo: OutPoint, monitor: &ChannelMonitor<ChannelSigner>
+	) -> std::io::Result<()> {
+		util::write_to_file(self.monitor_path(funding_txo), monitor)?;
+		// Only remove the log once the full monitor is safely on disk. If we crash before doing
+		// so, the stale entries are skipped on replay.
+		match fs::remove_file(self.wal_path(funding_txo)) {
+			Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+			_ => Ok(()),
+		}
+	}
+}
+
+impl<ChannelSigner: WriteableEcdsaChannelSigner> Persist<ChannelSigner> for WalFilesystemPersister {
+	fn persist_new_channel(&self, funding_txo: OutPoint, monitor: &ChannelMonitor<ChannelSigner>, _update_id: MonitorUpdateId) -> chain::ChannelMonitorUpdateStatus {
+		let mut pending_updates = self.pending_updates.lock().unwrap();
+		match self.write_monitor(funding_txo, monitor) {
+			Ok(()) => {
+				pending_updates.insert(funding_txo, 0);
+				chain::ChannelMonitorUpdateStatus::Completed
+			},
+			Err(_) => chain::ChannelMonitorUpdateStatus::PermanentFailure,
+		}
+	}
+
+	fn update_persisted_channel(&self, funding_txo: OutPoint, update: Option<&ChannelMonitorUpdate>, monitor: &ChannelMonitor<ChannelSigner>, _update_id: MonitorUpdateId) -> chain::ChannelMonitorUpdateStatus {
+		let mut pending_updates = self.pending_updates.lock().unwrap();
+		let pending = pending_updates.entry(funding_txo).or_insert(0);
+		let res = match update {
+			// Updates after the channel closed all share the same update_id and thus cannot be
+			// replayed in order, so we always write the full monitor for them.
+			Some(update) if *pending < self.max_pending_updates && update.update_id != CLOSED_CHANNEL_UPDATE_ID => {
+				self.append_update(funding_txo, update).map(|()| *pending += 1)
+			},
+			_ => self.write_monitor(funding_txo, monitor).map(|()| *pending = 0),
+		};
+		match res {
+			Ok(()) => chain::ChannelMonitorUpdateStatus::Completed,
+			Err(_) => chain::ChannelMonitorUpdateStatus::PermanentFailure,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::FilesystemPersister;
+	use super::WalFilesystemPersister;
+	use lightning::{check_closed_broadcast, check_closed_event, check_added_monitors};
+	use lightning::chain::channelmonitor::CLOSED_CHANNEL_UPDATE_ID;
+	use lightning::events::{ClosureReason, MessageSendEventsProvider};
+	use lightning::ln::functional_test_utils::*;
+	use lightning::util::test_utils;
+	use std::fs;
+	use std::io::Write;
+
+	fn wal_files(persister: &WalFilesystemPersister) -> Vec<std::path::PathBuf> {
+		let mut path = std::path::PathBuf::from(persister.filesystem_persister().get_data_dir());
+		path.push("monitor_updates");
+		match fs::read_dir(path) {
+			Ok(entries) => entries.map(|entry| entry.unwrap().path()).collect(),
+			Err(_) => Vec::new(),
+		}
+	}
+
+	// Test that updates are logged rather than written as full monitors, that they are replayed
+	// on read, and that a partially-written entry at the end of the log is discarded.
+	#[test]
+	fn test_wal_persister_replays_updates() {
+		let persister_0 = WalFilesystemPersister::new(FilesystemPersister::new("test_wal_persister_replays_0".to_string()), 1000);
+		let persister_1 = WalFilesystemPersister::new(FilesystemPersister::new("test_wal_persister_replays_1".to_string()), 1000);
+		let chanmon_cfgs = create_chanmon_cfgs(2);
+		let mut node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+		let chain_mon_0 = test_utils::TestChainMonitor::new(Some(&chanmon_cfgs[0].chain_source), &chanmon_cfgs[0].tx_broadcaster, &chanmon_cfgs[0].logger, &chanmon_cfgs[0].fee_estimator, &persister_0, node_cfgs[0].keys_manager);
+		let chain_mon_1 = test_utils::TestChainMonitor::new(Some(&chanmon_cfgs[1].chain_source), &chanmon_cfgs[1].tx_broadcaster, &chanmon_cfgs[1].logger, &chanmon_cfgs[1].fee_estimator, &persister_1, node_cfgs[1].keys_manager);
+		node_cfgs[0].chain_monitor = chain_mon_0;
+		node_cfgs[1].chain_monitor = chain_mon_1;
+		let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+		let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+		let _ = create_announced_chan_between_nodes(&nodes, 0, 1);
+		send_payment(&nodes[0], &vec!(&nodes[1])[..], 8000000);
+
+		macro_rules! check_persisted_data {
+			($persister: expr, $node: expr, $expected_update_id: expr) => {
+				let monitors = $persister.read_channelmonitors($node.keys_manager, $node.keys_manager,
+					$node.tx_broadcaster, $node.fee_estimator, $node.logger).unwrap();
+				assert_eq!(monitors.len(), 1);
+				assert_eq!(monitors[0].1.get_latest_update_id(), $expected_update_id);
+			}
+		}
+
+		// The full monitors on disk are stale, but the logged updates bring them up to date.
+		let full_monitors = persister_0.filesystem_persister().read_channelmonitors(nodes[0].keys_manager, nodes[0].keys_manager).unwrap();
+		assert!(full_monitors[0].1.get_latest_update_id() < 5);
+		check_persisted_data!(persister_0, nodes[0], 5);
+		check_persisted_data!(persister_1, nodes[1], 5);
+
+		// Simulate a crash in the middle of appending an update.
+		let wal_file = wal_files(&persister_0).pop().unwrap();
+		let wal_len = fs::metadata(&wal_file).unwrap().len();
+		fs::OpenOptions::new().append(true).open(&wal_file).unwrap().write_all(&[0, 0, 1, 0, 42]).unwrap();
+		check_persisted_data!(persister_0, nodes[0], 5);
+		assert_eq!(fs::metadata(&wal_file).unwrap().len(), wal_len);
+
+		// Updates appended after recovery are still replayed.
+		send_payment(&nodes[1], &vec!(&nodes[0])[..], 4000000);
+		check_persisted_data!(persister_0, nodes[0], 10);
+
+		// Updates after the channel is closed are always written as full monitors.
+		nodes[0].node.force_close_broadcasting_latest_txn(&nodes[0].node.list_channels()[0].channel_id, &nodes[1].node.get_our_node_id()).unwrap();
+		check_closed_event!(nodes[0], 1, ClosureReason::HolderForceClosed);
+		check_closed_broadcast!(nodes[0], true);
+		check_added_monitors!(nodes[0], 1);
+		assert!(wal_files(&persister_0).is_empty());
+		check_persisted_data!(persister_0, nodes[0], CLOSED_CHANNEL_UPDATE_ID);
+	}
+
+	// Test that a channel's log is compacted into a full monitor once it holds the maximum number
+	// of pending updates.
+	#[test]
+	fn test_wal_persister_compaction() {
+		let persister_0 = WalFilesystemPersister::new(FilesystemPersister::new("test_wal_persister_compaction_0".to_string()), 2);
+		let persister_1 = WalFilesystemPersister::new(FilesystemPersister::new("test_wal_persister_compaction_1".to_string()), 2);
+		let chanmon_cfgs = create_chanmon_cfgs(2);
+		let mut node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+		let chain_mon_0 = test_utils::TestChainMonitor::new(Some(&chanmon_cfgs[0].chain_source), &chanmon_cfgs[0].tx_broadcaster, &chanmon_cfgs[0].logger, &chanmon_cfgs[0].fee_estimator, &persister_0, node_cfgs[0].keys_manager);
+		let chain_mon_1 = test_utils::TestChainMonitor::new(Some(&chanmon_cfgs[1].chain_source), &chanmon_cfgs[1].tx_broadcaster, &chanmon_cfgs[1].logger, &chanmon_cfgs[1].fee_estimator, &persister_1, node_cfgs[1].keys_manager);
+		node_cfgs[0].chain_monitor = chain_mon_0;
+		node_cfgs[1].chain_monitor = chain_mon_1;
+		let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+		let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+		let _ = create_announced_chan_between_nodes(&nodes, 0, 1);
+		send_payment(&nodes[0], &vec!(&nodes[1])[..], 8000000);
+
+		// Of the five updates, the first three were compacted into the full monitor, the third
+		// one triggering the compaction, while the last two remain in the log.
+		let full_monitors = persister_0.filesystem_persister().read_channelmonitors(nodes[0].keys_manager, nodes[0].keys_manager).unwrap();
+		assert_eq!(full_monitors[0].1.get_latest_update_id(), 3);
+		let monitors = persister_0.read_channelmonitors(nodes[0].keys_manager, nodes[0].keys_manager,
+			nodes[0].tx_broadcaster, nodes[0].fee_estimator, nodes[0].logger).unwrap();
+		assert_eq!(monitors[0].1.get_latest_update_id(), 5);
+	}
+}
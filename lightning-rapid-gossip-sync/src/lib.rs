@@ -75,7 +75,8 @@ use core::ops::Deref;
 use core::sync::atomic::{AtomicBool, Ordering};
 
 use lightning::io;
-use lightning::routing::gossip::NetworkGraph;
+use lightning::routing::gossip::{GossipBatchResult, GossipMessage, GossipVerification, NetworkGraph};
+use lightning::routing::utxo::UtxoLookup;
 use lightning::util::logger::Logger;
 
 pub use crate::error::GraphSyncError;
@@ -95,7 +96,8 @@ pub struct RapidGossipSync<NG: Deref<Target=NetworkGraph<L>>, L: Deref>
 where L::Target: Logger {
 	network_graph: NG,
 	logger: L,
-	is_initial_sync_complete: AtomicBool
+	is_initial_sync_complete: AtomicBool,
+	gossip_verification: GossipVerification,
 }
 
 impl<NG: Deref<Target=NetworkGraph<L>>, L: Deref> RapidGossipSync<NG, L> where L::Target: Logger {
@@ -104,10 +106,43 @@ impl<NG: Deref<Target=NetworkGraph<L>>, L: Deref> RapidGossipSync<NG, L> where L
 		Self {
 			network_graph,
 			logger,
-			is_initial_sync_complete: AtomicBool::new(false)
+			is_initial_sync_complete: AtomicBool::new(false),
+			gossip_verification: GossipVerification::Full,
 		}
 	}
 
+	/// Sets how the signatures of gossip messages applied via
+	/// [`RapidGossipSync::update_network_graph_from_gossip_messages`] are verified, which is
+	/// [`GossipVerification::Full`] by default.
+	///
+	/// As the rapid gossip sync server is already trusted to provide an accurate view of the
+	/// network, signed gossip fetched from it can generally be verified by
+	/// [`GossipVerification::Sampled`] instead.
+	pub fn set_gossip_verification(&mut self, verification: GossipVerification) {
+		self.gossip_verification = verification;
+	}
+
+	/// Applies a batch of signed gossip messages, e.g. a full gossip dump fetched from the rapid
+	/// gossip sync server, verifying their signatures as set via
+	/// [`RapidGossipSync::set_gossip_verification`].
+	///
+	/// Unlike the unsigned rapid gossip sync data, messages whose signatures were verified can be
+	/// relayed to our peers. Marks the initial sync as complete on success.
+	///
+	/// See [`NetworkGraph::update_from_gossip_batch`] for details, including the requirements on
+	/// `random_seed_bytes`.
+	pub fn update_network_graph_from_gossip_messages<U: Deref>(
+		&self, messages: &[GossipMessage], utxo_lookup: &Option<U>, random_seed_bytes: &[u8; 32],
+	) -> Result<GossipBatchResult, GraphSyncError>
+	where
+		U::Target: UtxoLookup,
+	{
+		let result = self.network_graph.update_from_gossip_batch(
+			messages, self.gossip_verification, utxo_lookup, random_seed_bytes)?;
+		self.is_initial_sync_complete.store(true, Ordering::Release);
+		Ok(result)
+	}
+
 	/// Sync gossip data from a file.
 	/// Returns the last sync timestamp to be used the next time rapid sync data is queried.
 	///
@@ -16,6 +16,9 @@ pub(crate) struct SyncState {
 	pub last_sync_hash: Option<BlockHash>,
 	// Indicates whether we need to resync, e.g., after encountering an error.
 	pub pending_sync: bool,
+	// Unconfirmed transactions that were handed to mempool listeners during our last mempool
+	// sync, so that they're not handed over again.
+	pub seen_mempool_txids: HashSet<Txid>,
 }
 
 impl SyncState {
@@ -25,11 +28,23 @@ impl SyncState {
 			watched_outputs: HashMap::new(),
 			last_sync_hash: None,
 			pending_sync: false,
+			seen_mempool_txids: HashSet::new(),
 		}
 	}
 }
 
 
+// The heights observed while syncing, kept apart from the `SyncState` such that they can be
+// queried while a sync is in progress.
+#[derive(Default)]
+pub(crate) struct SyncHeights {
+	// The height of the tip we last finished syncing to.
+	pub synced_height: u32,
+	// The height of the most recent tip we've seen.
+	pub tip_height: u32,
+}
+
+
 // A queue that is to be filled by `Filter` and drained during the next syncing round.
 pub(crate) struct FilterQueue {
 	// Transactions that were registered via the `Filter` interface and have to be processed.
@@ -46,6 +61,11 @@ impl FilterQueue {
 		}
 	}
 
+	// Returns the number of transactions and outputs waiting to be processed.
+	pub fn len(&self) -> usize {
+		self.transactions.len() + self.outputs.len()
+	}
+
 	// Processes the transaction and output queues and adds them to the given [`SyncState`].
 	//
 	// Returns `true` if new items had been registered.
@@ -0,0 +1,643 @@
+use crate::error::{TxSyncError, InternalError};
+use crate::common::{SyncState, SyncHeights, FilterQueue, ConfirmedTx};
+
+use lightning::util::logger::Logger;
+use lightning::{log_error, log_info, log_debug, log_trace};
+use lightning::chain::WatchedOutput;
+use lightning::chain::{Confirm, Filter, SyncProgress};
+
+use bitcoin::{BlockHash, OutPoint, Script, Transaction, TxMerkleNode, Txid};
+use bitcoin::hashes::Hash;
+use bitcoin::hashes::sha256d::Hash as Sha256d;
+
+use electrum_client::Client as ElectrumClient;
+use electrum_client::{ElectrumApi, GetHistoryRes, GetMerkleRes, HeaderNotification};
+
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use core::ops::Deref;
+
+// The maximum number of requests we group into a single batch, to avoid running into any
+// server-side limits.
+const MAX_BATCH_SIZE: usize = 100;
+
+/// The health of the connection of an [`ElectrumSyncClient`] to its configured servers, as handed
+/// to [`ElectrumSyncConfig::status_callback`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ElectrumSyncStatus {
+	/// A request to the server at `server_url` failed.
+	///
+	/// If `retry_in` is set, the failed operation is retried via the next configured server after
+	/// the given delay. Otherwise, all retries are exhausted and the operation failed.
+	Degraded {
+		/// The URL of the server a request to which failed.
+		server_url: String,
+		/// The number of consecutive failed attempts of the current operation.
+		failed_attempts: u8,
+		/// The delay after which the operation is retried, if it is retried at all.
+		retry_in: Option<Duration>,
+	},
+	/// An operation succeeded via the server at `server_url` after syncing was previously
+	/// [`ElectrumSyncStatus::Degraded`].
+	Recovered {
+		/// The URL of the server via which the operation succeeded.
+		server_url: String,
+	},
+}
+
+/// Configuration of an [`ElectrumSyncClient`], as given to [`ElectrumSyncClient::with_config`].
+pub struct ElectrumSyncConfig {
+	/// The number of times connecting and [`ElectrumSyncClient::sync`] are retried after failing,
+	/// e.g., due to a server being unreachable. Every retry is made via the next configured server.
+	pub max_retries: u8,
+	/// The delay before the first retry, which is doubled on every subsequent retry.
+	pub initial_backoff: Duration,
+	/// The upper bound of the delay before any retry.
+	pub max_backoff: Duration,
+	/// A callback which is informed whenever syncing is degraded or recovers, e.g., to surface the
+	/// health of the chain source to the user.
+	pub status_callback: Option<Box<dyn Fn(ElectrumSyncStatus) + Send + Sync>>,
+}
+
+impl Default for ElectrumSyncConfig {
+	fn default() -> Self {
+		Self {
+			max_retries: 3,
+			initial_backoff: Duration::from_millis(500),
+			max_backoff: Duration::from_secs(30),
+			status_callback: None,
+		}
+	}
+}
+
+// The server we're currently using, and our connection to it if we're connected.
+struct ServerConnection {
+	server_idx: usize,
+	client: Option<Arc<ElectrumClient>>,
+}
+
+/// Synchronizes LDK with one of a given set of [`Electrum`] servers.
+///
+/// Needs to be registered with a [`ChainMonitor`] via the [`Filter`] interface to be informed of
+/// transactions and outputs to monitor for on-chain confirmation, unconfirmation, and
+/// reconfirmation.
+///
+/// Note that registration via [`Filter`] needs to happen before any calls to
+/// [`Watch::watch_channel`] to ensure we get notified of the items to monitor.
+///
+/// The confirmation status of all monitored items is retrieved via batched script history
+/// queries. If a server fails to respond, syncing is retried via the next configured server with
+/// exponential backoff, as configured via [`ElectrumSyncConfig`].
+///
+/// [`Electrum`]: https://electrumx.readthedocs.io/en/latest/protocol.html
+/// [`ChainMonitor`]: lightning::chain::chainmonitor::ChainMonitor
+/// [`Watch::watch_channel`]: lightning::chain::Watch::watch_channel
+/// [`Filter`]: lightning::chain::Filter
+pub struct ElectrumSyncClient<L: Deref>
+where
+	L::Target: Logger,
+{
+	sync_state: Mutex<SyncState>,
+	queue: Mutex<FilterQueue>,
+	heights: Mutex<SyncHeights>,
+	// The script pubkeys of registered transactions, via the history of which we learn about their
+	// confirmation.
+	watched_tx_scripts: Mutex<HashMap<Txid, Script>>,
+	server_urls: Vec<String>,
+	connection: Mutex<ServerConnection>,
+	config: ElectrumSyncConfig,
+	degraded: AtomicBool,
+	logger: L,
+}
+
+impl<L: Deref> ElectrumSyncClient<L>
+where
+	L::Target: Logger,
+{
+	/// Returns a new [`ElectrumSyncClient`] object connected to the given server, using the default
+	/// [`ElectrumSyncConfig`].
+	pub fn new(server_url: String, logger: L) -> Result<Self, TxSyncError> {
+		Self::with_config(vec![server_url], ElectrumSyncConfig::default(), logger)
+	}
+
+	/// Returns a new [`ElectrumSyncClient`] object connected to the first reachable server of
+	/// `server_urls`, failing over to the respective next server whenever a request fails.
+	///
+	/// Fails with [`TxSyncError::InvalidConfig`] if no server URL is given, and with
+	/// [`TxSyncError::Failed`] if we couldn't connect to any server within the configured retries.
+	pub fn with_config(server_urls: Vec<String>, config: ElectrumSyncConfig, logger: L) -> Result<Self, TxSyncError> {
+		if server_urls.is_empty() {
+			return Err(TxSyncError::InvalidConfig);
+		}
+
+		let sync_client = Self {
+			sync_state: Mutex::new(SyncState::new()),
+			queue: Mutex::new(FilterQueue::new()),
+			heights: Mutex::new(SyncHeights::default()),
+			watched_tx_scripts: Mutex::new(HashMap::new()),
+			server_urls,
+			connection: Mutex::new(ServerConnection { server_idx: 0, client: None }),
+			config,
+			degraded: AtomicBool::new(false),
+			logger,
+		};
+
+		// Connect upfront so that we fail early if none of the servers is reachable.
+		sync_client.with_retries(|_| Ok(()))?;
+		Ok(sync_client)
+	}
+
+	/// Synchronizes the given `confirmables` via their [`Confirm`] interface implementations. This
+	/// method should be called regularly to keep LDK up-to-date with current chain data.
+	///
+	/// For example, instances of [`ChannelManager`] and [`ChainMonitor`] can be informed about the
+	/// newest on-chain activity related to the items previously registered via the [`Filter`]
+	/// interface.
+	///
+	/// [`Confirm`]: lightning::chain::Confirm
+	/// [`ChainMonitor`]: lightning::chain::chainmonitor::ChainMonitor
+	/// [`ChannelManager`]: lightning::ln::channelmanager::ChannelManager
+	/// [`Filter`]: lightning::chain::Filter
+	pub fn sync(&self, confirmables: Vec<&(dyn Confirm + Sync + Send)>) -> Result<(), TxSyncError> {
+		// This lock makes sure we're syncing once at a time.
+		let mut sync_state = self.sync_state.lock().unwrap();
+
+		self.with_retries(|client| self.sync_once(client, &mut sync_state, &confirmables))
+	}
+
+	/// Returns the progress of syncing towards the chain tip last seen during [`Self::sync`], along
+	/// with the number of transactions and outputs registered via [`Filter`] since which have yet
+	/// to be scanned for, e.g., to pass [`SyncProgress::tip_height`] to
+	/// [`ChannelManager::set_chain_tip_height`].
+	///
+	/// [`Filter`]: lightning::chain::Filter
+	/// [`ChannelManager::set_chain_tip_height`]: lightning::ln::channelmanager::ChannelManager::set_chain_tip_height
+	pub fn sync_progress(&self) -> SyncProgress {
+		let heights = self.heights.lock().unwrap();
+		SyncProgress {
+			synced_height: heights.synced_height,
+			tip_height: heights.tip_height,
+			pending_scripts: self.queue.lock().unwrap().len(),
+		}
+	}
+
+	fn sync_once(
+		&self, client: &ElectrumClient, sync_state: &mut SyncState,
+		confirmables: &Vec<&(dyn Confirm + Sync + Send)>,
+	) -> Result<(), InternalError> {
+		log_info!(self.logger, "Starting transaction sync.");
+
+		let mut tip = client.block_headers_subscribe()?;
+		self.heights.lock().unwrap().tip_height = tip.height as u32;
+
+		loop {
+			let pending_registrations = self.queue.lock().unwrap().process_queues(sync_state);
+			let tip_hash = tip.header.block_hash();
+			let tip_is_new = Some(tip_hash) != sync_state.last_sync_hash;
+
+			// We loop until any registered transactions have been processed at least once, or the
+			// tip hasn't been updated during the last iteration.
+			if !sync_state.pending_sync && !pending_registrations && !tip_is_new {
+				// Nothing to do.
+				break;
+			} else {
+				// Update the known tip to the newest one.
+				if tip_is_new {
+					// First check for any unconfirmed transactions and act on it immediately.
+					match self.get_unconfirmed_transactions(client, confirmables) {
+						Ok(unconfirmed_txs) => {
+							// Double-check the tip. If it changed, a reorg happened since we
+							// started syncing and we need to restart last-minute.
+							if self.check_update_tip(client, &mut tip)? {
+								continue;
+							}
+
+							self.sync_unconfirmed_transactions(sync_state, confirmables, unconfirmed_txs);
+						},
+						Err(err) => {
+							// (Semi-)permanent failure, retry later.
+							log_error!(self.logger, "Failed during transaction sync, aborting.");
+							sync_state.pending_sync = true;
+							return Err(err);
+						}
+					}
+
+					// Inform the interface of the new block.
+					self.heights.lock().unwrap().tip_height = tip.height as u32;
+					for c in confirmables {
+						c.best_block_updated(&tip.header, tip.height as u32);
+					}
+				}
+
+				match self.get_confirmed_transactions(client, sync_state) {
+					Ok(confirmed_txs) => {
+						// Double-check the tip. If it changed, a reorg happened since we started
+						// syncing and we need to restart last-minute.
+						if self.check_update_tip(client, &mut tip)? {
+							continue;
+						}
+
+						self.sync_confirmed_transactions(sync_state, confirmables, confirmed_txs);
+					}
+					Err(InternalError::Inconsistency) => {
+						// Immediately restart syncing when we encounter any inconsistencies.
+						log_debug!(self.logger, "Encountered inconsistency during transaction sync, restarting.");
+						sync_state.pending_sync = true;
+						continue;
+					}
+					Err(err) => {
+						// (Semi-)permanent failure, retry later.
+						log_error!(self.logger, "Failed during transaction sync, aborting.");
+						sync_state.pending_sync = true;
+						return Err(err);
+					}
+				}
+				sync_state.last_sync_hash = Some(tip_hash);
+				sync_state.pending_sync = false;
+				self.heights.lock().unwrap().synced_height = tip.height as u32;
+			}
+		}
+		log_info!(self.logger, "Finished transaction sync.");
+		Ok(())
+	}
+
+	// Runs `op` via the current server, retrying via the respective next server with exponential
+	// backoff on failure, and keeps the status callback informed.
+	fn with_retries<T, F>(&self, mut op: F) -> Result<T, TxSyncError>
+	where
+		F: FnMut(&ElectrumClient) -> Result<T, InternalError>,
+	{
+		let mut failed_attempts: u8 = 0;
+		let mut backoff = self.config.initial_backoff;
+		loop {
+			let server_idx = self.connection.lock().unwrap().server_idx;
+			match self.connected_client().and_then(|client| op(&client)) {
+				Ok(res) => {
+					if self.degraded.swap(false, Ordering::AcqRel) {
+						log_info!(self.logger, "Recovered syncing via Electrum server {}.", self.server_urls[server_idx]);
+						self.notify_status(ElectrumSyncStatus::Recovered {
+							server_url: self.server_urls[server_idx].clone(),
+						});
+					}
+					return Ok(res);
+				}
+				Err(_) => {
+					let retry_in = if failed_attempts < self.config.max_retries { Some(backoff) } else { None };
+					failed_attempts = failed_attempts.saturating_add(1);
+					self.degraded.store(true, Ordering::Release);
+					self.disconnect_and_fail_over(server_idx);
+
+					let server_url = self.server_urls[server_idx].clone();
+					if let Some(delay) = retry_in {
+						log_debug!(self.logger, "Request to Electrum server {} failed, retrying in {}ms ({}/{}).",
+							server_url, delay.as_millis(), failed_attempts, self.config.max_retries);
+					} else {
+						log_error!(self.logger, "Request to Electrum server {} failed, giving up after {} attempts.",
+							server_url, failed_attempts);
+					}
+					self.notify_status(ElectrumSyncStatus::Degraded { server_url, failed_attempts, retry_in });
+
+					match retry_in {
+						Some(delay) => {
+							std::thread::sleep(delay);
+							backoff = cmp::min(backoff.checked_mul(2).unwrap_or(self.config.max_backoff), self.config.max_backoff);
+						}
+						None => return Err(TxSyncError::Failed),
+					}
+				}
+			}
+		}
+	}
+
+	fn notify_status(&self, status: ElectrumSyncStatus) {
+		if let Some(callback) = &self.config.status_callback {
+			callback(status);
+		}
+	}
+
+	// Returns our client connected to the current server, connecting first if needed.
+	fn connected_client(&self) -> Result<Arc<ElectrumClient>, InternalError> {
+		let mut connection = self.connection.lock().unwrap();
+		if let Some(client) = &connection.client {
+			return Ok(Arc::clone(client));
+		}
+
+		let server_url = &self.server_urls[connection.server_idx];
+		match ElectrumClient::new(server_url) {
+			Ok(client) => {
+				log_debug!(self.logger, "Connected to Electrum server {}.", server_url);
+				let client = Arc::new(client);
+				connection.client = Some(Arc::clone(&client));
+				Ok(client)
+			}
+			Err(e) => {
+				log_error!(self.logger, "Failed to connect to Electrum server {}: {}", server_url, e);
+				Err(InternalError::Failed)
+			}
+		}
+	}
+
+	// Drops our connection to the server at `failed_server_idx` and moves on to the next server.
+	fn disconnect_and_fail_over(&self, failed_server_idx: usize) {
+		let mut connection = self.connection.lock().unwrap();
+		if connection.server_idx == failed_server_idx {
+			connection.client = None;
+			connection.server_idx = (failed_server_idx + 1) % self.server_urls.len();
+		}
+	}
+
+	// Checks whether the tip changed since we last retrieved it, in which case `cur_tip` is updated
+	// and `true` is returned.
+	fn check_update_tip(
+		&self, client: &ElectrumClient, cur_tip: &mut HeaderNotification,
+	) -> Result<bool, InternalError> {
+		let check_tip = client.block_headers_subscribe()?;
+		let check_tip_hash = check_tip.header.block_hash();
+
+		// Restart if either the tip changed or we got some divergent tip change notification since
+		// we started. In the latter case we make sure we clear the queue before continuing.
+		let mut restart_sync = check_tip_hash != cur_tip.header.block_hash();
+		while let Some(queued_notification) = client.block_headers_pop()? {
+			if queued_notification.header.block_hash() != check_tip_hash {
+				restart_sync = true;
+			}
+		}
+
+		if restart_sync {
+			*cur_tip = check_tip;
+		}
+		Ok(restart_sync)
+	}
+
+	fn sync_confirmed_transactions(
+		&self, sync_state: &mut SyncState, confirmables: &Vec<&(dyn Confirm + Sync + Send)>, confirmed_txs: Vec<ConfirmedTx>,
+	) {
+		let mut watched_tx_scripts = self.watched_tx_scripts.lock().unwrap();
+		for ctx in confirmed_txs {
+			for c in confirmables {
+				c.transactions_confirmed(
+					&ctx.block_header,
+					&[(ctx.pos, &ctx.tx)],
+					ctx.block_height,
+				);
+			}
+
+			let txid = ctx.tx.txid();
+			sync_state.watched_transactions.remove(&txid);
+			watched_tx_scripts.remove(&txid);
+
+			for input in &ctx.tx.input {
+				sync_state.watched_outputs.remove(&input.previous_output);
+			}
+		}
+	}
+
+	fn get_confirmed_transactions(
+		&self, client: &ElectrumClient, sync_state: &SyncState,
+	) -> Result<Vec<ConfirmedTx>, InternalError> {
+
+		// First, check the confirmation status of registered transactions as well as the status of
+		// dependent transactions of registered outputs, all via batched queries of the histories
+		// of the respective script pubkeys.
+		let mut watched_txids = Vec::with_capacity(sync_state.watched_transactions.len());
+		let mut watched_script_pubkeys = Vec::with_capacity(
+			sync_state.watched_transactions.len() + sync_state.watched_outputs.len());
+
+		for txid in &sync_state.watched_transactions {
+			// If the server doesn't know the transaction yet, it can't be confirmed either.
+			if let Some(script_pubkey) = self.get_tx_script(client, txid)? {
+				self.watched_tx_scripts.lock().unwrap().insert(*txid, script_pubkey.clone());
+				watched_txids.push(*txid);
+				watched_script_pubkeys.push(script_pubkey);
+			}
+		}
+		let num_tx_lookups = watched_txids.len();
+
+		let watched_outputs = sync_state.watched_outputs.values().collect::<Vec<_>>();
+		watched_script_pubkeys.extend(watched_outputs.iter().map(|output| output.script_pubkey.clone()));
+
+		let histories = self.batch_script_get_history(client, &watched_script_pubkeys)?;
+		let (tx_histories, output_histories) = histories.split_at(num_tx_lookups);
+
+		// Collect the confirmed registered transactions as well as any confirmed transactions
+		// touching the script pubkeys of registered outputs, which might be spends.
+		let mut candidates: Vec<(Txid, u32, Option<OutPoint>)> = Vec::new();
+		for (txid, history) in watched_txids.iter().zip(tx_histories) {
+			if let Some(entry) = history.iter().find(|entry| entry.tx_hash == *txid && entry.height > 0) {
+				candidates.push((*txid, entry.height as u32, None));
+			}
+		}
+		for (output, history) in watched_outputs.iter().zip(output_histories) {
+			let outpoint = output.outpoint.into_bitcoin_outpoint();
+			for entry in history.iter().filter(|entry| entry.height > 0) {
+				candidates.push((entry.tx_hash, entry.height as u32, Some(outpoint)));
+			}
+		}
+
+		let mut candidate_txids = Vec::new();
+		for (txid, _, _) in &candidates {
+			if !candidate_txids.contains(txid) {
+				candidate_txids.push(*txid);
+			}
+		}
+		let candidate_txs = self.batch_transaction_get(client, &candidate_txids)?
+			.into_iter()
+			.map(|tx| (tx.txid(), tx))
+			.collect::<HashMap<Txid, Transaction>>();
+
+		let mut confirmed_txs = Vec::new();
+		let mut confirmed_txids = HashSet::new();
+		for (txid, height, spent_outpoint) in candidates {
+			let tx = candidate_txs.get(&txid).ok_or(InternalError::Failed)?;
+			if let Some(outpoint) = spent_outpoint {
+				// The history of an output's script pubkey also includes the transaction creating
+				// the output, as well as any transactions spending other outputs to the same script.
+				if !tx.input.iter().any(|txin| txin.previous_output == outpoint) {
+					continue;
+				}
+			}
+
+			if confirmed_txids.insert(txid) {
+				confirmed_txs.push(self.get_confirmed_tx(client, tx.clone(), height)?);
+			}
+		}
+
+		// Sort all confirmed transactions first by block height, then by in-block
+		// position, and finally feed them to the interface in order.
+		confirmed_txs.sort_unstable_by(|tx1, tx2| {
+			tx1.block_height.cmp(&tx2.block_height).then_with(|| tx1.pos.cmp(&tx2.pos))
+		});
+
+		Ok(confirmed_txs)
+	}
+
+	fn get_confirmed_tx(
+		&self, client: &ElectrumClient, tx: Transaction, prob_conf_height: u32,
+	) -> Result<ConfirmedTx, InternalError> {
+		let txid = tx.txid();
+		let merkle_res = match client.transaction_get_merkle(&txid, prob_conf_height as usize) {
+			Ok(merkle_res) => merkle_res,
+			Err(electrum_client::Error::Protocol(_)) => {
+				// The transaction isn't confirmed at the height the server told us about anymore.
+				log_trace!(self.logger, "Inconsistency: Tx {} was unconfirmed during syncing.", txid);
+				return Err(InternalError::Inconsistency);
+			}
+			Err(e) => return Err(e.into()),
+		};
+
+		let block_header = client.block_header(prob_conf_height as usize)?;
+		if merkle_res.block_height as u32 != prob_conf_height ||
+			!validate_merkle_proof(&txid, &block_header.merkle_root, &merkle_res)
+		{
+			log_trace!(self.logger, "Inconsistency: Block {} was unconfirmed during syncing.", block_header.block_hash());
+			return Err(InternalError::Inconsistency);
+		}
+
+		Ok(ConfirmedTx { tx, block_header, block_height: prob_conf_height, pos: merkle_res.pos })
+	}
+
+	fn get_unconfirmed_transactions(
+		&self, client: &ElectrumClient, confirmables: &Vec<&(dyn Confirm + Sync + Send)>,
+	) -> Result<Vec<Txid>, InternalError> {
+		// Query the interface for relevant txids and check whether the relevant blocks are still
+		// in the best chain, mark them unconfirmed otherwise
+		let relevant_txids = confirmables
+			.iter()
+			.flat_map(|c| c.get_relevant_txids())
+			.collect::<HashSet<(Txid, Option<BlockHash>)>>();
+
+		let mut unconfirmed_txs = Vec::new();
+		let mut confirmed_txs = Vec::with_capacity(relevant_txids.len());
+		let mut script_pubkeys = Vec::with_capacity(relevant_txids.len());
+
+		for (txid, block_hash_opt) in relevant_txids {
+			if let Some(block_hash) = block_hash_opt {
+				match self.get_tx_script(client, &txid)? {
+					Some(script_pubkey) => {
+						confirmed_txs.push((txid, block_hash));
+						script_pubkeys.push(script_pubkey);
+					}
+					// The server doesn't know the transaction anymore, so it surely isn't confirmed.
+					None => unconfirmed_txs.push(txid),
+				}
+			} else {
+				log_error!(self.logger, "Untracked confirmation of funding transaction. Please ensure none of your channels had been created with LDK prior to version 0.0.113!");
+				panic!("Untracked confirmation of funding transaction. Please ensure none of your channels had been created with LDK prior to version 0.0.113!");
+			}
+		}
+
+		let histories = self.batch_script_get_history(client, &script_pubkeys)?;
+		for ((txid, block_hash), history) in confirmed_txs.into_iter().zip(histories) {
+			if let Some(entry) = history.iter().find(|entry| entry.tx_hash == txid && entry.height > 0) {
+				let block_header = client.block_header(entry.height as usize)?;
+				if block_header.block_hash() == block_hash {
+					// Skip if the transaction is still confirmed in the block in question.
+					continue;
+				}
+			}
+
+			unconfirmed_txs.push(txid);
+		}
+		Ok(unconfirmed_txs)
+	}
+
+	fn sync_unconfirmed_transactions(
+		&self, sync_state: &mut SyncState, confirmables: &Vec<&(dyn Confirm + Sync + Send)>, unconfirmed_txs: Vec<Txid>,
+	) {
+		for txid in unconfirmed_txs {
+			for c in confirmables {
+				c.transaction_unconfirmed(&txid);
+			}
+
+			sync_state.watched_transactions.insert(txid);
+		}
+	}
+
+	// Returns a script pubkey of the given transaction, which we use to look up its confirmation
+	// status, or `None` if the server doesn't know the transaction.
+	fn get_tx_script(&self, client: &ElectrumClient, txid: &Txid) -> Result<Option<Script>, InternalError> {
+		if let Some(script_pubkey) = self.watched_tx_scripts.lock().unwrap().get(txid) {
+			return Ok(Some(script_pubkey.clone()));
+		}
+
+		match client.transaction_get(txid) {
+			Ok(tx) => {
+				// We watch an arbitrary output of the transaction of interest in order to retrieve
+				// the associated script history.
+				match tx.output.first() {
+					Some(txout) => Ok(Some(txout.script_pubkey.clone())),
+					None => {
+						log_error!(self.logger, "Retrieved transaction {} without outputs. Please verify server integrity.", txid);
+						Err(InternalError::Failed)
+					}
+				}
+			}
+			Err(electrum_client::Error::Protocol(_)) => Ok(None),
+			Err(e) => Err(e.into()),
+		}
+	}
+
+	fn batch_script_get_history(
+		&self, client: &ElectrumClient, script_pubkeys: &[Script],
+	) -> Result<Vec<Vec<GetHistoryRes>>, InternalError> {
+		let mut histories = Vec::with_capacity(script_pubkeys.len());
+		for chunk in script_pubkeys.chunks(MAX_BATCH_SIZE) {
+			histories.append(&mut client.batch_script_get_history(chunk.iter())?);
+		}
+
+		if histories.len() != script_pubkeys.len() {
+			log_error!(self.logger, "Retrieved {} script histories while querying {}. Please verify server integrity.",
+				histories.len(), script_pubkeys.len());
+			return Err(InternalError::Failed);
+		}
+		Ok(histories)
+	}
+
+	fn batch_transaction_get(
+		&self, client: &ElectrumClient, txids: &[Txid],
+	) -> Result<Vec<Transaction>, InternalError> {
+		let mut txs = Vec::with_capacity(txids.len());
+		for chunk in txids.chunks(MAX_BATCH_SIZE) {
+			txs.append(&mut client.batch_transaction_get(chunk.iter())?);
+		}
+		Ok(txs)
+	}
+}
+
+// Checks the given Merkle proof of the inclusion of `txid` against the given `merkle_root`.
+fn validate_merkle_proof(txid: &Txid, merkle_root: &TxMerkleNode, merkle_res: &GetMerkleRes) -> bool {
+	let mut index = merkle_res.pos;
+	let mut cur = txid.as_hash();
+	for bytes in &merkle_res.merkle {
+		// The server returns the hashes in their displayed, i.e., reversed, byte order.
+		let mut bytes = *bytes;
+		bytes.reverse();
+		let next_hash = Sha256d::from_inner(bytes);
+
+		let (left, right) = if index % 2 == 0 { (cur, next_hash) } else { (next_hash, cur) };
+		let data = [&left[..], &right[..]].concat();
+		cur = Sha256d::hash(&data);
+		index /= 2;
+	}
+
+	cur == merkle_root.as_hash()
+}
+
+impl<L: Deref> Filter for ElectrumSyncClient<L>
+where
+	L::Target: Logger,
+{
+	fn register_tx(&self, txid: &Txid, script_pubkey: &Script) {
+		let mut locked_queue = self.queue.lock().unwrap();
+		locked_queue.transactions.insert(*txid);
+		self.watched_tx_scripts.lock().unwrap().insert(*txid, script_pubkey.clone());
+	}
+
+	fn register_output(&self, output: WatchedOutput) {
+		let mut locked_queue = self.queue.lock().unwrap();
+		locked_queue.outputs.insert(output.outpoint.into_bitcoin_outpoint(), output);
+	}
+}
@@ -5,6 +5,8 @@ use std::fmt;
 pub enum TxSyncError {
 	/// A transaction sync failed and needs to be retried eventually.
 	Failed,
+	/// The configuration a sync client was to be constructed with was invalid.
+	InvalidConfig,
 }
 
 impl std::error::Error for TxSyncError {}
@@ -13,12 +15,13 @@ impl fmt::Display for TxSyncError {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		match *self {
 			Self::Failed => write!(f, "Failed to conduct transaction sync."),
+			Self::InvalidConfig => write!(f, "Invalid sync client configuration."),
 		}
 	}
 }
 
 #[derive(Debug)]
-#[cfg(any(feature = "esplora-blocking", feature = "esplora-async"))]
+#[cfg(any(feature = "esplora-blocking", feature = "esplora-async", feature = "electrum"))]
 pub(crate) enum InternalError {
 	/// A transaction sync failed and needs to be retried eventually.
 	Failed,
@@ -26,7 +29,7 @@ pub(crate) enum InternalError {
 	Inconsistency,
 }
 
-#[cfg(any(feature = "esplora-blocking", feature = "esplora-async"))]
+#[cfg(any(feature = "esplora-blocking", feature = "esplora-async", feature = "electrum"))]
 impl fmt::Display for InternalError {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		match *self {
@@ -38,7 +41,7 @@ impl fmt::Display for InternalError {
 	}
 }
 
-#[cfg(any(feature = "esplora-blocking", feature = "esplora-async"))]
+#[cfg(any(feature = "esplora-blocking", feature = "esplora-async", feature = "electrum"))]
 impl std::error::Error for InternalError {}
 
 #[cfg(any(feature = "esplora-blocking", feature = "esplora-async"))]
@@ -55,7 +58,14 @@ impl From<esplora_client::Error> for InternalError {
 	}
 }
 
-#[cfg(any(feature = "esplora-blocking", feature = "esplora-async"))]
+#[cfg(feature = "electrum")]
+impl From<electrum_client::Error> for InternalError {
+	fn from(_e: electrum_client::Error) -> Self {
+		Self::Failed
+	}
+}
+
+#[cfg(any(feature = "esplora-blocking", feature = "esplora-async", feature = "electrum"))]
 impl From<InternalError> for TxSyncError {
 	fn from(_e: InternalError) -> Self {
 		Self::Failed
@@ -1,12 +1,12 @@
 use crate::error::{TxSyncError, InternalError};
-use crate::common::{SyncState, FilterQueue, ConfirmedTx};
+use crate::common::{SyncState, SyncHeights, FilterQueue, ConfirmedTx};
 
 use lightning::util::logger::Logger;
 use lightning::{log_error, log_info, log_debug, log_trace};
 use lightning::chain::WatchedOutput;
-use lightning::chain::{Confirm, Filter};
+use lightning::chain::{Confirm, Filter, MempoolListener, SyncProgress};
 
-use bitcoin::{BlockHash, Script, Txid};
+use bitcoin::{BlockHash, Script, Transaction, Txid};
 
 use esplora_client::Builder;
 #[cfg(feature = "async-interface")]
@@ -17,6 +17,30 @@ use esplora_client::blocking::BlockingClient;
 use std::collections::HashSet;
 use core::ops::Deref;
 
+/// Configuration of the HTTP client used by an [`EsploraSyncClient`], as given to
+/// [`EsploraSyncClient::with_config`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EsploraClientConfig {
+	/// A SOCKS5 or HTTP proxy through which all requests are made, e.g.,
+	/// `socks5://127.0.0.1:9050`.
+	///
+	/// Not supported when targeting WASM.
+	pub proxy: Option<String>,
+	/// Headers included in every request, e.g., an `Authorization` header required by a private
+	/// Esplora instance.
+	pub headers: Vec<(String, String)>,
+	/// The timeout of each request, in seconds. Requests never time out if this is `None`.
+	///
+	/// Not supported when targeting WASM.
+	pub timeout_secs: Option<u64>,
+	/// The number of times [`EsploraSyncClient::sync`] and [`EsploraSyncClient::sync_mempool`]
+	/// are retried after failing, e.g., due to a request failing to complete.
+	///
+	/// Retries happen immediately, thus any longer-lasting outage still needs to be handled by
+	/// calling the respective method again later.
+	pub max_retries: u8,
+}
+
 /// Synchronizes LDK with a given [`Esplora`] server.
 ///
 /// Needs to be registered with a [`ChainMonitor`] via the [`Filter`] interface to be informed of
@@ -39,7 +63,9 @@ where
 {
 	sync_state: MutexType<SyncState>,
 	queue: std::sync::Mutex<FilterQueue>,
+	heights: std::sync::Mutex<SyncHeights>,
 	client: EsploraClientType,
+	max_retries: u8,
 	logger: L,
 }
 
@@ -58,6 +84,17 @@ where
 		EsploraSyncClient::from_client(client, logger)
 	}
 
+	/// Returns a new [`EsploraSyncClient`] object, making requests to the server as configured by
+	/// `config`.
+	///
+	/// Fails with [`TxSyncError::InvalidConfig`] if the proxy URL or any header is invalid.
+	pub fn with_config(server_url: String, config: EsploraClientConfig, logger: L) -> Result<Self, TxSyncError> {
+		let client = build_client(server_url, &config)?;
+		let mut sync_client = EsploraSyncClient::from_client(client, logger);
+		sync_client.max_retries = config.max_retries;
+		Ok(sync_client)
+	}
+
 	/// Returns a new [`EsploraSyncClient`] object using the given Esplora client.
 	pub fn from_client(client: EsploraClientType, logger: L) -> Self {
 		let sync_state = MutexType::new(SyncState::new());
@@ -65,7 +102,9 @@ where
 		Self {
 			sync_state,
 			queue,
+			heights: std::sync::Mutex::new(SyncHeights::default()),
 			client,
+			max_retries: 0,
 			logger,
 		}
 	}
@@ -83,6 +122,20 @@ where
 	/// [`Filter`]: lightning::chain::Filter
 	#[maybe_async]
 	pub fn sync(&self, confirmables: Vec<&(dyn Confirm + Sync + Send)>) -> Result<(), TxSyncError> {
+		let mut retries = 0;
+		loop {
+			match maybe_await!(self.sync_once(&confirmables)) {
+				Err(_) if retries < self.max_retries => {
+					retries += 1;
+					log_debug!(self.logger, "Retrying failed transaction sync ({}/{}).", retries, self.max_retries);
+				},
+				res => return res,
+			}
+		}
+	}
+
+	#[maybe_async]
+	fn sync_once(&self, confirmables: &Vec<&(dyn Confirm + Sync + Send)>) -> Result<(), TxSyncError> {
 		// This lock makes sure we're syncing once at a time.
 		#[cfg(not(feature = "async-interface"))]
 		let mut sync_state = self.sync_state.lock().unwrap();
@@ -106,7 +159,7 @@ where
 				// Update the known tip to the newest one.
 				if tip_is_new {
 					// First check for any unconfirmed transactions and act on it immediately.
-					match maybe_await!(self.get_unconfirmed_transactions(&confirmables)) {
+					match maybe_await!(self.get_unconfirmed_transactions(confirmables)) {
 						Ok(unconfirmed_txs) => {
 							// Double-check the tip hash. If it changed, a reorg happened since
 							// we started syncing and we need to restart last-minute.
@@ -116,7 +169,7 @@ where
 								continue;
 							}
 
-							self.sync_unconfirmed_transactions(&mut sync_state, &confirmables, unconfirmed_txs);
+							self.sync_unconfirmed_transactions(&mut sync_state, confirmables, unconfirmed_txs);
 						},
 						Err(err) => {
 							// (Semi-)permanent failure, retry later.
@@ -126,7 +179,7 @@ where
 						}
 					}
 
-					match maybe_await!(self.sync_best_block_updated(&confirmables, &tip_hash)) {
+					match maybe_await!(self.sync_best_block_updated(confirmables, &tip_hash)) {
 						Ok(()) => {}
 						Err(InternalError::Inconsistency) => {
 							// Immediately restart syncing when we encounter any inconsistencies.
@@ -154,7 +207,7 @@ where
 
 						self.sync_confirmed_transactions(
 							&mut sync_state,
-							&confirmables,
+							confirmables,
 							confirmed_txs,
 						);
 					}
@@ -173,12 +226,129 @@ where
 				}
 				sync_state.last_sync_hash = Some(tip_hash);
 				sync_state.pending_sync = false;
+				let mut heights = self.heights.lock().unwrap();
+				heights.synced_height = heights.tip_height;
 			}
 		}
 		log_info!(self.logger, "Finished transaction sync.");
 		Ok(())
 	}
 
+	/// Returns the progress of syncing towards the chain tip last seen during [`Self::sync`], along
+	/// with the number of transactions and outputs registered via [`Filter`] since which have yet
+	/// to be scanned for, e.g., to pass [`SyncProgress::tip_height`] to
+	/// [`ChannelManager::set_chain_tip_height`].
+	///
+	/// [`Filter`]: lightning::chain::Filter
+	/// [`ChannelManager::set_chain_tip_height`]: lightning::ln::channelmanager::ChannelManager::set_chain_tip_height
+	pub fn sync_progress(&self) -> SyncProgress {
+		let heights = self.heights.lock().unwrap();
+		SyncProgress {
+			synced_height: heights.synced_height,
+			tip_height: heights.tip_height,
+			pending_scripts: self.queue.lock().unwrap().len(),
+		}
+	}
+
+	/// Hands any unconfirmed transactions spending registered outputs, as well as unconfirmed
+	/// transactions spending their outputs in turn, to the given `mempool_listeners` via their
+	/// [`MempoolListener`] interface implementations.
+	///
+	/// This allows, e.g., a [`ChainMonitor`] to learn payment preimages from a counterparty's
+	/// HTLC claims before they confirm. Calling this method is optional and independent of
+	/// [`EsploraSyncClient::sync`], which remains responsible for keeping LDK up-to-date with
+	/// on-chain data. Transactions are handed over only once, unless they left the mempool in the
+	/// meantime.
+	///
+	/// [`MempoolListener`]: lightning::chain::MempoolListener
+	/// [`ChainMonitor`]: lightning::chain::chainmonitor::ChainMonitor
+	#[maybe_async]
+	pub fn sync_mempool(&self, mempool_listeners: Vec<&(dyn MempoolListener + Sync + Send)>) -> Result<(), TxSyncError> {
+		#[cfg(not(feature = "async-interface"))]
+		let mut sync_state = self.sync_state.lock().unwrap();
+		#[cfg(feature = "async-interface")]
+		let mut sync_state = self.sync_state.lock().await;
+
+		log_trace!(self.logger, "Starting mempool sync.");
+
+		if self.queue.lock().unwrap().process_queues(&mut sync_state) {
+			// Make sure the next call to `sync` also processes the newly registered items.
+			sync_state.pending_sync = true;
+		}
+
+		let mut retries = 0;
+		let unconfirmed_txs = loop {
+			match maybe_await!(self.get_unconfirmed_spends(&sync_state)) {
+				Ok(unconfirmed_txs) => break unconfirmed_txs,
+				Err(_) if retries < self.max_retries => {
+					retries += 1;
+					log_debug!(self.logger, "Retrying failed mempool sync ({}/{}).", retries, self.max_retries);
+				},
+				Err(err) => {
+					log_error!(self.logger, "Failed during mempool sync, aborting.");
+					return Err(TxSyncError::from(err));
+				}
+			}
+		};
+
+		let mut seen_mempool_txids = HashSet::new();
+		for tx in unconfirmed_txs {
+			let txid = tx.txid();
+			if !sync_state.seen_mempool_txids.contains(&txid) {
+				log_debug!(self.logger, "Handing unconfirmed transaction {} to mempool listeners.", txid);
+				for l in &mempool_listeners {
+					l.transaction_seen_in_mempool(&tx);
+				}
+			}
+			seen_mempool_txids.insert(txid);
+		}
+		sync_state.seen_mempool_txids = seen_mempool_txids;
+
+		log_trace!(self.logger, "Finished mempool sync.");
+		Ok(())
+	}
+
+	#[maybe_async]
+	fn get_unconfirmed_spends(&self, sync_state: &SyncState) -> Result<Vec<Transaction>, InternalError> {
+		// Unconfirmed spends of registered outputs are returned ahead of unconfirmed spends of
+		// their outputs, such that parents are always handed over before their children.
+		let mut unconfirmed_txs: Vec<Transaction> = Vec::new();
+		for output in sync_state.watched_outputs.values() {
+			if let Some(tx) = maybe_await!(self.get_unconfirmed_spend(&output.outpoint.txid, output.outpoint.index as u64))? {
+				if !unconfirmed_txs.iter().any(|known_tx| known_tx.txid() == tx.txid()) {
+					unconfirmed_txs.push(tx);
+				}
+			}
+		}
+
+		let num_parents = unconfirmed_txs.len();
+		for parent_idx in 0..num_parents {
+			let parent_txid = unconfirmed_txs[parent_idx].txid();
+			for vout in 0..unconfirmed_txs[parent_idx].output.len() {
+				if let Some(tx) = maybe_await!(self.get_unconfirmed_spend(&parent_txid, vout as u64))? {
+					if !unconfirmed_txs.iter().any(|known_tx| known_tx.txid() == tx.txid()) {
+						unconfirmed_txs.push(tx);
+					}
+				}
+			}
+		}
+
+		Ok(unconfirmed_txs)
+	}
+
+	#[maybe_async]
+	fn get_unconfirmed_spend(&self, txid: &Txid, index: u64) -> Result<Option<Transaction>, InternalError> {
+		if let Some(output_status) = maybe_await!(self.client.get_output_status(txid, index))? {
+			let is_confirmed = output_status.status.map_or(false, |status| status.confirmed);
+			if let Some(spending_txid) = output_status.txid {
+				if !is_confirmed {
+					return Ok(maybe_await!(self.client.get_tx(&spending_txid))?);
+				}
+			}
+		}
+		Ok(None)
+	}
+
 	#[maybe_async]
 	fn sync_best_block_updated(
 		&self, confirmables: &Vec<&(dyn Confirm + Sync + Send)>, tip_hash: &BlockHash,
@@ -189,6 +359,7 @@ where
 		let tip_status = maybe_await!(self.client.get_block_status(&tip_hash))?;
 		if tip_status.in_best_chain {
 			if let Some(tip_height) = tip_status.height {
+				self.heights.lock().unwrap().tip_height = tip_height;
 				for c in confirmables {
 					c.best_block_updated(&tip_header, tip_height);
 				}
@@ -366,6 +537,58 @@ type EsploraClientType = AsyncClient;
 #[cfg(not(feature = "async-interface"))]
 type EsploraClientType = BlockingClient;
 
+#[cfg(feature = "async-interface")]
+fn build_client(server_url: String, config: &EsploraClientConfig) -> Result<EsploraClientType, TxSyncError> {
+	use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+	let mut headers = HeaderMap::new();
+	for (name, value) in config.headers.iter() {
+		let name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| TxSyncError::InvalidConfig)?;
+		let value = HeaderValue::from_str(value).map_err(|_| TxSyncError::InvalidConfig)?;
+		headers.insert(name, value);
+	}
+	#[allow(unused_mut)]
+	let mut builder = reqwest::Client::builder().default_headers(headers);
+	#[cfg(not(target_arch = "wasm32"))]
+	{
+		if let Some(proxy) = &config.proxy {
+			builder = builder.proxy(reqwest::Proxy::all(proxy).map_err(|_| TxSyncError::InvalidConfig)?);
+		}
+		if let Some(timeout_secs) = config.timeout_secs {
+			builder = builder.timeout(core::time::Duration::from_secs(timeout_secs));
+		}
+	}
+	let client = builder.build().map_err(|_| TxSyncError::InvalidConfig)?;
+	Ok(AsyncClient::from_client(server_url, client))
+}
+
+#[cfg(not(feature = "async-interface"))]
+fn build_client(server_url: String, config: &EsploraClientConfig) -> Result<EsploraClientType, TxSyncError> {
+	let mut builder = ureq::AgentBuilder::new();
+	if let Some(proxy) = &config.proxy {
+		builder = builder.proxy(ureq::Proxy::new(proxy).map_err(|_| TxSyncError::InvalidConfig)?);
+	}
+	if let Some(timeout_secs) = config.timeout_secs {
+		builder = builder.timeout(core::time::Duration::from_secs(timeout_secs));
+	}
+	if !config.headers.is_empty() {
+		// ureq only validates headers once a request is made, thus check them upfront by the rules
+		// of RFC 7230.
+		let is_valid_name = |name: &str| !name.is_empty() &&
+			name.bytes().all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+		let is_valid_value = |value: &str| value.bytes().all(|b| b == b'\t' || (b >= 0x20 && b != 0x7f));
+		if !config.headers.iter().all(|(name, value)| is_valid_name(name) && is_valid_value(value)) {
+			return Err(TxSyncError::InvalidConfig);
+		}
+		let headers = config.headers.clone();
+		builder = builder.middleware(move |request: ureq::Request, next: ureq::MiddlewareNext| {
+			let request = headers.iter().fold(request, |request, (name, value)| request.set(name, value));
+			next.handle(request)
+		});
+	}
+	Ok(BlockingClient::from_agent(server_url, builder.build()))
+}
+
 
 impl<L: Deref> Filter for EsploraSyncClient<L>
 where
@@ -5,12 +5,22 @@
 //! implementations to be synchronized, i.e., usually instances of [`ChannelManager`] and
 //! [`ChainMonitor`].
 //!
+//! Optionally, `fn sync_mempool` may additionally be called with [`MempoolListener`]
+//! implementations, usually the [`ChainMonitor`], to learn of unconfirmed spends of the monitored
+//! outputs, e.g., to learn payment preimages from a counterparty's HTLC claims before they confirm.
+//!
 //! ## Features and Backend Support
 //!
 //!- `esplora-blocking` enables syncing against an Esplora backend based on a blocking client.
 //!- `esplora-async` enables syncing against an Esplora backend based on an async client.
 //!- `esplora-async-https` enables the async Esplora client with support for HTTPS.
 //!
+//!- `electrum` enables syncing against an Electrum backend based on a blocking client.
+//!
+//! Either Esplora client may be configured to use a proxy, custom headers, request timeouts and
+//! retries via `EsploraSyncClient::with_config`. The Electrum client may be configured to fail over
+//! across multiple servers with exponential-backoff retries via `ElectrumSyncClient::with_config`.
+//!
 //! ## Version Compatibility
 //!
 //! Currently this crate is compatible with LDK version 0.0.114 and above using channels which were
@@ -55,6 +65,7 @@
 //!
 //! [`Confirm`]: lightning::chain::Confirm
 //! [`Filter`]: lightning::chain::Filter
+//! [`MempoolListener`]: lightning::chain::MempoolListener
 //! [`ChainMonitor`]: lightning::chain::chainmonitor::ChainMonitor
 //! [`ChannelManager`]: lightning::ln::channelmanager::ChannelManager
 
@@ -74,11 +85,17 @@ extern crate bdk_macros;
 #[cfg(any(feature = "esplora-blocking", feature = "esplora-async"))]
 mod esplora;
 
-#[cfg(any(feature = "esplora-blocking", feature = "esplora-async"))]
+#[cfg(feature = "electrum")]
+mod electrum;
+
+#[cfg(any(feature = "esplora-blocking", feature = "esplora-async", feature = "electrum"))]
 mod common;
 
 mod error;
 pub use error::TxSyncError;
 
 #[cfg(any(feature = "esplora-blocking", feature = "esplora-async"))]
-pub use esplora::EsploraSyncClient;
+pub use esplora::{EsploraClientConfig, EsploraSyncClient};
+
+#[cfg(feature = "electrum")]
+pub use electrum::{ElectrumSyncClient, ElectrumSyncConfig, ElectrumSyncStatus};
@@ -1,6 +1,9 @@
-#![cfg(any(feature = "esplora-blocking", feature = "esplora-async"))]
-use lightning_transaction_sync::EsploraSyncClient;
-use lightning::chain::{Confirm, Filter};
+#![cfg(any(feature = "esplora-blocking", feature = "esplora-async", feature = "electrum"))]
+#[cfg(any(feature = "esplora-blocking", feature = "esplora-async"))]
+use lightning_transaction_sync::{EsploraClientConfig, EsploraSyncClient, TxSyncError};
+#[cfg(feature = "electrum")]
+use lightning_transaction_sync::{ElectrumSyncClient, ElectrumSyncConfig, ElectrumSyncStatus};
+use lightning::chain::{Confirm, Filter, SyncProgress};
 use lightning::chain::transaction::TransactionData;
 use lightning::util::logger::{Logger, Record};
 
@@ -171,6 +174,7 @@ fn test_esplora_syncs() {
 
 	tx_sync.sync(vec![&confirmable]).unwrap();
 	assert_eq!(confirmable.best_block.lock().unwrap().1, 102);
+	assert_eq!(tx_sync.sync_progress(), SyncProgress { synced_height: 102, tip_height: 102, pending_scripts: 0 });
 
 	let events = std::mem::take(&mut *confirmable.events.lock().unwrap());
 	assert_eq!(events.len(), 1);
@@ -179,8 +183,10 @@ fn test_esplora_syncs() {
 	let new_address = bitcoind.client.get_new_address(Some("test"), Some(AddressType::Legacy)).unwrap();
 	let txid = bitcoind.client.send_to_address(&new_address, Amount::from_sat(5000), None, None, None, None, None, None).unwrap();
 	tx_sync.register_tx(&txid, &new_address.script_pubkey());
+	assert_eq!(tx_sync.sync_progress().pending_scripts, 1);
 
 	tx_sync.sync(vec![&confirmable]).unwrap();
+	assert_eq!(tx_sync.sync_progress().pending_scripts, 0);
 
 	let events = std::mem::take(&mut *confirmable.events.lock().unwrap());
 	assert_eq!(events.len(), 0);
@@ -338,3 +344,166 @@ async fn test_esplora_connects_to_public_server() {
 	tx_sync.sync(vec![&confirmable]).unwrap();
 	assert_ne!(confirmable.best_block.lock().unwrap().1, 0);
 }
+
+#[tokio::test]
+#[cfg(any(feature = "esplora-async", feature = "esplora-blocking"))]
+async fn test_esplora_syncs_with_config() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	generate_blocks_and_wait(&bitcoind, &electrsd, 101);
+	let mut logger = TestLogger {};
+	let esplora_url = format!("http://{}", electrsd.esplora_url.as_ref().unwrap());
+
+	let invalid_config = EsploraClientConfig {
+		headers: vec![("Invalid Header".to_string(), "value".to_string())],
+		..Default::default()
+	};
+	assert!(matches!(EsploraSyncClient::with_config(esplora_url.clone(), invalid_config, &mut logger),
+		Err(TxSyncError::InvalidConfig)));
+
+	let config = EsploraClientConfig {
+		headers: vec![("Authorization".to_string(), "Bearer test".to_string())],
+		timeout_secs: Some(10),
+		max_retries: 2,
+		..Default::default()
+	};
+	let tx_sync = EsploraSyncClient::with_config(esplora_url, config, &mut logger).unwrap();
+	let confirmable = TestConfirmable::new();
+
+	#[cfg(feature = "esplora-async")]
+	tx_sync.sync(vec![&confirmable]).await.unwrap();
+	#[cfg(not(feature = "esplora-async"))]
+	tx_sync.sync(vec![&confirmable]).unwrap();
+	assert_eq!(confirmable.best_block.lock().unwrap().1, 102);
+	assert_eq!(tx_sync.sync_progress(), SyncProgress { synced_height: 102, tip_height: 102, pending_scripts: 0 });
+}
+
+#[test]
+#[cfg(feature = "electrum")]
+fn test_electrum_syncs() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	generate_blocks_and_wait(&bitcoind, &electrsd, 101);
+	let mut logger = TestLogger {};
+	let electrum_url = format!("tcp://{}", electrsd.electrum_url);
+	let tx_sync = ElectrumSyncClient::new(electrum_url, &mut logger).unwrap();
+	let confirmable = TestConfirmable::new();
+
+	// Check we pick up on new best blocks
+	assert_eq!(confirmable.best_block.lock().unwrap().1, 0);
+
+	tx_sync.sync(vec![&confirmable]).unwrap();
+	assert_eq!(confirmable.best_block.lock().unwrap().1, 102);
+	assert_eq!(tx_sync.sync_progress(), SyncProgress { synced_height: 102, tip_height: 102, pending_scripts: 0 });
+
+	let events = std::mem::take(&mut *confirmable.events.lock().unwrap());
+	assert_eq!(events.len(), 1);
+
+	// Check registered confirmed transactions are marked confirmed
+	let new_address = bitcoind.client.get_new_address(Some("test"), Some(AddressType::Legacy)).unwrap();
+	let txid = bitcoind.client.send_to_address(&new_address, Amount::from_sat(5000), None, None, None, None, None, None).unwrap();
+	tx_sync.register_tx(&txid, &new_address.script_pubkey());
+	assert_eq!(tx_sync.sync_progress().pending_scripts, 1);
+
+	tx_sync.sync(vec![&confirmable]).unwrap();
+	assert_eq!(tx_sync.sync_progress().pending_scripts, 0);
+
+	let events = std::mem::take(&mut *confirmable.events.lock().unwrap());
+	assert_eq!(events.len(), 0);
+	assert!(confirmable.confirmed_txs.lock().unwrap().is_empty());
+	assert!(confirmable.unconfirmed_txs.lock().unwrap().is_empty());
+
+	generate_blocks_and_wait(&bitcoind, &electrsd, 1);
+	tx_sync.sync(vec![&confirmable]).unwrap();
+
+	let events = std::mem::take(&mut *confirmable.events.lock().unwrap());
+	assert_eq!(events.len(), 2);
+	assert!(confirmable.confirmed_txs.lock().unwrap().contains_key(&txid));
+	assert!(confirmable.unconfirmed_txs.lock().unwrap().is_empty());
+
+	// Check previously confirmed transactions are marked unconfirmed when they are reorged.
+	let best_block_hash = bitcoind.client.get_best_block_hash().unwrap();
+	bitcoind.client.invalidate_block(&best_block_hash).unwrap();
+
+	// We're getting back to the previous height with a new tip, but best block shouldn't change.
+	generate_blocks_and_wait(&bitcoind, &electrsd, 1);
+	assert_ne!(bitcoind.client.get_best_block_hash().unwrap(), best_block_hash);
+	tx_sync.sync(vec![&confirmable]).unwrap();
+	let events = std::mem::take(&mut *confirmable.events.lock().unwrap());
+	assert_eq!(events.len(), 0);
+
+	// Now we're surpassing previous height, getting new tip.
+	generate_blocks_and_wait(&bitcoind, &electrsd, 1);
+	assert_ne!(bitcoind.client.get_best_block_hash().unwrap(), best_block_hash);
+	tx_sync.sync(vec![&confirmable]).unwrap();
+
+	// Transaction still confirmed but under new tip.
+	assert!(confirmable.confirmed_txs.lock().unwrap().contains_key(&txid));
+	assert!(confirmable.unconfirmed_txs.lock().unwrap().is_empty());
+
+	// Check we got unconfirmed, then reconfirmed in the meantime.
+	let events = std::mem::take(&mut *confirmable.events.lock().unwrap());
+	assert_eq!(events.len(), 3);
+
+	match events[0] {
+		TestConfirmableEvent::Unconfirmed(t) => {
+			assert_eq!(t, txid);
+		},
+		_ => panic!("Unexpected event"),
+	}
+
+	match events[1] {
+		TestConfirmableEvent::BestBlockUpdated(..) => {},
+		_ => panic!("Unexpected event"),
+	}
+
+	match events[2] {
+		TestConfirmableEvent::Confirmed(t, _, _) => {
+			assert_eq!(t, txid);
+		},
+		_ => panic!("Unexpected event"),
+	}
+}
+
+#[test]
+#[cfg(feature = "electrum")]
+fn test_electrum_fails_over() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	generate_blocks_and_wait(&bitcoind, &electrsd, 101);
+	let mut logger = TestLogger {};
+
+	let statuses = std::sync::Arc::new(Mutex::new(Vec::new()));
+	let callback_statuses = std::sync::Arc::clone(&statuses);
+	let config = ElectrumSyncConfig {
+		max_retries: 2,
+		initial_backoff: Duration::from_millis(10),
+		max_backoff: Duration::from_millis(20),
+		status_callback: Some(Box::new(move |status: ElectrumSyncStatus| callback_statuses.lock().unwrap().push(status))),
+	};
+
+	// Nothing listens on the first server's port, thus we have to fail over to the second one.
+	let unreachable_url = "tcp://127.0.0.1:1".to_string();
+	let electrum_url = format!("tcp://{}", electrsd.electrum_url);
+	let tx_sync = ElectrumSyncClient::with_config(vec![unreachable_url.clone(), electrum_url.clone()], config, &mut logger).unwrap();
+
+	assert_eq!(*statuses.lock().unwrap(), vec![
+		ElectrumSyncStatus::Degraded {
+			server_url: unreachable_url,
+			failed_attempts: 1,
+			retry_in: Some(Duration::from_millis(10)),
+		},
+		ElectrumSyncStatus::Recovered { server_url: electrum_url },
+	]);
+
+	let confirmable = TestConfirmable::new();
+	tx_sync.sync(vec![&confirmable]).unwrap();
+	assert_eq!(confirmable.best_block.lock().unwrap().1, 102);
+	assert_eq!(tx_sync.sync_progress(), SyncProgress { synced_height: 102, tip_height: 102, pending_scripts: 0 });
+	assert_eq!(statuses.lock().unwrap().len(), 2);
+
+	// If no server is reachable, we give up once all retries are exhausted.
+	let config = ElectrumSyncConfig {
+		max_retries: 1,
+		initial_backoff: Duration::from_millis(10),
+		..Default::default()
+	};
+	assert!(ElectrumSyncClient::with_config(vec!["tcp://127.0.0.1:1".to_string()], config, &mut TestLogger {}).is_err());
+}
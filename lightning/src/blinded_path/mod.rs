@@ -8,17 +8,27 @@
 // licenses.
 
 //! Creating blinded paths and related utilities live here.
+//!
+//! Blinded paths may be built for onion messages via [`BlindedPath::new_for_message`] and for
+//! payments via [`BlindedPath::new_for_payment`], given only the public keys and hop data of the
+//! nodes along the path, so they can be constructed without running a node, e.g., by an LSP on
+//! behalf of its clients. The node a [`BlindedHop`] was blinded for can decrypt and verify it via
+//! [`BlindedHop::decrypt_message_tlvs`] or [`BlindedHop::decrypt_payment_tlvs`].
 
+pub mod payment;
 pub(crate) mod utils;
 
 use bitcoin::hashes::{Hash, HashEngine};
+use bitcoin::hashes::hmac::{Hmac, HmacEngine};
 use bitcoin::hashes::sha256::Hash as Sha256;
 use bitcoin::secp256k1::{self, PublicKey, Scalar, Secp256k1, SecretKey};
+use bitcoin::secp256k1::ecdh::SharedSecret;
 
 use crate::sign::{EntropySource, NodeSigner, Recipient};
 use crate::onion_message::ControlTlvs;
 use crate::ln::msgs::DecodeError;
 use crate::ln::onion_utils;
+use crate::offers::invoice::BlindedPayInfo;
 use crate::util::chacha20poly1305rfc::{ChaChaPolyReadAdapter, ChaChaPolyWriteAdapter};
 use crate::util::ser::{FixedLengthReader, LengthReadableArgs, Readable, VecWriter, Writeable, Writer};
 
@@ -67,18 +77,110 @@ impl BlindedPath {
 	pub fn new_for_message<ES: EntropySource, T: secp256k1::Signing + secp256k1::Verification>
 		(node_pks: &[PublicKey], entropy_source: &ES, secp_ctx: &Secp256k1<T>) -> Result<Self, ()>
 	{
-		if node_pks.len() < 2 { return Err(()) }
 		let blinding_secret_bytes = entropy_source.get_secure_random_bytes();
 		let blinding_secret = SecretKey::from_slice(&blinding_secret_bytes[..]).expect("RNG is busted");
+		Self::new_for_message_with_blinding_secret(node_pks, &blinding_secret, secp_ctx)
+	}
+
+	/// Similar to [`BlindedPath::new_for_message`], but blinds the path with the given
+	/// `blinding_secret` rather than a freshly generated one, making the path deterministic.
+	///
+	/// The `blinding_secret` must be kept private and never be reused across paths, as it allows
+	/// unblinding the path.
+	pub fn new_for_message_with_blinding_secret<T: secp256k1::Signing + secp256k1::Verification>
+		(node_pks: &[PublicKey], blinding_secret: &SecretKey, secp_ctx: &Secp256k1<T>) -> Result<Self, ()>
+	{
+		if node_pks.len() < 2 { return Err(()) }
 		let introduction_node_id = node_pks[0];
 
 		Ok(BlindedPath {
 			introduction_node_id,
-			blinding_point: PublicKey::from_secret_key(secp_ctx, &blinding_secret),
-			blinded_hops: blinded_message_hops(secp_ctx, node_pks, &blinding_secret).map_err(|_| ())?,
+			blinding_point: PublicKey::from_secret_key(secp_ctx, blinding_secret),
+			blinded_hops: blinded_message_hops(secp_ctx, node_pks, blinding_secret).map_err(|_| ())?,
 		})
 	}
 
+	/// Create a blinded path for a payment, to be forwarded along `intermediate_nodes` to the
+	/// payee, along with the [`BlindedPayInfo`] a sender needs to pay over it, see
+	/// [`payment::compute_payinfo`]. If `intermediate_nodes` is empty, the payee is the
+	/// introduction node.
+	///
+	/// Errors if the [`BlindedPayInfo`] cannot be computed or if a `node_id` is invalid.
+	pub fn new_for_payment<ES: EntropySource + ?Sized, T: secp256k1::Signing + secp256k1::Verification>(
+		intermediate_nodes: &[payment::ForwardNode], payee_node_id: PublicKey,
+		payee_tlvs: payment::ReceiveTlvs, payee_htlc_maximum_msat: u64,
+		min_final_cltv_expiry_delta: u16, entropy_source: &ES, secp_ctx: &Secp256k1<T>
+	) -> Result<(BlindedPayInfo, Self), ()> {
+		let blinding_secret_bytes = entropy_source.get_secure_random_bytes();
+		let blinding_secret = SecretKey::from_slice(&blinding_secret_bytes[..]).expect("RNG is busted");
+		Self::new_for_payment_with_blinding_secret(intermediate_nodes, payee_node_id, payee_tlvs,
+			payee_htlc_maximum_msat, min_final_cltv_expiry_delta, &blinding_secret, secp_ctx)
+	}
+
+	/// Similar to [`BlindedPath::new_for_payment`], but blinds the path with the given
+	/// `blinding_secret` rather than a freshly generated one, making the path deterministic.
+	///
+	/// The `blinding_secret` must be kept private and never be reused across paths, as it allows
+	/// unblinding the path.
+	pub fn new_for_payment_with_blinding_secret<T: secp256k1::Signing + secp256k1::Verification>(
+		intermediate_nodes: &[payment::ForwardNode], payee_node_id: PublicKey,
+		payee_tlvs: payment::ReceiveTlvs, payee_htlc_maximum_msat: u64,
+		min_final_cltv_expiry_delta: u16, blinding_secret: &SecretKey, secp_ctx: &Secp256k1<T>
+	) -> Result<(BlindedPayInfo, Self), ()> {
+		Self::new_for_payment_internal(intermediate_nodes, payee_node_id, payee_tlvs,
+			payee_htlc_maximum_msat, min_final_cltv_expiry_delta, 0, blinding_secret, secp_ctx)
+	}
+
+	/// Similar to [`BlindedPath::new_for_payment`], but pads the path with `dummy_hop_count` hops
+	/// preceding the payee's, which the payee peels by itself, see
+	/// [`payment::BlindedPaymentTlvs::Dummy`].
+	pub fn new_for_payment_with_dummy_hops<ES: EntropySource + ?Sized, T: secp256k1::Signing + secp256k1::Verification>(
+		intermediate_nodes: &[payment::ForwardNode], payee_node_id: PublicKey,
+		payee_tlvs: payment::ReceiveTlvs, payee_htlc_maximum_msat: u64,
+		min_final_cltv_expiry_delta: u16, dummy_hop_count: u8, entropy_source: &ES,
+		secp_ctx: &Secp256k1<T>
+	) -> Result<(BlindedPayInfo, Self), ()> {
+		let blinding_secret_bytes = entropy_source.get_secure_random_bytes();
+		let blinding_secret = SecretKey::from_slice(&blinding_secret_bytes[..]).expect("RNG is busted");
+		Self::new_for_payment_internal(intermediate_nodes, payee_node_id, payee_tlvs,
+			payee_htlc_maximum_msat, min_final_cltv_expiry_delta, dummy_hop_count, &blinding_secret,
+			secp_ctx)
+	}
+
+	fn new_for_payment_internal<T: secp256k1::Signing + secp256k1::Verification>(
+		intermediate_nodes: &[payment::ForwardNode], payee_node_id: PublicKey,
+		payee_tlvs: payment::ReceiveTlvs, payee_htlc_maximum_msat: u64,
+		min_final_cltv_expiry_delta: u16, dummy_hop_count: u8, blinding_secret: &SecretKey,
+		secp_ctx: &Secp256k1<T>
+	) -> Result<(BlindedPayInfo, Self), ()> {
+		let payinfo = payment::compute_payinfo(intermediate_nodes, &payee_tlvs,
+			payee_htlc_maximum_msat, min_final_cltv_expiry_delta)?;
+		let introduction_node_id = intermediate_nodes.first()
+			.map_or(payee_node_id, |node| node.node_id);
+
+		Ok((payinfo, BlindedPath {
+			introduction_node_id,
+			blinding_point: PublicKey::from_secret_key(secp_ctx, blinding_secret),
+			blinded_hops: payment::blinded_hops(secp_ctx, intermediate_nodes, payee_node_id,
+				&payee_tlvs, dummy_hop_count, blinding_secret).map_err(|_| ())?,
+		}))
+	}
+
+	/// The unblinded node a sender has to find a route to in order to use this path.
+	pub fn introduction_node_id(&self) -> PublicKey {
+		self.introduction_node_id
+	}
+
+	/// The blinding point the introduction node uses to decrypt its [`BlindedHop`].
+	pub fn blinding_point(&self) -> PublicKey {
+		self.blinding_point
+	}
+
+	/// The hops composing this path, starting with the introduction node's.
+	pub fn blinded_hops(&self) -> &[BlindedHop] {
+		&self.blinded_hops
+	}
+
 	// Advance the blinded onion message path by one hop, so make the second hop into the new
 	// introduction node.
 	pub(super) fn advance_message_path_by_one<NS: Deref, T: secp256k1::Signing + secp256k1::Verification>
@@ -116,6 +218,90 @@ impl BlindedPath {
 	}
 }
 
+impl BlindedHop {
+	/// Decrypts this hop of a blinded path for an onion message, given the secret key of the node
+	/// it was blinded for and the blinding point it was reached with, which for the introduction
+	/// node is [`BlindedPath::blinding_point`].
+	///
+	/// Returns the hop's TLVs along with the blinding point for the next hop, or an error if the
+	/// hop was not blinded for `node_secret` or its payload is invalid.
+	pub fn decrypt_message_tlvs<T: secp256k1::Signing + secp256k1::Verification>(
+		&self, node_secret: &SecretKey, blinding_point: &PublicKey, secp_ctx: &Secp256k1<T>
+	) -> Result<(BlindedMessageTlvs, PublicKey), ()> {
+		let (tlvs, next_blinding_point) = self.decrypt_payload(node_secret, blinding_point, secp_ctx)?;
+		match tlvs {
+			ControlTlvs::Forward(ForwardTlvs { next_node_id, next_blinding_override }) => Ok((
+				BlindedMessageTlvs::Forward { next_node_id, next_blinding_override },
+				next_blinding_override.unwrap_or(next_blinding_point),
+			)),
+			ControlTlvs::Receive(ReceiveTlvs { path_id }) =>
+				Ok((BlindedMessageTlvs::Receive { path_id }, next_blinding_point)),
+		}
+	}
+
+	/// Decrypts this hop of a blinded path for a payment, see [`BlindedHop::decrypt_message_tlvs`].
+	pub fn decrypt_payment_tlvs<T: secp256k1::Signing + secp256k1::Verification>(
+		&self, node_secret: &SecretKey, blinding_point: &PublicKey, secp_ctx: &Secp256k1<T>
+	) -> Result<(payment::BlindedPaymentTlvs, PublicKey), ()> {
+		self.decrypt_payload(node_secret, blinding_point, secp_ctx)
+	}
+
+	fn decrypt_payload<R: Readable, T: secp256k1::Signing + secp256k1::Verification>(
+		&self, node_secret: &SecretKey, blinding_point: &PublicKey, secp_ctx: &Secp256k1<T>
+	) -> Result<(R, PublicKey), ()> {
+		let encrypted_data_ss = SharedSecret::new(blinding_point, node_secret);
+
+		// Check that this hop was blinded for our node before attempting to decrypt it.
+		let blinding_factor = {
+			let mut hmac = HmacEngine::<Sha256>::new(b"blinded_node_id");
+			hmac.input(encrypted_data_ss.as_ref());
+			Hmac::from_engine(hmac).into_inner()
+		};
+		let blinded_node_id = PublicKey::from_secret_key(secp_ctx, node_secret)
+			.mul_tweak(secp_ctx, &Scalar::from_be_bytes(blinding_factor).map_err(|_| ())?)
+			.map_err(|_| ())?;
+		if blinded_node_id != self.blinded_node_id { return Err(()) }
+
+		let rho = onion_utils::gen_rho_from_shared_secret(encrypted_data_ss.as_ref());
+		let mut s = Cursor::new(&self.encrypted_payload);
+		let mut reader = FixedLengthReader::new(&mut s, self.encrypted_payload.len() as u64);
+		let tlvs = match ChaChaPolyReadAdapter::read(&mut reader, rho) {
+			Ok(ChaChaPolyReadAdapter { readable }) => readable,
+			Err(_) => return Err(()),
+		};
+
+		let next_blinding_factor = {
+			let mut sha = Sha256::engine();
+			sha.input(&blinding_point.serialize()[..]);
+			sha.input(encrypted_data_ss.as_ref());
+			Sha256::from_engine(sha).into_inner()
+		};
+		let next_blinding_point = blinding_point
+			.mul_tweak(secp_ctx, &Scalar::from_be_bytes(next_blinding_factor).map_err(|_| ())?)
+			.map_err(|_| ())?;
+		Ok((tlvs, next_blinding_point))
+	}
+}
+
+/// The decrypted payload of a [`BlindedHop`] in a blinded path for an onion message, see
+/// [`BlindedHop::decrypt_message_tlvs`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BlindedMessageTlvs {
+	/// The onion message is to be forwarded to the next hop.
+	Forward {
+		/// The node id of the next hop in the onion message's path.
+		next_node_id: PublicKey,
+		/// The blinding point to hand to the next hop, if it differs from the one derived for it.
+		next_blinding_override: Option<PublicKey>,
+	},
+	/// The onion message is to be received by this hop.
+	Receive {
+		/// The identifier of the blinded path, if any, used by the recipient to check that the path
+		/// is being used in the right context.
+		path_id: Option<[u8; 32]>,
+	},
+}
+
 /// Construct blinded onion message hops for the given `unblinded_path`.
 fn blinded_message_hops<T: secp256k1::Signing + secp256k1::Verification>(
 	secp_ctx: &Secp256k1<T>, unblinded_path: &[PublicKey], session_priv: &SecretKey
@@ -231,3 +417,125 @@ impl Writeable for ReceiveTlvs {
 		Ok(())
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+	use super::{BlindedHop, BlindedMessageTlvs, BlindedPath};
+	use hex;
+
+	use crate::prelude::*;
+
+	fn pubkey(hex_str: &str) -> PublicKey {
+		PublicKey::from_slice(&hex::decode(hex_str).unwrap()[..]).unwrap()
+	}
+
+	/// The keys of the BOLT 4 route blinding test vectors, see `bolt04/route-blinding-test.json`,
+	/// blinding the Bob -> Carol and Dave -> Eve routes.
+	pub(super) struct RouteBlindingTestVectors {
+		pub(super) node_secrets: [SecretKey; 4],
+		pub(super) session_keys: [SecretKey; 2],
+	}
+
+	pub(super) fn route_blinding_test_vectors() -> RouteBlindingTestVectors {
+		let secret = |byte: u8| SecretKey::from_slice(&[byte; 32]).unwrap();
+		RouteBlindingTestVectors {
+			// Bob, Carol, Dave and Eve.
+			node_secrets: [secret(0x42), secret(0x43), secret(0x44), secret(0x45)],
+			// The session keys of the Bob -> Carol and Dave -> Eve routes.
+			session_keys: [secret(0x02), secret(0x01)],
+		}
+	}
+
+	/// Checks that `path` blinds the given route as in the BOLT 4 route blinding test vectors,
+	/// which only depends on the nodes' keys and the session key rather than on the hops' TLVs.
+	///
+	/// `decrypt` decrypts a hop given the node's secret and the hop's ephemeral pubkey, returning the
+	/// ephemeral pubkey of the next hop.
+	pub(super) fn check_route_blinding_test_vectors<F: Fn(&BlindedHop, &SecretKey, &PublicKey) -> PublicKey>(
+		path: &BlindedPath, blinded_node_ids: &[&str], ephemeral_pubkeys: &[&str], node_secrets: &[SecretKey],
+		decrypt: F
+	) {
+		let secp_ctx = Secp256k1::new();
+		assert_eq!(path.introduction_node_id(), PublicKey::from_secret_key(&secp_ctx, &node_secrets[0]));
+		assert_eq!(path.blinding_point(), pubkey(ephemeral_pubkeys[0]));
+		let actual_blinded_node_ids: Vec<PublicKey> =
+			path.blinded_hops().iter().map(|hop| hop.blinded_node_id).collect();
+		let expected_blinded_node_ids: Vec<PublicKey> =
+			blinded_node_ids.iter().map(|hex_str| pubkey(hex_str)).collect();
+		assert_eq!(actual_blinded_node_ids, expected_blinded_node_ids);
+
+		// Each hop derives the ephemeral pubkey of the next while decrypting its own.
+		for (idx, hop) in path.blinded_hops().iter().enumerate().take(path.blinded_hops().len() - 1) {
+			let next_blinding_point = decrypt(hop, &node_secrets[idx], &pubkey(ephemeral_pubkeys[idx]));
+			assert_eq!(next_blinding_point, pubkey(ephemeral_pubkeys[idx + 1]));
+		}
+	}
+
+	fn decrypt_message_hop(hop: &BlindedHop, node_secret: &SecretKey, blinding_point: &PublicKey) -> PublicKey {
+		hop.decrypt_message_tlvs(node_secret, blinding_point, &Secp256k1::new()).unwrap().1
+	}
+
+	#[test]
+	fn matches_route_blinding_test_vectors() {
+		let secp_ctx = Secp256k1::new();
+		let vectors = route_blinding_test_vectors();
+		let node_pks: Vec<PublicKey> = vectors.node_secrets.iter()
+			.map(|secret| PublicKey::from_secret_key(&secp_ctx, secret)).collect();
+
+		let bob_carol = BlindedPath::new_for_message_with_blinding_secret(
+			&node_pks[..2], &vectors.session_keys[0], &secp_ctx).unwrap();
+		check_route_blinding_test_vectors(&bob_carol, &[
+			"03da173ad2aee2f701f17e59fbd16cb708906d69838a5f088e8123fb36e89a2c25",
+			"02e466727716f044290abf91a14a6d90e87487da160c2a3cbd0d465d7a78eb83a7",
+		], &[
+			"024d4b6cd1361032ca9bd2aeb9d900aa4d45d9ead80ac9423374c451a7254d0766",
+			"034e09f450a80c3d252b258aba0a61215bf60dda3b0dc78ffb0736ea1259dfd8a0",
+		], &vectors.node_secrets[..2], decrypt_message_hop);
+
+		let dave_eve = BlindedPath::new_for_message_with_blinding_secret(
+			&node_pks[2..], &vectors.session_keys[1], &secp_ctx).unwrap();
+		check_route_blinding_test_vectors(&dave_eve, &[
+			"036861b366f284f0a11738ffbf7eda46241a8977592878fe3175ae1d1e4754eccf",
+			"021982a48086cb8984427d3727fe35a03d396b234f0701f5249daa12e8105c8dae",
+		], &[
+			"031b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078f",
+			"03e09038ee76e50f444b19abf0a555e8697e035f62937168b80adf0931b31ce52a",
+		], &vectors.node_secrets[2..], decrypt_message_hop);
+	}
+
+	#[test]
+	fn constructs_and_decrypts_message_path() {
+		let secp_ctx = Secp256k1::new();
+		let node_secrets = [
+			SecretKey::from_slice(&[41; 32]).unwrap(),
+			SecretKey::from_slice(&[42; 32]).unwrap(),
+			SecretKey::from_slice(&[43; 32]).unwrap(),
+		];
+		let node_pks: Vec<PublicKey> = node_secrets.iter()
+			.map(|secret| PublicKey::from_secret_key(&secp_ctx, secret)).collect();
+		let blinding_secret = SecretKey::from_slice(&[44; 32]).unwrap();
+
+		assert!(BlindedPath::new_for_message_with_blinding_secret(&node_pks[..1], &blinding_secret, &secp_ctx).is_err());
+		let path = BlindedPath::new_for_message_with_blinding_secret(&node_pks, &blinding_secret, &secp_ctx).unwrap();
+		assert_eq!(path.introduction_node_id(), node_pks[0]);
+		assert_eq!(path.blinding_point(), PublicKey::from_secret_key(&secp_ctx, &blinding_secret));
+		assert_eq!(path.blinded_hops().len(), 3);
+		assert_eq!(BlindedPath::new_for_message_with_blinding_secret(&node_pks, &blinding_secret, &secp_ctx), Ok(path.clone()));
+
+		// Each hop may only be decrypted by the node it was blinded for.
+		assert!(path.blinded_hops()[0].decrypt_message_tlvs(&node_secrets[1], &path.blinding_point(), &secp_ctx).is_err());
+
+		let mut blinding_point = path.blinding_point();
+		for (idx, hop) in path.blinded_hops().iter().enumerate() {
+			let (tlvs, next_blinding_point) = hop.decrypt_message_tlvs(&node_secrets[idx], &blinding_point, &secp_ctx).unwrap();
+			match node_pks.get(idx + 1) {
+				Some(next_node_id) => assert_eq!(tlvs, BlindedMessageTlvs::Forward {
+					next_node_id: *next_node_id, next_blinding_override: None,
+				}),
+				None => assert_eq!(tlvs, BlindedMessageTlvs::Receive { path_id: None }),
+			}
+			blinding_point = next_blinding_point;
+		}
+	}
+}
@@ -0,0 +1,476 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Data structures and methods for constructing [`BlindedPath`]s to send a payment over.
+//!
+//! [`BlindedPath`]: crate::blinded_path::BlindedPath
+
+use bitcoin::secp256k1::{self, PublicKey, Secp256k1, SecretKey};
+use bitcoin::secp256k1::ecdh::SharedSecret;
+
+use crate::blinded_path::BlindedHop;
+use crate::blinded_path::utils;
+use crate::io;
+use crate::ln::features::BlindedHopFeatures;
+use crate::ln::msgs::DecodeError;
+use crate::ln::onion_utils;
+use crate::offers::invoice::BlindedPayInfo;
+use crate::onion_message::Padding;
+use crate::util::chacha20poly1305rfc::ChaChaPolyReadAdapter;
+use crate::util::ser::{self, FixedLengthReader, HighZeroBytesDroppedBigSize, LengthReadableArgs, Readable, WithoutLength, Writeable, Writer};
+
+use core::convert::TryFrom;
+use crate::prelude::*;
+
+/// An intermediate node, its outbound channel, and relay parameters.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ForwardNode {
+	/// The TLVs for this node's [`BlindedHop`], where the fee parameters contained within are also
+	/// used for [`BlindedPayInfo`] construction.
+	pub tlvs: ForwardTlvs,
+	/// This node's pubkey.
+	pub node_id: PublicKey,
+	/// The maximum value, in msat, that may be accepted by this node.
+	pub htlc_maximum_msat: u64,
+}
+
+/// Data to construct a [`BlindedHop`] for forwarding a payment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ForwardTlvs {
+	/// The short channel id this payment should be forwarded out over.
+	pub short_channel_id: u64,
+	/// Payment parameters for relaying over [`Self::short_channel_id`].
+	pub payment_relay: PaymentRelay,
+	/// Payment constraints for relaying over [`Self::short_channel_id`].
+	pub payment_constraints: PaymentConstraints,
+	/// Supported and required features when relaying a payment onion containing this object's
+	/// corresponding [`BlindedHop::encrypted_payload`].
+	pub features: BlindedHopFeatures,
+}
+
+/// Data to construct a [`BlindedHop`] for receiving a payment. This payload is custom to LDK and
+/// may not be valid if received by another lightning implementation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReceiveTlvs {
+	/// Used by the recipient to identify the payment and check that the blinded path is being used
+	/// in the right context, e.g., the payment secret of the invoice the path was included in.
+	pub path_id: [u8; 32],
+	/// Constraints for the receiver of this payment.
+	pub payment_constraints: PaymentConstraints,
+}
+
+/// Parameters for relaying over a given [`BlindedHop`].
+///
+/// [`BlindedHop`]: crate::blinded_path::BlindedHop
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PaymentRelay {
+	/// Number of blocks subtracted from an incoming HTLC's `cltv_expiry` for this [`BlindedHop`].
+	pub cltv_expiry_delta: u16,
+	/// Liquidity fee charged (in millionths of the amount transferred) for relaying a payment over
+	/// this [`BlindedHop`], (i.e., 10,000 is 1%).
+	pub fee_proportional_millionths: u32,
+	/// Base fee charged (in millisatoshi) for relaying a payment over this [`BlindedHop`].
+	pub fee_base_msat: u32,
+}
+
+/// Constraints for relaying over a given [`BlindedHop`].
+///
+/// [`BlindedHop`]: crate::blinded_path::BlindedHop
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PaymentConstraints {
+	/// The maximum total CLTV delta that is acceptable when relaying a payment over this
+	/// [`BlindedHop`].
+	pub max_cltv_expiry: u32,
+	/// The minimum value, in msat, that may be accepted by the node corresponding to this
+	/// [`BlindedHop`].
+	pub htlc_minimum_msat: u64,
+}
+
+/// The decrypted payload of a [`BlindedHop`] in a blinded path for a payment, see
+/// [`BlindedHop::decrypt_payment_tlvs`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BlindedPaymentTlvs {
+	/// This blinded payment data is for a forwarding node.
+	Forward(ForwardTlvs),
+	/// This blinded payment data is for the receiving node.
+	Receive(ReceiveTlvs),
+	/// This blinded payment data is for a dummy hop preceding the receiving node's, which the
+	/// receiving node peels by itself. Dummy hops pad the number of hops in a path, obscuring the
+	/// receiving node's distance from the introduction node.
+	Dummy,
+}
+
+/// The (empty) data of a dummy [`BlindedHop`], see [`BlindedPaymentTlvs::Dummy`].
+struct DummyTlvs;
+
+impl Writeable for ForwardTlvs {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		encode_tlv_stream!(w, {
+			(2, self.short_channel_id, required),
+			(10, self.payment_relay, required),
+			(12, self.payment_constraints, required),
+			(14, WithoutLength(&self.features), required)
+		});
+		Ok(())
+	}
+}
+
+impl Writeable for ReceiveTlvs {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		encode_tlv_stream!(w, {
+			(6, self.path_id, required),
+			(12, self.payment_constraints, required),
+		});
+		Ok(())
+	}
+}
+
+impl Writeable for DummyTlvs {
+	fn write<W: Writer>(&self, _w: &mut W) -> Result<(), io::Error> {
+		Ok(())
+	}
+}
+
+impl Writeable for BlindedPaymentTlvs {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		match self {
+			Self::Forward(tlvs) => tlvs.write(w)?,
+			Self::Receive(tlvs) => tlvs.write(w)?,
+			Self::Dummy => DummyTlvs.write(w)?,
+		}
+		Ok(())
+	}
+}
+
+impl Readable for BlindedPaymentTlvs {
+	fn read<R: io::Read>(mut r: &mut R) -> Result<Self, DecodeError> {
+		let mut _padding: Option<Padding> = None;
+		let mut short_channel_id: Option<u64> = None;
+		let mut path_id: Option<[u8; 32]> = None;
+		let mut payment_relay: Option<PaymentRelay> = None;
+		let mut payment_constraints: Option<PaymentConstraints> = None;
+		let mut features: Option<BlindedHopFeatures> = None;
+		decode_tlv_stream!(&mut r, {
+			(1, _padding, option),
+			(2, short_channel_id, option),
+			(6, path_id, option),
+			(10, payment_relay, option),
+			(12, payment_constraints, option),
+			(14, features, (option, encoding: (BlindedHopFeatures, WithoutLength))),
+		});
+
+		if let Some(short_channel_id) = short_channel_id {
+			if path_id.is_some() { return Err(DecodeError::InvalidValue) }
+			Ok(BlindedPaymentTlvs::Forward(ForwardTlvs {
+				short_channel_id,
+				payment_relay: payment_relay.ok_or(DecodeError::InvalidValue)?,
+				payment_constraints: payment_constraints.ok_or(DecodeError::InvalidValue)?,
+				features: features.unwrap_or_else(BlindedHopFeatures::empty),
+			}))
+		} else if path_id.is_none() && payment_constraints.is_none() {
+			if payment_relay.is_some() || features.is_some() { return Err(DecodeError::InvalidValue) }
+			Ok(BlindedPaymentTlvs::Dummy)
+		} else {
+			if payment_relay.is_some() || features.is_some() { return Err(DecodeError::InvalidValue) }
+			Ok(BlindedPaymentTlvs::Receive(ReceiveTlvs {
+				path_id: path_id.ok_or(DecodeError::InvalidValue)?,
+				payment_constraints: payment_constraints.ok_or(DecodeError::InvalidValue)?,
+			}))
+		}
+	}
+}
+
+/// Decrypts the `encrypted_tlvs` of a payment onion payload given the shared secret of our node id
+/// and the blinding point of our hop, which is given alongside the `encrypted_tlvs` if we're the
+/// introduction node of the blinded path and by the previous hop otherwise.
+pub(crate) fn decrypt_blinded_payment_tlvs(
+	encrypted_tlvs: &[u8], encrypted_data_ss: &SharedSecret
+) -> Result<BlindedPaymentTlvs, DecodeError> {
+	let rho = onion_utils::gen_rho_from_shared_secret(encrypted_data_ss.as_ref());
+	let mut s = io::Cursor::new(encrypted_tlvs);
+	let mut reader = FixedLengthReader::new(&mut s, encrypted_tlvs.len() as u64);
+	let read_adapter: ChaChaPolyReadAdapter<BlindedPaymentTlvs> = LengthReadableArgs::read(&mut reader, rho)?;
+	Ok(read_adapter.readable)
+}
+
+impl Writeable for PaymentRelay {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		self.cltv_expiry_delta.write(w)?;
+		self.fee_proportional_millionths.write(w)?;
+		HighZeroBytesDroppedBigSize(self.fee_base_msat).write(w)
+	}
+}
+
+impl Readable for PaymentRelay {
+	fn read<R: io::Read>(r: &mut R) -> Result<Self, DecodeError> {
+		let cltv_expiry_delta: u16 = Readable::read(r)?;
+		let fee_proportional_millionths: u32 = Readable::read(r)?;
+		let fee_base_msat: HighZeroBytesDroppedBigSize<u32> = Readable::read(r)?;
+		Ok(Self { cltv_expiry_delta, fee_proportional_millionths, fee_base_msat: fee_base_msat.0 })
+	}
+}
+
+impl Writeable for PaymentConstraints {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		self.max_cltv_expiry.write(w)?;
+		HighZeroBytesDroppedBigSize(self.htlc_minimum_msat).write(w)
+	}
+}
+
+impl Readable for PaymentConstraints {
+	fn read<R: io::Read>(r: &mut R) -> Result<Self, DecodeError> {
+		let max_cltv_expiry: u32 = Readable::read(r)?;
+		let htlc_minimum_msat: HighZeroBytesDroppedBigSize<u64> = Readable::read(r)?;
+		Ok(Self { max_cltv_expiry, htlc_minimum_msat: htlc_minimum_msat.0 })
+	}
+}
+
+/// Construct blinded payment hops for the given `intermediate_nodes` and payee info, with
+/// `dummy_hop_count` dummy hops for the payee preceding its final hop.
+pub(super) fn blinded_hops<T: secp256k1::Signing + secp256k1::Verification>(
+	secp_ctx: &Secp256k1<T>, intermediate_nodes: &[ForwardNode], payee_node_id: PublicKey,
+	payee_tlvs: &ReceiveTlvs, dummy_hop_count: u8, session_priv: &SecretKey
+) -> Result<Vec<BlindedHop>, secp256k1::Error> {
+	let mut unblinded_path: Vec<PublicKey> = intermediate_nodes.iter().map(|node| node.node_id).collect();
+	unblinded_path.extend(core::iter::repeat(payee_node_id).take(dummy_hop_count as usize + 1));
+
+	let final_hop_idx = unblinded_path.len() - 1;
+	let mut blinded_hops = Vec::with_capacity(unblinded_path.len());
+	utils::construct_keys_callback(secp_ctx, &unblinded_path, None, session_priv, |blinded_node_id, _, _, encrypted_payload_ss, _, _| {
+		let encrypted_payload = match intermediate_nodes.get(blinded_hops.len()) {
+			Some(node) => super::encrypt_payload(&node.tlvs, encrypted_payload_ss),
+			None if blinded_hops.len() < final_hop_idx => super::encrypt_payload(DummyTlvs, encrypted_payload_ss),
+			None => super::encrypt_payload(payee_tlvs, encrypted_payload_ss),
+		};
+		blinded_hops.push(BlindedHop { blinded_node_id, encrypted_payload });
+	})?;
+	Ok(blinded_hops)
+}
+
+/// Returns the amount an intermediate node forwards given the amount it receives, i.e., the
+/// inverse of charging its fees, or `None` if the amount does not cover its base fee.
+pub(crate) fn amt_to_forward_msat(inbound_amt_msat: u64, payment_relay: &PaymentRelay) -> Option<u64> {
+	let inbound_amt = inbound_amt_msat as u128;
+	let base = payment_relay.fee_base_msat as u128;
+	let prop = payment_relay.fee_proportional_millionths as u128;
+
+	let post_base_fee_inbound_amt = inbound_amt.checked_sub(base)?;
+	let mut amt_to_forward =
+		(post_base_fee_inbound_amt * 1_000_000 + 1_000_000 + prop - 1) / (prop + 1_000_000);
+
+	// Rounding up above may leave the node with less than its full fee, so correct for that.
+	let fee = ((amt_to_forward * prop) / 1_000_000) + base;
+	if inbound_amt - fee < amt_to_forward {
+		amt_to_forward -= 1;
+	}
+	u64::try_from(amt_to_forward).ok()
+}
+
+/// Computes the [`BlindedPayInfo`] for a blinded path through the given `intermediate_nodes` to a
+/// payee with the given constraints, aggregating the fees and CLTV deltas of all hops as
+/// specified in BOLT 4.
+///
+/// Errors if no amount can be sent over the path within all of its hops' HTLC limits, or if the
+/// path's aggregate fees or CLTV delta overflow.
+pub fn compute_payinfo(
+	intermediate_nodes: &[ForwardNode], payee_tlvs: &ReceiveTlvs, payee_htlc_maximum_msat: u64,
+	min_final_cltv_expiry_delta: u16,
+) -> Result<BlindedPayInfo, ()> {
+	let mut fee_base_msat: u128 = 0;
+	let mut fee_proportional_millionths: u128 = 0;
+	let mut cltv_expiry_delta = min_final_cltv_expiry_delta;
+	for node in intermediate_nodes.iter().rev() {
+		let next_base_msat = node.tlvs.payment_relay.fee_base_msat as u128;
+		let next_prop_mil = node.tlvs.payment_relay.fee_proportional_millionths as u128;
+		fee_base_msat = (next_base_msat * 1_000_000 + fee_base_msat * (1_000_000 + next_prop_mil)
+			+ 1_000_000 - 1) / 1_000_000;
+		fee_proportional_millionths = ((fee_proportional_millionths + next_prop_mil) * 1_000_000
+			+ fee_proportional_millionths * next_prop_mil + 1_000_000 - 1) / 1_000_000;
+		cltv_expiry_delta = cltv_expiry_delta.checked_add(node.tlvs.payment_relay.cltv_expiry_delta)
+			.ok_or(())?;
+	}
+
+	// The limits of each hop are translated to amounts as seen by the payee, as the fees of all
+	// following hops are already included in the amount a hop receives.
+	let mut htlc_minimum_msat: u64 = 1;
+	let mut htlc_maximum_msat: u64 = 21_000_000 * 100_000_000 * 1_000;
+	for node in intermediate_nodes.iter() {
+		htlc_minimum_msat = amt_to_forward_msat(
+			core::cmp::max(node.tlvs.payment_constraints.htlc_minimum_msat, htlc_minimum_msat),
+			&node.tlvs.payment_relay
+		).unwrap_or(1);
+		htlc_maximum_msat = amt_to_forward_msat(
+			core::cmp::min(node.htlc_maximum_msat, htlc_maximum_msat), &node.tlvs.payment_relay
+		).ok_or(())?;
+	}
+	htlc_minimum_msat = core::cmp::max(payee_tlvs.payment_constraints.htlc_minimum_msat, htlc_minimum_msat);
+	htlc_maximum_msat = core::cmp::min(payee_htlc_maximum_msat, htlc_maximum_msat);
+	if htlc_maximum_msat < htlc_minimum_msat { return Err(()) }
+
+	Ok(BlindedPayInfo {
+		fee_base_msat: u32::try_from(fee_base_msat).map_err(|_| ())?,
+		fee_proportional_millionths: u32::try_from(fee_proportional_millionths).map_err(|_| ())?,
+		cltv_expiry_delta,
+		htlc_minimum_msat,
+		htlc_maximum_msat,
+		features: BlindedHopFeatures::empty(),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+	use super::{BlindedPaymentTlvs, ForwardNode, ForwardTlvs, PaymentConstraints, PaymentRelay, ReceiveTlvs};
+	use crate::blinded_path::{BlindedHop, BlindedPath};
+	use crate::blinded_path::tests::{check_route_blinding_test_vectors, route_blinding_test_vectors};
+	use crate::ln::features::BlindedHopFeatures;
+
+	use crate::prelude::*;
+
+	fn forward_node(node_secret: &SecretKey, short_channel_id: u64) -> ForwardNode {
+		ForwardNode {
+			node_id: PublicKey::from_secret_key(&Secp256k1::new(), node_secret),
+			tlvs: ForwardTlvs {
+				short_channel_id,
+				payment_relay: PaymentRelay {
+					cltv_expiry_delta: 144,
+					fee_proportional_millionths: 500,
+					fee_base_msat: 100,
+				},
+				payment_constraints: PaymentConstraints {
+					max_cltv_expiry: 800_000,
+					htlc_minimum_msat: 1_000,
+				},
+				features: BlindedHopFeatures::empty(),
+			},
+			htlc_maximum_msat: 1_000_000,
+		}
+	}
+
+	#[test]
+	fn constructs_and_decrypts_payment_path() {
+		let secp_ctx = Secp256k1::new();
+		let node_secrets = [
+			SecretKey::from_slice(&[41; 32]).unwrap(),
+			SecretKey::from_slice(&[42; 32]).unwrap(),
+			SecretKey::from_slice(&[43; 32]).unwrap(),
+		];
+		let blinding_secret = SecretKey::from_slice(&[44; 32]).unwrap();
+		let intermediate_nodes = vec![forward_node(&node_secrets[0], 1), forward_node(&node_secrets[1], 2)];
+		let payee_tlvs = ReceiveTlvs {
+			path_id: [45; 32],
+			payment_constraints: PaymentConstraints { max_cltv_expiry: 800_000, htlc_minimum_msat: 1 },
+		};
+		let payee_node_id = PublicKey::from_secret_key(&secp_ctx, &node_secrets[2]);
+
+		let (_, path) = BlindedPath::new_for_payment_with_blinding_secret(&intermediate_nodes,
+			payee_node_id, payee_tlvs.clone(), 5_000_000, 12, &blinding_secret, &secp_ctx).unwrap();
+		assert_eq!(path.introduction_node_id, intermediate_nodes[0].node_id);
+		assert_eq!(path.blinding_point, PublicKey::from_secret_key(&secp_ctx, &blinding_secret));
+		assert_eq!(path.blinded_hops.len(), 3);
+
+		// Paths are deterministic given the blinding secret.
+		assert_eq!(BlindedPath::new_for_payment_with_blinding_secret(&intermediate_nodes, payee_node_id,
+			payee_tlvs.clone(), 5_000_000, 12, &blinding_secret, &secp_ctx).unwrap().1, path);
+
+		// Each hop may only be decrypted by the node it was blinded for.
+		assert!(path.blinded_hops[0].decrypt_payment_tlvs(&node_secrets[1], &path.blinding_point, &secp_ctx).is_err());
+
+		let mut blinding_point = path.blinding_point;
+		for (idx, hop) in path.blinded_hops.iter().enumerate() {
+			let (tlvs, next_blinding_point) = hop.decrypt_payment_tlvs(&node_secrets[idx], &blinding_point, &secp_ctx).unwrap();
+			match intermediate_nodes.get(idx) {
+				Some(node) => assert_eq!(tlvs, BlindedPaymentTlvs::Forward(node.tlvs.clone())),
+				None => assert_eq!(tlvs, BlindedPaymentTlvs::Receive(payee_tlvs.clone())),
+			}
+			blinding_point = next_blinding_point;
+		}
+	}
+
+	#[test]
+	fn matches_route_blinding_test_vectors() {
+		// The Bob -> Carol route of the BOLT 4 route blinding test vectors, blinded for a payment.
+		let secp_ctx = Secp256k1::new();
+		let vectors = route_blinding_test_vectors();
+		let intermediate_nodes = vec![forward_node(&vectors.node_secrets[0], 1729)];
+		let payee_tlvs = ReceiveTlvs {
+			path_id: [45; 32],
+			payment_constraints: PaymentConstraints { max_cltv_expiry: 800_000, htlc_minimum_msat: 1 },
+		};
+		let payee_node_id = PublicKey::from_secret_key(&secp_ctx, &vectors.node_secrets[1]);
+		let (_, path) = BlindedPath::new_for_payment_with_blinding_secret(&intermediate_nodes,
+			payee_node_id, payee_tlvs, 5_000_000, 12, &vectors.session_keys[0], &secp_ctx).unwrap();
+		check_route_blinding_test_vectors(&path, &[
+			"03da173ad2aee2f701f17e59fbd16cb708906d69838a5f088e8123fb36e89a2c25",
+			"02e466727716f044290abf91a14a6d90e87487da160c2a3cbd0d465d7a78eb83a7",
+		], &[
+			"024d4b6cd1361032ca9bd2aeb9d900aa4d45d9ead80ac9423374c451a7254d0766",
+			"034e09f450a80c3d252b258aba0a61215bf60dda3b0dc78ffb0736ea1259dfd8a0",
+		], &vectors.node_secrets[..2], |hop: &BlindedHop, node_secret: &SecretKey, blinding_point: &PublicKey| {
+			hop.decrypt_payment_tlvs(node_secret, blinding_point, &Secp256k1::new()).unwrap().1
+		});
+	}
+
+	#[test]
+	fn computes_payinfo() {
+		// The fees and CLTV deltas are aggregated as in the BOLT 4 route blinding example.
+		let intermediate_nodes = vec![
+			forward_node(&SecretKey::from_slice(&[41; 32]).unwrap(), 1),
+			forward_node(&SecretKey::from_slice(&[42; 32]).unwrap(), 2),
+		];
+		let payee_tlvs = ReceiveTlvs {
+			path_id: [45; 32],
+			payment_constraints: PaymentConstraints { max_cltv_expiry: 800_000, htlc_minimum_msat: 1 },
+		};
+		let payinfo = super::compute_payinfo(&intermediate_nodes, &payee_tlvs, 5_000_000, 12).unwrap();
+		assert_eq!(payinfo.fee_base_msat, 201);
+		assert_eq!(payinfo.fee_proportional_millionths, 1001);
+		assert_eq!(payinfo.cltv_expiry_delta, 300);
+		assert_eq!(payinfo.htlc_minimum_msat, 900);
+		assert_eq!(payinfo.htlc_maximum_msat, 998_802);
+
+		// Paths over which no amount fits within all hops' limits are rejected.
+		assert!(super::compute_payinfo(&intermediate_nodes, &payee_tlvs, 800, 12).is_err());
+	}
+
+	#[test]
+	fn pads_payment_path_with_dummy_hops() {
+		let secp_ctx = Secp256k1::new();
+		let node_secrets = [
+			SecretKey::from_slice(&[41; 32]).unwrap(),
+			SecretKey::from_slice(&[42; 32]).unwrap(),
+		];
+		let intermediate_nodes = vec![forward_node(&node_secrets[0], 1)];
+		let payee_tlvs = ReceiveTlvs {
+			path_id: [45; 32],
+			payment_constraints: PaymentConstraints { max_cltv_expiry: 800_000, htlc_minimum_msat: 1 },
+		};
+		let payee_node_id = PublicKey::from_secret_key(&secp_ctx, &node_secrets[1]);
+		let keys_manager = crate::util::test_utils::TestKeysInterface::new(&[0; 32], bitcoin::Network::Testnet);
+
+		let (payinfo, path) = BlindedPath::new_for_payment_with_dummy_hops(&intermediate_nodes,
+			payee_node_id, payee_tlvs.clone(), 5_000_000, 12, 2, &keys_manager, &secp_ctx).unwrap();
+		assert_eq!(path.blinded_hops.len(), 4);
+
+		// Dummy hops don't change what it takes to pay over the path.
+		assert_eq!(payinfo, super::compute_payinfo(&intermediate_nodes, &payee_tlvs, 5_000_000, 12).unwrap());
+
+		// The payee peels its dummy hops before reaching its final one.
+		let (tlvs, mut blinding_point) = path.blinded_hops[0]
+			.decrypt_payment_tlvs(&node_secrets[0], &path.blinding_point, &secp_ctx).unwrap();
+		assert_eq!(tlvs, BlindedPaymentTlvs::Forward(intermediate_nodes[0].tlvs.clone()));
+		for hop in path.blinded_hops[1..3].iter() {
+			let (tlvs, next_blinding_point) = hop.decrypt_payment_tlvs(&node_secrets[1], &blinding_point, &secp_ctx).unwrap();
+			assert_eq!(tlvs, BlindedPaymentTlvs::Dummy);
+			blinding_point = next_blinding_point;
+		}
+		let (tlvs, _) = path.blinded_hops[3].decrypt_payment_tlvs(&node_secrets[1], &blinding_point, &secp_ctx).unwrap();
+		assert_eq!(tlvs, BlindedPaymentTlvs::Receive(payee_tlvs));
+	}
+}
@@ -14,9 +14,14 @@
 //! disconnections, transaction broadcasting, and feerate information requests.
 
 use core::{cmp, ops::Deref};
+use core::time::Duration;
 
 use bitcoin::blockdata::transaction::Transaction;
 
+use crate::prelude::*;
+use crate::sync::Mutex;
+use crate::util::time::Time;
+
 /// An interface to send a transaction to the Bitcoin network.
 pub trait BroadcasterInterface {
 	/// Sends a list of transactions out to (hopefully) be mined.
@@ -45,8 +50,32 @@ pub enum ConfirmationTarget {
 	Normal,
 	/// We'd like this transaction to confirm in the next few blocks.
 	HighPriority,
+	/// We're claiming outputs on-chain which are at risk of being claimed by our counterparty if
+	/// we don't confirm in time, e.g., HTLCs close to their expiry or revoked outputs.
+	///
+	/// This should be at least as high as [`ConfirmationTarget::HighPriority`], as any delay may
+	/// result in a loss of funds.
+	OnChainSweep,
+	/// We're bumping the fee of one of our commitment transactions via its anchor output, but none
+	/// of the HTLCs it contains (if any) are at immediate risk.
+	///
+	/// The commitment transaction should confirm eventually, but 12-18 blocks is fine.
+	AnchorChannelFee,
+	/// We're negotiating the fee of a cooperative closing transaction and this is the lowest
+	/// feerate we're willing to accept. As our funds are not at risk, waiting a day or more for
+	/// confirmation is fine.
+	ChannelCloseMinimum,
 }
 
+impl_writeable_tlv_based_enum!(ConfirmationTarget,
+	(0, Background) => {},
+	(2, Normal) => {},
+	(4, HighPriority) => {},
+	(6, OnChainSweep) => {},
+	(8, AnchorChannelFee) => {},
+	(10, ChannelCloseMinimum) => {};
+);
+
 /// A trait which should be implemented to provide feerate information on a number of time
 /// horizons.
 ///
@@ -92,9 +121,221 @@ impl<F: Deref> LowerBoundedFeeEstimator<F> where F::Target: FeeEstimator {
 	}
 }
 
+/// Converts a feerate in satoshis per virtual byte to satoshis per 1000 weight units.
+pub fn sat_per_vbyte_to_sat_per_1000_weight(sat_per_vbyte: u32) -> u32 {
+	sat_per_vbyte.saturating_mul(250)
+}
+
+/// Converts a feerate in satoshis per 1000 virtual bytes to satoshis per 1000 weight units,
+/// rounding down.
+///
+/// This is the unit Bitcoin Core's `estimatesmartfee` RPC returns its estimates in (after
+/// converting from BTC to satoshis), and allows expressing fractional sat/vB feerates.
+pub fn sat_per_kvbyte_to_sat_per_1000_weight(sat_per_kvbyte: u32) -> u32 {
+	sat_per_kvbyte / 4
+}
+
+/// Converts a feerate in satoshis per 1000 weight units to satoshis per virtual byte, rounding up
+/// so that the result is never below the given feerate.
+pub fn sat_per_1000_weight_to_sat_per_vbyte(sat_per_1000_weight: u32) -> u32 {
+	((sat_per_1000_weight as u64 + 249) / 250) as u32
+}
+
+#[cfg(not(any(feature = "no-std", test)))]
+type ConfiguredTime = std::time::Instant;
+#[cfg(feature = "no-std")]
+type ConfiguredTime = crate::util::time::Eternity;
+#[cfg(all(not(feature = "no-std"), test))]
+type ConfiguredTime = crate::util::time::tests::SinceEpoch;
+
+/// A [`FeeEstimator`] which caches the estimates of another one for a configurable time per
+/// [`ConfirmationTarget`], clamping them to sane bounds.
+///
+/// Fee sources are often remote services which are slow to query or rate-limited, while LDK may
+/// request estimates frequently. Cached estimates are returned until their time-to-live expires,
+/// after which the next request for the respective [`ConfirmationTarget`] queries the wrapped
+/// source again. By default, estimates are bounded below by [`FEERATE_FLOOR_SATS_PER_KW`] and are
+/// not bounded above.
+///
+/// With the `no-std` feature, no time is considered to ever pass and thus estimates are cached
+/// until [`CachingFeeEstimatorUsingTime::clear_cache`] is called. To expire them using a
+/// host-supplied clock instead, use a [`CachingFeeEstimatorUsingTime`] with a [`ProvidedTime`].
+///
+/// [`ProvidedTime`]: crate::util::clock::ProvidedTime
+pub type CachingFeeEstimator<F> = CachingFeeEstimatorUsingTime<F, ConfiguredTime>;
+
+/// [`FeeEstimator`] implementation caching the estimates of another [`FeeEstimator`].
+///
+/// This is not exported to bindings users generally all users should use the
+/// [`CachingFeeEstimator`] type alias.
+pub struct CachingFeeEstimatorUsingTime<F: Deref, T: Time> where F::Target: FeeEstimator {
+	source: F,
+	default_ttl: Duration,
+	target_ttls: HashMap<ConfirmationTarget, Duration>,
+	min_sat_per_1000_weight: u32,
+	max_sat_per_1000_weight: u32,
+	cache: Mutex<HashMap<ConfirmationTarget, (u32, T)>>,
+}
+
+impl<F: Deref, T: Time> CachingFeeEstimatorUsingTime<F, T> where F::Target: FeeEstimator {
+	/// Creates a new `CachingFeeEstimator` caching the estimates of `source` for `default_ttl`.
+	pub fn new(source: F, default_ttl: Duration) -> Self {
+		Self {
+			source,
+			default_ttl,
+			target_ttls: HashMap::new(),
+			min_sat_per_1000_weight: FEERATE_FLOOR_SATS_PER_KW,
+			max_sat_per_1000_weight: u32::max_value(),
+			cache: Mutex::new(HashMap::new()),
+		}
+	}
+
+	/// Sets the time estimates for the given [`ConfirmationTarget`] are cached for, overriding the
+	/// default.
+	pub fn with_target_ttl(mut self, confirmation_target: ConfirmationTarget, ttl: Duration) -> Self {
+		self.target_ttls.insert(confirmation_target, ttl);
+		self
+	}
+
+	/// Clamps all estimates to be between `min_sat_per_1000_weight` and `max_sat_per_1000_weight`.
+	///
+	/// Note that the minimum is never set below [`FEERATE_FLOOR_SATS_PER_KW`] and takes precedence
+	/// over the maximum.
+	pub fn with_feerate_bounds(mut self, min_sat_per_1000_weight: u32, max_sat_per_1000_weight: u32) -> Self {
+		self.min_sat_per_1000_weight = cmp::max(min_sat_per_1000_weight, FEERATE_FLOOR_SATS_PER_KW);
+		self.max_sat_per_1000_weight = max_sat_per_1000_weight;
+		self
+	}
+
+	/// Drops all cached estimates, such that the next request for any [`ConfirmationTarget`]
+	/// queries the wrapped source again.
+	pub fn clear_cache(&self) {
+		self.cache.lock().unwrap().clear();
+	}
+}
+
+impl<F: Deref, T: Time> FeeEstimator for CachingFeeEstimatorUsingTime<F, T> where F::Target: FeeEstimator {
+	fn get_est_sat_per_1000_weight(&self, confirmation_target: ConfirmationTarget) -> u32 {
+		let ttl = self.target_ttls.get(&confirmation_target).copied().unwrap_or(self.default_ttl);
+		let mut cache = self.cache.lock().unwrap();
+		if let Some((sat_per_1000_weight, fetched_at)) = cache.get(&confirmation_target) {
+			if fetched_at.elapsed() < ttl {
+				return *sat_per_1000_weight;
+			}
+		}
+
+		let sat_per_1000_weight = cmp::max(
+			cmp::min(self.source.get_est_sat_per_1000_weight(confirmation_target), self.max_sat_per_1000_weight),
+			self.min_sat_per_1000_weight,
+		);
+		cache.insert(confirmation_target, (sat_per_1000_weight, T::now()));
+		sat_per_1000_weight
+	}
+}
+
+/// A [`FeeEstimator`] which always returns the same, statically configured feerate for a given
+/// [`ConfirmationTarget`].
+///
+/// Useful as the fallback of a [`CombinedFeeEstimator`], or when no fee estimation source is
+/// available at all.
+#[derive(Clone, Debug)]
+pub struct StaticFeeEstimator {
+	default_sat_per_1000_weight: u32,
+	target_sat_per_1000_weight: HashMap<ConfirmationTarget, u32>,
+}
+
+impl StaticFeeEstimator {
+	/// Creates a new `StaticFeeEstimator` returning `sat_per_1000_weight` for all
+	/// [`ConfirmationTarget`]s.
+	pub fn new(sat_per_1000_weight: u32) -> Self {
+		Self { default_sat_per_1000_weight: sat_per_1000_weight, target_sat_per_1000_weight: HashMap::new() }
+	}
+
+	/// Sets the feerate returned for the given [`ConfirmationTarget`], overriding the default.
+	pub fn with_target_feerate(mut self, confirmation_target: ConfirmationTarget, sat_per_1000_weight: u32) -> Self {
+		self.target_sat_per_1000_weight.insert(confirmation_target, sat_per_1000_weight);
+		self
+	}
+}
+
+impl FeeEstimator for StaticFeeEstimator {
+	fn get_est_sat_per_1000_weight(&self, confirmation_target: ConfirmationTarget) -> u32 {
+		self.target_sat_per_1000_weight.get(&confirmation_target).copied()
+			.unwrap_or(self.default_sat_per_1000_weight)
+	}
+}
+
+/// A [`FeeEstimator`] combining a primary fee estimation source with a mempool minimum feerate
+/// floor and a static fallback, such that a single misbehaving source cannot result in unusable
+/// or absurd feerates.
+///
+/// For each [`ConfirmationTarget`], the primary estimate is used unless it is below
+/// [`FEERATE_FLOOR_SATS_PER_KW`], which is taken to mean no estimate is available (e.g., because
+/// the source is unreachable or hasn't collected enough data yet), in which case the fallback's
+/// estimate is used instead. The result is then capped at the configured maximum feerate, if
+/// any, and finally raised to at least the mempool minimum feerate, as transactions paying less
+/// would not be relayed at all.
+///
+/// The mempool minimum feerate is taken from another [`FeeEstimator`], which should return the
+/// current minimum feerate accepted into the mempool regardless of the given target, e.g., based
+/// on the `mempoolminfee` reported by Bitcoin Core's `getmempoolinfo` RPC.
+pub struct CombinedFeeEstimator<P: Deref, M: Deref>
+where
+	P::Target: FeeEstimator,
+	M::Target: FeeEstimator,
+{
+	primary: P,
+	mempool_min_fee: M,
+	fallback: StaticFeeEstimator,
+	max_sat_per_1000_weight: Option<u32>,
+}
+
+impl<P: Deref, M: Deref> CombinedFeeEstimator<P, M>
+where
+	P::Target: FeeEstimator,
+	M::Target: FeeEstimator,
+{
+	/// Creates a new `CombinedFeeEstimator` from the given sources. No maximum feerate is applied
+	/// unless set via [`CombinedFeeEstimator::with_max_feerate`].
+	pub fn new(primary: P, mempool_min_fee: M, fallback: StaticFeeEstimator) -> Self {
+		Self { primary, mempool_min_fee, fallback, max_sat_per_1000_weight: None }
+	}
+
+	/// Caps all estimates of the primary source and the fallback at `max_sat_per_1000_weight`.
+	///
+	/// Note that the mempool minimum feerate still takes precedence over the cap.
+	pub fn with_max_feerate(mut self, max_sat_per_1000_weight: u32) -> Self {
+		self.max_sat_per_1000_weight = Some(max_sat_per_1000_weight);
+		self
+	}
+}
+
+impl<P: Deref, M: Deref> FeeEstimator for CombinedFeeEstimator<P, M>
+where
+	P::Target: FeeEstimator,
+	M::Target: FeeEstimator,
+{
+	fn get_est_sat_per_1000_weight(&self, confirmation_target: ConfirmationTarget) -> u32 {
+		let mut sat_per_1000_weight = self.primary.get_est_sat_per_1000_weight(confirmation_target);
+		if sat_per_1000_weight < FEERATE_FLOOR_SATS_PER_KW {
+			sat_per_1000_weight = self.fallback.get_est_sat_per_1000_weight(confirmation_target);
+		}
+		if let Some(max_sat_per_1000_weight) = self.max_sat_per_1000_weight {
+			sat_per_1000_weight = cmp::min(sat_per_1000_weight, max_sat_per_1000_weight);
+		}
+		cmp::max(sat_per_1000_weight, self.mempool_min_fee.get_est_sat_per_1000_weight(confirmation_target))
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::{FEERATE_FLOOR_SATS_PER_KW, LowerBoundedFeeEstimator, ConfirmationTarget, FeeEstimator};
+	use super::{CombinedFeeEstimator, StaticFeeEstimator, CachingFeeEstimator};
+	use super::{sat_per_vbyte_to_sat_per_1000_weight, sat_per_kvbyte_to_sat_per_1000_weight, sat_per_1000_weight_to_sat_per_vbyte};
+	use crate::util::time::tests::SinceEpoch;
+
+	use core::sync::atomic::{AtomicU32, Ordering};
+	use core::time::Duration;
 
 	struct TestFeeEstimator {
 		sat_per_kw: u32,
@@ -123,4 +364,108 @@ mod tests {
 
 		assert_eq!(fee_estimator.bounded_sat_per_1000_weight(ConfirmationTarget::Background), sat_per_kw);
 	}
+
+	#[test]
+	fn test_static_fee_estimator() {
+		let fee_estimator = StaticFeeEstimator::new(1000)
+			.with_target_feerate(ConfirmationTarget::OnChainSweep, 5000);
+
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::Normal), 1000);
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::OnChainSweep), 5000);
+	}
+
+	#[test]
+	fn test_combined_fee_estimator_uses_primary() {
+		let primary = TestFeeEstimator { sat_per_kw: 2000 };
+		let mempool_min_fee = TestFeeEstimator { sat_per_kw: FEERATE_FLOOR_SATS_PER_KW };
+		let fee_estimator = CombinedFeeEstimator::new(&primary, &mempool_min_fee, StaticFeeEstimator::new(1000));
+
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::Normal), 2000);
+	}
+
+	#[test]
+	fn test_combined_fee_estimator_falls_back_without_estimate() {
+		let primary = TestFeeEstimator { sat_per_kw: 0 };
+		let mempool_min_fee = TestFeeEstimator { sat_per_kw: FEERATE_FLOOR_SATS_PER_KW };
+		let fallback = StaticFeeEstimator::new(1000)
+			.with_target_feerate(ConfirmationTarget::OnChainSweep, 5000);
+		let fee_estimator = CombinedFeeEstimator::new(&primary, &mempool_min_fee, fallback);
+
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::Normal), 1000);
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::OnChainSweep), 5000);
+	}
+
+	#[test]
+	fn test_combined_fee_estimator_bounds() {
+		let primary = TestFeeEstimator { sat_per_kw: 1_000_000 };
+		let mempool_min_fee = TestFeeEstimator { sat_per_kw: 3000 };
+		let fee_estimator = CombinedFeeEstimator::new(&primary, &mempool_min_fee, StaticFeeEstimator::new(1000))
+			.with_max_feerate(10_000);
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::HighPriority), 10_000);
+
+		// The mempool minimum feerate takes precedence over the maximum feerate.
+		let fee_estimator = CombinedFeeEstimator::new(&primary, &mempool_min_fee, StaticFeeEstimator::new(1000))
+			.with_max_feerate(2000);
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::HighPriority), 3000);
+	}
+
+	struct CountingFeeEstimator {
+		sat_per_kw: AtomicU32,
+		queries: AtomicU32,
+	}
+
+	impl FeeEstimator for CountingFeeEstimator {
+		fn get_est_sat_per_1000_weight(&self, _: ConfirmationTarget) -> u32 {
+			self.queries.fetch_add(1, Ordering::Relaxed);
+			self.sat_per_kw.load(Ordering::Relaxed)
+		}
+	}
+
+	#[test]
+	fn test_feerate_unit_conversions() {
+		assert_eq!(sat_per_vbyte_to_sat_per_1000_weight(1), 250);
+		assert_eq!(sat_per_kvbyte_to_sat_per_1000_weight(1_500), 375);
+		assert_eq!(sat_per_1000_weight_to_sat_per_vbyte(250), 1);
+		assert_eq!(sat_per_1000_weight_to_sat_per_vbyte(FEERATE_FLOOR_SATS_PER_KW), 2);
+		assert_eq!(sat_per_vbyte_to_sat_per_1000_weight(u32::max_value()), u32::max_value());
+		assert_eq!(sat_per_1000_weight_to_sat_per_vbyte(u32::max_value()), u32::max_value() / 250 + 1);
+	}
+
+	#[test]
+	fn test_caching_fee_estimator_expires_estimates() {
+		let source = CountingFeeEstimator { sat_per_kw: AtomicU32::new(1000), queries: AtomicU32::new(0) };
+		let fee_estimator = CachingFeeEstimator::new(&source, Duration::from_secs(60))
+			.with_target_ttl(ConfirmationTarget::OnChainSweep, Duration::from_secs(10));
+
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::Normal), 1000);
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::OnChainSweep), 1000);
+		assert_eq!(source.queries.load(Ordering::Relaxed), 2);
+
+		// Cached estimates are returned until they expire.
+		source.sat_per_kw.store(2000, Ordering::Relaxed);
+		SinceEpoch::advance(Duration::from_secs(10));
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::Normal), 1000);
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::OnChainSweep), 2000);
+		assert_eq!(source.queries.load(Ordering::Relaxed), 3);
+
+		SinceEpoch::advance(Duration::from_secs(50));
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::Normal), 2000);
+		assert_eq!(source.queries.load(Ordering::Relaxed), 4);
+
+		source.sat_per_kw.store(3000, Ordering::Relaxed);
+		fee_estimator.clear_cache();
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::Normal), 3000);
+	}
+
+	#[test]
+	fn test_caching_fee_estimator_bounds() {
+		let source = CountingFeeEstimator { sat_per_kw: AtomicU32::new(0), queries: AtomicU32::new(0) };
+		let fee_estimator = CachingFeeEstimator::new(&source, Duration::from_secs(0));
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::Normal), FEERATE_FLOOR_SATS_PER_KW);
+
+		let fee_estimator = fee_estimator.with_feerate_bounds(500, 10_000);
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::Normal), 500);
+		source.sat_per_kw.store(1_000_000, Ordering::Relaxed);
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::Normal), 10_000);
+	}
 }
@@ -23,11 +23,12 @@
 //! events. The remote server would make use of [`ChainMonitor`] for block processing and for
 //! servicing [`ChannelMonitor`] updates from the client.
 
-use bitcoin::blockdata::block::BlockHeader;
+use bitcoin::blockdata::block::{Block, BlockHeader};
+use bitcoin::blockdata::transaction::Transaction;
 use bitcoin::hash_types::{Txid, BlockHash};
 
 use crate::chain;
-use crate::chain::{ChannelMonitorUpdateStatus, Filter, WatchedOutput};
+use crate::chain::{BestBlock, ChannelMonitorUpdateStatus, Filter, WatchedOutput};
 use crate::chain::chaininterface::{BroadcasterInterface, FeeEstimator};
 use crate::chain::channelmonitor::{ChannelMonitor, ChannelMonitorUpdate, Balance, MonitorEvent, TransactionOutputs, LATENCY_GRACE_PERIOD_BLOCKS};
 use crate::chain::transaction::{OutPoint, TransactionData};
@@ -42,11 +43,14 @@ use crate::ln::channelmanager::ChannelDetails;
 
 use crate::prelude::*;
 use crate::sync::{RwLock, RwLockReadGuard, Mutex, MutexGuard};
+use core::{cmp, mem};
 use core::ops::Deref;
 use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+#[cfg(not(feature = "no-std"))]
+use core::time::Duration;
 use bitcoin::secp256k1::PublicKey;
 
-#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 /// A specific update's ID stored in a `MonitorUpdateId`, separated out to make the contents
 /// entirely opaque.
 enum UpdateOrigin {
@@ -60,7 +64,7 @@ enum UpdateOrigin {
 }
 
 /// An opaque identifier describing a specific [`Persist`] method call.
-#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 pub struct MonitorUpdateId {
 	contents: UpdateOrigin,
 }
@@ -148,6 +152,39 @@ pub trait Persist<ChannelSigner: WriteableEcdsaChannelSigner> {
 	fn update_persisted_channel(&self, channel_id: OutPoint, update: Option<&ChannelMonitorUpdate>, data: &ChannelMonitor<ChannelSigner>, update_id: MonitorUpdateId) -> ChannelMonitorUpdateStatus;
 }
 
+/// An executor which runs a batch of independent jobs, potentially concurrently.
+///
+/// Used by [`ChainMonitor::block_connected_parallel`] and friends to process block data for many
+/// [`ChannelMonitor`]s at once, which can substantially speed up replaying blocks on startup for
+/// nodes with many channels. This may be implemented on top of any thread pool, e.g. via
+/// `rayon::scope` or `std::thread::scope`.
+pub trait ChainSyncExecutor {
+	/// Runs each of the given jobs, in any order and on any thread.
+	///
+	/// This method **must not** return until every job has run to completion, as the jobs borrow
+	/// state from the calling [`ChainMonitor`].
+	fn execute<'a>(&self, jobs: Vec<Box<dyn FnOnce() + Send + 'a>>);
+}
+
+/// When a [`Persist`] call which returned [`ChannelMonitorUpdateStatus::InProgress`] was made,
+/// used to report how long an update has been pending in [`ChainMonitor::monitor_status`].
+#[derive(Clone, Copy)]
+struct PendingUpdateStart {
+	height: u32,
+	#[cfg(not(feature = "no-std"))]
+	instant: std::time::Instant,
+}
+
+impl PendingUpdateStart {
+	fn now(height: usize) -> Self {
+		Self {
+			height: height as u32,
+			#[cfg(not(feature = "no-std"))]
+			instant: std::time::Instant::now(),
+		}
+	}
+}
+
 struct MonitorHolder<ChannelSigner: WriteableEcdsaChannelSigner> {
 	monitor: ChannelMonitor<ChannelSigner>,
 	/// The full set of pending monitor updates for this Channel.
@@ -167,7 +204,7 @@ struct MonitorHolder<ChannelSigner: WriteableEcdsaChannelSigner> {
 	/// the pending payment entry, and then reloading before the monitor is persisted, resulting in
 	/// the ChannelManager re-adding the same payment entry, before the same block is replayed,
 	/// resulting in a duplicate PaymentSent event.
-	pending_monitor_updates: Mutex<Vec<MonitorUpdateId>>,
+	pending_monitor_updates: Mutex<Vec<(MonitorUpdateId, PendingUpdateStart)>>,
 	/// When the user returns a PermanentFailure error from an update_persisted_channel call during
 	/// block processing, we inform the ChannelManager that the channel should be closed
 	/// asynchronously. In order to ensure no further changes happen before the ChannelManager has
@@ -184,14 +221,17 @@ struct MonitorHolder<ChannelSigner: WriteableEcdsaChannelSigner> {
 }
 
 impl<ChannelSigner: WriteableEcdsaChannelSigner> MonitorHolder<ChannelSigner> {
-	fn has_pending_offchain_updates(&self, pending_monitor_updates_lock: &MutexGuard<Vec<MonitorUpdateId>>) -> bool {
-		pending_monitor_updates_lock.iter().any(|update_id|
+	fn has_pending_offchain_updates(&self, pending_monitor_updates_lock: &MutexGuard<Vec<(MonitorUpdateId, PendingUpdateStart)>>) -> bool {
+		pending_monitor_updates_lock.iter().any(|(update_id, _)|
 			if let UpdateOrigin::OffChain(_) = update_id.contents { true } else { false })
 	}
-	fn has_pending_chainsync_updates(&self, pending_monitor_updates_lock: &MutexGuard<Vec<MonitorUpdateId>>) -> bool {
-		pending_monitor_updates_lock.iter().any(|update_id|
+	fn has_pending_chainsync_updates(&self, pending_monitor_updates_lock: &MutexGuard<Vec<(MonitorUpdateId, PendingUpdateStart)>>) -> bool {
+		pending_monitor_updates_lock.iter().any(|(update_id, _)|
 			if let UpdateOrigin::ChainSync(_) = update_id.contents { true } else { false })
 	}
+	fn pending_update_ids(&self) -> Vec<MonitorUpdateId> {
+		self.pending_monitor_updates.lock().unwrap().iter().map(|(update_id, _)| *update_id).collect()
+	}
 }
 
 /// A read-only reference to a current ChannelMonitor.
@@ -210,6 +250,253 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> Deref for LockedChannelMonitor<
 	}
 }
 
+/// A [`Persist`] call which returned [`ChannelMonitorUpdateStatus::InProgress`] and has not yet
+/// been marked complete via [`ChainMonitor::channel_monitor_updated`], as included in a
+/// [`MonitorStatus`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PendingMonitorUpdate {
+	/// The identifier to pass to [`ChainMonitor::channel_monitor_updated`] once the update
+	/// completes.
+	pub update_id: MonitorUpdateId,
+	/// The highest block height the [`ChainMonitor`] had seen when the update was started.
+	pub pending_since_height: u32,
+	/// How long the update has been in progress.
+	#[cfg(not(feature = "no-std"))]
+	pub pending_duration: Duration,
+}
+
+/// A snapshot of the persistence state of a [`ChannelMonitor`], as returned by
+/// [`ChainMonitor::monitor_status`].
+///
+/// A [`ChannelMonitor`] with long-pending updates will eventually cause the `ChannelManager` to
+/// stop using the channel, and HTLCs which cannot be resolved in time may lead to a force-close,
+/// so operators may wish to alert on [`Self::pending_updates`] which have been in progress for
+/// too long.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MonitorStatus {
+	/// The funding outpoint of the channel being monitored.
+	pub funding_txo: OutPoint,
+	/// The node id of our counterparty, if known.
+	pub counterparty_node_id: Option<PublicKey>,
+	/// The [`ChannelMonitorUpdate::update_id`] of the latest update applied to the in-memory
+	/// [`ChannelMonitor`].
+	pub latest_update_id: u64,
+	/// The highest [`ChannelMonitorUpdate::update_id`] which has completed persistence along with
+	/// all updates before it, or `None` if the [`ChannelMonitor`] itself has yet to complete its
+	/// initial persistence.
+	///
+	/// This is equal to [`Self::latest_update_id`] when no off-chain updates are in progress.
+	pub latest_persisted_update_id: Option<u64>,
+	/// Any updates, both from the `ChannelManager` and from chain sync, for which persistence is
+	/// in progress, in the order in which they were started.
+	pub pending_updates: Vec<PendingMonitorUpdate>,
+	/// The latest block the [`ChannelMonitor`] has seen.
+	pub best_block: BestBlock,
+	/// Whether persistence of an update has failed with
+	/// [`ChannelMonitorUpdateStatus::PermanentFailure`], in which case the channel will be closed.
+	pub persist_failed: bool,
+}
+
+/// Limits applied when fee-bumping pending claims via
+/// [`ChainMonitor::rebroadcast_pending_claims_with_fee_bumps`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClaimFeeBumpConfig {
+	/// The highest feerate, in satoshis per 1000 weight units, to which pending claims will be
+	/// forcibly bumped.
+	///
+	/// Note that claims still use any higher feerate returned by the [`FeeEstimator`], which
+	/// should thus be bounded separately if desired, e.g., via [`CombinedFeeEstimator`].
+	///
+	/// Default value: 50,000 (200 sat/vbyte).
+	///
+	/// [`CombinedFeeEstimator`]: crate::chain::chaininterface::CombinedFeeEstimator
+	pub max_feerate_sat_per_1000_weight: u32,
+	/// The number of blocks before our counterparty is able to contest a pending claim at which
+	/// an [`Event::ClaimAtRisk`] is generated for it.
+	///
+	/// Default value: 6.
+	///
+	/// [`Event::ClaimAtRisk`]: crate::events::Event::ClaimAtRisk
+	pub at_risk_threshold_blocks: u32,
+}
+
+impl Default for ClaimFeeBumpConfig {
+	fn default() -> Self {
+		Self {
+			max_feerate_sat_per_1000_weight: 50_000,
+			at_risk_threshold_blocks: 6,
+		}
+	}
+}
+
+/// An amount which will become ours once the chain reaches a given height, as included in a
+/// [`BalanceReport`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MaturingBalance {
+	/// The amount, in satoshis, excluding the on-chain fees which may be required to claim it.
+	pub amount_satoshis: u64,
+	/// The height at which the amount is expected to mature.
+	pub maturity_height: u32,
+}
+
+impl MaturingBalance {
+	/// The number of blocks which remain until the amount matures, given the current height.
+	pub fn blocks_until_maturity(&self, current_height: u32) -> u32 {
+		self.maturity_height.saturating_sub(current_height)
+	}
+}
+
+/// A consistent snapshot of our balances across both open channels and [`ChannelMonitor`]s.
+///
+/// Summing [`ChannelDetails::balance_msat`] and the results of
+/// [`ChainMonitor::get_claimable_balances`] independently is prone to counting a channel twice
+/// (or not at all) if it is opened or closed in between the two calls, and to mixing funds which
+/// are ours with funds which may only become ours. A [`BalanceReport`] built from a single list of
+/// channels avoids both, see [`ChainMonitor::get_balance_report`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BalanceReport {
+	/// Our balance in open channels whose funding transaction has been created, in
+	/// millisatoshis, as the sum of their [`ChannelDetails::balance_msat`].
+	pub channel_balance_msat: u64,
+	/// The on-chain balance, in satoshis, which we can claim now.
+	///
+	/// This includes [`Balance::ClaimableOnChannelClose`] for channels which are missing from the
+	/// list of open channels the report was built with, as well as
+	/// [`Balance::ContentiousClaimable`] and [`Balance::CounterpartyRevokedOutputClaimable`].
+	pub claimable_satoshis: u64,
+	/// Balances which are ours but are awaiting confirmations until they are considered
+	/// spendable, i.e. [`Balance::ClaimableAwaitingConfirmations`], sorted by maturity height.
+	pub awaiting_confirmations: Vec<MaturingBalance>,
+	/// Outbound HTLCs which we can claim back once they time out, unless our counterparty claims
+	/// them first, i.e. [`Balance::MaybeTimeoutClaimableHTLC`], sorted by maturity height.
+	///
+	/// These are not included in [`Self::total_balance_satoshis`].
+	pub maybe_timeout_htlcs: Vec<MaturingBalance>,
+	/// The balance, in satoshis, of inbound HTLCs which we can only claim if we learn their
+	/// preimage, i.e. [`Balance::MaybePreimageClaimableHTLC`].
+	///
+	/// This is not included in [`Self::total_balance_satoshis`].
+	pub maybe_preimage_htlc_satoshis: u64,
+	/// The [`Balance`]s the report was built from.
+	pub monitor_balances: Vec<Balance>,
+}
+
+impl BalanceReport {
+	/// Builds a report from a list of open channels and the [`Balance`]s of all
+	/// [`ChannelMonitor`]s for channels which are *not* in that list.
+	///
+	/// Prefer [`ChainMonitor::get_balance_report`], which ensures both are consistent, unless you
+	/// are using a custom [`chain::Watch`] implementation.
+	pub fn new(channels: &[ChannelDetails], monitor_balances: Vec<Balance>) -> Self {
+		let channel_balance_msat = channels.iter()
+			.filter(|chan| chan.funding_txo.is_some())
+			.map(|chan| chan.balance_msat)
+			.sum();
+		let mut claimable_satoshis = 0;
+		let mut awaiting_confirmations = Vec::new();
+		let mut maybe_timeout_htlcs = Vec::new();
+		let mut maybe_preimage_htlc_satoshis = 0;
+		for balance in monitor_balances.iter() {
+			match balance {
+				Balance::ClaimableOnChannelClose { claimable_amount_satoshis } |
+				Balance::ContentiousClaimable { claimable_amount_satoshis, .. } |
+				Balance::CounterpartyRevokedOutputClaimable { claimable_amount_satoshis } =>
+					claimable_satoshis += claimable_amount_satoshis,
+				Balance::ClaimableAwaitingConfirmations { claimable_amount_satoshis, confirmation_height } =>
+					awaiting_confirmations.push(MaturingBalance {
+						amount_satoshis: *claimable_amount_satoshis, maturity_height: *confirmation_height,
+					}),
+				Balance::MaybeTimeoutClaimableHTLC { claimable_amount_satoshis, claimable_height, .. } =>
+					maybe_timeout_htlcs.push(MaturingBalance {
+						amount_satoshis: *claimable_amount_satoshis, maturity_height: *claimable_height,
+					}),
+				Balance::MaybePreimageClaimableHTLC { claimable_amount_satoshis, .. } =>
+					maybe_preimage_htlc_satoshis += claimable_amount_satoshis,
+			}
+		}
+		awaiting_confirmations.sort_unstable_by_key(|balance| balance.maturity_height);
+		maybe_timeout_htlcs.sort_unstable_by_key(|balance| balance.maturity_height);
+		Self {
+			channel_balance_msat, claimable_satoshis, awaiting_confirmations, maybe_timeout_htlcs,
+			maybe_preimage_htlc_satoshis, monitor_balances,
+		}
+	}
+
+	/// The total balance, in satoshis, which is ours either off-chain or on-chain, excluding any
+	/// HTLCs which may or may not end up being ours.
+	///
+	/// This is the sum of [`Self::channel_balance_msat`] (rounded down), [`Self::claimable_satoshis`]
+	/// and [`Self::awaiting_confirmations`], and does not consider any on-chain fees.
+	pub fn total_balance_satoshis(&self) -> u64 {
+		self.channel_balance_msat / 1000 + self.claimable_satoshis +
+			self.awaiting_confirmations.iter().map(|balance| balance.amount_satoshis).sum::<u64>()
+	}
+
+	/// The amount, in satoshis, which will have matured by the given height, out of
+	/// [`Self::awaiting_confirmations`].
+	pub fn matured_by_height_satoshis(&self, height: u32) -> u64 {
+		self.awaiting_confirmations.iter()
+			.filter(|balance| balance.maturity_height <= height)
+			.map(|balance| balance.amount_satoshis)
+			.sum()
+	}
+}
+
+/// When the on-chain balances of a single closed or closing channel become spendable, as returned
+/// by [`ChainMonitor::get_balance_maturity_schedules`].
+///
+/// Rather than a single pending amount, this allows showing the part of a channel's balance which
+/// is available now and the parts which will only become available in some number of blocks,
+/// e.g. once the `to_self_delay` (CSV) of our commitment transaction output has passed or an
+/// outbound HTLC has timed out (CLTV).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BalanceMaturitySchedule {
+	/// The funding outpoint of the channel.
+	pub funding_txo: OutPoint,
+	/// The node id of our counterparty in the channel, if known. This is only missing for
+	/// [`ChannelMonitor`]s which were created prior to LDK 0.0.110.
+	pub counterparty_node_id: Option<PublicKey>,
+	/// The height of the best block the channel's [`ChannelMonitor`] is aware of, relative to which
+	/// the remaining blocks until each balance matures may be computed via
+	/// [`MaturingBalance::blocks_until_maturity`].
+	pub current_height: u32,
+	/// The balance, in satoshis, which we can claim now, see [`BalanceReport::claimable_satoshis`].
+	pub claimable_satoshis: u64,
+	/// Balances which are ours but are awaiting confirmations until they are considered
+	/// spendable, sorted by maturity height, see [`BalanceReport::awaiting_confirmations`].
+	pub awaiting_confirmations: Vec<MaturingBalance>,
+	/// Outbound HTLCs which we can claim back once they time out, unless our counterparty claims
+	/// them first, sorted by maturity height, see [`BalanceReport::maybe_timeout_htlcs`].
+	pub maybe_timeout_htlcs: Vec<MaturingBalance>,
+	/// The balance, in satoshis, of inbound HTLCs which we can only claim if we learn their
+	/// preimage, see [`BalanceReport::maybe_preimage_htlc_satoshis`].
+	pub maybe_preimage_htlc_satoshis: u64,
+}
+
+impl BalanceMaturitySchedule {
+	/// The amount, in satoshis, which is spendable at the given height, out of
+	/// [`Self::claimable_satoshis`] and [`Self::awaiting_confirmations`].
+	pub fn spendable_by_height_satoshis(&self, height: u32) -> u64 {
+		self.claimable_satoshis + self.awaiting_confirmations.iter()
+			.filter(|balance| balance.maturity_height <= height)
+			.map(|balance| balance.amount_satoshis)
+			.sum::<u64>()
+	}
+
+	/// The number of blocks which remain until all of [`Self::awaiting_confirmations`] has
+	/// matured, or 0 if it is empty.
+	pub fn blocks_until_fully_matured(&self) -> u32 {
+		self.awaiting_confirmations.last()
+			.map(|balance| balance.blocks_until_maturity(self.current_height))
+			.unwrap_or(0)
+	}
+}
+
+/// The maximum number of [`MonitorEvent`]s a [`ChainMonitor`] with a limited
+/// [`ChainMonitor::with_monitor_event_batch_size`] leaves queued in a single [`ChannelMonitor`].
+/// Any events beyond it are released in the next batch regardless of the batch size.
+const MAX_QUEUED_EVENTS_PER_MONITOR: usize = 1000;
+
 /// An implementation of [`chain::Watch`] for monitoring channels.
 ///
 /// Connected and disconnected blocks must be provided to `ChainMonitor` as documented by
@@ -248,6 +535,9 @@ pub struct ChainMonitor<ChannelSigner: WriteableEcdsaChannelSigner, C: Deref, T:
 	pending_monitor_events: Mutex<Vec<(OutPoint, Vec<MonitorEvent>, Option<PublicKey>)>>,
 	/// The best block height seen, used as a proxy for the passage of time.
 	highest_chain_height: AtomicUsize,
+	/// The maximum number of [`MonitorEvent`]s handed out per call to
+	/// [`chain::Watch::release_pending_monitor_events`].
+	monitor_event_batch_size: usize,
 
 	event_notifier: Notifier,
 }
@@ -286,53 +576,109 @@ where C::Target: chain::Filter,
 		}
 
 		for (funding_outpoint, monitor_state) in monitor_states.iter() {
-			let monitor = &monitor_state.monitor;
-			let mut txn_outputs;
-			{
-				txn_outputs = process(monitor, txdata);
-				let update_id = MonitorUpdateId {
-					contents: UpdateOrigin::ChainSync(self.sync_persistence_id.get_increment()),
-				};
-				let mut pending_monitor_updates = monitor_state.pending_monitor_updates.lock().unwrap();
-				if let Some(height) = best_height {
-					if !monitor_state.has_pending_chainsync_updates(&pending_monitor_updates) {
-						// If there are not ChainSync persists awaiting completion, go ahead and
-						// set last_chain_persist_height here - we wouldn't want the first
-						// InProgress to always immediately be considered "overly delayed".
-						monitor_state.last_chain_persist_height.store(height as usize, Ordering::Release);
-					}
-				}
+			let txn_outputs = self.update_monitor_with_chain_data(
+				*funding_outpoint, monitor_state, best_height, txdata, &process);
+			self.register_txn_outputs(header, txn_outputs);
+		}
+	}
 
-				log_trace!(self.logger, "Syncing Channel Monitor for channel {}", log_funding_info!(monitor));
-				match self.persister.update_persisted_channel(*funding_outpoint, None, monitor, update_id) {
-					ChannelMonitorUpdateStatus::Completed =>
-						log_trace!(self.logger, "Finished syncing Channel Monitor for channel {}", log_funding_info!(monitor)),
-					ChannelMonitorUpdateStatus::PermanentFailure => {
-						monitor_state.channel_perm_failed.store(true, Ordering::Release);
-						self.pending_monitor_events.lock().unwrap().push((*funding_outpoint, vec![MonitorEvent::UpdateFailed(*funding_outpoint)], monitor.get_counterparty_node_id()));
-						self.event_notifier.notify();
-					},
-					ChannelMonitorUpdateStatus::InProgress => {
-						log_debug!(self.logger, "Channel Monitor sync for channel {} in progress, holding events until completion!", log_funding_info!(monitor));
-						pending_monitor_updates.push(update_id);
-					},
-				}
+	/// Like [`Self::process_chain_data`], but hands each [`ChannelMonitor`] off to the given
+	/// [`ChainSyncExecutor`] as an independent job, allowing monitors to be processed concurrently.
+	///
+	/// Outputs to watch are only registered with the [`chain::Filter`] once all jobs have
+	/// completed, so the chain source need not be thread-safe.
+	fn process_chain_data_parallel<FN, E: Deref>(&self, header: &BlockHeader, best_height: Option<u32>, txdata: &TransactionData, process: FN, executor: E)
+	where
+		Self: Sync,
+		FN: Fn(&ChannelMonitor<ChannelSigner>, &TransactionData) -> Vec<TransactionOutputs> + Sync,
+		E::Target: ChainSyncExecutor,
+		ChannelMonitor<ChannelSigner>: Sync,
+	{
+		let monitor_states = self.monitors.write().unwrap();
+		if let Some(height) = best_height {
+			let old_height = self.highest_chain_height.load(Ordering::Acquire);
+			let new_height = height as usize;
+			if new_height > old_height {
+				self.highest_chain_height.store(new_height, Ordering::Release);
 			}
+		}
 
-			// Register any new outputs with the chain source for filtering, storing any dependent
-			// transactions from within the block that previously had not been included in txdata.
-			if let Some(ref chain_source) = self.chain_source {
-				let block_hash = header.block_hash();
-				for (txid, mut outputs) in txn_outputs.drain(..) {
-					for (idx, output) in outputs.drain(..) {
-						// Register any new outputs with the chain source for filtering
-						let output = WatchedOutput {
-							block_hash: Some(block_hash),
-							outpoint: OutPoint { txid, index: idx as u16 },
-							script_pubkey: output.script_pubkey,
-						};
-						chain_source.register_output(output)
-					}
+		let txn_outputs = Mutex::new(Vec::with_capacity(monitor_states.len()));
+		{
+			let process = &process;
+			let txn_outputs = &txn_outputs;
+			let mut jobs: Vec<Box<dyn FnOnce() + Send + '_>> = Vec::with_capacity(monitor_states.len());
+			for (funding_outpoint, monitor_state) in monitor_states.iter() {
+				let funding_outpoint = *funding_outpoint;
+				jobs.push(Box::new(move || {
+					let outputs = self.update_monitor_with_chain_data(
+						funding_outpoint, monitor_state, best_height, txdata, process);
+					txn_outputs.lock().unwrap().push(outputs);
+				}));
+			}
+			executor.execute(jobs);
+		}
+
+		for outputs in txn_outputs.into_inner().unwrap() {
+			self.register_txn_outputs(header, outputs);
+		}
+	}
+
+	/// Processes the given chain data for a single [`ChannelMonitor`] and persists the result,
+	/// returning any new outputs the monitor wishes to watch.
+	fn update_monitor_with_chain_data<FN>(
+		&self, funding_outpoint: OutPoint, monitor_state: &MonitorHolder<ChannelSigner>,
+		best_height: Option<u32>, txdata: &TransactionData, process: &FN
+	) -> Vec<TransactionOutputs>
+	where
+		FN: Fn(&ChannelMonitor<ChannelSigner>, &TransactionData) -> Vec<TransactionOutputs>
+	{
+		let monitor = &monitor_state.monitor;
+		let txn_outputs = process(monitor, txdata);
+		let update_id = MonitorUpdateId {
+			contents: UpdateOrigin::ChainSync(self.sync_persistence_id.get_increment()),
+		};
+		let mut pending_monitor_updates = monitor_state.pending_monitor_updates.lock().unwrap();
+		if let Some(height) = best_height {
+			if !monitor_state.has_pending_chainsync_updates(&pending_monitor_updates) {
+				// If there are not ChainSync persists awaiting completion, go ahead and
+				// set last_chain_persist_height here - we wouldn't want the first
+				// InProgress to always immediately be considered "overly delayed".
+				monitor_state.last_chain_persist_height.store(height as usize, Ordering::Release);
+			}
+		}
+
+		log_trace!(self.logger, "Syncing Channel Monitor for channel {}", log_funding_info!(monitor));
+		match self.persister.update_persisted_channel(funding_outpoint, None, monitor, update_id) {
+			ChannelMonitorUpdateStatus::Completed =>
+				log_trace!(self.logger, "Finished syncing Channel Monitor for channel {}", log_funding_info!(monitor)),
+			ChannelMonitorUpdateStatus::PermanentFailure => {
+				monitor_state.channel_perm_failed.store(true, Ordering::Release);
+				self.pending_monitor_events.lock().unwrap().push((funding_outpoint, vec![MonitorEvent::UpdateFailed(funding_outpoint)], monitor.get_counterparty_node_id()));
+				self.event_notifier.notify();
+			},
+			ChannelMonitorUpdateStatus::InProgress => {
+				log_debug!(self.logger, "Channel Monitor sync for channel {} in progress, holding events until completion!", log_funding_info!(monitor));
+				pending_monitor_updates.push((update_id, PendingUpdateStart::now(self.highest_chain_height.load(Ordering::Acquire))));
+			},
+		}
+		txn_outputs
+	}
+
+	/// Register any new outputs with the chain source for filtering, storing any dependent
+	/// transactions from within the block that previously had not been included in txdata.
+	fn register_txn_outputs(&self, header: &BlockHeader, mut txn_outputs: Vec<TransactionOutputs>) {
+		if let Some(ref chain_source) = self.chain_source {
+			let block_hash = header.block_hash();
+			for (txid, mut outputs) in txn_outputs.drain(..) {
+				for (idx, output) in outputs.drain(..) {
+					// Register any new outputs with the chain source for filtering
+					let output = WatchedOutput {
+						block_hash: Some(block_hash),
+						outpoint: OutPoint { txid, index: idx as u16 },
+						script_pubkey: output.script_pubkey,
+					};
+					chain_source.register_output(output)
 				}
 			}
 		}
@@ -356,10 +702,133 @@ where C::Target: chain::Filter,
 			persister,
 			pending_monitor_events: Mutex::new(Vec::new()),
 			highest_chain_height: AtomicUsize::new(0),
+			monitor_event_batch_size: usize::max_value(),
 			event_notifier: Notifier::new(),
 		}
 	}
 
+	/// Limits the number of [`MonitorEvent`]s handed to the `ChannelManager` per call to
+	/// [`chain::Watch::release_pending_monitor_events`] to `max_events`, rather than releasing all
+	/// of them at once.
+	///
+	/// A large reorg or a burst of blocks may resolve thousands of HTLCs at once. With a limit set,
+	/// the resulting events are drained in batches. Events are released by priority:
+	///  * persistence completions and failures (see [`Self::channel_monitor_updated`]) come first,
+	///    as they unblock channel operation,
+	///  * followed by events of [`ChannelMonitor`]s which saw their commitment transaction
+	///    confirm or resolved an HTLC on chain, as funds are at stake,
+	///  * followed by events of all other [`ChannelMonitor`]s.
+	///
+	/// Events of a single [`ChannelMonitor`] are always released in the order they were
+	/// generated. Events which did not fit in a batch remain queued in their [`ChannelMonitor`]
+	/// (and are persisted with it), so block processing may continue while a backlog is drained.
+	/// At most 1000 events are left queued per [`ChannelMonitor`], with any beyond that released
+	/// in the next batch even if it then exceeds `max_events`. Whenever a backlog remains, the
+	/// future returned by [`Self::get_update_future`] is woken, such that the background
+	/// processor continues draining it.
+	///
+	/// A `max_events` of zero is treated as one.
+	pub fn with_monitor_event_batch_size(mut self, max_events: usize) -> Self {
+		self.monitor_event_batch_size = cmp::max(max_events, 1);
+		self
+	}
+
+	/// Gets the number of [`MonitorEvent`]s which have yet to be released via
+	/// [`chain::Watch::release_pending_monitor_events`], including those held back until an
+	/// in-progress [`ChannelMonitor`] persistence completes.
+	pub fn pending_monitor_event_count(&self) -> usize {
+		let user_event_count = self.pending_monitor_events.lock().unwrap().iter()
+			.map(|(_, events, _)| events.len())
+			.sum::<usize>();
+		let monitor_event_count = self.monitors.read().unwrap().values()
+			.map(|monitor_state| monitor_state.monitor.pending_monitor_event_count())
+			.sum::<usize>();
+		user_event_count + monitor_event_count
+	}
+
+	/// Releases up to `max_events` pending [`MonitorEvent`]s in the order documented at
+	/// [`Self::with_monitor_event_batch_size`], returning them alongside whether any releasable
+	/// events remain queued.
+	///
+	/// As with [`chain::Watch::release_pending_monitor_events`], no events are released for a
+	/// [`ChannelMonitor`] while a chain-sync persistence is in progress.
+	fn release_monitor_events(&self, max_events: usize) -> (Vec<(OutPoint, Vec<MonitorEvent>, Option<PublicKey>)>, bool) {
+		let mut remaining_events = max_events;
+		let mut released_events = Vec::new();
+		let mut events_remain;
+		{
+			let mut user_events = self.pending_monitor_events.lock().unwrap();
+			let mut released_entries = 0;
+			for (_, events, _) in user_events.iter() {
+				if events.len() > remaining_events { break; }
+				remaining_events -= events.len();
+				released_entries += 1;
+			}
+			released_events.extend(user_events.drain(..released_entries));
+			if remaining_events > 0 {
+				if let Some((funding_txo, events, counterparty_node_id)) = user_events.first_mut() {
+					let unreleased_events = events.split_off(remaining_events);
+					released_events.push((*funding_txo, mem::replace(events, unreleased_events), *counterparty_node_id));
+					remaining_events = 0;
+				}
+			}
+			events_remain = !user_events.is_empty();
+		}
+
+		let monitor_states = self.monitors.read().unwrap();
+		let mut releasable_states = monitor_states.values()
+			.filter(|monitor_state| self.can_release_monitor_events(monitor_state))
+			.collect::<Vec<_>>();
+		// `sort_by_cached_key` is stable, and `false` sorts first.
+		releasable_states.sort_by_cached_key(|monitor_state| !monitor_state.monitor.has_urgent_pending_monitor_events());
+		for monitor_state in releasable_states {
+			// Release at least enough events to keep the monitor's queue bounded, even if that
+			// exceeds `max_events`.
+			let overflowing_events = monitor_state.monitor.pending_monitor_event_count()
+				.saturating_sub(MAX_QUEUED_EVENTS_PER_MONITOR);
+			let monitor_events = monitor_state.monitor.get_and_clear_pending_monitor_events_chunk(
+				cmp::max(remaining_events, overflowing_events));
+			remaining_events = remaining_events.saturating_sub(monitor_events.len());
+			if monitor_events.len() > 0 {
+				let monitor_outpoint = monitor_state.monitor.get_funding_txo().0;
+				let counterparty_node_id = monitor_state.monitor.get_counterparty_node_id();
+				released_events.push((monitor_outpoint, monitor_events, counterparty_node_id));
+			}
+			events_remain |= monitor_state.monitor.pending_monitor_event_count() > 0;
+		}
+		(released_events, events_remain)
+	}
+
+	/// Checks whether the given [`ChannelMonitor`]'s events may be handed out, i.e. no chain-sync
+	/// persistence is in progress or it has been in progress for too long to keep waiting.
+	fn can_release_monitor_events(&self, monitor_state: &MonitorHolder<ChannelSigner>) -> bool {
+		let is_pending_monitor_update = monitor_state.has_pending_chainsync_updates(&monitor_state.pending_monitor_updates.lock().unwrap());
+		if is_pending_monitor_update &&
+				monitor_state.last_chain_persist_height.load(Ordering::Acquire) + LATENCY_GRACE_PERIOD_BLOCKS as usize
+					> self.highest_chain_height.load(Ordering::Acquire)
+		{
+			log_info!(self.logger, "A Channel Monitor sync is still in progress, refusing to provide monitor events!");
+			return false;
+		}
+		if monitor_state.channel_perm_failed.load(Ordering::Acquire) {
+			// If a `UpdateOrigin::ChainSync` persistence failed with `PermanantFailure`,
+			// we don't really know if the latest `ChannelMonitor` state is on disk or not.
+			// We're supposed to hold monitor updates until the latest state is on disk to
+			// avoid duplicate events, but the user told us persistence is screw-y and may
+			// not complete. We can't hold events forever because we may learn some payment
+			// preimage, so instead we just log and hope the user complied with the
+			// `PermanentFailure` requirements of having at least the local-disk copy
+			// updated.
+			log_info!(self.logger, "A Channel Monitor sync returned PermanentFailure. Returning monitor events but duplicate events may appear after reload!");
+		}
+		if is_pending_monitor_update {
+			log_error!(self.logger, "A ChannelMonitor sync took longer than {} blocks to complete.", LATENCY_GRACE_PERIOD_BLOCKS);
+			log_error!(self.logger, "   To avoid funds-loss, we are allowing monitor updates to be released.");
+			log_error!(self.logger, "   This may cause duplicate payment events to be generated.");
+		}
+		true
+	}
+
 	/// Gets the balances in the contained [`ChannelMonitor`]s which are claimable on-chain or
 	/// claims which are awaiting confirmation.
 	///
@@ -385,6 +854,50 @@ where C::Target: chain::Filter,
 		ret
 	}
 
+	/// Gets a [`BalanceReport`] combining the balances of the given open channels with those of
+	/// all other [`ChannelMonitor`]s.
+	///
+	/// `channels` should be the result of a single call to [`ChannelManager::list_channels`],
+	/// such that each channel is counted exactly once even if it is closed concurrently.
+	///
+	/// [`ChannelManager::list_channels`]: crate::ln::channelmanager::ChannelManager::list_channels
+	pub fn get_balance_report(&self, channels: &[ChannelDetails]) -> BalanceReport {
+		let ignored_channels: Vec<&ChannelDetails> = channels.iter().collect();
+		BalanceReport::new(channels, self.get_claimable_balances(&ignored_channels))
+	}
+
+	/// Gets a [`BalanceMaturitySchedule`] for each of the contained [`ChannelMonitor`]s which has
+	/// any balances left, sorted by funding outpoint.
+	///
+	/// As with [`Self::get_claimable_balances`], channels in `ignored_channels` are skipped,
+	/// allowing you to skip channels which are still open.
+	pub fn get_balance_maturity_schedules(&self, ignored_channels: &[&ChannelDetails]) -> Vec<BalanceMaturitySchedule> {
+		let mut ret = Vec::new();
+		let monitor_states = self.monitors.read().unwrap();
+		for (funding_outpoint, monitor_state) in monitor_states.iter() {
+			if ignored_channels.iter().any(|chan| chan.funding_txo.as_ref() == Some(funding_outpoint)) {
+				continue;
+			}
+			let balances = monitor_state.monitor.get_claimable_balances();
+			if balances.is_empty() {
+				continue;
+			}
+			let BalanceReport {
+				claimable_satoshis, awaiting_confirmations, maybe_timeout_htlcs,
+				maybe_preimage_htlc_satoshis, ..
+			} = BalanceReport::new(&[], balances);
+			ret.push(BalanceMaturitySchedule {
+				funding_txo: *funding_outpoint,
+				counterparty_node_id: monitor_state.monitor.get_counterparty_node_id(),
+				current_height: monitor_state.monitor.current_best_block().height(),
+				claimable_satoshis, awaiting_confirmations, maybe_timeout_htlcs,
+				maybe_preimage_htlc_satoshis,
+			});
+		}
+		ret.sort_unstable_by_key(|schedule| schedule.funding_txo);
+		ret
+	}
+
 	/// Gets the [`LockedChannelMonitor`] for a given funding outpoint, returning an `Err` if no
 	/// such [`ChannelMonitor`] is currently being monitored for.
 	///
@@ -411,7 +924,7 @@ where C::Target: chain::Filter,
 	/// Lists the pending updates for each [`ChannelMonitor`] (by `OutPoint` being monitored).
 	pub fn list_pending_monitor_updates(&self) -> HashMap<OutPoint, Vec<MonitorUpdateId>> {
 		self.monitors.read().unwrap().iter().map(|(outpoint, holder)| {
-			(*outpoint, holder.pending_monitor_updates.lock().unwrap().clone())
+			(*outpoint, holder.pending_update_ids())
 		}).collect()
 	}
 
@@ -419,7 +932,44 @@ where C::Target: chain::Filter,
 	/// Lists the pending updates for each [`ChannelMonitor`] (by `OutPoint` being monitored).
 	pub fn list_pending_monitor_updates(&self) -> Vec<(OutPoint, Vec<MonitorUpdateId>)> {
 		self.monitors.read().unwrap().iter().map(|(outpoint, holder)| {
-			(*outpoint, holder.pending_monitor_updates.lock().unwrap().clone())
+			(*outpoint, holder.pending_update_ids())
+		}).collect()
+	}
+
+	/// Gets the persistence status of each [`ChannelMonitor`], in random order.
+	///
+	/// This allows monitoring for persistence which is stuck or failing before it results in
+	/// channels being unusable or force-closed. See [`MonitorStatus`] for more details.
+	pub fn monitor_status(&self) -> Vec<MonitorStatus> {
+		self.monitors.read().unwrap().iter().map(|(funding_txo, holder)| {
+			let pending_monitor_updates = holder.pending_monitor_updates.lock().unwrap();
+			let latest_update_id = holder.monitor.get_latest_update_id();
+			let latest_persisted_update_id = match pending_monitor_updates.iter()
+				.filter_map(|(update_id, _)| match update_id.contents {
+					UpdateOrigin::OffChain(id) => Some(id),
+					UpdateOrigin::ChainSync(_) => None,
+				}).min()
+			{
+				Some(lowest_pending_id) => lowest_pending_id.checked_sub(1),
+				None => Some(latest_update_id),
+			};
+			let pending_updates = pending_monitor_updates.iter().map(|(update_id, start)| {
+				PendingMonitorUpdate {
+					update_id: *update_id,
+					pending_since_height: start.height,
+					#[cfg(not(feature = "no-std"))]
+					pending_duration: start.instant.elapsed(),
+				}
+			}).collect();
+			MonitorStatus {
+				funding_txo: *funding_txo,
+				counterparty_node_id: holder.monitor.get_counterparty_node_id(),
+				latest_update_id,
+				latest_persisted_update_id,
+				pending_updates,
+				best_block: holder.monitor.current_best_block(),
+				persist_failed: holder.channel_perm_failed.load(Ordering::Acquire),
+			}
 		}).collect()
 	}
 
@@ -451,7 +1001,7 @@ where C::Target: chain::Filter,
 			return Err(APIError::APIMisuseError { err: format!("No ChannelMonitor matching funding outpoint {:?} found", funding_txo) });
 		};
 		let mut pending_monitor_updates = monitor_data.pending_monitor_updates.lock().unwrap();
-		pending_monitor_updates.retain(|update_id| *update_id != completed_update_id);
+		pending_monitor_updates.retain(|(update_id, _)| *update_id != completed_update_id);
 
 		match completed_update_id {
 			MonitorUpdateId { contents: UpdateOrigin::OffChain(_) } => {
@@ -554,6 +1104,80 @@ where C::Target: chain::Filter,
 			)
 		}
 	}
+
+	/// Triggers rebroadcasts of pending claims as with [`Self::rebroadcast_pending_claims`], but
+	/// additionally bumps their feerate once per block within the limits of the given `config`, and
+	/// generates an [`Event::ClaimAtRisk`] for any claim which our counterparty will soon be able
+	/// to contest.
+	///
+	/// May be called in place of [`Self::rebroadcast_pending_claims`] on the same timer, as
+	/// feerates are bumped at most once per block regardless of how often it is called.
+	///
+	/// [`Event::ClaimAtRisk`]: crate::events::Event::ClaimAtRisk
+	pub fn rebroadcast_pending_claims_with_fee_bumps(&self, config: &ClaimFeeBumpConfig) {
+		let mut generated_events = false;
+		let monitors = self.monitors.read().unwrap();
+		for (_, monitor_holder) in &*monitors {
+			generated_events |= monitor_holder.monitor.bump_pending_claims(
+				&*self.broadcaster, &*self.fee_estimator, &*self.logger,
+				config.max_feerate_sat_per_1000_weight, config.at_risk_threshold_blocks,
+			);
+		}
+		if generated_events {
+			self.event_notifier.notify();
+		}
+	}
+
+	/// Connects a block as with [`chain::Listen::block_connected`], but processes each
+	/// [`ChannelMonitor`] as an independent job on the given [`ChainSyncExecutor`].
+	///
+	/// This is useful to speed up replaying many blocks on startup for nodes with a large number
+	/// of channels. Any resulting [`MonitorEvent`]s are queued exactly as with the sequential
+	/// [`chain::Listen`] implementation.
+	pub fn block_connected_parallel<E: Deref>(&self, block: &Block, height: u32, executor: E)
+	where Self: Sync, E::Target: ChainSyncExecutor, ChannelMonitor<ChannelSigner>: Sync
+	{
+		let txdata: Vec<_> = block.txdata.iter().enumerate().collect();
+		self.filtered_block_connected_parallel(&block.header, &txdata, height, executor);
+	}
+
+	/// Connects a block with possibly-filtered transaction data as with
+	/// [`chain::Listen::filtered_block_connected`], but processes each [`ChannelMonitor`] as an
+	/// independent job on the given [`ChainSyncExecutor`].
+	pub fn filtered_block_connected_parallel<E: Deref>(&self, header: &BlockHeader, txdata: &TransactionData, height: u32, executor: E)
+	where Self: Sync, E::Target: ChainSyncExecutor, ChannelMonitor<ChannelSigner>: Sync
+	{
+		log_debug!(self.logger, "New best block {} at height {} provided via filtered_block_connected_parallel", header.block_hash(), height);
+		self.process_chain_data_parallel(header, Some(height), txdata, |monitor, txdata| {
+			monitor.block_connected(
+				header, txdata, height, &*self.broadcaster, &*self.fee_estimator, &*self.logger)
+		}, executor);
+	}
+
+	/// Processes confirmed transactions as with [`chain::Confirm::transactions_confirmed`], but
+	/// processes each [`ChannelMonitor`] as an independent job on the given [`ChainSyncExecutor`].
+	pub fn transactions_confirmed_parallel<E: Deref>(&self, header: &BlockHeader, txdata: &TransactionData, height: u32, executor: E)
+	where Self: Sync, E::Target: ChainSyncExecutor, ChannelMonitor<ChannelSigner>: Sync
+	{
+		log_debug!(self.logger, "{} provided transactions confirmed at height {} in block {}", txdata.len(), height, header.block_hash());
+		self.process_chain_data_parallel(header, None, txdata, |monitor, txdata| {
+			monitor.transactions_confirmed(
+				header, txdata, height, &*self.broadcaster, &*self.fee_estimator, &*self.logger)
+		}, executor);
+	}
+
+	/// Updates the best block as with [`chain::Confirm::best_block_updated`], but processes each
+	/// [`ChannelMonitor`] as an independent job on the given [`ChainSyncExecutor`].
+	pub fn best_block_updated_parallel<E: Deref>(&self, header: &BlockHeader, height: u32, executor: E)
+	where Self: Sync, E::Target: ChainSyncExecutor, ChannelMonitor<ChannelSigner>: Sync
+	{
+		log_debug!(self.logger, "New best block {} at height {} provided via best_block_updated_parallel", header.block_hash(), height);
+		self.process_chain_data_parallel(header, Some(height), &[], |monitor, txdata| {
+			debug_assert!(txdata.is_empty());
+			monitor.best_block_updated(
+				header, height, &*self.broadcaster, &*self.fee_estimator, &*self.logger)
+		}, executor);
+	}
 }
 
 impl<ChannelSigner: WriteableEcdsaChannelSigner, C: Deref, T: Deref, F: Deref, L: Deref, P: Deref>
@@ -632,6 +1256,27 @@ where
 	}
 }
 
+impl<ChannelSigner: WriteableEcdsaChannelSigner, C: Deref, T: Deref, F: Deref, L: Deref, P: Deref>
+chain::MempoolListener for ChainMonitor<ChannelSigner, C, T, F, L, P>
+where
+	C::Target: chain::Filter,
+	T::Target: BroadcasterInterface,
+	F::Target: FeeEstimator,
+	L::Target: Logger,
+	P::Target: Persist<ChannelSigner>,
+{
+	fn transaction_seen_in_mempool(&self, tx: &Transaction) {
+		let mut generated_events = false;
+		let monitor_states = self.monitors.read().unwrap();
+		for monitor_state in monitor_states.values() {
+			generated_events |= monitor_state.monitor.transaction_seen_in_mempool(tx, &*self.logger);
+		}
+		if generated_events {
+			self.event_notifier.notify();
+		}
+	}
+}
+
 impl<ChannelSigner: WriteableEcdsaChannelSigner, C: Deref , T: Deref , F: Deref , L: Deref , P: Deref >
 chain::Watch<ChannelSigner> for ChainMonitor<ChannelSigner, C, T, F, L, P>
 where C::Target: chain::Filter,
@@ -662,7 +1307,7 @@ where C::Target: chain::Filter,
 		match persist_res {
 			ChannelMonitorUpdateStatus::InProgress => {
 				log_info!(self.logger, "Persistence of new ChannelMonitor for channel {} in progress", log_funding_info!(monitor));
-				pending_monitor_updates.push(update_id);
+				pending_monitor_updates.push((update_id, PendingUpdateStart::now(self.highest_chain_height.load(Ordering::Acquire))));
 			},
 			ChannelMonitorUpdateStatus::PermanentFailure => {
 				log_error!(self.logger, "Persistence of new ChannelMonitor for channel {} failed", log_funding_info!(monitor));
@@ -715,7 +1360,7 @@ where C::Target: chain::Filter,
 				let persist_res = self.persister.update_persisted_channel(funding_txo, Some(update), monitor, update_id);
 				match persist_res {
 					ChannelMonitorUpdateStatus::InProgress => {
-						pending_monitor_updates.push(update_id);
+						pending_monitor_updates.push((update_id, PendingUpdateStart::now(self.highest_chain_height.load(Ordering::Acquire))));
 						log_debug!(self.logger, "Persistence of ChannelMonitorUpdate for channel {} in progress", log_funding_info!(monitor));
 					},
 					ChannelMonitorUpdateStatus::PermanentFailure => {
@@ -738,38 +1383,10 @@ where C::Target: chain::Filter,
 	}
 
 	fn release_pending_monitor_events(&self) -> Vec<(OutPoint, Vec<MonitorEvent>, Option<PublicKey>)> {
-		let mut pending_monitor_events = self.pending_monitor_events.lock().unwrap().split_off(0);
-		for monitor_state in self.monitors.read().unwrap().values() {
-			let is_pending_monitor_update = monitor_state.has_pending_chainsync_updates(&monitor_state.pending_monitor_updates.lock().unwrap());
-			if is_pending_monitor_update &&
-					monitor_state.last_chain_persist_height.load(Ordering::Acquire) + LATENCY_GRACE_PERIOD_BLOCKS as usize
-						> self.highest_chain_height.load(Ordering::Acquire)
-			{
-				log_info!(self.logger, "A Channel Monitor sync is still in progress, refusing to provide monitor events!");
-			} else {
-				if monitor_state.channel_perm_failed.load(Ordering::Acquire) {
-					// If a `UpdateOrigin::ChainSync` persistence failed with `PermanantFailure`,
-					// we don't really know if the latest `ChannelMonitor` state is on disk or not.
-					// We're supposed to hold monitor updates until the latest state is on disk to
-					// avoid duplicate events, but the user told us persistence is screw-y and may
-					// not complete. We can't hold events forever because we may learn some payment
-					// preimage, so instead we just log and hope the user complied with the
-					// `PermanentFailure` requirements of having at least the local-disk copy
-					// updated.
-					log_info!(self.logger, "A Channel Monitor sync returned PermanentFailure. Returning monitor events but duplicate events may appear after reload!");
-				}
-				if is_pending_monitor_update {
-					log_error!(self.logger, "A ChannelMonitor sync took longer than {} blocks to complete.", LATENCY_GRACE_PERIOD_BLOCKS);
-					log_error!(self.logger, "   To avoid funds-loss, we are allowing monitor updates to be released.");
-					log_error!(self.logger, "   This may cause duplicate payment events to be generated.");
-				}
-				let monitor_events = monitor_state.monitor.get_and_clear_pending_monitor_events();
-				if monitor_events.len() > 0 {
-					let monitor_outpoint = monitor_state.monitor.get_funding_txo().0;
-					let counterparty_node_id = monitor_state.monitor.get_counterparty_node_id();
-					pending_monitor_events.push((monitor_outpoint, monitor_events, counterparty_node_id));
-				}
-			}
+		let (pending_monitor_events, events_remain) = self.release_monitor_events(self.monitor_event_batch_size);
+		if events_remain {
+			// Wake the consumer to come back for the rest of the backlog.
+			self.event_notifier.notify();
 		}
 		pending_monitor_events
 	}
@@ -825,16 +1442,158 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner, C: Deref, T: Deref, F: Deref, L
 
 #[cfg(test)]
 mod tests {
-	use crate::{check_added_monitors, check_closed_broadcast, check_closed_event};
+	use crate::{check_added_monitors, check_closed_broadcast, check_closed_event, commitment_signed_dance};
 	use crate::{expect_payment_sent, expect_payment_claimed, expect_payment_sent_without_paths, expect_payment_path_successful, get_event_msg};
-	use crate::{get_htlc_update_msgs, get_local_commitment_txn, get_revoke_commit_msgs, get_route_and_payment_hash, unwrap_send_err};
-	use crate::chain::{ChannelMonitorUpdateStatus, Confirm, Watch};
-	use crate::chain::channelmonitor::LATENCY_GRACE_PERIOD_BLOCKS;
+	use crate::{get_htlc_update_msgs, get_local_commitment_txn, get_monitor, get_revoke_commit_msgs, get_route_and_payment_hash, unwrap_send_err};
+	use crate::chain::{ChannelMonitorUpdateStatus, Confirm, Listen, Watch};
+	use crate::chain::chainmonitor::{ChainMonitor, ChainSyncExecutor};
+	use crate::chain::channelmonitor::{ChannelMonitor, LATENCY_GRACE_PERIOD_BLOCKS, MonitorEvent};
 	use crate::events::{Event, ClosureReason, MessageSendEvent, MessageSendEventsProvider};
 	use crate::ln::channelmanager::{PaymentSendFailure, PaymentId, RecipientOnionFields};
 	use crate::ln::functional_test_utils::*;
 	use crate::ln::msgs::ChannelMessageHandler;
 	use crate::util::errors::APIError;
+	use crate::util::enforcing_trait_impls::EnforcingSigner;
+	use crate::util::ser::{ReadableArgs, Writeable};
+	use crate::util::test_utils;
+
+	use bitcoin::hash_types::BlockHash;
+	use bitcoin::network::constants::Network;
+
+	use core::sync::atomic::{AtomicUsize, Ordering};
+
+	/// A [`ChainSyncExecutor`] which simply runs each job in turn on the calling thread.
+	struct SequentialExecutor {
+		jobs_run: AtomicUsize,
+	}
+
+	impl ChainSyncExecutor for SequentialExecutor {
+		fn execute<'a>(&self, jobs: Vec<Box<dyn FnOnce() + Send + 'a>>) {
+			for job in jobs {
+				self.jobs_run.fetch_add(1, Ordering::AcqRel);
+				job();
+			}
+		}
+	}
+
+	#[test]
+	fn parallel_block_connection() {
+		// Test that connecting blocks via the parallel entry points hands each ChannelMonitor to
+		// the executor and generates the same monitor events as the sequential path.
+		let chanmon_cfgs = create_chanmon_cfgs(3);
+		let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+		let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+		let chan_1 = create_announced_chan_between_nodes(&nodes, 0, 1);
+		let chan_2 = create_announced_chan_between_nodes(&nodes, 0, 2);
+
+		// Load copies of node A's monitors into a standalone ChainMonitor with thread-safe
+		// parameters.
+		let broadcaster = test_utils::TestBroadcaster::new(Network::Testnet);
+		let logger = test_utils::TestLogger::new();
+		let fee_estimator = test_utils::TestFeeEstimator { sat_per_kw: crate::sync::Mutex::new(253) };
+		let persister = test_utils::TestPersister::new();
+		let chain_monitor: ChainMonitor<EnforcingSigner, &test_utils::TestChainSource, _, _, _, _> =
+			ChainMonitor::new(None, &broadcaster, &logger, &fee_estimator, &persister);
+		for chan_id in [chan_1.2, chan_2.2].iter() {
+			let serialized = get_monitor!(nodes[0], *chan_id).encode();
+			let keys_manager = &chanmon_cfgs[0].keys_manager;
+			let (_, monitor) = <(BlockHash, ChannelMonitor<EnforcingSigner>)>::read(
+				&mut &serialized[..], (keys_manager, keys_manager)).unwrap();
+			let funding_txo = monitor.get_funding_txo().0;
+			assert_eq!(chain_monitor.watch_channel(funding_txo, monitor), ChannelMonitorUpdateStatus::Completed);
+		}
+
+		// Confirm B's commitment transaction, which only the monitor for chan_1 cares about.
+		let remote_txn = get_local_commitment_txn!(nodes[1], chan_1.2);
+		let executor = SequentialExecutor { jobs_run: AtomicUsize::new(0) };
+		let block = create_dummy_block(nodes[0].best_block_hash(), 42, vec![remote_txn[0].clone()]);
+		chain_monitor.block_connected_parallel(&block, nodes[0].best_block_info().1 + 1, &executor);
+		assert_eq!(executor.jobs_run.load(Ordering::Acquire), 2);
+
+		let monitor_events = chain_monitor.release_pending_monitor_events();
+		assert_eq!(monitor_events.len(), 1);
+		assert_eq!(monitor_events[0].0.to_channel_id(), chan_1.2);
+		match monitor_events[0].1[..] {
+			[MonitorEvent::CommitmentTxConfirmed(_)] => {},
+			_ => panic!("Unexpected monitor events"),
+		}
+
+		let header = create_dummy_header(block.block_hash(), 42);
+		chain_monitor.best_block_updated_parallel(&header, nodes[0].best_block_info().1 + 2, &executor);
+		assert_eq!(executor.jobs_run.load(Ordering::Acquire), 4);
+	}
+
+	#[test]
+	fn monitor_events_released_in_batches() {
+		// Test that a ChainMonitor with a limited batch size releases monitor events a batch at a
+		// time, persistence completions first, and wakes its consumer while a backlog remains.
+		let chanmon_cfgs = create_chanmon_cfgs(3);
+		let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+		let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+		let chan_1 = create_announced_chan_between_nodes(&nodes, 0, 1);
+		let chan_2 = create_announced_chan_between_nodes(&nodes, 0, 2);
+
+		let broadcaster = test_utils::TestBroadcaster::new(Network::Testnet);
+		let logger = test_utils::TestLogger::new();
+		let fee_estimator = test_utils::TestFeeEstimator { sat_per_kw: crate::sync::Mutex::new(253) };
+		let persister = test_utils::TestPersister::new();
+		let chain_monitor: ChainMonitor<EnforcingSigner, &test_utils::TestChainSource, _, _, _, _> =
+			ChainMonitor::new(None, &broadcaster, &logger, &fee_estimator, &persister)
+				.with_monitor_event_batch_size(1);
+		let mut funding_txos = Vec::new();
+		for chan_id in [chan_1.2, chan_2.2].iter() {
+			let serialized = get_monitor!(nodes[0], *chan_id).encode();
+			let keys_manager = &chanmon_cfgs[0].keys_manager;
+			let (_, monitor) = <(BlockHash, ChannelMonitor<EnforcingSigner>)>::read(
+				&mut &serialized[..], (keys_manager, keys_manager)).unwrap();
+			let funding_txo = monitor.get_funding_txo().0;
+			assert_eq!(chain_monitor.watch_channel(funding_txo, monitor), ChannelMonitorUpdateStatus::Completed);
+			funding_txos.push(funding_txo);
+		}
+
+		// Confirm both counterparties' commitment transactions at once, giving each monitor a
+		// CommitmentTxConfirmed event, and complete a persistence for the second channel.
+		let remote_txn_1 = get_local_commitment_txn!(nodes[1], chan_1.2);
+		let remote_txn_2 = get_local_commitment_txn!(nodes[2], chan_2.2);
+		let block = create_dummy_block(nodes[0].best_block_hash(), 42,
+			vec![remote_txn_1[0].clone(), remote_txn_2[0].clone()]);
+		chain_monitor.block_connected(&block, nodes[0].best_block_info().1 + 1);
+		chain_monitor.force_channel_monitor_updated(funding_txos[1], 42);
+		assert_eq!(chain_monitor.pending_monitor_event_count(), 3);
+		// Clear any notification from the setup above.
+		chain_monitor.get_update_future();
+
+		let monitor_events = chain_monitor.release_pending_monitor_events();
+		assert_eq!(monitor_events.len(), 1);
+		assert_eq!(monitor_events[0].0, funding_txos[1]);
+		match monitor_events[0].1[..] {
+			[MonitorEvent::Completed { monitor_update_id: 42, .. }] => {},
+			_ => panic!("Unexpected monitor events"),
+		}
+		assert!(chain_monitor.get_update_future().poll_is_complete());
+		assert_eq!(chain_monitor.pending_monitor_event_count(), 2);
+
+		let mut closed_funding_txos = Vec::new();
+		for _ in 0..2 {
+			let monitor_events = chain_monitor.release_pending_monitor_events();
+			assert_eq!(monitor_events.len(), 1);
+			match monitor_events[0].1[..] {
+				[MonitorEvent::CommitmentTxConfirmed(_)] => {},
+				_ => panic!("Unexpected monitor events"),
+			}
+			closed_funding_txos.push(monitor_events[0].0);
+		}
+		closed_funding_txos.sort_unstable();
+		funding_txos.sort_unstable();
+		assert_eq!(closed_funding_txos, funding_txos);
+
+		// Once the backlog is drained, the consumer is no longer woken.
+		assert_eq!(chain_monitor.pending_monitor_event_count(), 0);
+		assert!(!chain_monitor.get_update_future().poll_is_complete());
+		assert!(chain_monitor.release_monitor_events(10).0.is_empty());
+	}
 
 	#[test]
 	fn test_async_ooo_offchain_updates() {
@@ -941,6 +1700,52 @@ mod tests {
 		check_added_monitors!(nodes[0], 1);
 	}
 
+	#[test]
+	fn test_monitor_status() {
+		// Test that the persistence status of a ChannelMonitor reflects an in-progress update until
+		// it is marked complete.
+		let chanmon_cfgs = create_chanmon_cfgs(2);
+		let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+		let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+		create_announced_chan_between_nodes(&nodes, 0, 1);
+
+		let (payment_preimage, payment_hash, _) = route_payment(&nodes[0], &[&nodes[1]], 1_000_000);
+
+		let statuses = nodes[1].chain_monitor.chain_monitor.monitor_status();
+		assert_eq!(statuses.len(), 1);
+		let status = &statuses[0];
+		assert_eq!(status.counterparty_node_id, Some(nodes[0].node.get_our_node_id()));
+		assert_eq!(status.latest_persisted_update_id, Some(status.latest_update_id));
+		assert!(status.pending_updates.is_empty());
+		assert_eq!(status.best_block.height(), nodes[1].best_block_info().1);
+		assert!(!status.persist_failed);
+		let funding_txo = status.funding_txo;
+		let persisted_update_id = status.latest_update_id;
+
+		chanmon_cfgs[1].persister.set_update_ret(ChannelMonitorUpdateStatus::InProgress);
+		nodes[1].node.claim_funds(payment_preimage);
+		check_added_monitors!(nodes[1], 1);
+
+		let status = nodes[1].chain_monitor.chain_monitor.monitor_status().pop().unwrap();
+		assert_eq!(status.latest_update_id, persisted_update_id + 1);
+		assert_eq!(status.latest_persisted_update_id, Some(persisted_update_id));
+		assert_eq!(status.pending_updates.len(), 1);
+		assert_eq!(status.pending_updates[0].pending_since_height, nodes[1].best_block_info().1);
+		assert!(!status.persist_failed);
+
+		nodes[1].chain_monitor.chain_monitor.channel_monitor_updated(funding_txo, status.pending_updates[0].update_id).unwrap();
+		let status = nodes[1].chain_monitor.chain_monitor.monitor_status().pop().unwrap();
+		assert_eq!(status.latest_persisted_update_id, Some(persisted_update_id + 1));
+		assert!(status.pending_updates.is_empty());
+
+		expect_payment_claimed!(nodes[1], payment_hash, 1_000_000);
+		let updates = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+		nodes[0].node.handle_update_fulfill_htlc(&nodes[1].node.get_our_node_id(), &updates.update_fulfill_htlcs[0]);
+		commitment_signed_dance!(nodes[0], nodes[1], updates.commitment_signed, false);
+		expect_payment_sent!(nodes[0], payment_preimage);
+	}
+
 	fn do_chainsync_pauses_events(block_timeout: bool) {
 		// When a chainsync monitor update occurs, any MonitorUpdates should be held before being
 		// passed upstream to a `ChannelManager` via `Watch::release_pending_monitor_events`. This
@@ -49,7 +49,8 @@ use crate::chain::onchaintx::OnchainTxHandler;
 use crate::chain::package::{CounterpartyOfferedHTLCOutput, CounterpartyReceivedHTLCOutput, HolderFundingOutput, HolderHTLCOutput, PackageSolvingData, PackageTemplate, RevokedOutput, RevokedHTLCOutput};
 use crate::chain::Filter;
 use crate::util::logger::Logger;
-use crate::util::ser::{Readable, ReadableArgs, RequiredWrapper, MaybeReadable, UpgradableRequired, Writer, Writeable, U48};
+use crate::util::ser::{Readable, ReadableArgs, RequiredWrapper, MaybeReadable, UnknownTlvRecords, UpgradableRequired, VecWriter, Writer, Writeable, U48};
+use crate::util::config::{ForceCloseFeePolicy, UserConfig};
 use crate::util::byte_utils;
 use crate::events::Event;
 #[cfg(anchors)]
@@ -520,6 +521,11 @@ pub(crate) enum ChannelMonitorUpdateStep {
 		/// If set to false, we shouldn't broadcast the latest holder commitment transaction as we
 		/// think we've fallen behind!
 		should_broadcast: bool,
+		/// Set if our counterparty proved that we've fallen behind, in which case we must never
+		/// broadcast our latest holder commitment transaction.
+		their_current_per_commitment_point: Option<PublicKey>,
+		/// The policy selecting how urgently our commitment transaction should confirm, if any.
+		fee_policy: Option<ForceCloseFeePolicy>,
 	},
 	ShutdownScript {
 		scriptpubkey: Script,
@@ -561,12 +567,57 @@ impl_writeable_tlv_based_enum_upgradable!(ChannelMonitorUpdateStep,
 	},
 	(4, ChannelForceClosed) => {
 		(0, should_broadcast, required),
+		(1, their_current_per_commitment_point, option),
+		(3, fee_policy, option),
 	},
 	(5, ShutdownScript) => {
 		(0, scriptpubkey, required),
 	},
 );
 
+/// An HTLC in one of the latest commitment transactions tracked by a [`ChannelMonitor`], as
+/// returned by [`ChannelMonitor::get_monitored_htlcs`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MonitoredHTLC {
+	/// The txid of the commitment transaction the HTLC is in.
+	pub commitment_txid: Txid,
+	/// Whether the commitment transaction is our own, rather than our counterparty's.
+	pub holder_commitment: bool,
+	/// The payment hash of the HTLC.
+	pub payment_hash: PaymentHash,
+	/// The value of the HTLC, in millisatoshis.
+	pub amount_msat: u64,
+	/// The absolute block height at which the HTLC expires.
+	pub cltv_expiry: u32,
+	/// Whether the HTLC was offered by us, rather than by our counterparty.
+	pub outbound: bool,
+	/// The index of the HTLC's output in the commitment transaction, or `None` if the HTLC is
+	/// dust and thus has no output.
+	pub transaction_output_index: Option<u32>,
+	/// The state of the HTLC, as far as this [`ChannelMonitor`] knows.
+	pub state: MonitoredHTLCState,
+}
+
+/// The state of a [`MonitoredHTLC`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MonitoredHTLCState {
+	/// The HTLC is unresolved and we do not know its payment preimage.
+	Pending,
+	/// The HTLC is unresolved but we know its payment preimage, allowing us to claim it on chain.
+	PreimageKnown,
+	/// The commitment transaction the HTLC is in was confirmed and the HTLC has been irrevocably
+	/// claimed or failed on chain.
+	ResolvedOnChain {
+		/// The txid of the transaction which resolved the HTLC, which may be the commitment
+		/// transaction itself if the HTLC is dust.
+		///
+		/// Will be `None` for HTLCs resolved on LDK versions prior to 0.0.110.
+		resolving_txid: Option<Txid>,
+		/// The payment preimage the HTLC was claimed with, if we claimed it.
+		payment_preimage: Option<PaymentPreimage>,
+	},
+}
+
 /// Details about the balance(s) available for spending once the channel appears on chain.
 ///
 /// See [`ChannelMonitor::get_claimable_balances`] for more details on when these will or will not
@@ -896,6 +947,16 @@ pub(crate) struct ChannelMonitorImpl<Signer: WriteableEcdsaChannelSigner> {
 
 	/// The node_id of our counterparty
 	counterparty_node_id: Option<PublicKey>,
+
+	/// The counterparty's current per-commitment point, set once it proved that our latest holder
+	/// commitment transaction has been revoked. Once set we never broadcast our holder commitment
+	/// transaction, relying on the counterparty to broadcast its own.
+	counterparty_stale_state_point: Option<PublicKey>,
+
+	/// TLV records written by a newer version of LDK which we did not understand, retained so
+	/// that they are not lost when we are persisted, see
+	/// [`UserConfig::preserve_unknown_even_tlvs`].
+	unknown_tlv_records: UnknownTlvRecords,
 }
 
 /// Transaction outputs to watch for on-chain spends.
@@ -923,8 +984,9 @@ impl<Signer: WriteableEcdsaChannelSigner> Writeable for ChannelMonitor<Signer> {
 const SERIALIZATION_VERSION: u8 = 1;
 const MIN_SERIALIZATION_VERSION: u8 = 1;
 
-impl<Signer: WriteableEcdsaChannelSigner> Writeable for ChannelMonitorImpl<Signer> {
-	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
+	/// Writes this monitor, with `signer_data` in place of our signer's serialization if set.
+	fn write_with_signer_data<W: Writer>(&self, writer: &mut W, signer_data: Option<&[u8]>) -> Result<(), Error> {
 		write_ver_prefix!(writer, SERIALIZATION_VERSION, MIN_SERIALIZATION_VERSION);
 
 		self.latest_update_id.write(writer)?;
@@ -1072,7 +1134,7 @@ impl<Signer: WriteableEcdsaChannelSigner> Writeable for ChannelMonitorImpl<Signe
 				script.write(writer)?;
 			}
 		}
-		self.onchain_tx_handler.write(writer)?;
+		self.onchain_tx_handler.write_with_signer_data(writer, signer_data)?;
 
 		self.lockdown_from_offchain.write(writer)?;
 		self.holder_tx_signed.write(writer)?;
@@ -1086,12 +1148,19 @@ impl<Signer: WriteableEcdsaChannelSigner> Writeable for ChannelMonitorImpl<Signe
 			(11, self.confirmed_commitment_tx_counterparty_output, option),
 			(13, self.spendable_txids_confirmed, vec_type),
 			(15, self.counterparty_fulfilled_htlcs, required),
-		});
+			(17, self.counterparty_stale_state_point, option),
+		}, self.unknown_tlv_records);
 
 		Ok(())
 	}
 }
 
+impl<Signer: WriteableEcdsaChannelSigner> Writeable for ChannelMonitorImpl<Signer> {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		self.write_with_signer_data(writer, None)
+	}
+}
+
 impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitor<Signer> {
 	/// For lockorder enforcement purposes, we need to have a single site which constructs the
 	/// `inner` mutex, otherwise cases where we lock two monitors at the same time (eg in our
@@ -1199,6 +1268,8 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitor<Signer> {
 
 			best_block,
 			counterparty_node_id: Some(counterparty_node_id),
+			counterparty_stale_state_point: None,
+			unknown_tlv_records: UnknownTlvRecords::new(),
 		})
 	}
 
@@ -1274,6 +1345,29 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitor<Signer> {
 		self.inner.lock().unwrap().get_latest_update_id()
 	}
 
+	/// Gets the [`ChannelSigner::channel_keys_id`] of the signer for this channel.
+	///
+	/// [`ChannelSigner::channel_keys_id`]: crate::sign::ChannelSigner::channel_keys_id
+	pub(crate) fn channel_keys_id(&self) -> [u8; 32] {
+		self.inner.lock().unwrap().channel_keys_id
+	}
+
+	/// Gets the value of the channel this ChannelMonitor is monitoring for.
+	pub(crate) fn channel_value_satoshis(&self) -> u64 {
+		self.inner.lock().unwrap().channel_value_satoshis
+	}
+
+	/// Serializes this ChannelMonitor with the given signer in place of our own, which a reader
+	/// must then provide instead via its [`SignerProvider::read_chan_signer`].
+	pub(crate) fn encode_with_signer<S: Writeable>(&self, signer: &S) -> Vec<u8> {
+		let mut signer_data = VecWriter(Vec::new());
+		signer.write(&mut signer_data).expect("No in-memory data may fail to serialize");
+		let mut res = VecWriter(Vec::new());
+		self.inner.lock().unwrap().write_with_signer_data(&mut res, Some(&signer_data.0))
+			.expect("No in-memory data may fail to serialize");
+		res.0
+	}
+
 	/// Gets the funding transaction outpoint of the channel this ChannelMonitor is monitoring for.
 	pub fn get_funding_txo(&self) -> (OutPoint, Script) {
 		self.inner.lock().unwrap().get_funding_txo().clone()
@@ -1286,6 +1380,28 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitor<Signer> {
 			.iter().map(|(txid, outputs)| (*txid, outputs.clone())).collect()
 	}
 
+	/// Gets the txid of our latest holder commitment transaction, i.e., the one we'd broadcast if
+	/// the channel were force-closed now.
+	///
+	/// Unlike [`Self::get_latest_holder_commitment_txn`], this never signs the transaction and is
+	/// thus safe to call at any time, e.g., when inspecting a persisted monitor.
+	pub fn get_latest_holder_commitment_txid(&self) -> Txid {
+		self.inner.lock().unwrap().current_holder_commitment_tx.txid
+	}
+
+	/// Gets the txid of the latest commitment transaction we signed for our counterparty, if any.
+	pub fn get_latest_counterparty_commitment_txid(&self) -> Option<Txid> {
+		self.inner.lock().unwrap().current_counterparty_commitment_txid
+	}
+
+	/// Gets the HTLCs in our latest holder commitment transaction and in the latest commitment
+	/// transaction of our counterparty, along with their state.
+	///
+	/// An HTLC which is in both commitment transactions is returned once for each.
+	pub fn get_monitored_htlcs(&self) -> Vec<MonitoredHTLC> {
+		self.inner.lock().unwrap().get_monitored_htlcs()
+	}
+
 	/// Loads the funding txo and outputs to watch into the given `chain::Filter` by repeatedly
 	/// calling `chain::Filter::register_output` and `chain::Filter::register_tx` until all outputs
 	/// have been registered.
@@ -1310,6 +1426,31 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitor<Signer> {
 		self.inner.lock().unwrap().get_and_clear_pending_monitor_events()
 	}
 
+	/// Like [`Self::get_and_clear_pending_monitor_events`], but only returns (and clears) up to
+	/// `max_events` of the oldest events, leaving the rest pending.
+	pub(crate) fn get_and_clear_pending_monitor_events_chunk(&self, max_events: usize) -> Vec<MonitorEvent> {
+		let mut inner = self.inner.lock().unwrap();
+		let chunk_len = cmp::min(max_events, inner.pending_monitor_events.len());
+		let remaining_events = inner.pending_monitor_events.split_off(chunk_len);
+		mem::replace(&mut inner.pending_monitor_events, remaining_events)
+	}
+
+	/// The number of [`MonitorEvent`]s which have yet to be handed to the `ChannelManager`.
+	pub(crate) fn pending_monitor_event_count(&self) -> usize {
+		self.inner.lock().unwrap().pending_monitor_events.len()
+	}
+
+	/// Whether any pending [`MonitorEvent`] has funds at stake, i.e. our commitment transaction
+	/// confirmed or an HTLC was resolved on chain, which we have to claim or fail backwards before
+	/// the inbound HTLC expires.
+	pub(crate) fn has_urgent_pending_monitor_events(&self) -> bool {
+		self.inner.lock().unwrap().pending_monitor_events.iter().any(|event| match event {
+			MonitorEvent::HTLCEvent(_) => true,
+			MonitorEvent::CommitmentTxConfirmed(_) => true,
+			MonitorEvent::Completed { .. } | MonitorEvent::UpdateFailed(_) => false,
+		})
+	}
+
 	/// Gets the list of pending events which were generated by previous actions, clearing the list
 	/// in the process.
 	///
@@ -1342,6 +1483,17 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitor<Signer> {
 		self.inner.lock().unwrap().counterparty_node_id
 	}
 
+	/// Gets the counterparty's current per-commitment point if it proved, on reconnection, that our
+	/// latest holder commitment transaction has been revoked, i.e. that this `ChannelMonitor` has
+	/// fallen behind.
+	///
+	/// If set, we never broadcast our holder commitment transaction and instead rely on our
+	/// counterparty broadcasting its latest commitment transaction, sweeping our balance in it once
+	/// it confirms. [`Self::get_latest_holder_commitment_txn`] must never be broadcast.
+	pub fn get_counterparty_stale_state_point(&self) -> Option<PublicKey> {
+		self.inner.lock().unwrap().counterparty_stale_state_point
+	}
+
 	/// Used by ChannelManager deserialization to broadcast the latest holder state if its copy of
 	/// the Channel was out-of-date.
 	///
@@ -1491,6 +1643,19 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitor<Signer> {
 			header, height, broadcaster, &bounded_fee_estimator, logger)
 	}
 
+	/// Processes a transaction seen in the mempool but not yet confirmed, learning any payment
+	/// preimages it reveals for HTLCs we offered. See [`chain::MempoolListener`] for details.
+	///
+	/// Any preimages learned are provided via [`MonitorEvent::HTLCEvent`] such that the
+	/// corresponding inbound HTLCs may be claimed without waiting for confirmation. Returns whether
+	/// any such events were generated.
+	pub fn transaction_seen_in_mempool<L: Deref>(&self, tx: &Transaction, logger: L) -> bool
+	where
+		L::Target: Logger,
+	{
+		self.inner.lock().unwrap().check_mempool_tx_for_preimages(tx, &logger)
+	}
+
 	/// Returns the set of txids that should be monitored for re-organization out of the chain.
 	pub fn get_relevant_txids(&self) -> Vec<(Txid, Option<BlockHash>)> {
 		let inner = self.inner.lock().unwrap();
@@ -1530,6 +1695,44 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitor<Signer> {
 			current_height, &broadcaster, &fee_estimator, &logger,
 		);
 	}
+
+	/// Triggers rebroadcasts of pending claims as with [`Self::rebroadcast_pending_claims`], but
+	/// additionally bumps their feerate once per block, using RBF or, for claims requiring external
+	/// funding, a new `BumpTransaction` event. Forced bumps never exceed
+	/// `max_feerate_sat_per_1000_weight`, though claims will still track higher feerates returned
+	/// by the `fee_estimator`.
+	///
+	/// Any pending claim which our counterparty will be able to contest within
+	/// `at_risk_threshold_blocks` generates a single [`Event::ClaimAtRisk`]. Returns whether any
+	/// such events were generated.
+	pub fn bump_pending_claims<B: Deref, F: Deref, L: Deref>(
+		&self, broadcaster: B, fee_estimator: F, logger: L, max_feerate_sat_per_1000_weight: u32,
+		at_risk_threshold_blocks: u32,
+	) -> bool
+	where
+		B::Target: BroadcasterInterface,
+		F::Target: FeeEstimator,
+		L::Target: Logger,
+	{
+		let fee_estimator = LowerBoundedFeeEstimator::new(fee_estimator);
+		let mut inner = self.inner.lock().unwrap();
+		let current_height = inner.best_block.height;
+		inner.onchain_tx_handler.bump_pending_claims(
+			current_height, max_feerate_sat_per_1000_weight as u64, &broadcaster, &fee_estimator, &logger,
+		);
+
+		let channel_id = inner.funding_info.0.to_channel_id();
+		let claims_at_risk = inner.onchain_tx_handler.get_new_claims_at_risk(current_height, at_risk_threshold_blocks);
+		let generated_events = !claims_at_risk.is_empty();
+		for (outpoints, deadline_height, feerate_sat_per_1000_weight) in claims_at_risk {
+			log_warn!(logger, "Claim for inputs {:?} remains unconfirmed with our counterparty able to contest it at height {}",
+				outpoints, deadline_height);
+			inner.pending_events.push(Event::ClaimAtRisk {
+				channel_id, outpoints, deadline_height, feerate_sat_per_1000_weight,
+			});
+		}
+		generated_events
+	}
 }
 
 impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
@@ -2439,10 +2642,19 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
 						ret = Err(());
 					}
 				},
-				ChannelMonitorUpdateStep::ChannelForceClosed { should_broadcast } => {
+				ChannelMonitorUpdateStep::ChannelForceClosed { should_broadcast, their_current_per_commitment_point, fee_policy } => {
 					log_trace!(logger, "Updating ChannelMonitor: channel force closed, should broadcast: {}", should_broadcast);
 					self.lockdown_from_offchain = true;
-					if *should_broadcast {
+					if fee_policy.is_some() {
+						self.onchain_tx_handler.force_close_fee_policy = *fee_policy;
+					}
+					if let Some(point) = their_current_per_commitment_point {
+						log_error!(logger, "Channel {} has fallen behind its counterparty, we will never broadcast our latest holder commitment transaction", log_bytes!(self.funding_info.0.to_channel_id()));
+						self.counterparty_stale_state_point = Some(*point);
+					}
+					if self.counterparty_stale_state_point.is_some() {
+						log_info!(logger, "Waiting for our counterparty to broadcast its latest commitment transaction");
+					} else if *should_broadcast {
 						// There's no need to broadcast our commitment transaction if we've seen one
 						// confirmed (even with 1 confirmation) as it'll be rejected as
 						// duplicate/conflicting.
@@ -2519,6 +2731,71 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
 		&self.funding_info
 	}
 
+	fn get_monitored_htlcs(&self) -> Vec<MonitoredHTLC> {
+		let mut htlcs = Vec::new();
+		let holder_commitment_txid = self.current_holder_commitment_tx.txid;
+		for (htlc, _, _) in self.current_holder_commitment_tx.htlc_outputs.iter() {
+			htlcs.push(MonitoredHTLC {
+				commitment_txid: holder_commitment_txid,
+				holder_commitment: true,
+				payment_hash: htlc.payment_hash,
+				amount_msat: htlc.amount_msat,
+				cltv_expiry: htlc.cltv_expiry,
+				outbound: htlc.offered,
+				transaction_output_index: htlc.transaction_output_index,
+				state: self.monitored_htlc_state(&holder_commitment_txid, htlc),
+			});
+		}
+		if let Some(counterparty_commitment_txid) = self.current_counterparty_commitment_txid {
+			if let Some(htlc_outputs) = self.counterparty_claimable_outpoints.get(&counterparty_commitment_txid) {
+				for (htlc, _) in htlc_outputs.iter() {
+					htlcs.push(MonitoredHTLC {
+						commitment_txid: counterparty_commitment_txid,
+						holder_commitment: false,
+						payment_hash: htlc.payment_hash,
+						amount_msat: htlc.amount_msat,
+						cltv_expiry: htlc.cltv_expiry,
+						// HTLCs offered in our counterparty's commitment transaction were offered by them.
+						outbound: !htlc.offered,
+						transaction_output_index: htlc.transaction_output_index,
+						state: self.monitored_htlc_state(&counterparty_commitment_txid, htlc),
+					});
+				}
+			}
+		}
+		htlcs
+	}
+
+	fn monitored_htlc_state(&self, commitment_txid: &Txid, htlc: &HTLCOutputInCommitment) -> MonitoredHTLCState {
+		if self.funding_spend_confirmed == Some(*commitment_txid) {
+			let output_idx = match htlc.transaction_output_index {
+				Some(output_idx) => output_idx,
+				None => {
+					// Dust HTLCs have no output and are thus irrevocably resolved along with the
+					// commitment transaction itself. Resolutions without an output index cannot be
+					// told apart, so we must not match them against any particular dust HTLC.
+					return MonitoredHTLCState::ResolvedOnChain {
+						resolving_txid: Some(*commitment_txid),
+						payment_preimage: None,
+					};
+				},
+			};
+			if let Some(resolved) = self.htlcs_resolved_on_chain.iter()
+				.find(|resolved| resolved.commitment_tx_output_idx == Some(output_idx))
+			{
+				return MonitoredHTLCState::ResolvedOnChain {
+					resolving_txid: resolved.resolving_txid,
+					payment_preimage: resolved.payment_preimage,
+				};
+			}
+		}
+		if self.payment_preimages.contains_key(&htlc.payment_hash) {
+			MonitoredHTLCState::PreimageKnown
+		} else {
+			MonitoredHTLCState::Pending
+		}
+	}
+
 	pub fn get_outputs_to_watch(&self) -> &HashMap<Txid, Vec<(u32, Script)>> {
 		// If we've detected a counterparty commitment tx on chain, we must include it in the set
 		// of outputs to watch for spends of, otherwise we're likely to lose user funds. Because
@@ -2954,7 +3231,13 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
 	pub fn get_latest_holder_commitment_txn<L: Deref>(&mut self, logger: &L) -> Vec<Transaction> where L::Target: Logger {
 		log_debug!(logger, "Getting signed latest holder commitment transaction!");
 		self.holder_tx_signed = true;
-		let commitment_tx = self.onchain_tx_handler.get_fully_signed_holder_tx(&self.funding_redeemscript);
+		let commitment_tx = match self.onchain_tx_handler.get_fully_signed_holder_tx(&self.funding_redeemscript) {
+			Some(tx) => tx,
+			None => {
+				log_error!(logger, "Our signer refused to sign our latest holder commitment transaction");
+				return Vec::new();
+			},
+		};
 		let txid = commitment_tx.txid();
 		let mut holder_transactions = vec![commitment_tx];
 		// When anchor outputs are present, the HTLC transactions are only valid once the commitment
@@ -3225,7 +3508,6 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
 			let commitment_package = PackageTemplate::build_package(self.funding_info.0.txid.clone(), self.funding_info.0.index as u32, PackageSolvingData::HolderFundingOutput(funding_outp), self.best_block.height(), self.best_block.height());
 			claimable_outpoints.push(commitment_package);
 			self.pending_monitor_events.push(MonitorEvent::CommitmentTxConfirmed(self.funding_info.0));
-			let commitment_tx = self.onchain_tx_handler.get_fully_signed_holder_tx(&self.funding_redeemscript);
 			self.holder_tx_signed = true;
 			// We can't broadcast our HTLC transactions while the commitment transaction is
 			// unconfirmed. We'll delay doing so until we detect the confirmed commitment in
@@ -3235,7 +3517,8 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
 				// assuming it gets confirmed in the next block. Sadly, we have code which considers
 				// "not yet confirmed" things as discardable, so we cannot do that here.
 				let (mut new_outpoints, _) = self.get_broadcasted_holder_claims(&self.current_holder_commitment_tx, self.best_block.height());
-				let new_outputs = self.get_broadcasted_holder_watch_outputs(&self.current_holder_commitment_tx, &commitment_tx);
+				let new_outputs = self.get_broadcasted_holder_watch_outputs(&self.current_holder_commitment_tx,
+					self.onchain_tx_handler.get_unsigned_holder_commitment_tx());
 				if !new_outputs.is_empty() {
 					watch_outputs.push((self.current_holder_commitment_tx.txid.clone(), new_outputs));
 				}
@@ -3453,6 +3736,11 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
 	}
 
 	fn should_broadcast_holder_commitment_txn<L: Deref>(&self, logger: &L) -> bool where L::Target: Logger {
+		// If our counterparty proved we've fallen behind, our holder commitment transaction has
+		// been revoked and broadcasting it would allow our counterparty to claim all our funds.
+		if self.counterparty_stale_state_point.is_some() {
+			return false;
+		}
 		// There's no need to broadcast our commitment transaction if we've seen one confirmed (even
 		// with 1 confirmation) as it'll be rejected as duplicate/conflicting.
 		if self.funding_spend_confirmed.is_some() ||
@@ -3723,6 +4011,80 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
 		}
 	}
 
+	/// Checks whether an unconfirmed transaction claims any HTLCs we offered using their payment
+	/// preimage, in which case an [`MonitorEvent::HTLCEvent`] is generated immediately to relay the
+	/// preimage backwards. Unlike [`Self::is_resolving_htlc_output`], no on-chain state is tracked
+	/// as the transaction may never confirm, though the preimage is remembered such that seeing the
+	/// same claim again is a no-op.
+	fn check_mempool_tx_for_preimages<L: Deref>(&mut self, tx: &Transaction, logger: &L) -> bool where L::Target: Logger {
+		let mut generated_events = false;
+		for input in &tx.input {
+			match HTLCClaim::from_witness(&input.witness) {
+				Some(HTLCClaim::AcceptedPreimage) | Some(HTLCClaim::OfferedPreimage) => {},
+				_ => continue,
+			}
+			let mut payment_preimage = PaymentPreimage([0; 32]);
+			payment_preimage.0.copy_from_slice(input.witness.second_to_last().unwrap());
+			let payment_hash = PaymentHash(Sha256::hash(&payment_preimage.0).into_inner());
+			if self.payment_preimages.contains_key(&payment_hash) {
+				// We already know the preimage, either from a previous mempool transaction or from
+				// our counterparty directly.
+				continue;
+			}
+
+			let spent_txid = input.previous_output.txid;
+			let htlc_output_index = Some(input.previous_output.vout);
+			let holder_htlcs = Some(&self.current_holder_commitment_tx).into_iter()
+				.chain(self.prev_holder_signed_commitment_tx.as_ref())
+				.filter(|commitment_tx| commitment_tx.txid == spent_txid)
+				.flat_map(|commitment_tx| commitment_tx.htlc_outputs.iter()
+					.map(|(htlc, _, source)| (htlc, source.as_ref())));
+			let counterparty_htlcs = self.counterparty_claimable_outpoints.get(&spent_txid).into_iter()
+				.flat_map(|htlcs| htlcs.iter().map(|(htlc, source)| (htlc, source.as_ref().map(|source| &**source))));
+			let (htlc, source) = match holder_htlcs.chain(counterparty_htlcs).find(|(htlc, _)| {
+				htlc.transaction_output_index == htlc_output_index && htlc.payment_hash == payment_hash
+			}) {
+				Some(htlc_and_source) => htlc_and_source,
+				None => continue,
+			};
+			let amount_msat = htlc.amount_msat;
+
+			// HTLC sources are pruned from revoked counterparty commitment transactions, but may
+			// still be found in the latest unrevoked ones.
+			let mut source = source.cloned();
+			if source.is_none() {
+				let counterparty_txids = [self.current_counterparty_commitment_txid, self.prev_counterparty_commitment_txid];
+				source = counterparty_txids.iter()
+					.filter_map(|txid| txid.as_ref().and_then(|txid| self.counterparty_claimable_outpoints.get(txid)))
+					.flat_map(|htlcs| htlcs.iter())
+					.filter(|(htlc, _)| htlc.payment_hash == payment_hash && htlc.amount_msat == amount_msat)
+					.find_map(|(_, source)| source.as_ref().map(|source| (**source).clone()));
+			}
+			let source = match source {
+				Some(source) => source,
+				None => continue,
+			};
+
+			if self.pending_monitor_events.iter().any(|event| match event {
+				MonitorEvent::HTLCEvent(upd) => upd.source == source,
+				_ => false,
+			}) {
+				continue;
+			}
+			log_info!(logger, "Learned preimage for HTLC with payment hash {} from unconfirmed transaction {}",
+				log_bytes!(payment_hash.0), tx.txid());
+			self.payment_preimages.insert(payment_hash, payment_preimage);
+			self.pending_monitor_events.push(MonitorEvent::HTLCEvent(HTLCUpdate {
+				source,
+				payment_preimage: Some(payment_preimage),
+				payment_hash,
+				htlc_value_satoshis: Some(amount_msat / 1000),
+			}));
+			generated_events = true;
+		}
+		generated_events
+	}
+
 	/// Check if any transaction broadcasted is paying fund back to some address we can assume to own
 	fn is_paying_spendable_output<L: Deref>(&mut self, tx: &Transaction, height: u32, block_hash: &BlockHash, logger: &L) where L::Target: Logger {
 		let mut spendable_output = None;
@@ -3837,6 +4199,16 @@ const MAX_ALLOC_SIZE: usize = 64*1024;
 impl<'a, 'b, ES: EntropySource, SP: SignerProvider> ReadableArgs<(&'a ES, &'b SP)>
 		for (BlockHash, ChannelMonitor<SP::Signer>) {
 	fn read<R: io::Read>(reader: &mut R, args: (&'a ES, &'b SP)) -> Result<Self, DecodeError> {
+		let (entropy_source, signer_provider) = args;
+		ReadableArgs::read(reader, (entropy_source, signer_provider, &UserConfig::default()))
+	}
+}
+
+/// Reads a [`ChannelMonitor`], handling any unknown TLV records written by a newer version of LDK
+/// as configured by [`UserConfig::preserve_unknown_even_tlvs`].
+impl<'a, 'b, 'c, ES: EntropySource, SP: SignerProvider> ReadableArgs<(&'a ES, &'b SP, &'c UserConfig)>
+		for (BlockHash, ChannelMonitor<SP::Signer>) {
+	fn read<R: io::Read>(reader: &mut R, args: (&'a ES, &'b SP, &'c UserConfig)) -> Result<Self, DecodeError> {
 		macro_rules! unwrap_obj {
 			($key: expr) => {
 				match $key {
@@ -3846,7 +4218,7 @@ impl<'a, 'b, ES: EntropySource, SP: SignerProvider> ReadableArgs<(&'a ES, &'b SP
 			}
 		}
 
-		let (entropy_source, signer_provider) = args;
+		let (entropy_source, signer_provider, config) = args;
 
 		let _ver = read_ver_prefix!(reader, SERIALIZATION_VERSION);
 
@@ -4052,6 +4424,8 @@ impl<'a, 'b, ES: EntropySource, SP: SignerProvider> ReadableArgs<(&'a ES, &'b SP
 		let mut confirmed_commitment_tx_counterparty_output = None;
 		let mut spendable_txids_confirmed = Some(Vec::new());
 		let mut counterparty_fulfilled_htlcs = Some(HashMap::new());
+		let mut counterparty_stale_state_point = None;
+		let mut unknown_tlv_records = UnknownTlvRecords::new();
 		read_tlv_fields!(reader, {
 			(1, funding_spend_confirmed, option),
 			(3, htlcs_resolved_on_chain, vec_type),
@@ -4061,7 +4435,8 @@ impl<'a, 'b, ES: EntropySource, SP: SignerProvider> ReadableArgs<(&'a ES, &'b SP
 			(11, confirmed_commitment_tx_counterparty_output, option),
 			(13, spendable_txids_confirmed, vec_type),
 			(15, counterparty_fulfilled_htlcs, option),
-		});
+			(17, counterparty_stale_state_point, option),
+		}, unknown_tlv_records, config.preserve_unknown_even_tlvs);
 
 		Ok((best_block.block_hash(), ChannelMonitor::from_impl(ChannelMonitorImpl {
 			latest_update_id,
@@ -4115,6 +4490,8 @@ impl<'a, 'b, ES: EntropySource, SP: SignerProvider> ReadableArgs<(&'a ES, &'b SP
 
 			best_block,
 			counterparty_node_id,
+			counterparty_stale_state_point,
+			unknown_tlv_records,
 		})))
 	}
 }
@@ -12,6 +12,7 @@
 use bitcoin::blockdata::block::{Block, BlockHeader};
 use bitcoin::blockdata::constants::genesis_block;
 use bitcoin::blockdata::script::Script;
+use bitcoin::blockdata::transaction::Transaction;
 use bitcoin::hash_types::{BlockHash, Txid};
 use bitcoin::network::constants::Network;
 use bitcoin::secp256k1::PublicKey;
@@ -25,12 +26,14 @@ use crate::prelude::*;
 pub mod chaininterface;
 pub mod chainmonitor;
 pub mod channelmonitor;
+pub mod reconciler;
+pub mod replica;
 pub mod transaction;
 pub(crate) mod onchaintx;
 pub(crate) mod package;
 
 /// The best known block as identified by its hash and height.
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct BestBlock {
 	block_hash: BlockHash,
 	height: u32,
@@ -58,6 +61,36 @@ impl BestBlock {
 	pub fn height(&self) -> u32 { self.height }
 }
 
+/// The progress of a chain sync client in catching up with the best chain tip of its chain source.
+///
+/// May be passed to [`ChannelManager::set_chain_tip_height`] to defer operations which depend on
+/// an up-to-date view of the chain until syncing is (nearly) complete.
+///
+/// [`ChannelManager::set_chain_tip_height`]: crate::ln::channelmanager::ChannelManager::set_chain_tip_height
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SyncProgress {
+	/// The height of the best block which has been fully synced, or zero if none has been yet.
+	pub synced_height: u32,
+	/// The height of the best chain tip known to the chain source, or zero if not yet known.
+	pub tip_height: u32,
+	/// The number of transactions and outputs registered via [`Filter`] which have yet to be
+	/// scanned for.
+	pub pending_scripts: usize,
+}
+
+impl SyncProgress {
+	/// Returns the number of blocks the synced height is behind the chain tip.
+	pub fn blocks_behind(&self) -> u32 {
+		self.tip_height.saturating_sub(self.synced_height)
+	}
+
+	/// Returns whether syncing is complete, i.e., the chain tip is known and has been synced, and
+	/// no registered transactions or outputs are pending a scan.
+	pub fn is_synced(&self) -> bool {
+		self.tip_height != 0 && self.blocks_behind() == 0 && self.pending_scripts == 0
+	}
+}
+
 
 /// The `Listen` trait is used to notify when blocks have been connected or disconnected from the
 /// chain.
@@ -175,6 +208,25 @@ pub trait Confirm {
 	fn get_relevant_txids(&self) -> Vec<(Txid, Option<BlockHash>)>;
 }
 
+/// The `MempoolListener` trait is used to notify LDK of relevant transactions seen in the mempool
+/// prior to their confirmation.
+///
+/// Notifying LDK of unconfirmed transactions is entirely optional, as [`Listen`] or [`Confirm`]
+/// remain responsible for keeping LDK in sync with the chain. However, a counterparty claiming an
+/// HTLC we offered reveals the payment preimage as soon as its claim is broadcast. Learning of such
+/// a claim before it confirms allows the preimage to be used to claim the corresponding inbound
+/// HTLC earlier, reducing the risk of it timing out.
+pub trait MempoolListener {
+	/// Notifies LDK of a transaction that has been seen in the mempool but is not yet confirmed.
+	///
+	/// Should be called for transactions spending an output registered by
+	/// [`Filter::register_output`], as well as for transactions spending the outputs of such
+	/// transactions, e.g., a counterparty's HTLC claims spending an unconfirmed commitment
+	/// transaction. May be called repeatedly for the same transaction and with unrelated
+	/// transactions, which are ignored.
+	fn transaction_seen_in_mempool(&self, tx: &Transaction);
+}
+
 /// An enum representing the status of a channel monitor update persistence.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ChannelMonitorUpdateStatus {
@@ -352,7 +404,7 @@ pub trait Filter {
 ///
 /// [`ChannelMonitor`]: channelmonitor::ChannelMonitor
 /// [`ChannelMonitor::block_connected`]: channelmonitor::ChannelMonitor::block_connected
-#[derive(Clone, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct WatchedOutput {
 	/// First block where the transaction output may have been spent.
 	pub block_hash: Option<BlockHash>,
@@ -364,6 +416,12 @@ pub struct WatchedOutput {
 	pub script_pubkey: Script,
 }
 
+impl_writeable_tlv_based!(WatchedOutput, {
+	(0, block_hash, option),
+	(2, outpoint, required),
+	(4, script_pubkey, required),
+});
+
 impl<T: Listen> Listen for core::ops::Deref<Target = T> {
 	fn filtered_block_connected(&self, header: &BlockHeader, txdata: &TransactionData, height: u32) {
 		(**self).filtered_block_connected(header, txdata, height);
@@ -33,6 +33,7 @@ use crate::ln::chan_utils::{ChannelTransactionParameters, HolderCommitmentTransa
 use crate::chain::chaininterface::ConfirmationTarget;
 use crate::chain::chaininterface::{FeeEstimator, BroadcasterInterface, LowerBoundedFeeEstimator};
 use crate::chain::channelmonitor::{ANTI_REORG_DELAY, CLTV_SHARED_CLAIM_BUFFER};
+use crate::util::config::ForceCloseFeePolicy;
 use crate::sign::WriteableEcdsaChannelSigner;
 #[cfg(anchors)]
 use crate::chain::package::PackageSolvingData;
@@ -280,12 +281,25 @@ pub struct OnchainTxHandler<ChannelSigner: WriteableEcdsaChannelSigner> {
 
 	onchain_events_awaiting_threshold_conf: Vec<OnchainEventEntry>,
 
+	// The height at which pending claims were last forcibly fee-bumped by `bump_pending_claims`,
+	// such that we bump at most once per block regardless of how often it is called.
+	last_forced_bump_height: u32,
+
+	// Pending claims which have been reported as being at risk of not confirming before their
+	// deadline, such that each claim is only reported once.
+	claims_at_risk: HashSet<PackageID>,
+
+	// The policy selecting how urgently our commitment transaction should confirm when bumping it
+	// via its anchor output, as provided when the channel was force-closed.
+	pub(super) force_close_fee_policy: Option<ForceCloseFeePolicy>,
+
 	pub(super) secp_ctx: Secp256k1<secp256k1::All>,
 }
 
 impl<ChannelSigner: WriteableEcdsaChannelSigner> PartialEq for OnchainTxHandler<ChannelSigner> {
 	fn eq(&self, other: &Self) -> bool {
-		// `signer`, `secp_ctx`, and `pending_claim_events` are excluded on purpose.
+		// `signer`, `secp_ctx`, `pending_claim_events`, `last_forced_bump_height`, and
+		// `claims_at_risk` are excluded on purpose.
 		self.destination_script == other.destination_script &&
 			self.holder_commitment == other.holder_commitment &&
 			self.holder_htlc_sigs == other.holder_htlc_sigs &&
@@ -295,7 +309,8 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> PartialEq for OnchainTxHandler<
 			self.pending_claim_requests == other.pending_claim_requests &&
 			self.claimable_outpoints == other.claimable_outpoints &&
 			self.locktimed_packages == other.locktimed_packages &&
-			self.onchain_events_awaiting_threshold_conf == other.onchain_events_awaiting_threshold_conf
+			self.onchain_events_awaiting_threshold_conf == other.onchain_events_awaiting_threshold_conf &&
+			self.force_close_fee_policy == other.force_close_fee_policy
 	}
 }
 
@@ -304,6 +319,11 @@ const MIN_SERIALIZATION_VERSION: u8 = 1;
 
 impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner> {
 	pub(crate) fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
+		self.write_with_signer_data(writer, None)
+	}
+
+	/// Writes this handler, with `signer_data` in place of our signer's serialization if set.
+	pub(crate) fn write_with_signer_data<W: Writer>(&self, writer: &mut W, signer_data: Option<&[u8]>) -> Result<(), io::Error> {
 		write_ver_prefix!(writer, SERIALIZATION_VERSION, MIN_SERIALIZATION_VERSION);
 
 		self.destination_script.write(writer)?;
@@ -315,7 +335,10 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 		self.channel_transaction_parameters.write(writer)?;
 
 		let mut key_data = VecWriter(Vec::new());
-		self.signer.write(&mut key_data)?;
+		match signer_data {
+			Some(data) => key_data.0.extend_from_slice(data),
+			None => self.signer.write(&mut key_data)?,
+		}
 		assert!(key_data.0.len() < core::usize::MAX);
 		assert!(key_data.0.len() < core::u32::MAX as usize);
 		(key_data.0.len() as u32).write(writer)?;
@@ -348,7 +371,9 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 			entry.write(writer)?;
 		}
 
-		write_tlv_fields!(writer, {});
+		write_tlv_fields!(writer, {
+			(1, self.force_close_fee_policy, option),
+		});
 		Ok(())
 	}
 }
@@ -422,7 +447,10 @@ impl<'a, 'b, ES: EntropySource, SP: SignerProvider> ReadableArgs<(&'a ES, &'b SP
 			}
 		}
 
-		read_tlv_fields!(reader, {});
+		let mut force_close_fee_policy = None;
+		read_tlv_fields!(reader, {
+			(1, force_close_fee_policy, option),
+		});
 
 		let mut secp_ctx = Secp256k1::new();
 		secp_ctx.seeded_randomize(&entropy_source.get_secure_random_bytes());
@@ -441,6 +469,9 @@ impl<'a, 'b, ES: EntropySource, SP: SignerProvider> ReadableArgs<(&'a ES, &'b SP
 			onchain_events_awaiting_threshold_conf,
 			#[cfg(anchors)]
 			pending_claim_events: Vec::new(),
+			last_forced_bump_height: 0,
+			claims_at_risk: HashSet::new(),
+			force_close_fee_policy,
 			secp_ctx,
 		})
 	}
@@ -462,6 +493,9 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 			onchain_events_awaiting_threshold_conf: Vec::new(),
 			#[cfg(anchors)]
 			pending_claim_events: Vec::new(),
+			last_forced_bump_height: 0,
+			claims_at_risk: HashSet::new(),
+			force_close_fee_policy: None,
 			secp_ctx,
 		}
 	}
@@ -490,6 +524,65 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 		&mut self, current_height: u32, broadcaster: &B, fee_estimator: &LowerBoundedFeeEstimator<F>,
 		logger: &L,
 	)
+	where
+		B::Target: BroadcasterInterface,
+		F::Target: FeeEstimator,
+		L::Target: Logger,
+	{
+		self.rebroadcast_claims(current_height, None, broadcaster, fee_estimator, logger);
+	}
+
+	/// Triggers rebroadcasts of pending claims as with [`Self::rebroadcast_pending_claims`], but
+	/// additionally forces a feerate bump once per block, as long as the bumped feerate does not
+	/// exceed `max_feerate_sat_per_1000_weight`.
+	pub(crate) fn bump_pending_claims<B: Deref, F: Deref, L: Deref>(
+		&mut self, current_height: u32, max_feerate_sat_per_1000_weight: u64, broadcaster: &B,
+		fee_estimator: &LowerBoundedFeeEstimator<F>, logger: &L,
+	)
+	where
+		B::Target: BroadcasterInterface,
+		F::Target: FeeEstimator,
+		L::Target: Logger,
+	{
+		let max_forced_feerate = if current_height > self.last_forced_bump_height {
+			self.last_forced_bump_height = current_height;
+			Some(max_feerate_sat_per_1000_weight)
+		} else {
+			None
+		};
+		self.rebroadcast_claims(current_height, max_forced_feerate, broadcaster, fee_estimator, logger);
+	}
+
+	/// Returns the outpoints, deadline, and latest feerate of any pending claims which would allow
+	/// our counterparty to claim the same outputs within `at_risk_threshold_blocks`, excluding any
+	/// claims previously returned.
+	pub(crate) fn get_new_claims_at_risk(
+		&mut self, current_height: u32, at_risk_threshold_blocks: u32,
+	) -> Vec<(Vec<BitcoinOutPoint>, u32, u64)> {
+		let pending_claim_requests = &self.pending_claim_requests;
+		self.claims_at_risk.retain(|package_id| pending_claim_requests.contains_key(package_id));
+
+		let mut claims_at_risk = Vec::new();
+		for (package_id, request) in self.pending_claim_requests.iter() {
+			if !request.is_contentious_at_timelock() ||
+				request.timelock() > current_height.saturating_add(at_risk_threshold_blocks)
+			{
+				continue;
+			}
+			if self.claims_at_risk.insert(*package_id) {
+				let outpoints = request.outpoints().into_iter().cloned().collect();
+				claims_at_risk.push((outpoints, request.timelock(), request.previous_feerate()));
+			}
+		}
+		claims_at_risk
+	}
+
+	/// Rebroadcasts all pending claims. If `max_forced_feerate` is set, each claim's feerate is
+	/// forcibly bumped unless doing so would exceed it.
+	fn rebroadcast_claims<B: Deref, F: Deref, L: Deref>(
+		&mut self, current_height: u32, max_forced_feerate: Option<u64>, broadcaster: &B,
+		fee_estimator: &LowerBoundedFeeEstimator<F>, logger: &L,
+	)
 	where
 		B::Target: BroadcasterInterface,
 		F::Target: FeeEstimator,
@@ -502,7 +595,14 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 			bump_requests.push((*package_id, request.clone()));
 		}
 		for (package_id, request) in bump_requests {
-			self.generate_claim(current_height, &request, false /* force_feerate_bump */, fee_estimator, logger)
+			// A forced bump increases the previous feerate by 25%, see `feerate_bump`.
+			let previous_feerate = request.previous_feerate();
+			let force_feerate_bump = match max_forced_feerate {
+				Some(max_feerate) => previous_feerate != 0 &&
+					previous_feerate + previous_feerate / 4 <= max_feerate,
+				None => false,
+			};
+			self.generate_claim(current_height, &request, force_feerate_bump, fee_estimator, logger)
 				.map(|(_, new_feerate, claim)| {
 					let mut bumped_feerate = false;
 					if let Some(mut_request) = self.pending_claim_requests.get_mut(&package_id) {
@@ -596,7 +696,7 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 			{ // Attributes are not allowed on if expressions on our current MSRV of 1.41.
 				if cached_request.requires_external_funding() {
 					let target_feerate_sat_per_1000_weight = cached_request.compute_package_feerate(
-						fee_estimator, ConfirmationTarget::HighPriority, force_feerate_bump
+						fee_estimator, ConfirmationTarget::OnChainSweep, force_feerate_bump
 					);
 					if let Some(htlcs) = cached_request.construct_malleable_package_with_external_funding(self) {
 						return Some((
@@ -656,9 +756,11 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 					match chan_utils::get_anchor_output(&tx, funding_pubkey) {
 						// An anchor output was found, so we should yield a funding event externally.
 						Some((idx, _)) => {
-							// TODO: Use a lower confirmation target when both our and the
-							// counterparty's latest commitment don't have any HTLCs present.
-							let conf_target = ConfirmationTarget::HighPriority;
+							// Only HTLCs are time-sensitive, so by default we can afford to wait a
+							// while for the commitment to confirm when it doesn't have any.
+							let htlc_expiries = self.holder_commitment.htlcs().iter().map(|htlc| htlc.cltv_expiry);
+							let conf_target = self.force_close_fee_policy.unwrap_or_default()
+								.confirmation_target(htlc_expiries, cur_height);
 							let package_target_feerate_sat_per_1000_weight = cached_request
 								.compute_package_feerate(fee_estimator, conf_target, force_feerate_bump);
 							Some((
@@ -705,11 +807,7 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 		L::Target: Logger,
 	{
 		log_debug!(logger, "Updating claims view at height {} with {} claim requests", cur_height, requests.len());
-		let mut preprocessed_requests = Vec::with_capacity(requests.len());
-		let mut aggregated_request = None;
-
-		// Try to aggregate outputs if their timelock expiration isn't imminent (package timelock
-		// <= CLTV_SHARED_CLAIM_BUFFER) and they don't require an immediate nLockTime (aggregable).
+		let mut claimable_requests = Vec::with_capacity(requests.len());
 		for req in requests {
 			// Don't claim a outpoint twice that would be bad for privacy and may uselessly lock a CPFP input for a while
 			if let Some(_) = self.claimable_outpoints.get(req.outpoints()[0]) {
@@ -732,30 +830,40 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 					self.locktimed_packages.entry(package_locktime).or_insert(Vec::new()).push(req);
 					continue;
 				}
-
-				log_trace!(logger, "Test if outpoint can be aggregated with expiration {} against {}", req.timelock(), cur_height + CLTV_SHARED_CLAIM_BUFFER);
-				if req.timelock() <= cur_height + CLTV_SHARED_CLAIM_BUFFER || !req.aggregable() {
-					// Don't aggregate if outpoint package timelock is soon or marked as non-aggregable
-					preprocessed_requests.push(req);
-				} else if aggregated_request.is_none() {
-					aggregated_request = Some(req);
-				} else {
-					aggregated_request.as_mut().unwrap().merge_package(req);
-				}
+				claimable_requests.push(req);
 			}
 		}
-		if let Some(req) = aggregated_request {
-			preprocessed_requests.push(req);
-		}
 
 		// Claim everything up to and including `cur_height`
 		let remaining_locked_packages = self.locktimed_packages.split_off(&(cur_height + 1));
 		for (pop_height, mut entry) in self.locktimed_packages.iter_mut() {
 			log_trace!(logger, "Restoring delayed claim of package(s) at their timelock at {}.", pop_height);
-			preprocessed_requests.append(&mut entry);
+			claimable_requests.append(&mut entry);
 		}
 		self.locktimed_packages = remaining_locked_packages;
 
+		// Try to aggregate outputs into as few claims as possible, as long as they don't require
+		// differing nLockTimes. Outputs which our counterparty may soon claim as well (package
+		// timelock <= CLTV_SHARED_CLAIM_BUFFER) are still claimed on their own, as any competing
+		// claim would invalidate our aggregated claim and delay it until it is split again.
+		let mut preprocessed_requests = Vec::with_capacity(claimable_requests.len());
+		let mut aggregated_requests: Vec<PackageTemplate> = Vec::new();
+		for req in claimable_requests {
+			log_trace!(logger, "Test if outpoint can be aggregated with expiration {} against {}", req.timelock(), cur_height + CLTV_SHARED_CLAIM_BUFFER);
+			if !req.aggregable() ||
+				(req.is_contentious_at_timelock() && req.timelock() <= cur_height + CLTV_SHARED_CLAIM_BUFFER)
+			{
+				preprocessed_requests.push(req);
+			} else if let Some(aggregated_request) = aggregated_requests.iter_mut()
+				.find(|aggregated_request| aggregated_request.can_merge_with(&req, cur_height))
+			{
+				aggregated_request.merge_package(req);
+			} else {
+				aggregated_requests.push(req);
+			}
+		}
+		preprocessed_requests.append(&mut aggregated_requests);
+
 		// Generate claim transactions and track them to bump if necessary at
 		// height timer expiration (i.e in how many blocks we're going to take action).
 		for mut req in preprocessed_requests {
@@ -1100,10 +1208,13 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 	// Normally holder HTLCs are signed at the same time as the holder commitment tx.  However,
 	// in some configurations, the holder commitment tx has been signed and broadcast by a
 	// ChannelMonitor replica, so we handle that case here.
+	// Signing may fail if our signer can't sign for our holder transactions, as for the
+	// `WatchOnlySigner` of a `MonitorReplica`, in which case we simply don't claim.
 	fn sign_latest_holder_htlcs(&mut self) {
 		if self.holder_htlc_sigs.is_none() {
-			let (_sig, sigs) = self.signer.sign_holder_commitment_and_htlcs(&self.holder_commitment, &self.secp_ctx).expect("sign holder commitment");
-			self.holder_htlc_sigs = Some(Self::extract_holder_sigs(&self.holder_commitment, sigs));
+			if let Ok((_sig, sigs)) = self.signer.sign_holder_commitment_and_htlcs(&self.holder_commitment, &self.secp_ctx) {
+				self.holder_htlc_sigs = Some(Self::extract_holder_sigs(&self.holder_commitment, sigs));
+			}
 		}
 	}
 
@@ -1113,8 +1224,9 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 	fn sign_prev_holder_htlcs(&mut self) {
 		if self.prev_holder_htlc_sigs.is_none() {
 			if let Some(ref holder_commitment) = self.prev_holder_commitment {
-				let (_sig, sigs) = self.signer.sign_holder_commitment_and_htlcs(holder_commitment, &self.secp_ctx).expect("sign previous holder commitment");
-				self.prev_holder_htlc_sigs = Some(Self::extract_holder_sigs(holder_commitment, sigs));
+				if let Ok((_sig, sigs)) = self.signer.sign_holder_commitment_and_htlcs(holder_commitment, &self.secp_ctx) {
+					self.prev_holder_htlc_sigs = Some(Self::extract_holder_sigs(holder_commitment, sigs));
+				}
 			}
 		}
 	}
@@ -1133,10 +1245,16 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 	// have empty holder commitment transaction if a ChannelMonitor is asked to force-close just after Channel::get_outbound_funding_created,
 	// before providing a initial commitment transaction. For outbound channel, init ChannelMonitor at Channel::funding_signed, there is nothing
 	// to monitor before.
-	pub(crate) fn get_fully_signed_holder_tx(&mut self, funding_redeemscript: &Script) -> Transaction {
-		let (sig, htlc_sigs) = self.signer.sign_holder_commitment_and_htlcs(&self.holder_commitment, &self.secp_ctx).expect("signing holder commitment");
+	//
+	// Returns `None` if our signer refused to sign, e.g. as it's a `WatchOnlySigner`.
+	pub(crate) fn get_fully_signed_holder_tx(&mut self, funding_redeemscript: &Script) -> Option<Transaction> {
+		let (sig, htlc_sigs) = self.signer.sign_holder_commitment_and_htlcs(&self.holder_commitment, &self.secp_ctx).ok()?;
 		self.holder_htlc_sigs = Some(Self::extract_holder_sigs(&self.holder_commitment, htlc_sigs));
-		self.holder_commitment.add_holder_sig(funding_redeemscript, sig)
+		Some(self.holder_commitment.add_holder_sig(funding_redeemscript, sig))
+	}
+
+	pub(crate) fn get_unsigned_holder_commitment_tx(&self) -> &Transaction {
+		&self.holder_commitment.trust().built_transaction().transaction
 	}
 
 	#[cfg(any(test, feature="unsafe_revoked_tx_signing"))]
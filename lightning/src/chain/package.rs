@@ -457,7 +457,7 @@ impl PackageSolvingData {
 				return onchain_handler.get_fully_signed_htlc_tx(outpoint, &outp.preimage);
 			}
 			PackageSolvingData::HolderFundingOutput(ref outp) => {
-				return Some(onchain_handler.get_fully_signed_holder_tx(&outp.funding_redeemscript));
+				return onchain_handler.get_fully_signed_holder_tx(&outp.funding_redeemscript);
 			}
 			_ => { panic!("API Error!"); }
 		}
@@ -586,6 +586,22 @@ impl PackageTemplate {
 	pub(crate) fn set_timer(&mut self, new_timer: u32) {
 		self.height_timer = new_timer;
 	}
+	/// Returns whether our counterparty may claim any of the package's outputs by the time
+	/// [`Self::timelock`] is reached, in which case failing to confirm the package by then may
+	/// result in a loss of funds.
+	///
+	/// Note that this includes our HTLC timeout claims, as our counterparty may claim an HTLC we
+	/// offered with its preimage at any time, even once it expired.
+	pub(crate) fn is_contentious_at_timelock(&self) -> bool {
+		self.inputs.iter().any(|(_, input)| match input {
+			PackageSolvingData::RevokedOutput(..) |
+			PackageSolvingData::RevokedHTLCOutput(..) |
+			PackageSolvingData::CounterpartyOfferedHTLCOutput(..) |
+			PackageSolvingData::CounterpartyReceivedHTLCOutput(..) |
+			PackageSolvingData::HolderHTLCOutput(..) => true,
+			PackageSolvingData::HolderFundingOutput(..) => false,
+		})
+	}
 	pub(crate) fn outpoints(&self) -> Vec<&BitcoinOutPoint> {
 		self.inputs.iter().map(|(o, _)| o).collect()
 	}
@@ -628,6 +644,26 @@ impl PackageTemplate {
 			}
 		}
 	}
+	/// Returns whether `other` may be merged into this package via [`Self::merge_package`] without
+	/// delaying the claim of any of its outputs, i.e., whether both packages are aggregable, spend
+	/// compatible outputs confirmed at the same height, and share the same absolute timelock at
+	/// `current_height`.
+	pub(crate) fn can_merge_with(&self, other: &PackageTemplate, current_height: u32) -> bool {
+		if self.malleability != PackageMalleability::Malleable || other.malleability != PackageMalleability::Malleable {
+			return false;
+		}
+		if !self.aggregable || !other.aggregable || self.height_original != other.height_original {
+			return false;
+		}
+		let lead_input = match self.inputs.first() {
+			Some((_, lead_input)) => lead_input,
+			None => return false,
+		};
+		if !other.inputs.iter().all(|(_, input)| lead_input.is_compatible(input)) {
+			return false;
+		}
+		self.package_locktime(current_height) == other.package_locktime(current_height)
+	}
 	pub(crate) fn merge_package(&mut self, mut merge_from: PackageTemplate) {
 		assert_eq!(self.height_original, merge_from.height_original);
 		if self.malleability == PackageMalleability::Untractable || merge_from.malleability == PackageMalleability::Untractable {
@@ -927,7 +963,7 @@ fn compute_fee_from_spent_amounts<F: Deref, L: Deref>(input_amounts: u64, predic
 	where F::Target: FeeEstimator,
 	      L::Target: Logger,
 {
-	let mut updated_feerate = fee_estimator.bounded_sat_per_1000_weight(ConfirmationTarget::HighPriority) as u64;
+	let mut updated_feerate = fee_estimator.bounded_sat_per_1000_weight(ConfirmationTarget::OnChainSweep) as u64;
 	let mut fee = updated_feerate * (predicted_weight as u64) / 1000;
 	if input_amounts <= fee {
 		updated_feerate = fee_estimator.bounded_sat_per_1000_weight(ConfirmationTarget::Normal) as u64;
@@ -1160,6 +1196,37 @@ mod tests {
 		revoked_package.merge_package(counterparty_package);
 	}
 
+	#[test]
+	fn test_package_can_merge_by_locktime() {
+		let txid = Txid::from_hex("c2d4449afa8d26140898dd54d3390b057ba2a5afcf03ba29d7dc0d8b9ffe966e").unwrap();
+		let secp_ctx = Secp256k1::new();
+		let offered_outp = dumb_counterparty_offered_output!(secp_ctx, 1_000_000, false);
+		let received_outp = dumb_counterparty_output!(secp_ctx, 1_000_000, false);
+		let revk_outp = dumb_revk_output!(secp_ctx, false);
+
+		let mut package_one = PackageTemplate::build_package(txid, 0, offered_outp.clone(), 1000, 100);
+		let package_two = PackageTemplate::build_package(txid, 1, offered_outp.clone(), 1000, 100);
+		let received_package = PackageTemplate::build_package(txid, 2, received_outp.clone(), 1000, 100);
+		let other_received_package = PackageTemplate::build_package(txid, 3, received_outp, 1000, 100);
+		let revoked_package = PackageTemplate::build_package(txid, 4, revk_outp, 1000, 100);
+		let other_height_package = PackageTemplate::build_package(txid, 5, offered_outp, 1000, 101);
+
+		// HTLC preimage claims may be aggregated, but not with claims of another type or claims of
+		// outputs confirmed at another height.
+		assert!(package_one.can_merge_with(&package_two, 100));
+		assert!(!package_one.can_merge_with(&received_package, 100));
+		assert!(!package_one.can_merge_with(&revoked_package, 100));
+		assert!(!package_one.can_merge_with(&other_height_package, 100));
+
+		// HTLC timeout claims are never aggregated, as our counterparty may claim any of them with
+		// the preimage at any time.
+		assert!(!received_package.can_merge_with(&other_received_package, 100));
+
+		package_one.merge_package(package_two);
+		assert_eq!(package_one.outpoints().len(), 2);
+		assert_eq!(package_one.package_locktime(100), 100);
+	}
+
 	#[test]
 	fn test_package_split_malleable() {
 		let txid = Txid::from_hex("c2d4449afa8d26140898dd54d3390b057ba2a5afcf03ba29d7dc0d8b9ffe966e").unwrap();
@@ -0,0 +1,384 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Reconciliation of the views of multiple chain sources for [`Confirm`] users.
+//!
+//! When several chain sources, e.g., both `lightning-block-sync` and `lightning-transaction-sync`,
+//! or two Esplora servers, feed the same [`Confirm`] implementation, they may briefly disagree on
+//! the state of the chain, causing transactions to be repeatedly confirmed and unconfirmed. A
+//! [`ConfirmReconciler`] instead tracks the view of each source, only passing changes through once
+//! a quorum of sources agree on them, and reports any disagreements as [`SourceDivergence`]s.
+
+use bitcoin::blockdata::block::BlockHeader;
+use bitcoin::blockdata::transaction::Transaction;
+use bitcoin::hash_types::{BlockHash, Txid};
+
+use crate::chain::Confirm;
+use crate::chain::transaction::TransactionData;
+
+use crate::prelude::*;
+use crate::sync::Mutex;
+use core::ops::Deref;
+
+/// A disagreement between the chain sources of a [`ConfirmReconciler`], as returned by
+/// [`ConfirmReconciler::get_and_clear_divergences`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SourceDivergence {
+	/// Sources reported different best blocks at the same height.
+	BestBlock {
+		/// The height at which the sources' best blocks differ.
+		height: u32,
+		/// The hash of the best block reported by each source at `height`, by source index.
+		block_hashes: Vec<(usize, BlockHash)>,
+	},
+	/// Sources reported a transaction as confirmed in different blocks.
+	Confirmation {
+		/// The id of the transaction.
+		txid: Txid,
+		/// The hash of the block in which each source reported the transaction as confirmed, by
+		/// source index.
+		block_hashes: Vec<(usize, BlockHash)>,
+	},
+}
+
+/// A confirmation reported by some source which has yet to be agreed on by a quorum.
+struct PendingConfirmation {
+	index: usize,
+	tx: Transaction,
+}
+
+#[derive(Default)]
+struct SourceState {
+	best_block: Option<(BlockHash, u32)>,
+	/// The block each transaction was last reported as confirmed in by this source.
+	confirmed: HashMap<Txid, BlockHash>,
+	/// Transactions which were passed through as confirmed but which this source has since
+	/// reported as unconfirmed.
+	unconfirmed: HashSet<Txid>,
+}
+
+struct ReconcilerState {
+	sources: Vec<SourceState>,
+	pending_confirmations: HashMap<(Txid, BlockHash), PendingConfirmation>,
+	/// The block each transaction was passed through as confirmed in.
+	confirmed: HashMap<Txid, BlockHash>,
+	/// The best block last passed through.
+	best_block: Option<BlockHash>,
+	divergences: Vec<SourceDivergence>,
+}
+
+/// Reconciles the views of several chain sources before passing them to a [`Confirm`]
+/// implementation.
+///
+/// Each source reports its view through its own [`ReconciledSource`], as returned by
+/// [`Self::source`]. A new best block or transaction confirmation is only passed through once at
+/// least `quorum` sources agree on it, and a passed-through confirmation is only undone once at
+/// least `quorum` sources report the transaction as unconfirmed or confirmed in a different block.
+///
+/// Note that confirmations which reach a quorum out of chain order are passed through as they
+/// reach it, thus sources should generally be kept in sync with one another.
+pub struct ConfirmReconciler<C: Deref> where C::Target: Confirm {
+	confirm: C,
+	quorum: usize,
+	state: Mutex<ReconcilerState>,
+}
+
+impl<C: Deref> ConfirmReconciler<C> where C::Target: Confirm {
+	/// Constructs a new reconciler which passes a change in the view of the chain through to
+	/// `confirm` once at least `quorum` out of `num_sources` sources agree on it.
+	///
+	/// Panics if `quorum` is zero or greater than `num_sources`.
+	pub fn new(confirm: C, num_sources: usize, quorum: usize) -> Self {
+		assert!(quorum > 0 && quorum <= num_sources, "quorum must be between one and the number of sources");
+		let mut sources = Vec::with_capacity(num_sources);
+		sources.resize_with(num_sources, SourceState::default);
+		Self {
+			confirm,
+			quorum,
+			state: Mutex::new(ReconcilerState {
+				sources,
+				pending_confirmations: HashMap::new(),
+				confirmed: HashMap::new(),
+				best_block: None,
+				divergences: Vec::new(),
+			}),
+		}
+	}
+
+	/// Returns the [`Confirm`] implementation through which the source with the given `index`
+	/// should report its view of the chain.
+	///
+	/// Panics if `index` is not below the number of sources the reconciler was constructed with.
+	pub fn source(&self, index: usize) -> ReconciledSource<'_, C> {
+		assert!(index < self.state.lock().unwrap().sources.len(), "No source with the given index");
+		ReconciledSource { reconciler: self, index }
+	}
+
+	/// Returns all disagreements between sources observed since the last call.
+	///
+	/// A disagreement which persists across several calls into the reconciler may be reported
+	/// more than once.
+	pub fn get_and_clear_divergences(&self) -> Vec<SourceDivergence> {
+		core::mem::replace(&mut self.state.lock().unwrap().divergences, Vec::new())
+	}
+
+	fn transactions_confirmed(&self, source: usize, header: &BlockHeader, txdata: &TransactionData, height: u32) {
+		let block_hash = header.block_hash();
+		let mut state_lock = self.state.lock().unwrap();
+		let state = &mut *state_lock;
+		let mut newly_confirmed = Vec::new();
+		for (index, tx) in txdata.iter() {
+			let txid = tx.txid();
+			state.sources[source].confirmed.insert(txid, block_hash);
+			state.sources[source].unconfirmed.remove(&txid);
+			if state.confirmed.get(&txid) == Some(&block_hash) { continue; }
+
+			state.pending_confirmations.entry((txid, block_hash))
+				.or_insert_with(|| PendingConfirmation { index: *index, tx: (*tx).clone() });
+			let block_hashes = state.sources.iter().enumerate()
+				.filter_map(|(idx, source)| source.confirmed.get(&txid).map(|hash| (idx, *hash)))
+				.collect::<Vec<_>>();
+			if block_hashes.iter().any(|(_, hash)| *hash != block_hash) {
+				state.divergences.push(SourceDivergence::Confirmation { txid, block_hashes: block_hashes.clone() });
+			}
+			if block_hashes.iter().filter(|(_, hash)| *hash == block_hash).count() >= self.quorum {
+				if state.confirmed.insert(txid, block_hash).is_some() {
+					// A quorum now agrees the transaction was confirmed in a different block.
+					self.confirm.transaction_unconfirmed(&txid);
+				}
+				for source in state.sources.iter_mut() {
+					source.unconfirmed.remove(&txid);
+				}
+				newly_confirmed.push(state.pending_confirmations.remove(&(txid, block_hash)).unwrap());
+			}
+		}
+
+		if !newly_confirmed.is_empty() {
+			newly_confirmed.sort_unstable_by_key(|confirmation| confirmation.index);
+			let txdata = newly_confirmed.iter()
+				.map(|confirmation| (confirmation.index, &confirmation.tx))
+				.collect::<Vec<_>>();
+			self.confirm.transactions_confirmed(header, &txdata, height);
+		}
+	}
+
+	fn transaction_unconfirmed(&self, source: usize, txid: &Txid) {
+		let mut state_lock = self.state.lock().unwrap();
+		let state = &mut *state_lock;
+		if let Some(block_hash) = state.sources[source].confirmed.remove(txid) {
+			if !state.sources.iter().any(|source| source.confirmed.get(txid) == Some(&block_hash)) {
+				state.pending_confirmations.remove(&(*txid, block_hash));
+			}
+		}
+
+		let confirmed_block_hash = match state.confirmed.get(txid) {
+			Some(block_hash) => *block_hash,
+			None => return,
+		};
+		state.sources[source].unconfirmed.insert(*txid);
+		let disputing = state.sources.iter().filter(|source| source.unconfirmed.contains(txid) ||
+			source.confirmed.get(txid).map_or(false, |hash| *hash != confirmed_block_hash)).count();
+		if disputing >= self.quorum {
+			state.confirmed.remove(txid);
+			for source in state.sources.iter_mut() {
+				source.unconfirmed.remove(txid);
+			}
+			self.confirm.transaction_unconfirmed(txid);
+		}
+	}
+
+	fn best_block_updated(&self, source: usize, header: &BlockHeader, height: u32) {
+		let block_hash = header.block_hash();
+		let mut state_lock = self.state.lock().unwrap();
+		let state = &mut *state_lock;
+		state.sources[source].best_block = Some((block_hash, height));
+
+		let block_hashes = state.sources.iter().enumerate()
+			.filter_map(|(idx, source)| match source.best_block {
+				Some((hash, source_height)) if source_height == height => Some((idx, hash)),
+				_ => None,
+			}).collect::<Vec<_>>();
+		if block_hashes.iter().any(|(_, hash)| *hash != block_hash) {
+			state.divergences.push(SourceDivergence::BestBlock { height, block_hashes: block_hashes.clone() });
+		}
+		if block_hashes.iter().filter(|(_, hash)| *hash == block_hash).count() < self.quorum ||
+			state.best_block == Some(block_hash)
+		{
+			return;
+		}
+		state.best_block = Some(block_hash);
+		self.confirm.best_block_updated(header, height);
+
+		// Stop tracking transactions which no longer need to be monitored for reorganizations.
+		let relevant_txids = self.confirm.get_relevant_txids().into_iter()
+			.map(|(txid, _)| txid).collect::<HashSet<_>>();
+		let irrelevant_txids = state.confirmed.keys()
+			.filter(|txid| !relevant_txids.contains(*txid))
+			.cloned().collect::<Vec<_>>();
+		for txid in irrelevant_txids.iter() {
+			state.confirmed.remove(txid);
+			for source in state.sources.iter_mut() {
+				source.confirmed.remove(txid);
+				source.unconfirmed.remove(txid);
+			}
+		}
+	}
+
+	fn get_relevant_txids(&self, source: usize) -> Vec<(Txid, Option<BlockHash>)> {
+		let state = self.state.lock().unwrap();
+		let source_state = &state.sources[source];
+		// Return the source's own view of where transactions were confirmed, such that it reports
+		// any which were reorganized out of its view of the chain, including ones which have yet to
+		// reach a quorum.
+		let mut txids = self.confirm.get_relevant_txids().into_iter()
+			.filter(|(txid, _)| !source_state.unconfirmed.contains(txid))
+			.map(|(txid, block_hash)| (txid, source_state.confirmed.get(&txid).cloned().or(block_hash)))
+			.collect::<Vec<_>>();
+		for (txid, block_hash) in source_state.confirmed.iter() {
+			if !txids.iter().any(|(relevant_txid, _)| relevant_txid == txid) {
+				txids.push((*txid, Some(*block_hash)));
+			}
+		}
+		txids
+	}
+}
+
+/// The [`Confirm`] implementation through which a single chain source reports its view of the
+/// chain to a [`ConfirmReconciler`].
+pub struct ReconciledSource<'a, C: Deref> where C::Target: Confirm {
+	reconciler: &'a ConfirmReconciler<C>,
+	index: usize,
+}
+
+impl<'a, C: Deref> Confirm for ReconciledSource<'a, C> where C::Target: Confirm {
+	fn transactions_confirmed(&self, header: &BlockHeader, txdata: &TransactionData, height: u32) {
+		self.reconciler.transactions_confirmed(self.index, header, txdata, height);
+	}
+
+	fn transaction_unconfirmed(&self, txid: &Txid) {
+		self.reconciler.transaction_unconfirmed(self.index, txid);
+	}
+
+	fn best_block_updated(&self, header: &BlockHeader, height: u32) {
+		self.reconciler.best_block_updated(self.index, header, height);
+	}
+
+	fn get_relevant_txids(&self) -> Vec<(Txid, Option<BlockHash>)> {
+		self.reconciler.get_relevant_txids(self.index)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{ConfirmReconciler, SourceDivergence};
+	use crate::chain::Confirm;
+	use crate::chain::transaction::TransactionData;
+	use crate::ln::functional_test_utils::create_dummy_header;
+	use crate::prelude::*;
+	use crate::sync::Mutex;
+
+	use bitcoin::blockdata::block::BlockHeader;
+	use bitcoin::blockdata::transaction::{PackedLockTime, Transaction};
+	use bitcoin::hash_types::{BlockHash, Txid};
+	use bitcoin::hashes::Hash;
+
+	#[derive(Debug, PartialEq, Eq)]
+	enum ConfirmCall {
+		Confirmed(Vec<Txid>, BlockHash),
+		Unconfirmed(Txid),
+		BestBlock(BlockHash, u32),
+	}
+
+	struct TestConfirm {
+		calls: Mutex<Vec<ConfirmCall>>,
+		confirmed: Mutex<HashMap<Txid, BlockHash>>,
+	}
+
+	impl TestConfirm {
+		fn take_calls(&self) -> Vec<ConfirmCall> {
+			core::mem::replace(&mut *self.calls.lock().unwrap(), Vec::new())
+		}
+	}
+
+	impl Confirm for TestConfirm {
+		fn transactions_confirmed(&self, header: &BlockHeader, txdata: &TransactionData, _height: u32) {
+			let txids = txdata.iter().map(|(_, tx)| tx.txid()).collect::<Vec<_>>();
+			for txid in txids.iter() {
+				self.confirmed.lock().unwrap().insert(*txid, header.block_hash());
+			}
+			self.calls.lock().unwrap().push(ConfirmCall::Confirmed(txids, header.block_hash()));
+		}
+
+		fn transaction_unconfirmed(&self, txid: &Txid) {
+			self.confirmed.lock().unwrap().remove(txid);
+			self.calls.lock().unwrap().push(ConfirmCall::Unconfirmed(*txid));
+		}
+
+		fn best_block_updated(&self, header: &BlockHeader, height: u32) {
+			self.calls.lock().unwrap().push(ConfirmCall::BestBlock(header.block_hash(), height));
+		}
+
+		fn get_relevant_txids(&self) -> Vec<(Txid, Option<BlockHash>)> {
+			self.confirmed.lock().unwrap().iter().map(|(txid, hash)| (*txid, Some(*hash))).collect()
+		}
+	}
+
+	#[test]
+	fn requires_quorum() {
+		let confirm = TestConfirm { calls: Mutex::new(Vec::new()), confirmed: Mutex::new(HashMap::new()) };
+		let reconciler = ConfirmReconciler::new(&confirm, 2, 2);
+		let (source_a, source_b) = (reconciler.source(0), reconciler.source(1));
+
+		let header = create_dummy_header(BlockHash::all_zeros(), 42);
+		let tx = Transaction { version: 2, lock_time: PackedLockTime::ZERO, input: Vec::new(), output: Vec::new() };
+
+		// Changes are only passed through once both sources agree on them.
+		source_a.best_block_updated(&header, 1);
+		assert!(confirm.take_calls().is_empty());
+		source_b.best_block_updated(&header, 1);
+		assert_eq!(confirm.take_calls(), vec![ConfirmCall::BestBlock(header.block_hash(), 1)]);
+
+		source_a.transactions_confirmed(&header, &[(0, &tx)], 1);
+		assert!(confirm.take_calls().is_empty());
+		assert_eq!(source_a.get_relevant_txids(), vec![(tx.txid(), Some(header.block_hash()))]);
+		source_b.transactions_confirmed(&header, &[(0, &tx)], 1);
+		assert_eq!(confirm.take_calls(), vec![ConfirmCall::Confirmed(vec![tx.txid()], header.block_hash())]);
+
+		// A single source flip-flopping has no effect.
+		source_a.transaction_unconfirmed(&tx.txid());
+		assert!(confirm.take_calls().is_empty());
+		assert!(source_a.get_relevant_txids().is_empty());
+		source_a.transactions_confirmed(&header, &[(0, &tx)], 1);
+		source_a.transaction_unconfirmed(&tx.txid());
+		assert!(confirm.take_calls().is_empty());
+		source_b.transaction_unconfirmed(&tx.txid());
+		assert_eq!(confirm.take_calls(), vec![ConfirmCall::Unconfirmed(tx.txid())]);
+		assert!(reconciler.get_and_clear_divergences().is_empty());
+
+		// Sources disagreeing on the best block are reported.
+		let header_a = create_dummy_header(header.block_hash(), 43);
+		let header_b = create_dummy_header(header.block_hash(), 44);
+		source_a.best_block_updated(&header_a, 2);
+		source_b.best_block_updated(&header_b, 2);
+		assert!(confirm.take_calls().is_empty());
+		assert_eq!(reconciler.get_and_clear_divergences(), vec![SourceDivergence::BestBlock {
+			height: 2, block_hashes: vec![(0, header_a.block_hash()), (1, header_b.block_hash())],
+		}]);
+		source_b.best_block_updated(&header_a, 2);
+		assert_eq!(confirm.take_calls(), vec![ConfirmCall::BestBlock(header_a.block_hash(), 2)]);
+
+		// As are sources disagreeing on the block a transaction was confirmed in.
+		source_a.transactions_confirmed(&header_a, &[(0, &tx)], 2);
+		source_b.transactions_confirmed(&header_b, &[(0, &tx)], 2);
+		assert!(confirm.take_calls().is_empty());
+		assert_eq!(reconciler.get_and_clear_divergences(), vec![SourceDivergence::Confirmation {
+			txid: tx.txid(), block_hashes: vec![(0, header_a.block_hash()), (1, header_b.block_hash())],
+		}]);
+	}
+}
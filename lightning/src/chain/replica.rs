@@ -0,0 +1,680 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Replication of [`ChannelMonitor`]s to a hot-standby breach watcher.
+//!
+//! A [`ReplicatingWatch`] wraps the [`Watch`] implementation used by a [`ChannelManager`], e.g. a
+//! [`ChainMonitor`], shipping each new [`ChannelMonitor`] and each [`ChannelMonitorUpdate`] it is
+//! given to a [`MonitorSyncSink`] as a [`MonitorSyncMessage`]. It is up to the sink to deliver the
+//! messages to a replica, typically running on a separate machine, which applies them via a
+//! [`MonitorReplica`] to its own [`Watch`] implementation, e.g. a separate [`ChainMonitor`] fed
+//! chain data independently. The replica thus holds up-to-date [`ChannelMonitor`]s, and detects
+//! and punishes counterparty breaches, even while the primary node is down.
+//!
+//! The replica never holds the keys to our funds. Each [`ChannelMonitor`] is shipped with a
+//! [`WatchOnlySigner`] in place of its signer, which can only sign justice transactions. Thus the
+//! replica never broadcasts our own commitment transactions, leaving force-closing channels to the
+//! primary.
+//!
+//! Lost, reordered or mismatched messages are reported by the replica as [`ReplicaDivergence`]s,
+//! which should be treated as an alert that it may no longer be able to protect all channels.
+//!
+//! [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+//! [`ChainMonitor`]: crate::chain::chainmonitor::ChainMonitor
+
+use bitcoin::blockdata::script::Script;
+use bitcoin::hash_types::BlockHash;
+use bitcoin::secp256k1::PublicKey;
+
+use crate::chain::{ChannelMonitorUpdateStatus, Watch};
+use crate::chain::channelmonitor::{ChannelMonitor, ChannelMonitorUpdate, MonitorEvent, CLOSED_CHANNEL_UPDATE_ID};
+use crate::chain::transaction::OutPoint;
+use crate::ln::msgs::DecodeError;
+use crate::ln::script::ShutdownScript;
+use crate::sign::{ChannelSigner, EntropySource, SignerProvider, WatchOnlySigner, WatchOnlySignerSource, WriteableEcdsaChannelSigner};
+use crate::util::ser::{Readable, ReadableArgs, Writeable, Writer};
+
+use crate::io::{self, Cursor};
+use crate::prelude::*;
+use crate::sync::Mutex;
+use core::marker::PhantomData;
+use core::ops::Deref;
+
+/// A message shipped from a [`ReplicatingWatch`] to a [`MonitorReplica`].
+#[derive(Clone, PartialEq, Eq)]
+pub enum MonitorSyncMessage {
+	/// A new channel is being watched.
+	NewMonitor {
+		/// The funding outpoint of the channel.
+		funding_txo: OutPoint,
+		/// The [`ChannelMonitor`] for the channel, serialized with `signer` in place of its signer.
+		serialized_monitor: Vec<u8>,
+		/// The [`WatchOnlySigner`] for the channel.
+		signer: WatchOnlySigner,
+	},
+	/// An update was applied to the [`ChannelMonitor`] of a channel.
+	Update {
+		/// The funding outpoint of the channel.
+		funding_txo: OutPoint,
+		/// The update that was applied.
+		update: ChannelMonitorUpdate,
+	},
+	/// The latest update id of each [`ChannelMonitor`] on the primary, sent by
+	/// [`ReplicatingWatch::send_checkpoint`] to allow the replica to detect divergence even if no
+	/// further updates are sent.
+	Checkpoint {
+		/// The funding outpoint of each channel along with the latest update id of its monitor.
+		latest_update_ids: Vec<(OutPoint, u64)>,
+	},
+}
+
+impl_writeable_tlv_based_enum!(MonitorSyncMessage,
+	(0, NewMonitor) => {
+		(0, funding_txo, required),
+		(2, serialized_monitor, required),
+		(4, signer, required),
+	},
+	(2, Update) => {
+		(0, funding_txo, required),
+		(2, update, required),
+	},
+	(4, Checkpoint) => {
+		(0, latest_update_ids, vec_type),
+	};
+);
+
+/// Delivers [`MonitorSyncMessage`]s generated by a [`ReplicatingWatch`] to a [`MonitorReplica`].
+///
+/// Messages must be delivered in the order they were generated. Delivery may be asynchronous,
+/// though updates which have not yet been delivered are of course not protected by the replica.
+pub trait MonitorSyncSink {
+	/// Sends the given message to the replica.
+	fn send_sync_message(&self, message: MonitorSyncMessage);
+}
+
+/// A [`Watch`] implementation which passes everything through to an inner [`Watch`], shipping
+/// each new [`ChannelMonitor`] and [`ChannelMonitorUpdate`] to a replica via a
+/// [`MonitorSyncSink`].
+///
+/// Each [`ChannelMonitor`] is shipped with the [`WatchOnlySigner`] for its channel, as provided by
+/// the [`WatchOnlySignerSource`], rather than its own signer.
+///
+/// See the [module-level documentation](self) for more.
+pub struct ReplicatingWatch<ChannelSigner: WriteableEcdsaChannelSigner, W: Deref, S: Deref, WS: Deref>
+where
+	W::Target: Watch<ChannelSigner>,
+	S::Target: MonitorSyncSink,
+	WS::Target: WatchOnlySignerSource,
+{
+	watch: W,
+	sink: S,
+	signer_source: WS,
+	latest_update_ids: Mutex<HashMap<OutPoint, u64>>,
+	_signer: PhantomData<ChannelSigner>,
+}
+
+impl<ChannelSigner: WriteableEcdsaChannelSigner, W: Deref, S: Deref, WS: Deref> ReplicatingWatch<ChannelSigner, W, S, WS>
+where
+	W::Target: Watch<ChannelSigner>,
+	S::Target: MonitorSyncSink,
+	WS::Target: WatchOnlySignerSource,
+{
+	/// Creates a new [`ReplicatingWatch`] wrapping `watch`, shipping messages to `sink`.
+	///
+	/// `signer_source` provides the [`WatchOnlySigner`]s shipped along with new monitors, and is
+	/// typically the [`KeysManager`] also used as the [`ChannelManager`]'s [`SignerProvider`].
+	///
+	/// Note that monitors passed to `watch` prior to the [`ReplicatingWatch`] being created, e.g.
+	/// those loaded on startup, are not shipped to the replica, which must be provided them
+	/// separately, see [`MonitorReplica::new`].
+	///
+	/// [`KeysManager`]: crate::sign::KeysManager
+	/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+	pub fn new(watch: W, sink: S, signer_source: WS) -> Self {
+		Self { watch, sink, signer_source, latest_update_ids: Mutex::new(HashMap::new()), _signer: PhantomData }
+	}
+
+	/// Records the latest update id of a monitor passed to the inner [`Watch`] before the
+	/// [`ReplicatingWatch`] was created, e.g. on startup, so that it is included in checkpoints.
+	pub fn track_monitor(&self, funding_txo: OutPoint, latest_update_id: u64) {
+		self.latest_update_ids.lock().unwrap().insert(funding_txo, latest_update_id);
+	}
+
+	/// Ships a [`MonitorSyncMessage::Checkpoint`] to the replica. This should be called
+	/// periodically, e.g. on each [`ChannelManager::timer_tick_occurred`] call, to allow the
+	/// replica to detect lost updates promptly.
+	///
+	/// [`ChannelManager::timer_tick_occurred`]: crate::ln::channelmanager::ChannelManager::timer_tick_occurred
+	pub fn send_checkpoint(&self) {
+		let latest_update_ids = self.latest_update_ids.lock().unwrap().iter()
+			.map(|(funding_txo, update_id)| (*funding_txo, *update_id)).collect();
+		self.sink.send_sync_message(MonitorSyncMessage::Checkpoint { latest_update_ids });
+	}
+}
+
+impl<ChannelSigner: WriteableEcdsaChannelSigner, W: Deref, S: Deref, WS: Deref> Watch<ChannelSigner> for ReplicatingWatch<ChannelSigner, W, S, WS>
+where
+	W::Target: Watch<ChannelSigner>,
+	S::Target: MonitorSyncSink,
+	WS::Target: WatchOnlySignerSource,
+{
+	fn watch_channel(&self, funding_txo: OutPoint, monitor: ChannelMonitor<ChannelSigner>) -> ChannelMonitorUpdateStatus {
+		let signer = self.signer_source.get_watch_only_signer(monitor.channel_value_satoshis(), monitor.channel_keys_id());
+		let serialized_monitor = monitor.encode_with_signer(&signer);
+		let latest_update_id = monitor.get_latest_update_id();
+		let status = self.watch.watch_channel(funding_txo, monitor);
+		if status != ChannelMonitorUpdateStatus::PermanentFailure {
+			self.latest_update_ids.lock().unwrap().insert(funding_txo, latest_update_id);
+			self.sink.send_sync_message(MonitorSyncMessage::NewMonitor { funding_txo, serialized_monitor, signer });
+		}
+		status
+	}
+
+	fn update_channel(&self, funding_txo: OutPoint, update: &ChannelMonitorUpdate) -> ChannelMonitorUpdateStatus {
+		let status = self.watch.update_channel(funding_txo, update);
+		if status != ChannelMonitorUpdateStatus::PermanentFailure {
+			self.latest_update_ids.lock().unwrap().insert(funding_txo, update.update_id);
+			self.sink.send_sync_message(MonitorSyncMessage::Update { funding_txo, update: update.clone() });
+		}
+		status
+	}
+
+	fn release_pending_monitor_events(&self) -> Vec<(OutPoint, Vec<MonitorEvent>, Option<PublicKey>)> {
+		self.watch.release_pending_monitor_events()
+	}
+}
+
+/// A disagreement between a [`MonitorReplica`] and its primary, as returned by
+/// [`MonitorReplica::get_and_clear_divergences`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReplicaDivergence {
+	/// We were sent an update or checkpoint for a channel we have no monitor for, e.g. as the
+	/// [`MonitorSyncMessage::NewMonitor`] for it was lost.
+	UnknownChannel {
+		/// The funding outpoint of the channel.
+		funding_txo: OutPoint,
+	},
+	/// We were sent, or given on startup, a [`ChannelMonitor`] which we could not read or watch.
+	InvalidMonitor {
+		/// The funding outpoint of the channel.
+		funding_txo: OutPoint,
+	},
+	/// We were sent an update which does not directly follow the latest one we applied, i.e. some
+	/// updates were lost. The update was not applied, nor will any further updates to the channel
+	/// be, until the missing ones are delivered.
+	MissedUpdates {
+		/// The funding outpoint of the channel.
+		funding_txo: OutPoint,
+		/// The id of the latest update we applied.
+		latest_update_id: u64,
+		/// The id of the update we were sent.
+		received_update_id: u64,
+	},
+	/// The [`Watch`] of the replica failed to apply an update, which is thus not considered
+	/// applied.
+	UpdateFailed {
+		/// The funding outpoint of the channel.
+		funding_txo: OutPoint,
+		/// The id of the update which failed.
+		update_id: u64,
+	},
+	/// A checkpoint from the primary disagrees with the latest update we applied.
+	UpdateIdMismatch {
+		/// The funding outpoint of the channel.
+		funding_txo: OutPoint,
+		/// The latest update id of the primary's monitor.
+		primary_update_id: u64,
+		/// The latest update id of our monitor.
+		replica_update_id: u64,
+	},
+}
+
+/// The [`SignerProvider`] for the [`ChannelMonitor`]s of a [`MonitorReplica`], handing out the
+/// [`WatchOnlySigner`]s shipped by the primary.
+///
+/// It gains a signer each time a [`MonitorSyncMessage::NewMonitor`] is applied, thus it must be
+/// persisted after each such message, before the new [`ChannelMonitor`] may need to be read. On
+/// startup, it must be read before the replica's [`ChannelMonitor`]s, as it is needed to read
+/// them.
+///
+/// It cannot be used to open channels, thus [`SignerProvider::generate_channel_keys_id`] panics,
+/// as does [`SignerProvider::derive_channel_signer`] for channels we were not given a signer for.
+pub struct WatchOnlySignerProvider {
+	signers: Mutex<HashMap<[u8; 32], WatchOnlySigner>>,
+}
+
+impl WatchOnlySignerProvider {
+	/// Creates a new [`WatchOnlySignerProvider`] without any signers.
+	pub fn new() -> Self {
+		Self { signers: Mutex::new(HashMap::new()) }
+	}
+
+	fn add_signer(&self, signer: WatchOnlySigner) {
+		self.signers.lock().unwrap().insert(signer.channel_keys_id(), signer);
+	}
+}
+
+impl SignerProvider for WatchOnlySignerProvider {
+	type Signer = WatchOnlySigner;
+
+	fn generate_channel_keys_id(&self, _inbound: bool, _channel_value_satoshis: u64, _user_channel_id: u128) -> [u8; 32] {
+		panic!("WatchOnlySignerProvider cannot be used to open channels");
+	}
+
+	fn derive_channel_signer(&self, _channel_value_satoshis: u64, channel_keys_id: [u8; 32]) -> WatchOnlySigner {
+		self.signers.lock().unwrap().get(&channel_keys_id).cloned()
+			.expect("WatchOnlySignerProvider must be given the signer of each channel before its monitor is read")
+	}
+
+	fn read_chan_signer(&self, reader: &[u8]) -> Result<WatchOnlySigner, DecodeError> {
+		WatchOnlySigner::read(&mut Cursor::new(reader))
+	}
+
+	fn get_destination_script(&self) -> Result<Script, ()> { Err(()) }
+
+	fn get_shutdown_scriptpubkey(&self) -> Result<ShutdownScript, ()> { Err(()) }
+}
+
+impl Writeable for WatchOnlySignerProvider {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
+		let signers: Vec<WatchOnlySigner> = self.signers.lock().unwrap().values().cloned().collect();
+		write_tlv_fields!(writer, {
+			(0, signers, vec_type),
+		});
+		Ok(())
+	}
+}
+
+impl Readable for WatchOnlySignerProvider {
+	fn read<R: io::Read>(reader: &mut R) -> Result<Self, DecodeError> {
+		let mut signers: Option<Vec<WatchOnlySigner>> = Some(Vec::new());
+		read_tlv_fields!(reader, {
+			(0, signers, vec_type),
+		});
+		let provider = Self::new();
+		for signer in signers.unwrap() {
+			provider.add_signer(signer);
+		}
+		Ok(provider)
+	}
+}
+
+/// Provides the single [`WatchOnlySigner`] shipped along with a [`ChannelMonitor`] to read it,
+/// without requiring the signer to be added to the [`WatchOnlySignerProvider`] beforehand.
+struct SingleSignerProvider<'a>(&'a WatchOnlySigner);
+
+impl<'a> SignerProvider for SingleSignerProvider<'a> {
+	type Signer = WatchOnlySigner;
+
+	fn generate_channel_keys_id(&self, _inbound: bool, _channel_value_satoshis: u64, _user_channel_id: u128) -> [u8; 32] {
+		unreachable!()
+	}
+
+	fn derive_channel_signer(&self, _channel_value_satoshis: u64, _channel_keys_id: [u8; 32]) -> WatchOnlySigner {
+		self.0.clone()
+	}
+
+	fn read_chan_signer(&self, reader: &[u8]) -> Result<WatchOnlySigner, DecodeError> {
+		WatchOnlySigner::read(&mut Cursor::new(reader))
+	}
+
+	fn get_destination_script(&self) -> Result<Script, ()> { Err(()) }
+
+	fn get_shutdown_scriptpubkey(&self) -> Result<ShutdownScript, ()> { Err(()) }
+}
+
+/// Applies [`MonitorSyncMessage`]s shipped by a [`ReplicatingWatch`] to a [`Watch`]
+/// implementation, tracking any divergence from the primary.
+///
+/// See the [module-level documentation](self) for more.
+pub struct MonitorReplica<W: Deref, ES: Deref, SP: Deref<Target = WatchOnlySignerProvider>>
+where
+	W::Target: Watch<WatchOnlySigner>,
+	ES::Target: EntropySource + Sized,
+{
+	watch: W,
+	entropy_source: ES,
+	signer_provider: SP,
+	latest_update_ids: Mutex<HashMap<OutPoint, u64>>,
+	divergences: Mutex<Vec<ReplicaDivergence>>,
+}
+
+impl<W: Deref, ES: Deref, SP: Deref<Target = WatchOnlySignerProvider>> MonitorReplica<W, ES, SP>
+where
+	W::Target: Watch<WatchOnlySigner>,
+	ES::Target: EntropySource + Sized,
+{
+	/// Creates a new [`MonitorReplica`] applying messages to `watch`.
+	///
+	/// `monitors` are the [`ChannelMonitor`]s previously persisted by the replica, e.g. when it
+	/// restarts, read using `signer_provider`, which are passed to `watch` here. Any which `watch`
+	/// fails to watch are reported as [`ReplicaDivergence::InvalidMonitor`].
+	pub fn new(watch: W, entropy_source: ES, signer_provider: SP,
+		monitors: Vec<ChannelMonitor<WatchOnlySigner>>
+	) -> Self {
+		let mut latest_update_ids = HashMap::new();
+		let mut divergences = Vec::new();
+		for monitor in monitors {
+			let funding_txo = monitor.get_funding_txo().0;
+			let latest_update_id = monitor.get_latest_update_id();
+			if watch.watch_channel(funding_txo, monitor) == ChannelMonitorUpdateStatus::PermanentFailure {
+				divergences.push(ReplicaDivergence::InvalidMonitor { funding_txo });
+				continue;
+			}
+			latest_update_ids.insert(funding_txo, latest_update_id);
+		}
+		Self {
+			watch, entropy_source, signer_provider,
+			latest_update_ids: Mutex::new(latest_update_ids),
+			divergences: Mutex::new(divergences),
+		}
+	}
+
+	/// Applies a message shipped by the primary's [`ReplicatingWatch`].
+	///
+	/// Messages replayed after they were already applied are ignored.
+	pub fn handle_sync_message(&self, message: MonitorSyncMessage) {
+		let mut latest_update_ids = self.latest_update_ids.lock().unwrap();
+		let mut divergences = self.divergences.lock().unwrap();
+		match message {
+			MonitorSyncMessage::NewMonitor { funding_txo, serialized_monitor, signer } => {
+				if latest_update_ids.contains_key(&funding_txo) { return; }
+				let read_res: Result<(BlockHash, ChannelMonitor<WatchOnlySigner>), _> =
+					ReadableArgs::read(&mut Cursor::new(&serialized_monitor[..]), (&*self.entropy_source, &SingleSignerProvider(&signer)));
+				let monitor = match read_res {
+					Ok((_, monitor)) if monitor.get_funding_txo().0 == funding_txo &&
+						monitor.channel_keys_id() == signer.channel_keys_id() => monitor,
+					_ => {
+						divergences.push(ReplicaDivergence::InvalidMonitor { funding_txo });
+						return;
+					},
+				};
+				let latest_update_id = monitor.get_latest_update_id();
+				self.signer_provider.add_signer(signer);
+				if self.watch.watch_channel(funding_txo, monitor) == ChannelMonitorUpdateStatus::PermanentFailure {
+					divergences.push(ReplicaDivergence::InvalidMonitor { funding_txo });
+					return;
+				}
+				latest_update_ids.insert(funding_txo, latest_update_id);
+			},
+			MonitorSyncMessage::Update { funding_txo, update } => {
+				let latest_update_id = match latest_update_ids.get_mut(&funding_txo) {
+					Some(latest_update_id) => latest_update_id,
+					None => {
+						divergences.push(ReplicaDivergence::UnknownChannel { funding_txo });
+						return;
+					},
+				};
+				if update.update_id != CLOSED_CHANNEL_UPDATE_ID {
+					if update.update_id <= *latest_update_id { return; }
+					if update.update_id != *latest_update_id + 1 {
+						divergences.push(ReplicaDivergence::MissedUpdates {
+							funding_txo, latest_update_id: *latest_update_id, received_update_id: update.update_id,
+						});
+						return;
+					}
+				}
+				if self.watch.update_channel(funding_txo, &update) == ChannelMonitorUpdateStatus::PermanentFailure {
+					divergences.push(ReplicaDivergence::UpdateFailed { funding_txo, update_id: update.update_id });
+					return;
+				}
+				*latest_update_id = update.update_id;
+			},
+			MonitorSyncMessage::Checkpoint { latest_update_ids: primary_update_ids } => {
+				for (funding_txo, primary_update_id) in primary_update_ids {
+					match latest_update_ids.get(&funding_txo) {
+						Some(replica_update_id) if *replica_update_id == primary_update_id => {},
+						Some(replica_update_id) => divergences.push(ReplicaDivergence::UpdateIdMismatch {
+							funding_txo, primary_update_id, replica_update_id: *replica_update_id,
+						}),
+						None => divergences.push(ReplicaDivergence::UnknownChannel { funding_txo }),
+					}
+				}
+			},
+		}
+	}
+
+	/// Returns the latest update id we applied to the monitor of each channel.
+	pub fn latest_update_ids(&self) -> Vec<(OutPoint, u64)> {
+		self.latest_update_ids.lock().unwrap().iter()
+			.map(|(funding_txo, update_id)| (*funding_txo, *update_id)).collect()
+	}
+
+	/// Returns the divergences from the primary detected since the last call.
+	pub fn get_and_clear_divergences(&self) -> Vec<ReplicaDivergence> {
+		core::mem::replace(&mut *self.divergences.lock().unwrap(), Vec::new())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{MonitorReplica, MonitorSyncMessage, MonitorSyncSink, ReplicaDivergence, ReplicatingWatch, WatchOnlySignerProvider};
+	use crate::chain::{ChannelMonitorUpdateStatus, Listen, Watch};
+	use crate::chain::chainmonitor::ChainMonitor;
+	use crate::chain::channelmonitor::{ChannelMonitor, LATENCY_GRACE_PERIOD_BLOCKS};
+	use crate::events::ClosureReason;
+	use crate::io;
+	use crate::ln::functional_test_utils::*;
+	use crate::sign::{WatchOnlySigner, WatchOnlySignerSource};
+	use crate::util::enforcing_trait_impls::EnforcingSigner;
+	use crate::util::ser::{Readable, ReadableArgs, Writeable};
+	use crate::util::test_utils;
+	use crate::sync::Mutex;
+	use crate::{check_added_monitors, check_closed_broadcast, check_closed_event, check_spends, get_local_commitment_txn};
+
+	use bitcoin::hash_types::BlockHash;
+	use bitcoin::network::constants::Network;
+
+	struct TestSink {
+		messages: Mutex<Vec<MonitorSyncMessage>>,
+	}
+
+	impl MonitorSyncSink for TestSink {
+		fn send_sync_message(&self, message: MonitorSyncMessage) {
+			self.messages.lock().unwrap().push(message);
+		}
+	}
+
+	#[test]
+	fn replica_punishes_breach() {
+		// Test that a replica fed only the messages of a `ReplicatingWatch` tracks the channel's
+		// monitor closely enough to punish a counterparty broadcasting a revoked commitment.
+		let chanmon_cfgs = create_chanmon_cfgs(2);
+		let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+		let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+		let chan = create_announced_chan_between_nodes(&nodes, 0, 1);
+		let funding_txo = nodes[1].chain_monitor.chain_monitor.list_monitors()[0];
+
+		// The primary side, which we feed the monitor and updates of nodes[1] as its
+		// `ChannelManager` would.
+		let primary_persister = test_utils::TestPersister::new();
+		let primary_chain_monitor = test_utils::TestChainMonitor::new(Some(&chanmon_cfgs[1].chain_source),
+			&chanmon_cfgs[1].tx_broadcaster, &chanmon_cfgs[1].logger, &chanmon_cfgs[1].fee_estimator,
+			&primary_persister, &chanmon_cfgs[1].keys_manager);
+		let sink = TestSink { messages: Mutex::new(Vec::new()) };
+		let primary = ReplicatingWatch::new(&primary_chain_monitor, &sink, &chanmon_cfgs[1].keys_manager);
+
+		// The replica side, with its own broadcaster and without any of our keys.
+		let replica_broadcaster = test_utils::TestBroadcaster::new(Network::Testnet);
+		let replica_persister = test_utils::TestPersister::new();
+		let replica_chain_monitor = ChainMonitor::new(Some(&chanmon_cfgs[1].chain_source),
+			&replica_broadcaster, &chanmon_cfgs[1].logger, &chanmon_cfgs[1].fee_estimator, &replica_persister);
+		let replica_signer_provider = WatchOnlySignerProvider::new();
+		let replica = MonitorReplica::new(&replica_chain_monitor, &chanmon_cfgs[1].keys_manager,
+			&replica_signer_provider, Vec::new());
+
+		let serialized_monitor = nodes[1].chain_monitor.chain_monitor.get_monitor(funding_txo).unwrap().encode();
+		let (_, monitor) = <(BlockHash, ChannelMonitor<EnforcingSigner>)>::read(&mut io::Cursor::new(&serialized_monitor),
+			(&chanmon_cfgs[1].keys_manager, &chanmon_cfgs[1].keys_manager)).unwrap();
+		let applied_updates = nodes[1].chain_monitor.monitor_updates.lock().unwrap().get(&chan.2).map_or(0, |updates| updates.len());
+		assert_eq!(primary.watch_channel(funding_txo, monitor), ChannelMonitorUpdateStatus::Completed);
+
+		let revoked_txn = get_local_commitment_txn!(nodes[0], chan.2);
+		let payment_preimage = route_payment(&nodes[0], &[&nodes[1]], 3_000_000).0;
+		claim_payment(&nodes[0], &[&nodes[1]], payment_preimage);
+
+		for update in nodes[1].chain_monitor.monitor_updates.lock().unwrap().get(&chan.2).unwrap()[applied_updates..].iter() {
+			assert_eq!(primary.update_channel(funding_txo, update), ChannelMonitorUpdateStatus::Completed);
+		}
+		primary.send_checkpoint();
+
+		for message in sink.messages.lock().unwrap().drain(..) {
+			replica.handle_sync_message(message);
+		}
+		assert!(replica.get_and_clear_divergences().is_empty());
+		let latest_update_id = nodes[1].chain_monitor.chain_monitor.get_monitor(funding_txo).unwrap().get_latest_update_id();
+		assert_eq!(replica.latest_update_ids(), vec![(funding_txo, latest_update_id)]);
+
+		// The replica's signers survive a round-trip, as is needed to read its monitors on restart.
+		let replica_monitor = replica_chain_monitor.get_monitor(funding_txo).unwrap().encode();
+		let replica_signer_provider: WatchOnlySignerProvider = Readable::read(&mut io::Cursor::new(&replica_signer_provider.encode())).unwrap();
+		<(BlockHash, ChannelMonitor<WatchOnlySigner>)>::read(&mut io::Cursor::new(&replica_monitor),
+			(&chanmon_cfgs[1].keys_manager, &replica_signer_provider)).unwrap();
+
+		// Once the revoked commitment confirms, the replica broadcasts a justice transaction.
+		let block = create_dummy_block(nodes[1].best_block_hash(), 42, vec![revoked_txn[0].clone()]);
+		replica_chain_monitor.block_connected(&block, nodes[1].best_block_info().1 + 1);
+		let justice_txn = replica_broadcaster.txn_broadcasted.lock().unwrap().split_off(0);
+		assert!(!justice_txn.is_empty());
+		for tx in justice_txn.iter() {
+			check_spends!(tx, revoked_txn[0]);
+		}
+	}
+
+	#[test]
+	fn replica_does_not_broadcast_holder_commitment() {
+		// Test that a replica, lacking the keys to sign our own commitment transaction, doesn't
+		// broadcast it even once an HTLC times out, leaving that to the primary.
+		let chanmon_cfgs = create_chanmon_cfgs(2);
+		let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+		let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+		create_announced_chan_between_nodes(&nodes, 0, 1);
+		route_payment(&nodes[0], &[&nodes[1]], 3_000_000);
+		let funding_txo = nodes[0].chain_monitor.chain_monitor.list_monitors()[0];
+
+		let replica_broadcaster = test_utils::TestBroadcaster::new(Network::Testnet);
+		let replica_persister = test_utils::TestPersister::new();
+		let replica_chain_monitor = ChainMonitor::new(Some(&chanmon_cfgs[0].chain_source),
+			&replica_broadcaster, &chanmon_cfgs[0].logger, &chanmon_cfgs[0].fee_estimator, &replica_persister);
+		let replica_signer_provider = WatchOnlySignerProvider::new();
+		let replica = MonitorReplica::new(&replica_chain_monitor, &chanmon_cfgs[0].keys_manager,
+			&replica_signer_provider, Vec::new());
+
+		let monitor = nodes[0].chain_monitor.chain_monitor.get_monitor(funding_txo).unwrap();
+		let signer = chanmon_cfgs[0].keys_manager.get_watch_only_signer(monitor.channel_value_satoshis(), monitor.channel_keys_id());
+		let serialized_monitor = monitor.encode_with_signer(&signer);
+		replica.handle_sync_message(MonitorSyncMessage::NewMonitor { funding_txo, serialized_monitor, signer });
+		assert!(replica.get_and_clear_divergences().is_empty());
+
+		// The primary broadcasts its commitment and HTLC-Timeout transactions once the HTLC times
+		// out, while the replica fed the same blocks doesn't.
+		let first_block = nodes[0].blocks.lock().unwrap().len();
+		connect_blocks(&nodes[0], TEST_FINAL_CLTV + LATENCY_GRACE_PERIOD_BLOCKS + 1);
+		check_closed_broadcast!(nodes[0], true);
+		check_added_monitors!(nodes[0], 1);
+		check_closed_event!(nodes[0], 1, ClosureReason::HolderForceClosed);
+		assert!(!nodes[0].tx_broadcaster.txn_broadcasted.lock().unwrap().split_off(0).is_empty());
+
+		for (block, height) in nodes[0].blocks.lock().unwrap()[first_block..].iter() {
+			replica_chain_monitor.block_connected(block, *height);
+		}
+		assert!(replica_broadcaster.txn_broadcasted.lock().unwrap().is_empty());
+	}
+
+	#[test]
+	fn replica_reports_divergence() {
+		let chanmon_cfgs = create_chanmon_cfgs(2);
+		let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+		let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+		let chan = create_announced_chan_between_nodes(&nodes, 0, 1);
+		let funding_txo = nodes[1].chain_monitor.chain_monitor.list_monitors()[0];
+
+		let replica_persister = test_utils::TestPersister::new();
+		let replica_chain_monitor = ChainMonitor::new(Some(&chanmon_cfgs[1].chain_source),
+			&chanmon_cfgs[1].tx_broadcaster, &chanmon_cfgs[1].logger, &chanmon_cfgs[1].fee_estimator, &replica_persister);
+		let replica_signer_provider = WatchOnlySignerProvider::new();
+		let replica = MonitorReplica::new(&replica_chain_monitor, &chanmon_cfgs[1].keys_manager,
+			&replica_signer_provider, Vec::new());
+
+		// Updates to a channel we have no monitor for are reported.
+		send_payment(&nodes[0], &[&nodes[1]], 1_000_000);
+		let updates = nodes[1].chain_monitor.monitor_updates.lock().unwrap().get(&chan.2).unwrap().clone();
+		replica.handle_sync_message(MonitorSyncMessage::Update { funding_txo, update: updates[0].clone() });
+		assert_eq!(replica.get_and_clear_divergences(), vec![ReplicaDivergence::UnknownChannel { funding_txo }]);
+
+		let monitor = nodes[1].chain_monitor.chain_monitor.get_monitor(funding_txo).unwrap();
+		let signer = chanmon_cfgs[1].keys_manager.get_watch_only_signer(monitor.channel_value_satoshis(), monitor.channel_keys_id());
+
+		// Monitors shipped with the signer of a different channel are rejected.
+		let other_signer = chanmon_cfgs[1].keys_manager.get_watch_only_signer(monitor.channel_value_satoshis(), [42; 32]);
+		let serialized_monitor = monitor.encode_with_signer(&other_signer);
+		replica.handle_sync_message(MonitorSyncMessage::NewMonitor { funding_txo, serialized_monitor, signer: other_signer });
+		assert_eq!(replica.get_and_clear_divergences(), vec![ReplicaDivergence::InvalidMonitor { funding_txo }]);
+
+		let serialized_monitor = monitor.encode_with_signer(&signer);
+		replica.handle_sync_message(MonitorSyncMessage::NewMonitor { funding_txo, serialized_monitor, signer });
+		let latest_update_id = nodes[1].chain_monitor.chain_monitor.get_monitor(funding_txo).unwrap().get_latest_update_id();
+
+		// Replays of updates we already have are ignored.
+		replica.handle_sync_message(MonitorSyncMessage::Update { funding_txo, update: updates.last().unwrap().clone() });
+		assert!(replica.get_and_clear_divergences().is_empty());
+
+		// But gaps in the updates we're sent are reported, as are mismatched checkpoints.
+		send_payment(&nodes[0], &[&nodes[1]], 1_000_000);
+		let updates = nodes[1].chain_monitor.monitor_updates.lock().unwrap().get(&chan.2).unwrap().clone();
+		let skipped_update = updates.iter().find(|update| update.update_id == latest_update_id + 2).unwrap();
+		replica.handle_sync_message(MonitorSyncMessage::Update { funding_txo, update: skipped_update.clone() });
+		replica.handle_sync_message(MonitorSyncMessage::Checkpoint { latest_update_ids: vec![(funding_txo, latest_update_id + 2)] });
+		assert_eq!(replica.get_and_clear_divergences(), vec![
+			ReplicaDivergence::MissedUpdates { funding_txo, latest_update_id, received_update_id: latest_update_id + 2 },
+			ReplicaDivergence::UpdateIdMismatch { funding_txo, primary_update_id: latest_update_id + 2, replica_update_id: latest_update_id },
+		]);
+
+		// Updates our `Watch` fails to apply are reported and aren't considered applied.
+		let next_update = updates.iter().find(|update| update.update_id == latest_update_id + 1).unwrap();
+		replica_persister.set_update_ret(ChannelMonitorUpdateStatus::PermanentFailure);
+		replica.handle_sync_message(MonitorSyncMessage::Update { funding_txo, update: next_update.clone() });
+		assert_eq!(replica.get_and_clear_divergences(), vec![
+			ReplicaDivergence::UpdateFailed { funding_txo, update_id: latest_update_id + 1 },
+		]);
+		assert_eq!(replica.latest_update_ids(), vec![(funding_txo, latest_update_id)]);
+	}
+
+	#[test]
+	fn replica_reports_unwatchable_monitors() {
+		// Test that monitors given to a replica on startup which its `Watch` fails to watch are
+		// reported rather than tracked.
+		let chanmon_cfgs = create_chanmon_cfgs(2);
+		let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+		let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+		create_announced_chan_between_nodes(&nodes, 0, 1);
+		let funding_txo = nodes[1].chain_monitor.chain_monitor.list_monitors()[0];
+
+		let monitor = nodes[1].chain_monitor.chain_monitor.get_monitor(funding_txo).unwrap();
+		let signer = chanmon_cfgs[1].keys_manager.get_watch_only_signer(monitor.channel_value_satoshis(), monitor.channel_keys_id());
+		let serialized_monitor = monitor.encode_with_signer(&signer);
+		let replica_signer_provider = WatchOnlySignerProvider::new();
+		replica_signer_provider.add_signer(signer);
+		let (_, monitor) = <(BlockHash, ChannelMonitor<WatchOnlySigner>)>::read(&mut io::Cursor::new(&serialized_monitor),
+			(&chanmon_cfgs[1].keys_manager, &replica_signer_provider)).unwrap();
+
+		let replica_persister = test_utils::TestPersister::new();
+		replica_persister.set_update_ret(ChannelMonitorUpdateStatus::PermanentFailure);
+		let replica_chain_monitor = ChainMonitor::new(Some(&chanmon_cfgs[1].chain_source),
+			&chanmon_cfgs[1].tx_broadcaster, &chanmon_cfgs[1].logger, &chanmon_cfgs[1].fee_estimator, &replica_persister);
+		let replica = MonitorReplica::new(&replica_chain_monitor, &chanmon_cfgs[1].keys_manager,
+			&replica_signer_provider, vec![monitor]);
+		assert_eq!(replica.get_and_clear_divergences(), vec![ReplicaDivergence::InvalidMonitor { funding_txo }]);
+		assert!(replica.latest_update_ids().is_empty());
+	}
+}
@@ -18,6 +18,20 @@ use bitcoin::secp256k1;
 use bitcoin::secp256k1::{PublicKey, Secp256k1};
 use bitcoin::secp256k1::ecdsa::Signature;
 
+#[cfg(anchors)]
+/// Reports the on-chain funds available to bump the fees of the transactions of our anchor
+/// channels, i.e. to handle [`BumpTransactionEvent`]s.
+///
+/// Used to decide whether we can accept further anchor channels, see
+/// [`ChannelHandshakeConfig::anchor_channel_reserve_sats`].
+///
+/// [`ChannelHandshakeConfig::anchor_channel_reserve_sats`]: crate::util::config::ChannelHandshakeConfig::anchor_channel_reserve_sats
+pub trait AnchorReserve {
+	/// Returns the amount, in satoshis, of confirmed on-chain funds readily available to us for
+	/// fee-bumping.
+	fn available_reserve_sats(&self) -> u64;
+}
+
 /// A descriptor used to sign for a commitment transaction's anchor output.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct AnchorDescriptor {
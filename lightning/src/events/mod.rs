@@ -20,27 +20,33 @@ pub mod bump_transaction;
 #[cfg(anchors)]
 pub use bump_transaction::BumpTransactionEvent;
 
+use crate::chain::chaininterface::ConfirmationTarget;
 use crate::sign::SpendableOutputDescriptor;
-use crate::ln::channelmanager::{InterceptId, PaymentId, RecipientOnionFields};
+use crate::ln::channelmanager::{BatchPaymentId, InterceptId, PaymentId, PendingHTLCDirection, RecipientOnionFields};
 use crate::ln::channel::FUNDING_CONF_DEADLINE_BLOCKS;
-use crate::ln::features::ChannelTypeFeatures;
+use crate::ln::features::{ChannelTypeFeatures, InitFeatures};
 use crate::ln::msgs;
 use crate::ln::{PaymentPreimage, PaymentHash, PaymentSecret};
-use crate::routing::gossip::NetworkUpdate;
+use crate::offers::invoice::Invoice;
+use crate::offers::invoice_request::InvoiceRequest;
+use crate::offers::refund::Refund;
+use crate::routing::gossip::{NetworkUpdate, NodeId};
+use crate::util::config::HandshakeLimitViolation;
 use crate::util::errors::APIError;
 use crate::util::ser::{BigSize, FixedLengthReader, Writeable, Writer, MaybeReadable, Readable, RequiredWrapper, UpgradableRequired, WithoutLength};
 use crate::util::string::UntrustedString;
 use crate::routing::router::{BlindedTail, Path, RouteHop, RouteParameters};
 
-use bitcoin::{PackedLockTime, Transaction, OutPoint};
+use bitcoin::{PackedLockTime, Transaction, OutPoint, Txid};
 #[cfg(anchors)]
-use bitcoin::{Txid, TxIn, TxOut, Witness};
+use bitcoin::{TxIn, TxOut, Witness};
 use bitcoin::blockdata::script::Script;
 use bitcoin::hashes::Hash;
 use bitcoin::hashes::sha256::Hash as Sha256;
 use bitcoin::secp256k1::PublicKey;
 use crate::io;
 use crate::prelude::*;
+use core::convert::TryFrom;
 use core::time::Duration;
 use core::ops::Deref;
 use crate::sync::Arc;
@@ -83,6 +89,23 @@ impl_writeable_tlv_based_enum!(PaymentPurpose,
 	(2, SpontaneousPayment)
 );
 
+/// The [`InvoiceRequest`] or [`Refund`] a BOLT 12 [`Invoice`] we were asked to pay was created in
+/// response to, as given in the [`Bolt12PayerContext`] passed to
+/// [`ChannelManager::send_payment_for_bolt12_invoice`].
+///
+/// [`Bolt12PayerContext`]: crate::ln::channelmanager::Bolt12PayerContext
+/// [`ChannelManager::send_payment_for_bolt12_invoice`]: crate::ln::channelmanager::ChannelManager::send_payment_for_bolt12_invoice
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Bolt12PaymentContext {
+	/// The invoice is for an [`Offer`] we requested an invoice for with the contained
+	/// [`InvoiceRequest`].
+	///
+	/// [`Offer`]: crate::offers::offer::Offer
+	InvoiceRequest(InvoiceRequest),
+	/// The invoice is for the contained [`Refund`] we created.
+	Refund(Refund),
+}
+
 /// When the payment path failure took place and extra details about it. [`PathFailure::OnPath`] may
 /// contain a [`NetworkUpdate`] that needs to be applied to the [`NetworkGraph`].
 ///
@@ -116,6 +139,130 @@ impl_writeable_tlv_based_enum_upgradable!(PathFailure,
 	},
 );
 
+/// The reason a node gave for failing an HTLC we sent, decoded from its failure code as defined in
+/// [BOLT 4].
+///
+/// [BOLT 4]: https://github.com/lightning/bolts/blob/master/04-onion-routing.md#returning-errors
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum FailureReason {
+	/// The realm byte of the onion was not understood by the node.
+	InvalidRealm,
+	/// The node was temporarily unable to process the HTLC.
+	TemporaryNodeFailure,
+	/// The node is permanently unable to process HTLCs.
+	PermanentNodeFailure,
+	/// The node requires a feature which was not present in its `node_announcement`.
+	RequiredNodeFeatureMissing,
+	/// The node did not understand the onion version we used.
+	InvalidOnionVersion,
+	/// The HMAC of the onion was invalid when it reached the node.
+	InvalidOnionHmac,
+	/// The ephemeral key of the onion was unparseable by the node.
+	InvalidOnionKey,
+	/// The node's outgoing channel was temporarily unable to forward the HTLC, e.g. due to a lack
+	/// of liquidity.
+	TemporaryChannelFailure,
+	/// The node's outgoing channel is permanently unable to forward HTLCs.
+	PermanentChannelFailure,
+	/// The node's outgoing channel requires a feature which was not present in its
+	/// `channel_announcement`.
+	RequiredChannelFeatureMissing,
+	/// The node does not know of the next peer or channel it was asked to forward to.
+	UnknownNextPeer,
+	/// The amount of the HTLC was below the minimum of the node's outgoing channel.
+	AmountBelowMinimum,
+	/// The HTLC did not pay the fee required by the node's outgoing channel.
+	FeeInsufficient,
+	/// The CLTV expiry of the HTLC did not meet the CLTV delta required by the node's outgoing
+	/// channel.
+	IncorrectCltvExpiry,
+	/// The CLTV expiry of the HTLC was too close to the current block height for the node to
+	/// safely forward it.
+	ExpiryTooSoon,
+	/// The recipient did not recognize the payment hash or secret, or the payment amount was
+	/// incorrect or the CLTV expiry too soon.
+	IncorrectOrUnknownPaymentDetails,
+	/// The CLTV expiry of the HTLC did not match the one in the onion when it reached the
+	/// recipient.
+	FinalIncorrectCltvExpiry,
+	/// The amount of the HTLC was lower than the one in the onion when it reached the recipient.
+	FinalIncorrectHtlcAmount,
+	/// The node's outgoing channel has been disabled.
+	ChannelDisabled,
+	/// The CLTV expiry of the HTLC was too far in the future.
+	ExpiryTooFar,
+	/// The node was unable to parse its payload within the onion.
+	InvalidOnionPayload,
+	/// The recipient did not receive all parts of a multi-path payment in time.
+	MppTimeout,
+	/// The node was unable to process the HTLC within a blinded path.
+	InvalidOnionBlinding,
+	/// A failure code we do not know of.
+	Unknown {
+		/// The raw failure code.
+		failure_code: u16,
+	},
+}
+
+impl FailureReason {
+	/// Returns the [BOLT 4] failure code for this reason.
+	///
+	/// [BOLT 4]: https://github.com/lightning/bolts/blob/master/04-onion-routing.md#returning-errors
+	pub fn failure_code(&self) -> u16 {
+		const BADONION: u16 = 0x8000;
+		const PERM: u16 = 0x4000;
+		const NODE: u16 = 0x2000;
+		const UPDATE: u16 = 0x1000;
+		match self {
+			FailureReason::InvalidRealm => PERM|1,
+			FailureReason::TemporaryNodeFailure => NODE|2,
+			FailureReason::PermanentNodeFailure => PERM|NODE|2,
+			FailureReason::RequiredNodeFeatureMissing => PERM|NODE|3,
+			FailureReason::InvalidOnionVersion => BADONION|PERM|4,
+			FailureReason::InvalidOnionHmac => BADONION|PERM|5,
+			FailureReason::InvalidOnionKey => BADONION|PERM|6,
+			FailureReason::TemporaryChannelFailure => UPDATE|7,
+			FailureReason::PermanentChannelFailure => PERM|8,
+			FailureReason::RequiredChannelFeatureMissing => PERM|9,
+			FailureReason::UnknownNextPeer => PERM|10,
+			FailureReason::AmountBelowMinimum => UPDATE|11,
+			FailureReason::FeeInsufficient => UPDATE|12,
+			FailureReason::IncorrectCltvExpiry => UPDATE|13,
+			FailureReason::ExpiryTooSoon => UPDATE|14,
+			FailureReason::IncorrectOrUnknownPaymentDetails => PERM|15,
+			FailureReason::FinalIncorrectCltvExpiry => 18,
+			FailureReason::FinalIncorrectHtlcAmount => 19,
+			FailureReason::ChannelDisabled => UPDATE|20,
+			FailureReason::ExpiryTooFar => 21,
+			FailureReason::InvalidOnionPayload => PERM|22,
+			FailureReason::MppTimeout => 23,
+			FailureReason::InvalidOnionBlinding => BADONION|PERM|24,
+			FailureReason::Unknown { failure_code } => *failure_code,
+		}
+	}
+}
+
+impl From<u16> for FailureReason {
+	fn from(failure_code: u16) -> Self {
+		const KNOWN_REASONS: [FailureReason; 23] = [
+			FailureReason::InvalidRealm, FailureReason::TemporaryNodeFailure,
+			FailureReason::PermanentNodeFailure, FailureReason::RequiredNodeFeatureMissing,
+			FailureReason::InvalidOnionVersion, FailureReason::InvalidOnionHmac,
+			FailureReason::InvalidOnionKey, FailureReason::TemporaryChannelFailure,
+			FailureReason::PermanentChannelFailure, FailureReason::RequiredChannelFeatureMissing,
+			FailureReason::UnknownNextPeer, FailureReason::AmountBelowMinimum,
+			FailureReason::FeeInsufficient, FailureReason::IncorrectCltvExpiry,
+			FailureReason::ExpiryTooSoon, FailureReason::IncorrectOrUnknownPaymentDetails,
+			FailureReason::FinalIncorrectCltvExpiry, FailureReason::FinalIncorrectHtlcAmount,
+			FailureReason::ChannelDisabled, FailureReason::ExpiryTooFar,
+			FailureReason::InvalidOnionPayload, FailureReason::MppTimeout,
+			FailureReason::InvalidOnionBlinding,
+		];
+		KNOWN_REASONS.iter().find(|reason| reason.failure_code() == failure_code).copied()
+			.unwrap_or(FailureReason::Unknown { failure_code })
+	}
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 /// The reason the channel was closed. See individual variants more details.
 pub enum ClosureReason {
@@ -168,7 +315,18 @@ pub enum ClosureReason {
 	///
 	/// [`ChannelMonitor`]: crate::chain::channelmonitor::ChannelMonitor
 	/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
-	OutdatedChannelManager
+	OutdatedChannelManager,
+	/// The funding transaction of an outbound channel had yet to confirm when the user abandoned
+	/// the channel via [`ChannelManager::abandon_unfunded_channel`].
+	///
+	/// [`ChannelManager::abandon_unfunded_channel`]: crate::ln::channelmanager::ChannelManager::abandon_unfunded_channel
+	FundingAbandoned,
+	/// A HTLC pending in the channel was about to expire, and we closed the channel to resolve it
+	/// on-chain ahead of its expiry, see
+	/// [`HtlcExpiryWatchdogConfig::preemptively_resolve_critical_htlcs`].
+	///
+	/// [`HtlcExpiryWatchdogConfig::preemptively_resolve_critical_htlcs`]: crate::util::config::HtlcExpiryWatchdogConfig::preemptively_resolve_critical_htlcs
+	HTLCExpiryApproaching,
 }
 
 impl core::fmt::Display for ClosureReason {
@@ -188,10 +346,39 @@ impl core::fmt::Display for ClosureReason {
 			},
 			ClosureReason::DisconnectedPeer => f.write_str("the peer disconnected prior to the channel being funded"),
 			ClosureReason::OutdatedChannelManager => f.write_str("the ChannelManager read from disk was stale compared to ChannelMonitor(s)"),
+			ClosureReason::FundingAbandoned => f.write_str("the user abandoned the channel before its funding transaction confirmed"),
+			ClosureReason::HTLCExpiryApproaching => f.write_str("a pending HTLC was about to expire"),
 		}
 	}
 }
 
+/// The reason a peer was disconnected, as reported in [`Event::PeerDisconnected`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PeerDisconnectReason {
+	/// The connection was closed, as indicated via [`PeerManager::socket_disconnected`].
+	///
+	/// [`PeerManager::socket_disconnected`]: crate::ln::peer_handler::PeerManager::socket_disconnected
+	ConnectionClosed,
+	/// We disconnected the peer as requested via [`PeerManager::disconnect_by_node_id`] or
+	/// [`PeerManager::disconnect_all_peers`].
+	///
+	/// [`PeerManager::disconnect_by_node_id`]: crate::ln::peer_handler::PeerManager::disconnect_by_node_id
+	/// [`PeerManager::disconnect_all_peers`]: crate::ln::peer_handler::PeerManager::disconnect_all_peers
+	LocalRequest,
+	/// The peer did not respond to a ping in time.
+	PingTimeout,
+	/// We disconnected the peer due to a protocol error, e.g. because it sent us an invalid
+	/// message or one of our message handlers asked us to disconnect it.
+	ProtocolError,
+}
+
+impl_writeable_tlv_based_enum_upgradable!(PeerDisconnectReason,
+	(0, ConnectionClosed) => {},
+	(2, LocalRequest) => {},
+	(4, PingTimeout) => {},
+	(6, ProtocolError) => {},
+);
+
 impl_writeable_tlv_based_enum_upgradable!(ClosureReason,
 	(0, CounterpartyForceClosed) => { (1, peer_msg, required) },
 	(1, FundingTimedOut) => {},
@@ -201,6 +388,158 @@ impl_writeable_tlv_based_enum_upgradable!(ClosureReason,
 	(8, ProcessingError) => { (1, err, required) },
 	(10, DisconnectedPeer) => {},
 	(12, OutdatedChannelManager) => {},
+	(13, FundingAbandoned) => {},
+	(15, HTLCExpiryApproaching) => {},
+);
+
+/// A step a channel took through its lifecycle, as reported in [`Event::ChannelProgressed`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChannelProgress {
+	/// We sent an `open_channel` or `accept_channel` message and are negotiating the channel's
+	/// parameters with our counterparty.
+	Negotiating,
+	/// We broadcast the channel's funding transaction.
+	FundingBroadcast {
+		/// The channel's funding transaction output.
+		funding_txo: OutPoint,
+	},
+	/// The channel's funding transaction gained (or, after a reorg, lost) confirmations. This is
+	/// generated until the funding transaction has reached the channel's required depth.
+	FundingConfirmed {
+		/// The number of confirmations the funding transaction currently has.
+		confirmations: u32,
+		/// The number of confirmations required before the channel can be used.
+		required_confirmations: u32,
+	},
+	/// Our peer reconnected and we sent a `channel_reestablish`, which has to be answered before
+	/// the channel can be used again.
+	ReestablishInProgress,
+	/// We sent a `shutdown` message, either initiating a cooperative close or in response to our
+	/// counterparty's.
+	ShutdownSent,
+	/// We received a `shutdown` message from our counterparty.
+	ShutdownReceived,
+	/// A closing transaction fee was proposed in a `closing_signed` message, i.e. a round of
+	/// closing fee negotiation took place.
+	ClosingFeeProposed {
+		/// The proposed fee, in satoshis.
+		fee_satoshis: u64,
+		/// Whether the fee was proposed by our counterparty, rather than us.
+		proposed_by_counterparty: bool,
+	},
+}
+
+impl_writeable_tlv_based_enum_upgradable!(ChannelProgress,
+	(0, Negotiating) => {},
+	(2, FundingBroadcast) => { (0, funding_txo, required) },
+	(4, FundingConfirmed) => {
+		(0, confirmations, required),
+		(2, required_confirmations, required),
+	},
+	(6, ReestablishInProgress) => {},
+	(8, ShutdownSent) => {},
+	(10, ShutdownReceived) => {},
+	(12, ClosingFeeProposed) => {
+		(0, fee_satoshis, required),
+		(2, proposed_by_counterparty, required),
+	},
+);
+
+/// How close a HTLC is to expiring, as reported in [`Event::HTLCExpiryApproaching`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HTLCExpirySeverity {
+	/// The HTLC expires within [`HtlcExpiryWatchdogConfig::warning_blocks`].
+	///
+	/// [`HtlcExpiryWatchdogConfig::warning_blocks`]: crate::util::config::HtlcExpiryWatchdogConfig::warning_blocks
+	Warning,
+	/// The HTLC expires within [`HtlcExpiryWatchdogConfig::critical_blocks`].
+	///
+	/// [`HtlcExpiryWatchdogConfig::critical_blocks`]: crate::util::config::HtlcExpiryWatchdogConfig::critical_blocks
+	Critical,
+}
+
+impl_writeable_tlv_based_enum!(HTLCExpirySeverity,
+	(0, Warning) => {},
+	(2, Critical) => {}, ;
+);
+
+/// The feerates selected when a channel was force-closed, as reported in
+/// [`Event::ChannelClosed`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ForceCloseFeerates {
+	/// The [`ConfirmationTarget`] the channel's [`ForceCloseFeePolicy`] selected for our
+	/// commitment transaction.
+	///
+	/// [`ForceCloseFeePolicy`]: crate::util::config::ForceCloseFeePolicy
+	pub confirmation_target: ConfirmationTarget,
+	/// The feerate of our commitment transaction, as agreed upon with our counterparty.
+	pub commitment_feerate_sat_per_1000_weight: u32,
+	/// The feerate our [`FeeEstimator`] returned for [`Self::confirmation_target`] when the channel
+	/// was closed. For anchor channels, our commitment transaction is bumped towards this feerate
+	/// via its anchor output.
+	///
+	/// [`FeeEstimator`]: crate::chain::chaininterface::FeeEstimator
+	pub target_feerate_sat_per_1000_weight: u32,
+}
+
+impl_writeable_tlv_based!(ForceCloseFeerates, {
+	(0, confirmation_target, required),
+	(2, commitment_feerate_sat_per_1000_weight, required),
+	(4, target_feerate_sat_per_1000_weight, required),
+});
+
+/// Why the funding transaction of an outbound channel is no longer expected to confirm, as
+/// reported in [`Event::FundingTransactionDropped`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FundingDropReason {
+	/// A transaction spending one of the funding transaction's inputs was confirmed.
+	DoubleSpent {
+		/// The txid of the confirmed transaction conflicting with the funding transaction.
+		conflicting_txid: Txid,
+	},
+	/// The funding transaction was evicted from the mempool, as indicated via
+	/// [`ChannelManager::funding_transaction_evicted`].
+	///
+	/// [`ChannelManager::funding_transaction_evicted`]: crate::ln::channelmanager::ChannelManager::funding_transaction_evicted
+	Evicted,
+}
+
+impl_writeable_tlv_based_enum_upgradable!(FundingDropReason,
+	(0, DoubleSpent) => { (0, conflicting_txid, required) },
+	(2, Evicted) => {},
+);
+
+/// Why we failed back an HTLC we were asked to relay within a blinded path, as reported in
+/// [`Event::BlindedForwardRejected`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BlindedForwardRejection {
+	/// We do not relay HTLCs within blinded paths as [`UserConfig::accept_blinded_forwards`] is
+	/// not set.
+	///
+	/// [`UserConfig::accept_blinded_forwards`]: crate::util::config::UserConfig::accept_blinded_forwards
+	Disabled,
+	/// The encrypted payload the recipient provided for us could not be decrypted or was invalid,
+	/// e.g. as it requires features we do not understand.
+	InvalidPayload,
+	/// The HTLC violated the payment constraints the recipient set for us, i.e. its amount was
+	/// below the `htlc_minimum_msat` or its CLTV expiry above the `max_cltv_expiry`.
+	PaymentConstraints,
+	/// The HTLC could not be forwarded over the outbound channel, e.g. as it does not exist or
+	/// the HTLC does not satisfy its [`ChannelConfig`].
+	///
+	/// [`ChannelConfig`]: crate::util::config::ChannelConfig
+	OutboundChannel {
+		/// The BOLT 4 failure code we would have failed the HTLC back with if it was not within a
+		/// blinded path.
+		failure_code: u16,
+	},
+}
+
+impl_writeable_tlv_based_enum_upgradable!(BlindedForwardRejection,
+	(0, Disabled) => {},
+	(2, InvalidPayload) => {},
+	(4, PaymentConstraints) => {},
+	(6, OutboundChannel) => { (0, failure_code, required) },
 );
 
 /// Intended destination of a failed HTLC as indicated in [`Event::HTLCHandlingFailed`].
@@ -312,6 +651,53 @@ impl_writeable_tlv_based_enum!(PaymentFailureReason,
 	(10, UnexpectedError) => {}, ;
 );
 
+/// The reason a channel was found to be stale. Used in [`Event::ChannelStale`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChannelStaleReason {
+	/// The channel's counterparty has not been connected to us for at least
+	/// [`ChannelStalenessConfig::peer_unreachable_threshold_secs`].
+	///
+	/// [`ChannelStalenessConfig::peer_unreachable_threshold_secs`]: crate::util::config::ChannelStalenessConfig::peer_unreachable_threshold_secs
+	PeerUnreachable {
+		/// The time, as a UNIX timestamp, at which we last saw the counterparty connected.
+		last_connected_time: u64,
+	},
+	/// The channel has not been used to send, receive or forward any HTLCs for at least
+	/// [`ChannelStalenessConfig::inactivity_threshold_secs`].
+	///
+	/// [`ChannelStalenessConfig::inactivity_threshold_secs`]: crate::util::config::ChannelStalenessConfig::inactivity_threshold_secs
+	Inactive {
+		/// The time, as a UNIX timestamp, at which the channel was last used.
+		last_activity_time: u64,
+	},
+}
+
+impl_writeable_tlv_based_enum!(ChannelStaleReason,
+	(0, PeerUnreachable) => {
+		(0, last_connected_time, required),
+	},
+	(2, Inactive) => {
+		(0, last_activity_time, required),
+	};
+);
+
+/// A message which may carry custom TLV records, see [`Event::CustomMessageTlvsReceived`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TlvExtensibleMessage {
+	/// An `open_channel` message.
+	OpenChannel,
+	/// An `accept_channel` message.
+	AcceptChannel,
+	/// A `shutdown` message.
+	Shutdown,
+}
+
+impl_writeable_tlv_based_enum!(TlvExtensibleMessage,
+	(0, OpenChannel) => {},
+	(2, AcceptChannel) => {},
+	(4, Shutdown) => {};
+);
+
 /// An Event which you should probably take some action in response to.
 ///
 /// Note that while Writeable and Readable are implemented for Event, you probably shouldn't use
@@ -404,6 +790,32 @@ pub enum Event {
 		///
 		/// [`ChannelManager::claim_funds`]: crate::ln::channelmanager::ChannelManager::claim_funds
 		claim_deadline: Option<u32>,
+		/// The total fee, in millisatoshis, which was skimmed off the payment by the intermediary
+		/// node(s) which forwarded it to us, i.e. by how much the HTLCs we received fell short of
+		/// the amounts the sender intended for us.
+		///
+		/// This is only non-zero if an expected skim was registered for the payment via
+		/// [`ChannelManager::register_expected_skim`], as we otherwise reject HTLCs which underpay,
+		/// and is already deducted from `amount_msat`.
+		///
+		/// [`ChannelManager::register_expected_skim`]: crate::ln::channelmanager::ChannelManager::register_expected_skim
+		counterparty_skimmed_fee_msat: u64,
+		/// Whether this event may already have been provided to an event handler before we
+		/// restarted, because [`ChannelManager`] was persisted after the event was provided but
+		/// before it was removed from the pending events. You should take care not to fulfill the
+		/// same order twice in that case.
+		///
+		/// If [`ChannelManager::claim_funds`] has been called for the payment before the
+		/// [`ChannelManager`] was persisted, a replayed copy of this event is not provided at all.
+		///
+		/// Note that replays are only detected if the [`ChannelManager`] was persisted between the
+		/// event being provided and the restart. If it was last persisted before the event was
+		/// provided, the event is provided again with this set to `false`, thus event handlers
+		/// which need exactly-once delivery must still deduplicate by payment hash themselves.
+		///
+		/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+		/// [`ChannelManager::claim_funds`]: crate::ln::channelmanager::ChannelManager::claim_funds
+		is_replay: bool,
 	},
 	/// Indicates a payment has been claimed and we've received money!
 	///
@@ -464,6 +876,12 @@ pub enum Event {
 		///
 		/// [`Route::get_total_fees`]: crate::routing::router::Route::get_total_fees
 		fee_paid_msat: Option<u64>,
+		/// The number of attempts which were made to route this payment, counting the initial
+		/// attempt and each retry.
+		///
+		/// As attempts are not persisted, this may undercount for payments which were pending
+		/// across a restart. `None` for older [`Event`] serializations.
+		attempts: Option<u32>,
 	},
 	/// Indicates an outbound payment failed. Individual [`Event::PaymentPathFailed`] events
 	/// provide failure information for each path attempt in the payment, including retries.
@@ -486,6 +904,12 @@ pub enum Event {
 		/// The reason the payment failed. This is only `None` for events generated or serialized
 		/// by versions prior to 0.0.115.
 		reason: Option<PaymentFailureReason>,
+		/// The number of attempts which were made to route this payment, counting the initial
+		/// attempt and each retry.
+		///
+		/// As attempts are not persisted, this may undercount for payments which were pending
+		/// across a restart. `None` for older [`Event`] serializations.
+		attempts: Option<u32>,
 	},
 	/// Indicates that a path for an outbound payment was successful.
 	///
@@ -549,6 +973,33 @@ pub enum Event {
 		/// If this is `Some`, then the corresponding channel should be avoided when the payment is
 		/// retried. May be `None` for older [`Event`] serializations.
 		short_channel_id: Option<u64>,
+		/// The time each hop along the [`Path`] reported holding the HTLC for before failing it
+		/// back, in milliseconds, starting with the first hop.
+		///
+		/// Hold times are only reported by hops which support attributable failures and are only
+		/// included up to the first hop which doesn't, or which provided invalid attribution data.
+		/// This may be used to penalize hops which are slow to fail HTLCs back.
+		///
+		/// Hold times are reported in increments of 100 milliseconds.
+		hold_times: Vec<u32>,
+		/// The reason given for failing the HTLC, if the failure could be decoded.
+		///
+		/// Will be `None` if no node along the path provided a failure we could authenticate, if
+		/// we failed to initially send the payment, or for older [`Event`] serializations.
+		failure_reason: Option<FailureReason>,
+		/// The node which generated the failure, if it could be determined. This may be
+		/// compared against the nodes in `path` to find the failing hop.
+		///
+		/// Note that a node failing an HTLC may blame its outgoing channel, see
+		/// `short_channel_id` for the channel responsible for the failure.
+		failing_node_id: Option<NodeId>,
+		/// The number of attempts which had been made to route the payment when this path failed,
+		/// counting the initial attempt and each retry. This may be used to correlate path failures
+		/// with the retry they were part of.
+		///
+		/// As attempts are not persisted, this may undercount for payments which were pending
+		/// across a restart. `None` for older [`Event`] serializations.
+		attempts: Option<u32>,
 #[cfg(test)]
 		error_code: Option<u16>,
 #[cfg(test)]
@@ -735,7 +1186,13 @@ pub enum Event {
 		/// [`UserConfig::manually_accept_inbound_channels`]: crate::util::config::UserConfig::manually_accept_inbound_channels
 		user_channel_id: u128,
 		/// The reason the channel was closed.
-		reason: ClosureReason
+		reason: ClosureReason,
+		/// The feerates selected for our commitment transaction when the channel was force-closed.
+		///
+		/// This will be `None` for cooperative closures, channels which were closed before their
+		/// funding transaction was broadcast, and for objects serialized with LDK versions prior to
+		/// 0.0.116.
+		force_close_feerates: Option<ForceCloseFeerates>,
 	},
 	/// Used to indicate to the user that they can abandon the funding transaction and recycle the
 	/// inputs for another purpose.
@@ -796,6 +1253,20 @@ pub enum Event {
 		///
 		/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
 		channel_type: ChannelTypeFeatures,
+		/// The parameters of the request which violate our [`ChannelHandshakeLimits`], if
+		/// [`ChannelHandshakeLimits::negotiate_limit_violations`] is set. Otherwise, or if the
+		/// request is within our limits, this is empty.
+		///
+		/// Accepting the request accepts these parameters anyway. Our own parameters may be
+		/// adjusted in response via [`ChannelManager::accept_inbound_channel_with_overrides`], or
+		/// the request may be rejected, telling our counterparty which of the violations made us
+		/// do so, via [`ChannelManager::reject_inbound_channel`].
+		///
+		/// [`ChannelHandshakeLimits`]: crate::util::config::ChannelHandshakeLimits
+		/// [`ChannelHandshakeLimits::negotiate_limit_violations`]: crate::util::config::ChannelHandshakeLimits::negotiate_limit_violations
+		/// [`ChannelManager::accept_inbound_channel_with_overrides`]: crate::ln::channelmanager::ChannelManager::accept_inbound_channel_with_overrides
+		/// [`ChannelManager::reject_inbound_channel`]: crate::ln::channelmanager::ChannelManager::reject_inbound_channel
+		limit_violations: Vec<HandshakeLimitViolation>,
 	},
 	/// Indicates that the HTLC was accepted, but could not be processed when or after attempting to
 	/// forward it.
@@ -815,6 +1286,509 @@ pub enum Event {
 		/// Destination of the HTLC that failed to be processed.
 		failed_next_destination: HTLCDestination,
 	},
+	/// Indicates that one of our on-chain claims remains unconfirmed, although our counterparty will
+	/// soon be able to claim the same outputs with a competing transaction, which may result in a
+	/// loss of funds.
+	///
+	/// This event is only generated by [`ChainMonitor::rebroadcast_pending_claims_with_fee_bumps`].
+	/// No action is required as fee-bumping continues within the configured limits, but users may
+	/// wish to intervene, e.g., by raising
+	/// [`ClaimFeeBumpConfig::max_feerate_sat_per_1000_weight`].
+	///
+	/// [`ChainMonitor::rebroadcast_pending_claims_with_fee_bumps`]: crate::chain::chainmonitor::ChainMonitor::rebroadcast_pending_claims_with_fee_bumps
+	/// [`ClaimFeeBumpConfig::max_feerate_sat_per_1000_weight`]: crate::chain::chainmonitor::ClaimFeeBumpConfig::max_feerate_sat_per_1000_weight
+	ClaimAtRisk {
+		/// The channel_id of the force-closed channel the claim belongs to.
+		channel_id: [u8; 32],
+		/// The outputs being claimed.
+		outpoints: Vec<OutPoint>,
+		/// The height by which our counterparty may claim any of the outputs. For claims of HTLCs we
+		/// offered, this is the HTLC's expiry, although our counterparty may claim them with the
+		/// preimage at any time.
+		deadline_height: u32,
+		/// The feerate of the most recent version of the claim, in satoshis per 1000 weight units.
+		feerate_sat_per_1000_weight: u64,
+	},
+	/// Indicates that the set of channels included in our static channel backup has changed (or
+	/// that we've just started up), and that the given backup should be stored, replacing any
+	/// previous one.
+	///
+	/// The backup should be stored somewhere it survives the loss of all other node state, e.g.,
+	/// with a remote storage provider. If all other state is lost, it may be provided to
+	/// [`ChannelManager::recover_from_static_backup`] to sweep our balance in the included
+	/// channels.
+	///
+	/// This event is only generated during [`ChannelManager::timer_tick_occurred`] if
+	/// [`UserConfig::generate_static_backup_events`] is set.
+	///
+	/// [`ChannelManager::recover_from_static_backup`]: crate::ln::channelmanager::ChannelManager::recover_from_static_backup
+	/// [`ChannelManager::timer_tick_occurred`]: crate::ln::channelmanager::ChannelManager::timer_tick_occurred
+	/// [`UserConfig::generate_static_backup_events`]: crate::util::config::UserConfig::generate_static_backup_events
+	StaticBackupUpdated {
+		/// The encrypted [`StaticBackup`], which may only be decrypted using the same
+		/// [`NodeSigner::get_inbound_payment_key_material`] it was generated with.
+		///
+		/// [`StaticBackup`]: crate::ln::static_backup::StaticBackup
+		/// [`NodeSigner::get_inbound_payment_key_material`]: crate::sign::NodeSigner::get_inbound_payment_key_material
+		encrypted_backup: Vec<u8>,
+	},
+	/// Indicates that an inbound payment created via [`ChannelManager::create_inbound_payment`] or
+	/// a similar method has expired without being paid.
+	///
+	/// Any HTLCs for the payment hash which arrive later are failed back with an
+	/// `incorrect_or_unknown_payment_details` error, thus the corresponding invoice may be treated
+	/// as unpayable.
+	///
+	/// This event is only generated during [`ChannelManager::timer_tick_occurred`] if
+	/// [`UserConfig::generate_inbound_payment_expired_events`] was set when the payment was
+	/// created. It is not generated for payments for which an [`Event::PaymentClaimable`] was
+	/// generated.
+	///
+	/// [`ChannelManager::create_inbound_payment`]: crate::ln::channelmanager::ChannelManager::create_inbound_payment
+	/// [`ChannelManager::timer_tick_occurred`]: crate::ln::channelmanager::ChannelManager::timer_tick_occurred
+	/// [`UserConfig::generate_inbound_payment_expired_events`]: crate::util::config::UserConfig::generate_inbound_payment_expired_events
+	InboundPaymentExpired {
+		/// The hash of the payment which expired.
+		payment_hash: PaymentHash,
+	},
+	/// Indicates that we received some, but not all, parts of a multi-part payment and failed the
+	/// received parts back after waiting [`UserConfig::mpp_receive_timeout_ticks`] for the
+	/// remaining ones.
+	///
+	/// The sender may retry the payment, thus this does not necessarily mean the payment will not
+	/// eventually succeed. It may, however, be useful to correlate with reports of failed payments.
+	///
+	/// An [`Event::HTLCHandlingFailed`] is also generated for the failed-back parts.
+	///
+	/// [`UserConfig::mpp_receive_timeout_ticks`]: crate::util::config::UserConfig::mpp_receive_timeout_ticks
+	PartialPaymentTimedOut {
+		/// The hash of the payment which timed out.
+		payment_hash: PaymentHash,
+		/// The sum of the values of the parts we received and failed back, in millisatoshis.
+		received_msat: u64,
+		/// The total value of the payment the sender indicated, in millisatoshis.
+		total_msat: u64,
+	},
+	/// Indicates that a connection to a peer has been established and that we and the peer have
+	/// exchanged `init` messages.
+	///
+	/// This event is only generated by a [`PeerManager`] configured with
+	/// [`PeerManager::with_peer_events`], and is provided via its [`EventsProvider`]
+	/// implementation.
+	///
+	/// [`PeerManager`]: crate::ln::peer_handler::PeerManager
+	/// [`PeerManager::with_peer_events`]: crate::ln::peer_handler::PeerManager::with_peer_events
+	PeerConnected {
+		/// The node id of the peer.
+		node_id: PublicKey,
+		/// The features the peer sent us in its `init` message.
+		features: InitFeatures,
+		/// The address of the peer, if it was provided when the connection was registered with the
+		/// [`PeerManager`].
+		///
+		/// [`PeerManager`]: crate::ln::peer_handler::PeerManager
+		address: Option<msgs::NetAddress>,
+		/// Whether the peer initiated the connection.
+		inbound: bool,
+	},
+	/// Indicates that a peer for which an [`Event::PeerConnected`] was generated has been
+	/// disconnected.
+	///
+	/// This event is only generated by a [`PeerManager`] configured with
+	/// [`PeerManager::with_peer_events`].
+	///
+	/// [`PeerManager`]: crate::ln::peer_handler::PeerManager
+	/// [`PeerManager::with_peer_events`]: crate::ln::peer_handler::PeerManager::with_peer_events
+	PeerDisconnected {
+		/// The node id of the peer.
+		node_id: PublicKey,
+		/// The reason the peer was disconnected.
+		reason: PeerDisconnectReason,
+	},
+	/// Indicates that a peer returned an encrypted [`StaticBackup`] we previously stored with it,
+	/// which includes channels we do not currently know about, e.g. because we lost our local
+	/// state.
+	///
+	/// The backup may be provided to [`ChannelManager::recover_from_static_backup`] to sweep our
+	/// balance in the included channels. Note that the backup is only as recent as the last one we
+	/// sent the peer, thus it may include channels which have since been closed.
+	///
+	/// This event is only generated if [`UserConfig::store_static_backup_with_peers`] is set.
+	///
+	/// [`StaticBackup`]: crate::ln::static_backup::StaticBackup
+	/// [`ChannelManager::recover_from_static_backup`]: crate::ln::channelmanager::ChannelManager::recover_from_static_backup
+	/// [`UserConfig::store_static_backup_with_peers`]: crate::util::config::UserConfig::store_static_backup_with_peers
+	StaticBackupRetrieved {
+		/// The node id of the peer which returned the backup.
+		counterparty_node_id: PublicKey,
+		/// The encrypted [`StaticBackup`], as returned by the peer.
+		///
+		/// [`StaticBackup`]: crate::ln::static_backup::StaticBackup
+		encrypted_backup: Vec<u8>,
+	},
+	/// Indicates that a channel's counterparty proved, on reconnection, that we have fallen behind,
+	/// i.e. that our latest commitment transaction has been revoked, e.g. because we restarted from
+	/// an old backup or lost [`ChannelMonitor`] updates.
+	///
+	/// Broadcasting our commitment transaction would allow our counterparty to claim all funds in
+	/// the channel, thus the channel has been closed without broadcasting it, and our counterparty
+	/// has been asked to force-close the channel. Once its commitment transaction confirms, our
+	/// balance in it is provided in an [`Event::SpendableOutputs`]. The channel is tracked until
+	/// acknowledged, see [`ChannelManager::list_stale_channels`] and
+	/// [`ChannelManager::acknowledge_stale_channel`].
+	///
+	/// This indicates a violation of the [`chain::Watch`] requirements, and thus that other
+	/// channels may have fallen behind as well.
+	///
+	/// An [`Event::ChannelClosed`] is also generated for the channel.
+	///
+	/// [`ChannelMonitor`]: crate::chain::channelmonitor::ChannelMonitor
+	/// [`ChannelManager::list_stale_channels`]: crate::ln::channelmanager::ChannelManager::list_stale_channels
+	/// [`ChannelManager::acknowledge_stale_channel`]: crate::ln::channelmanager::ChannelManager::acknowledge_stale_channel
+	/// [`chain::Watch`]: crate::chain::Watch
+	StaleChannelState {
+		/// The channel_id of the channel which has fallen behind.
+		channel_id: [u8; 32],
+		/// The node id of the channel's counterparty.
+		counterparty_node_id: PublicKey,
+		/// The channel's funding transaction output.
+		funding_txo: OutPoint,
+		/// The `user_channel_id` of the channel.
+		user_channel_id: u128,
+	},
+	/// Indicates that one or more paths of an outbound payment could not be sent as their first
+	/// hop channel was unavailable, e.g. as it lacked the outbound capacity or its peer had just
+	/// disconnected, and the payment was immediately retried over our other channels.
+	///
+	/// Such retries do not count against the payment's [`Retry`] budget. An
+	/// [`Event::PaymentPathFailed`] is still generated for each of the failed paths beforehand.
+	///
+	/// [`Retry`]: crate::ln::channelmanager::Retry
+	PaymentFirstHopSubstituted {
+		/// The `payment_id` passed to [`ChannelManager::send_payment`].
+		///
+		/// [`ChannelManager::send_payment`]: crate::ln::channelmanager::ChannelManager::send_payment
+		payment_id: PaymentId,
+		/// The hash that was given to [`ChannelManager::send_payment`].
+		///
+		/// [`ChannelManager::send_payment`]: crate::ln::channelmanager::ChannelManager::send_payment
+		payment_hash: PaymentHash,
+		/// The short channel ids of the unavailable first hop channels.
+		failed_short_channel_ids: Vec<u64>,
+		/// The short channel ids of the first hop channels of the paths the payment was retried
+		/// over.
+		short_channel_ids: Vec<u64>,
+	},
+	/// Indicates that a channel has become stale per the [`UserConfig::channel_staleness`]
+	/// policy, i.e. that its counterparty has been unreachable or the channel unused for longer
+	/// than configured. Stale channels lock up liquidity which may be better used elsewhere, so
+	/// you may wish to close them.
+	///
+	/// This event is generated once when the channel first becomes stale. If the channel later
+	/// becomes live and then stale again, it will be generated again.
+	///
+	/// [`UserConfig::channel_staleness`]: crate::util::config::UserConfig::channel_staleness
+	ChannelStale {
+		/// The `channel_id` of the stale channel.
+		channel_id: [u8; 32],
+		/// The node id of the channel's counterparty.
+		counterparty_node_id: PublicKey,
+		/// The `user_channel_id` of the channel.
+		user_channel_id: u128,
+		/// Why the channel is considered stale.
+		reason: ChannelStaleReason,
+		/// If [`ChannelStalenessConfig::auto_force_close`] is set, the time, as a UNIX timestamp,
+		/// after which we will force-close the channel if it is still stale and its counterparty
+		/// is not connected at the time.
+		///
+		/// [`ChannelStalenessConfig::auto_force_close`]: crate::util::config::ChannelStalenessConfig::auto_force_close
+		force_close_after_time: Option<u64>,
+	},
+	/// Indicates that every payment in a batch sent via [`ChannelManager::send_batch_payments`] or
+	/// [`ChannelManager::send_batch_payments_to_hashes`] has either succeeded or failed.
+	///
+	/// An [`Event::PaymentSent`] or [`Event::PaymentFailed`] is generated for each of the payments
+	/// beforehand, except for those which failed to be sent at all.
+	///
+	/// [`ChannelManager::send_batch_payments`]: crate::ln::channelmanager::ChannelManager::send_batch_payments
+	/// [`ChannelManager::send_batch_payments_to_hashes`]: crate::ln::channelmanager::ChannelManager::send_batch_payments_to_hashes
+	BatchPaymentCompleted {
+		/// The id of the batch returned when sending it.
+		batch_id: BatchPaymentId,
+		/// The ids of the payments in the batch which succeeded.
+		successful_payment_ids: Vec<PaymentId>,
+		/// The ids of the payments in the batch which failed, including any which failed to be sent.
+		failed_payment_ids: Vec<PaymentId>,
+	},
+	/// Indicates that our best block appears to lag far enough behind the actual chain tip that a
+	/// pending HTLC is at or near its expiry, per [`UserConfig::chain_sync_safety`].
+	///
+	/// You should check that chain data is being provided to the [`ChannelManager`] and
+	/// [`ChainMonitor`] promptly. Once it is, channels with expired HTLCs may be force-closed to
+	/// claim or fail them on-chain.
+	///
+	/// This event is generated once when the lag is first detected. If our best block catches up
+	/// and later lags again, it will be generated again.
+	///
+	/// [`UserConfig::chain_sync_safety`]: crate::util::config::UserConfig::chain_sync_safety
+	/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+	/// [`ChainMonitor`]: crate::chain::chainmonitor::ChainMonitor
+	ChainSyncLagging {
+		/// The height of our best block.
+		best_block_height: u32,
+		/// The estimated height of the actual chain tip, extrapolated from the time elapsed since
+		/// the timestamp of our best block assuming one block every ten minutes.
+		estimated_chain_tip_height: u32,
+		/// The earliest `cltv_expiry` of any of our pending HTLCs.
+		earliest_htlc_expiry_height: u32,
+	},
+	/// Indicates that we failed to forward an HTLC over a channel we fund only because doing so
+	/// would have eaten into the commitment transaction fee headroom we keep in reserve, per
+	/// [`ChannelConfig::fee_spike_buffer_multiple`].
+	///
+	/// The HTLC is failed back with `temporary_channel_failure`, which would otherwise look like
+	/// a mysterious local failure given the channel appears to have enough outbound capacity.
+	/// Frequent occurrences indicate the channel needs rebalancing or a lower fee-spike buffer.
+	///
+	/// This event is generated for each HTLC rejected this way.
+	///
+	/// [`ChannelConfig::fee_spike_buffer_multiple`]: crate::util::config::ChannelConfig::fee_spike_buffer_multiple
+	HTLCRejectedForFeeSpikeBuffer {
+		/// The channel over which the HTLC would have been forwarded.
+		channel_id: [u8; 32],
+		/// The node id of the counterparty of the channel.
+		counterparty_node_id: PublicKey,
+		/// The amount of the rejected HTLC.
+		htlc_amount_msat: u64,
+		/// The maximum amount we could send in an HTLC over the channel at the time, having
+		/// reserved the fee-spike buffer.
+		next_outbound_htlc_limit_msat: u64,
+	},
+	/// Indicates that a BOLT 12 [`Invoice`] was passed to
+	/// [`ChannelManager::send_payment_for_bolt12_invoice`] and verified against the
+	/// [`InvoiceRequest`] or [`Refund`] it was created in response to, allowing the payment to be
+	/// audited.
+	///
+	/// If `awaiting_approval` is set, the invoice is for an amount above
+	/// [`UserConfig::bolt12_payment_approval_threshold_msat`] and will only be paid once
+	/// [`ChannelManager::approve_bolt12_payment`] is called with the `payment_id`. Otherwise, the
+	/// payment was initiated. This event is not generated if the invoice was neither paid nor
+	/// held for approval.
+	///
+	/// [`ChannelManager::send_payment_for_bolt12_invoice`]: crate::ln::channelmanager::ChannelManager::send_payment_for_bolt12_invoice
+	/// [`ChannelManager::approve_bolt12_payment`]: crate::ln::channelmanager::ChannelManager::approve_bolt12_payment
+	/// [`UserConfig::bolt12_payment_approval_threshold_msat`]: crate::util::config::UserConfig::bolt12_payment_approval_threshold_msat
+	Bolt12InvoiceReceived {
+		/// The id of the payment for the invoice, as returned by
+		/// [`ChannelManager::send_payment_for_bolt12_invoice`].
+		///
+		/// [`ChannelManager::send_payment_for_bolt12_invoice`]: crate::ln::channelmanager::ChannelManager::send_payment_for_bolt12_invoice
+		payment_id: PaymentId,
+		/// The invoice to be paid.
+		invoice: Invoice,
+		/// The [`InvoiceRequest`] or [`Refund`] the invoice was created in response to.
+		payment_context: Bolt12PaymentContext,
+		/// Whether the payment will only be initiated once approved via
+		/// [`ChannelManager::approve_bolt12_payment`].
+		///
+		/// [`ChannelManager::approve_bolt12_payment`]: crate::ln::channelmanager::ChannelManager::approve_bolt12_payment
+		awaiting_approval: bool,
+	},
+	/// Indicates that, following a call to [`ChannelManager::begin_shutdown`], no HTLCs remain
+	/// pending in any of our channels, nor waiting to be forwarded or claimed.
+	///
+	/// The node may now be stopped for maintenance without holding up any payments. Note that
+	/// channels for which a cooperative close was initiated may still be closing.
+	///
+	/// This event is generated at most once per call to [`ChannelManager::begin_shutdown`].
+	///
+	/// [`ChannelManager::begin_shutdown`]: crate::ln::channelmanager::ChannelManager::begin_shutdown
+	NodeQuiescent,
+	/// Indicates that an HTLC for which an [`Event::HTLCIntercepted`] was generated has been
+	/// neither forwarded nor failed, and will be failed back once the chain reaches
+	/// `auto_fail_height`, before our counterparty on the inbound edge may have to force-close to
+	/// claim it back.
+	///
+	/// [`ChannelManager::forward_intercepted_htlc`] or [`ChannelManager::fail_intercepted_htlc`]
+	/// should be called before then. This event is generated
+	/// [`UserConfig::intercepted_htlc_expiry_warning_blocks`] blocks ahead of the deadline, at most
+	/// once per HTLC unless we restart in the meantime.
+	///
+	/// [`ChannelManager::forward_intercepted_htlc`]: crate::ln::channelmanager::ChannelManager::forward_intercepted_htlc
+	/// [`ChannelManager::fail_intercepted_htlc`]: crate::ln::channelmanager::ChannelManager::fail_intercepted_htlc
+	/// [`UserConfig::intercepted_htlc_expiry_warning_blocks`]: crate::util::config::UserConfig::intercepted_htlc_expiry_warning_blocks
+	HTLCInterceptExpiring {
+		/// The id of the intercepted HTLC, as provided in the [`Event::HTLCIntercepted`].
+		intercept_id: InterceptId,
+		/// The fake scid that was programmed as the next hop's scid.
+		requested_next_hop_scid: u64,
+		/// The payment hash used for this HTLC.
+		payment_hash: PaymentHash,
+		/// The block height at which the HTLC will be failed back.
+		auto_fail_height: u32,
+	},
+	/// Indicates that the funding transaction we broadcast for an outbound channel is no longer
+	/// expected to confirm, because it was double-spent or evicted from the mempool.
+	///
+	/// The funding transaction may be re-broadcast via
+	/// [`ChannelManager::rebroadcast_funding_transaction`], e.g., after an eviction, or, if it can
+	/// no longer confirm, the channel abandoned via [`ChannelManager::abandon_unfunded_channel`]
+	/// rather than being left pending indefinitely.
+	///
+	/// [`ChannelManager::rebroadcast_funding_transaction`]: crate::ln::channelmanager::ChannelManager::rebroadcast_funding_transaction
+	/// [`ChannelManager::abandon_unfunded_channel`]: crate::ln::channelmanager::ChannelManager::abandon_unfunded_channel
+	FundingTransactionDropped {
+		/// The `channel_id` of the channel whose funding transaction was dropped.
+		channel_id: [u8; 32],
+		/// The `node_id` of the channel counterparty.
+		counterparty_node_id: PublicKey,
+		/// The `user_channel_id` value passed in to [`ChannelManager::create_channel`].
+		///
+		/// [`ChannelManager::create_channel`]: crate::ln::channelmanager::ChannelManager::create_channel
+		user_channel_id: u128,
+		/// The outpoint of the channel's funding transaction.
+		funding_txo: OutPoint,
+		/// Why the funding transaction is no longer expected to confirm.
+		reason: FundingDropReason,
+	},
+	/// Indicates that our counterparty sent us a `channel_update` disabling one of our channels in
+	/// their direction, i.e. they will no longer forward payments to us through it.
+	///
+	/// This is only generated when the channel was previously enabled (or we had not yet received
+	/// a `channel_update` for it), not for every disabling `channel_update` we receive. The
+	/// parameters of the latest `channel_update` are available via
+	/// [`ChannelCounterparty::latest_channel_update`].
+	///
+	/// [`ChannelCounterparty::latest_channel_update`]: crate::ln::channelmanager::ChannelCounterparty::latest_channel_update
+	ChannelDisabledByCounterparty {
+		/// The `channel_id` of the channel which was disabled.
+		channel_id: [u8; 32],
+		/// The `node_id` of the channel counterparty.
+		counterparty_node_id: PublicKey,
+		/// The `user_channel_id` value passed in to [`ChannelManager::create_channel`] for outbound
+		/// channels, or to [`ChannelManager::accept_inbound_channel`] for inbound channels.
+		///
+		/// [`ChannelManager::create_channel`]: crate::ln::channelmanager::ChannelManager::create_channel
+		/// [`ChannelManager::accept_inbound_channel`]: crate::ln::channelmanager::ChannelManager::accept_inbound_channel
+		user_channel_id: u128,
+		/// The timestamp of the disabling `channel_update`.
+		timestamp: u32,
+	},
+	/// Indicates that a channel took a step through its lifecycle, allowing wallet UIs to show the
+	/// progress of channels being opened, reconnected or closed in more detail than
+	/// [`Event::ChannelPending`], [`Event::ChannelReady`] and [`Event::ChannelClosed`] do.
+	///
+	/// This event is only generated if [`UserConfig::generate_channel_progress_events`] is set.
+	/// It is purely informational and need not be acted upon.
+	///
+	/// [`UserConfig::generate_channel_progress_events`]: crate::util::config::UserConfig::generate_channel_progress_events
+	ChannelProgressed {
+		/// The `channel_id` of the channel, which is the `temporary_channel_id` while the channel
+		/// is being negotiated.
+		channel_id: [u8; 32],
+		/// The `node_id` of the channel counterparty.
+		counterparty_node_id: PublicKey,
+		/// The `user_channel_id` value passed in to [`ChannelManager::create_channel`] for outbound
+		/// channels, or to [`ChannelManager::accept_inbound_channel`] for inbound channels.
+		///
+		/// [`ChannelManager::create_channel`]: crate::ln::channelmanager::ChannelManager::create_channel
+		/// [`ChannelManager::accept_inbound_channel`]: crate::ln::channelmanager::ChannelManager::accept_inbound_channel
+		user_channel_id: u128,
+		/// The step the channel took.
+		progress: ChannelProgress,
+	},
+	/// Indicates that a HTLC pending in one of our channels is close to its CLTV expiry, e.g.
+	/// because our counterparty or the next hop is not resolving it.
+	///
+	/// This event is generated once a HTLC comes within
+	/// [`HtlcExpiryWatchdogConfig::warning_blocks`] of its expiry, and again once it comes within
+	/// [`HtlcExpiryWatchdogConfig::critical_blocks`]. If
+	/// [`HtlcExpiryWatchdogConfig::preemptively_resolve_critical_htlcs`] is set, critical HTLCs
+	/// are resolved automatically, otherwise they are left to the usual handling of expiring
+	/// HTLCs, which may force-close the channel once the HTLC has expired.
+	///
+	/// [`HtlcExpiryWatchdogConfig::warning_blocks`]: crate::util::config::HtlcExpiryWatchdogConfig::warning_blocks
+	/// [`HtlcExpiryWatchdogConfig::critical_blocks`]: crate::util::config::HtlcExpiryWatchdogConfig::critical_blocks
+	/// [`HtlcExpiryWatchdogConfig::preemptively_resolve_critical_htlcs`]: crate::util::config::HtlcExpiryWatchdogConfig::preemptively_resolve_critical_htlcs
+	HTLCExpiryApproaching {
+		/// The `channel_id` of the channel the HTLC is pending in.
+		channel_id: [u8; 32],
+		/// The `node_id` of the channel counterparty.
+		counterparty_node_id: PublicKey,
+		/// The channel-specific ID of the HTLC.
+		htlc_id: u64,
+		/// Whether the HTLC was offered to us or by us.
+		direction: PendingHTLCDirection,
+		/// The payment hash the HTLC is locked to.
+		payment_hash: PaymentHash,
+		/// The value of the HTLC, in millisatoshis.
+		amount_msat: u64,
+		/// The absolute block height at which the HTLC expires.
+		cltv_expiry: u32,
+		/// How close the HTLC is to expiring.
+		severity: HTLCExpirySeverity,
+	},
+	/// Indicates that our counterparty set a feerate on one of our channels which we'd otherwise
+	/// have force-closed the channel over, but which we're tolerating for now as
+	/// [`FeerateDisagreementConfig::grace_period_ticks`] is set.
+	///
+	/// If our counterparty doesn't update the feerate to something we agree with within
+	/// `grace_period_ticks` timer ticks, or too much value becomes pending in HTLCs in the
+	/// meantime, the channel will be force-closed.
+	///
+	/// [`FeerateDisagreementConfig::grace_period_ticks`]: crate::util::config::FeerateDisagreementConfig::grace_period_ticks
+	CounterpartyFeerateDisagreement {
+		/// The `channel_id` of the channel our counterparty updated the feerate of.
+		channel_id: [u8; 32],
+		/// The `node_id` of the channel counterparty.
+		counterparty_node_id: PublicKey,
+		/// The feerate our counterparty set, in satoshis per 1000 weight units.
+		feerate_per_kw: u32,
+		/// The number of timer ticks after which the channel will be force-closed if the
+		/// disagreement persists.
+		grace_period_ticks: u8,
+	},
+	/// Indicates that our counterparty included custom TLV records in an `open_channel`,
+	/// `accept_channel` or `shutdown` message.
+	///
+	/// Only odd TLV types of at least [`MIN_CUSTOM_TLV_TYPE`] are surfaced here, all others are
+	/// either understood by LDK or ignored. Custom TLV records may be included in our own messages
+	/// via [`ChannelManager::set_message_tlv_extension`].
+	///
+	/// For `open_channel` messages this event is generated before any resulting
+	/// [`Event::OpenChannelRequest`], allowing the records to be considered when deciding whether
+	/// to accept the channel.
+	///
+	/// [`MIN_CUSTOM_TLV_TYPE`]: crate::ln::msgs::MIN_CUSTOM_TLV_TYPE
+	/// [`ChannelManager::set_message_tlv_extension`]: crate::ln::channelmanager::ChannelManager::set_message_tlv_extension
+	CustomMessageTlvsReceived {
+		/// The `channel_id` of the channel the message refers to. For `open_channel` and
+		/// `accept_channel` messages this is the temporary channel id.
+		channel_id: [u8; 32],
+		/// The `node_id` of the channel counterparty.
+		counterparty_node_id: PublicKey,
+		/// The type of message which carried the records.
+		message: TlvExtensibleMessage,
+		/// The custom TLV records as `(type, value)` pairs, in increasing type order.
+		custom_tlvs: Vec<(u64, Vec<u8>)>,
+	},
+	/// Indicates that we failed back an HTLC we were asked to relay as an intermediate hop within
+	/// a blinded path.
+	///
+	/// As required by BOLT 4, the HTLC is failed back with `invalid_onion_blinding` rather than
+	/// the actual reason, which is only provided here. If we are the introduction node of the
+	/// path the failure is returned as an encrypted error, otherwise via
+	/// `update_fail_malformed_htlc`.
+	BlindedForwardRejected {
+		/// The `channel_id` of the channel the HTLC was received over.
+		prev_channel_id: [u8; 32],
+		/// The payment hash of the HTLC.
+		payment_hash: PaymentHash,
+		/// Whether we are the introduction node of the blinded path.
+		is_introduction_node: bool,
+		/// Why the HTLC was failed back.
+		reason: BlindedForwardRejection,
+	},
 	#[cfg(anchors)]
 	/// Indicates that a transaction originating from LDK needs to have its fee bumped. This event
 	/// requires confirmed external funds to be readily available to spend.
@@ -834,7 +1808,7 @@ impl Writeable for Event {
 			},
 			&Event::PaymentClaimable { ref payment_hash, ref amount_msat, ref purpose,
 				ref receiver_node_id, ref via_channel_id, ref via_user_channel_id,
-				ref claim_deadline, ref onion_fields
+				ref claim_deadline, ref onion_fields, ref counterparty_skimmed_fee_msat, ref is_replay
 			} => {
 				1u8.write(writer)?;
 				let mut payment_secret = None;
@@ -859,20 +1833,24 @@ impl Writeable for Event {
 					(7, claim_deadline, option),
 					(8, payment_preimage, option),
 					(9, onion_fields, option),
+					(11, counterparty_skimmed_fee_msat, required),
+					(13, is_replay, required),
 				});
 			},
-			&Event::PaymentSent { ref payment_id, ref payment_preimage, ref payment_hash, ref fee_paid_msat } => {
+			&Event::PaymentSent { ref payment_id, ref payment_preimage, ref payment_hash, ref fee_paid_msat, ref attempts } => {
 				2u8.write(writer)?;
 				write_tlv_fields!(writer, {
 					(0, payment_preimage, required),
 					(1, payment_hash, required),
 					(3, payment_id, option),
 					(5, fee_paid_msat, option),
+					(7, attempts, option),
 				});
 			},
 			&Event::PaymentPathFailed {
 				ref payment_id, ref payment_hash, ref payment_failed_permanently, ref failure,
-				ref path, ref short_channel_id,
+				ref path, ref short_channel_id, ref hold_times, ref failure_reason, ref failing_node_id,
+				ref attempts,
 				#[cfg(test)]
 				ref error_code,
 				#[cfg(test)]
@@ -894,6 +1872,10 @@ impl Writeable for Event {
 					(9, None::<RouteParameters>, option), // retry in LDK versions prior to 0.0.115
 					(11, payment_id, option),
 					(13, failure, required),
+					(15, *hold_times, optional_vec),
+					(17, failure_reason.map(|reason| reason.failure_code()), option),
+					(19, failing_node_id, option),
+					(21, attempts, option),
 				});
 			},
 			&Event::PendingHTLCsForwardable { time_forwardable: _ } => {
@@ -931,7 +1913,7 @@ impl Writeable for Event {
 					(5, outbound_amount_forwarded_msat, option),
 				});
 			},
-			&Event::ChannelClosed { ref channel_id, ref user_channel_id, ref reason } => {
+			&Event::ChannelClosed { ref channel_id, ref user_channel_id, ref reason, ref force_close_feerates } => {
 				9u8.write(writer)?;
 				// `user_channel_id` used to be a single u64 value. In order to remain backwards
 				// compatible with versions prior to 0.0.113, the u128 is serialized as two
@@ -943,6 +1925,7 @@ impl Writeable for Event {
 					(1, user_channel_id_low, required),
 					(2, reason, required),
 					(3, user_channel_id_high, required),
+					(5, force_close_feerates, option),
 				});
 			},
 			&Event::DiscardFunding { ref channel_id, ref transaction } => {
@@ -961,12 +1944,13 @@ impl Writeable for Event {
 					(6, path.blinded_tail, option),
 				})
 			},
-			&Event::PaymentFailed { ref payment_id, ref payment_hash, ref reason } => {
+			&Event::PaymentFailed { ref payment_id, ref payment_hash, ref reason, ref attempts } => {
 				15u8.write(writer)?;
 				write_tlv_fields!(writer, {
 					(0, payment_id, required),
 					(1, reason, option),
 					(2, payment_hash, required),
+					(3, attempts, option),
 				})
 			},
 			&Event::OpenChannelRequest { .. } => {
@@ -1039,20 +2023,231 @@ impl Writeable for Event {
 					(8, funding_txo, required),
 				});
 			},
-			// Note that, going forward, all new events must only write data inside of
-			// `write_tlv_fields`. Versions 0.0.101+ will ignore odd-numbered events that write
-			// data via `write_tlv_fields`.
-		}
-		Ok(())
-	}
-}
-impl MaybeReadable for Event {
-	fn read<R: io::Read>(reader: &mut R) -> Result<Option<Self>, msgs::DecodeError> {
-		match Readable::read(reader)? {
-			// Note that we do not write a length-prefixed TLV for FundingGenerationReady events,
-			// unlike all other events, thus we return immediately here.
-			0u8 => Ok(None),
-			1u8 => {
+			&Event::ClaimAtRisk { ref channel_id, ref outpoints, ref deadline_height, ref feerate_sat_per_1000_weight } => {
+				33u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, channel_id, required),
+					(2, WithoutLength(outpoints), required),
+					(4, deadline_height, required),
+					(6, feerate_sat_per_1000_weight, required),
+				});
+			},
+			&Event::StaticBackupUpdated { ref encrypted_backup } => {
+				35u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, encrypted_backup, required),
+				});
+			},
+			&Event::InboundPaymentExpired { ref payment_hash } => {
+				37u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, payment_hash, required),
+				});
+			},
+			&Event::PartialPaymentTimedOut { ref payment_hash, ref received_msat, ref total_msat } => {
+				39u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, payment_hash, required),
+					(2, received_msat, required),
+					(4, total_msat, required),
+				});
+			},
+			&Event::PeerConnected { ref node_id, ref features, ref address, ref inbound } => {
+				41u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, node_id, required),
+					(2, features, required),
+					(4, address, option),
+					(6, inbound, required),
+				});
+			},
+			&Event::PeerDisconnected { ref node_id, ref reason } => {
+				43u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, node_id, required),
+					(2, reason, required),
+				});
+			},
+			&Event::StaticBackupRetrieved { ref counterparty_node_id, ref encrypted_backup } => {
+				45u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, counterparty_node_id, required),
+					(2, encrypted_backup, required),
+				});
+			},
+			&Event::StaleChannelState { ref channel_id, ref counterparty_node_id, ref funding_txo, ref user_channel_id } => {
+				47u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, channel_id, required),
+					(2, counterparty_node_id, required),
+					(4, funding_txo, required),
+					(6, user_channel_id, required),
+				});
+			},
+			&Event::PaymentFirstHopSubstituted {
+				ref payment_id, ref payment_hash, ref failed_short_channel_ids, ref short_channel_ids
+			} => {
+				49u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, payment_id, required),
+					(2, payment_hash, required),
+					(4, *failed_short_channel_ids, optional_vec),
+					(6, *short_channel_ids, optional_vec),
+				});
+			},
+			&Event::ChannelStale {
+				ref channel_id, ref counterparty_node_id, ref user_channel_id, ref reason, ref force_close_after_time
+			} => {
+				51u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, channel_id, required),
+					(2, counterparty_node_id, required),
+					(4, user_channel_id, required),
+					(6, reason, required),
+					(8, force_close_after_time, option),
+				});
+			},
+			&Event::BatchPaymentCompleted { ref batch_id, ref successful_payment_ids, ref failed_payment_ids } => {
+				53u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, batch_id, required),
+					(2, *successful_payment_ids, optional_vec),
+					(4, *failed_payment_ids, optional_vec),
+				});
+			},
+			&Event::ChainSyncLagging { ref best_block_height, ref estimated_chain_tip_height, ref earliest_htlc_expiry_height } => {
+				55u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, best_block_height, required),
+					(2, estimated_chain_tip_height, required),
+					(4, earliest_htlc_expiry_height, required),
+				});
+			},
+			&Event::HTLCRejectedForFeeSpikeBuffer { ref channel_id, ref counterparty_node_id, ref htlc_amount_msat, ref next_outbound_htlc_limit_msat } => {
+				57u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, channel_id, required),
+					(2, counterparty_node_id, required),
+					(4, htlc_amount_msat, required),
+					(6, next_outbound_htlc_limit_msat, required),
+				});
+			},
+			&Event::Bolt12InvoiceReceived { ref payment_id, ref invoice, ref payment_context, ref awaiting_approval } => {
+				59u8.write(writer)?;
+				let (invoice_request, refund) = match payment_context {
+					Bolt12PaymentContext::InvoiceRequest(invoice_request) => (Some(invoice_request), None),
+					Bolt12PaymentContext::Refund(refund) => (None, Some(refund)),
+				};
+				write_tlv_fields!(writer, {
+					(0, payment_id, required),
+					(2, invoice, required),
+					(4, awaiting_approval, required),
+					(5, invoice_request, option),
+					(7, refund, option),
+				});
+			},
+			&Event::NodeQuiescent => {
+				61u8.write(writer)?;
+				write_tlv_fields!(writer, {});
+			},
+			&Event::HTLCInterceptExpiring { ref intercept_id, ref requested_next_hop_scid, ref payment_hash, ref auto_fail_height } => {
+				63u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, intercept_id, required),
+					(2, requested_next_hop_scid, required),
+					(4, payment_hash, required),
+					(6, auto_fail_height, required),
+				});
+			},
+			&Event::FundingTransactionDropped { ref channel_id, ref counterparty_node_id, ref user_channel_id, ref funding_txo, ref reason } => {
+				65u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, channel_id, required),
+					(2, counterparty_node_id, required),
+					(4, user_channel_id, required),
+					(6, funding_txo, required),
+					(8, reason, required),
+				});
+			},
+			&Event::ChannelDisabledByCounterparty { ref channel_id, ref counterparty_node_id, ref user_channel_id, ref timestamp } => {
+				67u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, channel_id, required),
+					(2, counterparty_node_id, required),
+					(4, user_channel_id, required),
+					(6, timestamp, required),
+				});
+			},
+			&Event::CustomMessageTlvsReceived { ref channel_id, ref counterparty_node_id, ref message, ref custom_tlvs } => {
+				69u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, channel_id, required),
+					(2, counterparty_node_id, required),
+					(4, message, required),
+					(6, *custom_tlvs, vec_type),
+				});
+			},
+			&Event::ChannelProgressed { ref channel_id, ref counterparty_node_id, ref user_channel_id, ref progress } => {
+				71u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, channel_id, required),
+					(2, counterparty_node_id, required),
+					(4, user_channel_id, required),
+					(6, progress, upgradable_required),
+				});
+			},
+			&Event::HTLCExpiryApproaching {
+				ref channel_id, ref counterparty_node_id, ref htlc_id, ref direction, ref payment_hash,
+				ref amount_msat, ref cltv_expiry, ref severity
+			} => {
+				73u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, channel_id, required),
+					(2, counterparty_node_id, required),
+					(4, htlc_id, required),
+					(6, direction, required),
+					(8, payment_hash, required),
+					(10, amount_msat, required),
+					(12, cltv_expiry, required),
+					(14, severity, required),
+				});
+			},
+			&Event::CounterpartyFeerateDisagreement {
+				ref channel_id, ref counterparty_node_id, ref feerate_per_kw, ref grace_period_ticks
+			} => {
+				75u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, channel_id, required),
+					(2, counterparty_node_id, required),
+					(4, feerate_per_kw, required),
+					(6, grace_period_ticks, required),
+				});
+			},
+			&Event::BlindedForwardRejected {
+				ref prev_channel_id, ref payment_hash, ref is_introduction_node, ref reason
+			} => {
+				77u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, prev_channel_id, required),
+					(2, payment_hash, required),
+					(4, is_introduction_node, required),
+					(6, reason, required),
+				});
+			},
+			// Note that, going forward, all new events must only write data inside of
+			// `write_tlv_fields`. Versions 0.0.101+ will ignore odd-numbered events that write
+			// data via `write_tlv_fields`.
+		}
+		Ok(())
+	}
+}
+impl MaybeReadable for Event {
+	fn read<R: io::Read>(reader: &mut R) -> Result<Option<Self>, msgs::DecodeError> {
+		match Readable::read(reader)? {
+			// Note that we do not write a length-prefixed TLV for FundingGenerationReady events,
+			// unlike all other events, thus we return immediately here.
+			0u8 => Ok(None),
+			1u8 => {
 				let f = || {
 					let mut payment_hash = PaymentHash([0; 32]);
 					let mut payment_preimage = None;
@@ -1064,6 +2259,8 @@ impl MaybeReadable for Event {
 					let mut claim_deadline = None;
 					let mut via_user_channel_id = None;
 					let mut onion_fields = None;
+					let mut counterparty_skimmed_fee_msat: Option<u64> = None;
+					let mut is_replay: Option<bool> = None;
 					read_tlv_fields!(reader, {
 						(0, payment_hash, required),
 						(1, receiver_node_id, option),
@@ -1075,6 +2272,8 @@ impl MaybeReadable for Event {
 						(7, claim_deadline, option),
 						(8, payment_preimage, option),
 						(9, onion_fields, option),
+						(11, counterparty_skimmed_fee_msat, option),
+						(13, is_replay, option),
 					});
 					let purpose = match payment_secret {
 						Some(secret) => PaymentPurpose::InvoicePayment {
@@ -1093,6 +2292,8 @@ impl MaybeReadable for Event {
 						via_user_channel_id,
 						claim_deadline,
 						onion_fields,
+						counterparty_skimmed_fee_msat: counterparty_skimmed_fee_msat.unwrap_or(0),
+						is_replay: is_replay.unwrap_or(false),
 					}))
 				};
 				f()
@@ -1103,11 +2304,13 @@ impl MaybeReadable for Event {
 					let mut payment_hash = None;
 					let mut payment_id = None;
 					let mut fee_paid_msat = None;
+					let mut attempts = None;
 					read_tlv_fields!(reader, {
 						(0, payment_preimage, required),
 						(1, payment_hash, option),
 						(3, payment_id, option),
 						(5, fee_paid_msat, option),
+						(7, attempts, option),
 					});
 					if payment_hash.is_none() {
 						payment_hash = Some(PaymentHash(Sha256::hash(&payment_preimage.0[..]).into_inner()));
@@ -1117,6 +2320,7 @@ impl MaybeReadable for Event {
 						payment_preimage,
 						payment_hash: payment_hash.unwrap(),
 						fee_paid_msat,
+						attempts,
 					}))
 				};
 				f()
@@ -1135,6 +2339,10 @@ impl MaybeReadable for Event {
 					let mut short_channel_id = None;
 					let mut payment_id = None;
 					let mut failure_opt = None;
+					let mut hold_times: Option<Vec<u32>> = Some(vec![]);
+					let mut failure_code: Option<u16> = None;
+					let mut failing_node_id = None;
+					let mut attempts = None;
 					read_tlv_fields!(reader, {
 						(0, payment_hash, required),
 						(1, network_update, upgradable_option),
@@ -1144,6 +2352,10 @@ impl MaybeReadable for Event {
 						(7, short_channel_id, option),
 						(11, payment_id, option),
 						(13, failure_opt, upgradable_option),
+						(15, hold_times, optional_vec),
+						(17, failure_code, option),
+						(19, failing_node_id, option),
+						(21, attempts, option),
 					});
 					let failure = failure_opt.unwrap_or_else(|| PathFailure::OnPath { network_update });
 					Ok(Some(Event::PaymentPathFailed {
@@ -1153,6 +2365,10 @@ impl MaybeReadable for Event {
 						failure,
 						path: Path { hops: path.unwrap(), blinded_tail },
 						short_channel_id,
+						hold_times: hold_times.unwrap(),
+						failure_reason: failure_code.map(FailureReason::from),
+						failing_node_id,
+						attempts,
 						#[cfg(test)]
 						error_code,
 						#[cfg(test)]
@@ -1223,11 +2439,13 @@ impl MaybeReadable for Event {
 					let mut reason = UpgradableRequired(None);
 					let mut user_channel_id_low_opt: Option<u64> = None;
 					let mut user_channel_id_high_opt: Option<u64> = None;
+					let mut force_close_feerates = None;
 					read_tlv_fields!(reader, {
 						(0, channel_id, required),
 						(1, user_channel_id_low_opt, option),
 						(2, reason, upgradable_required),
 						(3, user_channel_id_high_opt, option),
+						(5, force_close_feerates, option),
 					});
 
 					// `user_channel_id` used to be a single u64 value. In order to remain
@@ -1236,7 +2454,10 @@ impl MaybeReadable for Event {
 					let user_channel_id = (user_channel_id_low_opt.unwrap_or(0) as u128) +
 						((user_channel_id_high_opt.unwrap_or(0) as u128) << 64);
 
-					Ok(Some(Event::ChannelClosed { channel_id, user_channel_id, reason: _init_tlv_based_struct_field!(reason, upgradable_required) }))
+					Ok(Some(Event::ChannelClosed {
+						channel_id, user_channel_id, reason: _init_tlv_based_struct_field!(reason, upgradable_required),
+						force_close_feerates,
+					}))
 				};
 				f()
 			},
@@ -1273,15 +2494,18 @@ impl MaybeReadable for Event {
 					let mut payment_hash = PaymentHash([0; 32]);
 					let mut payment_id = PaymentId([0; 32]);
 					let mut reason = None;
+					let mut attempts = None;
 					read_tlv_fields!(reader, {
 						(0, payment_id, required),
 						(1, reason, upgradable_option),
 						(2, payment_hash, required),
+						(3, attempts, option),
 					});
 					Ok(Some(Event::PaymentFailed {
 						payment_id,
 						payment_hash,
 						reason,
+						attempts,
 					}))
 				};
 				f()
@@ -1408,6 +2632,436 @@ impl MaybeReadable for Event {
 				};
 				f()
 			},
+			33u8 => {
+				let f = || {
+					let mut channel_id = [0; 32];
+					let mut outpoints = WithoutLength(Vec::new());
+					let mut deadline_height = 0;
+					let mut feerate_sat_per_1000_weight = 0;
+					read_tlv_fields!(reader, {
+						(0, channel_id, required),
+						(2, outpoints, required),
+						(4, deadline_height, required),
+						(6, feerate_sat_per_1000_weight, required),
+					});
+
+					Ok(Some(Event::ClaimAtRisk {
+						channel_id,
+						outpoints: outpoints.0,
+						deadline_height,
+						feerate_sat_per_1000_weight,
+					}))
+				};
+				f()
+			},
+			35u8 => {
+				let f = || {
+					let mut encrypted_backup = Vec::new();
+					read_tlv_fields!(reader, {
+						(0, encrypted_backup, required),
+					});
+					Ok(Some(Event::StaticBackupUpdated { encrypted_backup }))
+				};
+				f()
+			},
+			37u8 => {
+				let f = || {
+					let mut payment_hash = PaymentHash([0; 32]);
+					read_tlv_fields!(reader, {
+						(0, payment_hash, required),
+					});
+					Ok(Some(Event::InboundPaymentExpired { payment_hash }))
+				};
+				f()
+			},
+			39u8 => {
+				let f = || {
+					let mut payment_hash = PaymentHash([0; 32]);
+					let mut received_msat = 0;
+					let mut total_msat = 0;
+					read_tlv_fields!(reader, {
+						(0, payment_hash, required),
+						(2, received_msat, required),
+						(4, total_msat, required),
+					});
+					Ok(Some(Event::PartialPaymentTimedOut { payment_hash, received_msat, total_msat }))
+				};
+				f()
+			},
+			41u8 => {
+				let f = || {
+					let mut node_id = RequiredWrapper(None);
+					let mut features = RequiredWrapper(None);
+					let mut address = None;
+					let mut inbound = false;
+					read_tlv_fields!(reader, {
+						(0, node_id, required),
+						(2, features, required),
+						(4, address, option),
+						(6, inbound, required),
+					});
+					Ok(Some(Event::PeerConnected {
+						node_id: node_id.0.unwrap(),
+						features: features.0.unwrap(),
+						address,
+						inbound,
+					}))
+				};
+				f()
+			},
+			43u8 => {
+				let f = || {
+					let mut node_id = RequiredWrapper(None);
+					let mut reason = UpgradableRequired(None);
+					read_tlv_fields!(reader, {
+						(0, node_id, required),
+						(2, reason, upgradable_required),
+					});
+					Ok(Some(Event::PeerDisconnected {
+						node_id: node_id.0.unwrap(),
+						reason: _init_tlv_based_struct_field!(reason, upgradable_required),
+					}))
+				};
+				f()
+			},
+			45u8 => {
+				let f = || {
+					let mut counterparty_node_id = RequiredWrapper(None);
+					let mut encrypted_backup = Vec::new();
+					read_tlv_fields!(reader, {
+						(0, counterparty_node_id, required),
+						(2, encrypted_backup, required),
+					});
+					Ok(Some(Event::StaticBackupRetrieved {
+						counterparty_node_id: counterparty_node_id.0.unwrap(),
+						encrypted_backup,
+					}))
+				};
+				f()
+			},
+			47u8 => {
+				let f = || {
+					let mut channel_id = [0; 32];
+					let mut counterparty_node_id = RequiredWrapper(None);
+					let mut funding_txo = RequiredWrapper(None);
+					let mut user_channel_id: u128 = 0;
+					read_tlv_fields!(reader, {
+						(0, channel_id, required),
+						(2, counterparty_node_id, required),
+						(4, funding_txo, required),
+						(6, user_channel_id, required),
+					});
+					Ok(Some(Event::StaleChannelState {
+						channel_id,
+						counterparty_node_id: counterparty_node_id.0.unwrap(),
+						funding_txo: funding_txo.0.unwrap(),
+						user_channel_id,
+					}))
+				};
+				f()
+			},
+			49u8 => {
+				let f = || {
+					let mut payment_id = PaymentId([0; 32]);
+					let mut payment_hash = PaymentHash([0; 32]);
+					let mut failed_short_channel_ids: Option<Vec<u64>> = Some(vec![]);
+					let mut short_channel_ids: Option<Vec<u64>> = Some(vec![]);
+					read_tlv_fields!(reader, {
+						(0, payment_id, required),
+						(2, payment_hash, required),
+						(4, failed_short_channel_ids, optional_vec),
+						(6, short_channel_ids, optional_vec),
+					});
+					Ok(Some(Event::PaymentFirstHopSubstituted {
+						payment_id,
+						payment_hash,
+						failed_short_channel_ids: failed_short_channel_ids.unwrap(),
+						short_channel_ids: short_channel_ids.unwrap(),
+					}))
+				};
+				f()
+			},
+			51u8 => {
+				let f = || {
+					let mut channel_id = [0; 32];
+					let mut counterparty_node_id = RequiredWrapper(None);
+					let mut user_channel_id: u128 = 0;
+					let mut reason = RequiredWrapper(None);
+					let mut force_close_after_time = None;
+					read_tlv_fields!(reader, {
+						(0, channel_id, required),
+						(2, counterparty_node_id, required),
+						(4, user_channel_id, required),
+						(6, reason, required),
+						(8, force_close_after_time, option),
+					});
+					Ok(Some(Event::ChannelStale {
+						channel_id,
+						counterparty_node_id: counterparty_node_id.0.unwrap(),
+						user_channel_id,
+						reason: reason.0.unwrap(),
+						force_close_after_time,
+					}))
+				};
+				f()
+			},
+			53u8 => {
+				let f = || {
+					let mut batch_id = BatchPaymentId([0; 32]);
+					let mut successful_payment_ids: Option<Vec<PaymentId>> = Some(vec![]);
+					let mut failed_payment_ids: Option<Vec<PaymentId>> = Some(vec![]);
+					read_tlv_fields!(reader, {
+						(0, batch_id, required),
+						(2, successful_payment_ids, optional_vec),
+						(4, failed_payment_ids, optional_vec),
+					});
+					Ok(Some(Event::BatchPaymentCompleted {
+						batch_id,
+						successful_payment_ids: successful_payment_ids.unwrap(),
+						failed_payment_ids: failed_payment_ids.unwrap(),
+					}))
+				};
+				f()
+			},
+			55u8 => {
+				let f = || {
+					let mut best_block_height: u32 = 0;
+					let mut estimated_chain_tip_height: u32 = 0;
+					let mut earliest_htlc_expiry_height: u32 = 0;
+					read_tlv_fields!(reader, {
+						(0, best_block_height, required),
+						(2, estimated_chain_tip_height, required),
+						(4, earliest_htlc_expiry_height, required),
+					});
+					Ok(Some(Event::ChainSyncLagging {
+						best_block_height,
+						estimated_chain_tip_height,
+						earliest_htlc_expiry_height,
+					}))
+				};
+				f()
+			},
+			57u8 => {
+				let f = || {
+					let mut channel_id = [0; 32];
+					let mut counterparty_node_id = RequiredWrapper(None);
+					let mut htlc_amount_msat: u64 = 0;
+					let mut next_outbound_htlc_limit_msat: u64 = 0;
+					read_tlv_fields!(reader, {
+						(0, channel_id, required),
+						(2, counterparty_node_id, required),
+						(4, htlc_amount_msat, required),
+						(6, next_outbound_htlc_limit_msat, required),
+					});
+					Ok(Some(Event::HTLCRejectedForFeeSpikeBuffer {
+						channel_id,
+						counterparty_node_id: counterparty_node_id.0.unwrap(),
+						htlc_amount_msat,
+						next_outbound_htlc_limit_msat,
+					}))
+				};
+				f()
+			},
+			59u8 => {
+				let f = || {
+					let mut payment_id = PaymentId([0; 32]);
+					let mut invoice: RequiredWrapper<WithoutLength<Vec<u8>>> = RequiredWrapper(None);
+					let mut awaiting_approval = false;
+					let mut invoice_request: Option<WithoutLength<Vec<u8>>> = None;
+					let mut refund: Option<WithoutLength<Vec<u8>>> = None;
+					read_tlv_fields!(reader, {
+						(0, payment_id, required),
+						(2, invoice, required),
+						(4, awaiting_approval, required),
+						(5, invoice_request, option),
+						(7, refund, option),
+					});
+					let invoice = Invoice::try_from(invoice.0.unwrap().0)
+						.map_err(|_| msgs::DecodeError::InvalidValue)?;
+					let payment_context = match (invoice_request, refund) {
+						(Some(invoice_request), None) => Bolt12PaymentContext::InvoiceRequest(
+							InvoiceRequest::try_from(invoice_request.0).map_err(|_| msgs::DecodeError::InvalidValue)?
+						),
+						(None, Some(refund)) => Bolt12PaymentContext::Refund(
+							Refund::try_from(refund.0).map_err(|_| msgs::DecodeError::InvalidValue)?
+						),
+						_ => return Err(msgs::DecodeError::InvalidValue),
+					};
+					Ok(Some(Event::Bolt12InvoiceReceived {
+						payment_id,
+						invoice,
+						payment_context,
+						awaiting_approval,
+					}))
+				};
+				f()
+			},
+			61u8 => {
+				let f = || {
+					read_tlv_fields!(reader, {});
+					Ok(Some(Event::NodeQuiescent))
+				};
+				f()
+			},
+			63u8 => {
+				let f = || {
+					let mut intercept_id = RequiredWrapper(None);
+					let mut requested_next_hop_scid = RequiredWrapper(None);
+					let mut payment_hash = RequiredWrapper(None);
+					let mut auto_fail_height = RequiredWrapper(None);
+					read_tlv_fields!(reader, {
+						(0, intercept_id, required),
+						(2, requested_next_hop_scid, required),
+						(4, payment_hash, required),
+						(6, auto_fail_height, required),
+					});
+					Ok(Some(Event::HTLCInterceptExpiring {
+						intercept_id: intercept_id.0.unwrap(),
+						requested_next_hop_scid: requested_next_hop_scid.0.unwrap(),
+						payment_hash: payment_hash.0.unwrap(),
+						auto_fail_height: auto_fail_height.0.unwrap(),
+					}))
+				};
+				f()
+			},
+			65u8 => {
+				let f = || {
+					let mut channel_id = [0; 32];
+					let mut counterparty_node_id = RequiredWrapper(None);
+					let mut user_channel_id: u128 = 0;
+					let mut funding_txo = RequiredWrapper(None);
+					let mut reason = UpgradableRequired(None);
+					read_tlv_fields!(reader, {
+						(0, channel_id, required),
+						(2, counterparty_node_id, required),
+						(4, user_channel_id, required),
+						(6, funding_txo, required),
+						(8, reason, upgradable_required),
+					});
+					Ok(Some(Event::FundingTransactionDropped {
+						channel_id,
+						counterparty_node_id: counterparty_node_id.0.unwrap(),
+						user_channel_id,
+						funding_txo: funding_txo.0.unwrap(),
+						reason: _init_tlv_based_struct_field!(reason, upgradable_required),
+					}))
+				};
+				f()
+			},
+			67u8 => {
+				let f = || {
+					let mut channel_id = [0; 32];
+					let mut counterparty_node_id = RequiredWrapper(None);
+					let mut user_channel_id: u128 = 0;
+					let mut timestamp = 0;
+					read_tlv_fields!(reader, {
+						(0, channel_id, required),
+						(2, counterparty_node_id, required),
+						(4, user_channel_id, required),
+						(6, timestamp, required),
+					});
+					Ok(Some(Event::ChannelDisabledByCounterparty {
+						channel_id,
+						counterparty_node_id: counterparty_node_id.0.unwrap(),
+						user_channel_id,
+						timestamp,
+					}))
+				};
+				f()
+			},
+			69u8 => {
+				let f = || {
+					_init_and_read_tlv_fields!(reader, {
+						(0, channel_id, required),
+						(2, counterparty_node_id, required),
+						(4, message, required),
+						(6, custom_tlvs, vec_type),
+					});
+					Ok(Some(Event::CustomMessageTlvsReceived {
+						channel_id: channel_id.0.unwrap(),
+						counterparty_node_id: counterparty_node_id.0.unwrap(),
+						message: message.0.unwrap(),
+						custom_tlvs: custom_tlvs.unwrap(),
+					}))
+				};
+				f()
+			},
+			71u8 => {
+				let f = || {
+					_init_and_read_tlv_fields!(reader, {
+						(0, channel_id, required),
+						(2, counterparty_node_id, required),
+						(4, user_channel_id, required),
+						(6, progress, upgradable_required),
+					});
+					Ok(Some(Event::ChannelProgressed {
+						channel_id: channel_id.0.unwrap(),
+						counterparty_node_id: counterparty_node_id.0.unwrap(),
+						user_channel_id: user_channel_id.0.unwrap(),
+						progress: _init_tlv_based_struct_field!(progress, upgradable_required),
+					}))
+				};
+				f()
+			},
+			73u8 => {
+				let f = || {
+					_init_and_read_tlv_fields!(reader, {
+						(0, channel_id, required),
+						(2, counterparty_node_id, required),
+						(4, htlc_id, required),
+						(6, direction, required),
+						(8, payment_hash, required),
+						(10, amount_msat, required),
+						(12, cltv_expiry, required),
+						(14, severity, required),
+					});
+					Ok(Some(Event::HTLCExpiryApproaching {
+						channel_id: channel_id.0.unwrap(),
+						counterparty_node_id: counterparty_node_id.0.unwrap(),
+						htlc_id: htlc_id.0.unwrap(),
+						direction: direction.0.unwrap(),
+						payment_hash: payment_hash.0.unwrap(),
+						amount_msat: amount_msat.0.unwrap(),
+						cltv_expiry: cltv_expiry.0.unwrap(),
+						severity: severity.0.unwrap(),
+					}))
+				};
+				f()
+			},
+			75u8 => {
+				let f = || {
+					_init_and_read_tlv_fields!(reader, {
+						(0, channel_id, required),
+						(2, counterparty_node_id, required),
+						(4, feerate_per_kw, required),
+						(6, grace_period_ticks, required),
+					});
+					Ok(Some(Event::CounterpartyFeerateDisagreement {
+						channel_id: channel_id.0.unwrap(),
+						counterparty_node_id: counterparty_node_id.0.unwrap(),
+						feerate_per_kw: feerate_per_kw.0.unwrap(),
+						grace_period_ticks: grace_period_ticks.0.unwrap(),
+					}))
+				};
+				f()
+			},
+			77u8 => {
+				let f = || {
+					_init_and_read_tlv_fields!(reader, {
+						(0, prev_channel_id, required),
+						(2, payment_hash, required),
+						(4, is_introduction_node, required),
+						(6, reason, upgradable_required),
+					});
+					Ok(Some(Event::BlindedForwardRejected {
+						prev_channel_id: prev_channel_id.0.unwrap(),
+						payment_hash: payment_hash.0.unwrap(),
+						is_introduction_node: is_introduction_node.0.unwrap(),
+						reason: _init_tlv_based_struct_field!(reason, upgradable_required),
+					}))
+				};
+				f()
+			},
 			// Versions prior to 0.0.100 did not ignore odd types, instead returning InvalidValue.
 			// Version 0.0.100 failed to properly ignore odd types, possibly resulting in corrupt
 			// reads.
@@ -1577,6 +3231,22 @@ pub enum MessageSendEvent {
 		/// The message which should be sent.
 		msg: msgs::ClosingSigned,
 	},
+	/// Used to indicate that a closing_complete message should be sent to the peer with the given
+	/// node_id.
+	SendClosingComplete {
+		/// The node_id of the node which should receive this message
+		node_id: PublicKey,
+		/// The message which should be sent.
+		msg: msgs::ClosingComplete,
+	},
+	/// Used to indicate that a closing_sig message should be sent to the peer with the given
+	/// node_id.
+	SendClosingSig {
+		/// The node_id of the node which should receive this message
+		node_id: PublicKey,
+		/// The message which should be sent.
+		msg: msgs::ClosingSig,
+	},
 	/// Used to indicate that a shutdown message should be sent to the peer with the given node_id.
 	SendShutdown {
 		/// The node_id of the node which should receive this message
@@ -1674,6 +3344,22 @@ pub enum MessageSendEvent {
 		/// The gossip_timestamp_filter which should be sent.
 		msg: msgs::GossipTimestampFilter,
 	},
+	/// Used to indicate that a peer_storage message should be sent to the peer with the given
+	/// node_id.
+	SendPeerStorage {
+		/// The node_id of the node which should receive this message
+		node_id: PublicKey,
+		/// The message which should be sent.
+		msg: msgs::PeerStorage,
+	},
+	/// Used to indicate that a your_peer_storage message should be sent to the peer with the given
+	/// node_id.
+	SendYourPeerStorage {
+		/// The node_id of the node which should receive this message
+		node_id: PublicKey,
+		/// The message which should be sent.
+		msg: msgs::YourPeerStorage,
+	},
 }
 
 /// A trait indicating an object may generate message send events
@@ -1687,6 +3373,15 @@ pub trait MessageSendEventsProvider {
 pub trait OnionMessageProvider {
 	/// Gets the next pending onion message for the peer with the given node id.
 	fn next_onion_message_for_peer(&self, peer_node_id: PublicKey) -> Option<msgs::OnionMessage>;
+
+	/// Returns true if there may be onion messages pending for any peer.
+	///
+	/// Used by [`PeerManager::process_events`] to skip polling peers which otherwise have
+	/// nothing to send. Implementations which cannot cheaply answer this may rely on the
+	/// default, which always returns true.
+	///
+	/// [`PeerManager::process_events`]: crate::ln::peer_handler::PeerManager::process_events
+	fn has_pending_onion_messages(&self) -> bool { true }
 }
 
 /// A trait indicating an object may generate events.
@@ -170,12 +170,19 @@ pub fn build_commitment_secret(commitment_seed: &[u8; 32], idx: u64) -> [u8; 32]
 
 /// Build a closing transaction
 pub fn build_closing_transaction(to_holder_value_sat: u64, to_counterparty_value_sat: u64, to_holder_script: Script, to_counterparty_script: Script, funding_outpoint: OutPoint) -> Transaction {
+	build_closing_transaction_with_locktime(to_holder_value_sat, to_counterparty_value_sat, to_holder_script, to_counterparty_script, funding_outpoint, None)
+}
+
+/// Builds a closing transaction as [`build_closing_transaction`] does, but if a `locktime` is
+/// provided (as for closing transactions negotiated via `option_simple_close`), the transaction
+/// uses it as its locktime and signals replaceability.
+fn build_closing_transaction_with_locktime(to_holder_value_sat: u64, to_counterparty_value_sat: u64, to_holder_script: Script, to_counterparty_script: Script, funding_outpoint: OutPoint, locktime: Option<u32>) -> Transaction {
 	let txins = {
 		let mut ins: Vec<TxIn> = Vec::new();
 		ins.push(TxIn {
 			previous_output: funding_outpoint,
 			script_sig: Script::new(),
-			sequence: Sequence::MAX,
+			sequence: if locktime.is_some() { Sequence::ENABLE_RBF_NO_LOCKTIME } else { Sequence::MAX },
 			witness: Witness::new(),
 		});
 		ins
@@ -206,7 +213,7 @@ pub fn build_closing_transaction(to_holder_value_sat: u64, to_counterparty_value
 
 	Transaction {
 		version: 2,
-		lock_time: PackedLockTime::ZERO,
+		lock_time: locktime.map(PackedLockTime).unwrap_or(PackedLockTime::ZERO),
 		input: txins,
 		output: outputs,
 	}
@@ -573,6 +580,59 @@ impl_writeable_tlv_based!(HTLCOutputInCommitment, {
 	(8, transaction_output_index, option),
 });
 
+/// The lock of an HTLC, i.e., what the HTLC's recipient has to reveal in order to claim it.
+///
+/// Adaptor point locks are experimental groundwork for point time-locked contracts (PTLCs) and
+/// only available with `--cfg=ptlc`. PTLCs are not yet supported by the protocol, thus all HTLCs
+/// are currently locked to a payment hash.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HTLCLock {
+	/// Claimable by revealing the preimage of the payment hash.
+	PaymentHash(PaymentHash),
+	/// Claimable by revealing the discrete logarithm of the point, e.g., by completing an adaptor
+	/// signature.
+	#[cfg(ptlc)]
+	AdaptorPoint(PublicKey),
+}
+
+impl HTLCLock {
+	/// Returns the payment hash of a hash lock.
+	pub fn payment_hash(&self) -> Option<PaymentHash> {
+		match self {
+			HTLCLock::PaymentHash(payment_hash) => Some(*payment_hash),
+			#[cfg(ptlc)]
+			HTLCLock::AdaptorPoint(_) => None,
+		}
+	}
+
+	/// Returns whether `preimage` unlocks a hash lock. Always false for adaptor point locks.
+	pub fn is_unlocked_by_preimage(&self, preimage: &PaymentPreimage) -> bool {
+		match self {
+			HTLCLock::PaymentHash(payment_hash) =>
+				PaymentHash(Sha256::hash(&preimage.0[..]).into_inner()) == *payment_hash,
+			#[cfg(ptlc)]
+			HTLCLock::AdaptorPoint(_) => false,
+		}
+	}
+
+	/// Returns whether `secret` is the discrete logarithm of an adaptor point lock. Always false
+	/// for hash locks.
+	#[cfg(ptlc)]
+	pub fn is_unlocked_by_secret<T: secp256k1::Signing>(&self, secp_ctx: &Secp256k1<T>, secret: &SecretKey) -> bool {
+		match self {
+			HTLCLock::PaymentHash(_) => false,
+			HTLCLock::AdaptorPoint(point) => PublicKey::from_secret_key(secp_ctx, secret) == *point,
+		}
+	}
+}
+
+impl HTLCOutputInCommitment {
+	/// The lock of the HTLC, which is currently always [`HTLCLock::PaymentHash`].
+	pub fn lock(&self) -> HTLCLock {
+		HTLCLock::PaymentHash(self.payment_hash)
+	}
+}
+
 #[inline]
 pub(crate) fn get_htlc_redeemscript_with_explicit_keys(htlc: &HTLCOutputInCommitment, opt_anchors: bool, broadcaster_htlc_key: &PublicKey, countersignatory_htlc_key: &PublicKey, revocation_key: &PublicKey) -> Script {
 	let payment_hash160 = Ripemd160::hash(&htlc.payment_hash.0[..]).into_inner();
@@ -675,6 +735,74 @@ pub(crate) fn make_funding_redeemscript_from_slices(broadcaster_funding_key: &[u
 	}.push_opcode(opcodes::all::OP_PUSHNUM_2).push_opcode(opcodes::all::OP_CHECKMULTISIG).into_script()
 }
 
+/// Computes the coefficient each of the given keys is multiplied with when aggregating them, in
+/// the given order, as specified by MuSig2's `KeyAgg` algorithm (BIP 327).
+///
+/// Returns `None` for the astronomically unlikely case of a coefficient not being a valid scalar.
+#[cfg(taproot)]
+pub(crate) fn musig2_key_agg_coefficients(keys: &[PublicKey]) -> Option<Vec<Scalar>> {
+	fn tagged_hash_engine(tag: &str) -> bitcoin::hashes::sha256::HashEngine {
+		let tag_hash = Sha256::hash(tag.as_bytes());
+		let mut engine = Sha256::engine();
+		engine.input(&tag_hash[..]);
+		engine.input(&tag_hash[..]);
+		engine
+	}
+
+	let mut list_engine = tagged_hash_engine("KeyAgg list");
+	for key in keys {
+		list_engine.input(&key.serialize());
+	}
+	let list_hash = Sha256::from_engine(list_engine);
+
+	// The second distinct key is added untweaked, which allows for a faster aggregation.
+	let second_key = keys.first().and_then(|first_key| keys.iter().find(|key| *key != first_key));
+	let mut coefficients = Vec::with_capacity(keys.len());
+	for key in keys {
+		if Some(key) == second_key {
+			coefficients.push(Scalar::ONE);
+			continue;
+		}
+		let mut coefficient_engine = tagged_hash_engine("KeyAgg coefficient");
+		coefficient_engine.input(&list_hash[..]);
+		coefficient_engine.input(&key.serialize());
+		coefficients.push(Scalar::from_be_bytes(Sha256::from_engine(coefficient_engine).into_inner()).ok()?);
+	}
+	Some(coefficients)
+}
+
+/// Aggregates the given keys, in the given order, into a single key as specified by MuSig2's
+/// `KeyAgg` algorithm (BIP 327).
+///
+/// Returns `None` if `keys` is empty or the aggregate key is invalid, which only happens for keys
+/// chosen maliciously.
+#[cfg(taproot)]
+pub(crate) fn aggregate_musig2_keys<C: secp256k1::Verification>(
+	secp_ctx: &Secp256k1<C>, keys: &[PublicKey]
+) -> Option<PublicKey> {
+	let coefficients = musig2_key_agg_coefficients(keys)?;
+	let mut tweaked_keys = Vec::with_capacity(keys.len());
+	for (key, coefficient) in keys.iter().zip(coefficients.iter()) {
+		tweaked_keys.push(key.mul_tweak(secp_ctx, coefficient).ok()?);
+	}
+	PublicKey::combine_keys(&tweaked_keys.iter().collect::<Vec<_>>()).ok()
+}
+
+/// Gets the script of the taproot funding output of a simple taproot channel with the given
+/// funding keys, i.e. a key-path-only P2TR output to the MuSig2 aggregate of the keys sorted
+/// lexicographically.
+///
+/// Returns `None` if the keys are such that no valid aggregate key exists.
+#[cfg(taproot)]
+pub(crate) fn make_taproot_funding_script<C: secp256k1::Verification>(
+	secp_ctx: &Secp256k1<C>, funding_key_1: &PublicKey, funding_key_2: &PublicKey
+) -> Option<Script> {
+	let mut keys = [*funding_key_1, *funding_key_2];
+	keys.sort_unstable_by(|a, b| a.serialize().cmp(&b.serialize()));
+	let aggregate_key = aggregate_musig2_keys(secp_ctx, &keys)?;
+	Some(Script::new_v1_p2tr(secp_ctx, aggregate_key.x_only_public_key().0, None))
+}
+
 /// Builds an unsigned HTLC-Success or HTLC-Timeout transaction from the given channel and HTLC
 /// parameters. This is used by [`TrustedCommitmentTransaction::get_htlc_sigs`] to fetch the
 /// transaction which needs signing, and can be used to construct an HTLC transaction which is
@@ -1109,6 +1237,7 @@ pub struct ClosingTransaction {
 	to_counterparty_value_sat: u64,
 	to_holder_script: Script,
 	to_counterparty_script: Script,
+	locktime: Option<u32>,
 	built: Transaction,
 }
 
@@ -1121,16 +1250,43 @@ impl ClosingTransaction {
 		to_counterparty_script: Script,
 		funding_outpoint: OutPoint,
 	) -> Self {
-		let built = build_closing_transaction(
+		Self::new_with_locktime(to_holder_value_sat, to_counterparty_value_sat, to_holder_script,
+			to_counterparty_script, funding_outpoint, None)
+	}
+
+	/// Construct an object of the class for a closing transaction negotiated via
+	/// `option_simple_close`, which has the given locktime and signals replaceability.
+	pub fn new_simple_close(
+		to_holder_value_sat: u64,
+		to_counterparty_value_sat: u64,
+		to_holder_script: Script,
+		to_counterparty_script: Script,
+		funding_outpoint: OutPoint,
+		locktime: u32,
+	) -> Self {
+		Self::new_with_locktime(to_holder_value_sat, to_counterparty_value_sat, to_holder_script,
+			to_counterparty_script, funding_outpoint, Some(locktime))
+	}
+
+	fn new_with_locktime(
+		to_holder_value_sat: u64,
+		to_counterparty_value_sat: u64,
+		to_holder_script: Script,
+		to_counterparty_script: Script,
+		funding_outpoint: OutPoint,
+		locktime: Option<u32>,
+	) -> Self {
+		let built = build_closing_transaction_with_locktime(
 			to_holder_value_sat, to_counterparty_value_sat,
 			to_holder_script.clone(), to_counterparty_script.clone(),
-			funding_outpoint
+			funding_outpoint, locktime
 		);
 		ClosingTransaction {
 			to_holder_value_sat,
 			to_counterparty_value_sat,
 			to_holder_script,
 			to_counterparty_script,
+			locktime,
 			built
 		}
 	}
@@ -1152,10 +1308,10 @@ impl ClosingTransaction {
 	/// An external validating signer must call this method before signing
 	/// or using the built transaction.
 	pub fn verify(&self, funding_outpoint: OutPoint) -> Result<TrustedClosingTransaction, ()> {
-		let built = build_closing_transaction(
+		let built = build_closing_transaction_with_locktime(
 			self.to_holder_value_sat, self.to_counterparty_value_sat,
 			self.to_holder_script.clone(), self.to_counterparty_script.clone(),
-			funding_outpoint
+			funding_outpoint, self.locktime
 		);
 		if self.built != built {
 			return Err(())
@@ -1182,6 +1338,12 @@ impl ClosingTransaction {
 	pub fn to_counterparty_script(&self) -> &Script {
 		&self.to_counterparty_script
 	}
+
+	/// The locktime of a closing transaction negotiated via `option_simple_close`, or `None` for
+	/// a legacy closing transaction
+	pub fn locktime(&self) -> Option<u32> {
+		self.locktime
+	}
 }
 
 /// A wrapper on ClosingTransaction indicating that the built bitcoin
@@ -1791,6 +1953,34 @@ mod tests {
 				   "002087a3faeb1950a469c0e2db4a79b093a41b9526e5a6fc6ef5cb949bde3be379c7");
 	}
 
+	#[cfg(ptlc)]
+	#[test]
+	fn test_htlc_lock() {
+		use super::HTLCLock;
+		use crate::ln::PaymentPreimage;
+		use bitcoin::hashes::sha256::Hash as Sha256;
+
+		let secp_ctx = Secp256k1::new();
+		let preimage = PaymentPreimage([42; 32]);
+		let hash_lock = HTLCLock::PaymentHash(PaymentHash(Sha256::hash(&preimage.0).into_inner()));
+		assert!(hash_lock.is_unlocked_by_preimage(&preimage));
+		assert!(!hash_lock.is_unlocked_by_preimage(&PaymentPreimage([43; 32])));
+
+		let secret = SecretKey::from_slice(&[42; 32]).unwrap();
+		let point_lock = HTLCLock::AdaptorPoint(PublicKey::from_secret_key(&secp_ctx, &secret));
+		assert!(point_lock.is_unlocked_by_secret(&secp_ctx, &secret));
+		assert!(!point_lock.is_unlocked_by_secret(&secp_ctx, &SecretKey::from_slice(&[43; 32]).unwrap()));
+		assert!(!point_lock.is_unlocked_by_preimage(&preimage));
+		assert!(!hash_lock.is_unlocked_by_secret(&secp_ctx, &secret));
+		assert_eq!(point_lock.payment_hash(), None);
+
+		let htlc = HTLCOutputInCommitment {
+			offered: true, amount_msat: 1000, cltv_expiry: 100, payment_hash: hash_lock.payment_hash().unwrap(),
+			transaction_output_index: None,
+		};
+		assert_eq!(htlc.lock(), hash_lock);
+	}
+
 	#[test]
 	fn test_per_commitment_storage() {
 		// Test vectors from BOLT 3:
@@ -2135,4 +2325,32 @@ mod tests {
 			assert!(monitor.provide_secret(281474976710648, secrets.last().unwrap().clone()).is_err());
 		}
 	}
+
+	#[test]
+	#[cfg(taproot)]
+	fn test_musig2_key_aggregation() {
+		// Test vectors from BIP 327's `key_agg_vectors.json`.
+		let secp_ctx = Secp256k1::new();
+		let keys = [
+			"02F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9",
+			"03DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+			"023590A94E768F8E1815C2F24B4D80A8E3149316C3518CE7B7AD338368D038CA66",
+		].iter().map(|key| PublicKey::from_slice(&hex::decode(key).unwrap()).unwrap()).collect::<Vec<_>>();
+
+		let aggregate_key = super::aggregate_musig2_keys(&secp_ctx, &keys).unwrap();
+		assert_eq!(aggregate_key.x_only_public_key().0.serialize().to_vec(),
+			hex::decode("90539EEDE565F5D054F32CC0C220126889ED1E5D193BAF15AEF344FE59D4610C").unwrap());
+
+		let reversed_keys = [keys[2], keys[1], keys[0]];
+		let aggregate_key = super::aggregate_musig2_keys(&secp_ctx, &reversed_keys).unwrap();
+		assert_eq!(aggregate_key.x_only_public_key().0.serialize().to_vec(),
+			hex::decode("6204DE8B083426DC6EAF9502D27024D53FC826BF7D2012148A0575435DF54B2B").unwrap());
+
+		let same_keys = [keys[0], keys[0], keys[0]];
+		let aggregate_key = super::aggregate_musig2_keys(&secp_ctx, &same_keys).unwrap();
+		assert_eq!(aggregate_key.x_only_public_key().0.serialize().to_vec(),
+			hex::decode("B436E3BAD62B8CD409969A224731C193D051162D8C5AE8B109306127DA3AA935").unwrap());
+
+		assert!(super::aggregate_musig2_keys(&secp_ctx, &[]).is_none());
+	}
 }
@@ -13,9 +13,8 @@ use bitcoin::util::sighash;
 use bitcoin::consensus::encode;
 
 use bitcoin::hashes::Hash;
-use bitcoin::hashes::sha256::Hash as Sha256;
 use bitcoin::hashes::sha256d::Hash as Sha256d;
-use bitcoin::hash_types::{Txid, BlockHash};
+use bitcoin::hash_types::{Txid, BlockHash, WPubkeyHash};
 
 use bitcoin::secp256k1::constants::PUBLIC_KEY_SIZE;
 use bitcoin::secp256k1::{PublicKey,SecretKey};
@@ -27,21 +26,23 @@ use crate::ln::features::{ChannelTypeFeatures, InitFeatures};
 use crate::ln::msgs;
 use crate::ln::msgs::DecodeError;
 use crate::ln::script::{self, ShutdownScript};
-use crate::ln::channelmanager::{self, CounterpartyForwardingInfo, PendingHTLCStatus, HTLCSource, SentHTLCId, HTLCFailureMsg, PendingHTLCInfo, RAACommitmentOrder, BREAKDOWN_TIMEOUT, MIN_CLTV_EXPIRY_DELTA, MAX_LOCAL_BREAKDOWN_TIMEOUT};
+use crate::ln::channelmanager::{self, CounterpartyChannelUpdate, CounterpartyForwardingInfo, PendingHTLCStatus, HTLCSource, SentHTLCId, HTLCFailureMsg, PendingHTLCInfo, RAACommitmentOrder, PendingHTLCDetails, PendingHTLCDirection, PendingHTLCState, BREAKDOWN_TIMEOUT, MIN_CLTV_EXPIRY_DELTA, MAX_LOCAL_BREAKDOWN_TIMEOUT, MAX_SCID_ALIASES_PER_CHANNEL};
 use crate::ln::chan_utils::{CounterpartyCommitmentSecrets, TxCreationKeys, HTLCOutputInCommitment, htlc_success_tx_weight, htlc_timeout_tx_weight, make_funding_redeemscript, ChannelPublicKeys, CommitmentTransaction, HolderCommitmentTransaction, ChannelTransactionParameters, CounterpartyChannelTransactionParameters, MAX_HTLCS, get_commitment_transaction_number_obscure_factor, ClosingTransaction};
 use crate::ln::chan_utils;
+use crate::ln::chan_utils::HTLCLock;
 use crate::ln::onion_utils::HTLCFailReason;
+use crate::ln::static_backup::StaticChannelBackup;
 use crate::chain::BestBlock;
 use crate::chain::chaininterface::{FeeEstimator, ConfirmationTarget, LowerBoundedFeeEstimator};
 use crate::chain::channelmonitor::{ChannelMonitor, ChannelMonitorUpdate, ChannelMonitorUpdateStep, LATENCY_GRACE_PERIOD_BLOCKS, CLOSED_CHANNEL_UPDATE_ID};
 use crate::chain::transaction::{OutPoint, TransactionData};
 use crate::sign::{WriteableEcdsaChannelSigner, EntropySource, ChannelSigner, SignerProvider, NodeSigner, Recipient};
-use crate::events::ClosureReason;
+use crate::events::{ClosureReason, ForceCloseFeerates};
 use crate::routing::gossip::NodeId;
 use crate::util::ser::{Readable, ReadableArgs, Writeable, Writer, VecWriter};
 use crate::util::logger::Logger;
-use crate::util::errors::APIError;
-use crate::util::config::{UserConfig, ChannelConfig, LegacyChannelConfig, ChannelHandshakeConfig, ChannelHandshakeLimits};
+use crate::util::errors::{APIError, ChannelConfigUpdateError, CloseChannelError, OpenChannelError, SendHtlcError};
+use crate::util::config::{UserConfig, ChannelConfig, LegacyChannelConfig, ChannelHandshakeConfig, ChannelHandshakeLimits, HandshakeLimitViolation, InboundChannelOverrides, FeerateDisagreementConfig};
 use crate::util::scid_utils::scid_from_parts;
 
 use crate::io;
@@ -224,6 +225,19 @@ struct OutboundHTLCOutput {
 	payment_hash: PaymentHash,
 	state: OutboundHTLCState,
 	source: HTLCSource,
+	blinding_point: Option<PublicKey>,
+}
+
+impl InboundHTLCOutput {
+	fn lock(&self) -> HTLCLock {
+		HTLCLock::PaymentHash(self.payment_hash)
+	}
+}
+
+impl OutboundHTLCOutput {
+	fn lock(&self) -> HTLCLock {
+		HTLCLock::PaymentHash(self.payment_hash)
+	}
 }
 
 /// See AwaitingRemoteRevoke ChannelState for more info
@@ -235,6 +249,8 @@ enum HTLCUpdateAwaitingACK {
 		payment_hash: PaymentHash,
 		source: HTLCSource,
 		onion_routing_packet: msgs::OnionPacket,
+		// Set if we're forwarding the HTLC within a blinded path.
+		blinding_point: Option<PublicKey>,
 	},
 	ClaimHTLC {
 		payment_preimage: PaymentPreimage,
@@ -244,6 +260,20 @@ enum HTLCUpdateAwaitingACK {
 		htlc_id: u64,
 		err_packet: msgs::OnionErrorPacket,
 	},
+	FailMalformedHTLC {
+		htlc_id: u64,
+		failure_code: u16,
+		sha256_of_onion: [u8; 32],
+	},
+}
+
+/// The contents with which we fail an inbound HTLC back to our counterparty.
+enum HTLCFailureContents {
+	/// Fail the HTLC with an `update_fail_htlc` carrying the given encrypted failure.
+	Relay(msgs::OnionErrorPacket),
+	/// Fail the HTLC with an `update_fail_malformed_htlc`, as required for nodes within a blinded
+	/// path other than its introduction node.
+	Malformed { failure_code: u16, sha256_of_onion: [u8; 32] },
 }
 
 /// There are a few "states" and then a number of flags which can be applied:
@@ -540,14 +570,16 @@ pub(crate) type ShutdownResult = (
 /// the channel. Sadly, there isn't really a good number for this - if we expect to have no new
 /// HTLCs for days we may need this to suffice for feerate increases across days, but that may
 /// leave the channel less usable as we hold a bigger reserve.
+///
+/// The multiple used is configurable via [`ChannelConfig::fee_spike_buffer_multiple`], this is
+/// its default value.
 #[cfg(any(fuzzing, test))]
 pub const FEE_SPIKE_BUFFER_FEE_INCREASE_MULTIPLE: u64 = 2;
-#[cfg(not(any(fuzzing, test)))]
-const FEE_SPIKE_BUFFER_FEE_INCREASE_MULTIPLE: u64 = 2;
 
 /// If we fail to see a funding transaction confirmed on-chain within this many blocks after the
 /// channel creation on an inbound channel, we simply force-close and move on.
-/// This constant is the one suggested in BOLT 2.
+/// This constant is the one suggested in BOLT 2, and is the default for
+/// `ResourceLimitsConfig::funding_confirmation_deadline_blocks`.
 pub(crate) const FUNDING_CONF_DEADLINE_BLOCKS: u32 = 2016;
 
 /// In case of a concurrent update_add_htlc proposed by our counterparty, we might
@@ -706,6 +738,13 @@ pub(super) struct ChannelContext<Signer: ChannelSigner> {
 	/// closing_signed message and handling it in `maybe_propose_closing_signed`.
 	pending_counterparty_closing_signed: Option<msgs::ClosingSigned>,
 
+	/// The last closing_complete we sent for an `option_simple_close` closing, kept so that we can
+	/// complete the closing transaction our counterparty countersigns in its closing_sig.
+	last_sent_closing_complete: Option<msgs::ClosingComplete>,
+	/// As with `pending_counterparty_closing_signed`, a closing_complete which we received while
+	/// waiting for a `ChannelMonitor` update, to be handled in `maybe_propose_closing_complete`.
+	pending_counterparty_closing_complete: Option<msgs::ClosingComplete>,
+
 	/// The minimum and maximum absolute fee, in satoshis, we are willing to place on the closing
 	/// transaction. These are set once we reach `closing_negotiation_ready`.
 	#[cfg(test)]
@@ -770,9 +809,25 @@ pub(super) struct ChannelContext<Signer: ChannelSigner> {
 	minimum_depth: Option<u32>,
 
 	counterparty_forwarding_info: Option<CounterpartyForwardingInfo>,
+	// The latest `channel_update` our counterparty sent us for this channel.
+	counterparty_channel_update: Option<CounterpartyChannelUpdate>,
+	/// If the counterparty set a feerate we'd otherwise have force-closed over, but which we're
+	/// tolerating per [`FeerateDisagreementConfig::grace_period_ticks`], the number of timer ticks
+	/// which have passed since.
+	feerate_disagreement_ticks: Option<u8>,
+	/// While [`Self::feerate_disagreement_ticks`] is set, the
+	/// [`FeerateDisagreementConfig::max_pending_htlc_value_msat`] which new HTLCs in either
+	/// direction may not take the total value pending in the channel over.
+	feerate_disagreement_max_pending_htlc_value_msat: Option<u64>,
 
 	pub(crate) channel_transaction_parameters: ChannelTransactionParameters,
 	funding_transaction: Option<Transaction>,
+	// The funding transaction we broadcast for an outbound channel, kept until it reaches our
+	// minimum depth so that it may be re-broadcast and double-spends of its inputs detected.
+	broadcast_funding_transaction: Option<Transaction>,
+	// The txid and confirmation height of a transaction which we found to double-spend one of
+	// the inputs of `broadcast_funding_transaction`, if any.
+	funding_double_spend: Option<(Txid, u32)>,
 
 	counterparty_cur_commitment_point: Option<PublicKey>,
 	counterparty_prev_commitment_point: Option<PublicKey>,
@@ -783,6 +838,9 @@ pub(super) struct ChannelContext<Signer: ChannelSigner> {
 	commitment_secrets: CounterpartyCommitmentSecrets,
 
 	channel_update_status: ChannelUpdateStatus,
+	/// The number of timer ticks since we last gossiped a change of [`Self::channel_update_status`],
+	/// saturating at `u8::MAX`. Not persisted, such that status changes aren't delayed on restart.
+	ticks_since_channel_update_status_change: u8,
 	/// Once we reach `closing_negotiation_ready`, we set this, indicating if closing_signed does
 	/// not complete within a single timer tick (one minute), we should force-close the channel.
 	/// This prevents us from keeping unusable channels around forever if our counterparty wishes
@@ -813,6 +871,13 @@ pub(super) struct ChannelContext<Signer: ChannelSigner> {
 	/// See-also <https://github.com/lightningnetwork/lnd/issues/4006>
 	pub workaround_lnd_bug_4006: Option<msgs::ChannelReady>,
 
+	/// Set to the counterparty's current per-commitment point once its `channel_reestablish`
+	/// proved that we have fallen behind, i.e. that our latest commitment transaction has been
+	/// revoked. Once set, we must never broadcast our commitment transaction, and instead wait for
+	/// the counterparty to broadcast theirs. This is not persisted as the channel is immediately
+	/// closed.
+	counterparty_stale_state_point: Option<PublicKey>,
+
 	/// An option set when we wish to track how many ticks have elapsed while waiting for a response
 	/// from our counterparty after sending a message. If the peer has yet to respond after reaching
 	/// `DISCONNECT_PEER_AWAITING_RESPONSE_TICKS`, a reconnection should be attempted to try to
@@ -846,22 +911,40 @@ pub(super) struct ChannelContext<Signer: ChannelSigner> {
 	// publicly - allowing them to re-use their map of SCID -> channel for channel_update ->
 	// associated channel mapping.
 	//
-	// We only bother storing the most recent SCID alias at any time, though our counterparty has
-	// to store all of them.
+	// This is the alias we currently use, which is the most recent one our counterparty provided
+	// unless the user rotated to another one of `inbound_scid_aliases`.
 	latest_inbound_scid_alias: Option<u64>,
 
+	// All distinct SCID aliases our counterparty has offered us, oldest first. We keep at most
+	// `MAX_SCID_ALIASES_PER_CHANNEL` of them, which the user may rotate through so that invoices
+	// issued over a long-lived channel don't all share the same alias.
+	inbound_scid_aliases: Vec<u64>,
+
 	// We always offer our counterparty a static SCID alias, which we recognize as for this channel
-	// if we see it in HTLC forwarding instructions. We don't bother rotating the alias given we
-	// don't currently support node id aliases and eventually privacy should be provided with
-	// blinded paths instead of simple scid+node_id aliases.
+	// if we see it in HTLC forwarding instructions.
 	outbound_scid_alias: u64,
 
+	// Any further SCID aliases the user asked us to create for this channel, oldest first. We
+	// recognize all of them in addition to `outbound_scid_alias`, and offer the newest one to our
+	// counterparty whenever we (re-)send a `channel_ready`.
+	additional_outbound_scid_aliases: Vec<u64>,
+
 	// We track whether we already emitted a `ChannelPending` event.
 	channel_pending_event_emitted: bool,
 
 	// We track whether we already emitted a `ChannelReady` event.
 	channel_ready_event_emitted: bool,
 
+	// Whether the transaction which spent our funding output, as detected in
+	// `transactions_confirmed`, was our counterparty's commitment transaction. Not persisted, as
+	// the channel is closed right away.
+	funding_spent_by_counterparty_commitment: bool,
+
+	// The number of funding transaction confirmations we last reported in a
+	// `ChannelProgress::FundingConfirmed` event. Not persisted, as the events are only
+	// informational.
+	reported_funding_tx_confirmations: u32,
+
 	/// The unique identifier used to re-derive the private key material for the channel through
 	/// [`SignerProvider::derive_channel_signer`].
 	channel_keys_id: [u8; 32],
@@ -883,6 +966,12 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 		self.update_time_counter
 	}
 
+	/// Bumps the counter backing the `channel_update` timestamp, such that a freshly generated
+	/// `channel_update` supersedes any we previously broadcast.
+	pub fn bump_update_time_counter(&mut self) {
+		self.update_time_counter += 1;
+	}
+
 	pub fn get_latest_monitor_update_id(&self) -> u64 {
 		self.latest_monitor_update_id
 	}
@@ -913,6 +1002,12 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 		(self.channel_state & mask) == (ChannelState::ChannelReady as u32) && !self.monitor_pending_channel_ready
 	}
 
+	/// Returns true if either we or our counterparty have initiated a cooperative close of this
+	/// channel.
+	pub fn is_shutting_down(&self) -> bool {
+		self.channel_state & BOTH_SIDES_SHUTDOWN_MASK != 0
+	}
+
 	/// Returns true if this channel is currently available for use. This is a superset of
 	/// is_usable() and considers things like the channel being temporarily disabled.
 	/// Allowed in any state (including after shutdown)
@@ -920,6 +1015,22 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 		self.is_usable() && (self.channel_state & (ChannelState::PeerDisconnected as u32) == 0)
 	}
 
+	/// Returns the number of timer ticks since we last gossiped the channel as disabled or enabled,
+	/// saturating at `u8::MAX`.
+	pub fn ticks_since_channel_update_status_change(&self) -> u8 {
+		self.ticks_since_channel_update_status_change
+	}
+
+	/// Should be called on every timer tick, see [`Self::ticks_since_channel_update_status_change`].
+	pub fn tick_channel_update_status_change_timer(&mut self) {
+		self.ticks_since_channel_update_status_change = self.ticks_since_channel_update_status_change.saturating_add(1);
+	}
+
+	/// Should be called whenever we gossip the channel as disabled or enabled.
+	pub fn reset_channel_update_status_change_timer(&mut self) {
+		self.ticks_since_channel_update_status_change = 0;
+	}
+
 	// Public utilities:
 
 	pub fn channel_id(&self) -> [u8; 32] {
@@ -960,11 +1071,35 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 		self.latest_inbound_scid_alias
 	}
 
+	/// Gets all SCID aliases our counterparty has offered us which we still track, oldest first.
+	/// Allowed in any state (including after shutdown)
+	pub fn inbound_scid_aliases(&self) -> &[u64] {
+		&self.inbound_scid_aliases
+	}
+
 	/// Allowed in any state (including after shutdown)
 	pub fn outbound_scid_alias(&self) -> u64 {
 		self.outbound_scid_alias
 	}
 
+	/// Gets the SCID aliases created for this channel in addition to [`Self::outbound_scid_alias`],
+	/// oldest first.
+	/// Allowed in any state (including after shutdown)
+	pub fn additional_outbound_scid_aliases(&self) -> &[u64] {
+		&self.additional_outbound_scid_aliases
+	}
+
+	/// Gets the outbound SCID alias we offer our counterparty in `channel_ready`, i.e. the most
+	/// recently created one.
+	fn current_outbound_scid_alias(&self) -> u64 {
+		*self.additional_outbound_scid_aliases.last().unwrap_or(&self.outbound_scid_alias)
+	}
+
+	/// Returns true if the given SCID is one of the SCID aliases we offered our counterparty.
+	pub fn is_outbound_scid_alias(&self, scid: u64) -> bool {
+		scid == self.outbound_scid_alias || self.additional_outbound_scid_aliases.contains(&scid)
+	}
+
 	/// Only allowed immediately after deserialization if get_outbound_scid_alias returns 0,
 	/// indicating we were written by LDK prior to 0.0.106 which did not set outbound SCID aliases.
 	pub fn set_outbound_scid_alias(&mut self, outbound_scid_alias: u64) {
@@ -983,6 +1118,37 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 		self.funding_tx_confirmed_in
 	}
 
+	/// Returns the funding transaction we broadcast for this outbound channel, unless it has
+	/// already reached our minimum depth.
+	pub fn get_broadcast_funding_transaction(&self) -> Option<&Transaction> {
+		self.broadcast_funding_transaction.as_ref()
+	}
+
+	/// Records that the transaction with the given `txid`, confirmed at the given `height`,
+	/// double-spends one of the inputs of our broadcast funding transaction, returning whether we
+	/// didn't know so already.
+	pub fn record_funding_double_spend(&mut self, txid: Txid, height: u32) -> bool {
+		if self.funding_double_spend.map(|(spend_txid, _)| spend_txid) == Some(txid) { return false; }
+		self.funding_double_spend = Some((txid, height));
+		true
+	}
+
+	/// Forgets about the transaction double-spending our funding transaction if it was
+	/// unconfirmed, i.e. if it has the given `txid` or was confirmed above `best_height`.
+	pub fn funding_double_spend_unconfirmed(&mut self, txid: Option<&Txid>, best_height: u32) {
+		if let Some((spend_txid, spend_height)) = self.funding_double_spend {
+			if Some(&spend_txid) == txid || spend_height > best_height {
+				self.funding_double_spend = None;
+			}
+		}
+	}
+
+	/// Returns the height at which a transaction double-spending our funding transaction was
+	/// confirmed, if any.
+	pub fn get_funding_double_spend_height(&self) -> Option<u32> {
+		self.funding_double_spend.map(|(_, height)| height)
+	}
+
 	/// Returns the current number of confirmations on the funding transaction.
 	pub fn get_funding_tx_confirmations(&self, height: u32) -> u32 {
 		if self.funding_tx_confirmation_height == 0 {
@@ -993,6 +1159,20 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 		height.checked_sub(self.funding_tx_confirmation_height).map_or(0, |c| c + 1)
 	}
 
+	/// Returns the current number of confirmations on the funding transaction if it changed since
+	/// it was last returned, as long as the funding transaction has not yet exceeded the channel's
+	/// minimum depth.
+	pub(crate) fn funding_tx_confirmations_to_report(&mut self, height: u32) -> Option<u32> {
+		let confirmations = self.get_funding_tx_confirmations(height);
+		if confirmations == self.reported_funding_tx_confirmations ||
+			confirmations > self.minimum_depth.unwrap_or(0)
+		{
+			return None;
+		}
+		self.reported_funding_tx_confirmations = confirmations;
+		Some(confirmations)
+	}
+
 	fn get_holder_selected_contest_delay(&self) -> u16 {
 		self.channel_transaction_parameters.holder_selected_contest_delay
 	}
@@ -1027,6 +1207,12 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 
 	/// Allowed in any state (including after shutdown)
 	pub fn get_announced_htlc_max_msat(&self) -> u64 {
+		cmp::min(self.get_default_announced_htlc_max_msat(), self.config.options.forwarding_htlc_maximum_msat)
+	}
+
+	/// The `htlc_maximum_msat` we advertise absent a lower
+	/// [`ChannelConfig::forwarding_htlc_maximum_msat`].
+	fn get_default_announced_htlc_max_msat(&self) -> u64 {
 		return cmp::min(
 			// Upper bound by capacity. We make it a bit less than full capacity to prevent attempts
 			// to use full capacity. This is an effort to reduce routing failures, because in many cases
@@ -1037,6 +1223,33 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 		);
 	}
 
+	/// Allowed in any state (including after shutdown)
+	pub fn get_announced_htlc_min_msat(&self) -> u64 {
+		cmp::max(self.counterparty_htlc_minimum_msat, self.config.options.forwarding_htlc_minimum_msat)
+	}
+
+	/// Checks that the HTLC limits set in the given [`ChannelConfig`] are consistent with this
+	/// channel, i.e. that the HTLC minimum we would advertise is no greater than the maximum and
+	/// that the minimum doesn't exceed what the channel can carry, given its capacity, both
+	/// parties' reserves and our counterparty's `max_htlc_value_in_flight_msat`.
+	pub fn check_forwarding_htlc_limits(&self, config: &ChannelConfig) -> Result<(), ChannelConfigUpdateError> {
+		let htlc_minimum_msat = cmp::max(self.counterparty_htlc_minimum_msat, config.forwarding_htlc_minimum_msat);
+		let htlc_maximum_msat = cmp::min(self.get_default_announced_htlc_max_msat(), config.forwarding_htlc_maximum_msat);
+		if let Some(max_sendable_msat) = self.get_counterparty_htlc_maximum_msat() {
+			if htlc_minimum_msat > max_sendable_msat {
+				return Err(ChannelConfigUpdateError::HtlcMinimumExceedsCapacity {
+					channel_id: self.channel_id, htlc_minimum_msat, max_sendable_msat,
+				});
+			}
+		}
+		if htlc_maximum_msat < htlc_minimum_msat {
+			return Err(ChannelConfigUpdateError::HtlcMaximumBelowMinimum {
+				channel_id: self.channel_id, htlc_maximum_msat, htlc_minimum_msat,
+			});
+		}
+		Ok(())
+	}
+
 	/// Allowed in any state (including after shutdown)
 	pub fn get_counterparty_htlc_minimum_msat(&self) -> u64 {
 		self.counterparty_htlc_minimum_msat
@@ -1069,10 +1282,23 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 		cmp::max(self.config.options.cltv_expiry_delta, MIN_CLTV_EXPIRY_DELTA)
 	}
 
+	/// Returns the earliest `cltv_expiry` of any HTLC pending in the channel, in either direction.
+	pub fn earliest_pending_htlc_expiry(&self) -> Option<u32> {
+		self.pending_inbound_htlcs.iter().map(|htlc| htlc.cltv_expiry)
+			.chain(self.pending_outbound_htlcs.iter().map(|htlc| htlc.cltv_expiry))
+			.min()
+	}
+
 	pub fn get_max_dust_htlc_exposure_msat(&self) -> u64 {
 		self.config.options.max_dust_htlc_exposure_msat
 	}
 
+	/// Returns the multiple of the commitment transaction fee we keep in reserve when we're the
+	/// funder, see [`ChannelConfig::fee_spike_buffer_multiple`].
+	pub fn get_fee_spike_buffer_multiple(&self) -> u64 {
+		cmp::max(1, self.config.options.fee_spike_buffer_multiple)
+	}
+
 	/// Returns the previous [`ChannelConfig`] applied to this channel, if any.
 	pub fn prev_config(&self) -> Option<ChannelConfig> {
 		self.prev_config.map(|prev_config| prev_config.0)
@@ -1103,6 +1329,32 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 		self.channel_ready_event_emitted = true;
 	}
 
+	/// Returns whether the channel was closed as our counterparty's commitment transaction was
+	/// confirmed, as opposed to ours or a cooperative closing transaction.
+	pub(crate) fn funding_spent_by_counterparty_commitment(&self) -> bool {
+		self.funding_spent_by_counterparty_commitment
+	}
+
+	/// Returns whether the given transaction spending our funding output is our counterparty's
+	/// commitment transaction, i.e., a commitment transaction paying our balance to our payment
+	/// point. We can't tell whose commitment transaction it is if it has no such output and
+	/// neither one paying our counterparty's balance to its payment point, in which case we assume
+	/// it isn't our counterparty's.
+	fn is_counterparty_commitment_tx(&self, tx: &Transaction) -> bool {
+		// Commitment transactions encode the obscured commitment number in the upper bytes of their
+		// locktime and sequence, unlike cooperative closing transactions.
+		if tx.input.len() != 1 || tx.lock_time.0 >> 8 * 3 != 0x20 || tx.input[0].sequence.0 >> 8 * 3 != 0x80 {
+			return false;
+		}
+		let to_remote_script = |payment_point: &PublicKey| if self.opt_anchors() {
+			chan_utils::get_to_countersignatory_with_anchors_redeemscript(payment_point).to_v0_p2wsh()
+		} else {
+			Script::new_v0_p2wpkh(&WPubkeyHash::hash(&payment_point.serialize()))
+		};
+		let holder_to_remote_script = to_remote_script(&self.get_holder_pubkeys().payment_point);
+		tx.output.iter().any(|output| output.script_pubkey == holder_to_remote_script)
+	}
+
 	/// Tracks the number of ticks elapsed since the previous [`ChannelConfig`] was updated. Once
 	/// [`EXPIRE_PREV_CONFIG_TICKS`] is reached, the previous config is considered expired and will
 	/// no longer be considered when forwarding HTLCs.
@@ -1128,7 +1380,9 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 		let did_channel_update =
 			self.config.options.forwarding_fee_proportional_millionths != config.forwarding_fee_proportional_millionths ||
 			self.config.options.forwarding_fee_base_msat != config.forwarding_fee_base_msat ||
-			self.config.options.cltv_expiry_delta != config.cltv_expiry_delta;
+			self.config.options.cltv_expiry_delta != config.cltv_expiry_delta ||
+			self.config.options.forwarding_htlc_minimum_msat != config.forwarding_htlc_minimum_msat ||
+			self.config.options.forwarding_htlc_maximum_msat != config.forwarding_htlc_maximum_msat;
 		if did_channel_update {
 			self.prev_config = Some((self.config.options, 0));
 			// Update the counter, which backs the ChannelUpdate timestamp, to allow the relay
@@ -1144,6 +1398,52 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 		self.channel_state >= ChannelState::FundingSent as u32
 	}
 
+	/// Returns the feerates the channel's [`ForceCloseFeePolicy`] selects for our commitment
+	/// transaction if the channel is force-closed at the given height, or `None` if we haven't yet
+	/// exchanged funding signatures, in which case there is no commitment transaction to broadcast.
+	///
+	/// This must be called before [`Self::force_shutdown`].
+	///
+	/// [`ForceCloseFeePolicy`]: crate::util::config::ForceCloseFeePolicy
+	pub fn force_close_feerates<F: Deref>(&self, height: u32, fee_estimator: &LowerBoundedFeeEstimator<F>) -> Option<ForceCloseFeerates>
+		where F::Target: FeeEstimator
+	{
+		if self.channel_state & (ChannelState::FundingSent as u32 | ChannelState::ChannelReady as u32) == 0 {
+			return None;
+		}
+		let htlc_expiries = self.pending_inbound_htlcs.iter().map(|htlc| htlc.cltv_expiry)
+			.chain(self.pending_outbound_htlcs.iter().map(|htlc| htlc.cltv_expiry));
+		let confirmation_target = self.config.options.force_close_fee_policy.confirmation_target(htlc_expiries, height);
+		Some(ForceCloseFeerates {
+			confirmation_target,
+			commitment_feerate_sat_per_1000_weight: self.feerate_per_kw,
+			target_feerate_sat_per_1000_weight: fee_estimator.bounded_sat_per_1000_weight(confirmation_target),
+		})
+	}
+
+	/// Gets the data required to recover our balance in this channel from a static backup.
+	///
+	/// Returns `None` if funding_created has not yet been sent/received, or if the channel uses
+	/// anchor outputs, as our balance in our counterparty's commitment transactions then cannot be
+	/// claimed using a [`StaticPaymentOutputDescriptor`].
+	///
+	/// [`StaticPaymentOutputDescriptor`]: crate::sign::StaticPaymentOutputDescriptor
+	pub(crate) fn get_static_channel_backup(&self) -> Option<StaticChannelBackup> {
+		if !self.is_funding_initiated() || self.opt_anchors() {
+			return None;
+		}
+		let funding_txo = self.get_funding_txo()?;
+		Some(StaticChannelBackup {
+			channel_id: self.channel_id,
+			counterparty_node_id: self.counterparty_node_id,
+			funding_txo,
+			funding_script_pubkey: self.get_funding_redeemscript().to_v0_p2wsh(),
+			channel_value_satoshis: self.channel_value_satoshis,
+			channel_keys_id: self.channel_keys_id,
+			counterparty_pubkeys: self.get_counterparty_pubkeys().clone(),
+		})
+	}
+
 	/// Transaction nomenclature is somewhat confusing here as there are many different cases - a
 	/// transaction is referred to as "a's transaction" implying that a will be able to broadcast
 	/// the transaction. Thus, b will generally be sending a signature over such a transaction to
@@ -1458,6 +1758,99 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 		self.counterparty_forwarding_info.clone()
 	}
 
+	/// Get the parameters of the latest `channel_update` our counterparty sent us.
+	pub fn counterparty_channel_update(&self) -> Option<CounterpartyChannelUpdate> {
+		self.counterparty_channel_update.clone()
+	}
+
+	/// Returns the number of HTLCs our counterparty has added which have not yet been resolved.
+	pub fn get_pending_inbound_htlc_count(&self) -> usize {
+		self.pending_inbound_htlcs.len()
+	}
+
+	/// Returns the total value of the HTLCs our counterparty has added which have not yet been
+	/// resolved, in millisatoshis.
+	pub fn get_pending_inbound_htlc_value_msat(&self) -> u64 {
+		self.pending_inbound_htlcs.iter().map(|htlc| htlc.amount_msat).sum()
+	}
+
+	/// Returns the CLTV expiry of the inbound HTLC with the given id, if it has not yet been
+	/// resolved.
+	pub fn get_inbound_htlc_cltv_expiry(&self, htlc_id: u64) -> Option<u32> {
+		self.pending_inbound_htlcs.iter().find(|htlc| htlc.htlc_id == htlc_id).map(|htlc| htlc.cltv_expiry)
+	}
+
+	/// Returns the details of all HTLCs pending in the channel, including outbound HTLCs in the
+	/// holding cell.
+	pub fn get_pending_htlc_details(&self) -> Vec<PendingHTLCDetails> {
+		let mut res = Vec::with_capacity(self.pending_inbound_htlcs.len() +
+			self.pending_outbound_htlcs.len() + self.holding_cell_htlc_updates.len());
+		for htlc in self.pending_inbound_htlcs.iter() {
+			let state = match htlc.state {
+				InboundHTLCState::RemoteAnnounced(_) |
+				InboundHTLCState::AwaitingRemoteRevokeToAnnounce(_) |
+				InboundHTLCState::AwaitingAnnouncedRemoteRevoke(_) => PendingHTLCState::AwaitingAdd,
+				InboundHTLCState::Committed => {
+					// A claim or failure of the HTLC may be waiting in the holding cell.
+					self.holding_cell_htlc_updates.iter().find_map(|update| match update {
+						HTLCUpdateAwaitingACK::ClaimHTLC { htlc_id, .. } if *htlc_id == htlc.htlc_id =>
+							Some(PendingHTLCState::AwaitingClaimRemoval),
+						HTLCUpdateAwaitingACK::FailHTLC { htlc_id, .. } |
+						HTLCUpdateAwaitingACK::FailMalformedHTLC { htlc_id, .. } if *htlc_id == htlc.htlc_id =>
+							Some(PendingHTLCState::AwaitingFailRemoval),
+						_ => None,
+					}).unwrap_or(PendingHTLCState::Committed)
+				},
+				InboundHTLCState::LocalRemoved(InboundHTLCRemovalReason::Fulfill(_)) =>
+					PendingHTLCState::AwaitingClaimRemoval,
+				InboundHTLCState::LocalRemoved(_) => PendingHTLCState::AwaitingFailRemoval,
+			};
+			res.push(PendingHTLCDetails {
+				direction: PendingHTLCDirection::Inbound,
+				htlc_id: Some(htlc.htlc_id),
+				amount_msat: htlc.amount_msat,
+				cltv_expiry: htlc.cltv_expiry,
+				payment_hash: htlc.payment_hash,
+				state,
+			});
+		}
+		for htlc in self.pending_outbound_htlcs.iter() {
+			let state = match htlc.state {
+				OutboundHTLCState::LocalAnnounced(_) => PendingHTLCState::AwaitingAdd,
+				OutboundHTLCState::Committed => PendingHTLCState::Committed,
+				OutboundHTLCState::RemoteRemoved(OutboundHTLCOutcome::Success(_)) |
+				OutboundHTLCState::AwaitingRemoteRevokeToRemove(OutboundHTLCOutcome::Success(_)) |
+				OutboundHTLCState::AwaitingRemovedRemoteRevoke(OutboundHTLCOutcome::Success(_)) =>
+					PendingHTLCState::AwaitingClaimRemoval,
+				OutboundHTLCState::RemoteRemoved(OutboundHTLCOutcome::Failure(_)) |
+				OutboundHTLCState::AwaitingRemoteRevokeToRemove(OutboundHTLCOutcome::Failure(_)) |
+				OutboundHTLCState::AwaitingRemovedRemoteRevoke(OutboundHTLCOutcome::Failure(_)) =>
+					PendingHTLCState::AwaitingFailRemoval,
+			};
+			res.push(PendingHTLCDetails {
+				direction: PendingHTLCDirection::Outbound,
+				htlc_id: Some(htlc.htlc_id),
+				amount_msat: htlc.amount_msat,
+				cltv_expiry: htlc.cltv_expiry,
+				payment_hash: htlc.payment_hash,
+				state,
+			});
+		}
+		for update in self.holding_cell_htlc_updates.iter() {
+			if let &HTLCUpdateAwaitingACK::AddHTLC { amount_msat, cltv_expiry, payment_hash, .. } = update {
+				res.push(PendingHTLCDetails {
+					direction: PendingHTLCDirection::Outbound,
+					htlc_id: None,
+					amount_msat,
+					cltv_expiry,
+					payment_hash,
+					state: PendingHTLCState::HoldingCell,
+				});
+			}
+		}
+		res
+	}
+
 	/// Returns a HTLCStats about inbound pending htlcs
 	fn get_inbound_pending_htlc_stats(&self, outbound_feerate_update: Option<u32>) -> HTLCStats {
 		let context = self;
@@ -1545,6 +1938,44 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 	/// if-we-removed-it-already-but-haven't-fully-resolved-they-can-still-send-an-inbound-HTLC
 	/// corner case properly.
 	pub fn get_available_balances(&self) -> AvailableBalances {
+		self.get_available_balances_with_fee_spike_buffer_multiple(self.get_fee_spike_buffer_multiple())
+	}
+
+	/// Returns the total amount our counterparty can currently send us in new HTLCs. Unlike
+	/// [`AvailableBalances::inbound_capacity_msat`], this also respects the
+	/// `max_htlc_value_in_flight_msat` and `max_accepted_htlcs` limits we set for them.
+	pub fn get_receivable_capacity_msat(&self) -> u64 {
+		let inbound_stats = self.get_inbound_pending_htlc_stats(None);
+		if inbound_stats.pending_htlcs + 1 > self.holder_max_accepted_htlcs as u32 {
+			return 0;
+		}
+		cmp::min(self.get_available_balances().inbound_capacity_msat,
+			self.holder_max_htlc_value_in_flight_msat.saturating_sub(inbound_stats.pending_htlcs_value_msat))
+	}
+
+	/// Returns whether another outbound HTLC would take the number of HTLCs pending towards our
+	/// counterparty, including those in our holding cell, over `slot_limit_percent` of its
+	/// `max_accepted_htlcs`. Always returns false for a limit of 100 percent or more.
+	pub fn is_outbound_htlc_slot_limit_reached(&self, slot_limit_percent: u8) -> bool {
+		if slot_limit_percent >= 100 {
+			return false;
+		}
+		let slot_limit = self.counterparty_max_accepted_htlcs as u32 * slot_limit_percent as u32 / 100;
+		self.get_outbound_pending_htlc_stats(None).pending_htlcs + 1 > slot_limit
+	}
+
+	/// Returns whether an outbound HTLC of `amount_msat` exceeds our next-HTLC maximum only
+	/// because of the fee-spike buffer we keep in reserve as the channel funder, i.e. it would fit
+	/// if we reserved only the current commitment transaction fee.
+	pub fn is_htlc_blocked_by_fee_spike_buffer(&self, amount_msat: u64) -> bool {
+		if !self.is_outbound() || self.get_fee_spike_buffer_multiple() == 1 {
+			return false;
+		}
+		amount_msat > self.get_available_balances().next_outbound_htlc_limit_msat &&
+			amount_msat <= self.get_available_balances_with_fee_spike_buffer_multiple(1).next_outbound_htlc_limit_msat
+	}
+
+	fn get_available_balances_with_fee_spike_buffer_multiple(&self, fee_spike_buffer_multiple: u64) -> AvailableBalances {
 		let context = &self;
 		// Note that we have to handle overflow due to the above case.
 		let inbound_stats = context.get_inbound_pending_htlc_stats(None);
@@ -1579,9 +2010,9 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 			}
 
 			let htlc_above_dust = HTLCCandidate::new(real_dust_limit_timeout_sat * 1000, HTLCInitiator::LocalOffered);
-			let max_reserved_commit_tx_fee_msat = FEE_SPIKE_BUFFER_FEE_INCREASE_MULTIPLE * context.next_local_commit_tx_fee_msat(htlc_above_dust, Some(()));
+			let max_reserved_commit_tx_fee_msat = fee_spike_buffer_multiple * context.next_local_commit_tx_fee_msat(htlc_above_dust, Some(()));
 			let htlc_dust = HTLCCandidate::new(real_dust_limit_timeout_sat * 1000 - 1, HTLCInitiator::LocalOffered);
-			let min_reserved_commit_tx_fee_msat = FEE_SPIKE_BUFFER_FEE_INCREASE_MULTIPLE * context.next_local_commit_tx_fee_msat(htlc_dust, Some(()));
+			let min_reserved_commit_tx_fee_msat = fee_spike_buffer_multiple * context.next_local_commit_tx_fee_msat(htlc_dust, Some(()));
 
 			// We will first subtract the fee as if we were above-dust. Then, if the resulting
 			// value ends up being below dust, we have this fee available again. In that case,
@@ -1660,6 +2091,13 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 		available_capacity_msat = cmp::min(available_capacity_msat,
 			context.counterparty_max_htlc_value_in_flight_msat - outbound_stats.pending_htlcs_value_msat);
 
+		if let Some(max_pending_htlc_value_msat) = context.feerate_disagreement_max_pending_htlc_value_msat {
+			// While we tolerate a feerate disagreement, don't add HTLCs which would have us
+			// force-close the channel on the next timer tick.
+			available_capacity_msat = cmp::min(available_capacity_msat, max_pending_htlc_value_msat
+				.saturating_sub(inbound_stats.pending_htlcs_value_msat + outbound_stats.pending_htlcs_value_msat));
+		}
+
 		if outbound_stats.pending_htlcs + 1 > context.counterparty_max_accepted_htlcs as u32 {
 			available_capacity_msat = 0;
 		}
@@ -1885,11 +2323,35 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 		}
 	}
 
+	/// Gets the counterparty's current per-commitment point if its `channel_reestablish` proved
+	/// that we have fallen behind, in which case we must not broadcast our commitment transaction.
+	pub fn counterparty_stale_state_point(&self) -> Option<PublicKey> {
+		self.counterparty_stale_state_point
+	}
+
+	/// Commits to `shutdown_script` as our upfront shutdown script, replacing any script fetched
+	/// from the [`SignerProvider`] when the channel was created. Must be done before our
+	/// `open_channel` or `accept_channel` message is generated.
+	///
+	/// Hands the script back if it isn't compatible with our counterparty's features.
+	pub fn set_upfront_shutdown_script(&mut self, shutdown_script: ShutdownScript, their_features: &InitFeatures) -> Result<(), ShutdownScript> {
+		assert!(self.channel_state <= (ChannelState::OurInitSent as u32 | ChannelState::TheirInitSent as u32));
+		if !shutdown_script.is_compatible(their_features) {
+			return Err(shutdown_script);
+		}
+		self.shutdown_scriptpubkey = Some(shutdown_script);
+		self.config.commit_upfront_shutdown_pubkey = true;
+		Ok(())
+	}
+
 	/// Gets the latest commitment transaction and any dependent transactions for relay (forcing
 	/// shutdown of this channel - no more calls into this Channel may be made afterwards except
 	/// those explicitly stated to be allowed after shutdown completes, eg some simple getters).
 	/// Also returns the list of payment_hashes for channels which we can safely fail backwards
 	/// immediately (others we will have to allow to time out).
+	///
+	/// We never broadcast if we've learned that we have fallen behind, regardless of
+	/// `should_broadcast`.
 	pub fn force_shutdown(&mut self, should_broadcast: bool) -> ShutdownResult {
 		// Note that we MUST only generate a monitor update that indicates force-closure - we're
 		// called during initialization prior to the chain_monitor in the encompassing ChannelManager
@@ -1919,9 +2381,16 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 			// See test_duplicate_chan_id and test_pre_lockin_no_chan_closed_update for more.
 			if self.channel_state & (ChannelState::FundingSent as u32 | ChannelState::ChannelReady as u32 | ChannelState::ShutdownComplete as u32) != 0 {
 				self.latest_monitor_update_id = CLOSED_CHANNEL_UPDATE_ID;
+				// Broadcasting a revoked commitment transaction would allow our counterparty to claim
+				// all our funds.
+				let their_current_per_commitment_point = self.counterparty_stale_state_point;
+				let should_broadcast = should_broadcast && their_current_per_commitment_point.is_none();
 				Some((self.get_counterparty_node_id(), funding_txo, ChannelMonitorUpdate {
 					update_id: self.latest_monitor_update_id,
-					updates: vec![ChannelMonitorUpdateStep::ChannelForceClosed { should_broadcast }],
+					updates: vec![ChannelMonitorUpdateStep::ChannelForceClosed {
+						should_broadcast, their_current_per_commitment_point,
+						fee_policy: Some(self.config.options.force_close_fee_policy),
+					}],
 				}))
 			} else { None }
 		} else { None };
@@ -1959,8 +2428,12 @@ fn get_holder_max_htlc_value_in_flight_msat(channel_value_satoshis: u64, config:
 /// Guaranteed to return a value no larger than channel_value_satoshis
 ///
 /// This is used both for outbound and inbound channels and has lower bound
-/// of `MIN_THEIR_CHAN_RESERVE_SATOSHIS`.
+/// of `MIN_THEIR_CHAN_RESERVE_SATOSHIS`, unless
+/// [`ChannelHandshakeConfig::their_channel_reserve_zero`] is set, in which case it is zero.
 pub(crate) fn get_holder_selected_channel_reserve_satoshis(channel_value_satoshis: u64, config: &UserConfig) -> u64 {
+	if config.channel_handshake_config.their_channel_reserve_zero {
+		return 0;
+	}
 	let calculated_reserve = channel_value_satoshis.saturating_mul(config.channel_handshake_config.their_channel_reserve_proportional_millionths as u64) / 1_000_000;
 	cmp::min(channel_value_satoshis, cmp::max(calculated_reserve, MIN_THEIR_CHAN_RESERVE_SATOSHIS))
 }
@@ -2112,6 +2585,49 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 		(closing_transaction, total_fee_satoshis)
 	}
 
+	/// Builds a closing transaction for `option_simple_close`, in which the closer pays the full
+	/// `fee_satoshis` out of its own output. Returns `None` if the closer cannot afford the fee or
+	/// the transaction would have no outputs.
+	fn build_simple_closing_transaction(
+		&self, holder_is_closer: bool, fee_satoshis: u64, locktime: u32, include_closer_output: bool,
+		include_closee_output: bool,
+	) -> Option<ClosingTransaction> {
+		assert!(self.context.pending_inbound_htlcs.is_empty());
+		assert!(self.context.pending_outbound_htlcs.is_empty());
+		assert!(self.context.pending_update_fee.is_none());
+
+		let holder_balance_satoshis = self.context.value_to_self_msat / 1000;
+		let counterparty_balance_satoshis = (self.context.channel_value_satoshis * 1000 - self.context.value_to_self_msat) / 1000;
+		let (closer_balance_satoshis, closee_balance_satoshis) = if holder_is_closer {
+			(holder_balance_satoshis, counterparty_balance_satoshis)
+		} else {
+			(counterparty_balance_satoshis, holder_balance_satoshis)
+		};
+		if fee_satoshis > closer_balance_satoshis {
+			return None;
+		}
+
+		// If the closer's output is omitted, its remaining balance simply goes to fees.
+		let closer_value = if include_closer_output { closer_balance_satoshis - fee_satoshis } else { 0 };
+		let closee_value = if include_closee_output { closee_balance_satoshis } else { 0 };
+		if closer_value == 0 && closee_value == 0 {
+			return None;
+		}
+		let (value_to_holder, value_to_counterparty) = if holder_is_closer {
+			(closer_value, closee_value)
+		} else {
+			(closee_value, closer_value)
+		};
+
+		assert!(self.context.shutdown_scriptpubkey.is_some());
+		let holder_shutdown_script = self.get_closing_scriptpubkey();
+		let counterparty_shutdown_script = self.context.counterparty_shutdown_scriptpubkey.clone().unwrap();
+		let funding_outpoint = self.funding_outpoint().into_bitcoin_outpoint();
+
+		Some(ClosingTransaction::new_simple_close(value_to_holder, value_to_counterparty,
+			holder_shutdown_script, counterparty_shutdown_script, funding_outpoint, locktime))
+	}
+
 	fn funding_outpoint(&self) -> OutPoint {
 		self.context.channel_transaction_parameters.funding_outpoint.unwrap()
 	}
@@ -2148,8 +2664,6 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 		}
 		assert_eq!(self.context.channel_state & ChannelState::ShutdownComplete as u32, 0);
 
-		let payment_hash_calc = PaymentHash(Sha256::hash(&payment_preimage_arg.0[..]).into_inner());
-
 		// ChannelManager may generate duplicate claims/fails due to HTLC update events from
 		// on-chain ChannelsMonitors during block rescan. Ideally we'd figure out a way to drop
 		// these, but for now we just have to treat them as normal.
@@ -2158,7 +2672,7 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 		let mut htlc_value_msat = 0;
 		for (idx, htlc) in self.context.pending_inbound_htlcs.iter().enumerate() {
 			if htlc.htlc_id == htlc_id_arg {
-				assert_eq!(htlc.payment_hash, payment_hash_calc);
+				assert!(htlc.lock().is_unlocked_by_preimage(&payment_preimage_arg));
 				match htlc.state {
 					InboundHTLCState::Committed => {},
 					InboundHTLCState::LocalRemoved(ref reason) => {
@@ -2215,7 +2729,8 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 							return UpdateFulfillFetch::DuplicateClaim {};
 						}
 					},
-					&HTLCUpdateAwaitingACK::FailHTLC { htlc_id, .. } => {
+					&HTLCUpdateAwaitingACK::FailHTLC { htlc_id, .. } |
+					&HTLCUpdateAwaitingACK::FailMalformedHTLC { htlc_id, .. } => {
 						if htlc_id_arg == htlc_id {
 							log_warn!(logger, "Have preimage and want to fulfill HTLC with pending failure against channel {}", log_bytes!(self.context.channel_id()));
 							// TODO: We may actually be able to switch to a fulfill here, though its
@@ -2311,6 +2826,35 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 		}
 	}
 
+	/// Builds a [`ChannelMonitorUpdate`] providing the preimage of the given inbound HTLC to our
+	/// [`ChannelMonitor`] without claiming the HTLC off-chain yet, allowing us to delay sending the
+	/// `update_fulfill_htlc` while still being able to claim the HTLC on-chain in the meantime.
+	///
+	/// Returns `None` if no such HTLC is pending. Like the preimage update built when claiming an
+	/// HTLC, the returned update flies before any blocked monitor updates.
+	pub fn get_htlc_preimage_monitor_update(&mut self, htlc_id: u64, payment_preimage: PaymentPreimage) -> Option<&ChannelMonitorUpdate> {
+		if !self.context.pending_inbound_htlcs.iter().any(|htlc| htlc.htlc_id == htlc_id) {
+			return None;
+		}
+		self.context.latest_monitor_update_id += 1;
+		let insert_pos = self.context.pending_monitor_updates.iter().position(|upd| upd.blocked)
+			.unwrap_or(self.context.pending_monitor_updates.len());
+		let update_id = self.context.pending_monitor_updates.get(insert_pos)
+			.map(|upd| upd.update.update_id).unwrap_or(self.context.latest_monitor_update_id);
+		self.context.pending_monitor_updates.insert(insert_pos, PendingChannelMonitorUpdate {
+			update: ChannelMonitorUpdate {
+				update_id,
+				updates: vec![ChannelMonitorUpdateStep::PaymentPreimage { payment_preimage }],
+			},
+			blocked: false,
+		});
+		for held_update in self.context.pending_monitor_updates.iter_mut().skip(insert_pos + 1) {
+			held_update.update.update_id += 1;
+		}
+		self.monitor_updating_paused(false, false, false, Vec::new(), Vec::new(), Vec::new());
+		self.context.pending_monitor_updates.get(insert_pos).map(|upd| &upd.update)
+	}
+
 	/// We can only have one resolution per HTLC. In some cases around reconnect, we may fulfill
 	/// an HTLC more than once or fulfill once and then attempt to fail after reconnect. We cannot,
 	/// however, fail more than once as we wait for an upstream failure to be irrevocably committed
@@ -2321,7 +2865,18 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 	/// [`ChannelError::Ignore`].
 	pub fn queue_fail_htlc<L: Deref>(&mut self, htlc_id_arg: u64, err_packet: msgs::OnionErrorPacket, logger: &L)
 	-> Result<(), ChannelError> where L::Target: Logger {
-		self.fail_htlc(htlc_id_arg, err_packet, true, logger)
+		self.fail_htlc(htlc_id_arg, HTLCFailureContents::Relay(err_packet), true, logger)
+			.map(|msg_opt| assert!(msg_opt.is_none(), "We forced holding cell?"))
+	}
+
+	/// Used for failing back with [`msgs::UpdateFailMalformedHTLC`]. For now, this is used when we
+	/// want to fail blinded HTLCs where we are not the intro node.
+	///
+	/// See [`Self::queue_fail_htlc`] for more info.
+	pub fn queue_fail_malformed_htlc<L: Deref>(&mut self, htlc_id_arg: u64, failure_code: u16,
+		sha256_of_onion: [u8; 32], logger: &L
+	) -> Result<(), ChannelError> where L::Target: Logger {
+		self.fail_htlc(htlc_id_arg, HTLCFailureContents::Malformed { failure_code, sha256_of_onion }, true, logger)
 			.map(|msg_opt| assert!(msg_opt.is_none(), "We forced holding cell?"))
 	}
 
@@ -2333,8 +2888,8 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 	/// If we do fail twice, we `debug_assert!(false)` and return `Ok(None)`. Thus, this will always
 	/// return `Ok(_)` if preconditions are met. In any case, `Err`s will only be
 	/// [`ChannelError::Ignore`].
-	fn fail_htlc<L: Deref>(&mut self, htlc_id_arg: u64, err_packet: msgs::OnionErrorPacket, mut force_holding_cell: bool, logger: &L)
-	-> Result<Option<msgs::UpdateFailHTLC>, ChannelError> where L::Target: Logger {
+	fn fail_htlc<L: Deref>(&mut self, htlc_id_arg: u64, contents: HTLCFailureContents, mut force_holding_cell: bool, logger: &L)
+	-> Result<Option<HTLCFailureMsg>, ChannelError> where L::Target: Logger {
 		if (self.context.channel_state & (ChannelState::ChannelReady as u32)) != (ChannelState::ChannelReady as u32) {
 			panic!("Was asked to fail an HTLC when channel was not in an operational state");
 		}
@@ -2388,7 +2943,8 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 							return Ok(None);
 						}
 					},
-					&HTLCUpdateAwaitingACK::FailHTLC { htlc_id, .. } => {
+					&HTLCUpdateAwaitingACK::FailHTLC { htlc_id, .. } |
+					&HTLCUpdateAwaitingACK::FailMalformedHTLC { htlc_id, .. } => {
 						if htlc_id_arg == htlc_id {
 							debug_assert!(false, "Tried to fail an HTLC that was already failed");
 							return Err(ChannelError::Ignore("Unable to find a pending HTLC which matched the given HTLC ID".to_owned()));
@@ -2398,24 +2954,43 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 				}
 			}
 			log_trace!(logger, "Placing failure for HTLC ID {} in holding cell in channel {}.", htlc_id_arg, log_bytes!(self.context.channel_id()));
-			self.context.holding_cell_htlc_updates.push(HTLCUpdateAwaitingACK::FailHTLC {
-				htlc_id: htlc_id_arg,
-				err_packet,
+			self.context.holding_cell_htlc_updates.push(match contents {
+				HTLCFailureContents::Relay(err_packet) => HTLCUpdateAwaitingACK::FailHTLC {
+					htlc_id: htlc_id_arg,
+					err_packet,
+				},
+				HTLCFailureContents::Malformed { failure_code, sha256_of_onion } => HTLCUpdateAwaitingACK::FailMalformedHTLC {
+					htlc_id: htlc_id_arg,
+					failure_code,
+					sha256_of_onion,
+				},
 			});
 			return Ok(None);
 		}
 
-		log_trace!(logger, "Failing HTLC ID {} back with a update_fail_htlc message in channel {}.", htlc_id_arg, log_bytes!(self.context.channel_id()));
-		{
-			let htlc = &mut self.context.pending_inbound_htlcs[pending_idx];
-			htlc.state = InboundHTLCState::LocalRemoved(InboundHTLCRemovalReason::FailRelay(err_packet.clone()));
+		let channel_id = self.context.channel_id();
+		let htlc = &mut self.context.pending_inbound_htlcs[pending_idx];
+		match contents {
+			HTLCFailureContents::Relay(err_packet) => {
+				log_trace!(logger, "Failing HTLC ID {} back with a update_fail_htlc message in channel {}.", htlc_id_arg, log_bytes!(channel_id));
+				htlc.state = InboundHTLCState::LocalRemoved(InboundHTLCRemovalReason::FailRelay(err_packet.clone()));
+				Ok(Some(HTLCFailureMsg::Relay(msgs::UpdateFailHTLC {
+					channel_id,
+					htlc_id: htlc_id_arg,
+					reason: err_packet
+				})))
+			},
+			HTLCFailureContents::Malformed { failure_code, sha256_of_onion } => {
+				log_trace!(logger, "Failing HTLC ID {} back with a update_fail_malformed_htlc message in channel {}.", htlc_id_arg, log_bytes!(channel_id));
+				htlc.state = InboundHTLCState::LocalRemoved(InboundHTLCRemovalReason::FailMalformed((sha256_of_onion, failure_code)));
+				Ok(Some(HTLCFailureMsg::Malformed(msgs::UpdateFailMalformedHTLC {
+					channel_id,
+					htlc_id: htlc_id_arg,
+					sha256_of_onion,
+					failure_code,
+				})))
+			},
 		}
-
-		Ok(Some(msgs::UpdateFailHTLC {
-			channel_id: self.context.channel_id(),
-			htlc_id: htlc_id_arg,
-			reason: err_packet
-		}))
 	}
 
 	// Message handlers:
@@ -2525,6 +3100,12 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 				// The scid alias provided can be used to route payments *from* our counterparty,
 				// i.e. can be used for inbound payments and provided in invoices, but is not used
 				// when routing outbound payments.
+				if !self.context.inbound_scid_aliases.contains(&scid_alias) {
+					if self.context.inbound_scid_aliases.len() >= MAX_SCID_ALIASES_PER_CHANNEL {
+						self.context.inbound_scid_aliases.remove(0);
+					}
+					self.context.inbound_scid_aliases.push(scid_alias);
+				}
 				self.context.latest_inbound_scid_alias = Some(scid_alias);
 			}
 		}
@@ -2657,6 +3238,15 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 			}
 		}
 
+		if let Some(max_pending_htlc_value_msat) = self.context.feerate_disagreement_max_pending_htlc_value_msat {
+			let pending_htlcs_value_msat = inbound_stats.pending_htlcs_value_msat + outbound_stats.pending_htlcs_value_msat + msg.amount_msat;
+			if pending_htlcs_value_msat > max_pending_htlc_value_msat {
+				log_info!(logger, "Cannot accept HTLC that would put the value pending in channel {} at {} over the limit {} during a feerate disagreement",
+					log_bytes!(self.context.channel_id()), pending_htlcs_value_msat, max_pending_htlc_value_msat);
+				pending_forward_status = create_pending_htlc_status(self, pending_forward_status, 0x1000|7);
+			}
+		}
+
 		let pending_value_to_self_msat =
 			self.context.value_to_self_msat + inbound_stats.pending_htlcs_value_msat - removed_outbound_total_msat;
 		let pending_remote_value_msat =
@@ -2738,8 +3328,7 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 				let outcome = match check_preimage {
 					None => fail_reason.into(),
 					Some(payment_preimage) => {
-						let payment_hash = PaymentHash(Sha256::hash(&payment_preimage.0[..]).into_inner());
-						if payment_hash != htlc.payment_hash {
+						if !htlc.lock().is_unlocked_by_preimage(&payment_preimage) {
 							return Err(ChannelError::Close(format!("Remote tried to fulfill HTLC ({}) with an incorrect preimage", htlc_id)));
 						}
 						OutboundHTLCOutcome::Success(Some(payment_preimage))
@@ -2804,7 +3393,9 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
 			return Err(ChannelError::Close("Peer sent commitment_signed when we needed a channel_reestablish".to_owned()));
 		}
-		if self.context.channel_state & BOTH_SIDES_SHUTDOWN_MASK == BOTH_SIDES_SHUTDOWN_MASK && self.context.last_sent_closing_fee.is_some() {
+		if self.context.channel_state & BOTH_SIDES_SHUTDOWN_MASK == BOTH_SIDES_SHUTDOWN_MASK &&
+			(self.context.last_sent_closing_fee.is_some() || self.context.last_sent_closing_complete.is_some())
+		{
 			return Err(ChannelError::Close("Peer sent commitment_signed after we'd started exchanging closing_signeds".to_owned()));
 		}
 
@@ -3052,8 +3643,8 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 				// handling this case better and maybe fulfilling some of the HTLCs while attempting
 				// to rebalance channels.
 				match &htlc_update {
-					&HTLCUpdateAwaitingACK::AddHTLC {amount_msat, cltv_expiry, ref payment_hash, ref source, ref onion_routing_packet, ..} => {
-						match self.send_htlc(amount_msat, *payment_hash, cltv_expiry, source.clone(), onion_routing_packet.clone(), false, logger) {
+					&HTLCUpdateAwaitingACK::AddHTLC {amount_msat, cltv_expiry, ref payment_hash, ref source, ref onion_routing_packet, blinding_point, ..} => {
+						match self.send_htlc(amount_msat, *payment_hash, cltv_expiry, source.clone(), onion_routing_packet.clone(), blinding_point, false, logger) {
 							Ok(update_add_msg_option) => update_add_htlcs.push(update_add_msg_option.unwrap()),
 							Err(e) => {
 								match e {
@@ -3088,8 +3679,15 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 						update_fulfill_htlcs.push(update_fulfill_msg_option.unwrap());
 						monitor_update.updates.append(&mut additional_monitor_update.updates);
 					},
-					&HTLCUpdateAwaitingACK::FailHTLC { htlc_id, ref err_packet } => {
-						match self.fail_htlc(htlc_id, err_packet.clone(), false, logger) {
+					&HTLCUpdateAwaitingACK::FailHTLC { .. } | &HTLCUpdateAwaitingACK::FailMalformedHTLC { .. } => {
+						let (htlc_id, contents) = match &htlc_update {
+							&HTLCUpdateAwaitingACK::FailHTLC { htlc_id, ref err_packet } =>
+								(htlc_id, HTLCFailureContents::Relay(err_packet.clone())),
+							&HTLCUpdateAwaitingACK::FailMalformedHTLC { htlc_id, failure_code, sha256_of_onion } =>
+								(htlc_id, HTLCFailureContents::Malformed { failure_code, sha256_of_onion }),
+							_ => unreachable!(),
+						};
+						match self.fail_htlc(htlc_id, contents, false, logger) {
 							Ok(update_fail_msg_option) => {
 								// If an HTLC failure was previously added to the holding cell (via
 								// `queue_fail_htlc`) then generating the fail message itself must
@@ -3148,7 +3746,9 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
 			return Err(ChannelError::Close("Peer sent revoke_and_ack when we needed a channel_reestablish".to_owned()));
 		}
-		if self.context.channel_state & BOTH_SIDES_SHUTDOWN_MASK == BOTH_SIDES_SHUTDOWN_MASK && self.context.last_sent_closing_fee.is_some() {
+		if self.context.channel_state & BOTH_SIDES_SHUTDOWN_MASK == BOTH_SIDES_SHUTDOWN_MASK &&
+			(self.context.last_sent_closing_fee.is_some() || self.context.last_sent_closing_complete.is_some())
+		{
 			return Err(ChannelError::Close("Peer sent revoke_and_ack after we'd started exchanging closing_signeds".to_owned()));
 		}
 
@@ -3468,6 +4068,8 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 		// will be retransmitted.
 		self.context.last_sent_closing_fee = None;
 		self.context.pending_counterparty_closing_signed = None;
+		self.context.last_sent_closing_complete = None;
+		self.context.pending_counterparty_closing_complete = None;
 		self.context.closing_fee_limits = None;
 
 		let mut inbound_drop_count = 0;
@@ -3577,6 +4179,9 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 		if self.context.channel_state & !MULTI_STATE_FLAGS >= ChannelState::ChannelReady as u32 && self.context.minimum_depth != Some(0) {
 			funding_broadcastable = None;
 		}
+		if let Some(tx) = funding_broadcastable.as_ref() {
+			self.context.broadcast_funding_transaction = Some(tx.clone());
+		}
 
 		// We will never broadcast the funding transaction when we're in MonitorUpdateInProgress
 		// (and we assume the user never directly broadcasts the funding transaction and waits for
@@ -3592,7 +4197,7 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 			Some(msgs::ChannelReady {
 				channel_id: self.context.channel_id(),
 				next_per_commitment_point,
-				short_channel_id_alias: Some(self.context.outbound_scid_alias),
+				short_channel_id_alias: Some(self.context.current_outbound_scid_alias()),
 			})
 		} else { None };
 
@@ -3634,7 +4239,11 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 		}
 	}
 
-	pub fn update_fee<F: Deref, L: Deref>(&mut self, fee_estimator: &LowerBoundedFeeEstimator<F>, msg: &msgs::UpdateFee, logger: &L) -> Result<(), ChannelError>
+	/// Handles an `update_fee` from our counterparty, returning whether the new feerate started a
+	/// feerate disagreement we're tolerating per [`FeerateDisagreementConfig::grace_period_ticks`].
+	pub fn update_fee<F: Deref, L: Deref>(&mut self, fee_estimator: &LowerBoundedFeeEstimator<F>,
+		msg: &msgs::UpdateFee, disagreement_config: &FeerateDisagreementConfig, logger: &L
+	) -> Result<bool, ChannelError>
 		where F::Target: FeeEstimator, L::Target: Logger
 	{
 		if self.context.is_outbound() {
@@ -3643,7 +4252,23 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
 			return Err(ChannelError::Close("Peer sent update_fee when we needed a channel_reestablish".to_owned()));
 		}
-		Channel::<Signer>::check_remote_fee(fee_estimator, msg.feerate_per_kw, Some(self.context.feerate_per_kw), logger)?;
+		let mut disagreement_started = false;
+		match Channel::<Signer>::check_remote_fee(fee_estimator, msg.feerate_per_kw, Some(self.context.feerate_per_kw), logger) {
+			Ok(()) => {
+				self.context.feerate_disagreement_ticks = None;
+				self.context.feerate_disagreement_max_pending_htlc_value_msat = None;
+			},
+			Err(ChannelError::Close(err)) if disagreement_config.grace_period_ticks.is_some() => {
+				log_warn!(logger, "Tolerating feerate disagreement with our counterparty on channel {} for now: {}",
+					log_bytes!(self.context.channel_id()), err);
+				self.context.feerate_disagreement_max_pending_htlc_value_msat = Some(disagreement_config.max_pending_htlc_value_msat);
+				if self.context.feerate_disagreement_ticks.is_none() {
+					self.context.feerate_disagreement_ticks = Some(0);
+					disagreement_started = true;
+				}
+			},
+			Err(e) => return Err(e),
+		}
 		let feerate_over_dust_buffer = msg.feerate_per_kw > self.context.get_dust_buffer_feerate(None);
 
 		self.context.pending_update_fee = Some((msg.feerate_per_kw, FeeUpdateState::RemoteAnnounced));
@@ -3665,6 +4290,43 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 					msg.feerate_per_kw, counterparty_tx_dust_exposure)));
 			}
 		}
+		Ok(disagreement_started)
+	}
+
+	/// Checks whether a feerate disagreement we're tolerating has been resolved, possibly
+	/// returning an Err if it persisted past [`FeerateDisagreementConfig::grace_period_ticks`] or
+	/// too much value is pending in HTLCs, in which case the channel should be force-closed.
+	/// Should be called on a one-minute timer.
+	pub fn timer_check_feerate_disagreement<F: Deref, L: Deref>(&mut self,
+		fee_estimator: &LowerBoundedFeeEstimator<F>, disagreement_config: &FeerateDisagreementConfig,
+		logger: &L
+	) -> Result<(), ChannelError> where F::Target: FeeEstimator, L::Target: Logger {
+		let ticks = match self.context.feerate_disagreement_ticks {
+			Some(ticks) => ticks.saturating_add(1),
+			None => return Ok(()),
+		};
+		let feerate_per_kw = match self.context.pending_update_fee {
+			Some((feerate, _)) => feerate,
+			None => self.context.feerate_per_kw,
+		};
+		if Channel::<Signer>::check_remote_fee(fee_estimator, feerate_per_kw, None, logger).is_ok() {
+			log_info!(logger, "Feerate disagreement with our counterparty on channel {} resolved",
+				log_bytes!(self.context.channel_id()));
+			self.context.feerate_disagreement_ticks = None;
+			self.context.feerate_disagreement_max_pending_htlc_value_msat = None;
+			return Ok(());
+		}
+		self.context.feerate_disagreement_ticks = Some(ticks);
+		self.context.feerate_disagreement_max_pending_htlc_value_msat = Some(disagreement_config.max_pending_htlc_value_msat);
+		if ticks >= disagreement_config.grace_period_ticks.unwrap_or(0) {
+			return Err(ChannelError::Close(format!("Feerate disagreement (at {} sat/kW) persisted for {} timer ticks", feerate_per_kw, ticks)));
+		}
+		let pending_htlcs_value_msat = self.context.get_inbound_pending_htlc_stats(None).pending_htlcs_value_msat +
+			self.context.get_outbound_pending_htlc_stats(None).pending_htlcs_value_msat;
+		if pending_htlcs_value_msat > disagreement_config.max_pending_htlc_value_msat {
+			return Err(ChannelError::Close(format!("Pending HTLCs (totaling {} msat) exceed what we allow during a feerate disagreement (at {} sat/kW)",
+				pending_htlcs_value_msat, feerate_per_kw)));
+		}
 		Ok(())
 	}
 
@@ -3694,6 +4356,7 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 					amount_msat: htlc.amount_msat,
 					payment_hash: htlc.payment_hash,
 					cltv_expiry: htlc.cltv_expiry,
+					blinding_point: htlc.blinding_point,
 					onion_routing_packet: (**onion_packet).clone(),
 				});
 			}
@@ -3747,10 +4410,8 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 	/// May panic if some calls other than message-handling calls (which will all Err immediately)
 	/// have been called between remove_uncommitted_htlcs_and_mark_paused and this call.
 	///
-	/// Some links printed in log lines are included here to check them during build (when run with
-	/// `cargo doc --document-private-items`):
-	/// [`super::channelmanager::ChannelManager::force_close_without_broadcasting_txn`] and
-	/// [`super::channelmanager::ChannelManager::force_close_all_channels_without_broadcasting_txn`].
+	/// If the peer proves we have fallen behind, [`ChannelContext::counterparty_stale_state_point`]
+	/// is set and a close-required error is returned.
 	pub fn channel_reestablish<L: Deref, NS: Deref>(
 		&mut self, msg: &msgs::ChannelReestablish, logger: &L, node_signer: &NS,
 		genesis_block_hash: BlockHash, user_config: &UserConfig, best_block: &BestBlock
@@ -3779,20 +4440,15 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 				return Err(ChannelError::Close("Peer sent a garbage channel_reestablish with secret key not matching the commitment height provided".to_owned()));
 			}
 			if msg.next_remote_commitment_number > INITIAL_COMMITMENT_NUMBER - self.context.cur_holder_commitment_transaction_number {
-				macro_rules! log_and_panic {
-					($err_msg: expr) => {
-						log_error!(logger, $err_msg, log_bytes!(self.context.channel_id), log_pubkey!(self.context.counterparty_node_id));
-						panic!($err_msg, log_bytes!(self.context.channel_id), log_pubkey!(self.context.counterparty_node_id));
-					}
-				}
-				log_and_panic!("We have fallen behind - we have received proof that if we broadcast our counterparty is going to claim all our funds.\n\
+				log_error!(logger, "We have fallen behind - we have received proof that if we broadcast our counterparty is going to claim all our funds.\n\
 					This implies you have restarted with lost ChannelMonitor and ChannelManager state, the first of which is a violation of the LDK chain::Watch requirements.\n\
 					More specifically, this means you have a bug in your implementation that can cause loss of funds, or you are running with an old backup, which is unsafe.\n\
-					If you have restored from an old backup and wish to force-close channels and return to operation, you should start up, call\n\
-					ChannelManager::force_close_without_broadcasting_txn on channel {} with counterparty {} or\n\
-					ChannelManager::force_close_all_channels_without_broadcasting_txn, then reconnect to peer(s).\n\
+					Closing channel {} with counterparty {} without broadcasting, and waiting for our counterparty to broadcast its latest commitment transaction.\n\
 					Note that due to a long-standing bug in lnd you may have to reach out to peers running lnd-based nodes to ask them to manually force-close channels\n\
-					See https://github.com/lightningdevkit/rust-lightning/issues/1565 for more info.");
+					See https://github.com/lightningdevkit/rust-lightning/issues/1565 for more info.",
+					log_bytes!(self.context.channel_id), log_pubkey!(self.context.counterparty_node_id));
+				self.context.counterparty_stale_state_point = Some(msg.my_current_per_commitment_point);
+				return Err(ChannelError::Close("We have fallen behind - peer proved our latest commitment transaction has been revoked".to_owned()));
 			}
 		}
 
@@ -3815,6 +4471,7 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 			Some(msgs::Shutdown {
 				channel_id: self.context.channel_id,
 				scriptpubkey: self.get_closing_scriptpubkey(),
+				custom_tlvs: Vec::new(),
 			})
 		} else { None };
 
@@ -3842,7 +4499,7 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 				channel_ready: Some(msgs::ChannelReady {
 					channel_id: self.context.channel_id(),
 					next_per_commitment_point,
-					short_channel_id_alias: Some(self.context.outbound_scid_alias),
+					short_channel_id_alias: Some(self.context.current_outbound_scid_alias()),
 				}),
 				raa: None, commitment_update: None,
 				order: RAACommitmentOrder::CommitmentFirst,
@@ -3881,7 +4538,7 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 			Some(msgs::ChannelReady {
 				channel_id: self.context.channel_id(),
 				next_per_commitment_point,
-				short_channel_id_alias: Some(self.context.outbound_scid_alias),
+				short_channel_id_alias: Some(self.context.current_outbound_scid_alias()),
 			})
 		} else { None };
 
@@ -3934,10 +4591,10 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 	{
 		if let Some((min, max)) = self.context.closing_fee_limits { return (min, max); }
 
-		// Propose a range from our current Background feerate to our Normal feerate plus our
-		// force_close_avoidance_max_fee_satoshis.
+		// Propose a range from our current ChannelCloseMinimum feerate to our Normal feerate plus
+		// our force_close_avoidance_max_fee_satoshis.
 		// If we fail to come to consensus, we'll have to force-close.
-		let mut proposed_feerate = fee_estimator.bounded_sat_per_1000_weight(ConfirmationTarget::Background);
+		let mut proposed_feerate = fee_estimator.bounded_sat_per_1000_weight(ConfirmationTarget::ChannelCloseMinimum);
 		let normal_feerate = fee_estimator.bounded_sat_per_1000_weight(ConfirmationTarget::Normal);
 		let mut proposed_max_feerate = if self.context.is_outbound() { normal_feerate } else { u32::max_value() };
 
@@ -4140,6 +4797,7 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 			Some(msgs::Shutdown {
 				channel_id: self.context.channel_id,
 				scriptpubkey: self.get_closing_scriptpubkey(),
+				custom_tlvs: Vec::new(),
 			})
 		} else { None };
 
@@ -4339,11 +4997,241 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 		}
 	}
 
+	/// Checks if we're ready to cooperatively close the channel using `option_simple_close` and, if
+	/// we have a balance to pay fees with, returns the [`msgs::ClosingComplete`] offering our
+	/// closing transactions.
+	///
+	/// Either side may offer closing transactions paying their own fee, regardless of which side
+	/// funded the channel, with the channel being closed by whichever is countersigned first.
+	///
+	/// If our counterparty's `closing_complete` was delayed waiting on a `ChannelMonitor` update,
+	/// it is handled here instead, returning our [`msgs::ClosingSig`] and the fully signed closing
+	/// transaction.
+	pub fn maybe_propose_closing_complete<F: Deref, L: Deref>(
+		&mut self, fee_estimator: &LowerBoundedFeeEstimator<F>, best_block_height: u32, logger: &L)
+		-> Result<(Option<msgs::ClosingComplete>, Option<(msgs::ClosingSig, Transaction)>), ChannelError>
+		where F::Target: FeeEstimator, L::Target: Logger
+	{
+		if !self.closing_negotiation_ready() {
+			return Ok((None, None));
+		}
+
+		if let Some(msg) = self.context.pending_counterparty_closing_complete.take() {
+			return self.closing_complete(&msg, logger).map(|res| (None, res));
+		}
+
+		if self.context.last_sent_closing_complete.is_some() {
+			return Ok((None, None));
+		}
+
+		let holder_balance_satoshis = self.context.value_to_self_msat / 1000;
+		let counterparty_balance_satoshis = (self.context.channel_value_satoshis * 1000 - self.context.value_to_self_msat) / 1000;
+		let include_closee_output = counterparty_balance_satoshis > self.context.counterparty_dust_limit_satoshis;
+
+		// We pay for the largest of the closing transactions we offer, i.e., including both outputs
+		// if our counterparty's isn't dust, but never more than we have.
+		let mut feerate = fee_estimator.bounded_sat_per_1000_weight(ConfirmationTarget::ChannelCloseMinimum);
+		if let Some(target_feerate) = self.context.target_closing_feerate_sats_per_kw {
+			feerate = cmp::max(feerate, target_feerate);
+		}
+		let closee_scriptpubkey = self.context.counterparty_shutdown_scriptpubkey.clone().unwrap();
+		let tx_weight = self.get_closing_transaction_weight(Some(&self.get_closing_scriptpubkey()),
+			if include_closee_output { Some(&closee_scriptpubkey) } else { None });
+		let our_fee = cmp::min(feerate as u64 * tx_weight / 1000, holder_balance_satoshis);
+		if our_fee == 0 {
+			log_trace!(logger, "Not proposing a closing transaction for channel {} as we have no balance to pay its fee with",
+				log_bytes!(self.context.channel_id()));
+			return Ok((None, None));
+		}
+
+		let include_closer_output = holder_balance_satoshis > our_fee &&
+			holder_balance_satoshis - our_fee > self.context.holder_dust_limit_satoshis;
+		if !include_closer_output && !include_closee_output {
+			return Err(ChannelError::Close("Unable to build a closing transaction as both outputs would be dust".to_owned()));
+		}
+		// If our own output would be dust we'd rather give all of it to fees.
+		let fee_satoshis = if include_closer_output { our_fee } else { holder_balance_satoshis };
+		let locktime = best_block_height;
+
+		macro_rules! sign_closing_tx {
+			($include_closer_output: expr, $include_closee_output: expr) => { {
+				let closing_tx = self.build_simple_closing_transaction(true, fee_satoshis, locktime,
+					$include_closer_output, $include_closee_output)
+					.ok_or_else(|| ChannelError::Close("Unable to build a closing transaction".to_owned()))?;
+				self.context.holder_signer
+					.sign_closing_transaction(&closing_tx, &self.context.secp_ctx)
+					.map_err(|()| ChannelError::Close("Failed to get signature for closing transaction.".to_owned()))?
+			} }
+		}
+
+		// We always offer a transaction without our counterparty's output if we have one of our own,
+		// allowing our counterparty to drop its output if it considers it uneconomical.
+		let closer_output_only = if include_closer_output { Some(sign_closing_tx!(true, false)) } else { None };
+		let closee_output_only = if !include_closer_output { Some(sign_closing_tx!(false, true)) } else { None };
+		let closer_and_closee_outputs = if include_closer_output && include_closee_output {
+			Some(sign_closing_tx!(true, true))
+		} else { None };
+
+		log_trace!(logger, "Proposing a closing transaction for channel {} paying {} sat in fees with locktime {}",
+			log_bytes!(self.context.channel_id()), fee_satoshis, locktime);
+
+		let msg = msgs::ClosingComplete {
+			channel_id: self.context.channel_id,
+			closer_scriptpubkey: self.get_closing_scriptpubkey(),
+			closee_scriptpubkey,
+			fee_satoshis,
+			locktime,
+			closer_output_only,
+			closee_output_only,
+			closer_and_closee_outputs,
+		};
+		self.context.last_sent_closing_complete = Some(msg.clone());
+		Ok((Some(msg), None))
+	}
+
+	/// Handles a `closing_complete` from our counterparty, countersigning one of the closing
+	/// transactions it offered. On success, returns the [`msgs::ClosingSig`] to send back and the
+	/// fully signed closing transaction, at which point the channel is closed.
+	pub fn closing_complete<L: Deref>(&mut self, msg: &msgs::ClosingComplete, logger: &L)
+		-> Result<Option<(msgs::ClosingSig, Transaction)>, ChannelError>
+		where L::Target: Logger
+	{
+		if self.context.channel_state & BOTH_SIDES_SHUTDOWN_MASK != BOTH_SIDES_SHUTDOWN_MASK {
+			return Err(ChannelError::Close("Remote end sent us a closing_complete before both sides provided a shutdown".to_owned()));
+		}
+		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
+			return Err(ChannelError::Close("Peer sent closing_complete when we needed a channel_reestablish".to_owned()));
+		}
+		if !self.context.pending_inbound_htlcs.is_empty() || !self.context.pending_outbound_htlcs.is_empty() {
+			return Err(ChannelError::Close("Remote end sent us a closing_complete while there were still pending HTLCs".to_owned()));
+		}
+		if msg.fee_satoshis > TOTAL_BITCOIN_SUPPLY_SATOSHIS {
+			return Err(ChannelError::Close("Remote tried to send us a closing tx with > 21 million BTC fee".to_owned()));
+		}
+
+		if self.context.channel_state & ChannelState::MonitorUpdateInProgress as u32 != 0 {
+			// If our counterparty replaces its closing_complete before we handle it, we only need to
+			// handle the latest one.
+			self.context.pending_counterparty_closing_complete = Some(msg.clone());
+			return Ok(None);
+		}
+
+		if Some(&msg.closer_scriptpubkey) != self.context.counterparty_shutdown_scriptpubkey.as_ref() {
+			return Err(ChannelError::Close(format!("Remote sent us a closing_complete with a closer_scriptpubkey ({}) which did not match their shutdown scriptpubkey", msg.closer_scriptpubkey.to_bytes().to_hex())));
+		}
+		if msg.closee_scriptpubkey != self.get_closing_scriptpubkey() {
+			return Err(ChannelError::Close(format!("Remote sent us a closing_complete with a closee_scriptpubkey ({}) which did not match our shutdown scriptpubkey", msg.closee_scriptpubkey.to_bytes().to_hex())));
+		}
+		let counterparty_balance_satoshis = (self.context.channel_value_satoshis * 1000 - self.context.value_to_self_msat) / 1000;
+		if msg.fee_satoshis > counterparty_balance_satoshis {
+			return Err(ChannelError::Close(format!("Remote sent us a closing_complete with a fee ({} sat) greater than their balance ({} sat)", msg.fee_satoshis, counterparty_balance_satoshis)));
+		}
+
+		// We insist on a closing transaction including our output unless it would be dust.
+		let holder_balance_satoshis = self.context.value_to_self_msat / 1000;
+		let (counterparty_sig, include_closer_output, include_closee_output) =
+			if holder_balance_satoshis > self.context.holder_dust_limit_satoshis {
+				if let Some(sig) = msg.closer_and_closee_outputs {
+					(sig, true, true)
+				} else if let Some(sig) = msg.closee_output_only {
+					(sig, false, true)
+				} else {
+					return Err(ChannelError::Warn("Remote sent us a closing_complete without a closing transaction including our output".to_owned()));
+				}
+			} else if let Some(sig) = msg.closer_output_only {
+				(sig, true, false)
+			} else {
+				return Err(ChannelError::Warn("Remote sent us a closing_complete without a closing transaction omitting our dust output".to_owned()));
+			};
+
+		let closing_tx = self.build_simple_closing_transaction(false, msg.fee_satoshis, msg.locktime,
+			include_closer_output, include_closee_output)
+			.ok_or_else(|| ChannelError::Close("Remote sent us a closing_complete for a closing transaction with no outputs".to_owned()))?;
+		let funding_redeemscript = self.context.get_funding_redeemscript();
+		let sighash = closing_tx.trust().get_sighash_all(&funding_redeemscript, self.context.channel_value_satoshis);
+		secp_check!(self.context.secp_ctx.verify_ecdsa(&sighash, &counterparty_sig, self.context.counterparty_funding_pubkey()), "Invalid closing tx signature from peer".to_owned());
+
+		for outp in closing_tx.trust().built_transaction().output.iter() {
+			if !outp.script_pubkey.is_witness_program() && outp.value < MAX_STD_OUTPUT_DUST_LIMIT_SATOSHIS {
+				return Err(ChannelError::Close("Remote sent us a closing_complete with a dust output. Always use segwit closing scripts!".to_owned()));
+			}
+		}
+
+		let sig = self.context.holder_signer
+			.sign_closing_transaction(&closing_tx, &self.context.secp_ctx)
+			.map_err(|_| ChannelError::Close("External signer refused to sign closing transaction".to_owned()))?;
+		let tx = self.build_signed_closing_transaction(&closing_tx, &counterparty_sig, &sig);
+		self.context.channel_state = ChannelState::ShutdownComplete as u32;
+		self.context.update_time_counter += 1;
+
+		log_trace!(logger, "Countersigned closing transaction for channel {} paying {} sat in fees",
+			log_bytes!(self.context.channel_id()), msg.fee_satoshis);
+
+		Ok(Some((msgs::ClosingSig {
+			channel_id: self.context.channel_id,
+			closer_scriptpubkey: msg.closer_scriptpubkey.clone(),
+			closee_scriptpubkey: msg.closee_scriptpubkey.clone(),
+			fee_satoshis: msg.fee_satoshis,
+			locktime: msg.locktime,
+			closer_output_only: if include_closer_output && !include_closee_output { Some(sig) } else { None },
+			closee_output_only: if !include_closer_output { Some(sig) } else { None },
+			closer_and_closee_outputs: if include_closer_output && include_closee_output { Some(sig) } else { None },
+		}, tx)))
+	}
+
+	/// Handles a `closing_sig` from our counterparty, countersigning one of the closing
+	/// transactions we offered in our last `closing_complete`. On success, returns the fully signed
+	/// closing transaction, at which point the channel is closed.
+	pub fn closing_sig(&mut self, msg: &msgs::ClosingSig) -> Result<Transaction, ChannelError> {
+		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
+			return Err(ChannelError::Close("Peer sent closing_sig when we needed a channel_reestablish".to_owned()));
+		}
+		let sent = match &self.context.last_sent_closing_complete {
+			Some(sent) => sent.clone(),
+			None => return Err(ChannelError::Close("Remote end sent us a closing_sig before we sent a closing_complete".to_owned())),
+		};
+		if msg.fee_satoshis != sent.fee_satoshis || msg.locktime != sent.locktime ||
+			msg.closer_scriptpubkey != sent.closer_scriptpubkey || msg.closee_scriptpubkey != sent.closee_scriptpubkey
+		{
+			return Err(ChannelError::Close("Remote sent us a closing_sig which did not match our closing_complete".to_owned()));
+		}
+
+		let (counterparty_sig, holder_sig, include_closer_output, include_closee_output) =
+			match (msg.closer_output_only, msg.closee_output_only, msg.closer_and_closee_outputs) {
+				(Some(counterparty_sig), None, None) if sent.closer_output_only.is_some() =>
+					(counterparty_sig, sent.closer_output_only.unwrap(), true, false),
+				(None, Some(counterparty_sig), None) if sent.closee_output_only.is_some() =>
+					(counterparty_sig, sent.closee_output_only.unwrap(), false, true),
+				(None, None, Some(counterparty_sig)) if sent.closer_and_closee_outputs.is_some() =>
+					(counterparty_sig, sent.closer_and_closee_outputs.unwrap(), true, true),
+				_ => return Err(ChannelError::Close("Remote sent us a closing_sig which did not countersign exactly one of our closing transactions".to_owned())),
+			};
+
+		let closing_tx = self.build_simple_closing_transaction(true, sent.fee_satoshis, sent.locktime,
+			include_closer_output, include_closee_output)
+			.ok_or_else(|| ChannelError::Close("Unable to rebuild our closing transaction".to_owned()))?;
+		let funding_redeemscript = self.context.get_funding_redeemscript();
+		let sighash = closing_tx.trust().get_sighash_all(&funding_redeemscript, self.context.channel_value_satoshis);
+		secp_check!(self.context.secp_ctx.verify_ecdsa(&sighash, &counterparty_sig, self.context.counterparty_funding_pubkey()), "Invalid closing tx signature from peer".to_owned());
+
+		let tx = self.build_signed_closing_transaction(&closing_tx, &counterparty_sig, &holder_sig);
+		self.context.channel_state = ChannelState::ShutdownComplete as u32;
+		self.context.update_time_counter += 1;
+		Ok(tx)
+	}
+
 	fn internal_htlc_satisfies_config(
-		&self, htlc: &msgs::UpdateAddHTLC, amt_to_forward: u64, outgoing_cltv_value: u32, config: &ChannelConfig,
+		&self, htlc: &msgs::UpdateAddHTLC, amt_to_forward: u64, outgoing_cltv_value: u32, blinded: bool,
+		config: &ChannelConfig,
 	) -> Result<(), (&'static str, u16)> {
-		let fee = amt_to_forward.checked_mul(config.forwarding_fee_proportional_millionths as u64)
-			.and_then(|prop_fee| (prop_fee / 1000000).checked_add(config.forwarding_fee_base_msat as u64));
+		let (fee_base_msat, fee_proportional_millionths) = if blinded {
+			(config.blinded_forwarding_fee_base_msat.unwrap_or(config.forwarding_fee_base_msat),
+				config.blinded_forwarding_fee_proportional_millionths.unwrap_or(config.forwarding_fee_proportional_millionths))
+		} else {
+			(config.forwarding_fee_base_msat, config.forwarding_fee_proportional_millionths)
+		};
+		let fee = amt_to_forward.checked_mul(fee_proportional_millionths as u64)
+			.and_then(|prop_fee| (prop_fee / 1000000).checked_add(fee_base_msat as u64));
 		if fee.is_none() || htlc.amount_msat < fee.unwrap() ||
 			(htlc.amount_msat - fee.unwrap()) < amt_to_forward {
 			return Err((
@@ -4357,19 +5245,34 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 				0x1000 | 13, // incorrect_cltv_expiry
 			));
 		}
+		if amt_to_forward < config.forwarding_htlc_minimum_msat {
+			return Err((
+				"HTLC amount was below the htlc_minimum_msat",
+				0x1000 | 11, // amount_below_minimum
+			));
+		}
+		if amt_to_forward > config.forwarding_htlc_maximum_msat {
+			return Err((
+				"HTLC amount was above the htlc_maximum_msat",
+				0x1000 | 7, // temporary_channel_failure
+			));
+		}
 		Ok(())
 	}
 
 	/// Determines whether the parameters of an incoming HTLC to be forwarded satisfy the channel's
 	/// [`ChannelConfig`]. This first looks at the channel's current [`ChannelConfig`], and if
 	/// unsuccessful, falls back to the previous one if one exists.
+	///
+	/// If `blinded` is set, the HTLC is being relayed within a blinded path and the blinded
+	/// forwarding fee overrides of the [`ChannelConfig`] apply.
 	pub fn htlc_satisfies_config(
-		&self, htlc: &msgs::UpdateAddHTLC, amt_to_forward: u64, outgoing_cltv_value: u32,
+		&self, htlc: &msgs::UpdateAddHTLC, amt_to_forward: u64, outgoing_cltv_value: u32, blinded: bool,
 	) -> Result<(), (&'static str, u16)> {
-		self.internal_htlc_satisfies_config(&htlc, amt_to_forward, outgoing_cltv_value, &self.context.config())
+		self.internal_htlc_satisfies_config(&htlc, amt_to_forward, outgoing_cltv_value, blinded, &self.context.config())
 			.or_else(|err| {
 				if let Some(prev_config) = self.context.prev_config() {
-					self.internal_htlc_satisfies_config(htlc, amt_to_forward, outgoing_cltv_value, &prev_config)
+					self.internal_htlc_satisfies_config(htlc, amt_to_forward, outgoing_cltv_value, blinded, &prev_config)
 				} else {
 					Err(err)
 				}
@@ -4571,6 +5474,12 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 			return None;
 		}
 
+		if self.context.funding_tx_confirmation_height != 0 {
+			// Once our funding transaction reached our minimum depth there's no point in
+			// re-broadcasting it or watching for double-spends of its inputs anymore.
+			self.context.broadcast_funding_transaction = None;
+		}
+
 		let non_shutdown_state = self.context.channel_state & (!MULTI_STATE_FLAGS);
 		let need_commitment_update = if non_shutdown_state == ChannelState::FundingSent as u32 {
 			self.context.channel_state |= ChannelState::OurChannelReady as u32;
@@ -4605,7 +5514,7 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 					return Some(msgs::ChannelReady {
 						channel_id: self.context.channel_id,
 						next_per_commitment_point,
-						short_channel_id_alias: Some(self.context.outbound_scid_alias),
+						short_channel_id_alias: Some(self.context.current_outbound_scid_alias()),
 					});
 				}
 			} else {
@@ -4615,6 +5524,41 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 		None
 	}
 
+	/// Adds a further outbound SCID alias which we'll recognize as this channel, returning a
+	/// `channel_ready` offering it to our counterparty if they're currently connected.
+	///
+	/// Once the new alias has been offered, we'll offer it instead of any prior alias in any
+	/// `channel_ready` we re-send later. Must only be called once the channel is usable.
+	pub fn add_outbound_scid_alias(&mut self, scid_alias: u64) -> Option<msgs::ChannelReady> {
+		debug_assert!(self.context.is_usable());
+		self.context.additional_outbound_scid_aliases.push(scid_alias);
+		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) != 0 {
+			return None;
+		}
+		// A `channel_ready` always carries our first per-commitment point, see `channel_ready`.
+		let next_per_commitment_point =
+			self.context.holder_signer.get_per_commitment_point(INITIAL_COMMITMENT_NUMBER - 1, &self.context.secp_ctx);
+		Some(msgs::ChannelReady {
+			channel_id: self.context.channel_id,
+			next_per_commitment_point,
+			short_channel_id_alias: Some(scid_alias),
+		})
+	}
+
+	/// Switches the inbound SCID alias we use, e.g. in invoice route hints, to the next one our
+	/// counterparty offered us, wrapping around to the oldest one we track. Returns the now-used
+	/// alias, or `None` if our counterparty never offered us any.
+	pub fn rotate_inbound_scid_alias(&mut self) -> Option<u64> {
+		let aliases = &self.context.inbound_scid_aliases;
+		if aliases.is_empty() { return None; }
+		let next_idx = self.context.latest_inbound_scid_alias
+			.and_then(|alias| aliases.iter().position(|a| *a == alias))
+			.map(|idx| (idx + 1) % aliases.len())
+			.unwrap_or(aliases.len() - 1);
+		self.context.latest_inbound_scid_alias = Some(aliases[next_idx]);
+		self.context.latest_inbound_scid_alias
+	}
+
 	/// When a transaction is confirmed, we check whether it is or spends the funding transaction
 	/// In the first case, we store the confirmation height and calculating the short channel id.
 	/// In the second, we simply return an Err indicating we need to be force-closed now.
@@ -4678,6 +5622,7 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 				for inp in tx.input.iter() {
 					if inp.previous_output == funding_txo.into_bitcoin_outpoint() {
 						log_info!(logger, "Detected channel-closing tx {} spending {}:{}, closing channel {}", tx.txid(), inp.previous_output.txid, inp.previous_output.vout, log_bytes!(self.context.channel_id()));
+						self.context.funding_spent_by_counterparty_commitment = self.context.is_counterparty_commitment_tx(tx);
 						return Err(ClosureReason::CommitmentTxConfirmed);
 					}
 				}
@@ -4769,7 +5714,9 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 				return Err(ClosureReason::ProcessingError { err: err_reason });
 			}
 		} else if !self.context.is_outbound() && self.context.funding_tx_confirmed_in.is_none() &&
-				height >= self.context.channel_creation_height + FUNDING_CONF_DEADLINE_BLOCKS {
+				height >= self.context.channel_creation_height + genesis_node_signer
+					.map(|(_, _, user_config)| user_config.resource_limits.funding_confirmation_deadline_blocks)
+					.unwrap_or(FUNDING_CONF_DEADLINE_BLOCKS) {
 			log_info!(logger, "Closing channel {} due to funding timeout", log_bytes!(self.context.channel_id));
 			// If funding_tx_confirmed_in is unset, the channel must not be active
 			assert!(non_shutdown_state <= ChannelState::ChannelReady as u32);
@@ -5043,10 +5990,10 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 	///
 	/// `Err`s will only be [`ChannelError::Ignore`].
 	pub fn queue_add_htlc<L: Deref>(&mut self, amount_msat: u64, payment_hash: PaymentHash, cltv_expiry: u32, source: HTLCSource,
-		onion_routing_packet: msgs::OnionPacket, logger: &L)
+		onion_routing_packet: msgs::OnionPacket, blinding_point: Option<PublicKey>, logger: &L)
 	-> Result<(), ChannelError> where L::Target: Logger {
 		self
-			.send_htlc(amount_msat, payment_hash, cltv_expiry, source, onion_routing_packet, true, logger)
+			.send_htlc(amount_msat, payment_hash, cltv_expiry, source, onion_routing_packet, blinding_point, true, logger)
 			.map(|msg_opt| assert!(msg_opt.is_none(), "We forced holding cell?"))
 			.map_err(|err| {
 				if let ChannelError::Ignore(_) = err { /* fine */ }
@@ -5055,6 +6002,39 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 			})
 	}
 
+	/// Checks whether an outbound HTLC of `amount_msat` can currently be added to this channel.
+	pub fn check_send_htlc(&self, amount_msat: u64) -> Result<(), SendHtlcError> {
+		if (self.context.channel_state & (ChannelState::ChannelReady as u32 | BOTH_SIDES_SHUTDOWN_MASK)) != (ChannelState::ChannelReady as u32) {
+			return Err(SendHtlcError::ChannelNotReady);
+		}
+		let channel_value_msat = self.context.channel_value_satoshis * 1000;
+		if amount_msat > channel_value_msat {
+			return Err(SendHtlcError::AmountExceedsChannelValue { amount_msat, channel_value_msat });
+		}
+
+		if amount_msat == 0 {
+			return Err(SendHtlcError::ZeroAmount);
+		}
+
+		let available_balances = self.context.get_available_balances();
+		if amount_msat < available_balances.next_outbound_htlc_minimum_msat {
+			return Err(SendHtlcError::AmountBelowHtlcMinimum {
+				amount_msat, next_outbound_htlc_minimum_msat: available_balances.next_outbound_htlc_minimum_msat,
+			});
+		}
+
+		if amount_msat > available_balances.next_outbound_htlc_limit_msat {
+			return Err(SendHtlcError::AmountAboveHtlcLimit {
+				amount_msat, next_outbound_htlc_limit_msat: available_balances.next_outbound_htlc_limit_msat,
+			});
+		}
+
+		if (self.context.channel_state & (ChannelState::PeerDisconnected as u32)) != 0 {
+			return Err(SendHtlcError::PeerDisconnected { counterparty_node_id: self.context.counterparty_node_id });
+		}
+		Ok(())
+	}
+
 	/// Adds a pending outbound HTLC to this channel, note that you probably want
 	/// [`Self::send_htlc_and_commit`] instead cause you'll want both messages at once.
 	///
@@ -5072,40 +6052,19 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 	///
 	/// `Err`s will only be [`ChannelError::Ignore`].
 	fn send_htlc<L: Deref>(&mut self, amount_msat: u64, payment_hash: PaymentHash, cltv_expiry: u32, source: HTLCSource,
-		onion_routing_packet: msgs::OnionPacket, mut force_holding_cell: bool, logger: &L)
+		onion_routing_packet: msgs::OnionPacket, blinding_point: Option<PublicKey>, mut force_holding_cell: bool, logger: &L)
 	-> Result<Option<msgs::UpdateAddHTLC>, ChannelError> where L::Target: Logger {
-		if (self.context.channel_state & (ChannelState::ChannelReady as u32 | BOTH_SIDES_SHUTDOWN_MASK)) != (ChannelState::ChannelReady as u32) {
-			return Err(ChannelError::Ignore("Cannot send HTLC until channel is fully established and we haven't started shutting down".to_owned()));
-		}
-		let channel_total_msat = self.context.channel_value_satoshis * 1000;
-		if amount_msat > channel_total_msat {
-			return Err(ChannelError::Ignore(format!("Cannot send amount {}, because it is more than the total value of the channel {}", amount_msat, channel_total_msat)));
-		}
-
-		if amount_msat == 0 {
-			return Err(ChannelError::Ignore("Cannot send 0-msat HTLC".to_owned()));
-		}
-
-		let available_balances = self.context.get_available_balances();
-		if amount_msat < available_balances.next_outbound_htlc_minimum_msat {
-			return Err(ChannelError::Ignore(format!("Cannot send less than our next-HTLC minimum - {} msat",
-				available_balances.next_outbound_htlc_minimum_msat)));
-		}
-
-		if amount_msat > available_balances.next_outbound_htlc_limit_msat {
-			return Err(ChannelError::Ignore(format!("Cannot send more than our next-HTLC maximum - {} msat",
-				available_balances.next_outbound_htlc_limit_msat)));
-		}
-
-		if (self.context.channel_state & (ChannelState::PeerDisconnected as u32)) != 0 {
+		self.check_send_htlc(amount_msat).map_err(|e| match e {
 			// Note that this should never really happen, if we're !is_live() on receipt of an
 			// incoming HTLC for relay will result in us rejecting the HTLC and we won't allow
 			// the user to send directly into a !is_live() channel. However, if we
 			// disconnected during the time the previous hop was doing the commitment dance we may
 			// end up getting here after the forwarding delay. In any case, returning an
 			// IgnoreError will get ChannelManager to do the right thing and fail backwards now.
-			return Err(ChannelError::Ignore("Cannot send an HTLC while disconnected from channel counterparty".to_owned()));
-		}
+			SendHtlcError::PeerDisconnected { .. } =>
+				ChannelError::Ignore("Cannot send an HTLC while disconnected from channel counterparty".to_owned()),
+			e => ChannelError::Ignore(e.to_string()),
+		})?;
 
 		let need_holding_cell = (self.context.channel_state & (ChannelState::AwaitingRemoteRevoke as u32 | ChannelState::MonitorUpdateInProgress as u32)) != 0;
 		log_debug!(logger, "Pushing new outbound HTLC for {} msat {}", amount_msat,
@@ -5125,6 +6084,7 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 				cltv_expiry,
 				source,
 				onion_routing_packet,
+				blinding_point,
 			});
 			return Ok(None);
 		}
@@ -5136,6 +6096,7 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 			cltv_expiry,
 			state: OutboundHTLCState::LocalAnnounced(Box::new(onion_routing_packet.clone())),
 			source,
+			blinding_point,
 		});
 
 		let res = msgs::UpdateAddHTLC {
@@ -5144,6 +6105,7 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 			amount_msat,
 			payment_hash,
 			cltv_expiry,
+			blinding_point,
 			onion_routing_packet,
 		};
 		self.context.next_holder_htlc_id += 1;
@@ -5284,7 +6246,7 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 	/// Shorthand for calling [`Self::send_htlc`] followed by a commitment update, see docs on
 	/// [`Self::send_htlc`] and [`Self::build_commitment_no_state_update`] for more info.
 	pub fn send_htlc_and_commit<L: Deref>(&mut self, amount_msat: u64, payment_hash: PaymentHash, cltv_expiry: u32, source: HTLCSource, onion_routing_packet: msgs::OnionPacket, logger: &L) -> Result<Option<&ChannelMonitorUpdate>, ChannelError> where L::Target: Logger {
-		let send_res = self.send_htlc(amount_msat, payment_hash, cltv_expiry, source, onion_routing_packet, false, logger);
+		let send_res = self.send_htlc(amount_msat, payment_hash, cltv_expiry, source, onion_routing_packet, None, false, logger);
 		if let Err(e) = &send_res { if let ChannelError::Ignore(_) = e {} else { debug_assert!(false, "Sending cannot trigger channel failure"); } }
 		match send_res? {
 			Some(_) => {
@@ -5296,17 +6258,35 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 		}
 	}
 
-	pub fn channel_update(&mut self, msg: &msgs::ChannelUpdate) -> Result<(), ChannelError> {
+	/// Handles a `channel_update` from our counterparty, returning whether it newly disabled the
+	/// channel in their direction.
+	pub fn channel_update(&mut self, msg: &msgs::ChannelUpdate) -> Result<bool, ChannelError> {
 		if msg.contents.htlc_minimum_msat >= self.context.channel_value_satoshis * 1000 {
 			return Err(ChannelError::Close("Minimum htlc value is greater than channel value".to_string()));
 		}
+		let was_disabled = match &self.context.counterparty_channel_update {
+			// Updates may be relayed to us out of order, only track the latest one.
+			Some(latest_update) if latest_update.timestamp > msg.contents.timestamp => return Ok(false),
+			Some(latest_update) => latest_update.is_disabled,
+			None => false,
+		};
 		self.context.counterparty_forwarding_info = Some(CounterpartyForwardingInfo {
 			fee_base_msat: msg.contents.fee_base_msat,
 			fee_proportional_millionths: msg.contents.fee_proportional_millionths,
 			cltv_expiry_delta: msg.contents.cltv_expiry_delta
 		});
+		let is_disabled = msg.contents.flags & 2 == 2;
+		self.context.counterparty_channel_update = Some(CounterpartyChannelUpdate {
+			timestamp: msg.contents.timestamp,
+			is_disabled,
+			fee_base_msat: msg.contents.fee_base_msat,
+			fee_proportional_millionths: msg.contents.fee_proportional_millionths,
+			cltv_expiry_delta: msg.contents.cltv_expiry_delta,
+			htlc_minimum_msat: msg.contents.htlc_minimum_msat,
+			htlc_maximum_msat: msg.contents.htlc_maximum_msat,
+		});
 
-		Ok(())
+		Ok(is_disabled && !was_disabled)
 	}
 
 	/// Begins the shutdown process, getting a message for the remote peer and returning all
@@ -5316,27 +6296,30 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 	/// [`ChannelMonitorUpdate`] will be returned).
 	pub fn get_shutdown<SP: Deref>(&mut self, signer_provider: &SP, their_features: &InitFeatures,
 		target_feerate_sats_per_kw: Option<u32>, override_shutdown_script: Option<ShutdownScript>)
-	-> Result<(msgs::Shutdown, Option<&ChannelMonitorUpdate>, Vec<(HTLCSource, PaymentHash)>), APIError>
+	-> Result<(msgs::Shutdown, Option<&ChannelMonitorUpdate>, Vec<(HTLCSource, PaymentHash)>), CloseChannelError>
 	where SP::Target: SignerProvider {
 		for htlc in self.context.pending_outbound_htlcs.iter() {
 			if let OutboundHTLCState::LocalAnnounced(_) = htlc.state {
-				return Err(APIError::APIMisuseError{err: "Cannot begin shutdown with pending HTLCs. Process pending events first".to_owned()});
+				return Err(CloseChannelError::HtlcsPendingCommitment);
 			}
 		}
 		if self.context.channel_state & BOTH_SIDES_SHUTDOWN_MASK != 0 {
 			if (self.context.channel_state & ChannelState::LocalShutdownSent as u32) == ChannelState::LocalShutdownSent as u32 {
-				return Err(APIError::APIMisuseError{err: "Shutdown already in progress".to_owned()});
+				return Err(CloseChannelError::ShutdownInProgress);
 			}
 			else if (self.context.channel_state & ChannelState::RemoteShutdownSent as u32) == ChannelState::RemoteShutdownSent as u32 {
-				return Err(APIError::ChannelUnavailable{err: "Shutdown already in progress by remote".to_owned()});
+				return Err(CloseChannelError::ShutdownInitiatedByCounterparty);
 			}
 		}
 		if self.context.shutdown_scriptpubkey.is_some() && override_shutdown_script.is_some() {
-			return Err(APIError::APIMisuseError{err: "Cannot override shutdown script for a channel with one already set".to_owned()});
+			return Err(CloseChannelError::ShutdownScriptAlreadySet);
 		}
 		assert_eq!(self.context.channel_state & ChannelState::ShutdownComplete as u32, 0);
-		if self.context.channel_state & (ChannelState::PeerDisconnected as u32 | ChannelState::MonitorUpdateInProgress as u32) != 0 {
-			return Err(APIError::ChannelUnavailable{err: "Cannot begin shutdown while peer is disconnected or we're waiting on a monitor update, maybe force-close instead?".to_owned()});
+		if self.context.channel_state & ChannelState::PeerDisconnected as u32 != 0 {
+			return Err(CloseChannelError::PeerDisconnected);
+		}
+		if self.context.channel_state & ChannelState::MonitorUpdateInProgress as u32 != 0 {
+			return Err(CloseChannelError::MonitorUpdateInProgress);
 		}
 
 		// If we haven't funded the channel yet, we don't need to bother ensuring the shutdown
@@ -5356,12 +6339,12 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 						// otherwise, use the shutdown scriptpubkey provided by the signer
 						match signer_provider.get_shutdown_scriptpubkey() {
 							Ok(scriptpubkey) => scriptpubkey,
-							Err(_) => return Err(APIError::ChannelUnavailable{err: "Failed to get shutdown scriptpubkey".to_owned()}),
+							Err(_) => return Err(CloseChannelError::ShutdownScriptUnavailable),
 						}
 					},
 				};
 				if !shutdown_scriptpubkey.is_compatible(their_features) {
-					return Err(APIError::IncompatibleShutdownScript { script: shutdown_scriptpubkey.clone() });
+					return Err(CloseChannelError::IncompatibleShutdownScript { script: shutdown_scriptpubkey.clone() });
 				}
 				self.context.shutdown_scriptpubkey = Some(shutdown_scriptpubkey);
 				true
@@ -5394,6 +6377,7 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 		let shutdown = msgs::Shutdown {
 			channel_id: self.context.channel_id,
 			scriptpubkey: self.get_closing_scriptpubkey(),
+			custom_tlvs: Vec::new(),
 		};
 
 		// Go ahead and drop holding cell updates as we'd rather fail payments than wait to send
@@ -5439,7 +6423,7 @@ impl<Signer: WriteableEcdsaChannelSigner> OutboundV1Channel<Signer> {
 		fee_estimator: &LowerBoundedFeeEstimator<F>, entropy_source: &ES, signer_provider: &SP, counterparty_node_id: PublicKey, their_features: &InitFeatures,
 		channel_value_satoshis: u64, push_msat: u64, user_id: u128, config: &UserConfig, current_chain_height: u32,
 		outbound_scid_alias: u64
-	) -> Result<OutboundV1Channel<Signer>, APIError>
+	) -> Result<OutboundV1Channel<Signer>, OpenChannelError>
 	where ES::Target: EntropySource,
 	      SP::Target: SignerProvider<Signer = Signer>,
 	      F::Target: FeeEstimator,
@@ -5450,23 +6434,32 @@ impl<Signer: WriteableEcdsaChannelSigner> OutboundV1Channel<Signer> {
 		let pubkeys = holder_signer.pubkeys().clone();
 
 		if !their_features.supports_wumbo() && channel_value_satoshis > MAX_FUNDING_SATOSHIS_NO_WUMBO {
-			return Err(APIError::APIMisuseError{err: format!("funding_value must not exceed {}, it was {}", MAX_FUNDING_SATOSHIS_NO_WUMBO, channel_value_satoshis)});
+			return Err(OpenChannelError::ChannelValueTooHigh {
+				channel_value_satoshis, max_channel_value_satoshis: MAX_FUNDING_SATOSHIS_NO_WUMBO,
+			});
 		}
 		if channel_value_satoshis >= TOTAL_BITCOIN_SUPPLY_SATOSHIS {
-			return Err(APIError::APIMisuseError{err: format!("funding_value must be smaller than the total bitcoin supply, it was {}", channel_value_satoshis)});
+			return Err(OpenChannelError::ChannelValueTooHigh {
+				channel_value_satoshis, max_channel_value_satoshis: TOTAL_BITCOIN_SUPPLY_SATOSHIS - 1,
+			});
 		}
 		let channel_value_msat = channel_value_satoshis * 1000;
 		if push_msat > channel_value_msat {
-			return Err(APIError::APIMisuseError { err: format!("Push value ({}) was larger than channel_value ({})", push_msat, channel_value_msat) });
+			return Err(OpenChannelError::PushAmountTooHigh { push_msat, max_push_msat: channel_value_msat });
 		}
 		if holder_selected_contest_delay < BREAKDOWN_TIMEOUT {
-			return Err(APIError::APIMisuseError {err: format!("Configured with an unreasonable our_to_self_delay ({}) putting user funds at risks", holder_selected_contest_delay)});
+			return Err(OpenChannelError::ToSelfDelayTooLow {
+				our_to_self_delay: holder_selected_contest_delay, min_to_self_delay: BREAKDOWN_TIMEOUT,
+			});
 		}
 		let holder_selected_channel_reserve_satoshis = get_holder_selected_channel_reserve_satoshis(channel_value_satoshis, config);
-		if holder_selected_channel_reserve_satoshis < MIN_CHAN_DUST_LIMIT_SATOSHIS {
+		if !config.channel_handshake_config.their_channel_reserve_zero && holder_selected_channel_reserve_satoshis < MIN_CHAN_DUST_LIMIT_SATOSHIS {
 			// Protocol level safety check in place, although it should never happen because
 			// of `MIN_THEIR_CHAN_RESERVE_SATOSHIS`
-			return Err(APIError::APIMisuseError { err: format!("Holder selected channel  reserve below implemention limit dust_limit_satoshis {}", holder_selected_channel_reserve_satoshis) });
+			return Err(OpenChannelError::ChannelReserveTooLow {
+				channel_reserve_satoshis: holder_selected_channel_reserve_satoshis,
+				min_channel_reserve_satoshis: MIN_CHAN_DUST_LIMIT_SATOSHIS,
+			});
 		}
 
 		let channel_type = Self::get_initial_channel_type(&config, their_features);
@@ -5477,7 +6470,9 @@ impl<Signer: WriteableEcdsaChannelSigner> OutboundV1Channel<Signer> {
 		let value_to_self_msat = channel_value_satoshis * 1000 - push_msat;
 		let commitment_tx_fee = commit_tx_fee_msat(feerate, MIN_AFFORDABLE_HTLC_COUNT, channel_type.requires_anchors_zero_fee_htlc_tx());
 		if value_to_self_msat < commitment_tx_fee {
-			return Err(APIError::APIMisuseError{ err: format!("Funding amount ({}) can't even pay fee for initial commitment transaction fee of {}.", value_to_self_msat / 1000, commitment_tx_fee / 1000) });
+			return Err(OpenChannelError::InsufficientFundsForFees {
+				value_to_self_msat, commitment_tx_fee_msat: commitment_tx_fee,
+			});
 		}
 
 		let mut secp_ctx = Secp256k1::new();
@@ -5486,19 +6481,19 @@ impl<Signer: WriteableEcdsaChannelSigner> OutboundV1Channel<Signer> {
 		let shutdown_scriptpubkey = if config.channel_handshake_config.commit_upfront_shutdown_pubkey {
 			match signer_provider.get_shutdown_scriptpubkey() {
 				Ok(scriptpubkey) => Some(scriptpubkey),
-				Err(_) => return Err(APIError::ChannelUnavailable { err: "Failed to get shutdown scriptpubkey".to_owned()}),
+				Err(_) => return Err(OpenChannelError::ShutdownScriptUnavailable),
 			}
 		} else { None };
 
 		if let Some(shutdown_scriptpubkey) = &shutdown_scriptpubkey {
 			if !shutdown_scriptpubkey.is_compatible(&their_features) {
-				return Err(APIError::IncompatibleShutdownScript { script: shutdown_scriptpubkey.clone() });
+				return Err(OpenChannelError::IncompatibleShutdownScript { script: shutdown_scriptpubkey.clone() });
 			}
 		}
 
 		let destination_script = match signer_provider.get_destination_script() {
 			Ok(script) => script,
-			Err(_) => return Err(APIError::ChannelUnavailable { err: "Failed to get destination script".to_owned()}),
+			Err(_) => return Err(OpenChannelError::DestinationScriptUnavailable),
 		};
 
 		let temporary_channel_id = entropy_source.get_secure_random_bytes();
@@ -5559,6 +6554,8 @@ impl<Signer: WriteableEcdsaChannelSigner> OutboundV1Channel<Signer> {
 
 				last_sent_closing_fee: None,
 				pending_counterparty_closing_signed: None,
+				last_sent_closing_complete: None,
+				pending_counterparty_closing_complete: None,
 				closing_fee_limits: None,
 				target_closing_feerate_sats_per_kw: None,
 
@@ -5583,6 +6580,9 @@ impl<Signer: WriteableEcdsaChannelSigner> OutboundV1Channel<Signer> {
 				minimum_depth: None, // Filled in in accept_channel
 
 				counterparty_forwarding_info: None,
+				counterparty_channel_update: None,
+				feerate_disagreement_ticks: None,
+				feerate_disagreement_max_pending_htlc_value_msat: None,
 
 				channel_transaction_parameters: ChannelTransactionParameters {
 					holder_pubkeys: pubkeys,
@@ -5594,6 +6594,8 @@ impl<Signer: WriteableEcdsaChannelSigner> OutboundV1Channel<Signer> {
 					opt_non_zero_fee_anchors: None
 				},
 				funding_transaction: None,
+				broadcast_funding_transaction: None,
+				funding_double_spend: None,
 
 				counterparty_cur_commitment_point: None,
 				counterparty_prev_commitment_point: None,
@@ -5604,6 +6606,7 @@ impl<Signer: WriteableEcdsaChannelSigner> OutboundV1Channel<Signer> {
 				commitment_secrets: CounterpartyCommitmentSecrets::new(),
 
 				channel_update_status: ChannelUpdateStatus::Enabled,
+				ticks_since_channel_update_status_change: u8::max_value(),
 				closing_signed_in_flight: false,
 
 				announcement_sigs: None,
@@ -5614,13 +6617,18 @@ impl<Signer: WriteableEcdsaChannelSigner> OutboundV1Channel<Signer> {
 				next_remote_commitment_tx_fee_info_cached: Mutex::new(None),
 
 				workaround_lnd_bug_4006: None,
+				counterparty_stale_state_point: None,
 				sent_message_awaiting_response: None,
 
 				latest_inbound_scid_alias: None,
+				inbound_scid_aliases: Vec::new(),
 				outbound_scid_alias,
+				additional_outbound_scid_aliases: Vec::new(),
 
 				channel_pending_event_emitted: false,
 				channel_ready_event_emitted: false,
+				funding_spent_by_counterparty_commitment: false,
+				reported_funding_tx_confirmations: 0,
 
 				#[cfg(any(test, fuzzing))]
 				historical_inbound_htlc_fulfills: HashSet::new(),
@@ -5793,6 +6801,7 @@ impl<Signer: WriteableEcdsaChannelSigner> OutboundV1Channel<Signer> {
 				None => Builder::new().into_script(),
 			}),
 			channel_type: Some(self.context.channel_type.clone()),
+			custom_tlvs: Vec::new(),
 		}
 	}
 
@@ -5813,7 +6822,9 @@ impl<Signer: WriteableEcdsaChannelSigner> OutboundV1Channel<Signer> {
 		if msg.channel_reserve_satoshis > self.context.channel_value_satoshis {
 			return Err(ChannelError::Close(format!("Bogus channel_reserve_satoshis ({}). Must not be greater than ({})", msg.channel_reserve_satoshis, self.context.channel_value_satoshis)));
 		}
-		if msg.dust_limit_satoshis > self.context.holder_selected_channel_reserve_satoshis {
+		// A zero reserve is below any dust limit, but was explicitly chosen via
+		// `their_channel_reserve_zero`.
+		if self.context.holder_selected_channel_reserve_satoshis != 0 && msg.dust_limit_satoshis > self.context.holder_selected_channel_reserve_satoshis {
 			return Err(ChannelError::Close(format!("Dust limit ({}) is bigger than our channel reserve ({})", msg.dust_limit_satoshis, self.context.holder_selected_channel_reserve_satoshis)));
 		}
 		if msg.channel_reserve_satoshis > self.context.channel_value_satoshis - self.context.holder_selected_channel_reserve_satoshis {
@@ -5930,6 +6941,9 @@ impl<Signer: WriteableEcdsaChannelSigner> OutboundV1Channel<Signer> {
 /// A not-yet-funded inbound (from counterparty) channel using V1 channel establishment.
 pub(super) struct InboundV1Channel<Signer: ChannelSigner> {
 	pub context: ChannelContext<Signer>,
+	/// The [`ChannelHandshakeLimits`] the counterparty's `open_channel` violated, if we deferred
+	/// rejecting it to the user per [`ChannelHandshakeLimits::negotiate_limit_violations`].
+	limit_violations: Vec<HandshakeLimitViolation>,
 }
 
 impl<Signer: WriteableEcdsaChannelSigner> InboundV1Channel<Signer> {
@@ -6027,20 +7041,40 @@ impl<Signer: WriteableEcdsaChannelSigner> InboundV1Channel<Signer> {
 		}
 
 		// Now check against optional parameters as set by config...
-		if msg.funding_satoshis < config.channel_handshake_limits.min_funding_satoshis {
-			return Err(ChannelError::Close(format!("Funding satoshis ({}) is less than the user specified limit ({})", msg.funding_satoshis, config.channel_handshake_limits.min_funding_satoshis)));
+		let limits = &config.channel_handshake_limits;
+		let mut limit_violations = Vec::new();
+		if msg.funding_satoshis < limits.min_funding_satoshis {
+			limit_violations.push(HandshakeLimitViolation::FundingTooLow {
+				funding_satoshis: msg.funding_satoshis, min_funding_satoshis: limits.min_funding_satoshis,
+			});
+		}
+		if msg.htlc_minimum_msat > limits.max_htlc_minimum_msat {
+			limit_violations.push(HandshakeLimitViolation::HTLCMinimumTooHigh {
+				htlc_minimum_msat: msg.htlc_minimum_msat, max_htlc_minimum_msat: limits.max_htlc_minimum_msat,
+			});
 		}
-		if msg.htlc_minimum_msat > config.channel_handshake_limits.max_htlc_minimum_msat {
-			return Err(ChannelError::Close(format!("htlc_minimum_msat ({}) is higher than the user specified limit ({})", msg.htlc_minimum_msat,  config.channel_handshake_limits.max_htlc_minimum_msat)));
+		if msg.max_htlc_value_in_flight_msat < limits.min_max_htlc_value_in_flight_msat {
+			limit_violations.push(HandshakeLimitViolation::MaxHTLCValueInFlightTooLow {
+				max_htlc_value_in_flight_msat: msg.max_htlc_value_in_flight_msat,
+				min_max_htlc_value_in_flight_msat: limits.min_max_htlc_value_in_flight_msat,
+			});
 		}
-		if msg.max_htlc_value_in_flight_msat < config.channel_handshake_limits.min_max_htlc_value_in_flight_msat {
-			return Err(ChannelError::Close(format!("max_htlc_value_in_flight_msat ({}) is less than the user specified limit ({})", msg.max_htlc_value_in_flight_msat, config.channel_handshake_limits.min_max_htlc_value_in_flight_msat)));
+		if msg.channel_reserve_satoshis > limits.max_channel_reserve_satoshis {
+			limit_violations.push(HandshakeLimitViolation::ChannelReserveTooHigh {
+				channel_reserve_satoshis: msg.channel_reserve_satoshis,
+				max_channel_reserve_satoshis: limits.max_channel_reserve_satoshis,
+			});
 		}
-		if msg.channel_reserve_satoshis > config.channel_handshake_limits.max_channel_reserve_satoshis {
-			return Err(ChannelError::Close(format!("channel_reserve_satoshis ({}) is higher than the user specified limit ({})", msg.channel_reserve_satoshis, config.channel_handshake_limits.max_channel_reserve_satoshis)));
+		if msg.max_accepted_htlcs < limits.min_max_accepted_htlcs {
+			limit_violations.push(HandshakeLimitViolation::MaxAcceptedHTLCsTooLow {
+				max_accepted_htlcs: msg.max_accepted_htlcs, min_max_accepted_htlcs: limits.min_max_accepted_htlcs,
+			});
 		}
-		if msg.max_accepted_htlcs < config.channel_handshake_limits.min_max_accepted_htlcs {
-			return Err(ChannelError::Close(format!("max_accepted_htlcs ({}) is less than the user specified limit ({})", msg.max_accepted_htlcs, config.channel_handshake_limits.min_max_accepted_htlcs)));
+		// Unless the user wants to decide on them, reject the channel on the first violation.
+		if let Some(violation) = limit_violations.first() {
+			if !config.manually_accept_inbound_channels || !limits.negotiate_limit_violations {
+				return Err(ChannelError::Close(violation.to_string()));
+			}
 		}
 		if msg.dust_limit_satoshis < MIN_CHAN_DUST_LIMIT_SATOSHIS {
 			return Err(ChannelError::Close(format!("dust_limit_satoshis ({}) is less than the implementation limit ({})", msg.dust_limit_satoshis, MIN_CHAN_DUST_LIMIT_SATOSHIS)));
@@ -6058,7 +7092,8 @@ impl<Signer: WriteableEcdsaChannelSigner> InboundV1Channel<Signer> {
 		}
 
 		let holder_selected_channel_reserve_satoshis = get_holder_selected_channel_reserve_satoshis(msg.funding_satoshis, config);
-		if holder_selected_channel_reserve_satoshis < MIN_CHAN_DUST_LIMIT_SATOSHIS {
+		let their_channel_reserve_zero = config.channel_handshake_config.their_channel_reserve_zero;
+		if !their_channel_reserve_zero && holder_selected_channel_reserve_satoshis < MIN_CHAN_DUST_LIMIT_SATOSHIS {
 			// Protocol level safety check in place, although it should never happen because
 			// of `MIN_THEIR_CHAN_RESERVE_SATOSHIS`
 			return Err(ChannelError::Close(format!("Suitable channel reserve not found. remote_channel_reserve was ({}). dust_limit_satoshis is ({}).", holder_selected_channel_reserve_satoshis, MIN_CHAN_DUST_LIMIT_SATOSHIS)));
@@ -6070,7 +7105,7 @@ impl<Signer: WriteableEcdsaChannelSigner> InboundV1Channel<Signer> {
 			log_debug!(logger, "channel_reserve_satoshis ({}) is smaller than our dust limit ({}). We can broadcast stale states without any risk, implying this channel is very insecure for our counterparty.",
 				msg.channel_reserve_satoshis, MIN_CHAN_DUST_LIMIT_SATOSHIS);
 		}
-		if holder_selected_channel_reserve_satoshis < msg.dust_limit_satoshis {
+		if !their_channel_reserve_zero && holder_selected_channel_reserve_satoshis < msg.dust_limit_satoshis {
 			return Err(ChannelError::Close(format!("Dust limit ({}) too high for the channel reserve we require the remote to keep ({})", msg.dust_limit_satoshis, holder_selected_channel_reserve_satoshis)));
 		}
 
@@ -6185,6 +7220,8 @@ impl<Signer: WriteableEcdsaChannelSigner> InboundV1Channel<Signer> {
 
 				last_sent_closing_fee: None,
 				pending_counterparty_closing_signed: None,
+				last_sent_closing_complete: None,
+				pending_counterparty_closing_complete: None,
 				closing_fee_limits: None,
 				target_closing_feerate_sats_per_kw: None,
 
@@ -6210,6 +7247,9 @@ impl<Signer: WriteableEcdsaChannelSigner> InboundV1Channel<Signer> {
 				minimum_depth: Some(cmp::max(config.channel_handshake_config.minimum_depth, 1)),
 
 				counterparty_forwarding_info: None,
+				counterparty_channel_update: None,
+				feerate_disagreement_ticks: None,
+				feerate_disagreement_max_pending_htlc_value_msat: None,
 
 				channel_transaction_parameters: ChannelTransactionParameters {
 					holder_pubkeys: pubkeys,
@@ -6224,6 +7264,8 @@ impl<Signer: WriteableEcdsaChannelSigner> InboundV1Channel<Signer> {
 					opt_non_zero_fee_anchors: None
 				},
 				funding_transaction: None,
+				broadcast_funding_transaction: None,
+				funding_double_spend: None,
 
 				counterparty_cur_commitment_point: Some(msg.first_per_commitment_point),
 				counterparty_prev_commitment_point: None,
@@ -6234,6 +7276,7 @@ impl<Signer: WriteableEcdsaChannelSigner> InboundV1Channel<Signer> {
 				commitment_secrets: CounterpartyCommitmentSecrets::new(),
 
 				channel_update_status: ChannelUpdateStatus::Enabled,
+				ticks_since_channel_update_status_change: u8::max_value(),
 				closing_signed_in_flight: false,
 
 				announcement_sigs: None,
@@ -6244,13 +7287,18 @@ impl<Signer: WriteableEcdsaChannelSigner> InboundV1Channel<Signer> {
 				next_remote_commitment_tx_fee_info_cached: Mutex::new(None),
 
 				workaround_lnd_bug_4006: None,
+				counterparty_stale_state_point: None,
 				sent_message_awaiting_response: None,
 
 				latest_inbound_scid_alias: None,
+				inbound_scid_aliases: Vec::new(),
 				outbound_scid_alias,
+				additional_outbound_scid_aliases: Vec::new(),
 
 				channel_pending_event_emitted: false,
 				channel_ready_event_emitted: false,
+				funding_spent_by_counterparty_commitment: false,
+				reported_funding_tx_confirmations: 0,
 
 				#[cfg(any(test, fuzzing))]
 				historical_inbound_htlc_fulfills: HashSet::new(),
@@ -6259,7 +7307,8 @@ impl<Signer: WriteableEcdsaChannelSigner> InboundV1Channel<Signer> {
 				channel_keys_id,
 
 				pending_monitor_updates: Vec::new(),
-			}
+			},
+			limit_violations,
 		};
 
 		Ok(chan)
@@ -6275,6 +7324,60 @@ impl<Signer: WriteableEcdsaChannelSigner> InboundV1Channel<Signer> {
 		self.context.minimum_depth = Some(0);
 	}
 
+	/// Sets this channel to require no reserve from our counterparty, must be done before
+	/// `get_accept_channel`
+	pub fn set_0reserve(&mut self) {
+		assert!(self.context.inbound_awaiting_accept);
+		self.context.holder_selected_channel_reserve_satoshis = 0;
+	}
+
+	/// Gets the [`ChannelHandshakeLimits`] our counterparty's `open_channel` violated, which we
+	/// left for the user to decide on.
+	pub fn limit_violations(&self) -> &[HandshakeLimitViolation] {
+		&self.limit_violations
+	}
+
+	/// Overrides the parameters we selected for this channel from our [`ChannelHandshakeConfig`],
+	/// must be done before `get_accept_channel`. If any override is invalid, none are applied.
+	pub fn apply_overrides(&mut self, overrides: &InboundChannelOverrides) -> Result<(), APIError> {
+		assert!(self.context.inbound_awaiting_accept);
+		let channel_value_msat = self.context.channel_value_satoshis * 1000;
+		if let Some(reserve_satoshis) = overrides.their_channel_reserve_satoshis {
+			let counterparty_reserve_satoshis = self.context.counterparty_selected_channel_reserve_satoshis.unwrap_or(0);
+			if reserve_satoshis < MIN_CHAN_DUST_LIMIT_SATOSHIS || reserve_satoshis < self.context.counterparty_dust_limit_satoshis {
+				return Err(APIError::APIMisuseError { err: format!("Channel reserve override ({}) must be at least the dust limit of either side", reserve_satoshis) });
+			}
+			if reserve_satoshis.saturating_add(counterparty_reserve_satoshis) >= self.context.channel_value_satoshis {
+				return Err(APIError::APIMisuseError { err: format!("Channel reserve override ({}) leaves nothing to spend in the channel", reserve_satoshis) });
+			}
+		}
+		if let Some(htlc_minimum_msat) = overrides.our_htlc_minimum_msat {
+			if htlc_minimum_msat >= channel_value_msat {
+				return Err(APIError::APIMisuseError { err: format!("HTLC minimum override ({}) must be below the channel value", htlc_minimum_msat) });
+			}
+		}
+		if overrides.max_inbound_htlc_value_in_flight_msat == Some(0) {
+			return Err(APIError::APIMisuseError { err: "Maximum HTLC value in flight override must be non-zero".to_owned() });
+		}
+		if overrides.our_max_accepted_htlcs == Some(0) {
+			return Err(APIError::APIMisuseError { err: "Maximum accepted HTLCs override must be non-zero".to_owned() });
+		}
+
+		if let Some(reserve_satoshis) = overrides.their_channel_reserve_satoshis {
+			self.context.holder_selected_channel_reserve_satoshis = reserve_satoshis;
+		}
+		if let Some(htlc_minimum_msat) = overrides.our_htlc_minimum_msat {
+			self.context.holder_htlc_minimum_msat = cmp::max(htlc_minimum_msat, 1);
+		}
+		if let Some(max_in_flight_msat) = overrides.max_inbound_htlc_value_in_flight_msat {
+			self.context.holder_max_htlc_value_in_flight_msat = cmp::min(max_in_flight_msat, channel_value_msat);
+		}
+		if let Some(max_accepted_htlcs) = overrides.our_max_accepted_htlcs {
+			self.context.holder_max_accepted_htlcs = cmp::min(max_accepted_htlcs, MAX_HTLCS);
+		}
+		Ok(())
+	}
+
 	/// Marks an inbound channel as accepted and generates a [`msgs::AcceptChannel`] message which
 	/// should be sent back to the counterparty node.
 	///
@@ -6328,6 +7431,7 @@ impl<Signer: WriteableEcdsaChannelSigner> InboundV1Channel<Signer> {
 				None => Builder::new().into_script(),
 			}),
 			channel_type: Some(self.context.channel_type.clone()),
+			custom_tlvs: Vec::new(),
 			#[cfg(taproot)]
 			next_local_nonce: None,
 		}
@@ -6609,9 +7713,11 @@ impl<Signer: WriteableEcdsaChannelSigner> Writeable for Channel<Signer> {
 		}
 
 		let mut preimages: Vec<&Option<PaymentPreimage>> = vec![];
+		let mut pending_outbound_blinding_points: Vec<Option<PublicKey>> = Vec::new();
 
 		(self.context.pending_outbound_htlcs.len() as u64).write(writer)?;
 		for htlc in self.context.pending_outbound_htlcs.iter() {
+			pending_outbound_blinding_points.push(htlc.blinding_point);
 			htlc.htlc_id.write(writer)?;
 			htlc.amount_msat.write(writer)?;
 			htlc.cltv_expiry.write(writer)?;
@@ -6649,10 +7755,16 @@ impl<Signer: WriteableEcdsaChannelSigner> Writeable for Channel<Signer> {
 			}
 		}
 
+		let mut holding_cell_blinding_points: Vec<Option<PublicKey>> = Vec::new();
+		// Older versions don't know about malformed failures in the holding cell, so we write them
+		// as relay failures with a dummy packet and separately in an odd TLV below.
+		let mut malformed_htlcs: Vec<(u64, u16, [u8; 32])> = Vec::new();
+
 		(self.context.holding_cell_htlc_updates.len() as u64).write(writer)?;
 		for update in self.context.holding_cell_htlc_updates.iter() {
 			match update {
-				&HTLCUpdateAwaitingACK::AddHTLC { ref amount_msat, ref cltv_expiry, ref payment_hash, ref source, ref onion_routing_packet } => {
+				&HTLCUpdateAwaitingACK::AddHTLC { ref amount_msat, ref cltv_expiry, ref payment_hash, ref source, ref onion_routing_packet, blinding_point } => {
+					holding_cell_blinding_points.push(blinding_point);
 					0u8.write(writer)?;
 					amount_msat.write(writer)?;
 					cltv_expiry.write(writer)?;
@@ -6669,7 +7781,13 @@ impl<Signer: WriteableEcdsaChannelSigner> Writeable for Channel<Signer> {
 					2u8.write(writer)?;
 					htlc_id.write(writer)?;
 					err_packet.write(writer)?;
-				}
+				},
+				&HTLCUpdateAwaitingACK::FailMalformedHTLC { htlc_id, failure_code, sha256_of_onion } => {
+					malformed_htlcs.push((htlc_id, failure_code, sha256_of_onion));
+					2u8.write(writer)?;
+					htlc_id.write(writer)?;
+					msgs::OnionErrorPacket { data: Vec::new(), attribution_data: None }.write(writer)?;
+				},
 			}
 		}
 
@@ -6825,6 +7943,16 @@ impl<Signer: WriteableEcdsaChannelSigner> Writeable for Channel<Signer> {
 			(29, self.context.temporary_channel_id, option),
 			(31, channel_pending_event_emitted, option),
 			(33, self.context.pending_monitor_updates, vec_type),
+			(35, self.context.inbound_scid_aliases, optional_vec),
+			(37, self.context.additional_outbound_scid_aliases, optional_vec),
+			(39, self.context.broadcast_funding_transaction, option),
+			(41, self.context.counterparty_channel_update, option),
+			(43, self.context.feerate_disagreement_ticks, option),
+			(45, self.context.feerate_disagreement_max_pending_htlc_value_msat, option),
+			(47, pending_outbound_blinding_points, optional_vec),
+			(49, holding_cell_blinding_points, optional_vec),
+			(51, malformed_htlcs, optional_vec),
+			(53, self.context.funding_double_spend, option),
 		});
 
 		Ok(())
@@ -6918,6 +8046,7 @@ impl<'a, 'b, 'c, ES: Deref, SP: Deref> ReadableArgs<(&'a ES, &'b SP, u32, &'c Ch
 				cltv_expiry: Readable::read(reader)?,
 				payment_hash: Readable::read(reader)?,
 				source: Readable::read(reader)?,
+				blinding_point: None,
 				state: match <u8 as Readable>::read(reader)? {
 					0 => OutboundHTLCState::LocalAnnounced(Box::new(Readable::read(reader)?)),
 					1 => OutboundHTLCState::Committed,
@@ -6948,6 +8077,7 @@ impl<'a, 'b, 'c, ES: Deref, SP: Deref> ReadableArgs<(&'a ES, &'b SP, u32, &'c Ch
 					payment_hash: Readable::read(reader)?,
 					source: Readable::read(reader)?,
 					onion_routing_packet: Readable::read(reader)?,
+					blinding_point: None,
 				},
 				1 => HTLCUpdateAwaitingACK::ClaimHTLC {
 					payment_preimage: Readable::read(reader)?,
@@ -7103,6 +8233,17 @@ impl<'a, 'b, 'c, ES: Deref, SP: Deref> ReadableArgs<(&'a ES, &'b SP, u32, &'c Ch
 
 		let mut pending_monitor_updates = Some(Vec::new());
 
+		let mut inbound_scid_aliases: Option<Vec<u64>> = Some(Vec::new());
+		let mut additional_outbound_scid_aliases: Option<Vec<u64>> = Some(Vec::new());
+		let mut broadcast_funding_transaction: Option<Transaction> = None;
+		let mut funding_double_spend: Option<(Txid, u32)> = None;
+		let mut counterparty_channel_update: Option<CounterpartyChannelUpdate> = None;
+		let mut feerate_disagreement_ticks: Option<u8> = None;
+		let mut feerate_disagreement_max_pending_htlc_value_msat: Option<u64> = None;
+		let mut pending_outbound_blinding_points_opt: Option<Vec<Option<PublicKey>>> = None;
+		let mut holding_cell_blinding_points_opt: Option<Vec<Option<PublicKey>>> = None;
+		let mut malformed_htlcs: Option<Vec<(u64, u16, [u8; 32])>> = None;
+
 		read_tlv_fields!(reader, {
 			(0, announcement_sigs, option),
 			(1, minimum_depth, option),
@@ -7126,6 +8267,16 @@ impl<'a, 'b, 'c, ES: Deref, SP: Deref> ReadableArgs<(&'a ES, &'b SP, u32, &'c Ch
 			(29, temporary_channel_id, option),
 			(31, channel_pending_event_emitted, option),
 			(33, pending_monitor_updates, vec_type),
+			(35, inbound_scid_aliases, optional_vec),
+			(37, additional_outbound_scid_aliases, optional_vec),
+			(39, broadcast_funding_transaction, option),
+			(41, counterparty_channel_update, option),
+			(43, feerate_disagreement_ticks, option),
+			(45, feerate_disagreement_max_pending_htlc_value_msat, option),
+			(47, pending_outbound_blinding_points_opt, optional_vec),
+			(49, holding_cell_blinding_points_opt, optional_vec),
+			(51, malformed_htlcs, optional_vec),
+			(53, funding_double_spend, option),
 		});
 
 		let (channel_keys_id, holder_signer) = if let Some(channel_keys_id) = channel_keys_id {
@@ -7144,6 +8295,14 @@ impl<'a, 'b, 'c, ES: Deref, SP: Deref> ReadableArgs<(&'a ES, &'b SP, u32, &'c Ch
 			(holder_signer.channel_keys_id(), holder_signer)
 		};
 
+		let mut inbound_scid_aliases = inbound_scid_aliases.unwrap();
+		if let Some(alias) = latest_inbound_scid_alias {
+			// Channels written prior to us tracking all inbound aliases only stored the latest one.
+			if !inbound_scid_aliases.contains(&alias) {
+				inbound_scid_aliases.push(alias);
+			}
+		}
+
 		if let Some(preimages) = preimages_opt {
 			let mut iter = preimages.into_iter();
 			for htlc in pending_outbound_htlcs.iter_mut() {
@@ -7163,6 +8322,42 @@ impl<'a, 'b, 'c, ES: Deref, SP: Deref> ReadableArgs<(&'a ES, &'b SP, u32, &'c Ch
 			}
 		}
 
+		if let Some(blinding_pts) = pending_outbound_blinding_points_opt {
+			let mut iter = blinding_pts.into_iter();
+			for htlc in pending_outbound_htlcs.iter_mut() {
+				htlc.blinding_point = iter.next().ok_or(DecodeError::InvalidValue)?;
+			}
+			// We expect all blinding points to be consumed above
+			if iter.next().is_some() { return Err(DecodeError::InvalidValue) }
+		}
+
+		if let Some(blinding_pts) = holding_cell_blinding_points_opt {
+			let mut iter = blinding_pts.into_iter();
+			for htlc in holding_cell_htlc_updates.iter_mut() {
+				if let HTLCUpdateAwaitingACK::AddHTLC { ref mut blinding_point, .. } = htlc {
+					*blinding_point = iter.next().ok_or(DecodeError::InvalidValue)?;
+				}
+			}
+			// We expect all blinding points to be consumed above
+			if iter.next().is_some() { return Err(DecodeError::InvalidValue) }
+		}
+
+		if let Some(malformed_htlcs) = malformed_htlcs {
+			for (malformed_htlc_id, failure_code, sha256_of_onion) in malformed_htlcs {
+				let htlc_idx = holding_cell_htlc_updates.iter().position(|htlc| {
+					if let HTLCUpdateAwaitingACK::FailHTLC { htlc_id, err_packet } = htlc {
+						let matches = *htlc_id == malformed_htlc_id;
+						if matches { debug_assert!(err_packet.data.is_empty()) }
+						matches
+					} else { false }
+				}).ok_or(DecodeError::InvalidValue)?;
+				let malformed_htlc = HTLCUpdateAwaitingACK::FailMalformedHTLC {
+					htlc_id: malformed_htlc_id, failure_code, sha256_of_onion
+				};
+				let _ = core::mem::replace(&mut holding_cell_htlc_updates[htlc_idx], malformed_htlc);
+			}
+		}
+
 		let chan_features = channel_type.as_ref().unwrap();
 		if !chan_features.is_subset(our_supported_features) {
 			// If the channel was written by a new version and negotiated with features we don't
@@ -7237,6 +8432,8 @@ impl<'a, 'b, 'c, ES: Deref, SP: Deref> ReadableArgs<(&'a ES, &'b SP, u32, &'c Ch
 
 				last_sent_closing_fee: None,
 				pending_counterparty_closing_signed: None,
+				last_sent_closing_complete: None,
+				pending_counterparty_closing_complete: None,
 				closing_fee_limits: None,
 				target_closing_feerate_sats_per_kw,
 
@@ -7262,6 +8459,11 @@ impl<'a, 'b, 'c, ES: Deref, SP: Deref> ReadableArgs<(&'a ES, &'b SP, u32, &'c Ch
 
 				channel_transaction_parameters: channel_parameters,
 				funding_transaction,
+				broadcast_funding_transaction,
+				counterparty_channel_update,
+				feerate_disagreement_ticks,
+				feerate_disagreement_max_pending_htlc_value_msat,
+				funding_double_spend,
 
 				counterparty_cur_commitment_point,
 				counterparty_prev_commitment_point,
@@ -7272,6 +8474,7 @@ impl<'a, 'b, 'c, ES: Deref, SP: Deref> ReadableArgs<(&'a ES, &'b SP, u32, &'c Ch
 				commitment_secrets,
 
 				channel_update_status,
+				ticks_since_channel_update_status_change: u8::max_value(),
 				closing_signed_in_flight: false,
 
 				announcement_sigs,
@@ -7282,14 +8485,19 @@ impl<'a, 'b, 'c, ES: Deref, SP: Deref> ReadableArgs<(&'a ES, &'b SP, u32, &'c Ch
 				next_remote_commitment_tx_fee_info_cached: Mutex::new(None),
 
 				workaround_lnd_bug_4006: None,
+				counterparty_stale_state_point: None,
 				sent_message_awaiting_response: None,
 
 				latest_inbound_scid_alias,
+				inbound_scid_aliases,
 				// Later in the ChannelManager deserialization phase we scan for channels and assign scid aliases if its missing
 				outbound_scid_alias: outbound_scid_alias.unwrap_or(0),
+				additional_outbound_scid_aliases: additional_outbound_scid_aliases.unwrap(),
 
 				channel_pending_event_emitted: channel_pending_event_emitted.unwrap_or(true),
 				channel_ready_event_emitted: channel_ready_event_emitted.unwrap_or(true),
+				funding_spent_by_counterparty_commitment: false,
+				reported_funding_tx_confirmations: 0,
 
 				#[cfg(any(test, fuzzing))]
 				historical_inbound_htlc_fulfills,
@@ -7330,7 +8538,7 @@ mod tests {
 	use crate::routing::router::Path;
 	use crate::util::config::UserConfig;
 	use crate::util::enforcing_trait_impls::EnforcingSigner;
-	use crate::util::errors::APIError;
+	use crate::util::errors::OpenChannelError;
 	use crate::util::test_utils;
 	use crate::util::test_utils::OnGetShutdownScriptpubkey;
 	use bitcoin::secp256k1::{Secp256k1, ecdsa::Signature};
@@ -7426,7 +8634,7 @@ mod tests {
 		let node_id = PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[42; 32]).unwrap());
 		let config = UserConfig::default();
 		match OutboundV1Channel::<EnforcingSigner>::new(&LowerBoundedFeeEstimator::new(&TestFeeEstimator { fee_est: 253 }), &&keys_provider, &&keys_provider, node_id, &features, 10000000, 100000, 42, &config, 0, 42) {
-			Err(APIError::IncompatibleShutdownScript { script }) => {
+			Err(OpenChannelError::IncompatibleShutdownScript { script }) => {
 				assert_eq!(script.into_inner(), non_v0_segwit_shutdown_script.into_inner());
 			},
 			Err(e) => panic!("Unexpected error: {:?}", e),
@@ -7522,7 +8730,8 @@ mod tests {
 				session_priv: SecretKey::from_slice(&hex::decode("0fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff").unwrap()[..]).unwrap(),
 				first_hop_htlc_msat: 548,
 				payment_id: PaymentId([42; 32]),
-			}
+			},
+			blinding_point: None,
 		});
 
 		// Make sure when Node A calculates their local commitment transaction, none of the HTLCs pass
@@ -46,6 +46,8 @@
 //! - `OnionMessages` - requires/supports forwarding onion messages
 //!     (see [BOLT-7](https://github.com/lightning/bolts/pull/759/files) for more information).
 //     TODO: update link
+//! - `ProvideStorage` - requires/supports storing a small blob of data for peers
+//!     (see [BOLT-1](https://github.com/lightning/bolts/pull/1110) for more information).
 //! - `ChannelType` - node supports the channel_type field in open/accept
 //!     (see [BOLT-2](https://github.com/lightning/bolts/blob/master/02-peer-protocol.md) for more information).
 //! - `SCIDPrivacy` - supply channel aliases for routing
@@ -58,6 +60,9 @@
 //!      (see
 //!      [BOLT-2](https://github.com/lightning/bolts/blob/master/02-peer-protocol.md#the-channel_ready-message)
 //!      for more info).
+//! - `SimpleClose` - requires/supports the simplified cooperative close protocol, in which each
+//!      side proposes (and pays) its own closing transaction fee
+//!      (see [BOLT-2](https://github.com/lightning/bolts/pull/1096) for more information).
 //! - `Keysend` - send funds to a node without an invoice
 //!     (see the [`Keysend` feature assignment proposal](https://github.com/lightning/bolts/issues/605#issuecomment-606679798) for more information).
 //! - `AnchorsZeroFeeHtlcTx` - requires/supports that commitment transactions include anchor outputs
@@ -140,9 +145,11 @@ mod sealed {
 		// Byte 4
 		OnionMessages,
 		// Byte 5
-		ChannelType | SCIDPrivacy,
+		ProvideStorage | ChannelType | SCIDPrivacy,
 		// Byte 6
 		ZeroConf,
+		// Byte 7
+		SimpleClose,
 	]);
 	define_context!(NodeContext, [
 		// Byte 0
@@ -156,9 +163,11 @@ mod sealed {
 		// Byte 4
 		OnionMessages,
 		// Byte 5
-		ChannelType | SCIDPrivacy,
+		ProvideStorage | ChannelType | SCIDPrivacy,
 		// Byte 6
 		ZeroConf | Keysend,
+		// Byte 7
+		SimpleClose,
 	]);
 	define_context!(ChannelContext, []);
 	define_context!(InvoiceContext, [
@@ -387,6 +396,9 @@ mod sealed {
 	define_feature!(39, OnionMessages, [InitContext, NodeContext],
 		"Feature flags for `option_onion_messages`.", set_onion_messages_optional,
 		set_onion_messages_required, supports_onion_messages, requires_onion_messages);
+	define_feature!(43, ProvideStorage, [InitContext, NodeContext],
+		"Feature flags for `option_provide_storage`.", set_provide_storage_optional,
+		set_provide_storage_required, supports_provide_storage, requires_provide_storage);
 	define_feature!(45, ChannelType, [InitContext, NodeContext],
 		"Feature flags for `option_channel_type`.", set_channel_type_optional,
 		set_channel_type_required, supports_channel_type, requires_channel_type);
@@ -402,6 +414,9 @@ mod sealed {
 	define_feature!(55, Keysend, [NodeContext],
 		"Feature flags for keysend payments.", set_keysend_optional, set_keysend_required,
 		supports_keysend, requires_keysend);
+	define_feature!(61, SimpleClose, [InitContext, NodeContext],
+		"Feature flags for the simplified cooperative close protocol. Called `option_simple_close` in the BOLTs",
+		set_simple_close_optional, set_simple_close_required, supports_simple_close, requires_simple_close);
 	// Note: update the module-level docs when a new feature bit is added!
 
 	#[cfg(test)]
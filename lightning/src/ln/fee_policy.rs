@@ -0,0 +1,325 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Automated adjustment of per-channel forwarding fees.
+//!
+//! A [`FeePolicyManager`] periodically sets each channel's
+//! [`ChannelConfig::forwarding_fee_proportional_millionths`] based on how much of the channel's
+//! liquidity is on our side and on how much we recently forwarded over it, following the
+//! [`FeeCurve`]s in its [`FeePolicyConfig`]. Feed each [`Event::PaymentForwarded`] to
+//! [`FeePolicyManager::record_event`] and call [`FeePolicyManager::timer_tick_occurred`] along
+//! with [`ChannelManager::timer_tick_occurred`]. Changes are applied through
+//! [`ChannelManager::update_partial_channel_config`], which generates the [`ChannelUpdate`]s to
+//! broadcast.
+//!
+//! As every change has to be gossiped, fees are only re-evaluated every
+//! [`FeePolicyConfig::update_interval_ticks`] and only changed if they moved by at least
+//! [`FeePolicyConfig::min_fee_change_millionths`].
+//!
+//! [`ChannelConfig::forwarding_fee_proportional_millionths`]: crate::util::config::ChannelConfig::forwarding_fee_proportional_millionths
+//! [`Event::PaymentForwarded`]: crate::events::Event::PaymentForwarded
+//! [`ChannelManager::timer_tick_occurred`]: crate::ln::channelmanager::ChannelManager::timer_tick_occurred
+//! [`ChannelManager::update_partial_channel_config`]: crate::ln::channelmanager::ChannelManager::update_partial_channel_config
+//! [`ChannelUpdate`]: crate::ln::msgs::ChannelUpdate
+
+use crate::chain;
+use crate::chain::chaininterface::{BroadcasterInterface, FeeEstimator};
+use crate::events::Event;
+use crate::ln::channelmanager::{ChannelDetails, ChannelManager};
+use crate::routing::router::Router;
+use crate::sign::{EntropySource, NodeSigner, SignerProvider};
+use crate::util::config::ChannelConfigUpdate;
+use crate::util::logger::Logger;
+
+use core::ops::Deref;
+use crate::prelude::*;
+use crate::sync::Mutex;
+
+/// A piecewise-linear function, defined by a set of `(x, y)` points and flat beyond them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FeeCurve {
+	points: Vec<(u64, u32)>,
+}
+
+impl FeeCurve {
+	/// Creates a curve through the given `(x, y)` points.
+	///
+	/// Errors if no points are given or if the `x` coordinates are not strictly increasing.
+	pub fn new(points: Vec<(u64, u32)>) -> Result<Self, ()> {
+		if points.is_empty() || points.windows(2).any(|w| w[0].0 >= w[1].0) {
+			return Err(());
+		}
+		Ok(Self { points })
+	}
+
+	/// Creates a curve with the same value everywhere.
+	pub fn constant(y: u32) -> Self {
+		Self { points: vec![(0, y)] }
+	}
+
+	/// Evaluates the curve at `x`.
+	pub fn value_at(&self, x: u64) -> u32 {
+		let (first, last) = (self.points[0], self.points[self.points.len() - 1]);
+		if x <= first.0 { return first.1; }
+		if x >= last.0 { return last.1; }
+		let idx = self.points.iter().position(|&(point_x, _)| point_x > x).unwrap();
+		let ((x0, y0), (x1, y1)) = (self.points[idx - 1], self.points[idx]);
+		let (y0, y1) = (y0 as u128, y1 as u128);
+		let offset = (x - x0) as u128;
+		let width = (x1 - x0) as u128;
+		let y = if y1 >= y0 {
+			y0 + (y1 - y0) * offset / width
+		} else {
+			y0 - (y0 - y1) * offset / width
+		};
+		y as u32
+	}
+}
+
+/// Configuration for a [`FeePolicyManager`].
+#[derive(Clone, Debug)]
+pub struct FeePolicyConfig {
+	/// Maps the share of a channel's capacity which is spendable by us, in thousandths (i.e.,
+	/// `0` to `1000`), to its [`ChannelConfig::forwarding_fee_proportional_millionths`].
+	///
+	/// This is generally decreasing such that forwarding over channels which are depleted on our
+	/// side becomes more costly.
+	///
+	/// [`ChannelConfig::forwarding_fee_proportional_millionths`]: crate::util::config::ChannelConfig::forwarding_fee_proportional_millionths
+	pub liquidity_curve: FeeCurve,
+	/// Maps the amount forwarded out over a channel since the previous evaluation, in
+	/// milli-satoshis, to a multiplier in percent applied to the fee from the
+	/// [`Self::liquidity_curve`].
+	///
+	/// Default value: a constant `100`, i.e., forward volume doesn't affect fees.
+	pub volume_curve: FeeCurve,
+	/// The number of calls to [`FeePolicyManager::timer_tick_occurred`] between evaluations of
+	/// our channels' fees.
+	///
+	/// Default value: 60, i.e., hourly with ticks once a minute.
+	pub update_interval_ticks: u32,
+	/// The minimum difference between a channel's current and computed proportional fee for the
+	/// fee to be updated, limiting the [`ChannelUpdate`]s we gossip.
+	///
+	/// Default value: 10.
+	///
+	/// [`ChannelUpdate`]: crate::ln::msgs::ChannelUpdate
+	pub min_fee_change_millionths: u32,
+	/// Whether to only update fees of announced channels. Unannounced channels' fees are only
+	/// relevant where their counterparty includes them in route hints.
+	///
+	/// Default value: true.
+	pub public_channels_only: bool,
+}
+
+impl Default for FeePolicyConfig {
+	fn default() -> Self {
+		Self {
+			liquidity_curve: FeeCurve::new(vec![(0, 2_000), (500, 500), (1000, 100)]).unwrap(),
+			volume_curve: FeeCurve::constant(100),
+			update_interval_ticks: 60,
+			min_fee_change_millionths: 10,
+			public_channels_only: true,
+		}
+	}
+}
+
+struct FeePolicyState {
+	ticks_since_evaluation: u32,
+	forwarded_msat_by_channel: HashMap<[u8; 32], u64>,
+}
+
+/// Adjusts our channels' forwarding fees based on their liquidity and forward volume, see the
+/// [module-level documentation] for more.
+///
+/// [module-level documentation]: self
+pub struct FeePolicyManager<L: Deref> where L::Target: Logger {
+	config: FeePolicyConfig,
+	state: Mutex<FeePolicyState>,
+	logger: L,
+}
+
+impl<L: Deref> FeePolicyManager<L> where L::Target: Logger {
+	/// Creates a new manager applying the given policy.
+	pub fn new(config: FeePolicyConfig, logger: L) -> Self {
+		Self {
+			config,
+			state: Mutex::new(FeePolicyState {
+				ticks_since_evaluation: 0,
+				forwarded_msat_by_channel: HashMap::new(),
+			}),
+			logger,
+		}
+	}
+
+	/// Records the volume of forwarded payments. Should be called with every [`Event`] handled,
+	/// though only [`Event::PaymentForwarded`] is considered.
+	pub fn record_event(&self, event: &Event) {
+		if let Event::PaymentForwarded {
+			next_channel_id: Some(channel_id), outbound_amount_forwarded_msat: Some(amount_msat), ..
+		} = event {
+			let mut state = self.state.lock().unwrap();
+			let forwarded_msat = state.forwarded_msat_by_channel.entry(*channel_id).or_insert(0);
+			*forwarded_msat = forwarded_msat.saturating_add(*amount_msat);
+		}
+	}
+
+	/// Re-evaluates and updates our channels' fees every
+	/// [`FeePolicyConfig::update_interval_ticks`] calls. Should be called along with
+	/// [`ChannelManager::timer_tick_occurred`].
+	pub fn timer_tick_occurred<M: Deref, T: Deref, ES: Deref, NS: Deref, SP: Deref, F: Deref, R: Deref, CL: Deref>(
+		&self, channel_manager: &ChannelManager<M, T, ES, NS, SP, F, R, CL>
+	) where
+		M::Target: chain::Watch<<SP::Target as SignerProvider>::Signer>,
+		T::Target: BroadcasterInterface,
+		ES::Target: EntropySource,
+		NS::Target: NodeSigner,
+		SP::Target: SignerProvider,
+		F::Target: FeeEstimator,
+		R::Target: Router,
+		CL::Target: Logger,
+	{
+		let forwarded_msat_by_channel = {
+			let mut state = self.state.lock().unwrap();
+			state.ticks_since_evaluation += 1;
+			if state.ticks_since_evaluation < self.config.update_interval_ticks {
+				return;
+			}
+			state.ticks_since_evaluation = 0;
+			core::mem::take(&mut state.forwarded_msat_by_channel)
+		};
+
+		for channel in channel_manager.list_channels() {
+			let forwarded_msat = forwarded_msat_by_channel.get(&channel.channel_id).copied().unwrap_or(0);
+			let proportional_millionths = match self.fee_for_channel(&channel, forwarded_msat) {
+				Some(fee) => fee,
+				None => continue,
+			};
+			let config_update = ChannelConfigUpdate {
+				forwarding_fee_proportional_millionths: Some(proportional_millionths),
+				..Default::default()
+			};
+			match channel_manager.update_partial_channel_config(
+				&channel.counterparty.node_id, &[channel.channel_id], &config_update
+			) {
+				Ok(()) => log_debug!(self.logger, "Updated proportional fee of channel {} to {} millionths",
+					log_bytes!(channel.channel_id), proportional_millionths),
+				Err(e) => log_error!(self.logger, "Failed to update fee of channel {}: {:?}",
+					log_bytes!(channel.channel_id), e),
+			}
+		}
+	}
+
+	/// Computes the proportional fee the given channel should have, if it should be changed.
+	fn fee_for_channel(&self, channel: &ChannelDetails, forwarded_msat: u64) -> Option<u32> {
+		if !channel.is_usable || (self.config.public_channels_only && !channel.is_public) {
+			return None;
+		}
+		let current_fee = channel.config?.forwarding_fee_proportional_millionths;
+		let capacity_msat = channel.channel_value_satoshis.saturating_mul(1000);
+		if capacity_msat == 0 {
+			return None;
+		}
+		let liquidity_thousandths = channel.outbound_capacity_msat.saturating_mul(1000) / capacity_msat;
+		let base_fee = self.config.liquidity_curve.value_at(liquidity_thousandths) as u64;
+		let multiplier_percent = self.config.volume_curve.value_at(forwarded_msat) as u64;
+		let fee = core::cmp::min(base_fee * multiplier_percent / 100, u32::max_value() as u64) as u32;
+		let change = if fee > current_fee { fee - current_fee } else { current_fee - fee };
+		if change == 0 || change < self.config.min_fee_change_millionths {
+			return None;
+		}
+		Some(fee)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{FeeCurve, FeePolicyConfig, FeePolicyManager};
+	use crate::events::{Event, MessageSendEvent, MessageSendEventsProvider};
+	use crate::ln::functional_test_utils::*;
+
+	#[test]
+	fn fee_curve_interpolates() {
+		assert!(FeeCurve::new(Vec::new()).is_err());
+		assert!(FeeCurve::new(vec![(10, 1), (10, 2)]).is_err());
+		assert!(FeeCurve::new(vec![(10, 1), (5, 2)]).is_err());
+
+		let curve = FeeCurve::new(vec![(100, 1_000), (500, 200), (1000, 600)]).unwrap();
+		assert_eq!(curve.value_at(0), 1_000);
+		assert_eq!(curve.value_at(100), 1_000);
+		assert_eq!(curve.value_at(300), 600);
+		assert_eq!(curve.value_at(500), 200);
+		assert_eq!(curve.value_at(750), 400);
+		assert_eq!(curve.value_at(1000), 600);
+		assert_eq!(curve.value_at(u64::max_value()), 600);
+		assert_eq!(FeeCurve::constant(42).value_at(12345), 42);
+	}
+
+	#[test]
+	fn updates_fees_from_liquidity_and_volume() {
+		let chanmon_cfgs = create_chanmon_cfgs(2);
+		let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+		let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+		let channel_id = create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 100_000, 0).2;
+
+		// All of the channel's liquidity, less the reserve and fees, is on nodes[0]'s side.
+		let config = FeePolicyConfig {
+			liquidity_curve: FeeCurve::new(vec![(0, 5_000), (1000, 1_000)]).unwrap(),
+			volume_curve: FeeCurve::new(vec![(0, 100), (1_000_000, 200)]).unwrap(),
+			update_interval_ticks: 2,
+			min_fee_change_millionths: 10,
+			public_channels_only: true,
+		};
+		let fee_policy = FeePolicyManager::new(config, nodes[0].logger);
+
+		let fee_of_channel = || nodes[0].node.list_channels()[0].config.unwrap().forwarding_fee_proportional_millionths;
+		let initial_fee = fee_of_channel();
+
+		// Fees are only evaluated every `update_interval_ticks`.
+		fee_policy.timer_tick_occurred(&nodes[0].node);
+		assert_eq!(fee_of_channel(), initial_fee);
+		assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+
+		fee_policy.timer_tick_occurred(&nodes[0].node);
+		let liquidity_only_fee = fee_of_channel();
+		assert!(liquidity_only_fee > 1_000 && liquidity_only_fee < 1_500);
+		let events = nodes[0].node.get_and_clear_pending_msg_events();
+		assert_eq!(events.len(), 1);
+		match events[0] {
+			MessageSendEvent::BroadcastChannelUpdate { ref msg } =>
+				assert_eq!(msg.contents.fee_proportional_millionths, liquidity_only_fee),
+			_ => panic!("Unexpected event"),
+		}
+
+		// Without any change in liquidity or volume, no update is gossiped.
+		fee_policy.timer_tick_occurred(&nodes[0].node);
+		fee_policy.timer_tick_occurred(&nodes[0].node);
+		assert_eq!(fee_of_channel(), liquidity_only_fee);
+		assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+
+		// Forwarding over the channel doubles its fee, until the volume is no longer recent.
+		fee_policy.record_event(&Event::PaymentForwarded {
+			prev_channel_id: Some([0; 32]),
+			next_channel_id: Some(channel_id),
+			fee_earned_msat: Some(1_000),
+			claim_from_onchain_tx: false,
+			outbound_amount_forwarded_msat: Some(1_000_000),
+		});
+		fee_policy.timer_tick_occurred(&nodes[0].node);
+		fee_policy.timer_tick_occurred(&nodes[0].node);
+		assert_eq!(fee_of_channel(), liquidity_only_fee * 2);
+		assert_eq!(nodes[0].node.get_and_clear_pending_msg_events().len(), 1);
+
+		fee_policy.timer_tick_occurred(&nodes[0].node);
+		fee_policy.timer_tick_occurred(&nodes[0].node);
+		assert_eq!(fee_of_channel(), liquidity_only_fee);
+		assert_eq!(nodes[0].node.get_and_clear_pending_msg_events().len(), 1);
+	}
+}
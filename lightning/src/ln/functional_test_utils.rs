@@ -9,6 +9,34 @@
 
 //! A bunch of useful utilities for building networks of nodes and exchanging messages between
 //! nodes for functional tests.
+//!
+//! These are the same utilities LDK uses for its own functional tests and are available to
+//! downstream crates via the `test-harness` feature, allowing integration tests to run against
+//! real [`ChannelManager`]s and [`ChannelMonitor`]s rather than mocks. Note that this module is
+//! not covered by any API stability guarantees and may change in any release.
+//!
+//! A network is created in four steps, see [`create_chanmon_cfgs`], [`create_node_cfgs`],
+//! [`create_node_chanmgrs`] and [`create_network`]. From there, channels may be opened with, e.g.,
+//! [`create_announced_chan_between_nodes`], payments made with [`route_payment`],
+//! [`claim_payment`] and [`fail_payment`], and the chain advanced or reorganized with
+//! [`connect_blocks`], [`mine_transaction`] and [`disconnect_blocks`]:
+//! ```
+//! use lightning::ln::functional_test_utils::*;
+//!
+//! let chanmon_cfgs = create_chanmon_cfgs(3);
+//! let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+//! let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+//! let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+//!
+//! create_announced_chan_between_nodes(&nodes, 0, 1);
+//! create_announced_chan_between_nodes(&nodes, 1, 2);
+//!
+//! let (payment_preimage, _, _) = route_payment(&nodes[0], &[&nodes[1], &nodes[2]], 1_000_000);
+//! claim_payment(&nodes[0], &[&nodes[1], &nodes[2]], payment_preimage);
+//! ```
+//!
+//! Each node logs via a [`test_utils::TestLogger`] and panics if any messages or events are left
+//! unhandled when it is dropped, so each step of a test must consume what it generates.
 
 use crate::chain::{BestBlock, ChannelMonitorUpdateStatus, Confirm, Listen, Watch};
 use crate::sign::EntropySource;
@@ -204,6 +232,8 @@ pub fn create_dummy_block(prev_blockhash: BlockHash, time: u32, txdata: Vec<Tran
 	Block { header: create_dummy_header(prev_blockhash, time), txdata }
 }
 
+/// Connects `depth` empty blocks on top of the node's current best block, returning the hash of
+/// the last one.
 pub fn connect_blocks<'a, 'b, 'c, 'd>(node: &'a Node<'b, 'c, 'd>, depth: u32) -> BlockHash {
 	let skip_intermediaries = node.connect_style.borrow().skips_blocks();
 
@@ -220,6 +250,7 @@ pub fn connect_blocks<'a, 'b, 'c, 'd>(node: &'a Node<'b, 'c, 'd>, depth: u32) ->
 	hash
 }
 
+/// Connects the given block on top of the node's current best block.
 pub fn connect_block<'a, 'b, 'c, 'd>(node: &'a Node<'b, 'c, 'd>, block: &Block) {
 	do_connect_block(node, block.clone(), false);
 }
@@ -291,6 +322,8 @@ fn do_connect_block<'a, 'b, 'c, 'd>(node: &'a Node<'b, 'c, 'd>, block: Block, sk
 	node.node.test_process_background_events();
 }
 
+/// Disconnects the `count` most recently connected blocks, e.g. to simulate a reorg before
+/// connecting blocks of a competing chain via [`connect_blocks`] or [`connect_block`].
 pub fn disconnect_blocks<'a, 'b, 'c, 'd>(node: &'a Node<'b, 'c, 'd>, count: u32) {
 	call_claimable_balances(node);
 	#[cfg(feature = "std")] {
@@ -695,6 +728,12 @@ pub fn remove_first_msg_event_to_node(msg_node_id: &PublicKey, msg_events: &mut
 		MessageSendEvent::SendClosingSigned { node_id, .. } => {
 			node_id == msg_node_id
 		},
+		MessageSendEvent::SendClosingComplete { node_id, .. } => {
+			node_id == msg_node_id
+		},
+		MessageSendEvent::SendClosingSig { node_id, .. } => {
+			node_id == msg_node_id
+		},
 		MessageSendEvent::SendShutdown { node_id, .. } => {
 			node_id == msg_node_id
 		},
@@ -731,6 +770,12 @@ pub fn remove_first_msg_event_to_node(msg_node_id: &PublicKey, msg_events: &mut
 		MessageSendEvent::SendGossipTimestampFilter { node_id, .. } => {
 			node_id == msg_node_id
 		},
+		MessageSendEvent::SendPeerStorage { node_id, .. } => {
+			node_id == msg_node_id
+		},
+		MessageSendEvent::SendYourPeerStorage { node_id, .. } => {
+			node_id == msg_node_id
+		},
 		MessageSendEvent::SendAcceptChannelV2 { node_id, .. } => {
 			node_id == msg_node_id
 		},
@@ -1190,6 +1235,8 @@ pub fn create_chan_between_nodes_with_value_b<'a, 'b, 'c>(node_a: &Node<'a, 'b,
 	((*announcement).clone(), as_update, bs_update)
 }
 
+/// Opens, confirms and announces a channel from `nodes[a]` to `nodes[b]`, returning both
+/// channel updates, the channel id and the funding transaction.
 pub fn create_announced_chan_between_nodes<'a, 'b, 'c, 'd>(nodes: &'a Vec<Node<'b, 'c, 'd>>, a: usize, b: usize) -> (msgs::ChannelUpdate, msgs::ChannelUpdate, [u8; 32], Transaction) {
 	create_announced_chan_between_nodes_with_value(nodes, a, b, 100000, 10001)
 }
@@ -1853,7 +1900,7 @@ pub fn expect_payment_sent<CM: AChannelManager, H: NodeHolder<CM=CM>>(node: &H,
 		assert_eq!(events.len(), 1);
 	}
 	let expected_payment_id = match events[0] {
-		Event::PaymentSent { ref payment_id, ref payment_preimage, ref payment_hash, ref fee_paid_msat } => {
+		Event::PaymentSent { ref payment_id, ref payment_preimage, ref payment_hash, ref fee_paid_msat, .. } => {
 			assert_eq!(expected_payment_preimage, *payment_preimage);
 			assert_eq!(expected_payment_hash, *payment_hash);
 			if let Some(expected_fee_msat) = expected_fee_msat_opt {
@@ -2029,6 +2076,8 @@ pub fn expect_payment_failed_conditions_event<'a, 'b, 'c, 'd, 'e>(
 	if conditions.expected_mpp_parts_remain { assert_eq!(payment_failed_events.len(), 1); } else { assert_eq!(payment_failed_events.len(), 2); }
 	let expected_payment_id = match &payment_failed_events[0] {
 		Event::PaymentPathFailed { payment_hash, payment_failed_permanently, payment_id, failure,
+			#[cfg(test)]
+			failure_reason,
 			#[cfg(test)]
 			error_code,
 			#[cfg(test)]
@@ -2039,6 +2088,7 @@ pub fn expect_payment_failed_conditions_event<'a, 'b, 'c, 'd, 'e>(
 			{
 				assert!(error_code.is_some(), "expected error_code.is_some() = true");
 				assert!(error_data.is_some(), "expected error_data.is_some() = true");
+				assert_eq!(*failure_reason, error_code.map(crate::events::FailureReason::from), "unexpected failure_reason");
 				if let Some((code, data)) = conditions.expected_htlc_error_data {
 					assert_eq!(error_code.unwrap(), code, "unexpected error code");
 					assert_eq!(&error_data.as_ref().unwrap()[..], data, "unexpected error data");
@@ -2072,7 +2122,7 @@ pub fn expect_payment_failed_conditions_event<'a, 'b, 'c, 'd, 'e>(
 	};
 	if !conditions.expected_mpp_parts_remain {
 		match &payment_failed_events[1] {
-			Event::PaymentFailed { ref payment_hash, ref payment_id, ref reason } => {
+			Event::PaymentFailed { ref payment_hash, ref payment_id, ref reason, .. } => {
 				assert_eq!(*payment_hash, expected_payment_hash, "unexpected second payment_hash");
 				assert_eq!(*payment_id, expected_payment_id);
 				assert_eq!(reason.unwrap(), if expected_payment_failed_permanently {
@@ -2124,7 +2174,7 @@ pub fn do_pass_along_path<'a, 'b, 'c>(origin_node: &Node<'a, 'b, 'c>, expected_p
 				match &events_2[0] {
 					Event::PaymentClaimable { ref payment_hash, ref purpose, amount_msat,
 						receiver_node_id, ref via_channel_id, ref via_user_channel_id,
-						claim_deadline, onion_fields,
+						claim_deadline, onion_fields, ..
 					} => {
 						assert_eq!(our_payment_hash, *payment_hash);
 						assert_eq!(node.node.get_our_node_id(), receiver_node_id.unwrap());
@@ -2205,6 +2255,10 @@ pub fn do_claim_payment_along_route<'a, 'b, 'c>(origin_node: &Node<'a, 'b, 'c>,
 
 	check_added_monitors!(expected_paths[0].last().unwrap(), expected_paths.len());
 
+	pass_claimed_payment_along_route(origin_node, expected_paths, skip_last, our_payment_preimage)
+}
+
+pub fn pass_claimed_payment_along_route<'a, 'b, 'c>(origin_node: &Node<'a, 'b, 'c>, expected_paths: &[&[&Node<'a, 'b, 'c>]], skip_last: bool, our_payment_preimage: PaymentPreimage) -> u64 {
 	let mut expected_total_fee_msat = 0;
 
 	macro_rules! msgs_from_ev {
@@ -2327,12 +2381,18 @@ pub fn claim_payment_along_route<'a, 'b, 'c>(origin_node: &Node<'a, 'b, 'c>, exp
 	}
 }
 
+/// Claims a payment previously sent along `expected_route` by [`route_payment`], asserting the
+/// expected messages and events on each node along the way.
 pub fn claim_payment<'a, 'b, 'c>(origin_node: &Node<'a, 'b, 'c>, expected_route: &[&Node<'a, 'b, 'c>], our_payment_preimage: PaymentPreimage) {
 	claim_payment_along_route(origin_node, &[expected_route], false, our_payment_preimage);
 }
 
 pub const TEST_FINAL_CLTV: u32 = 70;
 
+/// Routes a payment of `recv_value` msat from `origin_node` along `expected_route`, which need
+/// not include the origin, asserting it is received by the last node without claiming it.
+///
+/// Returns the payment preimage, hash and secret.
 pub fn route_payment<'a, 'b, 'c>(origin_node: &Node<'a, 'b, 'c>, expected_route: &[&Node<'a, 'b, 'c>], recv_value: u64) -> (PaymentPreimage, PaymentHash, PaymentSecret) {
 	let payment_params = PaymentParameters::from_node_id(expected_route.last().unwrap().node.get_our_node_id(), TEST_FINAL_CLTV)
 		.with_bolt11_features(expected_route.last().unwrap().node.invoice_features()).unwrap();
@@ -2371,6 +2431,7 @@ pub fn route_over_limit<'a, 'b, 'c>(origin_node: &Node<'a, 'b, 'c>, expected_rou
 		assert!(err.contains("Cannot send value that would put us over the max HTLC value in flight our peer will accept")));
 }
 
+/// Routes a payment via [`route_payment`] and claims it via [`claim_payment`].
 pub fn send_payment<'a, 'b, 'c>(origin: &Node<'a, 'b, 'c>, expected_route: &[&Node<'a, 'b, 'c>], recv_value: u64) -> (PaymentPreimage, PaymentHash, PaymentSecret) {
 	let res = route_payment(&origin, expected_route, recv_value);
 	claim_payment(&origin, expected_route, res.0);
@@ -2474,7 +2535,7 @@ pub fn pass_failed_payment_back<'a, 'b, 'c>(origin_node: &Node<'a, 'b, 'c>, expe
 			};
 			if i == expected_paths.len() - 1 {
 				match events[1] {
-					Event::PaymentFailed { ref payment_hash, ref payment_id, ref reason } => {
+					Event::PaymentFailed { ref payment_hash, ref payment_id, ref reason, .. } => {
 						assert_eq!(*payment_hash, our_payment_hash, "unexpected second payment_hash");
 						assert_eq!(*payment_id, expected_payment_id);
 						assert_eq!(reason.unwrap(), expected_fail_reason);
@@ -2492,10 +2553,14 @@ pub fn pass_failed_payment_back<'a, 'b, 'c>(origin_node: &Node<'a, 'b, 'c>, expe
 	check_added_monitors!(expected_paths[0].last().unwrap(), 0);
 }
 
+/// Fails a payment previously sent along `expected_path` by [`route_payment`] back to the
+/// origin, asserting the expected messages and events on each node along the way.
 pub fn fail_payment<'a, 'b, 'c>(origin_node: &Node<'a, 'b, 'c>, expected_path: &[&Node<'a, 'b, 'c>], our_payment_hash: PaymentHash)  {
 	fail_payment_along_route(origin_node, &[&expected_path[..]], false, our_payment_hash);
 }
 
+/// Creates the chain, broadcaster, fee estimator, logger, persister and keys configuration for
+/// `node_count` test nodes. This is the first step in creating a network via [`create_network`].
 pub fn create_chanmon_cfgs(node_count: usize) -> Vec<TestChanMonCfg> {
 	let mut chan_mon_cfgs = Vec::new();
 	for i in 0..node_count {
@@ -2514,6 +2579,8 @@ pub fn create_chanmon_cfgs(node_count: usize) -> Vec<TestChanMonCfg> {
 	chan_mon_cfgs
 }
 
+/// Creates the chain monitor, keys manager and router for each node from the configurations
+/// returned by [`create_chanmon_cfgs`].
 pub fn create_node_cfgs<'a>(node_count: usize, chanmon_cfgs: &'a Vec<TestChanMonCfg>) -> Vec<NodeCfg<'a>> {
 	let mut nodes = Vec::new();
 
@@ -2554,6 +2621,8 @@ pub fn test_default_channel_config() -> UserConfig {
 	default_config
 }
 
+/// Creates a [`ChannelManager`] for each node, using the corresponding [`UserConfig`] in
+/// `node_config` or [`test_default_channel_config`] if `None`.
 pub fn create_node_chanmgrs<'a, 'b>(node_count: usize, cfgs: &'a Vec<NodeCfg<'b>>, node_config: &[Option<UserConfig>]) -> Vec<ChannelManager<&'a TestChainMonitor<'b>, &'b test_utils::TestBroadcaster, &'a test_utils::TestKeysInterface, &'a test_utils::TestKeysInterface, &'a test_utils::TestKeysInterface, &'b test_utils::TestFeeEstimator, &'a test_utils::TestRouter<'b>, &'b test_utils::TestLogger>> {
 	let mut chanmgrs = Vec::new();
 	for i in 0..node_count {
@@ -2570,6 +2639,7 @@ pub fn create_node_chanmgrs<'a, 'b>(node_count: usize, cfgs: &'a Vec<NodeCfg<'b>
 	chanmgrs
 }
 
+/// Finishes creating a network of test nodes, connecting each pair of nodes to each other.
 pub fn create_network<'a, 'b: 'a, 'c: 'b>(node_count: usize, cfgs: &'b Vec<NodeCfg<'c>>, chan_mgrs: &'a Vec<ChannelManager<&'b TestChainMonitor<'c>, &'c test_utils::TestBroadcaster, &'b test_utils::TestKeysInterface, &'b test_utils::TestKeysInterface, &'b test_utils::TestKeysInterface, &'c test_utils::TestFeeEstimator, &'c test_utils::TestRouter, &'c test_utils::TestLogger>>) -> Vec<Node<'a, 'b, 'c>> {
 	let mut nodes = Vec::new();
 	let chan_count = Rc::new(RefCell::new(0));
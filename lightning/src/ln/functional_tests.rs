@@ -13,12 +13,12 @@
 
 use crate::chain;
 use crate::chain::{ChannelMonitorUpdateStatus, Confirm, Listen, Watch};
-use crate::chain::chaininterface::LowerBoundedFeeEstimator;
+use crate::chain::chaininterface::{ConfirmationTarget, LowerBoundedFeeEstimator};
 use crate::chain::channelmonitor;
 use crate::chain::channelmonitor::{CLTV_CLAIM_BUFFER, LATENCY_GRACE_PERIOD_BLOCKS, ANTI_REORG_DELAY};
 use crate::chain::transaction::OutPoint;
 use crate::sign::{ChannelSigner, EcdsaChannelSigner, EntropySource};
-use crate::events::{Event, MessageSendEvent, MessageSendEventsProvider, PathFailure, PaymentPurpose, ClosureReason, HTLCDestination, PaymentFailureReason};
+use crate::events::{Event, MessageSendEvent, MessageSendEventsProvider, PathFailure, PaymentPurpose, ClosureReason, FundingDropReason, HTLCDestination, PaymentFailureReason};
 use crate::ln::{PaymentPreimage, PaymentSecret, PaymentHash};
 use crate::ln::channel::{commitment_tx_base_weight, COMMITMENT_TX_WEIGHT_PER_HTLC, CONCURRENT_INBOUND_HTLC_FEE_BUFFER, FEE_SPIKE_BUFFER_FEE_INCREASE_MULTIPLE, MIN_AFFORDABLE_HTLC_COUNT, get_holder_selected_channel_reserve_satoshis, OutboundV1Channel, InboundV1Channel};
 use crate::ln::channelmanager::{self, PaymentId, RAACommitmentOrder, PaymentSendFailure, RecipientOnionFields, BREAKDOWN_TIMEOUT, ENABLE_GOSSIP_TICKS, DISABLE_GOSSIP_TICKS, MIN_CLTV_EXPIRY_DELTA};
@@ -32,10 +32,10 @@ use crate::ln::msgs;
 use crate::ln::msgs::{ChannelMessageHandler, RoutingMessageHandler, ErrorAction};
 use crate::util::enforcing_trait_impls::EnforcingSigner;
 use crate::util::test_utils;
-use crate::util::errors::APIError;
+use crate::util::errors::{APIError, OpenChannelError, SendHtlcError};
 use crate::util::ser::{Writeable, ReadableArgs};
 use crate::util::string::UntrustedString;
-use crate::util::config::UserConfig;
+use crate::util::config::{UserConfig, ChannelConfigUpdate, ChannelDisableConfig, ForceCloseFeePolicy, HandshakeLimitViolation, InboundChannelOverrides};
 
 use bitcoin::hash_types::BlockHash;
 use bitcoin::blockdata::script::{Builder, Script};
@@ -134,8 +134,9 @@ fn test_funding_exceeds_no_wumbo_limit() {
 	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
 
 	match nodes[0].node.create_channel(nodes[1].node.get_our_node_id(), MAX_FUNDING_SATOSHIS_NO_WUMBO + 1, 0, 42, None) {
-		Err(APIError::APIMisuseError { err }) => {
-			assert_eq!(format!("funding_value must not exceed {}, it was {}", MAX_FUNDING_SATOSHIS_NO_WUMBO, MAX_FUNDING_SATOSHIS_NO_WUMBO + 1), err);
+		Err(OpenChannelError::ChannelValueTooHigh { channel_value_satoshis, max_channel_value_satoshis }) => {
+			assert_eq!(channel_value_satoshis, MAX_FUNDING_SATOSHIS_NO_WUMBO + 1);
+			assert_eq!(max_channel_value_satoshis, MAX_FUNDING_SATOSHIS_NO_WUMBO);
 		},
 		_ => panic!()
 	}
@@ -1131,7 +1132,7 @@ fn holding_cell_htlc_counting() {
 	{
 		unwrap_send_err!(nodes[1].node.send_payment_with_route(&route, payment_hash_1,
 				RecipientOnionFields::secret_only(payment_secret_1), PaymentId(payment_hash_1.0)
-			), true, APIError::ChannelUnavailable { .. }, {});
+			), true, APIError::ChannelCannotSendHtlc { .. }, {});
 		assert!(nodes[1].node.get_and_clear_pending_msg_events().is_empty());
 	}
 
@@ -1355,7 +1356,7 @@ fn test_basic_channel_reserve() {
 		RecipientOnionFields::secret_only(our_payment_secret), PaymentId(our_payment_hash.0)).err().unwrap();
 	match err {
 		PaymentSendFailure::AllFailedResendSafe(ref fails) => {
-			if let &APIError::ChannelUnavailable { .. } = &fails[0] {}
+			if let &APIError::ChannelCannotSendHtlc { .. } = &fails[0] {}
 			else { panic!("Unexpected error variant"); }
 		},
 		_ => panic!("Unexpected error variant"),
@@ -1392,6 +1393,7 @@ fn test_fee_spike_violation_fails_htlc() {
 		amount_msat: htlc_msat,
 		payment_hash: payment_hash,
 		cltv_expiry: htlc_cltv,
+		blinding_point: None,
 		onion_routing_packet: onion_packet,
 	};
 
@@ -1506,6 +1508,65 @@ fn test_fee_spike_violation_fails_htlc() {
 	check_added_monitors!(nodes[1], 2);
 }
 
+#[test]
+fn test_fee_spike_buffer_forward_rejection_event() {
+	// Test that a forward over a channel we fund which fails only because of our configured
+	// fee-spike buffer generates an `Event::HTLCRejectedForFeeSpikeBuffer`.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let mut config_100_percent = test_default_channel_config();
+	config_100_percent.channel_handshake_config.max_inbound_htlc_value_in_flight_percent_of_channel = 100;
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, Some(config_100_percent)]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 1_000_000, 0);
+	let chan_2 = create_announced_chan_between_nodes_with_value(&nodes, 1, 2, 100_000, 50_000_000);
+
+	let next_outbound_htlc_limit_msat = || {
+		nodes[1].node.list_channels().iter().find(|c| c.channel_id == chan_2.2).unwrap().next_outbound_htlc_limit_msat
+	};
+	let limit_with_buffer_msat = next_outbound_htlc_limit_msat();
+	nodes[1].node.update_partial_channel_config(&nodes[2].node.get_our_node_id(), &[chan_2.2], &ChannelConfigUpdate {
+		fee_spike_buffer_multiple: Some(1), ..Default::default()
+	}).unwrap();
+	let limit_without_buffer_msat = next_outbound_htlc_limit_msat();
+	assert!(limit_without_buffer_msat > limit_with_buffer_msat + 1000);
+	nodes[1].node.update_partial_channel_config(&nodes[2].node.get_our_node_id(), &[chan_2.2], &ChannelConfigUpdate {
+		fee_spike_buffer_multiple: Some(2), ..Default::default()
+	}).unwrap();
+	assert!(nodes[1].node.get_and_clear_pending_msg_events().is_empty());
+
+	let amount_msat = limit_with_buffer_msat + 1000;
+	let (route, payment_hash, _, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[2], amount_msat);
+	nodes[0].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	let payment_event = SendEvent::from_node(&nodes[0]);
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &payment_event.msgs[0]);
+	commitment_signed_dance!(nodes[1], nodes[0], payment_event.commitment_msg, false);
+	expect_pending_htlcs_forwardable!(nodes[1]);
+
+	let mut events = nodes[1].node.get_and_clear_pending_events();
+	let rejection_idx = events.iter().position(|e| matches!(e, Event::HTLCRejectedForFeeSpikeBuffer { .. })).unwrap();
+	match events.remove(rejection_idx) {
+		Event::HTLCRejectedForFeeSpikeBuffer { channel_id, counterparty_node_id, htlc_amount_msat, next_outbound_htlc_limit_msat } => {
+			assert_eq!(channel_id, chan_2.2);
+			assert_eq!(counterparty_node_id, nodes[2].node.get_our_node_id());
+			assert_eq!(htlc_amount_msat, amount_msat);
+			assert_eq!(next_outbound_htlc_limit_msat, limit_with_buffer_msat);
+		},
+		_ => panic!("Unexpected event"),
+	}
+	expect_pending_htlcs_forwardable_conditions(events,
+		&[HTLCDestination::NextHopChannel { node_id: Some(nodes[2].node.get_our_node_id()), channel_id: chan_2.2 }]);
+	nodes[1].node.process_pending_htlc_forwards();
+	check_added_monitors!(nodes[1], 1);
+
+	let bs_fail_updates = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &bs_fail_updates.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], bs_fail_updates.commitment_signed, false, true);
+	expect_payment_failed_with_update!(nodes[0], payment_hash, false, chan_2.0.contents.short_channel_id, false);
+}
+
 #[test]
 fn test_chan_reserve_violation_outbound_htlc_inbound_chan() {
 	let mut chanmon_cfgs = create_chanmon_cfgs(2);
@@ -1537,7 +1598,7 @@ fn test_chan_reserve_violation_outbound_htlc_inbound_chan() {
 	// However one more HTLC should be significantly over the reserve amount and fail.
 	unwrap_send_err!(nodes[1].node.send_payment_with_route(&route, our_payment_hash,
 			RecipientOnionFields::secret_only(our_payment_secret), PaymentId(our_payment_hash.0)
-		), true, APIError::ChannelUnavailable { .. }, {});
+		), true, APIError::ChannelCannotSendHtlc { .. }, {});
 	assert!(nodes[1].node.get_and_clear_pending_msg_events().is_empty());
 }
 
@@ -1581,6 +1642,7 @@ fn test_chan_reserve_violation_inbound_htlc_outbound_channel() {
 		amount_msat: htlc_msat,
 		payment_hash: payment_hash,
 		cltv_expiry: htlc_cltv,
+		blinding_point: None,
 		onion_routing_packet: onion_packet,
 	};
 
@@ -1633,7 +1695,7 @@ fn test_chan_reserve_dust_inbound_htlcs_outbound_chan() {
 	route.paths[0].hops[0].fee_msat += 1;
 	unwrap_send_err!(nodes[1].node.send_payment_with_route(&route, our_payment_hash,
 			RecipientOnionFields::secret_only(our_payment_secret), PaymentId(our_payment_hash.0)
-		), true, APIError::ChannelUnavailable { .. }, {});
+		), true, APIError::ChannelCannotSendHtlc { .. }, {});
 }
 
 #[test]
@@ -1652,7 +1714,10 @@ fn test_chan_init_feerate_unaffordability() {
 	// HTLC.
 	let mut push_amt = 100_000_000;
 	push_amt -= commit_tx_fee_msat(feerate_per_kw, MIN_AFFORDABLE_HTLC_COUNT as u64, opt_anchors);
-	assert_eq!(nodes[0].node.create_channel(nodes[1].node.get_our_node_id(), 100_000, push_amt + 1, 42, None).unwrap_err(),
+	let err = nodes[0].node.create_channel(nodes[1].node.get_our_node_id(), 100_000, push_amt + 1, 42, None).unwrap_err();
+	let commitment_tx_fee_msat = commit_tx_fee_msat(feerate_per_kw, MIN_AFFORDABLE_HTLC_COUNT as u64, opt_anchors);
+	assert_eq!(err, OpenChannelError::InsufficientFundsForFees { value_to_self_msat: commitment_tx_fee_msat - 1, commitment_tx_fee_msat });
+	assert_eq!(APIError::from(err),
 		APIError::APIMisuseError { err: "Funding amount (356) can't even pay fee for initial commitment transaction fee of 357.".to_string() });
 
 	// During open, we don't have a "counterparty channel reserve" to check against, so that
@@ -1757,6 +1822,7 @@ fn test_chan_reserve_violation_inbound_htlc_inbound_chan() {
 		amount_msat: htlc_msat + 1,
 		payment_hash: our_payment_hash_1,
 		cltv_expiry: htlc_cltv,
+		blinding_point: None,
 		onion_routing_packet: onion_packet,
 	};
 
@@ -1841,7 +1907,7 @@ fn test_channel_reserve_holding_cell_htlcs() {
 
 		unwrap_send_err!(nodes[0].node.send_payment_with_route(&route, our_payment_hash,
 				RecipientOnionFields::secret_only(our_payment_secret), PaymentId(our_payment_hash.0)
-			), true, APIError::ChannelUnavailable { .. }, {});
+			), true, APIError::ChannelCannotSendHtlc { .. }, {});
 		assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
 	}
 
@@ -1913,7 +1979,7 @@ fn test_channel_reserve_holding_cell_htlcs() {
 		let (_, our_payment_hash, our_payment_secret) = get_payment_preimage_hash!(nodes[2]);
 		unwrap_send_err!(nodes[0].node.send_payment_with_route(&route, our_payment_hash,
 				RecipientOnionFields::secret_only(our_payment_secret), PaymentId(our_payment_hash.0)
-			), true, APIError::ChannelUnavailable { .. }, {});
+			), true, APIError::ChannelCannotSendHtlc { .. }, {});
 		assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
 	}
 
@@ -1943,7 +2009,7 @@ fn test_channel_reserve_holding_cell_htlcs() {
 		route.paths[0].hops.last_mut().unwrap().fee_msat += 1;
 		unwrap_send_err!(nodes[0].node.send_payment_with_route(&route, our_payment_hash,
 				RecipientOnionFields::secret_only(our_payment_secret), PaymentId(our_payment_hash.0)
-			), true, APIError::ChannelUnavailable { .. }, {});
+			), true, APIError::ChannelCannotSendHtlc { .. }, {});
 		assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
 	}
 
@@ -3409,6 +3475,7 @@ fn fail_backward_pending_htlc_upon_channel_failure() {
 			amount_msat: 0,
 			payment_hash,
 			cltv_expiry,
+			blinding_point: None,
 			onion_routing_packet,
 		};
 		nodes[0].node.handle_update_add_htlc(&nodes[1].node.get_our_node_id(), &update_add_htlc);
@@ -4266,7 +4333,7 @@ macro_rules! check_spendable_outputs {
 				match event {
 					Event::SpendableOutputs { mut outputs } => {
 						for outp in outputs.drain(..) {
-							txn.push($keysinterface.backing.spend_spendable_outputs(&[&outp], Vec::new(), Builder::new().push_opcode(opcodes::all::OP_RETURN).into_script(), 253, None, &secp_ctx).unwrap());
+							txn.push($keysinterface.backing.spend_spendable_outputs(&[&outp], Vec::new(), Builder::new().push_opcode(opcodes::all::OP_RETURN).into_script(), 253, None, None, &secp_ctx).unwrap());
 							all_outputs.push(outp);
 						}
 					},
@@ -4274,7 +4341,7 @@ macro_rules! check_spendable_outputs {
 				};
 			}
 			if all_outputs.len() > 1 {
-				if let Ok(tx) = $keysinterface.backing.spend_spendable_outputs(&all_outputs.iter().map(|a| a).collect::<Vec<_>>(), Vec::new(), Builder::new().push_opcode(opcodes::all::OP_RETURN).into_script(), 253, None, &secp_ctx) {
+				if let Ok(tx) = $keysinterface.backing.spend_spendable_outputs(&all_outputs.iter().map(|a| a).collect::<Vec<_>>(), Vec::new(), Builder::new().push_opcode(opcodes::all::OP_RETURN).into_script(), 253, None, None, &secp_ctx) {
 					txn.push(tx);
 				}
 			}
@@ -4311,6 +4378,58 @@ fn test_claim_sizeable_push_msat() {
 	assert_eq!(spend_txn[0].input[0].sequence.0, BREAKDOWN_TIMEOUT as u32);
 }
 
+#[test]
+fn test_force_close_feerates_follow_policy() {
+	// Tests that the confirmation target picked by the channel's `ForceCloseFeePolicy` depends on
+	// the HTLCs pending in the channel and is surfaced in `Event::ChannelClosed`.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let mut config = test_default_channel_config();
+	config.channel_config.force_close_fee_policy = ForceCloseFeePolicy {
+		htlc_expiry_target: ConfirmationTarget::HighPriority,
+		pending_htlcs_target: ConfirmationTarget::Normal,
+		no_pending_htlcs_target: ConfirmationTarget::Background,
+	};
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[Some(config), None, None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+
+	let chan_a = create_announced_chan_between_nodes(&nodes, 0, 1);
+	let chan_b = create_announced_chan_between_nodes(&nodes, 0, 2);
+	let feerate = *chanmon_cfgs[0].fee_estimator.sat_per_kw.lock().unwrap();
+
+	// With no HTLCs pending, the policy's `no_pending_htlcs_target` is used.
+	nodes[0].node.force_close_broadcasting_latest_txn(&chan_a.2, &nodes[1].node.get_our_node_id()).unwrap();
+	check_closed_broadcast!(nodes[0], true);
+	check_added_monitors!(nodes[0], 1);
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		Event::ChannelClosed { reason: ClosureReason::HolderForceClosed, force_close_feerates: Some(ref feerates), .. } => {
+			assert_eq!(feerates.confirmation_target, ConfirmationTarget::Background);
+			assert_eq!(feerates.commitment_feerate_sat_per_1000_weight, feerate);
+			assert_eq!(feerates.target_feerate_sat_per_1000_weight, feerate);
+		},
+		_ => panic!("Unexpected event"),
+	}
+
+	// With an HTLC pending which is not yet close to expiring, `pending_htlcs_target` is used.
+	route_payment(&nodes[0], &[&nodes[2]], 1_000_000);
+	nodes[0].node.force_close_broadcasting_latest_txn(&chan_b.2, &nodes[2].node.get_our_node_id()).unwrap();
+	check_closed_broadcast!(nodes[0], true);
+	check_added_monitors!(nodes[0], 1);
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		Event::ChannelClosed { reason: ClosureReason::HolderForceClosed, force_close_feerates: Some(ref feerates), .. } => {
+			assert_eq!(feerates.confirmation_target, ConfirmationTarget::Normal);
+		},
+		_ => panic!("Unexpected event"),
+	}
+	nodes[0].tx_broadcaster.txn_broadcasted.lock().unwrap().clear();
+	nodes[2].node.get_and_clear_pending_events();
+	nodes[2].node.get_and_clear_pending_msg_events();
+}
+
 #[test]
 fn test_claim_on_remote_sizeable_push_msat() {
 	// Same test as previous, just test on remote commitment tx, as per_commitment_point registration changes following you're funder/fundee and
@@ -6027,7 +6146,7 @@ fn test_update_add_htlc_bolt2_sender_value_below_minimum_msat() {
 
 	unwrap_send_err!(nodes[0].node.send_payment_with_route(&route, our_payment_hash,
 			RecipientOnionFields::secret_only(our_payment_secret), PaymentId(our_payment_hash.0)
-		), true, APIError::ChannelUnavailable { .. }, {});
+		), true, APIError::ChannelCannotSendHtlc { .. }, {});
 	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
 }
 
@@ -6044,8 +6163,7 @@ fn test_update_add_htlc_bolt2_sender_zero_value_msat() {
 	route.paths[0].hops[0].fee_msat = 0;
 	unwrap_send_err!(nodes[0].node.send_payment_with_route(&route, our_payment_hash,
 			RecipientOnionFields::secret_only(our_payment_secret), PaymentId(our_payment_hash.0)),
-		true, APIError::ChannelUnavailable { ref err },
-		assert_eq!(err, "Cannot send 0-msat HTLC"));
+		true, APIError::ChannelCannotSendHtlc { err: SendHtlcError::ZeroAmount }, {});
 
 	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
 	nodes[0].logger.assert_log_contains("lightning::ln::channelmanager", "Cannot send 0-msat HTLC", 1);
@@ -6134,7 +6252,7 @@ fn test_update_add_htlc_bolt2_sender_exceed_max_htlc_num_and_htlc_id_increment()
 	}
 	unwrap_send_err!(nodes[0].node.send_payment_with_route(&route, our_payment_hash,
 			RecipientOnionFields::secret_only(our_payment_secret), PaymentId(our_payment_hash.0)
-		), true, APIError::ChannelUnavailable { .. }, {});
+		), true, APIError::ChannelCannotSendHtlc { .. }, {});
 
 	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
 }
@@ -6158,7 +6276,7 @@ fn test_update_add_htlc_bolt2_sender_exceed_max_htlc_value_in_flight() {
 	route.paths[0].hops[0].fee_msat =  max_in_flight + 1;
 	unwrap_send_err!(nodes[0].node.send_payment_with_route(&route, our_payment_hash,
 			RecipientOnionFields::secret_only(our_payment_secret), PaymentId(our_payment_hash.0)
-		), true, APIError::ChannelUnavailable { .. }, {});
+		), true, APIError::ChannelCannotSendHtlc { .. }, {});
 	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
 
 	send_payment(&nodes[0], &[&nodes[1]], max_in_flight);
@@ -6258,6 +6376,7 @@ fn test_update_add_htlc_bolt2_receiver_check_max_htlc_limit() {
 		amount_msat: 1000,
 		payment_hash: our_payment_hash,
 		cltv_expiry: htlc_cltv,
+		blinding_point: None,
 		onion_routing_packet: onion_packet.clone(),
 	};
 
@@ -6427,7 +6546,7 @@ fn test_update_fulfill_htlc_bolt2_update_fail_htlc_before_commitment() {
 	let update_msg = msgs::UpdateFailHTLC{
 		channel_id: chan.2,
 		htlc_id: 0,
-		reason: msgs::OnionErrorPacket { data: Vec::new()},
+		reason: msgs::OnionErrorPacket { data: Vec::new(), attribution_data: None },
 	};
 
 	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &update_msg);
@@ -6962,7 +7081,10 @@ fn test_user_configurable_csv_delay() {
 		&low_our_to_self_config, 0, 42)
 	{
 		match error {
-			APIError::APIMisuseError { err } => { assert!(regex::Regex::new(r"Configured with an unreasonable our_to_self_delay \(\d+\) putting user funds at risks").unwrap().is_match(err.as_str())); },
+			OpenChannelError::ToSelfDelayTooLow { our_to_self_delay, min_to_self_delay } => {
+				assert_eq!(our_to_self_delay, 6);
+				assert_eq!(min_to_self_delay, BREAKDOWN_TIMEOUT);
+			},
 			_ => panic!("Unexpected event"),
 		}
 	} else { assert!(false) }
@@ -7166,6 +7288,62 @@ fn test_announce_disable_channels() {
 	assert!(chans_disabled.is_empty());
 }
 
+#[test]
+fn test_announce_disable_channels_custom_config() {
+	// Tests that the ticks until a channel is gossiped as disabled or enabled and the minimum ticks
+	// between such changes follow `UserConfig::channel_disable`, and that channels are never
+	// gossiped as disabled if `disable_after_ticks` is unset.
+	let mut config_0 = test_default_channel_config();
+	config_0.channel_disable = ChannelDisableConfig {
+		disable_after_ticks: Some(2),
+		enable_after_ticks: 1,
+		min_ticks_between_status_changes: 5,
+	};
+	let mut config_1 = test_default_channel_config();
+	config_1.channel_disable.disable_after_ticks = None;
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[Some(config_0), Some(config_1)]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	nodes[0].node.peer_disconnected(&nodes[1].node.get_our_node_id());
+	nodes[1].node.peer_disconnected(&nodes[0].node.get_our_node_id());
+
+	for _ in 0..2 {
+		nodes[0].node.timer_tick_occurred();
+	}
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+	nodes[0].node.timer_tick_occurred();
+	let msg_events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(msg_events.len(), 1);
+	match msg_events[0] {
+		MessageSendEvent::BroadcastChannelUpdate { ref msg } => assert_eq!(msg.contents.flags & (1<<1), 1<<1),
+		_ => panic!("Unexpected event"),
+	}
+
+	for _ in 0..DISABLE_GOSSIP_TICKS * 2 {
+		nodes[1].node.timer_tick_occurred();
+	}
+	assert!(nodes[1].node.get_and_clear_pending_msg_events().is_empty());
+
+	reconnect_nodes(&nodes[0], &nodes[1], (false, false), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (false, false));
+
+	// The channel would be gossiped as enabled on the second tick, but at least five ticks have to
+	// pass since it was gossiped as disabled.
+	for _ in 0..4 {
+		nodes[0].node.timer_tick_occurred();
+	}
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+	nodes[0].node.timer_tick_occurred();
+	let msg_events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(msg_events.len(), 1);
+	match msg_events[0] {
+		MessageSendEvent::BroadcastChannelUpdate { ref msg } => assert_eq!(msg.contents.flags & (1<<1), 0),
+		_ => panic!("Unexpected event"),
+	}
+}
+
 #[test]
 fn test_bump_penalty_txn_on_revoked_commitment() {
 	// In case of penalty txn with too low feerates for getting into mempools, RBF-bump them to be sure
@@ -7663,6 +7841,30 @@ fn test_channel_conf_timeout() {
 	}
 }
 
+#[test]
+fn test_configured_channel_conf_timeout() {
+	// Tests that the funding confirmation deadline for inbound channels may be configured via
+	// `ResourceLimitsConfig::funding_confirmation_deadline_blocks`.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let mut limited_cfg = test_default_channel_config();
+	limited_cfg.resource_limits.funding_confirmation_deadline_blocks = 144;
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, Some(limited_cfg)]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+	let _funding_tx = create_chan_between_nodes_with_value_init(&nodes[0], &nodes[1], 1_000_000, 100_000);
+
+	connect_blocks(&nodes[1], 143);
+	check_added_monitors!(nodes[1], 0);
+	assert!(nodes[1].node.get_and_clear_pending_msg_events().is_empty());
+
+	connect_blocks(&nodes[1], 1);
+	check_added_monitors!(nodes[1], 1);
+	check_closed_event!(nodes[1], 1, ClosureReason::FundingTimedOut);
+	let close_ev = nodes[1].node.get_and_clear_pending_msg_events();
+	assert_eq!(close_ev.len(), 1);
+}
+
 #[test]
 fn test_override_channel_config() {
 	let chanmon_cfgs = create_chanmon_cfgs(2);
@@ -7752,6 +7954,61 @@ fn test_channel_update_has_correct_htlc_maximum_msat() {
 	assert_eq!(node_3_chan_update.contents.htlc_maximum_msat, channel_value_90_percent_msat);
 }
 
+#[test]
+fn test_zero_reserve_channel() {
+	// Test that with `their_channel_reserve_zero` set, our counterparty is not required to keep a
+	// reserve and can thus spend its full balance.
+	let mut zero_reserve_config = test_default_channel_config();
+	zero_reserve_config.channel_handshake_config.their_channel_reserve_zero = true;
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[Some(zero_reserve_config), None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+	create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 100_000, 10_000_000);
+
+	let chan = &nodes[1].node.list_channels()[0];
+	assert_eq!(chan.unspendable_punishment_reserve, Some(0));
+	assert_eq!(chan.outbound_capacity_msat, 10_000_000);
+	assert_eq!(chan.next_outbound_htlc_limit_msat, 10_000_000);
+	send_payment(&nodes[1], &[&nodes[0]], 10_000_000);
+}
+
+#[test]
+fn test_manually_accept_inbound_channel_from_trusted_peer_0reserve() {
+	// Test that a channel accepted via `accept_inbound_channel_from_trusted_peer_0reserve` requires
+	// no reserve from the channel opener, even though it is below the opener's dust limit.
+	let mut manually_accept_conf = UserConfig::default();
+	manually_accept_conf.manually_accept_inbound_channels = true;
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, Some(manually_accept_conf)]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+	nodes[0].node.create_channel(nodes[1].node.get_our_node_id(), 100_000, 0, 42, None).unwrap();
+	let open_channel = get_event_msg!(nodes[0], MessageSendEvent::SendOpenChannel, nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_open_channel(&nodes[0].node.get_our_node_id(), &open_channel);
+	let events = nodes[1].node.get_and_clear_pending_events();
+	match events[0] {
+		Event::OpenChannelRequest { temporary_channel_id, .. } => {
+			nodes[1].node.accept_inbound_channel_from_trusted_peer_0reserve(&temporary_channel_id, &nodes[0].node.get_our_node_id(), 23).unwrap();
+		}
+		_ => panic!("Unexpected event"),
+	}
+	let accept_channel = get_event_msg!(nodes[1], MessageSendEvent::SendAcceptChannel, nodes[0].node.get_our_node_id());
+	assert_eq!(accept_channel.channel_reserve_satoshis, 0);
+	assert!(accept_channel.dust_limit_satoshis > 0);
+
+	nodes[0].node.handle_accept_channel(&nodes[1].node.get_our_node_id(), &accept_channel);
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		Event::FundingGenerationReady { user_channel_id, .. } => assert_eq!(user_channel_id, 42),
+		_ => panic!("Unexpected event"),
+	}
+	assert_eq!(nodes[0].node.list_channels()[0].unspendable_punishment_reserve, Some(0));
+}
+
 #[test]
 fn test_manually_accept_inbound_channel_request() {
 	let mut manually_accept_conf = UserConfig::default();
@@ -7840,6 +8097,179 @@ fn test_manually_reject_inbound_channel_request() {
 	check_closed_event!(nodes[1], 1, ClosureReason::HolderForceClosed);
 }
 
+#[test]
+fn test_negotiate_inbound_channel_limit_violations() {
+	// Tests that when `ChannelHandshakeLimits::negotiate_limit_violations` is set, an inbound
+	// channel violating our limits is surfaced rather than rejected, and may either be accepted
+	// with overrides or rejected with the violations sent to our counterparty.
+	let mut negotiate_conf = UserConfig::default();
+	negotiate_conf.manually_accept_inbound_channels = true;
+	negotiate_conf.channel_handshake_limits.negotiate_limit_violations = true;
+	negotiate_conf.channel_handshake_limits.max_channel_reserve_satoshis = 500;
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, Some(negotiate_conf)]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+	for accept in [true, false].iter() {
+		nodes[0].node.create_channel(nodes[1].node.get_our_node_id(), 100000, 10001, 42, None).unwrap();
+		let open_channel = get_event_msg!(nodes[0], MessageSendEvent::SendOpenChannel, nodes[1].node.get_our_node_id());
+		nodes[1].node.handle_open_channel(&nodes[0].node.get_our_node_id(), &open_channel);
+		assert!(nodes[1].node.get_and_clear_pending_msg_events().is_empty());
+
+		let events = nodes[1].node.get_and_clear_pending_events();
+		assert_eq!(events.len(), 1);
+		let (temporary_channel_id, limit_violations) = match events[0] {
+			Event::OpenChannelRequest { temporary_channel_id, ref limit_violations, .. } => (temporary_channel_id, limit_violations.clone()),
+			_ => panic!("Unexpected event"),
+		};
+		assert_eq!(limit_violations, vec![HandshakeLimitViolation::ChannelReserveTooHigh {
+			channel_reserve_satoshis: open_channel.channel_reserve_satoshis,
+			max_channel_reserve_satoshis: 500,
+		}]);
+
+		if *accept {
+			// An invalid override leaves the channel awaiting acceptance.
+			let invalid_overrides = InboundChannelOverrides { our_max_accepted_htlcs: Some(0), ..Default::default() };
+			assert!(nodes[1].node.accept_inbound_channel_with_overrides(&temporary_channel_id, &nodes[0].node.get_our_node_id(), 23, invalid_overrides).is_err());
+
+			let overrides = InboundChannelOverrides { their_channel_reserve_satoshis: Some(2000), ..Default::default() };
+			nodes[1].node.accept_inbound_channel_with_overrides(&temporary_channel_id, &nodes[0].node.get_our_node_id(), 23, overrides).unwrap();
+			let accept_channel = get_event_msg!(nodes[1], MessageSendEvent::SendAcceptChannel, nodes[0].node.get_our_node_id());
+			assert_eq!(accept_channel.channel_reserve_satoshis, 2000);
+			nodes[0].node.handle_accept_channel(&nodes[1].node.get_our_node_id(), &accept_channel);
+			let events = nodes[0].node.get_and_clear_pending_events();
+			assert_eq!(events.len(), 1);
+			match events[0] {
+				Event::FundingGenerationReady { .. } => {},
+				_ => panic!("Unexpected event"),
+			}
+		} else {
+			nodes[1].node.reject_inbound_channel(&temporary_channel_id, &nodes[0].node.get_our_node_id(), &limit_violations).unwrap();
+			let msg_events = nodes[1].node.get_and_clear_pending_msg_events();
+			assert_eq!(msg_events.len(), 1);
+			match msg_events[0] {
+				MessageSendEvent::HandleError { ref node_id, action: ErrorAction::SendErrorMessage { ref msg } } => {
+					assert_eq!(*node_id, nodes[0].node.get_our_node_id());
+					assert_eq!(msg.channel_id, temporary_channel_id);
+					assert!(msg.data.contains(&limit_violations[0].to_string()));
+				},
+				_ => panic!("Unexpected event"),
+			}
+			check_closed_event!(nodes[1], 1, ClosureReason::HolderForceClosed);
+			assert!(nodes[1].node.list_channels().is_empty());
+		}
+	}
+}
+
+#[test]
+fn test_funding_transaction_dropped() {
+	// Tests that we generate an event when the funding transaction we broadcast is evicted from the
+	// mempool or double-spent, and that it may then be re-broadcast or, once the double-spend is
+	// reorg-safe, the channel abandoned.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let persister;
+	let new_chain_monitor;
+	let nodes_0_deserialized;
+	let mut nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+	let temporary_channel_id = nodes[0].node.create_channel(nodes[1].node.get_our_node_id(), 100_000, 0, 42, None).unwrap();
+	let open_channel = get_event_msg!(nodes[0], MessageSendEvent::SendOpenChannel, nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_open_channel(&nodes[0].node.get_our_node_id(), &open_channel);
+	let accept_channel = get_event_msg!(nodes[1], MessageSendEvent::SendAcceptChannel, nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_accept_channel(&nodes[1].node.get_our_node_id(), &accept_channel);
+
+	// Give the funding transaction an input, so that we can double-spend it later.
+	let (_, mut funding_tx, _) = create_funding_transaction(&nodes[0], &nodes[1].node.get_our_node_id(), 100_000, 42);
+	let funding_input = TxIn {
+		previous_output: BitcoinOutPoint { txid: bitcoin::Txid::from_slice(&[42; 32]).unwrap(), vout: 0 },
+		script_sig: Script::new(),
+		sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+		witness: Witness::from_vec(vec![vec![1]]),
+	};
+	funding_tx.input.push(funding_input.clone());
+	let funding_txo = OutPoint { txid: funding_tx.txid(), index: 0 };
+	let channel_id = funding_txo.to_channel_id();
+
+	nodes[0].node.funding_transaction_generated(&temporary_channel_id, &nodes[1].node.get_our_node_id(), funding_tx.clone()).unwrap();
+	let funding_created = get_event_msg!(nodes[0], MessageSendEvent::SendFundingCreated, nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_funding_created(&nodes[0].node.get_our_node_id(), &funding_created);
+	check_added_monitors!(nodes[1], 1);
+	expect_channel_pending_event(&nodes[1], &nodes[0].node.get_our_node_id());
+	let funding_signed = get_event_msg!(nodes[1], MessageSendEvent::SendFundingSigned, nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_funding_signed(&nodes[1].node.get_our_node_id(), &funding_signed);
+	check_added_monitors!(nodes[0], 1);
+	expect_channel_pending_event(&nodes[0], &nodes[1].node.get_our_node_id());
+	assert_eq!(nodes[0].tx_broadcaster.txn_broadcasted.lock().unwrap().split_off(0), vec![funding_tx.clone()]);
+
+	// Only our own unconfirmed funding transaction is reported as evicted, after which it may be
+	// re-broadcast.
+	assert!(!nodes[0].node.funding_transaction_evicted(&bitcoin::Txid::from_slice(&[43; 32]).unwrap()));
+	assert!(!nodes[1].node.funding_transaction_evicted(&funding_tx.txid()));
+	assert!(nodes[0].node.funding_transaction_evicted(&funding_tx.txid()));
+	assert_eq!(nodes[0].node.get_and_clear_pending_events(), vec![Event::FundingTransactionDropped {
+		channel_id,
+		counterparty_node_id: nodes[1].node.get_our_node_id(),
+		user_channel_id: 42,
+		funding_txo: funding_txo.into_bitcoin_outpoint(),
+		reason: FundingDropReason::Evicted,
+	}]);
+	nodes[0].node.rebroadcast_funding_transaction(&channel_id, &nodes[1].node.get_our_node_id()).unwrap();
+	assert_eq!(nodes[0].tx_broadcaster.txn_broadcasted.lock().unwrap().split_off(0), vec![funding_tx.clone()]);
+
+	// An evicted funding transaction may still confirm, so the channel can't be abandoned yet.
+	match nodes[0].node.abandon_unfunded_channel(&channel_id, &nodes[1].node.get_our_node_id()) {
+		Err(APIError::APIMisuseError { .. }) => {},
+		_ => panic!("Unexpected result"),
+	}
+
+	// Once a transaction spending the funding transaction's input confirms, it is reported as
+	// double-spent.
+	let conflicting_tx = Transaction {
+		version: 2,
+		lock_time: PackedLockTime::ZERO,
+		input: vec![funding_input],
+		output: vec![TxOut { value: 99_000, script_pubkey: Script::new() }],
+	};
+	mine_transaction(&nodes[0], &conflicting_tx);
+	assert_eq!(nodes[0].node.get_and_clear_pending_events(), vec![Event::FundingTransactionDropped {
+		channel_id,
+		counterparty_node_id: nodes[1].node.get_our_node_id(),
+		user_channel_id: 42,
+		funding_txo: funding_txo.into_bitcoin_outpoint(),
+		reason: FundingDropReason::DoubleSpent { conflicting_txid: conflicting_tx.txid() },
+	}]);
+
+	// The double-spend could still be reorged out until it reached ANTI_REORG_DELAY
+	// confirmations.
+	match nodes[0].node.abandon_unfunded_channel(&channel_id, &nodes[1].node.get_our_node_id()) {
+		Err(APIError::APIMisuseError { .. }) => {},
+		_ => panic!("Unexpected result"),
+	}
+	connect_blocks(&nodes[0], ANTI_REORG_DELAY - 1);
+
+	// The double-spend is remembered across a restart.
+	let funding_monitor_serialized = get_monitor!(nodes[0], channel_id).encode();
+	reload_node!(nodes[0], nodes[0].node.encode(), &[&funding_monitor_serialized], persister, new_chain_monitor, nodes_0_deserialized);
+	nodes[1].node.peer_disconnected(&nodes[0].node.get_our_node_id());
+	assert!(nodes[0].node.get_and_clear_pending_events().is_empty());
+
+	// Only the funder may abandon the channel, which is closed without broadcasting our commitment
+	// transaction.
+	match nodes[1].node.abandon_unfunded_channel(&channel_id, &nodes[0].node.get_our_node_id()) {
+		Err(APIError::APIMisuseError { .. }) => {},
+		_ => panic!("Unexpected result"),
+	}
+	nodes[0].node.abandon_unfunded_channel(&channel_id, &nodes[1].node.get_our_node_id()).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	check_closed_event!(nodes[0], 1, ClosureReason::FundingAbandoned);
+	assert!(nodes[0].tx_broadcaster.txn_broadcasted.lock().unwrap().is_empty());
+	assert_eq!(get_err_msg(&nodes[0], &nodes[1].node.get_our_node_id()).data, "Channel abandoned");
+	assert!(nodes[0].node.list_channels().is_empty());
+}
+
 #[test]
 fn test_reject_funding_before_inbound_channel_accepted() {
 	// This tests that when `UserConfig::manually_accept_inbound_channels` is set to true, inbound
@@ -9688,11 +10118,11 @@ fn do_test_max_dust_htlc_exposure(dust_outbound_balance: bool, exposure_breach_e
 		if on_holder_tx {
 			unwrap_send_err!(nodes[0].node.send_payment_with_route(&route, payment_hash,
 					RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)
-				), true, APIError::ChannelUnavailable { .. }, {});
+				), true, APIError::ChannelCannotSendHtlc { .. }, {});
 		} else {
 			unwrap_send_err!(nodes[0].node.send_payment_with_route(&route, payment_hash,
 					RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)
-				), true, APIError::ChannelUnavailable { .. }, {});
+				), true, APIError::ChannelCannotSendHtlc { .. }, {});
 		}
 	} else if exposure_breach_event == ExposureEvent::AtHTLCReception {
 		let (route, payment_hash, _, payment_secret) = get_route_and_payment_hash!(nodes[1], nodes[0], if on_holder_tx { dust_inbound_htlc_on_holder_tx_msat } else { dust_htlc_on_counterparty_tx_msat + 1 });
@@ -9870,6 +10300,104 @@ fn accept_busted_but_better_fee() {
 	};
 }
 
+#[test]
+fn test_feerate_disagreement_grace_period() {
+	// If a peer sends us a fee update that is too low while we're configured to tolerate feerate
+	// disagreements, we should generate an event, reject HTLCs which would take the value pending
+	// in the channel over our limit, and only close the channel once the grace period has passed.
+	let mut chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let mut config = test_default_channel_config();
+	config.feerate_disagreement.grace_period_ticks = Some(2);
+	config.feerate_disagreement.max_pending_htlc_value_msat = 1_000_000;
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, Some(config)]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+	let chan_id = create_chan_between_nodes(&nodes[0], &nodes[1]).3;
+
+	{
+		let mut feerate_lock = chanmon_cfgs[0].fee_estimator.sat_per_kw.lock().unwrap();
+		*feerate_lock = 1000;
+	}
+	nodes[0].node.timer_tick_occurred();
+	check_added_monitors!(nodes[0], 1);
+
+	let events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		MessageSendEvent::UpdateHTLCs { updates: msgs::CommitmentUpdate { ref update_fee, ref commitment_signed, .. }, .. } => {
+			nodes[1].node.handle_update_fee(&nodes[0].node.get_our_node_id(), update_fee.as_ref().unwrap());
+			commitment_signed_dance!(nodes[1], nodes[0], commitment_signed, false);
+		},
+		_ => panic!("Unexpected event"),
+	};
+	assert!(nodes[1].node.get_and_clear_pending_events().is_empty());
+
+	// Set nodes[1] to expect 5,000 sat/kW, then have nodes[0] decrease their feerate, which
+	// nodes[1] would usually close the channel over.
+	{
+		let mut feerate_lock = chanmon_cfgs[1].fee_estimator.sat_per_kw.lock().unwrap();
+		*feerate_lock = 5000;
+	}
+	{
+		let mut feerate_lock = chanmon_cfgs[0].fee_estimator.sat_per_kw.lock().unwrap();
+		*feerate_lock = 500;
+	}
+	nodes[0].node.timer_tick_occurred();
+	check_added_monitors!(nodes[0], 1);
+
+	let events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		MessageSendEvent::UpdateHTLCs { updates: msgs::CommitmentUpdate { ref update_fee, ref commitment_signed, .. }, .. } => {
+			nodes[1].node.handle_update_fee(&nodes[0].node.get_our_node_id(), update_fee.as_ref().unwrap());
+			commitment_signed_dance!(nodes[1], nodes[0], commitment_signed, false);
+		},
+		_ => panic!("Unexpected event"),
+	};
+
+	let events = nodes[1].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		Event::CounterpartyFeerateDisagreement { channel_id, counterparty_node_id, feerate_per_kw, grace_period_ticks } => {
+			assert_eq!(channel_id, chan_id);
+			assert_eq!(counterparty_node_id, nodes[0].node.get_our_node_id());
+			assert_eq!(feerate_per_kw, 500);
+			assert_eq!(grace_period_ticks, 2);
+		},
+		_ => panic!("Unexpected event"),
+	}
+
+	// While the disagreement lasts, nodes[1] fails back HTLCs over the pending value limit.
+	let (route, payment_hash, _, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[1], 2_000_000);
+	nodes[0].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	let payment_event = SendEvent::from_node(&nodes[0]);
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &payment_event.msgs[0]);
+	commitment_signed_dance!(nodes[1], nodes[0], payment_event.commitment_msg, false, true);
+	nodes[1].logger.assert_log_contains("lightning::ln::channel",
+		"Cannot accept HTLC that would put the value pending in channel", 1);
+
+	let htlc_fail_updates = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	assert!(htlc_fail_updates.update_add_htlcs.is_empty());
+	assert_eq!(htlc_fail_updates.update_fail_htlcs.len(), 1);
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &htlc_fail_updates.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], htlc_fail_updates.commitment_signed, false);
+	expect_payment_failed_conditions(&nodes[0], payment_hash, true, PaymentFailedConditions::new());
+
+	// The channel stays open until the grace period has passed.
+	nodes[1].node.timer_tick_occurred();
+	assert!(nodes[1].node.get_and_clear_pending_events().is_empty());
+	assert_eq!(nodes[1].node.list_channels().len(), 1);
+
+	nodes[1].node.timer_tick_occurred();
+	check_closed_event!(nodes[1], 1, ClosureReason::ProcessingError {
+		err: "Feerate disagreement (at 500 sat/kW) persisted for 2 timer ticks".to_owned() });
+	check_closed_broadcast!(nodes[1], true);
+	check_added_monitors!(nodes[1], 1);
+}
+
 fn do_payment_with_custom_min_final_cltv_expiry(valid_delta: bool, use_user_hash: bool) {
 	let mut chanmon_cfgs = create_chanmon_cfgs(2);
 	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
@@ -10056,3 +10584,60 @@ fn test_disconnects_peer_awaiting_response_ticks() {
 		}
 	}
 }
+
+#[test]
+fn test_forwarded_htlc_settlement_jitter() {
+	// Test that, with `forwarded_htlc_settlement_jitter_max_ms` set, a forwarding node holds back
+	// relaying the preimage to the previous hop until it next processes forwards, or at the latest
+	// until the next timer tick, while still handing it to the previous hop's `ChannelMonitor`
+	// right away.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let mut jitter_config = test_default_channel_config();
+	jitter_config.forwarded_htlc_settlement_jitter_max_ms = 500;
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, Some(jitter_config), None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	let chan_id_1 = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+	create_announced_chan_between_nodes(&nodes, 1, 2);
+
+	for release_on_timer_tick in [false, true].iter() {
+		let (payment_preimage, payment_hash, _) = route_payment(&nodes[0], &[&nodes[1], &nodes[2]], 1_000_000);
+		nodes[2].node.claim_funds(payment_preimage);
+		check_added_monitors!(nodes[2], 1);
+		expect_payment_claimed!(nodes[2], payment_hash, 1_000_000);
+		let carol_updates = get_htlc_update_msgs!(nodes[2], nodes[1].node.get_our_node_id());
+
+		// Bob learns the preimage and provides it to the monitor of his channel with Alice, such
+		// that he could claim the HTLC on-chain, but rather than claiming it from Alice right away
+		// asks to process forwards after a randomized delay.
+		nodes[1].node.handle_update_fulfill_htlc(&nodes[2].node.get_our_node_id(), &carol_updates.update_fulfill_htlcs[0]);
+		check_added_monitors!(nodes[1], 1);
+		assert_eq!(get_monitor!(nodes[1], chan_id_1).get_stored_preimages().get(&payment_hash), Some(&payment_preimage));
+		assert!(nodes[1].node.get_and_clear_pending_msg_events().is_empty());
+		commitment_signed_dance!(nodes[1], nodes[2], carol_updates.commitment_signed, false);
+
+		let events = nodes[1].node.get_and_clear_pending_events();
+		assert_eq!(events.len(), 1);
+		match events[0] {
+			Event::PendingHTLCsForwardable { time_forwardable } => {
+				assert!(time_forwardable >= core::time::Duration::from_millis(100));
+				assert!(time_forwardable <= core::time::Duration::from_millis(600));
+			},
+			_ => panic!("Unexpected event"),
+		}
+
+		if *release_on_timer_tick {
+			nodes[1].node.timer_tick_occurred();
+		} else {
+			nodes[1].node.process_pending_htlc_forwards();
+		}
+		check_added_monitors!(nodes[1], 1);
+		expect_payment_forwarded!(nodes[1], nodes[0], nodes[2], Some(1000), false, false);
+
+		let bob_updates = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+		assert_eq!(bob_updates.update_fulfill_htlcs.len(), 1);
+		nodes[0].node.handle_update_fulfill_htlc(&nodes[1].node.get_our_node_id(), &bob_updates.update_fulfill_htlcs[0]);
+		commitment_signed_dance!(nodes[0], nodes[1], bob_updates.commitment_signed, false);
+		expect_payment_sent!(nodes[0], payment_preimage);
+	}
+}
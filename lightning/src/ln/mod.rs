@@ -13,6 +13,9 @@
 #[macro_use]
 pub mod functional_test_utils;
 
+#[cfg(any(test, feature = "_test_utils"))]
+pub mod simulation;
+
 pub mod channelmanager;
 pub mod inbound_payment;
 pub mod msgs;
@@ -20,6 +23,8 @@ pub mod peer_handler;
 pub mod chan_utils;
 pub mod features;
 pub mod script;
+pub mod static_backup;
+pub mod fee_policy;
 
 #[cfg(fuzzing)]
 pub mod peer_channel_encryptor;
@@ -13,7 +13,9 @@
 use crate::sign::{ChannelSigner, EcdsaChannelSigner};
 #[cfg(anchors)]
 use crate::chain::channelmonitor::LATENCY_GRACE_PERIOD_BLOCKS;
-use crate::chain::channelmonitor::{ANTI_REORG_DELAY, Balance};
+use crate::chain::channelmonitor::{ANTI_REORG_DELAY, Balance, MonitoredHTLCState};
+use crate::chain::MempoolListener;
+use crate::chain::chainmonitor::{BalanceMaturitySchedule, ClaimFeeBumpConfig, MaturingBalance};
 use crate::chain::transaction::OutPoint;
 use crate::chain::chaininterface::LowerBoundedFeeEstimator;
 #[cfg(anchors)]
@@ -108,13 +110,60 @@ fn chanmon_fail_from_stale_commitment() {
 	expect_payment_failed_with_update!(nodes[0], payment_hash, false, update_a.contents.short_channel_id, true);
 }
 
+#[test]
+fn test_monitored_commitments_and_htlcs() {
+	// Tests the read-only accessors exposing the latest commitment transactions of a
+	// ChannelMonitor and the state of the HTLCs in them.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+	let (_, _, chan_id, _) = create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 1_000_000, 0);
+	let (payment_preimage, payment_hash, _) = route_payment(&nodes[0], &[&nodes[1]], 3_000_000);
+
+	let nodes_0_commitment_txid = get_local_commitment_txn!(nodes[0], chan_id)[0].txid();
+	let nodes_1_commitment_txid = get_local_commitment_txn!(nodes[1], chan_id)[0].txid();
+	{
+		let monitor = get_monitor!(nodes[0], chan_id);
+		assert_eq!(monitor.get_latest_holder_commitment_txid(), nodes_0_commitment_txid);
+		assert_eq!(monitor.get_latest_counterparty_commitment_txid(), Some(nodes_1_commitment_txid));
+
+		// The HTLC we offered is in both commitment transactions.
+		let htlcs = monitor.get_monitored_htlcs();
+		assert_eq!(htlcs.len(), 2);
+		for htlc in htlcs.iter() {
+			assert_eq!(htlc.payment_hash, payment_hash);
+			assert_eq!(htlc.amount_msat, 3_000_000);
+			assert!(htlc.outbound);
+			assert!(htlc.transaction_output_index.is_some());
+			assert_eq!(htlc.state, MonitoredHTLCState::Pending);
+		}
+		let holder_commitment_txids: Vec<_> = htlcs.iter()
+			.filter(|htlc| htlc.holder_commitment).map(|htlc| htlc.commitment_txid).collect();
+		assert_eq!(holder_commitment_txids, vec![nodes_0_commitment_txid]);
+	}
+
+	// Once the recipient claims the HTLC, its monitor knows the preimage.
+	nodes[1].node.claim_funds(payment_preimage);
+	check_added_monitors!(nodes[1], 1);
+	expect_payment_claimed!(nodes[1], payment_hash, 3_000_000);
+	let _ = nodes[1].node.get_and_clear_pending_msg_events();
+	let htlcs = get_monitor!(nodes[1], chan_id).get_monitored_htlcs();
+	assert_eq!(htlcs.len(), 2);
+	for htlc in htlcs.iter() {
+		assert!(!htlc.outbound);
+		assert_eq!(htlc.state, MonitoredHTLCState::PreimageKnown);
+	}
+}
+
 fn test_spendable_output<'a, 'b, 'c, 'd>(node: &'a Node<'b, 'c, 'd>, spendable_tx: &Transaction) {
 	let mut spendable = node.chain_monitor.chain_monitor.get_and_clear_pending_events();
 	assert_eq!(spendable.len(), 1);
 	if let Event::SpendableOutputs { outputs } = spendable.pop().unwrap() {
 		assert_eq!(outputs.len(), 1);
 		let spend_tx = node.keys_manager.backing.spend_spendable_outputs(&[&outputs[0]], Vec::new(),
-			Builder::new().push_opcode(opcodes::all::OP_RETURN).into_script(), 253, None, &Secp256k1::new()).unwrap();
+			Builder::new().push_opcode(opcodes::all::OP_RETURN).into_script(), 253, None, None, &Secp256k1::new()).unwrap();
 		check_spends!(spend_tx, spendable_tx);
 	} else { panic!(); }
 }
@@ -247,6 +296,84 @@ fn chanmon_claim_value_coop_close() {
 	check_closed_event!(nodes[1], 1, ClosureReason::CooperativeClosure);
 }
 
+#[test]
+fn balance_report_across_force_close() {
+	// Tests that `ChainMonitor::get_balance_report` counts a channel's balance exactly once, taking
+	// it from the `ChannelDetails` while the channel is open and from the `ChannelMonitor` once it
+	// has closed, and that `ChainMonitor::get_balance_maturity_schedules` reports when the balance
+	// of the closed channel becomes spendable.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+	let (_, _, chan_id, _) =
+		create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 1_000_000, 1_000_000);
+	let funding_txo = nodes[1].node.list_channels()[0].funding_txo.unwrap();
+
+	let channels = nodes[1].node.list_channels();
+	let open_channels: Vec<_> = channels.iter().collect();
+	assert!(nodes[1].chain_monitor.chain_monitor.get_balance_maturity_schedules(&open_channels).is_empty());
+
+	let report = nodes[1].chain_monitor.chain_monitor.get_balance_report(&nodes[1].node.list_channels());
+	assert_eq!(report.channel_balance_msat, 1_000_000);
+	assert!(report.monitor_balances.is_empty());
+	assert_eq!(report.total_balance_satoshis(), 1_000);
+
+	// If the channel is missing from the list of open channels, its balance is taken from the
+	// `ChannelMonitor` instead.
+	let report = nodes[1].chain_monitor.chain_monitor.get_balance_report(&[]);
+	assert_eq!(report.channel_balance_msat, 0);
+	assert_eq!(report.claimable_satoshis, 1_000);
+	assert_eq!(report.total_balance_satoshis(), 1_000);
+
+	nodes[0].node.force_close_broadcasting_latest_txn(&chan_id, &nodes[1].node.get_our_node_id()).unwrap();
+	check_added_monitors(&nodes[0], 1);
+	check_closed_broadcast(&nodes[0], 1, true);
+	check_closed_event(&nodes[0], 1, ClosureReason::HolderForceClosed, false);
+
+	let commitment_tx = {
+		let mut txn = nodes[0].tx_broadcaster.txn_broadcast();
+		assert_eq!(txn.len(), 1);
+		txn.pop().unwrap()
+	};
+	mine_transaction(&nodes[1], &commitment_tx);
+	check_added_monitors(&nodes[1], 1);
+	check_closed_broadcast(&nodes[1], 1, true);
+	check_closed_event(&nodes[1], 1, ClosureReason::CommitmentTxConfirmed, false);
+	assert!(nodes[1].node.list_channels().is_empty());
+
+	let current_height = nodes[1].best_block_info().1;
+	let maturity_height = current_height + ANTI_REORG_DELAY - 1;
+	let report = nodes[1].chain_monitor.chain_monitor.get_balance_report(&nodes[1].node.list_channels());
+	assert_eq!(report.channel_balance_msat, 0);
+	assert_eq!(report.claimable_satoshis, 0);
+	assert_eq!(report.awaiting_confirmations, vec![MaturingBalance {
+		amount_satoshis: 1_000, maturity_height,
+	}]);
+	assert_eq!(report.total_balance_satoshis(), 1_000);
+	assert_eq!(report.matured_by_height_satoshis(maturity_height - 1), 0);
+	assert_eq!(report.matured_by_height_satoshis(maturity_height), 1_000);
+
+	let schedules = nodes[1].chain_monitor.chain_monitor.get_balance_maturity_schedules(&[]);
+	assert_eq!(schedules, vec![BalanceMaturitySchedule {
+		funding_txo,
+		counterparty_node_id: Some(nodes[0].node.get_our_node_id()),
+		current_height,
+		claimable_satoshis: 0,
+		awaiting_confirmations: vec![MaturingBalance { amount_satoshis: 1_000, maturity_height }],
+		maybe_timeout_htlcs: Vec::new(),
+		maybe_preimage_htlc_satoshis: 0,
+	}]);
+	assert_eq!(schedules[0].blocks_until_fully_matured(), ANTI_REORG_DELAY - 1);
+	assert_eq!(schedules[0].spendable_by_height_satoshis(current_height), 0);
+	assert_eq!(schedules[0].spendable_by_height_satoshis(maturity_height), 1_000);
+
+	connect_blocks(&nodes[1], ANTI_REORG_DELAY - 1);
+	assert!(nodes[1].chain_monitor.chain_monitor.get_balance_maturity_schedules(&[]).is_empty());
+	test_spendable_output(&nodes[1], &commitment_tx);
+}
+
 fn sorted_vec<T: Ord>(mut v: Vec<T>) -> Vec<T> {
 	v.sort_unstable();
 	v
@@ -1879,6 +2006,211 @@ fn test_monitor_timer_based_claim() {
 	do_test_monitor_rebroadcast_pending_claims(true);
 }
 
+#[test]
+fn test_monitor_timer_based_claim_fee_bumps() {
+	// Test that `ChainMonitor::rebroadcast_pending_claims_with_fee_bumps` bumps the feerate of
+	// pending claims at most once per block and within the configured limits, and reports claims
+	// approaching their deadline exactly once.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+	let (_, _, chan_id, _) = create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 1_000_000, 500_000_000);
+	let revoked_local_txn = get_local_commitment_txn!(nodes[1], chan_id);
+	assert_eq!(revoked_local_txn.len(), 1);
+	let revoked_txid = revoked_local_txn[0].txid();
+	send_payment(&nodes[0], &[&nodes[1]], 3_000_000);
+
+	mine_transaction(&nodes[0], &revoked_local_txn[0]);
+	check_closed_broadcast!(nodes[0], true);
+	check_added_monitors!(nodes[0], 1);
+	check_closed_event!(nodes[0], 1, ClosureReason::CommitmentTxConfirmed);
+	let deadline_height = nodes[0].best_block_info().1 + BREAKDOWN_TIMEOUT as u32;
+
+	let get_justice_tx_feerate = || -> u64 {
+		let mut justice_txn: Vec<_> = nodes[0].tx_broadcaster.txn_broadcast().into_iter()
+			.filter(|tx| tx.input.iter().any(|inp| inp.previous_output.txid == revoked_txid))
+			.collect();
+		assert_eq!(justice_txn.len(), 1);
+		let justice_tx = justice_txn.pop().unwrap();
+		check_spends!(justice_tx, revoked_local_txn[0]);
+		let input_amount: u64 = justice_tx.input.iter()
+			.map(|inp| revoked_local_txn[0].output[inp.previous_output.vout as usize].value)
+			.sum();
+		let fee = input_amount - justice_tx.output.iter().map(|out| out.value).sum::<u64>();
+		fee * 1000 / justice_tx.weight() as u64
+	};
+	let initial_feerate = get_justice_tx_feerate();
+
+	// The first call forces a bump, while any further calls in the same block only rebroadcast.
+	let mut config = ClaimFeeBumpConfig { max_feerate_sat_per_1000_weight: u32::max_value(), at_risk_threshold_blocks: 0 };
+	nodes[0].chain_monitor.chain_monitor.rebroadcast_pending_claims_with_fee_bumps(&config);
+	let bumped_feerate = get_justice_tx_feerate();
+	assert!(bumped_feerate > initial_feerate);
+	nodes[0].chain_monitor.chain_monitor.rebroadcast_pending_claims_with_fee_bumps(&config);
+	assert_eq!(get_justice_tx_feerate(), bumped_feerate);
+	assert!(nodes[0].chain_monitor.chain_monitor.get_and_clear_pending_events().is_empty());
+
+	// Once a new block is connected, we don't bump beyond the maximum feerate.
+	connect_blocks(&nodes[0], 1);
+	nodes[0].tx_broadcaster.txn_broadcast();
+	config.max_feerate_sat_per_1000_weight = bumped_feerate as u32;
+	nodes[0].chain_monitor.chain_monitor.rebroadcast_pending_claims_with_fee_bumps(&config);
+	assert_eq!(get_justice_tx_feerate(), bumped_feerate);
+
+	// Once the claim is within the at-risk threshold of its deadline, we report it exactly once.
+	config.at_risk_threshold_blocks = deadline_height - nodes[0].best_block_info().1;
+	nodes[0].chain_monitor.chain_monitor.rebroadcast_pending_claims_with_fee_bumps(&config);
+	get_justice_tx_feerate();
+	let events = nodes[0].chain_monitor.chain_monitor.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match &events[0] {
+		Event::ClaimAtRisk { channel_id, outpoints, deadline_height: event_deadline_height, feerate_sat_per_1000_weight } => {
+			assert_eq!(*channel_id, chan_id);
+			assert!(outpoints.iter().all(|outpoint| outpoint.txid == revoked_txid));
+			assert_eq!(*event_deadline_height, deadline_height);
+			assert!(*feerate_sat_per_1000_weight > initial_feerate);
+		},
+		_ => panic!("Unexpected event"),
+	}
+	nodes[0].chain_monitor.chain_monitor.rebroadcast_pending_claims_with_fee_bumps(&config);
+	get_justice_tx_feerate();
+	assert!(nodes[0].chain_monitor.chain_monitor.get_and_clear_pending_events().is_empty());
+}
+
+#[test]
+fn test_monitor_htlc_timeout_claim_at_risk() {
+	// Test that an unconfirmed HTLC timeout claim on our counterparty's commitment transaction is
+	// reported as at risk once it approaches its deadline, as our counterparty may claim the HTLC
+	// with its preimage at any time.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+	let (_, _, chan_id, _) = create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 1_000_000, 500_000_000);
+	route_payment(&nodes[0], &[&nodes[1]], 1_000_000);
+	let htlc_expiry = nodes[0].best_block_info().1 + TEST_FINAL_CLTV + 1;
+
+	let commitment_txn = get_local_commitment_txn!(nodes[1], chan_id);
+	assert_eq!(commitment_txn.len(), 1);
+	let commitment_txid = commitment_txn[0].txid();
+	mine_transaction(&nodes[0], &commitment_txn[0]);
+	check_closed_broadcast!(nodes[0], true);
+	check_added_monitors!(nodes[0], 1);
+	check_closed_event!(nodes[0], 1, ClosureReason::CommitmentTxConfirmed);
+
+	let check_htlc_timeout_claim = || {
+		let mut htlc_txn: Vec<_> = nodes[0].tx_broadcaster.txn_broadcast().into_iter()
+			.filter(|tx| tx.input.iter().any(|inp| inp.previous_output.txid == commitment_txid))
+			.collect();
+		assert_eq!(htlc_txn.len(), 1);
+		check_spends!(htlc_txn.pop().unwrap(), commitment_txn[0]);
+	};
+
+	// Connect blocks up to the HTLC's expiry, producing our HTLC timeout claim.
+	connect_blocks(&nodes[0], htlc_expiry - nodes[0].best_block_info().1);
+	check_htlc_timeout_claim();
+
+	// As the claim remains unconfirmed at its deadline, we report it as at risk.
+	let mut config = ClaimFeeBumpConfig { max_feerate_sat_per_1000_weight: u32::max_value(), at_risk_threshold_blocks: 0 };
+	nodes[0].chain_monitor.chain_monitor.rebroadcast_pending_claims_with_fee_bumps(&config);
+	check_htlc_timeout_claim();
+	let events = nodes[0].chain_monitor.chain_monitor.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match &events[0] {
+		Event::ClaimAtRisk { channel_id, outpoints, deadline_height, .. } => {
+			assert_eq!(*channel_id, chan_id);
+			assert_eq!(outpoints.len(), 1);
+			assert_eq!(outpoints[0].txid, commitment_txid);
+			assert_eq!(*deadline_height, htlc_expiry);
+		},
+		_ => panic!("Unexpected event"),
+	}
+
+	// Further calls do not report the same claim again.
+	config.at_risk_threshold_blocks = 6;
+	nodes[0].chain_monitor.chain_monitor.rebroadcast_pending_claims_with_fee_bumps(&config);
+	check_htlc_timeout_claim();
+	assert!(nodes[0].chain_monitor.chain_monitor.get_and_clear_pending_events().is_empty());
+}
+
+#[test]
+fn test_htlc_timeout_claims_unaffected_by_preimage_claim() {
+	// Test that our HTLC timeout claims on our counterparty's commitment transaction are never
+	// aggregated, such that our counterparty claiming one of the HTLCs with its preimage does not
+	// invalidate, and thus delay, our claims of the remaining HTLCs.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+	let (_, _, chan_id, _) = create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 1_000_000, 500_000_000);
+	let (claimed_payment_preimage, claimed_payment_hash, _) = route_payment(&nodes[0], &[&nodes[1]], 1_000_000);
+	let (_, timeout_payment_hash_1, _) = route_payment(&nodes[0], &[&nodes[1]], 2_000_000);
+	let (_, timeout_payment_hash_2, _) = route_payment(&nodes[0], &[&nodes[1]], 3_000_000);
+	let htlc_expiry = nodes[0].best_block_info().1 + TEST_FINAL_CLTV + 1;
+
+	nodes[1].node.claim_funds(claimed_payment_preimage);
+	check_added_monitors!(nodes[1], 1);
+	expect_payment_claimed!(nodes[1], claimed_payment_hash, 1_000_000);
+	// Drop the `update_fulfill_htlc` such that nodes[0] can only learn the preimage on-chain.
+	get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+
+	let bs_txn = get_local_commitment_txn!(nodes[1], chan_id);
+	assert_eq!(bs_txn.len(), 2);
+	check_spends!(bs_txn[1], bs_txn[0]);
+	let commitment_txid = bs_txn[0].txid();
+
+	mine_transaction(&nodes[0], &bs_txn[0]);
+	check_closed_broadcast!(nodes[0], true);
+	check_added_monitors!(nodes[0], 1);
+	check_closed_event!(nodes[0], 1, ClosureReason::CommitmentTxConfirmed);
+	nodes[0].tx_broadcaster.txn_broadcast();
+
+	// Once the HTLCs expire, we claim each of them on its own.
+	connect_blocks(&nodes[0], htlc_expiry - nodes[0].best_block_info().1);
+	let htlc_timeout_txn: Vec<_> = nodes[0].tx_broadcaster.txn_broadcast().into_iter()
+		.filter(|tx| tx.input.iter().any(|inp| inp.previous_output.txid == commitment_txid))
+		.collect();
+	assert_eq!(htlc_timeout_txn.len(), 3);
+	for tx in htlc_timeout_txn.iter() {
+		assert_eq!(tx.input.len(), 1);
+		check_spends!(tx, bs_txn[0]);
+	}
+
+	// Our counterparty claims one of the HTLCs with its preimage, conflicting with only one of our
+	// claims.
+	mine_transaction(&nodes[0], &bs_txn[1]);
+	expect_payment_sent!(nodes[0], claimed_payment_preimage);
+	let claimed_outpoint = bs_txn[1].input[0].previous_output;
+	let remaining_txn: Vec<_> = htlc_timeout_txn.iter()
+		.filter(|tx| tx.input[0].previous_output != claimed_outpoint)
+		.collect();
+	assert_eq!(remaining_txn.len(), 2);
+	nodes[0].tx_broadcaster.txn_broadcast();
+
+	// Our remaining claims, as broadcast at the HTLCs' expiry, still confirm and resolve the
+	// HTLCs.
+	mine_transactions(&nodes[0], &remaining_txn);
+	connect_blocks(&nodes[0], ANTI_REORG_DELAY - 1);
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 4);
+	let mut failed_payment_hashes = Vec::new();
+	for event in events {
+		match event {
+			Event::PaymentPathFailed { .. } => {},
+			Event::PaymentFailed { payment_hash, .. } => failed_payment_hashes.push(payment_hash),
+			_ => panic!("Unexpected event"),
+		}
+	}
+	failed_payment_hashes.sort();
+	let mut expected_payment_hashes = vec![timeout_payment_hash_1, timeout_payment_hash_2];
+	expected_payment_hashes.sort();
+	assert_eq!(failed_payment_hashes, expected_payment_hashes);
+}
+
 #[cfg(anchors)]
 #[test]
 fn test_yield_anchors_events() {
@@ -2333,7 +2665,7 @@ fn test_anchors_aggregated_revoked_htlc_tx() {
 		if let Event::SpendableOutputs { outputs } = event {
 			assert_eq!(outputs.len(), 1);
 			let spend_tx = nodes[0].keys_manager.backing.spend_spendable_outputs(
-				&[&outputs[0]], Vec::new(), Script::new_op_return(&[]), 253, None, &Secp256k1::new(),
+				&[&outputs[0]], Vec::new(), Script::new_op_return(&[]), 253, None, None, &Secp256k1::new(),
 			).unwrap();
 			check_spends!(spend_tx, revoked_claims[idx]);
 		} else {
@@ -2353,3 +2685,49 @@ fn test_anchors_aggregated_revoked_htlc_tx() {
 	// revoked commitment which Bob has the preimage for.
 	assert_eq!(nodes[1].chain_monitor.chain_monitor.get_claimable_balances(&[]).len(), 6);
 }
+
+#[test]
+fn test_claim_htlc_from_mempool_preimage() {
+	// If our counterparty broadcasts an HTLC-Success transaction claiming an HTLC we forwarded to
+	// them, we should learn the preimage as soon as the claim is seen in the mempool and use it to
+	// claim the inbound HTLC, without waiting for the claim to confirm.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+	let chan_id_bc = create_announced_chan_between_nodes(&nodes, 1, 2).2;
+
+	let (payment_preimage, payment_hash, _) = route_payment(&nodes[0], &[&nodes[1], &nodes[2]], 1_000_000);
+	nodes[2].node.claim_funds(payment_preimage);
+	check_added_monitors!(nodes[2], 1);
+	expect_payment_claimed!(nodes[2], payment_hash, 1_000_000);
+	// Drop the `update_fulfill_htlc` such that nodes[1] can only learn the preimage on-chain.
+	get_htlc_update_msgs!(nodes[2], nodes[1].node.get_our_node_id());
+
+	let cs_txn = get_local_commitment_txn!(nodes[2], chan_id_bc);
+	assert_eq!(cs_txn.len(), 2);
+	check_spends!(cs_txn[1], cs_txn[0]);
+
+	// The commitment transaction itself doesn't reveal anything.
+	assert!(!get_monitor!(nodes[1], chan_id_bc).transaction_seen_in_mempool(&cs_txn[0], &nodes[1].logger));
+	nodes[1].chain_monitor.chain_monitor.transaction_seen_in_mempool(&cs_txn[0]);
+	assert!(nodes[1].node.get_and_clear_pending_events().is_empty());
+	assert!(nodes[1].node.get_and_clear_pending_msg_events().is_empty());
+
+	nodes[1].chain_monitor.chain_monitor.transaction_seen_in_mempool(&cs_txn[1]);
+	// The downstream channel remains open, but the claim is still considered on-chain.
+	expect_payment_forwarded!(nodes[1], nodes[0], nodes[2], Some(1000), false, true);
+	check_added_monitors!(nodes[1], 1);
+
+	// Seeing the same claim again doesn't generate a duplicate claim.
+	nodes[1].chain_monitor.chain_monitor.transaction_seen_in_mempool(&cs_txn[1]);
+
+	let updates = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	assert_eq!(updates.update_fulfill_htlcs.len(), 1);
+	nodes[0].node.handle_update_fulfill_htlc(&nodes[1].node.get_our_node_id(), &updates.update_fulfill_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], updates.commitment_signed, false);
+	expect_payment_sent!(nodes[0], payment_preimage);
+	assert!(nodes[1].node.get_and_clear_pending_events().is_empty());
+}
@@ -25,6 +25,8 @@
 //! track the network on the less-secure system.
 
 use bitcoin::blockdata::constants::ChainHash;
+use bitcoin::hashes::{Hash, HashEngine};
+use bitcoin::hashes::sha256::Hash as Sha256;
 use bitcoin::secp256k1::PublicKey;
 use bitcoin::secp256k1::ecdsa::Signature;
 use bitcoin::{secp256k1, Witness};
@@ -52,6 +54,10 @@ use crate::routing::gossip::{NodeAlias, NodeId};
 /// 21 million * 10^8 * 1000
 pub(crate) const MAX_VALUE_MSAT: u64 = 21_000_000_0000_0000_000;
 
+/// The minimum type of the custom TLV records which may be included in messages supporting them,
+/// e.g. [`OpenChannel::custom_tlvs`].
+pub const MIN_CUSTOM_TLV_TYPE: u64 = 1 << 16;
+
 #[cfg(taproot)]
 /// A partial signature that also contains the Musig2 nonce its signer used
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -161,6 +167,22 @@ pub struct Pong {
 	pub byteslen: u16,
 }
 
+/// A `peer_storage` message, asking the recipient to store a blob of data for us and to return it
+/// whenever we reconnect. Sent only to peers which support `option_provide_storage`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerStorage {
+	/// The data to be stored.
+	pub data: Vec<u8>,
+}
+
+/// A `your_peer_storage` message, returning the latest blob of data the recipient asked us to
+/// store in a [`PeerStorage`] message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct YourPeerStorage {
+	/// The stored data.
+	pub data: Vec<u8>,
+}
+
 /// An [`open_channel`] message to be sent to or received from a peer.
 ///
 /// Used in V1 channel establishment
@@ -213,6 +235,11 @@ pub struct OpenChannel {
 	/// If this is `None`, we derive the channel type from the intersection of our
 	/// feature bits with our counterparty's feature bits from the [`Init`] message.
 	pub channel_type: Option<ChannelTypeFeatures>,
+	/// Custom TLV records included in the message, e.g. to attach application-specific data to it.
+	///
+	/// Each record must have an odd type of at least [`MIN_CUSTOM_TLV_TYPE`], and records must be
+	/// sorted by type. Any unknown odd TLV records of such types are read into this field.
+	pub custom_tlvs: Vec<(u64, Vec<u8>)>,
 }
 
 /// An open_channel2 message to be sent by or received from the channel initiator.
@@ -272,6 +299,9 @@ pub struct OpenChannelV2 {
 	pub channel_type: Option<ChannelTypeFeatures>,
 	/// Optionally, a requirement that only confirmed inputs can be added
 	pub require_confirmed_inputs: Option<()>,
+	/// Optionally, a request for the channel acceptor to lease us inbound liquidity at the rates it
+	/// advertised in its `node_announcement`.
+	pub request_funds: Option<RequestFunds>,
 }
 
 /// An [`accept_channel`] message to be sent to or received from a peer.
@@ -317,6 +347,11 @@ pub struct AcceptChannel {
 	/// our feature bits with our counterparty's feature bits from the [`Init`] message.
 	/// This is required to match the equivalent field in [`OpenChannel::channel_type`].
 	pub channel_type: Option<ChannelTypeFeatures>,
+	/// Custom TLV records included in the message, e.g. to attach application-specific data to it.
+	///
+	/// Each record must have an odd type of at least [`MIN_CUSTOM_TLV_TYPE`], and records must be
+	/// sorted by type. Any unknown odd TLV records of such types are read into this field.
+	pub custom_tlvs: Vec<(u64, Vec<u8>)>,
 	#[cfg(taproot)]
 	/// Next nonce the channel initiator should use to create a funding output signature against
 	pub next_local_nonce: Option<musig2::types::PublicNonce>,
@@ -373,6 +408,9 @@ pub struct AcceptChannelV2 {
 	pub channel_type: Option<ChannelTypeFeatures>,
 	/// Optionally, a requirement that only confirmed inputs can be added
 	pub require_confirmed_inputs: Option<()>,
+	/// The channel acceptor's commitment to lease the funds requested in
+	/// [`OpenChannelV2::request_funds`], if any were requested and it agreed to.
+	pub will_fund: Option<WillFund>,
 }
 
 /// A [`funding_created`] message to be sent to or received from a peer.
@@ -563,6 +601,11 @@ pub struct Shutdown {
 	///
 	/// Must be in one of these forms: P2PKH, P2SH, P2WPKH, P2WSH, P2TR.
 	pub scriptpubkey: Script,
+	/// Custom TLV records included in the message, e.g. to attach application-specific data to it.
+	///
+	/// Each record must have an odd type of at least [`MIN_CUSTOM_TLV_TYPE`], and records must be
+	/// sorted by type. Any unknown odd TLV records of such types are read into this field.
+	pub custom_tlvs: Vec<(u64, Vec<u8>)>,
 }
 
 /// The minimum and maximum fees which the sender is willing to place on the closing transaction.
@@ -595,6 +638,59 @@ pub struct ClosingSigned {
 	pub fee_range: Option<ClosingSignedFeeRange>,
 }
 
+/// A [`closing_complete`] message to be sent to or received from a peer.
+///
+/// Sent as a part of the simplified cooperative close protocol (`option_simple_close`), in which
+/// the sender (the "closer") offers a closing transaction paying the full fee out of its own
+/// output.
+///
+/// [`closing_complete`]: https://github.com/lightning/bolts/pull/1096
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClosingComplete {
+	/// The channel ID
+	pub channel_id: [u8; 32],
+	/// The destination of the closer's funds on closing.
+	pub closer_scriptpubkey: Script,
+	/// The destination of the closee's funds on closing.
+	pub closee_scriptpubkey: Script,
+	/// The total fee for the closing transaction, paid entirely by the closer
+	pub fee_satoshis: u64,
+	/// The locktime of the closing transaction
+	pub locktime: u32,
+	/// A signature on the closing transaction which omits the closee's output
+	pub closer_output_only: Option<Signature>,
+	/// A signature on the closing transaction which omits the closer's output
+	pub closee_output_only: Option<Signature>,
+	/// A signature on the closing transaction which includes both outputs
+	pub closer_and_closee_outputs: Option<Signature>,
+}
+
+/// A [`closing_sig`] message to be sent to or received from a peer.
+///
+/// Sent by the "closee" in response to a [`ClosingComplete`], countersigning exactly one of the
+/// closing transactions the closer offered.
+///
+/// [`closing_sig`]: https://github.com/lightning/bolts/pull/1096
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClosingSig {
+	/// The channel ID
+	pub channel_id: [u8; 32],
+	/// The destination of the closer's funds on closing.
+	pub closer_scriptpubkey: Script,
+	/// The destination of the closee's funds on closing.
+	pub closee_scriptpubkey: Script,
+	/// The total fee for the closing transaction, paid entirely by the closer
+	pub fee_satoshis: u64,
+	/// The locktime of the closing transaction
+	pub locktime: u32,
+	/// A signature on the closing transaction which omits the closee's output
+	pub closer_output_only: Option<Signature>,
+	/// A signature on the closing transaction which omits the closer's output
+	pub closee_output_only: Option<Signature>,
+	/// A signature on the closing transaction which includes both outputs
+	pub closer_and_closee_outputs: Option<Signature>,
+}
+
 /// An [`update_add_htlc`] message to be sent to or received from a peer.
 ///
 /// [`update_add_htlc`]: https://github.com/lightning/bolts/blob/master/02-peer-protocol.md#adding-an-htlc-update_add_htlc
@@ -610,6 +706,9 @@ pub struct UpdateAddHTLC {
 	pub payment_hash: PaymentHash,
 	/// The expiry height of the HTLC
 	pub cltv_expiry: u32,
+	/// Provided if we are relaying or receiving a payment within a blinded path, to decrypt the onion
+	/// routing packet and the recipient-provided encrypted payload within.
+	pub blinding_point: Option<PublicKey>,
 	pub(crate) onion_routing_packet: OnionPacket,
 }
 
@@ -741,7 +840,7 @@ pub struct AnnouncementSignatures {
 }
 
 /// An address which can be used to connect to a remote peer.
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub enum NetAddress {
 	/// An IPv4 address/port on which the peer is listening.
 	IPv4 {
@@ -895,6 +994,119 @@ impl Readable for NetAddress {
 	}
 }
 
+/// An error when parsing a [`NetAddress`] from a string.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NetAddressParseError {
+	/// The string did not have the form `host:port`.
+	InvalidInput,
+	/// The port was missing or not a valid 16-bit number.
+	InvalidPort,
+	/// The host ended in `.onion` but was not a valid Tor v3 onion address.
+	InvalidOnionV3,
+}
+
+impl fmt::Display for NetAddressParseError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			NetAddressParseError::InvalidInput => f.write_str("Invalid input format, expected host:port"),
+			NetAddressParseError::InvalidPort => f.write_str("Invalid port"),
+			NetAddressParseError::InvalidOnionV3 => f.write_str("Invalid Tor v3 onion address"),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for NetAddressParseError {}
+
+/// Parses a Tor v3 onion service hostname (without the port), e.g.
+/// `pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd.onion`.
+///
+/// Note that the checksum included in the hostname is not verified.
+pub fn parse_onion_address(host: &str, port: u16) -> Result<NetAddress, NetAddressParseError> {
+	// A v3 onion hostname is the base32 encoding of the 32-byte pubkey, 2-byte checksum, and
+	// 1-byte version, i.e., 56 characters, followed by `.onion`.
+	if !host.is_ascii() || host.len() != 56 + ".onion".len() || !host.to_ascii_lowercase().ends_with(".onion") {
+		return Err(NetAddressParseError::InvalidOnionV3);
+	}
+	let bytes = crate::util::base32::decode(&host[..56])
+		.map_err(|_| NetAddressParseError::InvalidOnionV3)?;
+	if bytes.len() != 35 || bytes[34] != 3 {
+		return Err(NetAddressParseError::InvalidOnionV3);
+	}
+	let mut ed25519_pubkey = [0; 32];
+	ed25519_pubkey.copy_from_slice(&bytes[..32]);
+	Ok(NetAddress::OnionV3 {
+		ed25519_pubkey,
+		checksum: u16::from_be_bytes([bytes[32], bytes[33]]),
+		version: bytes[34],
+		port,
+	})
+}
+
+#[cfg(feature = "std")]
+impl From<std::net::SocketAddr> for NetAddress {
+	fn from(addr: std::net::SocketAddr) -> Self {
+		match addr {
+			std::net::SocketAddr::V4(addr) => NetAddress::IPv4 { addr: addr.ip().octets(), port: addr.port() },
+			std::net::SocketAddr::V6(addr) => NetAddress::IPv6 { addr: addr.ip().octets(), port: addr.port() },
+		}
+	}
+}
+
+/// Parses a [`NetAddress`] from a string of the form `host:port`, where the host is an IPv4
+/// address, a bracketed IPv6 address, a Tor v3 onion hostname, or a DNS hostname.
+#[cfg(feature = "std")]
+impl core::str::FromStr for NetAddress {
+	type Err = NetAddressParseError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		use core::convert::TryFrom;
+
+		if let Ok(addr) = s.parse::<std::net::SocketAddr>() {
+			return Ok(addr.into());
+		}
+		let mut parts = s.rsplitn(2, ':');
+		let port = parts.next().ok_or(NetAddressParseError::InvalidInput)?;
+		let host = parts.next().ok_or(NetAddressParseError::InvalidInput)?;
+		if host.is_empty() || host.contains(':') || host.starts_with('[') {
+			return Err(NetAddressParseError::InvalidInput);
+		}
+		let port: u16 = port.parse().map_err(|_| NetAddressParseError::InvalidPort)?;
+		if host.to_ascii_lowercase().ends_with(".onion") {
+			return parse_onion_address(host, port);
+		}
+		match Hostname::try_from(host.to_owned()) {
+			Ok(hostname) => Ok(NetAddress::Hostname { hostname, port }),
+			Err(_) => Err(NetAddressParseError::InvalidInput),
+		}
+	}
+}
+
+/// Formats a [`NetAddress`] as `host:port`, such that it may be parsed back via
+/// [`core::str::FromStr`]. Note that Tor v2 onion addresses are deprecated and thus formatted
+/// without validation.
+#[cfg(feature = "std")]
+impl fmt::Display for NetAddress {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			NetAddress::IPv4 { addr, port } => write!(f, "{}:{}", std::net::Ipv4Addr::from(*addr), port),
+			NetAddress::IPv6 { addr, port } => write!(f, "[{}]:{}", std::net::Ipv6Addr::from(*addr), port),
+			NetAddress::OnionV2(bytes) => {
+				let port = u16::from_be_bytes([bytes[10], bytes[11]]);
+				write!(f, "{}.onion:{}", crate::util::base32::encode(&bytes[..10]), port)
+			},
+			NetAddress::OnionV3 { ed25519_pubkey, checksum, version, port } => {
+				let mut bytes = [0; 35];
+				bytes[..32].copy_from_slice(&ed25519_pubkey[..]);
+				bytes[32..34].copy_from_slice(&checksum.to_be_bytes());
+				bytes[34] = *version;
+				write!(f, "{}.onion:{}", crate::util::base32::encode(&bytes), port)
+			},
+			NetAddress::Hostname { hostname, port } => write!(f, "{}:{}", hostname.as_str(), port),
+		}
+	}
+}
+
 /// Represents the set of gossip messages that require a signature from a node's identity key.
 pub enum UnsignedGossipMessage<'a> {
 	/// An unsigned channel announcement.
@@ -938,6 +1150,155 @@ pub struct UnsignedNodeAnnouncement {
 	pub(crate) excess_address_data: Vec<u8>,
 	pub(crate) excess_data: Vec<u8>,
 }
+
+impl UnsignedNodeAnnouncement {
+	/// The rates at which the node offers to lease inbound liquidity to channel initiators, as
+	/// advertised in the `option_will_fund` TLV of its `node_announcement`, if any.
+	///
+	/// Returns `None` if the announcement's TLV stream is invalid.
+	pub fn will_fund(&self) -> Option<LeaseRates> {
+		let mut will_fund: Option<LeaseRates> = None;
+		let mut read_tlv_stream = || -> Result<(), DecodeError> {
+			decode_tlv_stream!(&self.excess_data[..], {
+				(1, will_fund, option),
+			});
+			Ok(())
+		};
+		read_tlv_stream().ok().and(will_fund)
+	}
+}
+
+/// The rates at which a node offers to lease inbound liquidity, i.e., to contribute funds to
+/// channels opened to it for a fee, as advertised with liquidity ads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LeaseRates {
+	/// The weight, in weight units, of the seller's contribution to the funding transaction which
+	/// the buyer pays for at the funding transaction's feerate.
+	pub funding_weight: u16,
+	/// The fee, in basis points of the leased amount, charged for the lease.
+	pub lease_fee_basis: u16,
+	/// The maximum proportional fee, in thousandths of a basis point, the seller will charge for
+	/// forwarding over the leased channel for the duration of the lease.
+	pub channel_fee_max_proportional_thousandths: u16,
+	/// The fixed fee, in satoshis, charged for the lease.
+	pub lease_fee_base_sat: u32,
+	/// The maximum base fee, in milli-satoshis, the seller will charge for forwarding over the
+	/// leased channel for the duration of the lease.
+	pub channel_fee_max_base_msat: u32,
+}
+
+impl LeaseRates {
+	/// The fee, in satoshis, the buyer pays for leasing `lease_amount_sat` at these rates,
+	/// excluding the fee for [`Self::funding_weight`].
+	pub fn lease_fee_sat(&self, lease_amount_sat: u64) -> u64 {
+		self.lease_fee_base_sat as u64 + lease_amount_sat * self.lease_fee_basis as u64 / 10_000
+	}
+}
+
+impl Writeable for LeaseRates {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		self.funding_weight.write(w)?;
+		self.lease_fee_basis.write(w)?;
+		self.channel_fee_max_proportional_thousandths.write(w)?;
+		self.lease_fee_base_sat.write(w)?;
+		HighZeroBytesDroppedBigSize(self.channel_fee_max_base_msat).write(w)
+	}
+}
+
+impl Readable for LeaseRates {
+	fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
+		let funding_weight = Readable::read(r)?;
+		let lease_fee_basis = Readable::read(r)?;
+		let channel_fee_max_proportional_thousandths = Readable::read(r)?;
+		let lease_fee_base_sat = Readable::read(r)?;
+		let channel_fee_max_base_msat: HighZeroBytesDroppedBigSize<u32> = Readable::read(r)?;
+		Ok(Self {
+			funding_weight, lease_fee_basis, channel_fee_max_proportional_thousandths,
+			lease_fee_base_sat, channel_fee_max_base_msat: channel_fee_max_base_msat.0,
+		})
+	}
+}
+
+/// The number of blocks, counted from the [`RequestFunds::blockheight`], for which leased
+/// liquidity is committed to a channel.
+pub const LEASE_DURATION_BLOCKS: u32 = 4032;
+
+/// A request, sent by the initiator of a dual-funded channel in its `open_channel2` message, for
+/// the acceptor to contribute funds to the channel at the rates it advertised in its
+/// [`UnsignedNodeAnnouncement::will_fund`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RequestFunds {
+	/// The amount, in satoshis, the initiator requests the acceptor to contribute.
+	pub requested_sats: u64,
+	/// The current block height of the initiator, from which the lease's duration is counted.
+	pub blockheight: u32,
+}
+
+impl RequestFunds {
+	/// The block height at which the lease for the requested funds expires.
+	pub fn lease_expiry(&self) -> u32 {
+		self.blockheight.saturating_add(LEASE_DURATION_BLOCKS)
+	}
+}
+
+impl_writeable!(RequestFunds, {
+	requested_sats,
+	blockheight
+});
+
+/// The acceptor's commitment, sent in its `accept_channel2` message in response to a
+/// [`RequestFunds`], to contribute the requested funds at the given rates.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WillFund {
+	/// The acceptor's signature, by its node key, over the lease commitment. See
+	/// [`WillFund::lease_commitment_hash`].
+	pub signature: Signature,
+	/// The rates at which the requested funds are leased.
+	pub lease_rates: LeaseRates,
+}
+
+impl WillFund {
+	/// The hash the acceptor signs with its node key to commit to the forwarding fee caps of a
+	/// lease, given its `funding_pubkey` for the channel and the lease's expiry (see
+	/// [`RequestFunds::lease_expiry`]).
+	pub fn lease_commitment_hash(
+		funding_pubkey: &PublicKey, lease_expiry: u32, lease_rates: &LeaseRates
+	) -> secp256k1::Message {
+		let mut engine = Sha256::engine();
+		engine.input(b"option_will_fund");
+		engine.input(&funding_pubkey.serialize());
+		engine.input(&lease_expiry.to_be_bytes());
+		engine.input(&lease_rates.channel_fee_max_base_msat.to_be_bytes());
+		engine.input(&lease_rates.channel_fee_max_proportional_thousandths.to_be_bytes());
+		secp256k1::Message::from_slice(&Sha256::from_engine(engine).into_inner()).unwrap()
+	}
+
+	/// Checks that [`Self::signature`] was made by `node_id`, the acceptor's node key, over the
+	/// lease commitment for the acceptor's `funding_pubkey` and the initiator's `request`.
+	pub fn verify<C: secp256k1::Verification>(
+		&self, secp_ctx: &secp256k1::Secp256k1<C>, node_id: &PublicKey,
+		funding_pubkey: &PublicKey, request: &RequestFunds
+	) -> Result<(), secp256k1::Error> {
+		let msg = Self::lease_commitment_hash(funding_pubkey, request.lease_expiry(), &self.lease_rates);
+		secp_ctx.verify_ecdsa(&msg, &self.signature, node_id)
+	}
+}
+
+impl Writeable for WillFund {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		self.signature.write(w)?;
+		self.lease_rates.write(w)
+	}
+}
+
+impl Readable for WillFund {
+	fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
+		let signature = Readable::read(r)?;
+		let lease_rates = Readable::read(r)?;
+		Ok(Self { signature, lease_rates })
+	}
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 /// A [`node_announcement`] message to be sent to or received from a peer.
 ///
@@ -987,6 +1348,47 @@ pub struct ChannelAnnouncement {
 	pub contents: UnsignedChannelAnnouncement,
 }
 
+/// The unsigned part of a `channel_announcement_2` message, as proposed for announcing channels
+/// whose funding output may be a taproot output.
+///
+/// This message is experimental and its format may change as the proposal is finalized.
+#[cfg(taproot)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnsignedChannelAnnouncement2 {
+	/// The genesis hash of the blockchain where the channel is to be opened
+	pub chain_hash: BlockHash,
+	/// The advertised channel features
+	pub features: ChannelFeatures,
+	/// The short channel ID
+	pub short_channel_id: u64,
+	/// The value of the funding output, in satoshis
+	pub capacity_satoshis: u64,
+	/// One of the two `node_id`s which are endpoints of this channel
+	pub node_id_1: NodeId,
+	/// The other of the two `node_id`s which are endpoints of this channel
+	pub node_id_2: NodeId,
+	/// The funding key for the first node, if the funding output is derived from the funding keys
+	pub bitcoin_key_1: Option<NodeId>,
+	/// The funding key for the second node, if the funding output is derived from the funding keys
+	pub bitcoin_key_2: Option<NodeId>,
+	/// The taproot script tree root committed to by the funding output, if any
+	pub merkle_root_hash: Option<[u8; 32]>,
+	/// A proof that the funding output was confirmed, for nodes without access to the chain. It is
+	/// retained but not verified, the funding output is checked via a
+	/// [`UtxoLookup`](crate::routing::utxo::UtxoLookup) instead.
+	pub spv_proof: Option<Vec<u8>>,
+}
+/// A `channel_announcement_2` message to be sent to or received from a peer, see
+/// [`UnsignedChannelAnnouncement2`].
+#[cfg(taproot)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChannelAnnouncement2 {
+	/// The MuSig2 signature by both nodes' `node_id`s and, if present, funding keys
+	pub signature: secp256k1::schnorr::Signature,
+	/// The actual announcement
+	pub contents: UnsignedChannelAnnouncement2,
+}
+
 /// The unsigned part of a [`channel_update`] message.
 ///
 /// [`channel_update`]: https://github.com/lightning/bolts/blob/master/07-routing-gossip.md#the-channel_update-message
@@ -1226,6 +1628,10 @@ pub trait ChannelMessageHandler : MessageSendEventsProvider {
 	fn handle_shutdown(&self, their_node_id: &PublicKey, msg: &Shutdown);
 	/// Handle an incoming `closing_signed` message from the given peer.
 	fn handle_closing_signed(&self, their_node_id: &PublicKey, msg: &ClosingSigned);
+	/// Handle an incoming `closing_complete` message from the given peer.
+	fn handle_closing_complete(&self, their_node_id: &PublicKey, msg: &ClosingComplete);
+	/// Handle an incoming `closing_sig` message from the given peer.
+	fn handle_closing_sig(&self, their_node_id: &PublicKey, msg: &ClosingSig);
 
 	// Interactive channel construction
 	/// Handle an incoming `tx_add_input message` from the given peer.
@@ -1284,6 +1690,12 @@ pub trait ChannelMessageHandler : MessageSendEventsProvider {
 	/// Handle an incoming `channel_update` message from the given peer.
 	fn handle_channel_update(&self, their_node_id: &PublicKey, msg: &ChannelUpdate);
 
+	// Peer storage:
+	/// Handle an incoming `peer_storage` message from the given peer.
+	fn handle_peer_storage(&self, their_node_id: &PublicKey, msg: &PeerStorage);
+	/// Handle an incoming `your_peer_storage` message from the given peer.
+	fn handle_your_peer_storage(&self, their_node_id: &PublicKey, msg: &YourPeerStorage);
+
 	// Error:
 	/// Handle an incoming `error` message from the given peer.
 	fn handle_error(&self, their_node_id: &PublicKey, msg: &ErrorMessage);
@@ -1322,6 +1734,10 @@ pub trait RoutingMessageHandler : MessageSendEventsProvider {
 	/// Handle a `channel_announcement` message, returning `true` if it should be forwarded on, `false`
 	/// or returning an `Err` otherwise.
 	fn handle_channel_announcement(&self, msg: &ChannelAnnouncement) -> Result<bool, LightningError>;
+	/// Handle an experimental `channel_announcement_2` message, returning `true` if it should be
+	/// forwarded on, `false` or returning an `Err` otherwise.
+	#[cfg(taproot)]
+	fn handle_channel_announcement_2(&self, msg: &ChannelAnnouncement2) -> Result<bool, LightningError>;
 	/// Handle an incoming `channel_update` message, returning true if it should be forwarded on,
 	/// `false` or returning an `Err` otherwise.
 	fn handle_channel_update(&self, msg: &ChannelUpdate) -> Result<bool, LightningError>;
@@ -1408,6 +1824,7 @@ pub trait OnionMessageHandler : OnionMessageProvider {
 }
 
 mod fuzzy_internal_msgs {
+	use bitcoin::secp256k1::PublicKey;
 	use crate::prelude::*;
 	use crate::ln::{PaymentPreimage, PaymentSecret};
 
@@ -1430,6 +1847,21 @@ mod fuzzy_internal_msgs {
 			payment_metadata: Option<Vec<u8>>,
 			keysend_preimage: Option<PaymentPreimage>,
 		},
+		/// A hop within a blinded path, other than the recipient. The amount and CLTV to forward
+		/// are provided to the hop within `encrypted_tlvs`, thus are not written.
+		BlindedNonFinalNode {
+			encrypted_tlvs: Vec<u8>,
+			/// Only set for the introduction node of the blinded path.
+			intro_node_blinding_point: Option<PublicKey>,
+		},
+		/// The recipient at the end of a blinded path.
+		BlindedFinalNode {
+			total_msat: u64,
+			encrypted_tlvs: Vec<u8>,
+			/// Only set if the recipient is also the introduction node of the blinded path.
+			intro_node_blinding_point: Option<PublicKey>,
+			keysend_preimage: Option<PaymentPreimage>,
+		},
 	}
 
 	pub struct OnionHopData {
@@ -1499,6 +1931,41 @@ pub(crate) struct OnionErrorPacket {
 	// This really should be a constant size slice, but the spec lets these things be up to 128KB?
 	// (TODO) We limit it in decode to much lower...
 	pub(crate) data: Vec<u8>,
+	/// The hold times and HMACs of each hop the failure passed through, allowing the sender to
+	/// attribute the failure to a pair of hops even if the `data` is corrupted. `None` if the
+	/// failure was generated or relayed by a node which doesn't support attributable failures.
+	pub(crate) attribution_data: Option<AttributionData>,
+}
+
+/// The number of hops for which [`AttributionData`] carries hold times and HMACs.
+pub(crate) const MAX_ATTRIBUTABLE_HOPS: usize = 20;
+/// The length of each hop's hold time in [`AttributionData`].
+pub(crate) const ATTRIBUTION_HOLD_TIME_LEN: usize = 4;
+/// The length of each truncated HMAC in [`AttributionData`].
+pub(crate) const ATTRIBUTION_HMAC_LEN: usize = 4;
+/// The number of HMACs in [`AttributionData`]. Each hop provides an HMAC for each position it
+/// may be at in the path, of which those for positions further than [`MAX_ATTRIBUTABLE_HOPS`]
+/// from the sender are pruned as the failure is relayed.
+pub(crate) const ATTRIBUTION_HMAC_COUNT: usize = MAX_ATTRIBUTABLE_HOPS * (MAX_ATTRIBUTABLE_HOPS + 1) / 2;
+
+/// The attribution data of an [`OnionErrorPacket`], as proposed in [bolts#1044].
+///
+/// [bolts#1044]: https://github.com/lightning/bolts/pull/1044
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct AttributionData {
+	/// The hold time reported by each hop, most recent hop first, in units of 100 milliseconds.
+	pub(crate) hold_times: [u8; MAX_ATTRIBUTABLE_HOPS * ATTRIBUTION_HOLD_TIME_LEN],
+	/// The truncated HMACs provided by each hop, grouped by hop, most recent hop first.
+	pub(crate) hmacs: [u8; ATTRIBUTION_HMAC_COUNT * ATTRIBUTION_HMAC_LEN],
+}
+
+impl AttributionData {
+	pub(crate) fn new() -> Self {
+		Self {
+			hold_times: [0; MAX_ATTRIBUTABLE_HOPS * ATTRIBUTION_HOLD_TIME_LEN],
+			hmacs: [0; ATTRIBUTION_HMAC_COUNT * ATTRIBUTION_HMAC_LEN],
+		}
+	}
 }
 
 impl fmt::Display for DecodeError {
@@ -1544,7 +2011,7 @@ impl_writeable_msg!(AcceptChannel, {
 }, {
 	(0, shutdown_scriptpubkey, (option, encoding: (Script, WithoutLength))), // Don't encode length twice.
 	(1, channel_type, option),
-});
+}, custom_tlvs);
 
 #[cfg(taproot)]
 impl_writeable_msg!(AcceptChannel, {
@@ -1566,7 +2033,7 @@ impl_writeable_msg!(AcceptChannel, {
 	(0, shutdown_scriptpubkey, (option, encoding: (Script, WithoutLength))), // Don't encode length twice.
 	(1, channel_type, option),
 	(4, next_local_nonce, option),
-});
+}, custom_tlvs);
 
 impl_writeable_msg!(AcceptChannelV2, {
 	temporary_channel_id,
@@ -1588,6 +2055,7 @@ impl_writeable_msg!(AcceptChannelV2, {
 	(0, shutdown_scriptpubkey, option),
 	(1, channel_type, option),
 	(2, require_confirmed_inputs, option),
+	(1337, will_fund, option),
 });
 
 impl_writeable_msg!(TxAddInput, {
@@ -1644,6 +2112,14 @@ impl_writeable_msg!(TxAbort, {
 	data,
 }, {});
 
+impl_writeable_msg!(PeerStorage, {
+	data,
+}, {});
+
+impl_writeable_msg!(YourPeerStorage, {
+	data,
+}, {});
+
 impl_writeable_msg!(AnnouncementSignatures, {
 	channel_id,
 	short_channel_id,
@@ -1671,6 +2147,30 @@ impl_writeable!(ClosingSignedFeeRange, {
 	max_fee_satoshis
 });
 
+impl_writeable_msg!(ClosingComplete, {
+	channel_id,
+	closer_scriptpubkey,
+	closee_scriptpubkey,
+	fee_satoshis,
+	locktime,
+}, {
+	(1, closer_output_only, option),
+	(2, closee_output_only, option),
+	(3, closer_and_closee_outputs, option),
+});
+
+impl_writeable_msg!(ClosingSig, {
+	channel_id,
+	closer_scriptpubkey,
+	closee_scriptpubkey,
+	fee_satoshis,
+	locktime,
+}, {
+	(1, closer_output_only, option),
+	(2, closee_output_only, option),
+	(3, closer_and_closee_outputs, option),
+});
+
 #[cfg(not(taproot))]
 impl_writeable_msg!(CommitmentSigned, {
 	channel_id,
@@ -1786,7 +2286,7 @@ impl_writeable_msg!(OpenChannel, {
 }, {
 	(0, shutdown_scriptpubkey, (option, encoding: (Script, WithoutLength))), // Don't encode length twice.
 	(1, channel_type, option),
-});
+}, custom_tlvs);
 
 impl_writeable_msg!(OpenChannelV2, {
 	chain_hash,
@@ -1812,6 +2312,7 @@ impl_writeable_msg!(OpenChannelV2, {
 	(0, shutdown_scriptpubkey, option),
 	(1, channel_type, option),
 	(2, require_confirmed_inputs, option),
+	(1337, request_funds, option),
 });
 
 #[cfg(not(taproot))]
@@ -1833,13 +2334,36 @@ impl_writeable_msg!(RevokeAndACK, {
 impl_writeable_msg!(Shutdown, {
 	channel_id,
 	scriptpubkey
-}, {});
+}, {}, custom_tlvs);
 
-impl_writeable_msg!(UpdateFailHTLC, {
-	channel_id,
-	htlc_id,
-	reason
-}, {});
+impl Writeable for UpdateFailHTLC {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		self.channel_id.write(w)?;
+		self.htlc_id.write(w)?;
+		self.reason.data.write(w)?;
+		encode_tlv_stream!(w, {
+			(1, self.reason.attribution_data, option),
+		});
+		Ok(())
+	}
+}
+
+impl Readable for UpdateFailHTLC {
+	fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
+		let channel_id = Readable::read(r)?;
+		let htlc_id = Readable::read(r)?;
+		let data = Readable::read(r)?;
+		let mut attribution_data: Option<AttributionData> = None;
+		decode_tlv_stream!(r, {
+			(1, attribution_data, option),
+		});
+		Ok(UpdateFailHTLC {
+			channel_id,
+			htlc_id,
+			reason: OnionErrorPacket { data, attribution_data },
+		})
+	}
+}
 
 impl_writeable_msg!(UpdateFailMalformedHTLC, {
 	channel_id,
@@ -1861,10 +2385,35 @@ impl_writeable_msg!(UpdateFulfillHTLC, {
 
 // Note that this is written as a part of ChannelManager objects, and thus cannot change its
 // serialization format in a way which assumes we know the total serialized length/message end
-// position.
-impl_writeable!(OnionErrorPacket, {
-	data
-});
+// position. Thus, the attribution data is not persisted and a failure which is relayed after a
+// restart is relayed without it.
+impl Writeable for OnionErrorPacket {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		self.data.write(w)
+	}
+}
+
+impl Readable for OnionErrorPacket {
+	fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
+		Ok(OnionErrorPacket { data: Readable::read(r)?, attribution_data: None })
+	}
+}
+
+impl Writeable for AttributionData {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		w.write_all(&self.hold_times)?;
+		w.write_all(&self.hmacs)
+	}
+}
+
+impl Readable for AttributionData {
+	fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
+		let mut attribution_data = AttributionData::new();
+		r.read_exact(&mut attribution_data.hold_times)?;
+		r.read_exact(&mut attribution_data.hmacs)?;
+		Ok(attribution_data)
+	}
+}
 
 // Note that this is written as a part of ChannelManager objects, and thus cannot change its
 // serialization format in a way which assumes we know the total serialized length/message end
@@ -1904,7 +2453,9 @@ impl_writeable_msg!(UpdateAddHTLC, {
 	payment_hash,
 	cltv_expiry,
 	onion_routing_packet
-}, {});
+}, {
+	(0, blinding_point, option)
+});
 
 impl Readable for OnionMessage {
 	fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
@@ -1963,6 +2514,22 @@ impl Writeable for OnionHopData {
 					(5482373484, keysend_preimage, option)
 				});
 			},
+			OnionHopDataFormat::BlindedNonFinalNode { ref encrypted_tlvs, ref intro_node_blinding_point } => {
+				_encode_varint_length_prefixed_tlv!(w, {
+					(10, WithoutLength(encrypted_tlvs), required),
+					(12, intro_node_blinding_point, option)
+				});
+			},
+			OnionHopDataFormat::BlindedFinalNode { total_msat, ref encrypted_tlvs, ref intro_node_blinding_point, ref keysend_preimage } => {
+				_encode_varint_length_prefixed_tlv!(w, {
+					(2, HighZeroBytesDroppedBigSize(self.amt_to_forward), required),
+					(4, HighZeroBytesDroppedBigSize(self.outgoing_cltv_value), required),
+					(10, WithoutLength(encrypted_tlvs), required),
+					(12, intro_node_blinding_point, option),
+					(18, HighZeroBytesDroppedBigSize(total_msat), required),
+					(5482373484, keysend_preimage, option)
+				});
+			},
 		}
 		Ok(())
 	}
@@ -1970,23 +2537,52 @@ impl Writeable for OnionHopData {
 
 impl Readable for OnionHopData {
 	fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
-		let mut amt = HighZeroBytesDroppedBigSize(0u64);
-		let mut cltv_value = HighZeroBytesDroppedBigSize(0u32);
+		let mut amt: Option<HighZeroBytesDroppedBigSize<u64>> = None;
+		let mut cltv_value: Option<HighZeroBytesDroppedBigSize<u32>> = None;
 		let mut short_id: Option<u64> = None;
 		let mut payment_data: Option<FinalOnionHopData> = None;
 		let mut payment_metadata: Option<WithoutLength<Vec<u8>>> = None;
 		let mut keysend_preimage: Option<PaymentPreimage> = None;
+		let mut encrypted_tlvs: Option<WithoutLength<Vec<u8>>> = None;
+		let mut intro_node_blinding_point: Option<PublicKey> = None;
+		let mut total_msat: Option<HighZeroBytesDroppedBigSize<u64>> = None;
 		read_tlv_fields!(r, {
-			(2, amt, required),
-			(4, cltv_value, required),
+			(2, amt, option),
+			(4, cltv_value, option),
 			(6, short_id, option),
 			(8, payment_data, option),
+			(10, encrypted_tlvs, option),
+			(12, intro_node_blinding_point, option),
 			(16, payment_metadata, option),
+			(18, total_msat, option),
 			// See https://github.com/lightning/blips/blob/master/blip-0003.md
 			(5482373484, keysend_preimage, option)
 		});
 
-		let format = if let Some(short_channel_id) = short_id {
+		let format = if let Some(WithoutLength(encrypted_tlvs)) = encrypted_tlvs {
+			if short_id.is_some() || payment_data.is_some() || payment_metadata.is_some() {
+				return Err(DecodeError::InvalidValue);
+			}
+			if let Some(HighZeroBytesDroppedBigSize(total_msat)) = total_msat {
+				if total_msat > MAX_VALUE_MSAT { return Err(DecodeError::InvalidValue); }
+				OnionHopDataFormat::BlindedFinalNode {
+					total_msat, encrypted_tlvs, intro_node_blinding_point, keysend_preimage,
+				}
+			} else {
+				// The amount and CLTV to forward are given to intermediate hops within
+				// `encrypted_tlvs`.
+				if amt.is_some() || cltv_value.is_some() || keysend_preimage.is_some() {
+					return Err(DecodeError::InvalidValue);
+				}
+				return Ok(OnionHopData {
+					format: OnionHopDataFormat::BlindedNonFinalNode { encrypted_tlvs, intro_node_blinding_point },
+					amt_to_forward: 0,
+					outgoing_cltv_value: 0,
+				});
+			}
+		} else if intro_node_blinding_point.is_some() || total_msat.is_some() {
+			return Err(DecodeError::InvalidValue);
+		} else if let Some(short_channel_id) = short_id {
 			if payment_data.is_some() { return Err(DecodeError::InvalidValue); }
 			if payment_metadata.is_some() { return Err(DecodeError::InvalidValue); }
 			OnionHopDataFormat::NonFinalNode {
@@ -2005,13 +2601,15 @@ impl Readable for OnionHopData {
 			}
 		};
 
-		if amt.0 > MAX_VALUE_MSAT {
+		let amt = amt.ok_or(DecodeError::InvalidValue)?.0;
+		let cltv_value = cltv_value.ok_or(DecodeError::InvalidValue)?.0;
+		if amt > MAX_VALUE_MSAT {
 			return Err(DecodeError::InvalidValue);
 		}
 		Ok(OnionHopData {
 			format,
-			amt_to_forward: amt.0,
-			outgoing_cltv_value: cltv_value.0,
+			amt_to_forward: amt,
+			outgoing_cltv_value: cltv_value,
 		})
 	}
 }
@@ -2101,6 +2699,74 @@ impl_writeable!(ChannelAnnouncement, {
 	contents
 });
 
+#[cfg(taproot)]
+impl Writeable for UnsignedChannelAnnouncement2 {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		encode_tlv_stream!(w, {
+			(0, self.chain_hash, required),
+			(2, WithoutLength(&self.features), required),
+			(4, self.short_channel_id, required),
+			(6, self.capacity_satoshis, required),
+			(8, self.node_id_1, required),
+			(10, self.node_id_2, required),
+			(12, self.bitcoin_key_1, option),
+			(14, self.bitcoin_key_2, option),
+			(16, self.merkle_root_hash, option),
+			(19, self.spv_proof.as_ref().map(|proof| WithoutLength(proof)), option),
+		});
+		Ok(())
+	}
+}
+
+#[cfg(taproot)]
+impl Readable for UnsignedChannelAnnouncement2 {
+	fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
+		let mut chain_hash: Option<BlockHash> = None;
+		let mut features: Option<ChannelFeatures> = None;
+		let mut short_channel_id: Option<u64> = None;
+		let mut capacity_satoshis: Option<u64> = None;
+		let mut node_id_1: Option<NodeId> = None;
+		let mut node_id_2: Option<NodeId> = None;
+		let mut bitcoin_key_1: Option<NodeId> = None;
+		let mut bitcoin_key_2: Option<NodeId> = None;
+		let mut merkle_root_hash: Option<[u8; 32]> = None;
+		let mut spv_proof: Option<WithoutLength<Vec<u8>>> = None;
+		decode_tlv_stream!(r, {
+			(0, chain_hash, option),
+			(2, features, (option, encoding: (ChannelFeatures, WithoutLength))),
+			(4, short_channel_id, option),
+			(6, capacity_satoshis, option),
+			(8, node_id_1, option),
+			(10, node_id_2, option),
+			(12, bitcoin_key_1, option),
+			(14, bitcoin_key_2, option),
+			(16, merkle_root_hash, option),
+			(19, spv_proof, option),
+		});
+		if bitcoin_key_1.is_some() != bitcoin_key_2.is_some() {
+			return Err(DecodeError::InvalidValue);
+		}
+		Ok(Self {
+			chain_hash: chain_hash.ok_or(DecodeError::InvalidValue)?,
+			features: features.unwrap_or_else(ChannelFeatures::empty),
+			short_channel_id: short_channel_id.ok_or(DecodeError::InvalidValue)?,
+			capacity_satoshis: capacity_satoshis.ok_or(DecodeError::InvalidValue)?,
+			node_id_1: node_id_1.ok_or(DecodeError::InvalidValue)?,
+			node_id_2: node_id_2.ok_or(DecodeError::InvalidValue)?,
+			bitcoin_key_1,
+			bitcoin_key_2,
+			merkle_root_hash,
+			spv_proof: spv_proof.map(|proof| proof.0),
+		})
+	}
+}
+
+#[cfg(taproot)]
+impl_writeable!(ChannelAnnouncement2, {
+	signature,
+	contents
+});
+
 impl Writeable for UnsignedChannelUpdate {
 	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
 		// `message_flags` used to indicate presence of `htlc_maximum_msat`, but was deprecated in the spec.
@@ -2457,6 +3123,39 @@ mod tests {
 
 	use crate::chain::transaction::OutPoint;
 
+	#[test]
+	#[cfg(feature = "std")]
+	fn net_address_from_str() {
+		use crate::ln::msgs::{NetAddress, NetAddressParseError};
+
+		assert_eq!(NetAddress::IPv4 { addr: [127, 0, 0, 1], port: 1234 }, NetAddress::from_str("127.0.0.1:1234").unwrap());
+		assert_eq!(NetAddress::IPv6 { addr: [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1], port: 1234 },
+			NetAddress::from_str("[::1]:1234").unwrap());
+		let hostname = NetAddress::Hostname { hostname: Hostname::try_from("lightning-node.mydomain.com".to_string()).unwrap(), port: 1234 };
+		assert_eq!(hostname, NetAddress::from_str("lightning-node.mydomain.com:1234").unwrap());
+
+		let onion = "pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd.onion:9735";
+		match NetAddress::from_str(onion).unwrap() {
+			NetAddress::OnionV3 { version, port, .. } => assert_eq!((version, port), (3, 9735)),
+			_ => panic!("Expected an onion v3 address"),
+		}
+		assert_eq!(NetAddress::from_str(&onion.to_uppercase()).unwrap(), NetAddress::from_str(onion).unwrap());
+
+		for addr in &["127.0.0.1:1234", "[::1]:1234", "lightning-node.mydomain.com:1234", onion] {
+			assert_eq!(NetAddress::from_str(addr).unwrap().to_string(), *addr);
+		}
+
+		assert_eq!(NetAddress::from_str("127.0.0.1"), Err(NetAddressParseError::InvalidInput));
+		assert_eq!(NetAddress::from_str("example.com:port"), Err(NetAddressParseError::InvalidPort));
+		assert_eq!(NetAddress::from_str("example.com:65536"), Err(NetAddressParseError::InvalidPort));
+		assert_eq!(NetAddress::from_str("exa mple.com:1234"), Err(NetAddressParseError::InvalidInput));
+		assert_eq!(NetAddress::from_str("pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd.com:1234").unwrap(),
+			NetAddress::Hostname { hostname: Hostname::try_from("pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd.com".to_string()).unwrap(), port: 1234 });
+		assert_eq!(NetAddress::from_str("pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscrya.onion:1234"),
+			Err(NetAddressParseError::InvalidOnionV3));
+		assert_eq!(NetAddress::from_str("tooshort.onion:1234"), Err(NetAddressParseError::InvalidOnionV3));
+	}
+
 	#[test]
 	fn encoding_channel_reestablish() {
 		let public_key = {
@@ -2801,6 +3500,7 @@ mod tests {
 			channel_flags: if random_bit { 1 << 5 } else { 0 },
 			shutdown_scriptpubkey: if shutdown { Some(Address::p2pkh(&::bitcoin::PublicKey{compressed: true, inner: pubkey_1}, Network::Testnet).script_pubkey()) } else { None },
 			channel_type: if incl_chan_type { Some(ChannelTypeFeatures::empty()) } else { None },
+			custom_tlvs: Vec::new(),
 		};
 		let encoded_value = open_channel.encode();
 		let mut target_value = Vec::new();
@@ -2864,6 +3564,7 @@ mod tests {
 			shutdown_scriptpubkey: if shutdown { Some(Address::p2pkh(&::bitcoin::PublicKey{compressed: true, inner: pubkey_1}, Network::Testnet).script_pubkey()) } else { None },
 			channel_type: if incl_chan_type { Some(ChannelTypeFeatures::empty()) } else { None },
 			require_confirmed_inputs: if require_confirmed_inputs { Some(()) } else { None },
+			request_funds: None,
 		};
 		let encoded_value = open_channelv2.encode();
 		let mut target_value = Vec::new();
@@ -2949,6 +3650,7 @@ mod tests {
 			first_per_commitment_point: pubkey_6,
 			shutdown_scriptpubkey: if shutdown { Some(Address::p2pkh(&::bitcoin::PublicKey{compressed: true, inner: pubkey_1}, Network::Testnet).script_pubkey()) } else { None },
 			channel_type: None,
+			custom_tlvs: Vec::new(),
 			#[cfg(taproot)]
 			next_local_nonce: None,
 		};
@@ -2994,6 +3696,7 @@ mod tests {
 			shutdown_scriptpubkey: if shutdown { Some(Address::p2pkh(&::bitcoin::PublicKey{compressed: true, inner: pubkey_1}, Network::Testnet).script_pubkey()) } else { None },
 			channel_type: None,
 			require_confirmed_inputs: None,
+			will_fund: None,
 		};
 		let encoded_value = accept_channelv2.encode();
 		let mut target_value = hex::decode("0202020202020202020202020202020202020202020202020202020202020202").unwrap(); // temporary_channel_id
@@ -3024,6 +3727,102 @@ mod tests {
 		do_encoding_accept_channelv2(true);
 	}
 
+	#[test]
+	fn encoding_liquidity_ads_lease() {
+		let secp_ctx = Secp256k1::new();
+		let (node_privkey, node_id) = get_keys_from!("0101010101010101010101010101010101010101010101010101010101010101", secp_ctx);
+		let (_, funding_pubkey) = get_keys_from!("0202020202020202020202020202020202020202020202020202020202020202", secp_ctx);
+
+		let request_funds = msgs::RequestFunds { requested_sats: 1_000_000, blockheight: 800_000 };
+		assert_eq!(request_funds.lease_expiry(), 800_000 + msgs::LEASE_DURATION_BLOCKS);
+		assert_eq!(request_funds.encode(), hex::decode("00000000000f4240000c3500").unwrap());
+
+		let lease_rates = msgs::LeaseRates {
+			funding_weight: 444,
+			lease_fee_basis: 50,
+			channel_fee_max_proportional_thousandths: 10,
+			lease_fee_base_sat: 1_000,
+			channel_fee_max_base_msat: 5_000,
+		};
+		let msg = msgs::WillFund::lease_commitment_hash(&funding_pubkey, request_funds.lease_expiry(), &lease_rates);
+		let will_fund = msgs::WillFund { signature: secp_ctx.sign_ecdsa(&msg, &node_privkey), lease_rates };
+		assert!(will_fund.verify(&secp_ctx, &node_id, &funding_pubkey, &request_funds).is_ok());
+		assert!(will_fund.verify(&secp_ctx, &funding_pubkey, &funding_pubkey, &request_funds).is_err());
+		let other_request = msgs::RequestFunds { blockheight: 800_001, ..request_funds };
+		assert!(will_fund.verify(&secp_ctx, &node_id, &funding_pubkey, &other_request).is_err());
+
+		let encoded_will_fund = will_fund.encode();
+		let mut target_value = will_fund.signature.encode();
+		target_value.append(&mut hex::decode("01bc0032000a000003e81388").unwrap());
+		assert_eq!(encoded_will_fund, target_value);
+		assert_eq!(msgs::WillFund::read(&mut &encoded_will_fund[..]).unwrap(), will_fund);
+
+		// The lease request and commitment are carried in TLV 1337 of open_channel2 and
+		// accept_channel2, respectively.
+		let open_channelv2 = msgs::OpenChannelV2 {
+			chain_hash: BlockHash::from_hex("6fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000").unwrap(),
+			temporary_channel_id: [2; 32],
+			funding_feerate_sat_per_1000_weight: 253,
+			commitment_feerate_sat_per_1000_weight: 253,
+			funding_satoshis: 100_000,
+			dust_limit_satoshis: 546,
+			max_htlc_value_in_flight_msat: 100_000_000,
+			htlc_minimum_msat: 1,
+			to_self_delay: 144,
+			max_accepted_htlcs: 483,
+			locktime: 800_000,
+			funding_pubkey,
+			revocation_basepoint: funding_pubkey,
+			payment_basepoint: funding_pubkey,
+			delayed_payment_basepoint: funding_pubkey,
+			htlc_basepoint: funding_pubkey,
+			first_per_commitment_point: funding_pubkey,
+			second_per_commitment_point: funding_pubkey,
+			channel_flags: 0,
+			shutdown_scriptpubkey: None,
+			channel_type: None,
+			require_confirmed_inputs: None,
+			request_funds: Some(request_funds),
+		};
+		let encoded_value = open_channelv2.encode();
+		let mut without_request = open_channelv2.clone();
+		without_request.request_funds = None;
+		let mut target_value = without_request.encode();
+		target_value.append(&mut hex::decode("fd05390c00000000000f4240000c3500").unwrap());
+		assert_eq!(encoded_value, target_value);
+		assert_eq!(msgs::OpenChannelV2::read(&mut &encoded_value[..]).unwrap(), open_channelv2);
+
+		let accept_channelv2 = msgs::AcceptChannelV2 {
+			temporary_channel_id: [2; 32],
+			funding_satoshis: request_funds.requested_sats,
+			dust_limit_satoshis: 546,
+			max_htlc_value_in_flight_msat: 100_000_000,
+			htlc_minimum_msat: 1,
+			minimum_depth: 3,
+			to_self_delay: 144,
+			max_accepted_htlcs: 483,
+			funding_pubkey,
+			revocation_basepoint: funding_pubkey,
+			payment_basepoint: funding_pubkey,
+			delayed_payment_basepoint: funding_pubkey,
+			htlc_basepoint: funding_pubkey,
+			first_per_commitment_point: funding_pubkey,
+			second_per_commitment_point: funding_pubkey,
+			shutdown_scriptpubkey: None,
+			channel_type: None,
+			require_confirmed_inputs: None,
+			will_fund: Some(will_fund.clone()),
+		};
+		let encoded_value = accept_channelv2.encode();
+		let mut without_commitment = accept_channelv2.clone();
+		without_commitment.will_fund = None;
+		let mut target_value = without_commitment.encode();
+		target_value.append(&mut hex::decode("fd05394c").unwrap());
+		target_value.append(&mut encoded_will_fund.clone());
+		assert_eq!(encoded_value, target_value);
+		assert_eq!(msgs::AcceptChannelV2::read(&mut &encoded_value[..]).unwrap(), accept_channelv2);
+	}
+
 	#[test]
 	fn encoding_funding_created() {
 		let secp_ctx = Secp256k1::new();
@@ -3259,6 +4058,7 @@ mod tests {
 				else if script_type == 2 { Address::p2sh(&script, Network::Testnet).unwrap().script_pubkey() }
 				else if script_type == 3 { Address::p2wpkh(&::bitcoin::PublicKey{compressed: true, inner: pubkey_1}, Network::Testnet).unwrap().script_pubkey() }
 				else                     { Address::p2wsh(&script, Network::Testnet).script_pubkey() },
+			custom_tlvs: Vec::new(),
 		};
 		let encoded_value = shutdown.encode();
 		let mut target_value = hex::decode("0202020202020202020202020202020202020202020202020202020202020202").unwrap();
@@ -3282,6 +4082,30 @@ mod tests {
 		do_encoding_shutdown(4);
 	}
 
+	#[test]
+	fn encoding_custom_message_tlvs() {
+		let shutdown = msgs::Shutdown {
+			channel_id: [2; 32],
+			scriptpubkey: Builder::new().push_opcode(opcodes::OP_TRUE).into_script(),
+			custom_tlvs: vec![(msgs::MIN_CUSTOM_TLV_TYPE + 1, vec![0xab, 0xcd])],
+		};
+		let encoded_value = shutdown.encode();
+		assert_eq!(encoded_value[encoded_value.len() - 8..], hex::decode("fe0001000102abcd").unwrap()[..]);
+		let decoded: msgs::Shutdown = Readable::read(&mut &encoded_value[..]).unwrap();
+		assert_eq!(decoded, shutdown);
+
+		// Odd types below `MIN_CUSTOM_TLV_TYPE` are ignored as usual.
+		let mut encoded_value = hex::decode("02020202020202020202020202020202020202020202020202020202020202020001510301ff").unwrap();
+		encoded_value.append(&mut hex::decode("fe0001000102abcd").unwrap());
+		let decoded: msgs::Shutdown = Readable::read(&mut &encoded_value[..]).unwrap();
+		assert_eq!(decoded, shutdown);
+
+		// Even types are still required to be understood.
+		let encoded_value = hex::decode("02020202020202020202020202020202020202020202020202020202020202020001510301fffe0001000002abcd").unwrap();
+		let res: Result<msgs::Shutdown, msgs::DecodeError> = Readable::read(&mut &encoded_value[..]);
+		assert!(res.is_err());
+	}
+
 	#[test]
 	fn encoding_closing_signed() {
 		let secp_ctx = Secp256k1::new();
@@ -3314,6 +4138,43 @@ mod tests {
 			closing_signed_with_range);
 	}
 
+	#[test]
+	fn encoding_closing_complete_and_sig() {
+		let secp_ctx = Secp256k1::new();
+		let (privkey_1, _) = get_keys_from!("0101010101010101010101010101010101010101010101010101010101010101", secp_ctx);
+		let sig_1 = get_sig_on!(privkey_1, secp_ctx, String::from("01010101010101010101010101010101"));
+		let closer_scriptpubkey = Script::from(hex::decode("001479b000887626b294a914501a4cd226b58b235983").unwrap());
+		let closee_scriptpubkey = Script::from(hex::decode("00204ae81572f06e1b88fd5ced7a1a000945432e83e1551e6f721ee9c00b8cc33260").unwrap());
+		let closing_complete = msgs::ClosingComplete {
+			channel_id: [2; 32],
+			closer_scriptpubkey: closer_scriptpubkey.clone(),
+			closee_scriptpubkey: closee_scriptpubkey.clone(),
+			fee_satoshis: 2316138423780173,
+			locktime: 800000,
+			closer_output_only: Some(sig_1),
+			closee_output_only: None,
+			closer_and_closee_outputs: Some(sig_1),
+		};
+		let encoded_value = closing_complete.encode();
+		let target_value = hex::decode("02020202020202020202020202020202020202020202020202020202020202020016001479b000887626b294a914501a4cd226b58b235983002200204ae81572f06e1b88fd5ced7a1a000945432e83e1551e6f721ee9c00b8cc3326000083a840000034d000c35000140d977cb9b53d93a6ff64bb5f1e158b4094b66e798fb12911168a3ccdf80a83096340a6a95da0ae8d9f776528eecdbb747eb6b545495a4319ed5378e35b21e073a0340d977cb9b53d93a6ff64bb5f1e158b4094b66e798fb12911168a3ccdf80a83096340a6a95da0ae8d9f776528eecdbb747eb6b545495a4319ed5378e35b21e073a").unwrap();
+		assert_eq!(encoded_value, target_value);
+		assert_eq!(msgs::ClosingComplete::read(&mut Cursor::new(&target_value)).unwrap(), closing_complete);
+
+		let closing_sig = msgs::ClosingSig {
+			channel_id: [2; 32],
+			closer_scriptpubkey,
+			closee_scriptpubkey,
+			fee_satoshis: 2316138423780173,
+			locktime: 800000,
+			closer_output_only: None,
+			closee_output_only: Some(sig_1),
+			closer_and_closee_outputs: None,
+		};
+		let encoded_sig = closing_sig.encode();
+		assert_eq!(encoded_sig[..32], [2; 32]);
+		assert_eq!(msgs::ClosingSig::read(&mut Cursor::new(&encoded_sig)).unwrap(), closing_sig);
+	}
+
 	#[test]
 	fn encoding_update_add_htlc() {
 		let secp_ctx = Secp256k1::new();
@@ -3330,11 +4191,21 @@ mod tests {
 			amount_msat: 3608586615801332854,
 			payment_hash: PaymentHash([1; 32]),
 			cltv_expiry: 821716,
+			blinding_point: None,
 			onion_routing_packet
 		};
 		let encoded_value = update_add_htlc.encode();
 		let target_value = hex::decode("020202020202020202020202020202020202020202020202020202020202020200083a840000034d32144668701144760101010101010101010101010101010101010101010101010101010101010101000c89d4ff031b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078f010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010202020202020202020202020202020202020202020202020202020202020202").unwrap();
 		assert_eq!(encoded_value, target_value);
+
+		// The blinding point is written in the message's TLV stream.
+		let update_add_htlc = msgs::UpdateAddHTLC { blinding_point: Some(pubkey_1), ..update_add_htlc };
+		let mut target_value = target_value;
+		target_value.extend_from_slice(&hex::decode("0021").unwrap());
+		target_value.extend_from_slice(&pubkey_1.serialize());
+		assert_eq!(update_add_htlc.encode(), target_value);
+		let decoded_value: msgs::UpdateAddHTLC = Readable::read(&mut &target_value[..]).unwrap();
+		assert_eq!(decoded_value, update_add_htlc);
 	}
 
 	#[test]
@@ -3353,6 +4224,7 @@ mod tests {
 	fn encoding_update_fail_htlc() {
 		let reason = OnionErrorPacket {
 			data: [1; 32].to_vec(),
+			attribution_data: None,
 		};
 		let update_fail_htlc = msgs::UpdateFailHTLC {
 			channel_id: [2; 32],
@@ -3364,6 +4236,27 @@ mod tests {
 		assert_eq!(encoded_value, target_value);
 	}
 
+	#[test]
+	fn encoding_update_fail_htlc_with_attribution_data() {
+		let mut attribution_data = msgs::AttributionData::new();
+		attribution_data.hold_times[0] = 3;
+		attribution_data.hmacs[1] = 4;
+		let update_fail_htlc = msgs::UpdateFailHTLC {
+			channel_id: [2; 32],
+			htlc_id: 2316138423780173,
+			reason: OnionErrorPacket { data: [1; 32].to_vec(), attribution_data: Some(attribution_data) },
+		};
+		let encoded_value = update_fail_htlc.encode();
+		let mut target_value = hex::decode("020202020202020202020202020202020202020202020202020202020202020200083a840000034d00200101010101010101010101010101010101010101010101010101010101010101").unwrap();
+		target_value.extend_from_slice(&hex::decode("01fd0398").unwrap());
+		target_value.push(3);
+		target_value.extend_from_slice(&[0; 80 - 1]);
+		target_value.extend_from_slice(&[0, 4]);
+		target_value.extend_from_slice(&[0; 840 - 2]);
+		assert_eq!(encoded_value, target_value);
+		assert_eq!(msgs::UpdateFailHTLC::read(&mut Cursor::new(&encoded_value)).unwrap(), update_fail_htlc);
+	}
+
 	#[test]
 	fn encoding_update_fail_malformed_htlc() {
 		let update_fail_malformed_htlc = msgs::UpdateFailMalformedHTLC {
@@ -3516,6 +4409,24 @@ mod tests {
 		assert_eq!(encoded_value, target_value);
 	}
 
+	#[test]
+	fn encoding_peer_storage() {
+		let peer_storage = msgs::PeerStorage {
+			data: vec![0xde, 0xad, 0xbe, 0xef],
+		};
+		let encoded_value = peer_storage.encode();
+		let target_value = hex::decode("0004deadbeef").unwrap();
+		assert_eq!(encoded_value, target_value);
+		assert_eq!(msgs::PeerStorage::read(&mut Cursor::new(&target_value)).unwrap(), peer_storage);
+
+		let your_peer_storage = msgs::YourPeerStorage {
+			data: vec![0xde, 0xad, 0xbe, 0xef],
+		};
+		let encoded_value = your_peer_storage.encode();
+		assert_eq!(encoded_value, target_value);
+		assert_eq!(msgs::YourPeerStorage::read(&mut Cursor::new(&target_value)).unwrap(), your_peer_storage);
+	}
+
 	#[test]
 	fn encoding_nonfinal_onion_hop_data() {
 		let mut msg = msgs::OnionHopData {
@@ -3589,6 +4500,56 @@ mod tests {
 		assert_eq!(msg.outgoing_cltv_value, 0xffffffff);
 	}
 
+	#[test]
+	fn encoding_blinded_onion_hop_data() {
+		let secp_ctx = Secp256k1::new();
+		let (_, blinding_point) = get_keys_from!("0101010101010101010101010101010101010101010101010101010101010101", secp_ctx);
+
+		let msg = msgs::OnionHopData {
+			format: OnionHopDataFormat::BlindedFinalNode {
+				total_msat: 0x1badca1f,
+				encrypted_tlvs: vec![0x42; 32],
+				intro_node_blinding_point: Some(blinding_point),
+				keysend_preimage: None,
+			},
+			amt_to_forward: 0x0badf00d01020304,
+			outgoing_cltv_value: 0xffffffff,
+		};
+		let encoded_value = msg.encode();
+		let msg: msgs::OnionHopData = Readable::read(&mut Cursor::new(&encoded_value[..])).unwrap();
+		if let OnionHopDataFormat::BlindedFinalNode {
+			total_msat: 0x1badca1f, ref encrypted_tlvs, intro_node_blinding_point: Some(point), keysend_preimage: None,
+		} = msg.format {
+			assert_eq!(encrypted_tlvs, &vec![0x42; 32]);
+			assert_eq!(point, blinding_point);
+		} else { panic!(); }
+		assert_eq!(msg.amt_to_forward, 0x0badf00d01020304);
+		assert_eq!(msg.outgoing_cltv_value, 0xffffffff);
+
+		let msg = msgs::OnionHopData {
+			format: OnionHopDataFormat::BlindedNonFinalNode {
+				encrypted_tlvs: vec![0x42; 32],
+				intro_node_blinding_point: None,
+			},
+			amt_to_forward: 0x0badf00d01020304,
+			outgoing_cltv_value: 0xffffffff,
+		};
+		let encoded_value = msg.encode();
+		let msg: msgs::OnionHopData = Readable::read(&mut Cursor::new(&encoded_value[..])).unwrap();
+		if let OnionHopDataFormat::BlindedNonFinalNode { ref encrypted_tlvs, intro_node_blinding_point: None } = msg.format {
+			assert_eq!(encrypted_tlvs, &vec![0x42; 32]);
+		} else { panic!(); }
+
+		// Blinded payloads may not carry unblinded payment data.
+		let mut payload = hex::decode("082442424242424242424242424242424242424242424242424242424242424242421badca1f").unwrap();
+		payload.extend_from_slice(&[0x0a, 0x20]);
+		payload.extend_from_slice(&[0x42; 32]);
+		let mut encoded_value = vec![payload.len() as u8];
+		encoded_value.extend_from_slice(&payload);
+		let res: Result<msgs::OnionHopData, _> = Readable::read(&mut Cursor::new(&encoded_value[..]));
+		assert_eq!(res.err(), Some(msgs::DecodeError::InvalidValue));
+	}
+
 	#[test]
 	fn query_channel_range_end_blocknum() {
 		let tests: Vec<(u32, u32, u32)> = vec![
@@ -13,13 +13,15 @@
 
 use crate::chain::channelmonitor::{CLTV_CLAIM_BUFFER, LATENCY_GRACE_PERIOD_BLOCKS};
 use crate::sign::{EntropySource, NodeSigner, Recipient};
-use crate::events::{Event, HTLCDestination, MessageSendEvent, MessageSendEventsProvider, PathFailure, PaymentFailureReason};
-use crate::ln::{PaymentHash, PaymentSecret};
+use crate::events::{BlindedForwardRejection, Event, FailureReason, HTLCDestination, MessageSendEvent, MessageSendEventsProvider, PathFailure, PaymentFailureReason};
+use crate::ln::{PaymentHash, PaymentPreimage, PaymentSecret};
 use crate::ln::channel::EXPIRE_PREV_CONFIG_TICKS;
-use crate::ln::channelmanager::{HTLCForwardInfo, FailureCode, CLTV_FAR_FAR_AWAY, DISABLE_GOSSIP_TICKS, MIN_CLTV_EXPIRY_DELTA, PendingAddHTLCInfo, PendingHTLCInfo, PendingHTLCRouting, PaymentId, RecipientOnionFields};
+use crate::ln::channelmanager::{BlindedPaymentPathConfig, HTLCForwardInfo, FailureCode, CLTV_FAR_FAR_AWAY, DISABLE_GOSSIP_TICKS, MIN_CLTV_EXPIRY_DELTA, PendingAddHTLCInfo, PendingHTLCInfo, PendingHTLCRouting, PaymentId, RecipientOnionFields, Retry};
 use crate::ln::onion_utils;
-use crate::routing::gossip::{NetworkUpdate, RoutingFees};
-use crate::routing::router::{get_route, PaymentParameters, Route, RouteHint, RouteHintHop};
+use crate::routing::gossip::{NetworkUpdate, NodeId, RoutingFees};
+use crate::blinded_path::BlindedHop;
+use crate::blinded_path::payment::{PaymentConstraints, ReceiveTlvs};
+use crate::routing::router::{get_route, BlindedTail, Path, PaymentParameters, Route, RouteHint, RouteHintHop, RouteHop, RouteParameters};
 use crate::ln::features::{InitFeatures, InvoiceFeatures};
 use crate::ln::msgs;
 use crate::ln::msgs::{ChannelMessageHandler, ChannelUpdate};
@@ -27,7 +29,7 @@ use crate::ln::wire::Encode;
 use crate::util::ser::{Writeable, Writer};
 use crate::util::test_utils;
 use crate::util::config::{UserConfig, ChannelConfig};
-use crate::util::errors::APIError;
+use crate::util::errors::ChannelConfigUpdateError;
 
 use bitcoin::hash_types::BlockHash;
 
@@ -35,7 +37,7 @@ use bitcoin::hashes::Hash;
 use bitcoin::hashes::sha256::Hash as Sha256;
 
 use bitcoin::secp256k1;
-use bitcoin::secp256k1::{Secp256k1, SecretKey};
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
 
 use crate::io;
 use crate::prelude::*;
@@ -168,9 +170,10 @@ fn run_onion_failure_test_with_fail_intercept<F1,F2,F3>(_name: &str, test_case:
 
 	let events = nodes[0].node.get_and_clear_pending_events();
 	assert_eq!(events.len(), 2);
-	if let &Event::PaymentPathFailed { ref payment_failed_permanently, ref short_channel_id, ref error_code, failure: PathFailure::OnPath { ref network_update }, .. } = &events[0] {
+	if let &Event::PaymentPathFailed { ref payment_failed_permanently, ref short_channel_id, ref error_code, ref failure_reason, failure: PathFailure::OnPath { ref network_update }, .. } = &events[0] {
 		assert_eq!(*payment_failed_permanently, !expected_retryable);
 		assert_eq!(*error_code, expected_error_code);
+		assert_eq!(*failure_reason, expected_error_code.map(FailureReason::from));
 		if expected_channel_update.is_some() {
 			match network_update {
 				Some(update) => match update {
@@ -213,7 +216,7 @@ fn run_onion_failure_test_with_fail_intercept<F1,F2,F3>(_name: &str, test_case:
 		panic!("Unexpected event");
 	}
 	match events[1] {
-		Event::PaymentFailed { payment_hash: ev_payment_hash, payment_id: ev_payment_id, reason: ref ev_reason } => {
+		Event::PaymentFailed { payment_hash: ev_payment_hash, payment_id: ev_payment_id, reason: ref ev_reason, .. } => {
 			assert_eq!(*payment_hash, ev_payment_hash);
 			assert_eq!(payment_id, ev_payment_id);
 			assert_eq!(if expected_retryable {
@@ -315,6 +318,270 @@ fn test_fee_failures() {
 	claim_payment(&nodes[0], &[&nodes[1], &nodes[2]], payment_preimage_success);
 }
 
+#[test]
+fn test_failure_reason_and_failing_node() {
+	// Tests that the decoded failure reason and the node which generated a failure are surfaced
+	// when an intermediate node fails an HTLC we sent.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	let chan_0_1 = create_announced_chan_between_nodes(&nodes, 0, 1);
+	create_announced_chan_between_nodes(&nodes, 1, 2);
+
+	let (route, payment_hash, _, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[2], 40_000);
+	nodes[0].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+
+	// Underpay the fee of nodes[1] so that it fails the HTLC with fee_insufficient. As the route
+	// paid enough fees, the channel it was received over is blamed.
+	let update_0 = get_htlc_update_msgs!(nodes[0], nodes[1].node.get_our_node_id());
+	let mut update_add = update_0.update_add_htlcs[0].clone();
+	update_add.amount_msat -= 1;
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &update_add);
+	commitment_signed_dance!(nodes[1], nodes[0], &update_0.commitment_signed, false, true);
+
+	let update_1_0 = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	assert_eq!(update_1_0.update_fail_htlcs.len(), 1);
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &update_1_0.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], update_1_0.commitment_signed, false, true);
+
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 2);
+	match events[0] {
+		Event::PaymentPathFailed { ref failure_reason, ref failing_node_id, ref short_channel_id, .. } => {
+			assert_eq!(*failure_reason, Some(FailureReason::FeeInsufficient));
+			assert_eq!(failure_reason.unwrap().failure_code(), UPDATE|12);
+			assert_eq!(*failing_node_id, Some(NodeId::from_pubkey(&nodes[1].node.get_our_node_id())));
+			assert_eq!(*short_channel_id, Some(chan_0_1.0.contents.short_channel_id));
+		},
+		_ => panic!("Unexpected event"),
+	}
+	match events[1] {
+		Event::PaymentFailed { payment_hash: ev_payment_hash, .. } => assert_eq!(ev_payment_hash, payment_hash),
+		_ => panic!("Unexpected event"),
+	}
+}
+
+#[test]
+fn test_blinded_forward_rejected() {
+	// The introduction node must fail HTLCs whose blinded payload it cannot decrypt with
+	// invalid_onion_blinding without revealing why, surfacing the actual reason locally.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let chan = create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	let secp_ctx = Secp256k1::new();
+	let pubkey = |byte: u8| PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[byte; 32]).unwrap());
+	let route = Route {
+		paths: vec![Path {
+			hops: vec![RouteHop {
+				pubkey: nodes[1].node.get_our_node_id(),
+				node_features: nodes[1].node.node_features(),
+				short_channel_id: chan.0.contents.short_channel_id,
+				channel_features: nodes[1].node.channel_features(),
+				fee_msat: 0,
+				cltv_expiry_delta: 0,
+			}],
+			blinded_tail: Some(BlindedTail {
+				hops: vec![
+					BlindedHop { blinded_node_id: nodes[1].node.get_our_node_id(), encrypted_payload: vec![1; 32] },
+					BlindedHop { blinded_node_id: pubkey(2), encrypted_payload: vec![2; 32] },
+				],
+				blinding_point: pubkey(3),
+				excess_final_cltv_expiry_delta: 0,
+				final_value_msat: 10_000,
+			}),
+		}],
+		payment_params: None,
+	};
+	let payment_hash = PaymentHash([42; 32]);
+	nodes[0].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::spontaneous_empty(), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+
+	let update_0 = get_htlc_update_msgs!(nodes[0], nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &update_0.update_add_htlcs[0]);
+	commitment_signed_dance!(nodes[1], nodes[0], &update_0.commitment_signed, false, true);
+	nodes[1].logger.assert_log_contains("lightning::ln::channelmanager",
+		"Failed to accept/forward incoming HTLC: Unable to decrypt the blinded payment data for us", 1);
+	let events = nodes[1].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		Event::BlindedForwardRejected { prev_channel_id, payment_hash: ev_payment_hash, is_introduction_node, ref reason } => {
+			assert_eq!(prev_channel_id, chan.2);
+			assert_eq!(ev_payment_hash, payment_hash);
+			assert!(is_introduction_node);
+			assert_eq!(*reason, BlindedForwardRejection::InvalidPayload);
+		},
+		_ => panic!("Unexpected event"),
+	}
+
+	let update_1_0 = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	assert_eq!(update_1_0.update_fail_htlcs.len(), 1);
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &update_1_0.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], update_1_0.commitment_signed, false, true);
+
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 2);
+	match events[0] {
+		Event::PaymentPathFailed { ref failure_reason, ref error_code, ref error_data, .. } => {
+			assert_eq!(*failure_reason, Some(FailureReason::InvalidOnionBlinding));
+			assert_eq!(*error_code, Some(onion_utils::INVALID_ONION_BLINDING));
+			assert_eq!(*error_data, Some(vec![0; 32]));
+		},
+		_ => panic!("Unexpected event"),
+	}
+	match events[1] {
+		Event::PaymentFailed { payment_hash: ev_payment_hash, .. } => assert_eq!(ev_payment_hash, payment_hash),
+		_ => panic!("Unexpected event"),
+	}
+}
+
+/// Sends a payment from `nodes[0]` to `nodes[2]` over a blinded path created by `nodes[2]` through
+/// `nodes[1]`, returning the payment's preimage, hash and secret.
+fn send_blinded_payment(nodes: &[Node<'_, '_, '_>], amt_msat: u64) -> (PaymentPreimage, PaymentHash, PaymentSecret) {
+	let (payment_preimage, payment_hash, payment_secret) = get_payment_preimage_hash!(nodes[2]);
+	let payee_tlvs = ReceiveTlvs {
+		path_id: payment_secret.0,
+		payment_constraints: PaymentConstraints { max_cltv_expiry: u32::max_value(), htlc_minimum_msat: 1 },
+	};
+	let config = BlindedPaymentPathConfig { max_paths: 1, ..BlindedPaymentPathConfig::default() };
+	let paths = nodes[2].node.create_blinded_payment_paths(payee_tlvs, &config).unwrap();
+	assert_eq!(paths[0].1.introduction_node_id(), nodes[1].node.get_our_node_id());
+	let route_params = RouteParameters {
+		payment_params: PaymentParameters::blinded(paths),
+		final_value_msat: amt_msat,
+	};
+	nodes[0].node.send_payment(payment_hash, RecipientOnionFields::secret_only(payment_secret),
+		PaymentId(payment_hash.0), route_params, Retry::Attempts(0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	(payment_preimage, payment_hash, payment_secret)
+}
+
+#[test]
+fn test_blinded_forward() {
+	// Tests that the introduction node of a blinded path relays HTLCs within it, giving the next hop
+	// the blinding point it needs to decrypt its onion payload.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+	create_announced_chan_between_nodes(&nodes, 1, 2);
+
+	let (payment_preimage, payment_hash, payment_secret) = send_blinded_payment(&nodes, 10_000);
+	pass_along_route(&nodes[0], &[&[&nodes[1], &nodes[2]]], 10_000, payment_hash, payment_secret);
+	claim_payment(&nodes[0], &[&nodes[1], &nodes[2]], payment_preimage);
+}
+
+#[test]
+fn test_blinded_forward_disabled() {
+	// Tests that an introduction node which doesn't relay HTLCs within blinded paths per its config
+	// fails them with invalid_onion_blinding.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let mut no_blinded_forwards_config = test_default_channel_config();
+	no_blinded_forwards_config.accept_blinded_forwards = false;
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, Some(no_blinded_forwards_config), None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	let chan = create_announced_chan_between_nodes(&nodes, 0, 1);
+	create_announced_chan_between_nodes(&nodes, 1, 2);
+
+	let (_, payment_hash, _) = send_blinded_payment(&nodes, 10_000);
+	let update_0 = get_htlc_update_msgs!(nodes[0], nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &update_0.update_add_htlcs[0]);
+	commitment_signed_dance!(nodes[1], nodes[0], &update_0.commitment_signed, false, true);
+	let events = nodes[1].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		Event::BlindedForwardRejected { prev_channel_id, is_introduction_node, ref reason, .. } => {
+			assert_eq!(prev_channel_id, chan.2);
+			assert!(is_introduction_node);
+			assert_eq!(*reason, BlindedForwardRejection::Disabled);
+		},
+		_ => panic!("Unexpected event"),
+	}
+
+	let update_1_0 = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	assert_eq!(update_1_0.update_fail_htlcs.len(), 1);
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &update_1_0.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], update_1_0.commitment_signed, false, true);
+
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 2);
+	match events[0] {
+		Event::PaymentPathFailed { ref error_code, ref error_data, .. } => {
+			assert_eq!(*error_code, Some(onion_utils::INVALID_ONION_BLINDING));
+			assert_eq!(*error_data, Some(vec![0; 32]));
+		},
+		_ => panic!("Unexpected event"),
+	}
+	match events[1] {
+		Event::PaymentFailed { payment_hash: ev_payment_hash, .. } => assert_eq!(ev_payment_hash, payment_hash),
+		_ => panic!("Unexpected event"),
+	}
+}
+
+#[test]
+fn test_blinded_node_failure_is_malformed() {
+	// Tests that a node within a blinded path other than its introduction node fails HTLCs with
+	// `update_fail_malformed_htlc` and invalid_onion_blinding, which the introduction node then
+	// relays as an encrypted invalid_onion_blinding error.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+	create_announced_chan_between_nodes(&nodes, 1, 2);
+
+	let (_, payment_hash, _) = send_blinded_payment(&nodes, 10_000);
+	let payment_event = SendEvent::from_node(&nodes[0]);
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &payment_event.msgs[0]);
+	commitment_signed_dance!(nodes[1], nodes[0], payment_event.commitment_msg, false);
+	expect_pending_htlcs_forwardable!(nodes[1]);
+	check_added_monitors!(nodes[1], 1);
+
+	// Corrupt the onion's HMAC such that the blinded recipient fails to decode it.
+	let mut payment_event = SendEvent::from_node(&nodes[1]);
+	assert!(payment_event.msgs[0].blinding_point.is_some());
+	payment_event.msgs[0].onion_routing_packet.hmac[0] ^= 1;
+	nodes[2].node.handle_update_add_htlc(&nodes[1].node.get_our_node_id(), &payment_event.msgs[0]);
+	commitment_signed_dance!(nodes[2], nodes[1], payment_event.commitment_msg, false, true);
+
+	let update_2_1 = get_htlc_update_msgs!(nodes[2], nodes[1].node.get_our_node_id());
+	assert!(update_2_1.update_fail_htlcs.is_empty());
+	assert_eq!(update_2_1.update_fail_malformed_htlcs.len(), 1);
+	let update_malformed = &update_2_1.update_fail_malformed_htlcs[0];
+	assert_eq!(update_malformed.failure_code, onion_utils::INVALID_ONION_BLINDING);
+	assert_eq!(update_malformed.sha256_of_onion, [0; 32]);
+	nodes[1].node.handle_update_fail_malformed_htlc(&nodes[2].node.get_our_node_id(), update_malformed);
+	do_commitment_signed_dance(&nodes[1], &nodes[2], &update_2_1.commitment_signed, true, false);
+
+	let update_1_0 = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	assert_eq!(update_1_0.update_fail_htlcs.len(), 1);
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &update_1_0.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], update_1_0.commitment_signed, false, true);
+
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 2);
+	match events[0] {
+		Event::PaymentPathFailed { ref failure_reason, ref error_code, ref error_data, .. } => {
+			assert_eq!(*failure_reason, Some(FailureReason::InvalidOnionBlinding));
+			assert_eq!(*error_code, Some(onion_utils::INVALID_ONION_BLINDING));
+			assert_eq!(*error_data, Some(vec![0; 32]));
+		},
+		_ => panic!("Unexpected event"),
+	}
+	match events[1] {
+		Event::PaymentFailed { payment_hash: ev_payment_hash, .. } => assert_eq!(ev_payment_hash, payment_hash),
+		_ => panic!("Unexpected event"),
+	}
+}
+
 #[test]
 fn test_onion_failure() {
 	// When we check for amount_below_minimum below, we want to test that we're using the *right*
@@ -773,13 +1040,13 @@ fn do_test_onion_failure_stale_channel_update(announced_channel: bool) {
 		);
 	};
 
-	// Updates to cltv_expiry_delta below MIN_CLTV_EXPIRY_DELTA should fail with APIMisuseError.
+	// Updates to cltv_expiry_delta below MIN_CLTV_EXPIRY_DELTA should fail.
 	let mut invalid_config = default_config.clone();
 	invalid_config.cltv_expiry_delta = 0;
 	match nodes[1].node.update_channel_config(
 		channel_to_update_counterparty, &[channel_to_update.0], &invalid_config,
 	) {
-		Err(APIError::APIMisuseError{ .. }) => {},
+		Err(ChannelConfigUpdateError::CltvExpiryDeltaTooLow { cltv_expiry_delta: 0, .. }) => {},
 		_ => panic!("unexpected result applying invalid cltv_expiry_delta"),
 	}
 
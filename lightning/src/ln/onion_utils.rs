@@ -10,8 +10,10 @@
 use crate::ln::{PaymentHash, PaymentPreimage};
 use crate::ln::channelmanager::{HTLCSource, RecipientOnionFields};
 use crate::ln::msgs;
+use crate::ln::msgs::{ATTRIBUTION_HMAC_LEN, ATTRIBUTION_HOLD_TIME_LEN, MAX_ATTRIBUTABLE_HOPS};
 use crate::ln::wire::Encode;
-use crate::routing::gossip::NetworkUpdate;
+use crate::events::FailureReason;
+use crate::routing::gossip::{NetworkUpdate, NodeId};
 use crate::routing::router::{Path, RouteHop};
 use crate::util::chacha20::{ChaCha20, ChaChaReader};
 use crate::util::errors::{self, APIError};
@@ -30,6 +32,7 @@ use bitcoin::secp256k1;
 
 use crate::prelude::*;
 use crate::io::{Cursor, Read};
+use core::cmp;
 use core::convert::{AsMut, TryInto};
 use core::ops::Deref;
 
@@ -82,6 +85,14 @@ pub(super) fn gen_ammag_from_shared_secret(shared_secret: &[u8]) -> [u8; 32] {
 	Hmac::from_engine(hmac).into_inner()
 }
 
+#[inline]
+pub(super) fn gen_ammag_ext_from_shared_secret(shared_secret: &[u8]) -> [u8; 32] {
+	assert_eq!(shared_secret.len(), 32);
+	let mut hmac = HmacEngine::<Sha256>::new(&[0x61, 0x6d, 0x6d, 0x61, 0x67, 0x65, 0x78, 0x74]); // ammagext
+	hmac.input(&shared_secret);
+	Hmac::from_engine(hmac).into_inner()
+}
+
 #[cfg(test)]
 #[inline]
 pub(super) fn gen_pad_from_shared_secret(shared_secret: &[u8]) -> [u8; 32] {
@@ -104,12 +115,21 @@ pub(crate) fn next_hop_packet_pubkey<T: secp256k1::Signing + secp256k1::Verifica
 
 // can only fail if an intermediary hop has an invalid public key or session_priv is invalid
 #[inline]
-pub(super) fn construct_onion_keys_callback<T: secp256k1::Signing, FType: FnMut(SharedSecret, [u8; 32], PublicKey, &RouteHop, usize)> (secp_ctx: &Secp256k1<T>, path: &Vec<RouteHop>, session_priv: &SecretKey, mut callback: FType) -> Result<(), secp256k1::Error> {
+//
+// The callback is given the `RouteHop` for each unblinded hop, and `None` for each hop within the
+// path's blinded tail, if any, after its introduction node.
+pub(super) fn construct_onion_keys_callback<T: secp256k1::Signing, FType: FnMut(SharedSecret, [u8; 32], PublicKey, Option<&RouteHop>, usize)> (secp_ctx: &Secp256k1<T>, path: &Path, session_priv: &SecretKey, mut callback: FType) -> Result<(), secp256k1::Error> {
 	let mut blinded_priv = session_priv.clone();
 	let mut blinded_pub = PublicKey::from_secret_key(secp_ctx, &blinded_priv);
 
-	for (idx, hop) in path.iter().enumerate() {
-		let shared_secret = SharedSecret::new(&hop.pubkey, &blinded_priv);
+	let unblinded_hops_iter = path.hops.iter().map(|hop| (&hop.pubkey, Some(hop)));
+	let blinded_hops_iter = path.blinded_tail.as_ref()
+		.map(|tail| tail.hops.iter()).unwrap_or([].iter())
+		// The introduction node is included in the unblinded hops
+		.skip(1)
+		.map(|hop| (&hop.blinded_node_id, None));
+	for (idx, (pubkey, hop)) in unblinded_hops_iter.chain(blinded_hops_iter).enumerate() {
+		let shared_secret = SharedSecret::new(pubkey, &blinded_priv);
 
 		let mut sha = Sha256::engine();
 		sha.input(&blinded_pub.serialize()[..]);
@@ -131,7 +151,7 @@ pub(super) fn construct_onion_keys_callback<T: secp256k1::Signing, FType: FnMut(
 pub(super) fn construct_onion_keys<T: secp256k1::Signing>(secp_ctx: &Secp256k1<T>, path: &Path, session_priv: &SecretKey) -> Result<Vec<OnionKeys>, secp256k1::Error> {
 	let mut res = Vec::with_capacity(path.hops.len());
 
-	construct_onion_keys_callback(secp_ctx, &path.hops, session_priv, |shared_secret, _blinding_factor, ephemeral_pubkey, _, _| {
+	construct_onion_keys_callback(secp_ctx, &path, session_priv, |shared_secret, _blinding_factor, ephemeral_pubkey, _, _| {
 		let (rho, mu) = gen_rho_mu_from_shared_secret(shared_secret.as_ref());
 
 		res.push(OnionKeys {
@@ -161,26 +181,56 @@ pub(super) fn build_onion_payloads(path: &Path, total_msat: u64, mut recipient_o
 		// the intended recipient).
 		let value_msat = if cur_value_msat == 0 { hop.fee_msat } else { cur_value_msat };
 		let cltv = if cur_cltv == starting_htlc_offset { hop.cltv_expiry_delta + starting_htlc_offset } else { cur_cltv };
-		res.insert(0, msgs::OnionHopData {
-			format: if idx == 0 {
-				msgs::OnionHopDataFormat::FinalNode {
-					payment_data: if let Some(secret) = recipient_onion.payment_secret.take() {
-						Some(msgs::FinalOnionHopData {
-							payment_secret: secret,
-							total_msat,
-						})
-					} else { None },
-					payment_metadata: recipient_onion.payment_metadata.take(),
-					keysend_preimage: *keysend_preimage,
-				}
-			} else {
-				msgs::OnionHopDataFormat::NonFinalNode {
-					short_channel_id: last_short_channel_id,
-				}
-			},
-			amt_to_forward: value_msat,
-			outgoing_cltv_value: cltv,
-		});
+		if let (0, Some(blinded_tail)) = (idx, &path.blinded_tail) {
+			// The last unblinded hop is the introduction node which, as each hop within the blinded
+			// path, is given its payload in the form of the recipient's encrypted TLVs. Note that
+			// the introduction node's CLTV delta covers the entire blinded path.
+			let mut blinding_point = Some(blinded_tail.blinding_point);
+			let final_cltv = starting_htlc_offset + blinded_tail.excess_final_cltv_expiry_delta;
+			for (i, blinded_hop) in blinded_tail.hops.iter().enumerate() {
+				let format = if i == blinded_tail.hops.len() - 1 {
+					msgs::OnionHopDataFormat::BlindedFinalNode {
+						total_msat,
+						encrypted_tlvs: blinded_hop.encrypted_payload.clone(),
+						intro_node_blinding_point: blinding_point.take(),
+						keysend_preimage: *keysend_preimage,
+					}
+				} else {
+					msgs::OnionHopDataFormat::BlindedNonFinalNode {
+						encrypted_tlvs: blinded_hop.encrypted_payload.clone(),
+						intro_node_blinding_point: blinding_point.take(),
+					}
+				};
+				res.push(msgs::OnionHopData {
+					format,
+					amt_to_forward: blinded_tail.final_value_msat,
+					outgoing_cltv_value: final_cltv,
+				});
+			}
+			cur_value_msat += blinded_tail.final_value_msat;
+			cur_cltv += blinded_tail.excess_final_cltv_expiry_delta;
+		} else {
+			res.insert(0, msgs::OnionHopData {
+				format: if idx == 0 {
+					msgs::OnionHopDataFormat::FinalNode {
+						payment_data: if let Some(secret) = recipient_onion.payment_secret.take() {
+							Some(msgs::FinalOnionHopData {
+								payment_secret: secret,
+								total_msat,
+							})
+						} else { None },
+						payment_metadata: recipient_onion.payment_metadata.take(),
+						keysend_preimage: *keysend_preimage,
+					}
+				} else {
+					msgs::OnionHopDataFormat::NonFinalNode {
+						short_channel_id: last_short_channel_id,
+					}
+				},
+				amt_to_forward: value_msat,
+				outgoing_cltv_value: cltv,
+			});
+		}
 		cur_value_msat += hop.fee_msat;
 		if cur_value_msat >= 21000000 * 100000000 * 1000 {
 			return Err(APIError::InvalidRoute{err: "Channel fees overflowed?".to_owned()});
@@ -218,9 +268,19 @@ pub(super) fn construct_onion_packet(payloads: Vec<msgs::OnionHopData>, onion_ke
 		payloads, onion_keys, FixedSizeOnionPacket(packet_data), Some(associated_data))
 }
 
-#[cfg(test)]
-/// Used in testing to write bogus `BogusOnionHopData` as well as `RawOnionHopData`, which is
-/// otherwise not representable in `msgs::OnionHopData`.
+/// A serialized per-hop onion payload, including its `BigSize` length prefix, which is written
+/// as-is when constructing an onion packet.
+pub(super) struct RawHopPayload<'a>(pub(super) &'a [u8]);
+
+impl<'a> Writeable for RawHopPayload<'a> {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), crate::io::Error> {
+		writer.write_all(self.0)
+	}
+}
+
+/// Used to write caller-provided `RawHopPayload`s, as well as, in testing, bogus
+/// `BogusOnionHopData` and `RawOnionHopData`, which are otherwise not representable in
+/// `msgs::OnionHopData`.
 pub(super) fn construct_onion_packet_with_writable_hopdata<HD: Writeable>(payloads: Vec<HD>, onion_keys: Vec<OnionKeys>, prng_seed: [u8; 32], associated_data: &PaymentHash) -> Result<msgs::OnionPacket, ()> {
 	let mut packet_data = [0; ONION_DATA_LEN];
 
@@ -325,6 +385,109 @@ fn construct_onion_packet_with_init_noise<HD: Writeable, P: Packet>(
 	Ok(P::new(onion_keys.first().unwrap().ephemeral_pubkey, packet_data, hmac_res))
 }
 
+/// The units of the hold times in [`msgs::AttributionData`], in milliseconds.
+const HOLD_TIME_UNIT_MS: u64 = 100;
+
+impl msgs::AttributionData {
+	/// Gets the offset of the HMAC provided by the hop `hop_distance` hops downstream of the
+	/// most recent hop, for the most recent hop being at `position` in the path.
+	fn hmac_offset(hop_distance: usize, position: usize) -> usize {
+		debug_assert!(hop_distance + position < MAX_ATTRIBUTABLE_HOPS);
+		// The hop `hop_distance` hops downstream provides `MAX_ATTRIBUTABLE_HOPS - hop_distance`
+		// HMACs, and each hop before it one more.
+		let hop_start = hop_distance * (2 * MAX_ATTRIBUTABLE_HOPS + 1 - hop_distance) / 2;
+		(hop_start + position) * ATTRIBUTION_HMAC_LEN
+	}
+
+	/// Computes the HMAC the most recent hop provides for being at `position` in the path,
+	/// committing to the failure message, its own hold time and those of up to as many downstream
+	/// hops as the path may fit, and the HMACs those hops provided for their resulting positions.
+	fn compute_hmac(&self, um: &[u8; 32], message: &[u8], position: usize) -> [u8; ATTRIBUTION_HMAC_LEN] {
+		let hop_count = MAX_ATTRIBUTABLE_HOPS - position;
+		let mut hmac = HmacEngine::<Sha256>::new(um);
+		hmac.input(message);
+		hmac.input(&self.hold_times[..hop_count * ATTRIBUTION_HOLD_TIME_LEN]);
+		for hop_distance in 1..hop_count {
+			let offset = Self::hmac_offset(hop_distance, position);
+			hmac.input(&self.hmacs[offset..offset + ATTRIBUTION_HMAC_LEN]);
+		}
+		let mut res = [0; ATTRIBUTION_HMAC_LEN];
+		res.copy_from_slice(&Hmac::from_engine(hmac).into_inner()[..ATTRIBUTION_HMAC_LEN]);
+		res
+	}
+
+	/// Makes room for the hold time and HMACs of a new hop, pruning the HMACs each downstream hop
+	/// provided for the position the new hop would take.
+	fn shift_right(&mut self) {
+		self.hold_times.copy_within(..(MAX_ATTRIBUTABLE_HOPS - 1) * ATTRIBUTION_HOLD_TIME_LEN, ATTRIBUTION_HOLD_TIME_LEN);
+		for hop_distance in (0..MAX_ATTRIBUTABLE_HOPS - 1).rev() {
+			let src = Self::hmac_offset(hop_distance, 1);
+			let len = (MAX_ATTRIBUTABLE_HOPS - 1 - hop_distance) * ATTRIBUTION_HMAC_LEN;
+			self.hmacs.copy_within(src..src + len, Self::hmac_offset(hop_distance + 1, 0));
+		}
+	}
+
+	/// Reverts [`Self::shift_right`], revealing the attribution data as provided by the next hop
+	/// with its pruned hold time and HMACs zeroed.
+	fn shift_left(&mut self) {
+		self.hold_times.copy_within(ATTRIBUTION_HOLD_TIME_LEN.., 0);
+		for byte in self.hold_times[(MAX_ATTRIBUTABLE_HOPS - 1) * ATTRIBUTION_HOLD_TIME_LEN..].iter_mut() {
+			*byte = 0;
+		}
+		for hop_distance in 0..MAX_ATTRIBUTABLE_HOPS {
+			let dst = Self::hmac_offset(hop_distance, 0);
+			if hop_distance < MAX_ATTRIBUTABLE_HOPS - 1 {
+				let src = Self::hmac_offset(hop_distance + 1, 0);
+				let len = (MAX_ATTRIBUTABLE_HOPS - 1 - hop_distance) * ATTRIBUTION_HMAC_LEN;
+				self.hmacs.copy_within(src..src + len, dst + ATTRIBUTION_HMAC_LEN);
+			}
+			for byte in self.hmacs[dst..dst + ATTRIBUTION_HMAC_LEN].iter_mut() {
+				*byte = 0;
+			}
+		}
+	}
+
+	fn crypt(&mut self, shared_secret: &[u8]) {
+		let ammag_ext = gen_ammag_ext_from_shared_secret(shared_secret);
+		let mut chacha = ChaCha20::new(&ammag_ext, &[0u8; 8]);
+		chacha.process_in_place(&mut self.hold_times);
+		chacha.process_in_place(&mut self.hmacs);
+	}
+
+	/// Adds the hold time and HMACs of a hop originating or relaying the failure `message`, i.e.
+	/// the failure packet prior to the hop encrypting it, then encrypts the attribution data with
+	/// the hop's `shared_secret`.
+	///
+	/// As hops do not know their position in the path, an HMAC is provided for each position.
+	fn add_hop(&mut self, shared_secret: &[u8], message: &[u8], hold_time: u32) {
+		let um = gen_um_from_shared_secret(shared_secret);
+		self.shift_right();
+		self.hold_times[..ATTRIBUTION_HOLD_TIME_LEN].copy_from_slice(&hold_time.to_be_bytes());
+		for position in 0..MAX_ATTRIBUTABLE_HOPS {
+			let hmac = self.compute_hmac(&um, message, position);
+			let offset = Self::hmac_offset(0, position);
+			self.hmacs[offset..offset + ATTRIBUTION_HMAC_LEN].copy_from_slice(&hmac);
+		}
+		self.crypt(shared_secret);
+	}
+
+	/// Decrypts the attribution data added by the hop at `position` in the path, returning its
+	/// hold time if its HMAC over the failure `message` it received is valid, in which case the
+	/// attribution data is left as provided by the next hop.
+	fn remove_hop(&mut self, shared_secret: &[u8], message: &[u8], position: usize) -> Option<u32> {
+		if position >= MAX_ATTRIBUTABLE_HOPS { return None; }
+		self.crypt(shared_secret);
+		let um = gen_um_from_shared_secret(shared_secret);
+		let offset = Self::hmac_offset(0, position);
+		if !fixed_time_eq(&self.compute_hmac(&um, message, position), &self.hmacs[offset..offset + ATTRIBUTION_HMAC_LEN]) {
+			return None;
+		}
+		let hold_time = u32::from_be_bytes(self.hold_times[..ATTRIBUTION_HOLD_TIME_LEN].try_into().expect("len is 4"));
+		self.shift_left();
+		Some(hold_time)
+	}
+}
+
 /// Encrypts a failure packet. raw_packet can either be a
 /// msgs::DecodedOnionErrorPacket.encode() result or a msgs::OnionErrorPacket.data element.
 pub(super) fn encrypt_failure_packet(shared_secret: &[u8], raw_packet: &[u8]) -> msgs::OnionErrorPacket {
@@ -336,9 +499,24 @@ pub(super) fn encrypt_failure_packet(shared_secret: &[u8], raw_packet: &[u8]) ->
 	chacha.process(&raw_packet, &mut packet_crypted[..]);
 	msgs::OnionErrorPacket {
 		data: packet_crypted,
+		attribution_data: None,
 	}
 }
 
+/// Encrypts a failure packet as [`encrypt_failure_packet`] does, first adding our hold time, in
+/// milliseconds, and HMACs to its attribution data, if any, or new attribution data otherwise.
+pub(super) fn encrypt_attributable_failure_packet(
+	shared_secret: &[u8], raw_packet: &[u8], attribution_data: Option<&msgs::AttributionData>,
+	hold_time_ms: u64
+) -> msgs::OnionErrorPacket {
+	let mut attribution_data = attribution_data.cloned().unwrap_or_else(msgs::AttributionData::new);
+	let hold_time = cmp::min(hold_time_ms / HOLD_TIME_UNIT_MS, u32::max_value() as u64) as u32;
+	attribution_data.add_hop(shared_secret, raw_packet, hold_time);
+	let mut packet = encrypt_failure_packet(shared_secret, raw_packet);
+	packet.attribution_data = Some(attribution_data);
+	packet
+}
+
 pub(super) fn build_failure_packet(shared_secret: &[u8], failure_type: u16, failure_data: &[u8]) -> msgs::DecodedOnionErrorPacket {
 	assert_eq!(shared_secret.len(), 32);
 	assert!(failure_data.len() <= 256 - 2);
@@ -376,22 +554,50 @@ pub(super) fn build_first_hop_failure_packet(shared_secret: &[u8], failure_type:
 	encrypt_failure_packet(shared_secret, &failure_packet.encode()[..])
 }
 
+/// The failure code returned by nodes within a blinded path, as they must not reveal the actual
+/// reason for failing an HTLC.
+pub(super) const INVALID_ONION_BLINDING: u16 = 0x8000 | 0x4000 | 24;
+
+/// The result of decoding the failure of an HTLC we sent.
+pub(super) struct DecodedOnionFailure {
+	pub(super) network_update: Option<NetworkUpdate>,
+	pub(super) short_channel_id: Option<u64>,
+	pub(super) payment_retryable: bool,
+	pub(super) failure_reason: Option<FailureReason>,
+	pub(super) failing_node_id: Option<NodeId>,
+	pub(super) hold_times: Vec<u32>,
+	#[cfg(test)]
+	pub(super) onion_error_code: Option<u16>,
+	#[cfg(test)]
+	pub(super) onion_error_data: Option<Vec<u8>>,
+}
+
 /// Process failure we got back from upstream on a payment we sent (implying htlc_source is an
 /// OutboundRoute).
-/// Returns update, a boolean indicating that the payment itself failed, the short channel id of
-/// the responsible channel, and the error code.
+/// Returns a [`DecodedOnionFailure`] holding the update, whether the payment itself failed, the
+/// short channel id of the responsible channel, the error code, and the hold times, in
+/// milliseconds, of each hop which provided valid attribution data.
 #[inline]
-pub(super) fn process_onion_failure<T: secp256k1::Signing, L: Deref>(secp_ctx: &Secp256k1<T>, logger: &L, htlc_source: &HTLCSource, mut packet_decrypted: Vec<u8>) -> (Option<NetworkUpdate>, Option<u64>, bool, Option<u16>, Option<Vec<u8>>) where L::Target: Logger {
+pub(super) fn process_onion_failure<T: secp256k1::Signing, L: Deref>(secp_ctx: &Secp256k1<T>, logger: &L, htlc_source: &HTLCSource, mut packet_decrypted: Vec<u8>, mut attribution_data: Option<msgs::AttributionData>) -> DecodedOnionFailure where L::Target: Logger {
 	if let &HTLCSource::OutboundRoute { ref path, ref session_priv, ref first_hop_htlc_msat, .. } = htlc_source {
 		let mut res = None;
 		let mut htlc_msat = *first_hop_htlc_msat;
 		let mut error_code_ret = None;
+		#[cfg(test)]
 		let mut error_packet_ret = None;
+		let mut failing_node_ret = None;
 		let mut is_from_final_node = false;
+		let mut hold_times_ms = Vec::new();
+		let mut invalid_attribution_hop_idx = None;
 
 		// Handle packed channel/node updates for passing back for the route handler
-		construct_onion_keys_callback(secp_ctx, &path.hops, session_priv, |shared_secret, _, _, route_hop, route_hop_idx| {
+		construct_onion_keys_callback(secp_ctx, &path, session_priv, |shared_secret, _, _, route_hop_opt, route_hop_idx| {
 			if res.is_some() { return; }
+			// Failures from within a blinded path are returned by its introduction node.
+			let route_hop = match route_hop_opt {
+				Some(route_hop) => route_hop,
+				None => return,
+			};
 
 			let amt_to_forward = htlc_msat - route_hop.fee_msat;
 			htlc_msat = amt_to_forward;
@@ -404,6 +610,17 @@ pub(super) fn process_onion_failure<T: secp256k1::Signing, L: Deref>(secp_ctx: &
 			chacha.process(&packet_decrypted, &mut decryption_tmp[..]);
 			packet_decrypted = decryption_tmp;
 
+			// Once a hop provides invalid attribution data, that of any downstream hops is
+			// meaningless.
+			if let Some(mut data) = attribution_data.take() {
+				if let Some(hold_time) = data.remove_hop(shared_secret.as_ref(), &packet_decrypted, route_hop_idx) {
+					hold_times_ms.push(hold_time.saturating_mul(HOLD_TIME_UNIT_MS as u32));
+					attribution_data = Some(data);
+				} else {
+					invalid_attribution_hop_idx = Some(route_hop_idx);
+				}
+			}
+
 			// The failing hop includes either the inbound channel to the recipient or the outbound
 			// channel from the current hop (i.e., the next hop's inbound channel).
 			is_from_final_node = route_hop_idx + 1 == path.hops.len();
@@ -415,6 +632,7 @@ pub(super) fn process_onion_failure<T: secp256k1::Signing, L: Deref>(secp_ctx: &
 				hmac.input(&err_packet.encode()[32..]);
 
 				if fixed_time_eq(&Hmac::from_engine(hmac).into_inner(), &err_packet.hmac) {
+					failing_node_ret = Some(NodeId::from_pubkey(&route_hop.pubkey));
 					if let Some(error_code_slice) = err_packet.failuremsg.get(0..2) {
 						const BADONION: u16 = 0x8000;
 						const PERM: u16 = 0x4000;
@@ -423,7 +641,8 @@ pub(super) fn process_onion_failure<T: secp256k1::Signing, L: Deref>(secp_ctx: &
 
 						let error_code = u16::from_be_bytes(error_code_slice.try_into().expect("len is 2"));
 						error_code_ret = Some(error_code);
-						error_packet_ret = Some(err_packet.failuremsg[2..].to_vec());
+						#[cfg(test)]
+						{ error_packet_ret = Some(err_packet.failuremsg[2..].to_vec()); }
 
 						let (debug_field, debug_field_size) = errors::get_onion_debug_field(error_code);
 
@@ -591,12 +810,41 @@ pub(super) fn process_onion_failure<T: secp256k1::Signing, L: Deref>(secp_ctx: &
 				}
 			}
 		}).expect("Route that we sent via spontaneously grew invalid keys in the middle of it?");
-		if let Some((channel_update, short_channel_id, payment_retryable)) = res {
-			(channel_update, short_channel_id, payment_retryable, error_code_ret, error_packet_ret)
+		let failure_reason = error_code_ret.map(FailureReason::from);
+		if let Some((network_update, short_channel_id, payment_retryable)) = res {
+			DecodedOnionFailure {
+				network_update, short_channel_id, payment_retryable, failure_reason,
+				failing_node_id: failing_node_ret, hold_times: hold_times_ms,
+				#[cfg(test)]
+				onion_error_code: error_code_ret,
+				#[cfg(test)]
+				onion_error_data: error_packet_ret,
+			}
+		} else if let Some(hop_idx) = invalid_attribution_hop_idx {
+			// The failure was corrupted, but either the first hop with invalid attribution data or
+			// the hop before it must have corrupted it, so blame the channel between them.
+			let route_hop = &path.hops[hop_idx];
+			log_info!(logger, "Onion Error[from {}] could not be decoded, but attribution data blames channel {}", route_hop.pubkey, route_hop.short_channel_id);
+			DecodedOnionFailure {
+				network_update: None, short_channel_id: Some(route_hop.short_channel_id),
+				payment_retryable: hop_idx + 1 != path.hops.len(), failure_reason: None,
+				failing_node_id: None, hold_times: hold_times_ms,
+				#[cfg(test)]
+				onion_error_code: None,
+				#[cfg(test)]
+				onion_error_data: None,
+			}
 		} else {
 			// only not set either packet unparseable or hmac does not match with any
 			// payment not retryable only when garbage is from the final node
-			(None, None, !is_from_final_node, None, None)
+			DecodedOnionFailure {
+				network_update: None, short_channel_id: None, payment_retryable: !is_from_final_node,
+				failure_reason: None, failing_node_id: None, hold_times: hold_times_ms,
+				#[cfg(test)]
+				onion_error_code: None,
+				#[cfg(test)]
+				onion_error_data: None,
+			}
 		}
 	} else { unreachable!(); }
 }
@@ -696,36 +944,49 @@ impl HTLCFailReason {
 		Self::reason(failure_code, Vec::new())
 	}
 
+	/// Returns the failure code of the failure, if it was generated locally rather than received
+	/// (encrypted) from a downstream node.
+	pub(super) fn failure_code(&self) -> Option<u16> {
+		match self.0 {
+			HTLCFailReasonRepr::Reason { failure_code, .. } => Some(failure_code),
+			HTLCFailReasonRepr::LightningError { .. } => None,
+		}
+	}
+
 	pub(super) fn from_msg(msg: &msgs::UpdateFailHTLC) -> Self {
 		Self(HTLCFailReasonRepr::LightningError { err: msg.reason.clone() })
 	}
 
-	pub(super) fn get_encrypted_failure_packet(&self, incoming_packet_shared_secret: &[u8; 32], phantom_shared_secret: &Option<[u8; 32]>)
+	/// Encrypts the failure for the previous hop, attributing it to us having held the HTLC for
+	/// `hold_time_ms`.
+	pub(super) fn get_encrypted_failure_packet(&self, incoming_packet_shared_secret: &[u8; 32], phantom_shared_secret: &Option<[u8; 32]>, hold_time_ms: u64)
 	-> msgs::OnionErrorPacket {
 		match self.0 {
 			HTLCFailReasonRepr::Reason { ref failure_code, ref data } => {
 				if let Some(phantom_ss) = phantom_shared_secret {
 					let phantom_packet = build_failure_packet(phantom_ss, *failure_code, &data[..]).encode();
-					let encrypted_phantom_packet = encrypt_failure_packet(phantom_ss, &phantom_packet);
-					encrypt_failure_packet(incoming_packet_shared_secret, &encrypted_phantom_packet.data[..])
+					let encrypted_phantom_packet = encrypt_attributable_failure_packet(phantom_ss, &phantom_packet, None, 0);
+					encrypt_attributable_failure_packet(incoming_packet_shared_secret, &encrypted_phantom_packet.data[..],
+						encrypted_phantom_packet.attribution_data.as_ref(), hold_time_ms)
 				} else {
 					let packet = build_failure_packet(incoming_packet_shared_secret, *failure_code, &data[..]).encode();
-					encrypt_failure_packet(incoming_packet_shared_secret, &packet)
+					encrypt_attributable_failure_packet(incoming_packet_shared_secret, &packet, None, hold_time_ms)
 				}
 			},
 			HTLCFailReasonRepr::LightningError { ref err } => {
-				encrypt_failure_packet(incoming_packet_shared_secret, &err.data)
+				encrypt_attributable_failure_packet(incoming_packet_shared_secret, &err.data,
+					err.attribution_data.as_ref(), hold_time_ms)
 			}
 		}
 	}
 
 	pub(super) fn decode_onion_failure<T: secp256k1::Signing, L: Deref>(
 		&self, secp_ctx: &Secp256k1<T>, logger: &L, htlc_source: &HTLCSource
-	) -> (Option<NetworkUpdate>, Option<u64>, bool, Option<u16>, Option<Vec<u8>>)
+	) -> DecodedOnionFailure
 	where L::Target: Logger {
 		match self.0 {
 			HTLCFailReasonRepr::LightningError { ref err } => {
-				process_onion_failure(secp_ctx, logger, &htlc_source, err.data.clone())
+				process_onion_failure(secp_ctx, logger, &htlc_source, err.data.clone(), err.attribution_data.clone())
 			},
 			HTLCFailReasonRepr::Reason { ref failure_code, ref data, .. } => {
 				// we get a fail_malformed_htlc from the first hop
@@ -734,7 +995,15 @@ impl HTLCFailReason {
 				// generally ignores its view of our own channels as we provide them via
 				// ChannelDetails.
 				if let &HTLCSource::OutboundRoute { ref path, .. } = htlc_source {
-					(None, Some(path.hops[0].short_channel_id), true, Some(*failure_code), Some(data.clone()))
+					DecodedOnionFailure {
+						network_update: None, short_channel_id: Some(path.hops[0].short_channel_id),
+						payment_retryable: true, failure_reason: Some(FailureReason::from(*failure_code)),
+						failing_node_id: None, hold_times: Vec::new(),
+						#[cfg(test)]
+						onion_error_code: Some(*failure_code),
+						#[cfg(test)]
+						onion_error_data: Some(data.clone()),
+					}
 				} else { unreachable!(); }
 			}
 		}
@@ -889,9 +1158,11 @@ fn decode_next_hop<T, R: ReadableArgs<T>, N: NextPacketBytes>(shared_secret: [u8
 mod tests {
 	use crate::io;
 	use crate::prelude::*;
-	use crate::ln::PaymentHash;
+	use crate::blinded_path::BlindedHop;
+	use crate::ln::{PaymentHash, PaymentPreimage};
+	use crate::ln::channelmanager::RecipientOnionFields;
 	use crate::ln::features::{ChannelFeatures, NodeFeatures};
-	use crate::routing::router::{Path, Route, RouteHop};
+	use crate::routing::router::{BlindedTail, Path, Route, RouteHop};
 	use crate::ln::msgs;
 	use crate::util::ser::{Writeable, Writer, VecWriter};
 
@@ -1097,6 +1368,82 @@ mod tests {
 		assert_eq!(onion_packet_5.data, hex::decode("9c5add3963fc7f6ed7f148623c84134b5647e1306419dbe2174e523fa9e2fbed3a06a19f899145610741c83ad40b7712aefaddec8c6baf7325d92ea4ca4d1df8bce517f7e54554608bf2bd8071a4f52a7a2f7ffbb1413edad81eeea5785aa9d990f2865dc23b4bc3c301a94eec4eabebca66be5cf638f693ec256aec514620cc28ee4a94bd9565bc4d4962b9d3641d4278fb319ed2b84de5b665f307a2db0f7fbb757366067d88c50f7e829138fde4f78d39b5b5802f1b92a8a820865af5cc79f9f30bc3f461c66af95d13e5e1f0381c184572a91dee1c849048a647a1158cf884064deddbf1b0b88dfe2f791428d0ba0f6fb2f04e14081f69165ae66d9297c118f0907705c9c4954a199bae0bb96fad763d690e7daa6cfda59ba7f2c8d11448b604d12d").unwrap());
 	}
 
+	fn build_attributable_failure(onion_keys: &[OnionKeys], corrupting_hop: Option<usize>) -> msgs::OnionErrorPacket {
+		// Hop `idx` reports a hold time of `idx` units of 100ms.
+		let failing_hop = onion_keys.len() - 1;
+		let failure = super::build_failure_packet(onion_keys[failing_hop].shared_secret.as_ref(), 0x2002, &[0; 0]).encode();
+		let mut packet = super::encrypt_attributable_failure_packet(onion_keys[failing_hop].shared_secret.as_ref(),
+			&failure, None, failing_hop as u64 * 100);
+		for idx in (0..failing_hop).rev() {
+			if corrupting_hop == Some(idx + 1) { packet.data[0] ^= 1; }
+			packet = super::encrypt_attributable_failure_packet(onion_keys[idx].shared_secret.as_ref(),
+				&packet.data, packet.attribution_data.as_ref(), idx as u64 * 100);
+		}
+		packet
+	}
+
+	fn check_attributable_failure(onion_keys: &[OnionKeys], packet: msgs::OnionErrorPacket) -> (Vec<u32>, Option<usize>) {
+		let mut data = packet.data;
+		let mut attribution_data = packet.attribution_data.unwrap();
+		let mut hold_times = Vec::new();
+		for (idx, keys) in onion_keys.iter().enumerate() {
+			data = super::encrypt_failure_packet(keys.shared_secret.as_ref(), &data).data;
+			match attribution_data.remove_hop(keys.shared_secret.as_ref(), &data, idx) {
+				Some(hold_time) => hold_times.push(hold_time),
+				None => return (hold_times, Some(idx)),
+			}
+		}
+		(hold_times, None)
+	}
+
+	#[test]
+	fn attributable_failure_test_vectors() {
+		// Attribution data added on top of the Returning Errors test vectors from BOLT 4, with hop
+		// `idx` reporting a hold time of `idx + 1` units of 100ms. The expected values were
+		// computed by an implementation of bolts#1044 independent of this one, using the BOLT 4
+		// shared secrets, and the failure data must remain as given by the BOLT 4 vectors.
+		const EXPECTED_ATTRIBUTION_DATA: [&str; 5] = [
+			"d77d0715b5f71d1d1be56bd88b3bb7ebc1792bb739ea7ebc1bc3b031b8bc2df3a50e25aeb99f47d7f7ab39e24187d3f4df9c4333463b053832ee9ac07274a5261b8b2a01fc09ce9ea7cd04d7b585dfb865ca2086d513d7938226a7b5f5b07f764fa32b9aee263f54f9b7d3dc883175620d5d2a39583eada49b20d66468962c12865e4803836e76d8763d5551b3a30b8610843280f133e2c33bca424b3c501f0b7b3f2a951aba907c964c0f5d19a44e6d1d7279637321fa598adde927b3087d238f8b426ecde500d318617cdb7a56e6ce3520fc95be41a549973764e4dc483853ecc313947709f1b5199cb077d46e701fa633e11d3e13b03e9212c115ca6fa004b2f3dd912814693b705a561a06da54cdf603677a3abecdc22c7358c2de3cef771b366a568150aeecc86ad1990bb0f4e2865933b03ea0df87901bff467908273dc6cea31cbab0e2b8d398d10b001058c259ed221b7b55762f4c7e49c8c11a45a107b7a2c605c26dc5b0b10d719b1c844670102b2b6a36c43fe4753a78a483fc39166ae28420f112d50c10ee64ca69569a2f690712905236b7c2cb7ac8954f02922d2d918c56d42649261593c47b14b324a65038c3c5be8d3c403ce0c8f19299b1664bf077d7cf1636c4fb9685a8e58b7029fd0939fa07925a60bed339b23f973293598f595e75c8f9d455d7cebe4b5e23357c8bd47d66d6628b39427e37e0aecbabf46c11be6771f7136e108a143ae9bafba0fc47a51b6c7deef4cba54bae906398ee3162a41f2191ca386b628bde7e1dd63d1611aa01a95c456df337c763cb8c3a81a6013aa633739d8cd554c688102211725e6adad165adc1bcd429d020c51b4b25d2117e8bb27eb0cc7020f9070d4ad19ac31a76ebdf5f9246646aeadbfb9a3f1d75bd8237961e786302516a1a781780e8b73f58dc06f307e58bd0eb1d8f5c9111f01312974c1dc777a6a2d3834d8a2a40014e9818d0685cb3919f6b3b788ddc640b0ff9b1854d7098c7dd6f35196e902b26709640bc87935a3914869a807e8339281e9cedaaca99474c3e7bdd35050bb998ab4546f9900904e0e39135e861ff7862049269701081ebce32e4cca992c6967ff0fd239e38233eaf614af31e186635e9439ec5884d798f9174da6ff569d68ed5c092b78bd3f880f5e88a7a8ab36789e1b57b035fb6c32a6358f51f83e4e5f46220bcad072943df8bd9541a61b7dae8f30fa3dd5fb39b1fd9a0b8e802552b78d4ec306ecee15bfe6da14b29ba6d19ce5be4dd478bca74a52429cd5309d404655c3dec85c252",
+			"1571e10bb7f8aa9b8e7e99caaf9c892e106c817df1d8e3b7b0e39d1c48f631e473e17e205489dd7b3c634cac3be0825cbf01418cd46e83c24b8d9c207742db9a0f0e5bcd888086498159f08080ba7bf3dccf33b535ae958d7dad34c84b9ca08d77fb65c8bc290ff2c295dcfb894311da77df4c486247ee1eb2223d20e067e7cd0720debcfa2be4a57efe309ab08af35aae7d245697e55cde8c670878c565185f5cb191aa523f088cff45a0da0c6a84fbb15d4fc27c0c400a156223480d978543f57e528f97993943ec9a5b76a755b9fcb4b77dcd0cf9d23b058816f760423c7f683189a56688ceb61a0c11919c1dab8cbdcde7b527aca3f54bde651aa9f3f2178829cee3f1c0b9292758a40cc63bd998fcd0d3ed4bdcaf1023267b8f8e44130a63ad15f76145936552381eabb6d684c0a3af6ba8efcf207cebaea5b7acdbb63f8e7221102409d10c23f0514dc9f4d0efb2264161a193a999a23e992632710580a0d320f676d367b9190721194514457761af05207cdab2b6328b1b3767eacb36a7ef4f7bd2e16762d13df188e0898b7410f62459458712a44bf594ae662fd89eb300abb6952ff8ad40164f2bcd7f86db5c7650b654b79046de55d51aa8061ce35f867a3e8f5bf98ad920be827101c64fb871d86e53a4b3c0455bfac5784168218aa72cbee86d9c750a9fa63c363a8b43d7bf4b2762516706a306f0aa3be1ec788b5e13f8b24837e53ac414f211e11c7a093cd9653dfa5fba4e377c79adfa5e841e2ddb6afc054fc715c05ddc6c8fc3e1ee3406e1ffceb2df77dc2f02652614d1bfcfaddebaa53ba919c7051034e2c7b7cfaabdf89f26e7f8e3f956d205dfab747ad0cb505b85b54a68439621b25832cbc2898919d0cd7c0a64cfd235388982dd4dd68240cb668f57e1d2619a656ed326f8c92357ee0d9acead3c20008bc5f04ca8059b55d77861c6d04dfc57cfba57315075acbe1451c96cf28e1e328e142890248d18f53b5d3513ce574dea7156cf596fdb3d909095ec287651f9cf1bcdc791c5938a5dd9b47e84c004d24ab3ae74492c7e8dcc1da15f65324be2672947ec82074cac8ce2b925bc555facbbf1b55d63ea6fbea6a785c97d4caf2e1dad9551b7f66c31caae5ebc7c0047e892f201308fcf452c588be0e63d89152113d87bf0dbd01603b4cdc7f0b724b0714a9851887a01f709408882e18230fe810b9fafa58a666654576d8eba3005f07221f55a6193815a672e5db56204053bc4286fa3db38250396309fd28011b5708a26a2d76c4a333b69b6bfd272fb",
+			"34e34397b8621ec4f2b54dba6c14073e267324cd60b152bce76aec8729a6ddefb61bc263be4b57bd592aae604a32bea69afe6ef4a6b573c26b17d69381ec1fc9b5aa769d148f2f1f8b5377a73840bb6d98211cda2ae18440ea6b9faf20d83ed2bcb83b503da9706f855dabdbfd5ed242ea0384586a4c00e8d6bc56de1c9bf7f6263313ca9f93a3b465f8067a0f0ba6fd2656e95c2f93feb1bae8096bd7741d03547f72c14041a4be80dbca650fcac430b1416aa50959b6fbcf441d160dbbc78b7b9dc8ceb9689608f3d606d35e23ae1e10342f74f755e75ac957ae5549dd5ea53efe210c7c9297b2212ed8a5e3f8ecd585dbf1caa8783d14da900db6acfb8b6f4da71fa5ec3de7cae763f1e6d531b2d9a0c445cf44c57971802e1486bb928ad13fa4fc4b051313104355159ba4dec50591e637c7802beee2ee545bdd713208751added5fc0eb2bc89a5aa2decb18ee37dac39f22a33b60cc1a369d24de9f3d2d8b63c039e248806de4e36a47c7a0aed30edd30c3d62debdf1ad82bf7aedd7edec413850d91c261e12beec7ad1586a9ad25b2db62c58ca17119d61dcc4f3e5c4520c42a8e384a45d8659b338b3a08f9e123a1d3781f5fc97564ccff2c1d97f06fa0150cfa1e20eacabefb0c339ec109336d207cc63d9170752fc58314c43e6d4a528fd0975afa85f3aa186ff1b6b8cb12c97ed4ace295b0ef5f075f0217665b8bb180246b87982d10f43c9866b22878106f5214e99188781180478b07764a5e12876ddcb709e0a0a8dd42cf004c695c6fc1669a6fd0e4a1ca54b024d0d80eac492a9e5036501f36fb25b72a054189294955830e43c18e55668337c8c6733abb09fc2d4ade18d5a853a2b82f7b4d77151a64985004f1d9218f2945b63c56fdebd1e96a2a7e49fa70acb4c39873947b83c191c10e9a8f40f60f3ad5a2be47145c22ea59ed3f5f4e61cb069e875fb67142d281d784bf925cc286eacc2c43e94d08da4924b83e58dbf2e43fa625bdd620eba6d9ce960ff17d14ed1f2dbee7d08eceb540fdc75ff06dabc767267658fad8ce99e2a3236e46d2deedcb51c3c6f81589357edebac9772a70b3d910d83cd1b9ce6534a011e9fa557b891a23b5d88afcc0d9856c6dabeab25eea55e9a248182229e4927f268fe5431672fcce52f434ca3d27d1a2136bae5770bb36920df12fbc01d0e8165610efa04794f414c1417f1d4059435c5385bfe2de83ce0e238d6fd2dbd3c0487c69843298577bfa480fe2a16ab2a0e4bc712cd8b5a14871cda61c993b6835303d9043d7689a",
+			"74a4ea67339463642a2182738871b2ee724f31f531aa98d80f1c3043febca41d5ee52e8b1e127e61719a0d078db8909748d57839e58424b91f063c4fbc8a221bef261140e66a9b596ca6d420a973ad54f9cfc615573f52171bf61313ef1551bbf95c6c761ed20cd60ec126f96b4eed9a4fd7fbe3e678f23c17333f754a18110d226e481d3f717d04bec09a4fedd76d756092b3358e93a9dace9ffc5e9d3f9e2d8fc94aac3f836ad4063f340cc75347a8e96df60d0fb687a567d68acfa52d0ee423d2400a1ff09cf87e8cb30d5fb6f81746cf61615289383a900519fae24ebc4ddd8feb6ebb5aab3f3a4e3e82425298095b1dc226966cf224e2a41bcdc382940e52162ba26858448d41e0a4366ac65d0d99dc637445f9c5854f4d0aa32123f0bc1c9d6700b890acdbce15c8d8dd459dfbf0df5d911f7b1dc8bf1f78dc611cfea2c1d65d5e6726fe482e396fb9be370b8eb20c407a088043952b3d4846558618ca5ab9bed4219d76b3f05578e3b25e852af7b1920627b69452f8e2ab202ac98bd8439790764a40bf309ea2205c1632610956495720030a25dc7118e0c868fdfa78c3e9ecce58215579a0581b3bafdb7dbbe53be9e904567fdc0ce1236aab5d22f1ebc18997e3ea83d362d891e04c5785fd5238326f767bce499209f8db211a50e1402160486e98e7235cf397dbb9ae19fd9b79ef589c821c6f99f28be33452405a003b33f4540fe0a41dfcc286f4d7cc10b70552ba7850869abadcd4bb7f256823face853633d6e2a999ac9fcd259c71d08e266db5d744e1909a62c0db673745ad9585949d108ab96640d2bc27fb4acac7fa8b170a30055a5ede90e004df9a44bdc29aeb4a6bec1e85dde1de6aaf01c6a5d12405d0bec22f49026cb23264f8c04b8401d3c2ab6f2e109948b6193b3bec27adfe19fb8afb8a92364d6fc5b219e8737d583e7ff3a4bcb75d53edda3bf3f52896ac36d8a877ad9f296ea6c045603fc62ac4ae41272bde85ef7c3b3fd3538aacfd5b025fefbe277c2906821ecb20e6f75ea479fa3280f9100fb0089203455c56b6bc775e5c2f0f58c63edd63fa3eec0b40da4b276d0d41da2ec0ead865a98d12bc694e23d8eaadd2b4d0ee88e9570c88fb878930f492e036d27998d593e47763927ff7eb80b188864a3846dd2238f7f95f4090ed399ae95deaeb37abca1cf37c397cc12189affb42dca46b4ff6988eb8c060691d155302d448f50ff70a794d97c0408f8cee9385d6a71fa412e36edcb22dbf433db9db4779f27b682ee17fc05e70c8e794b9f7f6d1",
+			"84986c976d26bfd5bb2d34d3ec62cfdd63e0032bdb3d9d75f3e5d456f73dffa7e35aab1db4f1bd3b98ff585caf004f656c51037a3f4e810d275f3f6aea0c8e3a125ebee5f374b6440bcb9bb2955ebf7034f014124bb6cd15f79e3b8c60415903907acb66361b37a711dccbcb9d325abef87c6e06a0f6ddcb4bf9d1e11f907e6632937072cc740348e3caa01a630b44b0a6b3e96abbd4e8b3a50c9243f590b7e976db78d8a5503242c85f502d6cc0a8cc2969c78eb44ad456e23d71e645ea1d444375e50b6c4231a4447c0501346c3ba110ba216dd21eaec09ed00cc5fa169a8063c9bc74b86589842f5354199f8ac47fa908344c803b4b9743f943982429a8d1ad3b05b166a5dd82dad1dcffbeb4c8444ea16376c2c3d118c3c0b5de1a57c0ef6d0ba9059557b5f16de6a347f76a21aa0054dc99b988ffb2e8eac1a696b32a168ca1abef05b7fbebbfcc6d29ae7bdf0207fc472ac3ef1b3e400e1ef2897957d7a29a61db6983f9de7f482e80a977a5d7820c2d197e1d3a6c1fee01a0a38e2db3e7b81fffd7e52d955924b97cc67b21948a86950db6d7aa92b179dee2cb104dab60cc2c27c096927fb0e6af92586f7c73de244f81d1be20d8d57ae4a94dd6481954a9bd1b5cff4ab29ca221fa2bf9b28a362c9661206f896fc7cec563fb80aa5eaccb26c09fa4ef7a981e63028a9c4dac12f82ccb5bea090d56bbb1a4c431e315d9a169299224a8dbd099fb67ea61dfc604edf8a18ee742550b636836bb552dabb28820221bf8546331f32b0c143c1c89310c4fa2e1e0e895ce1a1eb0f43278fdb528131a3e32bfffe0c6de9006418f5309cba773ca38b6ad8507cc59445ccc0257506ebc16a4c01d4cd97e03fcf7a2049fea0db28447858f73b8e9fe98b391b136c9dc510288630a1f0af93b26a8891b857bfe4b818af99a1e011e6dbaa53982d29cf74ae7dffef45545279f19931708ed3eede5e82280eab908e8eb80abff3f1f023ab66869297b40da8496861dc455ac3abe1efa8a6f9e2c4eda48025d43a486a3f26f269743eaa30d6f0e1f48db6287751358a41f5b07aee0f098862e3493731fe2697acce734f004907c6f11eef189424fee52cd30ad708707eaf2e441f52bcf3d0c5440c1742458653c0c8a27b5ade784d9e09c8b47f1671901a29360e7e5e94946b9c75752a1a8d599d2a3e14ac81b84d42115cd688c8383a64fc6e7e1dc5568bb4837358ebe63207a4067af66b2027ad2ce8fb7ae3a452d40723a51fdf9f9c9913e8029a222cf81d12ad41e58860d75deb6de30ad",
+		];
+
+		let onion_keys = build_test_onion_keys();
+		let mut packet = msgs::OnionErrorPacket {
+			data: super::build_failure_packet(onion_keys[4].shared_secret.as_ref(), 0x2002, &[0; 0]).encode(),
+			attribution_data: None,
+		};
+		for (idx, expected) in (0..onion_keys.len()).rev().zip(EXPECTED_ATTRIBUTION_DATA.iter()) {
+			let shared_secret = onion_keys[idx].shared_secret.as_ref();
+			let legacy_packet = super::encrypt_failure_packet(shared_secret, &packet.data);
+			packet = super::encrypt_attributable_failure_packet(shared_secret, &packet.data,
+				packet.attribution_data.as_ref(), (idx as u64 + 1) * 100);
+			assert_eq!(packet.data, legacy_packet.data);
+			assert_eq!(packet.attribution_data.as_ref().unwrap().encode(), hex::decode(expected).unwrap());
+		}
+		assert_eq!(check_attributable_failure(&onion_keys, packet), (vec![1, 2, 3, 4, 5], None));
+	}
+
+	#[test]
+	fn attributable_failure_hold_times() {
+		let onion_keys = build_test_onion_keys();
+
+		let packet = build_attributable_failure(&onion_keys, None);
+		assert_eq!(check_attributable_failure(&onion_keys, packet), (vec![0, 1, 2, 3, 4], None));
+
+		// A hop tampering with the failure it relays is identified by the HMAC of the hop it
+		// received the failure from, while the hold times of the hops before it are still known.
+		let packet = build_attributable_failure(&onion_keys, Some(2));
+		assert_eq!(check_attributable_failure(&onion_keys, packet), (vec![0, 1], Some(2)));
+
+		// Corrupted attribution data is blamed on the first hop whose HMAC can't be verified.
+		let mut packet = build_attributable_failure(&onion_keys, None);
+		packet.attribution_data.as_mut().unwrap().hmacs[0] ^= 1;
+		assert_eq!(check_attributable_failure(&onion_keys, packet), (vec![], Some(0)));
+	}
+
 	struct RawOnionHopData {
 		data: Vec<u8>
 	}
@@ -1110,4 +1457,70 @@ mod tests {
 			writer.write_all(&self.data[..])
 		}
 	}
+
+	#[test]
+	fn blinded_keysend_payloads() {
+		// Test that the hops of a blinded tail are given their encrypted TLVs, with the keysend
+		// preimage provided only to the recipient.
+		let secp_ctx = Secp256k1::new();
+		let pubkey = |byte: u8| PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[byte; 32]).unwrap());
+		let path = Path {
+			hops: vec![
+				RouteHop {
+					pubkey: pubkey(1), channel_features: ChannelFeatures::empty(), node_features: NodeFeatures::empty(),
+					short_channel_id: 1, fee_msat: 100, cltv_expiry_delta: 40,
+				},
+				RouteHop {
+					pubkey: pubkey(2), channel_features: ChannelFeatures::empty(), node_features: NodeFeatures::empty(),
+					short_channel_id: 2, fee_msat: 200, cltv_expiry_delta: 60,
+				},
+			],
+			blinded_tail: Some(BlindedTail {
+				hops: vec![
+					BlindedHop { blinded_node_id: pubkey(2), encrypted_payload: vec![2; 32] },
+					BlindedHop { blinded_node_id: pubkey(3), encrypted_payload: vec![3; 32] },
+				],
+				blinding_point: pubkey(4),
+				excess_final_cltv_expiry_delta: 10,
+				final_value_msat: 1000,
+			}),
+		};
+		let keysend_preimage = Some(PaymentPreimage([42; 32]));
+
+		let (payloads, htlc_msat, htlc_cltv) = super::build_onion_payloads(&path, 1000,
+			RecipientOnionFields::spontaneous_empty(), 100, &keysend_preimage).unwrap();
+		assert_eq!(htlc_msat, 1300);
+		assert_eq!(htlc_cltv, 210);
+		assert_eq!(payloads.len(), 3);
+
+		match payloads[0].format {
+			msgs::OnionHopDataFormat::NonFinalNode { short_channel_id } => assert_eq!(short_channel_id, 2),
+			_ => panic!("Expected an unblinded forward to the introduction node"),
+		}
+		assert_eq!(payloads[0].amt_to_forward, 1200);
+		assert_eq!(payloads[0].outgoing_cltv_value, 170);
+		match payloads[1].format {
+			msgs::OnionHopDataFormat::BlindedNonFinalNode { ref encrypted_tlvs, intro_node_blinding_point } => {
+				assert_eq!(encrypted_tlvs, &vec![2; 32]);
+				assert_eq!(intro_node_blinding_point, Some(pubkey(4)));
+			},
+			_ => panic!("Expected a blinded forward from the introduction node"),
+		}
+		match payloads[2].format {
+			msgs::OnionHopDataFormat::BlindedFinalNode { total_msat, ref encrypted_tlvs, intro_node_blinding_point, keysend_preimage: preimage } => {
+				assert_eq!(total_msat, 1000);
+				assert_eq!(encrypted_tlvs, &vec![3; 32]);
+				assert!(intro_node_blinding_point.is_none());
+				assert_eq!(preimage, keysend_preimage);
+			},
+			_ => panic!("Expected a blinded payload for the recipient"),
+		}
+		assert_eq!(payloads[2].amt_to_forward, 1000);
+		assert_eq!(payloads[2].outgoing_cltv_value, 110);
+
+		// Onion keys are derived for each unblinded hop and each hop after the introduction node.
+		let onion_keys = super::construct_onion_keys(&secp_ctx, &path, &get_test_session_key()).unwrap();
+		assert_eq!(onion_keys.len(), 3);
+		assert!(super::construct_onion_packet(payloads, onion_keys, [0; 32], &PaymentHash([0; 32])).is_ok());
+	}
 }
@@ -11,26 +11,31 @@
 
 use bitcoin::hashes::Hash;
 use bitcoin::hashes::sha256::Hash as Sha256;
-use bitcoin::secp256k1::{self, Secp256k1, SecretKey};
+use bitcoin::secp256k1::{self, PublicKey, Secp256k1, SecretKey};
 
 use crate::sign::{EntropySource, NodeSigner, Recipient};
 use crate::events::{self, PaymentFailureReason};
 use crate::ln::{PaymentHash, PaymentPreimage, PaymentSecret};
 use crate::ln::channelmanager::{ChannelDetails, EventCompletionAction, HTLCSource, IDEMPOTENCY_TIMEOUT_TICKS, PaymentId};
-use crate::ln::onion_utils::HTLCFailReason;
-use crate::routing::router::{InFlightHtlcs, Path, PaymentParameters, Route, RouteParameters, Router};
+use crate::ln::msgs::{DecodeError, LightningError};
+use crate::ln::onion_utils::{DecodedOnionFailure, HTLCFailReason};
+use crate::offers::invoice_request::InvoiceRequest;
+use crate::offers::refund::Refund;
+use crate::routing::router::{InFlightHtlcs, Path, Payee, PaymentParameters, PaymentPrivacyLevel, Route, RouteParameters, Router};
+use crate::util::config::OutboundHtlcExposureConfig;
 use crate::util::errors::APIError;
 use crate::util::logger::Logger;
 use crate::util::time::Time;
 #[cfg(all(not(feature = "no-std"), test))]
 use crate::util::time::tests::SinceEpoch;
-use crate::util::ser::ReadableArgs;
+use crate::util::ser::{Readable, ReadableArgs, Writeable, Writer};
 
 use core::fmt::{self, Display, Formatter};
 use core::ops::Deref;
 
+use crate::io;
 use crate::prelude::*;
-use crate::sync::Mutex;
+use crate::sync::{Arc, Mutex};
 
 /// Stores the session_priv for each part of a payment that is still pending. For versions 0.0.102
 /// and later, also stores information for retrying the payment.
@@ -71,6 +76,8 @@ pub(crate) enum PendingOutboundPayment {
 		payment_hash: PaymentHash,
 		/// Will be `None` if the payment was serialized before 0.0.115.
 		reason: Option<PaymentFailureReason>,
+		/// The number of attempts made before the payment was abandoned. Not persisted.
+		attempts: Option<u32>,
 	},
 }
 
@@ -80,6 +87,16 @@ impl PendingOutboundPayment {
 			attempts.count += 1;
 		}
 	}
+	/// The number of attempts made to route this payment, counting the initial attempt and each
+	/// retry, if known.
+	pub(super) fn attempts(&self) -> Option<u32> {
+		match self {
+			PendingOutboundPayment::Retryable { attempts, .. } =>
+				Some(core::cmp::min(attempts.count.saturating_add(1), u32::max_value() as usize) as u32),
+			PendingOutboundPayment::Abandoned { attempts, .. } => *attempts,
+			_ => None,
+		}
+	}
 	fn is_auto_retryable_now(&self) -> bool {
 		match self {
 			PendingOutboundPayment::Retryable {
@@ -149,13 +166,15 @@ impl PendingOutboundPayment {
 	}
 
 	fn mark_abandoned(&mut self, reason: PaymentFailureReason) {
+		let attempts = self.attempts();
 		if let PendingOutboundPayment::Retryable { session_privs, payment_hash, .. } = self {
 			let mut our_session_privs = HashSet::new();
 			core::mem::swap(&mut our_session_privs, session_privs);
 			*self = PendingOutboundPayment::Abandoned {
 				session_privs: our_session_privs,
 				payment_hash: *payment_hash,
-				reason: Some(reason)
+				reason: Some(reason),
+				attempts,
 			};
 		}
 	}
@@ -247,6 +266,70 @@ impl Retry {
 	}
 }
 
+/// Overrides how routes are found for a single payment sent via
+/// [`ChannelManager::send_payment_with_route_override`], rather than using the [`ChannelManager`]'s
+/// own [`Router`].
+///
+/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+/// [`ChannelManager::send_payment_with_route_override`]: crate::ln::channelmanager::ChannelManager::send_payment_with_route_override
+#[derive(Clone)]
+pub enum RouteOverride {
+	/// Finds routes for the payment, including any retries of it, with the given [`Router`].
+	///
+	/// As a [`Router`] cannot be persisted, retries of the payment after a restart use the
+	/// [`ChannelManager`]'s own [`Router`].
+	///
+	/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+	Router(Arc<dyn Router + Send + Sync>),
+	/// Sends the payment's first attempt over the given [`Route`], with any retries of it using
+	/// routes found by the [`ChannelManager`]'s own [`Router`].
+	///
+	/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+	Route(Route),
+}
+
+/// A [`Router`] which finds routes for payments with a [`RouteOverride::Router`] using their
+/// override, and for all other payments using the wrapped default [`Router`].
+struct OverridingRouter<'a, R: Deref> where R::Target: Router {
+	router: &'a R,
+	overrides: &'a Mutex<HashMap<PaymentId, Arc<dyn Router + Send + Sync>>>,
+	/// The override of a payment which is being sent and thus not yet in `overrides`.
+	new_override: Option<(PaymentId, &'a Arc<dyn Router + Send + Sync>)>,
+}
+
+impl<'a, R: Deref> Router for OverridingRouter<'a, R> where R::Target: Router {
+	fn find_route(
+		&self, payer: &PublicKey, route_params: &RouteParameters,
+		first_hops: Option<&[&ChannelDetails]>, inflight_htlcs: &InFlightHtlcs
+	) -> Result<Route, LightningError> {
+		self.router.find_route(payer, route_params, first_hops, inflight_htlcs)
+	}
+
+	fn find_route_with_id(
+		&self, payer: &PublicKey, route_params: &RouteParameters,
+		first_hops: Option<&[&ChannelDetails]>, inflight_htlcs: &InFlightHtlcs,
+		payment_hash: PaymentHash, payment_id: PaymentId
+	) -> Result<Route, LightningError> {
+		// Don't hold the lock while the override is busy finding a route.
+		let route_override = self.overrides.lock().unwrap().get(&payment_id).cloned()
+			.or_else(|| match self.new_override {
+				Some((new_payment_id, router)) if new_payment_id == payment_id => Some(Arc::clone(router)),
+				_ => None,
+			});
+		match route_override {
+			Some(router) => router.find_route_with_id(payer, route_params, first_hops, inflight_htlcs, payment_hash, payment_id),
+			None => self.router.find_route_with_id(payer, route_params, first_hops, inflight_htlcs, payment_hash, payment_id),
+		}
+	}
+
+	fn find_routes(
+		&self, payer: &PublicKey, route_params: &[RouteParameters],
+		first_hops: Option<&[&ChannelDetails]>, inflight_htlcs: &InFlightHtlcs
+	) -> Vec<Result<Route, LightningError>> {
+		self.router.find_routes(payer, route_params, first_hops, inflight_htlcs)
+	}
+}
+
 #[cfg(feature = "std")]
 pub(super) fn has_expired(route_params: &RouteParameters) -> bool {
 	if let Some(expiry_time) = route_params.payment_params.expiry_time {
@@ -319,7 +402,11 @@ pub enum RetryableSendFailure {
 	///
 	/// [`PaymentParameters::expiry_time`]: crate::routing::router::PaymentParameters::expiry_time
 	PaymentExpired,
-	/// We were unable to find a route to the destination.
+	/// We were unable to find a route to the destination, or our view of the chain is too far
+	/// behind the chain tip to safely route the payment, see
+	/// [`ChannelManager::is_chain_sync_ready`].
+	///
+	/// [`ChannelManager::is_chain_sync_ready`]: crate::ln::channelmanager::ChannelManager::is_chain_sync_ready
 	RouteNotFound,
 	/// Indicates that a payment for the provided [`PaymentId`] is already in-flight and has not
 	/// yet completed (i.e. generated an [`Event::PaymentSent`] or [`Event::PaymentFailed`]).
@@ -328,6 +415,102 @@ pub enum RetryableSendFailure {
 	/// [`Event::PaymentSent`]: crate::events::Event::PaymentSent
 	/// [`Event::PaymentFailed`]: crate::events::Event::PaymentFailed
 	DuplicatePayment,
+	/// Sending the payment would exceed one of the limits on the HTLCs of our outbound payments in
+	/// flight at once given by [`UserConfig::outbound_htlc_exposure`]. It may be retried once some
+	/// of our pending payments have resolved.
+	///
+	/// [`UserConfig::outbound_htlc_exposure`]: crate::util::config::UserConfig::outbound_htlc_exposure
+	ExposureLimitReached,
+}
+
+/// Indicates that a batch of payments passed to
+/// [`ChannelManager::send_batch_payments_to_hashes`] was not sent at all.
+///
+/// [`ChannelManager::send_batch_payments_to_hashes`]: crate::ln::channelmanager::ChannelManager::send_batch_payments_to_hashes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BatchPaymentError {
+	/// The combined amount of the payments exceeds what we can currently send over our usable
+	/// channels.
+	InsufficientLiquidity {
+		/// The combined amount of the payments, excluding fees.
+		required_msat: u64,
+		/// The sum of [`ChannelDetails::next_outbound_htlc_limit_msat`] over our usable channels.
+		///
+		/// [`ChannelDetails::next_outbound_htlc_limit_msat`]: crate::ln::channelmanager::ChannelDetails::next_outbound_htlc_limit_msat
+		available_msat: u64,
+	},
+	/// The routes found for the payments would pay more in fees than the batch's fee budget.
+	FeeBudgetExceeded {
+		/// The combined fees of the routes found for the payments.
+		total_fee_msat: u64,
+		/// The fee budget the batch was sent with.
+		max_total_routing_fee_msat: u64,
+	},
+}
+
+/// The routing fee budget shared by the payments of a batch sent via
+/// [`OutboundPayments::send_payment_batch`], which also bounds the fees of their retries.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct BatchFeeBudget {
+	payment_ids: Vec<PaymentId>,
+	max_total_routing_fee_msat: u64,
+	/// The fees paid by the payments of the batch which succeeded.
+	paid_fee_msat: u64,
+}
+
+impl_writeable_tlv_based!(BatchFeeBudget, {
+	(0, payment_ids, vec_type),
+	(2, max_total_routing_fee_msat, required),
+	(4, paid_fee_msat, required),
+});
+
+/// A payment sent as part of a batch via [`OutboundPayments::send_payment_batch`].
+pub(super) struct BatchedPayment {
+	pub(super) payment_id: PaymentId,
+	pub(super) payment_hash: PaymentHash,
+	pub(super) recipient_onion: RecipientOnionFields,
+	pub(super) keysend_preimage: Option<PaymentPreimage>,
+	pub(super) route_params: RouteParameters,
+}
+
+/// The [`InvoiceRequest`] or [`Refund`] we created as the payer, which a BOLT 12 [`Invoice`] must
+/// have been created in response to for [`ChannelManager::send_payment_for_bolt12_invoice`] to
+/// pay it.
+///
+/// [`Invoice`]: crate::offers::invoice::Invoice
+/// [`ChannelManager::send_payment_for_bolt12_invoice`]: crate::ln::channelmanager::ChannelManager::send_payment_for_bolt12_invoice
+#[derive(Clone, Copy, Debug)]
+pub enum Bolt12PayerContext<'a> {
+	/// The invoice is for an [`Offer`] we requested an invoice for.
+	///
+	/// [`Offer`]: crate::offers::offer::Offer
+	InvoiceRequest(&'a InvoiceRequest),
+	/// The invoice is for a [`Refund`] we created.
+	Refund(&'a Refund),
+}
+
+/// Indicates an immediate error on [`ChannelManager::send_payment_for_bolt12_invoice`] or
+/// [`ChannelManager::approve_bolt12_payment`].
+///
+/// [`Invoice`]: crate::offers::invoice::Invoice
+/// [`ChannelManager::send_payment_for_bolt12_invoice`]: crate::ln::channelmanager::ChannelManager::send_payment_for_bolt12_invoice
+/// [`ChannelManager::approve_bolt12_payment`]: crate::ln::channelmanager::ChannelManager::approve_bolt12_payment
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Bolt12PaymentError {
+	/// The [`Invoice`] was not created in response to the given [`Bolt12PayerContext`].
+	UnexpectedInvoice,
+	/// The [`Invoice`] is for an amount other than the one given in the [`InvoiceRequest`] or
+	/// [`Refund`], or, if the [`InvoiceRequest`] has no amount, other than the offer's amount for
+	/// the requested quantity.
+	UnexpectedAmount,
+	/// The [`Invoice`] was verified but sending the payment failed.
+	SendingFailed(RetryableSendFailure),
+	/// No [`Invoice`] is awaiting approval for the [`PaymentId`] passed to
+	/// [`ChannelManager::approve_bolt12_payment`].
+	///
+	/// [`PaymentId`]: crate::ln::channelmanager::PaymentId
+	/// [`ChannelManager::approve_bolt12_payment`]: crate::ln::channelmanager::ChannelManager::approve_bolt12_payment
+	NotAwaitingApproval,
 }
 
 /// If a payment fails to send with [`ChannelManager::send_payment_with_route`], it can be in one
@@ -473,19 +656,435 @@ impl RecipientOnionFields {
 	}
 }
 
+/// A caller-constructed onion for [`ChannelManager::send_payment_with_custom_onion`].
+///
+/// [`ChannelManager::send_payment_with_custom_onion`]: crate::ln::channelmanager::ChannelManager::send_payment_with_custom_onion
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CustomOnion {
+	/// Serialized payloads for each hop in the path, in order, each including its `BigSize`
+	/// length prefix. For paths with a blinded tail, this includes a payload for each hop within
+	/// the blinded path after its introduction node. The onion packet is constructed from these
+	/// using a fresh session key.
+	HopPayloads(Vec<Vec<u8>>),
+	/// A fully constructed, serialized onion packet.
+	Packet {
+		/// The serialized onion packet, which must be exactly 1366 bytes long.
+		packet: Vec<u8>,
+		/// The session key the packet was constructed with, which is used to decrypt any failure
+		/// returned along the path.
+		session_priv: SecretKey,
+	},
+}
+
+/// The terminal status of an outbound payment which is no longer pending, as returned by
+/// [`ChannelManager::resolved_payment_status`].
+///
+/// [`ChannelManager::resolved_payment_status`]: crate::ln::channelmanager::ChannelManager::resolved_payment_status
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResolvedPaymentStatus {
+	/// The payment was fulfilled, i.e. an [`Event::PaymentSent`] was (or will be) generated for it.
+	///
+	/// [`Event::PaymentSent`]: crate::events::Event::PaymentSent
+	Fulfilled {
+		/// Hash of the payment that was claimed. `None` for serializations of [`ChannelManager`]
+		/// made before LDK version 0.0.104.
+		///
+		/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+		payment_hash: Option<PaymentHash>,
+	},
+	/// The payment failed, i.e. an [`Event::PaymentFailed`] was generated for it.
+	///
+	/// [`Event::PaymentFailed`]: crate::events::Event::PaymentFailed
+	Abandoned {
+		/// Hash of the payment that failed.
+		payment_hash: PaymentHash,
+		/// The reason the payment failed. Will be `None` if the payment failed on a version of LDK
+		/// which did not track failure reasons.
+		reason: Option<PaymentFailureReason>,
+	},
+}
+
+impl_writeable_tlv_based_enum!(ResolvedPaymentStatus,
+	(0, Fulfilled) => {
+		(1, payment_hash, option),
+	},
+	(2, Abandoned) => {
+		(0, payment_hash, required),
+		(1, reason, option),
+	};
+);
+
+/// A [`PaymentId`] we remember after its payment was resolved, in order to reject later sends
+/// which reuse it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ResolvedPayment {
+	pub(crate) payment_id: PaymentId,
+	pub(crate) status: ResolvedPaymentStatus,
+}
+
+impl_writeable_tlv_based!(ResolvedPayment, {
+	(0, payment_id, required),
+	(2, status, required),
+});
+
+/// An entry in the timeline of an outbound payment, as returned by
+/// [`ChannelManager::payment_trace`].
+///
+/// [`ChannelManager::payment_trace`]: crate::ln::channelmanager::ChannelManager::payment_trace
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PaymentTraceEvent {
+	/// An `invoice_request` for the payment was sent to the offer's issuer via onion messages.
+	InvoiceRequested {
+		/// The number of `invoice_request`s sent for the payment so far, including this one.
+		attempt: u32,
+	},
+	/// A BOLT 12 invoice for the payment was received, see [`Event::Bolt12InvoiceReceived`].
+	///
+	/// [`Event::Bolt12InvoiceReceived`]: crate::events::Event::Bolt12InvoiceReceived
+	InvoiceReceived,
+	/// A route was found for (the remaining amount of) the payment.
+	RouteFound {
+		/// The number of routes looked up for the payment so far, including this one.
+		attempt: u32,
+		/// The number of paths in the route.
+		path_count: usize,
+		/// The amount the route delivers to the recipient, excluding fees.
+		amount_msat: u64,
+		/// The fees paid to intermediate nodes along the route.
+		fee_msat: u64,
+	},
+	/// No route could be found for (the remaining amount of) the payment.
+	RouteNotFound {
+		/// The number of routes looked up for the payment so far, including this one.
+		attempt: u32,
+	},
+	/// An HTLC was handed to our first-hop channel along the given path.
+	HTLCSent {
+		/// The path the HTLC was sent along.
+		path: Path,
+	},
+	/// An HTLC could not be sent along the given path, e.g. because our first-hop channel was
+	/// unavailable.
+	HTLCSendFailed {
+		/// The path the HTLC was to be sent along.
+		path: Path,
+		/// The error which prevented sending the HTLC.
+		err: APIError,
+	},
+	/// An HTLC along the given path failed, see [`Event::PaymentPathFailed`].
+	///
+	/// [`Event::PaymentPathFailed`]: crate::events::Event::PaymentPathFailed
+	HTLCFailed {
+		/// The path the HTLC was sent along.
+		path: Path,
+		/// The channel responsible for the failure, if known.
+		short_channel_id: Option<u64>,
+		/// Whether the recipient rejected the payment, in which case it won't be retried.
+		payment_failed_permanently: bool,
+	},
+	/// An HTLC along the given path was claimed by the recipient.
+	HTLCClaimed {
+		/// The path the HTLC was sent along.
+		path: Path,
+	},
+	/// The payment was fulfilled or has failed.
+	Resolved {
+		/// How the payment was resolved.
+		status: ResolvedPaymentStatus,
+	},
+}
+
+#[derive(Default)]
+struct PaymentTrace {
+	events: Vec<PaymentTraceEvent>,
+	route_attempts: u32,
+	invoice_requests: u32,
+}
+
+/// The maximum number of events kept in the timeline of a single payment. Beyond it, the oldest
+/// events are dropped, such that a payment retried many times can't grow its timeline unbounded.
+const MAX_PAYMENT_TRACE_EVENTS: usize = 64;
+
+/// The timelines of the most recently traced outbound payments, bounded by
+/// [`UserConfig::payment_trace_limit`].
+///
+/// [`UserConfig::payment_trace_limit`]: crate::util::config::UserConfig::payment_trace_limit
+pub(super) struct PaymentTraces {
+	limit: usize,
+	traces: HashMap<PaymentId, PaymentTrace>,
+	/// The payments in `traces`, oldest first.
+	order: VecDeque<PaymentId>,
+}
+
+impl PaymentTraces {
+	pub(super) fn new(limit: usize) -> Self {
+		Self { limit, traces: HashMap::new(), order: VecDeque::new() }
+	}
+
+	/// Starts tracing the given payment, dropping the oldest trace beyond the limit, unless it is
+	/// already traced or payments aren't traced.
+	fn start_trace(&mut self, payment_id: PaymentId) {
+		if self.limit == 0 || self.traces.contains_key(&payment_id) { return; }
+		if self.order.len() >= self.limit {
+			if let Some(oldest_payment_id) = self.order.pop_front() {
+				self.traces.remove(&oldest_payment_id);
+			}
+		}
+		self.order.push_back(payment_id);
+		self.traces.insert(payment_id, PaymentTrace::default());
+	}
+
+	/// Gets the existing trace of the given payment. Returns `None` if the payment isn't traced,
+	/// including if its trace has been dropped in favor of more recent payments.
+	fn trace_mut(&mut self, payment_id: PaymentId) -> Option<&mut PaymentTrace> {
+		self.traces.get_mut(&payment_id)
+	}
+}
+
+impl PaymentTrace {
+	fn push(&mut self, event: PaymentTraceEvent) {
+		if self.events.len() >= MAX_PAYMENT_TRACE_EVENTS {
+			self.events.remove(0);
+		}
+		self.events.push(event);
+	}
+}
+
+/// A part of a payment with [`PaymentPrivacyLevel::DecoyPartsAndTimingSpread`] which has not been
+/// sent yet, see [`OutboundPayments::send_staggered_parts`].
+///
+/// The part is already tracked as pending by its [`PendingOutboundPayment`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct StaggeredPaymentPart {
+	payment_id: PaymentId,
+	payment_hash: PaymentHash,
+	recipient_onion: RecipientOnionFields,
+	keysend_preimage: Option<PaymentPreimage>,
+	path: Path,
+	total_value_msat: u64,
+	session_priv: [u8; 32],
+}
+
+impl Writeable for StaggeredPaymentPart {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
+		write_tlv_fields!(writer, {
+			(0, self.payment_id, required),
+			(2, self.payment_hash, required),
+			(4, self.recipient_onion, required),
+			(5, self.keysend_preimage, option),
+			(6, self.path.hops, vec_type),
+			(7, self.path.blinded_tail, option),
+			(8, self.total_value_msat, required),
+			(12, self.session_priv, required),
+		});
+		Ok(())
+	}
+}
+
+impl Readable for StaggeredPaymentPart {
+	fn read<R: io::Read>(reader: &mut R) -> Result<Self, DecodeError> {
+		_init_and_read_tlv_fields!(reader, {
+			(0, payment_id, required),
+			(2, payment_hash, required),
+			(4, recipient_onion, required),
+			(5, keysend_preimage, option),
+			(6, path_hops, vec_type),
+			(7, blinded_tail, option),
+			(8, total_value_msat, required),
+			(12, session_priv, required),
+		});
+		let path = Path { hops: path_hops.ok_or(DecodeError::InvalidValue)?, blinded_tail };
+		if path.hops.is_empty() {
+			return Err(DecodeError::InvalidValue);
+		}
+		Ok(Self {
+			payment_id: payment_id.0.unwrap(),
+			payment_hash: payment_hash.0.unwrap(),
+			recipient_onion: recipient_onion.0.unwrap(),
+			keysend_preimage,
+			path,
+			total_value_msat: total_value_msat.0.unwrap(),
+			session_priv: session_priv.0.unwrap(),
+		})
+	}
+}
+
 pub(super) struct OutboundPayments {
 	pub(super) pending_outbound_payments: Mutex<HashMap<PaymentId, PendingOutboundPayment>>,
 	pub(super) retry_lock: Mutex<()>,
+	/// The most recently resolved payments, oldest first, bounded by
+	/// `resolved_payment_history_limit`.
+	pub(super) resolved_payments: Mutex<Vec<ResolvedPayment>>,
+	pub(super) resolved_payment_history_limit: usize,
+	/// The [`RouteOverride::Router`]s of pending payments, which are not persisted.
+	pub(super) route_overrides: Mutex<HashMap<PaymentId, Arc<dyn Router + Send + Sync>>>,
+	/// The parts of payments with [`PaymentPrivacyLevel::DecoyPartsAndTimingSpread`] which are
+	/// yet to be sent, oldest first.
+	pub(super) staggered_parts: Mutex<VecDeque<StaggeredPaymentPart>>,
+	pub(super) exposure_limits: OutboundHtlcExposureConfig,
+	/// The timelines of recently traced payments, which are not persisted.
+	pub(super) payment_traces: Mutex<PaymentTraces>,
+	/// The fee budgets of batches of payments which are still pending.
+	pub(super) batch_fee_budgets: Mutex<Vec<BatchFeeBudget>>,
+	/// The payments abandoned since this was last drained as no route was found when retrying
+	/// them, along with the onion fields and parameters of the failed retry.
+	pub(super) retries_without_route: Mutex<Vec<(PaymentId, PaymentHash, RecipientOnionFields, RouteParameters)>>,
+	/// The payments whose retry was deferred since the last [`Self::check_retry_payments`] as it
+	/// would have exceeded our [`OutboundHtlcExposureConfig`] limits.
+	pub(super) exposure_deferred_retries: Mutex<HashSet<PaymentId>>,
 }
 
 impl OutboundPayments {
-	pub(super) fn new() -> Self {
+	pub(super) fn new(
+		resolved_payment_history_limit: usize, exposure_limits: OutboundHtlcExposureConfig,
+		payment_trace_limit: usize
+	) -> Self {
 		Self {
 			pending_outbound_payments: Mutex::new(HashMap::new()),
 			retry_lock: Mutex::new(()),
+			resolved_payments: Mutex::new(Vec::new()),
+			resolved_payment_history_limit,
+			route_overrides: Mutex::new(HashMap::new()),
+			staggered_parts: Mutex::new(VecDeque::new()),
+			exposure_limits,
+			payment_traces: Mutex::new(PaymentTraces::new(payment_trace_limit)),
+			batch_fee_budgets: Mutex::new(Vec::new()),
+			retries_without_route: Mutex::new(Vec::new()),
+			exposure_deferred_retries: Mutex::new(HashSet::new()),
 		}
 	}
 
+	/// Returns how much of the fee budget of the batch the payment with the given id is a part of
+	/// is left, counting the fees of the HTLCs of the given pending `outbounds`, if it was sent as
+	/// part of a batch with a fee budget.
+	fn remaining_batch_fee_budget(
+		&self, outbounds: &HashMap<PaymentId, PendingOutboundPayment>, payment_id: PaymentId
+	) -> Option<u64> {
+		let batch_fee_budgets = self.batch_fee_budgets.lock().unwrap();
+		let budget = batch_fee_budgets.iter().find(|budget| budget.payment_ids.contains(&payment_id))?;
+		let pending_fee_msat = budget.payment_ids.iter()
+			.filter_map(|payment_id| outbounds.get(payment_id))
+			.filter_map(|payment| payment.get_pending_fee_msat())
+			.fold(0u64, |total, fee| total.saturating_add(fee));
+		Some(budget.max_total_routing_fee_msat
+			.saturating_sub(budget.paid_fee_msat)
+			.saturating_sub(pending_fee_msat))
+	}
+
+	/// Returns whether sending the given `route` for a payment with the given `payment_params`
+	/// would exceed our [`OutboundHtlcExposureConfig`] limits, counting the HTLCs of the given
+	/// pending `outbounds`.
+	fn exceeds_exposure_limits<'a, I: Iterator<Item = &'a PendingOutboundPayment>>(
+		&self, outbounds: I, payment_params: &PaymentParameters, route: &Route
+	) -> bool {
+		fn destination(payment_params: &PaymentParameters) -> Option<PublicKey> {
+			match payment_params.payee {
+				Payee::Clear { node_id, .. } => Some(node_id),
+				Payee::Blinded { .. } => None,
+			}
+		}
+		fn exceeds<T: PartialOrd>(value: T, limit: Option<T>) -> bool {
+			limit.map_or(false, |limit| value > limit)
+		}
+
+		let limits = &self.exposure_limits;
+		let destination_node_id = destination(payment_params);
+		let mut total_msat = route.get_total_amount().saturating_add(route.get_total_fees());
+		let mut total_htlcs = route.paths.len() as u32;
+		let (mut destination_msat, mut destination_htlcs) = (total_msat, total_htlcs);
+		for payment in outbounds {
+			if let PendingOutboundPayment::Retryable {
+				session_privs, pending_amt_msat, pending_fee_msat, payment_params, ..
+			} = payment {
+				let amount_msat = pending_amt_msat.saturating_add(pending_fee_msat.unwrap_or(0));
+				total_msat = total_msat.saturating_add(amount_msat);
+				total_htlcs = total_htlcs.saturating_add(session_privs.len() as u32);
+				if destination_node_id.is_some() && payment_params.as_ref().and_then(destination) == destination_node_id {
+					destination_msat = destination_msat.saturating_add(amount_msat);
+					destination_htlcs = destination_htlcs.saturating_add(session_privs.len() as u32);
+				}
+			}
+		}
+
+		exceeds(total_msat, limits.max_total_in_flight_msat) ||
+			exceeds(total_htlcs, limits.max_total_in_flight_htlcs) ||
+			(destination_node_id.is_some() && (
+				exceeds(destination_msat, limits.max_in_flight_msat_per_destination) ||
+				exceeds(destination_htlcs, limits.max_in_flight_htlcs_per_destination)
+			))
+	}
+
+	/// Remembers `payment_id` as resolved, evicting the oldest remembered payments beyond the
+	/// configured limit.
+	fn record_resolved_payment(&self, payment_id: PaymentId, status: ResolvedPaymentStatus) {
+		if let ResolvedPaymentStatus::Abandoned { .. } = status {
+			// Fulfilled payments are traced as resolved once their first HTLC is claimed.
+			self.trace_payment(payment_id, PaymentTraceEvent::Resolved { status });
+		}
+		if self.resolved_payment_history_limit == 0 { return; }
+		let mut resolved_payments = self.resolved_payments.lock().unwrap();
+		resolved_payments.push(ResolvedPayment { payment_id, status });
+		if resolved_payments.len() > self.resolved_payment_history_limit {
+			let excess = resolved_payments.len() - self.resolved_payment_history_limit;
+			resolved_payments.drain(..excess);
+		}
+	}
+
+	pub(super) fn resolved_payment_status(&self, payment_id: &PaymentId) -> Option<ResolvedPaymentStatus> {
+		match self.pending_outbound_payments.lock().unwrap().get(payment_id) {
+			Some(PendingOutboundPayment::Fulfilled { payment_hash, .. }) =>
+				return Some(ResolvedPaymentStatus::Fulfilled { payment_hash: *payment_hash }),
+			Some(_) => return None,
+			None => {},
+		}
+		self.resolved_payments.lock().unwrap().iter().rev()
+			.find(|resolved| resolved.payment_id == *payment_id)
+			.map(|resolved| resolved.status)
+	}
+
+	/// Starts the timeline of the given payment, if payments are traced. Events are only traced
+	/// for payments whose timeline was started.
+	pub(super) fn start_payment_trace(&self, payment_id: PaymentId) {
+		self.payment_traces.lock().unwrap().start_trace(payment_id);
+	}
+
+	/// Appends `event` to the timeline of the given payment, if it is traced.
+	pub(super) fn trace_payment(&self, payment_id: PaymentId, event: PaymentTraceEvent) {
+		if let Some(trace) = self.payment_traces.lock().unwrap().trace_mut(payment_id) {
+			trace.push(event);
+		}
+	}
+
+	/// Traces the result of looking up a route for the given payment.
+	fn trace_route(&self, payment_id: PaymentId, route: Option<&Route>) {
+		if let Some(trace) = self.payment_traces.lock().unwrap().trace_mut(payment_id) {
+			trace.route_attempts += 1;
+			let attempt = trace.route_attempts;
+			trace.push(match route {
+				Some(route) => PaymentTraceEvent::RouteFound {
+					attempt,
+					path_count: route.paths.len(),
+					amount_msat: route.get_total_amount(),
+					fee_msat: route.get_total_fees(),
+				},
+				None => PaymentTraceEvent::RouteNotFound { attempt },
+			});
+		}
+	}
+
+	/// Traces sending an `invoice_request` for the given payment.
+	pub(super) fn trace_invoice_request(&self, payment_id: PaymentId) {
+		if let Some(trace) = self.payment_traces.lock().unwrap().trace_mut(payment_id) {
+			trace.invoice_requests += 1;
+			let attempt = trace.invoice_requests;
+			trace.push(PaymentTraceEvent::InvoiceRequested { attempt });
+		}
+	}
+
+	pub(super) fn payment_trace(&self, payment_id: &PaymentId) -> Option<Vec<PaymentTraceEvent>> {
+		self.payment_traces.lock().unwrap().traces.get(payment_id).map(|trace| trace.events.clone())
+	}
+
 	pub(super) fn send_payment<R: Deref, ES: Deref, NS: Deref, IH, SP, L: Deref>(
 		&self, payment_hash: PaymentHash, recipient_onion: RecipientOnionFields, payment_id: PaymentId,
 		retry_strategy: Retry, route_params: RouteParameters, router: &R,
@@ -502,9 +1101,55 @@ impl OutboundPayments {
 		SP: Fn(&Path, &PaymentHash, RecipientOnionFields, u64, u32, PaymentId,
 			&Option<PaymentPreimage>, [u8; 32]) -> Result<(), APIError>,
 	{
+		let router = OverridingRouter { router, overrides: &self.route_overrides, new_override: None };
 		self.send_payment_internal(payment_id, payment_hash, recipient_onion, None, retry_strategy,
-			route_params, router, first_hops, &compute_inflight_htlcs, entropy_source, node_signer,
-			best_block_height, logger, pending_events, &send_payment_along_path)
+			route_params, &&router, first_hops, &compute_inflight_htlcs, entropy_source, node_signer,
+			best_block_height, logger, pending_events, &send_payment_along_path, None)
+	}
+
+	pub(super) fn send_payment_with_route_override<R: Deref, ES: Deref, NS: Deref, IH, SP, L: Deref>(
+		&self, payment_hash: PaymentHash, recipient_onion: RecipientOnionFields, payment_id: PaymentId,
+		retry_strategy: Retry, route_params: RouteParameters, route_override: RouteOverride,
+		router: &R, first_hops: Vec<ChannelDetails>, compute_inflight_htlcs: IH, entropy_source: &ES,
+		node_signer: &NS, best_block_height: u32, logger: &L,
+		pending_events: &Mutex<VecDeque<(events::Event, Option<EventCompletionAction>)>>, send_payment_along_path: SP,
+	) -> Result<(), RetryableSendFailure>
+	where
+		R::Target: Router,
+		ES::Target: EntropySource,
+		NS::Target: NodeSigner,
+		L::Target: Logger,
+		IH: Fn() -> InFlightHtlcs,
+		SP: Fn(&Path, &PaymentHash, RecipientOnionFields, u64, u32, PaymentId,
+			&Option<PaymentPreimage>, [u8; 32]) -> Result<(), APIError>,
+	{
+		match route_override {
+			RouteOverride::Router(override_router) => {
+				// The override is only stored once the payment is added as pending, atomically with
+				// it, such that it can never apply to, nor be removed on behalf of, another payment
+				// with the same id.
+				let router = OverridingRouter {
+					router, overrides: &self.route_overrides, new_override: Some((payment_id, &override_router)),
+				};
+				self.send_payment_internal(payment_id, payment_hash, recipient_onion, None,
+					retry_strategy, route_params, &&router, first_hops, &compute_inflight_htlcs,
+					entropy_source, node_signer, best_block_height, logger, pending_events,
+					&send_payment_along_path, Some(Arc::clone(&override_router)))
+			},
+			RouteOverride::Route(route) => {
+				#[cfg(feature = "std")] {
+					if has_expired(&route_params) {
+						return Err(RetryableSendFailure::PaymentExpired)
+					}
+				}
+				let router = OverridingRouter { router, overrides: &self.route_overrides, new_override: None };
+				self.start_payment_trace(payment_id);
+				self.send_payment_along_route_internal(route, payment_id, payment_hash,
+					recipient_onion, None, retry_strategy, route_params, &&router, first_hops,
+					&compute_inflight_htlcs, entropy_source, node_signer, best_block_height, logger,
+					pending_events, &send_payment_along_path, None)
+			},
+		}
 	}
 
 	pub(super) fn send_payment_with_route<ES: Deref, NS: Deref, F>(
@@ -519,6 +1164,7 @@ impl OutboundPayments {
 			&Option<PaymentPreimage>, [u8; 32]) -> Result<(), APIError>
 	{
 		let onion_session_privs = self.add_new_pending_payment(payment_hash, recipient_onion.clone(), payment_id, None, route, None, None, entropy_source, best_block_height)?;
+		self.start_payment_trace(payment_id);
 		self.pay_route_internal(route, payment_hash, recipient_onion, None, payment_id, None,
 			onion_session_privs, node_signer, best_block_height, &send_payment_along_path)
 			.map_err(|e| { self.remove_outbound_if_all_failed(payment_id, &e); e })
@@ -545,7 +1191,7 @@ impl OutboundPayments {
 		let payment_hash = PaymentHash(Sha256::hash(&preimage.0).into_inner());
 		self.send_payment_internal(payment_id, payment_hash, recipient_onion, Some(preimage),
 			retry_strategy, route_params, router, first_hops, inflight_htlcs, entropy_source,
-			node_signer, best_block_height, logger, pending_events, send_payment_along_path)
+			node_signer, best_block_height, logger, pending_events, send_payment_along_path, None)
 			.map(|()| payment_hash)
 	}
 
@@ -565,6 +1211,7 @@ impl OutboundPayments {
 		let payment_hash = PaymentHash(Sha256::hash(&preimage.0).into_inner());
 		let onion_session_privs = self.add_new_pending_payment(payment_hash, recipient_onion.clone(),
 			payment_id, Some(preimage), &route, None, None, entropy_source, best_block_height)?;
+		self.start_payment_trace(payment_id);
 
 		match self.pay_route_internal(route, payment_hash, recipient_onion, Some(preimage),
 			payment_id, None, onion_session_privs, node_signer, best_block_height, &send_payment_along_path
@@ -594,11 +1241,15 @@ impl OutboundPayments {
 		L::Target: Logger,
 	{
 		let _single_thread = self.retry_lock.lock().unwrap();
+		let router = OverridingRouter { router, overrides: &self.route_overrides, new_override: None };
+		let router = &router;
+		self.exposure_deferred_retries.lock().unwrap().clear();
 		loop {
 			let mut outbounds = self.pending_outbound_payments.lock().unwrap();
+			let exposure_deferred_retries = self.exposure_deferred_retries.lock().unwrap();
 			let mut retry_id_route_params = None;
 			for (pmt_id, pmt) in outbounds.iter_mut() {
-				if pmt.is_auto_retryable_now() {
+				if pmt.is_auto_retryable_now() && !exposure_deferred_retries.contains(pmt_id) {
 					if let PendingOutboundPayment::Retryable { pending_amt_msat, total_msat, payment_params: Some(params), payment_hash, .. } = pmt {
 						if pending_amt_msat < total_msat {
 							retry_id_route_params = Some((*payment_hash, *pmt_id, RouteParameters {
@@ -610,9 +1261,10 @@ impl OutboundPayments {
 					} else { debug_assert!(false); }
 				}
 			}
+			core::mem::drop(exposure_deferred_retries);
 			core::mem::drop(outbounds);
 			if let Some((payment_hash, payment_id, route_params)) = retry_id_route_params {
-				self.retry_payment_internal(payment_hash, payment_id, route_params, router, first_hops(), &inflight_htlcs, entropy_source, node_signer, best_block_height, logger, pending_events, &send_payment_along_path)
+				self.retry_payment_internal(payment_hash, payment_id, route_params, None, &router, first_hops(), &inflight_htlcs, entropy_source, node_signer, best_block_height, logger, pending_events, &send_payment_along_path)
 			} else { break }
 		}
 
@@ -621,17 +1273,19 @@ impl OutboundPayments {
 			let mut retain = true;
 			if !pmt.is_auto_retryable_now() && pmt.remaining_parts() == 0 {
 				pmt.mark_abandoned(PaymentFailureReason::RetriesExhausted);
-				if let PendingOutboundPayment::Abandoned { payment_hash, reason, .. } = pmt {
+				if let PendingOutboundPayment::Abandoned { payment_hash, reason, attempts, .. } = pmt {
 					pending_events.lock().unwrap().push_back((events::Event::PaymentFailed {
 						payment_id: *pmt_id,
 						payment_hash: *payment_hash,
 						reason: *reason,
+						attempts: *attempts,
 					}, None));
 					retain = false;
 				}
 			}
 			retain
 		});
+		self.route_overrides.lock().unwrap().retain(|pmt_id, _| outbounds.contains_key(pmt_id));
 	}
 
 	pub(super) fn needs_abandon(&self) -> bool {
@@ -651,6 +1305,7 @@ impl OutboundPayments {
 		router: &R, first_hops: Vec<ChannelDetails>, inflight_htlcs: IH, entropy_source: &ES,
 		node_signer: &NS, best_block_height: u32, logger: &L,
 		pending_events: &Mutex<VecDeque<(events::Event, Option<EventCompletionAction>)>>, send_payment_along_path: SP,
+		route_override: Option<Arc<dyn Router + Send + Sync>>,
 	) -> Result<(), RetryableSendFailure>
 	where
 		R::Target: Router,
@@ -667,29 +1322,151 @@ impl OutboundPayments {
 			}
 		}
 
-		let route = router.find_route_with_id(
+		let route_res = router.find_route_with_id(
 			&node_signer.get_node_id(Recipient::Node).unwrap(), &route_params,
 			Some(&first_hops.iter().collect::<Vec<_>>()), &inflight_htlcs(),
 			payment_hash, payment_id,
-		).map_err(|_| RetryableSendFailure::RouteNotFound)?;
+		);
+		self.start_payment_trace(payment_id);
+		self.trace_route(payment_id, route_res.as_ref().ok());
+		let route = route_res.map_err(|_| RetryableSendFailure::RouteNotFound)?;
+
+		self.send_payment_along_route_internal(route, payment_id, payment_hash, recipient_onion,
+			keysend_preimage, retry_strategy, route_params, router, first_hops, &inflight_htlcs,
+			entropy_source, node_signer, best_block_height, logger, pending_events,
+			&send_payment_along_path, route_override)
+	}
 
-		let onion_session_privs = self.add_new_pending_payment(payment_hash,
-			recipient_onion.clone(), payment_id, keysend_preimage, &route, Some(retry_strategy),
-			Some(route_params.payment_params.clone()), entropy_source, best_block_height)
-			.map_err(|_| RetryableSendFailure::DuplicatePayment)?;
+	/// Sends each of the given [`BatchedPayment`]s, finding routes for all of them in a single call
+	/// to [`Router::find_routes`].
+	///
+	/// If `max_total_routing_fee_msat` is set and the routes found would pay more than it in fees in
+	/// total, none of the payments are sent. Otherwise, returns a result for each payment, in the
+	/// order given, which errors on the same conditions as [`Self::send_payment`].
+	///
+	/// Any retries of the payments are bounded by what is left of `max_total_routing_fee_msat`,
+	/// with a payment being abandoned if a route for its retry would exceed it.
+	pub(super) fn send_payment_batch<R: Deref, ES: Deref, NS: Deref, IH, SP, L: Deref>(
+		&self, payments: Vec<BatchedPayment>, max_total_routing_fee_msat: Option<u64>,
+		retry_strategy: Retry, router: &R, first_hops: Vec<ChannelDetails>, inflight_htlcs: IH,
+		entropy_source: &ES, node_signer: &NS, best_block_height: u32, logger: &L,
+		pending_events: &Mutex<VecDeque<(events::Event, Option<EventCompletionAction>)>>, send_payment_along_path: SP
+	) -> Result<Vec<Result<(), RetryableSendFailure>>, BatchPaymentError>
+	where
+		R::Target: Router,
+		ES::Target: EntropySource,
+		NS::Target: NodeSigner,
+		L::Target: Logger,
+		IH: Fn() -> InFlightHtlcs,
+		SP: Fn(&Path, &PaymentHash, RecipientOnionFields, u64, u32, PaymentId,
+			&Option<PaymentPreimage>, [u8; 32]) -> Result<(), APIError>,
+	{
+		let mut results: Vec<Option<Result<(), RetryableSendFailure>>> = Vec::with_capacity(payments.len());
+		let mut routable_payments = Vec::with_capacity(payments.len());
+		for (idx, payment) in payments.into_iter().enumerate() {
+			#[cfg(feature = "std")] {
+				if has_expired(&payment.route_params) {
+					results.push(Some(Err(RetryableSendFailure::PaymentExpired)));
+					continue;
+				}
+			}
+			results.push(None);
+			routable_payments.push((idx, payment));
+		}
+
+		let all_route_params = routable_payments.iter()
+			.map(|(_, payment)| payment.route_params.clone()).collect::<Vec<_>>();
+		let routes = router.find_routes(
+			&node_signer.get_node_id(Recipient::Node).unwrap(), &all_route_params,
+			Some(&first_hops.iter().collect::<Vec<_>>()), &inflight_htlcs(),
+		);
+		debug_assert_eq!(routes.len(), routable_payments.len());
+
+		if let Some(max_total_routing_fee_msat) = max_total_routing_fee_msat {
+			let total_fee_msat = routes.iter()
+				.filter_map(|route_res| route_res.as_ref().ok())
+				.map(|route| route.get_total_fees())
+				.fold(0u64, |total, fee| total.saturating_add(fee));
+			if total_fee_msat > max_total_routing_fee_msat {
+				log_info!(logger, "Not sending batch of {} payments as their routes would pay {} msat in fees, exceeding the budget of {} msat",
+					results.len(), total_fee_msat, max_total_routing_fee_msat);
+				return Err(BatchPaymentError::FeeBudgetExceeded { total_fee_msat, max_total_routing_fee_msat });
+			}
+			self.batch_fee_budgets.lock().unwrap().push(BatchFeeBudget {
+				payment_ids: routable_payments.iter().map(|(_, payment)| payment.payment_id).collect(),
+				max_total_routing_fee_msat,
+				paid_fee_msat: 0,
+			});
+		}
+
+		for ((idx, payment), route_res) in routable_payments.into_iter().zip(routes.into_iter()) {
+			self.start_payment_trace(payment.payment_id);
+			self.trace_route(payment.payment_id, route_res.as_ref().ok());
+			let res = match route_res {
+				Ok(route) => self.send_payment_along_route_internal(route, payment.payment_id,
+					payment.payment_hash, payment.recipient_onion, payment.keysend_preimage,
+					retry_strategy, payment.route_params, router, first_hops.clone(), &inflight_htlcs,
+					entropy_source, node_signer, best_block_height, logger, pending_events,
+					&send_payment_along_path, None),
+				Err(_) => Err(RetryableSendFailure::RouteNotFound),
+			};
+			results[idx] = Some(res);
+		}
+		Ok(results.into_iter().map(|res| res.unwrap()).collect())
+	}
+
+	/// Sends a payment over the given `route`, which was found for `route_params`, retrying any
+	/// failed paths per `retry_strategy`.
+	fn send_payment_along_route_internal<R: Deref, NS: Deref, ES: Deref, IH, SP, L: Deref>(
+		&self, route: Route, payment_id: PaymentId, payment_hash: PaymentHash,
+		recipient_onion: RecipientOnionFields, keysend_preimage: Option<PaymentPreimage>,
+		retry_strategy: Retry, route_params: RouteParameters, router: &R,
+		first_hops: Vec<ChannelDetails>, inflight_htlcs: &IH, entropy_source: &ES, node_signer: &NS,
+		best_block_height: u32, logger: &L,
+		pending_events: &Mutex<VecDeque<(events::Event, Option<EventCompletionAction>)>>, send_payment_along_path: &SP,
+		route_override: Option<Arc<dyn Router + Send + Sync>>,
+	) -> Result<(), RetryableSendFailure>
+	where
+		R::Target: Router,
+		ES::Target: EntropySource,
+		NS::Target: NodeSigner,
+		L::Target: Logger,
+		IH: Fn() -> InFlightHtlcs,
+		SP: Fn(&Path, &PaymentHash, RecipientOnionFields, u64, u32, PaymentId,
+			&Option<PaymentPreimage>, [u8; 32]) -> Result<(), APIError>
+	{
+		let onion_session_privs = route.paths.iter()
+			.map(|_| entropy_source.get_secure_random_bytes()).collect();
+		let onion_session_privs = {
+			// Check our exposure limits while holding the lock we insert the payment under, so that
+			// concurrent sends can't exceed them together.
+			let mut pending_outbounds = self.pending_outbound_payments.lock().unwrap();
+			if self.exceeds_exposure_limits(pending_outbounds.values(), &route_params.payment_params, &route) {
+				log_info!(logger, "Not sending payment with id {} as it would exceed our outbound HTLC exposure limits",
+					log_bytes!(payment_id.0));
+				return Err(RetryableSendFailure::ExposureLimitReached);
+			}
+			self.insert_new_pending_payment_locked(&mut pending_outbounds, payment_hash,
+				recipient_onion.clone(), payment_id, keysend_preimage, &route, Some(retry_strategy),
+				Some(route_params.payment_params.clone()), onion_session_privs, best_block_height,
+				route_override)
+				.map_err(|_| RetryableSendFailure::DuplicatePayment)?
+		};
 
 		let res = self.pay_route_internal(&route, payment_hash, recipient_onion, None, payment_id, None,
-			onion_session_privs, node_signer, best_block_height, &send_payment_along_path);
+			onion_session_privs, node_signer, best_block_height, send_payment_along_path);
 		log_info!(logger, "Result sending payment with id {}: {:?}", log_bytes!(payment_id.0), res);
 		if let Err(e) = res {
-			self.handle_pay_route_err(e, payment_id, payment_hash, route, route_params, router, first_hops, &inflight_htlcs, entropy_source, node_signer, best_block_height, logger, pending_events, &send_payment_along_path);
+			self.handle_pay_route_err(e, payment_id, payment_hash, route, route_params, router, first_hops, inflight_htlcs, entropy_source, node_signer, best_block_height, logger, pending_events, send_payment_along_path);
 		}
 		Ok(())
 	}
 
+	/// Retries the given payment, counting it as a retry attempt unless `first_hop_substitution` is
+	/// set, in which case it holds the unavailable first hops we're substituting for.
 	fn retry_payment_internal<R: Deref, NS: Deref, ES: Deref, IH, SP, L: Deref>(
 		&self, payment_hash: PaymentHash, payment_id: PaymentId, route_params: RouteParameters,
-		router: &R, first_hops: Vec<ChannelDetails>, inflight_htlcs: &IH, entropy_source: &ES,
+		first_hop_substitution: Option<Vec<u64>>, router: &R, first_hops: Vec<ChannelDetails>, inflight_htlcs: &IH, entropy_source: &ES,
 		node_signer: &NS, best_block_height: u32, logger: &L,
 		pending_events: &Mutex<VecDeque<(events::Event, Option<EventCompletionAction>)>>, send_payment_along_path: &SP,
 	)
@@ -710,14 +1487,28 @@ impl OutboundPayments {
 			}
 		}
 
-		let route = match router.find_route_with_id(
+		let route_res = router.find_route_with_id(
 			&node_signer.get_node_id(Recipient::Node).unwrap(), &route_params,
 			Some(&first_hops.iter().collect::<Vec<_>>()), &inflight_htlcs(),
 			payment_hash, payment_id,
-		) {
+		);
+		self.trace_route(payment_id, route_res.as_ref().ok());
+		let route = match route_res {
 			Ok(route) => route,
 			Err(e) => {
 				log_error!(logger, "Failed to find a route on retry, abandoning payment {}: {:#?}", log_bytes!(payment_id.0), e);
+				let recipient_onion = match self.pending_outbound_payments.lock().unwrap().get(&payment_id) {
+					Some(PendingOutboundPayment::Retryable { payment_secret, payment_metadata, .. }) =>
+						Some(RecipientOnionFields {
+							payment_secret: *payment_secret,
+							payment_metadata: payment_metadata.clone(),
+						}),
+					_ => None,
+				};
+				if let Some(recipient_onion) = recipient_onion {
+					self.retries_without_route.lock().unwrap()
+						.push((payment_id, payment_hash, recipient_onion, route_params));
+				}
 				self.abandon_payment(payment_id, PaymentFailureReason::RouteNotFound, pending_events);
 				return
 			}
@@ -739,13 +1530,16 @@ impl OutboundPayments {
 		macro_rules! abandon_with_entry {
 			($payment: expr, $reason: expr) => {
 				$payment.get_mut().mark_abandoned($reason);
-				if let PendingOutboundPayment::Abandoned { reason, .. } = $payment.get() {
+				if let PendingOutboundPayment::Abandoned { reason, attempts, .. } = $payment.get() {
 					if $payment.get().remaining_parts() == 0 {
 						pending_events.lock().unwrap().push_back((events::Event::PaymentFailed {
 							payment_id,
 							payment_hash,
 							reason: *reason,
+							attempts: *attempts,
 						}, None));
+						self.record_resolved_payment(payment_id,
+							ResolvedPaymentStatus::Abandoned { payment_hash, reason: *reason });
 						$payment.remove();
 					}
 				}
@@ -753,6 +1547,11 @@ impl OutboundPayments {
 		}
 		let (total_msat, recipient_onion, keysend_preimage) = {
 			let mut outbounds = self.pending_outbound_payments.lock().unwrap();
+			let exceeds_exposure_limits =
+				self.exceeds_exposure_limits(outbounds.values(), &route_params.payment_params, &route);
+			let exceeds_exposure_limits_alone = exceeds_exposure_limits &&
+				self.exceeds_exposure_limits(outbounds.get(&payment_id).into_iter(), &route_params.payment_params, &route);
+			let remaining_fee_budget_msat = self.remaining_batch_fee_budget(&outbounds, payment_id);
 			match outbounds.entry(payment_id) {
 				hash_map::Entry::Occupied(mut payment) => {
 					let res = match payment.get() {
@@ -783,12 +1582,35 @@ impl OutboundPayments {
 							return
 						},
 					};
-					if !payment.get().is_retryable_now() {
-						log_error!(logger, "Retries exhausted for payment id {}", log_bytes!(payment_id.0));
-						abandon_with_entry!(payment, PaymentFailureReason::RetriesExhausted);
+					if exceeds_exposure_limits_alone {
+						log_info!(logger, "Retrying payment id {} would exceed our outbound HTLC exposure limits on its own, abandoning it",
+							log_bytes!(payment_id.0));
+						abandon_with_entry!(payment, PaymentFailureReason::RouteNotFound);
+						return
+					}
+					if exceeds_exposure_limits {
+						// Once other payments' HTLCs are resolved we'll be woken up to retry again.
+						log_info!(logger, "Retrying payment id {} would exceed our outbound HTLC exposure limits, deferring it",
+							log_bytes!(payment_id.0));
+						self.exposure_deferred_retries.lock().unwrap().insert(payment_id);
 						return
 					}
-					payment.get_mut().increment_attempts();
+					if let Some(remaining_fee_budget_msat) = remaining_fee_budget_msat {
+						if route.get_total_fees() > remaining_fee_budget_msat {
+							log_info!(logger, "Retrying payment id {} would pay {} msat in fees, exceeding the {} msat left of its batch's fee budget, abandoning it",
+								log_bytes!(payment_id.0), route.get_total_fees(), remaining_fee_budget_msat);
+							abandon_with_entry!(payment, PaymentFailureReason::RouteNotFound);
+							return
+						}
+					}
+					if first_hop_substitution.is_none() {
+						if !payment.get().is_retryable_now() {
+							log_error!(logger, "Retries exhausted for payment id {}", log_bytes!(payment_id.0));
+							abandon_with_entry!(payment, PaymentFailureReason::RetriesExhausted);
+							return
+						}
+						payment.get_mut().increment_attempts();
+					}
 					for (path, session_priv_bytes) in route.paths.iter().zip(onion_session_privs.iter()) {
 						assert!(payment.get_mut().insert(*session_priv_bytes, path));
 					}
@@ -800,6 +1622,16 @@ impl OutboundPayments {
 				}
 			}
 		};
+		if let Some(failed_short_channel_ids) = first_hop_substitution {
+			log_info!(logger, "Retrying payment id {} over other first hops as channels {:?} were unavailable",
+				log_bytes!(payment_id.0), failed_short_channel_ids);
+			pending_events.lock().unwrap().push_back((events::Event::PaymentFirstHopSubstituted {
+				payment_id,
+				payment_hash,
+				failed_short_channel_ids,
+				short_channel_ids: route.paths.iter().map(|path| path.hops[0].short_channel_id).collect(),
+			}, None));
+		}
 		let res = self.pay_route_internal(&route, payment_hash, recipient_onion, keysend_preimage,
 			payment_id, Some(total_msat), onion_session_privs, node_signer, best_block_height,
 			&send_payment_along_path);
@@ -824,17 +1656,21 @@ impl OutboundPayments {
 		SP: Fn(&Path, &PaymentHash, RecipientOnionFields, u64, u32, PaymentId,
 			&Option<PaymentPreimage>, [u8; 32]) -> Result<(), APIError>
 	{
+		let attempts = self.pending_outbound_payments.lock().unwrap().get(&payment_id)
+			.and_then(|payment| payment.attempts());
 		match err {
 			PaymentSendFailure::AllFailedResendSafe(errs) => {
-				Self::push_path_failed_evs_and_scids(payment_id, payment_hash, &mut route_params, route.paths, errs.into_iter().map(|e| Err(e)), logger, pending_events);
-				self.retry_payment_internal(payment_hash, payment_id, route_params, router, first_hops, inflight_htlcs, entropy_source, node_signer, best_block_height, logger, pending_events, send_payment_along_path);
+				let unavailable_first_hops = Self::push_path_failed_evs_and_scids(payment_id, payment_hash, attempts, &mut route_params, route.paths, errs.into_iter().map(|e| Err(e)), logger, pending_events);
+				let substitution = Self::first_hop_substitution(&first_hops, &route_params, unavailable_first_hops);
+				self.retry_payment_internal(payment_hash, payment_id, route_params, substitution, router, first_hops, inflight_htlcs, entropy_source, node_signer, best_block_height, logger, pending_events, send_payment_along_path);
 			},
 			PaymentSendFailure::PartialFailure { failed_paths_retry: Some(mut retry), results, .. } => {
-				Self::push_path_failed_evs_and_scids(payment_id, payment_hash, &mut retry, route.paths, results.into_iter(), logger, pending_events);
+				let unavailable_first_hops = Self::push_path_failed_evs_and_scids(payment_id, payment_hash, attempts, &mut retry, route.paths, results.into_iter(), logger, pending_events);
+				let substitution = Self::first_hop_substitution(&first_hops, &retry, unavailable_first_hops);
 				// Some paths were sent, even if we failed to send the full MPP value our recipient may
 				// misbehave and claim the funds, at which point we have to consider the payment sent, so
 				// return `Ok()` here, ignoring any retry errors.
-				self.retry_payment_internal(payment_hash, payment_id, retry, router, first_hops, inflight_htlcs, entropy_source, node_signer, best_block_height, logger, pending_events, send_payment_along_path);
+				self.retry_payment_internal(payment_hash, payment_id, retry, substitution, router, first_hops, inflight_htlcs, entropy_source, node_signer, best_block_height, logger, pending_events, send_payment_along_path);
 			},
 			PaymentSendFailure::PartialFailure { failed_paths_retry: None, .. } => {
 				// This may happen if we send a payment and some paths fail, but only due to a temporary
@@ -843,7 +1679,7 @@ impl OutboundPayments {
 			},
 			PaymentSendFailure::PathParameterError(results) => {
 				log_error!(logger, "Failed to send to route due to parameter error in a single path. Your router is buggy");
-				Self::push_path_failed_evs_and_scids(payment_id, payment_hash, &mut route_params, route.paths, results.into_iter(), logger, pending_events);
+				Self::push_path_failed_evs_and_scids(payment_id, payment_hash, attempts, &mut route_params, route.paths, results.into_iter(), logger, pending_events);
 				self.abandon_payment(payment_id, PaymentFailureReason::UnexpectedError, pending_events);
 			},
 			PaymentSendFailure::ParameterError(e) => {
@@ -854,22 +1690,33 @@ impl OutboundPayments {
 		}
 	}
 
+	/// Generates a [`events::Event::PaymentPathFailed`] for each failed path, adding any
+	/// unavailable first hop channels to the `previously_failed_channels`.
+	///
+	/// Returns the unavailable first hop channels and the amount of the path sent over each, or
+	/// `None` if any path failed for another reason.
 	fn push_path_failed_evs_and_scids<I: ExactSizeIterator + Iterator<Item = Result<(), APIError>>, L: Deref>(
-		payment_id: PaymentId, payment_hash: PaymentHash, route_params: &mut RouteParameters,
-		paths: Vec<Path>, path_results: I, logger: &L,
+		payment_id: PaymentId, payment_hash: PaymentHash, attempts: Option<u32>,
+		route_params: &mut RouteParameters, paths: Vec<Path>, path_results: I, logger: &L,
 		pending_events: &Mutex<VecDeque<(events::Event, Option<EventCompletionAction>)>>,
-	) where L::Target: Logger {
+	) -> Option<Vec<(u64, u64)>> where L::Target: Logger {
 		let mut events = pending_events.lock().unwrap();
+		let mut unavailable_first_hops = Some(Vec::new());
 		debug_assert_eq!(paths.len(), path_results.len());
 		for (path, path_res) in paths.into_iter().zip(path_results) {
 			if let Err(e) = path_res {
 				if let APIError::MonitorUpdateInProgress = e { continue }
 				log_error!(logger, "Failed to send along path due to error: {:?}", e);
 				let mut failed_scid = None;
-				if let APIError::ChannelUnavailable { .. } = e {
+				if e.is_channel_unavailable() {
 					let scid = path.hops[0].short_channel_id;
 					failed_scid = Some(scid);
 					route_params.payment_params.previously_failed_channels.push(scid);
+					if let Some(unavailable_first_hops) = unavailable_first_hops.as_mut() {
+						unavailable_first_hops.push((scid, path.fee_msat() + path.final_value_msat()));
+					}
+				} else {
+					unavailable_first_hops = None;
 				}
 				events.push_back((events::Event::PaymentPathFailed {
 					payment_id: Some(payment_id),
@@ -878,6 +1725,10 @@ impl OutboundPayments {
 					failure: events::PathFailure::InitialSend { err: e },
 					path,
 					short_channel_id: failed_scid,
+					hold_times: Vec::new(),
+					failure_reason: None,
+					failing_node_id: None,
+					attempts,
 					#[cfg(test)]
 					error_code: None,
 					#[cfg(test)]
@@ -885,6 +1736,36 @@ impl OutboundPayments {
 				}, None));
 			}
 		}
+		unavailable_first_hops
+	}
+
+	/// Determines whether paths which failed as their first hop was unavailable can be retried
+	/// over our other channels without counting as a retry attempt, returning the unavailable
+	/// channels if so.
+	///
+	/// Each of our channels may only be substituted for once per payment, as it remains in the
+	/// `previously_failed_channels` once it has been.
+	fn first_hop_substitution(
+		first_hops: &[ChannelDetails], route_params: &RouteParameters,
+		unavailable_first_hops: Option<Vec<(u64, u64)>>
+	) -> Option<Vec<u64>> {
+		let unavailable_first_hops = unavailable_first_hops?;
+		let max_path_amt_msat = unavailable_first_hops.iter().map(|(_, amt_msat)| *amt_msat).max()?;
+		let previously_failed = &route_params.payment_params.previously_failed_channels;
+		let is_newly_failed_first_hop = |scid: u64| {
+			first_hops.iter().any(|chan| chan.get_outbound_payment_scid() == Some(scid)) &&
+				previously_failed.iter().filter(|failed_scid| **failed_scid == scid).count() == 1
+		};
+		if !unavailable_first_hops.iter().all(|(scid, _)| is_newly_failed_first_hop(*scid)) {
+			return None;
+		}
+		let have_alternative = first_hops.iter().any(|chan| {
+			chan.is_usable && chan.next_outbound_htlc_limit_msat >= max_path_amt_msat &&
+				chan.get_outbound_payment_scid().map_or(false, |scid| !previously_failed.contains(&scid))
+		});
+		if have_alternative {
+			Some(unavailable_first_hops.into_iter().map(|(scid, _)| scid).collect())
+		} else { None }
 	}
 
 	pub(super) fn send_probe<ES: Deref, NS: Deref, F>(
@@ -923,6 +1804,47 @@ impl OutboundPayments {
 		}
 	}
 
+	pub(super) fn send_payment_with_custom_onion<ES: Deref, NS: Deref, F>(
+		&self, path: Path, payment_hash: PaymentHash, custom_onion: CustomOnion, payment_id: PaymentId,
+		entropy_source: &ES, node_signer: &NS, best_block_height: u32, send_custom_onion_along_path: F
+	) -> Result<(), PaymentSendFailure>
+	where
+		ES::Target: EntropySource,
+		NS::Target: NodeSigner,
+		F: Fn(&Path, &PaymentHash, &CustomOnion, u32, PaymentId, [u8; 32]) -> Result<(), APIError>
+	{
+		let session_priv_bytes = match &custom_onion {
+			CustomOnion::HopPayloads(payloads) => {
+				let blinded_hop_count = path.blinded_tail.as_ref().map_or(0, |tail| tail.hops.len().saturating_sub(1));
+				if payloads.len() != path.hops.len() + blinded_hop_count {
+					return Err(PaymentSendFailure::ParameterError(APIError::APIMisuseError {
+						err: "A payload must be provided for each hop in the path".to_owned()
+					}));
+				}
+				entropy_source.get_secure_random_bytes()
+			},
+			CustomOnion::Packet { session_priv, .. } => session_priv.secret_bytes(),
+		};
+
+		let route = Route { paths: vec![path], payment_params: None };
+		let onion_session_privs = self.insert_new_pending_payment(payment_hash,
+			RecipientOnionFields::spontaneous_empty(), payment_id, None, &route, None, None,
+			vec![session_priv_bytes], best_block_height, None)?;
+
+		let send_payment_along_path = |path: &Path, payment_hash: &PaymentHash, _: RecipientOnionFields,
+			_: u64, cur_height: u32, payment_id: PaymentId, _: &Option<PaymentPreimage>, session_priv: [u8; 32]|
+			send_custom_onion_along_path(path, payment_hash, &custom_onion, cur_height, payment_id, session_priv);
+		match self.pay_route_internal(&route, payment_hash, RecipientOnionFields::spontaneous_empty(),
+			None, payment_id, None, onion_session_privs, node_signer, best_block_height, &send_payment_along_path
+		) {
+			Ok(()) => Ok(()),
+			Err(e) => {
+				self.remove_outbound_if_all_failed(payment_id, &e);
+				Err(e)
+			}
+		}
+	}
+
 	#[cfg(test)]
 	pub(super) fn test_set_payment_metadata(
 		&self, payment_id: PaymentId, new_payment_metadata: Option<Vec<u8>>
@@ -953,7 +1875,33 @@ impl OutboundPayments {
 			onion_session_privs.push(entropy_source.get_secure_random_bytes());
 		}
 
+		self.insert_new_pending_payment(payment_hash, recipient_onion, payment_id, keysend_preimage,
+			route, retry_strategy, payment_params, onion_session_privs, best_block_height, None)
+	}
+
+	fn insert_new_pending_payment(
+		&self, payment_hash: PaymentHash, recipient_onion: RecipientOnionFields, payment_id: PaymentId,
+		keysend_preimage: Option<PaymentPreimage>, route: &Route, retry_strategy: Option<Retry>,
+		payment_params: Option<PaymentParameters>, onion_session_privs: Vec<[u8; 32]>,
+		best_block_height: u32, route_override: Option<Arc<dyn Router + Send + Sync>>
+	) -> Result<Vec<[u8; 32]>, PaymentSendFailure> {
 		let mut pending_outbounds = self.pending_outbound_payments.lock().unwrap();
+		self.insert_new_pending_payment_locked(&mut pending_outbounds, payment_hash, recipient_onion,
+			payment_id, keysend_preimage, route, retry_strategy, payment_params, onion_session_privs,
+			best_block_height, route_override)
+	}
+
+	fn insert_new_pending_payment_locked(
+		&self, pending_outbounds: &mut HashMap<PaymentId, PendingOutboundPayment>,
+		payment_hash: PaymentHash, recipient_onion: RecipientOnionFields, payment_id: PaymentId,
+		keysend_preimage: Option<PaymentPreimage>, route: &Route, retry_strategy: Option<Retry>,
+		payment_params: Option<PaymentParameters>, onion_session_privs: Vec<[u8; 32]>,
+		best_block_height: u32, route_override: Option<Arc<dyn Router + Send + Sync>>
+	) -> Result<Vec<[u8; 32]>, PaymentSendFailure> {
+		debug_assert_eq!(route.paths.len(), onion_session_privs.len());
+		if self.resolved_payments.lock().unwrap().iter().any(|resolved| resolved.payment_id == payment_id) {
+			return Err(PaymentSendFailure::DuplicatePayment);
+		}
 		match pending_outbounds.entry(payment_id) {
 			hash_map::Entry::Occupied(_) => Err(PaymentSendFailure::DuplicatePayment),
 			hash_map::Entry::Vacant(entry) => {
@@ -975,6 +1923,9 @@ impl OutboundPayments {
 				for (path, session_priv_bytes) in route.paths.iter().zip(onion_session_privs.iter()) {
 					assert!(payment.insert(*session_priv_bytes, path));
 				}
+				if let Some(router) = route_override {
+					self.route_overrides.lock().unwrap().insert(payment_id, router);
+				}
 
 				Ok(onion_session_privs)
 			},
@@ -995,7 +1946,10 @@ impl OutboundPayments {
 		if route.paths.len() < 1 {
 			return Err(PaymentSendFailure::ParameterError(APIError::InvalidRoute{err: "There must be at least one path to send over".to_owned()}));
 		}
-		if recipient_onion.payment_secret.is_none() && route.paths.len() > 1 {
+		// Blinded recipients authenticate each part of a payment using its encrypted payload
+		// rather than a payment secret.
+		let requires_payment_secret = route.paths.iter().any(|path| path.blinded_tail.is_none());
+		if recipient_onion.payment_secret.is_none() && route.paths.len() > 1 && requires_payment_secret {
 			return Err(PaymentSendFailure::ParameterError(APIError::APIMisuseError{err: "Payment secret is required for multi-path payments".to_owned()}));
 		}
 		let mut total_value = 0;
@@ -1006,10 +1960,6 @@ impl OutboundPayments {
 				path_errs.push(Err(APIError::InvalidRoute{err: "Path didn't go anywhere/had bogus size".to_owned()}));
 				continue 'path_check;
 			}
-			if path.blinded_tail.is_some() {
-				path_errs.push(Err(APIError::InvalidRoute{err: "Sending to blinded paths isn't supported yet".to_owned()}));
-				continue 'path_check;
-			}
 			let dest_hop_idx = if path.blinded_tail.is_some() && path.blinded_tail.as_ref().unwrap().hops.len() > 1 {
 				usize::max_value() } else { path.hops.len() - 1 };
 			for (idx, hop) in path.hops.iter().enumerate() {
@@ -1028,20 +1978,37 @@ impl OutboundPayments {
 			total_value = amt_msat;
 		}
 
+		let stagger_parts = route.paths.len() > 1 && route.payment_params.as_ref().map_or(false,
+			|params| params.privacy_level == PaymentPrivacyLevel::DecoyPartsAndTimingSpread);
+
 		let cur_height = best_block_height + 1;
 		let mut results = Vec::new();
 		debug_assert_eq!(route.paths.len(), onion_session_privs.len());
-		for (path, session_priv) in route.paths.iter().zip(onion_session_privs.into_iter()) {
+		for (idx, (path, session_priv)) in route.paths.iter().zip(onion_session_privs.into_iter()).enumerate() {
+			if stagger_parts && idx != 0 {
+				// Only the first part is sent right away, the others are sent later via
+				// `send_staggered_parts`.
+				self.staggered_parts.lock().unwrap().push_back(StaggeredPaymentPart {
+					payment_id, payment_hash, recipient_onion: recipient_onion.clone(), keysend_preimage,
+					path: path.clone(), total_value_msat: total_value, session_priv,
+				});
+				results.push(Ok(()));
+				continue;
+			}
 			let mut path_res = send_payment_along_path(&path, &payment_hash, recipient_onion.clone(),
 				total_value, cur_height, payment_id, &keysend_preimage, session_priv);
 			match path_res {
-				Ok(_) => {},
+				Ok(_) => self.trace_payment(payment_id, PaymentTraceEvent::HTLCSent { path: path.clone() }),
 				Err(APIError::MonitorUpdateInProgress) => {
 					// While a MonitorUpdateInProgress is an Err(_), the payment is still
 					// considered "in flight" and we shouldn't remove it from the
 					// PendingOutboundPayment set.
+					self.trace_payment(payment_id, PaymentTraceEvent::HTLCSent { path: path.clone() });
 				},
-				Err(_) => {
+				Err(ref e) => {
+					self.trace_payment(payment_id, PaymentTraceEvent::HTLCSendFailed {
+						path: path.clone(), err: e.clone(),
+					});
 					let mut pending_outbounds = self.pending_outbound_payments.lock().unwrap();
 					if let Some(payment) = pending_outbounds.get_mut(&payment_id) {
 						let removed = payment.remove(&session_priv, Some(path));
@@ -1089,6 +2056,110 @@ impl OutboundPayments {
 		}
 	}
 
+	pub(super) fn has_staggered_parts(&self) -> bool {
+		!self.staggered_parts.lock().unwrap().is_empty()
+	}
+
+	/// Sends the oldest part of a payment with [`PaymentPrivacyLevel::DecoyPartsAndTimingSpread`]
+	/// which has not been sent yet, or all such parts if `send_all` is set. Returns whether any
+	/// parts were sent.
+	///
+	/// Parts which fail to be sent generate an [`events::Event::PaymentPathFailed`] and are retried
+	/// like any other failed path. Parts of payments which were fulfilled or abandoned in the
+	/// meantime are dropped instead.
+	///
+	/// The CLTV expiries of the parts are based on `best_block_height` at the time they are sent
+	/// rather than when the payment was initiated, as parts may be held across restarts.
+	pub(super) fn send_staggered_parts<F, L: Deref>(
+		&self, send_all: bool, best_block_height: u32,
+		pending_events: &Mutex<VecDeque<(events::Event, Option<EventCompletionAction>)>>,
+		logger: &L, send_payment_along_path: F
+	) -> bool
+	where
+		L::Target: Logger,
+		F: Fn(&Path, &PaymentHash, RecipientOnionFields, u64, u32, PaymentId,
+			&Option<PaymentPreimage>, [u8; 32]) -> Result<(), APIError>
+	{
+		let parts: Vec<StaggeredPaymentPart> = {
+			let mut staggered_parts = self.staggered_parts.lock().unwrap();
+			if send_all {
+				staggered_parts.drain(..).collect()
+			} else {
+				staggered_parts.pop_front().into_iter().collect()
+			}
+		};
+		let sent_parts = !parts.is_empty();
+		let cur_height = best_block_height + 1;
+
+		for part in parts {
+			{
+				let mut outbounds = self.pending_outbound_payments.lock().unwrap();
+				let mut payment = match outbounds.entry(part.payment_id) {
+					hash_map::Entry::Occupied(payment) => payment,
+					hash_map::Entry::Vacant(_) => continue,
+				};
+				if payment.get().is_fulfilled() || payment.get().abandoned() {
+					payment.get_mut().remove(&part.session_priv, Some(&part.path));
+					if payment.get().remaining_parts() == 0 {
+						if let PendingOutboundPayment::Abandoned { payment_hash, reason, attempts, .. } = payment.get() {
+							pending_events.lock().unwrap().push_back((events::Event::PaymentFailed {
+								payment_id: part.payment_id,
+								payment_hash: *payment_hash,
+								reason: *reason,
+								attempts: *attempts,
+							}, None));
+							self.record_resolved_payment(part.payment_id,
+								ResolvedPaymentStatus::Abandoned { payment_hash: *payment_hash, reason: *reason });
+							payment.remove();
+						}
+					}
+					continue;
+				}
+			}
+
+			let path_res = send_payment_along_path(&part.path, &part.payment_hash, part.recipient_onion,
+				part.total_value_msat, cur_height, part.payment_id, &part.keysend_preimage, part.session_priv);
+			match path_res {
+				Ok(()) | Err(APIError::MonitorUpdateInProgress) =>
+					self.trace_payment(part.payment_id, PaymentTraceEvent::HTLCSent { path: part.path }),
+				Err(e) => {
+					log_error!(logger, "Failed to send staggered payment part due to error: {:?}", e);
+					self.trace_payment(part.payment_id, PaymentTraceEvent::HTLCSendFailed {
+						path: part.path.clone(), err: e.clone(),
+					});
+					let mut failed_scid = None;
+					let mut attempts = None;
+					if let Some(payment) = self.pending_outbound_payments.lock().unwrap().get_mut(&part.payment_id) {
+						payment.remove(&part.session_priv, Some(&part.path));
+						attempts = payment.attempts();
+						if e.is_channel_unavailable() {
+							let scid = part.path.hops[0].short_channel_id;
+							payment.insert_previously_failed_scid(scid);
+							failed_scid = Some(scid);
+						}
+					}
+					pending_events.lock().unwrap().push_back((events::Event::PaymentPathFailed {
+						payment_id: Some(part.payment_id),
+						payment_hash: part.payment_hash,
+						payment_failed_permanently: false,
+						failure: events::PathFailure::InitialSend { err: e },
+						path: part.path,
+						short_channel_id: failed_scid,
+						hold_times: Vec::new(),
+						failure_reason: None,
+						failing_node_id: None,
+						attempts,
+						#[cfg(test)]
+						error_code: None,
+						#[cfg(test)]
+						error_data: None,
+					}, None));
+				},
+			}
+		}
+		sent_parts
+	}
+
 	#[cfg(test)]
 	pub(super) fn test_send_payment_internal<NS: Deref, F>(
 		&self, route: &Route, payment_hash: PaymentHash, recipient_onion: RecipientOnionFields,
@@ -1116,25 +2187,37 @@ impl OutboundPayments {
 		}
 	}
 
+	// Returns a bool indicating whether a PendingHTLCsForwardable event should be generated to retry
+	// payments which were deferred to stay within our exposure limits.
 	pub(super) fn claim_htlc<L: Deref>(
 		&self, payment_id: PaymentId, payment_preimage: PaymentPreimage, session_priv: SecretKey,
 		path: Path, from_onchain: bool,
 		pending_events: &Mutex<VecDeque<(events::Event, Option<EventCompletionAction>)>>,
 		logger: &L,
-	) where L::Target: Logger {
+	) -> bool where L::Target: Logger {
 		let mut session_priv_bytes = [0; 32];
 		session_priv_bytes.copy_from_slice(&session_priv[..]);
 		let mut outbounds = self.pending_outbound_payments.lock().unwrap();
 		let mut pending_events = pending_events.lock().unwrap();
 		if let hash_map::Entry::Occupied(mut payment) = outbounds.entry(payment_id) {
+			self.trace_payment(payment_id, PaymentTraceEvent::HTLCClaimed { path: path.clone() });
 			if !payment.get().is_fulfilled() {
 				let payment_hash = PaymentHash(Sha256::hash(&payment_preimage.0).into_inner());
 				let fee_paid_msat = payment.get().get_pending_fee_msat();
+				if let Some(budget) = self.batch_fee_budgets.lock().unwrap().iter_mut()
+					.find(|budget| budget.payment_ids.contains(&payment_id))
+				{
+					budget.paid_fee_msat = budget.paid_fee_msat.saturating_add(fee_paid_msat.unwrap_or(0));
+				}
+				self.trace_payment(payment_id, PaymentTraceEvent::Resolved {
+					status: ResolvedPaymentStatus::Fulfilled { payment_hash: Some(payment_hash) },
+				});
 				pending_events.push_back((events::Event::PaymentSent {
 					payment_id: Some(payment_id),
 					payment_preimage,
 					payment_hash,
 					fee_paid_msat,
+					attempts: payment.get().attempts(),
 				}, None));
 				payment.get_mut().mark_fulfilled();
 			}
@@ -1157,7 +2240,9 @@ impl OutboundPayments {
 			}
 		} else {
 			log_trace!(logger, "Received duplicative fulfill for HTLC with payment_preimage {}", log_bytes!(payment_preimage.0));
+			return false
 		}
+		!self.exposure_deferred_retries.lock().unwrap().is_empty()
 	}
 
 	pub(super) fn finalize_claims(&self, sources: Vec<HTLCSource>,
@@ -1198,7 +2283,7 @@ impl OutboundPayments {
 		let mut pending_outbound_payments = self.pending_outbound_payments.lock().unwrap();
 		let pending_events = pending_events.lock().unwrap();
 		pending_outbound_payments.retain(|payment_id, payment| {
-			if let PendingOutboundPayment::Fulfilled { session_privs, timer_ticks_without_htlcs, .. } = payment {
+			if let PendingOutboundPayment::Fulfilled { session_privs, timer_ticks_without_htlcs, payment_hash } = payment {
 				let mut no_remaining_entries = session_privs.is_empty();
 				if no_remaining_entries {
 					for (ev, _) in pending_events.iter() {
@@ -1217,13 +2302,20 @@ impl OutboundPayments {
 				}
 				if no_remaining_entries {
 					*timer_ticks_without_htlcs += 1;
-					*timer_ticks_without_htlcs <= IDEMPOTENCY_TIMEOUT_TICKS
+					if *timer_ticks_without_htlcs > IDEMPOTENCY_TIMEOUT_TICKS {
+						self.record_resolved_payment(*payment_id,
+							ResolvedPaymentStatus::Fulfilled { payment_hash: *payment_hash });
+						false
+					} else { true }
 				} else {
 					*timer_ticks_without_htlcs = 0;
 					true
 				}
 			} else { true }
 		});
+		self.batch_fee_budgets.lock().unwrap().retain(|budget|
+			budget.payment_ids.iter().any(|payment_id| pending_outbound_payments.contains_key(payment_id))
+		);
 	}
 
 	// Returns a bool indicating whether a PendingHTLCsForwardable event should be generated.
@@ -1233,10 +2325,14 @@ impl OutboundPayments {
 		probing_cookie_secret: [u8; 32], secp_ctx: &Secp256k1<secp256k1::All>,
 		pending_events: &Mutex<VecDeque<(events::Event, Option<EventCompletionAction>)>>, logger: &L,
 	) -> bool where L::Target: Logger {
-		#[cfg(test)]
-		let (network_update, short_channel_id, payment_retryable, onion_error_code, onion_error_data) = onion_error.decode_onion_failure(secp_ctx, logger, &source);
-		#[cfg(not(test))]
-		let (network_update, short_channel_id, payment_retryable, _, _) = onion_error.decode_onion_failure(secp_ctx, logger, &source);
+		let DecodedOnionFailure {
+			network_update, short_channel_id, payment_retryable, failure_reason, failing_node_id,
+			hold_times,
+			#[cfg(test)]
+			onion_error_code,
+			#[cfg(test)]
+			onion_error_data,
+		} = onion_error.decode_onion_failure(secp_ctx, logger, &source);
 
 		let payment_is_probe = payment_is_probe(payment_hash, &payment_id, probing_cookie_secret);
 		let mut session_priv_bytes = [0; 32];
@@ -1244,26 +2340,35 @@ impl OutboundPayments {
 		let mut outbounds = self.pending_outbound_payments.lock().unwrap();
 
 		// If any payments already need retry, there's no need to generate a redundant
-		// `PendingHTLCsForwardable`.
-		let already_awaiting_retry = outbounds.iter().any(|(_, pmt)| {
-			let mut awaiting_retry = false;
-			if pmt.is_auto_retryable_now() {
-				if let PendingOutboundPayment::Retryable { pending_amt_msat, total_msat, .. } = pmt {
-					if pending_amt_msat < total_msat {
-						awaiting_retry = true;
+		// `PendingHTLCsForwardable`. Payments whose retry we deferred won't be retried until
+		// another HTLC resolves though, which this one doing so should trigger.
+		let (already_awaiting_retry, retry_deferred_payments) = {
+			let exposure_deferred_retries = self.exposure_deferred_retries.lock().unwrap();
+			(outbounds.iter().any(|(pmt_id, pmt)| {
+				let mut awaiting_retry = false;
+				if pmt.is_auto_retryable_now() && !exposure_deferred_retries.contains(pmt_id) {
+					if let PendingOutboundPayment::Retryable { pending_amt_msat, total_msat, .. } = pmt {
+						if pending_amt_msat < total_msat {
+							awaiting_retry = true;
+						}
 					}
 				}
-			}
-			awaiting_retry
-		});
+				awaiting_retry
+			}), !exposure_deferred_retries.is_empty())
+		};
 
 		let mut full_failure_ev = None;
 		let mut pending_retry_ev = false;
+		let mut attempts = None;
 		let attempts_remaining = if let hash_map::Entry::Occupied(mut payment) = outbounds.entry(*payment_id) {
 			if !payment.get_mut().remove(&session_priv_bytes, Some(&path)) {
 				log_trace!(logger, "Received duplicative fail for HTLC with payment_hash {}", log_bytes!(payment_hash.0));
 				return false
 			}
+			self.trace_payment(*payment_id, PaymentTraceEvent::HTLCFailed {
+				path: path.clone(), short_channel_id, payment_failed_permanently: !payment_retryable,
+			});
+			attempts = payment.get().attempts();
 			if payment.get().is_fulfilled() {
 				log_trace!(logger, "Received failure of HTLC with payment_hash {} after payment completion", log_bytes!(payment_hash.0));
 				return false
@@ -1292,7 +2397,10 @@ impl OutboundPayments {
 							payment_id: *payment_id,
 							payment_hash: *payment_hash,
 							reason: *reason,
+							attempts,
 						});
+						self.record_resolved_payment(*payment_id,
+							ResolvedPaymentStatus::Abandoned { payment_hash: *payment_hash, reason: *reason });
 					}
 					payment.remove();
 				}
@@ -1335,6 +2443,10 @@ impl OutboundPayments {
 					failure: events::PathFailure::OnPath { network_update },
 					path: path.clone(),
 					short_channel_id,
+					hold_times,
+					failure_reason,
+					failing_node_id,
+					attempts,
 					#[cfg(test)]
 					error_code: onion_error_code,
 					#[cfg(test)]
@@ -1342,6 +2454,9 @@ impl OutboundPayments {
 				}
 			}
 		};
+		if retry_deferred_payments && !already_awaiting_retry {
+			pending_retry_ev = true;
+		}
 		let mut pending_events = pending_events.lock().unwrap();
 		pending_events.push_back((path_failure, None));
 		if let Some(ev) = full_failure_ev { pending_events.push_back((ev, None)); }
@@ -1355,13 +2470,16 @@ impl OutboundPayments {
 		let mut outbounds = self.pending_outbound_payments.lock().unwrap();
 		if let hash_map::Entry::Occupied(mut payment) = outbounds.entry(payment_id) {
 			payment.get_mut().mark_abandoned(reason);
-			if let PendingOutboundPayment::Abandoned { payment_hash, reason, .. } = payment.get() {
+			if let PendingOutboundPayment::Abandoned { payment_hash, reason, attempts, .. } = payment.get() {
 				if payment.get().remaining_parts() == 0 {
 					pending_events.lock().unwrap().push_back((events::Event::PaymentFailed {
 						payment_id,
 						payment_hash: *payment_hash,
 						reason: *reason,
+						attempts: *attempts,
 					}, None));
+					self.record_resolved_payment(payment_id,
+						ResolvedPaymentStatus::Abandoned { payment_hash: *payment_hash, reason: *reason });
 					payment.remove();
 				}
 			}
@@ -1425,6 +2543,7 @@ impl_writeable_tlv_based_enum_upgradable!(PendingOutboundPayment,
 		(0, session_privs, required),
 		(1, reason, option),
 		(2, payment_hash, required),
+		(not_written, attempts, (static_value, None)),
 	},
 );
 
@@ -1438,15 +2557,45 @@ mod tests {
 	use crate::ln::channelmanager::{PaymentId, RecipientOnionFields};
 	use crate::ln::features::{ChannelFeatures, NodeFeatures};
 	use crate::ln::msgs::{ErrorAction, LightningError};
-	use crate::ln::outbound_payment::{OutboundPayments, Retry, RetryableSendFailure};
+	use crate::ln::outbound_payment::{MAX_PAYMENT_TRACE_EVENTS, OutboundPayments, PaymentTraceEvent, ResolvedPaymentStatus, Retry, RetryableSendFailure};
 	use crate::routing::gossip::NetworkGraph;
 	use crate::routing::router::{InFlightHtlcs, Path, PaymentParameters, Route, RouteHop, RouteParameters};
 	use crate::sync::{Arc, Mutex};
+	use crate::util::config::OutboundHtlcExposureConfig;
 	use crate::util::errors::APIError;
 	use crate::util::test_utils;
 
 	use alloc::collections::VecDeque;
 
+	#[test]
+	fn payment_traces_are_bounded() {
+		let outbound_payments = OutboundPayments::new(0, OutboundHtlcExposureConfig::default(), 1);
+		let payment_id = PaymentId([42; 32]);
+		let other_payment_id = PaymentId([43; 32]);
+
+		// Events of payments whose trace wasn't started aren't traced.
+		outbound_payments.trace_payment(payment_id, PaymentTraceEvent::InvoiceReceived);
+		assert!(outbound_payments.payment_trace(&payment_id).is_none());
+
+		// Each trace only keeps its latest events.
+		outbound_payments.start_payment_trace(payment_id);
+		for _ in 0..MAX_PAYMENT_TRACE_EVENTS + 1 {
+			outbound_payments.trace_invoice_request(payment_id);
+		}
+		let trace = outbound_payments.payment_trace(&payment_id).unwrap();
+		assert_eq!(trace.len(), MAX_PAYMENT_TRACE_EVENTS);
+		assert_eq!(trace[0], PaymentTraceEvent::InvoiceRequested { attempt: 2 });
+
+		// Once a payment's trace is dropped for a more recent one, it isn't recreated.
+		outbound_payments.start_payment_trace(other_payment_id);
+		assert!(outbound_payments.payment_trace(&payment_id).is_none());
+		outbound_payments.record_resolved_payment(payment_id, ResolvedPaymentStatus::Abandoned {
+			payment_hash: PaymentHash([42; 32]), reason: None,
+		});
+		assert!(outbound_payments.payment_trace(&payment_id).is_none());
+		assert_eq!(outbound_payments.payment_trace(&other_payment_id), Some(Vec::new()));
+	}
+
 	#[test]
 	#[cfg(feature = "std")]
 	fn fails_paying_after_expiration() {
@@ -1455,7 +2604,7 @@ mod tests {
 	}
 	#[cfg(feature = "std")]
 	fn do_fails_paying_after_expiration(on_retry: bool) {
-		let outbound_payments = OutboundPayments::new();
+		let outbound_payments = OutboundPayments::new(0, OutboundHtlcExposureConfig::default(), 0);
 		let logger = test_utils::TestLogger::new();
 		let network_graph = Arc::new(NetworkGraph::new(Network::Testnet, &logger));
 		let scorer = Mutex::new(test_utils::TestScorer::new());
@@ -1479,7 +2628,7 @@ mod tests {
 				Some(Retry::Attempts(1)), Some(expired_route_params.payment_params.clone()),
 				&&keys_manager, 0).unwrap();
 			outbound_payments.retry_payment_internal(
-				PaymentHash([0; 32]), PaymentId([0; 32]), expired_route_params, &&router, vec![],
+				PaymentHash([0; 32]), PaymentId([0; 32]), expired_route_params, None, &&router, vec![],
 				&|| InFlightHtlcs::new(), &&keys_manager, &&keys_manager, 0, &&logger,
 				&pending_events, &|_, _, _, _, _, _, _, _| Ok(()));
 			let events = pending_events.lock().unwrap();
@@ -1503,7 +2652,7 @@ mod tests {
 		do_find_route_error(true);
 	}
 	fn do_find_route_error(on_retry: bool) {
-		let outbound_payments = OutboundPayments::new();
+		let outbound_payments = OutboundPayments::new(0, OutboundHtlcExposureConfig::default(), 0);
 		let logger = test_utils::TestLogger::new();
 		let network_graph = Arc::new(NetworkGraph::new(Network::Testnet, &logger));
 		let scorer = Mutex::new(test_utils::TestScorer::new());
@@ -1527,7 +2676,7 @@ mod tests {
 				Some(Retry::Attempts(1)), Some(route_params.payment_params.clone()),
 				&&keys_manager, 0).unwrap();
 			outbound_payments.retry_payment_internal(
-				PaymentHash([0; 32]), PaymentId([0; 32]), route_params, &&router, vec![],
+				PaymentHash([0; 32]), PaymentId([0; 32]), route_params, None, &&router, vec![],
 				&|| InFlightHtlcs::new(), &&keys_manager, &&keys_manager, 0, &&logger,
 				&pending_events, &|_, _, _, _, _, _, _, _| Ok(()));
 			let events = pending_events.lock().unwrap();
@@ -1546,7 +2695,7 @@ mod tests {
 
 	#[test]
 	fn initial_send_payment_path_failed_evs() {
-		let outbound_payments = OutboundPayments::new();
+		let outbound_payments = OutboundPayments::new(0, OutboundHtlcExposureConfig::default(), 0);
 		let logger = test_utils::TestLogger::new();
 		let network_graph = Arc::new(NetworkGraph::new(Network::Testnet, &logger));
 		let scorer = Mutex::new(test_utils::TestScorer::new());
@@ -15,22 +15,27 @@ use crate::chain::{ChannelMonitorUpdateStatus, Confirm, Listen, Watch};
 use crate::chain::channelmonitor::{ANTI_REORG_DELAY, HTLC_FAIL_BACK_BUFFER, LATENCY_GRACE_PERIOD_BLOCKS};
 use crate::sign::EntropySource;
 use crate::chain::transaction::OutPoint;
-use crate::events::{ClosureReason, Event, HTLCDestination, MessageSendEvent, MessageSendEventsProvider, PathFailure, PaymentFailureReason};
+use crate::events::{ClosureReason, Event, HTLCDestination, MessageSendEvent, MessageSendEventsProvider, PathFailure, PaymentFailureReason, PaymentPurpose};
 use crate::ln::channel::EXPIRE_PREV_CONFIG_TICKS;
-use crate::ln::channelmanager::{BREAKDOWN_TIMEOUT, ChannelManager, MPP_TIMEOUT_TICKS, MIN_CLTV_EXPIRY_DELTA, PaymentId, PaymentSendFailure, IDEMPOTENCY_TIMEOUT_TICKS, RecentPaymentDetails, RecipientOnionFields, HTLCForwardInfo, PendingHTLCRouting, PendingAddHTLCInfo};
+use crate::ln::channelmanager::{BatchPayment, BatchPaymentError, BREAKDOWN_TIMEOUT, ChannelManager, CustomOnion, MPP_TIMEOUT_TICKS, MIN_CLTV_EXPIRY_DELTA, PaymentId, PaymentSendFailure, PaymentStatus, PaymentTraceEvent, IDEMPOTENCY_TIMEOUT_TICKS, RecentPaymentDetails, RecipientOnionFields, ResolvedPaymentStatus, SkimTarget, ExpectedSkim, HTLCForwardInfo, PendingHTLCRouting, PendingAddHTLCInfo};
 use crate::ln::features::InvoiceFeatures;
-use crate::ln::{msgs, PaymentSecret, PaymentPreimage};
+use crate::ln::{msgs, PaymentHash, PaymentSecret, PaymentPreimage};
 use crate::ln::msgs::ChannelMessageHandler;
-use crate::ln::outbound_payment::Retry;
+use crate::ln::onion_utils;
+use crate::ln::outbound_payment::{Retry, RetryableSendFailure, RouteOverride};
 use crate::routing::gossip::{EffectiveCapacity, RoutingFees};
-use crate::routing::router::{get_route, Path, PaymentParameters, Route, Router, RouteHint, RouteHintHop, RouteHop, RouteParameters, find_route};
+use crate::routing::router::{get_route, Path, PaymentParameters, PaymentPrivacyLevel, Route, Router, RouteHint, RouteHintHop, RouteHop, RouteParameters, find_route};
 use crate::routing::scoring::ChannelUsage;
+use crate::util::config::{ChannelConfigUpdate, FirstHopChannelSelection, OutboundHtlcExposureConfig};
 use crate::util::test_utils;
-use crate::util::errors::APIError;
+use crate::util::errors::{APIError, ClaimFundsError, OpenChannelError, SendHtlcError};
 use crate::util::ser::Writeable;
 use crate::util::string::UntrustedString;
 
+use bitcoin::hashes::Hash;
+use bitcoin::hashes::sha256::Hash as Sha256;
 use bitcoin::network::constants::Network;
+use bitcoin::secp256k1::{Secp256k1, SecretKey};
 
 use crate::prelude::*;
 
@@ -161,12 +166,126 @@ fn mpp_retry() {
 	claim_payment_along_route(&nodes[0], &[&[&nodes[1], &nodes[3]], &[&nodes[2], &nodes[3]]], false, payment_preimage);
 }
 
-fn do_mpp_receive_timeout(send_partial_mpp: bool) {
+#[test]
+fn mpp_retry_tracks_payment_attempts() {
+	// Test that the number of attempts of an MPP payment is tracked across retries and reported
+	// in both `ChannelManager::payment_status` and payment path failures.
 	let chanmon_cfgs = create_chanmon_cfgs(4);
 	let node_cfgs = create_node_cfgs(4, &chanmon_cfgs);
 	let node_chanmgrs = create_node_chanmgrs(4, &node_cfgs, &[None, None, None, None]);
 	let nodes = create_network(4, &node_cfgs, &node_chanmgrs);
 
+	let (chan_1_update, _, _, _) = create_announced_chan_between_nodes(&nodes, 0, 1);
+	let (chan_2_update, _, _, _) = create_announced_chan_between_nodes(&nodes, 0, 2);
+	let (chan_3_update, _, _, _) = create_announced_chan_between_nodes(&nodes, 1, 3);
+	let (chan_4_update, _, chan_4_id, _) = create_announced_chan_between_nodes(&nodes, 3, 2);
+	// Rebalance
+	send_payment(&nodes[3], &vec!(&nodes[2])[..], 1_500_000);
+
+	let amt_msat = 1_000_000;
+	let (mut route, payment_hash, payment_preimage, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[3], amt_msat);
+	let path = route.paths[0].clone();
+	route.paths.push(path);
+	route.paths[0].hops[0].pubkey = nodes[1].node.get_our_node_id();
+	route.paths[0].hops[0].short_channel_id = chan_1_update.contents.short_channel_id;
+	route.paths[0].hops[1].short_channel_id = chan_3_update.contents.short_channel_id;
+	route.paths[1].hops[0].pubkey = nodes[2].node.get_our_node_id();
+	route.paths[1].hops[0].short_channel_id = chan_2_update.contents.short_channel_id;
+	route.paths[1].hops[1].short_channel_id = chan_4_update.contents.short_channel_id;
+
+	// Initiate the MPP payment.
+	let payment_id = PaymentId(payment_hash.0);
+	let mut route_params = RouteParameters {
+		payment_params: route.payment_params.clone().unwrap(),
+		final_value_msat: amt_msat,
+	};
+
+	nodes[0].router.expect_find_route(route_params.clone(), Ok(route.clone()));
+	nodes[0].node.send_payment(payment_hash, RecipientOnionFields::secret_only(payment_secret),
+		payment_id, route_params.clone(), Retry::Attempts(1)).unwrap();
+	check_added_monitors!(nodes[0], 2); // one monitor per path
+	assert_eq!(nodes[0].node.payment_status(&payment_id), Some(PaymentStatus::Recent(RecentPaymentDetails::Pending {
+		payment_id, payment_hash, total_msat: amt_msat, attempts: 1,
+	})));
+	let mut events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 2);
+
+	// Pass half of the payment along the success path.
+	let success_path_msgs = remove_first_msg_event_to_node(&nodes[1].node.get_our_node_id(), &mut events);
+	pass_along_path(&nodes[0], &[&nodes[1], &nodes[3]], 2_000_000, payment_hash, Some(payment_secret), success_path_msgs, false, None);
+
+	// Add the HTLC along the first hop.
+	let fail_path_msgs_1 = remove_first_msg_event_to_node(&nodes[2].node.get_our_node_id(), &mut events);
+	let (update_add, commitment_signed) = match fail_path_msgs_1 {
+		MessageSendEvent::UpdateHTLCs { node_id: _, updates: msgs::CommitmentUpdate { ref update_add_htlcs, ref update_fulfill_htlcs, ref update_fail_htlcs, ref update_fail_malformed_htlcs, ref update_fee, ref commitment_signed } } => {
+			assert_eq!(update_add_htlcs.len(), 1);
+			assert!(update_fail_htlcs.is_empty());
+			assert!(update_fulfill_htlcs.is_empty());
+			assert!(update_fail_malformed_htlcs.is_empty());
+			assert!(update_fee.is_none());
+			(update_add_htlcs[0].clone(), commitment_signed.clone())
+		},
+		_ => panic!("Unexpected event"),
+	};
+	nodes[2].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &update_add);
+	commitment_signed_dance!(nodes[2], nodes[0], commitment_signed, false);
+
+	// Attempt to forward the payment and complete the 2nd path's failure.
+	expect_pending_htlcs_forwardable!(&nodes[2]);
+	expect_pending_htlcs_forwardable_and_htlc_handling_failed!(&nodes[2], vec![HTLCDestination::NextHopChannel { node_id: Some(nodes[3].node.get_our_node_id()), channel_id: chan_4_id }]);
+	let htlc_updates = get_htlc_update_msgs!(nodes[2], nodes[0].node.get_our_node_id());
+	assert!(htlc_updates.update_add_htlcs.is_empty());
+	assert_eq!(htlc_updates.update_fail_htlcs.len(), 1);
+	assert!(htlc_updates.update_fulfill_htlcs.is_empty());
+	assert!(htlc_updates.update_fail_malformed_htlcs.is_empty());
+	check_added_monitors!(nodes[2], 1);
+	nodes[0].node.handle_update_fail_htlc(&nodes[2].node.get_our_node_id(), &htlc_updates.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[2], htlc_updates.commitment_signed, false);
+	let mut events = nodes[0].node.get_and_clear_pending_events();
+	match events[1] {
+		Event::PendingHTLCsForwardable { .. } => {},
+		_ => panic!("Unexpected event")
+	}
+	events.remove(1);
+	match events[0] {
+		Event::PaymentPathFailed { payment_id: ev_payment_id, attempts, .. } => {
+			assert_eq!(ev_payment_id, Some(payment_id));
+			assert_eq!(attempts, Some(1));
+		},
+		_ => panic!("Unexpected event")
+	}
+	expect_payment_failed_conditions_event(events, payment_hash, false, PaymentFailedConditions::new().mpp_parts_remain());
+
+	// Rebalance the channel so the second half of the payment can succeed.
+	send_payment(&nodes[3], &vec!(&nodes[2])[..], 1_500_000);
+
+	// Retry the second half of the payment and make sure it succeeds.
+	route.paths.remove(0);
+	route_params.final_value_msat = 1_000_000;
+	route_params.payment_params.previously_failed_channels.push(chan_4_update.contents.short_channel_id);
+	nodes[0].router.expect_find_route(route_params, Ok(route));
+	nodes[0].node.process_pending_htlc_forwards();
+	check_added_monitors!(nodes[0], 1);
+	assert_eq!(nodes[0].node.payment_status(&payment_id), Some(PaymentStatus::Recent(RecentPaymentDetails::Pending {
+		payment_id, payment_hash, total_msat: amt_msat, attempts: 2,
+	})));
+	let mut events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	pass_along_path(&nodes[0], &[&nodes[2], &nodes[3]], 2_000_000, payment_hash, Some(payment_secret), events.pop().unwrap(), true, None);
+	claim_payment_along_route(&nodes[0], &[&[&nodes[1], &nodes[3]], &[&nodes[2], &nodes[3]]], false, payment_preimage);
+	assert_eq!(nodes[0].node.payment_status(&payment_id), Some(PaymentStatus::Recent(RecentPaymentDetails::Fulfilled {
+		payment_id, payment_hash: Some(payment_hash),
+	})));
+}
+
+fn do_mpp_receive_timeout(send_partial_mpp: bool, timeout_ticks: Option<u8>) {
+	let chanmon_cfgs = create_chanmon_cfgs(4);
+	let node_cfgs = create_node_cfgs(4, &chanmon_cfgs);
+	let mut config = test_default_channel_config();
+	if let Some(ticks) = timeout_ticks { config.mpp_receive_timeout_ticks = ticks; }
+	let node_chanmgrs = create_node_chanmgrs(4, &node_cfgs, &[None, None, None, Some(config)]);
+	let nodes = create_network(4, &node_cfgs, &node_chanmgrs);
+
 	let (chan_1_update, _, _, _) = create_announced_chan_between_nodes(&nodes, 0, 1);
 	let (chan_2_update, _, _, _) = create_announced_chan_between_nodes(&nodes, 0, 2);
 	let (chan_3_update, _, chan_3_id, _) = create_announced_chan_between_nodes(&nodes, 1, 3);
@@ -195,12 +314,25 @@ fn do_mpp_receive_timeout(send_partial_mpp: bool) {
 
 	if send_partial_mpp {
 		// Time out the partial MPP
-		for _ in 0..MPP_TIMEOUT_TICKS {
+		for _ in 1..timeout_ticks.unwrap_or(MPP_TIMEOUT_TICKS) {
 			nodes[3].node.timer_tick_occurred();
 		}
+		assert!(nodes[3].node.get_and_clear_pending_events().is_empty());
+		nodes[3].node.timer_tick_occurred();
 
 		// Failed HTLC from node 3 -> 1
-		expect_pending_htlcs_forwardable_and_htlc_handling_failed!(nodes[3], vec![HTLCDestination::FailedPayment { payment_hash }]);
+		let mut events = nodes[3].node.get_and_clear_pending_events();
+		assert_eq!(events.len(), 3);
+		match events.pop().unwrap() {
+			Event::PartialPaymentTimedOut { payment_hash: timed_out_hash, received_msat, total_msat } => {
+				assert_eq!(timed_out_hash, payment_hash);
+				assert_eq!(received_msat, 100_000);
+				assert_eq!(total_msat, 200_000);
+			},
+			_ => panic!("Unexpected event"),
+		}
+		expect_pending_htlcs_forwardable_conditions(events, &[HTLCDestination::FailedPayment { payment_hash }]);
+		nodes[3].node.process_pending_htlc_forwards();
 		let htlc_fail_updates_3_1 = get_htlc_update_msgs!(nodes[3], nodes[1].node.get_our_node_id());
 		assert_eq!(htlc_fail_updates_3_1.update_fail_htlcs.len(), 1);
 		nodes[1].node.handle_update_fail_htlc(&nodes[3].node.get_our_node_id(), &htlc_fail_updates_3_1.update_fail_htlcs[0]);
@@ -232,14 +364,23 @@ fn do_mpp_receive_timeout(send_partial_mpp: bool) {
 
 #[test]
 fn mpp_receive_timeout() {
-	do_mpp_receive_timeout(true);
-	do_mpp_receive_timeout(false);
+	do_mpp_receive_timeout(true, None);
+	do_mpp_receive_timeout(false, None);
+	do_mpp_receive_timeout(true, Some(5));
+	do_mpp_receive_timeout(false, Some(5));
 }
 
 #[test]
 fn test_mpp_keysend() {
+	do_test_mpp_keysend(false);
+	do_test_mpp_keysend(true);
+}
+
+fn do_test_mpp_keysend(register_payment_hash: bool) {
+	// Test that we accept MPP keysends either if our config allows them or if the payment hash was
+	// registered as accepting them.
 	let mut mpp_keysend_config = test_default_channel_config();
-	mpp_keysend_config.accept_mpp_keysend = true;
+	mpp_keysend_config.accept_mpp_keysend = !register_payment_hash;
 	let chanmon_cfgs = create_chanmon_cfgs(4);
 	let node_cfgs = create_node_cfgs(4, &chanmon_cfgs);
 	let node_chanmgrs = create_node_chanmgrs(4, &node_cfgs, &[None, None, None, Some(mpp_keysend_config)]);
@@ -268,6 +409,9 @@ fn test_mpp_keysend() {
 	let payment_hash = nodes[0].node.send_spontaneous_payment(&route, Some(payment_preimage),
 		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_preimage.0)).unwrap();
 	check_added_monitors!(nodes[0], 2);
+	if register_payment_hash {
+		nodes[3].node.register_keysend_payment_hash(payment_hash, true);
+	}
 
 	let expected_route: &[&[&Node]] = &[&[&nodes[1], &nodes[3]], &[&nodes[2], &nodes[3]]];
 	let mut events = nodes[0].node.get_and_clear_pending_msg_events();
@@ -429,8 +573,7 @@ fn no_pending_leak_on_initial_send_failure() {
 
 	unwrap_send_err!(nodes[0].node.send_payment_with_route(&route, payment_hash,
 			RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)
-		), true, APIError::ChannelUnavailable { ref err },
-		assert_eq!(err, "Peer for first hop currently disconnected"));
+		), true, APIError::ChannelCannotSendHtlc { err: SendHtlcError::PeerDisconnected { .. } }, {});
 
 	assert!(!nodes[0].node.has_pending_payments());
 }
@@ -1377,6 +1520,275 @@ fn abandoned_send_payment_idempotent() {
 	claim_payment(&nodes[0], &[&nodes[1]], second_payment_preimage);
 }
 
+#[test]
+fn resolved_payment_ids_remembered_across_reload() {
+	// Tests that with `UserConfig::resolved_payment_history_limit` set, `PaymentId`s of resolved
+	// payments are remembered across restarts and may not be reused until they are evicted.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let mut config = test_default_channel_config();
+	config.resolved_payment_history_limit = 1;
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[Some(config), None]);
+	let persister;
+	let new_chain_monitor;
+	let nodes_0_deserialized;
+	let mut nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let chan_id = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+
+	let (route, first_payment_hash, first_payment_preimage, first_payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[1], 100_000);
+	let (_, second_payment_hash, second_payment_preimage, second_payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[1], 100_000);
+	let payment_id = PaymentId(first_payment_hash.0);
+	nodes[0].node.send_payment_with_route(&route, first_payment_hash,
+		RecipientOnionFields::secret_only(first_payment_secret), payment_id).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	pass_along_route(&nodes[0], &[&[&nodes[1]]], 100_000, first_payment_hash, first_payment_secret);
+	assert_eq!(nodes[0].node.resolved_payment_status(&payment_id), None);
+	claim_payment(&nodes[0], &[&nodes[1]], first_payment_preimage);
+
+	// Once the payment is no longer tracked as pending, it is still remembered as resolved.
+	for _ in 0..=IDEMPOTENCY_TIMEOUT_TICKS {
+		nodes[0].node.timer_tick_occurred();
+	}
+	assert!(nodes[0].node.list_recent_payments().is_empty());
+	let fulfilled_status = ResolvedPaymentStatus::Fulfilled { payment_hash: Some(first_payment_hash) };
+	assert_eq!(nodes[0].node.resolved_payment_status(&payment_id), Some(fulfilled_status));
+
+	let chan_0_monitor_serialized = get_monitor!(nodes[0], chan_id).encode();
+	reload_node!(nodes[0], config, &nodes[0].node.encode(), &[&chan_0_monitor_serialized], persister, new_chain_monitor, nodes_0_deserialized);
+	reconnect_nodes(&nodes[0], &nodes[1], (false, false), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (false, false));
+
+	// After the restart, the payment is still remembered and reusing its `PaymentId` is rejected.
+	assert_eq!(nodes[0].node.resolved_payment_status(&payment_id), Some(fulfilled_status));
+	match nodes[0].node.send_payment_with_route(&route, second_payment_hash,
+		RecipientOnionFields::secret_only(second_payment_secret), payment_id)
+	{
+		Err(PaymentSendFailure::DuplicatePayment) => {},
+		res => panic!("Unexpected send result: {:?}", res),
+	}
+
+	// Once another payment is resolved, the first is evicted and its `PaymentId` may be reused.
+	let (route, failed_payment_hash, _, failed_payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[1], 100_000);
+	let failed_payment_id = PaymentId(failed_payment_hash.0);
+	nodes[0].node.send_payment_with_route(&route, failed_payment_hash,
+		RecipientOnionFields::secret_only(failed_payment_secret), failed_payment_id).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	pass_along_route(&nodes[0], &[&[&nodes[1]]], 100_000, failed_payment_hash, failed_payment_secret);
+	fail_payment(&nodes[0], &[&nodes[1]], failed_payment_hash);
+	assert_eq!(nodes[0].node.resolved_payment_status(&failed_payment_id), Some(ResolvedPaymentStatus::Abandoned {
+		payment_hash: failed_payment_hash, reason: Some(PaymentFailureReason::RecipientRejected),
+	}));
+	assert_eq!(nodes[0].node.resolved_payment_status(&payment_id), None);
+
+	nodes[0].node.send_payment_with_route(&route, second_payment_hash,
+		RecipientOnionFields::secret_only(second_payment_secret), payment_id).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	pass_along_route(&nodes[0], &[&[&nodes[1]]], 100_000, second_payment_hash, second_payment_secret);
+	claim_payment(&nodes[0], &[&nodes[1]], second_payment_preimage);
+}
+
+#[test]
+fn payment_trace_timeline() {
+	// Tests that with `UserConfig::payment_trace_limit` set, the timeline of each payment is
+	// available via `ChannelManager::payment_trace`, and that only the most recent ones are kept.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let mut config = test_default_channel_config();
+	config.payment_trace_limit = 1;
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[Some(config), None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	let amt_msat = 100_000;
+	let (route, failed_payment_hash, _, failed_payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[1], amt_msat);
+	let route_params = RouteParameters {
+		payment_params: route.payment_params.clone().unwrap(),
+		final_value_msat: amt_msat,
+	};
+	let failed_payment_id = PaymentId(failed_payment_hash.0);
+	nodes[0].router.expect_find_route(route_params.clone(), Ok(route.clone()));
+	nodes[0].node.send_payment(failed_payment_hash, RecipientOnionFields::secret_only(failed_payment_secret),
+		failed_payment_id, route_params.clone(), Retry::Attempts(0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	pass_along_route(&nodes[0], &[&[&nodes[1]]], amt_msat, failed_payment_hash, failed_payment_secret);
+	fail_payment(&nodes[0], &[&nodes[1]], failed_payment_hash);
+
+	let trace = nodes[0].node.payment_trace(&failed_payment_id).unwrap();
+	assert_eq!(trace.len(), 4);
+	assert_eq!(trace[0], PaymentTraceEvent::RouteFound {
+		attempt: 1, path_count: 1, amount_msat: amt_msat, fee_msat: 0,
+	});
+	assert_eq!(trace[1], PaymentTraceEvent::HTLCSent { path: route.paths[0].clone() });
+	match &trace[2] {
+		PaymentTraceEvent::HTLCFailed { path, payment_failed_permanently: true, .. } =>
+			assert_eq!(*path, route.paths[0]),
+		ev => panic!("Unexpected trace event: {:?}", ev),
+	}
+	assert_eq!(trace[3], PaymentTraceEvent::Resolved {
+		status: ResolvedPaymentStatus::Abandoned {
+			payment_hash: failed_payment_hash, reason: Some(PaymentFailureReason::RecipientRejected),
+		},
+	});
+
+	// Once another payment is traced, the timeline of the first one is dropped.
+	let (route, payment_hash, payment_preimage, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[1], amt_msat);
+	let payment_id = PaymentId(payment_hash.0);
+	nodes[0].router.expect_find_route(route_params.clone(), Ok(route.clone()));
+	nodes[0].node.send_payment(payment_hash, RecipientOnionFields::secret_only(payment_secret),
+		payment_id, route_params, Retry::Attempts(0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	assert!(nodes[0].node.payment_trace(&failed_payment_id).is_none());
+	pass_along_route(&nodes[0], &[&[&nodes[1]]], amt_msat, payment_hash, payment_secret);
+	claim_payment(&nodes[0], &[&nodes[1]], payment_preimage);
+
+	assert_eq!(nodes[0].node.payment_trace(&payment_id).unwrap(), vec![
+		PaymentTraceEvent::RouteFound { attempt: 1, path_count: 1, amount_msat: amt_msat, fee_msat: 0 },
+		PaymentTraceEvent::HTLCSent { path: route.paths[0].clone() },
+		PaymentTraceEvent::HTLCClaimed { path: route.paths[0].clone() },
+		PaymentTraceEvent::Resolved {
+			status: ResolvedPaymentStatus::Fulfilled { payment_hash: Some(payment_hash) },
+		},
+	]);
+}
+
+#[test]
+fn settled_payment_preimages_retained() {
+	// Tests that the preimages of payments we claimed or sent are available via
+	// `ChannelManager::payment_preimage` for `UserConfig::payment_preimage_retention_ticks`,
+	// including across restarts.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let mut config = test_default_channel_config();
+	config.payment_preimage_retention_ticks = 2;
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[Some(config), Some(config)]);
+	let persister;
+	let new_chain_monitor;
+	let nodes_0_deserialized;
+	let mut nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let chan_id = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+
+	let (payment_preimage, payment_hash, _) = route_payment(&nodes[0], &[&nodes[1]], 100_000);
+	assert_eq!(nodes[0].node.payment_preimage(&payment_hash), None);
+	assert_eq!(nodes[1].node.payment_preimage(&payment_hash), None);
+	claim_payment(&nodes[0], &[&nodes[1]], payment_preimage);
+	assert_eq!(nodes[0].node.payment_preimage(&payment_hash), Some(payment_preimage));
+	assert_eq!(nodes[1].node.payment_preimage(&payment_hash), Some(payment_preimage));
+	assert_eq!(nodes[0].node.payment_preimage(&PaymentHash([42; 32])), None);
+
+	nodes[0].node.timer_tick_occurred();
+	let chan_0_monitor_serialized = get_monitor!(nodes[0], chan_id).encode();
+	reload_node!(nodes[0], config, &nodes[0].node.encode(), &[&chan_0_monitor_serialized], persister, new_chain_monitor, nodes_0_deserialized);
+	assert_eq!(nodes[0].node.payment_preimage(&payment_hash), Some(payment_preimage));
+
+	nodes[0].node.timer_tick_occurred();
+	assert_eq!(nodes[0].node.payment_preimage(&payment_hash), Some(payment_preimage));
+	nodes[0].node.timer_tick_occurred();
+	assert_eq!(nodes[0].node.payment_preimage(&payment_hash), None);
+
+	for _ in 0..3 {
+		nodes[1].node.timer_tick_occurred();
+	}
+	assert_eq!(nodes[1].node.payment_preimage(&payment_hash), None);
+}
+
+#[test]
+fn forwarding_channel_stats() {
+	// Tests that forwarding statistics are tracked for the outbound channel of forwarded HTLCs
+	// and, if configured, persisted.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let mut config = test_default_channel_config();
+	config.persist_channel_stats = true;
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, Some(config), None]);
+	let persister;
+	let new_chain_monitor;
+	let nodes_1_deserialized;
+	let mut nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	let chan_id_1 = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+	let chan_id_2 = create_announced_chan_between_nodes(&nodes, 1, 2).2;
+	assert_eq!(nodes[1].node.channel_stats(&chan_id_2), None);
+
+	let (route, payment_hash, payment_preimage, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[2], 100_000);
+	send_along_route_with_secret(&nodes[0], route.clone(), &[&[&nodes[1], &nodes[2]]], 100_000, payment_hash, payment_secret);
+	claim_payment(&nodes[0], &[&nodes[1], &nodes[2]], payment_preimage);
+	let (_, failed_payment_hash, _) = route_payment(&nodes[0], &[&nodes[1], &nodes[2]], 100_000);
+	fail_payment(&nodes[0], &[&nodes[1], &nodes[2]], failed_payment_hash);
+
+	let stats = nodes[1].node.channel_stats(&chan_id_2).unwrap();
+	assert_eq!(stats.forwards_succeeded, 1);
+	assert_eq!(stats.forwarded_amount_msat, 100_000);
+	assert_eq!(stats.fees_earned_msat, route.paths[0].hops[0].fee_msat);
+	assert!(stats.local_forward_failures.is_empty());
+	assert_eq!(stats.downstream_forward_failures, 1);
+	assert_eq!(stats.outbound_capacity_samples, 0);
+	assert_eq!(nodes[1].node.channel_stats(&chan_id_1), None);
+
+	nodes[1].node.timer_tick_occurred();
+	let stats = nodes[1].node.channel_stats(&chan_id_2).unwrap();
+	assert_eq!(stats.outbound_capacity_samples, 1);
+	let outbound_capacity_msat = nodes[1].node.list_channels().iter()
+		.find(|details| details.channel_id == chan_id_2).unwrap().outbound_capacity_msat;
+	assert_eq!(stats.average_outbound_capacity_msat, outbound_capacity_msat);
+	assert_eq!(nodes[1].node.channel_stats(&chan_id_1).unwrap().outbound_capacity_samples, 1);
+
+	let chan_1_monitor_serialized = get_monitor!(nodes[1], chan_id_1).encode();
+	let chan_2_monitor_serialized = get_monitor!(nodes[1], chan_id_2).encode();
+	reload_node!(nodes[1], config, &nodes[1].node.encode(), &[&chan_1_monitor_serialized, &chan_2_monitor_serialized], persister, new_chain_monitor, nodes_1_deserialized);
+	assert_eq!(nodes[1].node.channel_stats(&chan_id_2), Some(stats));
+
+	nodes[1].node.reset_channel_stats();
+	assert_eq!(nodes[1].node.channel_stats(&chan_id_2), None);
+}
+
+#[test]
+fn expired_inbound_payments_generate_events() {
+	// Tests that `Event::InboundPaymentExpired` is generated for payments which expired without
+	// being paid, but not for ones which were paid, and that HTLCs arriving late are failed.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let mut config = test_default_channel_config();
+	config.generate_inbound_payment_expired_events = true;
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, Some(config)]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	let (paid_preimage, _, _) = route_payment(&nodes[0], &[&nodes[1]], 100_000);
+	claim_payment(&nodes[0], &[&nodes[1]], paid_preimage);
+	let (route, expired_hash, _, expired_secret) = get_route_and_payment_hash!(nodes[0], nodes[1], 100_000);
+
+	nodes[1].node.timer_tick_occurred();
+	assert!(nodes[1].node.get_and_clear_pending_events().is_empty());
+
+	// Inbound payments expire two hours after their expiry time, see
+	// `ChannelManager::create_inbound_payment`.
+	let block = create_dummy_block(nodes[1].best_block_hash(), 7200 * 3, Vec::new());
+	connect_block(&nodes[0], &block);
+	connect_block(&nodes[1], &block);
+	nodes[1].node.timer_tick_occurred();
+	let events = nodes[1].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		Event::InboundPaymentExpired { payment_hash } => assert_eq!(payment_hash, expired_hash),
+		_ => panic!("Unexpected event"),
+	}
+	nodes[1].node.timer_tick_occurred();
+	assert!(nodes[1].node.get_and_clear_pending_events().is_empty());
+
+	nodes[0].node.send_payment_with_route(&route, expired_hash,
+		RecipientOnionFields::secret_only(expired_secret), PaymentId(expired_hash.0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	let mut events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	let payment_event = SendEvent::from_event(events.pop().unwrap());
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &payment_event.msgs[0]);
+	commitment_signed_dance!(nodes[1], nodes[0], payment_event.commitment_msg, false);
+	expect_pending_htlcs_forwardable_and_htlc_handling_failed!(nodes[1], vec![HTLCDestination::FailedPayment { payment_hash: expired_hash }]);
+	check_added_monitors!(nodes[1], 1);
+	let updates = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &updates.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], updates.commitment_signed, false);
+	expect_payment_failed!(nodes[0], expired_hash, true);
+}
+
 #[derive(PartialEq)]
 enum InterceptTest {
 	Forward,
@@ -1428,7 +1840,7 @@ fn test_trivial_inflight_htlc_tracking(){
 	}
 	let pending_payments = nodes[0].node.list_recent_payments();
 	assert_eq!(pending_payments.len(), 1);
-	assert_eq!(pending_payments[0], RecentPaymentDetails::Fulfilled { payment_hash: Some(payment_hash) });
+	assert_eq!(pending_payments[0], RecentPaymentDetails::Fulfilled { payment_id: PaymentId(payment_hash.0), payment_hash: Some(payment_hash) });
 
 	// Remove fulfilled payment
 	for _ in 0..=IDEMPOTENCY_TIMEOUT_TICKS {
@@ -1466,7 +1878,7 @@ fn test_trivial_inflight_htlc_tracking(){
 	}
 	let pending_payments = nodes[0].node.list_recent_payments();
 	assert_eq!(pending_payments.len(), 1);
-	assert_eq!(pending_payments[0], RecentPaymentDetails::Pending { payment_hash, total_msat: 500000 });
+	assert_eq!(pending_payments[0], RecentPaymentDetails::Pending { payment_id: PaymentId(payment_hash.0), payment_hash, total_msat: 500000, attempts: 1 });
 
 	// Now, let's claim the payment. This should result in the used liquidity to return `None`.
 	claim_payment(&nodes[0], &[&nodes[1], &nodes[2]], payment_preimage);
@@ -1736,35 +2148,761 @@ fn do_test_intercepted_payment(test: InterceptTest) {
 	}
 }
 
-#[derive(PartialEq)]
-enum AutoRetry {
-	Success,
-	Spontaneous,
-	FailAttempts,
-	FailTimeout,
-	FailOnRestart,
-	FailOnRetry,
-}
-
 #[test]
-fn automatic_retries() {
-	do_automatic_retries(AutoRetry::Success);
-	do_automatic_retries(AutoRetry::Spontaneous);
-	do_automatic_retries(AutoRetry::FailAttempts);
-	do_automatic_retries(AutoRetry::FailTimeout);
-	do_automatic_retries(AutoRetry::FailOnRestart);
-	do_automatic_retries(AutoRetry::FailOnRetry);
-}
-fn do_automatic_retries(test: AutoRetry) {
-	// Test basic automatic payment retries in ChannelManager. See individual `test` variant comments
-	// below.
+fn intercepted_htlc_auto_fail_deadline() {
+	// Tests that an intercepted HTLC which is neither forwarded nor failed is failed back
+	// `intercepted_htlc_auto_fail_cltv_delta` blocks ahead of the inbound HTLC's expiry, after an
+	// `Event::HTLCInterceptExpiring` warning.
 	let chanmon_cfgs = create_chanmon_cfgs(3);
 	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
-	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+	let mut intercept_forwards_config = test_default_channel_config();
+	intercept_forwards_config.accept_intercept_htlcs = true;
+	intercept_forwards_config.intercepted_htlc_auto_fail_cltv_delta = Some(100);
+	intercept_forwards_config.intercepted_htlc_expiry_warning_blocks = 6;
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, Some(intercept_forwards_config), None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	let scorer = test_utils::TestScorer::new();
+	let random_seed_bytes = chanmon_cfgs[0].keys_manager.get_secure_random_bytes();
 
-	let persister;
-	let new_chain_monitor;
-	let node_0_deserialized;
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	let amt_msat = 100_000;
+	let intercept_scid = nodes[1].node.get_intercept_scid();
+	let payment_params = PaymentParameters::from_node_id(nodes[2].node.get_our_node_id(), TEST_FINAL_CLTV)
+		.with_route_hints(vec![
+			RouteHint(vec![RouteHintHop {
+				src_node_id: nodes[1].node.get_our_node_id(),
+				short_channel_id: intercept_scid,
+				fees: RoutingFees {
+					base_msat: 1000,
+					proportional_millionths: 0,
+				},
+				cltv_expiry_delta: MIN_CLTV_EXPIRY_DELTA,
+				htlc_minimum_msat: None,
+				htlc_maximum_msat: None,
+			}])
+		]).unwrap()
+		.with_bolt11_features(nodes[2].node.invoice_features()).unwrap();
+	let route = get_route(
+		&nodes[0].node.get_our_node_id(), &payment_params, &nodes[0].network_graph.read_only(), None,
+		amt_msat, nodes[0].logger, &scorer, &(), &random_seed_bytes,
+	).unwrap();
+
+	let (payment_hash, payment_secret) = nodes[2].node.create_inbound_payment(Some(amt_msat), 60 * 60, None).unwrap();
+	nodes[0].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	let payment_event = SendEvent::from_node(&nodes[0]);
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &payment_event.msgs[0]);
+	commitment_signed_dance!(nodes[1], nodes[0], &payment_event.commitment_msg, false, true);
+
+	let events = nodes[1].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	let intercept_id = match events[0] {
+		Event::HTLCIntercepted { intercept_id, .. } => intercept_id,
+		_ => panic!("Unexpected event"),
+	};
+
+	let intercepted_htlcs = nodes[1].node.list_intercepted_htlcs();
+	assert_eq!(intercepted_htlcs.len(), 1);
+	assert_eq!(intercepted_htlcs[0].intercept_id, intercept_id);
+	assert_eq!(intercepted_htlcs[0].requested_next_hop_scid, intercept_scid);
+	assert_eq!(intercepted_htlcs[0].inbound_cltv_expiry, Some(payment_event.msgs[0].cltv_expiry));
+	let auto_fail_height = intercepted_htlcs[0].auto_fail_height;
+	assert_eq!(auto_fail_height, payment_event.msgs[0].cltv_expiry - 100);
+
+	// No warning is generated until the deadline is six blocks away, and only once.
+	let blocks_to_warning = auto_fail_height - 6 - nodes[1].best_block_info().1;
+	connect_blocks(&nodes[0], blocks_to_warning - 1);
+	connect_blocks(&nodes[1], blocks_to_warning - 1);
+	assert!(nodes[1].node.get_and_clear_pending_events().is_empty());
+	connect_blocks(&nodes[0], 1);
+	connect_blocks(&nodes[1], 1);
+	let events = nodes[1].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		Event::HTLCInterceptExpiring { intercept_id: id, requested_next_hop_scid, payment_hash: hash, auto_fail_height: height } => {
+			assert_eq!(id, intercept_id);
+			assert_eq!(requested_next_hop_scid, intercept_scid);
+			assert_eq!(hash, payment_hash);
+			assert_eq!(height, auto_fail_height);
+		},
+		_ => panic!("Unexpected event"),
+	}
+	connect_blocks(&nodes[0], 5);
+	connect_blocks(&nodes[1], 5);
+	assert!(nodes[1].node.get_and_clear_pending_events().is_empty());
+
+	// Once the deadline is reached, the HTLC is failed back.
+	connect_blocks(&nodes[0], 1);
+	connect_blocks(&nodes[1], 1);
+	expect_pending_htlcs_forwardable_and_htlc_handling_failed!(nodes[1], vec![HTLCDestination::InvalidForward { requested_forward_scid: intercept_scid }]);
+	check_added_monitors!(nodes[1], 1);
+	let htlc_timeout_updates = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	assert_eq!(htlc_timeout_updates.update_fail_htlcs.len(), 1);
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &htlc_timeout_updates.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], htlc_timeout_updates.commitment_signed, false);
+	expect_payment_failed!(nodes[0], payment_hash, false, 0x2000 | 2, []);
+	assert!(nodes[1].node.list_intercepted_htlcs().is_empty());
+}
+
+#[test]
+fn accepts_expected_skim_from_intercepting_counterparty() {
+	// Tests that an LSP which intercepts an HTLC may skim its fee off the amount it forwards on to
+	// the recipient, as long as the recipient registered the skim.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+
+	let mut zero_conf_chan_config = test_default_channel_config();
+	zero_conf_chan_config.manually_accept_inbound_channels = true;
+	let mut intercept_forwards_config = test_default_channel_config();
+	intercept_forwards_config.accept_intercept_htlcs = true;
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, Some(intercept_forwards_config), Some(zero_conf_chan_config)]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	let amt_msat = 100_000;
+	let skimmed_fee_msat = 2_000;
+	let intercept_scid = nodes[1].node.get_intercept_scid();
+	let payment_params = PaymentParameters::from_node_id(nodes[2].node.get_our_node_id(), TEST_FINAL_CLTV)
+		.with_route_hints(vec![
+			RouteHint(vec![RouteHintHop {
+				src_node_id: nodes[1].node.get_our_node_id(),
+				short_channel_id: intercept_scid,
+				fees: RoutingFees {
+					base_msat: 1000,
+					proportional_millionths: 0,
+				},
+				cltv_expiry_delta: MIN_CLTV_EXPIRY_DELTA,
+				htlc_minimum_msat: None,
+				htlc_maximum_msat: None,
+			}])
+		]).unwrap()
+		.with_bolt11_features(nodes[2].node.invoice_features()).unwrap();
+	let route = get_route!(nodes[0], payment_params, amt_msat).unwrap();
+
+	nodes[2].node.register_expected_skim(SkimTarget::Counterparty(nodes[1].node.get_our_node_id()),
+		ExpectedSkim::Amount(skimmed_fee_msat));
+
+	let (payment_hash, payment_secret) = nodes[2].node.create_inbound_payment(Some(amt_msat), 60 * 60, None).unwrap();
+	nodes[0].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	let payment_event = SendEvent::from_node(&nodes[0]);
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &payment_event.msgs[0]);
+	commitment_signed_dance!(nodes[1], nodes[0], &payment_event.commitment_msg, false, true);
+
+	let events = nodes[1].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	let (intercept_id, expected_outbound_amount_msat) = match events[0] {
+		Event::HTLCIntercepted { intercept_id, expected_outbound_amount_msat, .. } =>
+			(intercept_id, expected_outbound_amount_msat),
+		_ => panic!("Unexpected event")
+	};
+	assert_eq!(expected_outbound_amount_msat, amt_msat);
+
+	// Open the just-in-time channel and forward the HTLC on, less the skimmed fee.
+	let (_, channel_id) = open_zero_conf_channel(&nodes[1], &nodes[2], None);
+	nodes[1].node.forward_intercepted_htlc(intercept_id, &channel_id, nodes[2].node.get_our_node_id(),
+		expected_outbound_amount_msat - skimmed_fee_msat).unwrap();
+	expect_pending_htlcs_forwardable!(nodes[1]);
+	check_added_monitors!(nodes[1], 1);
+	let payment_event = SendEvent::from_node(&nodes[1]);
+	nodes[2].node.handle_update_add_htlc(&nodes[1].node.get_our_node_id(), &payment_event.msgs[0]);
+	commitment_signed_dance!(nodes[2], nodes[1], &payment_event.commitment_msg, false, true);
+	expect_pending_htlcs_forwardable!(nodes[2]);
+
+	let events = nodes[2].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		Event::PaymentClaimable { payment_hash: ev_hash, amount_msat, counterparty_skimmed_fee_msat, .. } => {
+			assert_eq!(ev_hash, payment_hash);
+			assert_eq!(amount_msat, amt_msat - skimmed_fee_msat);
+			assert_eq!(counterparty_skimmed_fee_msat, skimmed_fee_msat);
+		},
+		_ => panic!("Unexpected event")
+	}
+
+	assert!(nodes[2].node.unregister_expected_skim(&SkimTarget::Counterparty(nodes[1].node.get_our_node_id())));
+	assert!(!nodes[2].node.unregister_expected_skim(&SkimTarget::Counterparty(nodes[1].node.get_our_node_id())));
+}
+
+#[test]
+fn test_managed_intercept_scids() {
+	// Tests the lifecycle of intercept SCIDs created via `create_intercept_scid`, which expire if
+	// unused and may be revoked, failing back any HTLCs intercepted over them.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let mut intercept_forwards_config = test_default_channel_config();
+	intercept_forwards_config.accept_intercept_htlcs = true;
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, Some(intercept_forwards_config), None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	let client_node_id = nodes[2].node.get_our_node_id();
+	let intercept_scid = nodes[1].node.create_intercept_scid(client_node_id, 1000);
+	let unused_scid = nodes[1].node.create_intercept_scid(client_node_id, 10);
+	assert_ne!(intercept_scid, unused_scid);
+	let mut intercept_scids = nodes[1].node.list_intercept_scids();
+	intercept_scids.sort_by_key(|details| details.ttl_secs);
+	assert_eq!(intercept_scids.len(), 2);
+	assert_eq!(intercept_scids[0].short_channel_id, unused_scid);
+	assert_eq!(intercept_scids[1].short_channel_id, intercept_scid);
+	assert!(intercept_scids.iter().all(|details| details.client_node_id == client_node_id && details.last_used_at.is_none()));
+
+	// Once its TTL has passed, the unused intercept SCID is removed on the next timer tick.
+	connect_blocks(&nodes[0], 20);
+	connect_blocks(&nodes[1], 20);
+	nodes[1].node.timer_tick_occurred();
+	let intercept_scids = nodes[1].node.list_intercept_scids();
+	assert_eq!(intercept_scids.len(), 1);
+	assert_eq!(intercept_scids[0].short_channel_id, intercept_scid);
+
+	let amt_msat = 100_000;
+	let payment_params = PaymentParameters::from_node_id(client_node_id, TEST_FINAL_CLTV)
+		.with_route_hints(vec![
+			RouteHint(vec![RouteHintHop {
+				src_node_id: nodes[1].node.get_our_node_id(),
+				short_channel_id: intercept_scid,
+				fees: RoutingFees {
+					base_msat: 1000,
+					proportional_millionths: 0,
+				},
+				cltv_expiry_delta: MIN_CLTV_EXPIRY_DELTA,
+				htlc_minimum_msat: None,
+				htlc_maximum_msat: None,
+			}])
+		]).unwrap()
+		.with_bolt11_features(nodes[2].node.invoice_features()).unwrap();
+	let route = get_route!(nodes[0], payment_params, amt_msat).unwrap();
+	let (payment_hash, payment_secret) = nodes[2].node.create_inbound_payment(Some(amt_msat), 60 * 60, None).unwrap();
+	nodes[0].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	let payment_event = SendEvent::from_node(&nodes[0]);
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &payment_event.msgs[0]);
+	commitment_signed_dance!(nodes[1], nodes[0], &payment_event.commitment_msg, false, true);
+
+	let events = nodes[1].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		Event::HTLCIntercepted { requested_next_hop_scid, .. } => assert_eq!(requested_next_hop_scid, intercept_scid),
+		_ => panic!("Unexpected event")
+	}
+	assert!(nodes[1].node.list_intercept_scids()[0].last_used_at.is_some());
+
+	// Revoking the intercept SCID fails the intercepted HTLC back.
+	let revoked = nodes[1].node.revoke_intercept_scid(intercept_scid).unwrap();
+	assert_eq!(revoked.short_channel_id, intercept_scid);
+	assert!(nodes[1].node.list_intercept_scids().is_empty());
+	assert_eq!(nodes[1].node.revoke_intercept_scid(intercept_scid).unwrap_err(),
+		APIError::APIMisuseError { err: format!("Intercept SCID {} not found", intercept_scid) });
+
+	expect_pending_htlcs_forwardable_and_htlc_handling_failed_ignore!(nodes[1], vec![HTLCDestination::UnknownNextHop { requested_forward_scid: intercept_scid }]);
+	nodes[1].node.process_pending_htlc_forwards();
+	let update_fail = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	check_added_monitors!(&nodes[1], 1);
+	assert_eq!(update_fail.update_fail_htlcs.len(), 1);
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &update_fail.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], update_fail.commitment_signed, false);
+	let fail_conditions = PaymentFailedConditions::new()
+		.blamed_scid(intercept_scid)
+		.blamed_chan_closed(true)
+		.expected_htlc_error_data(0x4000 | 10, &[]);
+	expect_payment_failed_conditions(&nodes[0], payment_hash, false, fail_conditions);
+}
+
+/// Delivers the keysend payment sent by `nodes[0]` to `recipient`, returning its hash and
+/// preimage.
+fn pass_batch_payment<'a, 'b, 'c>(
+	nodes: &Vec<Node<'a, 'b, 'c>>, recipient: &Node<'a, 'b, 'c>, msg_events: &mut Vec<MessageSendEvent>
+) -> (PaymentHash, PaymentPreimage) {
+	let send_event = SendEvent::from_event(remove_first_msg_event_to_node(&recipient.node.get_our_node_id(), msg_events));
+	recipient.node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &send_event.msgs[0]);
+	commitment_signed_dance!(recipient, nodes[0], send_event.commitment_msg, false);
+	expect_pending_htlcs_forwardable!(recipient);
+	let events = recipient.node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		Event::PaymentClaimable { payment_hash, purpose: PaymentPurpose::SpontaneousPayment(preimage), .. } =>
+			(payment_hash, preimage),
+		_ => panic!("Unexpected event"),
+	}
+}
+
+#[test]
+fn test_batch_payments() {
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+	create_announced_chan_between_nodes(&nodes, 0, 2);
+
+	let amt_msat = 100_000;
+	let (batch_id, results) = nodes[0].node.send_batch_payments(vec![
+		(nodes[1].node.get_our_node_id(), amt_msat),
+		(nodes[2].node.get_our_node_id(), amt_msat),
+		// No route can be found for a payment which exceeds the capacity of our channels.
+		(nodes[2].node.get_our_node_id(), 1_000_000_000_000),
+	], Retry::Attempts(0));
+	assert_eq!(results, vec![
+		Ok(batch_id.payment_id(0)), Ok(batch_id.payment_id(1)), Err(RetryableSendFailure::RouteNotFound)
+	]);
+	assert_ne!(batch_id.payment_id(0), batch_id.payment_id(1));
+	check_added_monitors!(nodes[0], 2);
+
+	let mut msg_events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(msg_events.len(), 2);
+	let (_, preimage_1) = pass_batch_payment(&nodes, &nodes[1], &mut msg_events);
+	let (payment_hash_2, _) = pass_batch_payment(&nodes, &nodes[2], &mut msg_events);
+
+	// The batch is only completed once its last payment is resolved.
+	claim_payment(&nodes[0], &[&nodes[1]], preimage_1);
+
+	nodes[2].node.fail_htlc_backwards(&payment_hash_2);
+	expect_pending_htlcs_forwardable_and_htlc_handling_failed!(nodes[2], vec![HTLCDestination::FailedPayment { payment_hash: payment_hash_2 }]);
+	check_added_monitors!(nodes[2], 1);
+	let updates = get_htlc_update_msgs!(nodes[2], nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_update_fail_htlc(&nodes[2].node.get_our_node_id(), &updates.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[2], updates.commitment_signed, false);
+
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 3);
+	match events[0] {
+		Event::PaymentPathFailed { payment_hash, .. } => assert_eq!(payment_hash, payment_hash_2),
+		_ => panic!("Unexpected event"),
+	}
+	match events[1] {
+		Event::PaymentFailed { payment_id, .. } => assert_eq!(payment_id, batch_id.payment_id(1)),
+		_ => panic!("Unexpected event"),
+	}
+	match &events[2] {
+		Event::BatchPaymentCompleted { batch_id: completed_batch_id, successful_payment_ids, failed_payment_ids } => {
+			assert_eq!(*completed_batch_id, batch_id);
+			assert_eq!(*successful_payment_ids, vec![batch_id.payment_id(0)]);
+			let mut failed_payment_ids = failed_payment_ids.clone();
+			failed_payment_ids.sort_by_key(|payment_id| payment_id.0);
+			let mut expected_failed_ids = vec![batch_id.payment_id(1), batch_id.payment_id(2)];
+			expected_failed_ids.sort_by_key(|payment_id| payment_id.0);
+			assert_eq!(failed_payment_ids, expected_failed_ids);
+		},
+		_ => panic!("Unexpected event"),
+	}
+
+	// A batch which cannot be sent at all completes immediately.
+	let (failed_batch_id, results) = nodes[0].node.send_batch_payments(
+		vec![(nodes[1].node.get_our_node_id(), 1_000_000_000_000)], Retry::Attempts(0));
+	assert_eq!(results, vec![Err(RetryableSendFailure::RouteNotFound)]);
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match &events[0] {
+		Event::BatchPaymentCompleted { batch_id, successful_payment_ids, failed_payment_ids } => {
+			assert_eq!(*batch_id, failed_batch_id);
+			assert!(successful_payment_ids.is_empty());
+			assert_eq!(*failed_payment_ids, vec![failed_batch_id.payment_id(0)]);
+		},
+		_ => panic!("Unexpected event"),
+	}
+}
+
+fn invoice_batch_payment<'a, 'b, 'c>(
+	payee: &Node<'a, 'b, 'c>, payment_hash: PaymentHash, payment_secret: PaymentSecret, amount_msat: u64
+) -> BatchPayment {
+	let payment_params = PaymentParameters::from_node_id(payee.node.get_our_node_id(), TEST_FINAL_CLTV)
+		.with_bolt11_features(payee.node.invoice_features()).unwrap();
+	BatchPayment {
+		payment_hash,
+		recipient_onion: RecipientOnionFields::secret_only(payment_secret),
+		route_params: RouteParameters { payment_params, final_value_msat: amount_msat },
+	}
+}
+
+#[test]
+fn test_batch_payments_to_hashes() {
+	let chanmon_cfgs = create_chanmon_cfgs(4);
+	let node_cfgs = create_node_cfgs(4, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(4, &node_cfgs, &[None, None, None, None]);
+	let nodes = create_network(4, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+	create_announced_chan_between_nodes(&nodes, 0, 2);
+	create_announced_chan_between_nodes(&nodes, 2, 3);
+
+	let amt_msat = 100_000;
+	let (_, payment_hash_1, payment_secret_1) = get_payment_preimage_hash!(nodes[1]);
+	let (payment_preimage_2, payment_hash_2, payment_secret_2) = get_payment_preimage_hash!(nodes[3]);
+	let payments = vec![
+		invoice_batch_payment(&nodes[1], payment_hash_1, payment_secret_1, amt_msat),
+		invoice_batch_payment(&nodes[3], payment_hash_2, payment_secret_2, amt_msat),
+	];
+
+	// A batch exceeding our outbound capacity is rejected before any routes are found.
+	let available_msat = nodes[0].node.list_usable_channels().iter()
+		.map(|chan| chan.next_outbound_htlc_limit_msat).sum::<u64>();
+	let mut excessive_payments = payments.clone();
+	excessive_payments[1].route_params.final_value_msat = available_msat;
+	assert_eq!(
+		nodes[0].node.send_batch_payments_to_hashes(excessive_payments, None, Retry::Attempts(0)),
+		Err(BatchPaymentError::InsufficientLiquidity { required_msat: available_msat + amt_msat, available_msat })
+	);
+
+	// The payment to nodes[3] is forwarded by nodes[2] for the default base fee of 1000 msat,
+	// exceeding a budget of 999 msat.
+	assert_eq!(
+		nodes[0].node.send_batch_payments_to_hashes(payments.clone(), Some(999), Retry::Attempts(0)),
+		Err(BatchPaymentError::FeeBudgetExceeded { total_fee_msat: 1000, max_total_routing_fee_msat: 999 })
+	);
+	check_added_monitors!(nodes[0], 0);
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+	assert!(nodes[0].node.get_and_clear_pending_events().is_empty());
+	assert!(nodes[0].node.list_recent_payments().is_empty());
+
+	let (batch_id, results) = nodes[0].node.send_batch_payments_to_hashes(
+		payments, Some(1000), Retry::Attempts(0)).unwrap();
+	assert_eq!(results, vec![Ok(batch_id.payment_id(0)), Ok(batch_id.payment_id(1))]);
+	check_added_monitors!(nodes[0], 2);
+
+	let mut msg_events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(msg_events.len(), 2);
+	let ev = remove_first_msg_event_to_node(&nodes[1].node.get_our_node_id(), &mut msg_events);
+	pass_along_path(&nodes[0], &[&nodes[1]], amt_msat, payment_hash_1, Some(payment_secret_1), ev, true, None);
+	let ev = remove_first_msg_event_to_node(&nodes[2].node.get_our_node_id(), &mut msg_events);
+	pass_along_path(&nodes[0], &[&nodes[2], &nodes[3]], amt_msat, payment_hash_2, Some(payment_secret_2), ev, true, None);
+
+	claim_payment_along_route(&nodes[0], &[&[&nodes[2], &nodes[3]]], false, payment_preimage_2);
+
+	// The batch is completed once its last payment fails, reporting the partial success.
+	nodes[1].node.fail_htlc_backwards(&payment_hash_1);
+	expect_pending_htlcs_forwardable_and_htlc_handling_failed!(nodes[1], vec![HTLCDestination::FailedPayment { payment_hash: payment_hash_1 }]);
+	check_added_monitors!(nodes[1], 1);
+	let updates = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &updates.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], updates.commitment_signed, false);
+
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 3);
+	match events[0] {
+		Event::PaymentPathFailed { payment_hash, .. } => assert_eq!(payment_hash, payment_hash_1),
+		_ => panic!("Unexpected event"),
+	}
+	match events[1] {
+		Event::PaymentFailed { payment_id, .. } => assert_eq!(payment_id, batch_id.payment_id(0)),
+		_ => panic!("Unexpected event"),
+	}
+	match &events[2] {
+		Event::BatchPaymentCompleted { batch_id: completed_batch_id, successful_payment_ids, failed_payment_ids } => {
+			assert_eq!(*completed_batch_id, batch_id);
+			assert_eq!(*successful_payment_ids, vec![batch_id.payment_id(1)]);
+			assert_eq!(*failed_payment_ids, vec![batch_id.payment_id(0)]);
+		},
+		_ => panic!("Unexpected event"),
+	}
+}
+
+#[test]
+fn batch_fee_budget_bounds_retries() {
+	// Tests that the fee budget of a batch also bounds the routes of any retries of its payments.
+	let chanmon_cfgs = create_chanmon_cfgs(4);
+	let node_cfgs = create_node_cfgs(4, &chanmon_cfgs);
+	let mut expensive_config = test_default_channel_config();
+	expensive_config.channel_config.forwarding_fee_base_msat = 5000;
+	let node_chanmgrs = create_node_chanmgrs(4, &node_cfgs, &[None, None, None, Some(expensive_config)]);
+	let nodes = create_network(4, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+	// As nodes[2] funds the channel, nodes[1] cannot forward our first attempt over it.
+	let channel_id_2 = create_announced_chan_between_nodes(&nodes, 2, 1).2;
+	create_announced_chan_between_nodes(&nodes, 0, 3);
+	create_announced_chan_between_nodes(&nodes, 3, 2);
+
+	// The payment is first sent via nodes[1] for 1000 msat in fees, while retrying it via nodes[3]
+	// would exceed the batch's budget.
+	let amt_msat = 100_000;
+	let (_, payment_hash, payment_secret) = get_payment_preimage_hash!(nodes[2]);
+	let payments = vec![invoice_batch_payment(&nodes[2], payment_hash, payment_secret, amt_msat)];
+	let (batch_id, results) = nodes[0].node.send_batch_payments_to_hashes(
+		payments, Some(2000), Retry::Attempts(1)).unwrap();
+	assert_eq!(results, vec![Ok(batch_id.payment_id(0))]);
+	check_added_monitors!(nodes[0], 1);
+
+	let update_0 = get_htlc_update_msgs!(nodes[0], nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &update_0.update_add_htlcs[0]);
+	commitment_signed_dance!(nodes[1], nodes[0], &update_0.commitment_signed, false, true);
+	expect_pending_htlcs_forwardable_ignore!(nodes[1]);
+	nodes[1].node.process_pending_htlc_forwards();
+	expect_pending_htlcs_forwardable_and_htlc_handling_failed_ignore!(nodes[1],
+		vec![HTLCDestination::NextHopChannel { node_id: Some(nodes[2].node.get_our_node_id()), channel_id: channel_id_2 }]);
+	nodes[1].node.process_pending_htlc_forwards();
+	let update_1 = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	check_added_monitors!(nodes[1], 1);
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &update_1.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], update_1.commitment_signed, false);
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 2);
+	match events[0] {
+		Event::PaymentPathFailed { payment_hash: ev_payment_hash, .. } => assert_eq!(ev_payment_hash, payment_hash),
+		_ => panic!("Unexpected event"),
+	}
+	match events[1] {
+		Event::PendingHTLCsForwardable { .. } => {},
+		_ => panic!("Unexpected event"),
+	}
+
+	nodes[0].node.process_pending_htlc_forwards();
+	check_added_monitors!(nodes[0], 0);
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 2);
+	match events[0] {
+		Event::PaymentFailed { payment_hash: ev_payment_hash, reason, .. } => {
+			assert_eq!(ev_payment_hash, payment_hash);
+			assert_eq!(reason, Some(PaymentFailureReason::RouteNotFound));
+		},
+		_ => panic!("Unexpected event"),
+	}
+	match &events[1] {
+		Event::BatchPaymentCompleted { batch_id: completed_batch_id, successful_payment_ids, failed_payment_ids } => {
+			assert_eq!(*completed_batch_id, batch_id);
+			assert!(successful_payment_ids.is_empty());
+			assert_eq!(*failed_payment_ids, vec![batch_id.payment_id(0)]);
+		},
+		_ => panic!("Unexpected event"),
+	}
+}
+
+#[test]
+fn outbound_htlc_exposure_limits() {
+	// Tests that payments which would exceed our limits on in-flight outbound HTLCs are not sent,
+	// and that retries which would are deferred until other payments' HTLCs have resolved.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let mut config = test_default_channel_config();
+	config.outbound_htlc_exposure = OutboundHtlcExposureConfig {
+		max_total_in_flight_msat: Some(150_000),
+		max_in_flight_htlcs_per_destination: Some(1),
+		..Default::default()
+	};
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[Some(config), None, None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+	// As nodes[2] funds the channel, nodes[1] cannot forward our first attempt over it.
+	let chan_2 = create_announced_chan_between_nodes(&nodes, 2, 1);
+	let (channel_id_2, chan_2_scid) = (chan_2.2, chan_2.0.contents.short_channel_id);
+
+	let amt_msat = 100_000;
+	let route_params = |node_idx: usize, amt_msat: u64| RouteParameters {
+		payment_params: PaymentParameters::from_node_id(nodes[node_idx].node.get_our_node_id(), TEST_FINAL_CLTV)
+			.with_bolt11_features(nodes[node_idx].node.invoice_features()).unwrap(),
+		final_value_msat: amt_msat,
+	};
+	let (payment_preimage, payment_hash, payment_secret) = get_payment_preimage_hash!(nodes[2]);
+	nodes[0].node.send_payment(payment_hash, RecipientOnionFields::secret_only(payment_secret),
+		PaymentId(payment_hash.0), route_params(2, amt_msat), Retry::Attempts(1)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+
+	// A second HTLC to the same destination exceeds its HTLC count limit.
+	let (_, other_payment_hash, other_payment_secret) = get_payment_preimage_hash!(nodes[2]);
+	assert_eq!(nodes[0].node.send_payment(other_payment_hash,
+		RecipientOnionFields::secret_only(other_payment_secret), PaymentId(other_payment_hash.0),
+		route_params(2, 1_000), Retry::Attempts(0)),
+		Err(RetryableSendFailure::ExposureLimitReached));
+
+	// A payment to another destination exceeds the total value limit.
+	let (_, other_payment_hash, other_payment_secret) = get_payment_preimage_hash!(nodes[1]);
+	assert_eq!(nodes[0].node.send_payment(other_payment_hash,
+		RecipientOnionFields::secret_only(other_payment_secret), PaymentId(other_payment_hash.0),
+		route_params(1, amt_msat), Retry::Attempts(0)),
+		Err(RetryableSendFailure::ExposureLimitReached));
+
+	// Fail the first attempt at nodes[1].
+	let update_0 = get_htlc_update_msgs!(nodes[0], nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &update_0.update_add_htlcs[0]);
+	commitment_signed_dance!(nodes[1], nodes[0], &update_0.commitment_signed, false, true);
+	expect_pending_htlcs_forwardable_ignore!(nodes[1]);
+	nodes[1].node.process_pending_htlc_forwards();
+	expect_pending_htlcs_forwardable_and_htlc_handling_failed_ignore!(nodes[1],
+		vec![HTLCDestination::NextHopChannel { node_id: Some(nodes[2].node.get_our_node_id()), channel_id: channel_id_2 }]);
+	nodes[1].node.process_pending_htlc_forwards();
+	let update_1 = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	check_added_monitors!(nodes[1], 1);
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &update_1.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], update_1.commitment_signed, false);
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 2);
+	match events[0] {
+		Event::PaymentPathFailed { payment_hash: ev_payment_hash, .. } => assert_eq!(ev_payment_hash, payment_hash),
+		_ => panic!("Unexpected event"),
+	}
+	match events[1] {
+		Event::PendingHTLCsForwardable { .. } => {},
+		_ => panic!("Unexpected event"),
+	}
+
+	// Before the retry is attempted, another payment to the same destination takes its slot, so
+	// the retry is deferred.
+	create_announced_chan_between_nodes(&nodes, 1, 2);
+	let (other_payment_preimage, other_payment_hash, other_payment_secret) = get_payment_preimage_hash!(nodes[2]);
+	let mut other_route_params = route_params(2, 1_000);
+	other_route_params.payment_params.previously_failed_channels.push(chan_2_scid);
+	nodes[0].node.send_payment(other_payment_hash,
+		RecipientOnionFields::secret_only(other_payment_secret), PaymentId(other_payment_hash.0),
+		other_route_params, Retry::Attempts(0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+
+	nodes[0].node.process_pending_htlc_forwards();
+	assert!(nodes[0].node.get_and_clear_pending_events().is_empty());
+
+	let mut msg_events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(msg_events.len(), 1);
+	pass_along_path(&nodes[0], &[&nodes[1], &nodes[2]], 1_000, other_payment_hash,
+		Some(other_payment_secret), msg_events.pop().unwrap(), true, None);
+	do_claim_payment_along_route(&nodes[0], &[&[&nodes[1], &nodes[2]]], false, other_payment_preimage);
+
+	// Once the other payment is claimed, we're woken up to retry the deferred payment.
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 3);
+	match events[0] {
+		Event::PaymentSent { payment_hash: ev_payment_hash, .. } => assert_eq!(ev_payment_hash, other_payment_hash),
+		_ => panic!("Unexpected event"),
+	}
+	match events[1] {
+		Event::PendingHTLCsForwardable { .. } => {},
+		_ => panic!("Unexpected event"),
+	}
+	match events[2] {
+		Event::PaymentPathSuccessful { payment_hash: ev_payment_hash, .. } => assert_eq!(ev_payment_hash, Some(other_payment_hash)),
+		_ => panic!("Unexpected event"),
+	}
+
+	nodes[0].node.process_pending_htlc_forwards();
+	check_added_monitors!(nodes[0], 1);
+	let mut msg_events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(msg_events.len(), 1);
+	pass_along_path(&nodes[0], &[&nodes[1], &nodes[2]], amt_msat, payment_hash,
+		Some(payment_secret), msg_events.pop().unwrap(), true, None);
+	claim_payment_along_route(&nodes[0], &[&[&nodes[1], &nodes[2]]], false, payment_preimage);
+}
+
+#[test]
+fn first_hop_channel_round_robin() {
+	// Tests that with several channels to the same peer, payments are sent over each in turn.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let mut config = test_default_channel_config();
+	config.first_hop_channel_selection = FirstHopChannelSelection::RoundRobin;
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[Some(config), None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let channel_id_1 = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+	let channel_id_2 = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+
+	let amt_msat = 100_000;
+	let mut used_channel_ids = Vec::new();
+	for _ in 0..3 {
+		let (payment_preimage, payment_hash, payment_secret) = get_payment_preimage_hash!(nodes[1]);
+		let route_params = RouteParameters {
+			payment_params: PaymentParameters::from_node_id(nodes[1].node.get_our_node_id(), TEST_FINAL_CLTV)
+				.with_bolt11_features(nodes[1].node.invoice_features()).unwrap(),
+			final_value_msat: amt_msat,
+		};
+		nodes[0].node.send_payment(payment_hash, RecipientOnionFields::secret_only(payment_secret),
+			PaymentId(payment_hash.0), route_params, Retry::Attempts(0)).unwrap();
+		check_added_monitors!(nodes[0], 1);
+
+		let mut events = nodes[0].node.get_and_clear_pending_msg_events();
+		assert_eq!(events.len(), 1);
+		match events[0] {
+			MessageSendEvent::UpdateHTLCs { ref updates, .. } =>
+				used_channel_ids.push(updates.update_add_htlcs[0].channel_id),
+			_ => panic!("Unexpected event"),
+		}
+		pass_along_path(&nodes[0], &[&nodes[1]], amt_msat, payment_hash, Some(payment_secret),
+			events.pop().unwrap(), true, None);
+		claim_payment(&nodes[0], &[&nodes[1]], payment_preimage);
+	}
+
+	assert_ne!(used_channel_ids[0], used_channel_ids[1]);
+	assert!(used_channel_ids[0] == channel_id_1 || used_channel_ids[0] == channel_id_2);
+	assert!(used_channel_ids[1] == channel_id_1 || used_channel_ids[1] == channel_id_2);
+	assert_eq!(used_channel_ids[0], used_channel_ids[2]);
+}
+
+#[test]
+fn first_hop_channel_lowest_fee() {
+	// Tests that payments are sent over the channel over which we charge the lowest forwarding fees,
+	// keeping the capacity of the channels which earn us more for forwarding.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let mut config = test_default_channel_config();
+	config.first_hop_channel_selection = FirstHopChannelSelection::LowestFee;
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[Some(config), None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let channel_id_1 = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+	let channel_id_2 = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+	let node_b_id = nodes[1].node.get_our_node_id();
+
+	for (cheap_channel_id, expensive_channel_id) in [(channel_id_1, channel_id_2), (channel_id_2, channel_id_1)].iter() {
+		let cheap_fee = nodes[0].node.channel_details_by_id(cheap_channel_id).unwrap().config.unwrap().forwarding_fee_proportional_millionths;
+		nodes[0].node.update_partial_channel_config_by_id(expensive_channel_id, &ChannelConfigUpdate {
+			forwarding_fee_proportional_millionths: Some(cheap_fee + 100), ..Default::default()
+		}).unwrap();
+		let events = nodes[0].node.get_and_clear_pending_msg_events();
+		match events[..] {
+			[MessageSendEvent::BroadcastChannelUpdate { .. }] => {},
+			_ => panic!("Unexpected event"),
+		}
+
+		let amt_msat = 100_000;
+		let (payment_preimage, payment_hash, payment_secret) = get_payment_preimage_hash!(nodes[1]);
+		let route_params = RouteParameters {
+			payment_params: PaymentParameters::from_node_id(node_b_id, TEST_FINAL_CLTV)
+				.with_bolt11_features(nodes[1].node.invoice_features()).unwrap(),
+			final_value_msat: amt_msat,
+		};
+		nodes[0].node.send_payment(payment_hash, RecipientOnionFields::secret_only(payment_secret),
+			PaymentId(payment_hash.0), route_params, Retry::Attempts(0)).unwrap();
+		check_added_monitors!(nodes[0], 1);
+
+		let mut events = nodes[0].node.get_and_clear_pending_msg_events();
+		assert_eq!(events.len(), 1);
+		match events[0] {
+			MessageSendEvent::UpdateHTLCs { ref updates, .. } =>
+				assert_eq!(updates.update_add_htlcs[0].channel_id, *cheap_channel_id),
+			_ => panic!("Unexpected event"),
+		}
+		pass_along_path(&nodes[0], &[&nodes[1]], amt_msat, payment_hash, Some(payment_secret),
+			events.pop().unwrap(), true, None);
+		claim_payment(&nodes[0], &[&nodes[1]], payment_preimage);
+	}
+}
+
+#[derive(PartialEq)]
+enum AutoRetry {
+	Success,
+	Spontaneous,
+	FailAttempts,
+	FailTimeout,
+	FailOnRestart,
+	FailOnRetry,
+}
+
+#[test]
+fn automatic_retries() {
+	do_automatic_retries(AutoRetry::Success);
+	do_automatic_retries(AutoRetry::Spontaneous);
+	do_automatic_retries(AutoRetry::FailAttempts);
+	do_automatic_retries(AutoRetry::FailTimeout);
+	do_automatic_retries(AutoRetry::FailOnRestart);
+	do_automatic_retries(AutoRetry::FailOnRetry);
+}
+fn do_automatic_retries(test: AutoRetry) {
+	// Test basic automatic payment retries in ChannelManager. See individual `test` variant comments
+	// below.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+
+	let persister;
+	let new_chain_monitor;
+	let node_0_deserialized;
 
 	let mut nodes = create_network(3, &node_cfgs, &node_chanmgrs);
 	let channel_id_1 = create_announced_chan_between_nodes(&nodes, 0, 1).2;
@@ -1908,7 +3046,7 @@ fn do_automatic_retries(test: AutoRetry) {
 			let mut events = nodes[0].node.get_and_clear_pending_events();
 			assert_eq!(events.len(), 1);
 			match events[0] {
-				Event::PaymentFailed { payment_hash: ref ev_payment_hash, payment_id: ref ev_payment_id, reason: ref ev_reason } => {
+				Event::PaymentFailed { payment_hash: ref ev_payment_hash, payment_id: ref ev_payment_id, reason: ref ev_reason, .. } => {
 					assert_eq!(payment_hash, *ev_payment_hash);
 					assert_eq!(PaymentId(payment_hash.0), *ev_payment_id);
 					assert_eq!(PaymentFailureReason::RetriesExhausted, ev_reason.unwrap());
@@ -1945,7 +3083,7 @@ fn do_automatic_retries(test: AutoRetry) {
 		let mut events = nodes[0].node.get_and_clear_pending_events();
 		assert_eq!(events.len(), 1);
 		match events[0] {
-			Event::PaymentFailed { payment_hash: ref ev_payment_hash, payment_id: ref ev_payment_id, reason: ref ev_reason } => {
+			Event::PaymentFailed { payment_hash: ref ev_payment_hash, payment_id: ref ev_payment_id, reason: ref ev_reason, .. } => {
 				assert_eq!(payment_hash, *ev_payment_hash);
 				assert_eq!(PaymentId(payment_hash.0), *ev_payment_id);
 				assert_eq!(PaymentFailureReason::RetriesExhausted, ev_reason.unwrap());
@@ -1966,7 +3104,7 @@ fn do_automatic_retries(test: AutoRetry) {
 		let mut events = nodes[0].node.get_and_clear_pending_events();
 		assert_eq!(events.len(), 1);
 		match events[0] {
-			Event::PaymentFailed { payment_hash: ref ev_payment_hash, payment_id: ref ev_payment_id, reason: ref ev_reason } => {
+			Event::PaymentFailed { payment_hash: ref ev_payment_hash, payment_id: ref ev_payment_id, reason: ref ev_reason, .. } => {
 				assert_eq!(payment_hash, *ev_payment_hash);
 				assert_eq!(PaymentId(payment_hash.0), *ev_payment_id);
 				assert_eq!(PaymentFailureReason::RouteNotFound, ev_reason.unwrap());
@@ -2357,7 +3495,81 @@ fn retry_multi_path_single_failed_payment() {
 	assert_eq!(events.len(), 1);
 	match events[0] {
 		Event::PaymentPathFailed { payment_hash: ev_payment_hash, payment_failed_permanently: false,
-			failure: PathFailure::InitialSend { err: APIError::ChannelUnavailable { .. }},
+			failure: PathFailure::InitialSend { err: APIError::ChannelCannotSendHtlc { .. }},
+			short_channel_id: Some(expected_scid), .. } =>
+		{
+			assert_eq!(payment_hash, ev_payment_hash);
+			assert_eq!(expected_scid, route.paths[1].hops[0].short_channel_id);
+		},
+		_ => panic!("Unexpected event"),
+	}
+	let htlc_msgs = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(htlc_msgs.len(), 2);
+	check_added_monitors!(nodes[0], 2);
+}
+
+#[test]
+fn immediate_retry_on_failure() {
+	// Tests that we can/will retry immediately after a failure
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+	create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 1_000_000, 0);
+	create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 1_000_000, 0);
+
+	let amt_msat = 100_000_001;
+	let (_, payment_hash, _, payment_secret) = get_route_and_payment_hash!(&nodes[0], nodes[1], amt_msat);
+	#[cfg(feature = "std")]
+	let payment_expiry_secs = SystemTime::UNIX_EPOCH.elapsed().unwrap().as_secs() + 60 * 60;
+	#[cfg(not(feature = "std"))]
+	let payment_expiry_secs = 60 * 60;
+	let mut invoice_features = InvoiceFeatures::empty();
+	invoice_features.set_variable_length_onion_required();
+	invoice_features.set_payment_secret_required();
+	invoice_features.set_basic_mpp_optional();
+	let payment_params = PaymentParameters::from_node_id(nodes[1].node.get_our_node_id(), TEST_FINAL_CLTV)
+		.with_expiry_time(payment_expiry_secs as u64)
+		.with_bolt11_features(invoice_features).unwrap();
+	let route_params = RouteParameters {
+		payment_params,
+		final_value_msat: amt_msat,
+	};
+
+	let chans = nodes[0].node.list_usable_channels();
+	let mut route = Route {
+		paths: vec![
+			Path { hops: vec![RouteHop {
+				pubkey: nodes[1].node.get_our_node_id(),
+				node_features: nodes[1].node.node_features(),
+				short_channel_id: chans[0].short_channel_id.unwrap(),
+				channel_features: nodes[1].node.channel_features(),
+				fee_msat: 100_000_001, // Our default max-HTLC-value is 10% of the channel value, which this is one more than
+				cltv_expiry_delta: 100,
+			}], blinded_tail: None },
+		],
+		payment_params: Some(PaymentParameters::from_node_id(nodes[1].node.get_our_node_id(), TEST_FINAL_CLTV)),
+	};
+	nodes[0].router.expect_find_route(route_params.clone(), Ok(route.clone()));
+	// On retry, split the payment across both channels.
+	route.paths.push(route.paths[0].clone());
+	route.paths[0].hops[0].short_channel_id = chans[1].short_channel_id.unwrap();
+	route.paths[0].hops[0].fee_msat = 50_000_000;
+	route.paths[1].hops[0].fee_msat = 50_000_001;
+	let mut pay_params = route_params.payment_params.clone();
+	pay_params.previously_failed_channels.push(chans[0].short_channel_id.unwrap());
+	nodes[0].router.expect_find_route(RouteParameters {
+			payment_params: pay_params, final_value_msat: amt_msat,
+		}, Ok(route.clone()));
+
+	nodes[0].node.send_payment(payment_hash, RecipientOnionFields::secret_only(payment_secret),
+		PaymentId(payment_hash.0), route_params, Retry::Attempts(1)).unwrap();
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		Event::PaymentPathFailed { payment_hash: ev_payment_hash, payment_failed_permanently: false,
+			failure: PathFailure::InitialSend { err: APIError::ChannelCannotSendHtlc { .. }},
 			short_channel_id: Some(expected_scid), .. } =>
 		{
 			assert_eq!(payment_hash, ev_payment_hash);
@@ -2371,77 +3583,73 @@ fn retry_multi_path_single_failed_payment() {
 }
 
 #[test]
-fn immediate_retry_on_failure() {
-	// Tests that we can/will retry immediately after a failure
+fn substitutes_unavailable_first_hop() {
+	// Tests that if a payment fails to send over its first hop channel, we retry it over another
+	// channel immediately, without consuming a retry attempt.
 	let chanmon_cfgs = create_chanmon_cfgs(2);
 	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
-	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None, None]);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
 	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
 
-	create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 1_000_000, 0);
+	create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 100_000, 0);
 	create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 1_000_000, 0);
 
-	let amt_msat = 100_000_001;
+	// Our default max-HTLC-value is 10% of the channel value, so only the larger channel can carry
+	// this payment.
+	let amt_msat = 20_000_000;
 	let (_, payment_hash, _, payment_secret) = get_route_and_payment_hash!(&nodes[0], nodes[1], amt_msat);
-	#[cfg(feature = "std")]
-	let payment_expiry_secs = SystemTime::UNIX_EPOCH.elapsed().unwrap().as_secs() + 60 * 60;
-	#[cfg(not(feature = "std"))]
-	let payment_expiry_secs = 60 * 60;
-	let mut invoice_features = InvoiceFeatures::empty();
-	invoice_features.set_variable_length_onion_required();
-	invoice_features.set_payment_secret_required();
-	invoice_features.set_basic_mpp_optional();
-	let payment_params = PaymentParameters::from_node_id(nodes[1].node.get_our_node_id(), TEST_FINAL_CLTV)
-		.with_expiry_time(payment_expiry_secs as u64)
-		.with_bolt11_features(invoice_features).unwrap();
-	let route_params = RouteParameters {
-		payment_params,
-		final_value_msat: amt_msat,
-	};
+	let payment_params = PaymentParameters::from_node_id(nodes[1].node.get_our_node_id(), TEST_FINAL_CLTV);
+	let route_params = RouteParameters { payment_params, final_value_msat: amt_msat };
 
 	let chans = nodes[0].node.list_usable_channels();
+	let small_chan = chans.iter().find(|chan| chan.channel_value_satoshis == 100_000).unwrap();
+	let large_chan = chans.iter().find(|chan| chan.channel_value_satoshis == 1_000_000).unwrap();
 	let mut route = Route {
 		paths: vec![
 			Path { hops: vec![RouteHop {
 				pubkey: nodes[1].node.get_our_node_id(),
 				node_features: nodes[1].node.node_features(),
-				short_channel_id: chans[0].short_channel_id.unwrap(),
+				short_channel_id: small_chan.short_channel_id.unwrap(),
 				channel_features: nodes[1].node.channel_features(),
-				fee_msat: 100_000_001, // Our default max-HTLC-value is 10% of the channel value, which this is one more than
+				fee_msat: amt_msat,
 				cltv_expiry_delta: 100,
 			}], blinded_tail: None },
 		],
-		payment_params: Some(PaymentParameters::from_node_id(nodes[1].node.get_our_node_id(), TEST_FINAL_CLTV)),
+		payment_params: Some(route_params.payment_params.clone()),
 	};
 	nodes[0].router.expect_find_route(route_params.clone(), Ok(route.clone()));
-	// On retry, split the payment across both channels.
-	route.paths.push(route.paths[0].clone());
-	route.paths[0].hops[0].short_channel_id = chans[1].short_channel_id.unwrap();
-	route.paths[0].hops[0].fee_msat = 50_000_000;
-	route.paths[1].hops[0].fee_msat = 50_000_001;
+	route.paths[0].hops[0].short_channel_id = large_chan.short_channel_id.unwrap();
 	let mut pay_params = route_params.payment_params.clone();
-	pay_params.previously_failed_channels.push(chans[0].short_channel_id.unwrap());
+	pay_params.previously_failed_channels.push(small_chan.short_channel_id.unwrap());
 	nodes[0].router.expect_find_route(RouteParameters {
 			payment_params: pay_params, final_value_msat: amt_msat,
 		}, Ok(route.clone()));
 
 	nodes[0].node.send_payment(payment_hash, RecipientOnionFields::secret_only(payment_secret),
-		PaymentId(payment_hash.0), route_params, Retry::Attempts(1)).unwrap();
+		PaymentId(payment_hash.0), route_params, Retry::Attempts(0)).unwrap();
 	let events = nodes[0].node.get_and_clear_pending_events();
-	assert_eq!(events.len(), 1);
+	assert_eq!(events.len(), 2);
 	match events[0] {
 		Event::PaymentPathFailed { payment_hash: ev_payment_hash, payment_failed_permanently: false,
-			failure: PathFailure::InitialSend { err: APIError::ChannelUnavailable { .. }},
+			failure: PathFailure::InitialSend { err: APIError::ChannelCannotSendHtlc { .. }},
 			short_channel_id: Some(expected_scid), .. } =>
 		{
 			assert_eq!(payment_hash, ev_payment_hash);
-			assert_eq!(expected_scid, route.paths[1].hops[0].short_channel_id);
+			assert_eq!(expected_scid, small_chan.short_channel_id.unwrap());
+		},
+		_ => panic!("Unexpected event"),
+	}
+	match events[1] {
+		Event::PaymentFirstHopSubstituted { payment_hash: ev_payment_hash, ref failed_short_channel_ids, ref short_channel_ids, .. } => {
+			assert_eq!(payment_hash, ev_payment_hash);
+			assert_eq!(*failed_short_channel_ids, vec![small_chan.short_channel_id.unwrap()]);
+			assert_eq!(*short_channel_ids, vec![large_chan.short_channel_id.unwrap()]);
 		},
 		_ => panic!("Unexpected event"),
 	}
 	let htlc_msgs = nodes[0].node.get_and_clear_pending_msg_events();
-	assert_eq!(htlc_msgs.len(), 2);
-	check_added_monitors!(nodes[0], 2);
+	assert_eq!(htlc_msgs.len(), 1);
+	check_added_monitors!(nodes[0], 1);
 }
 
 #[test]
@@ -2647,7 +3855,7 @@ fn no_extra_retries_on_back_to_back_fail() {
 		_ => panic!("Unexpected event"),
 	}
 	match events[1] {
-		Event::PaymentFailed { payment_hash: ref ev_payment_hash, payment_id: ref ev_payment_id, reason: ref ev_reason } => {
+		Event::PaymentFailed { payment_hash: ref ev_payment_hash, payment_id: ref ev_payment_id, reason: ref ev_reason, .. } => {
 			assert_eq!(payment_hash, *ev_payment_hash);
 			assert_eq!(PaymentId(payment_hash.0), *ev_payment_id);
 			assert_eq!(PaymentFailureReason::RetriesExhausted, ev_reason.unwrap());
@@ -3194,6 +4402,37 @@ fn do_claim_from_closed_chan(fail_payment: bool) {
 	}
 }
 
+#[test]
+fn try_claim_funds_reports_unclaimable_payments() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	let amt_msat = 100_000;
+	let (payment_preimage, payment_hash, _) = route_payment(&nodes[0], &[&nodes[1]], amt_msat);
+
+	// A preimage we never received a payment for claims nothing.
+	let unknown_preimage = PaymentPreimage([42; 32]);
+	let unknown_hash = PaymentHash(Sha256::hash(&unknown_preimage.0).into_inner());
+	assert_eq!(nodes[1].node.try_claim_funds(unknown_preimage),
+		Err(ClaimFundsError::PaymentNotClaimable { payment_hash: unknown_hash }));
+	assert!(nodes[1].node.get_and_clear_pending_events().is_empty());
+	check_added_monitors!(nodes[1], 0);
+
+	assert_eq!(nodes[1].node.try_claim_funds(payment_preimage), Ok(()));
+	expect_payment_claimed!(nodes[1], payment_hash, amt_msat);
+	check_added_monitors!(nodes[1], 1);
+
+	// Once the claim is in flight the payment is no longer claimable.
+	assert_eq!(nodes[1].node.try_claim_funds(payment_preimage),
+		Err(ClaimFundsError::PaymentNotClaimable { payment_hash }));
+
+	pass_claimed_payment_along_route(&nodes[0], &[&[&nodes[1]]], false, payment_preimage);
+	expect_payment_sent!(nodes[0], payment_preimage);
+}
+
 #[test]
 fn claim_from_closed_chan() {
 	do_claim_from_closed_chan(true);
@@ -3347,3 +4586,386 @@ fn test_payment_metadata_consistency() {
 	do_test_payment_metadata_consistency(false, true);
 	do_test_payment_metadata_consistency(false, false);
 }
+
+#[test]
+fn test_pending_forward_limit() {
+	// Test that once `ResourceLimitsConfig::max_pending_forward_htlcs` HTLCs are pending
+	// forwarding, further HTLCs we're asked to forward are failed back rather than buffered.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let mut limited_cfg = test_default_channel_config();
+	limited_cfg.resource_limits.max_pending_forward_htlcs = 0;
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, Some(limited_cfg), None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+	let chan_2_scid = create_announced_chan_between_nodes(&nodes, 1, 2).0.contents.short_channel_id;
+
+	let (route, payment_hash, _, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[2], 100_000);
+	nodes[0].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	let payment_event = SendEvent::from_event(nodes[0].node.get_and_clear_pending_msg_events().remove(0));
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &payment_event.msgs[0]);
+	commitment_signed_dance!(nodes[1], nodes[0], payment_event.commitment_msg, false, true);
+	assert_eq!(nodes[1].node.pending_forward_limit_hits(), 1);
+
+	expect_pending_htlcs_forwardable_and_htlc_handling_failed!(nodes[1], vec![HTLCDestination::InvalidForward { requested_forward_scid: chan_2_scid }]);
+	check_added_monitors!(nodes[1], 1);
+	let htlc_fail_updates = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	assert!(htlc_fail_updates.update_add_htlcs.is_empty());
+	assert_eq!(htlc_fail_updates.update_fail_htlcs.len(), 1);
+
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &htlc_fail_updates.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], htlc_fail_updates.commitment_signed, false);
+	expect_payment_failed!(nodes[0], payment_hash, false, 0x2000 | 2, []);
+}
+
+#[test]
+fn test_pending_inbound_htlcs_per_peer_limit() {
+	// Test that once a peer has `ResourceLimitsConfig::max_pending_inbound_htlcs_per_peer` HTLCs
+	// pending with us, further HTLCs it asks us to forward are failed back.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let mut limited_cfg = test_default_channel_config();
+	limited_cfg.resource_limits.max_pending_inbound_htlcs_per_peer = 1;
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, Some(limited_cfg), None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+	create_announced_chan_between_nodes(&nodes, 1, 2);
+
+	let (payment_preimage, _, _) = route_payment(&nodes[0], &[&nodes[1], &nodes[2]], 100_000);
+
+	let (route, payment_hash, _, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[2], 100_000);
+	nodes[0].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	let payment_event = SendEvent::from_event(nodes[0].node.get_and_clear_pending_msg_events().remove(0));
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &payment_event.msgs[0]);
+	commitment_signed_dance!(nodes[1], nodes[0], payment_event.commitment_msg, false, true);
+
+	let htlc_fail_updates = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	assert!(htlc_fail_updates.update_add_htlcs.is_empty());
+	assert_eq!(htlc_fail_updates.update_fail_htlcs.len(), 1);
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &htlc_fail_updates.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], htlc_fail_updates.commitment_signed, false);
+	expect_payment_failed_conditions(&nodes[0], payment_hash, false,
+		PaymentFailedConditions::new().blamed_chan_closed(false));
+
+	// HTLCs which nodes[1] receives itself are not limited.
+	let (receive_preimage, _, _) = route_payment(&nodes[0], &[&nodes[1]], 100_000);
+	claim_payment(&nodes[0], &[&nodes[1]], receive_preimage);
+
+	// Once the first HTLC is resolved, nodes[0] may again forward HTLCs through nodes[1].
+	claim_payment(&nodes[0], &[&nodes[1], &nodes[2]], payment_preimage);
+	let (payment_preimage, _, _) = route_payment(&nodes[0], &[&nodes[1], &nodes[2]], 100_000);
+	claim_payment(&nodes[0], &[&nodes[1], &nodes[2]], payment_preimage);
+}
+
+#[test]
+fn test_pending_inbound_htlc_value_per_peer_limit() {
+	// Test that once a peer has HTLCs pending with us totalling close to
+	// `ResourceLimitsConfig::max_pending_inbound_htlc_value_msat_per_peer`, further HTLCs it asks us
+	// to forward which would exceed it are failed back.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let mut limited_cfg = test_default_channel_config();
+	limited_cfg.resource_limits.max_pending_inbound_htlc_value_msat_per_peer = 160_000;
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, Some(limited_cfg), None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+	create_announced_chan_between_nodes(&nodes, 1, 2);
+
+	let (payment_preimage, _, _) = route_payment(&nodes[0], &[&nodes[1], &nodes[2]], 100_000);
+	assert_eq!(nodes[1].node.inbound_htlc_limit_hits(), 0);
+
+	let (route, payment_hash, _, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[2], 100_000);
+	nodes[0].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	let payment_event = SendEvent::from_event(nodes[0].node.get_and_clear_pending_msg_events().remove(0));
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &payment_event.msgs[0]);
+	commitment_signed_dance!(nodes[1], nodes[0], payment_event.commitment_msg, false, true);
+	assert_eq!(nodes[1].node.inbound_htlc_limit_hits(), 1);
+
+	let htlc_fail_updates = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	assert!(htlc_fail_updates.update_add_htlcs.is_empty());
+	assert_eq!(htlc_fail_updates.update_fail_htlcs.len(), 1);
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &htlc_fail_updates.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], htlc_fail_updates.commitment_signed, false);
+	expect_payment_failed_conditions(&nodes[0], payment_hash, false,
+		PaymentFailedConditions::new().blamed_chan_closed(false));
+
+	// Smaller HTLCs which fit within the remaining budget are still forwarded.
+	let (small_payment_preimage, _, _) = route_payment(&nodes[0], &[&nodes[1], &nodes[2]], 50_000);
+	claim_payment(&nodes[0], &[&nodes[1], &nodes[2]], small_payment_preimage);
+	claim_payment(&nodes[0], &[&nodes[1], &nodes[2]], payment_preimage);
+}
+
+#[test]
+fn test_begin_shutdown() {
+	// Test that once `ChannelManager::begin_shutdown` is called new HTLCs and channels are rejected
+	// while HTLCs already in flight resolve as usual, after which an `Event::NodeQuiescent` is
+	// generated.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+	create_announced_chan_between_nodes(&nodes, 1, 2);
+
+	let (payment_preimage, _, _) = route_payment(&nodes[0], &[&nodes[1], &nodes[2]], 100_000);
+
+	nodes[1].node.begin_shutdown(false);
+	assert!(!nodes[1].node.is_quiescent());
+	assert!(nodes[1].node.get_and_clear_pending_events().is_empty());
+
+	// New HTLCs are failed back...
+	let (route, payment_hash, _, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[2], 100_000);
+	nodes[0].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	let payment_event = SendEvent::from_event(nodes[0].node.get_and_clear_pending_msg_events().remove(0));
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &payment_event.msgs[0]);
+	commitment_signed_dance!(nodes[1], nodes[0], payment_event.commitment_msg, false, true);
+
+	let htlc_fail_updates = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	assert!(htlc_fail_updates.update_add_htlcs.is_empty());
+	assert_eq!(htlc_fail_updates.update_fail_htlcs.len(), 1);
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &htlc_fail_updates.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], htlc_fail_updates.commitment_signed, false);
+	expect_payment_failed!(nodes[0], payment_hash, false, 0x2000 | 2, []);
+
+	// ...and new channels are rejected, whether inbound or outbound.
+	match nodes[1].node.create_channel(nodes[0].node.get_our_node_id(), 100_000, 0, 42, None) {
+		Err(OpenChannelError::ShuttingDown) => {},
+		_ => panic!("Unexpected result"),
+	}
+	nodes[0].node.create_channel(nodes[1].node.get_our_node_id(), 100_000, 0, 42, None).unwrap();
+	let open_channel_msg = get_event_msg!(nodes[0], MessageSendEvent::SendOpenChannel, nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_open_channel(&nodes[0].node.get_our_node_id(), &open_channel_msg);
+	let msg_events = nodes[1].node.get_and_clear_pending_msg_events();
+	assert_eq!(msg_events.len(), 1);
+	match msg_events[0] {
+		MessageSendEvent::HandleError { action: msgs::ErrorAction::SendErrorMessage { ref msg }, .. } => {
+			assert_eq!(msg.data, "No inbound channels accepted while shutting down");
+		},
+		_ => panic!("Unexpected event"),
+	}
+
+	// Once the HTLC which was in flight when we began shutting down is resolved we're quiescent.
+	claim_payment(&nodes[0], &[&nodes[1], &nodes[2]], payment_preimage);
+	assert!(nodes[1].node.is_quiescent());
+	nodes[1].node.timer_tick_occurred();
+	let events = nodes[1].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		Event::NodeQuiescent => {},
+		_ => panic!("Unexpected event"),
+	}
+
+	// The event is only generated once.
+	nodes[1].node.timer_tick_occurred();
+	assert!(nodes[1].node.get_and_clear_pending_events().is_empty());
+}
+
+#[test]
+fn custom_onion_payments() {
+	// Test that payments sent with caller-constructed onions, either from raw hop payloads or as a
+	// full onion packet, are claimed and failed back as any other payment.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+	create_announced_chan_between_nodes(&nodes, 1, 2);
+
+	let amt_msat = 100_000;
+	let cur_height = nodes[0].best_block_info().1 + 1;
+
+	// Send a payment from serialized hop payloads and claim it.
+	let (route, payment_hash, payment_preimage, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[2], amt_msat);
+	let path = route.paths[0].clone();
+	let (payloads, _, _) = onion_utils::build_onion_payloads(&path, amt_msat,
+		RecipientOnionFields::secret_only(payment_secret), cur_height, &None).unwrap();
+	let payloads = payloads.iter().map(|payload| payload.encode()).collect::<Vec<_>>();
+
+	// A payload must be given for each hop.
+	let res = nodes[0].node.send_payment_with_custom_onion(path.clone(), payment_hash,
+		CustomOnion::HopPayloads(payloads[1..].to_vec()), PaymentId(payment_hash.0));
+	match res {
+		Err(PaymentSendFailure::ParameterError(APIError::APIMisuseError { err })) =>
+			assert_eq!(err, "A payload must be provided for each hop in the path"),
+		_ => panic!("Unexpected result"),
+	}
+
+	nodes[0].node.send_payment_with_custom_onion(path, payment_hash,
+		CustomOnion::HopPayloads(payloads), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	pass_along_route(&nodes[0], &[&[&nodes[1], &nodes[2]]], amt_msat, payment_hash, payment_secret);
+	claim_payment(&nodes[0], &[&nodes[1], &nodes[2]], payment_preimage);
+
+	// Send a payment using a fully constructed onion packet and fail it back, which requires the
+	// packet's session key to decrypt the failure.
+	let (route, payment_hash, _, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[2], amt_msat);
+	let path = route.paths[0].clone();
+	let session_priv = SecretKey::from_slice(&[42; 32]).unwrap();
+	let onion_keys = onion_utils::construct_onion_keys(&Secp256k1::new(), &path, &session_priv).unwrap();
+	let (payloads, _, _) = onion_utils::build_onion_payloads(&path, amt_msat,
+		RecipientOnionFields::secret_only(payment_secret), cur_height, &None).unwrap();
+	let packet = onion_utils::construct_onion_packet(payloads, onion_keys, [0; 32], &payment_hash).unwrap();
+
+	nodes[0].node.send_payment_with_custom_onion(path, payment_hash,
+		CustomOnion::Packet { packet: packet.encode(), session_priv }, PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	pass_along_route(&nodes[0], &[&[&nodes[1], &nodes[2]]], amt_msat, payment_hash, payment_secret);
+	fail_payment(&nodes[0], &[&nodes[1], &nodes[2]], payment_hash);
+}
+
+#[test]
+fn send_payment_with_route_override() {
+	// Tests that a payment sent with a `RouteOverride` finds its routes as overridden, with
+	// retries of a fixed `Route` going through our own `Router` and retries of a payment with an
+	// overriding `Router` going through that `Router`.
+	struct FixedRouter {
+		/// The routes to return, in order, with the last one returned for any further calls.
+		routes: crate::sync::Mutex<Vec<Route>>,
+		calls: core::sync::atomic::AtomicUsize,
+	}
+	impl Router for FixedRouter {
+		fn find_route(
+			&self, _payer: &bitcoin::secp256k1::PublicKey, _route_params: &RouteParameters,
+			_first_hops: Option<&[&crate::ln::channelmanager::ChannelDetails]>,
+			_inflight_htlcs: &crate::routing::router::InFlightHtlcs
+		) -> Result<Route, msgs::LightningError> {
+			self.calls.fetch_add(1, core::sync::atomic::Ordering::AcqRel);
+			let mut routes = self.routes.lock().unwrap();
+			if routes.len() > 1 { Ok(routes.remove(0)) } else { Ok(routes[0].clone()) }
+		}
+	}
+
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	let amt_msat = 100_000;
+	let (route, payment_hash, payment_preimage, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[1], amt_msat);
+	let route_params = RouteParameters {
+		payment_params: route.payment_params.clone().unwrap(),
+		final_value_msat: amt_msat,
+	};
+
+	// Send over a fixed `Route`, without consulting any `Router`.
+	nodes[0].node.send_payment_with_route_override(payment_hash, RecipientOnionFields::secret_only(payment_secret),
+		PaymentId(payment_hash.0), route_params.clone(), Retry::Attempts(1), RouteOverride::Route(route.clone())).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	pass_along_route(&nodes[0], &[&[&nodes[1]]], amt_msat, payment_hash, payment_secret);
+	claim_payment(&nodes[0], &[&nodes[1]], payment_preimage);
+
+	// Send with a custom `Router`, which is used in place of our own, including for retries. Its
+	// first route goes over a channel we don't have, failing on the initial send and thus being
+	// retried over the second route it returns.
+	let (payment_preimage, payment_hash, payment_secret) = get_payment_preimage_hash!(nodes[1]);
+	let mut unusable_route = route.clone();
+	unusable_route.paths[0].hops[0].short_channel_id = 0xdeadbeef;
+	let router = crate::sync::Arc::new(FixedRouter {
+		routes: crate::sync::Mutex::new(vec![unusable_route, route]),
+		calls: core::sync::atomic::AtomicUsize::new(0),
+	});
+	nodes[0].node.send_payment_with_route_override(payment_hash, RecipientOnionFields::secret_only(payment_secret),
+		PaymentId(payment_hash.0), route_params.clone(), Retry::Attempts(1), RouteOverride::Router(router.clone())).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	assert_eq!(router.calls.load(core::sync::atomic::Ordering::Acquire), 2);
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		Event::PaymentPathFailed { failure: PathFailure::InitialSend { .. }, .. } => {},
+		_ => panic!("Unexpected event"),
+	}
+	pass_along_route(&nodes[0], &[&[&nodes[1]]], amt_msat, payment_hash, payment_secret);
+	claim_payment(&nodes[0], &[&nodes[1]], payment_preimage);
+
+	// A payment with the same id as a pending one is rejected, without its `Router` being stored
+	// for the pending payment.
+	let (_, payment_hash, payment_secret) = get_payment_preimage_hash!(nodes[1]);
+	let payment_id = PaymentId(payment_hash.0);
+	let (route, _, _, _) = get_route_and_payment_hash!(nodes[0], nodes[1], amt_msat);
+	nodes[0].node.send_payment_with_route_override(payment_hash, RecipientOnionFields::secret_only(payment_secret),
+		payment_id, route_params.clone(), Retry::Attempts(1), RouteOverride::Route(route.clone())).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	let other_router = crate::sync::Arc::new(FixedRouter {
+		routes: crate::sync::Mutex::new(vec![route]),
+		calls: core::sync::atomic::AtomicUsize::new(0),
+	});
+	assert_eq!(nodes[0].node.send_payment_with_route_override(payment_hash, RecipientOnionFields::secret_only(payment_secret),
+		payment_id, route_params, Retry::Attempts(1), RouteOverride::Router(other_router.clone())),
+		Err(RetryableSendFailure::DuplicatePayment));
+	assert_eq!(other_router.calls.load(core::sync::atomic::Ordering::Acquire), 1);
+	pass_along_route(&nodes[0], &[&[&nodes[1]]], amt_msat, payment_hash, payment_secret);
+	fail_payment(&nodes[0], &[&nodes[1]], payment_hash);
+	assert_eq!(other_router.calls.load(core::sync::atomic::Ordering::Acquire), 1);
+}
+
+#[test]
+fn staggers_private_payment_parts() {
+	// Tests that a payment with `PaymentPrivacyLevel::DecoyPartsAndTimingSpread` is split into
+	// several parts, only the first of which is sent right away. The other parts are sent one at
+	// a time on later calls to `process_pending_htlc_forwards`, or all at once on the next timer
+	// tick at the latest.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	let amt_msat = 3_000_000;
+	let (payment_preimage, payment_hash, payment_secret) = get_payment_preimage_hash!(nodes[1]);
+	let payment_params = PaymentParameters::from_node_id(nodes[1].node.get_our_node_id(), TEST_FINAL_CLTV)
+		.with_bolt11_features(nodes[1].node.invoice_features()).unwrap()
+		.with_privacy_level(PaymentPrivacyLevel::DecoyPartsAndTimingSpread);
+	let route_params = RouteParameters { payment_params, final_value_msat: amt_msat };
+	nodes[0].node.send_payment(payment_hash, RecipientOnionFields::secret_only(payment_secret),
+		PaymentId(payment_hash.0), route_params, Retry::Attempts(0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+
+	let mut events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	pass_along_path(&nodes[0], &[&nodes[1]], amt_msat, payment_hash, Some(payment_secret), events.pop().unwrap(), false, None);
+
+	// The next part is sent after a random delay.
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		Event::PendingHTLCsForwardable { time_forwardable } => {
+			assert!(time_forwardable >= core::time::Duration::from_millis(100));
+			assert!(time_forwardable <= core::time::Duration::from_millis(2_100));
+		},
+		_ => panic!("Unexpected event"),
+	}
+	nodes[0].node.process_pending_htlc_forwards();
+	check_added_monitors!(nodes[0], 1);
+	let mut events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	pass_along_path(&nodes[0], &[&nodes[1]], amt_msat, payment_hash, Some(payment_secret), events.pop().unwrap(), false, None);
+
+	// Any remaining parts are sent on the next timer tick.
+	nodes[0].node.timer_tick_occurred();
+	check_added_monitors!(nodes[0], 1);
+	let mut events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	pass_along_path(&nodes[0], &[&nodes[1]], amt_msat, payment_hash, Some(payment_secret), events.pop().unwrap(), true, None);
+	assert!(nodes[0].node.get_and_clear_pending_events().is_empty());
+
+	nodes[1].node.claim_funds(payment_preimage);
+	check_added_monitors!(nodes[1], 1);
+	expect_payment_claimed!(nodes[1], payment_hash, amt_msat);
+	let updates = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	assert_eq!(updates.update_fulfill_htlcs.len(), 3);
+	for update_fulfill in updates.update_fulfill_htlcs.iter() {
+		nodes[0].node.handle_update_fulfill_htlc(&nodes[1].node.get_our_node_id(), update_fulfill);
+	}
+	commitment_signed_dance!(nodes[0], nodes[1], updates.commitment_signed, false);
+	expect_payment_sent!(nodes[0], payment_preimage);
+}
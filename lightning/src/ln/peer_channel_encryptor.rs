@@ -39,9 +39,11 @@ const NOISE_CK: [u8; 32] = [0x26, 0x40, 0xf5, 0x2e, 0xeb, 0xcd, 0x9e, 0x88, 0x29
 // Sha256(NOISE_CK || "lightning")
 const NOISE_H: [u8; 32] = [0xd1, 0xfb, 0xf6, 0xde, 0xe4, 0xf6, 0x86, 0xf1, 0x32, 0xfd, 0x70, 0x2c, 0x4a, 0xbf, 0x8f, 0xba, 0x4b, 0xb4, 0x20, 0xd8, 0x9d, 0x2a, 0x04, 0x8a, 0x3c, 0x4f, 0x4c, 0x09, 0x2e, 0x37, 0xb6, 0x76];
 
-enum NoiseSecretKey<'a, 'b, NS: Deref> where NS::Target: NodeSigner {
+enum NoiseSecretKey<'a> {
 	InMemory(&'a SecretKey),
-	NodeSigner(&'b NS)
+	/// The shared secret of our node secret and the remote ephemeral key, as derived by our
+	/// [`NodeSigner`].
+	SharedSecret(SharedSecret),
 }
 
 pub enum NextNoiseStep {
@@ -208,20 +210,26 @@ impl PeerChannelEncryptor {
 		(res, temp_k)
 	}
 
-	#[inline]
-	fn inbound_noise_act<'a, 'b, NS: Deref>(
-		state: &mut BidirectionalNoiseState, act: &[u8], secret_key: NoiseSecretKey<'a, 'b, NS>
-	) -> Result<(PublicKey, [u8; 32]), LightningError> where NS::Target: NodeSigner {
+	/// Gets the remote ephemeral public key from an inbound act one or act two, which our node
+	/// secret must be multiplied with to process the act.
+	pub fn act_ephemeral_key(act: &[u8]) -> Result<PublicKey, LightningError> {
 		assert_eq!(act.len(), 50);
 
 		if act[0] != 0 {
 			return Err(LightningError{err: format!("Unknown handshake version number {}", act[0]), action: msgs::ErrorAction::DisconnectPeer{ msg: None }});
 		}
 
-		let their_pub = match PublicKey::from_slice(&act[1..34]) {
-			Err(_) => return Err(LightningError{err: format!("Invalid public key {}", &act[1..34].to_hex()), action: msgs::ErrorAction::DisconnectPeer{ msg: None }}),
-			Ok(key) => key,
-		};
+		match PublicKey::from_slice(&act[1..34]) {
+			Err(_) => Err(LightningError{err: format!("Invalid public key {}", &act[1..34].to_hex()), action: msgs::ErrorAction::DisconnectPeer{ msg: None }}),
+			Ok(key) => Ok(key),
+		}
+	}
+
+	#[inline]
+	fn inbound_noise_act(
+		state: &mut BidirectionalNoiseState, act: &[u8], secret_key: NoiseSecretKey
+	) -> Result<(PublicKey, [u8; 32]), LightningError> {
+		let their_pub = PeerChannelEncryptor::act_ephemeral_key(act)?;
 
 		let mut sha = Sha256::engine();
 		sha.input(&state.h);
@@ -230,12 +238,7 @@ impl PeerChannelEncryptor {
 
 		let ss = match secret_key {
 			NoiseSecretKey::InMemory(secret_key) => SharedSecret::new(&their_pub, secret_key),
-			NoiseSecretKey::NodeSigner(node_signer) => node_signer
-				.ecdh(Recipient::Node, &their_pub, None)
-				.map_err(|_| LightningError {
-					err: "Failed to derive shared secret".to_owned(),
-					action: msgs::ErrorAction::DisconnectPeer { msg: None }
-				})?,
+			NoiseSecretKey::SharedSecret(ss) => ss,
 		};
 		let temp_k = PeerChannelEncryptor::hkdf(state, ss);
 
@@ -272,6 +275,21 @@ impl PeerChannelEncryptor {
 	pub fn process_act_one_with_keys<C: secp256k1::Signing, NS: Deref>(
 		&mut self, act_one: &[u8], node_signer: &NS, our_ephemeral: SecretKey, secp_ctx: &Secp256k1<C>)
 	-> Result<[u8; 50], LightningError> where NS::Target: NodeSigner {
+		let their_ephemeral = PeerChannelEncryptor::act_ephemeral_key(act_one)?;
+		let shared_secret = node_signer.ecdh(Recipient::Node, &their_ephemeral, None)
+			.map_err(|_| LightningError {
+				err: "Failed to derive shared secret".to_owned(),
+				action: msgs::ErrorAction::DisconnectPeer { msg: None }
+			})?;
+		self.process_act_one_with_shared_secret(act_one, shared_secret, our_ephemeral, secp_ctx)
+	}
+
+	/// Processes act one as in [`Self::process_act_one_with_keys`], given the shared secret of our
+	/// node secret and [`Self::act_ephemeral_key`], e.g. as derived asynchronously.
+	pub fn process_act_one_with_shared_secret<C: secp256k1::Signing>(
+		&mut self, act_one: &[u8], shared_secret: SharedSecret, our_ephemeral: SecretKey,
+		secp_ctx: &Secp256k1<C>
+	) -> Result<[u8; 50], LightningError> {
 		assert_eq!(act_one.len(), 50);
 
 		match self.noise_state {
@@ -282,7 +300,7 @@ impl PeerChannelEncryptor {
 							panic!("Requested act at wrong step");
 						}
 
-						let (their_pub, _) = PeerChannelEncryptor::inbound_noise_act(bidirectional_state, act_one, NoiseSecretKey::NodeSigner(node_signer))?;
+						let (their_pub, _) = PeerChannelEncryptor::inbound_noise_act(bidirectional_state, act_one, NoiseSecretKey::SharedSecret(shared_secret))?;
 						ie.get_or_insert(their_pub);
 
 						re.get_or_insert(our_ephemeral);
@@ -302,6 +320,24 @@ impl PeerChannelEncryptor {
 	pub fn process_act_two<NS: Deref>(
 		&mut self, act_two: &[u8], node_signer: &NS)
 	-> Result<([u8; 66], PublicKey), LightningError> where NS::Target: NodeSigner {
+		let their_ephemeral = PeerChannelEncryptor::act_ephemeral_key(act_two)?;
+		let our_node_id = node_signer.get_node_id(Recipient::Node).map_err(|_| LightningError {
+			err: "Failed to encrypt message".to_owned(),
+			action: msgs::ErrorAction::DisconnectPeer { msg: None }
+		})?;
+		let shared_secret = node_signer.ecdh(Recipient::Node, &their_ephemeral, None)
+			.map_err(|_| LightningError {
+				err: "Failed to derive shared secret".to_owned(),
+				action: msgs::ErrorAction::DisconnectPeer { msg: None }
+			})?;
+		self.process_act_two_with_shared_secret(act_two, our_node_id, shared_secret)
+	}
+
+	/// Processes act two as in [`Self::process_act_two`], given our node id and the shared secret
+	/// of our node secret and [`Self::act_ephemeral_key`], e.g. as derived asynchronously.
+	pub fn process_act_two_with_shared_secret(
+		&mut self, act_two: &[u8], our_node_id: PublicKey, shared_secret: SharedSecret
+	) -> Result<([u8; 66], PublicKey), LightningError> {
 		assert_eq!(act_two.len(), 50);
 
 		let final_hkdf;
@@ -314,13 +350,9 @@ impl PeerChannelEncryptor {
 							panic!("Requested act at wrong step");
 						}
 
-						let (re, temp_k2) = PeerChannelEncryptor::inbound_noise_act(bidirectional_state, act_two, NoiseSecretKey::<NS>::InMemory(&ie))?;
+						let (_, temp_k2) = PeerChannelEncryptor::inbound_noise_act(bidirectional_state, act_two, NoiseSecretKey::InMemory(&ie))?;
 
 						let mut res = [0; 66];
-						let our_node_id = node_signer.get_node_id(Recipient::Node).map_err(|_| LightningError {
-							err: "Failed to encrypt message".to_owned(),
-							action: msgs::ErrorAction::DisconnectPeer { msg: None }
-						})?;
 
 						PeerChannelEncryptor::encrypt_with_ad(&mut res[1..50], 1, &temp_k2, &bidirectional_state.h, &our_node_id.serialize()[..]);
 
@@ -329,11 +361,7 @@ impl PeerChannelEncryptor {
 						sha.input(&res[1..50]);
 						bidirectional_state.h = Sha256::from_engine(sha).into_inner();
 
-						let ss = node_signer.ecdh(Recipient::Node, &re, None).map_err(|_| LightningError {
-							err: "Failed to derive shared secret".to_owned(),
-							action: msgs::ErrorAction::DisconnectPeer { msg: None }
-						})?;
-						let temp_k = PeerChannelEncryptor::hkdf(bidirectional_state, ss);
+						let temp_k = PeerChannelEncryptor::hkdf(bidirectional_state, shared_secret);
 
 						PeerChannelEncryptor::encrypt_with_ad(&mut res[50..], 0, &temp_k, &bidirectional_state.h, &[0; 0]);
 						final_hkdf = hkdf_extract_expand_twice(&bidirectional_state.ck, &[0; 0]);
@@ -18,8 +18,8 @@
 use bitcoin::blockdata::constants::ChainHash;
 use bitcoin::secp256k1::{self, Secp256k1, SecretKey, PublicKey};
 
-use crate::sign::{KeysManager, NodeSigner, Recipient};
-use crate::events::{MessageSendEvent, MessageSendEventsProvider, OnionMessageProvider};
+use crate::sign::{KeysManager, NodeSigner, NodeSignerStatus, Recipient};
+use crate::events::{Event, EventHandler, EventsProvider, MessageSendEvent, MessageSendEventsProvider, OnionMessageProvider, PeerDisconnectReason};
 use crate::ln::features::{InitFeatures, NodeFeatures};
 use crate::ln::msgs;
 use crate::ln::msgs::{ChannelMessageHandler, LightningError, NetAddress, OnionMessageHandler, RoutingMessageHandler};
@@ -29,17 +29,19 @@ use crate::ln::peer_channel_encryptor::{PeerChannelEncryptor,NextNoiseStep};
 use crate::ln::wire;
 use crate::ln::wire::{Encode, Type};
 use crate::onion_message::{CustomOnionMessageContents, CustomOnionMessageHandler, OffersMessage, OffersMessageHandler, SimpleArcOnionMessenger, SimpleRefOnionMessenger};
-use crate::routing::gossip::{NetworkGraph, P2PGossipSync, NodeId, NodeAlias};
+use crate::routing::gossip::{NetworkGraph, P2PGossipSync, NodeId, NodeAlias, PeerAddressResolver};
 use crate::util::atomic_counter::AtomicCounter;
-use crate::util::logger::Logger;
+use crate::util::config::{PeerKeepaliveConfig, ResourceLimitsConfig, InboundConnectionLimitConfig, WireMessageObserverConfig};
+use crate::util::logger::{Logger, WithContext};
 use crate::util::string::PrintableString;
 
 use crate::prelude::*;
 use crate::io;
 use alloc::collections::LinkedList;
 use crate::sync::{Arc, Mutex, MutexGuard, FairRwLock};
-use core::sync::atomic::{AtomicBool, AtomicU32, AtomicI32, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicI32, AtomicUsize, Ordering};
 use core::{cmp, hash, fmt, mem};
+use core::time::Duration;
 use core::ops::Deref;
 use core::convert::Infallible;
 #[cfg(feature = "std")] use std::error;
@@ -91,6 +93,8 @@ impl MessageSendEventsProvider for IgnoringMessageHandler {
 impl RoutingMessageHandler for IgnoringMessageHandler {
 	fn handle_node_announcement(&self, _msg: &msgs::NodeAnnouncement) -> Result<bool, LightningError> { Ok(false) }
 	fn handle_channel_announcement(&self, _msg: &msgs::ChannelAnnouncement) -> Result<bool, LightningError> { Ok(false) }
+	#[cfg(taproot)]
+	fn handle_channel_announcement_2(&self, _msg: &msgs::ChannelAnnouncement2) -> Result<bool, LightningError> { Ok(false) }
 	fn handle_channel_update(&self, _msg: &msgs::ChannelUpdate) -> Result<bool, LightningError> { Ok(false) }
 	fn get_next_channel_announcement(&self, _starting_point: u64) ->
 		Option<(msgs::ChannelAnnouncement, Option<msgs::ChannelUpdate>, Option<msgs::ChannelUpdate>)> { None }
@@ -108,6 +112,7 @@ impl RoutingMessageHandler for IgnoringMessageHandler {
 }
 impl OnionMessageProvider for IgnoringMessageHandler {
 	fn next_onion_message_for_peer(&self, _peer_node_id: PublicKey) -> Option<msgs::OnionMessage> { None }
+	fn has_pending_onion_messages(&self) -> bool { false }
 }
 impl OnionMessageHandler for IgnoringMessageHandler {
 	fn handle_onion_message(&self, _their_node_id: &PublicKey, _msg: &msgs::OnionMessage) {}
@@ -121,6 +126,9 @@ impl OnionMessageHandler for IgnoringMessageHandler {
 impl OffersMessageHandler for IgnoringMessageHandler {
 	fn handle_message(&self, _msg: OffersMessage) -> Option<OffersMessage> { None }
 }
+impl PeerAddressResolver for IgnoringMessageHandler {
+	fn resolve_addresses(&self, _node_id: &PublicKey) -> Vec<NetAddress> { Vec::new() }
+}
 impl CustomOnionMessageHandler for IgnoringMessageHandler {
 	type CustomMessage = Infallible;
 	fn handle_custom_message(&self, _msg: Infallible) -> Option<Infallible> {
@@ -179,12 +187,19 @@ impl CustomMessageHandler for IgnoringMessageHandler {
 /// A dummy struct which implements `ChannelMessageHandler` without having any channels.
 /// You can provide one of these as the route_handler in a MessageHandler.
 pub struct ErroringMessageHandler {
-	message_queue: Mutex<Vec<MessageSendEvent>>
+	message_queue: Mutex<Vec<MessageSendEvent>>,
+	advertise_channel_features: bool,
 }
 impl ErroringMessageHandler {
 	/// Constructs a new ErroringMessageHandler
 	pub fn new() -> Self {
-		Self { message_queue: Mutex::new(Vec::new()) }
+		Self { message_queue: Mutex::new(Vec::new()), advertise_channel_features: true }
+	}
+	/// Constructs a new ErroringMessageHandler which, unlike one returned by [`Self::new`], does
+	/// not claim support for any channel features in our [`msgs::Init`] messages, see
+	/// [`PeerManager::new_gossip_only`].
+	pub fn new_gossip_only() -> Self {
+		Self { message_queue: Mutex::new(Vec::new()), advertise_channel_features: false }
 	}
 	fn push_error(&self, node_id: &PublicKey, channel_id: [u8; 32]) {
 		self.message_queue.lock().unwrap().push(MessageSendEvent::HandleError {
@@ -226,6 +241,12 @@ impl ChannelMessageHandler for ErroringMessageHandler {
 	fn handle_closing_signed(&self, their_node_id: &PublicKey, msg: &msgs::ClosingSigned) {
 		ErroringMessageHandler::push_error(self, their_node_id, msg.channel_id);
 	}
+	fn handle_closing_complete(&self, their_node_id: &PublicKey, msg: &msgs::ClosingComplete) {
+		ErroringMessageHandler::push_error(self, their_node_id, msg.channel_id);
+	}
+	fn handle_closing_sig(&self, their_node_id: &PublicKey, msg: &msgs::ClosingSig) {
+		ErroringMessageHandler::push_error(self, their_node_id, msg.channel_id);
+	}
 	fn handle_update_add_htlc(&self, their_node_id: &PublicKey, msg: &msgs::UpdateAddHTLC) {
 		ErroringMessageHandler::push_error(self, their_node_id, msg.channel_id);
 	}
@@ -260,6 +281,7 @@ impl ChannelMessageHandler for ErroringMessageHandler {
 	fn handle_error(&self, _their_node_id: &PublicKey, _msg: &msgs::ErrorMessage) {}
 	fn provided_node_features(&self) -> NodeFeatures { NodeFeatures::empty() }
 	fn provided_init_features(&self, _their_node_id: &PublicKey) -> InitFeatures {
+		if !self.advertise_channel_features { return InitFeatures::empty(); }
 		// Set a number of features which various nodes may require to talk to us. It's totally
 		// reasonable to indicate we "support" all kinds of channel features...we just reject all
 		// channels.
@@ -328,6 +350,10 @@ impl ChannelMessageHandler for ErroringMessageHandler {
 	fn handle_tx_abort(&self, their_node_id: &PublicKey, msg: &msgs::TxAbort) {
 		ErroringMessageHandler::push_error(self, their_node_id, msg.channel_id);
 	}
+
+	// Peer storage is optional, so simply ignore it rather than erroring.
+	fn handle_peer_storage(&self, _their_node_id: &PublicKey, _msg: &msgs::PeerStorage) {}
+	fn handle_your_peer_storage(&self, _their_node_id: &PublicKey, _msg: &msgs::YourPeerStorage) {}
 }
 
 impl Deref for ErroringMessageHandler {
@@ -441,8 +467,9 @@ const FORWARD_INIT_SYNC_BUFFER_LIMIT_RATIO: usize = 2;
 /// refilled as we send bytes.
 const OUTBOUND_BUFFER_LIMIT_READ_PAUSE: usize = 12;
 /// When the outbound buffer has this many messages, we'll simply skip relaying gossip messages to
-/// the peer.
-const OUTBOUND_BUFFER_LIMIT_DROP_GOSSIP: usize = OUTBOUND_BUFFER_LIMIT_READ_PAUSE * FORWARD_INIT_SYNC_BUFFER_LIMIT_RATIO;
+/// the peer. Note that this is only the default, see
+/// [`ResourceLimitsConfig::max_outbound_gossip_buffer_messages`].
+pub(crate) const OUTBOUND_BUFFER_LIMIT_DROP_GOSSIP: usize = OUTBOUND_BUFFER_LIMIT_READ_PAUSE * FORWARD_INIT_SYNC_BUFFER_LIMIT_RATIO;
 
 /// If we've sent a ping, and are still awaiting a response, we may need to churn our way through
 /// the socket receive buffer before receiving the ping.
@@ -458,7 +485,9 @@ const OUTBOUND_BUFFER_LIMIT_DROP_GOSSIP: usize = OUTBOUND_BUFFER_LIMIT_READ_PAUS
 ///
 /// When we improve parallelism somewhat we should reduce this to e.g. this many timer ticks per
 /// two connected peers, assuming most LDK-running systems have at least two cores.
-const MAX_BUFFER_DRAIN_TICK_INTERVALS_PER_PEER: i8 = 4;
+///
+/// Note that this is only the default, see [`PeerKeepaliveConfig::max_pong_wait_ticks_per_peer`].
+pub(crate) const MAX_BUFFER_DRAIN_TICK_INTERVALS_PER_PEER: u32 = 4;
 
 /// This is the minimum number of messages we expect a peer to be able to handle within one timer
 /// tick. Once we have sent this many messages since the last ping, we send a ping right away to
@@ -470,6 +499,16 @@ const MAX_BUFFER_DRAIN_TICK_INTERVALS_PER_PEER: i8 = 4;
 /// [`FORWARD_INIT_SYNC_BUFFER_LIMIT_RATIO`]) than a hard limit.
 const BUFFER_DRAIN_MSGS_PER_TICK: usize = 32;
 
+/// The minimum number of timer ticks between two node_announcements sent via
+/// [`PeerManager::update_node_announcement`]. Updates made more often than this are delayed until
+/// this many ticks have passed, as other nodes rate-limit the gossip they accept and relay.
+const NODE_ANNOUNCEMENT_UPDATE_INTERVAL_TICKS: u32 = 30;
+
+/// The number of timer ticks after which we rebroadcast our latest node_announcement, roughly
+/// once a day given the recommended ten second interval. Other implementations prune nodes
+/// which have not sent a fresh node_announcement in two weeks from their graphs.
+const NODE_ANNOUNCEMENT_REBROADCAST_INTERVAL_TICKS: u32 = 6 * 60 * 24;
+
 struct Peer {
 	channel_encryptor: PeerChannelEncryptor,
 	/// We cache a `NodeId` here to avoid serializing peers' keys every time we forward gossip
@@ -484,13 +523,28 @@ struct Peer {
 	their_features: Option<InitFeatures>,
 	their_net_address: Option<NetAddress>,
 
+	/// Messages which have been encrypted and are ready to be sent, in order. Only messages of
+	/// [`MessagePriority::Channel`] are encrypted as soon as they are enqueued, all other messages
+	/// are only moved here once this buffer has been drained.
 	pending_outbound_buffer: LinkedList<Vec<u8>>,
 	pending_outbound_buffer_first_msg_offset: usize,
+	/// Queue messages of [`MessagePriority::Ping`] separately from `pending_outbound_buffer` so
+	/// that channel messages are never delayed behind them.
+	///
+	/// Note that these messages are *not* encrypted/MAC'd, and are only serialized.
+	pending_ping_buffer: LinkedList<Vec<u8>>,
+	/// Queue messages of [`MessagePriority::Gossip`] separately from `pending_outbound_buffer` so
+	/// that channel messages and pings are never delayed behind them.
+	///
+	/// Note that these messages are *not* encrypted/MAC'd, and are only serialized.
+	pending_gossip_buffer: LinkedList<Vec<u8>>,
 	/// Queue gossip broadcasts separately from `pending_outbound_buffer` so we can easily
 	/// prioritize channel messages over them.
 	///
 	/// Note that these messages are *not* encrypted/MAC'd, and are only serialized.
 	gossip_broadcast_buffer: LinkedList<Vec<u8>>,
+	/// The number of gossip broadcasts we've dropped for this peer as its outbound buffer was full.
+	gossip_broadcasts_dropped: usize,
 	awaiting_write_event: bool,
 
 	pending_read_buffer: Vec<u8>,
@@ -502,6 +556,13 @@ struct Peer {
 	msgs_sent_since_pong: usize,
 	awaiting_pong_timer_tick_intervals: i64,
 	received_message_since_timer_tick: bool,
+	/// The number of timer ticks since we last sent a ping, while not awaiting a pong.
+	ticks_since_ping: u32,
+	/// When we sent the ping we're awaiting a pong for, if any.
+	#[cfg(feature = "std")]
+	ping_sent_at: Option<std::time::Instant>,
+	/// The round-trip time of the latest ping we received a pong for.
+	last_ping_round_trip_time: Option<Duration>,
 	sent_gossip_timestamp_filter: bool,
 
 	/// Indicates we've received a `channel_announcement` since the last time we had
@@ -511,6 +572,40 @@ struct Peer {
 	received_channel_announce_since_backlogged: bool,
 
 	inbound_connection: bool,
+	/// Whether this is an inbound connection counted against our
+	/// [`InboundConnectionLimitConfig`] which has not yet completed the noise handshake.
+	inbound_handshake_pending: bool,
+	/// Whether the noise act in `pending_read_buffer` is waiting on our [`NodeSigner`] to derive
+	/// the shared secret it requires, see [`PeerManager::node_signer_unblocked`].
+	awaiting_node_signer: bool,
+}
+
+/// The priority class of an outbound message. Messages of a higher priority class are always sent
+/// before those of a lower one, while messages of the same class are sent in the order they were
+/// enqueued.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MessagePriority {
+	/// Channel messages, i.e., commitment updates and revocations as well as the HTLC updates
+	/// preceding them, and connection setup and error messages.
+	///
+	/// These share a class as they must be delivered in order, e.g. a `commitment_signed` covers
+	/// all updates sent before it.
+	Channel,
+	/// Pings and pongs, which are only used to detect stalled connections.
+	Ping,
+	/// Gossip sent in response to queries or as part of the initial sync. Gossip broadcasts have a
+	/// yet lower priority and may be dropped entirely if a peer's outbound buffer is full.
+	Gossip,
+}
+
+impl MessagePriority {
+	fn of_type(type_id: u16) -> Self {
+		match type_id {
+			msgs::Ping::TYPE | msgs::Pong::TYPE => MessagePriority::Ping,
+			type_id if is_gossip_msg(type_id) => MessagePriority::Gossip,
+			_ => MessagePriority::Channel,
+		}
+	}
 }
 
 impl Peer {
@@ -560,14 +655,37 @@ impl Peer {
 		if !gossip_processing_backlogged {
 			self.received_channel_announce_since_backlogged = false;
 		}
-		self.pending_outbound_buffer.len() < OUTBOUND_BUFFER_LIMIT_READ_PAUSE &&
+		self.queued_outbound_messages() < OUTBOUND_BUFFER_LIMIT_READ_PAUSE &&
 			(!gossip_processing_backlogged || !self.received_channel_announce_since_backlogged)
 	}
 
+	/// The number of messages queued for this peer, excluding gossip broadcasts.
+	fn queued_outbound_messages(&self) -> usize {
+		self.pending_outbound_buffer.len() + self.pending_ping_buffer.len() + self.pending_gossip_buffer.len()
+	}
+
+	/// Moves the next unencrypted message, in priority order, onto the outbound buffer once it has
+	/// been drained, such that messages are encrypted in the order in which they are sent.
+	fn encrypt_next_queued_message(&mut self) {
+		if !self.pending_outbound_buffer.is_empty() { return; }
+		let next_msg = self.pending_ping_buffer.pop_front()
+			.or_else(|| self.pending_gossip_buffer.pop_front());
+		if let Some(msg) = next_msg {
+			self.pending_outbound_buffer.push_back(self.channel_encryptor.encrypt_buffer(&msg[..]));
+		}
+	}
+
 	/// Determines if we should push additional gossip background sync (aka "backfill") onto a peer's
 	/// outbound buffer. This is checked every time the peer's buffer may have been drained.
+	/// Returns whether we have messages buffered for this peer, or gossip we're still syncing to
+	/// it, which a later call to `do_attempt_write_data` may make progress on.
+	fn has_pending_writes(&self) -> bool {
+		self.queued_outbound_messages() > 0 || !self.gossip_broadcast_buffer.is_empty() ||
+			match self.sync_status { InitSyncTracker::NoSyncRequested => false, _ => true }
+	}
+
 	fn should_buffer_gossip_backfill(&self) -> bool {
-		self.pending_outbound_buffer.is_empty() && self.gossip_broadcast_buffer.is_empty()
+		self.queued_outbound_messages() == 0 && self.gossip_broadcast_buffer.is_empty()
 			&& self.msgs_sent_since_pong < BUFFER_DRAIN_MSGS_PER_TICK
 			&& self.handshake_complete()
 	}
@@ -575,29 +693,68 @@ impl Peer {
 	/// Determines if we should push an onion message onto a peer's outbound buffer. This is checked
 	/// every time the peer's buffer may have been drained.
 	fn should_buffer_onion_message(&self) -> bool {
-		self.pending_outbound_buffer.is_empty() && self.handshake_complete()
+		self.queued_outbound_messages() == 0 && self.handshake_complete()
 			&& self.msgs_sent_since_pong < BUFFER_DRAIN_MSGS_PER_TICK
 	}
 
 	/// Determines if we should push additional gossip broadcast messages onto a peer's outbound
 	/// buffer. This is checked every time the peer's buffer may have been drained.
 	fn should_buffer_gossip_broadcast(&self) -> bool {
-		self.pending_outbound_buffer.is_empty() && self.handshake_complete()
+		self.queued_outbound_messages() == 0 && self.handshake_complete()
 			&& self.msgs_sent_since_pong < BUFFER_DRAIN_MSGS_PER_TICK
 	}
 
 	/// Returns whether this peer's outbound buffers are full and we should drop gossip broadcasts.
-	fn buffer_full_drop_gossip_broadcast(&self) -> bool {
+	///
+	/// `buffer_limit` is the configured [`ResourceLimitsConfig::max_outbound_gossip_buffer_messages`].
+	fn buffer_full_drop_gossip_broadcast(&self, buffer_limit: usize) -> bool {
 		let total_outbound_buffered =
-			self.gossip_broadcast_buffer.len() + self.pending_outbound_buffer.len();
+			self.gossip_broadcast_buffer.len() + self.queued_outbound_messages();
 
-		total_outbound_buffered > OUTBOUND_BUFFER_LIMIT_DROP_GOSSIP ||
+		total_outbound_buffered > buffer_limit ||
 			self.msgs_sent_since_pong > BUFFER_DRAIN_MSGS_PER_TICK * FORWARD_INIT_SYNC_BUFFER_LIMIT_RATIO
 	}
 
 	fn set_their_node_id(&mut self, node_id: PublicKey) {
 		self.their_node_id = Some((node_id, NodeId::from_pubkey(&node_id)));
 	}
+
+	/// Records that we've sent a ping which we expect a pong for, to measure its round-trip time.
+	fn ping_sent(&mut self) {
+		#[cfg(feature = "std")] {
+			self.ping_sent_at = Some(std::time::Instant::now());
+		}
+	}
+
+	fn pong_received(&mut self) {
+		#[cfg(feature = "std")] {
+			if let Some(ping_sent_at) = self.ping_sent_at.take() {
+				self.last_ping_round_trip_time = Some(ping_sent_at.elapsed());
+			}
+		}
+	}
+}
+
+/// Details of a connected peer, as returned by [`PeerManager::peer_by_node_id`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerDetails {
+	/// The node id of the peer.
+	pub counterparty_node_id: PublicKey,
+	/// The socket address the peer connected from or we connected to, if it was given via
+	/// [`PeerManager::new_outbound_connection`] or [`PeerManager::new_inbound_connection`].
+	pub socket_address: Option<NetAddress>,
+	/// The features the peer provided in its [`msgs::Init`] message.
+	pub init_features: InitFeatures,
+	/// Whether the peer connected to us, rather than us to it.
+	pub is_inbound_connection: bool,
+	/// The round-trip time of the latest ping we sent the peer, measured from when the ping was
+	/// enqueued until its pong was received.
+	///
+	/// This is `None` until a pong was received, and always `None` without the `std` feature.
+	pub ping_round_trip_time: Option<Duration>,
+	/// The number of gossip broadcasts not relayed to the peer because its outbound buffer was
+	/// full. See [`PeerManager::peer_gossip_broadcasts_dropped`].
+	pub gossip_broadcasts_dropped: usize,
 }
 
 /// SimpleArcPeerManager is useful when you need a PeerManager with a static lifetime, e.g.
@@ -685,6 +842,43 @@ APeerManager for PeerManager<Descriptor, CM, RM, OM, L, CMH, NS> where
 	fn as_ref(&self) -> &PeerManager<Descriptor, CM, RM, OM, L, CMH, NS> { self }
 }
 
+/// The direction of a message passed to a [`WireMessageObserver`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WireMessageDirection {
+	/// The message was received from the peer.
+	Inbound,
+	/// The message was queued to be sent to the peer.
+	Outbound,
+}
+
+/// Observes the raw messages exchanged with our peers, allowing protocol debugging or packet
+/// capture tooling to be built on top of a [`PeerManager`]. Set via
+/// [`PeerManager::with_wire_message_observer`].
+///
+/// Inbound messages are observed after they were decrypted and decoded, outbound messages when
+/// they are queued to be sent, subject to the limits set in the [`WireMessageObserverConfig`].
+///
+/// Observed messages are queued and handed to the observer once the [`PeerManager`] no longer
+/// holds any peer's lock, i.e. at the end of calls to [`PeerManager::read_event`],
+/// [`PeerManager::process_events`], [`PeerManager::write_buffer_space_avail`] and
+/// [`PeerManager::timer_tick_occurred`]. Observers should still return quickly, as they block
+/// the calling thread.
+pub trait WireMessageObserver {
+	/// Returns whether the serialized payload of messages of the given type should be passed to
+	/// [`Self::observe_message`].
+	///
+	/// Payloads may contain sensitive data and outbound messages have to be serialized an
+	/// additional time to provide them, thus by default no payloads are provided.
+	fn observe_payload(&self, _message_type: u16) -> bool { false }
+
+	/// Called with each observed message's direction, the peer it was exchanged with, its type
+	/// and, if requested via [`Self::observe_payload`], its serialization including the type.
+	fn observe_message(
+		&self, direction: WireMessageDirection, their_node_id: &PublicKey, message_type: u16,
+		payload: Option<&[u8]>
+	);
+}
+
 /// A PeerManager manages a set of peers, described by their [`SocketDescriptor`] and marshalls
 /// socket events into messages which it passes on to its [`MessageHandler`].
 ///
@@ -742,6 +936,12 @@ pub struct PeerManager<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: D
 	/// Used to track the last value sent in a node_announcement "timestamp" field. We ensure this
 	/// value increases strictly since we don't assume access to a time source.
 	last_node_announcement_serial: AtomicU32,
+	/// The contents of our latest node_announcement, used to rebroadcast it periodically. Never
+	/// locked while `peers` is held.
+	node_announcement_state: Mutex<Option<NodeAnnouncementState>>,
+	/// Our latest node_announcement, if our [`NodeSigner`] is still signing it. This is a leaf
+	/// lock, never locked while `peers` is held.
+	pending_node_announcement: Mutex<Option<msgs::UnsignedNodeAnnouncement>>,
 
 	ephemeral_key_midstate: Sha256Engine,
 
@@ -750,12 +950,84 @@ pub struct PeerManager<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: D
 	gossip_processing_backlogged: AtomicBool,
 	gossip_processing_backlog_lifted: AtomicBool,
 
+	resource_limits: ResourceLimitsConfig,
+	/// The number of gossip broadcasts we've skipped for a peer as its outbound buffer was full.
+	gossip_buffer_limit_hits: AtomicUsize,
+	keepalive_config: PeerKeepaliveConfig,
+
+	/// Whether [`Event::PeerConnected`] and [`Event::PeerDisconnected`] are generated.
+	generate_peer_events: bool,
+	/// Peer lifecycle events which have not yet been processed. This is a leaf lock, no other
+	/// locks may be taken while it is held.
+	pending_events: Mutex<Vec<Event>>,
+
+	/// Limits on new inbound connections, if any were set.
+	inbound_connection_limits: Option<InboundConnectionLimitConfig>,
+	/// State tracking inbound handshakes against `inbound_connection_limits`. This is a leaf lock,
+	/// no other locks may be taken while it is held.
+	inbound_handshakes: Mutex<InboundHandshakeTracker>,
+	/// The number of inbound connections we've refused due to `inbound_connection_limits`.
+	inbound_connection_limit_hits: AtomicUsize,
+
+	/// Peers which may have something to write, and thus need to be visited by the next call to
+	/// [`Self::process_events`]. Peers not in this set are skipped unless a full pass is needed.
+	/// This is a leaf lock, no other locks may be taken while it is held.
+	peers_needing_write: Mutex<HashSet<Descriptor>>,
+
+	/// The observer of wire messages, if any, and the limits on the messages passed to it.
+	wire_message_observer: Option<(Box<dyn WireMessageObserver + Send + Sync>, WireMessageObserverConfig)>,
+	/// The number of messages considered for observation, used for sampling.
+	wire_messages_seen: AtomicUsize,
+	/// The number of messages observed since the last timer tick.
+	wire_messages_observed_this_tick: AtomicUsize,
+	/// Messages to pass to our [`WireMessageObserver`] once we no longer hold any peer's lock.
+	pending_wire_message_observations: Mutex<Vec<(WireMessageDirection, PublicKey, u16, Option<Vec<u8>>)>>,
+
 	node_signer: NS,
 
 	logger: L,
 	secp_ctx: Secp256k1<secp256k1::SignOnly>
 }
 
+/// The contents of the latest node_announcement we broadcast or are about to broadcast.
+struct NodeAnnouncementState {
+	rgb: [u8; 3],
+	alias: [u8; 32],
+	addresses: Vec<NetAddress>,
+	ticks_since_broadcast: u32,
+	/// Whether an update was made which we have not yet broadcast due to rate-limiting.
+	update_pending: bool,
+}
+
+/// Where we are in the inbound handshake rate-limiting window for a given address.
+#[derive(Default)]
+struct AddressHandshakeState {
+	ticks_since_window_start: u32,
+	handshakes_in_window: u32,
+	failed_handshakes_in_window: u32,
+	cooldown_ticks_remaining: u32,
+}
+
+/// Tracks inbound connections which are working through the noise handshake.
+struct InboundHandshakeTracker {
+	pending_handshakes: usize,
+	/// Keyed by the remote address with its port zeroed.
+	addresses: HashMap<NetAddress, AddressHandshakeState>,
+}
+
+/// Gets the address we rate-limit inbound connections by, i.e. the given address without its
+/// port, as a single host will generally connect from many ports.
+fn rate_limited_address(address: &NetAddress) -> NetAddress {
+	match address {
+		NetAddress::IPv4 { addr, .. } => NetAddress::IPv4 { addr: *addr, port: 0 },
+		NetAddress::IPv6 { addr, .. } => NetAddress::IPv6 { addr: *addr, port: 0 },
+		NetAddress::OnionV2(addr) => NetAddress::OnionV2(*addr),
+		NetAddress::OnionV3 { ed25519_pubkey, checksum, version, .. } =>
+			NetAddress::OnionV3 { ed25519_pubkey: *ed25519_pubkey, checksum: *checksum, version: *version, port: 0 },
+		NetAddress::Hostname { hostname, .. } => NetAddress::Hostname { hostname: hostname.clone(), port: 0 },
+	}
+}
+
 enum MessageHandlingError {
 	PeerHandleError(PeerHandleError),
 	LightningError(LightningError),
@@ -835,6 +1107,32 @@ impl<Descriptor: SocketDescriptor, RM: Deref, L: Deref, NS: Deref> PeerManager<D
 			custom_message_handler: IgnoringMessageHandler{},
 		}, current_time, ephemeral_random_data, logger, node_signer)
 	}
+
+	/// Constructs a new `PeerManager` which only relays gossip via the given
+	/// `RoutingMessageHandler`, e.g. for network crawlers or explorers which don't need a
+	/// [`ChannelManager`] or [`ChainMonitor`].
+	///
+	/// Like [`Self::new_routing_only`], onion messages are ignored and channel messages generate
+	/// error messages, so no channel can ever be opened accidentally. Unlike it, we do not claim
+	/// support for any channel features in our [`msgs::Init`] messages, but only advertise the
+	/// features of the `RoutingMessageHandler`. Note that this may cause peers which require some
+	/// channel features to disconnect from us.
+	///
+	/// See [`Self::new_routing_only`] for a description of `current_time` and
+	/// `ephemeral_random_data`.
+	///
+	/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+	/// [`ChainMonitor`]: crate::chain::chainmonitor::ChainMonitor
+	///
+	/// This is not exported to bindings users as we can't export a PeerManager with a dummy channel handler
+	pub fn new_gossip_only(routing_message_handler: RM, current_time: u32, ephemeral_random_data: &[u8; 32], logger: L, node_signer: NS) -> Self {
+		Self::new(MessageHandler {
+			chan_handler: ErroringMessageHandler::new_gossip_only(),
+			route_handler: routing_message_handler,
+			onion_message_handler: IgnoringMessageHandler{},
+			custom_message_handler: IgnoringMessageHandler{},
+		}, current_time, ephemeral_random_data, logger, node_signer)
+	}
 }
 
 /// A simple wrapper that optionally prints ` from <pubkey>` for an optional pubkey.
@@ -912,13 +1210,138 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 			peer_counter: AtomicCounter::new(),
 			gossip_processing_backlogged: AtomicBool::new(false),
 			gossip_processing_backlog_lifted: AtomicBool::new(false),
+			resource_limits: ResourceLimitsConfig::default(),
+			gossip_buffer_limit_hits: AtomicUsize::new(0),
+			keepalive_config: PeerKeepaliveConfig::default(),
+			generate_peer_events: false,
+			pending_events: Mutex::new(Vec::new()),
+			inbound_connection_limits: None,
+			inbound_handshakes: Mutex::new(InboundHandshakeTracker {
+				pending_handshakes: 0, addresses: HashMap::new(),
+			}),
+			inbound_connection_limit_hits: AtomicUsize::new(0),
+			peers_needing_write: Mutex::new(HashSet::new()),
+			wire_message_observer: None,
+			wire_messages_seen: AtomicUsize::new(0),
+			wire_messages_observed_this_tick: AtomicUsize::new(0),
+			pending_wire_message_observations: Mutex::new(Vec::new()),
 			last_node_announcement_serial: AtomicU32::new(current_time),
+			node_announcement_state: Mutex::new(None),
+			pending_node_announcement: Mutex::new(None),
 			logger,
 			node_signer,
 			secp_ctx,
 		}
 	}
 
+	/// Sets the limits on per-peer outbound gossip buffering, replacing the defaults.
+	///
+	/// Only [`ResourceLimitsConfig::max_outbound_gossip_buffer_messages`] is used by the
+	/// `PeerManager`.
+	pub fn with_resource_limits(mut self, resource_limits: ResourceLimitsConfig) -> Self {
+		self.resource_limits = resource_limits;
+		self
+	}
+
+	/// Sets how often peers are pinged and how long they may take to respond, replacing the
+	/// defaults.
+	pub fn with_keepalive_config(mut self, keepalive_config: PeerKeepaliveConfig) -> Self {
+		self.keepalive_config = keepalive_config;
+		self
+	}
+
+	/// Limits the rate at which new inbound connections are accepted, per address and overall, as
+	/// described in [`InboundConnectionLimitConfig`].
+	///
+	/// By default no such limits are applied.
+	pub fn with_inbound_connection_limits(mut self, limits: InboundConnectionLimitConfig) -> Self {
+		self.inbound_connection_limits = Some(limits);
+		self
+	}
+
+	/// Enables the generation of [`Event::PeerConnected`] and [`Event::PeerDisconnected`], which
+	/// must then be processed regularly via [`EventsProvider::process_pending_events`].
+	pub fn with_peer_events(mut self) -> Self {
+		self.generate_peer_events = true;
+		self
+	}
+
+	/// Sets a [`WireMessageObserver`] to be called with the messages exchanged with our peers,
+	/// within the limits of the given `config`.
+	pub fn with_wire_message_observer(
+		mut self, observer: Box<dyn WireMessageObserver + Send + Sync>, config: WireMessageObserverConfig
+	) -> Self {
+		self.wire_message_observer = Some((observer, config));
+		self
+	}
+
+	/// Queues the message of the given type to be passed to our [`WireMessageObserver`], if it
+	/// should be as determined by its [`WireMessageObserverConfig`]. The message is only
+	/// serialized via `payload` if the observer requested it.
+	fn queue_wire_message_observation<P: FnOnce() -> Vec<u8>>(
+		&self, direction: WireMessageDirection, their_node_id: &PublicKey, message_type: u16, payload: P
+	) {
+		if let Some(observer) = self.sampled_wire_message_observer(message_type) {
+			let payload = if observer.observe_payload(message_type) { Some(payload()) } else { None };
+			self.pending_wire_message_observations.lock().unwrap()
+				.push((direction, *their_node_id, message_type, payload));
+		}
+	}
+
+	/// Passes any queued messages to our [`WireMessageObserver`]. Must not be called while holding
+	/// any peer's lock.
+	fn notify_wire_message_observer(&self) {
+		if let Some((observer, _)) = self.wire_message_observer.as_ref() {
+			let observations = mem::replace(&mut *self.pending_wire_message_observations.lock().unwrap(), Vec::new());
+			for (direction, their_node_id, message_type, payload) in observations {
+				observer.observe_message(direction, &their_node_id, message_type, payload.as_ref().map(|payload| &payload[..]));
+			}
+		}
+	}
+
+	/// Returns our [`WireMessageObserver`] if the next message of the given type should be passed
+	/// to it, as determined by its [`WireMessageObserverConfig`].
+	fn sampled_wire_message_observer(&self, message_type: u16) -> Option<&(dyn WireMessageObserver + Send + Sync)> {
+		let (observer, config) = self.wire_message_observer.as_ref()?;
+		if !config.observe_gossip && is_gossip_msg(message_type) { return None; }
+		let seen = self.wire_messages_seen.fetch_add(1, Ordering::Relaxed);
+		if seen % cmp::max(config.sample_interval, 1) as usize != 0 { return None; }
+		if self.wire_messages_observed_this_tick.fetch_add(1, Ordering::Relaxed) >= config.max_messages_per_tick as usize {
+			return None;
+		}
+		Some(&**observer)
+	}
+
+	fn push_peer_event(&self, event: Event) {
+		if self.generate_peer_events {
+			self.pending_events.lock().unwrap().push(event);
+		}
+	}
+
+	/// Gets the number of times a gossip broadcast was not relayed to a peer because its outbound
+	/// buffer exceeded [`ResourceLimitsConfig::max_outbound_gossip_buffer_messages`].
+	pub fn gossip_buffer_limit_hits(&self) -> usize {
+		self.gossip_buffer_limit_hits.load(Ordering::Relaxed)
+	}
+
+	/// Gets the number of inbound connections refused by [`Self::new_inbound_connection`] due to
+	/// the limits set via [`Self::with_inbound_connection_limits`].
+	pub fn inbound_connection_limit_hits(&self) -> usize {
+		self.inbound_connection_limit_hits.load(Ordering::Relaxed)
+	}
+
+	/// Gets the number of gossip broadcasts not relayed to the connected peer with the given node
+	/// id because its outbound buffer was full, or `None` if no such peer is connected.
+	///
+	/// Gossip is always sent only after any pending channel messages and pings, thus a peer which
+	/// can't keep up with the gossip we relay will see these dropped rather than its channel
+	/// messages delayed.
+	pub fn peer_gossip_broadcasts_dropped(&self, their_node_id: &PublicKey) -> Option<usize> {
+		let peers = self.peers.read().unwrap();
+		let descriptor = self.node_id_to_descriptor.lock().unwrap().get(their_node_id).cloned()?;
+		peers.get(&descriptor).map(|peer_mutex| peer_mutex.lock().unwrap().gossip_broadcasts_dropped)
+	}
+
 	/// Get a list of tuples mapping from node id to network addresses for peers which have
 	/// completed the initial handshake.
 	///
@@ -940,6 +1363,25 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 		}).collect()
 	}
 
+	/// Gets the [`PeerDetails`] of the peer with the given node id, or `None` if it is not
+	/// connected or has not yet completed the initial handshake.
+	pub fn peer_by_node_id(&self, their_node_id: &PublicKey) -> Option<PeerDetails> {
+		let peers = self.peers.read().unwrap();
+		let descriptor = self.node_id_to_descriptor.lock().unwrap().get(their_node_id).cloned()?;
+		let peer = peers.get(&descriptor)?.lock().unwrap();
+		if !peer.handshake_complete() {
+			return None;
+		}
+		Some(PeerDetails {
+			counterparty_node_id: peer.their_node_id.unwrap().0,
+			socket_address: peer.their_net_address.clone(),
+			init_features: peer.their_features.clone().unwrap(),
+			is_inbound_connection: peer.inbound_connection,
+			ping_round_trip_time: peer.last_ping_round_trip_time,
+			gossip_broadcasts_dropped: peer.gossip_broadcasts_dropped,
+		})
+	}
+
 	fn get_ephemeral_key(&self) -> SecretKey {
 		let mut ephemeral_hash = self.ephemeral_key_midstate.clone();
 		let counter = self.peer_counter.get_increment();
@@ -989,7 +1431,10 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 
 					pending_outbound_buffer: LinkedList::new(),
 					pending_outbound_buffer_first_msg_offset: 0,
+					pending_ping_buffer: LinkedList::new(),
+					pending_gossip_buffer: LinkedList::new(),
 					gossip_broadcast_buffer: LinkedList::new(),
+					gossip_broadcasts_dropped: 0,
 					awaiting_write_event: false,
 
 					pending_read_buffer,
@@ -1001,10 +1446,16 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 					msgs_sent_since_pong: 0,
 					awaiting_pong_timer_tick_intervals: 0,
 					received_message_since_timer_tick: false,
+					ticks_since_ping: 0,
+					#[cfg(feature = "std")]
+					ping_sent_at: None,
+					last_ping_round_trip_time: None,
 					sent_gossip_timestamp_filter: false,
 
 					received_channel_announce_since_backlogged: false,
 					inbound_connection: false,
+					inbound_handshake_pending: false,
+					awaiting_node_signer: false,
 				}));
 				Ok(res)
 			}
@@ -1020,13 +1471,20 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 	///
 	/// May refuse the connection by returning an Err, but will never write bytes to the remote end
 	/// (outbound connector always speaks first). If an `Err` is returned here you must disconnect
-	/// the connection immediately.
+	/// the connection immediately. Connections are refused if they exceed the limits set via
+	/// [`Self::with_inbound_connection_limits`], for which the remote network address should be
+	/// provided.
 	///
 	/// Panics if descriptor is duplicative with some other descriptor which has not yet been
 	/// [`socket_disconnected`].
 	///
 	/// [`socket_disconnected`]: PeerManager::socket_disconnected
 	pub fn new_inbound_connection(&self, descriptor: Descriptor, remote_network_address: Option<NetAddress>) -> Result<(), PeerHandleError> {
+		let inbound_handshake_pending = self.inbound_connection_limits.is_some();
+		if inbound_handshake_pending {
+			self.start_inbound_handshake(&remote_network_address)?;
+		}
+
 		let peer_encryptor = PeerChannelEncryptor::new_inbound(&self.node_signer);
 		let pending_read_buffer = [0; 50].to_vec(); // Noise act one is 50 bytes
 
@@ -1034,6 +1492,9 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 		match peers.entry(descriptor) {
 			hash_map::Entry::Occupied(_) => {
 				debug_assert!(false, "PeerManager driver duplicated descriptors!");
+				if inbound_handshake_pending {
+					self.end_inbound_handshake(&remote_network_address, false);
+				}
 				Err(PeerHandleError {})
 			},
 			hash_map::Entry::Vacant(e) => {
@@ -1045,7 +1506,10 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 
 					pending_outbound_buffer: LinkedList::new(),
 					pending_outbound_buffer_first_msg_offset: 0,
+					pending_ping_buffer: LinkedList::new(),
+					pending_gossip_buffer: LinkedList::new(),
 					gossip_broadcast_buffer: LinkedList::new(),
+					gossip_broadcasts_dropped: 0,
 					awaiting_write_event: false,
 
 					pending_read_buffer,
@@ -1057,16 +1521,94 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 					msgs_sent_since_pong: 0,
 					awaiting_pong_timer_tick_intervals: 0,
 					received_message_since_timer_tick: false,
+					ticks_since_ping: 0,
+					#[cfg(feature = "std")]
+					ping_sent_at: None,
+					last_ping_round_trip_time: None,
 					sent_gossip_timestamp_filter: false,
 
 					received_channel_announce_since_backlogged: false,
 					inbound_connection: true,
+					inbound_handshake_pending,
+					awaiting_node_signer: false,
 				}));
 				Ok(())
 			}
 		}
 	}
 
+	/// Checks a new inbound connection against our [`InboundConnectionLimitConfig`], counting it
+	/// as a pending handshake if it is accepted.
+	fn start_inbound_handshake(&self, remote_network_address: &Option<NetAddress>) -> Result<(), PeerHandleError> {
+		let limits = match self.inbound_connection_limits { Some(limits) => limits, None => return Ok(()) };
+		let mut handshakes = self.inbound_handshakes.lock().unwrap();
+		if handshakes.pending_handshakes >= limits.max_concurrent_inbound_handshakes {
+			log_debug!(self.logger, "Refusing inbound connection as {} handshakes are already pending", handshakes.pending_handshakes);
+			self.inbound_connection_limit_hits.fetch_add(1, Ordering::Relaxed);
+			return Err(PeerHandleError {});
+		}
+		if let Some(address) = remote_network_address {
+			let state = handshakes.addresses.entry(rate_limited_address(address)).or_insert_with(Default::default);
+			if state.cooldown_ticks_remaining > 0 {
+				log_debug!(self.logger, "Refusing inbound connection from {:?} as it failed too many handshakes recently", address);
+				self.inbound_connection_limit_hits.fetch_add(1, Ordering::Relaxed);
+				return Err(PeerHandleError {});
+			}
+			if state.handshakes_in_window >= limits.max_handshakes_per_address_per_window {
+				log_debug!(self.logger, "Refusing inbound connection from {:?} as it connected too often recently", address);
+				self.inbound_connection_limit_hits.fetch_add(1, Ordering::Relaxed);
+				return Err(PeerHandleError {});
+			}
+			state.handshakes_in_window += 1;
+		}
+		handshakes.pending_handshakes += 1;
+		Ok(())
+	}
+
+	/// Stops counting an inbound connection as a pending handshake, noting a failure against its
+	/// address if the handshake did not complete.
+	fn end_inbound_handshake(&self, remote_network_address: &Option<NetAddress>, failed: bool) {
+		let limits = match self.inbound_connection_limits { Some(limits) => limits, None => return };
+		let mut handshakes = self.inbound_handshakes.lock().unwrap();
+		handshakes.pending_handshakes = handshakes.pending_handshakes.saturating_sub(1);
+		if !failed { return; }
+		if let Some(address) = remote_network_address {
+			let state = handshakes.addresses.entry(rate_limited_address(address)).or_insert_with(Default::default);
+			state.failed_handshakes_in_window += 1;
+			if state.failed_handshakes_in_window >= limits.max_failed_handshakes_per_address {
+				log_debug!(self.logger, "Refusing further inbound connections from {:?} for {} timer ticks after {} failed handshakes",
+					address, limits.failed_handshake_cooldown_ticks, state.failed_handshakes_in_window);
+				state.failed_handshakes_in_window = 0;
+				state.cooldown_ticks_remaining = limits.failed_handshake_cooldown_ticks;
+			}
+		}
+	}
+
+	/// Called when a peer is removed from `peers`, ending its inbound handshake if it was still
+	/// pending.
+	fn peer_removed(&self, peer: &Peer, reason: PeerDisconnectReason) {
+		if peer.inbound_handshake_pending {
+			self.end_inbound_handshake(&peer.their_net_address, reason != PeerDisconnectReason::LocalRequest);
+		}
+	}
+
+	/// Advances the windows and cooldowns of our [`InboundConnectionLimitConfig`] by one tick.
+	fn inbound_handshakes_timer_tick(&self) {
+		let limits = match self.inbound_connection_limits { Some(limits) => limits, None => return };
+		let mut handshakes = self.inbound_handshakes.lock().unwrap();
+		handshakes.addresses.retain(|_, state| {
+			state.ticks_since_window_start += 1;
+			if state.ticks_since_window_start >= limits.handshake_window_ticks {
+				state.ticks_since_window_start = 0;
+				state.handshakes_in_window = 0;
+				state.failed_handshakes_in_window = 0;
+			}
+			state.cooldown_ticks_remaining = state.cooldown_ticks_remaining.saturating_sub(1);
+			state.handshakes_in_window != 0 || state.failed_handshakes_in_window != 0 ||
+				state.cooldown_ticks_remaining != 0
+		});
+	}
+
 	fn peer_should_read(&self, peer: &mut Peer) -> bool {
 		peer.should_read(self.gossip_processing_backlogged.load(Ordering::Relaxed))
 	}
@@ -1137,6 +1679,7 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 				self.maybe_send_extra_ping(peer);
 			}
 
+			peer.encrypt_next_queued_message();
 			let should_read = self.peer_should_read(peer);
 			let next_buff = match peer.pending_outbound_buffer.front() {
 				None => {
@@ -1191,6 +1734,8 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 				self.do_attempt_write_data(descriptor, &mut peer, false);
 			}
 		};
+		mem::drop(peers);
+		self.notify_wire_message_observer();
 		Ok(())
 	}
 
@@ -1212,29 +1757,182 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 	/// [`send_data`]: SocketDescriptor::send_data
 	/// [`process_events`]: PeerManager::process_events
 	pub fn read_event(&self, peer_descriptor: &mut Descriptor, data: &[u8]) -> Result<bool, PeerHandleError> {
-		match self.do_read_event(peer_descriptor, data) {
-			Ok(res) => Ok(res),
+		let res = self.do_read_event(peer_descriptor, data);
+		self.notify_wire_message_observer();
+		match res {
+			Ok(res) => {
+				// Reading may have enqueued responses which are only written out in the next call
+				// to `process_events`.
+				self.peers_needing_write.lock().unwrap().insert(peer_descriptor.clone());
+				Ok(res)
+			},
 			Err(e) => {
 				log_trace!(self.logger, "Disconnecting peer due to a protocol error (usually a duplicate connection).");
-				self.disconnect_event_internal(peer_descriptor);
+				self.disconnect_event_internal(peer_descriptor, PeerDisconnectReason::ProtocolError);
 				Err(e)
 			}
 		}
 	}
 
+	/// Processes the noise act one or act two in the peer's read buffer, deriving the shared secret
+	/// it requires via [`NodeSigner::ecdh_async`]. Returns `false` if our signer is still deriving
+	/// it, in which case it is retried in [`Self::node_signer_unblocked`].
+	fn process_noise_act(
+		&self, peers: &HashMap<Descriptor, Mutex<Peer>>, peer_descriptor: &Descriptor, peer: &mut Peer
+	) -> Result<bool, PeerHandleError> {
+		macro_rules! try_noise_step {
+			($res: expr) => {
+				match $res {
+					Ok(res) => res,
+					Err(e) => {
+						log_debug!(self.logger, "Error processing noise handshake; disconnecting peer with: {}", e.err);
+						return Err(PeerHandleError { });
+					},
+				}
+			}
+		}
+
+		let their_ephemeral = try_noise_step!(PeerChannelEncryptor::act_ephemeral_key(&peer.pending_read_buffer[..]));
+		let shared_secret = match self.node_signer.ecdh_async(Recipient::Node, &their_ephemeral, None) {
+			NodeSignerStatus::Complete(Ok(shared_secret)) => shared_secret,
+			NodeSignerStatus::Complete(Err(())) => {
+				log_debug!(self.logger, "Failed to derive shared secret for noise handshake; disconnecting peer");
+				return Err(PeerHandleError { });
+			},
+			NodeSignerStatus::InProgress => {
+				log_trace!(self.logger, "Waiting on our NodeSigner to derive the shared secret for a noise handshake");
+				peer.awaiting_node_signer = true;
+				return Ok(false);
+			},
+		};
+		peer.awaiting_node_signer = false;
+
+		match peer.channel_encryptor.get_noise_step() {
+			NextNoiseStep::ActOne => {
+				let act_two = try_noise_step!(peer.channel_encryptor
+					.process_act_one_with_shared_secret(&peer.pending_read_buffer[..],
+						shared_secret, self.get_ephemeral_key(), &self.secp_ctx)).to_vec();
+				peer.pending_outbound_buffer.push_back(act_two);
+				peer.pending_read_buffer = [0; 66].to_vec(); // act three is 66 bytes long
+			},
+			NextNoiseStep::ActTwo => {
+				let our_node_id = match self.node_signer.get_node_id(Recipient::Node) {
+					Ok(node_id) => node_id,
+					Err(()) => {
+						log_debug!(self.logger, "Failed to get our node id for noise handshake; disconnecting peer");
+						return Err(PeerHandleError { });
+					},
+				};
+				let (act_three, their_node_id) = try_noise_step!(peer.channel_encryptor
+					.process_act_two_with_shared_secret(&peer.pending_read_buffer[..], our_node_id, shared_secret));
+				peer.pending_outbound_buffer.push_back(act_three.to_vec());
+				peer.pending_read_buffer = [0; 18].to_vec(); // Message length header is 18 bytes
+				peer.pending_read_is_header = true;
+
+				peer.set_their_node_id(their_node_id);
+				self.insert_node_id(peers, peer_descriptor, peer)?;
+				let features = self.init_features(&their_node_id);
+				let networks = self.message_handler.chan_handler.get_genesis_hashes();
+				let resp = msgs::Init { features, networks, remote_network_address: filter_addresses(peer.their_net_address.clone()) };
+				self.enqueue_message(peer, &resp);
+				peer.awaiting_pong_timer_tick_intervals = 0;
+			},
+			_ => {
+				debug_assert!(false, "Only noise acts one and two require our node secret");
+				return Err(PeerHandleError { });
+			},
+		}
+		Ok(true)
+	}
+
+	/// Maps the node id of a peer we just finished the noise handshake with to its descriptor,
+	/// failing if we're already connected to the node.
+	fn insert_node_id(
+		&self, peers: &HashMap<Descriptor, Mutex<Peer>>, peer_descriptor: &Descriptor, peer: &mut Peer
+	) -> Result<(), PeerHandleError> {
+		match self.node_id_to_descriptor.lock().unwrap().entry(peer.their_node_id.unwrap().0) {
+			hash_map::Entry::Occupied(e) => {
+				log_trace!(self.logger, "Got second connection with {}, closing", log_pubkey!(peer.their_node_id.unwrap().0));
+				peer.their_node_id = None; // Unset so that we don't generate a peer_disconnected event
+				// Check that the peers map is consistent with the node_id_to_descriptor map, as
+				// this has been broken before.
+				debug_assert!(peers.get(e.get()).is_some());
+				Err(PeerHandleError { })
+			},
+			hash_map::Entry::Vacant(entry) => {
+				log_debug!(self.logger, "Finished noise handshake for connection with {}", log_pubkey!(peer.their_node_id.unwrap().0));
+				entry.insert(peer_descriptor.clone());
+				Ok(())
+			},
+		}
+	}
+
+	/// Retries the [`NodeSigner`] operations which returned [`NodeSignerStatus::InProgress`], i.e.
+	/// deriving the shared secrets for noise handshakes and signing our node_announcement.
+	///
+	/// Should be called whenever a pending operation of our [`NodeSigner`] completed. Note that
+	/// peers which haven't completed the noise handshake within a call to
+	/// [`Self::timer_tick_occurred`] are disconnected.
+	pub fn node_signer_unblocked(&self) {
+		let mut descriptors_needing_disconnect = Vec::new();
+		{
+			let peers = self.peers.read().unwrap();
+			for (descriptor, peer_mutex) in peers.iter() {
+				let mut peer_lock = peer_mutex.lock().unwrap();
+				let peer = &mut *peer_lock;
+				if !peer.awaiting_node_signer { continue; }
+				match self.process_noise_act(&*peers, descriptor, peer) {
+					Ok(true) => self.do_attempt_write_data(&mut descriptor.clone(), peer, false),
+					Ok(false) => {},
+					Err(_) => descriptors_needing_disconnect.push(descriptor.clone()),
+				}
+			}
+		}
+
+		if !descriptors_needing_disconnect.is_empty() {
+			let mut peers_lock = self.peers.write().unwrap();
+			for descriptor in descriptors_needing_disconnect {
+				if let Some(peer_mutex) = peers_lock.remove(&descriptor) {
+					let peer = peer_mutex.lock().unwrap();
+					if let Some((node_id, _)) = peer.their_node_id {
+						self.node_id_to_descriptor.lock().unwrap().remove(&node_id);
+					}
+					self.do_disconnect(descriptor, &*peer, "noise handshake failure", PeerDisconnectReason::ProtocolError);
+				}
+			}
+		}
+
+		let pending_node_announcement = self.pending_node_announcement.lock().unwrap().take();
+		if let Some(announcement) = pending_node_announcement {
+			self.sign_and_broadcast_unsigned_node_announcement(announcement);
+		}
+	}
+
 	/// Append a message to a peer's pending outbound/write buffer
 	fn enqueue_message<M: wire::Type>(&self, peer: &mut Peer, message: &M) {
 		if is_gossip_msg(message.type_id()) {
-			log_gossip!(self.logger, "Enqueueing message {:?} to {}", message, log_pubkey!(peer.their_node_id.unwrap().0));
+			log_gossip!(WithContext::from(&self.logger, Some(peer.their_node_id.unwrap().0), None, None), "Enqueueing message {:?} to {}", message, log_pubkey!(peer.their_node_id.unwrap().0));
 		} else {
-			log_trace!(self.logger, "Enqueueing message {:?} to {}", message, log_pubkey!(peer.their_node_id.unwrap().0))
+			log_trace!(WithContext::from(&self.logger, Some(peer.their_node_id.unwrap().0), None, None), "Enqueueing message {:?} to {}", message, log_pubkey!(peer.their_node_id.unwrap().0))
 		}
+		self.queue_wire_message_observation(WireMessageDirection::Outbound, &peer.their_node_id.unwrap().0,
+			message.type_id(), || encode_msg!(message));
 		peer.msgs_sent_since_pong += 1;
-		peer.pending_outbound_buffer.push_back(peer.channel_encryptor.encrypt_message(message));
+		match MessagePriority::of_type(message.type_id()) {
+			MessagePriority::Channel =>
+				peer.pending_outbound_buffer.push_back(peer.channel_encryptor.encrypt_message(message)),
+			MessagePriority::Ping => peer.pending_ping_buffer.push_back(encode_msg!(message)),
+			MessagePriority::Gossip => peer.pending_gossip_buffer.push_back(encode_msg!(message)),
+		}
 	}
 
 	/// Append a message to a peer's pending outbound/write gossip broadcast buffer
 	fn enqueue_encoded_gossip_broadcast(&self, peer: &mut Peer, encoded_message: Vec<u8>) {
+		if encoded_message.len() >= 2 {
+			let message_type = u16::from_be_bytes([encoded_message[0], encoded_message[1]]);
+			self.queue_wire_message_observation(WireMessageDirection::Outbound, &peer.their_node_id.unwrap().0,
+				message_type, || encoded_message.clone());
+		}
 		peer.msgs_sent_since_pong += 1;
 		peer.gossip_broadcast_buffer.push_back(encoded_message);
 	}
@@ -1310,6 +2008,13 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 						peer_node_id = peer.their_node_id.clone();
 					}
 
+					if peer.awaiting_node_signer {
+						// Our peer must wait for our response to its noise act before sending
+						// anything further.
+						log_debug!(self.logger, "Peer sent data while we were still responding to its noise act");
+						return Err(PeerHandleError { });
+					}
+
 					assert!(peer.pending_read_buffer.len() > 0);
 					assert!(peer.pending_read_buffer.len() > peer.pending_read_buffer_pos);
 
@@ -1323,58 +2028,22 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 					if peer.pending_read_buffer_pos == peer.pending_read_buffer.len() {
 						peer.pending_read_buffer_pos = 0;
 
-						macro_rules! insert_node_id {
-							() => {
-								match self.node_id_to_descriptor.lock().unwrap().entry(peer.their_node_id.unwrap().0) {
-									hash_map::Entry::Occupied(e) => {
-										log_trace!(self.logger, "Got second connection with {}, closing", log_pubkey!(peer.their_node_id.unwrap().0));
-										peer.their_node_id = None; // Unset so that we don't generate a peer_disconnected event
-										// Check that the peers map is consistent with the
-										// node_id_to_descriptor map, as this has been broken
-										// before.
-										debug_assert!(peers.get(e.get()).is_some());
-										return Err(PeerHandleError { })
-									},
-									hash_map::Entry::Vacant(entry) => {
-										log_debug!(self.logger, "Finished noise handshake for connection with {}", log_pubkey!(peer.their_node_id.unwrap().0));
-										entry.insert(peer_descriptor.clone())
-									},
-								};
-							}
-						}
-
 						let next_step = peer.channel_encryptor.get_noise_step();
 						match next_step {
-							NextNoiseStep::ActOne => {
-								let act_two = try_potential_handleerror!(peer, peer.channel_encryptor
-									.process_act_one_with_keys(&peer.pending_read_buffer[..],
-										&self.node_signer, self.get_ephemeral_key(), &self.secp_ctx)).to_vec();
-								peer.pending_outbound_buffer.push_back(act_two);
-								peer.pending_read_buffer = [0; 66].to_vec(); // act three is 66 bytes long
-							},
-							NextNoiseStep::ActTwo => {
-								let (act_three, their_node_id) = try_potential_handleerror!(peer,
-									peer.channel_encryptor.process_act_two(&peer.pending_read_buffer[..],
-										&self.node_signer));
-								peer.pending_outbound_buffer.push_back(act_three.to_vec());
-								peer.pending_read_buffer = [0; 18].to_vec(); // Message length header is 18 bytes
-								peer.pending_read_is_header = true;
-
-								peer.set_their_node_id(their_node_id);
-								insert_node_id!();
-								let features = self.init_features(&their_node_id);
-								let networks = self.message_handler.chan_handler.get_genesis_hashes();
-								let resp = msgs::Init { features, networks, remote_network_address: filter_addresses(peer.their_net_address.clone()) };
-								self.enqueue_message(peer, &resp);
-								peer.awaiting_pong_timer_tick_intervals = 0;
+							NextNoiseStep::ActOne | NextNoiseStep::ActTwo => {
+								self.process_noise_act(&peers, peer_descriptor, peer)?;
 							},
 							NextNoiseStep::ActThree => {
 								let their_node_id = try_potential_handleerror!(peer,
 									peer.channel_encryptor.process_act_three(&peer.pending_read_buffer[..]));
 								peer.pending_read_buffer = [0; 18].to_vec(); // Message length header is 18 bytes
 								peer.pending_read_is_header = true;
+								if peer.inbound_handshake_pending {
+									peer.inbound_handshake_pending = false;
+									self.end_inbound_handshake(&peer.their_net_address, false);
+								}
 								peer.set_their_node_id(their_node_id);
-								insert_node_id!();
+								self.insert_node_id(&peers, peer_descriptor, peer)?;
 								let features = self.init_features(&their_node_id);
 								let networks = self.message_handler.chan_handler.get_genesis_hashes();
 								let resp = msgs::Init { features, networks, remote_network_address: filter_addresses(peer.their_net_address.clone()) };
@@ -1447,6 +2116,11 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 										}
 									};
 
+									if let Some((their_node_id, _)) = peer.their_node_id.as_ref() {
+										self.queue_wire_message_observation(WireMessageDirection::Inbound, their_node_id,
+											message.type_id(), || msg_data.to_vec());
+									}
+
 									msg_to_handle = Some(message);
 								}
 							}
@@ -1546,6 +2220,12 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 				return Err(PeerHandleError { }.into());
 			}
 
+			self.push_peer_event(Event::PeerConnected {
+				node_id: their_node_id,
+				features: msg.features.clone(),
+				address: peer_lock.their_net_address.clone(),
+				inbound: peer_lock.inbound_connection,
+			});
 			peer_lock.their_features = Some(msg.features);
 			return Ok(None);
 		} else if peer_lock.their_features.is_none() {
@@ -1571,9 +2251,9 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 		mem::drop(peer_lock);
 
 		if is_gossip_msg(message.type_id()) {
-			log_gossip!(self.logger, "Received message {:?} from {}", message, log_pubkey!(their_node_id));
+			log_gossip!(WithContext::from(&self.logger, Some(their_node_id), None, None), "Received message {:?} from {}", message, log_pubkey!(their_node_id));
 		} else {
-			log_trace!(self.logger, "Received message {:?} from {}", message, log_pubkey!(their_node_id));
+			log_trace!(WithContext::from(&self.logger, Some(their_node_id), None, None), "Received message {:?} from {}", message, log_pubkey!(their_node_id));
 		}
 
 		let mut should_forward = None;
@@ -1607,6 +2287,7 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 				let mut peer_lock = peer_mutex.lock().unwrap();
 				peer_lock.awaiting_pong_timer_tick_intervals = 0;
 				peer_lock.msgs_sent_since_pong = 0;
+				peer_lock.pong_received();
 			},
 
 			// Channel messages:
@@ -1668,6 +2349,12 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 			wire::Message::ClosingSigned(msg) => {
 				self.message_handler.chan_handler.handle_closing_signed(&their_node_id, &msg);
 			},
+			wire::Message::ClosingComplete(msg) => {
+				self.message_handler.chan_handler.handle_closing_complete(&their_node_id, &msg);
+			},
+			wire::Message::ClosingSig(msg) => {
+				self.message_handler.chan_handler.handle_closing_sig(&their_node_id, &msg);
+			},
 
 			// Commitment messages:
 			wire::Message::UpdateAddHTLC(msg) => {
@@ -1696,6 +2383,14 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 				self.message_handler.chan_handler.handle_channel_reestablish(&their_node_id, &msg);
 			},
 
+			// Peer storage messages:
+			wire::Message::PeerStorage(msg) => {
+				self.message_handler.chan_handler.handle_peer_storage(&their_node_id, &msg);
+			},
+			wire::Message::YourPeerStorage(msg) => {
+				self.message_handler.chan_handler.handle_your_peer_storage(&their_node_id, &msg);
+			},
+
 			// Routing messages:
 			wire::Message::AnnouncementSignatures(msg) => {
 				self.message_handler.chan_handler.handle_announcement_signatures(&their_node_id, &msg);
@@ -1707,6 +2402,13 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 				}
 				self.update_gossip_backlogged();
 			},
+			#[cfg(taproot)]
+			wire::Message::ChannelAnnouncement2(msg) => {
+				// Relaying `channel_announcement_2`s isn't supported yet.
+				self.message_handler.route_handler.handle_channel_announcement_2(&msg)
+					.map_err(|e| -> MessageHandlingError { e.into() })?;
+				self.update_gossip_backlogged();
+			},
 			wire::Message::NodeAnnouncement(msg) => {
 				if self.message_handler.route_handler.handle_node_announcement(&msg)
 						.map_err(|e| -> MessageHandlingError { e.into() })? {
@@ -1756,12 +2458,13 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 	}
 
 	fn forward_broadcast_msg(&self, peers: &HashMap<Descriptor, Mutex<Peer>>, msg: &wire::Message<<<CMH as core::ops::Deref>::Target as wire::CustomMessageReader>::CustomMessage>, except_node: Option<&PublicKey>) {
+		let mut peers_needing_write = Vec::new();
 		match msg {
 			wire::Message::ChannelAnnouncement(ref msg) => {
 				log_gossip!(self.logger, "Sending message to all peers except {:?} or the announced channel's counterparties: {:?}", except_node, msg);
 				let encoded_msg = encode_msg!(msg);
 
-				for (_, peer_mutex) in peers.iter() {
+				for (descriptor, peer_mutex) in peers.iter() {
 					let mut peer = peer_mutex.lock().unwrap();
 					if !peer.handshake_complete() ||
 							!peer.should_forward_channel_announcement(msg.contents.short_channel_id) {
@@ -1769,8 +2472,10 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 					}
 					debug_assert!(peer.their_node_id.is_some());
 					debug_assert!(peer.channel_encryptor.is_ready_for_encryption());
-					if peer.buffer_full_drop_gossip_broadcast() {
+					if peer.buffer_full_drop_gossip_broadcast(self.resource_limits.max_outbound_gossip_buffer_messages) {
 						log_gossip!(self.logger, "Skipping broadcast message to {:?} as its outbound buffer is full", peer.their_node_id);
+						self.gossip_buffer_limit_hits.fetch_add(1, Ordering::Relaxed);
+						peer.gossip_broadcasts_dropped += 1;
 						continue;
 					}
 					if let Some((_, their_node_id)) = peer.their_node_id {
@@ -1782,13 +2487,14 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 						continue;
 					}
 					self.enqueue_encoded_gossip_broadcast(&mut *peer, encoded_msg.clone());
+					peers_needing_write.push(descriptor.clone());
 				}
 			},
 			wire::Message::NodeAnnouncement(ref msg) => {
 				log_gossip!(self.logger, "Sending message to all peers except {:?} or the announced node: {:?}", except_node, msg);
 				let encoded_msg = encode_msg!(msg);
 
-				for (_, peer_mutex) in peers.iter() {
+				for (descriptor, peer_mutex) in peers.iter() {
 					let mut peer = peer_mutex.lock().unwrap();
 					if !peer.handshake_complete() ||
 							!peer.should_forward_node_announcement(msg.contents.node_id) {
@@ -1796,8 +2502,10 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 					}
 					debug_assert!(peer.their_node_id.is_some());
 					debug_assert!(peer.channel_encryptor.is_ready_for_encryption());
-					if peer.buffer_full_drop_gossip_broadcast() {
+					if peer.buffer_full_drop_gossip_broadcast(self.resource_limits.max_outbound_gossip_buffer_messages) {
 						log_gossip!(self.logger, "Skipping broadcast message to {:?} as its outbound buffer is full", peer.their_node_id);
+						self.gossip_buffer_limit_hits.fetch_add(1, Ordering::Relaxed);
+						peer.gossip_broadcasts_dropped += 1;
 						continue;
 					}
 					if let Some((_, their_node_id)) = peer.their_node_id {
@@ -1809,13 +2517,14 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 						continue;
 					}
 					self.enqueue_encoded_gossip_broadcast(&mut *peer, encoded_msg.clone());
+					peers_needing_write.push(descriptor.clone());
 				}
 			},
 			wire::Message::ChannelUpdate(ref msg) => {
 				log_gossip!(self.logger, "Sending message to all peers except {:?}: {:?}", except_node, msg);
 				let encoded_msg = encode_msg!(msg);
 
-				for (_, peer_mutex) in peers.iter() {
+				for (descriptor, peer_mutex) in peers.iter() {
 					let mut peer = peer_mutex.lock().unwrap();
 					if !peer.handshake_complete() ||
 							!peer.should_forward_channel_announcement(msg.contents.short_channel_id)  {
@@ -1823,18 +2532,24 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 					}
 					debug_assert!(peer.their_node_id.is_some());
 					debug_assert!(peer.channel_encryptor.is_ready_for_encryption());
-					if peer.buffer_full_drop_gossip_broadcast() {
+					if peer.buffer_full_drop_gossip_broadcast(self.resource_limits.max_outbound_gossip_buffer_messages) {
 						log_gossip!(self.logger, "Skipping broadcast message to {:?} as its outbound buffer is full", peer.their_node_id);
+						self.gossip_buffer_limit_hits.fetch_add(1, Ordering::Relaxed);
+						peer.gossip_broadcasts_dropped += 1;
 						continue;
 					}
 					if except_node.is_some() && peer.their_node_id.as_ref().map(|(pk, _)| pk) == except_node {
 						continue;
 					}
 					self.enqueue_encoded_gossip_broadcast(&mut *peer, encoded_msg.clone());
+					peers_needing_write.push(descriptor.clone());
 				}
 			},
 			_ => debug_assert!(false, "We shouldn't attempt to forward anything but gossip messages"),
 		}
+		if !peers_needing_write.is_empty() {
+			self.peers_needing_write.lock().unwrap().extend(peers_needing_write);
+		}
 	}
 
 	/// Checks for any events generated by our handlers and processes them. Includes sending most
@@ -1851,6 +2566,10 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 	/// without doing any work. All available events that need handling will be handled before the
 	/// other calls return.
 	///
+	/// Only peers which received data, had messages generated for them, or still have data
+	/// buffered since the last call are visited, so calling this often on a node with many idle
+	/// peers is cheap.
+	///
 	/// [`send_payment`]: crate::ln::channelmanager::ChannelManager::send_payment
 	/// [`ChannelManager::process_pending_htlc_forwards`]: crate::ln::channelmanager::ChannelManager::process_pending_htlc_forwards
 	/// [`send_data`]: SocketDescriptor::send_data
@@ -1868,14 +2587,24 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 			let mut peers_to_disconnect = HashMap::new();
 			let mut events_generated = self.message_handler.chan_handler.get_and_clear_pending_msg_events();
 			events_generated.append(&mut self.message_handler.route_handler.get_and_clear_pending_msg_events());
+			let custom_msgs = self.message_handler.custom_message_handler.get_and_clear_pending_msg();
 
-			{
+			// Peers only need to be visited if they have something to write. If reads were paused
+			// on gossip backlog, or onion messages may be pending for any peer, we can't tell
+			// which peers do and fall back to visiting all of them.
+			let visit_all_peers = flush_read_disabled ||
+				self.message_handler.onion_message_handler.has_pending_onion_messages();
+			let have_work = visit_all_peers || !events_generated.is_empty() || !custom_msgs.is_empty() ||
+				!self.peers_needing_write.lock().unwrap().is_empty();
+
+			if have_work {
 				// TODO: There are some DoS attacks here where you can flood someone's outbound send
 				// buffer by doing things like announcing channels on another node. We should be willing to
 				// drop optional-ish messages when send buffers get full!
 
 				let peers_lock = self.peers.read().unwrap();
 				let peers = &*peers_lock;
+				let mut touched_peers = HashSet::new();
 				macro_rules! get_peer_for_forwarding {
 					($node_id: expr) => {
 						{
@@ -1891,6 +2620,7 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 										if !peer_lock.handshake_complete() {
 											continue;
 										}
+										touched_peers.insert(descriptor.clone());
 										peer_lock
 									},
 									None => {
@@ -2049,6 +2779,18 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 									log_bytes!(msg.channel_id));
 							self.enqueue_message(&mut *get_peer_for_forwarding!(node_id), msg);
 						},
+						MessageSendEvent::SendClosingComplete { ref node_id, ref msg } => {
+							log_debug!(self.logger, "Handling SendClosingComplete event in peer_handler for node {} for channel {}",
+									log_pubkey!(node_id),
+									log_bytes!(msg.channel_id));
+							self.enqueue_message(&mut *get_peer_for_forwarding!(node_id), msg);
+						},
+						MessageSendEvent::SendClosingSig { ref node_id, ref msg } => {
+							log_debug!(self.logger, "Handling SendClosingSig event in peer_handler for node {} for channel {}",
+									log_pubkey!(node_id),
+									log_bytes!(msg.channel_id));
+							self.enqueue_message(&mut *get_peer_for_forwarding!(node_id), msg);
+						},
 						MessageSendEvent::SendShutdown { ref node_id, ref msg } => {
 							log_debug!(self.logger, "Handling Shutdown event in peer_handler for node {} for channel {}",
 									log_pubkey!(node_id),
@@ -2167,18 +2909,43 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 						MessageSendEvent::SendGossipTimestampFilter { ref node_id, ref msg } => {
 							self.enqueue_message(&mut *get_peer_for_forwarding!(node_id), msg);
 						}
+						MessageSendEvent::SendPeerStorage { ref node_id, ref msg } => {
+							log_debug!(self.logger, "Handling SendPeerStorage event in peer_handler for node {} with {} bytes",
+								log_pubkey!(node_id), msg.data.len());
+							self.enqueue_message(&mut *get_peer_for_forwarding!(node_id), msg);
+						}
+						MessageSendEvent::SendYourPeerStorage { ref node_id, ref msg } => {
+							log_debug!(self.logger, "Handling SendYourPeerStorage event in peer_handler for node {} with {} bytes",
+								log_pubkey!(node_id), msg.data.len());
+							self.enqueue_message(&mut *get_peer_for_forwarding!(node_id), msg);
+						}
 					}
 				}
 
-				for (node_id, msg) in self.message_handler.custom_message_handler.get_and_clear_pending_msg() {
+				for (node_id, msg) in custom_msgs {
 					if peers_to_disconnect.get(&node_id).is_some() { continue; }
 					self.enqueue_message(&mut *get_peer_for_forwarding!(&node_id), &msg);
 				}
 
-				for (descriptor, peer_mutex) in peers.iter() {
-					let mut peer = peer_mutex.lock().unwrap();
-					if flush_read_disabled { peer.received_channel_announce_since_backlogged = false; }
-					self.do_attempt_write_data(&mut (*descriptor).clone(), &mut *peer, flush_read_disabled);
+				let mut peers_to_write = mem::take(&mut *self.peers_needing_write.lock().unwrap());
+				if visit_all_peers {
+					peers_to_write = peers.keys().cloned().collect();
+				} else {
+					peers_to_write.extend(touched_peers);
+				}
+				let mut still_pending = Vec::new();
+				for mut descriptor in peers_to_write {
+					if let Some(peer_mutex) = peers.get(&descriptor) {
+						let mut peer = peer_mutex.lock().unwrap();
+						if flush_read_disabled { peer.received_channel_announce_since_backlogged = false; }
+						self.do_attempt_write_data(&mut descriptor, &mut *peer, flush_read_disabled);
+						if peer.has_pending_writes() {
+							still_pending.push(descriptor);
+						}
+					}
+				}
+				if !still_pending.is_empty() {
+					self.peers_needing_write.lock().unwrap().extend(still_pending);
 				}
 			}
 			if !peers_to_disconnect.is_empty() {
@@ -2200,7 +2967,7 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 								// room in the send buffer, put the error message there...
 								self.do_attempt_write_data(&mut descriptor, &mut *peer, false);
 							}
-							self.do_disconnect(descriptor, &*peer, "DisconnectPeer HandleError");
+							self.do_disconnect(descriptor, &*peer, "DisconnectPeer HandleError", PeerDisconnectReason::ProtocolError);
 						} else { debug_assert!(false, "Missing connection for peer"); }
 					}
 				}
@@ -2214,14 +2981,16 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 			}
 			break;
 		}
+		self.notify_wire_message_observer();
 	}
 
 	/// Indicates that the given socket descriptor's connection is now closed.
 	pub fn socket_disconnected(&self, descriptor: &Descriptor) {
-		self.disconnect_event_internal(descriptor);
+		self.disconnect_event_internal(descriptor, PeerDisconnectReason::ConnectionClosed);
 	}
 
-	fn do_disconnect(&self, mut descriptor: Descriptor, peer: &Peer, reason: &'static str) {
+	fn do_disconnect(&self, mut descriptor: Descriptor, peer: &Peer, reason: &'static str, event_reason: PeerDisconnectReason) {
+		self.peer_removed(peer, event_reason);
 		if !peer.handshake_complete() {
 			log_trace!(self.logger, "Disconnecting peer which hasn't completed handshake due to {}", reason);
 			descriptor.disconnect_socket();
@@ -2233,11 +3002,12 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 			log_trace!(self.logger, "Disconnecting peer with id {} due to {}", node_id, reason);
 			self.message_handler.chan_handler.peer_disconnected(&node_id);
 			self.message_handler.onion_message_handler.peer_disconnected(&node_id);
+			self.push_peer_event(Event::PeerDisconnected { node_id, reason: event_reason });
 		}
 		descriptor.disconnect_socket();
 	}
 
-	fn disconnect_event_internal(&self, descriptor: &Descriptor) {
+	fn disconnect_event_internal(&self, descriptor: &Descriptor, reason: PeerDisconnectReason) {
 		let mut peers = self.peers.write().unwrap();
 		let peer_option = peers.remove(descriptor);
 		match peer_option {
@@ -2248,6 +3018,7 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 			},
 			Some(peer_lock) => {
 				let peer = peer_lock.lock().unwrap();
+				self.peer_removed(&peer, reason);
 				if let Some((node_id, _)) = peer.their_node_id {
 					log_trace!(self.logger, "Handling disconnection of peer {}", log_pubkey!(node_id));
 					let removed = self.node_id_to_descriptor.lock().unwrap().remove(&node_id);
@@ -2255,6 +3026,7 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 					if !peer.handshake_complete() { return; }
 					self.message_handler.chan_handler.peer_disconnected(&node_id);
 					self.message_handler.onion_message_handler.peer_disconnected(&node_id);
+					self.push_peer_event(Event::PeerDisconnected { node_id, reason });
 				}
 			}
 		};
@@ -2271,7 +3043,7 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 		if let Some(descriptor) = self.node_id_to_descriptor.lock().unwrap().remove(&node_id) {
 			let peer_opt = peers_lock.remove(&descriptor);
 			if let Some(peer_mutex) = peer_opt {
-				self.do_disconnect(descriptor, &*peer_mutex.lock().unwrap(), "client request");
+				self.do_disconnect(descriptor, &*peer_mutex.lock().unwrap(), "client request", PeerDisconnectReason::LocalRequest);
 			} else { debug_assert!(false, "node_id_to_descriptor thought we had a peer"); }
 		}
 	}
@@ -2284,7 +3056,7 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 		self.node_id_to_descriptor.lock().unwrap().clear();
 		let peers = &mut *peers_lock;
 		for (descriptor, peer_mutex) in peers.drain() {
-			self.do_disconnect(descriptor, &*peer_mutex.lock().unwrap(), "client request to disconnect all peers");
+			self.do_disconnect(descriptor, &*peer_mutex.lock().unwrap(), "client request to disconnect all peers", PeerDisconnectReason::LocalRequest);
 		}
 	}
 
@@ -2299,6 +3071,7 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 				byteslen: 64,
 			};
 			self.enqueue_message(peer, &ping);
+			peer.ping_sent();
 		}
 	}
 
@@ -2307,13 +3080,23 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 	///
 	/// This may be called on any timescale you want, however, roughly once every ten seconds is
 	/// preferred. The call rate determines both how often we send a ping to our peers and how much
-	/// time they have to respond before we disconnect them.
+	/// time they have to respond before we disconnect them, see [`PeerKeepaliveConfig`].
 	///
 	/// May call [`send_data`] on all [`SocketDescriptor`]s. Thus, be very careful with reentrancy
 	/// issues!
 	///
+	/// Also broadcasts our node_announcement if an update made via
+	/// [`Self::update_node_announcement`] was delayed, or if it has not been rebroadcast for a day.
+	///
+	/// The call rate also determines the windows and cooldowns applied to new inbound connections,
+	/// see [`InboundConnectionLimitConfig`], and the rate at which messages are passed to our
+	/// [`WireMessageObserver`], if any, see [`WireMessageObserverConfig`].
+	///
 	/// [`send_data`]: SocketDescriptor::send_data
 	pub fn timer_tick_occurred(&self) {
+		self.inbound_handshakes_timer_tick();
+		self.wire_messages_observed_this_tick.store(0, Ordering::Relaxed);
+
 		let mut descriptors_needing_disconnect = Vec::new();
 		{
 			let peers_lock = self.peers.read().unwrap();
@@ -2350,7 +3133,7 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 
 					if (peer.awaiting_pong_timer_tick_intervals > 0 && !peer.received_message_since_timer_tick)
 						|| peer.awaiting_pong_timer_tick_intervals as u64 >
-							MAX_BUFFER_DRAIN_TICK_INTERVALS_PER_PEER as u64 * peers_lock.len() as u64
+							self.keepalive_config.max_pong_wait_ticks_per_peer as u64 * peers_lock.len() as u64
 					{
 						descriptors_needing_disconnect.push(descriptor.clone());
 						break;
@@ -2362,12 +3145,19 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 						break;
 					}
 
+					peer.ticks_since_ping = peer.ticks_since_ping.saturating_add(1);
+					if peer.ticks_since_ping < self.keepalive_config.ping_interval_ticks {
+						break;
+					}
+					peer.ticks_since_ping = 0;
+
 					peer.awaiting_pong_timer_tick_intervals = 1;
 					let ping = msgs::Ping {
 						ponglen: 0,
 						byteslen: 64,
 					};
 					self.enqueue_message(&mut *peer, &ping);
+					peer.ping_sent();
 					break;
 				}
 				self.do_attempt_write_data(&mut (descriptor.clone()), &mut *peer, flush_read_disabled);
@@ -2383,11 +3173,32 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 						if let Some((node_id, _)) = peer.their_node_id {
 							self.node_id_to_descriptor.lock().unwrap().remove(&node_id);
 						}
-						self.do_disconnect(descriptor, &*peer, "ping/handshake timeout");
+						self.do_disconnect(descriptor, &*peer, "ping/handshake timeout", PeerDisconnectReason::PingTimeout);
 					}
 				}
 			}
 		}
+
+		let node_announcement = {
+			let mut state_lock = self.node_announcement_state.lock().unwrap();
+			match &mut *state_lock {
+				Some(state) => {
+					state.ticks_since_broadcast = state.ticks_since_broadcast.saturating_add(1);
+					if (state.update_pending && state.ticks_since_broadcast >= NODE_ANNOUNCEMENT_UPDATE_INTERVAL_TICKS)
+						|| state.ticks_since_broadcast >= NODE_ANNOUNCEMENT_REBROADCAST_INTERVAL_TICKS
+					{
+						state.ticks_since_broadcast = 0;
+						state.update_pending = false;
+						Some((state.rgb, state.alias, state.addresses.clone()))
+					} else { None }
+				},
+				None => None,
+			}
+		};
+		if let Some((rgb, alias, addresses)) = node_announcement {
+			self.sign_and_broadcast_node_announcement(rgb, alias, addresses);
+		}
+		self.notify_wire_message_observer();
 	}
 
 	#[allow(dead_code)]
@@ -2414,14 +3225,62 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 	/// tying these addresses together and to this node. If you wish to preserve user privacy,
 	/// addresses should likely contain only Tor Onion addresses.
 	///
+	/// The node_announcement will be rebroadcast periodically with the same contents as long as
+	/// [`Self::timer_tick_occurred`] is called. See [`Self::update_node_announcement`] to change
+	/// its contents at runtime.
+	///
 	/// Panics if `addresses` is absurdly large (more than 100).
 	///
 	/// [`get_and_clear_pending_msg_events`]: MessageSendEventsProvider::get_and_clear_pending_msg_events
-	pub fn broadcast_node_announcement(&self, rgb: [u8; 3], alias: [u8; 32], mut addresses: Vec<NetAddress>) {
+	pub fn broadcast_node_announcement(&self, rgb: [u8; 3], alias: [u8; 32], addresses: Vec<NetAddress>) {
+		if addresses.len() > 100 {
+			panic!("More than half the message size was taken up by public addresses!");
+		}
+
+		*self.node_announcement_state.lock().unwrap() = Some(NodeAnnouncementState {
+			rgb, alias, addresses: addresses.clone(), ticks_since_broadcast: 0, update_pending: false,
+		});
+		self.sign_and_broadcast_node_announcement(rgb, alias, addresses);
+	}
+
+	/// Updates the alias, color, and addresses in our node_announcement, re-signing it and
+	/// broadcasting it to all connected peers, e.g., after our public address changed.
+	///
+	/// To avoid flooding the network, if our node_announcement was broadcast recently the update
+	/// is only broadcast once enough calls to [`Self::timer_tick_occurred`] were made, with only
+	/// the latest update being sent. Otherwise, it is broadcast immediately.
+	///
+	/// See [`Self::broadcast_node_announcement`] for more on the arguments.
+	///
+	/// Panics if `addresses` is absurdly large (more than 100).
+	pub fn update_node_announcement(&self, rgb: [u8; 3], alias: [u8; 32], addresses: Vec<NetAddress>) {
 		if addresses.len() > 100 {
 			panic!("More than half the message size was taken up by public addresses!");
 		}
 
+		{
+			let mut state_lock = self.node_announcement_state.lock().unwrap();
+			match &mut *state_lock {
+				Some(state) if state.ticks_since_broadcast < NODE_ANNOUNCEMENT_UPDATE_INTERVAL_TICKS => {
+					log_debug!(self.logger, "Delaying node_announcement update as we broadcast one recently");
+					state.rgb = rgb;
+					state.alias = alias;
+					state.addresses = addresses;
+					state.update_pending = true;
+					return;
+				},
+				_ => {
+					*state_lock = Some(NodeAnnouncementState {
+						rgb, alias, addresses: addresses.clone(), ticks_since_broadcast: 0,
+						update_pending: false,
+					});
+				},
+			}
+		}
+		self.sign_and_broadcast_node_announcement(rgb, alias, addresses);
+	}
+
+	fn sign_and_broadcast_node_announcement(&self, rgb: [u8; 3], alias: [u8; 32], mut addresses: Vec<NetAddress>) {
 		// While all existing nodes handle unsorted addresses just fine, the spec requires that
 		// addresses be sorted for future compatibility.
 		addresses.sort_by_key(|addr| addr.get_id());
@@ -2440,14 +3299,31 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 			excess_address_data: Vec::new(),
 			excess_data: Vec::new(),
 		};
-		let node_announce_sig = match self.node_signer.sign_gossip_message(
+		self.sign_and_broadcast_unsigned_node_announcement(announcement);
+	}
+
+	fn sign_and_broadcast_unsigned_node_announcement(&self, announcement: msgs::UnsignedNodeAnnouncement) {
+		let status = self.node_signer.sign_gossip_message_async(
 			msgs::UnsignedGossipMessage::NodeAnnouncement(&announcement)
-		) {
-			Ok(sig) => sig,
-			Err(_) => {
-				log_error!(self.logger, "Failed to generate signature for node_announcement");
-				return;
-			},
+		);
+		let node_announce_sig = {
+			let mut pending_announcement = self.pending_node_announcement.lock().unwrap();
+			match status {
+				NodeSignerStatus::Complete(Ok(sig)) => {
+					*pending_announcement = None;
+					sig
+				},
+				NodeSignerStatus::Complete(Err(())) => {
+					*pending_announcement = None;
+					log_error!(self.logger, "Failed to generate signature for node_announcement");
+					return;
+				},
+				NodeSignerStatus::InProgress => {
+					log_debug!(self.logger, "Waiting on our NodeSigner to sign our node_announcement");
+					*pending_announcement = Some(announcement);
+					return;
+				},
+			}
 		};
 
 		let msg = msgs::NodeAnnouncement {
@@ -2461,6 +3337,28 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 	}
 }
 
+impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CMH: Deref, NS: Deref> EventsProvider for PeerManager<Descriptor, CM, RM, OM, L, CMH, NS> where
+		CM::Target: ChannelMessageHandler,
+		RM::Target: RoutingMessageHandler,
+		OM::Target: OnionMessageHandler,
+		L::Target: Logger,
+		CMH::Target: CustomMessageHandler,
+		NS::Target: NodeSigner
+{
+	/// Processes any [`Event::PeerConnected`] and [`Event::PeerDisconnected`] events generated
+	/// since the last call, if enabled via [`PeerManager::with_peer_events`].
+	///
+	/// An [`EventHandler`] may safely call back to the `PeerManager`, though note that events
+	/// are processed in the order in which they were generated and a peer may thus already be
+	/// disconnected when its [`Event::PeerConnected`] is handled.
+	fn process_pending_events<H: Deref>(&self, handler: H) where H::Target: EventHandler {
+		let pending_events = mem::take(&mut *self.pending_events.lock().unwrap());
+		for event in pending_events {
+			handler.handle_event(event);
+		}
+	}
+}
+
 fn is_gossip_msg(type_id: u16) -> bool {
 	match type_id {
 		msgs::ChannelAnnouncement::TYPE |
@@ -2470,6 +3368,8 @@ fn is_gossip_msg(type_id: u16) -> bool {
 		msgs::ReplyChannelRange::TYPE |
 		msgs::QueryShortChannelIds::TYPE |
 		msgs::ReplyShortChannelIdsEnd::TYPE => true,
+		#[cfg(taproot)]
+		msgs::ChannelAnnouncement2::TYPE => true,
 		_ => false
 	}
 }
@@ -2478,16 +3378,19 @@ fn is_gossip_msg(type_id: u16) -> bool {
 mod tests {
 	use crate::sign::{NodeSigner, Recipient};
 	use crate::events;
+	use crate::events::{EventsProvider, MessageSendEvent, MessageSendEventsProvider, PeerDisconnectReason};
 	use crate::io;
 	use crate::ln::features::{InitFeatures, NodeFeatures};
 	use crate::ln::peer_channel_encryptor::PeerChannelEncryptor;
-	use crate::ln::peer_handler::{CustomMessageHandler, PeerManager, MessageHandler, SocketDescriptor, IgnoringMessageHandler, filter_addresses};
+	use crate::ln::peer_handler::{CustomMessageHandler, PeerManager, MessageHandler, SocketDescriptor, ErroringMessageHandler, IgnoringMessageHandler, WireMessageDirection, WireMessageObserver, filter_addresses};
 	use crate::ln::{msgs, wire};
-	use crate::ln::msgs::{LightningError, NetAddress};
+	use crate::ln::wire::Encode;
+	use crate::ln::msgs::{ChannelMessageHandler, LightningError, NetAddress, RoutingMessageHandler};
+	use crate::util::config::{InboundConnectionLimitConfig, PeerKeepaliveConfig, WireMessageObserverConfig};
 	use crate::util::test_utils;
 
 	use bitcoin::Network;
-	use bitcoin::blockdata::constants::ChainHash;
+	use bitcoin::blockdata::constants::{ChainHash, genesis_block};
 	use bitcoin::secp256k1::{PublicKey, SecretKey};
 
 	use crate::prelude::*;
@@ -2720,6 +3623,7 @@ mod tests {
 								msg: msgs::Shutdown {
 									channel_id: [0; 32],
 									scriptpubkey: bitcoin::Script::new(),
+									custom_tlvs: Vec::new(),
 								},
 							});
 						cfgs[1].chan_handler.pending_events.lock().unwrap()
@@ -2728,6 +3632,7 @@ mod tests {
 								msg: msgs::Shutdown {
 									channel_id: [0; 32],
 									scriptpubkey: bitcoin::Script::new(),
+									custom_tlvs: Vec::new(),
 								},
 							});
 
@@ -2823,6 +3728,145 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn test_wire_message_observer() {
+		// Messages exchanged with our peers should be passed to our `WireMessageObserver`, with
+		// payloads only for the requested message types and within the configured rate limit.
+		struct TestWireMessageObserver {
+			observed: Arc<Mutex<Vec<(WireMessageDirection, PublicKey, u16, Option<Vec<u8>>)>>>,
+		}
+		impl WireMessageObserver for TestWireMessageObserver {
+			fn observe_payload(&self, message_type: u16) -> bool { message_type == msgs::Init::TYPE }
+			fn observe_message(
+				&self, direction: WireMessageDirection, their_node_id: &PublicKey, message_type: u16,
+				payload: Option<&[u8]>
+			) {
+				self.observed.lock().unwrap().push((direction, *their_node_id, message_type, payload.map(|p| p.to_vec())));
+			}
+		}
+
+		let cfgs = create_peermgr_cfgs(2);
+		let their_node_id = cfgs[1].node_signer.get_node_id(Recipient::Node).unwrap();
+		for max_messages_per_tick in [1, 10].iter() {
+			let observed = Arc::new(Mutex::new(Vec::new()));
+			let mut peers = create_network(2, &cfgs);
+			let peer_b = peers.pop().unwrap();
+			let config = WireMessageObserverConfig {
+				max_messages_per_tick: *max_messages_per_tick, ..Default::default()
+			};
+			let peer_a = peers.pop().unwrap().with_wire_message_observer(
+				Box::new(TestWireMessageObserver { observed: Arc::clone(&observed) }), config);
+			establish_connection(&peer_a, &peer_b);
+
+			{
+				let observed = observed.lock().unwrap();
+				assert_eq!(observed.len(), if *max_messages_per_tick == 1 { 1 } else { 2 });
+				assert_eq!(observed[0].0, WireMessageDirection::Outbound);
+				assert_eq!(observed[0].1, their_node_id);
+				assert_eq!(observed[0].2, msgs::Init::TYPE);
+				assert_eq!(&observed[0].3.as_ref().unwrap()[..2], &msgs::Init::TYPE.to_be_bytes());
+				if *max_messages_per_tick > 1 {
+					assert_eq!(observed[1].0, WireMessageDirection::Inbound);
+					assert_eq!(observed[1].2, msgs::Init::TYPE);
+					assert_eq!(&observed[1].3.as_ref().unwrap()[..2], &msgs::Init::TYPE.to_be_bytes());
+				}
+			}
+
+			// The rate limit is reset on each timer tick, after which we send a ping without
+			// providing its payload.
+			observed.lock().unwrap().clear();
+			peer_a.timer_tick_occurred();
+			let observed = observed.lock().unwrap();
+			assert_eq!(observed.len(), 1);
+			assert_eq!(observed[0].0, WireMessageDirection::Outbound);
+			assert_eq!(observed[0].2, msgs::Ping::TYPE);
+			assert!(observed[0].3.is_none());
+		}
+	}
+
+	#[test]
+	fn test_gossip_only_features() {
+		// A gossip-only `PeerManager` only advertises the features of its `RoutingMessageHandler`,
+		// while a routing-only one also claims support for channel features.
+		let cfgs = create_peermgr_cfgs(2);
+		let their_node_id = cfgs[1].node_signer.get_node_id(Recipient::Node).unwrap();
+		let gossip_only = PeerManager::<FileDescriptor, _, _, _, _, _, _>::new_gossip_only(
+			&cfgs[0].routing_handler, 0, &[0; 32], &cfgs[0].logger, &cfgs[0].node_signer);
+		let routing_only = PeerManager::<FileDescriptor, _, _, _, _, _, _>::new_routing_only(
+			&cfgs[0].routing_handler, 0, &[0; 32], &cfgs[0].logger, &cfgs[0].node_signer);
+
+		let gossip_only_features = gossip_only.init_features(&their_node_id);
+		assert_eq!(gossip_only_features, cfgs[0].routing_handler.provided_init_features(&their_node_id));
+		assert!(gossip_only_features.supports_gossip_queries());
+		assert!(!gossip_only_features.supports_static_remote_key());
+		assert!(routing_only.init_features(&their_node_id).supports_static_remote_key());
+
+		// Channel messages are still rejected with an error.
+		let handler = ErroringMessageHandler::new_gossip_only();
+		handler.handle_channel_reestablish(&their_node_id, &msgs::ChannelReestablish {
+			channel_id: [42; 32], next_local_commitment_number: 0, next_remote_commitment_number: 0,
+			your_last_per_commitment_secret: [0; 32],
+			my_current_per_commitment_point: their_node_id,
+			next_funding_txid: None,
+		});
+		match &handler.get_and_clear_pending_msg_events()[..] {
+			[MessageSendEvent::HandleError { node_id, action: msgs::ErrorAction::SendErrorMessage { msg } }] => {
+				assert_eq!(*node_id, their_node_id);
+				assert_eq!(msg.channel_id, [42; 32]);
+			},
+			events => panic!("Unexpected events {:?}", events),
+		}
+	}
+
+	#[test]
+	fn test_async_node_signer_handshake() {
+		// Tests that the noise handshake completes once our NodeSigner derived the shared secrets
+		// it requires asynchronously, both for inbound and outbound connections.
+		let cfgs = create_peermgr_cfgs(2);
+		*cfgs[0].node_signer.ecdh_in_progress.lock().unwrap() = true;
+		*cfgs[1].node_signer.ecdh_in_progress.lock().unwrap() = true;
+		let peers = create_network(2, &cfgs);
+
+		let id_a = peers[0].node_signer.get_node_id(Recipient::Node).unwrap();
+		let id_b = peers[1].node_signer.get_node_id(Recipient::Node).unwrap();
+		let mut fd_a = FileDescriptor {
+			fd: 1, outbound_data: Arc::new(Mutex::new(Vec::new())),
+			disconnect: Arc::new(AtomicBool::new(false)),
+		};
+		let mut fd_b = FileDescriptor {
+			fd: 1, outbound_data: Arc::new(Mutex::new(Vec::new())),
+			disconnect: Arc::new(AtomicBool::new(false)),
+		};
+		let initial_data = peers[1].new_outbound_connection(id_a, fd_b.clone(), None).unwrap();
+		peers[0].new_inbound_connection(fd_a.clone(), None).unwrap();
+
+		// Act two is only sent once the inbound peer's signer completed.
+		assert_eq!(peers[0].read_event(&mut fd_a, &initial_data).unwrap(), false);
+		peers[0].process_events();
+		assert!(fd_a.outbound_data.lock().unwrap().is_empty());
+
+		*cfgs[0].node_signer.ecdh_in_progress.lock().unwrap() = false;
+		peers[0].node_signer_unblocked();
+		let a_data = fd_a.outbound_data.lock().unwrap().split_off(0);
+		assert!(!a_data.is_empty());
+
+		// Act three is only sent once the outbound peer's signer completed.
+		assert_eq!(peers[1].read_event(&mut fd_b, &a_data).unwrap(), false);
+		peers[1].process_events();
+		assert!(fd_b.outbound_data.lock().unwrap().is_empty());
+
+		*cfgs[1].node_signer.ecdh_in_progress.lock().unwrap() = false;
+		peers[1].node_signer_unblocked();
+		let b_data = fd_b.outbound_data.lock().unwrap().split_off(0);
+		assert_eq!(peers[0].read_event(&mut fd_a, &b_data).unwrap(), false);
+		peers[0].process_events();
+		let a_data = fd_a.outbound_data.lock().unwrap().split_off(0);
+		assert_eq!(peers[1].read_event(&mut fd_b, &a_data).unwrap(), false);
+
+		assert!(peers[0].peer_by_node_id(&id_b).is_some());
+		assert!(peers[1].peer_by_node_id(&id_a).is_some());
+	}
+
 	#[test]
 	fn test_disconnect_peer() {
 		// Simple test which builds a network of PeerManager, connects and brings them to NoiseState::Finished and
@@ -2855,7 +3899,7 @@ mod tests {
 
 		let their_id = peers[1].node_signer.get_node_id(Recipient::Node).unwrap();
 
-		let msg = msgs::Shutdown { channel_id: [42; 32], scriptpubkey: bitcoin::Script::new() };
+		let msg = msgs::Shutdown { channel_id: [42; 32], scriptpubkey: bitcoin::Script::new(), custom_tlvs: Vec::new() };
 		a_chan_handler.pending_events.lock().unwrap().push(events::MessageSendEvent::SendShutdown {
 			node_id: their_id, msg: msg.clone()
 		});
@@ -2934,6 +3978,177 @@ mod tests {
 		assert_eq!(peers[0].peers.read().unwrap().len(), 0);
 	}
 
+	#[test]
+	fn test_node_announcement_updates() {
+		// Tests that node_announcement updates are rate-limited and that our latest
+		// node_announcement is rebroadcast periodically.
+		let cfgs = create_peermgr_cfgs(1);
+		let peers = create_network(1, &cfgs);
+		let serial = || peers[0].last_node_announcement_serial.load(Ordering::Acquire);
+		let addresses = vec![NetAddress::IPv4 { addr: [127, 0, 0, 1], port: 9735 }];
+
+		peers[0].broadcast_node_announcement([0; 3], [0; 32], addresses.clone());
+		assert_eq!(serial(), 1);
+
+		// An update right after a broadcast is delayed, with only the latest update being sent.
+		peers[0].update_node_announcement([1; 3], [1; 32], addresses.clone());
+		peers[0].update_node_announcement([2; 3], [2; 32], Vec::new());
+		assert_eq!(serial(), 1);
+		for _ in 0..super::NODE_ANNOUNCEMENT_UPDATE_INTERVAL_TICKS - 1 {
+			peers[0].timer_tick_occurred();
+		}
+		assert_eq!(serial(), 1);
+		peers[0].timer_tick_occurred();
+		assert_eq!(serial(), 2);
+		{
+			let state_lock = peers[0].node_announcement_state.lock().unwrap();
+			let state = state_lock.as_ref().unwrap();
+			assert_eq!((state.rgb, state.alias), ([2; 3], [2; 32]));
+			assert!(state.addresses.is_empty());
+			assert!(!state.update_pending);
+		}
+
+		// Once enough ticks have passed, updates are broadcast immediately.
+		for _ in 0..super::NODE_ANNOUNCEMENT_UPDATE_INTERVAL_TICKS {
+			peers[0].timer_tick_occurred();
+		}
+		assert_eq!(serial(), 2);
+		peers[0].update_node_announcement([3; 3], [3; 32], addresses);
+		assert_eq!(serial(), 3);
+
+		// Without updates, our node_announcement is rebroadcast periodically.
+		for _ in 0..super::NODE_ANNOUNCEMENT_REBROADCAST_INTERVAL_TICKS - 1 {
+			peers[0].timer_tick_occurred();
+		}
+		assert_eq!(serial(), 3);
+		peers[0].timer_tick_occurred();
+		assert_eq!(serial(), 4);
+	}
+
+	#[test]
+	fn test_outbound_message_priority() {
+		// Tests that channel messages are encrypted and sent before any pings, which are in turn
+		// sent before any gossip, regardless of the order in which they were enqueued.
+		let cfgs = create_peermgr_cfgs(2);
+		let peers = create_network(2, &cfgs);
+		let (fd_a, _fd_b) = establish_connection(&peers[0], &peers[1]);
+		let their_id = peers[1].node_signer.get_node_id(Recipient::Node).unwrap();
+		assert_eq!(peers[0].peer_gossip_broadcasts_dropped(&their_id), Some(0));
+		let our_id = peers[0].node_signer.get_node_id(Recipient::Node).unwrap();
+		assert_eq!(peers[0].peer_gossip_broadcasts_dropped(&our_id), None);
+
+		let peers_lock = peers[0].peers.read().unwrap();
+		let mut peer = peers_lock.get(&fd_a).unwrap().lock().unwrap();
+		assert_eq!(peer.queued_outbound_messages(), 0);
+		let query = msgs::QueryChannelRange {
+			chain_hash: genesis_block(Network::Testnet).header.block_hash(), first_blocknum: 0,
+			number_of_blocks: 1,
+		};
+		peers[0].enqueue_message(&mut *peer, &query);
+		peers[0].enqueue_message(&mut *peer, &msgs::Ping { ponglen: 0, byteslen: 64 });
+		peers[0].enqueue_message(&mut *peer, &msgs::Shutdown { channel_id: [42; 32], scriptpubkey: bitcoin::Script::new(), custom_tlvs: Vec::new() });
+
+		// Only the channel message is encrypted right away.
+		assert_eq!(peer.pending_outbound_buffer.len(), 1);
+		assert_eq!(peer.pending_ping_buffer.len(), 1);
+		assert_eq!(peer.pending_gossip_buffer.len(), 1);
+		assert_eq!(peer.queued_outbound_messages(), 3);
+
+		// Lower priority messages are only encrypted once all higher priority ones were sent.
+		peer.encrypt_next_queued_message();
+		assert_eq!(peer.pending_outbound_buffer.len(), 1);
+		peer.pending_outbound_buffer.pop_front();
+		peer.encrypt_next_queued_message();
+		assert!(peer.pending_ping_buffer.is_empty());
+		assert_eq!(peer.pending_gossip_buffer.len(), 1);
+		peer.pending_outbound_buffer.pop_front();
+		peer.encrypt_next_queued_message();
+		assert!(peer.pending_gossip_buffer.is_empty());
+		assert_eq!(peer.pending_outbound_buffer.len(), 1);
+	}
+
+	#[test]
+	fn test_keepalive_config_and_peer_details() {
+		// Tests that pings are only sent every `ping_interval_ticks` and that the round-trip time of
+		// the latest ping is exposed via `peer_by_node_id`.
+		let cfgs = create_peermgr_cfgs(2);
+		let mut peers = create_network(2, &cfgs);
+		peers[0].keepalive_config = PeerKeepaliveConfig { ping_interval_ticks: 2, max_pong_wait_ticks_per_peer: 4 };
+		let (mut fd_a, mut fd_b) = establish_connection(&peers[0], &peers[1]);
+		let their_id = peers[1].node_signer.get_node_id(Recipient::Node).unwrap();
+
+		let details = peers[0].peer_by_node_id(&their_id).unwrap();
+		assert_eq!(details.counterparty_node_id, their_id);
+		assert_eq!(details.socket_address, Some(NetAddress::IPv4 { addr: [127, 0, 0, 1], port: 1001 }));
+		assert!(details.is_inbound_connection);
+		assert_eq!(details.ping_round_trip_time, None);
+		assert_eq!(details.gossip_broadcasts_dropped, 0);
+		assert!(peers[0].peer_by_node_id(&peers[0].node_signer.get_node_id(Recipient::Node).unwrap()).is_none());
+
+		let awaiting_pong = |peer_manager: &PeerManager<_, _, _, _, _, _, _>, fd: &FileDescriptor|
+			peer_manager.peers.read().unwrap().get(fd).unwrap().lock().unwrap().awaiting_pong_timer_tick_intervals;
+
+		// No ping is sent on the first tick, but one is on the second.
+		peers[0].timer_tick_occurred();
+		assert_eq!(awaiting_pong(&peers[0], &fd_a), 0);
+		peers[0].timer_tick_occurred();
+		assert_eq!(awaiting_pong(&peers[0], &fd_a), 1);
+
+		// Deliver the ping and its pong, after which no pong is awaited anymore.
+		let a_data = fd_a.outbound_data.lock().unwrap().split_off(0);
+		assert!(!a_data.is_empty());
+		assert_eq!(peers[1].read_event(&mut fd_b, &a_data).unwrap(), false);
+		peers[1].process_events();
+		let b_data = fd_b.outbound_data.lock().unwrap().split_off(0);
+		assert_eq!(peers[0].read_event(&mut fd_a, &b_data).unwrap(), false);
+		assert_eq!(awaiting_pong(&peers[0], &fd_a), 0);
+
+		#[cfg(feature = "std")]
+		assert!(peers[0].peer_by_node_id(&their_id).unwrap().ping_round_trip_time.is_some());
+	}
+
+	#[test]
+	fn test_peer_events() {
+		// Tests that `PeerConnected` and `PeerDisconnected` events are generated if enabled.
+		let cfgs = create_peermgr_cfgs(2);
+		let mut peers = create_network(2, &cfgs);
+		peers[0].generate_peer_events = true;
+		let id_a = peers[0].node_signer.get_node_id(Recipient::Node).unwrap();
+		let id_b = peers[1].node_signer.get_node_id(Recipient::Node).unwrap();
+
+		let pending_events = |peer_manager: &PeerManager<_, _, _, _, _, _, _>| {
+			let events = Mutex::new(Vec::new());
+			peer_manager.process_pending_events(&|event| events.lock().unwrap().push(event));
+			events.into_inner().unwrap()
+		};
+
+		let (_, fd_b) = establish_connection(&peers[0], &peers[1]);
+		assert_eq!(pending_events(&peers[0]), vec![events::Event::PeerConnected {
+			node_id: id_b,
+			features: peers[1].init_features(&id_a),
+			address: Some(NetAddress::IPv4 { addr: [127, 0, 0, 1], port: 1001 }),
+			inbound: true,
+		}]);
+		assert!(pending_events(&peers[0]).is_empty());
+
+		peers[0].disconnect_by_node_id(id_b);
+		peers[1].socket_disconnected(&fd_b);
+		assert_eq!(pending_events(&peers[0]), vec![
+			events::Event::PeerDisconnected { node_id: id_b, reason: PeerDisconnectReason::LocalRequest },
+		]);
+
+		let (fd_a, fd_b) = establish_connection(&peers[0], &peers[1]);
+		peers[0].socket_disconnected(&fd_a);
+		peers[1].socket_disconnected(&fd_b);
+		let events = pending_events(&peers[0]);
+		assert_eq!(events.len(), 2);
+		assert_eq!(events[1],
+			events::Event::PeerDisconnected { node_id: id_b, reason: PeerDisconnectReason::ConnectionClosed });
+
+		// No events are generated unless enabled.
+		assert!(pending_events(&peers[1]).is_empty());
+	}
+
 	#[test]
 	fn test_do_attempt_write_data() {
 		// Create 2 peers with custom TestRoutingMessageHandlers and connect them.
@@ -2976,6 +4191,46 @@ mod tests {
 		assert_eq!(cfgs[1].routing_handler.chan_anns_recvd.load(Ordering::Acquire), 54);
 	}
 
+	#[test]
+	fn test_process_events_only_visits_peers_needing_write() {
+		// Once a connection is idle, process_events should not revisit the peer, but messages
+		// generated for it afterwards must still be written out.
+		let cfgs = create_peermgr_cfgs(2);
+		let a_chan_handler = test_utils::TestChannelMessageHandler::new(ChainHash::using_genesis_block(Network::Testnet));
+		let b_chan_handler = test_utils::TestChannelMessageHandler::new(ChainHash::using_genesis_block(Network::Testnet));
+		let mut peers = create_network(2, &cfgs);
+		let (fd_a, mut fd_b) = establish_connection(&peers[0], &peers[1]);
+
+		peers[0].process_events();
+		peers[1].process_events();
+		assert!(peers[0].peers_needing_write.lock().unwrap().is_empty());
+		assert!(peers[1].peers_needing_write.lock().unwrap().is_empty());
+
+		// With nothing to do, processing events again doesn't write anything.
+		fd_a.outbound_data.lock().unwrap().clear();
+		peers[0].process_events();
+		assert!(fd_a.outbound_data.lock().unwrap().is_empty());
+
+		let their_id = peers[1].node_signer.get_node_id(Recipient::Node).unwrap();
+		let msg = msgs::Shutdown { channel_id: [42; 32], scriptpubkey: bitcoin::Script::new(), custom_tlvs: Vec::new() };
+		a_chan_handler.pending_events.lock().unwrap().push(events::MessageSendEvent::SendShutdown {
+			node_id: their_id, msg: msg.clone()
+		});
+		peers[0].message_handler.chan_handler = &a_chan_handler;
+		b_chan_handler.expect_receive_msg(wire::Message::Shutdown(msg));
+		peers[1].message_handler.chan_handler = &b_chan_handler;
+
+		peers[0].process_events();
+		assert!(peers[0].peers_needing_write.lock().unwrap().is_empty());
+
+		let a_data = fd_a.outbound_data.lock().unwrap().split_off(0);
+		assert!(!a_data.is_empty());
+		assert_eq!(peers[1].read_event(&mut fd_b, &a_data).unwrap(), false);
+		assert!(peers[1].peers_needing_write.lock().unwrap().contains(&fd_b));
+		peers[1].process_events();
+		assert!(peers[1].peers_needing_write.lock().unwrap().is_empty());
+	}
+
 	#[test]
 	fn test_handshake_timeout() {
 		// Tests that we time out a peer still waiting on handshake completion after a full timer
@@ -3016,6 +4271,59 @@ mod tests {
 		assert!(peers[0].read_event(&mut fd_a, &b_data).is_err());
 	}
 
+	#[test]
+	fn test_inbound_connection_limits() {
+		// Tests that inbound connections are refused once an address connects too often or fails
+		// too many handshakes, or once too many handshakes are pending overall.
+		let cfgs = create_peermgr_cfgs(2);
+		let mut peers = create_network(2, &cfgs);
+		peers[0].inbound_connection_limits = Some(InboundConnectionLimitConfig {
+			max_handshakes_per_address_per_window: 2,
+			handshake_window_ticks: 2,
+			max_concurrent_inbound_handshakes: 3,
+			max_failed_handshakes_per_address: 2,
+			failed_handshake_cooldown_ticks: 3,
+		});
+
+		// Completed handshakes no longer count as pending.
+		establish_connection(&peers[0], &peers[1]);
+		assert_eq!(peers[0].inbound_handshakes.lock().unwrap().pending_handshakes, 0);
+
+		let fd = |fd| FileDescriptor {
+			fd, outbound_data: Arc::new(Mutex::new(Vec::new())),
+			disconnect: Arc::new(AtomicBool::new(false)),
+		};
+		let addr_x = |port| Some(NetAddress::IPv4 { addr: [10, 0, 0, 1], port });
+		let addr_y = Some(NetAddress::IPv4 { addr: [10, 0, 0, 2], port: 9735 });
+		let addr_z = Some(NetAddress::IPv4 { addr: [10, 0, 0, 3], port: 9735 });
+
+		// The same host may only connect twice per window, even from different ports...
+		peers[0].new_inbound_connection(fd(10), addr_x(1)).unwrap();
+		peers[0].new_inbound_connection(fd(11), addr_x(2)).unwrap();
+		assert!(peers[0].new_inbound_connection(fd(12), addr_x(3)).is_err());
+		assert_eq!(peers[0].inbound_connection_limit_hits(), 1);
+
+		// ...and only three handshakes may be pending at once.
+		peers[0].new_inbound_connection(fd(13), addr_y).unwrap();
+		assert!(peers[0].new_inbound_connection(fd(14), addr_z.clone()).is_err());
+		assert_eq!(peers[0].inbound_connection_limit_hits(), 2);
+
+		// Once the first host has failed two handshakes it is put in cooldown, though connections
+		// from elsewhere are accepted again.
+		peers[0].socket_disconnected(&fd(10));
+		peers[0].socket_disconnected(&fd(11));
+		peers[0].timer_tick_occurred();
+		assert!(peers[0].new_inbound_connection(fd(15), addr_x(4)).is_err());
+		assert_eq!(peers[0].inbound_connection_limit_hits(), 3);
+		peers[0].new_inbound_connection(fd(16), addr_z).unwrap();
+
+		peers[0].timer_tick_occurred();
+		assert!(peers[0].new_inbound_connection(fd(17), addr_x(5)).is_err());
+		peers[0].timer_tick_occurred();
+		peers[0].new_inbound_connection(fd(18), addr_x(6)).unwrap();
+		assert_eq!(peers[0].inbound_connection_limit_hits(), 4);
+	}
+
 	#[test]
 	fn test_filter_addresses(){
 		// Tests the filter_addresses function.
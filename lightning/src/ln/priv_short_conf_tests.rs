@@ -276,6 +276,64 @@ fn test_routed_scid_alias() {
 	// the 0xdeadbeef SCID alias.
 }
 
+#[test]
+fn test_scid_alias_rotation() {
+	// Test that we can create further SCID aliases for a private channel, which our counterparty
+	// then uses in its route hints, and that it can rotate back to an older alias which we still
+	// recognize when forwarding.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let mut no_announce_cfg = test_default_channel_config();
+	no_announce_cfg.accept_forwards_to_priv_channels = true;
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, Some(no_announce_cfg), None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+
+	create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 1_000_000, 0);
+	let channel_id = create_unannounced_chan_between_nodes_with_value(&nodes, 1, 2, 1_000_000, 0).0.channel_id;
+	let original_alias = nodes[2].node.list_usable_channels()[0].inbound_scid_alias.unwrap();
+
+	let new_alias = nodes[1].node.create_scid_alias(&channel_id, &nodes[2].node.get_our_node_id()).unwrap();
+	assert_ne!(new_alias, original_alias);
+	let bs_channel_ready = get_event_msg!(nodes[1], MessageSendEvent::SendChannelReady, nodes[2].node.get_our_node_id());
+	assert_eq!(bs_channel_ready.short_channel_id_alias, Some(new_alias));
+	nodes[2].node.handle_channel_ready(&nodes[1].node.get_our_node_id(), &bs_channel_ready);
+	let cs_update = get_event_msg!(nodes[2], MessageSendEvent::SendChannelUpdate, nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_channel_update(&nodes[2].node.get_our_node_id(), &cs_update);
+	assert_eq!(nodes[2].node.list_usable_channels()[0].inbound_scid_alias, Some(new_alias));
+
+	let send_via_hint = |scid_alias: u64| {
+		let last_hop = nodes[2].node.list_usable_channels();
+		let hop_hints = vec![RouteHint(vec![RouteHintHop {
+			src_node_id: nodes[1].node.get_our_node_id(),
+			short_channel_id: scid_alias,
+			fees: RoutingFees {
+				base_msat: last_hop[0].counterparty.forwarding_info.as_ref().unwrap().fee_base_msat,
+				proportional_millionths: last_hop[0].counterparty.forwarding_info.as_ref().unwrap().fee_proportional_millionths,
+			},
+			cltv_expiry_delta: last_hop[0].counterparty.forwarding_info.as_ref().unwrap().cltv_expiry_delta,
+			htlc_maximum_msat: None,
+			htlc_minimum_msat: None,
+		}])];
+		let payment_params = PaymentParameters::from_node_id(nodes[2].node.get_our_node_id(), 42)
+			.with_bolt11_features(nodes[2].node.invoice_features()).unwrap()
+			.with_route_hints(hop_hints).unwrap();
+		let (route, payment_hash, payment_preimage, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[2], payment_params, 100_000);
+		assert_eq!(route.paths[0].hops[1].short_channel_id, scid_alias);
+		nodes[0].node.send_payment_with_route(&route, payment_hash,
+			RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)).unwrap();
+		check_added_monitors!(nodes[0], 1);
+		pass_along_route(&nodes[0], &[&[&nodes[1], &nodes[2]]], 100_000, payment_hash, payment_secret);
+		claim_payment(&nodes[0], &[&nodes[1], &nodes[2]], payment_preimage);
+	};
+	send_via_hint(new_alias);
+
+	// nodes[2] can switch back to the original alias, which nodes[1] still recognizes.
+	assert_eq!(nodes[2].node.rotate_inbound_scid_alias(&channel_id, &nodes[1].node.get_our_node_id()), Ok(original_alias));
+	assert_eq!(nodes[2].node.list_usable_channels()[0].inbound_scid_alias, Some(original_alias));
+	send_via_hint(original_alias);
+	assert_eq!(nodes[2].node.rotate_inbound_scid_alias(&channel_id, &nodes[1].node.get_our_node_id()), Ok(new_alias));
+}
+
 #[test]
 fn test_scid_privacy_on_pub_channel() {
 	// Tests rejecting the scid_privacy feature for public channels and that we don't ever try to
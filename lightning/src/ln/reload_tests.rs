@@ -9,13 +9,13 @@
 
 //! Functional tests which test for correct behavior across node restarts.
 
-use crate::chain::{ChannelMonitorUpdateStatus, Watch};
+use crate::chain::{BestBlock, ChannelMonitorUpdateStatus, Watch};
 use crate::chain::chaininterface::LowerBoundedFeeEstimator;
-use crate::chain::channelmonitor::ChannelMonitor;
+use crate::chain::channelmonitor::{ANTI_REORG_DELAY, ChannelMonitor};
 use crate::sign::EntropySource;
 use crate::chain::transaction::OutPoint;
 use crate::events::{ClosureReason, Event, HTLCDestination, MessageSendEvent, MessageSendEventsProvider};
-use crate::ln::channelmanager::{ChannelManager, ChannelManagerReadArgs, PaymentId, RecipientOnionFields};
+use crate::ln::channelmanager::{ChainParameters, ChannelManager, ChannelManagerReadArgs, PaymentId, RecipientOnionFields, apply_channel_manager_delta};
 use crate::ln::msgs;
 use crate::ln::msgs::{ChannelMessageHandler, RoutingMessageHandler, ErrorAction};
 use crate::util::enforcing_trait_impls::EnforcingSigner;
@@ -23,9 +23,15 @@ use crate::util::test_utils;
 use crate::util::errors::APIError;
 use crate::util::ser::{Writeable, ReadableArgs};
 use crate::util::config::UserConfig;
+use crate::util::persist::{KVStorePersister, KVStoreReader, export_node_state, import_node_state, persist_channel_manager, persist_channel_manager_delta, read_channel_manager};
+use crate::routing::scoring::FixedPenaltyScorer;
 use crate::util::string::UntrustedString;
 
+use bitcoin::blockdata::opcodes;
+use bitcoin::blockdata::script::Builder;
 use bitcoin::hash_types::BlockHash;
+use bitcoin::network::constants::Network;
+use bitcoin::secp256k1::Secp256k1;
 
 use crate::prelude::*;
 use core::default::Default;
@@ -485,11 +491,12 @@ fn test_manager_serialize_deserialize_inconsistent_monitor() {
 	assert!(found_err);
 }
 
-fn do_test_data_loss_protect(reconnect_panicing: bool) {
-	// When we get a data_loss_protect proving we're behind, we immediately panic as the
-	// chain::Watch API requirements have been violated (e.g. the user restored from a backup). The
-	// panic message informs the user they should force-close without broadcasting, which is tested
-	// if `reconnect_panicing` is not set.
+fn do_test_data_loss_protect(reconnect_stale: bool) {
+	// When we get a data_loss_protect proving we're behind, the chain::Watch API requirements have
+	// been violated (e.g. the user restored from a backup). If `reconnect_stale` is set, we test
+	// that we close the channel without broadcasting and sweep our balance once our counterparty
+	// broadcasts its latest commitment transaction. Otherwise, we test that the user may
+	// force-close without broadcasting themselves.
 	let mut chanmon_cfgs = create_chanmon_cfgs(2);
 	// We broadcast during Drop because chanmon is out of sync with chanmgr, which would cause a panic
 	// during signing due to revoked tx
@@ -515,7 +522,7 @@ fn do_test_data_loss_protect(reconnect_panicing: bool) {
 
 	reload_node!(nodes[0], previous_node_state, &[&previous_chain_monitor_state], persister, new_chain_monitor, nodes_0_deserialized);
 
-	if reconnect_panicing {
+	if reconnect_stale {
 		nodes[0].node.peer_connected(&nodes[1].node.get_our_node_id(), &msgs::Init {
 			features: nodes[1].node.init_features(), networks: None, remote_network_address: None
 		}, true).unwrap();
@@ -539,9 +546,70 @@ fn do_test_data_loss_protect(reconnect_panicing: bool) {
 		}
 
 		let reestablish_0 = get_chan_reestablish_msgs!(nodes[1], nodes[0]);
-		// Check A panics upon seeing proof it has fallen behind.
+		// Check A closes the channel without broadcasting upon seeing proof it has fallen behind.
 		nodes[0].node.handle_channel_reestablish(&nodes[1].node.get_our_node_id(), &reestablish_0[0]);
-		return; // By this point we should have panic'ed!
+		check_added_monitors!(nodes[0], 1);
+		assert!(nodes[0].tx_broadcaster.txn_broadcasted.lock().unwrap().is_empty());
+		assert_eq!(get_monitor!(nodes[0], chan.2).get_counterparty_stale_state_point(),
+			Some(reestablish_0[0].my_current_per_commitment_point));
+
+		let events = nodes[0].node.get_and_clear_pending_events();
+		assert_eq!(events.len(), 2);
+		match events[0] {
+			Event::StaleChannelState { channel_id, counterparty_node_id, funding_txo, .. } => {
+				assert_eq!(channel_id, chan.2);
+				assert_eq!(counterparty_node_id, nodes[1].node.get_our_node_id());
+				assert_eq!(funding_txo.txid, chan.3.txid());
+			},
+			_ => panic!("Unexpected event"),
+		}
+		match events[1] {
+			Event::ChannelClosed { ref reason, .. } => assert_eq!(*reason, ClosureReason::ProcessingError {
+				err: "We have fallen behind - peer proved our latest commitment transaction has been revoked".to_owned()
+			}),
+			_ => panic!("Unexpected event"),
+		}
+		let stale_channels = nodes[0].node.list_stale_channels();
+		assert_eq!(stale_channels.len(), 1);
+		assert_eq!(stale_channels[0].their_current_per_commitment_point, reestablish_0[0].my_current_per_commitment_point);
+		assert!(stale_channels[0].funding_spend.is_none());
+
+		// A asks B to force-close, after which it can claim its balance from B's commitment.
+		let err_msg = check_closed_broadcast!(nodes[0], true).unwrap();
+		nodes[1].node.handle_error(&nodes[0].node.get_our_node_id(), &err_msg);
+		check_added_monitors!(nodes[1], 1);
+		check_closed_event!(nodes[1], 1, ClosureReason::CounterpartyForceClosed { peer_msg: UntrustedString(err_msg.data.clone()) });
+		check_closed_broadcast!(nodes[1], false);
+		let commitment_tx = {
+			let mut node_txn = nodes[1].tx_broadcaster.txn_broadcasted.lock().unwrap();
+			assert_eq!(node_txn.len(), 1);
+			node_txn.pop().unwrap()
+		};
+		check_spends!(commitment_tx, chan.3);
+
+		mine_transaction(&nodes[0], &commitment_tx);
+		assert_eq!(nodes[0].node.list_stale_channels()[0].funding_spend,
+			Some((commitment_tx.txid(), nodes[0].best_block_info().1)));
+		connect_blocks(&nodes[0], ANTI_REORG_DELAY - 1);
+		assert!(nodes[0].tx_broadcaster.txn_broadcasted.lock().unwrap().is_empty());
+
+		let events = nodes[0].node.get_and_clear_pending_events();
+		assert_eq!(events.len(), 1);
+		match &events[0] {
+			Event::SpendableOutputs { outputs } => {
+				assert_eq!(outputs.len(), 1);
+				let secp_ctx = Secp256k1::new();
+				let spend_tx = nodes[0].keys_manager.backing.spend_spendable_outputs(&[&outputs[0]], Vec::new(),
+					Builder::new().push_opcode(opcodes::all::OP_RETURN).into_script(), 253, None, None, &secp_ctx).unwrap();
+				check_spends!(spend_tx, commitment_tx);
+			},
+			_ => panic!("Unexpected event"),
+		}
+
+		nodes[0].node.acknowledge_stale_channel(&chan.2).unwrap();
+		assert!(nodes[0].node.list_stale_channels().is_empty());
+		assert!(nodes[0].node.acknowledge_stale_channel(&chan.2).is_err());
+		return;
 	}
 
 	nodes[0].node.force_close_without_broadcasting_txn(&chan.2, &nodes[1].node.get_our_node_id()).unwrap();
@@ -601,8 +669,7 @@ fn do_test_data_loss_protect(reconnect_panicing: bool) {
 }
 
 #[test]
-#[should_panic]
-fn test_data_loss_protect_showing_stale_state_panics() {
+fn test_data_loss_protect_showing_stale_state() {
 	do_test_data_loss_protect(true);
 }
 
@@ -1101,3 +1168,460 @@ fn removed_payment_no_manager_persistence() {
 
 	expect_payment_failed!(nodes[0], payment_hash, false);
 }
+
+#[test]
+fn test_recover_from_static_backup() {
+	// Tests that a node which lost all of its state is able to recover its balance in its channels
+	// from a static backup, by asking its peers to force-close and claiming its balance from their
+	// commitment transactions.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let mut backup_config = test_default_channel_config();
+	backup_config.generate_static_backup_events = true;
+	let persister;
+	let new_chain_monitor;
+	let recovered_node;
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[Some(backup_config), None]);
+	let mut nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+	let chan = create_announced_chan_between_nodes(&nodes, 0, 1);
+	send_payment(&nodes[0], &[&nodes[1]], 10_000_000);
+
+	nodes[0].node.timer_tick_occurred();
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	let encrypted_backup = match &events[0] {
+		Event::StaticBackupUpdated { encrypted_backup } => encrypted_backup.clone(),
+		_ => panic!("Unexpected event"),
+	};
+	assert_eq!(nodes[0].node.get_static_backup().channels.len(), 1);
+
+	// Payments don't affect the backup, thus no new one is generated.
+	send_payment(&nodes[0], &[&nodes[1]], 10_000_000);
+	nodes[0].node.timer_tick_occurred();
+	assert!(nodes[0].node.get_and_clear_pending_events().is_empty());
+
+	// Lose all of node A's state, restarting it with nothing but the backup.
+	nodes[0].node.peer_disconnected(&nodes[1].node.get_our_node_id());
+	nodes[1].node.peer_disconnected(&nodes[0].node.get_our_node_id());
+
+	persister = test_utils::TestPersister::new();
+	new_chain_monitor = test_utils::TestChainMonitor::new(Some(nodes[0].chain_source), nodes[0].tx_broadcaster.clone(), nodes[0].logger, nodes[0].fee_estimator, &persister, &nodes[0].keys_manager);
+	nodes[0].chain_monitor = &new_chain_monitor;
+	let (best_block_hash, best_block_height) = nodes[0].best_block_info();
+	recovered_node = ChannelManager::new(nodes[0].fee_estimator, nodes[0].chain_monitor, nodes[0].tx_broadcaster,
+		nodes[0].router, nodes[0].logger, nodes[0].keys_manager, nodes[0].keys_manager, nodes[0].keys_manager,
+		backup_config, ChainParameters { network: Network::Testnet, best_block: BestBlock::new(best_block_hash, best_block_height) });
+	nodes[0].node = &recovered_node;
+
+	assert!(nodes[0].node.recover_from_static_backup(&encrypted_backup[..encrypted_backup.len() - 1]).is_err());
+	assert_eq!(nodes[0].node.recover_from_static_backup(&encrypted_backup).unwrap(), vec![nodes[1].node.get_our_node_id()]);
+	// Recovering the same channel twice is a no-op.
+	assert!(nodes[0].node.recover_from_static_backup(&encrypted_backup).unwrap().is_empty());
+
+	nodes[0].node.peer_connected(&nodes[1].node.get_our_node_id(), &msgs::Init {
+		features: nodes[1].node.init_features(), networks: None, remote_network_address: None
+	}, true).unwrap();
+	nodes[1].node.peer_connected(&nodes[0].node.get_our_node_id(), &msgs::Init {
+		features: nodes[0].node.init_features(), networks: None, remote_network_address: None
+	}, false).unwrap();
+
+	let reestablish = get_chan_reestablish_msgs!(nodes[0], nodes[1]);
+	assert_eq!(reestablish.len(), 1);
+	assert_eq!(reestablish[0].channel_id, chan.2);
+	assert_eq!(reestablish[0].next_local_commitment_number, 0);
+	// Node A no longer knows about the channel, so we don't bother delivering node B's
+	// channel_reestablish.
+	assert_eq!(get_chan_reestablish_msgs!(nodes[1], nodes[0]).len(), 1);
+
+	nodes[1].node.handle_channel_reestablish(&nodes[0].node.get_our_node_id(), &reestablish[0]);
+	check_closed_broadcast!(nodes[1], true);
+	check_added_monitors!(nodes[1], 1);
+	check_closed_event!(nodes[1], 1, ClosureReason::ProcessingError {
+		err: "Peer sent a garbage channel_reestablish (usually an lnd node with lost state asking us to force-close for them)".to_string()
+	});
+	let commitment_tx = {
+		let mut node_txn = nodes[1].tx_broadcaster.txn_broadcasted.lock().unwrap();
+		assert_eq!(node_txn.len(), 1);
+		node_txn.pop().unwrap()
+	};
+	check_spends!(commitment_tx, chan.3);
+
+	// Our balance is only provided once the commitment transaction is no longer at risk of being
+	// reorged out.
+	mine_transaction(&nodes[0], &commitment_tx);
+	assert!(nodes[0].node.get_and_clear_pending_events().is_empty());
+	connect_blocks(&nodes[0], ANTI_REORG_DELAY - 1);
+
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match &events[0] {
+		Event::SpendableOutputs { outputs } => {
+			assert_eq!(outputs.len(), 1);
+			let secp_ctx = Secp256k1::new();
+			let spend_tx = nodes[0].keys_manager.backing.spend_spendable_outputs(&[&outputs[0]], Vec::new(),
+				Builder::new().push_opcode(opcodes::all::OP_RETURN).into_script(), 253, None, None, &secp_ctx).unwrap();
+			check_spends!(spend_tx, commitment_tx);
+		},
+		_ => panic!("Unexpected event"),
+	}
+}
+
+#[test]
+fn test_static_backup_peer_storage() {
+	// Tests that a node storing its static backup with its peers is sent the backup back on
+	// reconnection, and is told about it once it lost its state.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let mut client_config = test_default_channel_config();
+	client_config.store_static_backup_with_peers = true;
+	let mut provider_config = test_default_channel_config();
+	provider_config.peer_storage_quota_bytes = 1024;
+	let persister;
+	let new_chain_monitor;
+	let recovered_node;
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[Some(client_config), Some(provider_config)]);
+	let mut nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let node_a_id = nodes[0].node.get_our_node_id();
+	let node_b_id = nodes[1].node.get_our_node_id();
+	assert!(nodes[1].node.init_features().supports_provide_storage());
+	assert!(!nodes[0].node.init_features().supports_provide_storage());
+
+	let chan = create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	// Once our backup changes, it's sent to our peer, which stores it as long as it fits within
+	// its quota.
+	nodes[0].node.timer_tick_occurred();
+	assert!(nodes[0].node.get_and_clear_pending_events().is_empty());
+	let events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	let peer_storage = match &events[0] {
+		MessageSendEvent::SendPeerStorage { node_id, msg } => {
+			assert_eq!(*node_id, node_b_id);
+			msg.clone()
+		},
+		_ => panic!("Unexpected event"),
+	};
+	nodes[1].node.handle_peer_storage(&node_a_id, &peer_storage);
+	nodes[1].node.handle_peer_storage(&node_a_id, &msgs::PeerStorage { data: vec![0; 1025] });
+
+	nodes[0].node.timer_tick_occurred();
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+
+	macro_rules! reconnect_and_get_peer_storage {
+		() => { {
+			nodes[0].node.peer_connected(&node_b_id, &msgs::Init {
+				features: nodes[1].node.init_features(), networks: None, remote_network_address: None
+			}, true).unwrap();
+			nodes[1].node.peer_connected(&node_a_id, &msgs::Init {
+				features: nodes[0].node.init_features(), networks: None, remote_network_address: None
+			}, false).unwrap();
+
+			let mut peer_storage = None;
+			for event in nodes[0].node.get_and_clear_pending_msg_events() {
+				match event {
+					MessageSendEvent::SendChannelReestablish { .. } => {},
+					MessageSendEvent::SendPeerStorage { node_id, msg } => {
+						assert_eq!(node_id, node_b_id);
+						peer_storage = Some(msg);
+					},
+					_ => panic!("Unexpected event"),
+				}
+			}
+			let mut your_peer_storage = None;
+			for event in nodes[1].node.get_and_clear_pending_msg_events() {
+				match event {
+					MessageSendEvent::SendChannelReestablish { .. } => {},
+					MessageSendEvent::SendYourPeerStorage { node_id, msg } => {
+						assert_eq!(node_id, node_a_id);
+						your_peer_storage = Some(msg);
+					},
+					_ => panic!("Unexpected event"),
+				}
+			}
+			(peer_storage, your_peer_storage.unwrap())
+		} }
+	}
+
+	// On reconnection, the provider returns the stored backup, which includes no channels we don't
+	// know about, and we send it our latest backup again.
+	nodes[0].node.peer_disconnected(&node_b_id);
+	nodes[1].node.peer_disconnected(&node_a_id);
+	let (new_peer_storage, your_peer_storage) = reconnect_and_get_peer_storage!();
+	assert_eq!(your_peer_storage.data, peer_storage.data);
+	nodes[0].node.handle_your_peer_storage(&node_b_id, &your_peer_storage);
+	assert!(nodes[0].node.get_and_clear_pending_events().is_empty());
+	let peer_storage = new_peer_storage.unwrap();
+	nodes[1].node.handle_peer_storage(&node_a_id, &peer_storage);
+
+	// Lose all of node A's state, after which the backup returned by node B allows recovery.
+	nodes[0].node.peer_disconnected(&node_b_id);
+	nodes[1].node.peer_disconnected(&node_a_id);
+
+	persister = test_utils::TestPersister::new();
+	new_chain_monitor = test_utils::TestChainMonitor::new(Some(nodes[0].chain_source), nodes[0].tx_broadcaster.clone(), nodes[0].logger, nodes[0].fee_estimator, &persister, &nodes[0].keys_manager);
+	nodes[0].chain_monitor = &new_chain_monitor;
+	let (best_block_hash, best_block_height) = nodes[0].best_block_info();
+	recovered_node = ChannelManager::new(nodes[0].fee_estimator, nodes[0].chain_monitor, nodes[0].tx_broadcaster,
+		nodes[0].router, nodes[0].logger, nodes[0].keys_manager, nodes[0].keys_manager, nodes[0].keys_manager,
+		client_config, ChainParameters { network: Network::Testnet, best_block: BestBlock::new(best_block_hash, best_block_height) });
+	nodes[0].node = &recovered_node;
+
+	let (new_peer_storage, your_peer_storage) = reconnect_and_get_peer_storage!();
+	assert!(new_peer_storage.is_none());
+	assert_eq!(your_peer_storage.data, peer_storage.data);
+	nodes[0].node.handle_your_peer_storage(&node_b_id, &your_peer_storage);
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	let encrypted_backup = match &events[0] {
+		Event::StaticBackupRetrieved { counterparty_node_id, encrypted_backup } => {
+			assert_eq!(*counterparty_node_id, node_b_id);
+			encrypted_backup.clone()
+		},
+		_ => panic!("Unexpected event"),
+	};
+	assert_eq!(nodes[0].node.recover_from_static_backup(&encrypted_backup).unwrap(), vec![node_b_id]);
+
+	// Once we're recovering the channel, we aren't told about it again.
+	nodes[0].node.handle_your_peer_storage(&node_b_id, &your_peer_storage);
+	assert!(nodes[0].node.get_and_clear_pending_events().is_empty());
+	assert_eq!(nodes[1].node.list_channels()[0].channel_id, chan.2);
+}
+
+#[test]
+fn test_node_state_bundle_roundtrip() {
+	// Test that a node can be restarted from a bundle returned by `export_node_state` and that any
+	// corruption of the bundle is detected on import.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let persister;
+	let new_chain_monitor;
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes_0_deserialized;
+	let mut nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+	let chan_id = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+	send_payment(&nodes[0], &[&nodes[1]], 1_000_000);
+
+	let scorer = FixedPenaltyScorer::with_penalty(1_000);
+	let exported = export_node_state(nodes[0].node, &nodes[0].chain_monitor.chain_monitor, nodes[0].network_graph, &scorer).encode();
+
+	// The bundle ends with the scorer's checksum followed by an empty TLV stream.
+	let mut corrupted = exported.clone();
+	let corrupted_len = corrupted.len();
+	corrupted[corrupted_len - 2] ^= 1;
+	assert_eq!(import_node_state(&corrupted), Err(msgs::DecodeError::InvalidValue));
+	assert_eq!(import_node_state(&exported[..exported.len() - 1]), Err(msgs::DecodeError::ShortRead));
+
+	let bundle = import_node_state(&exported).unwrap();
+	assert_eq!(bundle.channel_monitors.len(), 1);
+	assert_eq!(bundle.network_graph, nodes[0].network_graph.encode());
+	assert_eq!(bundle.scorer, scorer.encode());
+	let monitors = bundle.read_channel_monitors(nodes[0].keys_manager, nodes[0].keys_manager).unwrap();
+	assert_eq!(monitors[0].1.get_funding_txo().0, bundle.channel_monitors[0].0);
+
+	nodes[0].node.peer_disconnected(&nodes[1].node.get_our_node_id());
+	nodes[1].node.peer_disconnected(&nodes[0].node.get_our_node_id());
+
+	let monitors_encoded: Vec<&[u8]> = bundle.channel_monitors.iter().map(|(_, monitor)| &monitor[..]).collect();
+	reload_node!(nodes[0], bundle.channel_manager.clone(), &monitors_encoded, persister, new_chain_monitor, nodes_0_deserialized);
+	reconnect_nodes(&nodes[0], &nodes[1], (false, false), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (false, false));
+
+	assert_eq!(nodes[0].node.list_channels()[0].channel_id, chan_id);
+	send_payment(&nodes[0], &[&nodes[1]], 1_000_000);
+}
+
+#[test]
+fn test_channel_manager_delta_persistence() {
+	// Test that a ChannelManager can be restarted from a snapshot combined with a delta only
+	// including the channels which changed since.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let persister;
+	let new_chain_monitor;
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+	let nodes_0_deserialized;
+	let mut nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+
+	let chan_id_1 = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+	let chan_id_2 = create_announced_chan_between_nodes(&nodes, 0, 2).2;
+
+	// No delta can be built until a snapshot has been persisted.
+	assert!(nodes[0].node.encode_delta().is_none());
+	let snapshot = nodes[0].node.snapshot();
+	let snapshot_encoded = snapshot.encoded().to_vec();
+	nodes[0].node.snapshot_persisted(snapshot);
+
+	send_payment(&nodes[0], &[&nodes[1]], 1_000_000);
+
+	// Only the channel with nodes[1] changed, thus the delta doesn't include the other channel.
+	let delta = nodes[0].node.encode_delta().unwrap();
+	assert!(delta.len() < nodes[0].node.encode().len());
+
+	// A delta which wasn't built on top of the given snapshot is ignored.
+	let config = UserConfig::default();
+	let newer_snapshot = nodes[0].node.snapshot();
+	assert_eq!(apply_channel_manager_delta(newer_snapshot.encoded(), &delta, nodes[0].keys_manager, nodes[0].keys_manager, &config).unwrap(),
+		newer_snapshot.encoded());
+
+	let chanman_encoded = apply_channel_manager_delta(&snapshot_encoded, &delta, nodes[0].keys_manager, nodes[0].keys_manager, &config).unwrap();
+	let chan_1_monitor = get_monitor!(nodes[0], chan_id_1).encode();
+	let chan_2_monitor = get_monitor!(nodes[0], chan_id_2).encode();
+
+	nodes[1].node.peer_disconnected(&nodes[0].node.get_our_node_id());
+	nodes[2].node.peer_disconnected(&nodes[0].node.get_our_node_id());
+	reload_node!(nodes[0], chanman_encoded, &[&chan_1_monitor, &chan_2_monitor], persister, new_chain_monitor, nodes_0_deserialized);
+
+	assert_eq!(nodes[0].node.list_channels().len(), 2);
+	reconnect_nodes(&nodes[0], &nodes[1], (false, false), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (false, false));
+	reconnect_nodes(&nodes[0], &nodes[2], (false, false), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (false, false));
+	send_payment(&nodes[0], &[&nodes[1]], 1_000_000);
+	send_payment(&nodes[0], &[&nodes[2]], 1_000_000);
+}
+
+struct DeltaStore {
+	persists_manager_deltas: bool,
+	objects: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl KVStorePersister for DeltaStore {
+	fn persist<W: Writeable>(&self, key: &str, object: &W) -> crate::io::Result<()> {
+		self.objects.lock().unwrap().insert(key.to_owned(), object.encode());
+		Ok(())
+	}
+
+	fn persists_manager_deltas(&self) -> bool { self.persists_manager_deltas }
+}
+
+impl KVStoreReader for DeltaStore {
+	fn read(&self, key: &str) -> crate::io::Result<Option<Vec<u8>>> {
+		Ok(self.objects.lock().unwrap().get(key).cloned())
+	}
+
+	fn list(&self, prefix: &str) -> crate::io::Result<Vec<String>> {
+		Ok(self.objects.lock().unwrap().keys().filter(|key| key.starts_with(prefix)).cloned().collect())
+	}
+}
+
+#[test]
+fn test_channel_manager_delta_store_reload() {
+	// Test that a ChannelManager persisted to a store which opts into deltas, once in full and then
+	// via a delta, is read back with the delta merged and can be restarted from it.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let persister;
+	let new_chain_monitor;
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+	let nodes_0_deserialized;
+	let mut nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+
+	let chan_id_1 = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+	let chan_id_2 = create_announced_chan_between_nodes(&nodes, 0, 2).2;
+	let config = UserConfig::default();
+
+	// Stores which didn't opt in always have the ChannelManager persisted in full.
+	let full_store = DeltaStore { persists_manager_deltas: false, objects: Mutex::new(HashMap::new()) };
+	persist_channel_manager(&full_store, nodes[0].node).unwrap();
+	persist_channel_manager_delta(&full_store, nodes[0].node).unwrap();
+	assert!(full_store.read("manager_delta").unwrap().is_none());
+	assert_eq!(full_store.read("manager").unwrap().unwrap(), nodes[0].node.encode());
+
+	let store = DeltaStore { persists_manager_deltas: true, objects: Mutex::new(HashMap::new()) };
+	assert!(read_channel_manager(&store, nodes[0].keys_manager, nodes[0].keys_manager, &config).unwrap().is_none());
+
+	// Without a prior full persistence, a delta can't be built and the ChannelManager is persisted
+	// in full instead.
+	persist_channel_manager_delta(&store, nodes[0].node).unwrap();
+	assert!(store.read("manager").unwrap().is_some());
+	assert!(store.read("manager_delta").unwrap().is_none());
+
+	send_payment(&nodes[0], &[&nodes[1]], 1_000_000);
+	persist_channel_manager_delta(&store, nodes[0].node).unwrap();
+	let delta = store.read("manager_delta").unwrap().unwrap();
+	assert!(delta.len() < nodes[0].node.encode().len());
+
+	let chanman_encoded = read_channel_manager(&store, nodes[0].keys_manager, nodes[0].keys_manager, &config).unwrap().unwrap();
+	assert_ne!(chanman_encoded, store.read("manager").unwrap().unwrap());
+	let chan_1_monitor = get_monitor!(nodes[0], chan_id_1).encode();
+	let chan_2_monitor = get_monitor!(nodes[0], chan_id_2).encode();
+
+	nodes[1].node.peer_disconnected(&nodes[0].node.get_our_node_id());
+	nodes[2].node.peer_disconnected(&nodes[0].node.get_our_node_id());
+	reload_node!(nodes[0], chanman_encoded, &[&chan_1_monitor, &chan_2_monitor], persister, new_chain_monitor, nodes_0_deserialized);
+
+	assert_eq!(nodes[0].node.list_channels().len(), 2);
+	reconnect_nodes(&nodes[0], &nodes[1], (false, false), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (false, false));
+	reconnect_nodes(&nodes[0], &nodes[2], (false, false), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (false, false));
+	send_payment(&nodes[0], &[&nodes[1]], 1_000_000);
+	send_payment(&nodes[0], &[&nodes[2]], 1_000_000);
+}
+
+fn do_test_payment_claimable_replay(claim_before_restart: bool) {
+	// Tests that a `PaymentClaimable` which was being handled when we were persisted is flagged as
+	// a replay on restart, or isn't provided again at all if we claimed the payment in the meantime.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let persister: test_utils::TestPersister;
+	let new_chain_monitor: test_utils::TestChainMonitor;
+	let nodes_1_deserialized: ChannelManager<&test_utils::TestChainMonitor, &test_utils::TestBroadcaster, &test_utils::TestKeysInterface, &test_utils::TestKeysInterface, &test_utils::TestKeysInterface, &test_utils::TestFeeEstimator, &test_utils::TestRouter, &test_utils::TestLogger>;
+	let mut nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let chan_id = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+
+	let (route, payment_hash, payment_preimage, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[1], 100_000);
+	nodes[0].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	let payment_event = SendEvent::from_node(&nodes[0]);
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &payment_event.msgs[0]);
+	commitment_signed_dance!(nodes[1], nodes[0], payment_event.commitment_msg, false);
+	expect_pending_htlcs_forwardable!(nodes[1]);
+
+	// Persist nodes[1] while its `PaymentClaimable` is being handled, claiming the payment first
+	// if requested.
+	let manager_serialized = core::cell::RefCell::new(Vec::new());
+	let monitor_serialized = core::cell::RefCell::new(Vec::new());
+	nodes[1].node.process_pending_events(&|event: Event| {
+		match event {
+			Event::PaymentClaimable { is_replay, .. } => {
+				assert!(!is_replay);
+				if claim_before_restart {
+					nodes[1].node.claim_funds(payment_preimage);
+				}
+				*monitor_serialized.borrow_mut() = get_monitor!(nodes[1], chan_id).encode();
+				*manager_serialized.borrow_mut() = nodes[1].node.encode();
+			},
+			Event::PaymentClaimed { payment_hash: claimed_hash, .. } => {
+				assert!(claim_before_restart);
+				assert_eq!(claimed_hash, payment_hash);
+			},
+			_ => panic!("Unexpected event"),
+		}
+	});
+	check_added_monitors!(nodes[1], if claim_before_restart { 1 } else { 0 });
+	nodes[1].node.get_and_clear_pending_msg_events();
+	nodes[0].node.peer_disconnected(&nodes[1].node.get_our_node_id());
+
+	reload_node!(nodes[1], manager_serialized.into_inner(), &[&monitor_serialized.into_inner()], persister, new_chain_monitor, nodes_1_deserialized);
+
+	let events = nodes[1].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		Event::PaymentClaimable { payment_hash: claimable_hash, is_replay, .. } => {
+			assert!(!claim_before_restart);
+			assert_eq!(claimable_hash, payment_hash);
+			assert!(is_replay);
+		},
+		Event::PaymentClaimed { payment_hash: claimed_hash, .. } => {
+			assert!(claim_before_restart);
+			assert_eq!(claimed_hash, payment_hash);
+		},
+		_ => panic!("Unexpected event"),
+	}
+}
+
+#[test]
+fn test_payment_claimable_replay() {
+	do_test_payment_claimable_replay(false);
+	do_test_payment_claimable_replay(true);
+}
@@ -15,13 +15,16 @@ use crate::chain::Confirm;
 use crate::events::{Event, MessageSendEventsProvider, ClosureReason, HTLCDestination};
 use crate::ln::channelmanager::ChannelManager;
 use crate::ln::msgs::{ChannelMessageHandler, Init};
+use crate::sign::SpendableOutputDescriptor;
 use crate::util::test_utils;
 use crate::util::ser::Writeable;
 use crate::util::string::UntrustedString;
 
-use bitcoin::blockdata::script::Builder;
+use bitcoin::blockdata::script::{Builder, Script};
+use bitcoin::blockdata::transaction::TxOut;
 use bitcoin::blockdata::opcodes;
 use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{PackedLockTime, Sequence};
 
 use crate::prelude::*;
 
@@ -577,8 +580,24 @@ fn do_test_to_remote_after_local_detection(style: ConnectStyle) {
 	if let Event::SpendableOutputs { outputs } = node_a_spendable.pop().unwrap() {
 		assert_eq!(outputs.len(), 1);
 		let spend_tx = nodes[0].keys_manager.backing.spend_spendable_outputs(&[&outputs[0]], Vec::new(),
-			Builder::new().push_opcode(opcodes::all::OP_RETURN).into_script(), 253, None, &Secp256k1::new()).unwrap();
+			Builder::new().push_opcode(opcodes::all::OP_RETURN).into_script(), 253, None, None, &Secp256k1::new()).unwrap();
 		check_spends!(spend_tx, remote_txn_b[0]);
+
+		// The to_remote output has no relative timelock, thus it may be spent with a replaceable
+		// sequence number and an anti-fee-sniping locktime.
+		let locktime = PackedLockTime(nodes[0].best_block_info().1);
+		let (psbt, _) = nodes[0].keys_manager.backing.spend_spendable_outputs_psbt(&[&outputs[0]], Vec::new(),
+			Builder::new().push_opcode(opcodes::all::OP_RETURN).into_script(), 253, Some(locktime),
+			Some(Sequence::ENABLE_RBF_NO_LOCKTIME), &Secp256k1::new()).unwrap();
+		let rbf_spend_tx = psbt.extract_tx();
+		check_spends!(rbf_spend_tx, remote_txn_b[0]);
+		assert_eq!(rbf_spend_tx.lock_time, locktime);
+		assert_eq!(rbf_spend_tx.input[0].sequence, Sequence::ENABLE_RBF_NO_LOCKTIME);
+
+		let selected = SpendableOutputDescriptor::select_spendable_outputs(&[&outputs[0]], &[], 253).unwrap();
+		assert_eq!(selected, vec![&outputs[0]]);
+		let too_large_output = TxOut { value: 21_000_000_00_000_000, script_pubkey: Script::new() };
+		assert!(SpendableOutputDescriptor::select_spendable_outputs(&[&outputs[0]], &[too_large_output], 253).is_err());
 	}
 
 	// nodes[1] is waiting for the to_self_delay to expire, which is many more than
@@ -597,7 +616,7 @@ fn do_test_to_remote_after_local_detection(style: ConnectStyle) {
 	if let Event::SpendableOutputs { outputs } = node_b_spendable.pop().unwrap() {
 		assert_eq!(outputs.len(), 1);
 		let spend_tx = nodes[1].keys_manager.backing.spend_spendable_outputs(&[&outputs[0]], Vec::new(),
-			Builder::new().push_opcode(opcodes::all::OP_RETURN).into_script(), 253, None, &Secp256k1::new()).unwrap();
+			Builder::new().push_opcode(opcodes::all::OP_RETURN).into_script(), 253, None, None, &Secp256k1::new()).unwrap();
 		check_spends!(spend_tx, remote_txn_a[0]);
 	}
 }
@@ -19,7 +19,7 @@ use crate::ln::msgs::{ChannelMessageHandler, ErrorAction};
 use crate::ln::script::ShutdownScript;
 use crate::util::test_utils;
 use crate::util::test_utils::OnGetShutdownScriptpubkey;
-use crate::util::errors::APIError;
+use crate::util::errors::{APIError, CloseChannelError};
 use crate::util::config::UserConfig;
 use crate::util::string::UntrustedString;
 
@@ -100,10 +100,10 @@ fn updates_shutdown_wait() {
 	let route_2 = get_route(&nodes[1].node.get_our_node_id(), &payment_params_2, &nodes[1].network_graph.read_only(), None, 100000, &logger, &scorer, &(), &random_seed_bytes).unwrap();
 	unwrap_send_err!(nodes[0].node.send_payment_with_route(&route_1, payment_hash,
 			RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)
-		), true, APIError::ChannelUnavailable {..}, {});
+		), true, APIError::ChannelCannotSendHtlc { .. }, {});
 	unwrap_send_err!(nodes[1].node.send_payment_with_route(&route_2, payment_hash,
 			RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)
-		), true, APIError::ChannelUnavailable {..}, {});
+		), true, APIError::ChannelCannotSendHtlc { .. }, {});
 
 	nodes[2].node.claim_funds(payment_preimage_0);
 	check_added_monitors!(nodes[2], 1);
@@ -686,7 +686,7 @@ fn test_unsupported_anysegwit_shutdown_script() {
 
 	let chan = create_announced_chan_between_nodes(&nodes, 0, 1);
 	match nodes[1].node.close_channel(&OutPoint { txid: chan.3.txid(), index: 0 }.to_channel_id(), &nodes[0].node.get_our_node_id()) {
-		Err(APIError::IncompatibleShutdownScript { script }) => {
+		Err(CloseChannelError::IncompatibleShutdownScript { script }) => {
 			assert_eq!(script.into_inner(), unsupported_shutdown_script.clone().into_inner());
 		},
 		Err(e) => panic!("Unexpected error: {:?}", e),
@@ -780,7 +780,61 @@ fn test_already_set_user_shutdown_script() {
 	let chan = create_announced_chan_between_nodes(&nodes, 0, 1);
 	let result = nodes[1].node.close_channel_with_feerate_and_script(&OutPoint { txid: chan.3.txid(), index: 0 }.to_channel_id(), &nodes[0].node.get_our_node_id(), None, Some(shutdown_script));
 
-	assert_eq!(result, Err(APIError::APIMisuseError { err: "Cannot override shutdown script for a channel with one already set".to_string() }));
+	assert_eq!(result, Err(CloseChannelError::ShutdownScriptAlreadySet));
+}
+
+#[test]
+fn test_channel_upfront_shutdown_script() {
+	// Tests that a shutdown script provided when opening or accepting a channel is committed to
+	// upfront and paid to in the cooperative closing transaction.
+	let mut manually_accept_config = test_default_channel_config();
+	manually_accept_config.manually_accept_inbound_channels = true;
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, Some(manually_accept_config)]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+	// Segwit v0 scripts of the form OP_0 <20-byte hash> and OP_0 <32-byte hash>
+	let opener_script = Builder::new().push_int(0)
+		.push_slice(&[1; 20])
+		.into_script();
+	let acceptor_script = Builder::new().push_int(0)
+		.push_slice(&[2; 32])
+		.into_script();
+
+	let temporary_channel_id = nodes[0].node.create_channel_with_upfront_shutdown_script(nodes[1].node.get_our_node_id(),
+		100000, 50_000_000, 42, None, ShutdownScript::try_from(opener_script.clone()).unwrap()).unwrap();
+	let open_channel = get_event_msg!(nodes[0], MessageSendEvent::SendOpenChannel, nodes[1].node.get_our_node_id());
+	assert_eq!(open_channel.shutdown_scriptpubkey, Some(opener_script.clone()));
+
+	nodes[1].node.handle_open_channel(&nodes[0].node.get_our_node_id(), &open_channel);
+	let events = nodes[1].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		Event::OpenChannelRequest { temporary_channel_id, .. } => {
+			nodes[1].node.accept_inbound_channel_with_upfront_shutdown_script(&temporary_channel_id,
+				&nodes[0].node.get_our_node_id(), 23, ShutdownScript::try_from(acceptor_script.clone()).unwrap()).unwrap();
+		},
+		_ => panic!("Unexpected event"),
+	}
+	let accept_channel = get_event_msg!(nodes[1], MessageSendEvent::SendAcceptChannel, nodes[0].node.get_our_node_id());
+	assert_eq!(accept_channel.shutdown_scriptpubkey, Some(acceptor_script.clone()));
+	nodes[0].node.handle_accept_channel(&nodes[1].node.get_our_node_id(), &accept_channel);
+
+	let funding_tx = sign_funding_transaction(&nodes[0], &nodes[1], 100000, temporary_channel_id);
+	let (channel_ready, channel_id) = create_chan_between_nodes_with_value_confirm(&nodes[0], &nodes[1], &funding_tx);
+	create_chan_between_nodes_with_value_b(&nodes[0], &nodes[1], &channel_ready);
+
+	// Having committed to a script upfront, it can no longer be overridden at close time.
+	let override_script = ShutdownScript::try_from(Builder::new().push_int(0).push_slice(&[3; 20]).into_script()).unwrap();
+	assert_eq!(nodes[0].node.close_channel_with_feerate_and_script(&channel_id, &nodes[1].node.get_our_node_id(), None, Some(override_script)),
+		Err(CloseChannelError::ShutdownScriptAlreadySet));
+
+	let (_, _, closing_tx) = close_channel(&nodes[0], &nodes[1], &channel_id, funding_tx, true);
+	assert!(closing_tx.output.iter().any(|output| output.script_pubkey == opener_script));
+	assert!(closing_tx.output.iter().any(|output| output.script_pubkey == acceptor_script));
+	check_closed_event!(nodes[0], 1, ClosureReason::CooperativeClosure);
+	check_closed_event!(nodes[1], 1, ClosureReason::CooperativeClosure);
 }
 
 #[derive(PartialEq)]
@@ -988,3 +1042,123 @@ fn simple_target_feerate_shutdown() {
 	check_closed_event!(nodes[0], 1, ClosureReason::CooperativeClosure);
 	check_closed_event!(nodes[1], 1, ClosureReason::CooperativeClosure);
 }
+
+#[test]
+fn test_simple_close() {
+	// Test that, if both peers negotiated `option_simple_close`, each offers a closing transaction
+	// paying its own fee which the other peer simply countersigns, with the channel being closed by
+	// whichever is countersigned first.
+	let mut simple_close_config = test_default_channel_config();
+	simple_close_config.negotiate_simple_close = true;
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[Some(simple_close_config), Some(simple_close_config)]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let chan = create_announced_chan_between_nodes(&nodes, 0, 1);
+	send_payment(&nodes[0], &[&nodes[1]], 10_000_000);
+
+	nodes[0].node.close_channel(&chan.2, &nodes[1].node.get_our_node_id()).unwrap();
+	let node_0_shutdown = get_event_msg!(nodes[0], MessageSendEvent::SendShutdown, nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_shutdown(&nodes[0].node.get_our_node_id(), &node_0_shutdown);
+
+	// Though it didn't fund the channel, nodes[1] offers a closing transaction as soon as both
+	// sides sent their shutdown, paying the fee out of its own output.
+	let events = nodes[1].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 2);
+	let node_1_shutdown = match events[0] {
+		MessageSendEvent::SendShutdown { ref msg, .. } => msg.clone(),
+		_ => panic!("Unexpected event"),
+	};
+	let node_1_closing_complete = match events[1] {
+		MessageSendEvent::SendClosingComplete { ref node_id, ref msg } => {
+			assert_eq!(*node_id, nodes[0].node.get_our_node_id());
+			msg.clone()
+		},
+		_ => panic!("Unexpected event"),
+	};
+	assert!(node_1_closing_complete.fee_satoshis > 0);
+
+	nodes[0].node.handle_shutdown(&nodes[1].node.get_our_node_id(), &node_1_shutdown);
+	let closing_complete = get_event_msg!(nodes[0], MessageSendEvent::SendClosingComplete, nodes[1].node.get_our_node_id());
+	assert_eq!(closing_complete.locktime, nodes[0].best_block_info().1);
+	assert!(closing_complete.closer_output_only.is_some());
+	assert!(closing_complete.closee_output_only.is_none());
+	assert!(closing_complete.closer_and_closee_outputs.is_some());
+
+	nodes[1].node.handle_closing_complete(&nodes[0].node.get_our_node_id(), &closing_complete);
+	let events = nodes[1].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 2);
+	let closing_sig = match events[0] {
+		MessageSendEvent::SendClosingSig { ref node_id, ref msg } => {
+			assert_eq!(*node_id, nodes[0].node.get_our_node_id());
+			msg.clone()
+		},
+		_ => panic!("Unexpected event"),
+	};
+	match events[1] {
+		MessageSendEvent::BroadcastChannelUpdate { .. } => {},
+		_ => panic!("Unexpected event"),
+	}
+	assert!(closing_sig.closer_and_closee_outputs.is_some());
+	assert!(closing_sig.closer_output_only.is_none());
+
+	let node_1_txn = nodes[1].tx_broadcaster.txn_broadcasted.lock().unwrap().split_off(0);
+	assert_eq!(node_1_txn.len(), 1);
+	assert_eq!(node_1_txn[0].output.len(), 2);
+	assert_eq!(node_1_txn[0].lock_time.0, closing_complete.locktime);
+	assert!(node_1_txn[0].input[0].sequence.is_rbf());
+	check_spends!(node_1_txn[0], chan.3);
+	// nodes[1] keeps its full balance (the 10_001 msat pushed at open plus the payment), as
+	// nodes[0] pays the entire fee.
+	assert!(node_1_txn[0].output.iter().any(|outp| outp.value == 10_010));
+
+	// nodes[0] likewise countersigns the closing transaction offered by nodes[1], which pays the
+	// entire fee out of nodes[1]'s output instead.
+	nodes[0].node.handle_closing_complete(&nodes[1].node.get_our_node_id(), &node_1_closing_complete);
+	let events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 2);
+	let node_0_closing_sig = match events[0] {
+		MessageSendEvent::SendClosingSig { ref msg, .. } => msg.clone(),
+		_ => panic!("Unexpected event"),
+	};
+	match events[1] {
+		MessageSendEvent::BroadcastChannelUpdate { .. } => {},
+		_ => panic!("Unexpected event"),
+	}
+	let node_0_txn = nodes[0].tx_broadcaster.txn_broadcasted.lock().unwrap().split_off(0);
+	assert_eq!(node_0_txn.len(), 1);
+	check_spends!(node_0_txn[0], chan.3);
+	assert!(node_0_txn[0].output.iter().any(|outp| outp.value == 10_010 - node_1_closing_complete.fee_satoshis));
+
+	// Both channels are already closed, thus the closing_sigs for the other transactions are
+	// ignored.
+	nodes[0].node.handle_closing_sig(&nodes[1].node.get_our_node_id(), &closing_sig);
+	nodes[1].node.handle_closing_sig(&nodes[0].node.get_our_node_id(), &node_0_closing_sig);
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+	assert!(nodes[1].node.get_and_clear_pending_msg_events().is_empty());
+	assert!(nodes[0].tx_broadcaster.txn_broadcasted.lock().unwrap().is_empty());
+
+	assert!(nodes[0].node.list_channels().is_empty());
+	assert!(nodes[1].node.list_channels().is_empty());
+	check_closed_event!(nodes[0], 1, ClosureReason::CooperativeClosure);
+	check_closed_event!(nodes[1], 1, ClosureReason::CooperativeClosure);
+}
+
+#[test]
+fn test_simple_close_falls_back_to_closing_signed() {
+	// Test that we use the legacy closing_signed negotiation with a peer which does not support
+	// `option_simple_close`.
+	let mut simple_close_config = test_default_channel_config();
+	simple_close_config.negotiate_simple_close = true;
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[Some(simple_close_config), None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let chan = create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	assert!(nodes[0].node.init_features().supports_simple_close());
+	assert!(!nodes[1].node.init_features().supports_simple_close());
+	close_channel(&nodes[0], &nodes[1], &chan.2, chan.3, true);
+	check_closed_event!(nodes[0], 1, ClosureReason::CooperativeClosure);
+	check_closed_event!(nodes[1], 1, ClosureReason::CooperativeClosure);
+}
@@ -0,0 +1,855 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! A deterministic simulation of a network of in-memory nodes.
+//!
+//! Building on the [`functional_test_utils`], a [`Simulation`] drives any number of [`Node`]s
+//! against a virtual clock. Messages between peers are delivered after a configurable per-link
+//! latency, links may be disconnected and reconnected on a script, and payments are routed with a
+//! user-provided [`Score`], retrying failed attempts up to a limit. As all randomness is drawn from
+//! a seeded [`SimRng`], the same scenario replays identically, allowing changes to scorers or
+//! retry strategies to be compared against each other.
+//!
+//! A typical scenario builds the network with [`create_random_topology`] and then drives it:
+//! ```ignore
+//! let chanmon_cfgs = create_chanmon_cfgs(20);
+//! let node_cfgs = create_node_cfgs(20, &chanmon_cfgs);
+//! let node_chanmgrs = create_node_chanmgrs(20, &node_cfgs, &[None; 20]);
+//! let nodes = create_network(20, &node_cfgs, &node_chanmgrs);
+//!
+//! let mut rng = SimRng::new(42);
+//! create_random_topology(&nodes, &mut rng, 10, 1_000_000);
+//!
+//! let scorer = ProbabilisticScorerUsingTime::<_, _, SimTime>::new(
+//! 	Default::default(), nodes[0].network_graph, nodes[0].logger);
+//! let mut sim = Simulation::new(&nodes, scorer, Default::default(), 42);
+//! sim.schedule_link_event(1_000, 3, 4, LinkEvent::Disconnect);
+//! let payment = sim.send_payment(0, 7, 10_000_000);
+//! assert!(sim.run_until_idle(600_000));
+//! println!("{:?}", sim.payments()[payment]);
+//! ```
+//!
+//! Note that the simulation is deterministic up to the order in which a [`ChannelManager`]
+//! internally generates messages for different channels with the same peer.
+//!
+//! [`functional_test_utils`]: crate::ln::functional_test_utils
+//! [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+
+use bitcoin::secp256k1::PublicKey;
+
+use crate::events::{Event, MessageSendEvent, MessageSendEventsProvider, PathFailure, PaymentFailureReason, PaymentPurpose};
+use crate::ln::{PaymentHash, PaymentSecret};
+use crate::ln::channelmanager::{PaymentId, PaymentSendFailure, RecipientOnionFields};
+use crate::ln::functional_test_utils::{Node, TEST_FINAL_CLTV, connect_blocks, create_announced_chan_between_nodes_with_value};
+use crate::ln::msgs::{self, ChannelMessageHandler, ErrorAction, RoutingMessageHandler};
+use crate::routing::router::{self, PaymentParameters, RouteParameters};
+use crate::routing::scoring::Score;
+use crate::util::clock::{ProvidedTime, TimeProvider};
+
+use crate::prelude::*;
+use alloc::collections::BTreeMap;
+use core::cell::Cell;
+use core::cmp;
+use core::time::Duration;
+
+/// The interval, in milliseconds of virtual time, at which
+/// [`ChannelManager::timer_tick_occurred`] is called on every node.
+///
+/// [`ChannelManager::timer_tick_occurred`]: crate::ln::channelmanager::ChannelManager::timer_tick_occurred
+pub const TIMER_TICK_INTERVAL_MS: u64 = 60_000;
+
+/// The latency applied to links for which none was set explicitly, unless overridden with
+/// [`Simulation::set_default_latency`].
+pub const DEFAULT_LINK_LATENCY_MS: u64 = 100;
+
+/// The number of attempts made for each payment, unless overridden with
+/// [`Simulation::set_max_attempts`].
+pub const DEFAULT_MAX_ATTEMPTS: usize = 3;
+
+/// A small, seedable xorshift pseudo-random number generator, so that a [`Simulation`] behaves
+/// identically across runs.
+#[derive(Clone, Debug)]
+pub struct SimRng(u64);
+
+impl SimRng {
+	/// Creates a new generator from the given seed.
+	pub fn new(seed: u64) -> Self {
+		// xorshift requires a non-zero state.
+		let state = seed ^ 0x9e37_79b9_7f4a_7c15;
+		Self(if state == 0 { 1 } else { state })
+	}
+
+	/// Returns the next pseudo-random `u64`.
+	pub fn next_u64(&mut self) -> u64 {
+		self.0 ^= self.0 << 13;
+		self.0 ^= self.0 >> 7;
+		self.0 ^= self.0 << 17;
+		self.0
+	}
+
+	/// Returns a pseudo-random value in `0..bound`. `bound` must be non-zero.
+	pub fn next_below(&mut self, bound: u64) -> u64 {
+		self.next_u64() % bound
+	}
+
+	/// Returns 32 pseudo-random bytes.
+	pub fn next_bytes(&mut self) -> [u8; 32] {
+		let mut bytes = [0; 32];
+		for chunk in bytes.chunks_mut(8) {
+			chunk.copy_from_slice(&self.next_u64().to_be_bytes());
+		}
+		bytes
+	}
+}
+
+std::thread_local! {
+	static SIM_NOW_MS: Cell<u64> = Cell::new(0);
+}
+
+/// A [`TimeProvider`] reading the virtual clock of the [`Simulation`] running on the current
+/// thread.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SimClock;
+
+impl TimeProvider for SimClock {
+	fn duration_since_epoch(&self) -> Duration {
+		SIM_NOW_MS.with(|now| Duration::from_millis(now.get()))
+	}
+}
+
+/// A [`Time`] following the virtual clock of a [`Simulation`], e.g. for a
+/// [`ProbabilisticScorerUsingTime`] whose liquidity estimates should decay in virtual time.
+///
+/// [`Time`]: crate::util::time::Time
+/// [`ProbabilisticScorerUsingTime`]: crate::routing::scoring::ProbabilisticScorerUsingTime
+pub type SimTime = ProvidedTime<SimClock>;
+
+/// Builds a ring of announced channels through all `nodes`, plus `extra_channels` additional
+/// channels between distinct random pairs, returning the endpoints and channel id of each.
+///
+/// Channel values are drawn from between half and one and a half times `channel_value_sat`, with
+/// half of each channel's value pushed to the counterparty so that payments may flow in either
+/// direction. Once all channels are open, every node is brought to the same block height.
+///
+/// Note that, with the default config, a single HTLC may not exceed 10% of a channel's value.
+pub fn create_random_topology<'a, 'b, 'c>(
+	nodes: &Vec<Node<'a, 'b, 'c>>, rng: &mut SimRng, extra_channels: usize, channel_value_sat: u64
+) -> Vec<(usize, usize, [u8; 32])> {
+	let node_count = nodes.len();
+	let mut pairs = Vec::new();
+	if node_count == 2 {
+		pairs.push((0, 1));
+	} else if node_count > 2 {
+		for a in 0..node_count {
+			pairs.push((cmp::min(a, (a + 1) % node_count), cmp::max(a, (a + 1) % node_count)));
+		}
+	}
+
+	let max_pairs = node_count * node_count.saturating_sub(1) / 2;
+	let mut remaining_attempts = extra_channels * 16;
+	let mut extra_pairs = 0;
+	while extra_pairs < extra_channels && pairs.len() < max_pairs && remaining_attempts > 0 {
+		remaining_attempts -= 1;
+		let a = rng.next_below(node_count as u64) as usize;
+		let b = rng.next_below(node_count as u64) as usize;
+		let pair = (cmp::min(a, b), cmp::max(a, b));
+		if a == b || pairs.contains(&pair) { continue; }
+		pairs.push(pair);
+		extra_pairs += 1;
+	}
+
+	let mut channels = Vec::with_capacity(pairs.len());
+	for (a, b) in pairs {
+		let value_sat = channel_value_sat / 2 + rng.next_below(cmp::max(channel_value_sat, 1));
+		let chan = create_announced_chan_between_nodes_with_value(nodes, a, b, value_sat, value_sat * 1000 / 2);
+		channels.push((a, b, chan.2));
+	}
+
+	let max_height = nodes.iter().map(|node| node.best_block_info().1).max().unwrap_or(0);
+	for node in nodes.iter() {
+		let height = node.best_block_info().1;
+		if height < max_height {
+			connect_blocks(node, max_height - height);
+		}
+	}
+	channels
+}
+
+/// A change to a link between two nodes, scheduled with [`Simulation::schedule_link_event`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkEvent {
+	/// The peers disconnect, dropping any messages in flight between them.
+	Disconnect,
+	/// The peers reconnect, if they were disconnected.
+	Reconnect,
+	/// The latency of messages sent over the link changes to the given number of milliseconds.
+	///
+	/// Messages already in flight are not affected, and messages are never reordered.
+	SetLatency(u64),
+}
+
+/// The state of a payment sent with [`Simulation::send_payment`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SimPaymentStatus {
+	/// The payment has neither succeeded nor exhausted its attempts.
+	Pending,
+	/// The payment reached the recipient and was claimed.
+	Succeeded {
+		/// The total fee paid along the successful route, if known.
+		fee_paid_msat: Option<u64>,
+		/// The virtual time at which the payer learned of the success.
+		completed_at_ms: u64,
+	},
+	/// No route could be found or all attempts failed.
+	Failed {
+		/// The virtual time at which the payment was given up on.
+		completed_at_ms: u64,
+	},
+}
+
+/// A payment sent with [`Simulation::send_payment`].
+#[derive(Clone, Debug)]
+pub struct SimPayment {
+	/// The index of the paying node.
+	pub payer: usize,
+	/// The index of the receiving node.
+	pub payee: usize,
+	/// The amount sent to the payee.
+	pub amount_msat: u64,
+	/// The payment hash, shared by all attempts.
+	pub payment_hash: PaymentHash,
+	/// The virtual time at which the payment was started.
+	pub started_at_ms: u64,
+	/// The number of attempts made so far, including ones which failed to send.
+	pub attempts: usize,
+	/// The current state of the payment.
+	pub status: SimPaymentStatus,
+	payment_secret: PaymentSecret,
+}
+
+impl SimPayment {
+	/// Returns the virtual time it took for the payment to complete, if it has.
+	pub fn duration_ms(&self) -> Option<u64> {
+		match self.status {
+			SimPaymentStatus::Pending => None,
+			SimPaymentStatus::Succeeded { completed_at_ms, .. } |
+			SimPaymentStatus::Failed { completed_at_ms } => Some(completed_at_ms - self.started_at_ms),
+		}
+	}
+}
+
+/// Drives a set of [`Node`]s against a virtual clock, delivering messages between them with
+/// scripted latencies and disconnects. See the [module-level documentation] for details.
+///
+/// Channels must be opened before the simulation starts, e.g. with [`create_random_topology`], as
+/// channel establishment messages are not supported.
+///
+/// [module-level documentation]: self
+pub struct Simulation<'n, 'a: 'n, 'b: 'a, 'c: 'b, S: Score> {
+	nodes: &'n [Node<'a, 'b, 'c>],
+	node_ids: HashMap<PublicKey, usize>,
+	scorer: S,
+	score_params: S::ScoreParams,
+	rng: SimRng,
+	now_ms: u64,
+	next_timer_tick_ms: u64,
+	default_latency_ms: u64,
+	/// Latency overrides, keyed by the lower and then higher node index of each link.
+	latencies: HashMap<(usize, usize), u64>,
+	/// The latest delivery time of any message sent over each directed link, used to avoid
+	/// reordering messages when the link's latency changes.
+	last_delivery_ms: HashMap<(usize, usize), u64>,
+	disconnected: HashSet<(usize, usize)>,
+	/// Messages in flight, keyed by their delivery time and then the order in which they were sent.
+	in_flight: BTreeMap<(u64, u64), (usize, usize, MessageSendEvent)>,
+	scheduled: BTreeMap<(u64, u64), (usize, usize, LinkEvent)>,
+	next_seq: u64,
+	max_attempts: usize,
+	payments: Vec<SimPayment>,
+	payment_ids: HashMap<PaymentId, usize>,
+	events: Vec<(u64, usize, Event)>,
+}
+
+impl<'n, 'a: 'n, 'b: 'a, 'c: 'b, S: Score> Simulation<'n, 'a, 'b, 'c, S> {
+	/// Creates a new simulation over the given, already connected, `nodes`, routing payments with
+	/// `scorer` and drawing all randomness from `seed`.
+	///
+	/// The virtual clock of the current thread, as read by [`SimClock`], is reset to zero.
+	pub fn new(nodes: &'n [Node<'a, 'b, 'c>], scorer: S, score_params: S::ScoreParams, seed: u64) -> Self {
+		SIM_NOW_MS.with(|now| now.set(0));
+		let node_ids = nodes.iter().enumerate()
+			.map(|(idx, node)| (node.node.get_our_node_id(), idx))
+			.collect();
+		Self {
+			nodes,
+			node_ids,
+			scorer,
+			score_params,
+			rng: SimRng::new(seed),
+			now_ms: 0,
+			next_timer_tick_ms: TIMER_TICK_INTERVAL_MS,
+			default_latency_ms: DEFAULT_LINK_LATENCY_MS,
+			latencies: HashMap::new(),
+			last_delivery_ms: HashMap::new(),
+			disconnected: HashSet::new(),
+			in_flight: BTreeMap::new(),
+			scheduled: BTreeMap::new(),
+			next_seq: 0,
+			max_attempts: DEFAULT_MAX_ATTEMPTS,
+			payments: Vec::new(),
+			payment_ids: HashMap::new(),
+			events: Vec::new(),
+		}
+	}
+
+	/// Returns the current virtual time, in milliseconds since the simulation started.
+	pub fn now_ms(&self) -> u64 {
+		self.now_ms
+	}
+
+	/// Returns the scorer used to route payments.
+	pub fn scorer(&self) -> &S {
+		&self.scorer
+	}
+
+	/// Returns all payments sent so far, indexed as returned by [`Simulation::send_payment`].
+	pub fn payments(&self) -> &[SimPayment] {
+		&self.payments
+	}
+
+	/// Returns every [`Event`] handled so far, with the virtual time and index of the node at
+	/// which it was generated.
+	pub fn events(&self) -> &[(u64, usize, Event)] {
+		&self.events
+	}
+
+	/// Sets the latency of links for which none was set explicitly.
+	pub fn set_default_latency(&mut self, latency_ms: u64) {
+		self.default_latency_ms = latency_ms;
+	}
+
+	/// Sets the latency of the link between nodes `a` and `b`, in both directions.
+	pub fn set_link_latency(&mut self, a: usize, b: usize, latency_ms: u64) {
+		self.latencies.insert(link(a, b), latency_ms);
+	}
+
+	/// Sets the number of attempts made for each payment before it is considered failed.
+	pub fn set_max_attempts(&mut self, max_attempts: usize) {
+		self.max_attempts = max_attempts;
+	}
+
+	/// Schedules a change to the link between nodes `a` and `b` at the given virtual time.
+	pub fn schedule_link_event(&mut self, at_ms: u64, a: usize, b: usize, event: LinkEvent) {
+		let seq = self.next_seq();
+		self.scheduled.insert((cmp::max(at_ms, self.now_ms), seq), (a, b, event));
+	}
+
+	/// Disconnects nodes `a` and `b`, dropping any messages in flight between them.
+	pub fn disconnect(&mut self, a: usize, b: usize) {
+		if !self.disconnected.insert(link(a, b)) { return; }
+		let a_id = self.nodes[a].node.get_our_node_id();
+		let b_id = self.nodes[b].node.get_our_node_id();
+		self.nodes[a].node.peer_disconnected(&b_id);
+		self.nodes[b].node.peer_disconnected(&a_id);
+
+		let dropped = self.in_flight.iter()
+			.filter(|(_, (from, to, _))| link(*from, *to) == link(a, b))
+			.map(|(key, _)| *key)
+			.collect::<Vec<_>>();
+		for key in dropped {
+			self.in_flight.remove(&key);
+		}
+		self.last_delivery_ms.remove(&(a, b));
+		self.last_delivery_ms.remove(&(b, a));
+	}
+
+	/// Reconnects nodes `a` and `b` if they were disconnected, with `a` as the initiator.
+	pub fn reconnect(&mut self, a: usize, b: usize) {
+		if !self.disconnected.remove(&link(a, b)) { return; }
+		let (node_a, node_b) = (&self.nodes[a], &self.nodes[b]);
+		node_a.node.peer_connected(&node_b.node.get_our_node_id(), &msgs::Init {
+			features: node_b.override_init_features.borrow().clone().unwrap_or_else(|| node_b.node.init_features()),
+			networks: None,
+			remote_network_address: None,
+		}, false).unwrap();
+		node_b.node.peer_connected(&node_a.node.get_our_node_id(), &msgs::Init {
+			features: node_a.override_init_features.borrow().clone().unwrap_or_else(|| node_a.node.init_features()),
+			networks: None,
+			remote_network_address: None,
+		}, true).unwrap();
+	}
+
+	/// Starts a payment of `amount_msat` from node `payer` to node `payee`, returning its index in
+	/// [`Simulation::payments`].
+	///
+	/// The payment is routed using the simulation's scorer and, whenever an attempt fails, retried
+	/// over a new route until the maximum number of attempts is reached.
+	pub fn send_payment(&mut self, payer: usize, payee: usize, amount_msat: u64) -> usize {
+		let (payment_hash, payment_secret) = self.nodes[payee].node
+			.create_inbound_payment(Some(amount_msat), 7200, None).unwrap();
+		self.payments.push(SimPayment {
+			payer, payee, amount_msat, payment_hash, started_at_ms: self.now_ms, attempts: 0,
+			status: SimPaymentStatus::Pending, payment_secret,
+		});
+		let payment_idx = self.payments.len() - 1;
+		self.start_attempt(payment_idx);
+		payment_idx
+	}
+
+	/// Runs the simulation for `duration_ms` of virtual time.
+	pub fn run_for(&mut self, duration_ms: u64) {
+		let end_ms = self.now_ms + duration_ms;
+		loop {
+			self.settle();
+			let wakeup_ms = self.next_wakeup_ms();
+			if wakeup_ms > end_ms { break; }
+			self.advance_to(wakeup_ms);
+		}
+		self.set_now(end_ms);
+	}
+
+	/// Runs the simulation until all payments have completed and no messages are in flight,
+	/// returning false if that did not happen within `timeout_ms` of virtual time.
+	pub fn run_until_idle(&mut self, timeout_ms: u64) -> bool {
+		let end_ms = self.now_ms + timeout_ms;
+		loop {
+			self.settle();
+			if self.is_idle() { return true; }
+			let wakeup_ms = self.next_wakeup_ms();
+			if wakeup_ms > end_ms {
+				self.set_now(end_ms);
+				return false;
+			}
+			self.advance_to(wakeup_ms);
+		}
+	}
+
+	fn is_idle(&self) -> bool {
+		self.in_flight.is_empty() &&
+			self.payments.iter().all(|payment| payment.status != SimPaymentStatus::Pending)
+	}
+
+	fn next_seq(&mut self) -> u64 {
+		self.next_seq += 1;
+		self.next_seq
+	}
+
+	fn set_now(&mut self, now_ms: u64) {
+		self.now_ms = now_ms;
+		SIM_NOW_MS.with(|now| now.set(now_ms));
+	}
+
+	fn next_wakeup_ms(&self) -> u64 {
+		let mut wakeup_ms = self.next_timer_tick_ms;
+		if let Some((at_ms, _)) = self.in_flight.keys().next() {
+			wakeup_ms = cmp::min(wakeup_ms, *at_ms);
+		}
+		if let Some((at_ms, _)) = self.scheduled.keys().next() {
+			wakeup_ms = cmp::min(wakeup_ms, *at_ms);
+		}
+		wakeup_ms
+	}
+
+	/// Advances the clock, applying any link events, message deliveries and timer ticks which
+	/// have come due.
+	fn advance_to(&mut self, at_ms: u64) {
+		self.set_now(at_ms);
+		loop {
+			let key = match self.scheduled.keys().next() {
+				Some(key) if key.0 <= self.now_ms => *key,
+				_ => break,
+			};
+			let (a, b, event) = self.scheduled.remove(&key).unwrap();
+			match event {
+				LinkEvent::Disconnect => self.disconnect(a, b),
+				LinkEvent::Reconnect => self.reconnect(a, b),
+				LinkEvent::SetLatency(latency_ms) => self.set_link_latency(a, b, latency_ms),
+			}
+		}
+		loop {
+			let key = match self.in_flight.keys().next() {
+				Some(key) if key.0 <= self.now_ms => *key,
+				_ => break,
+			};
+			let (from, to, msg_event) = self.in_flight.remove(&key).unwrap();
+			self.deliver(from, to, msg_event);
+		}
+		if self.now_ms >= self.next_timer_tick_ms {
+			for node in self.nodes.iter() {
+				node.node.timer_tick_occurred();
+			}
+			self.next_timer_tick_ms += TIMER_TICK_INTERVAL_MS;
+		}
+	}
+
+	/// Processes events and outbound messages on all nodes until none generate any more.
+	fn settle(&mut self) {
+		loop {
+			let mut made_progress = false;
+			for node_idx in 0..self.nodes.len() {
+				made_progress |= self.process_node(node_idx);
+			}
+			if !made_progress { break; }
+		}
+	}
+
+	fn process_node(&mut self, node_idx: usize) -> bool {
+		let nodes = self.nodes;
+		let node = &nodes[node_idx];
+		let events = node.node.get_and_clear_pending_events();
+		let handled_events = !events.is_empty();
+		for event in events {
+			self.handle_event(node_idx, event);
+		}
+
+		let mut msg_events = node.node.get_and_clear_pending_msg_events();
+		// Messages to different peers may be generated in any order, so sort them by recipient to
+		// keep delivery deterministic, retaining the relative order of messages to each peer.
+		msg_events.sort_by_key(|msg_event| self.destination(msg_event));
+		let sent_messages = !msg_events.is_empty();
+		for msg_event in msg_events {
+			self.enqueue(node_idx, msg_event);
+		}
+
+		// Monitor updates complete immediately, so there is nothing to check here.
+		node.chain_monitor.added_monitors.lock().unwrap().clear();
+		handled_events || sent_messages
+	}
+
+	fn handle_event(&mut self, node_idx: usize, event: Event) {
+		let nodes = self.nodes;
+		let node = &nodes[node_idx];
+		match &event {
+			Event::PendingHTLCsForwardable { .. } => node.node.process_pending_htlc_forwards(),
+			Event::PaymentClaimable { purpose, .. } => match purpose {
+				PaymentPurpose::InvoicePayment { payment_preimage: Some(preimage), .. } |
+				PaymentPurpose::SpontaneousPayment(preimage) => node.node.claim_funds(*preimage),
+				PaymentPurpose::InvoicePayment { payment_preimage: None, .. } => {},
+			},
+			Event::PaymentPathFailed {
+				payment_id: Some(payment_id), path, short_channel_id, payment_failed_permanently,
+				failure, ..
+			} if self.payment_ids.contains_key(payment_id) => {
+				if let PathFailure::OnPath { network_update: Some(update) } = failure {
+					node.network_graph.handle_network_update(update);
+				}
+				match short_channel_id {
+					Some(scid) => self.scorer.payment_path_failed(path, *scid),
+					// The failure came from the recipient, so penalize all channels on the path.
+					None if *payment_failed_permanently => self.scorer.payment_path_failed(path, u64::max_value()),
+					None => {},
+				}
+			},
+			Event::PaymentPathSuccessful { payment_id, path, .. }
+				if self.payment_ids.contains_key(payment_id) =>
+			{
+				self.scorer.payment_path_successful(path);
+			},
+			Event::PaymentSent { payment_id: Some(payment_id), fee_paid_msat, .. } => {
+				if let Some(&payment_idx) = self.payment_ids.get(payment_id) {
+					let completed_at_ms = self.now_ms;
+					let payment = &mut self.payments[payment_idx];
+					if payment.status == SimPaymentStatus::Pending {
+						payment.status = SimPaymentStatus::Succeeded { fee_paid_msat: *fee_paid_msat, completed_at_ms };
+					}
+				}
+			},
+			Event::PaymentFailed { payment_id, reason, .. } => {
+				if let Some(&payment_idx) = self.payment_ids.get(payment_id) {
+					if self.payments[payment_idx].status == SimPaymentStatus::Pending {
+						if *reason == Some(PaymentFailureReason::RecipientRejected) {
+							self.payments[payment_idx].status = SimPaymentStatus::Failed { completed_at_ms: self.now_ms };
+						} else {
+							self.start_attempt(payment_idx);
+						}
+					}
+				}
+			},
+			_ => {},
+		}
+		self.events.push((self.now_ms, node_idx, event));
+	}
+
+	/// Routes and sends a new attempt for the given payment, marking it failed if no attempts
+	/// remain or no route can be found.
+	fn start_attempt(&mut self, payment_idx: usize) {
+		let nodes = self.nodes;
+		loop {
+			let payment = &mut self.payments[payment_idx];
+			if payment.attempts >= self.max_attempts {
+				payment.status = SimPaymentStatus::Failed { completed_at_ms: self.now_ms };
+				return;
+			}
+			payment.attempts += 1;
+			let (payer, payee) = (&nodes[payment.payer], &nodes[payment.payee]);
+			let (amount_msat, payment_hash, payment_secret) =
+				(payment.amount_msat, payment.payment_hash, payment.payment_secret);
+
+			let payment_params = PaymentParameters::from_node_id(payee.node.get_our_node_id(), TEST_FINAL_CLTV)
+				.with_bolt11_features(payee.node.invoice_features()).unwrap();
+			let route_params = RouteParameters { payment_params, final_value_msat: amount_msat };
+			let first_hops = payer.node.list_usable_channels();
+			let random_seed_bytes = self.rng.next_bytes();
+			let route = match router::find_route(
+				&payer.node.get_our_node_id(), &route_params, payer.network_graph,
+				Some(&first_hops.iter().collect::<Vec<_>>()), payer.logger, &self.scorer,
+				&self.score_params, &random_seed_bytes
+			) {
+				Ok(route) => route,
+				Err(_) => {
+					self.payments[payment_idx].status = SimPaymentStatus::Failed { completed_at_ms: self.now_ms };
+					return;
+				},
+			};
+
+			let payment_id = PaymentId(self.rng.next_bytes());
+			match payer.node.send_payment_with_route(&route, payment_hash, RecipientOnionFields::secret_only(payment_secret), payment_id) {
+				Ok(()) => {
+					self.payment_ids.insert(payment_id, payment_idx);
+					return;
+				},
+				Err(PaymentSendFailure::PartialFailure { .. }) => {
+					// Some paths are in flight, so wait for them to resolve before retrying.
+					self.payment_ids.insert(payment_id, payment_idx);
+					payer.node.abandon_payment(payment_id);
+					return;
+				},
+				// Nothing was sent, so retry immediately.
+				Err(_) => {},
+			}
+		}
+	}
+
+	fn destination(&self, msg_event: &MessageSendEvent) -> Option<usize> {
+		let node_id = match msg_event {
+			MessageSendEvent::UpdateHTLCs { node_id, .. } |
+			MessageSendEvent::SendRevokeAndACK { node_id, .. } |
+			MessageSendEvent::SendChannelReestablish { node_id, .. } |
+			MessageSendEvent::SendChannelReady { node_id, .. } |
+			MessageSendEvent::SendAnnouncementSignatures { node_id, .. } |
+			MessageSendEvent::SendChannelUpdate { node_id, .. } |
+			MessageSendEvent::SendShutdown { node_id, .. } |
+			MessageSendEvent::SendClosingSigned { node_id, .. } |
+			MessageSendEvent::SendClosingComplete { node_id, .. } |
+			MessageSendEvent::SendClosingSig { node_id, .. } |
+			MessageSendEvent::HandleError { node_id, .. } => node_id,
+			_ => return None,
+		};
+		Some(*self.node_ids.get(node_id).expect("Message sent to a node outside the simulation"))
+	}
+
+	fn link_latency_ms(&self, a: usize, b: usize) -> u64 {
+		*self.latencies.get(&link(a, b)).unwrap_or(&self.default_latency_ms)
+	}
+
+	/// Queues a message for delivery after the link's latency. Gossip is instead handed to every
+	/// node's gossip handler immediately, and errors are handled at once.
+	fn enqueue(&mut self, from: usize, msg_event: MessageSendEvent) {
+		match msg_event {
+			MessageSendEvent::BroadcastChannelAnnouncement { msg, update_msg } => {
+				for node in self.nodes.iter() {
+					let _ = node.gossip_sync.handle_channel_announcement(&msg);
+					if let Some(update_msg) = &update_msg {
+						let _ = node.gossip_sync.handle_channel_update(update_msg);
+					}
+				}
+			},
+			MessageSendEvent::SendChannelAnnouncement { msg, update_msg, .. } => {
+				for node in self.nodes.iter() {
+					let _ = node.gossip_sync.handle_channel_announcement(&msg);
+					let _ = node.gossip_sync.handle_channel_update(&update_msg);
+				}
+			},
+			MessageSendEvent::BroadcastChannelUpdate { msg } => {
+				for node in self.nodes.iter() {
+					let _ = node.gossip_sync.handle_channel_update(&msg);
+				}
+			},
+			MessageSendEvent::BroadcastNodeAnnouncement { msg } => {
+				for node in self.nodes.iter() {
+					let _ = node.gossip_sync.handle_node_announcement(&msg);
+				}
+			},
+			MessageSendEvent::SendChannelRangeQuery { .. } |
+			MessageSendEvent::SendShortIdsQuery { .. } |
+			MessageSendEvent::SendReplyChannelRange { .. } |
+			MessageSendEvent::SendGossipTimestampFilter { .. } |
+			MessageSendEvent::SendPeerStorage { .. } |
+			MessageSendEvent::SendYourPeerStorage { .. } => {},
+			MessageSendEvent::HandleError { node_id, action } => {
+				let to = self.node_ids[&node_id];
+				if self.disconnected.contains(&link(from, to)) { return; }
+				let from_id = self.nodes[from].node.get_our_node_id();
+				match action {
+					ErrorAction::DisconnectPeer { msg } => {
+						if let Some(msg) = msg {
+							self.nodes[to].node.handle_error(&from_id, &msg);
+						}
+						self.disconnect(from, to);
+					},
+					ErrorAction::DisconnectPeerWithWarning { .. } => self.disconnect(from, to),
+					ErrorAction::SendErrorMessage { msg } => self.nodes[to].node.handle_error(&from_id, &msg),
+					_ => {},
+				}
+			},
+			msg_event => {
+				let to = match self.destination(&msg_event) {
+					Some(to) => to,
+					None => panic!("Unsupported message in simulation; channels must be opened beforehand: {:?}", msg_event),
+				};
+				if self.disconnected.contains(&link(from, to)) { return; }
+				let earliest_ms = self.last_delivery_ms.get(&(from, to)).cloned().unwrap_or(0);
+				let deliver_at_ms = cmp::max(self.now_ms + self.link_latency_ms(from, to), earliest_ms);
+				self.last_delivery_ms.insert((from, to), deliver_at_ms);
+				let seq = self.next_seq();
+				self.in_flight.insert((deliver_at_ms, seq), (from, to, msg_event));
+			},
+		}
+	}
+
+	fn deliver(&mut self, from: usize, to: usize, msg_event: MessageSendEvent) {
+		let from_id = self.nodes[from].node.get_our_node_id();
+		let node = self.nodes[to].node;
+		match msg_event {
+			MessageSendEvent::UpdateHTLCs { updates, .. } => {
+				for update_add in updates.update_add_htlcs.iter() {
+					node.handle_update_add_htlc(&from_id, update_add);
+				}
+				for update_fulfill in updates.update_fulfill_htlcs.iter() {
+					node.handle_update_fulfill_htlc(&from_id, update_fulfill);
+				}
+				for update_fail in updates.update_fail_htlcs.iter() {
+					node.handle_update_fail_htlc(&from_id, update_fail);
+				}
+				for update_fail_malformed in updates.update_fail_malformed_htlcs.iter() {
+					node.handle_update_fail_malformed_htlc(&from_id, update_fail_malformed);
+				}
+				if let Some(update_fee) = &updates.update_fee {
+					node.handle_update_fee(&from_id, update_fee);
+				}
+				node.handle_commitment_signed(&from_id, &updates.commitment_signed);
+			},
+			MessageSendEvent::SendRevokeAndACK { msg, .. } => node.handle_revoke_and_ack(&from_id, &msg),
+			MessageSendEvent::SendChannelReestablish { msg, .. } => node.handle_channel_reestablish(&from_id, &msg),
+			MessageSendEvent::SendChannelReady { msg, .. } => node.handle_channel_ready(&from_id, &msg),
+			MessageSendEvent::SendAnnouncementSignatures { msg, .. } => node.handle_announcement_signatures(&from_id, &msg),
+			MessageSendEvent::SendChannelUpdate { msg, .. } => node.handle_channel_update(&from_id, &msg),
+			MessageSendEvent::SendShutdown { msg, .. } => node.handle_shutdown(&from_id, &msg),
+			MessageSendEvent::SendClosingSigned { msg, .. } => node.handle_closing_signed(&from_id, &msg),
+			MessageSendEvent::SendClosingComplete { msg, .. } => node.handle_closing_complete(&from_id, &msg),
+			MessageSendEvent::SendClosingSig { msg, .. } => node.handle_closing_sig(&from_id, &msg),
+			_ => unreachable!("Only directed messages are queued"),
+		}
+	}
+}
+
+/// Returns the key identifying the undirected link between nodes `a` and `b`.
+fn link(a: usize, b: usize) -> (usize, usize) {
+	(cmp::min(a, b), cmp::max(a, b))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::ln::functional_test_utils::*;
+	use crate::routing::scoring::{ProbabilisticScorerUsingTime, ProbabilisticScoringDecayParameters, ProbabilisticScoringFeeParameters};
+	use crate::util::test_utils::TestScorer;
+
+	fn run_random_network(seed: u64) -> Vec<(SimPaymentStatus, usize)> {
+		let chanmon_cfgs = create_chanmon_cfgs(6);
+		let node_cfgs = create_node_cfgs(6, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(6, &node_cfgs, &[None, None, None, None, None, None]);
+		let nodes = create_network(6, &node_cfgs, &node_chanmgrs);
+
+		let mut rng = SimRng::new(seed);
+		let channels = create_random_topology(&nodes, &mut rng, 3, 1_000_000);
+		assert_eq!(channels.len(), 9);
+
+		let mut sim = Simulation::new(&nodes, TestScorer::new(), (), seed);
+		sim.set_link_latency(0, 1, 500);
+		sim.schedule_link_event(2_000, 2, 3, LinkEvent::SetLatency(1_000));
+		sim.send_payment(0, 3, 1_000_000);
+		sim.send_payment(4, 1, 2_000_000);
+		sim.run_for(1_000);
+		sim.send_payment(5, 2, 3_000_000);
+		assert!(sim.run_until_idle(600_000));
+
+		// Every payment was claimed by its recipient.
+		let claimed = sim.events().iter().filter(|(_, _, event)| match event {
+			Event::PaymentClaimed { .. } => true,
+			_ => false,
+		}).count();
+		assert_eq!(claimed, 3);
+
+		sim.payments().iter().map(|payment| (payment.status, payment.attempts)).collect()
+	}
+
+	#[test]
+	fn simulation_is_deterministic() {
+		let results = run_random_network(42);
+		for (status, attempts) in results.iter() {
+			match status {
+				SimPaymentStatus::Succeeded { .. } => {},
+				_ => panic!("Expected payment to succeed, got {:?}", status),
+			}
+			assert_eq!(*attempts, 1);
+		}
+		assert_eq!(results, run_random_network(42));
+	}
+
+	#[test]
+	fn retries_around_disconnected_link() {
+		let chanmon_cfgs = create_chanmon_cfgs(4);
+		let node_cfgs = create_node_cfgs(4, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(4, &node_cfgs, &[None, None, None, None]);
+		let nodes = create_network(4, &node_cfgs, &node_chanmgrs);
+
+		// A ring of four nodes gives two routes from node 0 to node 2.
+		let mut rng = SimRng::new(7);
+		create_random_topology(&nodes, &mut rng, 0, 1_000_000);
+
+		let scorer = ProbabilisticScorerUsingTime::<_, _, SimTime>::new(
+			ProbabilisticScoringDecayParameters::default(), nodes[0].network_graph, nodes[0].logger);
+		let mut sim = Simulation::new(&nodes, scorer, ProbabilisticScoringFeeParameters::default(), 7);
+
+		// With the link between nodes 1 and 2 down, an attempt through node 1 fails and the scorer
+		// steers the retry through node 3.
+		sim.disconnect(1, 2);
+		let first_payment = sim.send_payment(0, 2, 5_000_000);
+		sim.run_for(10_000);
+		match sim.payments()[first_payment].status {
+			SimPaymentStatus::Succeeded { completed_at_ms, .. } => assert!(completed_at_ms < 10_000),
+			status => panic!("Expected payment to succeed, got {:?}", status),
+		}
+		assert!(sim.payments()[first_payment].attempts <= 2);
+
+		// While node 0 has no connected peers, no route can be found.
+		sim.schedule_link_event(20_000, 0, 1, LinkEvent::Disconnect);
+		sim.schedule_link_event(20_000, 0, 3, LinkEvent::Disconnect);
+		for &(a, b) in [(0, 1), (0, 3), (1, 2)].iter() {
+			sim.schedule_link_event(40_000, a, b, LinkEvent::Reconnect);
+		}
+		sim.run_for(20_000);
+		let failed_payment = sim.send_payment(0, 2, 5_000_000);
+		match sim.payments()[failed_payment].status {
+			SimPaymentStatus::Failed { completed_at_ms } => assert_eq!(completed_at_ms, 30_000),
+			status => panic!("Expected payment to fail, got {:?}", status),
+		}
+		assert_eq!(sim.payments()[failed_payment].attempts, 1);
+
+		// Once the links are restored, payments go through again.
+		sim.run_for(20_000);
+		let final_payment = sim.send_payment(0, 2, 5_000_000);
+		assert!(sim.run_until_idle(60_000));
+		match sim.payments()[final_payment].status {
+			SimPaymentStatus::Succeeded { .. } => {},
+			status => panic!("Expected payment to succeed, got {:?}", status),
+		}
+	}
+}
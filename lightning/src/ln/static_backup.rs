@@ -0,0 +1,227 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Static channel backups, which allow recovering our balance in our channels after losing all
+//! other node state.
+//!
+//! A [`StaticBackup`] only contains the minimum data required to ask our peers to force-close our
+//! channels and to claim our balance from the commitment transactions they broadcast in response.
+//! It does not change as payments are made over our channels, thus it only needs to be updated
+//! when channels are opened or closed. See [`ChannelManager::get_encrypted_static_backup`] and
+//! [`Event::StaticBackupUpdated`] for how to obtain one, and
+//! [`ChannelManager::recover_from_static_backup`] for how to use it.
+//!
+//! Note that recovering from a static backup is a last resort. It relies on our peers being
+//! online and cooperating, any funds in pending HTLCs are lost, and our peers may broadcast a
+//! revoked commitment transaction without us being able to punish them. Channels using anchor
+//! outputs are not included in static backups.
+//!
+//! [`ChannelManager::get_encrypted_static_backup`]: crate::ln::channelmanager::ChannelManager::get_encrypted_static_backup
+//! [`ChannelManager::recover_from_static_backup`]: crate::ln::channelmanager::ChannelManager::recover_from_static_backup
+//! [`Event::StaticBackupUpdated`]: crate::events::Event::StaticBackupUpdated
+
+use bitcoin::blockdata::script::Script;
+use bitcoin::hash_types::Txid;
+use bitcoin::secp256k1::PublicKey;
+
+use crate::chain::transaction::OutPoint;
+use crate::ln::chan_utils::ChannelPublicKeys;
+use crate::ln::msgs::DecodeError;
+use crate::sign::{KeyMaterial, StaticPaymentOutputDescriptor};
+use crate::util::chacha20poly1305rfc::ChaCha20Poly1305RFC;
+use crate::util::crypto::hkdf_extract_expand_twice;
+use crate::util::ser::{Readable, Writeable};
+
+use crate::io::Cursor;
+use crate::prelude::*;
+
+/// The version byte prefixing each encrypted [`StaticBackup`].
+const STATIC_BACKUP_VERSION: u8 = 1;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+/// The data required to recover our balance in a single channel, see the [module-level
+/// documentation] for more.
+///
+/// [module-level documentation]: self
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StaticChannelBackup {
+	/// The channel's ID.
+	pub channel_id: [u8; 32],
+	/// The node id of our counterparty, which we have to connect to in order to recover the
+	/// channel.
+	pub counterparty_node_id: PublicKey,
+	/// The outpoint of the channel's funding output.
+	pub funding_txo: OutPoint,
+	/// The script of the channel's funding output, allowing the funding output to be watched for
+	/// spends via [`chain::Filter::register_output`].
+	///
+	/// [`chain::Filter::register_output`]: crate::chain::Filter::register_output
+	pub funding_script_pubkey: Script,
+	/// The value of the channel, in satoshis.
+	pub channel_value_satoshis: u64,
+	/// The identifier used to re-derive the keys used in the channel through
+	/// [`SignerProvider::derive_channel_signer`].
+	///
+	/// [`SignerProvider::derive_channel_signer`]: crate::sign::SignerProvider::derive_channel_signer
+	pub channel_keys_id: [u8; 32],
+	/// Our counterparty's public keys and basepoints for the channel.
+	pub counterparty_pubkeys: ChannelPublicKeys,
+}
+
+impl_writeable_tlv_based!(StaticChannelBackup, {
+	(0, channel_id, required),
+	(2, counterparty_node_id, required),
+	(4, funding_txo, required),
+	(6, funding_script_pubkey, required),
+	(8, channel_value_satoshis, required),
+	(10, channel_keys_id, required),
+	(12, counterparty_pubkeys, required),
+});
+
+/// A backup of the data required to recover our balance in each of our channels after losing all
+/// other node state, see the [module-level documentation] for more.
+///
+/// [module-level documentation]: self
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StaticBackup {
+	/// The channels included in the backup.
+	pub channels: Vec<StaticChannelBackup>,
+}
+
+impl_writeable_tlv_based!(StaticBackup, {
+	(0, channels, vec_type),
+});
+
+/// The key used to encrypt and decrypt a [`StaticBackup`], derived from
+/// [`NodeSigner::get_inbound_payment_key_material`].
+///
+/// [`NodeSigner::get_inbound_payment_key_material`]: crate::sign::NodeSigner::get_inbound_payment_key_material
+pub struct StaticBackupKey([u8; 32]);
+
+impl StaticBackupKey {
+	/// Derives a new [`StaticBackupKey`] from the given key material.
+	pub fn new(key_material: &KeyMaterial) -> Self {
+		let (key, _) = hkdf_extract_expand_twice(b"LDK Static Channel Backup Key Expansion", &key_material.0);
+		Self(key)
+	}
+}
+
+impl StaticBackup {
+	/// Serializes and encrypts the backup with the given key and nonce. The nonce must never be
+	/// reused for the same key.
+	pub fn encrypt(&self, key: &StaticBackupKey, nonce: [u8; NONCE_LEN]) -> Vec<u8> {
+		let plaintext = self.encode();
+		let mut res = vec![0; 1 + NONCE_LEN + plaintext.len() + TAG_LEN];
+		res[0] = STATIC_BACKUP_VERSION;
+		res[1..1 + NONCE_LEN].copy_from_slice(&nonce);
+		let (ciphertext, tag) = res[1 + NONCE_LEN..].split_at_mut(plaintext.len());
+		let mut chacha = ChaCha20Poly1305RFC::new(&key.0, &nonce, &[STATIC_BACKUP_VERSION]);
+		chacha.encrypt(&plaintext, ciphertext, tag);
+		res
+	}
+
+	/// Decrypts and deserializes a backup previously returned by [`Self::encrypt`].
+	///
+	/// Fails with [`DecodeError::UnknownVersion`] if the backup was written by a newer version of
+	/// LDK, or [`DecodeError::InvalidValue`] if it was not encrypted with the given key.
+	pub fn decrypt(encrypted_backup: &[u8], key: &StaticBackupKey) -> Result<Self, DecodeError> {
+		if encrypted_backup.len() < 1 + NONCE_LEN + TAG_LEN {
+			return Err(DecodeError::ShortRead);
+		}
+		if encrypted_backup[0] != STATIC_BACKUP_VERSION {
+			return Err(DecodeError::UnknownVersion);
+		}
+		let nonce = &encrypted_backup[1..1 + NONCE_LEN];
+		let (ciphertext, tag) = encrypted_backup[1 + NONCE_LEN..].split_at(encrypted_backup.len() - 1 - NONCE_LEN - TAG_LEN);
+		let mut plaintext = vec![0; ciphertext.len()];
+		let mut chacha = ChaCha20Poly1305RFC::new(&key.0, nonce, &[STATIC_BACKUP_VERSION]);
+		if !chacha.decrypt(ciphertext, &mut plaintext, tag) {
+			return Err(DecodeError::InvalidValue);
+		}
+		let mut reader = Cursor::new(&plaintext);
+		let backup: StaticBackup = Readable::read(&mut reader)?;
+		if reader.position() != plaintext.len() as u64 {
+			return Err(DecodeError::InvalidValue);
+		}
+		Ok(backup)
+	}
+}
+
+/// A channel which is being recovered from a [`StaticBackup`].
+pub(crate) struct RecoveringChannel {
+	pub(crate) backup: StaticChannelBackup,
+	/// The transaction which spent the funding output, along with the height at which it was
+	/// confirmed, once it has been seen.
+	pub(crate) funding_spend: Option<(Txid, u32)>,
+	/// Our balance in the transaction which spent the funding output, if any.
+	pub(crate) claimable_output: Option<StaticPaymentOutputDescriptor>,
+}
+
+impl_writeable_tlv_based!(RecoveringChannel, {
+	(0, backup, required),
+	(2, funding_spend, option),
+	(4, claimable_output, option),
+});
+
+#[cfg(test)]
+mod tests {
+	use super::{StaticBackup, StaticBackupKey, StaticChannelBackup};
+
+	use bitcoin::blockdata::script::Script;
+	use bitcoin::hash_types::Txid;
+	use bitcoin::hashes::Hash;
+	use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+
+	use crate::chain::transaction::OutPoint;
+	use crate::ln::chan_utils::ChannelPublicKeys;
+	use crate::ln::msgs::DecodeError;
+	use crate::sign::KeyMaterial;
+
+	fn dummy_backup() -> StaticBackup {
+		let secp_ctx = Secp256k1::new();
+		let pubkey = PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[42; 32]).unwrap());
+		let channel = StaticChannelBackup {
+			channel_id: [1; 32],
+			counterparty_node_id: pubkey,
+			funding_txo: OutPoint { txid: Txid::from_slice(&[2; 32]).unwrap(), index: 0 },
+			funding_script_pubkey: Script::new(),
+			channel_value_satoshis: 100_000,
+			channel_keys_id: [3; 32],
+			counterparty_pubkeys: ChannelPublicKeys {
+				funding_pubkey: pubkey,
+				revocation_basepoint: pubkey,
+				payment_point: pubkey,
+				delayed_payment_basepoint: pubkey,
+				htlc_basepoint: pubkey,
+			},
+		};
+		StaticBackup { channels: vec![channel.clone(), channel] }
+	}
+
+	#[test]
+	fn encrypted_backup_roundtrip() {
+		let backup = dummy_backup();
+		let key = StaticBackupKey::new(&KeyMaterial([4; 32]));
+		let encrypted_backup = backup.encrypt(&key, [5; 12]);
+		assert_eq!(StaticBackup::decrypt(&encrypted_backup, &key).unwrap(), backup);
+
+		let other_key = StaticBackupKey::new(&KeyMaterial([6; 32]));
+		assert_eq!(StaticBackup::decrypt(&encrypted_backup, &other_key), Err(DecodeError::InvalidValue));
+
+		let mut corrupted_backup = encrypted_backup.clone();
+		*corrupted_backup.last_mut().unwrap() ^= 1;
+		assert_eq!(StaticBackup::decrypt(&corrupted_backup, &key), Err(DecodeError::InvalidValue));
+
+		let mut unknown_version_backup = encrypted_backup;
+		unknown_version_backup[0] = 2;
+		assert_eq!(StaticBackup::decrypt(&unknown_version_backup, &key), Err(DecodeError::UnknownVersion));
+		assert_eq!(StaticBackup::decrypt(&[1; 28], &key), Err(DecodeError::ShortRead));
+	}
+}
@@ -53,6 +53,8 @@ pub(crate) enum Message<T> where T: core::fmt::Debug + Type + TestEq {
 	Warning(msgs::WarningMessage),
 	Ping(msgs::Ping),
 	Pong(msgs::Pong),
+	PeerStorage(msgs::PeerStorage),
+	YourPeerStorage(msgs::YourPeerStorage),
 	OpenChannel(msgs::OpenChannel),
 	OpenChannelV2(msgs::OpenChannelV2),
 	AcceptChannel(msgs::AcceptChannel),
@@ -71,6 +73,8 @@ pub(crate) enum Message<T> where T: core::fmt::Debug + Type + TestEq {
 	ChannelReady(msgs::ChannelReady),
 	Shutdown(msgs::Shutdown),
 	ClosingSigned(msgs::ClosingSigned),
+	ClosingComplete(msgs::ClosingComplete),
+	ClosingSig(msgs::ClosingSig),
 	OnionMessage(msgs::OnionMessage),
 	UpdateAddHTLC(msgs::UpdateAddHTLC),
 	UpdateFulfillHTLC(msgs::UpdateFulfillHTLC),
@@ -82,6 +86,8 @@ pub(crate) enum Message<T> where T: core::fmt::Debug + Type + TestEq {
 	ChannelReestablish(msgs::ChannelReestablish),
 	AnnouncementSignatures(msgs::AnnouncementSignatures),
 	ChannelAnnouncement(msgs::ChannelAnnouncement),
+	#[cfg(taproot)]
+	ChannelAnnouncement2(msgs::ChannelAnnouncement2),
 	NodeAnnouncement(msgs::NodeAnnouncement),
 	ChannelUpdate(msgs::ChannelUpdate),
 	QueryShortChannelIds(msgs::QueryShortChannelIds),
@@ -104,6 +110,8 @@ impl<T> Writeable for Message<T> where T: core::fmt::Debug + Type + TestEq {
 			&Message::Warning(ref msg) => msg.write(writer),
 			&Message::Ping(ref msg) => msg.write(writer),
 			&Message::Pong(ref msg) => msg.write(writer),
+			&Message::PeerStorage(ref msg) => msg.write(writer),
+			&Message::YourPeerStorage(ref msg) => msg.write(writer),
 			&Message::OpenChannel(ref msg) => msg.write(writer),
 			&Message::OpenChannelV2(ref msg) => msg.write(writer),
 			&Message::AcceptChannel(ref msg) => msg.write(writer),
@@ -122,6 +130,8 @@ impl<T> Writeable for Message<T> where T: core::fmt::Debug + Type + TestEq {
 			&Message::ChannelReady(ref msg) => msg.write(writer),
 			&Message::Shutdown(ref msg) => msg.write(writer),
 			&Message::ClosingSigned(ref msg) => msg.write(writer),
+			&Message::ClosingComplete(ref msg) => msg.write(writer),
+			&Message::ClosingSig(ref msg) => msg.write(writer),
 			&Message::OnionMessage(ref msg) => msg.write(writer),
 			&Message::UpdateAddHTLC(ref msg) => msg.write(writer),
 			&Message::UpdateFulfillHTLC(ref msg) => msg.write(writer),
@@ -133,6 +143,8 @@ impl<T> Writeable for Message<T> where T: core::fmt::Debug + Type + TestEq {
 			&Message::ChannelReestablish(ref msg) => msg.write(writer),
 			&Message::AnnouncementSignatures(ref msg) => msg.write(writer),
 			&Message::ChannelAnnouncement(ref msg) => msg.write(writer),
+			#[cfg(taproot)]
+			&Message::ChannelAnnouncement2(ref msg) => msg.write(writer),
 			&Message::NodeAnnouncement(ref msg) => msg.write(writer),
 			&Message::ChannelUpdate(ref msg) => msg.write(writer),
 			&Message::QueryShortChannelIds(ref msg) => msg.write(writer),
@@ -155,6 +167,8 @@ impl<T> Type for Message<T> where T: core::fmt::Debug + Type + TestEq {
 			&Message::Warning(ref msg) => msg.type_id(),
 			&Message::Ping(ref msg) => msg.type_id(),
 			&Message::Pong(ref msg) => msg.type_id(),
+			&Message::PeerStorage(ref msg) => msg.type_id(),
+			&Message::YourPeerStorage(ref msg) => msg.type_id(),
 			&Message::OpenChannel(ref msg) => msg.type_id(),
 			&Message::OpenChannelV2(ref msg) => msg.type_id(),
 			&Message::AcceptChannel(ref msg) => msg.type_id(),
@@ -173,6 +187,8 @@ impl<T> Type for Message<T> where T: core::fmt::Debug + Type + TestEq {
 			&Message::ChannelReady(ref msg) => msg.type_id(),
 			&Message::Shutdown(ref msg) => msg.type_id(),
 			&Message::ClosingSigned(ref msg) => msg.type_id(),
+			&Message::ClosingComplete(ref msg) => msg.type_id(),
+			&Message::ClosingSig(ref msg) => msg.type_id(),
 			&Message::OnionMessage(ref msg) => msg.type_id(),
 			&Message::UpdateAddHTLC(ref msg) => msg.type_id(),
 			&Message::UpdateFulfillHTLC(ref msg) => msg.type_id(),
@@ -184,6 +200,8 @@ impl<T> Type for Message<T> where T: core::fmt::Debug + Type + TestEq {
 			&Message::ChannelReestablish(ref msg) => msg.type_id(),
 			&Message::AnnouncementSignatures(ref msg) => msg.type_id(),
 			&Message::ChannelAnnouncement(ref msg) => msg.type_id(),
+			#[cfg(taproot)]
+			&Message::ChannelAnnouncement2(ref msg) => msg.type_id(),
 			&Message::NodeAnnouncement(ref msg) => msg.type_id(),
 			&Message::ChannelUpdate(ref msg) => msg.type_id(),
 			&Message::QueryShortChannelIds(ref msg) => msg.type_id(),
@@ -240,6 +258,12 @@ fn do_read<R: io::Read, T, H: core::ops::Deref>(buffer: &mut R, message_type: u1
 		msgs::Pong::TYPE => {
 			Ok(Message::Pong(Readable::read(buffer)?))
 		},
+		msgs::PeerStorage::TYPE => {
+			Ok(Message::PeerStorage(Readable::read(buffer)?))
+		},
+		msgs::YourPeerStorage::TYPE => {
+			Ok(Message::YourPeerStorage(Readable::read(buffer)?))
+		},
 		msgs::OpenChannel::TYPE => {
 			Ok(Message::OpenChannel(Readable::read(buffer)?))
 		},
@@ -294,6 +318,12 @@ fn do_read<R: io::Read, T, H: core::ops::Deref>(buffer: &mut R, message_type: u1
 		msgs::ClosingSigned::TYPE => {
 			Ok(Message::ClosingSigned(Readable::read(buffer)?))
 		},
+		msgs::ClosingComplete::TYPE => {
+			Ok(Message::ClosingComplete(Readable::read(buffer)?))
+		},
+		msgs::ClosingSig::TYPE => {
+			Ok(Message::ClosingSig(Readable::read(buffer)?))
+		},
 		msgs::OnionMessage::TYPE => {
 			Ok(Message::OnionMessage(Readable::read(buffer)?))
 		},
@@ -327,6 +357,10 @@ fn do_read<R: io::Read, T, H: core::ops::Deref>(buffer: &mut R, message_type: u1
 		msgs::ChannelAnnouncement::TYPE => {
 			Ok(Message::ChannelAnnouncement(Readable::read(buffer)?))
 		},
+		#[cfg(taproot)]
+		msgs::ChannelAnnouncement2::TYPE => {
+			Ok(Message::ChannelAnnouncement2(Readable::read(buffer)?))
+		},
 		msgs::NodeAnnouncement::TYPE => {
 			Ok(Message::NodeAnnouncement(Readable::read(buffer)?))
 		},
@@ -428,6 +462,14 @@ impl Encode for msgs::Pong {
 	const TYPE: u16 = 19;
 }
 
+impl Encode for msgs::PeerStorage {
+	const TYPE: u16 = 7;
+}
+
+impl Encode for msgs::YourPeerStorage {
+	const TYPE: u16 = 9;
+}
+
 impl Encode for msgs::OpenChannel {
 	const TYPE: u16 = 32;
 }
@@ -456,6 +498,14 @@ impl Encode for msgs::ClosingSigned {
 	const TYPE: u16 = 39;
 }
 
+impl Encode for msgs::ClosingComplete {
+	const TYPE: u16 = 40;
+}
+
+impl Encode for msgs::ClosingSig {
+	const TYPE: u16 = 41;
+}
+
 impl Encode for msgs::OpenChannelV2 {
 	const TYPE: u16 = 64;
 }
@@ -544,6 +594,11 @@ impl Encode for msgs::ChannelAnnouncement {
 	const TYPE: u16 = 256;
 }
 
+#[cfg(taproot)]
+impl Encode for msgs::ChannelAnnouncement2 {
+	const TYPE: u16 = 267;
+}
+
 impl Encode for msgs::NodeAnnouncement {
 	const TYPE: u16 = 257;
 }
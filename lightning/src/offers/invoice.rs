@@ -102,6 +102,7 @@ use bitcoin::secp256k1::schnorr::Signature;
 use bitcoin::util::address::{Address, Payload, WitnessVersion};
 use bitcoin::util::schnorr::TweakedPublicKey;
 use core::convert::{Infallible, TryFrom};
+use core::ops::Deref;
 use core::time::Duration;
 use crate::io;
 use crate::blinded_path::BlindedPath;
@@ -116,6 +117,7 @@ use crate::offers::parse::{ParseError, ParsedMessage, SemanticError};
 use crate::offers::payer::{PAYER_METADATA_TYPE, PayerTlvStream, PayerTlvStreamRef};
 use crate::offers::refund::{IV_BYTES as REFUND_IV_BYTES, Refund, RefundContents};
 use crate::offers::signer;
+use crate::util::clock::TimeProvider;
 use crate::util::ser::{HighZeroBytesDroppedBigSize, Iterable, SeekReadable, WithoutLength, Writeable, Writer};
 use crate::util::string::PrintableString;
 
@@ -232,7 +234,7 @@ impl<'a> InvoiceBuilder<'a, DerivedSigningPubkey> {
 }
 
 impl<'a, S: SigningPubkeyStrategy> InvoiceBuilder<'a, S> {
-	fn check_amount_msats(invoice_request: &InvoiceRequest) -> Result<u64, SemanticError> {
+	pub(crate) fn check_amount_msats(invoice_request: &InvoiceRequest) -> Result<u64, SemanticError> {
 		match invoice_request.amount_msats() {
 			Some(amount_msats) => Ok(amount_msats),
 			None => match invoice_request.contents.inner.offer.amount() {
@@ -421,7 +423,6 @@ impl<'a> UnsignedInvoice<'a> {
 /// [`Refund`]: crate::offers::refund::Refund
 /// [`InvoiceRequest`]: crate::offers::invoice_request::InvoiceRequest
 #[derive(Clone, Debug)]
-#[cfg_attr(test, derive(PartialEq))]
 pub struct Invoice {
 	bytes: Vec<u8>,
 	contents: InvoiceContents,
@@ -494,12 +495,21 @@ impl Invoice {
 	/// Whether the invoice has expired.
 	#[cfg(feature = "std")]
 	pub fn is_expired(&self) -> bool {
+		match SystemTime::UNIX_EPOCH.elapsed() {
+			Ok(elapsed) => self.is_expired_no_std(elapsed),
+			Err(_) => false,
+		}
+	}
+
+	/// Whether the invoice has expired, according to the given [`TimeProvider`].
+	pub fn is_expired_with_time<T: Deref>(&self, time_provider: T) -> bool where T::Target: TimeProvider {
+		self.is_expired_no_std(time_provider.duration_since_epoch())
+	}
+
+	fn is_expired_no_std(&self, duration_since_epoch: Duration) -> bool {
 		let absolute_expiry = self.created_at().checked_add(self.relative_expiry());
 		match absolute_expiry {
-			Some(seconds_from_epoch) => match SystemTime::UNIX_EPOCH.elapsed() {
-				Ok(elapsed) => elapsed > seconds_from_epoch,
-				Err(_) => false,
-			},
+			Some(seconds_from_epoch) => duration_since_epoch > seconds_from_epoch,
 			None => false,
 		}
 	}
@@ -596,6 +606,36 @@ impl Invoice {
 		self.contents.verify(TlvStream::new(&self.bytes), key, secp_ctx)
 	}
 
+	/// Whether the invoice was created in response to the given [`InvoiceRequest`], i.e., whether
+	/// it contains the same offer and invoice request fields.
+	///
+	/// Useful for checking an invoice received out of band before paying it.
+	pub fn is_for_invoice_request(&self, invoice_request: &InvoiceRequest) -> bool {
+		match self.contents {
+			InvoiceContents::ForOffer { .. } => self.echoes_records(&invoice_request.bytes),
+			InvoiceContents::ForRefund { .. } => false,
+		}
+	}
+
+	/// Whether the invoice was created in response to the given [`Refund`], i.e., whether it
+	/// contains the same refund fields.
+	///
+	/// Useful for checking an invoice received out of band before paying it.
+	pub fn is_for_refund(&self, refund: &Refund) -> bool {
+		match self.contents {
+			InvoiceContents::ForOffer { .. } => false,
+			InvoiceContents::ForRefund { .. } => self.echoes_records(&refund.bytes),
+		}
+	}
+
+	/// Whether the payer, offer, and invoice request records of the invoice are exactly those in
+	/// the given TLV stream.
+	fn echoes_records(&self, bytes: &[u8]) -> bool {
+		let invoice_records = TlvStream::new(&self.bytes).range(..INVOICE_REQUEST_TYPES.end);
+		let records = TlvStream::new(bytes).range(..INVOICE_REQUEST_TYPES.end);
+		invoice_records.map(|record| record.record_bytes).eq(records.map(|record| record.record_bytes))
+	}
+
 	#[cfg(test)]
 	pub(super) fn as_tlv_stream(&self) -> FullInvoiceTlvStreamRef {
 		let (payer_tlv_stream, offer_tlv_stream, invoice_request_tlv_stream, invoice_tlv_stream) =
@@ -716,6 +756,14 @@ impl InvoiceFields {
 	}
 }
 
+impl PartialEq for Invoice {
+	fn eq(&self, other: &Self) -> bool {
+		self.bytes.eq(&other.bytes)
+	}
+}
+
+impl Eq for Invoice {}
+
 impl Writeable for Invoice {
 	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
 		WithoutLength(&self.bytes).write(writer)
@@ -1071,6 +1119,40 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn checks_invoice_corresponds_to_request_or_refund() {
+		let offer = OfferBuilder::new("foo".into(), recipient_pubkey())
+			.amount_msats(1000)
+			.build().unwrap();
+		let invoice_request = offer.request_invoice(vec![1; 32], payer_pubkey()).unwrap()
+			.build().unwrap()
+			.sign(payer_sign).unwrap();
+		let other_invoice_request = offer.request_invoice(vec![2; 32], payer_pubkey()).unwrap()
+			.build().unwrap()
+			.sign(payer_sign).unwrap();
+		let invoice = invoice_request.respond_with_no_std(payment_paths(), payment_hash(), now())
+			.unwrap()
+			.build().unwrap()
+			.sign(recipient_sign).unwrap();
+		assert!(invoice.is_for_invoice_request(&invoice_request));
+		assert!(!invoice.is_for_invoice_request(&other_invoice_request));
+
+		let refund = RefundBuilder::new("foo".into(), vec![1; 32], payer_pubkey(), 1000).unwrap()
+			.build().unwrap();
+		let other_refund = RefundBuilder::new("foo".into(), vec![1; 32], payer_pubkey(), 2000).unwrap()
+			.build().unwrap();
+		let refund_invoice = refund
+			.respond_with_no_std(payment_paths(), payment_hash(), recipient_pubkey(), now())
+			.unwrap()
+			.build().unwrap()
+			.sign(recipient_sign).unwrap();
+		assert!(refund_invoice.is_for_refund(&refund));
+		assert!(!refund_invoice.is_for_refund(&other_refund));
+
+		assert!(!invoice.is_for_refund(&refund));
+		assert!(!refund_invoice.is_for_invoice_request(&invoice_request));
+	}
+
 	#[test]
 	fn builds_invoice_for_refund_with_defaults() {
 		let payment_paths = payment_paths();
@@ -1312,6 +1394,8 @@ mod tests {
 		let (_, _, _, tlv_stream, _) = invoice.as_tlv_stream();
 		#[cfg(feature = "std")]
 		assert!(!invoice.is_expired());
+		assert!(!invoice.is_expired_with_time(&FixedTime(now + one_hour)));
+		assert!(invoice.is_expired_with_time(&FixedTime(now + one_hour + Duration::from_secs(1))));
 		assert_eq!(invoice.relative_expiry(), one_hour);
 		assert_eq!(tlv_stream.relative_expiry, Some(one_hour.as_secs() as u32));
 
@@ -1328,6 +1412,7 @@ mod tests {
 		let (_, _, _, tlv_stream, _) = invoice.as_tlv_stream();
 		#[cfg(feature = "std")]
 		assert!(invoice.is_expired());
+		assert!(invoice.is_expired_with_time(&FixedTime(now)));
 		assert_eq!(invoice.relative_expiry(), one_hour - Duration::from_secs(1));
 		assert_eq!(tlv_stream.relative_expiry, Some(one_hour.as_secs() as u32 - 1));
 	}
@@ -16,8 +16,12 @@
 //! For an "offer for money" (e.g., refund, ATM withdrawal), where an offer doesn't exist as a
 //! precursor, see [`Refund`].
 //!
+//! Payers unable to send the request in an onion message may instead hand it to the merchant
+//! out-of-band, e.g. over HTTPS, using its bech32 encoding, see [`InvoiceRequest::from_str`].
+//!
 //! [`Invoice`]: crate::offers::invoice::Invoice
 //! [`Refund`]: crate::offers::refund::Refund
+//! [`InvoiceRequest::from_str`]: core::str::FromStr::from_str
 //!
 //! ```
 //! extern crate bitcoin;
@@ -58,6 +62,7 @@ use bitcoin::secp256k1::{KeyPair, Message, PublicKey, Secp256k1, self};
 use bitcoin::secp256k1::schnorr::Signature;
 use core::convert::{Infallible, TryFrom};
 use core::ops::Deref;
+use core::str::FromStr;
 use crate::sign::EntropySource;
 use crate::io;
 use crate::blinded_path::BlindedPath;
@@ -67,8 +72,8 @@ use crate::ln::inbound_payment::{ExpandedKey, IV_LEN, Nonce};
 use crate::ln::msgs::DecodeError;
 use crate::offers::invoice::{BlindedPayInfo, DerivedSigningPubkey, ExplicitSigningPubkey, InvoiceBuilder};
 use crate::offers::merkle::{SignError, SignatureTlvStream, SignatureTlvStreamRef, self};
-use crate::offers::offer::{Offer, OfferContents, OfferTlvStream, OfferTlvStreamRef};
-use crate::offers::parse::{ParseError, ParsedMessage, SemanticError};
+use crate::offers::offer::{Amount, Offer, OfferContents, OfferTlvStream, OfferTlvStreamRef};
+use crate::offers::parse::{Bech32Encode, ParseError, ParsedMessage, SemanticError};
 use crate::offers::payer::{PayerContents, PayerTlvStream, PayerTlvStreamRef};
 use crate::offers::signer::{Metadata, MetadataMaterial};
 use crate::util::ser::{HighZeroBytesDroppedBigSize, SeekReadable, WithoutLength, Writeable, Writer};
@@ -389,7 +394,6 @@ impl<'a> UnsignedInvoiceRequest<'a> {
 /// [`Invoice`]: crate::offers::invoice::Invoice
 /// [`Offer`]: crate::offers::offer::Offer
 #[derive(Clone, Debug)]
-#[cfg_attr(test, derive(PartialEq))]
 pub struct InvoiceRequest {
 	pub(super) bytes: Vec<u8>,
 	pub(super) contents: InvoiceRequestContents,
@@ -440,6 +444,12 @@ impl InvoiceRequest {
 		self.contents.inner.amount_msats
 	}
 
+	/// The [`Offer::description`] of the offer the invoice is requested for. Intended to be
+	/// displayed to the user but with the caveat that it has not been verified in any way.
+	pub fn description(&self) -> PrintableString {
+		self.contents.inner.offer.description()
+	}
+
 	/// Features pertaining to requesting an invoice.
 	pub fn features(&self) -> &InvoiceRequestFeatures {
 		&self.contents.inner.features
@@ -450,6 +460,24 @@ impl InvoiceRequest {
 		self.contents.inner.quantity
 	}
 
+	/// The amount an [`Invoice`] sent in response is expected to be for, i.e., the requested
+	/// [`amount_msats`] or else the offer's bitcoin amount times the requested [`quantity`], if
+	/// either is known.
+	///
+	/// [`Invoice`]: crate::offers::invoice::Invoice
+	/// [`amount_msats`]: Self::amount_msats
+	/// [`quantity`]: Self::quantity
+	pub(crate) fn expected_invoice_amount_msats(&self) -> Option<u64> {
+		match self.amount_msats() {
+			Some(amount_msats) => Some(amount_msats),
+			None => match self.contents.inner.offer.amount() {
+				Some(Amount::Bitcoin { amount_msats }) =>
+					amount_msats.checked_mul(self.quantity().unwrap_or(1)),
+				_ => None,
+			},
+		}
+	}
+
 	/// A possibly transient pubkey used to sign the invoice request.
 	pub fn payer_id(&self) -> PublicKey {
 		self.contents.payer_id
@@ -648,6 +676,38 @@ impl InvoiceRequestContentsWithoutPayerId {
 	}
 }
 
+impl PartialEq for InvoiceRequest {
+	fn eq(&self, other: &Self) -> bool {
+		self.bytes.eq(&other.bytes)
+	}
+}
+
+impl Eq for InvoiceRequest {}
+
+impl AsRef<[u8]> for InvoiceRequest {
+	fn as_ref(&self) -> &[u8] {
+		&self.bytes
+	}
+}
+
+impl Bech32Encode for InvoiceRequest {
+	const BECH32_HRP: &'static str = "lnr";
+}
+
+impl FromStr for InvoiceRequest {
+	type Err = ParseError;
+
+	fn from_str(s: &str) -> Result<Self, <Self as FromStr>::Err> {
+		InvoiceRequest::from_bech32_str(s)
+	}
+}
+
+impl core::fmt::Display for InvoiceRequest {
+	fn fmt(&self, f: &mut core::fmt::Formatter) -> Result<(), core::fmt::Error> {
+		self.fmt_bech32_str(f)
+	}
+}
+
 impl Writeable for InvoiceRequest {
 	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
 		WithoutLength(&self.bytes).write(writer)
@@ -794,6 +854,7 @@ mod tests {
 	use crate::offers::offer::{Amount, OfferBuilder, OfferTlvStreamRef, Quantity};
 	use crate::offers::parse::{ParseError, SemanticError};
 	use crate::offers::payer::PayerTlvStreamRef;
+	use crate::offers::refund::RefundBuilder;
 	use crate::offers::test_utils::*;
 	use crate::util::ser::{BigSize, Writeable};
 	use crate::util::string::PrintableString;
@@ -1785,4 +1846,31 @@ mod tests {
 			Err(e) => assert_eq!(e, ParseError::Decode(DecodeError::InvalidValue)),
 		}
 	}
+	#[test]
+	fn parses_invoice_request_from_bech32_str() {
+		let invoice_request = OfferBuilder::new("foo".into(), recipient_pubkey())
+			.amount_msats(1000)
+			.build().unwrap()
+			.request_invoice(vec![1; 32], payer_pubkey()).unwrap()
+			.build().unwrap()
+			.sign(payer_sign).unwrap();
+
+		let encoded_invoice_request = invoice_request.to_string();
+		assert!(encoded_invoice_request.starts_with("lnr1"));
+		match encoded_invoice_request.parse::<InvoiceRequest>() {
+			Ok(parsed) => assert_eq!(parsed, invoice_request),
+			Err(e) => panic!("error parsing invoice_request: {:?}", e),
+		}
+
+		// Refunds share the HRP but aren't for an offer nor signed.
+		let refund = RefundBuilder::new("foo".into(), vec![1; 32], payer_pubkey(), 1000).unwrap()
+			.build().unwrap();
+		assert!(refund.to_string().parse::<InvoiceRequest>().is_err());
+
+		let encoded_offer = OfferBuilder::new("foo".into(), recipient_pubkey()).build().unwrap().to_string();
+		match encoded_offer.parse::<InvoiceRequest>() {
+			Ok(_) => panic!("expected error"),
+			Err(e) => assert_eq!(e, ParseError::InvalidBech32Hrp),
+		}
+	}
 }
@@ -66,7 +66,7 @@ pub(super) fn verify_signature(
 	secp_ctx.verify_schnorr(signature, &digest, &pubkey)
 }
 
-pub(super) fn message_digest(tag: &str, bytes: &[u8]) -> Message {
+pub(crate) fn message_digest(tag: &str, bytes: &[u8]) -> Message {
 	let tag = sha256::Hash::hash(tag.as_bytes());
 	let merkle_root = root_hash(bytes);
 	Message::from_slice(&tagged_hash(tag, merkle_root)).unwrap()
@@ -229,8 +229,6 @@ mod tests {
 	use bitcoin::secp256k1::schnorr::Signature;
 	use core::convert::Infallible;
 	use crate::offers::offer::{Amount, OfferBuilder};
-	use crate::offers::invoice_request::InvoiceRequest;
-	use crate::offers::parse::Bech32Encode;
 	use crate::util::ser::Writeable;
 
 	#[test]
@@ -348,20 +346,4 @@ mod tests {
 
 		assert_eq!(tlv_stream, invoice_request.bytes);
 	}
-
-	impl AsRef<[u8]> for InvoiceRequest {
-		fn as_ref(&self) -> &[u8] {
-			&self.bytes
-		}
-	}
-
-	impl Bech32Encode for InvoiceRequest {
-		const BECH32_HRP: &'static str = "lnr";
-	}
-
-	impl core::fmt::Display for InvoiceRequest {
-		fn fmt(&self, f: &mut core::fmt::Formatter) -> Result<(), core::fmt::Error> {
-			self.fmt_bech32_str(f)
-		}
-	}
 }
@@ -15,10 +15,11 @@
 pub mod invoice;
 pub mod invoice_error;
 pub mod invoice_request;
-mod merkle;
+pub(crate) mod merkle;
 pub mod offer;
 pub mod parse;
 mod payer;
+pub mod payer_id;
 pub mod refund;
 #[allow(unused)]
 pub(crate) mod signer;
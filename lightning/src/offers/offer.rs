@@ -84,6 +84,7 @@ use crate::offers::invoice_request::{DerivedPayerId, ExplicitPayerId, InvoiceReq
 use crate::offers::merkle::TlvStream;
 use crate::offers::parse::{Bech32Encode, ParseError, ParsedMessage, SemanticError};
 use crate::offers::signer::{Metadata, MetadataMaterial, self};
+use crate::util::clock::TimeProvider;
 use crate::util::ser::{HighZeroBytesDroppedBigSize, WithoutLength, Writeable, Writer};
 use crate::util::string::PrintableString;
 
@@ -413,6 +414,11 @@ impl Offer {
 		self.contents.is_expired()
 	}
 
+	/// Whether the offer has expired, according to the given [`TimeProvider`].
+	pub fn is_expired_with_time<T: Deref>(&self, time_provider: T) -> bool where T::Target: TimeProvider {
+		self.contents.is_expired_no_std(time_provider.duration_since_epoch())
+	}
+
 	/// The issuer of the offer, possibly beginning with `user@domain` or `domain`. Intended to be
 	/// displayed to the user but with the caveat that it has not been verified in any way.
 	pub fn issuer(&self) -> Option<PrintableString> {
@@ -556,11 +562,15 @@ impl OfferContents {
 
 	#[cfg(feature = "std")]
 	pub(super) fn is_expired(&self) -> bool {
+		match SystemTime::UNIX_EPOCH.elapsed() {
+			Ok(elapsed) => self.is_expired_no_std(elapsed),
+			Err(_) => false,
+		}
+	}
+
+	pub(super) fn is_expired_no_std(&self, duration_since_epoch: Duration) -> bool {
 		match self.absolute_expiry {
-			Some(seconds_from_epoch) => match SystemTime::UNIX_EPOCH.elapsed() {
-				Ok(elapsed) => elapsed > seconds_from_epoch,
-				Err(_) => false,
-			},
+			Some(seconds_from_epoch) => duration_since_epoch > seconds_from_epoch,
 			None => false,
 		}
 	}
@@ -1137,6 +1147,7 @@ mod tests {
 			.unwrap();
 		#[cfg(feature = "std")]
 		assert!(!offer.is_expired());
+		assert!(!offer.is_expired_with_time(&FixedTime(Duration::from_secs(1))));
 		assert_eq!(offer.absolute_expiry(), Some(future_expiry));
 		assert_eq!(offer.as_tlv_stream().absolute_expiry, Some(future_expiry.as_secs()));
 
@@ -1147,6 +1158,8 @@ mod tests {
 			.unwrap();
 		#[cfg(feature = "std")]
 		assert!(offer.is_expired());
+		assert!(offer.is_expired_with_time(&FixedTime(Duration::from_secs(1))));
+		assert!(!offer.is_expired_with_time(&FixedTime(past_expiry)));
 		assert_eq!(offer.absolute_expiry(), Some(past_expiry));
 		assert_eq!(offer.as_tlv_stream().absolute_expiry, Some(past_expiry.as_secs()));
 	}
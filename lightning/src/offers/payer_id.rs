@@ -0,0 +1,302 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Rotation of payer ids used when requesting invoices for offers.
+//!
+//! Using the same [`InvoiceRequest::payer_id`] for all purchases allows merchants to link them,
+//! while deriving a fresh payer id for each purchase via
+//! [`Offer::request_invoice_deriving_payer_id`] leaves no way to later prove having made an
+//! earlier payment. A [`PayerIdStore`] sits in between: it derives payer ids per merchant from
+//! nonces it keeps track of, rotating them according to a [`PayerIdRotation`], such that the keys
+//! for any payer id used in the past can be re-derived, e.g., to sign a proof-of-payer.
+//!
+//! The store only holds a random nonce and a count of payer ids per merchant, from which the
+//! nonces for all payer ids used for the merchant are derived, such that its size doesn't grow
+//! with the number of rotations. The nonces are useless without the [`ExpandedKey`] the payer ids
+//! are derived from. The store must be persisted by the user after each change, e.g., via its
+//! [`Writeable`] implementation.
+//!
+//! [`InvoiceRequest::payer_id`]: crate::offers::invoice_request::InvoiceRequest::payer_id
+//! [`Offer::request_invoice_deriving_payer_id`]: crate::offers::offer::Offer::request_invoice_deriving_payer_id
+//! [`Writeable`]: crate::util::ser::Writeable
+
+use bitcoin::hashes::{Hash, HashEngine};
+use bitcoin::hashes::sha256::Hash as Sha256;
+use bitcoin::secp256k1::{self, KeyPair, PublicKey, Secp256k1};
+use core::ops::Deref;
+use crate::ln::inbound_payment::{ExpandedKey, Nonce};
+use crate::offers::invoice_request::{ExplicitPayerId, InvoiceRequestBuilder};
+use crate::offers::offer::Offer;
+use crate::offers::parse::SemanticError;
+use crate::offers::signer;
+use crate::sign::EntropySource;
+
+use crate::prelude::*;
+
+/// How often a [`PayerIdStore`] rotates the payer id used for a merchant.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PayerIdRotation {
+	/// A new payer id is used for every invoice request, such that no two purchases from the same
+	/// merchant can be linked by it.
+	EveryRequest,
+	/// The same payer id is used for the given number of invoice requests to a merchant before it
+	/// is rotated.
+	AfterRequests(u32),
+	/// The current payer id for the merchant is always reused, e.g., for recurring payments which
+	/// the merchant should be able to link.
+	Never,
+}
+
+/// The payer ids used for a merchant, see [`PayerIdStore`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct MerchantPayerIds {
+	/// The nonce the nonces of each payer id for the merchant are derived from.
+	base_nonce: [u8; Nonce::LENGTH],
+	/// The number of payer ids used for the merchant, the last one being current.
+	count: u32,
+	/// The number of invoice requests the current payer id has been used for.
+	current_uses: u32,
+}
+
+impl_writeable_tlv_based!(MerchantPayerIds, {
+	(0, base_nonce, required),
+	(2, count, required),
+	(4, current_uses, required),
+});
+
+impl MerchantPayerIds {
+	/// Returns the nonce the payer id with the given index was derived from.
+	fn nonce(&self, index: u32) -> Nonce {
+		let mut engine = Sha256::engine();
+		engine.input(&self.base_nonce);
+		engine.input(&index.to_be_bytes());
+		let hash = Sha256::from_engine(engine);
+		let mut nonce = [0; Nonce::LENGTH];
+		nonce.copy_from_slice(&hash.into_inner()[..Nonce::LENGTH]);
+		Nonce(nonce)
+	}
+}
+
+/// Tracks the payer ids used for each merchant, rotating them per [`PayerIdRotation`].
+///
+/// Merchants are identified by the [`Offer::signing_pubkey`] of their offers, so offers using
+/// derived signing pubkeys are each treated as their own merchant.
+///
+/// See the [module-level documentation] for details.
+///
+/// [module-level documentation]: crate::offers::payer_id
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PayerIdStore {
+	merchants: HashMap<PublicKey, MerchantPayerIds>,
+}
+
+impl_writeable_tlv_based!(PayerIdStore, {
+	(0, merchants, required),
+});
+
+impl PayerIdStore {
+	/// Creates an empty store.
+	pub fn new() -> Self {
+		Self { merchants: HashMap::new() }
+	}
+
+	/// Returns the keys for the payer id to use for the next invoice request to `merchant`, first
+	/// rotating it if required by `rotation`.
+	///
+	/// The store must be persisted afterwards.
+	pub fn next_payer_keys<ES: Deref, T: secp256k1::Signing>(
+		&mut self, merchant: PublicKey, rotation: PayerIdRotation, expanded_key: &ExpandedKey,
+		entropy_source: ES, secp_ctx: &Secp256k1<T>
+	) -> KeyPair
+	where
+		ES::Target: EntropySource,
+	{
+		let payer_ids = self.merchants.entry(merchant).or_insert_with(|| MerchantPayerIds {
+			base_nonce: Nonce::from_entropy_source(entropy_source).0, count: 0, current_uses: 0,
+		});
+		let rotate = payer_ids.count == 0 || match rotation {
+			PayerIdRotation::EveryRequest => true,
+			PayerIdRotation::AfterRequests(requests) => payer_ids.current_uses >= requests,
+			PayerIdRotation::Never => false,
+		};
+		if rotate && payer_ids.count < u32::max_value() {
+			payer_ids.count += 1;
+			payer_ids.current_uses = 0;
+		}
+		payer_ids.current_uses = payer_ids.current_uses.saturating_add(1);
+
+		let nonce = payer_ids.nonce(payer_ids.count - 1);
+		signer::derive_payer_keys(nonce, expanded_key, secp_ctx)
+	}
+
+	/// Creates an [`InvoiceRequestBuilder`] for `offer` using the payer id given by
+	/// [`Self::next_payer_keys`], along with the keys to sign the resulting invoice request with.
+	///
+	/// The [`InvoiceRequest::metadata`] is derived as with
+	/// [`Offer::request_invoice_deriving_metadata`], such that [`Invoice::verify`] may be used to
+	/// check that a received invoice was requested by us.
+	///
+	/// The store must be persisted afterwards.
+	///
+	/// This is not exported to bindings users as builder patterns don't map outside of move semantics.
+	///
+	/// [`InvoiceRequest::metadata`]: crate::offers::invoice_request::InvoiceRequest::metadata
+	/// [`Invoice::verify`]: crate::offers::invoice::Invoice::verify
+	pub fn request_invoice<'a, ES: Deref, T: secp256k1::Signing>(
+		&mut self, offer: &'a Offer, rotation: PayerIdRotation, expanded_key: &ExpandedKey,
+		entropy_source: ES, secp_ctx: &Secp256k1<T>
+	) -> Result<(InvoiceRequestBuilder<'a, 'a, ExplicitPayerId, secp256k1::SignOnly>, KeyPair), SemanticError>
+	where
+		ES::Target: EntropySource,
+	{
+		let keys = self.next_payer_keys(
+			offer.signing_pubkey(), rotation, expanded_key, &*entropy_source, secp_ctx
+		);
+		let builder = offer.request_invoice_deriving_metadata(
+			keys.public_key(), expanded_key, entropy_source
+		)?;
+		Ok((builder, keys))
+	}
+
+	/// Returns the keys for `payer_id` if it was ever used for `merchant`, e.g., to sign a
+	/// proof-of-payer for an earlier purchase.
+	pub fn payer_keys_for<T: secp256k1::Signing>(
+		&self, merchant: &PublicKey, payer_id: &PublicKey, expanded_key: &ExpandedKey,
+		secp_ctx: &Secp256k1<T>
+	) -> Option<KeyPair> {
+		let payer_ids = self.merchants.get(merchant)?;
+		(0..payer_ids.count)
+			.map(|index| signer::derive_payer_keys(payer_ids.nonce(index), expanded_key, secp_ctx))
+			.find(|keys| keys.public_key() == *payer_id)
+	}
+
+	/// Returns the number of payer ids used for `merchant` so far.
+	pub fn payer_id_count(&self, merchant: &PublicKey) -> usize {
+		self.merchants.get(merchant).map_or(0, |payer_ids| payer_ids.count as usize)
+	}
+
+	/// Forgets all payer ids used for `merchant`, such that they can no longer be proven to be
+	/// ours. Returns whether any payer ids were known for the merchant.
+	pub fn forget_merchant(&mut self, merchant: &PublicKey) -> bool {
+		self.merchants.remove(merchant).is_some()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{PayerIdRotation, PayerIdStore};
+
+	use bitcoin::secp256k1::{KeyPair, Message, Secp256k1};
+	use bitcoin::secp256k1::schnorr::Signature;
+	use core::cell::Cell;
+	use core::convert::Infallible;
+	use crate::io::Cursor;
+	use crate::ln::inbound_payment::ExpandedKey;
+	use crate::offers::offer::OfferBuilder;
+	use crate::offers::test_utils::*;
+	use crate::sign::{EntropySource, KeyMaterial};
+	use crate::util::ser::{Readable, Writeable};
+
+	struct CountingEntropy(Cell<u8>);
+
+	impl EntropySource for CountingEntropy {
+		fn get_secure_random_bytes(&self) -> [u8; 32] {
+			self.0.set(self.0.get() + 1);
+			[self.0.get(); 32]
+		}
+	}
+
+	fn sign_with(keys: KeyPair) -> impl Fn(&Message) -> Result<Signature, Infallible> {
+		move |digest| Ok(Secp256k1::new().sign_schnorr_no_aux_rand(digest, &keys))
+	}
+
+	#[test]
+	fn rotates_payer_ids_per_merchant() {
+		let expanded_key = ExpandedKey::new(&KeyMaterial([42; 32]));
+		let entropy = CountingEntropy(Cell::new(0));
+		let secp_ctx = Secp256k1::new();
+		let mut store = PayerIdStore::new();
+
+		let merchant = pubkey(1);
+		let other_merchant = pubkey(2);
+
+		// Payer ids are reused until rotated, but never shared across merchants.
+		let rotation = PayerIdRotation::AfterRequests(2);
+		let first = store.next_payer_keys(merchant, rotation, &expanded_key, &entropy, &secp_ctx);
+		let second = store.next_payer_keys(merchant, rotation, &expanded_key, &entropy, &secp_ctx);
+		let third = store.next_payer_keys(merchant, rotation, &expanded_key, &entropy, &secp_ctx);
+		assert_eq!(first.public_key(), second.public_key());
+		assert_ne!(first.public_key(), third.public_key());
+
+		let other = store.next_payer_keys(other_merchant, rotation, &expanded_key, &entropy, &secp_ctx);
+		assert_ne!(other.public_key(), first.public_key());
+		assert_ne!(other.public_key(), third.public_key());
+
+		let fourth = store.next_payer_keys(merchant, PayerIdRotation::Never, &expanded_key, &entropy, &secp_ctx);
+		assert_eq!(fourth.public_key(), third.public_key());
+		let fifth = store.next_payer_keys(merchant, PayerIdRotation::EveryRequest, &expanded_key, &entropy, &secp_ctx);
+		assert_ne!(fifth.public_key(), third.public_key());
+		assert_eq!(store.payer_id_count(&merchant), 3);
+
+		// All payer ids used in the past can be proven to be ours after reloading the store.
+		let store: PayerIdStore = Readable::read(&mut Cursor::new(store.encode())).unwrap();
+		for keys in [first, third, fifth].iter() {
+			assert_eq!(
+				store.payer_keys_for(&merchant, &keys.public_key(), &expanded_key, &secp_ctx)
+					.map(|keys| keys.secret_key()),
+				Some(keys.secret_key())
+			);
+		}
+		assert_eq!(store.payer_keys_for(&merchant, &other.public_key(), &expanded_key, &secp_ctx), None);
+		assert_eq!(store.payer_keys_for(&merchant, &payer_pubkey(), &expanded_key, &secp_ctx), None);
+
+		// A different base key derives different payer ids.
+		let other_key = ExpandedKey::new(&KeyMaterial([43; 32]));
+		assert_eq!(store.payer_keys_for(&merchant, &first.public_key(), &other_key, &secp_ctx), None);
+
+		let mut store = store;
+		assert!(store.forget_merchant(&merchant));
+		assert_eq!(store.payer_keys_for(&merchant, &first.public_key(), &expanded_key, &secp_ctx), None);
+		assert!(!store.forget_merchant(&merchant));
+	}
+
+	#[test]
+	fn requests_invoice_with_rotated_payer_id() {
+		let expanded_key = ExpandedKey::new(&KeyMaterial([42; 32]));
+		let entropy = CountingEntropy(Cell::new(0));
+		let secp_ctx = Secp256k1::new();
+		let mut store = PayerIdStore::new();
+
+		let offer = OfferBuilder::new("foo".into(), recipient_pubkey())
+			.amount_msats(1000)
+			.build().unwrap();
+		let (builder, keys) = store.request_invoice(
+			&offer, PayerIdRotation::EveryRequest, &expanded_key, &entropy, &secp_ctx
+		).unwrap();
+		let invoice_request = builder.build().unwrap().sign(sign_with(keys)).unwrap();
+		assert_eq!(invoice_request.payer_id(), keys.public_key());
+
+		let invoice = invoice_request.respond_with_no_std(payment_paths(), payment_hash(), now())
+			.unwrap()
+			.build().unwrap()
+			.sign(recipient_sign).unwrap();
+		assert!(invoice.verify(&expanded_key, &secp_ctx));
+
+		let (builder, next_keys) = store.request_invoice(
+			&offer, PayerIdRotation::EveryRequest, &expanded_key, &entropy, &secp_ctx
+		).unwrap();
+		let next_invoice_request = builder.build().unwrap().sign(sign_with(next_keys)).unwrap();
+		assert_ne!(next_invoice_request.payer_id(), invoice_request.payer_id());
+		assert_eq!(
+			store.payer_keys_for(&offer.signing_pubkey(), &invoice_request.payer_id(), &expanded_key, &secp_ctx)
+				.map(|keys| keys.secret_key()),
+			Some(keys.secret_key())
+		);
+	}
+}
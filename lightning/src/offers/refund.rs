@@ -91,6 +91,7 @@ use crate::offers::offer::{OfferTlvStream, OfferTlvStreamRef};
 use crate::offers::parse::{Bech32Encode, ParseError, ParsedMessage, SemanticError};
 use crate::offers::payer::{PayerContents, PayerTlvStream, PayerTlvStreamRef};
 use crate::offers::signer::{Metadata, MetadataMaterial, self};
+use crate::util::clock::TimeProvider;
 use crate::util::ser::{SeekReadable, WithoutLength, Writeable, Writer};
 use crate::util::string::PrintableString;
 
@@ -281,7 +282,6 @@ impl<'a, T: secp256k1::Signing> RefundBuilder<'a, T> {
 /// [`Invoice`]: crate::offers::invoice::Invoice
 /// [`Offer`]: crate::offers::offer::Offer
 #[derive(Clone, Debug)]
-#[cfg_attr(test, derive(PartialEq))]
 pub struct Refund {
 	pub(super) bytes: Vec<u8>,
 	pub(super) contents: RefundContents,
@@ -328,6 +328,11 @@ impl Refund {
 		self.contents.is_expired()
 	}
 
+	/// Whether the refund has expired, according to the given [`TimeProvider`].
+	pub fn is_expired_with_time<T: Deref>(&self, time_provider: T) -> bool where T::Target: TimeProvider {
+		self.contents.is_expired_no_std(time_provider.duration_since_epoch())
+	}
+
 	/// The issuer of the refund, possibly beginning with `user@domain` or `domain`. Intended to be
 	/// displayed to the user but with the caveat that it has not been verified in any way.
 	pub fn issuer(&self) -> Option<PrintableString> {
@@ -505,11 +510,15 @@ impl RefundContents {
 
 	#[cfg(feature = "std")]
 	pub(super) fn is_expired(&self) -> bool {
+		match SystemTime::UNIX_EPOCH.elapsed() {
+			Ok(elapsed) => self.is_expired_no_std(elapsed),
+			Err(_) => false,
+		}
+	}
+
+	pub(super) fn is_expired_no_std(&self, duration_since_epoch: Duration) -> bool {
 		match self.absolute_expiry {
-			Some(seconds_from_epoch) => match SystemTime::UNIX_EPOCH.elapsed() {
-				Ok(elapsed) => elapsed > seconds_from_epoch,
-				Err(_) => false,
-			},
+			Some(seconds_from_epoch) => duration_since_epoch > seconds_from_epoch,
 			None => false,
 		}
 	}
@@ -571,6 +580,14 @@ impl RefundContents {
 	}
 }
 
+impl PartialEq for Refund {
+	fn eq(&self, other: &Self) -> bool {
+		self.bytes.eq(&other.bytes)
+	}
+}
+
+impl Eq for Refund {}
+
 impl Writeable for Refund {
 	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
 		WithoutLength(&self.bytes).write(writer)
@@ -923,6 +940,7 @@ mod tests {
 		let (_, tlv_stream, _) = refund.as_tlv_stream();
 		#[cfg(feature = "std")]
 		assert!(!refund.is_expired());
+		assert!(!refund.is_expired_with_time(&FixedTime(Duration::from_secs(1))));
 		assert_eq!(refund.absolute_expiry(), Some(future_expiry));
 		assert_eq!(tlv_stream.absolute_expiry, Some(future_expiry.as_secs()));
 
@@ -934,6 +952,7 @@ mod tests {
 		let (_, tlv_stream, _) = refund.as_tlv_stream();
 		#[cfg(feature = "std")]
 		assert!(refund.is_expired());
+		assert!(refund.is_expired_with_time(&FixedTime(Duration::from_secs(1))));
 		assert_eq!(refund.absolute_expiry(), Some(past_expiry));
 		assert_eq!(tlv_stream.absolute_expiry, Some(past_expiry.as_secs()));
 	}
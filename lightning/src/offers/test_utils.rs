@@ -18,6 +18,7 @@ use crate::sign::EntropySource;
 use crate::ln::PaymentHash;
 use crate::ln::features::BlindedHopFeatures;
 use crate::offers::invoice::BlindedPayInfo;
+use crate::util::clock::TimeProvider;
 
 pub(super) fn payer_keys() -> KeyPair {
 	let secp_ctx = Secp256k1::new();
@@ -110,6 +111,14 @@ pub(super) fn now() -> Duration {
 		.expect("SystemTime::now() should come after SystemTime::UNIX_EPOCH")
 }
 
+pub(super) struct FixedTime(pub(super) Duration);
+
+impl TimeProvider for FixedTime {
+	fn duration_since_epoch(&self) -> Duration {
+		self.0
+	}
+}
+
 pub(super) struct FixedEntropy;
 
 impl EntropySource for FixedEntropy {
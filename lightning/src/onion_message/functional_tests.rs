@@ -10,17 +10,23 @@
 //! Onion message testing and test utilities live here.
 
 use crate::blinded_path::BlindedPath;
-use crate::sign::{NodeSigner, Recipient};
-use crate::ln::features::InitFeatures;
-use crate::ln::msgs::{self, DecodeError, OnionMessageHandler};
-use super::{CustomOnionMessageContents, CustomOnionMessageHandler, Destination, MessageRouter, OffersMessage, OffersMessageHandler, OnionMessageContents, OnionMessagePath, OnionMessenger, SendError};
-use crate::util::ser::{Writeable, Writer};
+use crate::sign::{DeterministicEntropySource, NodeSigner, Recipient};
+use crate::ln::PaymentHash;
+use crate::ln::features::{BlindedHopFeatures, ChannelFeatures, InitFeatures};
+use crate::ln::msgs::{self, DecodeError, NetAddress, OnionMessageHandler};
+use crate::offers::invoice::BlindedPayInfo;
+use crate::offers::offer::OfferBuilder;
+use crate::routing::gossip::{NetworkGraph, PeerAddressResolver};
+use super::{CustomOnionMessageContents, CustomOnionMessageHandler, DefaultMessageRouter, Destination, MessageRouter, OffersMessage, OffersMessageHandler, OnionMessageContents, OnionMessagePath, OnionMessagePathCache, OnionMessageStats, OnionMessenger, REPLY_PATH_EXPIRY_TICKS, SendError};
+use crate::util::ser::{Readable, Writeable, Writer};
 use crate::util::test_utils;
 
 use bitcoin::network::constants::Network;
-use bitcoin::secp256k1::{PublicKey, Secp256k1};
+use bitcoin::secp256k1::{KeyPair, PublicKey, Secp256k1, SecretKey};
 
 use crate::io;
+use core::convert::Infallible;
+use core::time::Duration;
 use crate::io_extras::read_to_end;
 use crate::sync::{Arc, Mutex};
 
@@ -221,6 +227,187 @@ fn two_unblinded_hops() {
 	pass_along_path(&nodes);
 }
 
+#[test]
+fn default_message_router() {
+	struct TestResolver;
+	impl PeerAddressResolver for TestResolver {
+		fn resolve_addresses(&self, _node_id: &PublicKey) -> Vec<NetAddress> {
+			vec![NetAddress::IPv4 { addr: [127, 0, 0, 1], port: 9735 }]
+		}
+	}
+
+	let nodes = create_nodes(3);
+	let logger = test_utils::TestLogger::new();
+	let network_graph = NetworkGraph::new(Network::Testnet, &logger);
+	let router = DefaultMessageRouter::new(&network_graph, &TestResolver);
+	let (sender, peer, destination) = (nodes[0].get_node_pk(), nodes[1].get_node_pk(), nodes[2].get_node_pk());
+
+	// Peers are routed to directly.
+	let path = router.find_path(sender, vec![peer], Destination::Node(peer)).unwrap();
+	assert!(path.intermediate_nodes.is_empty());
+
+	// Other nodes are only reachable through a peer they have a channel with, otherwise we have to
+	// connect to them first.
+	assert!(router.find_path(sender, vec![peer], Destination::Node(destination)).is_err());
+	assert_eq!(router.first_node_addresses(&Destination::Node(destination)),
+		vec![NetAddress::IPv4 { addr: [127, 0, 0, 1], port: 9735 }]);
+
+	network_graph.add_channel_from_partial_announcement(42, 0, ChannelFeatures::empty(), peer, destination).unwrap();
+	let path = router.find_path(sender, vec![peer], Destination::Node(destination)).unwrap();
+	assert_eq!(path.intermediate_nodes, vec![peer]);
+
+	let test_msg = OnionMessageContents::Custom(TestCustomMessage::Response);
+	nodes[0].messenger.send_onion_message(path, test_msg, None).unwrap();
+	nodes[2].custom_message_handler.expect_message(TestCustomMessage::Response);
+	pass_along_path(&nodes);
+}
+
+#[test]
+fn onion_message_stats() {
+	let nodes = create_nodes(3);
+	let test_msg = OnionMessageContents::Custom(TestCustomMessage::Response);
+
+	let path = OnionMessagePath {
+		intermediate_nodes: vec![nodes[1].get_node_pk()],
+		destination: Destination::Node(nodes[2].get_node_pk()),
+	};
+	nodes[0].messenger.send_onion_message(path, test_msg, None).unwrap();
+	nodes[2].custom_message_handler.expect_message(TestCustomMessage::Response);
+	pass_along_path(&nodes);
+
+	let sender_stats = nodes[0].messenger.onion_message_stats();
+	assert_eq!(sender_stats, OnionMessageStats { sent: 1, ..Default::default() });
+	assert_eq!(nodes[0].messenger.peer_onion_message_stats(&nodes[1].get_node_pk()), Some(sender_stats));
+
+	let forwarder_stats = nodes[1].messenger.onion_message_stats();
+	assert_eq!(forwarder_stats, OnionMessageStats { received: 1, forwarded: 1, ..Default::default() });
+	assert_eq!(nodes[1].messenger.peer_onion_message_stats(&nodes[0].get_node_pk()),
+		Some(OnionMessageStats { received: 1, ..Default::default() }));
+	assert_eq!(nodes[1].messenger.peer_onion_message_stats(&nodes[2].get_node_pk()),
+		Some(OnionMessageStats { forwarded: 1, ..Default::default() }));
+
+	assert_eq!(nodes[2].messenger.onion_message_stats(), OnionMessageStats { received: 1, ..Default::default() });
+	assert_eq!(nodes[2].messenger.peer_onion_message_stats(&nodes[0].get_node_pk()), None);
+
+	// Sending to a peer we aren't connected to is counted as a drop.
+	let path = OnionMessagePath {
+		intermediate_nodes: vec![],
+		destination: Destination::Node(nodes[2].get_node_pk()),
+	};
+	let test_msg = OnionMessageContents::Custom(TestCustomMessage::Response);
+	let err = nodes[0].messenger.send_onion_message(path, test_msg, None).unwrap_err();
+	assert_eq!(err, SendError::InvalidFirstHop);
+	assert_eq!(nodes[0].messenger.onion_message_stats(),
+		OnionMessageStats { sent: 1, dropped_unknown_peer: 1, ..Default::default() });
+
+	// Per-peer counters are forgotten on disconnection, while the totals are kept.
+	nodes[1].messenger.peer_disconnected(&nodes[2].get_node_pk());
+	assert_eq!(nodes[1].messenger.peer_onion_message_stats(&nodes[2].get_node_pk()), None);
+	assert_eq!(nodes[1].messenger.onion_message_stats(), forwarder_stats);
+}
+
+#[test]
+fn caches_onion_message_paths() {
+	let nodes = create_nodes(3);
+	let secp_ctx = Secp256k1::new();
+
+	// Our router always returns direct paths, thus any intermediate nodes come from the cache.
+	let destination = Destination::Node(nodes[2].get_node_pk());
+	assert!(nodes[0].messenger.find_path(destination.clone()).unwrap().intermediate_nodes.is_empty());
+
+	let path = OnionMessagePath {
+		intermediate_nodes: vec![nodes[1].get_node_pk()],
+		destination: destination.clone(),
+	};
+	let test_msg = OnionMessageContents::Custom(TestCustomMessage::Response);
+	nodes[0].messenger.send_onion_message(path, test_msg, None).unwrap();
+	nodes[2].custom_message_handler.expect_message(TestCustomMessage::Response);
+	pass_along_path(&nodes);
+	assert_eq!(nodes[0].messenger.find_path(destination.clone()).unwrap().intermediate_nodes,
+		vec![nodes[1].get_node_pk()]);
+
+	// The route is also reused for blinded paths with the node as their introduction node.
+	let blinded_path = BlindedPath::new_for_message(&[nodes[2].get_node_pk(), nodes[1].get_node_pk()], &*nodes[1].keys_manager, &secp_ctx).unwrap();
+	assert_eq!(nodes[0].messenger.find_path(Destination::BlindedPath(blinded_path)).unwrap().intermediate_nodes,
+		vec![nodes[1].get_node_pk()]);
+
+	let reply_path = BlindedPath::new_for_message(&[nodes[1].get_node_pk(), nodes[0].get_node_pk()], &*nodes[0].keys_manager, &secp_ctx).unwrap();
+	assert_eq!(nodes[0].messenger.cached_reply_path(&nodes[2].get_node_pk()), None);
+	nodes[0].messenger.cache_reply_path(nodes[2].get_node_pk(), reply_path.clone());
+	assert_eq!(nodes[0].messenger.cached_reply_path(&nodes[2].get_node_pk()), Some(reply_path.clone()));
+
+	// The cache survives a round-trip through serialization.
+	let path_cache = nodes[0].messenger.path_cache();
+	let read_cache: OnionMessagePathCache = Readable::read(&mut io::Cursor::new(path_cache.encode())).unwrap();
+	assert_eq!(read_cache, path_cache);
+
+	// Once the first hop disconnects, we fall back to our router, while keeping the reply path.
+	nodes[0].messenger.peer_disconnected(&nodes[1].get_node_pk());
+	assert!(nodes[0].messenger.find_path(destination).unwrap().intermediate_nodes.is_empty());
+	assert_eq!(nodes[0].messenger.cached_reply_path(&nodes[2].get_node_pk()), Some(reply_path));
+}
+
+#[test]
+fn caches_invoice_request_reply_paths() {
+	let nodes = create_nodes(2);
+	let secp_ctx = Secp256k1::new();
+	let (payer, recipient) = (nodes[0].get_node_pk(), nodes[1].get_node_pk());
+
+	let payer_keys = KeyPair::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[42; 32]).unwrap());
+	let recipient_keys = KeyPair::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[43; 32]).unwrap());
+	let offer = OfferBuilder::new("foo".into(), recipient_keys.public_key())
+		.amount_msats(1000)
+		.build().unwrap();
+	let invoice_request = |metadata| offer.request_invoice(vec![metadata; 32], payer_keys.public_key()).unwrap()
+		.build().unwrap()
+		.sign::<_, Infallible>(|digest| Ok(secp_ctx.sign_schnorr_no_aux_rand(digest, &payer_keys)))
+		.unwrap();
+	let send_invoice_request = |invoice_request, reply_path| {
+		let path = OnionMessagePath { intermediate_nodes: vec![], destination: Destination::Node(recipient) };
+		let message = OnionMessageContents::<TestCustomMessage>::Offers(OffersMessage::InvoiceRequest(invoice_request));
+		nodes[0].messenger.send_onion_message(path, message, reply_path).unwrap();
+		pass_along_path(&nodes);
+	};
+
+	// Once an invoice is received for our request, its reply path is cached.
+	let reply_path = BlindedPath::new_for_message(&[recipient, payer], &*nodes[0].keys_manager, &secp_ctx).unwrap();
+	let first_request = invoice_request(1);
+	send_invoice_request(first_request.clone(), Some(reply_path.clone()));
+	assert_eq!(nodes[0].messenger.cached_reply_path(&recipient), None);
+
+	let payinfo = BlindedPayInfo {
+		fee_base_msat: 1, fee_proportional_millionths: 1_000, cltv_expiry_delta: 42,
+		htlc_minimum_msat: 100, htlc_maximum_msat: 1_000_000_000_000, features: BlindedHopFeatures::empty(),
+	};
+	let invoice = first_request
+		.respond_with_no_std(vec![(reply_path.clone(), payinfo)], PaymentHash([42; 32]), Duration::from_secs(1_000_000)).unwrap()
+		.build().unwrap()
+		.sign::<_, Infallible>(|digest| Ok(secp_ctx.sign_schnorr_no_aux_rand(digest, &recipient_keys)))
+		.unwrap();
+	let path = OnionMessagePath { intermediate_nodes: vec![], destination: Destination::BlindedPath(reply_path.clone()) };
+	let message = OnionMessageContents::<TestCustomMessage>::Offers(OffersMessage::Invoice(invoice));
+	nodes[1].messenger.send_onion_message(path, message, None).unwrap();
+	let onion_msg = nodes[1].messenger.release_pending_msgs().get(&payer).unwrap()[0].clone();
+	nodes[0].messenger.handle_onion_message(&recipient, &onion_msg);
+	assert_eq!(nodes[0].messenger.cached_reply_path(&recipient), Some(reply_path.clone()));
+
+	// The cached reply path is used for subsequent requests, and forgotten if they time out.
+	send_invoice_request(invoice_request(2), None);
+	nodes[0].messenger.timer_tick_occurred();
+	assert_eq!(nodes[0].messenger.cached_reply_path(&recipient), Some(reply_path.clone()));
+	nodes[0].messenger.timer_tick_occurred();
+	assert_eq!(nodes[0].messenger.cached_reply_path(&recipient), None);
+
+	// Cached reply paths expire.
+	nodes[0].messenger.cache_reply_path(recipient, reply_path.clone());
+	for _ in 0..REPLY_PATH_EXPIRY_TICKS - 1 {
+		nodes[0].messenger.timer_tick_occurred();
+	}
+	assert_eq!(nodes[0].messenger.cached_reply_path(&recipient), Some(reply_path));
+	nodes[0].messenger.timer_tick_occurred();
+	assert_eq!(nodes[0].messenger.cached_reply_path(&recipient), None);
+}
+
 #[test]
 fn two_unblinded_two_blinded() {
 	let nodes = create_nodes(5);
@@ -406,6 +593,59 @@ fn peer_buffer_full() {
 	}
 	let err = nodes[0].messenger.send_onion_message(path, OnionMessageContents::Custom(test_msg), None).unwrap_err();
 	assert_eq!(err, SendError::BufferFull);
+	assert_eq!(nodes[0].messenger.onion_message_stats(),
+		OnionMessageStats { sent: 188, dropped_buffer_full: 1, ..Default::default() });
+}
+
+#[test]
+fn async_node_signer_ecdh() {
+	// Tests that onion messages received while our NodeSigner is still deriving their shared secrets
+	// are handled once it completed, and that only a bounded number per peer are queued.
+	let nodes = create_nodes(2);
+	let peer_node_id = nodes[0].get_node_pk();
+	let path = OnionMessagePath {
+		intermediate_nodes: vec![],
+		destination: Destination::Node(nodes[1].get_node_pk()),
+	};
+	for _ in 0..65 {
+		let test_msg = OnionMessageContents::Custom(TestCustomMessage::Response);
+		nodes[0].messenger.send_onion_message(path.clone(), test_msg, None).unwrap();
+	}
+	let onion_msgs = nodes[0].messenger.release_pending_msgs().remove(&nodes[1].get_node_pk()).unwrap();
+	assert_eq!(onion_msgs.len(), 65);
+
+	*nodes[1].keys_manager.ecdh_in_progress.lock().unwrap() = true;
+	for onion_msg in onion_msgs.iter() {
+		nodes[1].messenger.handle_onion_message(&peer_node_id, onion_msg);
+	}
+	// Messages waiting on our signer are only counted as received once handled, while those beyond
+	// the per-peer limit are counted as dropped.
+	assert_eq!(nodes[1].messenger.onion_message_stats(),
+		OnionMessageStats { dropped_awaiting_node_signer: 1, ..Default::default() });
+
+	// The queued messages are retried, but remain queued while our signer is still in progress.
+	nodes[1].messenger.node_signer_unblocked();
+	assert_eq!(nodes[1].messenger.onion_message_stats(),
+		OnionMessageStats { dropped_awaiting_node_signer: 1, ..Default::default() });
+
+	*nodes[1].keys_manager.ecdh_in_progress.lock().unwrap() = false;
+	for _ in 0..64 {
+		nodes[1].custom_message_handler.expect_message(TestCustomMessage::Response);
+	}
+	nodes[1].messenger.node_signer_unblocked();
+	assert_eq!(nodes[1].messenger.onion_message_stats(),
+		OnionMessageStats { received: 64, dropped_awaiting_node_signer: 1, ..Default::default() });
+	assert_eq!(nodes[1].messenger.peer_onion_message_stats(&peer_node_id),
+		Some(OnionMessageStats { received: 64, dropped_awaiting_node_signer: 1, ..Default::default() }));
+
+	// Messages still waiting on our signer when their peer disconnects are counted as dropped.
+	*nodes[1].keys_manager.ecdh_in_progress.lock().unwrap() = true;
+	nodes[1].messenger.handle_onion_message(&peer_node_id, &onion_msgs[0]);
+	nodes[1].messenger.peer_disconnected(&peer_node_id);
+	*nodes[1].keys_manager.ecdh_in_progress.lock().unwrap() = false;
+	nodes[1].messenger.node_signer_unblocked();
+	assert_eq!(nodes[1].messenger.onion_message_stats(),
+		OnionMessageStats { received: 65, dropped_awaiting_node_signer: 2, ..Default::default() });
 }
 
 #[test]
@@ -429,3 +669,37 @@ fn many_hops() {
 	nodes[num_nodes-1].custom_message_handler.expect_message(TestCustomMessage::Response);
 	pass_along_path(&nodes);
 }
+
+#[test]
+fn deterministic_with_deterministic_entropy_source() {
+	// Given the same seeds and sequence of calls, messengers using a `DeterministicEntropySource`
+	// find the same paths and generate byte-for-byte identical onion messages.
+	let nodes = create_nodes(3);
+	let send_deterministically = |entropy_seed: [u8; 32]| {
+		let keys_manager = Arc::new(test_utils::TestKeysInterface::new(&[0; 32], Network::Testnet));
+		let messenger = OnionMessenger::new(
+			Arc::new(DeterministicEntropySource::new(entropy_seed)), keys_manager,
+			Arc::new(test_utils::TestLogger::new()), Arc::new(TestMessageRouter {}),
+			Arc::new(TestOffersMessageHandler {}), Arc::new(TestCustomMessageHandler::new())
+		);
+		let mut features = InitFeatures::empty();
+		features.set_onion_messages_optional();
+		let init_msg = msgs::Init { features, networks: None, remote_network_address: None };
+		for node in nodes.iter().skip(1) {
+			messenger.peer_connected(&node.get_node_pk(), &init_msg, true).unwrap();
+		}
+
+		let destination = Destination::Node(nodes[2].get_node_pk());
+		let path = messenger.find_path(destination).unwrap();
+		let test_msg = OnionMessageContents::Custom(TestCustomMessage::Response);
+		messenger.send_onion_message(path, test_msg, None).unwrap();
+		let mut msgs = messenger.release_pending_msgs();
+		let onion_msgs = msgs.remove(&nodes[2].get_node_pk()).unwrap();
+		assert_eq!(onion_msgs.len(), 1);
+		onion_msgs[0].encode()
+	};
+
+	let sent_msg = send_deterministically([42; 32]);
+	assert_eq!(send_deterministically([42; 32]), sent_msg);
+	assert_ne!(send_deterministically([43; 32]), sent_msg);
+}
@@ -16,23 +16,53 @@ use bitcoin::hashes::sha256::Hash as Sha256;
 use bitcoin::secp256k1::{self, PublicKey, Scalar, Secp256k1, SecretKey};
 
 use crate::blinded_path::{BlindedPath, ForwardTlvs, ReceiveTlvs, utils};
-use crate::sign::{EntropySource, KeysManager, NodeSigner, Recipient};
+use crate::sign::{EntropySource, KeysManager, NodeSigner, NodeSignerStatus, Recipient};
 use crate::events::OnionMessageProvider;
 use crate::ln::features::{InitFeatures, NodeFeatures};
-use crate::ln::msgs::{self, OnionMessageHandler};
+use crate::ln::msgs::{self, NetAddress, OnionMessageHandler};
 use crate::ln::onion_utils;
 use crate::ln::peer_handler::IgnoringMessageHandler;
+use crate::offers::invoice::Invoice;
+use crate::offers::invoice_request::InvoiceRequest;
 pub use super::packet::{CustomOnionMessageContents, OnionMessageContents};
-use super::offers::OffersMessageHandler;
+use super::offers::{OffersMessage, OffersMessageHandler};
+use super::path_cache::OnionMessagePathCache;
+use crate::routing::gossip::{NetworkGraph, NodeId, PeerAddressResolver};
 use super::packet::{BIG_PACKET_HOP_DATA_LEN, ForwardControlTlvs, Packet, Payload, ReceiveControlTlvs, SMALL_PACKET_HOP_DATA_LEN};
+use crate::util::config::ResourceLimitsConfig;
 use crate::util::logger::Logger;
 use crate::util::ser::Writeable;
 
 use core::ops::Deref;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use crate::io;
 use crate::sync::{Arc, Mutex};
 use crate::prelude::*;
 
+/// The maximum number of onion messages received from a single peer we queue while our
+/// [`NodeSigner`] is still deriving the shared secrets they require. Further ones are dropped and
+/// counted in [`OnionMessageStats::dropped_awaiting_node_signer`].
+const MAX_MESSAGES_AWAITING_NODE_SIGNER_PER_PEER: usize = 64;
+
+/// The maximum number of [`InvoiceRequest`]s we track while awaiting an [`Invoice`] in response,
+/// beyond which the oldest one is no longer tracked.
+const MAX_INVOICE_REQUESTS_AWAITING_INVOICE: usize = 64;
+
+/// The number of calls to [`OnionMessenger::timer_tick_occurred`] after which we give up on
+/// receiving an [`Invoice`] in response to an [`InvoiceRequest`].
+const INVOICE_REQUEST_TIMEOUT_TICKS: u8 = 2;
+
+/// An [`InvoiceRequest`] we sent, awaiting an [`Invoice`] over its reply path.
+struct InvoiceRequestAwaitingInvoice {
+	invoice_request: InvoiceRequest,
+	/// The destination node, or the introduction node of the destination blinded path.
+	node_id: PublicKey,
+	reply_path: BlindedPath,
+	/// Whether the reply path was taken from our [`OnionMessagePathCache`].
+	cached_reply_path: bool,
+	ticks: u8,
+}
+
 /// A sender, receiver and forwarder of onion messages. In upcoming releases, this object will be
 /// used to retrieve invoices and fulfill invoice requests from [offers]. Currently, only sending
 /// and receiving custom onion messages is supported.
@@ -134,6 +164,26 @@ where
 	node_signer: NS,
 	logger: L,
 	pending_messages: Mutex<HashMap<PublicKey, VecDeque<msgs::OnionMessage>>>,
+	resource_limits: ResourceLimitsConfig,
+	/// The number of onion messages we've refused to buffer as our outbound buffer was full.
+	onion_message_buffer_limit_hits: AtomicUsize,
+	/// Counters of onion messages we've received, forwarded, sent, and dropped, both in total and
+	/// for each connected peer.
+	///
+	/// This is a leaf lock, no other locks may be taken while it is held.
+	onion_message_stats: Mutex<OnionMessageStatsTracker>,
+	/// Routes to and reply paths from nodes we've exchanged onion messages with.
+	///
+	/// This is a leaf lock, no other locks may be taken while it is held.
+	path_cache: Mutex<OnionMessagePathCache>,
+	/// The [`InvoiceRequest`]s we sent, to cache their reply path once an [`Invoice`] is received
+	/// over it.
+	///
+	/// This is a leaf lock, no other locks may be taken while it is held.
+	awaiting_invoice: Mutex<VecDeque<InvoiceRequestAwaitingInvoice>>,
+	/// Onion messages we received while our [`NodeSigner`] was still deriving the shared secrets
+	/// they require, by the peer we received them from.
+	awaiting_node_signer: Mutex<HashMap<PublicKey, VecDeque<msgs::OnionMessage>>>,
 	secp_ctx: Secp256k1<secp256k1::All>,
 	message_router: MR,
 	offers_handler: OMH,
@@ -152,6 +202,63 @@ pub trait MessageRouter {
 	) -> Result<OnionMessagePath, ()>;
 }
 
+/// A [`MessageRouter`] routing to a [`Destination`] whose first node is either a peer or has a
+/// channel with a peer in the [`NetworkGraph`].
+///
+/// If no path is found, [`Self::first_node_addresses`] gives the addresses at which the first node
+/// may be connected to before trying again, including those of unannounced nodes known to the
+/// [`PeerAddressResolver`].
+pub struct DefaultMessageRouter<G: Deref<Target = NetworkGraph<L>>, L: Deref, R: Deref>
+where L::Target: Logger, R::Target: PeerAddressResolver {
+	network_graph: G,
+	peer_address_resolver: R,
+}
+
+impl<G: Deref<Target = NetworkGraph<L>>, L: Deref, R: Deref> DefaultMessageRouter<G, L, R>
+where L::Target: Logger, R::Target: PeerAddressResolver {
+	/// Creates a [`DefaultMessageRouter`] finding paths over the given [`NetworkGraph`] and
+	/// resolving the addresses of unannounced nodes via `peer_address_resolver`.
+	pub fn new(network_graph: G, peer_address_resolver: R) -> Self {
+		Self { network_graph, peer_address_resolver }
+	}
+
+	/// Returns the addresses at which the first node of `destination` may be connected to, see
+	/// [`NetworkGraph::peer_addresses`].
+	pub fn first_node_addresses(&self, destination: &Destination) -> Vec<NetAddress> {
+		self.network_graph.peer_addresses(&destination.first_node(), &self.peer_address_resolver)
+	}
+}
+
+impl<G: Deref<Target = NetworkGraph<L>>, L: Deref, R: Deref> MessageRouter for DefaultMessageRouter<G, L, R>
+where L::Target: Logger, R::Target: PeerAddressResolver {
+	fn find_path(
+		&self, _sender: PublicKey, peers: Vec<PublicKey>, destination: Destination
+	) -> Result<OnionMessagePath, ()> {
+		let first_node = destination.first_node();
+		if peers.contains(&first_node) {
+			return Ok(OnionMessagePath { intermediate_nodes: vec![], destination });
+		}
+
+		let network_graph = self.network_graph.read_only();
+		let first_node_info = network_graph.node(&NodeId::from_pubkey(&first_node)).ok_or(())?;
+		for scid in first_node_info.channels.iter() {
+			let channel = match network_graph.channel(*scid) {
+				Some(channel) => channel,
+				None => continue,
+			};
+			let counterparty = if channel.node_one == NodeId::from_pubkey(&first_node) {
+				channel.node_two
+			} else {
+				channel.node_one
+			};
+			if let Some(peer) = peers.iter().find(|peer| NodeId::from_pubkey(peer) == counterparty) {
+				return Ok(OnionMessagePath { intermediate_nodes: vec![*peer], destination });
+			}
+		}
+		Err(())
+	}
+}
+
 /// A path for sending an [`msgs::OnionMessage`].
 #[derive(Clone)]
 pub struct OnionMessagePath {
@@ -178,6 +285,14 @@ impl Destination {
 			Destination::BlindedPath(BlindedPath { blinded_hops, .. }) => blinded_hops.len(),
 		}
 	}
+
+	/// The node id of the destination node, or the introduction node of the blinded path.
+	fn first_node(&self) -> PublicKey {
+		match self {
+			Destination::Node(node_id) => *node_id,
+			Destination::BlindedPath(BlindedPath { introduction_node_id, .. }) => *introduction_node_id,
+		}
+	}
 }
 
 /// Errors that may occur when [sending an onion message].
@@ -210,6 +325,57 @@ pub enum SendError {
 	BlindedPathAdvanceFailed,
 }
 
+/// Counters of onion messages handled by an [`OnionMessenger`].
+///
+/// Available in aggregate via [`OnionMessenger::onion_message_stats`] and for a single connected
+/// peer via [`OnionMessenger::peer_onion_message_stats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OnionMessageStats {
+	/// The number of onion messages received from peers, whether destined for us or to forward.
+	pub received: u64,
+	/// The number of onion messages forwarded on behalf of others.
+	pub forwarded: u64,
+	/// The number of onion messages we originated, including responses, which were queued for
+	/// sending.
+	pub sent: u64,
+	/// The number of onion messages, sent or forwarded, which were dropped because our outbound
+	/// onion message buffer was full.
+	pub dropped_buffer_full: u64,
+	/// The number of responses to onion messages which were dropped because no path to the reply
+	/// destination could be found.
+	pub dropped_no_path: u64,
+	/// The number of onion messages, sent or forwarded, which were dropped because the next hop was
+	/// not a connected peer supporting onion messages.
+	pub dropped_unknown_peer: u64,
+	/// The number of received onion messages which were dropped because too many from the same
+	/// peer were already waiting on our [`NodeSigner`] to derive the shared secrets they require.
+	///
+	/// [`NodeSigner`]: crate::sign::NodeSigner
+	pub dropped_awaiting_node_signer: u64,
+}
+
+struct OnionMessageStatsTracker {
+	total: OnionMessageStats,
+	per_peer: HashMap<PublicKey, OnionMessageStats>,
+}
+
+impl OnionMessageStatsTracker {
+	fn new() -> Self {
+		Self { total: OnionMessageStats::default(), per_peer: HashMap::new() }
+	}
+
+	/// Applies `update` to the total counters and, if `peer_node_id` is a connected peer, to its
+	/// counters as well.
+	fn record<F: Fn(&mut OnionMessageStats)>(&mut self, peer_node_id: Option<&PublicKey>, update: F) {
+		update(&mut self.total);
+		if let Some(peer_node_id) = peer_node_id {
+			if let Some(peer_stats) = self.per_peer.get_mut(peer_node_id) {
+				update(peer_stats);
+			}
+		}
+	}
+}
+
 /// Handler for custom onion messages. If you are using [`SimpleArcOnionMessenger`],
 /// [`SimpleRefOnionMessenger`], or prefer to ignore inbound custom onion messages,
 /// [`IgnoringMessageHandler`] must be provided to [`OnionMessenger::new`]. Otherwise, a custom
@@ -255,6 +421,12 @@ where
 			entropy_source,
 			node_signer,
 			pending_messages: Mutex::new(HashMap::new()),
+			resource_limits: ResourceLimitsConfig::default(),
+			onion_message_buffer_limit_hits: AtomicUsize::new(0),
+			onion_message_stats: Mutex::new(OnionMessageStatsTracker::new()),
+			path_cache: Mutex::new(OnionMessagePathCache::new()),
+			awaiting_invoice: Mutex::new(VecDeque::new()),
+			awaiting_node_signer: Mutex::new(HashMap::new()),
 			secp_ctx,
 			logger,
 			message_router,
@@ -263,12 +435,143 @@ where
 		}
 	}
 
+	/// Sets the limits on outbound onion message buffering, replacing the defaults.
+	///
+	/// Only [`ResourceLimitsConfig::max_onion_message_buffer_bytes`] and
+	/// [`ResourceLimitsConfig::max_onion_message_buffer_bytes_per_peer`] are used by the
+	/// `OnionMessenger`.
+	pub fn with_resource_limits(mut self, resource_limits: ResourceLimitsConfig) -> Self {
+		self.resource_limits = resource_limits;
+		self
+	}
+
+	/// Sets the [`OnionMessagePathCache`], e.g. as persisted from [`Self::path_cache`] before a
+	/// restart, replacing the empty default.
+	pub fn with_path_cache(mut self, path_cache: OnionMessagePathCache) -> Self {
+		self.path_cache = Mutex::new(path_cache);
+		self
+	}
+
+	/// Gets a copy of the current [`OnionMessagePathCache`], to be persisted and restored via
+	/// [`Self::with_path_cache`].
+	pub fn path_cache(&self) -> OnionMessagePathCache {
+		self.path_cache.lock().unwrap().clone()
+	}
+
+	/// Caches a reply path to us which a response from `node_id` was received over, to be reused
+	/// for subsequent messages to the node, see [`Self::cached_reply_path`].
+	///
+	/// Reply paths of [`InvoiceRequest`]s are cached once an [`Invoice`] is received in response.
+	/// For other messages, as the sender of an onion message is unknown to us, this has to be
+	/// called by the handler which matched the response to its request.
+	pub fn cache_reply_path(&self, node_id: PublicKey, reply_path: BlindedPath) {
+		self.path_cache.lock().unwrap().cache_reply_path(node_id, reply_path);
+	}
+
+	/// Gets the reply path last cached for `node_id` via [`Self::cache_reply_path`], if any.
+	pub fn cached_reply_path(&self, node_id: &PublicKey) -> Option<BlindedPath> {
+		self.path_cache.lock().unwrap().reply_path(node_id)
+	}
+
+	/// Finds a path for sending an onion message to the given [`Destination`], reusing the route
+	/// we last sent an onion message over to the destination node or the introduction node of a
+	/// destination blinded path if its first hop is still connected, and consulting our
+	/// [`MessageRouter`] otherwise.
+	pub fn find_path(&self, destination: Destination) -> Result<OnionMessagePath, ()> {
+		let sender = self.node_signer.get_node_id(Recipient::Node)?;
+		self.find_path_with_cache(sender, destination)
+	}
+
+	fn find_path_with_cache(&self, sender: PublicKey, destination: Destination) -> Result<OnionMessagePath, ()> {
+		let mut peers: Vec<PublicKey> = self.pending_messages.lock().unwrap().keys().copied().collect();
+		// Hand the peers to the router in a consistent order, rather than in that of our hash map,
+		// so that paths are chosen deterministically given a deterministic `EntropySource`.
+		peers.sort_unstable();
+		if let Some(mut route) = self.path_cache.lock().unwrap().route(&destination.first_node()) {
+			if peers.contains(&route[0]) {
+				route.pop();
+				return Ok(OnionMessagePath { intermediate_nodes: route, destination });
+			}
+		}
+		self.message_router.find_path(sender, peers, destination)
+	}
+
+	/// Expires the reply paths in our [`OnionMessagePathCache`], and forgets those which we sent an
+	/// [`InvoiceRequest`] over without receiving an [`Invoice`] in response in time.
+	///
+	/// Should be called roughly once per minute, e.g. alongside
+	/// [`ChannelManager::timer_tick_occurred`].
+	///
+	/// [`ChannelManager::timer_tick_occurred`]: crate::ln::channelmanager::ChannelManager::timer_tick_occurred
+	pub fn timer_tick_occurred(&self) {
+		let mut timed_out_reply_paths = Vec::new();
+		self.awaiting_invoice.lock().unwrap().retain(|awaiting| {
+			if awaiting.ticks + 1 < INVOICE_REQUEST_TIMEOUT_TICKS { return true; }
+			if awaiting.cached_reply_path {
+				timed_out_reply_paths.push((awaiting.node_id, awaiting.reply_path.clone()));
+			}
+			false
+		});
+		for awaiting in self.awaiting_invoice.lock().unwrap().iter_mut() {
+			awaiting.ticks += 1;
+		}
+
+		let mut path_cache = self.path_cache.lock().unwrap();
+		for (node_id, reply_path) in timed_out_reply_paths.iter() {
+			log_trace!(self.logger, "Forgetting reply path for {} as no invoice was received over it", node_id);
+			path_cache.forget_reply_path(node_id, reply_path);
+		}
+		path_cache.timer_tick_occurred();
+	}
+
+	/// Caches the reply path of the [`InvoiceRequest`] the given [`Invoice`] was sent in response
+	/// to, if we sent it.
+	fn invoice_received(&self, invoice: &Invoice) {
+		let awaiting = {
+			let mut awaiting_invoice = self.awaiting_invoice.lock().unwrap();
+			awaiting_invoice.iter()
+				.position(|awaiting| invoice.is_for_invoice_request(&awaiting.invoice_request))
+				.and_then(|idx| awaiting_invoice.remove(idx))
+		};
+		if let Some(InvoiceRequestAwaitingInvoice { node_id, reply_path, .. }) = awaiting {
+			self.path_cache.lock().unwrap().cache_reply_path(node_id, reply_path);
+		}
+	}
+
+	/// Gets the number of onion messages, either sent by us or forwarded on behalf of others, which
+	/// were dropped because our outbound onion message buffer was full.
+	pub fn onion_message_buffer_limit_hits(&self) -> usize {
+		self.onion_message_buffer_limit_hits.load(Ordering::Relaxed)
+	}
+
+	/// Gets the counters of onion messages received, forwarded, sent, and dropped since this
+	/// `OnionMessenger` was constructed.
+	pub fn onion_message_stats(&self) -> OnionMessageStats {
+		self.onion_message_stats.lock().unwrap().total
+	}
+
+	/// Gets the counters of onion messages received from, forwarded or sent to, and dropped on the
+	/// way to the given peer since it last connected, or `None` if the peer is not connected or
+	/// does not support onion messages.
+	pub fn peer_onion_message_stats(&self, peer_node_id: &PublicKey) -> Option<OnionMessageStats> {
+		self.onion_message_stats.lock().unwrap().per_peer.get(peer_node_id).copied()
+	}
+
+	fn record_onion_message_stats<F: Fn(&mut OnionMessageStats)>(
+		&self, peer_node_id: Option<&PublicKey>, update: F
+	) {
+		self.onion_message_stats.lock().unwrap().record(peer_node_id, update);
+	}
+
 	/// Send an onion message with contents `message` to the destination of `path`.
 	///
+	/// If `message` is an [`InvoiceRequest`] and no `reply_path` is given, the reply path the
+	/// destination last sent an [`Invoice`] over is used, if any, see [`OnionMessagePathCache`].
+	///
 	/// See [`OnionMessenger`] for example usage.
 	pub fn send_onion_message<T: CustomOnionMessageContents>(
 		&self, path: OnionMessagePath, message: OnionMessageContents<T>,
-		reply_path: Option<BlindedPath>
+		mut reply_path: Option<BlindedPath>
 	) -> Result<(), SendError> {
 		let OnionMessagePath { intermediate_nodes, mut destination } = path;
 		if let Destination::BlindedPath(BlindedPath { ref blinded_hops, .. }) = destination {
@@ -292,6 +595,27 @@ where
 			}
 		}
 
+		let destination_node_id = destination.first_node();
+		let mut route = intermediate_nodes.clone();
+		route.push(destination_node_id);
+
+		let invoice_request = match &message {
+			OnionMessageContents::Offers(OffersMessage::InvoiceRequest(invoice_request)) =>
+				Some(invoice_request.clone()),
+			_ => None,
+		};
+		let mut cached_reply_path = false;
+		if invoice_request.is_some() && reply_path.is_none() {
+			reply_path = self.path_cache.lock().unwrap().reply_path(&destination_node_id);
+			cached_reply_path = reply_path.is_some();
+		}
+		let sent_reply_path = reply_path.clone();
+		let forget_cached_reply_path = || {
+			if let (true, Some(reply_path)) = (cached_reply_path, &sent_reply_path) {
+				self.path_cache.lock().unwrap().forget_reply_path(&destination_node_id, reply_path);
+			}
+		};
+
 		let blinding_secret_bytes = self.entropy_source.get_secure_random_bytes();
 		let blinding_secret = SecretKey::from_slice(&blinding_secret_bytes[..]).expect("RNG is busted");
 		let (introduction_node_id, blinding_point) = if intermediate_nodes.len() != 0 {
@@ -308,18 +632,53 @@ where
 			.map_err(|e| SendError::Secp256k1(e))?;
 
 		let prng_seed = self.entropy_source.get_secure_random_bytes();
-		let onion_routing_packet = construct_onion_message_packet(
-			packet_payloads, packet_keys, prng_seed).map_err(|()| SendError::TooBigPacket)?;
-
-		let mut pending_per_peer_msgs = self.pending_messages.lock().unwrap();
-		if outbound_buffer_full(&introduction_node_id, &pending_per_peer_msgs) { return Err(SendError::BufferFull) }
-		match pending_per_peer_msgs.entry(introduction_node_id) {
-			hash_map::Entry::Vacant(_) => Err(SendError::InvalidFirstHop),
-			hash_map::Entry::Occupied(mut e) => {
-				e.get_mut().push_back(msgs::OnionMessage { blinding_point, onion_routing_packet });
-				Ok(())
+		let onion_routing_packet = match construct_onion_message_packet(packet_payloads, packet_keys, prng_seed) {
+			Ok(onion_routing_packet) => onion_routing_packet,
+			Err(()) => {
+				forget_cached_reply_path();
+				return Err(SendError::TooBigPacket);
+			},
+		};
+
+		let res = {
+			let mut pending_per_peer_msgs = self.pending_messages.lock().unwrap();
+			if outbound_buffer_full(&introduction_node_id, &pending_per_peer_msgs, &self.resource_limits) {
+				self.onion_message_buffer_limit_hits.fetch_add(1, Ordering::Relaxed);
+				Err(SendError::BufferFull)
+			} else {
+				match pending_per_peer_msgs.entry(introduction_node_id) {
+					hash_map::Entry::Vacant(_) => Err(SendError::InvalidFirstHop),
+					hash_map::Entry::Occupied(mut e) => {
+						e.get_mut().push_back(msgs::OnionMessage { blinding_point, onion_routing_packet });
+						Ok(())
+					}
+				}
 			}
+		};
+		match &res {
+			Ok(()) => {
+				self.record_onion_message_stats(Some(&introduction_node_id), |s| s.sent += 1);
+				self.path_cache.lock().unwrap().cache_route(destination_node_id, route);
+				if let (Some(invoice_request), Some(reply_path)) = (invoice_request, sent_reply_path.clone()) {
+					let mut awaiting_invoice = self.awaiting_invoice.lock().unwrap();
+					if awaiting_invoice.len() >= MAX_INVOICE_REQUESTS_AWAITING_INVOICE {
+						awaiting_invoice.pop_front();
+					}
+					awaiting_invoice.push_back(InvoiceRequestAwaitingInvoice {
+						invoice_request, node_id: destination_node_id, reply_path, cached_reply_path, ticks: 0,
+					});
+				}
+			},
+			Err(SendError::BufferFull) =>
+				self.record_onion_message_stats(Some(&introduction_node_id), |s| s.dropped_buffer_full += 1),
+			Err(SendError::InvalidFirstHop) => {
+				self.record_onion_message_stats(None, |s| s.dropped_unknown_peer += 1);
+				self.path_cache.lock().unwrap().forget_route(&destination_node_id);
+				forget_cached_reply_path();
+			},
+			Err(_) => {},
 		}
+		res
 	}
 
 	fn respond_with_onion_message<T: CustomOnionMessageContents>(
@@ -337,8 +696,6 @@ where
 			}
 		};
 
-		let peers = self.pending_messages.lock().unwrap().keys().copied().collect();
-
 		let destination = match reply_path {
 			Some(reply_path) => Destination::BlindedPath(reply_path),
 			None => {
@@ -350,13 +707,14 @@ where
 			},
 		};
 
-		let path = match self.message_router.find_path(sender, peers, destination) {
+		let path = match self.find_path_with_cache(sender, destination) {
 			Ok(path) => path,
 			Err(()) => {
 				log_trace!(
 					self.logger, "Failed to find path when responding to onion message with \
 					path_id {:02x?}", path_id
 				);
+				self.record_onion_message_stats(None, |s| s.dropped_no_path += 1);
 				return;
 			},
 		};
@@ -372,6 +730,37 @@ where
 		}
 	}
 
+	/// Retries handling the onion messages we received while our [`NodeSigner`] was still deriving
+	/// the shared secrets they require, see [`NodeSignerStatus::InProgress`].
+	///
+	/// Should be called whenever a pending operation of our [`NodeSigner`] completed.
+	pub fn node_signer_unblocked(&self) {
+		let pending_messages = core::mem::take(&mut *self.awaiting_node_signer.lock().unwrap());
+		for (peer_node_id, msgs) in pending_messages {
+			for msg in msgs {
+				self.handle_onion_message(&peer_node_id, &msg);
+			}
+		}
+	}
+
+	/// Queues an onion message from `peer_node_id` until our [`NodeSigner`] derived the shared
+	/// secrets it requires, unless too many from the same peer are already waiting.
+	fn await_node_signer(&self, peer_node_id: &PublicKey, msg: &msgs::OnionMessage) {
+		let mut awaiting_node_signer = self.awaiting_node_signer.lock().unwrap();
+		let peer_msgs = awaiting_node_signer.entry(*peer_node_id).or_insert_with(VecDeque::new);
+		if peer_msgs.len() >= MAX_MESSAGES_AWAITING_NODE_SIGNER_PER_PEER {
+			core::mem::drop(awaiting_node_signer);
+			log_debug!(self.logger, "Dropping onion message from peer {} as too many of its messages are waiting on our NodeSigner", peer_node_id);
+			self.record_onion_message_stats(Some(peer_node_id), |s| s.dropped_awaiting_node_signer += 1);
+			return;
+		}
+		log_trace!(self.logger, "Waiting on our NodeSigner to derive the shared secrets for an onion message from peer {}", peer_node_id);
+		peer_msgs.push_back(msg.clone());
+		core::mem::drop(awaiting_node_signer);
+		// The message is counted as received again once it is retried.
+		self.record_onion_message_stats(Some(peer_node_id), |s| s.received = s.received.saturating_sub(1));
+	}
+
 	#[cfg(test)]
 	pub(super) fn release_pending_msgs(&self) -> HashMap<PublicKey, VecDeque<msgs::OnionMessage>> {
 		let mut pending_msgs = self.pending_messages.lock().unwrap();
@@ -385,9 +774,10 @@ where
 	}
 }
 
-fn outbound_buffer_full(peer_node_id: &PublicKey, buffer: &HashMap<PublicKey, VecDeque<msgs::OnionMessage>>) -> bool {
-	const MAX_TOTAL_BUFFER_SIZE: usize = (1 << 20) * 128;
-	const MAX_PER_PEER_BUFFER_SIZE: usize = (1 << 10) * 256;
+fn outbound_buffer_full(
+	peer_node_id: &PublicKey, buffer: &HashMap<PublicKey, VecDeque<msgs::OnionMessage>>,
+	limits: &ResourceLimitsConfig
+) -> bool {
 	let mut total_buffered_bytes = 0;
 	let mut peer_buffered_bytes = 0;
 	for (pk, peer_buf) in buffer {
@@ -398,8 +788,8 @@ fn outbound_buffer_full(peer_node_id: &PublicKey, buffer: &HashMap<PublicKey, Ve
 			}
 			total_buffered_bytes += om_len;
 
-			if total_buffered_bytes >= MAX_TOTAL_BUFFER_SIZE ||
-				peer_buffered_bytes >= MAX_PER_PEER_BUFFER_SIZE
+			if total_buffered_bytes >= limits.max_onion_message_buffer_bytes ||
+				peer_buffered_bytes >= limits.max_onion_message_buffer_bytes_per_peer
 			{
 				return true
 			}
@@ -421,13 +811,18 @@ where
 	/// Handle an incoming onion message. Currently, if a message was destined for us we will log, but
 	/// soon we'll delegate the onion message to a handler that can generate invoices or send
 	/// payments.
-	fn handle_onion_message(&self, _peer_node_id: &PublicKey, msg: &msgs::OnionMessage) {
-		let control_tlvs_ss = match self.node_signer.ecdh(Recipient::Node, &msg.blinding_point, None) {
-			Ok(ss) => ss,
-			Err(e) =>  {
+	fn handle_onion_message(&self, peer_node_id: &PublicKey, msg: &msgs::OnionMessage) {
+		self.record_onion_message_stats(Some(peer_node_id), |s| s.received += 1);
+		let control_tlvs_ss = match self.node_signer.ecdh_async(Recipient::Node, &msg.blinding_point, None) {
+			NodeSignerStatus::Complete(Ok(ss)) => ss,
+			NodeSignerStatus::Complete(Err(e)) =>  {
 				log_error!(self.logger, "Failed to retrieve node secret: {:?}", e);
 				return
-			}
+			},
+			NodeSignerStatus::InProgress => {
+				self.await_node_signer(peer_node_id, msg);
+				return
+			},
 		};
 		let onion_decode_ss = {
 			let blinding_factor = {
@@ -435,14 +830,18 @@ where
 				hmac.input(control_tlvs_ss.as_ref());
 				Hmac::from_engine(hmac).into_inner()
 			};
-			match self.node_signer.ecdh(Recipient::Node, &msg.onion_routing_packet.public_key,
+			match self.node_signer.ecdh_async(Recipient::Node, &msg.onion_routing_packet.public_key,
 				Some(&Scalar::from_be_bytes(blinding_factor).unwrap()))
 			{
-				Ok(ss) => ss.secret_bytes(),
-				Err(()) => {
+				NodeSignerStatus::Complete(Ok(ss)) => ss.secret_bytes(),
+				NodeSignerStatus::Complete(Err(())) => {
 					log_trace!(self.logger, "Failed to compute onion packet shared secret");
 					return
-				}
+				},
+				NodeSignerStatus::InProgress => {
+					self.await_node_signer(peer_node_id, msg);
+					return
+				},
 			}
 		};
 		match onion_utils::decode_next_untagged_hop(
@@ -458,6 +857,9 @@ where
 
 				let response = match message {
 					OnionMessageContents::Offers(msg) => {
+						if let OffersMessage::Invoice(invoice) = &msg {
+							self.invoice_received(invoice);
+						}
 						self.offers_handler.handle_message(msg)
 							.map(|msg| OnionMessageContents::Offers(msg))
 					},
@@ -516,8 +918,11 @@ where
 				};
 
 				let mut pending_per_peer_msgs = self.pending_messages.lock().unwrap();
-				if outbound_buffer_full(&next_node_id, &pending_per_peer_msgs) {
+				if outbound_buffer_full(&next_node_id, &pending_per_peer_msgs, &self.resource_limits) {
 					log_trace!(self.logger, "Dropping forwarded onion message to peer {:?}: outbound buffer full", next_node_id);
+					self.onion_message_buffer_limit_hits.fetch_add(1, Ordering::Relaxed);
+					core::mem::drop(pending_per_peer_msgs);
+					self.record_onion_message_stats(Some(&next_node_id), |s| s.dropped_buffer_full += 1);
 					return
 				}
 
@@ -527,6 +932,8 @@ where
 				match pending_per_peer_msgs.entry(next_node_id) {
 					hash_map::Entry::Vacant(_) => {
 						log_trace!(self.logger, "Dropping forwarded onion message to disconnected peer {:?}", next_node_id);
+						core::mem::drop(pending_per_peer_msgs);
+						self.record_onion_message_stats(None, |s| s.dropped_unknown_peer += 1);
 						return
 					},
 					hash_map::Entry::Occupied(mut e) => {
@@ -534,6 +941,8 @@ where
 						log_trace!(self.logger, "Forwarding an onion message to peer {}", next_node_id);
 					}
 				};
+				core::mem::drop(pending_per_peer_msgs);
+				self.record_onion_message_stats(Some(&next_node_id), |s| s.forwarded += 1);
 			},
 			Err(e) => {
 				log_trace!(self.logger, "Errored decoding onion message packet: {:?}", e);
@@ -548,6 +957,9 @@ where
 		if init.features.supports_onion_messages() {
 			let mut peers = self.pending_messages.lock().unwrap();
 			peers.insert(their_node_id.clone(), VecDeque::new());
+			core::mem::drop(peers);
+			self.onion_message_stats.lock().unwrap().per_peer
+				.insert(their_node_id.clone(), OnionMessageStats::default());
 		}
 		Ok(())
 	}
@@ -555,6 +967,19 @@ where
 	fn peer_disconnected(&self, their_node_id: &PublicKey) {
 		let mut pending_msgs = self.pending_messages.lock().unwrap();
 		pending_msgs.remove(their_node_id);
+		core::mem::drop(pending_msgs);
+		let awaiting_node_signer = self.awaiting_node_signer.lock().unwrap().remove(their_node_id);
+		if let Some(msgs) = awaiting_node_signer {
+			if !msgs.is_empty() {
+				log_debug!(self.logger, "Dropping {} onion messages from disconnected peer {} which were waiting on our NodeSigner", msgs.len(), their_node_id);
+				let dropped = msgs.len() as u64;
+				self.record_onion_message_stats(None, |s| {
+					s.received += dropped;
+					s.dropped_awaiting_node_signer += dropped;
+				});
+			}
+		}
+		self.onion_message_stats.lock().unwrap().per_peer.remove(their_node_id);
 	}
 
 	fn provided_node_features(&self) -> NodeFeatures {
@@ -587,6 +1012,10 @@ where
 		}
 		None
 	}
+
+	fn has_pending_onion_messages(&self) -> bool {
+		self.pending_messages.lock().unwrap().values().any(|msgs| !msgs.is_empty())
+	}
 }
 
 // TODO: parameterize the below Simple* types with OnionMessenger and handle the messages it
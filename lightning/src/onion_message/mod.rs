@@ -23,10 +23,14 @@
 mod messenger;
 mod offers;
 mod packet;
+mod path_cache;
+mod remote_routing;
 #[cfg(test)]
 mod functional_tests;
 
 // Re-export structs so they can be imported with just the `onion_message::` module prefix.
-pub use self::messenger::{CustomOnionMessageContents, CustomOnionMessageHandler, Destination, MessageRouter, OnionMessageContents, OnionMessagePath, OnionMessenger, SendError, SimpleArcOnionMessenger, SimpleRefOnionMessenger};
+pub use self::messenger::{CustomOnionMessageContents, CustomOnionMessageHandler, DefaultMessageRouter, Destination, MessageRouter, OnionMessageContents, OnionMessagePath, OnionMessageStats, OnionMessenger, SendError, SimpleArcOnionMessenger, SimpleRefOnionMessenger};
 pub use self::offers::{OffersMessage, OffersMessageHandler};
-pub(crate) use self::packet::{ControlTlvs, Packet};
+pub use self::path_cache::{MAX_CACHED_ONION_MESSAGE_NODES, OnionMessagePathCache, REPLY_PATH_EXPIRY_TICKS};
+pub use self::remote_routing::{MAX_REMOTE_ROUTING_MESSAGE_LEN, PaymentPathResult, REMOTE_ROUTE_REQUEST_TIMEOUT_TICKS, RemoteRouteProvider, RemoteRoutingMessage, RemoteRoutingOrCustomMessage, RemoteRoutingServer, RemoteRoutingWithCustomHandler, RouteRequest, RouteResponse, ScorerHint, ScorerHintChannel, ScorerHintsRequest, ScorerHintsResponse};
+pub(crate) use self::packet::{ControlTlvs, Packet, Padding};
@@ -0,0 +1,191 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! A persistable cache of onion message paths to the nodes we frequently exchange onion messages
+//! with, see [`OnionMessagePathCache`].
+
+use bitcoin::secp256k1::PublicKey;
+
+use crate::blinded_path::BlindedPath;
+
+use crate::prelude::*;
+
+/// The maximum number of nodes an [`OnionMessagePathCache`] holds paths for, beyond which the
+/// least recently used node is evicted.
+pub const MAX_CACHED_ONION_MESSAGE_NODES: usize = 256;
+
+/// The number of calls to [`OnionMessenger::timer_tick_occurred`] after which a cached reply path
+/// expires, unless the node responded over it again in the meantime.
+///
+/// [`OnionMessenger::timer_tick_occurred`]: super::OnionMessenger::timer_tick_occurred
+pub const REPLY_PATH_EXPIRY_TICKS: u16 = 60 * 24;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct CachedOnionMessagePaths {
+	/// The hops over which we last sent an onion message to the node, ending with the node itself,
+	/// or empty if we have no route cached.
+	route: Vec<PublicKey>,
+	/// A reply path to us which the node last responded over.
+	reply_path: Option<BlindedPath>,
+	/// The number of timer ticks since the reply path was cached.
+	reply_path_age_ticks: u16,
+	/// The value of [`OnionMessagePathCache::use_counter`] when the entry was last used.
+	last_used: u64,
+}
+
+impl_writeable_tlv_based!(CachedOnionMessagePaths, {
+	(0, route, optional_vec),
+	(2, reply_path, option),
+	(4, last_used, required),
+	(6, reply_path_age_ticks, (default_value, 0)),
+});
+
+/// A cache of onion message routes to and validated reply paths from the nodes we frequently
+/// exchange onion messages with, such as our LSP or popular merchants, keyed by their node id.
+///
+/// Routes are cached by the [`OnionMessenger`] whenever it sends an onion message, and are reused
+/// by [`OnionMessenger::find_path`] and when responding to onion messages as long as their first
+/// hop is still connected, skipping the [`MessageRouter`]. As onion messages are not
+/// acknowledged, a route is cached once a message was handed to its first hop.
+///
+/// Reply paths are cached once a response was received over them, i.e., when an [`Invoice`] is
+/// received for an [`InvoiceRequest`] we sent, or via [`OnionMessenger::cache_reply_path`] for
+/// other messages. They are set on subsequent [`InvoiceRequest`]s sent to the node without a
+/// reply path, and are forgotten if no [`Invoice`] is received in response, if sending over them
+/// fails, or after [`REPLY_PATH_EXPIRY_TICKS`].
+///
+/// The cache should be persisted, e.g. from [`OnionMessenger::path_cache`] on shutdown, and given
+/// to [`OnionMessenger::with_path_cache`] on startup, so that BOLT 12 interactions with the same
+/// counterparties succeed faster after a restart.
+///
+/// [`OnionMessenger`]: super::OnionMessenger
+/// [`OnionMessenger::find_path`]: super::OnionMessenger::find_path
+/// [`OnionMessenger::cache_reply_path`]: super::OnionMessenger::cache_reply_path
+/// [`OnionMessenger::path_cache`]: super::OnionMessenger::path_cache
+/// [`OnionMessenger::with_path_cache`]: super::OnionMessenger::with_path_cache
+/// [`MessageRouter`]: super::MessageRouter
+/// [`Invoice`]: crate::offers::invoice::Invoice
+/// [`InvoiceRequest`]: crate::offers::invoice_request::InvoiceRequest
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OnionMessagePathCache {
+	nodes: HashMap<PublicKey, CachedOnionMessagePaths>,
+	/// Incremented whenever an entry is used, to evict the least recently used node.
+	use_counter: u64,
+}
+
+impl OnionMessagePathCache {
+	/// Creates an empty cache.
+	pub fn new() -> Self {
+		Self { nodes: HashMap::new(), use_counter: 0 }
+	}
+
+	/// Forgets all paths cached for the given node.
+	pub fn forget_node(&mut self, node_id: &PublicKey) {
+		self.nodes.remove(node_id);
+	}
+
+	/// Gets the cached route to the given node, ending with the node itself, marking it as used.
+	pub(super) fn route(&mut self, node_id: &PublicKey) -> Option<Vec<PublicKey>> {
+		self.use_counter += 1;
+		let use_counter = self.use_counter;
+		self.nodes.get_mut(node_id)
+			.filter(|paths| !paths.route.is_empty())
+			.map(|paths| {
+				paths.last_used = use_counter;
+				paths.route.clone()
+			})
+	}
+
+	/// Gets the cached reply path for the given node, marking it as used.
+	pub(super) fn reply_path(&mut self, node_id: &PublicKey) -> Option<BlindedPath> {
+		self.use_counter += 1;
+		let use_counter = self.use_counter;
+		self.nodes.get_mut(node_id)
+			.and_then(|paths| {
+				paths.last_used = use_counter;
+				paths.reply_path.clone()
+			})
+	}
+
+	/// Caches the route to the given node, which must end with the node itself.
+	pub(super) fn cache_route(&mut self, node_id: PublicKey, route: Vec<PublicKey>) {
+		debug_assert_eq!(route.last(), Some(&node_id));
+		self.entry(node_id).route = route;
+	}
+
+	/// Forgets the cached route to the given node, keeping any cached reply path.
+	pub(super) fn forget_route(&mut self, node_id: &PublicKey) {
+		if let Some(paths) = self.nodes.get_mut(node_id) {
+			paths.route.clear();
+			if paths.reply_path.is_none() {
+				self.nodes.remove(node_id);
+			}
+		}
+	}
+
+	/// Caches a reply path the given node responded over.
+	pub(super) fn cache_reply_path(&mut self, node_id: PublicKey, reply_path: BlindedPath) {
+		let paths = self.entry(node_id);
+		paths.reply_path = Some(reply_path);
+		paths.reply_path_age_ticks = 0;
+	}
+
+	/// Forgets the cached reply path for the given node if it is `reply_path`, keeping any cached
+	/// route.
+	pub(super) fn forget_reply_path(&mut self, node_id: &PublicKey, reply_path: &BlindedPath) {
+		if let Some(paths) = self.nodes.get_mut(node_id) {
+			if paths.reply_path.as_ref() != Some(reply_path) { return; }
+			paths.reply_path = None;
+			if paths.route.is_empty() {
+				self.nodes.remove(node_id);
+			}
+		}
+	}
+
+	/// Ages the cached reply paths, forgetting those cached [`REPLY_PATH_EXPIRY_TICKS`] ago.
+	pub(super) fn timer_tick_occurred(&mut self) {
+		self.nodes.retain(|_, paths| {
+			if paths.reply_path.is_some() {
+				paths.reply_path_age_ticks += 1;
+				if paths.reply_path_age_ticks >= REPLY_PATH_EXPIRY_TICKS {
+					paths.reply_path = None;
+				}
+			}
+			paths.reply_path.is_some() || !paths.route.is_empty()
+		});
+	}
+
+	fn entry(&mut self, node_id: PublicKey) -> &mut CachedOnionMessagePaths {
+		self.use_counter += 1;
+		if !self.nodes.contains_key(&node_id) && self.nodes.len() >= MAX_CACHED_ONION_MESSAGE_NODES {
+			let least_recently_used = self.nodes.iter()
+				.min_by_key(|(_, paths)| paths.last_used)
+				.map(|(node_id, _)| *node_id);
+			if let Some(evicted_node_id) = least_recently_used {
+				self.nodes.remove(&evicted_node_id);
+			}
+		}
+		let paths = self.nodes.entry(node_id).or_insert_with(|| CachedOnionMessagePaths {
+			route: Vec::new(), reply_path: None, reply_path_age_ticks: 0, last_used: 0,
+		});
+		paths.last_used = self.use_counter;
+		paths
+	}
+}
+
+impl Default for OnionMessagePathCache {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl_writeable_tlv_based!(OnionMessagePathCache, {
+	(0, nodes, required),
+	(2, use_counter, required),
+});
@@ -0,0 +1,898 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! A protocol for delegating pathfinding to a trusted routing server over onion messages.
+//!
+//! Devices too constrained to sync and search the network graph can use a
+//! [`RemoteRouteProvider`] with a [`DelegatedRouter`] to request candidate routes from a
+//! [`RemoteRoutingServer`], ask for the penalties the server's scorer assigns to channels, and
+//! feed the results of their payment attempts back to the server's scorer. Both are
+//! [`CustomOnionMessageHandler`]s and should be given to the [`OnionMessenger`] of their
+//! respective nodes, alongside any other custom handler via a [`RemoteRoutingWithCustomHandler`].
+//!
+//! [`DelegatedRouter`]: crate::routing::router::DelegatedRouter
+//! [`OnionMessenger`]: super::OnionMessenger
+
+use bitcoin::secp256k1::PublicKey;
+
+use crate::io;
+use crate::ln::channelmanager::ChannelDetails;
+use crate::ln::msgs::{DecodeError, ErrorAction, LightningError};
+use crate::onion_message::{CustomOnionMessageContents, CustomOnionMessageHandler};
+use crate::onion_message::packet::BIG_PACKET_HOP_DATA_LEN;
+use crate::routing::gossip::{EffectiveCapacity, NodeId};
+use crate::routing::router::{InFlightHtlcs, Path, Route, RouteFuture, RouteParameters, RouteProvider, RouteRequestResult, Router};
+use crate::routing::scoring::{ChannelUsage, LockableScore, Score};
+use crate::sign::EntropySource;
+use crate::sync::{Mutex, MutexGuard};
+use crate::util::logger::Logger;
+use crate::util::ser::{Readable, Writeable, Writer};
+
+use core::ops::Deref;
+
+use crate::prelude::*;
+
+// TLV record types for the `onionmsg_tlv` TLV stream, picked from the experimental odd range.
+const ROUTE_REQUEST_TLV_TYPE: u64 = 65_537;
+const ROUTE_RESPONSE_TLV_TYPE: u64 = 65_539;
+const PAYMENT_PATH_RESULT_TLV_TYPE: u64 = 65_541;
+const SCORER_HINTS_REQUEST_TLV_TYPE: u64 = 65_543;
+const SCORER_HINTS_RESPONSE_TLV_TYPE: u64 = 65_545;
+
+/// The number of calls to [`RemoteRouteProvider::timer_tick_occurred`] after which a
+/// [`RouteRequest`] or [`ScorerHintsRequest`] which was not responded to is failed.
+pub const REMOTE_ROUTE_REQUEST_TIMEOUT_TICKS: u8 = 2;
+
+/// The maximum serialized length of a [`RemoteRoutingMessage`], leaving room in the largest
+/// onion message packet for the payloads of the hops it is sent over and its reply path.
+///
+/// Larger messages cannot be sent, so [`RemoteRouteProvider`]s fail requests which would exceed
+/// it and [`RemoteRoutingServer`]s trim their responses to fit.
+pub const MAX_REMOTE_ROUTING_MESSAGE_LEN: usize = BIG_PACKET_HOP_DATA_LEN - 4096;
+
+/// A request for candidate [`Route`]s sent by a [`RemoteRouteProvider`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct RouteRequest {
+	/// An identifier for the request, echoed in the [`RouteResponse`].
+	pub request_id: [u8; 32],
+	/// The node id routes should start from.
+	pub payer: PublicKey,
+	/// The parameters of the payment routes are requested for.
+	pub route_params: RouteParameters,
+	/// The usable channels of the payer, which routes must start with.
+	///
+	/// If empty, the server is asked to route from [`Self::payer`] over the public network graph.
+	pub first_hops: Vec<ChannelDetails>,
+	/// The payer's HTLCs which are currently in-flight, whose liquidity routes should account
+	/// for.
+	pub inflight_htlcs: InFlightHtlcs,
+}
+
+impl_writeable_tlv_based!(RouteRequest, {
+	(0, request_id, required),
+	(2, payer, required),
+	(4, route_params, required),
+	(6, first_hops, vec_type),
+	(8, inflight_htlcs, required),
+});
+
+/// A response to a [`RouteRequest`] sent by a [`RemoteRoutingServer`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct RouteResponse {
+	/// The [`RouteRequest::request_id`] of the request responded to.
+	pub request_id: [u8; 32],
+	/// The candidate routes found.
+	pub routes: Vec<Route>,
+	/// A description of why no routes were found, if any.
+	pub error: Option<String>,
+}
+
+impl_writeable_tlv_based!(RouteResponse, {
+	(0, request_id, required),
+	(2, routes, vec_type),
+	(4, error, option),
+});
+
+/// The result of an attempt to pay over a [`Path`], fed back to a [`RemoteRoutingServer`] to
+/// update its scorer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PaymentPathResult {
+	/// The path the payment was attempted over.
+	pub path: Path,
+	/// The channel the payment failed at, or `None` if the payment succeeded.
+	pub failed_short_channel_id: Option<u64>,
+}
+
+impl Writeable for PaymentPathResult {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		// A `Path` is only serialized as part of a `Route`.
+		let route = Route { paths: vec![self.path.clone()], payment_params: None };
+		write_tlv_fields!(w, {
+			(0, route, required),
+			(2, self.failed_short_channel_id, option),
+		});
+		Ok(())
+	}
+}
+
+impl Readable for PaymentPathResult {
+	fn read<R: io::Read>(r: &mut R) -> Result<Self, DecodeError> {
+		_init_and_read_tlv_fields!(r, {
+			(0, route, required),
+			(2, failed_short_channel_id, option),
+		});
+		let route: Route = route.0.unwrap();
+		if route.paths.len() != 1 {
+			return Err(DecodeError::InvalidValue);
+		}
+		let path = route.paths.into_iter().next().unwrap();
+		Ok(Self { path, failed_short_channel_id })
+	}
+}
+
+/// A channel, in the direction from [`Self::source`] to [`Self::target`], whose penalty is
+/// requested from a [`RemoteRoutingServer`]'s scorer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScorerHintChannel {
+	/// The short channel id of the channel.
+	pub short_channel_id: u64,
+	/// The node the channel is used from.
+	pub source: NodeId,
+	/// The node the channel is used towards.
+	pub target: NodeId,
+}
+
+impl_writeable_tlv_based!(ScorerHintChannel, {
+	(0, short_channel_id, required),
+	(2, source, required),
+	(4, target, required),
+});
+
+/// A request for the penalties a [`RemoteRoutingServer`]'s scorer assigns to channels, sent by
+/// a [`RemoteRouteProvider`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScorerHintsRequest {
+	/// An identifier for the request, echoed in the [`ScorerHintsResponse`].
+	pub request_id: [u8; 32],
+	/// The amount penalties are requested for.
+	pub amount_msat: u64,
+	/// The channels penalties are requested for.
+	pub channels: Vec<ScorerHintChannel>,
+}
+
+impl_writeable_tlv_based!(ScorerHintsRequest, {
+	(0, request_id, required),
+	(2, amount_msat, required),
+	(4, channels, vec_type),
+});
+
+/// The penalty a [`RemoteRoutingServer`]'s scorer assigns to a channel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScorerHint {
+	/// The channel the penalty applies to.
+	pub channel: ScorerHintChannel,
+	/// The penalty, as returned by [`Score::channel_penalty_msat`].
+	pub penalty_msat: u64,
+}
+
+impl_writeable_tlv_based!(ScorerHint, {
+	(0, channel, required),
+	(2, penalty_msat, required),
+});
+
+/// A response to a [`ScorerHintsRequest`] sent by a [`RemoteRoutingServer`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScorerHintsResponse {
+	/// The [`ScorerHintsRequest::request_id`] of the request responded to.
+	pub request_id: [u8; 32],
+	/// The penalties of the requested channels. May omit channels if the penalties of all of them
+	/// would not fit in a single onion message.
+	pub hints: Vec<ScorerHint>,
+}
+
+impl_writeable_tlv_based!(ScorerHintsResponse, {
+	(0, request_id, required),
+	(2, hints, vec_type),
+});
+
+/// Possible remote routing messages sent and received via an [`OnionMessage`].
+///
+/// [`OnionMessage`]: crate::ln::msgs::OnionMessage
+#[derive(Clone, Debug, PartialEq)]
+pub enum RemoteRoutingMessage {
+	/// A request for candidate routes, sent by a client to the server.
+	RouteRequest(RouteRequest),
+	/// The routes found for a [`RouteRequest`], sent by the server in response.
+	RouteResponse(RouteResponse),
+	/// The result of a payment attempt, sent by a client to the server.
+	PaymentPathResult(PaymentPathResult),
+	/// A request for the penalties of channels, sent by a client to the server.
+	ScorerHintsRequest(ScorerHintsRequest),
+	/// The penalties found for a [`ScorerHintsRequest`], sent by the server in response.
+	ScorerHintsResponse(ScorerHintsResponse),
+}
+
+impl RemoteRoutingMessage {
+	/// Returns whether `tlv_type` corresponds to a TLV record for remote routing.
+	pub fn is_known_type(tlv_type: u64) -> bool {
+		match tlv_type {
+			ROUTE_REQUEST_TLV_TYPE | ROUTE_RESPONSE_TLV_TYPE | PAYMENT_PATH_RESULT_TLV_TYPE |
+			SCORER_HINTS_REQUEST_TLV_TYPE | SCORER_HINTS_RESPONSE_TLV_TYPE => true,
+			_ => false,
+		}
+	}
+
+	/// Reads a remote routing message of the given `tlv_type`, returning `None` if the type is not
+	/// a remote routing one.
+	pub fn read_message<R: io::Read>(tlv_type: u64, buffer: &mut R) -> Result<Option<Self>, DecodeError> {
+		match tlv_type {
+			ROUTE_REQUEST_TLV_TYPE => Ok(Some(Self::RouteRequest(Readable::read(buffer)?))),
+			ROUTE_RESPONSE_TLV_TYPE => Ok(Some(Self::RouteResponse(Readable::read(buffer)?))),
+			PAYMENT_PATH_RESULT_TLV_TYPE => Ok(Some(Self::PaymentPathResult(Readable::read(buffer)?))),
+			SCORER_HINTS_REQUEST_TLV_TYPE => Ok(Some(Self::ScorerHintsRequest(Readable::read(buffer)?))),
+			SCORER_HINTS_RESPONSE_TLV_TYPE => Ok(Some(Self::ScorerHintsResponse(Readable::read(buffer)?))),
+			_ => Ok(None),
+		}
+	}
+
+	/// Returns whether the message fits in an onion message, see
+	/// [`MAX_REMOTE_ROUTING_MESSAGE_LEN`].
+	fn fits_onion_message(&self) -> bool {
+		self.serialized_length() <= MAX_REMOTE_ROUTING_MESSAGE_LEN
+	}
+}
+
+impl CustomOnionMessageContents for RemoteRoutingMessage {
+	fn tlv_type(&self) -> u64 {
+		match self {
+			RemoteRoutingMessage::RouteRequest(_) => ROUTE_REQUEST_TLV_TYPE,
+			RemoteRoutingMessage::RouteResponse(_) => ROUTE_RESPONSE_TLV_TYPE,
+			RemoteRoutingMessage::PaymentPathResult(_) => PAYMENT_PATH_RESULT_TLV_TYPE,
+			RemoteRoutingMessage::ScorerHintsRequest(_) => SCORER_HINTS_REQUEST_TLV_TYPE,
+			RemoteRoutingMessage::ScorerHintsResponse(_) => SCORER_HINTS_RESPONSE_TLV_TYPE,
+		}
+	}
+}
+
+impl Writeable for RemoteRoutingMessage {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		match self {
+			RemoteRoutingMessage::RouteRequest(message) => message.write(w),
+			RemoteRoutingMessage::RouteResponse(message) => message.write(w),
+			RemoteRoutingMessage::PaymentPathResult(message) => message.write(w),
+			RemoteRoutingMessage::ScorerHintsRequest(message) => message.write(w),
+			RemoteRoutingMessage::ScorerHintsResponse(message) => message.write(w),
+		}
+	}
+}
+
+struct PendingRouteRequest {
+	future: RouteFuture,
+	ticks: u8,
+}
+
+/// A [`RouteProvider`] requesting candidate routes from a [`RemoteRoutingServer`] over onion
+/// messages, for use with a [`DelegatedRouter`].
+///
+/// Requests are resolved asynchronously. Messages queued for the server must be fetched via
+/// [`Self::release_pending_messages`] and sent to [`Self::server_node_id`] with
+/// [`OnionMessenger::send_onion_message`], including a reply path back to us over which the
+/// server responds. The provider must also be the [`CustomOnionMessageHandler`] of our
+/// [`OnionMessenger`], e.g. via a [`RemoteRoutingWithCustomHandler`], to handle those
+/// responses, after which the payment should be retried to use the routes received.
+///
+/// As routes returned are validated by the [`DelegatedRouter`], a misbehaving server can at most
+/// fail our payments. It does, however, learn about every payment we make.
+///
+/// [`DelegatedRouter`]: crate::routing::router::DelegatedRouter
+/// [`OnionMessenger`]: super::OnionMessenger
+/// [`OnionMessenger::send_onion_message`]: super::OnionMessenger::send_onion_message
+pub struct RemoteRouteProvider<ES: Deref> where ES::Target: EntropySource {
+	server_node_id: PublicKey,
+	entropy_source: ES,
+	pending_requests: Mutex<HashMap<[u8; 32], PendingRouteRequest>>,
+	/// The ids of scorer hint requests which were not yet responded to, with the number of timer
+	/// ticks since they were sent.
+	pending_hint_requests: Mutex<HashMap<[u8; 32], u8>>,
+	/// The latest hint received for each channel, by its short channel id and source.
+	scorer_hints: Mutex<HashMap<(u64, NodeId), ScorerHint>>,
+	pending_messages: Mutex<Vec<RemoteRoutingMessage>>,
+}
+
+impl<ES: Deref> RemoteRouteProvider<ES> where ES::Target: EntropySource {
+	/// Creates a new provider requesting routes from the server with the given node id.
+	pub fn new(server_node_id: PublicKey, entropy_source: ES) -> Self {
+		Self {
+			server_node_id,
+			entropy_source,
+			pending_requests: Mutex::new(HashMap::new()),
+			pending_hint_requests: Mutex::new(HashMap::new()),
+			scorer_hints: Mutex::new(HashMap::new()),
+			pending_messages: Mutex::new(Vec::new()),
+		}
+	}
+
+	/// The node id of the [`RemoteRoutingServer`] routes are requested from.
+	pub fn server_node_id(&self) -> PublicKey {
+		self.server_node_id
+	}
+
+	/// Returns the messages queued for sending to [`Self::server_node_id`].
+	pub fn release_pending_messages(&self) -> Vec<RemoteRoutingMessage> {
+		core::mem::take(&mut *self.pending_messages.lock().unwrap())
+	}
+
+	/// Queues feedback to the server that a payment over the given `path` succeeded.
+	pub fn payment_path_successful(&self, path: &Path) {
+		let queued = self.queue_message(RemoteRoutingMessage::PaymentPathResult(
+			PaymentPathResult { path: path.clone(), failed_short_channel_id: None }
+		));
+		// Paths are limited by the size of a payment onion, which is far smaller.
+		debug_assert!(queued);
+	}
+
+	/// Queues feedback to the server that a payment over the given `path` failed at the channel
+	/// with the given `short_channel_id`.
+	pub fn payment_path_failed(&self, path: &Path, short_channel_id: u64) {
+		let queued = self.queue_message(RemoteRoutingMessage::PaymentPathResult(
+			PaymentPathResult { path: path.clone(), failed_short_channel_id: Some(short_channel_id) }
+		));
+		debug_assert!(queued);
+	}
+
+	/// Queues a request for the penalties the server's scorer assigns to sending `amount_msat`
+	/// over each of the given `channels`, which are returned by [`Self::scorer_hint_msat`] once
+	/// the server responded.
+	///
+	/// Errors if the request does not fit in an onion message, in which case it should be split
+	/// into requests for fewer channels.
+	pub fn request_scorer_hints(&self, amount_msat: u64, channels: Vec<ScorerHintChannel>) -> Result<(), ()> {
+		let request_id = self.entropy_source.get_secure_random_bytes();
+		let message = RemoteRoutingMessage::ScorerHintsRequest(ScorerHintsRequest {
+			request_id, amount_msat, channels,
+		});
+		if !self.queue_message(message) {
+			return Err(());
+		}
+		self.pending_hint_requests.lock().unwrap().insert(request_id, 0);
+		Ok(())
+	}
+
+	/// Returns the penalty the server's scorer last assigned to the given channel in the
+	/// direction from `source` to `target`, if the server sent one.
+	pub fn scorer_hint_msat(&self, short_channel_id: u64, source: &NodeId, target: &NodeId) -> Option<u64> {
+		self.scorer_hints.lock().unwrap().get(&(short_channel_id, *source))
+			.filter(|hint| hint.channel.target == *target)
+			.map(|hint| hint.penalty_msat)
+	}
+
+	/// Queues the message if it fits in an onion message, returning whether it did.
+	fn queue_message(&self, message: RemoteRoutingMessage) -> bool {
+		if !message.fits_onion_message() {
+			return false;
+		}
+		self.pending_messages.lock().unwrap().push(message);
+		true
+	}
+
+	/// Fails requests which the server did not respond to within
+	/// [`REMOTE_ROUTE_REQUEST_TIMEOUT_TICKS`] calls.
+	///
+	/// Should be called roughly once a minute, e.g. alongside
+	/// [`ChannelManager::timer_tick_occurred`].
+	///
+	/// [`ChannelManager::timer_tick_occurred`]: crate::ln::channelmanager::ChannelManager::timer_tick_occurred
+	pub fn timer_tick_occurred(&self) {
+		self.pending_requests.lock().unwrap().retain(|_, request| {
+			request.ticks += 1;
+			if request.ticks < REMOTE_ROUTE_REQUEST_TIMEOUT_TICKS {
+				return true;
+			}
+			request.future.resolve(Err(LightningError {
+				err: "Route request to the routing server timed out".to_owned(),
+				action: ErrorAction::IgnoreError,
+			}));
+			false
+		});
+		self.pending_hint_requests.lock().unwrap().retain(|_, ticks| {
+			*ticks += 1;
+			*ticks < REMOTE_ROUTE_REQUEST_TIMEOUT_TICKS
+		});
+	}
+}
+
+impl<ES: Deref> RouteProvider for RemoteRouteProvider<ES> where ES::Target: EntropySource {
+	fn request_routes(
+		&self, payer: &PublicKey, route_params: &RouteParameters, first_hops: &[&ChannelDetails],
+		inflight_htlcs: &InFlightHtlcs
+	) -> RouteRequestResult {
+		let request_id = self.entropy_source.get_secure_random_bytes();
+		let message = RemoteRoutingMessage::RouteRequest(RouteRequest {
+			request_id,
+			payer: *payer,
+			route_params: route_params.clone(),
+			first_hops: first_hops.iter().map(|channel| (*channel).clone()).collect(),
+			inflight_htlcs: inflight_htlcs.clone(),
+		});
+		if !self.queue_message(message) {
+			return RouteRequestResult::Sync(Err(LightningError {
+				err: "Route request is too large to send to the routing server".to_owned(),
+				action: ErrorAction::IgnoreError,
+			}));
+		}
+		let future = RouteFuture::new();
+		self.pending_requests.lock().unwrap().insert(
+			request_id, PendingRouteRequest { future: future.clone(), ticks: 0 }
+		);
+		RouteRequestResult::Async(future)
+	}
+}
+
+impl<ES: Deref> CustomOnionMessageHandler for RemoteRouteProvider<ES> where ES::Target: EntropySource {
+	type CustomMessage = RemoteRoutingMessage;
+
+	fn handle_custom_message(&self, msg: Self::CustomMessage) -> Option<Self::CustomMessage> {
+		match msg {
+			RemoteRoutingMessage::RouteResponse(response) => {
+				if let Some(request) = self.pending_requests.lock().unwrap().remove(&response.request_id) {
+					let result = match response.error {
+						Some(err) => Err(LightningError { err, action: ErrorAction::IgnoreError }),
+						None => Ok(response.routes),
+					};
+					request.future.resolve(result);
+				}
+			},
+			RemoteRoutingMessage::ScorerHintsResponse(response) => {
+				if self.pending_hint_requests.lock().unwrap().remove(&response.request_id).is_some() {
+					let mut scorer_hints = self.scorer_hints.lock().unwrap();
+					for hint in response.hints {
+						scorer_hints.insert((hint.channel.short_channel_id, hint.channel.source), hint);
+					}
+				}
+			},
+			_ => {},
+		}
+		None
+	}
+
+	fn read_custom_message<R: io::Read>(&self, message_type: u64, buffer: &mut R) -> Result<Option<Self::CustomMessage>, DecodeError> {
+		RemoteRoutingMessage::read_message(message_type, buffer)
+	}
+}
+
+/// Serves [`RouteRequest`]s and [`ScorerHintsRequest`]s from [`RemoteRouteProvider`]s using a
+/// local [`Router`] and scorer, and updates the scorer with the [`PaymentPathResult`]s they feed
+/// back.
+///
+/// Should be the [`CustomOnionMessageHandler`] of the server's [`OnionMessenger`], e.g. via a
+/// [`RemoteRoutingWithCustomHandler`], which sends responses over the reply path of each
+/// request.
+///
+/// Requests and feedback are not authenticated, so any node able to reach us may use our
+/// [`Router`] and manipulate our scorer. A server should thus only be reachable by clients it
+/// trusts, e.g. by only accepting onion messages from them.
+///
+/// [`OnionMessenger`]: super::OnionMessenger
+pub struct RemoteRoutingServer<R: Deref, S: Deref, SP: Sized, Sc: Score<ScoreParams = SP>, L: Deref>
+where
+	R::Target: Router,
+	S::Target: for<'a> LockableScore<'a, Locked = MutexGuard<'a, Sc>>,
+	L::Target: Logger,
+{
+	router: R,
+	scorer: S,
+	score_params: SP,
+	logger: L,
+}
+
+impl<R: Deref, S: Deref, SP: Sized, Sc: Score<ScoreParams = SP>, L: Deref> RemoteRoutingServer<R, S, SP, Sc, L>
+where
+	R::Target: Router,
+	S::Target: for<'a> LockableScore<'a, Locked = MutexGuard<'a, Sc>>,
+	L::Target: Logger,
+{
+	/// Creates a new server finding routes with the given `router` and feeding payment results to
+	/// the given `scorer`, which should be the one used by the `router`. Scorer hints are
+	/// computed with the given `score_params`.
+	pub fn new(router: R, scorer: S, score_params: SP, logger: L) -> Self {
+		Self { router, scorer, score_params, logger }
+	}
+
+	fn handle_route_request(&self, request: RouteRequest) -> RouteResponse {
+		let RouteRequest { request_id, payer, route_params, first_hops, inflight_htlcs } = request;
+		let first_hop_refs = first_hops.iter().collect::<Vec<_>>();
+		let first_hops = if first_hop_refs.is_empty() { None } else { Some(&first_hop_refs[..]) };
+		match self.router.find_route(&payer, &route_params, first_hops, &inflight_htlcs) {
+			Ok(route) => {
+				let response = RouteResponse { request_id, routes: vec![route], error: None };
+				if RemoteRoutingMessage::RouteResponse(response.clone()).fits_onion_message() {
+					return response;
+				}
+				log_debug!(self.logger, "Route found for remote request from {} is too large to send", payer);
+				RouteResponse {
+					request_id, routes: Vec::new(),
+					error: Some("Route found is too large to send in an onion message".to_owned()),
+				}
+			},
+			Err(e) => {
+				log_debug!(self.logger, "Failed to find a route for remote request from {}: {}", payer, e.err);
+				RouteResponse { request_id, routes: Vec::new(), error: Some(e.err) }
+			},
+		}
+	}
+
+	fn handle_scorer_hints_request(&self, request: ScorerHintsRequest) -> ScorerHintsResponse {
+		let ScorerHintsRequest { request_id, amount_msat, channels } = request;
+		let mut response = ScorerHintsResponse { request_id, hints: Vec::with_capacity(channels.len()) };
+		{
+			let scorer = self.scorer.lock();
+			let usage = ChannelUsage {
+				amount_msat, inflight_htlc_msat: 0, effective_capacity: EffectiveCapacity::Unknown,
+			};
+			for channel in channels {
+				let penalty_msat = scorer.channel_penalty_msat(
+					channel.short_channel_id, &channel.source, &channel.target, usage, &self.score_params);
+				response.hints.push(ScorerHint { channel, penalty_msat });
+			}
+		}
+		// Requests fitting in an onion message should generally result in responses which do, too,
+		// but drop any hints which would not fit rather than failing to respond at all.
+		let overhead_len = ScorerHintsResponse { request_id, hints: Vec::new() }.serialized_length();
+		let mut response_len = overhead_len;
+		let mut fitting_hints = 0;
+		for hint in response.hints.iter() {
+			// Leave room for the growth of the length prefixes.
+			let hint_len = hint.serialized_length() + 4;
+			if response_len + hint_len > MAX_REMOTE_ROUTING_MESSAGE_LEN { break; }
+			response_len += hint_len;
+			fitting_hints += 1;
+		}
+		if fitting_hints < response.hints.len() {
+			log_debug!(self.logger, "Dropping {} scorer hints which do not fit in an onion message",
+				response.hints.len() - fitting_hints);
+			response.hints.truncate(fitting_hints);
+		}
+		response
+	}
+}
+
+impl<R: Deref, S: Deref, SP: Sized, Sc: Score<ScoreParams = SP>, L: Deref> CustomOnionMessageHandler for RemoteRoutingServer<R, S, SP, Sc, L>
+where
+	R::Target: Router,
+	S::Target: for<'a> LockableScore<'a, Locked = MutexGuard<'a, Sc>>,
+	L::Target: Logger,
+{
+	type CustomMessage = RemoteRoutingMessage;
+
+	fn handle_custom_message(&self, msg: Self::CustomMessage) -> Option<Self::CustomMessage> {
+		match msg {
+			RemoteRoutingMessage::RouteRequest(request) => {
+				Some(RemoteRoutingMessage::RouteResponse(self.handle_route_request(request)))
+			},
+			RemoteRoutingMessage::ScorerHintsRequest(request) => {
+				Some(RemoteRoutingMessage::ScorerHintsResponse(self.handle_scorer_hints_request(request)))
+			},
+			RemoteRoutingMessage::PaymentPathResult(result) => {
+				match result.failed_short_channel_id {
+					Some(short_channel_id) => self.scorer.lock().payment_path_failed(&result.path, short_channel_id),
+					None => self.scorer.lock().payment_path_successful(&result.path),
+				}
+				None
+			},
+			RemoteRoutingMessage::RouteResponse(_)|RemoteRoutingMessage::ScorerHintsResponse(_) => {
+				log_trace!(self.logger, "Ignoring response sent to the routing server");
+				None
+			},
+		}
+	}
+
+	fn read_custom_message<RD: io::Read>(&self, message_type: u64, buffer: &mut RD) -> Result<Option<Self::CustomMessage>, DecodeError> {
+		RemoteRoutingMessage::read_message(message_type, buffer)
+	}
+}
+
+/// A message handled by a [`RemoteRoutingWithCustomHandler`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum RemoteRoutingOrCustomMessage<M: CustomOnionMessageContents> {
+	/// A remote routing message.
+	RemoteRouting(RemoteRoutingMessage),
+	/// A message of the wrapped custom handler.
+	Custom(M),
+}
+
+impl<M: CustomOnionMessageContents> CustomOnionMessageContents for RemoteRoutingOrCustomMessage<M> {
+	fn tlv_type(&self) -> u64 {
+		match self {
+			RemoteRoutingOrCustomMessage::RemoteRouting(message) => message.tlv_type(),
+			RemoteRoutingOrCustomMessage::Custom(message) => message.tlv_type(),
+		}
+	}
+}
+
+impl<M: CustomOnionMessageContents> Writeable for RemoteRoutingOrCustomMessage<M> {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		match self {
+			RemoteRoutingOrCustomMessage::RemoteRouting(message) => message.write(w),
+			RemoteRoutingOrCustomMessage::Custom(message) => message.write(w),
+		}
+	}
+}
+
+/// A [`CustomOnionMessageHandler`] handing remote routing messages to a [`RemoteRouteProvider`]
+/// or [`RemoteRoutingServer`] and all other custom messages to another handler, allowing the
+/// remote routing protocol to be used alongside other custom onion message protocols.
+pub struct RemoteRoutingWithCustomHandler<RR: Deref, CMH: Deref>
+where
+	RR::Target: CustomOnionMessageHandler<CustomMessage = RemoteRoutingMessage>,
+	CMH::Target: CustomOnionMessageHandler,
+{
+	remote_routing_handler: RR,
+	custom_handler: CMH,
+}
+
+impl<RR: Deref, CMH: Deref> RemoteRoutingWithCustomHandler<RR, CMH>
+where
+	RR::Target: CustomOnionMessageHandler<CustomMessage = RemoteRoutingMessage>,
+	CMH::Target: CustomOnionMessageHandler,
+{
+	/// Creates a new handler combining the given remote routing handler and custom handler.
+	pub fn new(remote_routing_handler: RR, custom_handler: CMH) -> Self {
+		Self { remote_routing_handler, custom_handler }
+	}
+}
+
+impl<RR: Deref, CMH: Deref> CustomOnionMessageHandler for RemoteRoutingWithCustomHandler<RR, CMH>
+where
+	RR::Target: CustomOnionMessageHandler<CustomMessage = RemoteRoutingMessage>,
+	CMH::Target: CustomOnionMessageHandler,
+{
+	type CustomMessage = RemoteRoutingOrCustomMessage<<CMH::Target as CustomOnionMessageHandler>::CustomMessage>;
+
+	fn handle_custom_message(&self, msg: Self::CustomMessage) -> Option<Self::CustomMessage> {
+		match msg {
+			RemoteRoutingOrCustomMessage::RemoteRouting(message) =>
+				self.remote_routing_handler.handle_custom_message(message)
+					.map(RemoteRoutingOrCustomMessage::RemoteRouting),
+			RemoteRoutingOrCustomMessage::Custom(message) =>
+				self.custom_handler.handle_custom_message(message)
+					.map(RemoteRoutingOrCustomMessage::Custom),
+		}
+	}
+
+	fn read_custom_message<R: io::Read>(&self, message_type: u64, buffer: &mut R) -> Result<Option<Self::CustomMessage>, DecodeError> {
+		if RemoteRoutingMessage::is_known_type(message_type) {
+			Ok(self.remote_routing_handler.read_custom_message(message_type, buffer)?
+				.map(RemoteRoutingOrCustomMessage::RemoteRouting))
+		} else {
+			Ok(self.custom_handler.read_custom_message(message_type, buffer)?
+				.map(RemoteRoutingOrCustomMessage::Custom))
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use bitcoin::network::constants::Network;
+	use bitcoin::secp256k1::PublicKey;
+
+	use crate::ln::PaymentHash;
+	use crate::ln::channelmanager::{ChannelDetails, PaymentId};
+	use crate::ln::features::{ChannelFeatures, NodeFeatures};
+	use crate::ln::msgs::LightningError;
+	use crate::onion_message::{CustomOnionMessageContents, CustomOnionMessageHandler};
+	use crate::routing::gossip::NodeId;
+	use crate::ln::peer_handler::IgnoringMessageHandler;
+	use crate::routing::router::{DelegatedRouter, InFlightHtlcs, Path, PaymentParameters, Route, RouteFeeLimits, RouteHop, RouteParameters, Router};
+	use crate::routing::scoring::{ChannelUsage, Score};
+	use crate::sync::Mutex;
+	use crate::util::ser::Writeable;
+	use crate::util::test_utils;
+
+	use super::{MAX_REMOTE_ROUTING_MESSAGE_LEN, REMOTE_ROUTE_REQUEST_TIMEOUT_TICKS, RemoteRouteProvider, RemoteRoutingMessage, RemoteRoutingOrCustomMessage, RemoteRoutingServer, RemoteRoutingWithCustomHandler, ScorerHintChannel};
+
+	struct StaticRouter(Route);
+	impl Router for StaticRouter {
+		fn find_route(
+			&self, _: &PublicKey, _: &RouteParameters, _: Option<&[&ChannelDetails]>, _: &InFlightHtlcs
+		) -> Result<Route, LightningError> {
+			Ok(self.0.clone())
+		}
+	}
+
+	#[derive(Default)]
+	struct RecordingScorer {
+		failed_short_channel_ids: Vec<u64>,
+		successful_paths: usize,
+	}
+
+	#[cfg(c_bindings)]
+	impl crate::util::ser::Writeable for RecordingScorer {
+		fn write<W: crate::util::ser::Writer>(&self, _: &mut W) -> Result<(), crate::io::Error> { unreachable!(); }
+	}
+
+	impl Score for RecordingScorer {
+		type ScoreParams = ();
+		fn channel_penalty_msat(&self, short_channel_id: u64, _: &NodeId, _: &NodeId, usage: ChannelUsage, _: &()) -> u64 {
+			short_channel_id + usage.amount_msat
+		}
+		fn payment_path_failed(&mut self, _: &Path, short_channel_id: u64) {
+			self.failed_short_channel_ids.push(short_channel_id);
+		}
+		fn payment_path_successful(&mut self, _: &Path) { self.successful_paths += 1; }
+		fn probe_failed(&mut self, _: &Path, _: u64) {}
+		fn probe_successful(&mut self, _: &Path) {}
+	}
+
+	/// Encodes and decodes the message as if it was sent in an onion message.
+	fn deliver(message: RemoteRoutingMessage) -> RemoteRoutingMessage {
+		let encoded = message.encode();
+		let decoded = RemoteRoutingMessage::read_message(message.tlv_type(), &mut &encoded[..])
+			.unwrap().unwrap();
+		assert_eq!(decoded, message);
+		decoded
+	}
+
+	#[test]
+	fn requests_routes_from_remote_server() {
+		let keys_manager = test_utils::TestKeysInterface::new(&[42; 32], Network::Testnet);
+		let logger = test_utils::TestLogger::new();
+		let (payer, first_hop, payee, server_id) =
+			(test_utils::pubkey(1), test_utils::pubkey(2), test_utils::pubkey(3), test_utils::pubkey(4));
+		let route_params = RouteParameters {
+			payment_params: PaymentParameters::from_node_id(payee, 42),
+			final_value_msat: 10_000,
+		};
+
+		let hop = |pubkey: PublicKey, short_channel_id: u64, fee_msat: u64| RouteHop {
+			pubkey, node_features: NodeFeatures::empty(), short_channel_id,
+			channel_features: ChannelFeatures::empty(), fee_msat, cltv_expiry_delta: 42,
+		};
+		let route = Route {
+			paths: vec![Path { hops: vec![hop(first_hop, 42, 100), hop(payee, 43, 10_000)], blinded_tail: None }],
+			payment_params: None,
+		};
+
+		let server_router = StaticRouter(route.clone());
+		let scorer = Mutex::new(RecordingScorer::default());
+		let server = RemoteRoutingServer::new(&server_router, &scorer, (), &logger);
+		let provider = RemoteRouteProvider::new(server_id, &keys_manager);
+		let router = DelegatedRouter::new(&provider, &logger, RouteFeeLimits::default());
+
+		// The payment fails until the server responded, after which its routes are used.
+		// Our in-flight HTLCs are passed on to the server.
+		let (payment_hash, payment_id) = (PaymentHash([42; 32]), PaymentId([42; 32]));
+		let mut inflight_htlcs = InFlightHtlcs::new();
+		inflight_htlcs.process_path(&route.paths[0], payer);
+		assert!(router.find_route_with_id(&payer, &route_params, None, &inflight_htlcs, payment_hash, payment_id).is_err());
+		let mut messages = provider.release_pending_messages();
+		assert_eq!(messages.len(), 1);
+		match &messages[0] {
+			RemoteRoutingMessage::RouteRequest(request) => assert_eq!(request.inflight_htlcs, inflight_htlcs),
+			_ => panic!(),
+		}
+		assert!(provider.release_pending_messages().is_empty());
+		let response = server.handle_custom_message(deliver(messages.pop().unwrap())).unwrap();
+		assert!(provider.handle_custom_message(deliver(response)).is_none());
+		let found_route = router.find_route_with_id(&payer, &route_params, None, &InFlightHtlcs::new(), payment_hash, payment_id).unwrap();
+		assert_eq!(found_route.paths, route.paths);
+
+		// Payment results are fed back to the server's scorer.
+		provider.payment_path_failed(&route.paths[0], 43);
+		provider.payment_path_successful(&route.paths[0]);
+		for message in provider.release_pending_messages() {
+			assert!(server.handle_custom_message(deliver(message)).is_none());
+		}
+		assert_eq!(scorer.lock().unwrap().failed_short_channel_ids, vec![43]);
+		assert_eq!(scorer.lock().unwrap().successful_paths, 1);
+
+		// Requests the server does not respond to time out.
+		let payment_id = PaymentId([43; 32]);
+		assert!(router.find_route_with_id(&payer, &route_params, None, &InFlightHtlcs::new(), payment_hash, payment_id).is_err());
+		assert_eq!(provider.release_pending_messages().len(), 1);
+		for _ in 1..REMOTE_ROUTE_REQUEST_TIMEOUT_TICKS {
+			provider.timer_tick_occurred();
+		}
+		assert_eq!(provider.pending_requests.lock().unwrap().len(), 1);
+		provider.timer_tick_occurred();
+		assert!(provider.pending_requests.lock().unwrap().is_empty());
+		assert!(router.find_route_with_id(&payer, &route_params, None, &InFlightHtlcs::new(), payment_hash, payment_id).is_err());
+		assert!(provider.release_pending_messages().is_empty());
+
+		// A retry after the timeout sends a new request.
+		assert!(router.find_route_with_id(&payer, &route_params, None, &InFlightHtlcs::new(), payment_hash, payment_id).is_err());
+		assert_eq!(provider.release_pending_messages().len(), 1);
+	}
+
+	#[test]
+	fn requests_scorer_hints_from_remote_server() {
+		let keys_manager = test_utils::TestKeysInterface::new(&[42; 32], Network::Testnet);
+		let logger = test_utils::TestLogger::new();
+		let (node_a, node_b) = (NodeId::from_pubkey(&test_utils::pubkey(1)), NodeId::from_pubkey(&test_utils::pubkey(2)));
+
+		let server_router = StaticRouter(Route { paths: Vec::new(), payment_params: None });
+		let scorer = Mutex::new(RecordingScorer::default());
+		let server = RemoteRoutingServer::new(&server_router, &scorer, (), &logger);
+		let provider = RemoteRouteProvider::new(test_utils::pubkey(4), &keys_manager);
+
+		let channel = ScorerHintChannel { short_channel_id: 42, source: node_a, target: node_b };
+		provider.request_scorer_hints(1_000, vec![channel]).unwrap();
+		assert_eq!(provider.scorer_hint_msat(42, &node_a, &node_b), None);
+		let mut messages = provider.release_pending_messages();
+		assert_eq!(messages.len(), 1);
+		let response = server.handle_custom_message(deliver(messages.pop().unwrap())).unwrap();
+		assert!(provider.handle_custom_message(deliver(response.clone())).is_none());
+		assert_eq!(provider.scorer_hint_msat(42, &node_a, &node_b), Some(1_042));
+		// Hints are directional.
+		assert_eq!(provider.scorer_hint_msat(42, &node_b, &node_a), None);
+
+		// Responses to requests we did not send are ignored.
+		let channel = ScorerHintChannel { short_channel_id: 43, source: node_a, target: node_b };
+		provider.request_scorer_hints(1_000, vec![channel]).unwrap();
+		let request = provider.release_pending_messages().pop().unwrap();
+		for _ in 0..REMOTE_ROUTE_REQUEST_TIMEOUT_TICKS {
+			provider.timer_tick_occurred();
+		}
+		let response = server.handle_custom_message(deliver(request)).unwrap();
+		provider.handle_custom_message(deliver(response));
+		assert_eq!(provider.scorer_hint_msat(43, &node_a, &node_b), None);
+
+		// Requests which do not fit in an onion message are refused rather than dropped later.
+		let channels = vec![channel; MAX_REMOTE_ROUTING_MESSAGE_LEN / 16];
+		assert!(provider.request_scorer_hints(1_000, channels).is_err());
+		assert!(provider.release_pending_messages().is_empty());
+	}
+
+	#[test]
+	fn fails_oversized_route_requests() {
+		let keys_manager = test_utils::TestKeysInterface::new(&[42; 32], Network::Testnet);
+		let logger = test_utils::TestLogger::new();
+		let payee = test_utils::pubkey(3);
+		let route_params = RouteParameters {
+			payment_params: PaymentParameters::from_node_id(payee, 42),
+			final_value_msat: 10_000,
+		};
+		let provider = RemoteRouteProvider::new(test_utils::pubkey(4), &keys_manager);
+		let router = DelegatedRouter::new(&provider, &logger, RouteFeeLimits::default());
+
+		// Far more in-flight HTLCs than fit in an onion message.
+		let hops = (0..MAX_REMOTE_ROUTING_MESSAGE_LEN as u64 / 8).map(|short_channel_id| RouteHop {
+			pubkey: payee, node_features: NodeFeatures::empty(), short_channel_id,
+			channel_features: ChannelFeatures::empty(), fee_msat: 1, cltv_expiry_delta: 42,
+		}).collect();
+		let mut inflight_htlcs = InFlightHtlcs::new();
+		inflight_htlcs.process_path(&Path { hops, blinded_tail: None }, test_utils::pubkey(1));
+		let err = router.find_route_with_id(
+			&test_utils::pubkey(1), &route_params, None, &inflight_htlcs,
+			PaymentHash([42; 32]), PaymentId([42; 32])
+		).unwrap_err();
+		assert_eq!(err.err, "Route request is too large to send to the routing server");
+		assert!(provider.release_pending_messages().is_empty());
+	}
+
+	#[test]
+	fn combines_with_custom_handler() {
+		let keys_manager = test_utils::TestKeysInterface::new(&[42; 32], Network::Testnet);
+		let provider = RemoteRouteProvider::new(test_utils::pubkey(4), &keys_manager);
+		let handler = RemoteRoutingWithCustomHandler::new(&provider, IgnoringMessageHandler {});
+
+		let channel = ScorerHintChannel {
+			short_channel_id: 42, source: NodeId::from_pubkey(&test_utils::pubkey(1)),
+			target: NodeId::from_pubkey(&test_utils::pubkey(2)),
+		};
+		provider.request_scorer_hints(1_000, vec![channel]).unwrap();
+		let message = provider.release_pending_messages().pop().unwrap();
+		let encoded = message.encode();
+		match handler.read_custom_message(message.tlv_type(), &mut &encoded[..]).unwrap() {
+			Some(RemoteRoutingOrCustomMessage::RemoteRouting(decoded)) => assert_eq!(decoded, message),
+			_ => panic!(),
+		}
+		// Other types are left to the custom handler, which doesn't know this one.
+		assert!(handler.read_custom_message(4242, &mut &encoded[..]).unwrap().is_none());
+	}
+}
@@ -14,8 +14,9 @@ use bitcoin::secp256k1::PublicKey;
 use bitcoin::secp256k1::Secp256k1;
 use bitcoin::secp256k1;
 
+use bitcoin::hashes::sha256::Hash as Sha256;
 use bitcoin::hashes::sha256d::Hash as Sha256dHash;
-use bitcoin::hashes::Hash;
+use bitcoin::hashes::{Hash, HashEngine};
 use bitcoin::hashes::hex::FromHex;
 use bitcoin::hash_types::BlockHash;
 
@@ -25,31 +26,42 @@ use bitcoin::blockdata::constants::genesis_block;
 use crate::events::{MessageSendEvent, MessageSendEventsProvider};
 use crate::ln::features::{ChannelFeatures, NodeFeatures, InitFeatures};
 use crate::ln::msgs::{DecodeError, ErrorAction, Init, LightningError, RoutingMessageHandler, NetAddress, MAX_VALUE_MSAT};
-use crate::ln::msgs::{ChannelAnnouncement, ChannelUpdate, NodeAnnouncement, GossipTimestampFilter};
+use crate::ln::msgs::{ChannelAnnouncement, ChannelUpdate, LeaseRates, NodeAnnouncement, GossipTimestampFilter};
 use crate::ln::msgs::{QueryChannelRange, ReplyChannelRange, QueryShortChannelIds, ReplyShortChannelIdsEnd};
 use crate::ln::msgs;
+#[cfg(taproot)]
+use crate::ln::chan_utils::aggregate_musig2_keys;
+#[cfg(taproot)]
+use crate::offers::merkle;
+use crate::routing::interchange::{self, InterchangeError};
 use crate::routing::utxo::{self, UtxoLookup, UtxoResolver};
-use crate::util::ser::{Readable, ReadableArgs, Writeable, Writer, MaybeReadable};
+use crate::util::ser::{Readable, ReadableArgs, Writeable, Writer, MaybeReadable, WithoutLength};
 use crate::util::logger::{Logger, Level};
 use crate::util::scid_utils::{block_from_scid, scid_from_parts, MAX_SCID_BLOCK};
 use crate::util::string::PrintableString;
 use crate::util::indexed_map::{IndexedMap, Entry as IndexedMapEntry};
+use crate::util::persist::{KVStorePersister, KVStoreReader};
 
 use crate::io;
 use crate::io_extras::{copy, sink};
 use crate::prelude::*;
 use core::{cmp, fmt};
 use core::convert::TryFrom;
-use crate::sync::{RwLock, RwLockReadGuard, LockTestExt};
+use crate::sync::{RwLock, LockTestExt};
 #[cfg(feature = "std")]
 use core::sync::atomic::{AtomicUsize, Ordering};
 use crate::sync::Mutex;
+use core::marker::PhantomData;
 use core::ops::{Bound, Deref};
 use core::str::FromStr;
 
 #[cfg(feature = "std")]
 use std::time::{SystemTime, UNIX_EPOCH};
 
+/// The tag of the tagged hash signed in a `channel_announcement_2`.
+#[cfg(taproot)]
+const CHANNEL_ANNOUNCEMENT_2_SIGNATURE_TAG: &str = concat!("lightning", "channel_announcement_2", "signature");
+
 /// We remove stale channel directional info two weeks after the last update, per BOLT 7's
 /// suggestion.
 const STALE_CHANNEL_UPDATE_AGE_LIMIT_SECS: u64 = 60 * 60 * 24 * 14;
@@ -161,6 +173,19 @@ impl FromStr for NodeId {
 	}
 }
 
+/// Provides addresses for peers which we know how to reach but which have not announced any, e.g.,
+/// unannounced clients of an LSP, whose endpoints the application learned out-of-band.
+///
+/// Consulted by [`NetworkGraph::peer_addresses`], which the [`DefaultMessageRouter`] and
+/// reconnection logic such as `lightning-net-tokio`'s `connect_outbound_to_peer` use to find
+/// where to connect to a peer before giving up on it.
+///
+/// [`DefaultMessageRouter`]: crate::onion_message::DefaultMessageRouter
+pub trait PeerAddressResolver {
+	/// Returns the known addresses of the peer with the given `node_id`, if any.
+	fn resolve_addresses(&self, node_id: &PublicKey) -> Vec<NetAddress>;
+}
+
 /// Represents the network as nodes and channels between them
 pub struct NetworkGraph<L: Deref> where L::Target: Logger {
 	secp_ctx: Secp256k1<secp256k1::VerifyOnly>,
@@ -168,6 +193,9 @@ pub struct NetworkGraph<L: Deref> where L::Target: Logger {
 	genesis_hash: BlockHash,
 	logger: L,
 	// Lock order: channels -> nodes
+	//
+	// Both maps share their shards with any outstanding `ReadOnlyNetworkGraph` snapshots, which
+	// are copied on write while a snapshot is held, see `IndexedMap`.
 	channels: RwLock<IndexedMap<u64, ChannelInfo>>,
 	nodes: RwLock<IndexedMap<NodeId, NodeInfo>>,
 	// Lock order: removed_channels -> removed_nodes
@@ -191,12 +219,59 @@ pub struct NetworkGraph<L: Deref> where L::Target: Logger {
 	removed_nodes: Mutex<HashMap<NodeId, Option<u64>>>,
 	/// Announcement messages which are awaiting an on-chain lookup to be processed.
 	pub(super) pending_checks: utxo::PendingChecks,
+	/// The channels moved out of memory into an [`OffloadedChannelStore`], each mapped to the
+	/// latest update received for either of its directions since, if any.
+	///
+	/// This is a leaf lock, no other locks may be taken while it is held.
+	offloaded_channels: Mutex<HashMap<u64, [Option<PendingOffloadedUpdate>; 2]>>,
+	/// The most recent channel disables, see [`NetworkGraph::channels_disabled_since`].
+	///
+	/// This is a leaf lock, no other locks may be taken while it is held.
+	disabled_channels: Mutex<DisabledChannelLog>,
+}
+
+/// The number of channel disables remembered for [`NetworkGraph::channels_disabled_since`].
+const MAX_DISABLED_CHANNEL_LOG_LEN: usize = 16_384;
+
+/// The short channel ids of the channels most recently disabled by a `channel_update`, once per
+/// disable, each identified by a sequence number.
+struct DisabledChannelLog {
+	/// The sequence number of the next disable, i.e., one past that of the last entry.
+	next_seq: u64,
+	short_channel_ids: VecDeque<u64>,
+}
+
+impl DisabledChannelLog {
+	fn new() -> Self {
+		Self { next_seq: 0, short_channel_ids: VecDeque::new() }
+	}
+
+	fn push(&mut self, short_channel_id: u64) {
+		if self.short_channel_ids.len() >= MAX_DISABLED_CHANNEL_LOG_LEN {
+			self.short_channel_ids.pop_front();
+		}
+		self.short_channel_ids.push_back(short_channel_id);
+		self.next_seq += 1;
+	}
+}
+
+/// A `channel_update` for a channel in an [`OffloadedChannelStore`], queued until it is applied to
+/// the store or the channel is loaded back into memory.
+struct PendingOffloadedUpdate {
+	contents: msgs::UnsignedChannelUpdate,
+	full_msg: Option<msgs::ChannelUpdate>,
+	sig: Option<secp256k1::ecdsa::Signature>,
 }
 
 /// A read-only view of [`NetworkGraph`].
+///
+/// This is a consistent snapshot of the graph taken when [`NetworkGraph::read_only`] was called.
+/// It does not hold any locks on the graph, so updates may continue to be applied while it is
+/// held, though they will not be visible through it.
 pub struct ReadOnlyNetworkGraph<'a> {
-	channels: RwLockReadGuard<'a, IndexedMap<u64, ChannelInfo>>,
-	nodes: RwLockReadGuard<'a, IndexedMap<NodeId, NodeInfo>>,
+	channels: IndexedMap<u64, ChannelInfo>,
+	nodes: IndexedMap<NodeId, NodeInfo>,
+	_graph: PhantomData<&'a ()>,
 }
 
 /// Update to the [`NetworkGraph`] based on payment failure information conveyed via the Onion
@@ -258,6 +333,8 @@ where U::Target: UtxoLookup, L::Target: Logger
 	#[cfg(feature = "std")]
 	full_syncs_requested: AtomicUsize,
 	pending_events: Mutex<Vec<MessageSendEvent>>,
+	verification: GossipVerification,
+	random_seed_bytes: [u8; 32],
 	logger: L,
 }
 
@@ -275,10 +352,34 @@ where U::Target: UtxoLookup, L::Target: Logger
 			full_syncs_requested: AtomicUsize::new(0),
 			utxo_lookup,
 			pending_events: Mutex::new(vec![]),
+			verification: GossipVerification::Full,
+			random_seed_bytes: [0; 32],
 			logger,
 		}
 	}
 
+	/// Sets how the signatures of gossip messages received from our peers are verified, which is
+	/// [`GossipVerification::Full`] by default.
+	///
+	/// As our peers are not told apart when handling gossip, [`GossipVerification::Sampled`] may
+	/// only be used if every peer we sync gossip from via this [`P2PGossipSync`] is trusted, e.g.
+	/// when it only ever connects to a gossip node we operate. Messages whose signatures were not
+	/// sampled are applied as if they were unsigned and are thus not relayed, while those with an
+	/// invalid sampled signature are rejected.
+	///
+	/// `random_seed_bytes` determines which messages are sampled and must be unpredictable to our
+	/// peers.
+	pub fn set_gossip_verification(&mut self, verification: GossipVerification, random_seed_bytes: [u8; 32]) {
+		self.verification = verification;
+		self.random_seed_bytes = random_seed_bytes;
+	}
+
+	/// Whether we skip verifying the signature of a message with the given contents.
+	fn skips_signature<M: Writeable>(&self, contents: &M) -> bool {
+		self.verification != GossipVerification::Full &&
+			!self.verification.verifies(&self.random_seed_bytes, &contents.encode()[..])
+	}
+
 	/// Adds a provider used to check new announcements. Does not affect
 	/// existing announcements unless they are updated.
 	/// Add, update or remove the provider would replace the current one.
@@ -373,6 +474,86 @@ impl<L: Deref> NetworkGraph<L> where L::Target: Logger {
 	}
 }
 
+/// How the signatures of gossip messages from a given source are verified, see
+/// [`NetworkGraph::update_from_gossip_batch`] and [`P2PGossipSync::set_gossip_verification`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GossipVerification {
+	/// The signatures of every message are verified, as is done for gossip received from our
+	/// peers. Messages with an invalid signature are skipped.
+	Full,
+	/// Only the signatures of a random sample of roughly one in `one_in` messages are verified,
+	/// which is much cheaper on low-end devices syncing a full gossip dump. This must only be used
+	/// for sources which are trusted to provide valid gossip, e.g. a gossip server we operate.
+	///
+	/// If any sampled signature in a batch is invalid, the source can no longer be trusted and the
+	/// whole batch is rejected. Messages whose signatures were not verified are applied as if they
+	/// were unsigned, and are thus not relayed to our peers.
+	Sampled {
+		/// The inverse of the rate at which messages are sampled. Values of 0 and 1 verify every
+		/// message.
+		one_in: u32,
+	},
+}
+
+impl GossipVerification {
+	/// Whether the signature of a message with the given serialized contents is verified.
+	fn verifies(&self, random_seed_bytes: &[u8; 32], contents: &[u8]) -> bool {
+		match self {
+			GossipVerification::Full => true,
+			GossipVerification::Sampled { one_in } => {
+				let mut engine = Sha256::engine();
+				engine.input(&random_seed_bytes[..]);
+				engine.input(contents);
+				let hash = Sha256::from_engine(engine).into_inner();
+				let sample = u32::from_be_bytes([hash[0], hash[1], hash[2], hash[3]]);
+				sample % cmp::max(*one_in, 1) == 0
+			},
+		}
+	}
+}
+
+/// The state of the signatures of a message in a batch passed to
+/// [`NetworkGraph::update_from_gossip_batch`].
+enum BatchSignature {
+	/// The signatures have yet to be checked, against the keys of the given channel endpoints for
+	/// channel updates.
+	Unchecked { channel_nodes: Option<(NodeId, NodeId)> },
+	/// The signatures are valid, or are those of a message we already have.
+	Valid,
+	/// The signatures were not sampled for verification.
+	Unverified,
+	/// The signatures are invalid.
+	Invalid,
+	/// The signatures were not checked as the message fails to apply anyway.
+	Skipped,
+}
+
+/// A signed gossip message to apply to a [`NetworkGraph`] as part of a batch, see
+/// [`NetworkGraph::update_from_gossip_batch`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GossipMessage {
+	/// A [`ChannelAnnouncement`].
+	ChannelAnnouncement(ChannelAnnouncement),
+	/// A [`NodeAnnouncement`].
+	NodeAnnouncement(NodeAnnouncement),
+	/// A [`ChannelUpdate`].
+	ChannelUpdate(ChannelUpdate),
+}
+
+/// The outcome of applying a batch of gossip messages via
+/// [`NetworkGraph::update_from_gossip_batch`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GossipBatchResult {
+	/// The number of messages which were applied to the graph.
+	pub applied: usize,
+	/// The number of messages which were not applied, e.g. as they were outdated, referred to an
+	/// unknown channel or, with [`GossipVerification::Full`], had an invalid signature.
+	pub ignored: usize,
+	/// The number of messages whose signatures were verified. This excludes messages which we
+	/// already had and those which were skipped as they would not have been applied anyway.
+	pub signatures_verified: usize,
+}
+
 macro_rules! secp_verify_sig {
 	( $secp_ctx: expr, $msg: expr, $sig: expr, $pubkey: expr, $msg_type: expr ) => {
 		match $secp_ctx.verify_ecdsa($msg, $sig, $pubkey) {
@@ -413,6 +594,10 @@ impl<G: Deref<Target=NetworkGraph<L>>, U: Deref, L: Deref> RoutingMessageHandler
 where U::Target: UtxoLookup, L::Target: Logger
 {
 	fn handle_node_announcement(&self, msg: &msgs::NodeAnnouncement) -> Result<bool, LightningError> {
+		if self.skips_signature(&msg.contents) {
+			self.network_graph.update_node_from_unsigned_announcement(&msg.contents)?;
+			return Ok(false);
+		}
 		self.network_graph.update_node_from_announcement(msg)?;
 		Ok(msg.contents.excess_data.len() <=  MAX_EXCESS_BYTES_FOR_RELAY &&
 		   msg.contents.excess_address_data.len() <= MAX_EXCESS_BYTES_FOR_RELAY &&
@@ -420,11 +605,27 @@ where U::Target: UtxoLookup, L::Target: Logger
 	}
 
 	fn handle_channel_announcement(&self, msg: &msgs::ChannelAnnouncement) -> Result<bool, LightningError> {
+		if self.skips_signature(&msg.contents) {
+			self.network_graph.update_channel_from_unsigned_announcement(&msg.contents, &self.utxo_lookup)?;
+			return Ok(false);
+		}
 		self.network_graph.update_channel_from_announcement(msg, &self.utxo_lookup)?;
 		Ok(msg.contents.excess_data.len() <= MAX_EXCESS_BYTES_FOR_RELAY)
 	}
 
+	#[cfg(taproot)]
+	fn handle_channel_announcement_2(&self, msg: &msgs::ChannelAnnouncement2) -> Result<bool, LightningError> {
+		self.network_graph.update_channel_from_announcement_2(msg, &self.utxo_lookup)?;
+		// We don't store `channel_announcement_2`s, so can't serve them to peers syncing from us
+		// either, thus we don't relay them for now.
+		Ok(false)
+	}
+
 	fn handle_channel_update(&self, msg: &msgs::ChannelUpdate) -> Result<bool, LightningError> {
+		if self.skips_signature(&msg.contents) {
+			self.network_graph.update_channel_unsigned(&msg.contents)?;
+			return Ok(false);
+		}
 		self.network_graph.update_channel(msg)?;
 		Ok(msg.contents.excess_data.len() <= MAX_EXCESS_BYTES_FOR_RELAY)
 	}
@@ -829,6 +1030,9 @@ pub struct ChannelInfo {
 	/// (which we can probably assume we are - no-std environments probably won't have a full
 	/// network graph in memory!).
 	announcement_received_time: u64,
+	/// The number of times a `channel_update` disabled a previously-enabled direction of the
+	/// channel since we learned about it.
+	disabled_count: u32,
 }
 
 impl ChannelInfo {
@@ -871,6 +1075,12 @@ impl ChannelInfo {
 			self.two_to_one.as_ref()
 		}
 	}
+
+	/// Returns the number of times we received a `channel_update` disabling a previously-enabled
+	/// direction of the channel, which indicates how often its peers went offline.
+	pub fn disabled_count(&self) -> u32 {
+		self.disabled_count
+	}
 }
 
 impl fmt::Display for ChannelInfo {
@@ -883,6 +1093,7 @@ impl fmt::Display for ChannelInfo {
 
 impl Writeable for ChannelInfo {
 	fn write<W: crate::util::ser::Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
+		let disabled_count = if self.disabled_count == 0 { None } else { Some(self.disabled_count) };
 		write_tlv_fields!(writer, {
 			(0, self.features, required),
 			(1, self.announcement_received_time, (default_value, 0)),
@@ -892,6 +1103,7 @@ impl Writeable for ChannelInfo {
 			(8, self.two_to_one, required),
 			(10, self.capacity_sats, required),
 			(12, self.announcement_message, required),
+			(13, disabled_count, option),
 		});
 		Ok(())
 	}
@@ -925,6 +1137,7 @@ impl Readable for ChannelInfo {
 		let mut two_to_one_wrap: Option<ChannelUpdateInfoDeserWrapper> = None;
 		_init_tlv_field_var!(capacity_sats, required);
 		_init_tlv_field_var!(announcement_message, required);
+		_init_tlv_field_var!(disabled_count, (default_value, 0));
 		read_tlv_fields!(reader, {
 			(0, features, required),
 			(1, announcement_received_time, (default_value, 0)),
@@ -934,6 +1147,7 @@ impl Readable for ChannelInfo {
 			(8, two_to_one_wrap, upgradable_option),
 			(10, capacity_sats, required),
 			(12, announcement_message, required),
+			(13, disabled_count, (default_value, 0)),
 		});
 
 		Ok(ChannelInfo {
@@ -945,6 +1159,7 @@ impl Readable for ChannelInfo {
 			capacity_sats: _init_tlv_based_struct_field!(capacity_sats, required),
 			announcement_message: _init_tlv_based_struct_field!(announcement_message, required),
 			announcement_received_time: _init_tlv_based_struct_field!(announcement_received_time, (default_value, 0)),
+			disabled_count: _init_tlv_based_struct_field!(disabled_count, (default_value, 0)),
 		})
 	}
 }
@@ -1099,7 +1314,9 @@ pub struct NodeAnnouncementInfo {
 	/// Mostly redundant with the data we store in fields explicitly.
 	/// Everything else is useful only for sending out for initial routing sync.
 	/// Not stored if contains excess data to prevent DoS.
-	pub announcement_message: Option<NodeAnnouncement>
+	pub announcement_message: Option<NodeAnnouncement>,
+	/// The rates at which the node offers to lease inbound liquidity, if it advertised any.
+	pub will_fund: Option<LeaseRates>,
 }
 
 impl NodeAnnouncementInfo {
@@ -1121,6 +1338,7 @@ impl Writeable for NodeAnnouncementInfo {
 			(6, self.alias, required),
 			(8, self.announcement_message, option),
 			(10, empty_addresses, vec_type), // Versions prior to 0.0.115 require this field
+			(11, self.will_fund, option),
 		});
 		Ok(())
 	}
@@ -1135,10 +1353,11 @@ impl Readable for NodeAnnouncementInfo {
 			(6, alias, required),
 			(8, announcement_message, option),
 			(10, _addresses, vec_type), // deprecated, not used anymore
+			(11, will_fund, option),
 		});
 		let _: Option<Vec<NetAddress>> = _addresses;
 		Ok(Self { features: features.0.unwrap(), last_update: last_update.0.unwrap(), rgb: rgb.0.unwrap(),
-			alias: alias.0.unwrap(), announcement_message })
+			alias: alias.0.unwrap(), announcement_message, will_fund })
     }
 }
 
@@ -1317,6 +1536,8 @@ impl<L: Deref> ReadableArgs<L> for NetworkGraph<L> where L::Target: Logger {
 			removed_nodes: Mutex::new(HashMap::new()),
 			removed_channels: Mutex::new(HashMap::new()),
 			pending_checks: utxo::PendingChecks::new(),
+			offloaded_channels: Mutex::new(HashMap::new()),
+			disabled_channels: Mutex::new(DisabledChannelLog::new()),
 		})
 	}
 }
@@ -1349,6 +1570,341 @@ impl<L: Deref> PartialEq for NetworkGraph<L> where L::Target: Logger {
 	}
 }
 
+/// The key prefix under which an [`OffloadedChannelStore`] stores offloaded channels.
+pub const OFFLOADED_CHANNELS_KEY_PREFIX: &str = "network_graph/channels/";
+
+/// The key prefix under which an [`OffloadedChannelStore`] stores the offloaded channels of each
+/// node.
+pub const OFFLOADED_NODES_KEY_PREFIX: &str = "network_graph/nodes/";
+
+fn offloaded_channel_key(short_channel_id: u64) -> String {
+	format!("{}{}", OFFLOADED_CHANNELS_KEY_PREFIX, short_channel_id)
+}
+
+fn offloaded_node_key(node_id: &NodeId) -> String {
+	format!("{}{}", OFFLOADED_NODES_KEY_PREFIX, node_id)
+}
+
+/// A storage backend for a [`NetworkGraph`] on low-memory devices which cannot hold the full graph
+/// in memory, keeping the cold channels in a KV store.
+///
+/// Only the hot subgraph, e.g. the channels near our own node or the nodes we commonly pay, should
+/// be kept in memory, with [`Self::load_subgraph`] reloading the channels around a recipient from
+/// the store before routing to it, as done by an [`OffloadingRouter`]. This trades additional
+/// latency when routing to rarely used destinations for a much smaller resident set.
+///
+/// Each offloaded [`ChannelInfo`] is written at [`OFFLOADED_CHANNELS_KEY_PREFIX`] followed by its
+/// short channel id, and each node with offloaded channels has a [`NodeInfo`] listing them written
+/// at [`OFFLOADED_NODES_KEY_PREFIX`] followed by its [`NodeId`]. As [`KVStorePersister`] does not
+/// support removing keys, pruned channels are overwritten with an empty value.
+///
+/// The store itself is stateless, with the [`NetworkGraph`] tracking which of its channels are
+/// offloaded and queueing the gossip updates received for them, which are applied to the store by
+/// [`Self::apply_pending_updates`] or once the channel is loaded. No I/O is done while holding the
+/// graph's locks. As the [`NetworkGraph`] does not persist which channels are offloaded,
+/// [`Self::restore`] has to be called after reading it on startup.
+///
+/// [`OffloadingRouter`]: crate::routing::router::OffloadingRouter
+pub struct OffloadedChannelStore<K: Deref> where K::Target: KVStorePersister + KVStoreReader {
+	store: K,
+}
+
+impl<K: Deref> OffloadedChannelStore<K> where K::Target: KVStorePersister + KVStoreReader {
+	/// Creates a new [`OffloadedChannelStore`] backed by the given store.
+	pub fn new(store: K) -> Self {
+		Self { store }
+	}
+
+	fn read_entry<T: Readable>(&self, key: &str) -> io::Result<Option<T>> {
+		match self.store.read(key)? {
+			// Pruned entries are overwritten with an empty value.
+			Some(bytes) if bytes.is_empty() => Ok(None),
+			Some(bytes) => T::read(&mut &bytes[..]).map(Some)
+				.map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid offloaded network graph entry")),
+			None => Ok(None),
+		}
+	}
+
+	fn read_node_record(&self, node_id: &NodeId) -> io::Result<NodeInfo> {
+		Ok(self.read_entry(&offloaded_node_key(node_id))?
+			.unwrap_or(NodeInfo { channels: Vec::new(), announcement_info: None }))
+	}
+
+	/// Registers the channels in the store with the given graph as offloaded, returning their
+	/// number. Must be called after reading the graph on startup, as otherwise gossip updates for
+	/// the offloaded channels are dropped.
+	pub fn restore<L: Deref>(&self, network_graph: &NetworkGraph<L>) -> io::Result<usize>
+	where L::Target: Logger {
+		let mut short_channel_ids = Vec::new();
+		for key in self.store.list(OFFLOADED_CHANNELS_KEY_PREFIX)? {
+			let short_channel_id = match key.get(OFFLOADED_CHANNELS_KEY_PREFIX.len()..).map(|id| id.parse::<u64>()) {
+				Some(Ok(short_channel_id)) if key.starts_with(OFFLOADED_CHANNELS_KEY_PREFIX) => short_channel_id,
+				_ => continue,
+			};
+			if self.read_entry::<ChannelInfo>(&key)?.is_some() {
+				short_channel_ids.push(short_channel_id);
+			}
+		}
+
+		let channels = network_graph.channels.read().unwrap();
+		let mut offloaded_channels = network_graph.offloaded_channels.lock().unwrap();
+		let mut restored = 0;
+		for short_channel_id in short_channel_ids {
+			// Channels announced again since they were offloaded are tracked in memory.
+			if channels.contains_key(&short_channel_id) { continue; }
+			offloaded_channels.entry(short_channel_id).or_insert([None, None]);
+			restored += 1;
+		}
+		Ok(restored)
+	}
+
+	/// Moves all channels for which `keep_in_memory` returns false out of the graph's memory and
+	/// into the store, returning the number of channels offloaded.
+	///
+	/// The nodes of offloaded channels are kept in memory, so that their announcements continue to
+	/// be tracked. Channels updated while they are being persisted are kept in memory until they
+	/// are offloaded again. If persisting fails, the in-memory graph is left unchanged.
+	pub fn offload_channels<L: Deref, F: Fn(u64, &ChannelInfo) -> bool>(
+		&self, network_graph: &NetworkGraph<L>, keep_in_memory: F
+	) -> io::Result<usize> where L::Target: Logger {
+		let cold_channels: Vec<(u64, ChannelInfo)> = network_graph.read_only().channels().unordered_iter()
+			.filter(|(short_channel_id, channel)| !keep_in_memory(**short_channel_id, channel))
+			.map(|(short_channel_id, channel)| (*short_channel_id, channel.clone()))
+			.collect();
+
+		let mut node_records: HashMap<NodeId, NodeInfo> = HashMap::new();
+		for (short_channel_id, channel) in cold_channels.iter() {
+			self.store.persist(&offloaded_channel_key(*short_channel_id), channel)?;
+			for node_id in [channel.node_one, channel.node_two].iter() {
+				let record = match node_records.entry(*node_id) {
+					hash_map::Entry::Occupied(entry) => entry.into_mut(),
+					hash_map::Entry::Vacant(entry) => entry.insert(self.read_node_record(node_id)?),
+				};
+				if !record.channels.contains(short_channel_id) {
+					record.channels.push(*short_channel_id);
+				}
+			}
+		}
+		for (node_id, record) in node_records.iter() {
+			self.store.persist(&offloaded_node_key(node_id), record)?;
+		}
+
+		let mut channels = network_graph.channels.write().unwrap();
+		let mut nodes = network_graph.nodes.write().unwrap();
+		let mut offloaded_channels = network_graph.offloaded_channels.lock().unwrap();
+		let mut offloaded = 0;
+		for (short_channel_id, channel) in cold_channels.iter() {
+			if channels.get(short_channel_id) != Some(channel) { continue; }
+			channels.remove(short_channel_id);
+			for node_id in [channel.node_one, channel.node_two].iter() {
+				if let Some(node) = nodes.get_mut(node_id) {
+					node.channels.retain(|id| id != short_channel_id);
+				}
+			}
+			offloaded_channels.insert(*short_channel_id, [None, None]);
+			offloaded += 1;
+		}
+		Ok(offloaded)
+	}
+
+	/// Loads the offloaded channels within `max_hops` hops of the given node back into the graph's
+	/// memory, applying any updates queued for them, and returns the number of channels loaded.
+	pub fn load_subgraph<L: Deref>(
+		&self, network_graph: &NetworkGraph<L>, node_id: &NodeId, max_hops: u8
+	) -> io::Result<usize> where L::Target: Logger {
+		let mut loaded = 0;
+		let mut visited_nodes = HashSet::new();
+		visited_nodes.insert(*node_id);
+		let mut frontier = vec![*node_id];
+		for _ in 0..max_hops {
+			if frontier.is_empty() { break; }
+
+			let mut stored_channels = Vec::new();
+			for current_node_id in frontier.iter() {
+				let record = self.read_node_record(current_node_id)?;
+				let offloaded_ids: Vec<u64> = {
+					let offloaded_channels = network_graph.offloaded_channels.lock().unwrap();
+					record.channels.into_iter().filter(|id| offloaded_channels.contains_key(id)).collect()
+				};
+				for short_channel_id in offloaded_ids {
+					if let Some(channel) = self.read_entry::<ChannelInfo>(&offloaded_channel_key(short_channel_id))? {
+						stored_channels.push((short_channel_id, channel));
+					}
+				}
+			}
+
+			let mut channels = network_graph.channels.write().unwrap();
+			let mut nodes = network_graph.nodes.write().unwrap();
+			let mut offloaded_channels = network_graph.offloaded_channels.lock().unwrap();
+			for (short_channel_id, mut channel) in stored_channels {
+				// Skip channels loaded or announced again while we were reading them.
+				let pending_updates = match offloaded_channels.remove(&short_channel_id) {
+					Some(pending_updates) => pending_updates,
+					None => continue,
+				};
+				if channels.contains_key(&short_channel_id) { continue; }
+				for update in pending_updates.iter().filter_map(|update| update.as_ref()) {
+					if let Err(e) = network_graph.apply_channel_update(
+						&mut channel, &update.contents, update.full_msg.as_ref(), update.sig.as_ref()
+					) {
+						log_gossip!(network_graph.logger, "Dropped update for offloaded channel {}: {}", short_channel_id, e.err);
+					}
+				}
+				for node_id in [channel.node_one, channel.node_two].iter() {
+					match nodes.entry(*node_id) {
+						IndexedMapEntry::Occupied(node_entry) => {
+							node_entry.into_mut().channels.push(short_channel_id);
+						},
+						IndexedMapEntry::Vacant(node_entry) => {
+							node_entry.insert(NodeInfo {
+								channels: vec!(short_channel_id),
+								announcement_info: None,
+							});
+						}
+					}
+				}
+				channels.insert(short_channel_id, channel);
+				loaded += 1;
+			}
+
+			let mut next_frontier = Vec::new();
+			for current_node_id in frontier.drain(..) {
+				let node = match nodes.get(&current_node_id) {
+					Some(node) => node,
+					None => continue,
+				};
+				for short_channel_id in node.channels.iter() {
+					if let Some(channel) = channels.get(short_channel_id) {
+						let counterparty_node_id = if channel.node_one == current_node_id {
+							channel.node_two
+						} else {
+							channel.node_one
+						};
+						if visited_nodes.insert(counterparty_node_id) {
+							next_frontier.push(counterparty_node_id);
+						}
+					}
+				}
+			}
+			frontier = next_frontier;
+		}
+		Ok(loaded)
+	}
+
+	/// Applies the gossip updates the graph queued for offloaded channels to the store, returning
+	/// the number of updates applied.
+	///
+	/// Updates which fail to apply, e.g. because of an invalid signature, are dropped, as are the
+	/// updates of a channel which fails to be persisted.
+	pub fn apply_pending_updates<L: Deref>(&self, network_graph: &NetworkGraph<L>) -> io::Result<usize>
+	where L::Target: Logger {
+		let pending_updates: Vec<(u64, Vec<PendingOffloadedUpdate>)> = network_graph.offloaded_channels
+			.lock().unwrap().iter_mut()
+			.filter_map(|(short_channel_id, pending_updates)| {
+				let updates: Vec<_> = pending_updates.iter_mut().filter_map(|update| update.take()).collect();
+				if updates.is_empty() { None } else { Some((*short_channel_id, updates)) }
+			})
+			.collect();
+
+		let mut applied = 0;
+		for (short_channel_id, updates) in pending_updates {
+			let key = offloaded_channel_key(short_channel_id);
+			let mut channel = match self.read_entry::<ChannelInfo>(&key)? {
+				Some(channel) => channel,
+				None => continue,
+			};
+			let mut updated = false;
+			for update in updates.iter() {
+				match network_graph.apply_channel_update(
+					&mut channel, &update.contents, update.full_msg.as_ref(), update.sig.as_ref()
+				) {
+					Ok(()) => updated = true,
+					Err(e) => log_gossip!(network_graph.logger, "Dropped update for offloaded channel {}: {}", short_channel_id, e.err),
+				}
+			}
+			if !updated { continue; }
+			self.store.persist(&key, &channel)?;
+			applied += updates.len();
+
+			// The channel may have been loaded back into memory while we were applying its updates.
+			if !network_graph.offloaded_channels.lock().unwrap().contains_key(&short_channel_id) {
+				let mut channels = network_graph.channels.write().unwrap();
+				if let Some(channel) = channels.get_mut(&short_channel_id) {
+					for update in updates.iter() {
+						let _ = network_graph.apply_channel_update(
+							channel, &update.contents, update.full_msg.as_ref(), update.sig.as_ref());
+					}
+				}
+			}
+		}
+		Ok(applied)
+	}
+
+	/// Prunes the offloaded channels which the graph would prune if they were in memory, see
+	/// [`NetworkGraph::remove_stale_channels_and_tracking_with_time`], returning the number of
+	/// channels removed from the store.
+	pub fn remove_stale_channels_with_time<L: Deref>(
+		&self, network_graph: &NetworkGraph<L>, current_time_unix: u64
+	) -> io::Result<usize> where L::Target: Logger {
+		if current_time_unix > u32::max_value() as u64 { return Ok(0); } // Remove by 2106
+		if current_time_unix < STALE_CHANNEL_UPDATE_AGE_LIMIT_SECS { return Ok(0); }
+		let min_time_unix: u32 = (current_time_unix - STALE_CHANNEL_UPDATE_AGE_LIMIT_SECS) as u32;
+
+		let short_channel_ids: Vec<u64> = network_graph.offloaded_channels.lock().unwrap().keys().copied().collect();
+		let mut node_records: HashMap<NodeId, NodeInfo> = HashMap::new();
+		let mut removed = 0;
+		for short_channel_id in short_channel_ids {
+			let key = offloaded_channel_key(short_channel_id);
+			let mut channel = match self.read_entry::<ChannelInfo>(&key)? {
+				Some(channel) => channel,
+				None => continue,
+			};
+			let mut pruned = false;
+			if channel.one_to_two.as_ref().map_or(false, |update| update.last_update < min_time_unix) {
+				channel.one_to_two = None;
+				pruned = true;
+			}
+			if channel.two_to_one.as_ref().map_or(false, |update| update.last_update < min_time_unix) {
+				channel.two_to_one = None;
+				pruned = true;
+			}
+			let stale = (channel.one_to_two.is_none() || channel.two_to_one.is_none()) &&
+				channel.announcement_received_time < min_time_unix as u64;
+			if !stale {
+				if pruned { self.store.persist(&key, &channel)?; }
+				continue;
+			}
+
+			// Leave channels loaded back into memory to be pruned there.
+			if network_graph.offloaded_channels.lock().unwrap().remove(&short_channel_id).is_none() {
+				continue;
+			}
+			self.store.persist(&key, &WithoutLength(&Vec::<u8>::new()))?;
+			for node_id in [channel.node_one, channel.node_two].iter() {
+				let record = match node_records.entry(*node_id) {
+					hash_map::Entry::Occupied(entry) => entry.into_mut(),
+					hash_map::Entry::Vacant(entry) => entry.insert(self.read_node_record(node_id)?),
+				};
+				record.channels.retain(|id| *id != short_channel_id);
+			}
+			network_graph.removed_channels.lock().unwrap().insert(short_channel_id, Some(current_time_unix));
+			removed += 1;
+		}
+		for (node_id, record) in node_records.iter() {
+			self.store.persist(&offloaded_node_key(node_id), record)?;
+		}
+
+		// Nodes left without any channels are removed, as when pruning the graph's memory.
+		let mut nodes = network_graph.nodes.write().unwrap();
+		for (node_id, record) in node_records.iter() {
+			if record.channels.is_empty() && nodes.get(node_id).map_or(false, |node| node.channels.is_empty()) {
+				nodes.remove(node_id);
+			}
+		}
+		Ok(removed)
+	}
+}
+
 impl<L: Deref> NetworkGraph<L> where L::Target: Logger {
 	/// Creates a new, empty, network graph.
 	pub fn new(network: Network, logger: L) -> NetworkGraph<L> {
@@ -1362,19 +1918,66 @@ impl<L: Deref> NetworkGraph<L> where L::Target: Logger {
 			removed_channels: Mutex::new(HashMap::new()),
 			removed_nodes: Mutex::new(HashMap::new()),
 			pending_checks: utxo::PendingChecks::new(),
+			offloaded_channels: Mutex::new(HashMap::new()),
+			disabled_channels: Mutex::new(DisabledChannelLog::new()),
 		}
 	}
 
 	/// Returns a read-only view of the network graph.
+	///
+	/// The view is a snapshot, so holding it (e.g. for the duration of route finding) does not
+	/// block gossip from being applied to the graph. While a snapshot is held, updates to the
+	/// graph's channels or nodes copy the parts of the graph they touch, so snapshots should not be
+	/// kept around for longer than necessary.
 	pub fn read_only(&'_ self) -> ReadOnlyNetworkGraph<'_> {
+		// Hold both read locks while cloning so that we don't observe a write to only one map.
 		let channels = self.channels.read().unwrap();
 		let nodes = self.nodes.read().unwrap();
 		ReadOnlyNetworkGraph {
-			channels,
-			nodes,
+			channels: channels.clone(),
+			nodes: nodes.clone(),
+			_graph: PhantomData,
 		}
 	}
 
+	/// Informs the graph of the current best block height.
+	///
+	/// Successful [`UtxoLookup`] results are cached and reused when a channel is announced again.
+	/// Once a height has been provided here, cached results older than
+	/// [`DEFAULT_UTXO_REVALIDATION_INTERVAL_BLOCKS`] (or the interval set via
+	/// [`Self::set_utxo_revalidation_interval`]) go stale and the next announcement of the channel
+	/// causes its funding output to be looked up again, removing the channel from the graph if the
+	/// output has since been spent.
+	///
+	/// If this is never called, chain-validated channels are never revalidated.
+	///
+	/// [`DEFAULT_UTXO_REVALIDATION_INTERVAL_BLOCKS`]: crate::routing::utxo::DEFAULT_UTXO_REVALIDATION_INTERVAL_BLOCKS
+	pub fn best_block_updated(&self, height: u32) {
+		self.pending_checks.best_block_updated(height);
+	}
+
+	/// Sets the number of blocks after which cached [`UtxoLookup`] results go stale, see
+	/// [`Self::best_block_updated`].
+	pub fn set_utxo_revalidation_interval(&self, blocks: u32) {
+		self.pending_checks.set_revalidation_interval(blocks);
+	}
+
+	/// Returns the short channel ids of the channels a `channel_update` disabled since `cursor`,
+	/// once for each time a previously-enabled direction of a channel was disabled, along with the
+	/// cursor to pass to the next call.
+	///
+	/// Allows tracking how often channels go offline without scanning the whole graph, see also
+	/// [`ChannelInfo::disabled_count`]. Pass a `cursor` of 0 on the first call. Only the most
+	/// recent disables are remembered, so a caller falling far behind misses the oldest ones, and
+	/// none are persisted with the graph.
+	pub fn channels_disabled_since(&self, cursor: u64) -> (Vec<u64>, u64) {
+		let log = self.disabled_channels.lock().unwrap();
+		let first_seq = log.next_seq - log.short_channel_ids.len() as u64;
+		// A cursor past the end was handed out by a previous instance of the graph.
+		let skip = if cursor > log.next_seq { 0 } else { cursor.saturating_sub(first_seq) as usize };
+		(log.short_channel_ids.iter().skip(skip).cloned().collect(), log.next_seq)
+	}
+
 	/// The unix timestamp provided by the most recent rapid gossip sync.
 	/// It will be set by the rapid sync process after every sync completion.
 	pub fn get_last_rapid_gossip_sync_timestamp(&self) -> Option<u32> {
@@ -1387,6 +1990,22 @@ impl<L: Deref> NetworkGraph<L> where L::Target: Logger {
 		self.last_rapid_gossip_sync_timestamp.lock().unwrap().replace(last_rapid_gossip_sync_timestamp);
 	}
 
+	/// Returns the addresses at which the peer with the given `node_id` may be reached.
+	///
+	/// These are the addresses from the peer's latest node_announcement or, if the peer has not
+	/// announced any, those given by `resolver`. Use an [`IgnoringMessageHandler`] as `resolver`
+	/// to only consider announced addresses.
+	///
+	/// [`IgnoringMessageHandler`]: crate::ln::peer_handler::IgnoringMessageHandler
+	pub fn peer_addresses<R: Deref>(&self, node_id: &PublicKey, resolver: &R) -> Vec<NetAddress>
+	where R::Target: PeerAddressResolver {
+		let announced_addresses = self.read_only().get_addresses(node_id).unwrap_or(Vec::new());
+		if !announced_addresses.is_empty() {
+			return announced_addresses;
+		}
+		resolver.resolve_addresses(node_id)
+	}
+
 	/// Clears the `NodeAnnouncementInfo` field for all nodes in the `NetworkGraph` for testing
 	/// purposes.
 	#[cfg(test)]
@@ -1396,6 +2015,96 @@ impl<L: Deref> NetworkGraph<L> where L::Target: Logger {
 		}
 	}
 
+	/// Writes the channels in the graph, along with the latest update in each direction, in the
+	/// portable [`interchange`] format.
+	///
+	/// Unlike the [`Writeable`] serialization of the graph, the output can be read by other LDK
+	/// versions via [`Self::read_interchange`] as well as by external tooling. Node announcements
+	/// and the original gossip messages are not included.
+	pub fn write_interchange(&self) -> String {
+		let graph = self.read_only();
+		let mut scids: Vec<u64> = graph.channels().unordered_keys().map(|scid| *scid).collect();
+		scids.sort_unstable();
+
+		let mut out = interchange::write_header(interchange::NETWORK_GRAPH_KIND);
+		for scid in scids {
+			let channel = graph.channels().get(&scid).unwrap();
+			let capacity_sats = channel.capacity_sats.map_or("-".to_owned(), |capacity| capacity.to_string());
+			out += &format!("channel {} {} {} {} {}\n", scid, channel.node_one, channel.node_two,
+				capacity_sats, channel.announcement_received_time);
+			for (direction, info) in [&channel.one_to_two, &channel.two_to_one].iter().enumerate() {
+				if let Some(info) = info {
+					out += &format!("direction {} {} {} {} {} {} {} {} {}\n", scid, direction,
+						info.last_update, interchange::write_bool(info.enabled), info.cltv_expiry_delta,
+						info.htlc_minimum_msat, info.htlc_maximum_msat, info.fees.base_msat,
+						info.fees.proportional_millionths);
+				}
+			}
+		}
+		out
+	}
+
+	/// Adds the channels in `data`, as written by [`Self::write_interchange`], to the graph.
+	///
+	/// Channels which are already known are left as-is rather than being overwritten. If `data` is
+	/// malformed, an error is returned and the graph is not modified.
+	pub fn read_interchange(&self, data: &str) -> Result<(), InterchangeError> {
+		let mut channels: Vec<(u64, ChannelInfo)> = Vec::new();
+		for record in interchange::read_records(data, interchange::NETWORK_GRAPH_KIND)? {
+			match record.record_type() {
+				"channel" => {
+					let node_one: PublicKey = record.field(2)?;
+					let node_two: PublicKey = record.field(3)?;
+					if node_one == node_two {
+						return Err(record.error());
+					}
+					channels.push((record.field(1)?, ChannelInfo {
+						features: ChannelFeatures::empty(),
+						node_one: NodeId::from_pubkey(&node_one),
+						one_to_two: None,
+						node_two: NodeId::from_pubkey(&node_two),
+						two_to_one: None,
+						capacity_sats: record.optional_field(4)?,
+						announcement_message: None,
+						announcement_received_time: record.field(5)?,
+						disabled_count: 0,
+					}));
+				},
+				"direction" => {
+					let scid: u64 = record.field(1)?;
+					let channel = channels.last_mut()
+						.filter(|(last_scid, _)| *last_scid == scid)
+						.map(|(_, channel)| channel)
+						.ok_or(record.error())?;
+					let info = ChannelUpdateInfo {
+						last_update: record.field(3)?,
+						enabled: record.bool_field(4)?,
+						cltv_expiry_delta: record.field(5)?,
+						htlc_minimum_msat: record.field(6)?,
+						htlc_maximum_msat: record.field(7)?,
+						fees: RoutingFees {
+							base_msat: record.field(8)?,
+							proportional_millionths: record.field(9)?,
+						},
+						last_update_message: None,
+					};
+					match record.field::<u8>(2)? {
+						0 => channel.one_to_two = Some(info),
+						1 => channel.two_to_one = Some(info),
+						_ => return Err(record.error()),
+					}
+				},
+				_ => {},
+			}
+		}
+
+		for (scid, channel) in channels {
+			// We only fail if the channel is already known, in which case we keep what we have.
+			let _ = self.add_channel_between_nodes(scid, channel, None);
+		}
+		Ok(())
+	}
+
 	/// For an already known node (from channel announcements), update its stored properties from a
 	/// given node announcement.
 	///
@@ -1403,9 +2112,14 @@ impl<L: Deref> NetworkGraph<L> where L::Target: Logger {
 	/// RoutingMessageHandler implementation to call it indirectly. This may be useful to accept
 	/// routing messages from a source using a protocol other than the lightning P2P protocol.
 	pub fn update_node_from_announcement(&self, msg: &msgs::NodeAnnouncement) -> Result<(), LightningError> {
+		self.verify_node_announcement(msg)?;
+		self.update_node_from_announcement_intern(&msg.contents, Some(&msg))
+	}
+
+	fn verify_node_announcement(&self, msg: &msgs::NodeAnnouncement) -> Result<(), LightningError> {
 		let msg_hash = hash_to_message!(&Sha256dHash::hash(&msg.contents.encode()[..])[..]);
 		secp_verify_sig!(self.secp_ctx, &msg_hash, &msg.signature, &get_pubkey_from_node_id!(msg.contents.node_id, "node_announcement"), "node_announcement");
-		self.update_node_from_announcement_intern(&msg.contents, Some(&msg))
+		Ok(())
 	}
 
 	/// For an already known node (from channel announcements), update its stored properties from a
@@ -1446,6 +2160,7 @@ impl<L: Deref> NetworkGraph<L> where L::Target: Logger {
 					rgb: msg.rgb,
 					alias: msg.alias,
 					announcement_message: if should_relay { full_msg.cloned() } else { None },
+					will_fund: msg.will_fund(),
 				});
 
 				Ok(())
@@ -1467,12 +2182,81 @@ impl<L: Deref> NetworkGraph<L> where L::Target: Logger {
 	where
 		U::Target: UtxoLookup,
 	{
+		self.verify_channel_announcement(msg)?;
+		self.update_channel_from_unsigned_announcement_intern(&msg.contents, Some(msg), utxo_lookup, false)
+	}
+
+	/// Store or update channel info from an experimental `channel_announcement_2` message, see
+	/// [`msgs::UnsignedChannelAnnouncement2`].
+	///
+	/// Only announcements including both funding keys and no `merkle_root_hash` are supported,
+	/// i.e. those of channels funded by either a P2WSH output or a simple taproot channel's P2TR
+	/// output. The latter are only accepted via this message, never via a `channel_announcement`.
+	/// The announcement itself isn't stored, so the channel is not relayed to our peers.
+	///
+	/// If a [`UtxoLookup`] object is provided via `utxo_lookup`, it will be called to verify
+	/// the corresponding UTXO exists on chain and is correctly-formatted.
+	#[cfg(taproot)]
+	pub fn update_channel_from_announcement_2<U: Deref>(
+		&self, msg: &msgs::ChannelAnnouncement2, utxo_lookup: &Option<U>,
+	) -> Result<(), LightningError>
+	where
+		U::Target: UtxoLookup,
+	{
+		let contents = &msg.contents;
+		let (bitcoin_key_1, bitcoin_key_2) = match (contents.bitcoin_key_1, contents.bitcoin_key_2) {
+			(Some(bitcoin_key_1), Some(bitcoin_key_2)) if contents.merkle_root_hash.is_none() =>
+				(bitcoin_key_1, bitcoin_key_2),
+			_ => return Err(LightningError {
+				err: "Unsupported channel_announcement_2 without funding keys or with a script tree".to_owned(),
+				action: ErrorAction::IgnoreAndLog(Level::Gossip),
+			}),
+		};
+
+		let unsigned_announcement = msgs::UnsignedChannelAnnouncement {
+			features: contents.features.clone(),
+			chain_hash: contents.chain_hash,
+			short_channel_id: contents.short_channel_id,
+			node_id_1: contents.node_id_1,
+			node_id_2: contents.node_id_2,
+			bitcoin_key_1,
+			bitcoin_key_2,
+			excess_data: Vec::new(),
+		};
+		// Aggregating the keys and verifying the signature is comparatively expensive, so check
+		// whether we'd accept the channel at all first.
+		let revalidating_utxo = self.pre_channel_announcement_validation_check(&unsigned_announcement, utxo_lookup)?;
+
+		let mut signing_keys = Vec::with_capacity(4);
+		for node_id in [contents.node_id_1, contents.node_id_2, bitcoin_key_1, bitcoin_key_2].iter() {
+			signing_keys.push(get_pubkey_from_node_id!(node_id, "channel_announcement_2"));
+		}
+		let invalid_signature = || LightningError {
+			err: "Invalid signature on channel_announcement_2 message".to_owned(),
+			action: ErrorAction::SendWarningMessage {
+				msg: msgs::WarningMessage {
+					channel_id: [0; 32],
+					data: "Invalid signature on channel_announcement_2 message".to_owned(),
+				},
+				log_level: Level::Trace,
+			},
+		};
+		let aggregate_key = aggregate_musig2_keys(&self.secp_ctx, &signing_keys)
+			.ok_or_else(invalid_signature)?;
+		let msg_hash = merkle::message_digest(CHANNEL_ANNOUNCEMENT_2_SIGNATURE_TAG, &contents.encode());
+		self.secp_ctx.verify_schnorr(&msg.signature, &msg_hash, &aggregate_key.x_only_public_key().0)
+			.map_err(|_| invalid_signature())?;
+
+		self.update_channel_from_checked_announcement_intern(&unsigned_announcement, None, utxo_lookup, revalidating_utxo, true)
+	}
+
+	fn verify_channel_announcement(&self, msg: &msgs::ChannelAnnouncement) -> Result<(), LightningError> {
 		let msg_hash = hash_to_message!(&Sha256dHash::hash(&msg.contents.encode()[..])[..]);
 		secp_verify_sig!(self.secp_ctx, &msg_hash, &msg.node_signature_1, &get_pubkey_from_node_id!(msg.contents.node_id_1, "channel_announcement"), "channel_announcement");
 		secp_verify_sig!(self.secp_ctx, &msg_hash, &msg.node_signature_2, &get_pubkey_from_node_id!(msg.contents.node_id_2, "channel_announcement"), "channel_announcement");
 		secp_verify_sig!(self.secp_ctx, &msg_hash, &msg.bitcoin_signature_1, &get_pubkey_from_node_id!(msg.contents.bitcoin_key_1, "channel_announcement"), "channel_announcement");
 		secp_verify_sig!(self.secp_ctx, &msg_hash, &msg.bitcoin_signature_2, &get_pubkey_from_node_id!(msg.contents.bitcoin_key_2, "channel_announcement"), "channel_announcement");
-		self.update_channel_from_unsigned_announcement_intern(&msg.contents, Some(msg), utxo_lookup)
+		Ok(())
 	}
 
 	/// Store or update channel info from a channel announcement.
@@ -1500,7 +2284,7 @@ impl<L: Deref> NetworkGraph<L> where L::Target: Logger {
 	where
 		U::Target: UtxoLookup,
 	{
-		self.update_channel_from_unsigned_announcement_intern(msg, None, utxo_lookup)
+		self.update_channel_from_unsigned_announcement_intern(msg, None, utxo_lookup, false)
 	}
 
 	/// Update channel from partial announcement data received via rapid gossip sync
@@ -1525,6 +2309,7 @@ impl<L: Deref> NetworkGraph<L> where L::Target: Logger {
 			capacity_sats: None,
 			announcement_message: None,
 			announcement_received_time: timestamp,
+			disabled_count: 0,
 		};
 
 		self.add_channel_between_nodes(short_channel_id, channel_info, None)
@@ -1561,6 +2346,9 @@ impl<L: Deref> NetworkGraph<L> where L::Target: Logger {
 				entry.insert(channel_info);
 			}
 		};
+		// A re-announced offloaded channel is tracked in memory again, with the copy in the store
+		// left to be overwritten if it is offloaded again.
+		self.offloaded_channels.lock().unwrap().remove(&short_channel_id);
 
 		for current_node_id in [node_id_a, node_id_b].iter() {
 			match nodes.entry(current_node_id.clone()) {
@@ -1579,9 +2367,26 @@ impl<L: Deref> NetworkGraph<L> where L::Target: Logger {
 		Ok(())
 	}
 
-	fn update_channel_from_unsigned_announcement_intern<U: Deref>(
-		&self, msg: &msgs::UnsignedChannelAnnouncement, full_msg: Option<&msgs::ChannelAnnouncement>, utxo_lookup: &Option<U>
+	/// Checks and adds the given announcement to the graph. If `allow_taproot_funding` is set, the
+	/// funding output may also be a simple taproot channel's P2TR output, which must only be the
+	/// case for announcements derived from a `channel_announcement_2`.
+	pub(super) fn update_channel_from_unsigned_announcement_intern<U: Deref>(
+		&self, msg: &msgs::UnsignedChannelAnnouncement, full_msg: Option<&msgs::ChannelAnnouncement>, utxo_lookup: &Option<U>,
+		allow_taproot_funding: bool
 	) -> Result<(), LightningError>
+	where
+		U::Target: UtxoLookup,
+	{
+		let revalidating_utxo = self.pre_channel_announcement_validation_check(msg, utxo_lookup)?;
+		self.update_channel_from_checked_announcement_intern(msg, full_msg, utxo_lookup, revalidating_utxo, allow_taproot_funding)
+	}
+
+	/// Checks whether we'd accept the channel in the given announcement at all before any
+	/// signatures are verified, returning whether we already know the channel and are only
+	/// re-validating its funding output.
+	fn pre_channel_announcement_validation_check<U: Deref>(
+		&self, msg: &msgs::UnsignedChannelAnnouncement, utxo_lookup: &Option<U>
+	) -> Result<bool, LightningError>
 	where
 		U::Target: UtxoLookup,
 	{
@@ -1596,6 +2401,7 @@ impl<L: Deref> NetworkGraph<L> where L::Target: Logger {
 			});
 		}
 
+		let mut revalidating_utxo = false;
 		{
 			let channels = self.channels.read().unwrap();
 
@@ -1612,11 +2418,20 @@ impl<L: Deref> NetworkGraph<L> where L::Target: Logger {
 					// We use the Node IDs rather than the bitcoin_keys to check for "equivalence"
 					// as we didn't (necessarily) store the bitcoin keys, and we only really care
 					// if the peers on the channel changed anyway.
+					//
+					// Once our cached lookup result for the channel goes stale, we use the
+					// duplicate announcement as a cue to check the funding output is still
+					// unspent.
 					if msg.node_id_1 == chan.node_one && msg.node_id_2 == chan.node_two {
-						return Err(LightningError {
-							err: "Already have chain-validated channel".to_owned(),
-							action: ErrorAction::IgnoreDuplicateGossip
-						});
+						if utxo_lookup.is_none() ||
+							!self.pending_checks.utxo_revalidation_due(msg.short_channel_id)
+						{
+							return Err(LightningError {
+								err: "Already have chain-validated channel".to_owned(),
+								action: ErrorAction::IgnoreDuplicateGossip
+							});
+						}
+						revalidating_utxo = true;
 					}
 				} else if utxo_lookup.is_none() {
 					// Similarly, if we can't check the chain right now anyway, ignore the
@@ -1640,9 +2455,40 @@ impl<L: Deref> NetworkGraph<L> where L::Target: Logger {
 					action: ErrorAction::IgnoreAndLog(Level::Gossip)});
 			}
 		}
+		Ok(revalidating_utxo)
+	}
 
-		let utxo_value = self.pending_checks.check_channel_announcement(
-			utxo_lookup, msg, full_msg)?;
+	/// Adds the channel in the given announcement, which passed
+	/// [`Self::pre_channel_announcement_validation_check`], to the graph once its funding output
+	/// has been checked.
+	fn update_channel_from_checked_announcement_intern<U: Deref>(
+		&self, msg: &msgs::UnsignedChannelAnnouncement, full_msg: Option<&msgs::ChannelAnnouncement>, utxo_lookup: &Option<U>,
+		revalidating_utxo: bool, allow_taproot_funding: bool
+	) -> Result<(), LightningError>
+	where
+		U::Target: UtxoLookup,
+	{
+		let utxo_value = match self.pending_checks.check_channel_announcement(
+			utxo_lookup, msg, full_msg, allow_taproot_funding, revalidating_utxo
+		) {
+			Ok(utxo_value) => utxo_value,
+			Err(e) => {
+				// Only prune the channel if the lookup found the funding output we previously
+				// validated to be gone (or no longer matching), not on any other failure, e.g. our
+				// chain source not knowing the chain.
+				if revalidating_utxo && self.pending_checks.take_spent_funding_output(msg.short_channel_id) {
+					log_gossip!(self.logger, "Removing channel {} as its funding output is no longer on-chain", msg.short_channel_id);
+					self.channel_failed_permanent(msg.short_channel_id);
+				}
+				return Err(e);
+			},
+		};
+		if revalidating_utxo {
+			return Err(LightningError {
+				err: "Already have chain-validated channel".to_owned(),
+				action: ErrorAction::IgnoreDuplicateGossip
+			});
+		}
 
 		#[allow(unused_mut, unused_assignments)]
 		let mut announcement_received_time = 0;
@@ -1661,6 +2507,7 @@ impl<L: Deref> NetworkGraph<L> where L::Target: Logger {
 			announcement_message: if msg.excess_data.len() <= MAX_EXCESS_BYTES_FOR_RELAY
 				{ full_msg.cloned() } else { None },
 			announcement_received_time,
+			disabled_count: 0,
 		};
 
 		self.add_channel_between_nodes(msg.short_channel_id, chan_info, utxo_value)?;
@@ -1770,13 +2617,22 @@ impl<L: Deref> NetworkGraph<L> where L::Target: Logger {
 		// Sadly BTreeMap::retain was only stabilized in 1.53 so we can't switch to it for some
 		// time.
 		let mut scids_to_remove = Vec::new();
-		for (scid, info) in channels.unordered_iter_mut() {
+		// Only mutate the channels with stale updates, so that we don't copy the parts of the
+		// graph shared with snapshots which don't need to change.
+		let stale_scids: Vec<u64> = channels.unordered_iter().filter(|(_, info)| {
+			info.one_to_two.as_ref().map_or(false, |update| update.last_update < min_time_unix) ||
+				info.two_to_one.as_ref().map_or(false, |update| update.last_update < min_time_unix)
+		}).map(|(scid, _)| *scid).collect();
+		for scid in stale_scids {
+			let info = channels.get_mut(&scid).expect("We just accessed this scid, it should be present");
 			if info.one_to_two.is_some() && info.one_to_two.as_ref().unwrap().last_update < min_time_unix {
 				info.one_to_two = None;
 			}
 			if info.two_to_one.is_some() && info.two_to_one.as_ref().unwrap().last_update < min_time_unix {
 				info.two_to_one = None;
 			}
+		}
+		for (scid, info) in channels.unordered_iter() {
 			if info.one_to_two.is_none() || info.two_to_one.is_none() {
 				// We check the announcement_received_time here to ensure we don't drop
 				// announcements that we just received and are just waiting for our peer to send a
@@ -1839,9 +2695,208 @@ impl<L: Deref> NetworkGraph<L> where L::Target: Logger {
 		self.update_channel_intern(msg, None, None)
 	}
 
-	fn update_channel_intern(&self, msg: &msgs::UnsignedChannelUpdate, full_msg: Option<&msgs::ChannelUpdate>, sig: Option<&secp256k1::ecdsa::Signature>) -> Result<(), LightningError> {
-		let chan_enabled = msg.flags & (1 << 1) != (1 << 1);
+	/// Applies a batch of signed gossip messages, e.g. a full gossip dump fetched during initial
+	/// sync, verifying their signatures as configured for their source by `verification`.
+	///
+	/// All signatures are checked before any message is applied, and messages are applied in
+	/// dependency order, i.e. channel announcements first, then node announcements and finally
+	/// channel updates, regardless of their order in `messages`.
+	///
+	/// As secp256k1 offers no batch verification of ECDSA signatures, batching instead avoids the
+	/// work which verifying the messages one at a time would repeat: messages which we already
+	/// have, duplicates and updates which are outdated compared to what we have are skipped
+	/// without verifying their signatures, and each node's public key is only parsed once. The
+	/// graph is only locked briefly to find out which messages this applies to, the signatures
+	/// are verified without holding any locks.
+	///
+	/// `random_seed_bytes` determines which messages are sampled with
+	/// [`GossipVerification::Sampled`] and must be unpredictable to the source. It is ignored with
+	/// [`GossipVerification::Full`].
+	///
+	/// Errors without applying any message if a sampled signature is invalid. As with
+	/// [`Self::update_channel_from_announcement`], a [`UtxoLookup`] is used to verify announced
+	/// channels exist on-chain if provided.
+	pub fn update_from_gossip_batch<U: Deref>(
+		&self, messages: &[GossipMessage], verification: GossipVerification,
+		utxo_lookup: &Option<U>, random_seed_bytes: &[u8; 32],
+	) -> Result<GossipBatchResult, LightningError>
+	where
+		U::Target: UtxoLookup,
+	{
+		let mut result = GossipBatchResult::default();
+
+		// The endpoints of channels announced in this batch, against whose keys the signatures of
+		// their channel updates are checked.
+		let mut batch_channel_nodes = HashMap::new();
+		for message in messages.iter() {
+			if let GossipMessage::ChannelAnnouncement(msg) = message {
+				batch_channel_nodes.insert(msg.contents.short_channel_id,
+					(msg.contents.node_id_1, msg.contents.node_id_2));
+			}
+		}
+
+		// First find out which messages need their signatures checked at all while holding the
+		// read lock, then verify them after releasing it.
+		let mut signatures = Vec::with_capacity(messages.len());
+		{
+			let read_only = self.read_only();
+			for message in messages.iter() {
+				signatures.push(match message {
+					GossipMessage::ChannelAnnouncement(msg) => {
+						match read_only.channel(msg.contents.short_channel_id) {
+							Some(chan) if chan.announcement_message.as_ref() == Some(msg) =>
+								BatchSignature::Valid,
+							_ => BatchSignature::Unchecked { channel_nodes: None },
+						}
+					},
+					GossipMessage::NodeAnnouncement(msg) => {
+						match read_only.node(&msg.contents.node_id).and_then(|node| node.announcement_info.as_ref()) {
+							Some(info) if info.announcement_message.as_ref() == Some(msg) =>
+								BatchSignature::Valid,
+							Some(info) if info.last_update >= msg.contents.timestamp => BatchSignature::Skipped,
+							_ => BatchSignature::Unchecked { channel_nodes: None },
+						}
+					},
+					GossipMessage::ChannelUpdate(msg) => {
+						let scid = msg.contents.short_channel_id;
+						let chan = read_only.channel(scid);
+						let channel_nodes = batch_channel_nodes.get(&scid).cloned()
+							.or_else(|| chan.map(|chan| (chan.node_one, chan.node_two)));
+						match (channel_nodes, chan) {
+							// Updates for unknown channels fail to apply anyway.
+							(None, _) => BatchSignature::Skipped,
+							(Some(nodes), Some(chan)) if nodes == (chan.node_one, chan.node_two) => {
+								let direction = if msg.contents.flags & 1 == 1 { &chan.two_to_one } else { &chan.one_to_two };
+								match direction {
+									Some(info) if info.last_update_message.as_ref() == Some(msg) =>
+										BatchSignature::Valid,
+									Some(info) if info.last_update >= msg.contents.timestamp =>
+										BatchSignature::Skipped,
+									_ => BatchSignature::Unchecked { channel_nodes: Some(nodes) },
+								}
+							},
+							(Some(nodes), _) => BatchSignature::Unchecked { channel_nodes: Some(nodes) },
+						}
+					},
+				});
+			}
+		}
+
+		let mut pubkeys = HashMap::new();
+		let mut first_message_idxs = HashMap::new();
+		for (idx, message) in messages.iter().enumerate() {
+			let channel_nodes = match signatures[idx] {
+				BatchSignature::Unchecked { channel_nodes } => channel_nodes,
+				_ => continue,
+			};
+			let contents = match message {
+				GossipMessage::ChannelAnnouncement(msg) => msg.contents.encode(),
+				GossipMessage::NodeAnnouncement(msg) => msg.contents.encode(),
+				GossipMessage::ChannelUpdate(msg) => msg.contents.encode(),
+			};
+			let contents_hash = Sha256dHash::hash(&contents[..]);
+			if let Some(first_idx) = first_message_idxs.get(&contents_hash) {
+				if messages[*first_idx] == *message {
+					// A duplicate fails to apply after the first copy was applied anyway.
+					signatures[idx] = BatchSignature::Skipped;
+					continue;
+				}
+			} else {
+				first_message_idxs.insert(contents_hash, idx);
+			}
+			if !verification.verifies(random_seed_bytes, &contents[..]) {
+				signatures[idx] = BatchSignature::Unverified;
+				continue;
+			}
+			let msg_hash = hash_to_message!(&contents_hash[..]);
+			result.signatures_verified += 1;
+			match self.verify_batch_message(message, &msg_hash, channel_nodes, &mut pubkeys) {
+				Ok(()) => signatures[idx] = BatchSignature::Valid,
+				Err(e) => {
+					if verification != GossipVerification::Full {
+						log_debug!(self.logger, "Rejecting gossip batch as message {} had an invalid signature", idx);
+						return Err(e);
+					}
+					log_trace!(self.logger, "Skipping gossip message {} with an invalid signature", idx);
+					signatures[idx] = BatchSignature::Invalid;
+				},
+			}
+		}
+
+		for pass in 0..3 {
+			for (message, signature) in messages.iter().zip(signatures.iter()) {
+				let verified = match signature {
+					BatchSignature::Valid => true,
+					BatchSignature::Unverified => false,
+					_ => {
+						// Only count skipped messages once.
+						if pass == 0 { result.ignored += 1; }
+						continue;
+					},
+				};
+				let res = match (pass, message) {
+					(0, GossipMessage::ChannelAnnouncement(msg)) =>
+						self.update_channel_from_unsigned_announcement_intern(
+							&msg.contents, if verified { Some(msg) } else { None }, utxo_lookup),
+					(1, GossipMessage::NodeAnnouncement(msg)) =>
+						self.update_node_from_announcement_intern(
+							&msg.contents, if verified { Some(msg) } else { None }),
+					(2, GossipMessage::ChannelUpdate(msg)) =>
+						self.update_channel_intern(
+							&msg.contents, if verified { Some(msg) } else { None }, None),
+					_ => continue,
+				};
+				match res {
+					Ok(()) => result.applied += 1,
+					Err(_) => result.ignored += 1,
+				}
+			}
+		}
+		log_trace!(self.logger, "Applied {} of {} gossip messages in a batch, verifying {} signatures",
+			result.applied, messages.len(), result.signatures_verified);
+		Ok(result)
+	}
+
+	/// Verifies the signatures of a message of a batch given the hash of its contents, looking up
+	/// node public keys in `pubkeys` and adding them once parsed.
+	fn verify_batch_message(
+		&self, message: &GossipMessage, msg_hash: &secp256k1::Message,
+		channel_nodes: Option<(NodeId, NodeId)>, pubkeys: &mut HashMap<NodeId, PublicKey>,
+	) -> Result<(), LightningError> {
+		fn node_pubkey(
+			pubkeys: &mut HashMap<NodeId, PublicKey>, node_id: &NodeId, msg_type: &str,
+		) -> Result<PublicKey, LightningError> {
+			if let Some(pubkey) = pubkeys.get(node_id) { return Ok(*pubkey); }
+			let pubkey = get_pubkey_from_node_id!(node_id, msg_type);
+			pubkeys.insert(*node_id, pubkey);
+			Ok(pubkey)
+		}
+
+		match message {
+			GossipMessage::ChannelAnnouncement(msg) => {
+				let node_1 = node_pubkey(pubkeys, &msg.contents.node_id_1, "channel_announcement")?;
+				let node_2 = node_pubkey(pubkeys, &msg.contents.node_id_2, "channel_announcement")?;
+				secp_verify_sig!(self.secp_ctx, msg_hash, &msg.node_signature_1, &node_1, "channel_announcement");
+				secp_verify_sig!(self.secp_ctx, msg_hash, &msg.node_signature_2, &node_2, "channel_announcement");
+				secp_verify_sig!(self.secp_ctx, msg_hash, &msg.bitcoin_signature_1, &get_pubkey_from_node_id!(msg.contents.bitcoin_key_1, "channel_announcement"), "channel_announcement");
+				secp_verify_sig!(self.secp_ctx, msg_hash, &msg.bitcoin_signature_2, &get_pubkey_from_node_id!(msg.contents.bitcoin_key_2, "channel_announcement"), "channel_announcement");
+			},
+			GossipMessage::NodeAnnouncement(msg) => {
+				let node = node_pubkey(pubkeys, &msg.contents.node_id, "node_announcement")?;
+				secp_verify_sig!(self.secp_ctx, msg_hash, &msg.signature, &node, "node_announcement");
+			},
+			GossipMessage::ChannelUpdate(msg) => {
+				let (node_one, node_two) = channel_nodes.expect("Channel updates are only verified for known channels");
+				// Updates are signed by the node the channel direction originates at.
+				let node_id = if msg.contents.flags & 1 == 1 { node_two } else { node_one };
+				let node = node_pubkey(pubkeys, &node_id, "channel_update")?;
+				secp_verify_sig!(self.secp_ctx, msg_hash, &msg.signature, &node, "channel_update");
+			},
+		}
+		Ok(())
+	}
 
+	fn update_channel_intern(&self, msg: &msgs::UnsignedChannelUpdate, full_msg: Option<&msgs::ChannelUpdate>, sig: Option<&secp256k1::ecdsa::Signature>) -> Result<(), LightningError> {
 		if msg.chain_hash != self.genesis_hash {
 			return Err(LightningError {
 				err: "Channel update chain hash does not match genesis hash".to_owned(),
@@ -1867,86 +2922,115 @@ impl<L: Deref> NetworkGraph<L> where L::Target: Logger {
 			None => {
 				core::mem::drop(channels);
 				self.pending_checks.check_hold_pending_channel_update(msg, full_msg)?;
+				if let Some(pending_updates) = self.offloaded_channels.lock().unwrap().get_mut(&msg.short_channel_id) {
+					// Keep the latest update for each direction, which is checked once it is applied.
+					let pending_update = &mut pending_updates[(msg.flags & 1) as usize];
+					if pending_update.as_ref().map_or(true, |update| update.contents.timestamp < msg.timestamp) {
+						*pending_update = Some(PendingOffloadedUpdate {
+							contents: msg.clone(), full_msg: full_msg.cloned(), sig: sig.cloned(),
+						});
+					}
+					return Err(LightningError{err: "Queued update for offloaded channel".to_owned(), action: ErrorAction::IgnoreError});
+				}
 				return Err(LightningError{err: "Couldn't find channel for update".to_owned(), action: ErrorAction::IgnoreError});
 			},
-			Some(channel) => {
-				if msg.htlc_maximum_msat > MAX_VALUE_MSAT {
-					return Err(LightningError{err:
-						"htlc_maximum_msat is larger than maximum possible msats".to_owned(),
-						action: ErrorAction::IgnoreError});
-				}
+			Some(channel) => self.apply_channel_update(channel, msg, full_msg, sig),
+		}
+	}
 
-				if let Some(capacity_sats) = channel.capacity_sats {
-					// It's possible channel capacity is available now, although it wasn't available at announcement (so the field is None).
-					// Don't query UTXO set here to reduce DoS risks.
-					if capacity_sats > MAX_VALUE_MSAT / 1000 || msg.htlc_maximum_msat > capacity_sats * 1000 {
-						return Err(LightningError{err:
-							"htlc_maximum_msat is larger than channel capacity or capacity is bogus".to_owned(),
-							action: ErrorAction::IgnoreError});
-					}
-				}
-				macro_rules! check_update_latest {
-					($target: expr) => {
-						if let Some(existing_chan_info) = $target.as_ref() {
-							// The timestamp field is somewhat of a misnomer - the BOLTs use it to
-							// order updates to ensure you always have the latest one, only
-							// suggesting  that it be at least the current time. For
-							// channel_updates specifically, the BOLTs discuss the possibility of
-							// pruning based on the timestamp field being more than two weeks old,
-							// but only in the non-normative section.
-							if existing_chan_info.last_update > msg.timestamp {
-								return Err(LightningError{err: "Update older than last processed update".to_owned(), action: ErrorAction::IgnoreDuplicateGossip});
-							} else if existing_chan_info.last_update == msg.timestamp {
-								return Err(LightningError{err: "Update had same timestamp as last processed update".to_owned(), action: ErrorAction::IgnoreDuplicateGossip});
-							}
-						}
+	/// Applies a `channel_update` to the given channel, verifying its signature if given.
+	fn apply_channel_update(
+		&self, channel: &mut ChannelInfo, msg: &msgs::UnsignedChannelUpdate,
+		full_msg: Option<&msgs::ChannelUpdate>, sig: Option<&secp256k1::ecdsa::Signature>
+	) -> Result<(), LightningError> {
+		let chan_enabled = msg.flags & (1 << 1) != (1 << 1);
+
+		if msg.htlc_maximum_msat > MAX_VALUE_MSAT {
+			return Err(LightningError{err:
+				"htlc_maximum_msat is larger than maximum possible msats".to_owned(),
+				action: ErrorAction::IgnoreError});
+		}
+
+		if let Some(capacity_sats) = channel.capacity_sats {
+			// It's possible channel capacity is available now, although it wasn't available at announcement (so the field is None).
+			// Don't query UTXO set here to reduce DoS risks.
+			if capacity_sats > MAX_VALUE_MSAT / 1000 || msg.htlc_maximum_msat > capacity_sats * 1000 {
+				return Err(LightningError{err:
+					"htlc_maximum_msat is larger than channel capacity or capacity is bogus".to_owned(),
+					action: ErrorAction::IgnoreError});
+			}
+		}
+		macro_rules! check_update_latest {
+			($target: expr) => {
+				if let Some(existing_chan_info) = $target.as_ref() {
+					// The timestamp field is somewhat of a misnomer - the BOLTs use it to
+					// order updates to ensure you always have the latest one, only
+					// suggesting  that it be at least the current time. For
+					// channel_updates specifically, the BOLTs discuss the possibility of
+					// pruning based on the timestamp field being more than two weeks old,
+					// but only in the non-normative section.
+					if existing_chan_info.last_update > msg.timestamp {
+						return Err(LightningError{err: "Update older than last processed update".to_owned(), action: ErrorAction::IgnoreDuplicateGossip});
+					} else if existing_chan_info.last_update == msg.timestamp {
+						return Err(LightningError{err: "Update had same timestamp as last processed update".to_owned(), action: ErrorAction::IgnoreDuplicateGossip});
 					}
 				}
+			}
+		}
 
-				macro_rules! get_new_channel_info {
-					() => { {
-						let last_update_message = if msg.excess_data.len() <= MAX_EXCESS_BYTES_FOR_RELAY
-							{ full_msg.cloned() } else { None };
-
-						let updated_channel_update_info = ChannelUpdateInfo {
-							enabled: chan_enabled,
-							last_update: msg.timestamp,
-							cltv_expiry_delta: msg.cltv_expiry_delta,
-							htlc_minimum_msat: msg.htlc_minimum_msat,
-							htlc_maximum_msat: msg.htlc_maximum_msat,
-							fees: RoutingFees {
-								base_msat: msg.fee_base_msat,
-								proportional_millionths: msg.fee_proportional_millionths,
-							},
-							last_update_message
-						};
-						Some(updated_channel_update_info)
-					} }
-				}
+		macro_rules! get_new_channel_info {
+			() => { {
+				let last_update_message = if msg.excess_data.len() <= MAX_EXCESS_BYTES_FOR_RELAY
+					{ full_msg.cloned() } else { None };
 
-				let msg_hash = hash_to_message!(&Sha256dHash::hash(&msg.encode()[..])[..]);
-				if msg.flags & 1 == 1 {
-					check_update_latest!(channel.two_to_one);
-					if let Some(sig) = sig {
-						secp_verify_sig!(self.secp_ctx, &msg_hash, &sig, &PublicKey::from_slice(channel.node_two.as_slice()).map_err(|_| LightningError{
-							err: "Couldn't parse source node pubkey".to_owned(),
-							action: ErrorAction::IgnoreAndLog(Level::Debug)
-						})?, "channel_update");
-					}
-					channel.two_to_one = get_new_channel_info!();
-				} else {
-					check_update_latest!(channel.one_to_two);
-					if let Some(sig) = sig {
-						secp_verify_sig!(self.secp_ctx, &msg_hash, &sig, &PublicKey::from_slice(channel.node_one.as_slice()).map_err(|_| LightningError{
-							err: "Couldn't parse destination node pubkey".to_owned(),
-							action: ErrorAction::IgnoreAndLog(Level::Debug)
-						})?, "channel_update");
-					}
-					channel.one_to_two = get_new_channel_info!();
+				let updated_channel_update_info = ChannelUpdateInfo {
+					enabled: chan_enabled,
+					last_update: msg.timestamp,
+					cltv_expiry_delta: msg.cltv_expiry_delta,
+					htlc_minimum_msat: msg.htlc_minimum_msat,
+					htlc_maximum_msat: msg.htlc_maximum_msat,
+					fees: RoutingFees {
+						base_msat: msg.fee_base_msat,
+						proportional_millionths: msg.fee_proportional_millionths,
+					},
+					last_update_message
+				};
+				Some(updated_channel_update_info)
+			} }
+		}
+
+		macro_rules! count_disable {
+			($target: expr) => {
+				if !chan_enabled && $target.as_ref().map_or(false, |info: &ChannelUpdateInfo| info.enabled) {
+					channel.disabled_count = channel.disabled_count.saturating_add(1);
+					self.disabled_channels.lock().unwrap().push(msg.short_channel_id);
 				}
 			}
 		}
 
+		let msg_hash = hash_to_message!(&Sha256dHash::hash(&msg.encode()[..])[..]);
+		if msg.flags & 1 == 1 {
+			check_update_latest!(channel.two_to_one);
+			if let Some(sig) = sig {
+				secp_verify_sig!(self.secp_ctx, &msg_hash, &sig, &PublicKey::from_slice(channel.node_two.as_slice()).map_err(|_| LightningError{
+					err: "Couldn't parse source node pubkey".to_owned(),
+					action: ErrorAction::IgnoreAndLog(Level::Debug)
+				})?, "channel_update");
+			}
+			count_disable!(channel.two_to_one);
+			channel.two_to_one = get_new_channel_info!();
+		} else {
+			check_update_latest!(channel.one_to_two);
+			if let Some(sig) = sig {
+				secp_verify_sig!(self.secp_ctx, &msg_hash, &sig, &PublicKey::from_slice(channel.node_one.as_slice()).map_err(|_| LightningError{
+					err: "Couldn't parse destination node pubkey".to_owned(),
+					action: ErrorAction::IgnoreAndLog(Level::Debug)
+				})?, "channel_update");
+			}
+			count_disable!(channel.one_to_two);
+			channel.one_to_two = get_new_channel_info!();
+		}
+
 		Ok(())
 	}
 
@@ -1976,7 +3060,7 @@ impl ReadOnlyNetworkGraph<'_> {
 	///
 	/// This is not exported to bindings users because we don't want to return lifetime'd references
 	pub fn channels(&self) -> &IndexedMap<u64, ChannelInfo> {
-		&*self.channels
+		&self.channels
 	}
 
 	/// Returns information on a channel with the given id.
@@ -1994,7 +3078,7 @@ impl ReadOnlyNetworkGraph<'_> {
 	///
 	/// This is not exported to bindings users because we don't want to return lifetime'd references
 	pub fn nodes(&self) -> &IndexedMap<NodeId, NodeInfo> {
-		&*self.nodes
+		&self.nodes
 	}
 
 	/// Returns information on a node with the given id.
@@ -2024,15 +3108,26 @@ pub(crate) mod tests {
 	use crate::ln::chan_utils::make_funding_redeemscript;
 	#[cfg(feature = "std")]
 	use crate::ln::features::InitFeatures;
-	use crate::routing::gossip::{P2PGossipSync, NetworkGraph, NetworkUpdate, NodeAlias, MAX_EXCESS_BYTES_FOR_RELAY, NodeId, RoutingFees, ChannelUpdateInfo, ChannelInfo, NodeAnnouncementInfo, NodeInfo};
+	use crate::ln::peer_handler::IgnoringMessageHandler;
+	use crate::routing::gossip::{GossipBatchResult, GossipMessage, GossipVerification, P2PGossipSync, NetworkGraph, NetworkUpdate, NodeAlias, MAX_EXCESS_BYTES_FOR_RELAY, NodeId, RoutingFees, ChannelUpdateInfo, ChannelInfo, NodeAnnouncementInfo, NodeInfo, OffloadedChannelStore, PeerAddressResolver, OFFLOADED_CHANNELS_KEY_PREFIX};
+	use crate::routing::interchange::InterchangeError;
 	use crate::routing::utxo::{UtxoLookupError, UtxoResult};
-	use crate::ln::msgs::{RoutingMessageHandler, UnsignedNodeAnnouncement, NodeAnnouncement,
+	use crate::ln::msgs::{RoutingMessageHandler, UnsignedNodeAnnouncement, NodeAnnouncement, LeaseRates,
 		UnsignedChannelAnnouncement, ChannelAnnouncement, UnsignedChannelUpdate, ChannelUpdate,
-		ReplyChannelRange, QueryChannelRange, QueryShortChannelIds, MAX_VALUE_MSAT};
+		ReplyChannelRange, QueryChannelRange, QueryShortChannelIds, NetAddress, MAX_VALUE_MSAT};
 	use crate::util::config::UserConfig;
+	use crate::util::persist::{KVStorePersister, KVStoreReader};
 	use crate::util::test_utils;
 	use crate::util::ser::{ReadableArgs, Readable, Writeable};
 	use crate::util::scid_utils::scid_from_parts;
+	#[cfg(taproot)]
+	use crate::ln::chan_utils::{make_taproot_funding_script, musig2_key_agg_coefficients};
+	#[cfg(taproot)]
+	use crate::ln::msgs::{ChannelAnnouncement2, UnsignedChannelAnnouncement2};
+	#[cfg(taproot)]
+	use crate::offers::merkle;
+	#[cfg(taproot)]
+	use super::CHANNEL_ANNOUNCEMENT_2_SIGNATURE_TAG;
 
 	use crate::routing::gossip::REMOVED_ENTRIES_TRACKING_AGE_LIMIT_SECS;
 	use super::STALE_CHANNEL_UPDATE_AGE_LIMIT_SECS;
@@ -2048,11 +3143,13 @@ pub(crate) mod tests {
 
 	use bitcoin::secp256k1::{PublicKey, SecretKey};
 	use bitcoin::secp256k1::{All, Secp256k1};
+	#[cfg(taproot)]
+	use bitcoin::secp256k1::{KeyPair, Scalar};
 
 	use crate::io;
 	use bitcoin::secp256k1;
 	use crate::prelude::*;
-	use crate::sync::Arc;
+	use crate::sync::{Arc, Mutex};
 
 	fn create_network_graph() -> NetworkGraph<Arc<test_utils::TestLogger>> {
 		let logger = Arc::new(test_utils::TestLogger::new());
@@ -2138,6 +3235,48 @@ pub(crate) mod tests {
 			&PublicKey::from_secret_key(secp_ctx, &node_2_btckey)).to_v0_p2wsh()
 	}
 
+	#[cfg(taproot)]
+	fn get_signed_channel_announcement_2<F: Fn(&mut UnsignedChannelAnnouncement2)>(f: F, node_1_key: &SecretKey, node_2_key: &SecretKey, secp_ctx: &Secp256k1<secp256k1::All>) -> ChannelAnnouncement2 {
+		let node_1_btckey = SecretKey::from_slice(&[40; 32]).unwrap();
+		let node_2_btckey = SecretKey::from_slice(&[39; 32]).unwrap();
+		let secret_keys = [*node_1_key, *node_2_key, node_1_btckey, node_2_btckey];
+		let public_keys = secret_keys.iter()
+			.map(|key| PublicKey::from_secret_key(&secp_ctx, key))
+			.collect::<Vec<_>>();
+
+		let mut unsigned_announcement = UnsignedChannelAnnouncement2 {
+			chain_hash: genesis_block(Network::Testnet).header.block_hash(),
+			features: channelmanager::provided_channel_features(&UserConfig::default()),
+			short_channel_id: 0,
+			capacity_satoshis: 1_000_000,
+			node_id_1: NodeId::from_pubkey(&public_keys[0]),
+			node_id_2: NodeId::from_pubkey(&public_keys[1]),
+			bitcoin_key_1: Some(NodeId::from_pubkey(&public_keys[2])),
+			bitcoin_key_2: Some(NodeId::from_pubkey(&public_keys[3])),
+			merkle_root_hash: None,
+			spv_proof: None,
+		};
+		f(&mut unsigned_announcement);
+
+		// A MuSig2 session between both nodes yields a signature valid for the aggregate key, which
+		// we get more simply by signing with the equally aggregated secret keys.
+		let coefficients = musig2_key_agg_coefficients(&public_keys).unwrap();
+		let mut aggregate_secret_key: Option<SecretKey> = None;
+		for (key, coefficient) in secret_keys.iter().zip(coefficients.iter()) {
+			let tweaked_key = key.mul_tweak(coefficient).unwrap();
+			aggregate_secret_key = Some(match aggregate_secret_key {
+				Some(aggregate_key) => aggregate_key.add_tweak(&Scalar::from(tweaked_key)).unwrap(),
+				None => tweaked_key,
+			});
+		}
+		let keypair = KeyPair::from_secret_key(&secp_ctx, &aggregate_secret_key.unwrap());
+		let msghash = merkle::message_digest(CHANNEL_ANNOUNCEMENT_2_SIGNATURE_TAG, &unsigned_announcement.encode());
+		ChannelAnnouncement2 {
+			signature: secp_ctx.sign_schnorr_no_aux_rand(&msghash, &keypair),
+			contents: unsigned_announcement,
+		}
+	}
+
 	pub(crate) fn get_signed_channel_update<F: Fn(&mut UnsignedChannelUpdate)>(f: F, node_key: &SecretKey, secp_ctx: &Secp256k1<secp256k1::All>) -> ChannelUpdate {
 		let mut unsigned_channel_update = UnsignedChannelUpdate {
 			chain_hash: genesis_block(Network::Testnet).header.block_hash(),
@@ -2219,6 +3358,97 @@ pub(crate) mod tests {
 		};
 	}
 
+	#[test]
+	fn resolves_unannounced_peer_addresses() {
+		struct TestResolver;
+		impl PeerAddressResolver for TestResolver {
+			fn resolve_addresses(&self, _node_id: &PublicKey) -> Vec<NetAddress> {
+				vec![NetAddress::IPv4 { addr: [127, 0, 0, 1], port: 9735 }]
+			}
+		}
+
+		let network_graph = create_network_graph();
+		let (secp_ctx, gossip_sync) = create_gossip_sync(&network_graph);
+
+		let node_1_privkey = &SecretKey::from_slice(&[42; 32]).unwrap();
+		let node_2_privkey = &SecretKey::from_slice(&[41; 32]).unwrap();
+		let node_1_id = PublicKey::from_secret_key(&secp_ctx, node_1_privkey);
+		let node_2_id = PublicKey::from_secret_key(&secp_ctx, node_2_privkey);
+		let valid_announcement = get_signed_channel_announcement(|_| {}, node_1_privkey, node_2_privkey, &secp_ctx);
+		gossip_sync.handle_channel_announcement(&valid_announcement).unwrap();
+		let announced_address = NetAddress::IPv4 { addr: [10, 0, 0, 1], port: 9735 };
+		let node_announcement = get_signed_node_announcement(|unsigned_announcement| {
+			unsigned_announcement.addresses = vec![announced_address.clone()];
+		}, node_1_privkey, &secp_ctx);
+		gossip_sync.handle_node_announcement(&node_announcement).unwrap();
+
+		let no_resolver = &IgnoringMessageHandler {};
+		assert_eq!(network_graph.peer_addresses(&node_1_id, &no_resolver), vec![announced_address.clone()]);
+		assert!(network_graph.peer_addresses(&node_2_id, &no_resolver).is_empty());
+
+		// Only peers without announced addresses are resolved.
+		let resolver = &TestResolver;
+		assert_eq!(network_graph.peer_addresses(&node_1_id, &resolver), vec![announced_address]);
+		assert_eq!(network_graph.peer_addresses(&node_2_id, &resolver),
+			vec![NetAddress::IPv4 { addr: [127, 0, 0, 1], port: 9735 }]);
+	}
+
+	#[test]
+	fn handling_node_announcement_lease_rates() {
+		let network_graph = create_network_graph();
+		let (secp_ctx, gossip_sync) = create_gossip_sync(&network_graph);
+
+		let node_1_privkey = &SecretKey::from_slice(&[42; 32]).unwrap();
+		let node_2_privkey = &SecretKey::from_slice(&[41; 32]).unwrap();
+		let node_1_id = NodeId::from_pubkey(&PublicKey::from_secret_key(&secp_ctx, node_1_privkey));
+		let channel_announcement = get_signed_channel_announcement(|_| {}, node_1_privkey, node_2_privkey, &secp_ctx);
+		assert!(gossip_sync.handle_channel_announcement(&channel_announcement).unwrap());
+
+		let lease_rates = LeaseRates {
+			funding_weight: 444,
+			lease_fee_basis: 50,
+			channel_fee_max_proportional_thousandths: 10,
+			lease_fee_base_sat: 1_000,
+			channel_fee_max_base_msat: 5_000,
+		};
+		assert_eq!(lease_rates.lease_fee_sat(1_000_000), 6_000);
+		let encoded_lease_rates = lease_rates.encode();
+		// The lease rates are followed by an unknown odd TLV, which is ignored.
+		let mut will_fund_tlv = vec![1, encoded_lease_rates.len() as u8];
+		will_fund_tlv.extend_from_slice(&encoded_lease_rates);
+		will_fund_tlv.extend_from_slice(&[3, 1, 42]);
+
+		let announcement = get_signed_node_announcement(|unsigned_announcement| {
+			unsigned_announcement.excess_data = will_fund_tlv.clone();
+		}, node_1_privkey, &secp_ctx);
+		assert_eq!(announcement.contents.will_fund(), Some(lease_rates));
+		assert!(gossip_sync.handle_node_announcement(&announcement).unwrap());
+		macro_rules! will_fund { ($graph: expr) => {
+			$graph.read_only().node(&node_1_id).unwrap().announcement_info.as_ref().unwrap().will_fund
+		} }
+		assert_eq!(will_fund!(network_graph), Some(lease_rates));
+
+		// The lease rates survive a serialization roundtrip of the graph.
+		let logger = test_utils::TestLogger::new();
+		let read_network_graph = NetworkGraph::read(&mut &network_graph.encode()[..], &logger).unwrap();
+		assert_eq!(will_fund!(read_network_graph), Some(lease_rates));
+
+		// Lease rates followed by an unknown even TLV are not understood, nor are truncated ones.
+		let announcement = get_signed_node_announcement(|unsigned_announcement| {
+			unsigned_announcement.timestamp += 1;
+			unsigned_announcement.excess_data = will_fund_tlv[..will_fund_tlv.len() - 3].to_vec();
+			unsigned_announcement.excess_data.extend_from_slice(&[4, 1, 42]);
+		}, node_1_privkey, &secp_ctx);
+		assert_eq!(announcement.contents.will_fund(), None);
+		assert!(gossip_sync.handle_node_announcement(&announcement).unwrap());
+		assert_eq!(will_fund!(network_graph), None);
+
+		let announcement = get_signed_node_announcement(|unsigned_announcement| {
+			unsigned_announcement.excess_data = will_fund_tlv[..will_fund_tlv.len() - 5].to_vec();
+		}, node_1_privkey, &secp_ctx);
+		assert_eq!(announcement.contents.will_fund(), None);
+	}
+
 	#[test]
 	fn handling_channel_announcements() {
 		let secp_ctx = Secp256k1::new();
@@ -2353,6 +3583,210 @@ pub(crate) mod tests {
 		};
 	}
 
+	#[test]
+	#[cfg(taproot)]
+	fn handling_channel_announcements_2() {
+		let secp_ctx = Secp256k1::new();
+		let logger = test_utils::TestLogger::new();
+
+		let node_1_privkey = &SecretKey::from_slice(&[42; 32]).unwrap();
+		let node_2_privkey = &SecretKey::from_slice(&[41; 32]).unwrap();
+
+		let valid_announcement = get_signed_channel_announcement_2(|_| {}, node_1_privkey, node_2_privkey, &secp_ctx);
+		let encoded_announcement = valid_announcement.encode();
+		assert_eq!(ChannelAnnouncement2::read(&mut &encoded_announcement[..]).unwrap(), valid_announcement);
+
+		// Without UTXO lookups the announcement is accepted but never relayed.
+		let network_graph = NetworkGraph::new(Network::Testnet, &logger);
+		let gossip_sync = P2PGossipSync::new(&network_graph, None, &logger);
+		match gossip_sync.handle_channel_announcement_2(&valid_announcement) {
+			Ok(res) => assert!(!res),
+			_ => panic!()
+		};
+		match network_graph.read_only().channels().get(&valid_announcement.contents.short_channel_id) {
+			Some(channel) => assert_eq!(channel.capacity_sats, None),
+			None => panic!()
+		};
+
+		// Announcements of channels we already know are ignored before the signature is checked.
+		let mut duplicate_announcement = valid_announcement.clone();
+		duplicate_announcement.contents.capacity_satoshis += 1;
+		match gossip_sync.handle_channel_announcement_2(&duplicate_announcement) {
+			Ok(_) => panic!(),
+			Err(e) => assert_eq!(e.err, "Already have non-chain-validated channel")
+		};
+
+		// Announcements whose contents don't match the signature are rejected.
+		let mut invalid_announcement = get_signed_channel_announcement_2(|unsigned_announcement| {
+			unsigned_announcement.short_channel_id += 1;
+		}, node_1_privkey, node_2_privkey, &secp_ctx);
+		invalid_announcement.contents.capacity_satoshis += 1;
+		match gossip_sync.handle_channel_announcement_2(&invalid_announcement) {
+			Ok(_) => panic!(),
+			Err(e) => assert_eq!(e.err, "Invalid signature on channel_announcement_2 message")
+		};
+
+		// Announcements committing to a script tree or omitting the funding keys aren't supported.
+		let script_tree_announcement = get_signed_channel_announcement_2(|unsigned_announcement| {
+			unsigned_announcement.short_channel_id += 2;
+			unsigned_announcement.merkle_root_hash = Some([42; 32]);
+		}, node_1_privkey, node_2_privkey, &secp_ctx);
+		match gossip_sync.handle_channel_announcement_2(&script_tree_announcement) {
+			Ok(_) => panic!(),
+			Err(e) => assert_eq!(e.err, "Unsupported channel_announcement_2 without funding keys or with a script tree")
+		};
+		let keyless_announcement = get_signed_channel_announcement_2(|unsigned_announcement| {
+			unsigned_announcement.short_channel_id += 3;
+			unsigned_announcement.bitcoin_key_1 = None;
+			unsigned_announcement.bitcoin_key_2 = None;
+		}, node_1_privkey, node_2_privkey, &secp_ctx);
+		match gossip_sync.handle_channel_announcement_2(&keyless_announcement) {
+			Ok(_) => panic!(),
+			Err(e) => assert_eq!(e.err, "Unsupported channel_announcement_2 without funding keys or with a script tree")
+		};
+
+		// With UTXO lookups, the funding output must pay to the aggregate of the funding keys.
+		let chain_source = test_utils::TestChainSource::new(Network::Testnet);
+		let network_graph = NetworkGraph::new(Network::Testnet, &logger);
+		let gossip_sync = P2PGossipSync::new(&network_graph, Some(&chain_source), &logger);
+		*chain_source.utxo_ret.lock().unwrap() =
+			UtxoResult::Sync(Ok(TxOut { value: 1_000_000, script_pubkey: get_channel_script(&secp_ctx) }));
+		let p2wsh_announcement = get_signed_channel_announcement_2(|unsigned_announcement| {
+			unsigned_announcement.short_channel_id += 4;
+		}, node_1_privkey, node_2_privkey, &secp_ctx);
+		assert!(gossip_sync.handle_channel_announcement_2(&p2wsh_announcement).is_ok());
+
+		let taproot_script = make_taproot_funding_script(&secp_ctx,
+			&PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[40; 32]).unwrap()),
+			&PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[39; 32]).unwrap())).unwrap();
+		*chain_source.utxo_ret.lock().unwrap() =
+			UtxoResult::Sync(Ok(TxOut { value: 1_000_000, script_pubkey: taproot_script }));
+		let taproot_announcement = get_signed_channel_announcement_2(|unsigned_announcement| {
+			unsigned_announcement.short_channel_id += 5;
+		}, node_1_privkey, node_2_privkey, &secp_ctx);
+		match gossip_sync.handle_channel_announcement_2(&taproot_announcement) {
+			Ok(res) => assert!(!res),
+			_ => panic!()
+		};
+		match network_graph.read_only().channels().get(&taproot_announcement.contents.short_channel_id) {
+			Some(channel) => assert_eq!(channel.capacity_sats, Some(1_000_000)),
+			None => panic!()
+		};
+
+		// A P2TR funding output is never accepted for a legacy `channel_announcement`.
+		let legacy_announcement = get_signed_channel_announcement(|unsigned_announcement| {
+			unsigned_announcement.short_channel_id += 6;
+		}, node_1_privkey, node_2_privkey, &secp_ctx);
+		match gossip_sync.handle_channel_announcement(&legacy_announcement) {
+			Ok(_) => panic!(),
+			Err(e) => assert!(e.err.starts_with("Channel announcement key")),
+		};
+	}
+
+	#[test]
+	fn handling_gossip_batch() {
+		let secp_ctx = Secp256k1::new();
+		let logger = test_utils::TestLogger::new();
+		let chain_source = test_utils::TestChainSource::new(Network::Testnet);
+		*chain_source.utxo_ret.lock().unwrap() =
+			UtxoResult::Sync(Ok(TxOut { value: 1000_000, script_pubkey: get_channel_script(&secp_ctx) }));
+
+		let node_1_privkey = &SecretKey::from_slice(&[42; 32]).unwrap();
+		let node_2_privkey = &SecretKey::from_slice(&[41; 32]).unwrap();
+		let channel_announcement = get_signed_channel_announcement(|_| {}, node_1_privkey, node_2_privkey, &secp_ctx);
+		let short_channel_id = channel_announcement.contents.short_channel_id;
+		let node_announcement = get_signed_node_announcement(|_| {}, node_1_privkey, &secp_ctx);
+		let channel_update = get_signed_channel_update(|_| {}, node_1_privkey, &secp_ctx);
+		// An update for the direction from node 1 which is signed by node 2.
+		let forged_channel_update = get_signed_channel_update(|unsigned_channel_update| {
+			unsigned_channel_update.timestamp += 10;
+		}, node_2_privkey, &secp_ctx);
+
+		// Messages are applied in dependency order, skipping those with invalid signatures.
+		let messages = vec![
+			GossipMessage::ChannelUpdate(channel_update.clone()),
+			GossipMessage::NodeAnnouncement(node_announcement.clone()),
+			GossipMessage::ChannelUpdate(forged_channel_update.clone()),
+			GossipMessage::ChannelAnnouncement(channel_announcement.clone()),
+		];
+		let network_graph = NetworkGraph::new(Network::Testnet, &logger);
+		let result = network_graph.update_from_gossip_batch(
+			&messages, GossipVerification::Full, &Some(&chain_source), &[0; 32]).unwrap();
+		assert_eq!(result, GossipBatchResult { applied: 3, ignored: 1, signatures_verified: 4 });
+		{
+			let read_only = network_graph.read_only();
+			let channel = read_only.channel(short_channel_id).unwrap();
+			assert!(channel.announcement_message.is_some());
+			let one_to_two = channel.one_to_two.as_ref().unwrap();
+			assert_eq!(one_to_two.last_update, channel_update.contents.timestamp);
+			assert!(one_to_two.last_update_message.is_some());
+			let node_id = NodeId::from_pubkey(&PublicKey::from_secret_key(&secp_ctx, node_1_privkey));
+			assert!(read_only.node(&node_id).unwrap().announcement_info.is_some());
+		}
+
+		// Messages we already have, or which duplicate others, are not verified again.
+		let newer_channel_update = get_signed_channel_update(|unsigned_channel_update| {
+			unsigned_channel_update.timestamp += 20;
+		}, node_1_privkey, &secp_ctx);
+		let mut repeated_messages = messages.clone();
+		repeated_messages.push(GossipMessage::ChannelUpdate(newer_channel_update.clone()));
+		repeated_messages.push(GossipMessage::ChannelUpdate(newer_channel_update.clone()));
+		let result = network_graph.update_from_gossip_batch(
+			&repeated_messages, GossipVerification::Full, &Some(&chain_source), &[0; 32]).unwrap();
+		assert_eq!(result, GossipBatchResult { applied: 1, ignored: 5, signatures_verified: 2 });
+		assert_eq!(network_graph.read_only().channel(short_channel_id).unwrap().one_to_two.as_ref().unwrap()
+			.last_update_message, Some(newer_channel_update));
+
+		// If every message is sampled, a single invalid signature rejects the whole batch.
+		let network_graph = NetworkGraph::new(Network::Testnet, &logger);
+		assert!(network_graph.update_from_gossip_batch(
+			&messages, GossipVerification::Sampled { one_in: 1 }, &Some(&chain_source), &[0; 32]).is_err());
+		assert!(network_graph.read_only().channels().is_empty());
+
+		// Messages which are not sampled are applied as unsigned ones, without being relayed.
+		let result = network_graph.update_from_gossip_batch(
+			&messages, GossipVerification::Sampled { one_in: u32::max_value() }, &Some(&chain_source), &[0; 32]
+		).unwrap();
+		assert_eq!(result, GossipBatchResult { applied: 4, ignored: 0, signatures_verified: 0 });
+		let read_only = network_graph.read_only();
+		let channel = read_only.channel(short_channel_id).unwrap();
+		assert!(channel.announcement_message.is_none());
+		let one_to_two = channel.one_to_two.as_ref().unwrap();
+		assert_eq!(one_to_two.last_update, forged_channel_update.contents.timestamp);
+		assert!(one_to_two.last_update_message.is_none());
+	}
+
+	#[test]
+	fn handling_unsampled_gossip() {
+		let network_graph = create_network_graph();
+		let (secp_ctx, mut gossip_sync) = create_gossip_sync(&network_graph);
+		gossip_sync.set_gossip_verification(GossipVerification::Sampled { one_in: u32::max_value() }, [0; 32]);
+
+		let node_1_privkey = &SecretKey::from_slice(&[42; 32]).unwrap();
+		let node_2_privkey = &SecretKey::from_slice(&[41; 32]).unwrap();
+		// Signatures which are not sampled are not checked, but the messages are not relayed.
+		let mut channel_announcement = get_signed_channel_announcement(|_| {}, node_1_privkey, node_2_privkey, &secp_ctx);
+		channel_announcement.node_signature_1 = channel_announcement.node_signature_2;
+		assert!(!gossip_sync.handle_channel_announcement(&channel_announcement).unwrap());
+		let mut channel_update = get_signed_channel_update(|_| {}, node_2_privkey, &secp_ctx);
+		channel_update.signature = channel_announcement.node_signature_2;
+		assert!(!gossip_sync.handle_channel_update(&channel_update).unwrap());
+
+		let read_only = network_graph.read_only();
+		let channel = read_only.channel(channel_announcement.contents.short_channel_id).unwrap();
+		assert!(channel.announcement_message.is_none());
+		assert!(channel.one_to_two.as_ref().unwrap().last_update_message.is_none());
+		core::mem::drop(read_only);
+
+		// Verifying every message rejects the invalid signature.
+		gossip_sync.set_gossip_verification(GossipVerification::Sampled { one_in: 1 }, [0; 32]);
+		let mut newer_channel_update = get_signed_channel_update(|unsigned_channel_update| {
+			unsigned_channel_update.timestamp += 10;
+		}, node_1_privkey, &secp_ctx);
+		newer_channel_update.signature = channel_announcement.node_signature_2;
+		assert!(gossip_sync.handle_channel_update(&newer_channel_update).is_err());
+	}
+
 	#[test]
 	fn handling_channel_update() {
 		let secp_ctx = Secp256k1::new();
@@ -2468,6 +3902,33 @@ pub(crate) mod tests {
 		};
 	}
 
+	#[test]
+	fn read_only_graph_is_a_snapshot() {
+		// A read-only view shouldn't block updates to the graph, and shouldn't observe them either.
+		let logger = test_utils::TestLogger::new();
+		let network_graph = NetworkGraph::new(Network::Testnet, &logger);
+		let secp_ctx = Secp256k1::new();
+		let node_1_privkey = &SecretKey::from_slice(&[42; 32]).unwrap();
+		let node_2_privkey = &SecretKey::from_slice(&[41; 32]).unwrap();
+		let chain_source: Option<&test_utils::TestChainSource> = None;
+
+		let valid_channel_announcement = get_signed_channel_announcement(|_| {}, node_1_privkey, node_2_privkey, &secp_ctx);
+		let short_channel_id = valid_channel_announcement.contents.short_channel_id;
+		assert!(network_graph.update_channel_from_announcement(&valid_channel_announcement, &chain_source).is_ok());
+
+		let snapshot = network_graph.read_only();
+		let valid_channel_update = get_signed_channel_update(|_| {}, node_1_privkey, &secp_ctx);
+		assert!(network_graph.update_channel(&valid_channel_update).is_ok());
+		assert!(snapshot.channel(short_channel_id).unwrap().one_to_two.is_none());
+		assert!(network_graph.read_only().channel(short_channel_id).unwrap().one_to_two.is_some());
+
+		network_graph.channel_failed_permanent(short_channel_id);
+		assert!(network_graph.read_only().channel(short_channel_id).is_none());
+		assert_eq!(network_graph.read_only().nodes().len(), 0);
+		assert!(snapshot.channel(short_channel_id).is_some());
+		assert_eq!(snapshot.nodes().len(), 2);
+	}
+
 	#[test]
 	fn handling_network_update() {
 		let logger = test_utils::TestLogger::new();
@@ -2872,6 +4333,143 @@ pub(crate) mod tests {
 		assert!(<NetworkGraph<_>>::read(&mut io::Cursor::new(&w.0), logger).unwrap() == network_graph);
 	}
 
+	struct TestStore {
+		objects: Mutex<HashMap<String, Vec<u8>>>,
+	}
+
+	impl KVStorePersister for TestStore {
+		fn persist<W: Writeable>(&self, key: &str, object: &W) -> io::Result<()> {
+			self.objects.lock().unwrap().insert(key.to_owned(), object.encode());
+			Ok(())
+		}
+	}
+
+	impl KVStoreReader for TestStore {
+		fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+			Ok(self.objects.lock().unwrap().get(key).cloned())
+		}
+
+		fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+			Ok(self.objects.lock().unwrap().keys().filter(|key| key.starts_with(prefix)).cloned().collect())
+		}
+	}
+
+	#[test]
+	fn offloads_and_reloads_channels() {
+		let network_graph = create_network_graph();
+		let (secp_ctx, gossip_sync) = create_gossip_sync(&network_graph);
+
+		let node_1_privkey = &SecretKey::from_slice(&[42; 32]).unwrap();
+		let node_2_privkey = &SecretKey::from_slice(&[41; 32]).unwrap();
+		let node_3_privkey = &SecretKey::from_slice(&[43; 32]).unwrap();
+		let node_1_id = NodeId::from_pubkey(&PublicKey::from_secret_key(&secp_ctx, node_1_privkey));
+		let node_3_id = NodeId::from_pubkey(&PublicKey::from_secret_key(&secp_ctx, node_3_privkey));
+
+		let announcement = get_signed_channel_announcement(|_| {}, node_1_privkey, node_2_privkey, &secp_ctx);
+		assert!(gossip_sync.handle_channel_announcement(&announcement).unwrap());
+		let announcement = get_signed_channel_announcement(|unsigned_announcement| {
+			unsigned_announcement.short_channel_id = 1;
+		}, node_2_privkey, node_3_privkey, &secp_ctx);
+		assert!(gossip_sync.handle_channel_announcement(&announcement).unwrap());
+		let announcement = get_signed_node_announcement(|_| {}, node_3_privkey, &secp_ctx);
+		gossip_sync.handle_node_announcement(&announcement).unwrap();
+
+		// Only keep the channel of our "own" node in memory, its counterparty's other channel being
+		// offloaded while both of its nodes are kept.
+		let store = TestStore { objects: Mutex::new(HashMap::new()) };
+		let channel_store = OffloadedChannelStore::new(&store);
+		assert_eq!(channel_store.offload_channels(&network_graph, |short_channel_id, _| short_channel_id == 0).unwrap(), 1);
+		assert_eq!(store.list(OFFLOADED_CHANNELS_KEY_PREFIX).unwrap(), vec!["network_graph/channels/1".to_owned()]);
+		{
+			let read_only = network_graph.read_only();
+			assert_eq!(read_only.channels().len(), 1);
+			assert_eq!(read_only.nodes().len(), 3);
+			assert!(read_only.node(&node_3_id).unwrap().channels.is_empty());
+			assert!(read_only.node(&node_3_id).unwrap().announcement_info.is_some());
+		}
+
+		// Updates for offloaded channels are queued and applied to the store.
+		let update = get_signed_channel_update(|unsigned_update| {
+			unsigned_update.short_channel_id = 1;
+		}, node_2_privkey, &secp_ctx);
+		match gossip_sync.handle_channel_update(&update) {
+			Ok(_) => panic!(),
+			Err(e) => assert_eq!(e.err, "Queued update for offloaded channel"),
+		};
+		assert_eq!(channel_store.apply_pending_updates(&network_graph).unwrap(), 1);
+		assert_eq!(channel_store.apply_pending_updates(&network_graph).unwrap(), 0);
+
+		// Channels one hop away are only reloaded once the hop limit reaches them.
+		assert_eq!(channel_store.load_subgraph(&network_graph, &node_1_id, 1).unwrap(), 0);
+		assert_eq!(channel_store.load_subgraph(&network_graph, &node_1_id, 2).unwrap(), 1);
+		{
+			let read_only = network_graph.read_only();
+			assert_eq!(read_only.channels().len(), 2);
+			assert_eq!(read_only.channel(1).unwrap().node_two, node_3_id);
+			assert!(read_only.channel(1).unwrap().one_to_two.is_some());
+			assert_eq!(read_only.node(&node_3_id).unwrap().channels, vec![1]);
+		}
+
+		// Channels already in memory are not loaded again.
+		assert_eq!(channel_store.load_subgraph(&network_graph, &node_1_id, 2).unwrap(), 0);
+
+		// Offloaded channels are tracked again after a restart, and pruned once stale.
+		assert_eq!(channel_store.offload_channels(&network_graph, |short_channel_id, _| short_channel_id == 0).unwrap(), 1);
+		let mut w = test_utils::TestVecWriter(Vec::new());
+		network_graph.write(&mut w).unwrap();
+		let logger = Arc::new(test_utils::TestLogger::new());
+		let network_graph = NetworkGraph::read(&mut io::Cursor::new(&w.0), logger).unwrap();
+		assert_eq!(channel_store.restore(&network_graph).unwrap(), 1);
+		assert_eq!(channel_store.remove_stale_channels_with_time(&network_graph, u32::max_value() as u64).unwrap(), 1);
+		assert_eq!(channel_store.restore(&network_graph).unwrap(), 0);
+		assert_eq!(channel_store.load_subgraph(&network_graph, &node_1_id, 2).unwrap(), 0);
+		assert!(network_graph.read_only().node(&node_3_id).is_none());
+	}
+
+	#[test]
+	fn network_graph_interchange() {
+		let network_graph = create_network_graph();
+		let (secp_ctx, gossip_sync) = create_gossip_sync(&network_graph);
+
+		let node_1_privkey = &SecretKey::from_slice(&[42; 32]).unwrap();
+		let node_2_privkey = &SecretKey::from_slice(&[41; 32]).unwrap();
+
+		let valid_announcement = get_signed_channel_announcement(|_| {}, node_1_privkey, node_2_privkey, &secp_ctx);
+		assert!(gossip_sync.handle_channel_announcement(&valid_announcement).unwrap());
+		let valid_update = get_signed_channel_update(|_| {}, node_1_privkey, &secp_ctx);
+		assert!(gossip_sync.handle_channel_update(&valid_update).unwrap());
+
+		let exported = network_graph.write_interchange();
+		assert!(exported.starts_with("ldk-interchange 1 network-graph\n"));
+
+		let imported_graph = create_network_graph();
+		imported_graph.read_interchange(&exported).unwrap();
+		assert_eq!(imported_graph.write_interchange(), exported);
+		{
+			let read_only = imported_graph.read_only();
+			let channel = read_only.channels().get(&0).unwrap();
+			assert_eq!(channel.node_one, valid_announcement.contents.node_id_1);
+			assert_eq!(channel.node_two, valid_announcement.contents.node_id_2);
+			assert!(channel.two_to_one.is_none());
+			let update = channel.one_to_two.as_ref().unwrap();
+			assert_eq!(update.last_update, 100);
+			assert_eq!(update.fees, RoutingFees { base_msat: 10_000, proportional_millionths: 20 });
+			assert_eq!(read_only.nodes().len(), 2);
+		}
+
+		// Channels we already know about are not overwritten.
+		let node_1 = valid_announcement.contents.node_id_1;
+		let node_2 = valid_announcement.contents.node_id_2;
+		let conflicting = format!("ldk-interchange 1 network-graph\nchannel 0 {} {} - 0\n", node_2, node_1);
+		imported_graph.read_interchange(&conflicting).unwrap();
+		assert_eq!(imported_graph.write_interchange(), exported);
+
+		// A direction must follow the channel it refers to.
+		let malformed = format!("ldk-interchange 1 network-graph\nchannel 1 {} {} - 0\ndirection 0 0 100 1 144 0 1000 0 0\n", node_1, node_2);
+		assert_eq!(imported_graph.read_interchange(&malformed), Err(InterchangeError::InvalidRecord { line: 3 }));
+		assert!(imported_graph.read_only().channels().get(&1).is_none());
+	}
+
 	#[test]
 	fn network_graph_tlv_serialization() {
 		let network_graph = create_network_graph();
@@ -3310,6 +4908,7 @@ pub(crate) mod tests {
 			capacity_sats: None,
 			announcement_message: None,
 			announcement_received_time: 87654,
+			disabled_count: 0,
 		};
 
 		let mut encoded_chan_info: Vec<u8> = Vec::new();
@@ -3328,6 +4927,7 @@ pub(crate) mod tests {
 			capacity_sats: None,
 			announcement_message: None,
 			announcement_received_time: 87654,
+			disabled_count: 0,
 		};
 
 		let mut encoded_chan_info: Vec<u8> = Vec::new();
@@ -3365,7 +4965,8 @@ pub(crate) mod tests {
 			last_update: 0,
 			rgb: [0u8; 3],
 			alias: NodeAlias([0u8; 32]),
-			announcement_message: Some(announcement_message)
+			announcement_message: Some(announcement_message),
+			will_fund: None,
 		};
 
 		let mut encoded_valid_node_ann_info = Vec::new();
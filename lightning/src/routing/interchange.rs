@@ -0,0 +1,171 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! A portable, versioned text format for exchanging routing data.
+//!
+//! Unlike the [`Writeable`] serialization of [`NetworkGraph`] and [`ProbabilisticScorer`], which
+//! is specific to LDK's internal state, the interchange format is meant to be shared between
+//! nodes running different LDK versions and to be consumed by external tooling. It is written via
+//! [`NetworkGraph::write_interchange`] and [`ProbabilisticScorer::write_interchange`] and read via
+//! the corresponding `read_interchange` methods.
+//!
+//! # Format
+//!
+//! The data is UTF-8 text made up of lines separated by `\n`. The first line is a header of the
+//! form `ldk-interchange <version> <kind>`, where `<version>` is [`INTERCHANGE_FORMAT_VERSION`]
+//! and `<kind>` is either `network-graph` or `scorer`. Every following line is a record made up of
+//! fields separated by single spaces, the first of which is the record type. Empty lines and lines
+//! starting with `#` are ignored, as are records of unknown types, so that later versions may add
+//! record types without breaking older readers. Readers must reject versions they do not know.
+//!
+//! Integers are written in decimal, node ids as the hex encoding of the compressed public key,
+//! booleans as `0` or `1`, and values which are unknown as `-`.
+//!
+//! A `network-graph` export contains the following records:
+//! ```text
+//! channel <short_channel_id> <node_one> <node_two> <capacity_sats> <announcement_received_time>
+//! direction <short_channel_id> <direction> <last_update> <enabled> <cltv_expiry_delta> <htlc_minimum_msat> <htlc_maximum_msat> <fee_base_msat> <fee_proportional_millionths>
+//! ```
+//! where a `direction` record follows the `channel` record it refers to, and `<direction>` is `0`
+//! for the direction from `node_one` to `node_two` and `1` otherwise.
+//!
+//! A `scorer` export contains the following records:
+//! ```text
+//! liquidity <short_channel_id> <min_liquidity_offset_msat> <max_liquidity_offset_msat> <last_updated> <min_liquidity_offset_history> <max_liquidity_offset_history>
+//! failures <short_channel_id> <failure_weight> <last_failed>
+//! ```
+//! where times are given in seconds since the UNIX epoch and the liquidity offset histories are
+//! the eight comma-separated buckets of the historical liquidity trackers.
+//!
+//! [`Writeable`]: crate::util::ser::Writeable
+//! [`NetworkGraph`]: crate::routing::gossip::NetworkGraph
+//! [`NetworkGraph::write_interchange`]: crate::routing::gossip::NetworkGraph::write_interchange
+//! [`ProbabilisticScorer`]: crate::routing::scoring::ProbabilisticScorer
+//! [`ProbabilisticScorer::write_interchange`]: crate::routing::scoring::ProbabilisticScorerUsingTime::write_interchange
+
+use crate::prelude::*;
+
+use core::str::FromStr;
+
+/// The version of the interchange format written by this version of LDK.
+pub const INTERCHANGE_FORMAT_VERSION: u32 = 1;
+
+const HEADER_PREFIX: &str = "ldk-interchange";
+
+pub(crate) const NETWORK_GRAPH_KIND: &str = "network-graph";
+pub(crate) const SCORER_KIND: &str = "scorer";
+
+/// An error when reading data in the interchange format.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InterchangeError {
+	/// The data does not start with a valid header.
+	InvalidHeader,
+	/// The data was written with a version of the format we do not know.
+	UnsupportedVersion(u32),
+	/// The data is not of the kind being read, e.g., a scorer export was read as a network graph.
+	UnexpectedKind,
+	/// The record on the given (1-based) line is malformed.
+	InvalidRecord {
+		/// The line of the malformed record.
+		line: usize,
+	},
+}
+
+/// A record of data in the interchange format, along with the line it was read from.
+pub(crate) struct Record<'a> {
+	pub(crate) line: usize,
+	pub(crate) fields: Vec<&'a str>,
+}
+
+impl<'a> Record<'a> {
+	pub(crate) fn record_type(&self) -> &'a str {
+		self.fields[0]
+	}
+
+	pub(crate) fn error(&self) -> InterchangeError {
+		InterchangeError::InvalidRecord { line: self.line }
+	}
+
+	/// Parses the field at the given index, counting the record type as field zero.
+	pub(crate) fn field<T: FromStr>(&self, index: usize) -> Result<T, InterchangeError> {
+		self.fields.get(index).and_then(|field| field.parse().ok()).ok_or(self.error())
+	}
+
+	/// Parses the field at the given index, which may be `-` if unknown.
+	pub(crate) fn optional_field<T: FromStr>(&self, index: usize) -> Result<Option<T>, InterchangeError> {
+		match self.fields.get(index) {
+			Some(&"-") => Ok(None),
+			_ => self.field(index).map(Some),
+		}
+	}
+
+	pub(crate) fn bool_field(&self, index: usize) -> Result<bool, InterchangeError> {
+		match self.fields.get(index) {
+			Some(&"0") => Ok(false),
+			Some(&"1") => Ok(true),
+			_ => Err(self.error()),
+		}
+	}
+
+	/// Parses a field of comma-separated values.
+	pub(crate) fn list_field<T: FromStr>(&self, index: usize) -> Result<Vec<T>, InterchangeError> {
+		let field = self.fields.get(index).ok_or(self.error())?;
+		field.split(',').map(|value| value.parse().map_err(|_| self.error())).collect()
+	}
+}
+
+pub(crate) fn write_header(kind: &str) -> String {
+	format!("{} {} {}\n", HEADER_PREFIX, INTERCHANGE_FORMAT_VERSION, kind)
+}
+
+pub(crate) fn write_bool(value: bool) -> &'static str {
+	if value { "1" } else { "0" }
+}
+
+/// Checks the header of `data` and returns its records, skipping empty lines and comments.
+pub(crate) fn read_records<'a>(data: &'a str, kind: &str) -> Result<Vec<Record<'a>>, InterchangeError> {
+	let mut lines = data.lines();
+	let header: Vec<&str> = lines.next().ok_or(InterchangeError::InvalidHeader)?.split(' ').collect();
+	if header.len() != 3 || header[0] != HEADER_PREFIX {
+		return Err(InterchangeError::InvalidHeader);
+	}
+	let version: u32 = header[1].parse().map_err(|_| InterchangeError::InvalidHeader)?;
+	if version != INTERCHANGE_FORMAT_VERSION {
+		return Err(InterchangeError::UnsupportedVersion(version));
+	}
+	if header[2] != kind {
+		return Err(InterchangeError::UnexpectedKind);
+	}
+	Ok(lines.enumerate()
+		.filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+		.map(|(idx, line)| Record { line: idx + 2, fields: line.split(' ').collect() })
+		.collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{InterchangeError, read_records};
+
+	#[test]
+	fn reads_records_after_checking_header() {
+		assert_eq!(read_records("", "scorer").err(), Some(InterchangeError::InvalidHeader));
+		assert_eq!(read_records("ldk-interchange x scorer", "scorer").err(), Some(InterchangeError::InvalidHeader));
+		assert_eq!(read_records("ldk-interchange 2 scorer", "scorer").err(), Some(InterchangeError::UnsupportedVersion(2)));
+		assert_eq!(read_records("ldk-interchange 1 scorer", "network-graph").err(), Some(InterchangeError::UnexpectedKind));
+
+		let records = read_records("ldk-interchange 1 scorer\n# comment\n\nfailures 42 - 1\n", "scorer").unwrap();
+		assert_eq!(records.len(), 1);
+		assert_eq!(records[0].line, 4);
+		assert_eq!(records[0].record_type(), "failures");
+		assert_eq!(records[0].field::<u64>(1), Ok(42));
+		assert_eq!(records[0].optional_field::<u32>(2), Ok(None));
+		assert_eq!(records[0].bool_field(3), Ok(true));
+		assert_eq!(records[0].field::<u64>(4), Err(InterchangeError::InvalidRecord { line: 4 }));
+	}
+}
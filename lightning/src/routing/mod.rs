@@ -11,6 +11,7 @@
 
 pub mod utxo;
 pub mod gossip;
+pub mod interchange;
 pub mod router;
 pub mod scoring;
 #[cfg(test)]
@@ -19,17 +19,22 @@ use crate::ln::channelmanager::{ChannelDetails, PaymentId};
 use crate::ln::features::{Bolt12InvoiceFeatures, ChannelFeatures, InvoiceFeatures, NodeFeatures};
 use crate::ln::msgs::{DecodeError, ErrorAction, LightningError, MAX_VALUE_MSAT};
 use crate::offers::invoice::BlindedPayInfo;
-use crate::routing::gossip::{DirectedChannelInfo, EffectiveCapacity, ReadOnlyNetworkGraph, NetworkGraph, NodeId, RoutingFees};
+use crate::routing::gossip::{DirectedChannelInfo, EffectiveCapacity, ReadOnlyNetworkGraph, NetworkGraph, NodeId, OffloadedChannelStore, RoutingFees};
 use crate::routing::scoring::{ChannelUsage, LockableScore, Score};
+use crate::sign::EntropySource;
 use crate::util::ser::{Writeable, Readable, ReadableArgs, Writer};
 use crate::util::logger::{Level, Logger};
+use crate::util::persist::{KVStorePersister, KVStoreReader};
 use crate::util::chacha20::ChaCha20;
 
 use crate::io;
 use crate::prelude::*;
-use crate::sync::{Mutex, MutexGuard};
+use crate::sync::{Arc, Mutex, MutexGuard, RwLock};
 use alloc::collections::BinaryHeap;
 use core::{cmp, fmt};
+use core::hash::BuildHasher;
+#[allow(deprecated)] // `SipHasher` is deprecated in favor of the std-only `DefaultHasher`.
+use core::hash::SipHasher;
 use core::ops::Deref;
 
 /// A [`Router`] implemented using [`find_route`].
@@ -41,7 +46,9 @@ pub struct DefaultRouter<G: Deref<Target = NetworkGraph<L>>, L: Deref, S: Deref,
 	logger: L,
 	random_seed_bytes: Mutex<[u8; 32]>,
 	scorer: S,
-	score_params: SP
+	score_params: RwLock<SP>,
+	route_cache_config: Option<RouteCacheConfig>,
+	route_cache: Mutex<RouteCache>,
 }
 
 impl<G: Deref<Target = NetworkGraph<L>>, L: Deref, S: Deref, SP: Sized, Sc: Score<ScoreParams = SP>> DefaultRouter<G, L, S, SP, Sc> where
@@ -51,7 +58,122 @@ impl<G: Deref<Target = NetworkGraph<L>>, L: Deref, S: Deref, SP: Sized, Sc: Scor
 	/// Creates a new router.
 	pub fn new(network_graph: G, logger: L, random_seed_bytes: [u8; 32], scorer: S, score_params: SP) -> Self {
 		let random_seed_bytes = Mutex::new(random_seed_bytes);
-		Self { network_graph, logger, random_seed_bytes, scorer, score_params }
+		let score_params = RwLock::new(score_params);
+		let route_cache = Mutex::new(RouteCache { routes: HashMap::new(), use_counter: 0, hits: 0 });
+		Self { network_graph, logger, random_seed_bytes, scorer, score_params, route_cache_config: None, route_cache }
+	}
+
+	/// Creates a new router, drawing the seed for its path and CLTV randomization from the given
+	/// [`EntropySource`].
+	///
+	/// Together with a [`DeterministicEntropySource`], this allows routes to be found
+	/// reproducibly, e.g. in integration tests.
+	///
+	/// [`DeterministicEntropySource`]: crate::sign::DeterministicEntropySource
+	pub fn from_entropy_source<ES: Deref>(network_graph: G, logger: L, entropy_source: ES, scorer: S, score_params: SP) -> Self
+	where ES::Target: EntropySource {
+		Self::new(network_graph, logger, entropy_source.get_secure_random_bytes(), scorer, score_params)
+	}
+
+	/// Enables caching of recently found routes, keyed by the payee and the power-of-two bucket of
+	/// the amount being sent.
+	///
+	/// When a route is requested for a cached payee and amount bucket, the cached path is rebuilt
+	/// for the exact amount being sent rather than finding a route from scratch. Only single-path
+	/// routes to a payee which isn't blinded are cached. A cached route is discarded and a route
+	/// found from scratch if any public channel on it was updated or removed from the graph since
+	/// it was cached, if the path can no longer carry the payment, if the payment is being retried
+	/// after failing over some channels, or once it has been reused
+	/// [`RouteCacheConfig::max_reuses`] times.
+	///
+	/// Note that rebuilding a cached route does not consider the scorer's current view of the
+	/// network or any in-flight HTLCs beyond the capacity of our own channels.
+	pub fn with_route_cache(mut self, config: RouteCacheConfig) -> Self {
+		self.route_cache_config = Some(config);
+		self
+	}
+
+	/// Returns the number of routes which were served from the cache enabled via
+	/// [`Self::with_route_cache`].
+	pub fn route_cache_hits(&self) -> usize {
+		self.route_cache.lock().unwrap().hits
+	}
+
+	fn cached_route(
+		&self, config: &RouteCacheConfig, key: &(PublicKey, u8), payer: &PublicKey,
+		params: &RouteParameters, first_hops: Option<&[&ChannelDetails]>,
+		random_seed_bytes: &[u8; 32]
+	) -> Option<Route> {
+		let graph_lock = self.network_graph.read_only();
+		let hops = {
+			let mut cache = self.route_cache.lock().unwrap();
+			cache.use_counter += 1;
+			let use_counter = cache.use_counter;
+			let entry = cache.routes.get_mut(key)?;
+			if entry.reuses >= config.max_reuses || !entry.is_current(&graph_lock) {
+				cache.routes.remove(key);
+				return None;
+			}
+			entry.reuses += 1;
+			entry.last_used = use_counter;
+			entry.hops.clone()
+		};
+		match build_route_from_hops_internal(
+			payer, &hops, &params.payment_params, &graph_lock, params.final_value_msat, first_hops,
+			&*self.logger, random_seed_bytes
+		) {
+			Ok(mut route) => {
+				add_random_cltv_offset(&mut route, &params.payment_params, &graph_lock, random_seed_bytes);
+				self.route_cache.lock().unwrap().hits += 1;
+				Some(route)
+			},
+			Err(_) => {
+				self.route_cache.lock().unwrap().routes.remove(key);
+				None
+			},
+		}
+	}
+
+	fn cache_route(&self, config: &RouteCacheConfig, key: (PublicKey, u8), payer: &PublicKey, route: &Route) {
+		if route.paths.len() != 1 || route.paths[0].blinded_tail.is_some() { return; }
+		let hops = &route.paths[0].hops;
+		let graph_lock = self.network_graph.read_only();
+		let mut channel_updates = Vec::with_capacity(hops.len());
+		let mut source = NodeId::from_pubkey(payer);
+		for hop in hops.iter() {
+			if let Some(last_update) = directional_last_update(&graph_lock, hop.short_channel_id, &source) {
+				channel_updates.push((hop.short_channel_id, source, last_update));
+			}
+			source = NodeId::from_pubkey(&hop.pubkey);
+		}
+
+		let mut cache = self.route_cache.lock().unwrap();
+		if !cache.routes.contains_key(&key) && cache.routes.len() >= config.max_entries {
+			let lru_key = cache.routes.iter().min_by_key(|(_, entry)| entry.last_used).map(|(lru_key, _)| *lru_key);
+			if let Some(lru_key) = lru_key {
+				cache.routes.remove(&lru_key);
+			} else { return; }
+		}
+		cache.use_counter += 1;
+		let last_used = cache.use_counter;
+		cache.routes.insert(key, CachedRoute {
+			hops: hops.iter().map(|hop| hop.pubkey).collect(),
+			channel_updates, reuses: 0, last_used,
+		});
+	}
+
+	fn next_random_seed_bytes(&self) -> [u8; 32] {
+		let mut locked_random_seed_bytes = self.random_seed_bytes.lock().unwrap();
+		*locked_random_seed_bytes = Sha256::hash(&*locked_random_seed_bytes).into_inner();
+		*locked_random_seed_bytes
+	}
+
+	/// Replaces the parameters passed to the scorer when finding routes, e.g., to tune
+	/// [`ProbabilisticScoringFeeParameters`] at runtime. Takes effect for the next route found.
+	///
+	/// [`ProbabilisticScoringFeeParameters`]: crate::routing::scoring::ProbabilisticScoringFeeParameters
+	pub fn set_score_params(&self, score_params: SP) {
+		*self.score_params.write().unwrap() = score_params;
 	}
 }
 
@@ -66,17 +188,235 @@ impl< G: Deref<Target = NetworkGraph<L>>, L: Deref, S: Deref,  SP: Sized, Sc: Sc
 		first_hops: Option<&[&ChannelDetails]>,
 		inflight_htlcs: &InFlightHtlcs
 	) -> Result<Route, LightningError> {
-		let random_seed_bytes = {
-			let mut locked_random_seed_bytes = self.random_seed_bytes.lock().unwrap();
-			*locked_random_seed_bytes = Sha256::hash(&*locked_random_seed_bytes).into_inner();
-			*locked_random_seed_bytes
+		let random_seed_bytes = self.next_random_seed_bytes();
+
+		let cache_key = match (&self.route_cache_config, &params.payment_params.payee) {
+			(Some(_), Payee::Clear { node_id, .. }) =>
+				Some((*node_id, route_cache_amount_bucket(params.final_value_msat))),
+			_ => None,
 		};
-		find_route(
+		if let (Some(config), Some(key)) = (&self.route_cache_config, &cache_key) {
+			// If the payment is being retried we'd rather find a fresh route, which replaces the
+			// cached one below.
+			if params.payment_params.previously_failed_channels.is_empty() {
+				if let Some(route) = self.cached_route(config, key, payer, params, first_hops, &random_seed_bytes) {
+					return Ok(route);
+				}
+			}
+		}
+
+		let route = find_route(
 			payer, params, &self.network_graph, first_hops, &*self.logger,
 			&ScorerAccountingForInFlightHtlcs::new(self.scorer.lock(), inflight_htlcs),
-			&self.score_params,
+			&*self.score_params.read().unwrap(),
 			&random_seed_bytes
-		)
+		)?;
+		if let (Some(config), Some(key)) = (&self.route_cache_config, cache_key) {
+			self.cache_route(config, key, payer, &route);
+		}
+		Ok(route)
+	}
+
+	/// Finds routes for all payments while holding a single read lock on the [`NetworkGraph`], so
+	/// that they are all found against the same snapshot of it. Where a single-path route was
+	/// already found to the same payee earlier in the batch, its hops are reused rather than
+	/// pathfinding again. The route cache, if enabled, is not consulted.
+	fn find_routes(
+		&self, payer: &PublicKey, route_params: &[RouteParameters],
+		first_hops: Option<&[&ChannelDetails]>, inflight_htlcs: &InFlightHtlcs
+	) -> Vec<Result<Route, LightningError>> {
+		let mut inflight_htlcs = inflight_htlcs.clone();
+		let mut scorer = self.scorer.lock();
+		let score_params = self.score_params.read().unwrap();
+		let graph_lock = self.network_graph.read_only();
+		let mut found_hops: HashMap<PublicKey, Vec<PublicKey>> = HashMap::new();
+		let mut routes = Vec::with_capacity(route_params.len());
+		for params in route_params.iter() {
+			let random_seed_bytes = self.next_random_seed_bytes();
+			let payee_node_id = match &params.payment_params.payee {
+				Payee::Clear { node_id, .. } => Some(*node_id),
+				Payee::Blinded { .. } => None,
+			};
+			let reused_route = payee_node_id.as_ref()
+				.and_then(|node_id| found_hops.get(node_id))
+				.and_then(|hops| build_route_from_hops_internal(
+					payer, hops, &params.payment_params, &graph_lock, params.final_value_msat, first_hops,
+					&*self.logger, &random_seed_bytes
+				).ok());
+			let route_res = match reused_route {
+				Some(route) => Ok(route),
+				None => get_route(
+					payer, &params.payment_params, &graph_lock, first_hops, params.final_value_msat,
+					&*self.logger, &ScorerAccountingForInFlightHtlcs::new(&mut scorer, &inflight_htlcs),
+					&*score_params, &random_seed_bytes
+				),
+			};
+			let route_res = route_res.map(|mut route| {
+				add_random_cltv_offset(&mut route, &params.payment_params, &graph_lock, &random_seed_bytes);
+				route
+			});
+			if let Ok(route) = &route_res {
+				for path in route.paths.iter() {
+					inflight_htlcs.process_path(path, *payer);
+				}
+				if let (Some(node_id), 1) = (payee_node_id, route.paths.len()) {
+					if route.paths[0].blinded_tail.is_none() {
+						found_hops.entry(node_id)
+							.or_insert_with(|| route.paths[0].hops.iter().map(|hop| hop.pubkey).collect());
+					}
+				}
+			}
+			routes.push(route_res);
+		}
+		routes
+	}
+}
+
+/// Configuration for the route cache of a [`DefaultRouter`], enabled via
+/// [`DefaultRouter::with_route_cache`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RouteCacheConfig {
+	/// The maximum number of routes to cache. Once full, the least recently used route is evicted
+	/// to make room for a new one.
+	///
+	/// Default value: 128
+	pub max_entries: usize,
+	/// The number of times a cached route may be reused before a route is found from scratch
+	/// again, allowing changes in the scorer's view of the network to be picked up.
+	///
+	/// Default value: 16
+	pub max_reuses: u32,
+}
+
+impl Default for RouteCacheConfig {
+	fn default() -> Self {
+		Self { max_entries: 128, max_reuses: 16 }
+	}
+}
+
+struct CachedRoute {
+	/// The node ids along the route, excluding the payer.
+	hops: Vec<PublicKey>,
+	/// The short channel id, source node and `last_update` of each channel on the route which was
+	/// in the graph when the route was cached.
+	channel_updates: Vec<(u64, NodeId, Option<u32>)>,
+	reuses: u32,
+	last_used: u64,
+}
+
+impl CachedRoute {
+	/// Returns false if any public channel on the route was updated or removed since it was cached.
+	fn is_current(&self, network_graph: &ReadOnlyNetworkGraph) -> bool {
+		self.channel_updates.iter().all(|(scid, source, last_update)|
+			directional_last_update(network_graph, *scid, source) == Some(*last_update))
+	}
+}
+
+struct RouteCache {
+	routes: HashMap<(PublicKey, u8), CachedRoute>,
+	use_counter: u64,
+	hits: usize,
+}
+
+/// Routes are cached per power of two of the amount sent.
+fn route_cache_amount_bucket(amount_msat: u64) -> u8 {
+	(64 - amount_msat.leading_zeros()) as u8
+}
+
+/// Returns the `last_update` of the given channel in the direction from `source`, if the channel
+/// is in the graph.
+fn directional_last_update(network_graph: &ReadOnlyNetworkGraph, short_channel_id: u64, source: &NodeId) -> Option<Option<u32>> {
+	network_graph.channel(short_channel_id).map(|channel| {
+		let update = if channel.node_one == *source { &channel.one_to_two } else { &channel.two_to_one };
+		update.as_ref().map(|update| update.last_update)
+	})
+}
+
+/// A [`Router`] which loads the channels a [`NetworkGraph`] offloaded to an
+/// [`OffloadedChannelStore`] around the payer and the recipient back into memory before finding a
+/// route with the wrapped [`Router`].
+///
+/// Channels are loaded up to `max_hops` hops away from the payer, the recipient and the sources
+/// of its route hints, or the introduction nodes of its blinded paths. Failures to read from the
+/// store are logged and the route is searched for in whatever is in memory.
+pub struct OffloadingRouter<R: Deref, G: Deref<Target = NetworkGraph<L>>, L: Deref, K: Deref> where
+	R::Target: Router,
+	L::Target: Logger,
+	K::Target: KVStorePersister + KVStoreReader,
+{
+	router: R,
+	network_graph: G,
+	channel_store: OffloadedChannelStore<K>,
+	max_hops: u8,
+	logger: L,
+}
+
+impl<R: Deref, G: Deref<Target = NetworkGraph<L>>, L: Deref, K: Deref> OffloadingRouter<R, G, L, K> where
+	R::Target: Router,
+	L::Target: Logger,
+	K::Target: KVStorePersister + KVStoreReader,
+{
+	/// Creates a new router loading channels from the given store into `network_graph`, which
+	/// should be the graph `router` finds routes in.
+	pub fn new(router: R, network_graph: G, channel_store: OffloadedChannelStore<K>, max_hops: u8, logger: L) -> Self {
+		Self { router, network_graph, channel_store, max_hops, logger }
+	}
+
+	fn load_subgraphs(&self, payer: &PublicKey, route_params: &RouteParameters) {
+		let mut node_ids = vec![NodeId::from_pubkey(payer)];
+		match &route_params.payment_params.payee {
+			Payee::Clear { node_id, route_hints, .. } => {
+				node_ids.push(NodeId::from_pubkey(node_id));
+				for hop in route_hints.iter().flat_map(|hint| hint.0.iter()) {
+					node_ids.push(NodeId::from_pubkey(&hop.src_node_id));
+				}
+			},
+			Payee::Blinded { route_hints, .. } => {
+				for (_, path) in route_hints.iter() {
+					node_ids.push(NodeId::from_pubkey(&path.introduction_node_id));
+				}
+			},
+		}
+		node_ids.sort_unstable();
+		node_ids.dedup();
+		for node_id in node_ids.iter() {
+			if let Err(e) = self.channel_store.load_subgraph(&self.network_graph, node_id, self.max_hops) {
+				log_error!(self.logger, "Failed to load offloaded channels around node {}: {}", node_id, e);
+			}
+		}
+	}
+}
+
+impl<R: Deref, G: Deref<Target = NetworkGraph<L>>, L: Deref, K: Deref> Router for OffloadingRouter<R, G, L, K> where
+	R::Target: Router,
+	L::Target: Logger,
+	K::Target: KVStorePersister + KVStoreReader,
+{
+	fn find_route(
+		&self, payer: &PublicKey, route_params: &RouteParameters,
+		first_hops: Option<&[&ChannelDetails]>, inflight_htlcs: &InFlightHtlcs
+	) -> Result<Route, LightningError> {
+		self.load_subgraphs(payer, route_params);
+		self.router.find_route(payer, route_params, first_hops, inflight_htlcs)
+	}
+
+	fn find_route_with_id(
+		&self, payer: &PublicKey, route_params: &RouteParameters,
+		first_hops: Option<&[&ChannelDetails]>, inflight_htlcs: &InFlightHtlcs,
+		payment_hash: PaymentHash, payment_id: PaymentId
+	) -> Result<Route, LightningError> {
+		self.load_subgraphs(payer, route_params);
+		self.router.find_route_with_id(payer, route_params, first_hops, inflight_htlcs, payment_hash, payment_id)
+	}
+
+	fn find_routes(
+		&self, payer: &PublicKey, route_params: &[RouteParameters],
+		first_hops: Option<&[&ChannelDetails]>, inflight_htlcs: &InFlightHtlcs
+	) -> Vec<Result<Route, LightningError>> {
+		for params in route_params.iter() {
+			self.load_subgraphs(payer, params);
+		}
+		self.router.find_routes(payer, route_params, first_hops, inflight_htlcs)
 	}
 }
 
@@ -96,6 +436,286 @@ pub trait Router {
 	) -> Result<Route, LightningError> {
 		self.find_route(payer, route_params, first_hops, inflight_htlcs)
 	}
+	/// Finds a [`Route`] for each of the given payments, returning the results in the same order,
+	/// e.g., to pay many recipients at once via [`ChannelManager::send_batch_payments`].
+	///
+	/// Implementations may share work between the payments. By default, routes are found one after
+	/// another with [`Router::find_route`], accounting for the liquidity used by earlier routes in
+	/// the batch as in-flight HTLCs.
+	///
+	/// [`ChannelManager::send_batch_payments`]: crate::ln::channelmanager::ChannelManager::send_batch_payments
+	fn find_routes(
+		&self, payer: &PublicKey, route_params: &[RouteParameters],
+		first_hops: Option<&[&ChannelDetails]>, inflight_htlcs: &InFlightHtlcs
+	) -> Vec<Result<Route, LightningError>> {
+		let mut inflight_htlcs = inflight_htlcs.clone();
+		route_params.iter().map(|params| {
+			let route_res = self.find_route(payer, params, first_hops, &inflight_htlcs);
+			if let Ok(route) = &route_res {
+				for path in route.paths.iter() {
+					inflight_htlcs.process_path(path, *payer);
+				}
+			}
+			route_res
+		}).collect()
+	}
+}
+
+/// The result of a [`RouteProvider::request_routes`] call. A request may resolve either
+/// synchronously, returning the `Sync` variant, or asynchronously, returning a [`RouteFuture`] in
+/// the `Async` variant.
+#[derive(Clone)]
+pub enum RouteRequestResult {
+	/// A result which was resolved synchronously. It either includes the candidate [`Route`]s
+	/// found or a [`LightningError`] describing why none could be.
+	Sync(Result<Vec<Route>, LightningError>),
+	/// A result which will be resolved asynchronously. It includes a [`RouteFuture`], a `clone` of
+	/// which you must keep locally and call [`RouteFuture::resolve`] on once the request completes.
+	///
+	/// Until the future is resolved, [`DelegatedRouter`] fails requests for a route for the same
+	/// payment. The payment should thus be retried with the same [`PaymentId`] once the future has
+	/// been resolved, at which point the resolved candidates will be used. If the payment will not
+	/// be retried, call [`DelegatedRouter::remove_pending_request`] instead.
+	Async(RouteFuture),
+}
+
+/// The `RouteProvider` trait defines behavior for requesting candidate routes from an external
+/// source, such as a remote pathfinding service, for use by a [`DelegatedRouter`].
+///
+/// See [`RemoteRouteProvider`] for a provider requesting routes from a trusted server over onion
+/// messages.
+///
+/// [`RemoteRouteProvider`]: crate::onion_message::RemoteRouteProvider
+pub trait RouteProvider {
+	/// Requests candidate [`Route`]s from `payer` for a payment with the given values.
+	///
+	/// Routes must start with one of our `first_hops`, may use any number of paths up to the
+	/// [`PaymentParameters::max_path_count`], and should pay exactly
+	/// [`RouteParameters::final_value_msat`] to the payee.
+	///
+	/// `inflight_htlcs` are our HTLCs which are currently in-flight, whose liquidity routes should
+	/// account for.
+	fn request_routes(
+		&self, payer: &PublicKey, route_params: &RouteParameters, first_hops: &[&ChannelDetails],
+		inflight_htlcs: &InFlightHtlcs
+	) -> RouteRequestResult;
+}
+
+/// Represents a future resolution of a [`RouteProvider::request_routes`] query resolving async.
+///
+/// See [`RouteRequestResult::Async`] and [`RouteFuture::resolve`] for more info.
+#[derive(Clone)]
+pub struct RouteFuture {
+	result: Arc<Mutex<Option<Result<Vec<Route>, LightningError>>>>,
+}
+
+impl RouteFuture {
+	/// Builds a new future for later resolution.
+	pub fn new() -> Self {
+		Self { result: Arc::new(Mutex::new(None)) }
+	}
+
+	/// Resolves this future with the candidate routes found, or the reason none were.
+	pub fn resolve(&self, result: Result<Vec<Route>, LightningError>) {
+		*self.result.lock().unwrap() = Some(result);
+	}
+
+	fn take_result(&self) -> Option<Result<Vec<Route>, LightningError>> {
+		self.result.lock().unwrap().take()
+	}
+}
+
+/// Limits on the routing fees a [`DelegatedRouter`] accepts for routes returned by its
+/// [`RouteProvider`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RouteFeeLimits {
+	/// The maximum total routing fee, in millisatoshis, accepted for any payment, in addition to
+	/// [`Self::max_fee_proportional_millionths`].
+	///
+	/// Default value: 50,000 msat.
+	pub max_fee_base_msat: u64,
+	/// The maximum total routing fee accepted, in millionths of the amount being sent, in addition
+	/// to [`Self::max_fee_base_msat`].
+	///
+	/// Default value: 10,000 (1%).
+	pub max_fee_proportional_millionths: u32,
+}
+
+impl Default for RouteFeeLimits {
+	fn default() -> Self {
+		Self { max_fee_base_msat: 50_000, max_fee_proportional_millionths: 10_000 }
+	}
+}
+
+impl RouteFeeLimits {
+	fn max_fee_msat(&self, amount_msat: u64) -> u64 {
+		let proportional_fee_msat =
+			(amount_msat as u128 * self.max_fee_proportional_millionths as u128 / 1_000_000) as u64;
+		self.max_fee_base_msat.saturating_add(proportional_fee_msat)
+	}
+}
+
+/// A [`Router`] which delegates pathfinding to a [`RouteProvider`], e.g. for devices too
+/// constrained to store and search the network graph themselves.
+///
+/// As the provider is not trusted, each candidate [`Route`] it returns is validated against our
+/// usable first hops, the [`RouteParameters`] of the payment and the configured
+/// [`RouteFeeLimits`], and the cheapest valid candidate is used.
+pub struct DelegatedRouter<P: Deref, L: Deref> where P::Target: RouteProvider, L::Target: Logger {
+	provider: P,
+	logger: L,
+	fee_limits: RouteFeeLimits,
+	/// Requests which the provider is resolving asynchronously, by the payment they are for.
+	pending_requests: Mutex<HashMap<PaymentId, RouteFuture>>,
+}
+
+impl<P: Deref, L: Deref> DelegatedRouter<P, L> where P::Target: RouteProvider, L::Target: Logger {
+	/// Creates a new router requesting routes from the given `provider`.
+	pub fn new(provider: P, logger: L, fee_limits: RouteFeeLimits) -> Self {
+		Self { provider, logger, fee_limits, pending_requests: Mutex::new(HashMap::new()) }
+	}
+
+	/// Forgets the outstanding asynchronous route request for the given payment, if any, e.g.
+	/// once the payment has been abandoned. Returns whether a request was pending.
+	pub fn remove_pending_request(&self, payment_id: &PaymentId) -> bool {
+		self.pending_requests.lock().unwrap().remove(payment_id).is_some()
+	}
+
+	fn route_request_pending() -> LightningError {
+		LightningError {
+			err: "Route request is pending with the route provider".to_owned(),
+			action: ErrorAction::IgnoreError,
+		}
+	}
+
+	/// Picks the cheapest of the candidate `routes` which passes validation.
+	fn select_route(
+		&self, payer: &PublicKey, route_params: &RouteParameters,
+		first_hops: Option<&[&ChannelDetails]>, routes: Result<Vec<Route>, LightningError>
+	) -> Result<Route, LightningError> {
+		let mut best_route: Option<Route> = None;
+		for mut route in routes? {
+			if let Err(e) = self.validate_route(payer, route_params, first_hops, &route) {
+				log_debug!(self.logger, "Ignoring route from route provider: {}", e);
+				continue;
+			}
+			let is_cheaper = best_route.as_ref()
+				.map_or(true, |best| route.get_total_amount() + route.get_total_fees() < best.get_total_amount() + best.get_total_fees());
+			if is_cheaper {
+				route.payment_params = Some(route_params.payment_params.clone());
+				best_route = Some(route);
+			}
+		}
+		best_route.ok_or_else(|| LightningError {
+			err: "None of the routes returned by the route provider were valid".to_owned(),
+			action: ErrorAction::IgnoreError,
+		})
+	}
+
+	fn validate_route(
+		&self, payer: &PublicKey, route_params: &RouteParameters,
+		first_hops: Option<&[&ChannelDetails]>, route: &Route
+	) -> Result<(), &'static str> {
+		let payment_params = &route_params.payment_params;
+		if route.paths.is_empty() {
+			return Err("Route has no paths");
+		}
+		if route.paths.len() > payment_params.max_path_count as usize {
+			return Err("Route has more paths than allowed");
+		}
+		let total_amount_msat = route.get_total_amount();
+		if total_amount_msat < route_params.final_value_msat {
+			return Err("Route does not pay the full amount");
+		}
+		// Any overpayment is lost to us just like a fee, so count it against the fee limit.
+		let total_fee_msat = route.get_total_fees()
+			.saturating_add(total_amount_msat - route_params.final_value_msat);
+		if total_fee_msat > self.fee_limits.max_fee_msat(route_params.final_value_msat) {
+			return Err("Route fees exceed the configured limit");
+		}
+
+		let mut first_hop_usage_msat = HashMap::new();
+		for path in route.paths.iter() {
+			let first_hop = path.hops.first().ok_or("Route contains an empty path")?;
+			if path.hops.iter().any(|hop| hop.pubkey == *payer) {
+				return Err("Path loops back through us");
+			}
+			if path.hops.iter().any(|hop| payment_params.previously_failed_channels.contains(&hop.short_channel_id)) {
+				return Err("Path uses a channel which previously failed this payment");
+			}
+			match &payment_params.payee {
+				Payee::Clear { node_id, .. } => {
+					if path.blinded_tail.is_some() || path.hops.last().map(|hop| &hop.pubkey) != Some(node_id) {
+						return Err("Path does not end at the payee");
+					}
+				},
+				Payee::Blinded { .. } => {
+					if path.blinded_tail.is_none() {
+						return Err("Path does not end in one of the payee's blinded paths");
+					}
+				},
+			}
+			let total_cltv_expiry_delta = path.hops.iter().map(|hop| hop.cltv_expiry_delta as u64).sum::<u64>();
+			if total_cltv_expiry_delta > payment_params.max_total_cltv_expiry_delta as u64 {
+				return Err("Path exceeds the maximum total CLTV expiry delta");
+			}
+
+			if let Some(first_hops) = first_hops {
+				let channel = first_hops.iter()
+					.find(|channel| channel.get_outbound_payment_scid() == Some(first_hop.short_channel_id))
+					.filter(|channel| channel.counterparty.node_id == first_hop.pubkey)
+					.ok_or("Path does not start with one of our usable channels")?;
+				let path_amount_msat = path.final_value_msat() + path.fee_msat();
+				if path_amount_msat < channel.next_outbound_htlc_minimum_msat {
+					return Err("Path sends less than our first hop's HTLC minimum");
+				}
+				let usage_msat = first_hop_usage_msat.entry(first_hop.short_channel_id).or_insert(0);
+				*usage_msat += path_amount_msat;
+				if *usage_msat > channel.next_outbound_htlc_limit_msat {
+					return Err("Route exceeds the available balance of our first hop");
+				}
+			}
+		}
+		Ok(())
+	}
+}
+
+impl<P: Deref, L: Deref> Router for DelegatedRouter<P, L> where P::Target: RouteProvider, L::Target: Logger {
+	fn find_route(
+		&self, payer: &PublicKey, route_params: &RouteParameters,
+		first_hops: Option<&[&ChannelDetails]>, inflight_htlcs: &InFlightHtlcs
+	) -> Result<Route, LightningError> {
+		match self.provider.request_routes(payer, route_params, first_hops.unwrap_or(&[]), inflight_htlcs) {
+			RouteRequestResult::Sync(routes) => self.select_route(payer, route_params, first_hops, routes),
+			// Without a payment id, the eventual result cannot be matched to a later request.
+			RouteRequestResult::Async(_) => Err(LightningError {
+				err: "Route provider cannot resolve requests without a payment id asynchronously".to_owned(),
+				action: ErrorAction::IgnoreError,
+			}),
+		}
+	}
+
+	fn find_route_with_id(
+		&self, payer: &PublicKey, route_params: &RouteParameters,
+		first_hops: Option<&[&ChannelDetails]>, inflight_htlcs: &InFlightHtlcs,
+		_payment_hash: PaymentHash, payment_id: PaymentId
+	) -> Result<Route, LightningError> {
+		let pending_request = self.pending_requests.lock().unwrap().remove(&payment_id);
+		let future = match pending_request {
+			Some(future) => future,
+			None => match self.provider.request_routes(payer, route_params, first_hops.unwrap_or(&[]), inflight_htlcs) {
+				RouteRequestResult::Sync(routes) => return self.select_route(payer, route_params, first_hops, routes),
+				RouteRequestResult::Async(future) => future,
+			},
+		};
+		match future.take_result() {
+			Some(routes) => self.select_route(payer, route_params, first_hops, routes),
+			None => {
+				self.pending_requests.lock().unwrap().insert(payment_id, future);
+				Err(Self::route_request_pending())
+			},
+		}
+	}
 }
 
 /// [`Score`] implementation that factors in in-flight HTLC liquidity.
@@ -142,6 +762,15 @@ impl<'a, S: Score> Score for ScorerAccountingForInFlightHtlcs<'a, S> {
 		}
 	}
 
+	fn channel_success_probability(&self, short_channel_id: u64, source: &NodeId, target: &NodeId, usage: ChannelUsage, score_params: &Self::ScoreParams) -> Option<f64> {
+		let used_liquidity = self.inflight_htlcs.used_liquidity_msat(source, target, short_channel_id).unwrap_or(0);
+		let usage = ChannelUsage {
+			inflight_htlc_msat: usage.inflight_htlc_msat + used_liquidity,
+			..usage
+		};
+		self.scorer.channel_success_probability(short_channel_id, source, target, usage, score_params)
+	}
+
 	fn payment_path_failed(&mut self, path: &Path, short_channel_id: u64) {
 		self.scorer.payment_path_failed(path, short_channel_id)
 	}
@@ -157,11 +786,15 @@ impl<'a, S: Score> Score for ScorerAccountingForInFlightHtlcs<'a, S> {
 	fn probe_successful(&mut self, path: &Path) {
 		self.scorer.probe_successful(path)
 	}
+
+	fn payment_path_hold_times(&mut self, path: &Path, hold_times: &[u32]) {
+		self.scorer.payment_path_hold_times(path, hold_times)
+	}
 }
 
 /// A data structure for tracking in-flight HTLCs. May be used during pathfinding to account for
 /// in-use channel liquidity.
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct InFlightHtlcs(
 	// A map with liquidity value (in msat) keyed by a short channel id and the direction the HTLC
 	// is traveling in. The direction boolean is determined by checking if the HTLC source's public
@@ -481,6 +1114,10 @@ pub const DEFAULT_MAX_TOTAL_CLTV_EXPIRY_DELTA: u32 = 1008;
 // limits, but for now more than 10 paths likely carries too much one-path failure.
 pub const DEFAULT_MAX_PATH_COUNT: u8 = 10;
 
+/// The minimum number of parts a payment is split into if its [`PaymentPrivacyLevel`] is not
+/// [`PaymentPrivacyLevel::Standard`], unless limited by [`PaymentParameters::max_path_count`].
+pub const MIN_PRIVATE_PAYMENT_PARTS: u8 = 3;
+
 // The median hop CLTV expiry delta currently seen in the network.
 const MEDIAN_HOP_CLTV_EXPIRY_DELTA: u32 = 40;
 
@@ -497,6 +1134,36 @@ const MEDIAN_HOP_CLTV_EXPIRY_DELTA: u32 = 40;
 // down from (1300-93) / 61 = 19.78... to arrive at a conservative estimate of 19.
 const MAX_PATH_LENGTH_ESTIMATE: u8 = 19;
 
+/// How much effort is spent on hiding the amount and timing of a payment from the nodes it is
+/// routed through, see [`PaymentParameters::privacy_level`].
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum PaymentPrivacyLevel {
+	/// The payment is only split into multiple parts if it cannot be sent over a single path.
+	Standard,
+	/// The payment is split into at least [`MIN_PRIVATE_PAYMENT_PARTS`] parts, even if fewer
+	/// would suffice, so that no intermediate node sees the full payment amount. The paths of the
+	/// parts do not share any nodes other than us and the recipient.
+	///
+	/// Note that this may cause payments to fail to route which would otherwise succeed, and that
+	/// each additional part pays the base fees of its path.
+	DecoyParts,
+	/// As with [`Self::DecoyParts`], but additionally only the first part is sent immediately. The
+	/// other parts are sent one at a time over the following
+	/// [`ChannelManager::process_pending_htlc_forwards`] calls, for which randomly delayed
+	/// [`Event::PendingHTLCsForwardable`]s are generated, making it harder for intermediate nodes
+	/// to correlate the parts by their timing.
+	///
+	/// [`ChannelManager::process_pending_htlc_forwards`]: crate::ln::channelmanager::ChannelManager::process_pending_htlc_forwards
+	/// [`Event::PendingHTLCsForwardable`]: crate::events::Event::PendingHTLCsForwardable
+	DecoyPartsAndTimingSpread,
+}
+
+impl_writeable_tlv_based_enum!(PaymentPrivacyLevel,
+	(0, Standard) => {},
+	(2, DecoyParts) => {},
+	(4, DecoyPartsAndTimingSpread) => {};
+);
+
 /// Information used to route a payment.
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub struct PaymentParameters {
@@ -529,6 +1196,69 @@ pub struct PaymentParameters {
 	/// Default value: 2
 	pub max_channel_saturation_power_of_half: u8,
 
+	/// The minimum amount, in millisatoshis, which each part of a multi-path payment must carry.
+	///
+	/// Paths which cannot contribute at least this much to the payment are not considered during
+	/// pathfinding, which avoids splitting a payment into many small parts that each pay the
+	/// per-channel base fees. Regardless of this value, each part must carry at least
+	/// 1/[`max_path_count`] of the payment, and no part is required to carry more than the full
+	/// payment amount.
+	///
+	/// Default value: 0
+	///
+	/// [`max_path_count`]: Self::max_path_count
+	pub min_mpp_part_msat: u64,
+
+	/// Whether to prefer sending a payment over fewer, larger MPP parts rather than many smaller
+	/// ones.
+	///
+	/// If set, [`max_channel_saturation_power_of_half`] is not enforced while searching for paths
+	/// and our own channels are always tried in descending order of available outbound capacity,
+	/// rather than preferring the smallest channel which can carry the payment.
+	///
+	/// Default value: false
+	///
+	/// [`max_channel_saturation_power_of_half`]: Self::max_channel_saturation_power_of_half
+	pub prefer_fewer_mpp_parts: bool,
+
+	/// Whether a payment may be split across several of our channels to the same first-hop peer.
+	///
+	/// If unset, only the channel with the most available outbound capacity to each peer is
+	/// considered, which may be useful where a peer's channels share the same onward liquidity.
+	///
+	/// Default value: true
+	pub split_across_first_hop_channels: bool,
+
+	/// The weight, as a percentage, given to the scorer's channel penalties relative to fees when
+	/// comparing paths.
+	///
+	/// Channel penalties express the fees we are willing to pay to avoid a channel which we
+	/// estimate to be unreliable. A value of 0 ignores them (except for channels the scorer
+	/// considers unusable), selecting the cheapest path regardless of its reliability, which may
+	/// suit batch payouts that can be retried. Values above 100 prefer more reliable but more
+	/// expensive paths, which may suit time-sensitive payments.
+	///
+	/// Default value: 100
+	pub channel_penalty_weight_percent: u16,
+
+	/// The minimum estimated success probability, in millionths, that each path of the payment
+	/// must have.
+	///
+	/// A path's estimated success probability is the product of the probabilities returned by
+	/// [`Score::channel_success_probability`] for each of its channels. For scorers which don't
+	/// provide such estimates, a channel's probability is estimated from its capacity, assuming
+	/// its liquidity is uniformly distributed. Paths below this threshold are discarded and their
+	/// least reliable channel is not considered further. A value of 0 disables this check.
+	///
+	/// Default value: 0
+	pub min_path_success_probability_millionths: u32,
+
+	/// How much effort is spent on hiding the amount and timing of the payment from the nodes it
+	/// is routed through.
+	///
+	/// Default value: [`PaymentPrivacyLevel::Standard`]
+	pub privacy_level: PaymentPrivacyLevel,
+
 	/// A list of SCIDs which this payment was previously attempted over and which caused the
 	/// payment to fail. Future attempts for the same payment shouldn't be relayed through any of
 	/// these SCIDs.
@@ -554,6 +1284,12 @@ impl Writeable for PaymentParameters {
 			(7, self.previously_failed_channels, vec_type),
 			(8, *blinded_hints, optional_vec),
 			(9, self.payee.final_cltv_expiry_delta(), option),
+			(11, self.min_mpp_part_msat, required),
+			(13, self.prefer_fewer_mpp_parts, required),
+			(15, self.split_across_first_hop_channels, required),
+			(17, self.channel_penalty_weight_percent, required),
+			(19, self.min_path_success_probability_millionths, required),
+			(21, self.privacy_level, required),
 		});
 		Ok(())
 	}
@@ -572,6 +1308,12 @@ impl ReadableArgs<u32> for PaymentParameters {
 			(7, previously_failed_channels, vec_type),
 			(8, blinded_route_hints, optional_vec),
 			(9, final_cltv_expiry_delta, (default_value, default_final_cltv_expiry_delta)),
+			(11, min_mpp_part_msat, (default_value, 0)),
+			(13, prefer_fewer_mpp_parts, (default_value, false)),
+			(15, split_across_first_hop_channels, (default_value, true)),
+			(17, channel_penalty_weight_percent, (default_value, 100)),
+			(19, min_path_success_probability_millionths, (default_value, 0)),
+			(21, privacy_level, (default_value, PaymentPrivacyLevel::Standard)),
 		});
 		let clear_route_hints = route_hints.unwrap_or(vec![]);
 		let blinded_route_hints = blinded_route_hints.unwrap_or(vec![]);
@@ -594,6 +1336,12 @@ impl ReadableArgs<u32> for PaymentParameters {
 			max_path_count: _init_tlv_based_struct_field!(max_path_count, (default_value, unused)),
 			payee,
 			max_channel_saturation_power_of_half: _init_tlv_based_struct_field!(max_channel_saturation_power_of_half, (default_value, unused)),
+			min_mpp_part_msat: _init_tlv_based_struct_field!(min_mpp_part_msat, (default_value, unused)),
+			prefer_fewer_mpp_parts: _init_tlv_based_struct_field!(prefer_fewer_mpp_parts, (default_value, unused)),
+			split_across_first_hop_channels: _init_tlv_based_struct_field!(split_across_first_hop_channels, (default_value, unused)),
+			channel_penalty_weight_percent: _init_tlv_based_struct_field!(channel_penalty_weight_percent, (default_value, unused)),
+			min_path_success_probability_millionths: _init_tlv_based_struct_field!(min_path_success_probability_millionths, (default_value, unused)),
+			privacy_level: _init_tlv_based_struct_field!(privacy_level, (default_value, unused)),
 			expiry_time,
 			previously_failed_channels: previously_failed_channels.unwrap_or(Vec::new()),
 		})
@@ -613,6 +1361,35 @@ impl PaymentParameters {
 			max_total_cltv_expiry_delta: DEFAULT_MAX_TOTAL_CLTV_EXPIRY_DELTA,
 			max_path_count: DEFAULT_MAX_PATH_COUNT,
 			max_channel_saturation_power_of_half: 2,
+			min_mpp_part_msat: 0,
+			prefer_fewer_mpp_parts: false,
+			split_across_first_hop_channels: true,
+			channel_penalty_weight_percent: 100,
+			min_path_success_probability_millionths: 0,
+			privacy_level: PaymentPrivacyLevel::Standard,
+			previously_failed_channels: Vec::new(),
+		}
+	}
+
+	/// Creates parameters for paying to a blinded payee from the provided blinded route hints.
+	///
+	/// As no invoice is required, this may be used for spontaneous payments to a recipient which
+	/// is only known by its blinded paths, e.g. via [`ChannelManager::send_spontaneous_payment_with_retry`].
+	///
+	/// [`ChannelManager::send_spontaneous_payment_with_retry`]: crate::ln::channelmanager::ChannelManager::send_spontaneous_payment_with_retry
+	pub fn blinded(blinded_route_hints: Vec<(BlindedPayInfo, BlindedPath)>) -> Self {
+		Self {
+			payee: Payee::Blinded { route_hints: blinded_route_hints, features: None },
+			expiry_time: None,
+			max_total_cltv_expiry_delta: DEFAULT_MAX_TOTAL_CLTV_EXPIRY_DELTA,
+			max_path_count: DEFAULT_MAX_PATH_COUNT,
+			max_channel_saturation_power_of_half: 2,
+			min_mpp_part_msat: 0,
+			prefer_fewer_mpp_parts: false,
+			split_across_first_hop_channels: true,
+			channel_penalty_weight_percent: 100,
+			min_path_success_probability_millionths: 0,
+			privacy_level: PaymentPrivacyLevel::Standard,
 			previously_failed_channels: Vec::new(),
 		}
 	}
@@ -650,6 +1427,18 @@ impl PaymentParameters {
 		}
 	}
 
+	/// Includes the payee's features. Errors if the parameters were not initialized with blinded
+	/// payment paths.
+	///
+	/// This is not exported to bindings users since bindings don't support move semantics
+	pub fn with_bolt12_features(self, features: Bolt12InvoiceFeatures) -> Result<Self, ()> {
+		match self.payee {
+			Payee::Clear { .. } => Err(()),
+			Payee::Blinded { route_hints, .. } =>
+				Ok(Self { payee: Payee::Blinded { route_hints, features: Some(features) }, ..self })
+		}
+	}
+
 	/// Includes hints for routing to the payee. Errors if the parameters were initialized with
 	/// blinded payment paths.
 	///
@@ -666,32 +1455,74 @@ impl PaymentParameters {
 		}
 	}
 
-	/// Includes a payment expiration in seconds relative to the UNIX epoch.
+	/// Includes a payment expiration in seconds relative to the UNIX epoch.
+	///
+	/// This is not exported to bindings users since bindings don't support move semantics
+	pub fn with_expiry_time(self, expiry_time: u64) -> Self {
+		Self { expiry_time: Some(expiry_time), ..self }
+	}
+
+	/// Includes a limit for the total CLTV expiry delta which is considered during routing
+	///
+	/// This is not exported to bindings users since bindings don't support move semantics
+	pub fn with_max_total_cltv_expiry_delta(self, max_total_cltv_expiry_delta: u32) -> Self {
+		Self { max_total_cltv_expiry_delta, ..self }
+	}
+
+	/// Includes a limit for the maximum number of payment paths that may be used.
+	///
+	/// This is not exported to bindings users since bindings don't support move semantics
+	pub fn with_max_path_count(self, max_path_count: u8) -> Self {
+		Self { max_path_count, ..self }
+	}
+
+	/// Includes a limit for the maximum number of payment paths that may be used.
+	///
+	/// This is not exported to bindings users since bindings don't support move semantics
+	pub fn with_max_channel_saturation_power_of_half(self, max_channel_saturation_power_of_half: u8) -> Self {
+		Self { max_channel_saturation_power_of_half, ..self }
+	}
+
+	/// Includes a minimum amount for each part of a multi-path payment.
+	///
+	/// This is not exported to bindings users since bindings don't support move semantics
+	pub fn with_min_mpp_part_msat(self, min_mpp_part_msat: u64) -> Self {
+		Self { min_mpp_part_msat, ..self }
+	}
+
+	/// Includes a preference for fewer, larger multi-path payment parts.
+	///
+	/// This is not exported to bindings users since bindings don't support move semantics
+	pub fn with_prefer_fewer_mpp_parts(self, prefer_fewer_mpp_parts: bool) -> Self {
+		Self { prefer_fewer_mpp_parts, ..self }
+	}
+
+	/// Includes whether a payment may be split across several of our channels to the same peer.
 	///
 	/// This is not exported to bindings users since bindings don't support move semantics
-	pub fn with_expiry_time(self, expiry_time: u64) -> Self {
-		Self { expiry_time: Some(expiry_time), ..self }
+	pub fn with_split_across_first_hop_channels(self, split_across_first_hop_channels: bool) -> Self {
+		Self { split_across_first_hop_channels, ..self }
 	}
 
-	/// Includes a limit for the total CLTV expiry delta which is considered during routing
+	/// Includes the weight given to channel penalties relative to fees, as a percentage.
 	///
 	/// This is not exported to bindings users since bindings don't support move semantics
-	pub fn with_max_total_cltv_expiry_delta(self, max_total_cltv_expiry_delta: u32) -> Self {
-		Self { max_total_cltv_expiry_delta, ..self }
+	pub fn with_channel_penalty_weight_percent(self, channel_penalty_weight_percent: u16) -> Self {
+		Self { channel_penalty_weight_percent, ..self }
 	}
 
-	/// Includes a limit for the maximum number of payment paths that may be used.
+	/// Includes a minimum estimated success probability, in millionths, for each payment path.
 	///
 	/// This is not exported to bindings users since bindings don't support move semantics
-	pub fn with_max_path_count(self, max_path_count: u8) -> Self {
-		Self { max_path_count, ..self }
+	pub fn with_min_path_success_probability_millionths(self, min_path_success_probability_millionths: u32) -> Self {
+		Self { min_path_success_probability_millionths, ..self }
 	}
 
-	/// Includes a limit for the maximum number of payment paths that may be used.
+	/// Includes the [`PaymentPrivacyLevel`] the payment is sent with.
 	///
 	/// This is not exported to bindings users since bindings don't support move semantics
-	pub fn with_max_channel_saturation_power_of_half(self, max_channel_saturation_power_of_half: u8) -> Self {
-		Self { max_channel_saturation_power_of_half, ..self }
+	pub fn with_privacy_level(self, privacy_level: PaymentPrivacyLevel) -> Self {
+		Self { privacy_level, ..self }
 	}
 }
 
@@ -968,6 +1799,35 @@ impl<'a> CandidateRouteHop<'a> {
 	}
 }
 
+/// Scales a channel penalty by [`PaymentParameters::channel_penalty_weight_percent`], keeping
+/// penalties of `u64::max_value()`, which mark a channel as unusable, as-is.
+#[inline]
+fn weighted_channel_penalty_msat(channel_penalty_msat: u64, channel_penalty_weight_percent: u16) -> u64 {
+	if channel_penalty_msat == u64::max_value() || channel_penalty_weight_percent == 100 {
+		return channel_penalty_msat;
+	}
+	let weighted_penalty_msat = channel_penalty_msat as u128 * channel_penalty_weight_percent as u128 / 100;
+	cmp::min(weighted_penalty_msat, u64::max_value() as u128) as u64
+}
+
+/// Estimates the probability of routing `usage.amount_msat` over a channel for scorers which don't
+/// estimate success probabilities themselves, assuming its liquidity is uniformly distributed
+/// across its capacity.
+fn capacity_success_probability(usage: &ChannelUsage) -> f64 {
+	let amount_msat = usage.amount_msat.saturating_add(usage.inflight_htlc_msat);
+	match usage.effective_capacity {
+		EffectiveCapacity::ExactLiquidity { liquidity_msat } =>
+			if amount_msat <= liquidity_msat { 1.0 } else { 0.0 },
+		EffectiveCapacity::Infinite => 1.0,
+		capacity => {
+			let capacity_msat = capacity.as_msat();
+			if amount_msat > capacity_msat { return 0.0; }
+			(capacity_msat - amount_msat) as f64 / capacity_msat.saturating_add(1) as f64 +
+				1.0 / capacity_msat.saturating_add(1) as f64
+		},
+	}
+}
+
 #[inline]
 fn max_htlc_from_capacity(capacity: EffectiveCapacity, max_channel_saturation_power_of_half: u8) -> u64 {
 	let saturation_shift: u32 = max_channel_saturation_power_of_half as u32;
@@ -1207,10 +2067,46 @@ impl fmt::Display for LoggedPayeePubkey {
 	}
 }
 
+/// A [`BuildHasher`] for the maps we index by node or channel during pathfinding, keyed by the
+/// random seed passed to [`get_route`].
+///
+/// Without `std`, our [`HashMap`]s are not keyed by OS randomness, which would allow others to
+/// announce nodes and channels colliding in them and slow our pathfinding to a crawl. Keying them
+/// by the seed instead also makes pathfinding independent of the platform's randomness, such that
+/// it behaves identically with and without `std`.
+#[derive(Clone, Copy)]
+struct PathfindingHasherBuilder {
+	k0: u64,
+	k1: u64,
+}
+
+impl PathfindingHasherBuilder {
+	fn new(random_seed_bytes: &[u8; 32]) -> Self {
+		// Derive the keys from the seed rather than using it directly, as it is also used for path
+		// randomization.
+		let mut prng = ChaCha20::new(random_seed_bytes, b"pathfinding\0");
+		let mut keys = [0u8; 16];
+		prng.process_in_place(&mut keys);
+		let mut k0 = [0u8; 8];
+		let mut k1 = [0u8; 8];
+		k0.copy_from_slice(&keys[..8]);
+		k1.copy_from_slice(&keys[8..]);
+		Self { k0: u64::from_le_bytes(k0), k1: u64::from_le_bytes(k1) }
+	}
+}
+
+#[allow(deprecated)]
+impl BuildHasher for PathfindingHasherBuilder {
+	type Hasher = SipHasher;
+	fn build_hasher(&self) -> SipHasher {
+		SipHasher::new_with_keys(self.k0, self.k1)
+	}
+}
+
 #[inline]
 fn sort_first_hop_channels(
-	channels: &mut Vec<&ChannelDetails>, used_channel_liquidities: &HashMap<(u64, bool), u64>,
-	recommended_value_msat: u64, our_node_pubkey: &PublicKey
+	channels: &mut Vec<&ChannelDetails>, used_channel_liquidities: &HashMap<(u64, bool), u64, PathfindingHasherBuilder>,
+	recommended_value_msat: u64, prefer_fewer_mpp_parts: bool, our_node_pubkey: &PublicKey
 ) {
 	// Sort the first_hops channels to the same node(s) in priority order of which channel we'd
 	// most like to use.
@@ -1222,7 +2118,8 @@ fn sort_first_hop_channels(
 	// Second, because simply always sorting in descending order would always use our largest
 	// available outbound capacity, needlessly fragmenting our available channel capacities,
 	// sort channels above `recommended_value_msat` in ascending order, preferring channels
-	// which have enough, but not too much, capacity for the payment.
+	// which have enough, but not too much, capacity for the payment. If the payer prefers fewer
+	// MPP parts, we skip this and always sort in descending order.
 	//
 	// Available outbound balances factor in liquidity already reserved for previously found paths.
 	channels.sort_unstable_by(|chan_a, chan_b| {
@@ -1232,7 +2129,9 @@ fn sort_first_hop_channels(
 		let chan_b_outbound_limit_msat = chan_b.next_outbound_htlc_limit_msat
 			.saturating_sub(*used_channel_liquidities.get(&(chan_b.get_outbound_payment_scid().unwrap(),
 			our_node_pubkey < &chan_b.counterparty.node_id)).unwrap_or(&0));
-		if chan_b_outbound_limit_msat < recommended_value_msat || chan_a_outbound_limit_msat < recommended_value_msat {
+		if prefer_fewer_mpp_parts || chan_b_outbound_limit_msat < recommended_value_msat ||
+			chan_a_outbound_limit_msat < recommended_value_msat
+		{
 			// Sort in descending order
 			chan_b_outbound_limit_msat.cmp(&chan_a_outbound_limit_msat)
 		} else {
@@ -1288,14 +2187,14 @@ where L::Target: Logger, GL::Target: Logger {
 pub(crate) fn get_route<L: Deref, S: Score>(
 	our_node_pubkey: &PublicKey, payment_params: &PaymentParameters, network_graph: &ReadOnlyNetworkGraph,
 	first_hops: Option<&[&ChannelDetails]>, final_value_msat: u64, logger: L, scorer: &S, score_params: &S::ScoreParams,
-	_random_seed_bytes: &[u8; 32]
+	random_seed_bytes: &[u8; 32]
 ) -> Result<Route, LightningError>
 where L::Target: Logger {
 	// If we're routing to a blinded recipient, we won't have their node id. Therefore, keep the
 	// unblinded payee id as an option. We also need a non-optional "payee id" for path construction,
 	// so use a dummy id for this in the blinded case.
 	let payee_node_id_opt = payment_params.payee.node_id().map(|pk| NodeId::from_pubkey(&pk));
-	const DUMMY_BLINDED_PAYEE_ID: [u8; 33] = [42u8; 33];
+	const DUMMY_BLINDED_PAYEE_ID: [u8; 33] = [2; 33];
 	let maybe_dummy_payee_pk = payment_params.payee.node_id().unwrap_or_else(|| PublicKey::from_slice(&DUMMY_BLINDED_PAYEE_ID).unwrap());
 	let maybe_dummy_payee_node_id = NodeId::from_pubkey(&maybe_dummy_payee_pk);
 	let our_node_id = NodeId::from_pubkey(&our_node_pubkey);
@@ -1322,9 +2221,38 @@ where L::Target: Logger {
 				}
 			}
 		},
-		_ => return Err(LightningError{err: "Routing to blinded paths isn't supported yet".to_owned(), action: ErrorAction::IgnoreError}),
-
+		Payee::Blinded { route_hints, .. } => {
+			if route_hints.iter().all(|(_, path)| &path.introduction_node_id == our_node_pubkey) {
+				return Err(LightningError{err: "Cannot generate a route to blinded paths if we are the introduction node to all of them".to_owned(), action: ErrorAction::IgnoreError});
+			}
+			for (_, blinded_path) in route_hints.iter() {
+				if blinded_path.blinded_hops.len() == 0 {
+					return Err(LightningError{err: "0-hop blinded path provided".to_owned(), action: ErrorAction::IgnoreError});
+				}
+			}
+		},
 	}
+	// Blinded paths are routed to as single-hop route hints from their introduction node to the
+	// dummy payee over a fake channel charging the path's aggregate fees and CLTV delta. Once paths
+	// are selected, the last hop over such a fake channel is replaced with the blinded path itself.
+	let blinded_hint_scid = |idx: usize| u64::max_value() - idx as u64;
+	let blinded_route_hints: Vec<RouteHint> = match &payment_params.payee {
+		Payee::Blinded { route_hints, .. } => route_hints.iter().enumerate()
+			.filter(|(_, (_, path))| &path.introduction_node_id != our_node_pubkey)
+			.map(|(idx, (payinfo, path))| RouteHint(vec![RouteHintHop {
+				src_node_id: path.introduction_node_id,
+				short_channel_id: blinded_hint_scid(idx),
+				fees: RoutingFees {
+					base_msat: payinfo.fee_base_msat,
+					proportional_millionths: payinfo.fee_proportional_millionths,
+				},
+				cltv_expiry_delta: payinfo.cltv_expiry_delta,
+				htlc_minimum_msat: Some(payinfo.htlc_minimum_msat),
+				htlc_maximum_msat: Some(payinfo.htlc_maximum_msat),
+			}]))
+			.collect(),
+		Payee::Clear { .. } => Vec::new(),
+	};
 	let final_cltv_expiry_delta = payment_params.payee.final_cltv_expiry_delta().unwrap_or(0);
 	if payment_params.max_total_cltv_expiry_delta <= final_cltv_expiry_delta {
 		return Err(LightningError{err: "Can't find a route where the maximum total CLTV expiry delta is below the final CLTV expiry.".to_owned(), action: ErrorAction::IgnoreError});
@@ -1409,12 +2337,16 @@ where L::Target: Logger {
 		LoggedPayeePubkey(payment_params.payee.node_id()), if allow_mpp { "with" } else { "without" },
 		first_hops.map(|hops| hops.len()).unwrap_or(0), if first_hops.is_some() { "" } else { "not " });
 
+	// All maps indexed by node or channel below are keyed by our seed, see
+	// `PathfindingHasherBuilder`.
+	let hasher_builder = PathfindingHasherBuilder::new(random_seed_bytes);
+
 	// Step (1).
 	// Prepare the data we'll use for payee-to-payer search by
 	// inserting first hops suggested by the caller as targets.
 	// Our search will then attempt to reach them while traversing from the payee node.
-	let mut first_hop_targets: HashMap<_, Vec<&ChannelDetails>> =
-		HashMap::with_capacity(if first_hops.is_some() { first_hops.as_ref().unwrap().len() } else { 0 });
+	let mut first_hop_targets: HashMap<_, Vec<&ChannelDetails>, PathfindingHasherBuilder> =
+		HashMap::with_capacity_and_hasher(if first_hops.is_some() { first_hops.as_ref().unwrap().len() } else { 0 }, hasher_builder);
 	if let Some(hops) = first_hops {
 		for chan in hops {
 			if chan.get_outbound_payment_scid().is_none() {
@@ -1440,7 +2372,8 @@ where L::Target: Logger {
 
 	// Map from node_id to information about the best current path to that node, including feerate
 	// information.
-	let mut dist: HashMap<NodeId, PathBuildingHop> = HashMap::with_capacity(network_nodes.len());
+	let mut dist: HashMap<NodeId, PathBuildingHop, PathfindingHasherBuilder> =
+		HashMap::with_capacity_and_hasher(network_nodes.len(), hasher_builder);
 
 	// During routing, if we ignore a path due to an htlc_minimum_msat limit, we set this,
 	// indicating that we may wish to try again with a higher value, potentially paying to meet an
@@ -1463,24 +2396,44 @@ where L::Target: Logger {
 	// Thus to avoid this effect, we require from our collected links to provide
 	// at least a minimal contribution to the recommended value yet-to-be-fulfilled.
 	// This requirement is currently set to be 1/max_path_count of the payment
-	// value to ensure we only ever return routes that do not violate this limit.
+	// value to ensure we only ever return routes that do not violate this limit, or the
+	// payer-provided `min_mpp_part_msat`, whichever is larger.
+	let split_for_privacy = allow_mpp && payment_params.privacy_level != PaymentPrivacyLevel::Standard;
+
+	// If the payer wants to hide the payment amount, no single path may contribute more than an
+	// equal share of at least `MIN_PRIVATE_PAYMENT_PARTS` parts.
+	let maximal_value_contribution_msat: u64 = if split_for_privacy {
+		let part_count = cmp::min(MIN_PRIVATE_PAYMENT_PARTS, payment_params.max_path_count) as u64;
+		(final_value_msat + (part_count - 1)) / part_count
+	} else {
+		final_value_msat
+	};
+
 	let minimal_value_contribution_msat: u64 = if allow_mpp {
-		(final_value_msat + (payment_params.max_path_count as u64 - 1)) / payment_params.max_path_count as u64
+		let max_path_count_contribution_msat =
+			(final_value_msat + (payment_params.max_path_count as u64 - 1)) / payment_params.max_path_count as u64;
+		cmp::min(cmp::max(max_path_count_contribution_msat, payment_params.min_mpp_part_msat), maximal_value_contribution_msat)
 	} else {
 		final_value_msat
 	};
 
 	// When we start collecting routes we enforce the max_channel_saturation_power_of_half
-	// requirement strictly. After we've collected enough (or if we fail to find new routes) we
+	// requirement strictly, unless the payer prefers fewer, larger MPP parts. After we've collected enough (or if we fail to find new routes) we
 	// drop the requirement by setting this to 0.
-	let mut channel_saturation_pow_half = payment_params.max_channel_saturation_power_of_half;
+	let mut channel_saturation_pow_half = if payment_params.prefer_fewer_mpp_parts { 0 } else {
+		payment_params.max_channel_saturation_power_of_half
+	};
 
 	// Keep track of how much liquidity has been used in selected channels. Used to determine
 	// if the channel can be used by additional MPP paths or to inform path finding decisions. It is
 	// aware of direction *only* to ensure that the correct htlc_maximum_msat value is used. Hence,
 	// liquidity used in one direction will not offset any used in the opposite direction.
-	let mut used_channel_liquidities: HashMap<(u64, bool), u64> =
-		HashMap::with_capacity(network_nodes.len());
+	let mut used_channel_liquidities: HashMap<(u64, bool), u64, PathfindingHasherBuilder> =
+		HashMap::with_capacity_and_hasher(network_nodes.len(), hasher_builder);
+
+	// The intermediate nodes of the paths found so far if the payment is split for privacy, which
+	// further paths may not route through.
+	let mut privacy_used_nodes: HashSet<NodeId, PathfindingHasherBuilder> = HashSet::with_hasher(hasher_builder);
 
 	// Keeping track of how much value we already collected across other paths. Helps to decide
 	// when we want to stop looking for new paths.
@@ -1488,7 +2441,13 @@ where L::Target: Logger {
 
 	for (_, channels) in first_hop_targets.iter_mut() {
 		sort_first_hop_channels(channels, &used_channel_liquidities, recommended_value_msat,
-			our_node_pubkey);
+			payment_params.prefer_fewer_mpp_parts, our_node_pubkey);
+		if !payment_params.split_across_first_hop_channels {
+			// Only the channel with the most available outbound capacity is kept for each peer.
+			channels.sort_unstable_by(|chan_a, chan_b|
+				chan_b.next_outbound_htlc_limit_msat.cmp(&chan_a.next_outbound_htlc_limit_msat));
+			channels.truncate(1);
+		}
 	}
 
 	log_trace!(logger, "Building path from {} to payer {} for value {} msat.",
@@ -1567,13 +2526,16 @@ where L::Target: Logger {
 
 					let payment_failed_on_this_channel =
 						payment_params.previously_failed_channels.contains(&short_channel_id);
+					// Any path through a node carries value from the node onwards, so excluding
+					// its outgoing hops keeps other parts' paths away from the node entirely.
+					let node_used_by_other_part = privacy_used_nodes.contains(&$src_node_id);
 
 					// If HTLC minimum is larger than the amount we're going to transfer, we shouldn't
 					// bother considering this channel. If retrying with recommended_value_msat may
 					// allow us to hit the HTLC minimum limit, set htlc_minimum_limit so that we go
 					// around again with a higher amount.
 					if !contributes_sufficient_value || exceeds_max_path_length ||
-						exceeds_cltv_delta_limit || payment_failed_on_this_channel {
+						exceeds_cltv_delta_limit || payment_failed_on_this_channel || node_used_by_other_part {
 						// Path isn't useful, ignore it and move on.
 					} else if may_overpay_to_meet_path_minimum_msat {
 						hit_minimum_limit = true;
@@ -1635,9 +2597,9 @@ where L::Target: Logger {
 								inflight_htlc_msat: used_liquidity_msat,
 								effective_capacity,
 							};
-							let channel_penalty_msat = scorer.channel_penalty_msat(
+							let channel_penalty_msat = weighted_channel_penalty_msat(scorer.channel_penalty_msat(
 								short_channel_id, &$src_node_id, &$dest_node_id, channel_usage, score_params
-							);
+							), payment_params.channel_penalty_weight_percent);
 							let path_penalty_msat = $next_hops_path_penalty_msat
 								.saturating_add(channel_penalty_msat);
 							let new_graph_node = RouteGraphNode {
@@ -1831,7 +2793,7 @@ where L::Target: Logger {
 		// it matters only if the fees are exactly the same.
 		let route_hints = match &payment_params.payee {
 			Payee::Clear { route_hints, .. } => route_hints,
-			_ => return Err(LightningError{err: "Routing to blinded paths isn't supported yet".to_owned(), action: ErrorAction::IgnoreError}),
+			Payee::Blinded { .. } => &blinded_route_hints,
 		};
 		for route in route_hints.iter().filter(|route| !route.0.is_empty()) {
 			let first_hop_in_route = &(route.0)[0];
@@ -1887,9 +2849,9 @@ where L::Target: Logger {
 						inflight_htlc_msat: used_liquidity_msat,
 						effective_capacity: candidate.effective_capacity(),
 					};
-					let channel_penalty_msat = scorer.channel_penalty_msat(
+					let channel_penalty_msat = weighted_channel_penalty_msat(scorer.channel_penalty_msat(
 						hop.short_channel_id, &source, &target, channel_usage, score_params
-					);
+					), payment_params.channel_penalty_weight_percent);
 					aggregate_next_hops_path_penalty_msat = aggregate_next_hops_path_penalty_msat
 						.saturating_add(channel_penalty_msat);
 
@@ -1902,7 +2864,7 @@ where L::Target: Logger {
 					// Searching for a direct channel between last checked hop and first_hop_targets
 					if let Some(first_channels) = first_hop_targets.get_mut(&NodeId::from_pubkey(&prev_hop_id)) {
 						sort_first_hop_channels(first_channels, &used_channel_liquidities,
-							recommended_value_msat, our_node_pubkey);
+							recommended_value_msat, payment_params.prefer_fewer_mpp_parts, our_node_pubkey);
 						for details in first_channels {
 							let first_hop_candidate = CandidateRouteHop::FirstHop { details };
 							add_entry!(first_hop_candidate, our_node_id, NodeId::from_pubkey(&prev_hop_id),
@@ -1943,7 +2905,7 @@ where L::Target: Logger {
 						// path.
 						if let Some(first_channels) = first_hop_targets.get_mut(&NodeId::from_pubkey(&hop.src_node_id)) {
 							sort_first_hop_channels(first_channels, &used_channel_liquidities,
-								recommended_value_msat, our_node_pubkey);
+								recommended_value_msat, payment_params.prefer_fewer_mpp_parts, our_node_pubkey);
 							for details in first_channels {
 								let first_hop_candidate = CandidateRouteHop::FirstHop { details };
 								add_entry!(first_hop_candidate, our_node_id,
@@ -1966,6 +2928,7 @@ where L::Target: Logger {
 		// At this point, targets are filled with the data from first and
 		// last hops communicated by the caller, and the payment receiver.
 		let mut found_new_path = false;
+		let mut rejected_unreliable_path = false;
 
 		// Step (3).
 		// If this loop terminates due the exhaustion of targets, two situations are possible:
@@ -2043,9 +3006,46 @@ where L::Target: Logger {
 				// recompute the fees again, so that if that's the case, we match the currently
 				// underpaid htlc_minimum_msat with fees.
 				debug_assert_eq!(payment_path.get_value_msat(), value_contribution_msat);
-				value_contribution_msat = cmp::min(value_contribution_msat, final_value_msat);
+				value_contribution_msat = cmp::min(value_contribution_msat, maximal_value_contribution_msat);
 				payment_path.update_value_and_recompute_fees(value_contribution_msat);
 
+				if payment_params.min_path_success_probability_millionths != 0 {
+					// Discard paths which we estimate are too unlikely to succeed, disabling their
+					// least reliable channel so that we look for a different path next time.
+					let mut path_success_probability = 1.0;
+					let mut least_reliable_hop: Option<(u64, f64)> = None;
+					let prev_hop_iter = core::iter::once(&our_node_id)
+						.chain(payment_path.hops.iter().map(|(hop, _)| &hop.node_id));
+					for (prev_hop, (hop, _)) in prev_hop_iter.zip(payment_path.hops.iter()) {
+						let short_channel_id = hop.candidate.short_channel_id();
+						let channel_usage = ChannelUsage {
+							amount_msat: value_contribution_msat + hop.next_hops_fee_msat,
+							inflight_htlc_msat: used_channel_liquidities
+								.get(&(short_channel_id, *prev_hop < hop.node_id)).copied().unwrap_or(0),
+							effective_capacity: hop.candidate.effective_capacity(),
+						};
+						let success_probability = scorer.channel_success_probability(
+							short_channel_id, prev_hop, &hop.node_id, channel_usage, score_params
+						).unwrap_or_else(|| capacity_success_probability(&channel_usage));
+						path_success_probability *= success_probability;
+						if least_reliable_hop.map_or(true, |(_, lowest_probability)| success_probability < lowest_probability) {
+							least_reliable_hop = Some((short_channel_id, success_probability));
+						}
+					}
+					let min_success_probability = payment_params.min_path_success_probability_millionths as f64 / 1_000_000.0;
+					if path_success_probability < min_success_probability {
+						if let Some((victim_scid, _)) = least_reliable_hop {
+							log_trace!(logger, "Disabling channel {} as the path through it has an estimated success probability of {}.",
+								victim_scid, path_success_probability);
+							let exhausted = u64::max_value();
+							*used_channel_liquidities.entry((victim_scid, false)).or_default() = exhausted;
+							*used_channel_liquidities.entry((victim_scid, true)).or_default() = exhausted;
+							rejected_unreliable_path = true;
+						}
+						break 'path_construction;
+					}
+				}
+
 				// Since a path allows to transfer as much value as
 				// the smallest channel it has ("bottleneck"), we should recompute
 				// the fees so sender HTLC don't overpay fees when traversing
@@ -2073,6 +3073,14 @@ where L::Target: Logger {
 					}
 					debug_assert!(*used_liquidity_msat <= hop_max_msat);
 				}
+				if split_for_privacy {
+					// Keep the paths of the parts apart so that no intermediate node sees more than
+					// one of them. Paths without intermediate nodes may still carry several parts.
+					for (hop, _) in payment_path.hops[..payment_path.hops.len() - 1].iter() {
+						privacy_used_nodes.insert(hop.node_id);
+					}
+					prevented_redundant_path_selection = true;
+				}
 				if !prevented_redundant_path_selection {
 					// If we weren't capped by hitting a liquidity limit on a channel in the path,
 					// we'll probably end up picking the same path again on the next iteration.
@@ -2111,6 +3119,11 @@ where L::Target: Logger {
 			}
 		}
 
+		if rejected_unreliable_path {
+			// We disabled a channel of the rejected path, so the next search may find another one.
+			continue 'paths_collection;
+		}
+
 		if !allow_mpp {
 			if !found_new_path && channel_saturation_pow_half != 0 {
 				channel_saturation_pow_half = 0;
@@ -2216,7 +3229,9 @@ where L::Target: Logger {
 		}
 		key
 	});
+	// Parts split off for privacy are kept separate even if they share a path.
 	for idx in 0..(selected_route.len() - 1) {
+		if split_for_privacy { break; }
 		if idx + 1 >= selected_route.len() { break; }
 		if iter_equal(selected_route[idx    ].hops.iter().map(|h| (h.0.candidate.short_channel_id(), h.0.node_id)),
 		              selected_route[idx + 1].hops.iter().map(|h| (h.0.candidate.short_channel_id(), h.0.node_id))) {
@@ -2260,7 +3275,21 @@ where L::Target: Logger {
 	for results_vec in selected_paths {
 		let mut hops = Vec::with_capacity(results_vec.len());
 		for res in results_vec { hops.push(res?); }
-		paths.push(Path { hops, blinded_tail: None });
+		let mut blinded_tail = None;
+		if let Payee::Blinded { route_hints, .. } = &payment_params.payee {
+			// We're never the introduction node, so there is always a hop to it before the hop
+			// over its fake channel to the dummy payee.
+			let final_hop = hops.pop().expect("Paths to blinded payees are at least two hops");
+			let hint_idx = (u64::max_value() - final_hop.short_channel_id) as usize;
+			let (_, blinded_path) = &route_hints[hint_idx];
+			blinded_tail = Some(BlindedTail {
+				hops: blinded_path.blinded_hops.clone(),
+				blinding_point: blinded_path.blinding_point,
+				excess_final_cltv_expiry_delta: final_hop.cltv_expiry_delta,
+				final_value_msat: final_hop.fee_msat,
+			});
+		}
+		paths.push(Path { hops, blinded_tail });
 	}
 	let route = Route {
 		paths,
@@ -2372,15 +3401,15 @@ where L::Target: Logger, GL::Target: Logger {
 	let graph_lock = network_graph.read_only();
 	let mut route = build_route_from_hops_internal(
 		our_node_pubkey, hops, &route_params.payment_params, &graph_lock,
-		route_params.final_value_msat, logger, random_seed_bytes)?;
+		route_params.final_value_msat, None, logger, random_seed_bytes)?;
 	add_random_cltv_offset(&mut route, &route_params.payment_params, &graph_lock, random_seed_bytes);
 	Ok(route)
 }
 
 fn build_route_from_hops_internal<L: Deref>(
 	our_node_pubkey: &PublicKey, hops: &[PublicKey], payment_params: &PaymentParameters,
-	network_graph: &ReadOnlyNetworkGraph, final_value_msat: u64, logger: L,
-	random_seed_bytes: &[u8; 32]
+	network_graph: &ReadOnlyNetworkGraph, final_value_msat: u64,
+	first_hops: Option<&[&ChannelDetails]>, logger: L, random_seed_bytes: &[u8; 32]
 ) -> Result<Route, LightningError> where L::Target: Logger {
 
 	struct HopScorer {
@@ -2435,7 +3464,7 @@ fn build_route_from_hops_internal<L: Deref>(
 
 	let scorer = HopScorer { our_node_id, hop_ids };
 
-	get_route(our_node_pubkey, payment_params, network_graph, None, final_value_msat,
+	get_route(our_node_pubkey, payment_params, network_graph, first_hops, final_value_msat,
 		logger, &scorer, &(), random_seed_bytes)
 }
 
@@ -2444,20 +3473,22 @@ mod tests {
 	use crate::blinded_path::{BlindedHop, BlindedPath};
 	use crate::routing::gossip::{NetworkGraph, P2PGossipSync, NodeId, EffectiveCapacity};
 	use crate::routing::utxo::UtxoResult;
-	use crate::routing::router::{get_route, build_route_from_hops_internal, add_random_cltv_offset, default_node_features,
-		BlindedTail, InFlightHtlcs, Path, PaymentParameters, Route, RouteHint, RouteHintHop, RouteHop, RoutingFees,
-		DEFAULT_MAX_TOTAL_CLTV_EXPIRY_DELTA, MAX_PATH_LENGTH_ESTIMATE};
+	use crate::routing::router::{get_route, build_route_from_hops_internal, add_random_cltv_offset, capacity_success_probability, default_node_features,
+		BlindedTail, PathfindingHasherBuilder, DefaultRouter, DelegatedRouter, InFlightHtlcs, Path, PaymentParameters, Route, RouteCacheConfig, RouteFeeLimits, RouteFuture,
+		PaymentPrivacyLevel, RouteHint, RouteHintHop, RouteHop, RouteParameters, RouteProvider, RouteRequestResult, Router, RoutingFees,
+		DEFAULT_MAX_TOTAL_CLTV_EXPIRY_DELTA, MAX_PATH_LENGTH_ESTIMATE, MIN_PRIVATE_PAYMENT_PARTS};
 	use crate::routing::scoring::{ChannelUsage, FixedPenaltyScorer, Score, ProbabilisticScorer, ProbabilisticScoringFeeParameters, ProbabilisticScoringDecayParameters};
 	use crate::routing::test_utils::{add_channel, add_or_update_node, build_graph, build_line_graph, id_to_feature_flags, get_nodes, update_channel};
 	use crate::chain::transaction::OutPoint;
 	use crate::sign::EntropySource;
 	use crate::ln::features::{ChannelFeatures, InitFeatures, NodeFeatures};
 	use crate::ln::msgs::{ErrorAction, LightningError, UnsignedChannelUpdate, MAX_VALUE_MSAT};
-	use crate::ln::channelmanager;
+	use crate::ln::PaymentHash;
+	use crate::ln::channelmanager::{self, PaymentId};
 	use crate::util::config::UserConfig;
 	use crate::util::test_utils as ln_test_utils;
 	use crate::util::chacha20::ChaCha20;
-	use crate::util::ser::{Readable, Writeable};
+	use crate::util::ser::{Readable, ReadableArgs, Writeable};
 	#[cfg(c_bindings)]
 	use crate::util::ser::Writer;
 
@@ -2475,7 +3506,7 @@ mod tests {
 
 	use crate::io::Cursor;
 	use crate::prelude::*;
-	use crate::sync::Arc;
+	use crate::sync::{Arc, Mutex};
 
 	use core::convert::TryInto;
 
@@ -2490,6 +3521,7 @@ mod tests {
 				forwarding_info: None,
 				outbound_htlc_minimum_msat: None,
 				outbound_htlc_maximum_msat: None,
+				latest_channel_update: None,
 			},
 			funding_txo: Some(OutPoint { txid: bitcoin::Txid::from_slice(&[0; 32]).unwrap(), index: 0 }),
 			channel_type: None,
@@ -5381,6 +6413,131 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn configurable_mpp_splitting() {
+		// Test that the MPP knobs in `PaymentParameters` restrict how a payment is split across our
+		// own channels.
+		let secp_ctx = Secp256k1::new();
+		let (_, our_id, _, nodes) = get_nodes(&secp_ctx);
+		let logger = Arc::new(ln_test_utils::TestLogger::new());
+		let network_graph = NetworkGraph::new(Network::Testnet, Arc::clone(&logger));
+		let scorer = ln_test_utils::TestScorer::new();
+		let config = UserConfig::default();
+		let payment_params = PaymentParameters::from_node_id(nodes[0], 42).with_bolt11_features(channelmanager::provided_invoice_features(&config)).unwrap();
+		let keys_manager = ln_test_utils::TestKeysInterface::new(&[0u8; 32], Network::Testnet);
+		let random_seed_bytes = keys_manager.get_secure_random_bytes();
+
+		{
+			// Without splitting across channels to the same peer, two 50k channels cannot carry a
+			// 100k payment.
+			let first_hops = [
+				&get_channel_details(Some(3), nodes[0], channelmanager::provided_init_features(&config), 50_000),
+				&get_channel_details(Some(2), nodes[0], channelmanager::provided_init_features(&config), 50_000),
+			];
+			let route = get_route(&our_id, &payment_params, &network_graph.read_only(), Some(&first_hops),
+				100_000, Arc::clone(&logger), &scorer, &(), &random_seed_bytes).unwrap();
+			assert_eq!(route.paths.len(), 2);
+
+			let no_split_params = payment_params.clone().with_split_across_first_hop_channels(false);
+			assert!(get_route(&our_id, &no_split_params, &network_graph.read_only(), Some(&first_hops),
+				100_000, Arc::clone(&logger), &scorer, &(), &random_seed_bytes).is_err());
+		}
+		{
+			// A minimum part size excludes channels which can only carry a smaller part.
+			let first_hops = [
+				&get_channel_details(Some(3), nodes[0], channelmanager::provided_init_features(&config), 60_000),
+				&get_channel_details(Some(2), nodes[0], channelmanager::provided_init_features(&config), 40_000),
+			];
+			let route = get_route(&our_id, &payment_params, &network_graph.read_only(), Some(&first_hops),
+				100_000, Arc::clone(&logger), &scorer, &(), &random_seed_bytes).unwrap();
+			assert_eq!(route.paths.len(), 2);
+
+			let min_part_params = payment_params.clone().with_min_mpp_part_msat(50_000);
+			assert!(get_route(&our_id, &min_part_params, &network_graph.read_only(), Some(&first_hops),
+				100_000, Arc::clone(&logger), &scorer, &(), &random_seed_bytes).is_err());
+		}
+		{
+			// By default we pick the smallest channel above 3x the payment value, but when preferring
+			// fewer parts we always pick the largest channel.
+			let first_hops = [
+				&get_channel_details(Some(2), nodes[0], channelmanager::provided_init_features(&config), 50_000),
+				&get_channel_details(Some(6), nodes[0], channelmanager::provided_init_features(&config), 300_000),
+				&get_channel_details(Some(4), nodes[0], channelmanager::provided_init_features(&config), 1_000_000),
+			];
+			let route = get_route(&our_id, &payment_params, &network_graph.read_only(), Some(&first_hops),
+				100_000, Arc::clone(&logger), &scorer, &(), &random_seed_bytes).unwrap();
+			assert_eq!(route.paths.len(), 1);
+			assert_eq!(route.paths[0].hops[0].short_channel_id, 6);
+
+			let fewer_parts_params = payment_params.clone().with_prefer_fewer_mpp_parts(true);
+			let route = get_route(&our_id, &fewer_parts_params, &network_graph.read_only(), Some(&first_hops),
+				100_000, Arc::clone(&logger), &scorer, &(), &random_seed_bytes).unwrap();
+			assert_eq!(route.paths.len(), 1);
+			assert_eq!(route.paths[0].hops[0].short_channel_id, 4);
+
+			// The new parameters survive a serialization round-trip.
+			let params = fewer_parts_params.with_min_mpp_part_msat(1_000).with_split_across_first_hop_channels(false);
+			let read_params: PaymentParameters = ReadableArgs::read(&mut &params.encode()[..], 42).unwrap();
+			assert_eq!(read_params, params);
+		}
+	}
+
+	#[test]
+	fn splits_payments_for_privacy() {
+		// Test that payments with a non-standard privacy level are split into at least
+		// `MIN_PRIVATE_PAYMENT_PARTS` parts, even if a single path could carry the full amount.
+		let secp_ctx = Secp256k1::new();
+		let (_, our_id, _, nodes) = get_nodes(&secp_ctx);
+		let logger = Arc::new(ln_test_utils::TestLogger::new());
+		let network_graph = NetworkGraph::new(Network::Testnet, Arc::clone(&logger));
+		let scorer = ln_test_utils::TestScorer::new();
+		let config = UserConfig::default();
+		let payment_params = PaymentParameters::from_node_id(nodes[0], 42).with_bolt11_features(channelmanager::provided_invoice_features(&config)).unwrap();
+		let keys_manager = ln_test_utils::TestKeysInterface::new(&[0u8; 32], Network::Testnet);
+		let random_seed_bytes = keys_manager.get_secure_random_bytes();
+		let first_hops = [
+			&get_channel_details(Some(2), nodes[0], channelmanager::provided_init_features(&config), 1_000_000),
+		];
+
+		let route = get_route(&our_id, &payment_params, &network_graph.read_only(), Some(&first_hops),
+			100_000, Arc::clone(&logger), &scorer, &(), &random_seed_bytes).unwrap();
+		assert_eq!(route.paths.len(), 1);
+
+		let private_params = payment_params.clone().with_privacy_level(PaymentPrivacyLevel::DecoyParts);
+		let route = get_route(&our_id, &private_params, &network_graph.read_only(), Some(&first_hops),
+			100_000, Arc::clone(&logger), &scorer, &(), &random_seed_bytes).unwrap();
+		assert_eq!(route.paths.len(), MIN_PRIVATE_PAYMENT_PARTS as usize);
+		assert_eq!(route.get_total_amount(), 100_000);
+		for path in route.paths.iter() {
+			assert_eq!(path.hops.len(), 1);
+			assert!(path.final_value_msat() <= 33_334);
+		}
+
+		// The number of parts remains limited by `max_path_count`.
+		let limited_params = private_params.clone().with_max_path_count(2);
+		let route = get_route(&our_id, &limited_params, &network_graph.read_only(), Some(&first_hops),
+			100_000, Arc::clone(&logger), &scorer, &(), &random_seed_bytes).unwrap();
+		assert_eq!(route.paths.len(), 2);
+
+		let read_params: PaymentParameters = ReadableArgs::read(&mut &private_params.encode()[..], 42).unwrap();
+		assert_eq!(read_params, private_params);
+
+		// On two-hop routes the parts may not share their intermediate node, even though they do
+		// not share any interior channel either way.
+		let (secp_ctx, network_graph, _, _, logger) = build_graph();
+		let (_, our_id, _, nodes) = get_nodes(&secp_ctx);
+		let private_params = PaymentParameters::from_node_id(nodes[2], 42)
+			.with_bolt11_features(channelmanager::provided_invoice_features(&config)).unwrap()
+			.with_max_path_count(2)
+			.with_privacy_level(PaymentPrivacyLevel::DecoyParts);
+		let route = get_route(&our_id, &private_params, &network_graph.read_only(), None,
+			100_000, Arc::clone(&logger), &scorer, &(), &random_seed_bytes).unwrap();
+		assert_eq!(route.paths.len(), 2);
+		assert_eq!(route.paths[0].hops.len(), 2);
+		assert_eq!(route.paths[1].hops.len(), 2);
+		assert_ne!(route.paths[0].hops[0].pubkey, route.paths[1].hops[0].pubkey);
+	}
+
 	#[test]
 	fn prefers_shorter_route_with_higher_fees() {
 		let (secp_ctx, network_graph, _, _, logger) = build_graph();
@@ -5456,6 +6613,31 @@ mod tests {
 		fn probe_successful(&mut self, _path: &Path) {}
 	}
 
+	struct UnreliableChannelScorer {
+		short_channel_id: u64,
+		penalty_msat: u64,
+	}
+
+	#[cfg(c_bindings)]
+	impl Writeable for UnreliableChannelScorer {
+		fn write<W: Writer>(&self, _w: &mut W) -> Result<(), crate::io::Error> { unimplemented!() }
+	}
+
+	impl Score for UnreliableChannelScorer {
+		type ScoreParams = ();
+		fn channel_penalty_msat(&self, short_channel_id: u64, _: &NodeId, _: &NodeId, _: ChannelUsage, _score_params:&Self::ScoreParams) -> u64 {
+			if short_channel_id == self.short_channel_id { self.penalty_msat } else { 0 }
+		}
+		fn channel_success_probability(&self, short_channel_id: u64, _: &NodeId, _: &NodeId, _: ChannelUsage, _score_params:&Self::ScoreParams) -> Option<f64> {
+			if short_channel_id == self.short_channel_id { Some(0.5) } else { None }
+		}
+
+		fn payment_path_failed(&mut self, _path: &Path, _short_channel_id: u64) {}
+		fn payment_path_successful(&mut self, _path: &Path) {}
+		fn probe_failed(&mut self, _path: &Path, _short_channel_id: u64) {}
+		fn probe_successful(&mut self, _path: &Path) {}
+	}
+
 	#[test]
 	fn avoids_routing_through_bad_channels_and_nodes() {
 		let (secp_ctx, network, _, _, logger) = build_graph();
@@ -5502,6 +6684,70 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn trades_off_fees_against_reliability() {
+		let (secp_ctx, network, _, _, logger) = build_graph();
+		let (_, our_id, _, nodes) = get_nodes(&secp_ctx);
+		let payment_params = PaymentParameters::from_node_id(nodes[6], 42).with_route_hints(last_hops(&nodes)).unwrap();
+		let network_graph = network.read_only();
+		let keys_manager = ln_test_utils::TestKeysInterface::new(&[0u8; 32], Network::Testnet);
+		let random_seed_bytes = keys_manager.get_secure_random_bytes();
+		let scorer = UnreliableChannelScorer { short_channel_id: 6, penalty_msat: 150 };
+
+		// By default, the penalty for channel 6 is lower than the additional fees of avoiding it.
+		let route = get_route(
+			&our_id, &payment_params, &network_graph, None, 100,
+			Arc::clone(&logger), &scorer, &(), &random_seed_bytes
+		).unwrap();
+		let path = route.paths[0].hops.iter().map(|hop| hop.short_channel_id).collect::<Vec<_>>();
+		assert_eq!(route.get_total_fees(), 100);
+		assert_eq!(path, vec![2, 4, 6, 11, 8]);
+
+		// Weighting penalties more heavily prefers the more expensive but more reliable path.
+		let reliable_params = payment_params.clone().with_channel_penalty_weight_percent(200);
+		let route = get_route(
+			&our_id, &reliable_params, &network_graph, None, 100,
+			Arc::clone(&logger), &scorer, &(), &random_seed_bytes
+		).unwrap();
+		let path = route.paths[0].hops.iter().map(|hop| hop.short_channel_id).collect::<Vec<_>>();
+		assert_eq!(route.get_total_fees(), 300);
+		assert_eq!(path, vec![2, 4, 7, 10]);
+
+		// Ignoring penalties altogether prefers the cheapest path, even if heavily penalized.
+		let scorer = UnreliableChannelScorer { short_channel_id: 6, penalty_msat: 10_000 };
+		let cheap_params = payment_params.clone().with_channel_penalty_weight_percent(0);
+		let route = get_route(
+			&our_id, &cheap_params, &network_graph, None, 100,
+			Arc::clone(&logger), &scorer, &(), &random_seed_bytes
+		).unwrap();
+		let path = route.paths[0].hops.iter().map(|hop| hop.short_channel_id).collect::<Vec<_>>();
+		assert_eq!(path, vec![2, 4, 6, 11, 8]);
+
+		// A minimum success probability above that of channel 6 excludes it outright.
+		let min_probability_params = cheap_params.with_min_path_success_probability_millionths(600_000);
+		let route = get_route(
+			&our_id, &min_probability_params, &network_graph, None, 100,
+			Arc::clone(&logger), &scorer, &(), &random_seed_bytes
+		).unwrap();
+		let path = route.paths[0].hops.iter().map(|hop| hop.short_channel_id).collect::<Vec<_>>();
+		assert_eq!(route.get_total_fees(), 300);
+		assert_eq!(path, vec![2, 4, 7, 10]);
+	}
+
+	#[test]
+	fn estimates_success_probability_from_capacity() {
+		// Scorers which don't estimate success probabilities still have paths checked against
+		// the minimum success probability, by estimating it from channel capacities.
+		let usage = |amount_msat, effective_capacity| ChannelUsage { amount_msat, inflight_htlc_msat: 0, effective_capacity };
+		assert_eq!(capacity_success_probability(&usage(1_000, EffectiveCapacity::Infinite)), 1.0);
+		let liquidity = EffectiveCapacity::ExactLiquidity { liquidity_msat: 1_000 };
+		assert_eq!(capacity_success_probability(&usage(1_000, liquidity)), 1.0);
+		assert_eq!(capacity_success_probability(&usage(1_001, liquidity)), 0.0);
+		let total = EffectiveCapacity::Total { capacity_msat: 1_000_000, htlc_maximum_msat: 1_000_000 };
+		assert!((capacity_success_probability(&usage(500_000, total)) - 0.5).abs() < 0.001);
+		assert_eq!(capacity_success_probability(&usage(1_000_001, total)), 0.0);
+	}
+
 	#[test]
 	fn total_fees_single_path() {
 		let route = Route {
@@ -5772,7 +7018,7 @@ mod tests {
 		let payment_params = PaymentParameters::from_node_id(nodes[3], 0);
 		let hops = [nodes[1], nodes[2], nodes[4], nodes[3]];
 		let route = build_route_from_hops_internal(&our_id, &hops, &payment_params,
-			 &network_graph, 100, Arc::clone(&logger), &random_seed_bytes).unwrap();
+			 &network_graph, 100, None, Arc::clone(&logger), &random_seed_bytes).unwrap();
 		let route_hop_pubkeys = route.paths[0].hops.iter().map(|hop| hop.pubkey).collect::<Vec<_>>();
 		assert_eq!(hops.len(), route.paths[0].hops.len());
 		for (idx, hop_pubkey) in hops.iter().enumerate() {
@@ -5780,6 +7026,94 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn reuses_cached_routes() {
+		let (secp_ctx, network_graph, gossip_sync, _, logger) = build_graph();
+		let (_, our_id, privkeys, nodes) = get_nodes(&secp_ctx);
+		let scorer = Mutex::new(FixedPenaltyScorer::with_penalty(0));
+		let keys_manager = ln_test_utils::TestKeysInterface::new(&[0u8; 32], Network::Testnet);
+		let random_seed_bytes = keys_manager.get_secure_random_bytes();
+		let router = DefaultRouter::new(Arc::clone(&network_graph), Arc::clone(&logger),
+			random_seed_bytes, &scorer, ()).with_route_cache(RouteCacheConfig::default());
+
+		let route_params = |final_value_msat| RouteParameters {
+			payment_params: PaymentParameters::from_node_id(nodes[2], 42), final_value_msat,
+		};
+		let route = router.find_route(&our_id, &route_params(100), None, &InFlightHtlcs::new()).unwrap();
+		assert_eq!(router.route_cache_hits(), 0);
+		let hops = route.paths[0].hops.iter().map(|hop| hop.short_channel_id).collect::<Vec<_>>();
+		assert_eq!(hops, vec![2, 4]);
+
+		// A payment in the same amount bucket reuses the route, with fees for the new amount.
+		let route = router.find_route(&our_id, &route_params(120), None, &InFlightHtlcs::new()).unwrap();
+		assert_eq!(router.route_cache_hits(), 1);
+		assert_eq!(route.paths[0].hops.iter().map(|hop| hop.short_channel_id).collect::<Vec<_>>(), hops);
+		assert_eq!(route.get_total_amount(), 120);
+
+		// A payment in another bucket doesn't.
+		router.find_route(&our_id, &route_params(1000), None, &InFlightHtlcs::new()).unwrap();
+		assert_eq!(router.route_cache_hits(), 1);
+
+		// Once a channel on the route is updated, the route is found from scratch and cached again.
+		update_channel(&gossip_sync, &secp_ctx, &privkeys[1], UnsignedChannelUpdate {
+			chain_hash: genesis_block(Network::Testnet).header.block_hash(),
+			short_channel_id: 4,
+			timestamp: 2,
+			flags: 0,
+			cltv_expiry_delta: (4 << 4) | 1,
+			htlc_minimum_msat: 0,
+			htlc_maximum_msat: MAX_VALUE_MSAT,
+			fee_base_msat: 0,
+			fee_proportional_millionths: 1000000,
+			excess_data: Vec::new()
+		});
+		router.find_route(&our_id, &route_params(100), None, &InFlightHtlcs::new()).unwrap();
+		assert_eq!(router.route_cache_hits(), 1);
+		router.find_route(&our_id, &route_params(100), None, &InFlightHtlcs::new()).unwrap();
+		assert_eq!(router.route_cache_hits(), 2);
+
+		// Retries after a failure over a channel on the route don't use the cache.
+		let mut retry_params = route_params(100);
+		retry_params.payment_params.previously_failed_channels.push(4);
+		let _ = router.find_route(&our_id, &retry_params, None, &InFlightHtlcs::new());
+		assert_eq!(router.route_cache_hits(), 2);
+	}
+
+	#[test]
+	fn finds_batch_routes() {
+		let (secp_ctx, network_graph, _, _, logger) = build_graph();
+		let (_, our_id, _, nodes) = get_nodes(&secp_ctx);
+		let scorer = Mutex::new(FixedPenaltyScorer::with_penalty(0));
+		let keys_manager = ln_test_utils::TestKeysInterface::new(&[0u8; 32], Network::Testnet);
+		let random_seed_bytes = keys_manager.get_secure_random_bytes();
+		let router = DefaultRouter::new(Arc::clone(&network_graph), Arc::clone(&logger),
+			random_seed_bytes, &scorer, ());
+
+		let route_params = |payee, final_value_msat| RouteParameters {
+			payment_params: PaymentParameters::from_node_id(payee, 42), final_value_msat,
+		};
+		let routes = router.find_routes(&our_id, &[
+			route_params(nodes[2], 100), route_params(nodes[3], 100), route_params(nodes[2], 250),
+		], None, &InFlightHtlcs::new());
+		assert_eq!(routes.len(), 3);
+		let routes = routes.into_iter().map(|route| route.unwrap()).collect::<Vec<_>>();
+		let hops = |route: &Route| route.paths[0].hops.iter().map(|hop| hop.short_channel_id).collect::<Vec<_>>();
+		assert_eq!(hops(&routes[0]), vec![2, 4]);
+		assert_eq!(routes[0].get_total_amount(), 100);
+		assert_eq!(routes[1].paths[0].hops.last().unwrap().pubkey, nodes[3]);
+		assert_eq!(routes[1].get_total_amount(), 100);
+		// The second payment to the same payee reuses the hops of the first.
+		assert_eq!(hops(&routes[2]), hops(&routes[0]));
+		assert_eq!(routes[2].get_total_amount(), 250);
+
+		// Payments which cannot be routed fail without affecting the others.
+		let routes = router.find_routes(&our_id, &[
+			route_params(nodes[2], MAX_VALUE_MSAT + 1), route_params(nodes[2], 100),
+		], None, &InFlightHtlcs::new());
+		assert!(routes[0].is_err());
+		assert_eq!(hops(routes[1].as_ref().unwrap()), vec![2, 4]);
+	}
+
 	#[test]
 	fn avoids_saturating_channels() {
 		let (secp_ctx, network_graph, gossip_sync, _, logger) = build_graph();
@@ -5825,6 +7159,22 @@ mod tests {
 			(route.paths[1].hops[1].short_channel_id == 4 && route.paths[0].hops[1].short_channel_id == 13));
 	}
 
+	#[test]
+	fn pathfinding_hasher_is_keyed_by_seed() {
+		use core::hash::{BuildHasher, Hasher};
+
+		let secp_ctx = Secp256k1::new();
+		let (_, our_id, _, _) = get_nodes(&secp_ctx);
+		let node_id = NodeId::from_pubkey(&our_id);
+		let hash_with_seed = |seed: [u8; 32]| {
+			let mut hasher = PathfindingHasherBuilder::new(&seed).build_hasher();
+			core::hash::Hash::hash(&node_id, &mut hasher);
+			hasher.finish()
+		};
+		assert_eq!(hash_with_seed([42; 32]), hash_with_seed([42; 32]));
+		assert_ne!(hash_with_seed([42; 32]), hash_with_seed([43; 32]));
+	}
+
 	#[cfg(not(feature = "no-std"))]
 	pub(super) fn random_init_seed() -> u64 {
 		// Because the default HashMap in std pulls OS randomness, we can use it as a (bad) RNG.
@@ -6199,6 +7549,117 @@ mod tests {
 		assert_eq!(route.paths[0].blinded_tail.as_ref().unwrap().excess_final_cltv_expiry_delta, 40);
 		assert_eq!(route.paths[0].hops.last().unwrap().cltv_expiry_delta, 40);
 	}
+
+	#[test]
+	fn simple_blinded_route_hints() {
+		// Make sure we route to a blinded path's introduction node and hand back the blinded path as
+		// the route's blinded tail.
+		let (secp_ctx, network_graph, _, _, logger) = build_graph();
+		let (_, our_id, _, nodes) = get_nodes(&secp_ctx);
+		let scorer = ln_test_utils::TestScorer::new();
+		let keys_manager = ln_test_utils::TestKeysInterface::new(&[0u8; 32], Network::Testnet);
+		let random_seed_bytes = keys_manager.get_secure_random_bytes();
+
+		let blinded_path = BlindedPath {
+			introduction_node_id: nodes[2],
+			blinding_point: ln_test_utils::pubkey(42),
+			blinded_hops: vec![
+				BlindedHop { blinded_node_id: ln_test_utils::pubkey(43), encrypted_payload: Vec::new() },
+				BlindedHop { blinded_node_id: ln_test_utils::pubkey(44), encrypted_payload: Vec::new() }
+			],
+		};
+		let blinded_payinfo = crate::offers::invoice::BlindedPayInfo {
+			fee_base_msat: 100,
+			fee_proportional_millionths: 0,
+			htlc_minimum_msat: 1,
+			htlc_maximum_msat: 100_000,
+			cltv_expiry_delta: 15,
+			features: crate::ln::features::BlindedHopFeatures::empty(),
+		};
+		let payment_params = PaymentParameters::blinded(vec![(blinded_payinfo.clone(), blinded_path.clone())]);
+
+		let route = get_route(&our_id, &payment_params, &network_graph.read_only(), None, 1000, Arc::clone(&logger), &scorer, &(), &random_seed_bytes).unwrap();
+		assert_eq!(route.paths.len(), 1);
+		assert_eq!(route.paths[0].hops.len(), 2);
+		assert_eq!(route.paths[0].hops[0].pubkey, nodes[1]);
+		assert_eq!(route.paths[0].hops[0].short_channel_id, 2);
+		assert_eq!(route.paths[0].hops[1].pubkey, nodes[2]);
+		assert_eq!(route.paths[0].hops[1].short_channel_id, 4);
+		assert_eq!(route.paths[0].hops[1].fee_msat, 100);
+		assert_eq!(route.paths[0].hops[1].cltv_expiry_delta, 15);
+		let blinded_tail = route.paths[0].blinded_tail.as_ref().unwrap();
+		assert_eq!(blinded_tail.hops, blinded_path.blinded_hops);
+		assert_eq!(blinded_tail.blinding_point, blinded_path.blinding_point);
+		assert_eq!(blinded_tail.excess_final_cltv_expiry_delta, 0);
+		assert_eq!(blinded_tail.final_value_msat, 1000);
+		assert_eq!(route.get_total_amount(), 1000);
+
+		// We can't route to a blinded path we're the introduction node of.
+		let mut our_blinded_path = blinded_path;
+		our_blinded_path.introduction_node_id = our_id;
+		let payment_params = PaymentParameters::blinded(vec![(blinded_payinfo, our_blinded_path)]);
+		if let Err(LightningError{err, action: ErrorAction::IgnoreError}) = get_route(&our_id, &payment_params, &network_graph.read_only(), None, 1000, Arc::clone(&logger), &scorer, &(), &random_seed_bytes) {
+			assert_eq!(err, "Cannot generate a route to blinded paths if we are the introduction node to all of them");
+		} else { panic!(); }
+	}
+
+	#[test]
+	fn delegated_router_validates_routes() {
+		struct StaticRouteProvider(Mutex<RouteRequestResult>);
+		impl RouteProvider for StaticRouteProvider {
+			fn request_routes(&self, _: &PublicKey, _: &RouteParameters, _: &[&channelmanager::ChannelDetails], _: &InFlightHtlcs) -> RouteRequestResult {
+				self.0.lock().unwrap().clone()
+			}
+		}
+
+		let secp_ctx = Secp256k1::new();
+		let (_, our_id, _, nodes) = get_nodes(&secp_ctx);
+		let logger = ln_test_utils::TestLogger::new();
+		let our_chans = vec![get_channel_details(Some(42), nodes[0], InitFeatures::from_le_bytes(vec![0b11]), 100_000)];
+		let first_hops = our_chans.iter().collect::<Vec<_>>();
+		let route_params = RouteParameters {
+			payment_params: PaymentParameters::from_node_id(nodes[1], 42),
+			final_value_msat: 10_000,
+		};
+
+		let hop = |pubkey: PublicKey, short_channel_id: u64, fee_msat: u64| RouteHop {
+			pubkey, node_features: NodeFeatures::empty(), short_channel_id,
+			channel_features: ChannelFeatures::empty(), fee_msat, cltv_expiry_delta: 42,
+		};
+		let route = |hops: Vec<RouteHop>| Route { paths: vec![Path { hops, blinded_tail: None }], payment_params: None };
+		let cheap_route = route(vec![hop(nodes[0], 42, 100), hop(nodes[1], 43, 10_000)]);
+		let expensive_route = route(vec![hop(nodes[0], 42, 1_000), hop(nodes[1], 44, 10_000)]);
+		let unknown_first_hop_route = route(vec![hop(nodes[2], 45, 10), hop(nodes[1], 46, 10_000)]);
+		let wrong_payee_route = route(vec![hop(nodes[0], 42, 10), hop(nodes[2], 47, 10_000)]);
+		let underpaying_route = route(vec![hop(nodes[0], 42, 10), hop(nodes[1], 43, 9_000)]);
+		let over_balance_route = route(vec![hop(nodes[0], 42, 100_000), hop(nodes[1], 43, 10_000)]);
+
+		// The cheapest route which passes validation is picked.
+		let provider = StaticRouteProvider(Mutex::new(RouteRequestResult::Sync(Ok(vec![
+			expensive_route.clone(), unknown_first_hop_route, wrong_payee_route, underpaying_route,
+			over_balance_route, cheap_route.clone(),
+		]))));
+		let router = DelegatedRouter::new(&provider, &logger, RouteFeeLimits::default());
+		let found_route = router.find_route(&our_id, &route_params, Some(&first_hops), &InFlightHtlcs::new()).unwrap();
+		assert_eq!(found_route.paths, cheap_route.paths);
+		assert_eq!(found_route.payment_params, Some(route_params.payment_params.clone()));
+
+		// Routes exceeding our fee limits are rejected.
+		let strict_router = DelegatedRouter::new(&provider, &logger,
+			RouteFeeLimits { max_fee_base_msat: 50, max_fee_proportional_millionths: 0 });
+		assert!(strict_router.find_route(&our_id, &route_params, Some(&first_hops), &InFlightHtlcs::new()).is_err());
+
+		// Asynchronous requests fail until resolved, after which the resolved routes are used.
+		let future = RouteFuture::new();
+		*provider.0.lock().unwrap() = RouteRequestResult::Async(future.clone());
+		let (payment_hash, payment_id) = (PaymentHash([42; 32]), PaymentId([42; 32]));
+		assert!(router.find_route_with_id(&our_id, &route_params, Some(&first_hops), &InFlightHtlcs::new(), payment_hash, payment_id).is_err());
+		assert!(router.find_route_with_id(&our_id, &route_params, Some(&first_hops), &InFlightHtlcs::new(), payment_hash, payment_id).is_err());
+		future.resolve(Ok(vec![expensive_route.clone()]));
+		let found_route = router.find_route_with_id(&our_id, &route_params, Some(&first_hops), &InFlightHtlcs::new(), payment_hash, payment_id).unwrap();
+		assert_eq!(found_route.paths, expensive_route.paths);
+		assert!(router.find_route(&our_id, &route_params, Some(&first_hops), &InFlightHtlcs::new()).is_err());
+	}
 }
 
 #[cfg(all(any(test, ldk_bench), not(feature = "no-std")))]
@@ -6272,6 +7733,7 @@ pub(crate) mod bench_utils {
 				forwarding_info: None,
 				outbound_htlc_minimum_msat: None,
 				outbound_htlc_maximum_msat: None,
+				latest_channel_update: None,
 			},
 			funding_txo: Some(OutPoint {
 				txid: bitcoin::Txid::from_slice(&[0; 32]).unwrap(), index: 0
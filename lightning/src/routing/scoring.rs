@@ -58,9 +58,11 @@
 
 use crate::ln::msgs::DecodeError;
 use crate::routing::gossip::{EffectiveCapacity, NetworkGraph, NodeId};
+use crate::routing::interchange::{self, InterchangeError, Record};
 use crate::routing::router::Path;
 use crate::util::ser::{Readable, ReadableArgs, Writeable, Writer};
 use crate::util::logger::Logger;
+use crate::util::scid_utils;
 use crate::util::time::Time;
 
 use crate::prelude::*;
@@ -104,6 +106,21 @@ pub trait Score $(: $supertrait)* {
 		&self, short_channel_id: u64, source: &NodeId, target: &NodeId, usage: ChannelUsage, score_params: &Self::ScoreParams
 	) -> u64;
 
+	/// Returns the estimated probability, between `0.0` and `1.0`, that `usage.amount_msat` can be
+	/// routed through the given channel in the direction from `source` to `target`.
+	///
+	/// Used by the router to enforce [`PaymentParameters::min_path_success_probability_millionths`].
+	/// Returns `None` by default, indicating the scorer does not estimate success probabilities,
+	/// in which case the router estimates the probability from the channel's capacity, assuming
+	/// its liquidity is uniformly distributed.
+	///
+	/// [`PaymentParameters::min_path_success_probability_millionths`]: crate::routing::router::PaymentParameters::min_path_success_probability_millionths
+	fn channel_success_probability(
+		&self, _short_channel_id: u64, _source: &NodeId, _target: &NodeId, _usage: ChannelUsage, _score_params: &Self::ScoreParams
+	) -> Option<f64> {
+		None
+	}
+
 	/// Handles updating channel penalties after failing to route through a channel.
 	fn payment_path_failed(&mut self, path: &Path, short_channel_id: u64);
 
@@ -115,6 +132,21 @@ pub trait Score $(: $supertrait)* {
 
 	/// Handles updating channel penalties after a probe over the given path succeeded.
 	fn probe_successful(&mut self, path: &Path);
+
+	/// Handles the hold times, in milliseconds, reported by the hops along a failed payment path,
+	/// starting with the first hop. See [`Event::PaymentPathFailed::hold_times`] for details.
+	///
+	/// May be used to penalize hops which are slow to fail HTLCs back. Does nothing by default.
+	///
+	/// [`Event::PaymentPathFailed::hold_times`]: crate::events::Event::PaymentPathFailed::hold_times
+	fn payment_path_hold_times(&mut self, _path: &Path, _hold_times: &[u32]) {}
+
+	/// Informs the scorer of the current best block height, e.g., to consider the age of channels
+	/// or to decay what it learned in terms of blocks rather than wall-clock time. Should be called
+	/// whenever a new block is connected and on startup.
+	///
+	/// Does nothing by default.
+	fn best_block_updated(&mut self, _height: u32) {}
 }
 
 impl<S: Score, T: DerefMut<Target=S> $(+ $supertrait)*> Score for T {
@@ -125,6 +157,12 @@ impl<S: Score, T: DerefMut<Target=S> $(+ $supertrait)*> Score for T {
 		self.deref().channel_penalty_msat(short_channel_id, source, target, usage, score_params)
 	}
 
+	fn channel_success_probability(
+		&self, short_channel_id: u64, source: &NodeId, target: &NodeId, usage: ChannelUsage, score_params: &Self::ScoreParams
+	) -> Option<f64> {
+		self.deref().channel_success_probability(short_channel_id, source, target, usage, score_params)
+	}
+
 	fn payment_path_failed(&mut self, path: &Path, short_channel_id: u64) {
 		self.deref_mut().payment_path_failed(path, short_channel_id)
 	}
@@ -140,6 +178,14 @@ impl<S: Score, T: DerefMut<Target=S> $(+ $supertrait)*> Score for T {
 	fn probe_successful(&mut self, path: &Path) {
 		self.deref_mut().probe_successful(path)
 	}
+
+	fn payment_path_hold_times(&mut self, path: &Path, hold_times: &[u32]) {
+		self.deref_mut().payment_path_hold_times(path, hold_times)
+	}
+
+	fn best_block_updated(&mut self, height: u32) {
+		self.deref_mut().best_block_updated(height)
+	}
 }
 } }
 
@@ -203,6 +249,9 @@ impl<'a, T: Score + 'a> Score for MultiThreadedScoreLock<'a, T> {
 	fn channel_penalty_msat(&self, scid: u64, source: &NodeId, target: &NodeId, usage: ChannelUsage, score_params: &Self::ScoreParams) -> u64 {
 		self.0.channel_penalty_msat(scid, source, target, usage, score_params)
 	}
+	fn channel_success_probability(&self, scid: u64, source: &NodeId, target: &NodeId, usage: ChannelUsage, score_params: &Self::ScoreParams) -> Option<f64> {
+		self.0.channel_success_probability(scid, source, target, usage, score_params)
+	}
 	fn payment_path_failed(&mut self, path: &Path, short_channel_id: u64) {
 		self.0.payment_path_failed(path, short_channel_id)
 	}
@@ -215,6 +264,12 @@ impl<'a, T: Score + 'a> Score for MultiThreadedScoreLock<'a, T> {
 	fn probe_successful(&mut self, path: &Path) {
 		self.0.probe_successful(path)
 	}
+	fn payment_path_hold_times(&mut self, path: &Path, hold_times: &[u32]) {
+		self.0.payment_path_hold_times(path, hold_times)
+	}
+	fn best_block_updated(&mut self, height: u32) {
+		self.0.best_block_updated(height)
+	}
 }
 #[cfg(c_bindings)]
 impl<'a, T: Score + 'a> Writeable for MultiThreadedScoreLock<'a, T> {
@@ -348,7 +403,10 @@ type ConfiguredTime = Eternity;
 /// terms of the entire path's success probability. This allows the router to directly compare
 /// penalties for different paths. See the documentation of those parameters for the exact formulas.
 ///
-/// The liquidity bounds are decayed by halving them every [`liquidity_offset_half_life`].
+/// The liquidity bounds are decayed by halving them every [`liquidity_offset_half_life`]. With the
+/// `no-std` feature, no time is considered to pass unless it is injected via
+/// [`ProbabilisticScorerUsingTime::time_passed`]. To decay them using a host-supplied clock
+/// instead, use a [`ProbabilisticScorerUsingTime`] with a [`ProvidedTime`].
 ///
 /// Further, we track the history of our upper and lower liquidity bounds for each channel,
 /// allowing us to assign a second penalty (using [`historical_liquidity_penalty_multiplier_msat`]
@@ -356,6 +414,11 @@ type ConfiguredTime = Eternity;
 /// formula, but using the history of a channel rather than our latest estimates for the liquidity
 /// bounds.
 ///
+/// Finally, we keep a log of how often payments failed at each channel. As the liquidity bounds
+/// decay, a channel which repeatedly fails us would otherwise soon be tried again, so a long-term
+/// [`failure_penalty_msat`] may be assigned based on this log, which is persisted along with the
+/// liquidity bounds and decays far more slowly, per [`failure_log_half_life`].
+///
 /// # Note
 ///
 /// Mixing the `no-std` feature between serialization and deserialization results in undefined
@@ -367,6 +430,9 @@ type ConfiguredTime = Eternity;
 /// [`liquidity_offset_half_life`]: ProbabilisticScoringDecayParameters::liquidity_offset_half_life
 /// [`historical_liquidity_penalty_multiplier_msat`]: ProbabilisticScoringFeeParameters::historical_liquidity_penalty_multiplier_msat
 /// [`historical_liquidity_penalty_amount_multiplier_msat`]: ProbabilisticScoringFeeParameters::historical_liquidity_penalty_amount_multiplier_msat
+/// [`ProvidedTime`]: crate::util::clock::ProvidedTime
+/// [`failure_penalty_msat`]: ProbabilisticScoringFeeParameters::failure_penalty_msat
+/// [`failure_log_half_life`]: ProbabilisticScoringDecayParameters::failure_log_half_life
 pub type ProbabilisticScorer<G, L> = ProbabilisticScorerUsingTime::<G, L, ConfiguredTime>;
 
 /// Probabilistic [`Score`] implementation.
@@ -379,6 +445,14 @@ where L::Target: Logger {
 	logger: L,
 	// TODO: Remove entries of closed channels.
 	channel_liquidities: HashMap<u64, ChannelLiquidity<T>>,
+	channel_failures: HashMap<u64, ChannelFailureLog<T>>,
+	// The best block height as last given to `best_block_updated`, and the number of times channels
+	// were disabled, learned from the network graph as of `disabled_channels_cursor`.
+	best_block_height: u32,
+	channel_disables: HashMap<u64, ChannelDisableLog>,
+	disabled_channels_cursor: u64,
+	// The total time given to `time_passed`, used to decay our data in whole half-lives.
+	time_passed: Duration,
 }
 
 /// Parameters for configuring [`ProbabilisticScorer`].
@@ -517,6 +591,82 @@ pub struct ProbabilisticScoringFeeParameters {
 	/// [`base_penalty_msat`]: Self::base_penalty_msat
 	/// [`anti_probing_penalty_msat`]: Self::anti_probing_penalty_msat
 	pub considered_impossible_penalty_msat: u64,
+
+	/// A penalty applied to a channel for each payment which failed at it, as recorded in the
+	/// scorer's failure log, scaled according to the [`failure_penalty_curve`].
+	///
+	/// Unlike the liquidity penalties, which treat failures as information about a channel's
+	/// current balance, this penalty reflects a channel's reliability over the long term. Recorded
+	/// failures are persisted with the scorer and decay per [`failure_log_half_life`].
+	///
+	/// Default value: 0 msat
+	///
+	/// [`failure_penalty_curve`]: Self::failure_penalty_curve
+	/// [`failure_log_half_life`]: ProbabilisticScoringDecayParameters::failure_log_half_life
+	pub failure_penalty_msat: u64,
+
+	/// How the [`failure_penalty_msat`] grows with the number of failures recorded for a channel.
+	///
+	/// Default value: [`FailurePenaltyCurve::Linear`]
+	///
+	/// [`failure_penalty_msat`]: Self::failure_penalty_msat
+	pub failure_penalty_curve: FailurePenaltyCurve,
+
+	/// The maximum penalty applied to a channel based on its recorded failures.
+	///
+	/// Default value: 1,000,000 msat
+	pub max_failure_penalty_msat: u64,
+
+	/// A penalty applied to channels younger than [`young_channel_age_blocks`], decreasing linearly
+	/// from the full penalty for channels funded in the current block to zero for channels of that
+	/// age.
+	///
+	/// Young channels fail payments disproportionately often, e.g., as their operators are still
+	/// setting up their liquidity. The age of channels is determined from the funding block height
+	/// encoded in their short channel id, relative to the height last given to
+	/// [`Score::best_block_updated`]. No penalty is applied before it was called.
+	///
+	/// Default value: 0 msat
+	///
+	/// [`young_channel_age_blocks`]: Self::young_channel_age_blocks
+	pub young_channel_penalty_msat: u64,
+
+	/// The age in blocks up to which channels are penalized per [`young_channel_penalty_msat`].
+	///
+	/// Default value: 1008 (roughly a week)
+	///
+	/// [`young_channel_penalty_msat`]: Self::young_channel_penalty_msat
+	pub young_channel_age_blocks: u32,
+
+	/// A penalty applied to a channel for each time we received a `channel_update` disabling it,
+	/// which happens whenever one of its peers goes offline, bounded by
+	/// [`max_disabled_channel_penalty_msat`].
+	///
+	/// Disables are learned from the network graph whenever [`Score::best_block_updated`] is
+	/// called, and the number of disables counted for a channel is halved every
+	/// [`disabled_channel_half_life_blocks`] blocks since it was last disabled.
+	///
+	/// Default value: 0 msat
+	///
+	/// [`max_disabled_channel_penalty_msat`]: Self::max_disabled_channel_penalty_msat
+	/// [`disabled_channel_half_life_blocks`]: ProbabilisticScoringDecayParameters::disabled_channel_half_life_blocks
+	pub disabled_channel_penalty_msat: u64,
+
+	/// The maximum penalty applied to a channel based on how often it was disabled.
+	///
+	/// Default value: 100,000 msat
+	pub max_disabled_channel_penalty_msat: u64,
+}
+
+/// How the penalty for the failures recorded for a channel grows with their number. See
+/// [`ProbabilisticScoringFeeParameters::failure_penalty_msat`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailurePenaltyCurve {
+	/// The penalty grows in proportion to the number of failures.
+	Linear,
+	/// The penalty grows with the square of the number of failures, barely penalizing channels
+	/// which failed us once or twice while quickly deprioritizing those which fail repeatedly.
+	Quadratic,
 }
 
 impl Default for ProbabilisticScoringFeeParameters {
@@ -531,6 +681,13 @@ impl Default for ProbabilisticScoringFeeParameters {
 			considered_impossible_penalty_msat: 1_0000_0000_000,
 			historical_liquidity_penalty_multiplier_msat: 10_000,
 			historical_liquidity_penalty_amount_multiplier_msat: 64,
+			failure_penalty_msat: 0,
+			failure_penalty_curve: FailurePenaltyCurve::Linear,
+			max_failure_penalty_msat: 1_000_000,
+			young_channel_penalty_msat: 0,
+			young_channel_age_blocks: 1008,
+			disabled_channel_penalty_msat: 0,
+			max_disabled_channel_penalty_msat: 100_000,
 		}
 	}
 }
@@ -584,15 +741,22 @@ impl ProbabilisticScoringFeeParameters {
 			manual_node_penalties: HashMap::new(),
 			anti_probing_penalty_msat: 0,
 			considered_impossible_penalty_msat: 0,
+			failure_penalty_msat: 0,
+			failure_penalty_curve: FailurePenaltyCurve::Linear,
+			max_failure_penalty_msat: 0,
+			young_channel_penalty_msat: 0,
+			young_channel_age_blocks: 0,
+			disabled_channel_penalty_msat: 0,
+			max_disabled_channel_penalty_msat: 0,
 		}
 	}
 }
 
 /// Parameters for configuring [`ProbabilisticScorer`].
 ///
-/// Used to configure decay parameters that are static throughout the lifetime of the scorer.
-/// these decay parameters affect the score of the channel penalty and are not changed on a
-/// per-route penalty cost call.
+/// Used to configure decay parameters that are held by the scorer, rather than passed on each
+/// per-route penalty cost call. They may be changed via
+/// [`ProbabilisticScorerUsingTime::set_decay_params`].
 #[derive(Copy, Clone)]
 pub struct ProbabilisticScoringDecayParameters {
 	/// If we aren't learning any new datapoints for a channel, the historical liquidity bounds
@@ -603,6 +767,9 @@ pub struct ProbabilisticScoringDecayParameters {
 	///
 	/// Note that after 16 or more half lives all historical data will be completely gone.
 	///
+	/// When built with the `no-std` feature, this only decays as described for
+	/// [`Self::liquidity_offset_half_life`].
+	///
 	/// Default value: 14 days
 	///
 	/// [`historical_estimated_channel_liquidity_probabilities`]: ProbabilisticScorerUsingTime::historical_estimated_channel_liquidity_probabilities
@@ -625,9 +792,35 @@ pub struct ProbabilisticScoringDecayParameters {
 	///
 	/// # Note
 	///
-	/// When built with the `no-std` feature, time will never elapse. Therefore, the channel
-	/// liquidity knowledge will never decay except when the bounds cross.
+	/// When built with the `no-std` feature, time will never elapse for a [`ProbabilisticScorer`].
+	/// Therefore, the channel liquidity knowledge will only decay when the bounds cross or as time
+	/// is injected via [`ProbabilisticScorerUsingTime::time_passed`]. Alternatively, use a
+	/// [`ProbabilisticScorerUsingTime`] with a [`ProvidedTime`] to decay it using a host-supplied
+	/// clock.
+	///
+	/// [`ProvidedTime`]: crate::util::clock::ProvidedTime
 	pub liquidity_offset_half_life: Duration,
+
+	/// Whenever this amount of time elapses since a payment last failed at a channel, the number
+	/// of failures recorded for it in the failure log is halved.
+	///
+	/// Default value: 30 days
+	///
+	/// # Note
+	///
+	/// When built with the `no-std` feature, time will never elapse for a [`ProbabilisticScorer`].
+	/// Therefore, recorded failures will only decay as described for
+	/// [`Self::liquidity_offset_half_life`].
+	pub failure_log_half_life: Duration,
+
+	/// Whenever this many blocks are connected since a channel was last disabled, the number of
+	/// times it was disabled is halved for
+	/// [`ProbabilisticScoringFeeParameters::disabled_channel_penalty_msat`].
+	///
+	/// As it is measured in blocks, this decays in `no-std` environments as well.
+	///
+	/// Default value: 2016 (roughly two weeks)
+	pub disabled_channel_half_life_blocks: u32,
 }
 
 impl Default for ProbabilisticScoringDecayParameters {
@@ -635,6 +828,8 @@ impl Default for ProbabilisticScoringDecayParameters {
 		Self {
 			liquidity_offset_half_life: Duration::from_secs(6 * 60 * 60),
 			historical_no_updates_half_life: Duration::from_secs(60 * 60 * 24 * 14),
+			failure_log_half_life: Duration::from_secs(60 * 60 * 24 * 30),
+			disabled_channel_half_life_blocks: 2016,
 		}
 	}
 }
@@ -645,6 +840,8 @@ impl ProbabilisticScoringDecayParameters {
 		Self {
 			liquidity_offset_half_life: Duration::from_secs(6 * 60 * 60),
 			historical_no_updates_half_life: Duration::from_secs(60 * 60 * 24 * 14),
+			failure_log_half_life: Duration::from_secs(60 * 60 * 24 * 30),
+			disabled_channel_half_life_blocks: 2016,
 		}
 	}
 }
@@ -790,6 +987,67 @@ impl HistoricalMinMaxBuckets<'_> {
 	}
 }
 
+/// The fixed-point value of a single failure in [`ChannelFailureLog::failure_weight`].
+const FAILURE_WEIGHT_ONE: u32 = 1024;
+
+/// The number of payments which failed at a channel, decaying over time.
+struct ChannelFailureLog<T: Time> {
+	/// The number of failures, as a fixed-point number where [`FAILURE_WEIGHT_ONE`] is a single
+	/// failure, as of `last_failed`.
+	failure_weight: u32,
+
+	/// Time when a payment last failed at the channel.
+	last_failed: T,
+}
+
+impl<T: Time> ChannelFailureLog<T> {
+	fn new() -> Self {
+		Self { failure_weight: 0, last_failed: T::now() }
+	}
+
+	/// Returns the failure weight, halved once for each `half_life` elapsed since the last failure.
+	fn decayed_failure_weight(&self, half_life: Duration) -> u32 {
+		let half_lives = self.last_failed.elapsed().as_secs().checked_div(half_life.as_secs())
+			.unwrap_or(u64::max_value());
+		if half_lives >= 32 { 0 } else { self.failure_weight >> half_lives }
+	}
+
+	fn record_failure(&mut self, half_life: Duration) {
+		self.failure_weight = self.decayed_failure_weight(half_life).saturating_add(FAILURE_WEIGHT_ONE);
+		self.last_failed = T::now();
+	}
+}
+
+/// The number of times a channel was disabled, decaying with the blocks connected since.
+struct ChannelDisableLog {
+	/// The number of disables, as a fixed-point number where [`FAILURE_WEIGHT_ONE`] is a single
+	/// disable, as of `last_disabled_height`.
+	disable_weight: u32,
+
+	/// The best block height when the channel was last disabled.
+	last_disabled_height: u32,
+}
+
+impl ChannelDisableLog {
+	/// Returns the disable weight, halved once for each `half_life_blocks` connected since the
+	/// channel was last disabled.
+	fn decayed_disable_weight(&self, height: u32, half_life_blocks: u32) -> u32 {
+		let half_lives = height.saturating_sub(self.last_disabled_height).checked_div(half_life_blocks)
+			.unwrap_or(u32::max_value());
+		if half_lives >= 32 { 0 } else { self.disable_weight >> half_lives }
+	}
+
+	fn record_disable(&mut self, height: u32, half_life_blocks: u32) {
+		self.disable_weight = self.decayed_disable_weight(height, half_life_blocks).saturating_add(FAILURE_WEIGHT_ONE);
+		self.last_disabled_height = cmp::max(height, self.last_disabled_height);
+	}
+}
+
+impl_writeable_tlv_based!(ChannelDisableLog, {
+	(0, disable_weight, required),
+	(2, last_disabled_height, required),
+});
+
 /// Accounting for channel liquidity balance uncertainty.
 ///
 /// Direction is defined in terms of [`NodeId`] partial ordering, where the source node is the
@@ -823,6 +1081,24 @@ struct DirectedChannelLiquidity<L: Deref<Target = u64>, BRT: Deref<Target = Hist
 	decay_params: ProbabilisticScoringDecayParameters,
 }
 
+/// The current liquidity estimate of a channel in one direction and the resulting penalty for a
+/// given amount, as returned by [`ProbabilisticScorerUsingTime::channel_penalty_details`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelPenaltyDetails {
+	/// The estimated lower bound of the liquidity available in the channel's direction, in msat.
+	pub min_liquidity_msat: u64,
+	/// The estimated upper bound of the liquidity available in the channel's direction, in msat.
+	pub max_liquidity_msat: u64,
+	/// The effective capacity of the channel in the given direction, in msat.
+	pub capacity_msat: u64,
+	/// The estimated probability of successfully sending the amount over the channel.
+	pub success_probability: f64,
+	/// The part of [`Self::penalty_msat`] due to failures recorded for the channel.
+	pub failure_penalty_msat: u64,
+	/// The total penalty the router would assign to sending the amount over the channel.
+	pub penalty_msat: u64,
+}
+
 impl<G: Deref<Target = NetworkGraph<L>>, L: Deref, T: Time> ProbabilisticScorerUsingTime<G, L, T> where L::Target: Logger {
 	/// Creates a new scorer using the given scoring parameters for sending payments from a node
 	/// through a network graph.
@@ -832,6 +1108,11 @@ impl<G: Deref<Target = NetworkGraph<L>>, L: Deref, T: Time> ProbabilisticScorerU
 			network_graph,
 			logger,
 			channel_liquidities: HashMap::new(),
+			channel_failures: HashMap::new(),
+			best_block_height: 0,
+			channel_disables: HashMap::new(),
+			disabled_channels_cursor: 0,
+			time_passed: Duration::from_secs(0),
 		}
 	}
 
@@ -841,6 +1122,160 @@ impl<G: Deref<Target = NetworkGraph<L>>, L: Deref, T: Time> ProbabilisticScorerU
 		self
 	}
 
+	/// Returns the number of payments which failed at the given channel, as recorded in the failure
+	/// log and decayed per [`ProbabilisticScoringDecayParameters::failure_log_half_life`], or `None`
+	/// if none did.
+	pub fn recorded_channel_failures(&self, short_channel_id: u64) -> Option<f64> {
+		self.channel_failures.get(&short_channel_id).map(|log| {
+			log.decayed_failure_weight(self.decay_params.failure_log_half_life) as f64 / FAILURE_WEIGHT_ONE as f64
+		})
+	}
+
+	/// Removes any failures recorded for the given channel from the failure log, e.g. once its
+	/// operator has fixed the issue which caused them.
+	pub fn clear_recorded_channel_failures(&mut self, short_channel_id: u64) {
+		self.channel_failures.remove(&short_channel_id);
+	}
+
+	/// Writes the liquidity estimates and failure logs of the scorer in the portable
+	/// [`interchange`] format.
+	///
+	/// Unlike the [`Writeable`] serialization of the scorer, the output can be read by other LDK
+	/// versions via [`Self::read_interchange`] as well as by external tooling.
+	pub fn write_interchange(&self) -> String {
+		let mut out = interchange::write_header(interchange::SCORER_KIND);
+
+		let mut liquidities: Vec<_> = self.channel_liquidities.iter().collect();
+		liquidities.sort_unstable_by_key(|(scid, _)| **scid);
+		for (scid, liquidity) in liquidities {
+			out += &format!("liquidity {} {} {} {} {} {}\n", scid,
+				liquidity.min_liquidity_offset_msat, liquidity.max_liquidity_offset_msat,
+				epoch_secs(&liquidity.last_updated),
+				write_buckets(&liquidity.min_liquidity_offset_history),
+				write_buckets(&liquidity.max_liquidity_offset_history));
+		}
+
+		let mut failures: Vec<_> = self.channel_failures.iter().collect();
+		failures.sort_unstable_by_key(|(scid, _)| **scid);
+		for (scid, log) in failures {
+			out += &format!("failures {} {} {}\n", scid, log.failure_weight, epoch_secs(&log.last_failed));
+		}
+		out
+	}
+
+	/// Reads liquidity estimates and failure logs, as written by [`Self::write_interchange`], into
+	/// the scorer, replacing any existing state for the channels in `data`.
+	///
+	/// If `data` is malformed, an error is returned and the scorer is not modified.
+	pub fn read_interchange(&mut self, data: &str) -> Result<(), InterchangeError> {
+		let mut liquidities: Vec<(u64, ChannelLiquidity<T>)> = Vec::new();
+		let mut failures: Vec<(u64, ChannelFailureLog<T>)> = Vec::new();
+		for record in interchange::read_records(data, interchange::SCORER_KIND)? {
+			match record.record_type() {
+				"liquidity" => {
+					liquidities.push((record.field(1)?, ChannelLiquidity {
+						min_liquidity_offset_msat: record.field(2)?,
+						max_liquidity_offset_msat: record.field(3)?,
+						last_updated: time_from_epoch_secs(record.field(4)?),
+						min_liquidity_offset_history: read_buckets(&record, 5)?,
+						max_liquidity_offset_history: read_buckets(&record, 6)?,
+					}));
+				},
+				"failures" => {
+					failures.push((record.field(1)?, ChannelFailureLog {
+						failure_weight: record.field(2)?,
+						last_failed: time_from_epoch_secs(record.field(3)?),
+					}));
+				},
+				_ => {},
+			}
+		}
+
+		self.channel_liquidities.extend(liquidities);
+		self.channel_failures.extend(failures);
+		Ok(())
+	}
+
+	/// Returns the penalty for the failures recorded for the given channel.
+	fn failure_penalty_msat(&self, short_channel_id: u64, score_params: &ProbabilisticScoringFeeParameters) -> u64 {
+		if score_params.failure_penalty_msat == 0 { return 0; }
+		let failure_weight = match self.channel_failures.get(&short_channel_id) {
+			Some(log) => log.decayed_failure_weight(self.decay_params.failure_log_half_life) as u128,
+			None => return 0,
+		};
+		let one = FAILURE_WEIGHT_ONE as u128;
+		let penalty_msat = match score_params.failure_penalty_curve {
+			FailurePenaltyCurve::Linear =>
+				score_params.failure_penalty_msat as u128 * failure_weight / one,
+			FailurePenaltyCurve::Quadratic =>
+				score_params.failure_penalty_msat as u128 * failure_weight * failure_weight / (one * one),
+		};
+		cmp::min(penalty_msat, score_params.max_failure_penalty_msat as u128) as u64
+	}
+
+	/// Returns the penalty for the given channel being young or having been disabled repeatedly.
+	fn channel_reliability_penalty_msat(&self, short_channel_id: u64, score_params: &ProbabilisticScoringFeeParameters) -> u64 {
+		let mut penalty_msat = 0;
+		if score_params.young_channel_penalty_msat != 0 && self.best_block_height != 0 {
+			let age_blocks = self.best_block_height.saturating_sub(scid_utils::block_from_scid(&short_channel_id));
+			if age_blocks < score_params.young_channel_age_blocks {
+				let remaining_blocks = (score_params.young_channel_age_blocks - age_blocks) as u128;
+				penalty_msat = (score_params.young_channel_penalty_msat as u128 * remaining_blocks
+					/ score_params.young_channel_age_blocks as u128) as u64;
+			}
+		}
+		if score_params.disabled_channel_penalty_msat != 0 {
+			if let Some(log) = self.channel_disables.get(&short_channel_id) {
+				let disable_weight = log.decayed_disable_weight(
+					self.best_block_height, self.decay_params.disabled_channel_half_life_blocks) as u128;
+				let disabled_penalty_msat = score_params.disabled_channel_penalty_msat as u128
+					* disable_weight / FAILURE_WEIGHT_ONE as u128;
+				penalty_msat = penalty_msat.saturating_add(cmp::min(
+					disabled_penalty_msat, score_params.max_disabled_channel_penalty_msat as u128) as u64);
+			}
+		}
+		penalty_msat
+	}
+
+	fn update_liquidity_for_failed_path(&mut self, path: &Path, short_channel_id: u64) {
+		let amount_msat = path.final_value_msat();
+		log_trace!(self.logger, "Scoring path through to SCID {} as having failed at {} msat", short_channel_id, amount_msat);
+		let network_graph = self.network_graph.read_only();
+		for (hop_idx, hop) in path.hops.iter().enumerate() {
+			let target = NodeId::from_pubkey(&hop.pubkey);
+			let channel_directed_from_source = network_graph.channels()
+				.get(&hop.short_channel_id)
+				.and_then(|channel| channel.as_directed_to(&target));
+
+			let at_failed_channel = hop.short_channel_id == short_channel_id;
+			if at_failed_channel && hop_idx == 0 {
+				log_warn!(self.logger, "Payment failed at the first hop - we do not attempt to learn channel info in such cases as we can directly observe local state.\n\tBecause we know the local state, we should generally not see failures here - this may be an indication that your channel peer on channel {} is broken and you may wish to close the channel.", hop.short_channel_id);
+			}
+
+			// Only score announced channels.
+			if let Some((channel, source)) = channel_directed_from_source {
+				let capacity_msat = channel.effective_capacity().as_msat();
+				if at_failed_channel {
+					self.channel_liquidities
+						.entry(hop.short_channel_id)
+						.or_insert_with(ChannelLiquidity::new)
+						.as_directed_mut(source, &target, 0, capacity_msat, self.decay_params)
+						.failed_at_channel(amount_msat, format_args!("SCID {}, towards {:?}", hop.short_channel_id, target), &self.logger);
+				} else {
+					self.channel_liquidities
+						.entry(hop.short_channel_id)
+						.or_insert_with(ChannelLiquidity::new)
+						.as_directed_mut(source, &target, 0, capacity_msat, self.decay_params)
+						.failed_downstream(amount_msat, format_args!("SCID {}, towards {:?}", hop.short_channel_id, target), &self.logger);
+				}
+			} else {
+				log_debug!(self.logger, "Not able to penalize channel with SCID {} as we do not have graph info for it (likely a route-hint last-hop).",
+					hop.short_channel_id);
+			}
+			if at_failed_channel { break; }
+		}
+	}
+
 	/// Dump the contents of this scorer into the configured logger.
 	///
 	/// Note that this writes roughly one line per channel for which we have a liquidity estimate,
@@ -950,6 +1385,91 @@ impl<G: Deref<Target = NetworkGraph<L>>, L: Deref, T: Time> ProbabilisticScorerU
 		}
 		None
 	}
+
+	/// Returns the parameters the liquidity bounds and failure log of this scorer are decayed with.
+	pub fn decay_params(&self) -> &ProbabilisticScoringDecayParameters {
+		&self.decay_params
+	}
+
+	/// Replaces the parameters the liquidity bounds and failure log of this scorer are decayed
+	/// with, keeping all data learned so far.
+	///
+	/// The new half-lives apply to all time elapsed since the data was last updated, not only to
+	/// the time to come.
+	pub fn set_decay_params(&mut self, decay_params: ProbabilisticScoringDecayParameters) {
+		self.decay_params = decay_params;
+	}
+
+	/// Informs the scorer that `elapsed` time has passed, decaying the liquidity bounds, their
+	/// history and the failure log of every channel by each half-life completed in the total time
+	/// given here so far.
+	///
+	/// This allows the host to inject the passage of time where the scorer's [`Time`] can't measure
+	/// it, i.e. for a [`ProbabilisticScorer`] built with the `no-std` feature, e.g. from a host
+	/// clock or the timestamps of connected blocks. It decays in addition to any time measured by
+	/// [`Time`], thus should not be called otherwise.
+	///
+	/// As all channels are decayed at once, each half-life is counted from the first call rather
+	/// than from when a channel was last updated.
+	pub fn time_passed(&mut self, elapsed: Duration) {
+		let previous = self.time_passed;
+		self.time_passed = previous.checked_add(elapsed).unwrap_or(previous);
+		let half_lives_completed = |half_life: Duration| -> u32 {
+			match (previous.as_secs().checked_div(half_life.as_secs()), self.time_passed.as_secs().checked_div(half_life.as_secs())) {
+				(Some(before), Some(after)) => cmp::min(after - before, u32::max_value() as u64) as u32,
+				// A zero half-life decays everything as soon as any time passes.
+				_ => if elapsed.as_secs() > 0 { u32::max_value() } else { 0 },
+			}
+		};
+		let liquidity_half_lives = half_lives_completed(self.decay_params.liquidity_offset_half_life);
+		let history_half_lives = half_lives_completed(self.decay_params.historical_no_updates_half_life);
+		let failure_half_lives = half_lives_completed(self.decay_params.failure_log_half_life);
+
+		if liquidity_half_lives != 0 || history_half_lives != 0 {
+			for liquidity in self.channel_liquidities.values_mut() {
+				liquidity.min_liquidity_offset_msat = liquidity.min_liquidity_offset_msat.checked_shr(liquidity_half_lives).unwrap_or(0);
+				liquidity.max_liquidity_offset_msat = liquidity.max_liquidity_offset_msat.checked_shr(liquidity_half_lives).unwrap_or(0);
+				liquidity.min_liquidity_offset_history.time_decay_data(history_half_lives);
+				liquidity.max_liquidity_offset_history.time_decay_data(history_half_lives);
+			}
+		}
+		if failure_half_lives != 0 {
+			self.channel_failures.retain(|_, log| {
+				log.failure_weight = log.failure_weight.checked_shr(failure_half_lives).unwrap_or(0);
+				log.failure_weight != 0
+			});
+		}
+	}
+
+	/// Returns the current liquidity estimate for the channel with `scid` towards the given
+	/// `target` node, as well as the penalty assigned to sending `amount_msat` over it with the
+	/// given `score_params`, e.g., to learn why the router picked a given path.
+	///
+	/// Returns `None` if the channel isn't part of the network graph.
+	pub fn channel_penalty_details(
+		&self, scid: u64, target: &NodeId, amount_msat: u64, score_params: &ProbabilisticScoringFeeParameters
+	) -> Option<ChannelPenaltyDetails> {
+		let graph = self.network_graph.read_only();
+		let chan = graph.channels().get(&scid)?;
+		let (directed_info, source) = chan.as_directed_to(target)?;
+		let effective_capacity = directed_info.effective_capacity();
+		let capacity_msat = effective_capacity.as_msat();
+
+		let default_liquidity = ChannelLiquidity::new();
+		let dir_liq = self.channel_liquidities.get(&scid).unwrap_or(&default_liquidity)
+			.as_directed(source, target, 0, capacity_msat, self.decay_params);
+
+		let usage = ChannelUsage { amount_msat, inflight_htlc_msat: 0, effective_capacity };
+		Some(ChannelPenaltyDetails {
+			min_liquidity_msat: dir_liq.min_liquidity_msat(),
+			max_liquidity_msat: dir_liq.max_liquidity_msat(),
+			capacity_msat,
+			success_probability: self.channel_success_probability(scid, source, target, usage, score_params)
+				.unwrap_or(0.0),
+			failure_penalty_msat: self.failure_penalty_msat(scid, score_params),
+			penalty_msat: self.channel_penalty_msat(scid, source, target, usage, score_params),
+		})
+	}
 }
 
 impl<T: Time> ChannelLiquidity<T> {
@@ -1111,6 +1631,23 @@ impl<L: Deref<Target = u64>, BRT: Deref<Target = HistoricalBucketRangeTracker>,
 		res
 	}
 
+	/// Returns the estimated probability that an HTLC of `amount_msat` can be routed through the
+	/// channel in this direction, based on our current liquidity bounds.
+	fn success_probability(&self, amount_msat: u64) -> f64 {
+		let max_liquidity_msat = self.max_liquidity_msat();
+		let min_liquidity_msat = core::cmp::min(self.min_liquidity_msat(), max_liquidity_msat);
+
+		if amount_msat <= min_liquidity_msat {
+			1.0
+		} else if amount_msat >= max_liquidity_msat {
+			0.0
+		} else {
+			let numerator = (max_liquidity_msat - amount_msat).saturating_add(1);
+			let denominator = (max_liquidity_msat - min_liquidity_msat).saturating_add(1);
+			numerator as f64 / denominator as f64
+		}
+	}
+
 	/// Computes the liquidity penalty from the penalty multipliers.
 	#[inline(always)]
 	fn combined_penalty_msat(amount_msat: u64, negative_log10_times_2048: u64,
@@ -1249,7 +1786,8 @@ impl<G: Deref<Target = NetworkGraph<L>>, L: Deref, T: Time> Score for Probabilis
 				if usage.amount_msat > amount_msat {
 					return u64::max_value();
 				} else {
-					return base_penalty_msat;
+					return base_penalty_msat
+						.saturating_add(self.failure_penalty_msat(short_channel_id, score_params));
 				}
 			},
 			EffectiveCapacity::Total { capacity_msat, htlc_maximum_msat } => {
@@ -1270,44 +1808,40 @@ impl<G: Deref<Target = NetworkGraph<L>>, L: Deref, T: Time> Score for Probabilis
 			.penalty_msat(amount_msat, score_params)
 			.saturating_add(anti_probing_penalty_msat)
 			.saturating_add(base_penalty_msat)
+			.saturating_add(self.failure_penalty_msat(short_channel_id, score_params))
+			.saturating_add(self.channel_reliability_penalty_msat(short_channel_id, score_params))
 	}
 
-	fn payment_path_failed(&mut self, path: &Path, short_channel_id: u64) {
-		let amount_msat = path.final_value_msat();
-		log_trace!(self.logger, "Scoring path through to SCID {} as having failed at {} msat", short_channel_id, amount_msat);
-		let network_graph = self.network_graph.read_only();
-		for (hop_idx, hop) in path.hops.iter().enumerate() {
-			let target = NodeId::from_pubkey(&hop.pubkey);
-			let channel_directed_from_source = network_graph.channels()
-				.get(&hop.short_channel_id)
-				.and_then(|channel| channel.as_directed_to(&target));
+	fn channel_success_probability(
+		&self, short_channel_id: u64, source: &NodeId, target: &NodeId, usage: ChannelUsage, _score_params: &ProbabilisticScoringFeeParameters
+	) -> Option<f64> {
+		match usage.effective_capacity {
+			EffectiveCapacity::ExactLiquidity { liquidity_msat: amount_msat } |
+				EffectiveCapacity::HintMaxHTLC { amount_msat } =>
+			{
+				return Some(if usage.amount_msat > amount_msat { 0.0 } else { 1.0 });
+			},
+			_ => {},
+		}
 
-			let at_failed_channel = hop.short_channel_id == short_channel_id;
-			if at_failed_channel && hop_idx == 0 {
-				log_warn!(self.logger, "Payment failed at the first hop - we do not attempt to learn channel info in such cases as we can directly observe local state.\n\tBecause we know the local state, we should generally not see failures here - this may be an indication that your channel peer on channel {} is broken and you may wish to close the channel.", hop.short_channel_id);
-			}
+		let capacity_msat = usage.effective_capacity.as_msat();
+		let success_probability = self.channel_liquidities
+			.get(&short_channel_id)
+			.unwrap_or(&ChannelLiquidity::new())
+			.as_directed(source, target, usage.inflight_htlc_msat, capacity_msat, self.decay_params)
+			.success_probability(usage.amount_msat);
+		Some(success_probability)
+	}
 
-			// Only score announced channels.
-			if let Some((channel, source)) = channel_directed_from_source {
-				let capacity_msat = channel.effective_capacity().as_msat();
-				if at_failed_channel {
-					self.channel_liquidities
-						.entry(hop.short_channel_id)
-						.or_insert_with(ChannelLiquidity::new)
-						.as_directed_mut(source, &target, 0, capacity_msat, self.decay_params)
-						.failed_at_channel(amount_msat, format_args!("SCID {}, towards {:?}", hop.short_channel_id, target), &self.logger);
-				} else {
-					self.channel_liquidities
-						.entry(hop.short_channel_id)
-						.or_insert_with(ChannelLiquidity::new)
-						.as_directed_mut(source, &target, 0, capacity_msat, self.decay_params)
-						.failed_downstream(amount_msat, format_args!("SCID {}, towards {:?}", hop.short_channel_id, target), &self.logger);
-				}
-			} else {
-				log_debug!(self.logger, "Not able to penalize channel with SCID {} as we do not have graph info for it (likely a route-hint last-hop).",
-					hop.short_channel_id);
-			}
-			if at_failed_channel { break; }
+	fn payment_path_failed(&mut self, path: &Path, short_channel_id: u64) {
+		self.update_liquidity_for_failed_path(path, short_channel_id);
+
+		// Failures at our first hop reflect local state rather than the channel's reliability.
+		if path.hops.iter().skip(1).any(|hop| hop.short_channel_id == short_channel_id) {
+			let half_life = self.decay_params.failure_log_half_life;
+			self.channel_failures.entry(short_channel_id)
+				.or_insert_with(ChannelFailureLog::new)
+				.record_failure(half_life);
 		}
 	}
 
@@ -1338,11 +1872,34 @@ impl<G: Deref<Target = NetworkGraph<L>>, L: Deref, T: Time> Score for Probabilis
 	}
 
 	fn probe_failed(&mut self, path: &Path, short_channel_id: u64) {
-		self.payment_path_failed(path, short_channel_id)
+		// Probes are expected to fail, so they are not recorded in the failure log.
+		self.update_liquidity_for_failed_path(path, short_channel_id)
 	}
 
 	fn probe_successful(&mut self, path: &Path) {
-		self.payment_path_failed(path, u64::max_value())
+		self.update_liquidity_for_failed_path(path, u64::max_value())
+	}
+
+	/// Sets the height the age of channels is determined relative to for
+	/// [`ProbabilisticScoringFeeParameters::young_channel_penalty_msat`], and learns about channels
+	/// disabled since the last call from the network graph for
+	/// [`ProbabilisticScoringFeeParameters::disabled_channel_penalty_msat`], counting them as
+	/// disabled at the given height.
+	fn best_block_updated(&mut self, height: u32) {
+		let half_life_blocks = self.decay_params.disabled_channel_half_life_blocks;
+		let (disabled_channels, cursor) = self.network_graph.channels_disabled_since(self.disabled_channels_cursor);
+		self.disabled_channels_cursor = cursor;
+		for short_channel_id in disabled_channels {
+			self.channel_disables.entry(short_channel_id)
+				.or_insert(ChannelDisableLog { disable_weight: 0, last_disabled_height: height })
+				.record_disable(height, half_life_blocks);
+		}
+		// Forget channels whose disables fully decayed once per half-life, rather than on every block.
+		let previous_half_lives = self.best_block_height.checked_div(half_life_blocks);
+		if previous_half_lives != height.checked_div(half_life_blocks) {
+			self.channel_disables.retain(|_, log| log.decayed_disable_weight(height, half_life_blocks) != 0);
+		}
+		self.best_block_height = height;
 	}
 }
 
@@ -1661,6 +2218,10 @@ impl<G: Deref<Target = NetworkGraph<L>>, L: Deref, T: Time> Writeable for Probab
 	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
 		write_tlv_fields!(w, {
 			(0, self.channel_liquidities, required),
+			(1, self.channel_failures, required),
+			(3, self.channel_disables, required),
+			(5, self.best_block_height, required),
+			(7, self.time_passed, required),
 		});
 		Ok(())
 	}
@@ -1674,18 +2235,92 @@ ReadableArgs<(ProbabilisticScoringDecayParameters, G, L)> for ProbabilisticScore
 	) -> Result<Self, DecodeError> {
 		let (decay_params, network_graph, logger) = args;
 		let mut channel_liquidities = HashMap::new();
+		let mut channel_failures = None;
+		let mut channel_disables = None;
+		let mut best_block_height = None;
+		let mut time_passed = None;
 		read_tlv_fields!(r, {
 			(0, channel_liquidities, required),
+			(1, channel_failures, option),
+			(3, channel_disables, option),
+			(5, best_block_height, option),
+			(7, time_passed, option),
 		});
 		Ok(Self {
 			decay_params,
 			network_graph,
 			logger,
 			channel_liquidities,
+			channel_failures: channel_failures.unwrap_or_else(HashMap::new),
+			best_block_height: best_block_height.unwrap_or(0),
+			channel_disables: channel_disables.unwrap_or_else(HashMap::new),
+			disabled_channels_cursor: 0,
+			time_passed: time_passed.unwrap_or(Duration::from_secs(0)),
 		})
 	}
 }
 
+/// Returns the wallclock time of `time` in seconds since the UNIX epoch.
+fn epoch_secs<T: Time>(time: &T) -> u64 {
+	(T::duration_since_epoch() - time.elapsed()).as_secs()
+}
+
+/// Returns the [`Time`] at the given number of seconds since the UNIX epoch, using the current
+/// time if the wallclock is behind it, as is done when reading a [`ChannelLiquidity`].
+fn time_from_epoch_secs<T: Time>(secs: u64) -> T {
+	let duration_since_epoch = Duration::from_secs(secs);
+	let wall_clock_now = T::duration_since_epoch();
+	let now = T::now();
+	if wall_clock_now > duration_since_epoch {
+		now - (wall_clock_now - duration_since_epoch)
+	} else { now }
+}
+
+fn write_buckets(tracker: &HistoricalBucketRangeTracker) -> String {
+	tracker.buckets.iter().map(|bucket| bucket.to_string()).collect::<Vec<_>>().join(",")
+}
+
+fn read_buckets(record: &Record, index: usize) -> Result<HistoricalBucketRangeTracker, InterchangeError> {
+	let buckets: Vec<u16> = record.list_field(index)?;
+	if buckets.len() != 8 {
+		return Err(record.error());
+	}
+	let mut tracker = HistoricalBucketRangeTracker::new();
+	tracker.buckets.copy_from_slice(&buckets);
+	Ok(tracker)
+}
+
+impl<T: Time> Writeable for ChannelFailureLog<T> {
+	#[inline]
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		let duration_since_epoch = T::duration_since_epoch() - self.last_failed.elapsed();
+		write_tlv_fields!(w, {
+			(0, self.failure_weight, required),
+			(2, duration_since_epoch, required),
+		});
+		Ok(())
+	}
+}
+
+impl<T: Time> Readable for ChannelFailureLog<T> {
+	#[inline]
+	fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
+		let mut failure_weight = 0;
+		let mut duration_since_epoch = Duration::from_secs(0);
+		read_tlv_fields!(r, {
+			(0, failure_weight, required),
+			(2, duration_since_epoch, required),
+		});
+		// As with `ChannelLiquidity`, guard against the wallclock time having jumped backwards.
+		let wall_clock_now = T::duration_since_epoch();
+		let now = T::now();
+		let last_failed = if wall_clock_now > duration_since_epoch {
+			now - (wall_clock_now - duration_since_epoch)
+		} else { now };
+		Ok(Self { failure_weight, last_failed })
+	}
+}
+
 impl<T: Time> Writeable for ChannelLiquidity<T> {
 	#[inline]
 	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
@@ -1740,15 +2375,16 @@ impl<T: Time> Readable for ChannelLiquidity<T> {
 
 #[cfg(test)]
 mod tests {
-	use super::{ChannelLiquidity, HistoricalBucketRangeTracker, ProbabilisticScoringFeeParameters, ProbabilisticScoringDecayParameters, ProbabilisticScorerUsingTime};
+	use super::{ChannelLiquidity, FailurePenaltyCurve, HistoricalBucketRangeTracker, ProbabilisticScoringFeeParameters, ProbabilisticScoringDecayParameters, ProbabilisticScorerUsingTime};
 	use crate::blinded_path::{BlindedHop, BlindedPath};
 	use crate::util::config::UserConfig;
-	use crate::util::time::Time;
+	use crate::util::time::{Eternity, Time};
 	use crate::util::time::tests::SinceEpoch;
 
 	use crate::ln::channelmanager;
 	use crate::ln::msgs::{ChannelAnnouncement, ChannelUpdate, UnsignedChannelAnnouncement, UnsignedChannelUpdate};
 	use crate::routing::gossip::{EffectiveCapacity, NetworkGraph, NodeId};
+	use crate::routing::interchange::InterchangeError;
 	use crate::routing::router::{BlindedTail, Path, RouteHop};
 	use crate::routing::scoring::{ChannelUsage, Score};
 	use crate::util::ser::{ReadableArgs, Writeable};
@@ -2575,6 +3211,293 @@ mod tests {
 		assert_eq!(deserialized_scorer.channel_penalty_msat(42, &source, &target, usage, &params), 300);
 	}
 
+	#[test]
+	fn penalizes_and_persists_recorded_failures() {
+		let logger = TestLogger::new();
+		let network_graph = network_graph(&logger);
+		let params = ProbabilisticScoringFeeParameters {
+			failure_penalty_msat: 1_000,
+			failure_penalty_curve: FailurePenaltyCurve::Quadratic,
+			max_failure_penalty_msat: 10_000,
+			..ProbabilisticScoringFeeParameters::zero_penalty()
+		};
+		let decay_params = ProbabilisticScoringDecayParameters {
+			failure_log_half_life: Duration::from_secs(100),
+			..ProbabilisticScoringDecayParameters::default()
+		};
+		let mut scorer = ProbabilisticScorer::new(decay_params, &network_graph, &logger);
+		let source = source_node_id();
+		let target = target_node_id();
+		let usage = ChannelUsage {
+			amount_msat: 100,
+			inflight_htlc_msat: 0,
+			effective_capacity: EffectiveCapacity::Total { capacity_msat: 1_000, htlc_maximum_msat: 1_000 },
+		};
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage, &params), 0);
+		assert_eq!(scorer.recorded_channel_failures(42), None);
+
+		scorer.payment_path_failed(&payment_path_for_amount(500), 42);
+		assert_eq!(scorer.recorded_channel_failures(42), Some(1.0));
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage, &params), 1_000);
+		scorer.payment_path_failed(&payment_path_for_amount(500), 42);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage, &params), 4_000);
+
+		// Neither failures at our first hop nor failed probes are recorded.
+		scorer.payment_path_failed(&payment_path_for_amount(500), 41);
+		assert_eq!(scorer.recorded_channel_failures(41), None);
+		scorer.probe_failed(&payment_path_for_amount(500), 42);
+		assert_eq!(scorer.recorded_channel_failures(42), Some(2.0));
+
+		let mut serialized_scorer = Vec::new();
+		scorer.write(&mut serialized_scorer).unwrap();
+
+		let mut serialized_scorer = io::Cursor::new(&serialized_scorer);
+		let mut deserialized_scorer =
+			<ProbabilisticScorer>::read(&mut serialized_scorer, (decay_params, &network_graph, &logger)).unwrap();
+		assert_eq!(deserialized_scorer.channel_penalty_msat(42, &source, &target, usage, &params), 4_000);
+
+		SinceEpoch::advance(Duration::from_secs(100));
+		assert_eq!(deserialized_scorer.recorded_channel_failures(42), Some(1.0));
+		assert_eq!(deserialized_scorer.channel_penalty_msat(42, &source, &target, usage, &params), 1_000);
+
+		for _ in 0..3 {
+			deserialized_scorer.payment_path_failed(&payment_path_for_amount(500), 42);
+		}
+		assert_eq!(deserialized_scorer.channel_penalty_msat(42, &source, &target, usage, &params), 10_000);
+
+		deserialized_scorer.clear_recorded_channel_failures(42);
+		assert_eq!(deserialized_scorer.channel_penalty_msat(42, &source, &target, usage, &params), 0);
+	}
+
+	#[test]
+	fn decays_by_injected_time() {
+		// Without a clock, e.g. when built with `no-std`, our data only decays as the host tells us
+		// time has passed.
+		let logger = TestLogger::new();
+		let network_graph = network_graph(&logger);
+		let params = ProbabilisticScoringFeeParameters {
+			liquidity_penalty_multiplier_msat: 1_000,
+			considered_impossible_penalty_msat: u64::max_value(),
+			..ProbabilisticScoringFeeParameters::zero_penalty()
+		};
+		let decay_params = ProbabilisticScoringDecayParameters {
+			liquidity_offset_half_life: Duration::from_secs(10),
+			failure_log_half_life: Duration::from_secs(100),
+			..ProbabilisticScoringDecayParameters::zero_penalty()
+		};
+		let mut scorer = ProbabilisticScorerUsingTime::<_, _, Eternity>::new(decay_params, &network_graph, &logger);
+		let source = source_node_id();
+		let target = target_node_id();
+		let usage = ChannelUsage {
+			amount_msat: 896,
+			inflight_htlc_msat: 0,
+			effective_capacity: EffectiveCapacity::Total { capacity_msat: 1_024, htlc_maximum_msat: 1_024 },
+		};
+
+		scorer.payment_path_failed(&payment_path_for_amount(768), 42);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage, &params), u64::max_value());
+		assert_eq!(scorer.recorded_channel_failures(42), Some(1.0));
+
+		// Nothing decays until a full half-life has passed in total...
+		scorer.time_passed(Duration::from_secs(9));
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage, &params), u64::max_value());
+
+		// ...at which point the upper bound moves half-way to the capacity.
+		scorer.time_passed(Duration::from_secs(1));
+		assert_ne!(scorer.channel_penalty_msat(42, &source, &target, usage, &params), u64::max_value());
+		let usage = ChannelUsage { amount_msat: 960, ..usage };
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage, &params), u64::max_value());
+		assert_eq!(scorer.recorded_channel_failures(42), Some(1.0));
+
+		// The total time passed is persisted, such that the failure log still decays after one
+		// failure log half-life in total.
+		scorer.time_passed(Duration::from_secs(70));
+		let mut serialized_scorer = Vec::new();
+		scorer.write(&mut serialized_scorer).unwrap();
+		let mut serialized_scorer = io::Cursor::new(&serialized_scorer);
+		let mut deserialized_scorer = <ProbabilisticScorerUsingTime::<_, _, Eternity>>::read(
+			&mut serialized_scorer, (decay_params, &network_graph, &logger)).unwrap();
+		deserialized_scorer.time_passed(Duration::from_secs(10));
+		assert_eq!(deserialized_scorer.recorded_channel_failures(42), Some(1.0));
+		deserialized_scorer.time_passed(Duration::from_secs(10));
+		assert_eq!(deserialized_scorer.recorded_channel_failures(42), Some(0.5));
+		let usage = ChannelUsage { amount_msat: 1_023, ..usage };
+		assert_ne!(deserialized_scorer.channel_penalty_msat(42, &source, &target, usage, &params), u64::max_value());
+	}
+
+	#[test]
+	fn exports_and_imports_interchange_format() {
+		let logger = TestLogger::new();
+		let network_graph = network_graph(&logger);
+		let params = ProbabilisticScoringFeeParameters {
+			liquidity_penalty_multiplier_msat: 1_000,
+			failure_penalty_msat: 1_000,
+			..ProbabilisticScoringFeeParameters::zero_penalty()
+		};
+		let decay_params = ProbabilisticScoringDecayParameters::default();
+		let mut scorer = ProbabilisticScorer::new(decay_params, &network_graph, &logger);
+		let source = source_node_id();
+		let target = target_node_id();
+		let usage = ChannelUsage {
+			amount_msat: 500,
+			inflight_htlc_msat: 0,
+			effective_capacity: EffectiveCapacity::Total { capacity_msat: 1_000, htlc_maximum_msat: 1_000 },
+		};
+
+		scorer.payment_path_failed(&payment_path_for_amount(500), 42);
+		SinceEpoch::advance(Duration::from_secs(10));
+		let penalty_msat = scorer.channel_penalty_msat(42, &source, &target, usage, &params);
+		assert_ne!(penalty_msat, 0);
+
+		let exported = scorer.write_interchange();
+		assert!(exported.starts_with("ldk-interchange 1 scorer\n"));
+
+		let mut imported_scorer = ProbabilisticScorer::new(decay_params, &network_graph, &logger);
+		imported_scorer.read_interchange(&exported).unwrap();
+		assert_eq!(imported_scorer.channel_penalty_msat(42, &source, &target, usage, &params), penalty_msat);
+		assert_eq!(imported_scorer.recorded_channel_failures(42), scorer.recorded_channel_failures(42));
+		assert_eq!(imported_scorer.write_interchange(), exported);
+
+		// Malformed data is rejected without modifying the scorer.
+		let malformed = format!("{}liquidity 43 0 0 0 1,2,3 0,0,0,0,0,0,0,0\n", exported);
+		assert_eq!(imported_scorer.read_interchange(&malformed),
+			Err(InterchangeError::InvalidRecord { line: malformed.lines().count() }));
+		assert_eq!(imported_scorer.write_interchange(), exported);
+		assert_eq!(imported_scorer.read_interchange("ldk-interchange 1 network-graph\n"),
+			Err(InterchangeError::UnexpectedKind));
+	}
+
+	#[test]
+	fn penalizes_young_and_disabled_channels() {
+		let logger = TestLogger::new();
+		let network_graph = network_graph(&logger);
+		let params = ProbabilisticScoringFeeParameters {
+			young_channel_penalty_msat: 1_000,
+			young_channel_age_blocks: 100,
+			disabled_channel_penalty_msat: 300,
+			max_disabled_channel_penalty_msat: 500,
+			..ProbabilisticScoringFeeParameters::zero_penalty()
+		};
+		let decay_params = ProbabilisticScoringDecayParameters {
+			disabled_channel_half_life_blocks: 10,
+			..ProbabilisticScoringDecayParameters::default()
+		};
+		let mut scorer = ProbabilisticScorer::new(decay_params, &network_graph, &logger);
+		let source = source_node_id();
+		let target = target_node_id();
+		let usage = ChannelUsage {
+			amount_msat: 100,
+			inflight_htlc_msat: 0,
+			effective_capacity: EffectiveCapacity::Total { capacity_msat: 1_000, htlc_maximum_msat: 1_000 },
+		};
+
+		// Without knowing the best block height, the age of channels is unknown.
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage, &params), 0);
+
+		// Channel 42 was funded in block 0.
+		scorer.best_block_updated(50);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage, &params), 500);
+		scorer.best_block_updated(100);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage, &params), 0);
+
+		let disable_channel = |timestamp, flags| {
+			network_graph.update_channel_unsigned(&UnsignedChannelUpdate {
+				chain_hash: genesis_block(Network::Testnet).header.block_hash(),
+				short_channel_id: 42,
+				timestamp,
+				flags,
+				cltv_expiry_delta: 18,
+				htlc_minimum_msat: 0,
+				htlc_maximum_msat: 1_000,
+				fee_base_msat: 1,
+				fee_proportional_millionths: 0,
+				excess_data: Vec::new(),
+			}).unwrap();
+		};
+
+		// Only updates disabling a previously-enabled direction are counted.
+		disable_channel(101, 2);
+		disable_channel(102, 2);
+		assert_eq!(network_graph.read_only().channel(42).unwrap().disabled_count(), 1);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage, &params), 0);
+		scorer.best_block_updated(101);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage, &params), 300);
+
+		disable_channel(103, 0);
+		disable_channel(104, 2);
+		assert_eq!(network_graph.channels_disabled_since(0), (vec![42, 42], 2));
+		assert_eq!(network_graph.channels_disabled_since(1), (vec![42], 2));
+		scorer.best_block_updated(102);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage, &params), 500);
+
+		// Disables already learned aren't counted again.
+		scorer.best_block_updated(103);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage, &params), 500);
+
+		// The disables decay by half every 10 blocks since the channel was last disabled.
+		scorer.best_block_updated(112);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage, &params), 300);
+		scorer.best_block_updated(122);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage, &params), 150);
+
+		// The decayed disables and the best block height are persisted.
+		let mut serialized_scorer = Vec::new();
+		scorer.write(&mut serialized_scorer).unwrap();
+		let mut serialized_scorer = io::Cursor::new(&serialized_scorer);
+		let deserialized_scorer =
+			<ProbabilisticScorer>::read(&mut serialized_scorer, (decay_params, &network_graph, &logger)).unwrap();
+		assert_eq!(deserialized_scorer.channel_penalty_msat(42, &source, &target, usage, &params), 150);
+
+		// Once fully decayed, the channel is forgotten.
+		scorer.best_block_updated(102 + 32 * 10);
+		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage, &params), 0);
+		assert!(scorer.channel_disables.is_empty());
+	}
+
+	#[test]
+	fn tunes_decay_params_and_reports_penalty_details() {
+		let logger = TestLogger::new();
+		let network_graph = network_graph(&logger);
+		let params = ProbabilisticScoringFeeParameters {
+			liquidity_penalty_multiplier_msat: 1_000,
+			..ProbabilisticScoringFeeParameters::zero_penalty()
+		};
+		let decay_params = ProbabilisticScoringDecayParameters {
+			liquidity_offset_half_life: Duration::from_secs(3600),
+			..ProbabilisticScoringDecayParameters::default()
+		};
+		let mut scorer = ProbabilisticScorer::new(decay_params, &network_graph, &logger);
+		let source = source_node_id();
+		let target = target_node_id();
+		assert!(scorer.channel_penalty_details(41, &target, 250, &params).is_none());
+
+		scorer.payment_path_failed(&payment_path_for_amount(500), 42);
+		let details = scorer.channel_penalty_details(42, &target, 250, &params).unwrap();
+		assert_eq!(Some((details.min_liquidity_msat, details.max_liquidity_msat)),
+			scorer.estimated_channel_liquidity_range(42, &target));
+		assert_eq!(details.capacity_msat, 1_000);
+		assert_eq!(details.failure_penalty_msat, 0);
+
+		let effective_capacity = network_graph.read_only().channel(42).unwrap()
+			.as_directed_to(&target).unwrap().0.effective_capacity();
+		let usage = ChannelUsage { amount_msat: 250, inflight_htlc_msat: 0, effective_capacity };
+		assert_eq!(details.penalty_msat, scorer.channel_penalty_msat(42, &source, &target, usage, &params));
+		assert_eq!(Some(details.success_probability),
+			scorer.channel_success_probability(42, &source, &target, usage, &params));
+
+		// Shortening the half-life applies to the data learned so far without dropping it.
+		SinceEpoch::advance(Duration::from_secs(10));
+		assert_eq!(scorer.channel_penalty_details(42, &target, 250, &params).unwrap(), details);
+		scorer.set_decay_params(ProbabilisticScoringDecayParameters {
+			liquidity_offset_half_life: Duration::from_secs(10),
+			..decay_params
+		});
+		assert_eq!(scorer.decay_params().liquidity_offset_half_life, Duration::from_secs(10));
+		let decayed_details = scorer.channel_penalty_details(42, &target, 250, &params).unwrap();
+		assert!(decayed_details.max_liquidity_msat > details.max_liquidity_msat);
+		assert!(decayed_details.penalty_msat < details.penalty_msat);
+	}
+
 	#[test]
 	fn decays_persisted_liquidity_bounds() {
 		let logger = TestLogger::new();
@@ -2821,6 +3744,7 @@ mod tests {
 		let decay_params = ProbabilisticScoringDecayParameters {
 			liquidity_offset_half_life: Duration::from_secs(60 * 60),
 			historical_no_updates_half_life: Duration::from_secs(10),
+			..ProbabilisticScoringDecayParameters::default()
 		};
 		let mut scorer = ProbabilisticScorer::new(decay_params, &network_graph, &logger);
 		let source = source_node_id();
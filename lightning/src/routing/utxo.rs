@@ -13,11 +13,15 @@
 //! channel matches a UTXO on-chain, requiring at least some marginal on-chain transacting in
 //! order to announce a channel. This module handles that checking.
 
-use bitcoin::{BlockHash, TxOut};
+use bitcoin::{BlockHash, Script, TxOut};
 use bitcoin::hashes::hex::ToHex;
+#[cfg(taproot)]
+use bitcoin::secp256k1::{self, PublicKey, Secp256k1};
 
 use crate::events::MessageSendEvent;
 use crate::ln::chan_utils::make_funding_redeemscript_from_slices;
+#[cfg(taproot)]
+use crate::ln::chan_utils::make_taproot_funding_script;
 use crate::ln::msgs::{self, LightningError, ErrorAction};
 use crate::routing::gossip::{NetworkGraph, NodeId, P2PGossipSync};
 use crate::util::logger::{Level, Logger};
@@ -70,12 +74,26 @@ pub trait UtxoLookup {
 enum ChannelAnnouncement {
 	Full(msgs::ChannelAnnouncement),
 	Unsigned(msgs::UnsignedChannelAnnouncement),
+	/// An announcement derived from a verified `channel_announcement_2`, whose funding output may
+	/// be a simple taproot channel's P2TR output.
+	#[cfg(taproot)]
+	Taproot(msgs::UnsignedChannelAnnouncement),
 }
 impl ChannelAnnouncement {
+	fn unsigned(msg: msgs::UnsignedChannelAnnouncement, allow_taproot_funding: bool) -> Self {
+		if allow_taproot_funding {
+			#[cfg(taproot)]
+			return ChannelAnnouncement::Taproot(msg);
+		}
+		ChannelAnnouncement::Unsigned(msg)
+	}
+
 	fn node_id_1(&self) -> &NodeId {
 		match self {
 			ChannelAnnouncement::Full(msg) => &msg.contents.node_id_1,
 			ChannelAnnouncement::Unsigned(msg) => &msg.node_id_1,
+			#[cfg(taproot)]
+			ChannelAnnouncement::Taproot(msg) => &msg.node_id_1,
 		}
 	}
 }
@@ -201,6 +219,8 @@ impl UtxoFuture {
 			let announcement_msg = match async_messages.channel_announce.as_ref().unwrap() {
 				ChannelAnnouncement::Full(signed_msg) => &signed_msg.contents,
 				ChannelAnnouncement::Unsigned(msg) => &msg,
+				#[cfg(taproot)]
+				ChannelAnnouncement::Taproot(msg) => &msg,
 			};
 
 			pending_checks.lookup_completed(announcement_msg, &Arc::downgrade(&self.state));
@@ -232,6 +252,10 @@ impl UtxoFuture {
 			ChannelAnnouncement::Unsigned(msg) => {
 				let _ = graph.update_channel_from_unsigned_announcement(&msg, &Some(&resolver));
 			},
+			#[cfg(taproot)]
+			ChannelAnnouncement::Taproot(msg) => {
+				let _ = graph.update_channel_from_unsigned_announcement_intern(&msg, None, &Some(&resolver), true);
+			},
 		}
 
 		for announce in core::iter::once(node_a).chain(core::iter::once(node_b)) {
@@ -298,16 +322,123 @@ impl PendingChecksContext {
 	}
 }
 
+/// The default number of blocks after which a cached [`UtxoLookup`] result is considered stale.
+/// If a chain-validated channel is announced again after this many blocks we look its funding
+/// output up again, pruning the channel if the output has since been spent.
+///
+/// May be changed via [`NetworkGraph::set_utxo_revalidation_interval`].
+pub const DEFAULT_UTXO_REVALIDATION_INTERVAL_BLOCKS: u32 = 144;
+
+/// A funding output which we've looked up and found to match a channel's announced keys.
+struct CachedUtxo {
+	txout: TxOut,
+	/// The best block height at the time we last looked the output up.
+	checked_height: u32,
+}
+
+struct UtxoCache {
+	/// The best block height we've been told about. Until this is set cached entries never go
+	/// stale and previously-validated channels are never revalidated.
+	best_block_height: Option<u32>,
+	revalidation_interval_blocks: u32,
+	utxos: HashMap<u64, CachedUtxo>,
+	/// The channels whose previously-validated funding output a revalidating lookup found to be
+	/// spent (or no longer matching), until the graph prunes them.
+	spent_funding_outputs: HashSet<u64>,
+}
+
+impl UtxoCache {
+	fn is_stale(&self, utxo: &CachedUtxo) -> bool {
+		match self.best_block_height {
+			Some(height) => height >= utxo.checked_height.saturating_add(self.revalidation_interval_blocks),
+			None => false,
+		}
+	}
+}
+
 /// A set of messages which are pending UTXO lookups for processing.
 pub(super) struct PendingChecks {
 	internal: Mutex<PendingChecksContext>,
+	/// Results of recent successful lookups, keyed by SCID. Always locked after `internal`, if
+	/// both are held.
+	utxo_cache: Mutex<UtxoCache>,
+	#[cfg(taproot)]
+	secp_ctx: Secp256k1<secp256k1::VerifyOnly>,
 }
 
 impl PendingChecks {
 	pub(super) fn new() -> Self {
-		PendingChecks { internal: Mutex::new(PendingChecksContext {
-			channels: HashMap::new(), nodes: HashMap::new(),
-		}) }
+		PendingChecks {
+			internal: Mutex::new(PendingChecksContext {
+				channels: HashMap::new(), nodes: HashMap::new(),
+			}),
+			utxo_cache: Mutex::new(UtxoCache {
+				best_block_height: None,
+				revalidation_interval_blocks: DEFAULT_UTXO_REVALIDATION_INTERVAL_BLOCKS,
+				utxos: HashMap::new(),
+				spent_funding_outputs: HashSet::new(),
+			}),
+			#[cfg(taproot)]
+			secp_ctx: Secp256k1::verification_only(),
+		}
+	}
+
+	/// Updates the best block height used to decide when cached lookup results go stale, dropping
+	/// any entries which have.
+	pub(super) fn best_block_updated(&self, height: u32) {
+		let mut cache = self.utxo_cache.lock().unwrap();
+		cache.best_block_height = Some(height);
+		let mut utxos = core::mem::replace(&mut cache.utxos, HashMap::new());
+		utxos.retain(|_, utxo| !cache.is_stale(utxo));
+		cache.utxos = utxos;
+	}
+
+	/// Sets the number of blocks after which cached lookup results go stale.
+	pub(super) fn set_revalidation_interval(&self, blocks: u32) {
+		self.utxo_cache.lock().unwrap().revalidation_interval_blocks = blocks;
+	}
+
+	/// Returns true if a lookup made while revalidating the given channel found its funding output
+	/// to be spent, forgetting about it.
+	pub(super) fn take_spent_funding_output(&self, short_channel_id: u64) -> bool {
+		self.utxo_cache.lock().unwrap().spent_funding_outputs.remove(&short_channel_id)
+	}
+
+	fn funding_output_spent(&self, short_channel_id: u64) {
+		let mut cache = self.utxo_cache.lock().unwrap();
+		cache.utxos.remove(&short_channel_id);
+		cache.spent_funding_outputs.insert(short_channel_id);
+	}
+
+	/// Returns true if the funding output of an already chain-validated channel should be looked
+	/// up again, i.e. if we know the best block height and have no fresh cached result for it.
+	pub(super) fn utxo_revalidation_due(&self, short_channel_id: u64) -> bool {
+		let cache = self.utxo_cache.lock().unwrap();
+		if cache.best_block_height.is_none() { return false; }
+		match cache.utxos.get(&short_channel_id) {
+			Some(utxo) => cache.is_stale(utxo),
+			None => true,
+		}
+	}
+
+	/// Forgets any cached lookup result for the given channel.
+	fn remove_cached_utxo(&self, short_channel_id: u64) {
+		self.utxo_cache.lock().unwrap().utxos.remove(&short_channel_id);
+	}
+
+	/// Returns the value of the cached funding output for the given channel if it is still fresh
+	/// and its script is accepted by `script_matches`.
+	fn get_cached_utxo_value<F: Fn(&Script) -> bool>(&self, short_channel_id: u64, script_matches: F) -> Option<u64> {
+		let cache = self.utxo_cache.lock().unwrap();
+		cache.utxos.get(&short_channel_id)
+			.filter(|utxo| !cache.is_stale(utxo) && script_matches(&utxo.txout.script_pubkey))
+			.map(|utxo| utxo.txout.value)
+	}
+
+	fn cache_utxo(&self, short_channel_id: u64, txout: TxOut) {
+		let mut cache = self.utxo_cache.lock().unwrap();
+		let checked_height = cache.best_block_height.unwrap_or(0);
+		cache.utxos.insert(short_channel_id, CachedUtxo { txout, checked_height });
 	}
 
 	/// Checks if there is a pending `channel_update` UTXO validation for the given channel,
@@ -410,6 +541,8 @@ impl PendingChecks {
 						let pending_matches = match &pending_msgs.unsafe_well_ordered_double_lock_self().channel_announce {
 							Some(ChannelAnnouncement::Full(pending_msg)) => Some(pending_msg) == full_msg,
 							Some(ChannelAnnouncement::Unsigned(pending_msg)) => pending_msg == msg,
+							#[cfg(taproot)]
+							Some(ChannelAnnouncement::Taproot(pending_msg)) => pending_msg == msg,
 							None => {
 								// This shouldn't actually be reachable. We set the
 								// `channel_announce` field under the same lock as setting the
@@ -453,20 +586,42 @@ impl PendingChecks {
 
 	pub(super) fn check_channel_announcement<U: Deref>(&self,
 		utxo_lookup: &Option<U>, msg: &msgs::UnsignedChannelAnnouncement,
-		full_msg: Option<&msgs::ChannelAnnouncement>
+		full_msg: Option<&msgs::ChannelAnnouncement>, allow_taproot_funding: bool,
+		revalidating_utxo: bool
 	) -> Result<Option<u64>, msgs::LightningError> where U::Target: UtxoLookup {
+		let expected_script =
+			make_funding_redeemscript_from_slices(msg.bitcoin_key_1.as_slice(), msg.bitcoin_key_2.as_slice()).to_v0_p2wsh();
+		// Simple taproot channels are funded by a P2TR output to the aggregate of the funding keys
+		// instead, which we accept as well if the announcement came from a `channel_announcement_2`.
+		#[cfg(taproot)]
+		let taproot_script = if !allow_taproot_funding { None } else {
+			match (PublicKey::from_slice(msg.bitcoin_key_1.as_slice()), PublicKey::from_slice(msg.bitcoin_key_2.as_slice())) {
+				(Ok(bitcoin_key_1), Ok(bitcoin_key_2)) =>
+					make_taproot_funding_script(&self.secp_ctx, &bitcoin_key_1, &bitcoin_key_2),
+				_ => None,
+			}
+		};
+		#[cfg(not(taproot))]
+		let taproot_script: Option<Script> = None;
+		let script_matches = |script: &Script| {
+			taproot_script.as_ref() == Some(script) || *script == expected_script
+		};
 		let handle_result = |res| {
 			match res {
 				Ok(TxOut { value, script_pubkey }) => {
-					let expected_script =
-						make_funding_redeemscript_from_slices(msg.bitcoin_key_1.as_slice(), msg.bitcoin_key_2.as_slice()).to_v0_p2wsh();
-					if script_pubkey != expected_script {
+					if !script_matches(&script_pubkey) {
+						let expected_scripts = match taproot_script {
+							Some(ref taproot_script) => format!("{} or {}", expected_script.to_hex(), taproot_script.to_hex()),
+							None => expected_script.to_hex(),
+						};
+						if revalidating_utxo { self.funding_output_spent(msg.short_channel_id); }
 						return Err(LightningError{
 							err: format!("Channel announcement key ({}) didn't match on-chain script ({})",
-								expected_script.to_hex(), script_pubkey.to_hex()),
+								expected_scripts, script_pubkey.to_hex()),
 							action: ErrorAction::IgnoreError
 						});
 					}
+					self.cache_utxo(msg.short_channel_id, TxOut { value, script_pubkey });
 					Ok(Some(value))
 				},
 				Err(UtxoLookupError::UnknownChain) => {
@@ -477,6 +632,13 @@ impl PendingChecks {
 					})
 				},
 				Err(UtxoLookupError::UnknownTx) => {
+					// If we'd previously validated this output it's since been spent (or reorg'd
+					// out), so don't keep serving it from the cache.
+					if revalidating_utxo {
+						self.funding_output_spent(msg.short_channel_id);
+					} else {
+						self.remove_cached_utxo(msg.short_channel_id);
+					}
 					Err(LightningError {
 						err: "Channel announced without corresponding UTXO entry".to_owned(),
 						action: ErrorAction::IgnoreError
@@ -494,6 +656,11 @@ impl PendingChecks {
 				Ok(None)
 			},
 			&Some(ref utxo_lookup) => {
+				if let Some(value) = self.get_cached_utxo_value(msg.short_channel_id, &script_matches) {
+					// We recently looked this output up and it matched, don't bother the backend
+					// again.
+					return Ok(Some(value));
+				}
 				match utxo_lookup.get_utxo(&msg.chain_hash, msg.short_channel_id) {
 					UtxoResult::Sync(res) => handle_result(res),
 					UtxoResult::Async(future) => {
@@ -508,7 +675,7 @@ impl PendingChecks {
 								Some(Arc::downgrade(&future.state)), &mut pending_checks.channels)?;
 							async_messages.channel_announce = Some(
 								if let Some(msg) = full_msg { ChannelAnnouncement::Full(msg.clone()) }
-								else { ChannelAnnouncement::Unsigned(msg.clone()) });
+								else { ChannelAnnouncement::unsigned(msg.clone(), allow_taproot_funding) });
 							pending_checks.nodes.entry(msg.node_id_1)
 								.or_insert(Vec::new()).push(Arc::downgrade(&future.state));
 							pending_checks.nodes.entry(msg.node_id_2)
@@ -799,6 +966,75 @@ mod tests {
 			.contents.features.supports_unknown_test_feature());
 	}
 
+	#[test]
+	fn test_cached_lookup_revalidation() {
+		// Test that lookup results are cached until they go stale, at which point a re-announced
+		// channel is revalidated and pruned if its funding output has been spent.
+		let (valid_announcement, chain_source, network_graph, good_script, ..) = get_test_objects();
+		let scid = valid_announcement.contents.short_channel_id;
+
+		*chain_source.utxo_ret.lock().unwrap() =
+			UtxoResult::Sync(Ok(TxOut { value: 1_000_000, script_pubkey: good_script.clone() }));
+		network_graph.best_block_updated(100);
+		network_graph.update_channel_from_announcement(&valid_announcement, &Some(&chain_source)).unwrap();
+		assert_eq!(chain_source.get_utxo_call_count.load(Ordering::Relaxed), 1);
+
+		// While the cached result is fresh, duplicate announcements don't hit the backend.
+		network_graph.best_block_updated(100 + DEFAULT_UTXO_REVALIDATION_INTERVAL_BLOCKS - 1);
+		assert_eq!(
+			network_graph.update_channel_from_announcement(&valid_announcement, &Some(&chain_source)).unwrap_err().err,
+			"Already have chain-validated channel");
+		assert_eq!(chain_source.get_utxo_call_count.load(Ordering::Relaxed), 1);
+
+		// Once it goes stale we look the output up again, keeping the channel if it's unspent...
+		network_graph.best_block_updated(100 + DEFAULT_UTXO_REVALIDATION_INTERVAL_BLOCKS);
+		assert_eq!(
+			network_graph.update_channel_from_announcement(&valid_announcement, &Some(&chain_source)).unwrap_err().err,
+			"Already have chain-validated channel");
+		assert_eq!(chain_source.get_utxo_call_count.load(Ordering::Relaxed), 2);
+		assert!(network_graph.read_only().channels().get(&scid).is_some());
+
+		assert_eq!(
+			network_graph.update_channel_from_announcement(&valid_announcement, &Some(&chain_source)).unwrap_err().err,
+			"Already have chain-validated channel");
+		assert_eq!(chain_source.get_utxo_call_count.load(Ordering::Relaxed), 2);
+
+		// ...or if the lookup fails for reasons unrelated to the output...
+		network_graph.best_block_updated(100 + 2 * DEFAULT_UTXO_REVALIDATION_INTERVAL_BLOCKS);
+		*chain_source.utxo_ret.lock().unwrap() = UtxoResult::Sync(Err(UtxoLookupError::UnknownChain));
+		assert!(network_graph.update_channel_from_announcement(&valid_announcement, &Some(&chain_source)).is_err());
+		assert_eq!(chain_source.get_utxo_call_count.load(Ordering::Relaxed), 3);
+		assert!(network_graph.read_only().channels().get(&scid).is_some());
+
+		// ...and pruning it if the output has since been spent.
+		*chain_source.utxo_ret.lock().unwrap() = UtxoResult::Sync(Err(UtxoLookupError::UnknownTx));
+		assert_eq!(
+			network_graph.update_channel_from_announcement(&valid_announcement, &Some(&chain_source)).unwrap_err().err,
+			"Channel announced without corresponding UTXO entry");
+		assert_eq!(chain_source.get_utxo_call_count.load(Ordering::Relaxed), 4);
+		assert!(network_graph.read_only().channels().get(&scid).is_none());
+	}
+
+	#[test]
+	fn test_configurable_revalidation_interval() {
+		let (valid_announcement, chain_source, network_graph, good_script, ..) = get_test_objects();
+
+		*chain_source.utxo_ret.lock().unwrap() =
+			UtxoResult::Sync(Ok(TxOut { value: 1_000_000, script_pubkey: good_script }));
+		network_graph.set_utxo_revalidation_interval(6);
+		network_graph.best_block_updated(100);
+		network_graph.update_channel_from_announcement(&valid_announcement, &Some(&chain_source)).unwrap();
+		assert_eq!(chain_source.get_utxo_call_count.load(Ordering::Relaxed), 1);
+
+		network_graph.best_block_updated(105);
+		assert!(network_graph.update_channel_from_announcement(&valid_announcement, &Some(&chain_source)).is_err());
+		assert_eq!(chain_source.get_utxo_call_count.load(Ordering::Relaxed), 1);
+
+		network_graph.best_block_updated(106);
+		assert!(network_graph.update_channel_from_announcement(&valid_announcement, &Some(&chain_source)).is_err());
+		assert_eq!(chain_source.get_utxo_call_count.load(Ordering::Relaxed), 2);
+	}
+
 	#[test]
 	fn test_checks_backpressure() {
 		// Test that too_many_checks_pending returns true when there are many checks pending, and
@@ -45,7 +45,7 @@ use crate::ln::msgs::{UnsignedChannelAnnouncement, UnsignedGossipMessage};
 use crate::ln::script::ShutdownScript;
 
 use crate::prelude::*;
-use core::convert::TryInto;
+use core::convert::{TryFrom, TryInto};
 use core::ops::Deref;
 use core::sync::atomic::{AtomicUsize, Ordering};
 use crate::io::{self, Error};
@@ -53,6 +53,7 @@ use crate::ln::msgs::{DecodeError, MAX_VALUE_MSAT};
 use crate::util::atomic_counter::AtomicCounter;
 use crate::util::chacha20::ChaCha20;
 use crate::util::invoice::construct_invoice_preimage;
+use crate::util::message_signing;
 
 /// Used as initial key material, to be expanded into multiple secret keys (but not to be used
 /// directly). This is used within LDK to encrypt/decrypt inbound payment data.
@@ -260,6 +261,13 @@ impl SpendableOutputDescriptor {
 	/// height to avoid fee sniping, unless you have some specific reason to use a different
 	/// locktime.
 	///
+	/// The `sequence` argument is used to set the sequence number of each input which does not
+	/// require a relative timelock, i.e., all but [`SpendableOutputDescriptor::DelayedPaymentOutput`]s.
+	/// If `None`, such inputs will have a sequence number of 0. Note that any sequence number below
+	/// [`Sequence::ENABLE_LOCKTIME_NO_RBF`] signals replaceability per BIP 125, e.g.,
+	/// [`Sequence::ENABLE_RBF_NO_LOCKTIME`], and that `locktime` is only enforced if some input
+	/// has a sequence number below [`Sequence::MAX`].
+	///
 	/// Returns the PSBT and expected max transaction weight.
 	///
 	/// Returns `Err(())` if the output value is greater than the input value minus required fee,
@@ -267,7 +275,8 @@ impl SpendableOutputDescriptor {
 	/// does not match the one we can spend.
 	///
 	/// We do not enforce that outputs meet the dust limit or that any output scripts are standard.
-	pub fn create_spendable_outputs_psbt(descriptors: &[&SpendableOutputDescriptor], outputs: Vec<TxOut>, change_destination_script: Script, feerate_sat_per_1000_weight: u32, locktime: Option<PackedLockTime>) -> Result<(PartiallySignedTransaction, usize), ()> {
+	pub fn create_spendable_outputs_psbt(descriptors: &[&SpendableOutputDescriptor], outputs: Vec<TxOut>, change_destination_script: Script, feerate_sat_per_1000_weight: u32, locktime: Option<PackedLockTime>, sequence: Option<Sequence>) -> Result<(PartiallySignedTransaction, usize), ()> {
+		let sequence = sequence.unwrap_or(Sequence::ZERO);
 		let mut input = Vec::with_capacity(descriptors.len());
 		let mut input_value = 0;
 		let mut witness_weight = 0;
@@ -279,7 +288,7 @@ impl SpendableOutputDescriptor {
 					input.push(TxIn {
 						previous_output: descriptor.outpoint.into_bitcoin_outpoint(),
 						script_sig: Script::new(),
-						sequence: Sequence::ZERO,
+						sequence,
 						witness: Witness::new(),
 					});
 					witness_weight += StaticPaymentOutputDescriptor::MAX_WITNESS_LENGTH;
@@ -305,7 +314,7 @@ impl SpendableOutputDescriptor {
 					input.push(TxIn {
 						previous_output: outpoint.into_bitcoin_outpoint(),
 						script_sig: Script::new(),
-						sequence: Sequence::ZERO,
+						sequence,
 						witness: Witness::new(),
 					});
 					witness_weight += 1 + 73 + 34;
@@ -337,6 +346,35 @@ impl SpendableOutputDescriptor {
 		};
 		Ok((psbt, expected_max_weight))
 	}
+
+	/// Selects a subset of the given descriptors sufficient to fund the given outputs at the given
+	/// feerate, preferring the fewest, largest descriptors, such that the remaining descriptors
+	/// can be spent separately or combined with other wallet inputs later.
+	///
+	/// Returns `Err(())` if all descriptors together are insufficient or if a descriptor was
+	/// duplicated. See [`Self::create_spendable_outputs_psbt`] to spend the selected descriptors.
+	pub fn select_spendable_outputs<'a>(descriptors: &[&'a SpendableOutputDescriptor], outputs: &[TxOut], feerate_sat_per_1000_weight: u32) -> Result<Vec<&'a SpendableOutputDescriptor>, ()> {
+		let mut candidates = descriptors.to_vec();
+		candidates.sort_unstable_by(|a, b| b.output_value().cmp(&a.output_value()));
+		// Assume the change, if any, will go to a P2WPKH output, which is the common case and
+		// thus what the feerate should account for.
+		let change_script = Script::new_v0_p2wpkh(&WPubkeyHash::all_zeros());
+		for selected_count in 1..=candidates.len() {
+			let selected = &candidates[..selected_count];
+			if Self::create_spendable_outputs_psbt(selected, outputs.to_vec(), change_script.clone(), feerate_sat_per_1000_weight, None, None).is_ok() {
+				return Ok(selected.to_vec());
+			}
+		}
+		Err(())
+	}
+
+	fn output_value(&self) -> u64 {
+		match self {
+			SpendableOutputDescriptor::StaticOutput { output, .. } => output.value,
+			SpendableOutputDescriptor::DelayedPaymentOutput(descriptor) => descriptor.output.value,
+			SpendableOutputDescriptor::StaticPaymentOutput(descriptor) => descriptor.output.value,
+		}
+	}
 }
 
 /// A trait to handle Lightning channel key material without concretizing the channel type or
@@ -574,7 +612,30 @@ pub trait EntropySource {
 	fn get_secure_random_bytes(&self) -> [u8; 32];
 }
 
+/// The result of a [`NodeSigner`] operation which may complete asynchronously, e.g. because the
+/// node secret lives in a remote HSM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NodeSignerStatus<T> {
+	/// The operation completed, either successfully or with an error.
+	Complete(Result<T, ()>),
+	/// The operation has been started but hasn't completed yet.
+	///
+	/// Once it completes, the signer must return its result from the next call with the same
+	/// parameters, and the `node_signer_unblocked` method of whichever LDK object requested the
+	/// operation must be called so that the operation is retried, i.e.
+	/// [`PeerManager::node_signer_unblocked`] or [`OnionMessenger::node_signer_unblocked`].
+	///
+	/// [`PeerManager::node_signer_unblocked`]: crate::ln::peer_handler::PeerManager::node_signer_unblocked
+	/// [`OnionMessenger::node_signer_unblocked`]: crate::onion_message::OnionMessenger::node_signer_unblocked
+	InProgress,
+}
+
 /// A trait that can handle cryptographic operations at the scope level of a node.
+///
+/// Signers which can't perform node-identity operations synchronously, e.g. because the node
+/// secret lives in a remote HSM, may override the `*_async` variants of some methods, which LDK
+/// uses where it is able to wait on the result. By default, these simply call the synchronous
+/// variants.
 pub trait NodeSigner {
 	/// Get secret key material as bytes for use in encrypting and decrypting inbound payment data.
 	///
@@ -628,6 +689,54 @@ pub trait NodeSigner {
 	/// message to be broadcast, as otherwise it may prevent one from receiving funds over the
 	/// corresponding channel.
 	fn sign_gossip_message(&self, msg: UnsignedGossipMessage) -> Result<Signature, ()>;
+
+	/// Sign an arbitrary message with our node secret, such that the signature may be verified
+	/// against our node id, e.g., to prove ownership of our node to a third party.
+	///
+	/// The signature must follow the format implemented by [`message_signing::sign`], which is
+	/// compatible with lnd's and Core Lightning's `signmessage` commands, and thus can be checked
+	/// with [`message_signing::verify`].
+	///
+	/// [`message_signing::sign`]: crate::util::message_signing::sign
+	/// [`message_signing::verify`]: crate::util::message_signing::verify
+	fn sign_message(&self, msg: &[u8]) -> Result<String, ()>;
+
+	/// Gets the ECDH shared secret as in [`Self::ecdh`], possibly asynchronously.
+	///
+	/// This is used by the [`PeerManager`] during the noise handshake and by the
+	/// [`OnionMessenger`] to decode inbound onion messages. Note that the [`PeerManager`]
+	/// disconnects peers which haven't completed the handshake within a call to
+	/// [`PeerManager::timer_tick_occurred`], so a pending derivation must complete before then.
+	///
+	/// [`PeerManager`]: crate::ln::peer_handler::PeerManager
+	/// [`PeerManager::timer_tick_occurred`]: crate::ln::peer_handler::PeerManager::timer_tick_occurred
+	/// [`OnionMessenger`]: crate::onion_message::OnionMessenger
+	fn ecdh_async(
+		&self, recipient: Recipient, other_key: &PublicKey, tweak: Option<&Scalar>
+	) -> NodeSignerStatus<SharedSecret> {
+		NodeSignerStatus::Complete(self.ecdh(recipient, other_key, tweak))
+	}
+
+	/// Signs an invoice as in [`Self::sign_invoice`], possibly asynchronously.
+	///
+	/// This is used by the invoice creation utilities in `lightning-invoice`, which return the
+	/// unsigned invoice while signing is in progress so that it can be signed once the signer
+	/// completes.
+	fn sign_invoice_async(
+		&self, hrp_bytes: &[u8], invoice_data: &[u5], recipient: Recipient
+	) -> NodeSignerStatus<RecoverableSignature> {
+		NodeSignerStatus::Complete(self.sign_invoice(hrp_bytes, invoice_data, recipient))
+	}
+
+	/// Signs a gossip message as in [`Self::sign_gossip_message`], possibly asynchronously.
+	///
+	/// This is used by the [`PeerManager`] to sign our `node_announcement`, which is broadcast
+	/// once the signer completes.
+	///
+	/// [`PeerManager`]: crate::ln::peer_handler::PeerManager
+	fn sign_gossip_message_async(&self, msg: UnsignedGossipMessage) -> NodeSignerStatus<Signature> {
+		NodeSignerStatus::Complete(self.sign_gossip_message(msg))
+	}
 }
 
 /// A trait that can return signer instances for individual channels.
@@ -1142,6 +1251,184 @@ impl<ES: Deref> ReadableArgs<ES> for InMemorySigner where ES::Target: EntropySou
 	}
 }
 
+/// An [`EcdsaChannelSigner`] holding only the key material needed to punish our counterparty for
+/// broadcasting a revoked commitment transaction, i.e. our revocation base key.
+///
+/// It is used by the [`ChannelMonitor`]s of a [`MonitorReplica`], which can thus act as a breach
+/// watcher without holding any keys which can spend our funds. All signing other than for justice
+/// transactions fails, thus such a [`ChannelMonitor`] never broadcasts our own commitment or HTLC
+/// transactions, nor claims HTLC outputs off of our counterparty's commitment transactions.
+///
+/// It must not be used by a [`ChannelManager`], as it does not know the channel's commitment
+/// seed and thus panics in [`ChannelSigner::get_per_commitment_point`] and
+/// [`ChannelSigner::release_commitment_secret`].
+///
+/// [`ChannelMonitor`]: crate::chain::channelmonitor::ChannelMonitor
+/// [`MonitorReplica`]: crate::chain::replica::MonitorReplica
+/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WatchOnlySigner {
+	revocation_base_key: SecretKey,
+	holder_channel_pubkeys: ChannelPublicKeys,
+	channel_parameters: Option<ChannelTransactionParameters>,
+	channel_value_satoshis: u64,
+	channel_keys_id: [u8; 32],
+}
+
+impl WatchOnlySigner {
+	/// Creates a [`WatchOnlySigner`] for the same channel as the given [`InMemorySigner`], keeping
+	/// only its revocation base key.
+	pub fn from_in_memory_signer(signer: &InMemorySigner) -> Self {
+		WatchOnlySigner {
+			revocation_base_key: signer.revocation_base_key,
+			holder_channel_pubkeys: signer.holder_channel_pubkeys.clone(),
+			channel_parameters: signer.channel_parameters.clone(),
+			channel_value_satoshis: signer.channel_value_satoshis,
+			channel_keys_id: signer.channel_keys_id,
+		}
+	}
+
+	fn get_channel_parameters(&self) -> &ChannelTransactionParameters {
+		self.channel_parameters.as_ref().unwrap()
+	}
+
+	fn counterparty_pubkeys(&self) -> &ChannelPublicKeys {
+		&self.get_channel_parameters().counterparty_parameters.as_ref().unwrap().pubkeys
+	}
+
+	fn holder_selected_contest_delay(&self) -> u16 {
+		self.get_channel_parameters().holder_selected_contest_delay
+	}
+
+	fn opt_anchors(&self) -> bool {
+		self.get_channel_parameters().opt_anchors.is_some()
+	}
+}
+
+impl ChannelSigner for WatchOnlySigner {
+	fn get_per_commitment_point(&self, _idx: u64, _secp_ctx: &Secp256k1<secp256k1::All>) -> PublicKey {
+		panic!("WatchOnlySigner does not know the commitment seed and may only be used by ChannelMonitors");
+	}
+
+	fn release_commitment_secret(&self, _idx: u64) -> [u8; 32] {
+		panic!("WatchOnlySigner does not know the commitment seed and may only be used by ChannelMonitors");
+	}
+
+	fn validate_holder_commitment(&self, _holder_tx: &HolderCommitmentTransaction, _preimages: Vec<PaymentPreimage>) -> Result<(), ()> {
+		Ok(())
+	}
+
+	fn pubkeys(&self) -> &ChannelPublicKeys { &self.holder_channel_pubkeys }
+
+	fn channel_keys_id(&self) -> [u8; 32] { self.channel_keys_id }
+
+	fn provide_channel_parameters(&mut self, channel_parameters: &ChannelTransactionParameters) {
+		assert!(self.channel_parameters.is_none() || self.channel_parameters.as_ref().unwrap() == channel_parameters);
+		if self.channel_parameters.is_some() {
+			// The channel parameters were already set and they match, return early.
+			return;
+		}
+		assert!(channel_parameters.is_populated(), "Channel parameters must be fully populated");
+		self.channel_parameters = Some(channel_parameters.clone());
+	}
+}
+
+impl EcdsaChannelSigner for WatchOnlySigner {
+	fn sign_counterparty_commitment(&self, _commitment_tx: &CommitmentTransaction, _preimages: Vec<PaymentPreimage>, _secp_ctx: &Secp256k1<secp256k1::All>) -> Result<(Signature, Vec<Signature>), ()> {
+		Err(())
+	}
+
+	fn validate_counterparty_revocation(&self, _idx: u64, _secret: &SecretKey) -> Result<(), ()> {
+		Ok(())
+	}
+
+	fn sign_holder_commitment_and_htlcs(&self, _commitment_tx: &HolderCommitmentTransaction, _secp_ctx: &Secp256k1<secp256k1::All>) -> Result<(Signature, Vec<Signature>), ()> {
+		Err(())
+	}
+
+	#[cfg(any(test,feature = "unsafe_revoked_tx_signing"))]
+	fn unsafe_sign_holder_commitment_and_htlcs(&self, _commitment_tx: &HolderCommitmentTransaction, _secp_ctx: &Secp256k1<secp256k1::All>) -> Result<(Signature, Vec<Signature>), ()> {
+		Err(())
+	}
+
+	fn sign_justice_revoked_output(&self, justice_tx: &Transaction, input: usize, amount: u64, per_commitment_key: &SecretKey, secp_ctx: &Secp256k1<secp256k1::All>) -> Result<Signature, ()> {
+		let revocation_key = chan_utils::derive_private_revocation_key(&secp_ctx, &per_commitment_key, &self.revocation_base_key);
+		let per_commitment_point = PublicKey::from_secret_key(secp_ctx, &per_commitment_key);
+		let revocation_pubkey = chan_utils::derive_public_revocation_key(&secp_ctx, &per_commitment_point, &self.pubkeys().revocation_basepoint);
+		let witness_script = {
+			let counterparty_delayedpubkey = chan_utils::derive_public_key(&secp_ctx, &per_commitment_point, &self.counterparty_pubkeys().delayed_payment_basepoint);
+			chan_utils::get_revokeable_redeemscript(&revocation_pubkey, self.holder_selected_contest_delay(), &counterparty_delayedpubkey)
+		};
+		let mut sighash_parts = sighash::SighashCache::new(justice_tx);
+		let sighash = hash_to_message!(&sighash_parts.segwit_signature_hash(input, &witness_script, amount, EcdsaSighashType::All).unwrap()[..]);
+		Ok(sign(secp_ctx, &sighash, &revocation_key))
+	}
+
+	fn sign_justice_revoked_htlc(&self, justice_tx: &Transaction, input: usize, amount: u64, per_commitment_key: &SecretKey, htlc: &HTLCOutputInCommitment, secp_ctx: &Secp256k1<secp256k1::All>) -> Result<Signature, ()> {
+		let revocation_key = chan_utils::derive_private_revocation_key(&secp_ctx, &per_commitment_key, &self.revocation_base_key);
+		let per_commitment_point = PublicKey::from_secret_key(secp_ctx, &per_commitment_key);
+		let revocation_pubkey = chan_utils::derive_public_revocation_key(&secp_ctx, &per_commitment_point, &self.pubkeys().revocation_basepoint);
+		let witness_script = {
+			let counterparty_htlcpubkey = chan_utils::derive_public_key(&secp_ctx, &per_commitment_point, &self.counterparty_pubkeys().htlc_basepoint);
+			let holder_htlcpubkey = chan_utils::derive_public_key(&secp_ctx, &per_commitment_point, &self.pubkeys().htlc_basepoint);
+			chan_utils::get_htlc_redeemscript_with_explicit_keys(&htlc, self.opt_anchors(), &counterparty_htlcpubkey, &holder_htlcpubkey, &revocation_pubkey)
+		};
+		let mut sighash_parts = sighash::SighashCache::new(justice_tx);
+		let sighash = hash_to_message!(&sighash_parts.segwit_signature_hash(input, &witness_script, amount, EcdsaSighashType::All).unwrap()[..]);
+		Ok(sign(secp_ctx, &sighash, &revocation_key))
+	}
+
+	#[cfg(anchors)]
+	fn sign_holder_htlc_transaction(
+		&self, _htlc_tx: &Transaction, _input: usize, _htlc_descriptor: &HTLCDescriptor,
+		_secp_ctx: &Secp256k1<secp256k1::All>
+	) -> Result<Signature, ()> {
+		Err(())
+	}
+
+	fn sign_counterparty_htlc_transaction(&self, _htlc_tx: &Transaction, _input: usize, _amount: u64, _per_commitment_point: &PublicKey, _htlc: &HTLCOutputInCommitment, _secp_ctx: &Secp256k1<secp256k1::All>) -> Result<Signature, ()> {
+		Err(())
+	}
+
+	fn sign_closing_transaction(&self, _closing_tx: &ClosingTransaction, _secp_ctx: &Secp256k1<secp256k1::All>) -> Result<Signature, ()> {
+		Err(())
+	}
+
+	fn sign_holder_anchor_input(
+		&self, _anchor_tx: &Transaction, _input: usize, _secp_ctx: &Secp256k1<secp256k1::All>,
+	) -> Result<Signature, ()> {
+		Err(())
+	}
+
+	fn sign_channel_announcement_with_funding_key(
+		&self, _msg: &UnsignedChannelAnnouncement, _secp_ctx: &Secp256k1<secp256k1::All>
+	) -> Result<Signature, ()> {
+		Err(())
+	}
+}
+
+impl WriteableEcdsaChannelSigner for WatchOnlySigner {}
+
+impl_writeable_tlv_based!(WatchOnlySigner, {
+	(0, revocation_base_key, required),
+	(2, holder_channel_pubkeys, required),
+	(4, channel_parameters, option),
+	(6, channel_value_satoshis, required),
+	(8, channel_keys_id, required),
+});
+
+/// A source of the [`WatchOnlySigner`]s for our channels, given to a [`ReplicatingWatch`] to ship
+/// them to a [`MonitorReplica`] along with each new [`ChannelMonitor`].
+///
+/// [`ReplicatingWatch`]: crate::chain::replica::ReplicatingWatch
+/// [`MonitorReplica`]: crate::chain::replica::MonitorReplica
+/// [`ChannelMonitor`]: crate::chain::channelmonitor::ChannelMonitor
+pub trait WatchOnlySignerSource {
+	/// Gets the [`WatchOnlySigner`] for the channel with the given value and
+	/// [`ChannelSigner::channel_keys_id`].
+	fn get_watch_only_signer(&self, channel_value_satoshis: u64, channel_keys_id: [u8; 32]) -> WatchOnlySigner;
+}
+
 /// Simple implementation of [`EntropySource`], [`NodeSigner`], and [`SignerProvider`] that takes a
 /// 32-byte seed for use as a BIP 32 extended key and derives keys from that.
 ///
@@ -1381,6 +1668,32 @@ impl KeysManager {
 		Ok(())
 	}
 
+	/// Creates a [`PartiallySignedTransaction`] which spends the given descriptors to the given
+	/// outputs, plus an output to the given change destination (if sufficient change value
+	/// remains), with all inputs signed and finalized. The transaction will have a feerate, at
+	/// least, of the given value.
+	///
+	/// Unlike [`Self::spend_spendable_outputs`], this allows the PSBT to be further processed,
+	/// e.g., to be passed to other signers or to have its signatures verified before broadcast. To
+	/// combine the descriptors with other wallet inputs instead, create the PSBT with
+	/// [`SpendableOutputDescriptor::create_spendable_outputs_psbt`], add the other inputs, and
+	/// sign with [`Self::sign_spendable_outputs_psbt`].
+	///
+	/// See [`SpendableOutputDescriptor::create_spendable_outputs_psbt`] for the `locktime` and
+	/// `sequence` arguments. Returns the PSBT and expected max transaction weight.
+	///
+	/// Returns `Err(())` if the output value is greater than the input value minus required fee,
+	/// if a descriptor was duplicated, or if an output descriptor `script_pubkey`
+	/// does not match the one we can spend.
+	///
+	/// May panic if the [`SpendableOutputDescriptor`]s were not generated by channels which used
+	/// this [`KeysManager`] or one of the [`InMemorySigner`] created by this [`KeysManager`].
+	pub fn spend_spendable_outputs_psbt<C: Signing>(&self, descriptors: &[&SpendableOutputDescriptor], outputs: Vec<TxOut>, change_destination_script: Script, feerate_sat_per_1000_weight: u32, locktime: Option<PackedLockTime>, sequence: Option<Sequence>, secp_ctx: &Secp256k1<C>) -> Result<(PartiallySignedTransaction, usize), ()> {
+		let (mut psbt, expected_max_weight) = SpendableOutputDescriptor::create_spendable_outputs_psbt(descriptors, outputs, change_destination_script, feerate_sat_per_1000_weight, locktime, sequence)?;
+		self.sign_spendable_outputs_psbt(descriptors, &mut psbt, secp_ctx)?;
+		Ok((psbt, expected_max_weight))
+	}
+
 	/// Creates a [`Transaction`] which spends the given descriptors to the given outputs, plus an
 	/// output to the given change destination (if sufficient change value remains). The
 	/// transaction will have a feerate, at least, of the given value.
@@ -1390,6 +1703,13 @@ impl KeysManager {
 	/// height to avoid fee sniping, unless you have some specific reason to use a different
 	/// locktime.
 	///
+	/// The `sequence` argument is used to set the sequence number of inputs which do not require a
+	/// relative timelock, e.g., to [`Sequence::ENABLE_RBF_NO_LOCKTIME`]. If `None`, such inputs
+	/// will have a sequence number of 0.
+	///
+	/// To only spend as many of the given descriptors as are required to fund the outputs, see
+	/// [`SpendableOutputDescriptor::select_spendable_outputs`].
+	///
 	/// Returns `Err(())` if the output value is greater than the input value minus required fee,
 	/// if a descriptor was duplicated, or if an output descriptor `script_pubkey`
 	/// does not match the one we can spend.
@@ -1398,9 +1718,8 @@ impl KeysManager {
 	///
 	/// May panic if the [`SpendableOutputDescriptor`]s were not generated by channels which used
 	/// this [`KeysManager`] or one of the [`InMemorySigner`] created by this [`KeysManager`].
-	pub fn spend_spendable_outputs<C: Signing>(&self, descriptors: &[&SpendableOutputDescriptor], outputs: Vec<TxOut>, change_destination_script: Script, feerate_sat_per_1000_weight: u32, locktime: Option<PackedLockTime>, secp_ctx: &Secp256k1<C>) -> Result<Transaction, ()> {
-		let (mut psbt, expected_max_weight) = SpendableOutputDescriptor::create_spendable_outputs_psbt(descriptors, outputs, change_destination_script, feerate_sat_per_1000_weight, locktime)?;
-		self.sign_spendable_outputs_psbt(descriptors, &mut psbt, secp_ctx)?;
+	pub fn spend_spendable_outputs<C: Signing>(&self, descriptors: &[&SpendableOutputDescriptor], outputs: Vec<TxOut>, change_destination_script: Script, feerate_sat_per_1000_weight: u32, locktime: Option<PackedLockTime>, sequence: Option<Sequence>, secp_ctx: &Secp256k1<C>) -> Result<Transaction, ()> {
+		let (psbt, expected_max_weight) = self.spend_spendable_outputs_psbt(descriptors, outputs, change_destination_script, feerate_sat_per_1000_weight, locktime, sequence, secp_ctx)?;
 
 		let spend_tx = psbt.extract_tx();
 
@@ -1458,6 +1777,10 @@ impl NodeSigner for KeysManager {
 		let msg_hash = hash_to_message!(&Sha256dHash::hash(&msg.encode()[..])[..]);
 		Ok(self.secp_ctx.sign_ecdsa(&msg_hash, &self.node_secret))
 	}
+
+	fn sign_message(&self, msg: &[u8]) -> Result<String, ()> {
+		message_signing::sign(msg, &self.node_secret).map_err(|_| ())
+	}
 }
 
 impl SignerProvider for KeysManager {
@@ -1496,6 +1819,12 @@ impl SignerProvider for KeysManager {
 	}
 }
 
+impl WatchOnlySignerSource for KeysManager {
+	fn get_watch_only_signer(&self, channel_value_satoshis: u64, channel_keys_id: [u8; 32]) -> WatchOnlySigner {
+		WatchOnlySigner::from_in_memory_signer(&self.derive_channel_keys(channel_value_satoshis, &channel_keys_id))
+	}
+}
+
 /// Similar to [`KeysManager`], but allows the node using this struct to receive phantom node
 /// payments.
 ///
@@ -1565,6 +1894,10 @@ impl NodeSigner for PhantomKeysManager {
 	fn sign_gossip_message(&self, msg: UnsignedGossipMessage) -> Result<Signature, ()> {
 		self.inner.sign_gossip_message(msg)
 	}
+
+	fn sign_message(&self, msg: &[u8]) -> Result<String, ()> {
+		self.inner.sign_message(msg)
+	}
 }
 
 impl SignerProvider for PhantomKeysManager {
@@ -1591,6 +1924,12 @@ impl SignerProvider for PhantomKeysManager {
 	}
 }
 
+impl WatchOnlySignerSource for PhantomKeysManager {
+	fn get_watch_only_signer(&self, channel_value_satoshis: u64, channel_keys_id: [u8; 32]) -> WatchOnlySigner {
+		WatchOnlySigner::from_in_memory_signer(&self.derive_channel_keys(channel_value_satoshis, &channel_keys_id))
+	}
+}
+
 impl PhantomKeysManager {
 	/// Constructs a [`PhantomKeysManager`] given a 32-byte seed and an additional `cross_node_seed`
 	/// that is shared across all nodes that intend to participate in [phantom node payments]
@@ -1617,8 +1956,13 @@ impl PhantomKeysManager {
 	}
 
 	/// See [`KeysManager::spend_spendable_outputs`] for documentation on this method.
-	pub fn spend_spendable_outputs<C: Signing>(&self, descriptors: &[&SpendableOutputDescriptor], outputs: Vec<TxOut>, change_destination_script: Script, feerate_sat_per_1000_weight: u32, locktime: Option<PackedLockTime>, secp_ctx: &Secp256k1<C>) -> Result<Transaction, ()> {
-		self.inner.spend_spendable_outputs(descriptors, outputs, change_destination_script, feerate_sat_per_1000_weight, locktime, secp_ctx)
+	pub fn spend_spendable_outputs<C: Signing>(&self, descriptors: &[&SpendableOutputDescriptor], outputs: Vec<TxOut>, change_destination_script: Script, feerate_sat_per_1000_weight: u32, locktime: Option<PackedLockTime>, sequence: Option<Sequence>, secp_ctx: &Secp256k1<C>) -> Result<Transaction, ()> {
+		self.inner.spend_spendable_outputs(descriptors, outputs, change_destination_script, feerate_sat_per_1000_weight, locktime, sequence, secp_ctx)
+	}
+
+	/// See [`KeysManager::spend_spendable_outputs_psbt`] for documentation on this method.
+	pub fn spend_spendable_outputs_psbt<C: Signing>(&self, descriptors: &[&SpendableOutputDescriptor], outputs: Vec<TxOut>, change_destination_script: Script, feerate_sat_per_1000_weight: u32, locktime: Option<PackedLockTime>, sequence: Option<Sequence>, secp_ctx: &Secp256k1<C>) -> Result<(PartiallySignedTransaction, usize), ()> {
+		self.inner.spend_spendable_outputs_psbt(descriptors, outputs, change_destination_script, feerate_sat_per_1000_weight, locktime, sequence, secp_ctx)
 	}
 
 	/// See [`KeysManager::derive_channel_keys`] for documentation on this method.
@@ -1638,6 +1982,196 @@ impl PhantomKeysManager {
 	}
 }
 
+/// The purpose for which a destination script is requested from a [`SweepDestinationProvider`].
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum SweepPurpose {
+	/// Our balance in a cooperative channel close. Requested when we (or our counterparty) begin
+	/// closing a channel, or when opening a channel if we commit to an upfront shutdown script.
+	CooperativeClose,
+	/// Our claims of contestable on-chain outputs, i.e. revoked outputs claimed by justice
+	/// transactions and HTLC outputs claimed from counterparty commitment transactions. Requested
+	/// once per channel when it is opened.
+	///
+	/// Unless the channel was opened before `option_static_remotekey` was negotiated, our balance
+	/// on counterparty commitment transactions is not claimed to this script. Instead it pays to a
+	/// key derived by the channel signer and is surfaced as a
+	/// [`SpendableOutputDescriptor::StaticPaymentOutput`].
+	OnChainClaim,
+	/// The sweep of [`SpendableOutputDescriptor`]s handed to us via [`Event::SpendableOutputs`].
+	/// Requested by [`SweepDestinationSignerProvider::get_spendable_outputs_destination_script`],
+	/// whose result should be passed as the change destination to
+	/// [`KeysManager::spend_spendable_outputs`].
+	///
+	/// [`Event::SpendableOutputs`]: crate::events::Event::SpendableOutputs
+	SpendableOutputs,
+}
+
+/// A source of the scripts to which LDK sends our on-chain funds, allowing them to be directed to
+/// different wallets depending on their [`SweepPurpose`], e.g. sending funds swept from
+/// cooperative closes to a hot wallet but claims of contestable outputs to cold storage.
+///
+/// Use a [`SweepDestinationSignerProvider`] to have the scripts returned here used in place of
+/// [`SignerProvider::get_destination_script`] and [`SignerProvider::get_shutdown_scriptpubkey`].
+pub trait SweepDestinationProvider {
+	/// Gets a script pubkey to send funds to for the given `purpose`.
+	///
+	/// This method should return a different value each time it is called, i.e. rotate addresses,
+	/// to avoid linking on-chain funds across channels as controlled to the same user.
+	///
+	/// Scripts requested for [`SweepPurpose::CooperativeClose`] must be segwit scripts as supported
+	/// by [`ShutdownScript`]. If this function returns an error, this will result in a channel
+	/// failing to open or close, see [`SignerProvider::get_destination_script`] and
+	/// [`SignerProvider::get_shutdown_scriptpubkey`].
+	fn get_sweep_destination_script(&self, purpose: SweepPurpose) -> Result<Script, ()>;
+}
+
+/// A [`SignerProvider`] which wraps another, but draws all destination scripts from a
+/// [`SweepDestinationProvider`] rather than from the wrapped [`SignerProvider`].
+pub struct SweepDestinationSignerProvider<SP: Deref, D: Deref>
+where SP::Target: SignerProvider, D::Target: SweepDestinationProvider {
+	signer_provider: SP,
+	destination_provider: D,
+}
+
+impl<SP: Deref, D: Deref> SweepDestinationSignerProvider<SP, D>
+where SP::Target: SignerProvider, D::Target: SweepDestinationProvider {
+	/// Constructs a [`SweepDestinationSignerProvider`] deriving channel signers from
+	/// `signer_provider` and drawing destination scripts from `destination_provider`.
+	pub fn new(signer_provider: SP, destination_provider: D) -> Self {
+		Self { signer_provider, destination_provider }
+	}
+
+	/// Gets a script pubkey to sweep the [`SpendableOutputDescriptor`]s handed to us via
+	/// [`Event::SpendableOutputs`] to, e.g. as the `change_destination_script` of
+	/// [`KeysManager::spend_spendable_outputs`].
+	///
+	/// [`Event::SpendableOutputs`]: crate::events::Event::SpendableOutputs
+	pub fn get_spendable_outputs_destination_script(&self) -> Result<Script, ()> {
+		self.destination_provider.get_sweep_destination_script(SweepPurpose::SpendableOutputs)
+	}
+}
+
+impl<SP: Deref, D: Deref> SignerProvider for SweepDestinationSignerProvider<SP, D>
+where SP::Target: SignerProvider, D::Target: SweepDestinationProvider {
+	type Signer = <SP::Target as SignerProvider>::Signer;
+
+	fn generate_channel_keys_id(&self, inbound: bool, channel_value_satoshis: u64, user_channel_id: u128) -> [u8; 32] {
+		self.signer_provider.generate_channel_keys_id(inbound, channel_value_satoshis, user_channel_id)
+	}
+
+	fn derive_channel_signer(&self, channel_value_satoshis: u64, channel_keys_id: [u8; 32]) -> Self::Signer {
+		self.signer_provider.derive_channel_signer(channel_value_satoshis, channel_keys_id)
+	}
+
+	fn read_chan_signer(&self, reader: &[u8]) -> Result<Self::Signer, DecodeError> {
+		self.signer_provider.read_chan_signer(reader)
+	}
+
+	fn get_destination_script(&self) -> Result<Script, ()> {
+		self.destination_provider.get_sweep_destination_script(SweepPurpose::OnChainClaim)
+	}
+
+	fn get_shutdown_scriptpubkey(&self) -> Result<ShutdownScript, ()> {
+		let script = self.destination_provider.get_sweep_destination_script(SweepPurpose::CooperativeClose)?;
+		ShutdownScript::try_from(script).map_err(|_| ())
+	}
+}
+
+/// An [`EntropySource`] which derives its output from a fixed seed rather than from a secure
+/// source of randomness.
+///
+/// By providing one to a [`ChannelManager`] and [`OnionMessenger`], and drawing the
+/// `random_seed_bytes` of a [`DefaultRouter`] from it, every random choice LDK makes (e.g.
+/// temporary channel ids, onion session keys, payment path and CLTV randomization) is replayed
+/// identically given the same seed and sequence of calls. As neither consults the wall clock,
+/// instead deriving time from the blocks and timer ticks they're given, their behavior is then
+/// fully reproducible. This is useful for integration tests and for reproducing bug reports.
+///
+/// This MUST NOT be used in production as its output is entirely predictable from its seed.
+///
+/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+/// [`OnionMessenger`]: crate::onion_message::OnionMessenger
+/// [`DefaultRouter`]: crate::routing::router::DefaultRouter
+#[cfg(any(test, feature = "_test_utils"))]
+pub struct DeterministicEntropySource {
+	seed: [u8; 32],
+	index: AtomicCounter,
+}
+
+#[cfg(any(test, feature = "_test_utils"))]
+impl DeterministicEntropySource {
+	/// Constructs a [`DeterministicEntropySource`] whose output is fully determined by `seed`.
+	pub fn new(seed: [u8; 32]) -> Self {
+		Self { seed, index: AtomicCounter::new() }
+	}
+}
+
+#[cfg(any(test, feature = "_test_utils"))]
+impl EntropySource for DeterministicEntropySource {
+	fn get_secure_random_bytes(&self) -> [u8; 32] {
+		let index = self.index.get_increment();
+		let mut engine = Sha256::engine();
+		engine.input(&self.seed);
+		engine.input(&index.to_be_bytes());
+		Sha256::from_engine(engine).into_inner()
+	}
+}
+
+#[test]
+fn deterministic_entropy_source_replays() {
+	let entropy_source = DeterministicEntropySource::new([42; 32]);
+	let first = entropy_source.get_secure_random_bytes();
+	let second = entropy_source.get_secure_random_bytes();
+	assert_ne!(first, second);
+
+	let replayed_source = DeterministicEntropySource::new([42; 32]);
+	assert_eq!(replayed_source.get_secure_random_bytes(), first);
+	assert_eq!(replayed_source.get_secure_random_bytes(), second);
+
+	let other_source = DeterministicEntropySource::new([43; 32]);
+	assert_ne!(other_source.get_secure_random_bytes(), first);
+}
+
+#[test]
+fn sweep_destination_signer_provider_uses_purpose_scripts() {
+	use crate::sync::Mutex;
+
+	struct TestDestinationProvider {
+		cold_storage_script: Script,
+		hot_wallet_scripts: Mutex<Vec<Script>>,
+	}
+	impl SweepDestinationProvider for TestDestinationProvider {
+		fn get_sweep_destination_script(&self, purpose: SweepPurpose) -> Result<Script, ()> {
+			match purpose {
+				SweepPurpose::OnChainClaim => Ok(self.cold_storage_script.clone()),
+				SweepPurpose::CooperativeClose | SweepPurpose::SpendableOutputs =>
+					self.hot_wallet_scripts.lock().unwrap().pop().ok_or(()),
+			}
+		}
+	}
+
+	let keys_manager = KeysManager::new(&[42; 32], 42, 42);
+	let cold_storage_script = Script::new_v0_p2wpkh(&WPubkeyHash::hash(&[1; 33]));
+	let first_hot_wallet_script = Script::new_v0_p2wpkh(&WPubkeyHash::hash(&[2; 33]));
+	let second_hot_wallet_script = Script::new_v0_p2wpkh(&WPubkeyHash::hash(&[3; 33]));
+	let destination_provider = TestDestinationProvider {
+		cold_storage_script: cold_storage_script.clone(),
+		hot_wallet_scripts: Mutex::new(vec![
+			Script::new(), second_hot_wallet_script.clone(), first_hot_wallet_script.clone(),
+		]),
+	};
+	let signer_provider = SweepDestinationSignerProvider::new(&keys_manager, &destination_provider);
+
+	assert_eq!(signer_provider.get_destination_script(), Ok(cold_storage_script));
+	assert_ne!(signer_provider.get_destination_script(), keys_manager.get_destination_script());
+
+	// Cooperative close scripts are rotated and must be valid shutdown scripts.
+	assert_eq!(signer_provider.get_shutdown_scriptpubkey().unwrap().into_inner(), first_hot_wallet_script);
+	assert_eq!(signer_provider.get_shutdown_scriptpubkey().unwrap().into_inner(), second_hot_wallet_script);
+	assert!(signer_provider.get_shutdown_scriptpubkey().is_err());
+	assert!(signer_provider.get_spendable_outputs_destination_script().is_err());
+}
+
 // Ensure that EcdsaChannelSigner can have a vtable
 #[test]
 pub fn dyn_sign() {
@@ -0,0 +1,99 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Unpadded RFC 4648 base32 encoding, as used for Tor onion service hostnames.
+
+use crate::prelude::*;
+
+const ALPHABET: &'static [u8] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+/// Encodes some bytes as a lowercase, unpadded RFC 4648 base32 string.
+pub(crate) fn encode(data: &[u8]) -> String {
+	let mut ret = Vec::with_capacity((data.len() * 8 + 4) / 5);
+	let mut buffer = 0u16;
+	let mut buffer_bits = 0;
+	for &b in data {
+		buffer = (buffer << 8) | b as u16;
+		buffer_bits += 8;
+		while buffer_bits >= 5 {
+			buffer_bits -= 5;
+			ret.push(ALPHABET[((buffer >> buffer_bits) & 0x1F) as usize]);
+		}
+	}
+	if buffer_bits > 0 {
+		ret.push(ALPHABET[((buffer << (5 - buffer_bits)) & 0x1F) as usize]);
+	}
+	String::from_utf8(ret).unwrap()
+}
+
+/// Decodes an unpadded RFC 4648 base32 string, ignoring case, to the original bytes, failing if
+/// the string was not encoded by a proper base32 encoder.
+pub(crate) fn decode(data: &str) -> Result<Vec<u8>, ()> {
+	let mut ret = Vec::with_capacity(data.len() * 5 / 8);
+	let mut buffer = 0u16;
+	let mut buffer_bits = 0;
+	for c in data.bytes() {
+		let value = match c.to_ascii_lowercase() {
+			c @ b'a'..=b'z' => c - b'a',
+			c @ b'2'..=b'7' => c - b'2' + 26,
+			_ => return Err(()),
+		};
+		buffer = (buffer << 5) | value as u16;
+		buffer_bits += 5;
+		if buffer_bits >= 8 {
+			buffer_bits -= 8;
+			ret.push((buffer >> buffer_bits) as u8);
+		}
+	}
+	// Any trailing bits must be zero padding, and there may not be a trailing character which
+	// does not carry any data.
+	if buffer_bits >= 5 || buffer & ((1 << buffer_bits) - 1) != 0 {
+		return Err(());
+	}
+	Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const TEST_DATA: &[(&str, &[u8])] = &[
+		("", b""),
+		("my", b"f"),
+		("mzxq", b"fo"),
+		("mzxw6", b"foo"),
+		("mzxw6yq", b"foob"),
+		("mzxw6ytb", b"fooba"),
+		("mzxw6ytboi", b"foobar"),
+	];
+
+	#[test]
+	fn test_encode() {
+		for &(base32, data) in TEST_DATA {
+			assert_eq!(encode(data), base32);
+		}
+	}
+
+	#[test]
+	fn test_decode() {
+		for &(base32, data) in TEST_DATA {
+			assert_eq!(decode(base32).unwrap(), data);
+			assert_eq!(decode(&base32.to_uppercase()).unwrap(), data);
+		}
+	}
+
+	#[test]
+	fn test_decode_wrong() {
+		const WRONG_DATA: &[&str] = &["m", "mz", "my======", "m1", "mzxw6yr"];
+
+		for &data in WRONG_DATA {
+			assert!(decode(data).is_err(), "{} shouldn't be decodable", data);
+		}
+	}
+}
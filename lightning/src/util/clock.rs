@@ -0,0 +1,165 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! A pluggable source of wall-clock time.
+//!
+//! Without `std`, [`std::time::SystemTime`] is not available and time-dependent logic, such as
+//! offer and invoice expiry checks or scorer decay, has no way to learn the current time. A
+//! [`TimeProvider`] allows embedded and WASM targets to supply a host clock instead.
+
+use crate::util::time::Time;
+
+use core::marker::PhantomData;
+use core::ops::Sub;
+use core::time::Duration;
+
+/// A source of the current wall-clock time.
+pub trait TimeProvider {
+	/// Returns the current time as a [`Duration`] since the UNIX epoch.
+	fn duration_since_epoch(&self) -> Duration;
+}
+
+/// A [`TimeProvider`] backed by [`std::time::SystemTime`].
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemTimeProvider;
+
+#[cfg(feature = "std")]
+impl TimeProvider for SystemTimeProvider {
+	fn duration_since_epoch(&self) -> Duration {
+		use std::time::SystemTime;
+		SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or(Duration::from_secs(0))
+	}
+}
+
+/// A measurement of time read from a [`TimeProvider`] of type `P`, which is instantiated via
+/// [`Default`] each time the current time is needed.
+///
+/// This allows using time-dependent logic which is generic over a measurement of time with a
+/// host-supplied clock, e.g. a [`ProbabilisticScorerUsingTime`] whose liquidity estimates decay
+/// without `std`:
+/// ```
+/// # use lightning::routing::gossip::NetworkGraph;
+/// # use lightning::routing::scoring::ProbabilisticScorerUsingTime;
+/// # use lightning::util::clock::{ProvidedTime, TimeProvider};
+/// # use lightning::util::logger::Logger;
+/// # use core::time::Duration;
+/// # fn host_seconds_since_epoch() -> u64 { 1_700_000_000 }
+/// #[derive(Default)]
+/// struct HostClock;
+///
+/// impl TimeProvider for HostClock {
+/// 	fn duration_since_epoch(&self) -> Duration {
+/// 		Duration::from_secs(host_seconds_since_epoch())
+/// 	}
+/// }
+///
+/// type Scorer<'a, L> = ProbabilisticScorerUsingTime<&'a NetworkGraph<L>, L, ProvidedTime<HostClock>>;
+/// ```
+///
+/// Note that, as with [`SystemTimeProvider`], time measured this way may go backwards, in which
+/// case no time is considered to have elapsed.
+///
+/// [`ProbabilisticScorerUsingTime`]: crate::routing::scoring::ProbabilisticScorerUsingTime
+pub struct ProvidedTime<P: TimeProvider + Default> {
+	duration_since_epoch: Duration,
+	_provider: PhantomData<P>,
+}
+
+impl<P: TimeProvider + Default> ProvidedTime<P> {
+	fn from_duration(duration_since_epoch: Duration) -> Self {
+		Self { duration_since_epoch, _provider: PhantomData }
+	}
+}
+
+impl<P: TimeProvider + Default> Clone for ProvidedTime<P> {
+	fn clone(&self) -> Self {
+		*self
+	}
+}
+
+impl<P: TimeProvider + Default> Copy for ProvidedTime<P> {}
+
+impl<P: TimeProvider + Default> PartialEq for ProvidedTime<P> {
+	fn eq(&self, other: &Self) -> bool {
+		self.duration_since_epoch == other.duration_since_epoch
+	}
+}
+
+impl<P: TimeProvider + Default> Eq for ProvidedTime<P> {}
+
+impl<P: TimeProvider + Default> core::fmt::Debug for ProvidedTime<P> {
+	fn fmt(&self, f: &mut core::fmt::Formatter) -> Result<(), core::fmt::Error> {
+		f.debug_tuple("ProvidedTime").field(&self.duration_since_epoch).finish()
+	}
+}
+
+impl<P: TimeProvider + Default> Time for ProvidedTime<P> {
+	fn now() -> Self {
+		Self::from_duration(Self::duration_since_epoch())
+	}
+
+	fn duration_since(&self, earlier: Self) -> Duration {
+		self.duration_since_epoch.checked_sub(earlier.duration_since_epoch).unwrap_or(Duration::from_secs(0))
+	}
+
+	fn duration_since_epoch() -> Duration {
+		P::default().duration_since_epoch()
+	}
+
+	fn elapsed(&self) -> Duration {
+		Self::now().duration_since(*self)
+	}
+}
+
+impl<P: TimeProvider + Default> Sub<Duration> for ProvidedTime<P> {
+	type Output = Self;
+
+	fn sub(self, other: Duration) -> Self {
+		Self::from_duration(self.duration_since_epoch.checked_sub(other).unwrap_or(Duration::from_secs(0)))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{ProvidedTime, TimeProvider};
+	use crate::util::time::Time;
+
+	use core::cell::Cell;
+	use core::time::Duration;
+
+	#[derive(Default)]
+	struct TestClock;
+
+	impl TestClock {
+		thread_local! {
+			static NOW: Cell<Duration> = Cell::new(Duration::from_secs(1_000));
+		}
+	}
+
+	impl TimeProvider for TestClock {
+		fn duration_since_epoch(&self) -> Duration {
+			Self::NOW.with(|now| now.get())
+		}
+	}
+
+	#[test]
+	fn provided_time_follows_provider() {
+		let start = ProvidedTime::<TestClock>::now();
+		assert_eq!(start.elapsed(), Duration::from_secs(0));
+
+		TestClock::NOW.with(|now| now.set(Duration::from_secs(1_010)));
+		assert_eq!(start.elapsed(), Duration::from_secs(10));
+		assert_eq!(ProvidedTime::<TestClock>::now() - Duration::from_secs(10), start);
+
+		// Time going backwards is treated as no time having passed.
+		TestClock::NOW.with(|now| now.set(Duration::from_secs(900)));
+		assert_eq!(start.elapsed(), Duration::from_secs(0));
+	}
+}
@@ -10,6 +10,8 @@
 //! Various user-configurable channel limits and settings which ChannelManager
 //! applies for you.
 
+use crate::chain::chaininterface::ConfirmationTarget;
+use crate::chain::channelmonitor::CLTV_CLAIM_BUFFER;
 use crate::ln::channel::MAX_FUNDING_SATOSHIS_NO_WUMBO;
 use crate::ln::channelmanager::{BREAKDOWN_TIMEOUT, MAX_LOCAL_BREAKDOWN_TIMEOUT};
 
@@ -149,6 +151,23 @@ pub struct ChannelHandshakeConfig {
 	/// Maximum value: 1,000,000, any values larger than 1 Million will be treated as 1 Million (or 100%)
 	///                instead, although channel negotiations will fail in that case.
 	pub their_channel_reserve_proportional_millionths: u32,
+	/// If set, we require our counterparty to keep no channel reserve at all (`option_zero_reserve`)
+	/// on channels we open or automatically accept, overriding
+	/// [`ChannelHandshakeConfig::their_channel_reserve_proportional_millionths`] and its lower bound.
+	///
+	/// This lets our counterparty spend its entire balance, which is useful for channels from an
+	/// LSP to mobile clients with tiny balances, but means it can broadcast a revoked state without
+	/// losing anything. Thus, this should only be set for counterparties trusted to have little to
+	/// gain from doing so. For inbound channels which are accepted manually, see
+	/// [`ChannelManager::accept_inbound_channel_from_trusted_peer_0reserve`] instead.
+	///
+	/// Note that counterparties which require our selected reserve to be no lower than their dust
+	/// limit will reject such channels.
+	///
+	/// Default value: false.
+	///
+	/// [`ChannelManager::accept_inbound_channel_from_trusted_peer_0reserve`]: crate::ln::channelmanager::ChannelManager::accept_inbound_channel_from_trusted_peer_0reserve
+	pub their_channel_reserve_zero: bool,
 	#[cfg(anchors)]
 	/// If set, we attempt to negotiate the `anchors_zero_fee_htlc_tx`option for outbound channels.
 	///
@@ -170,6 +189,26 @@ pub struct ChannelHandshakeConfig {
 	/// [`DecodeError::InvalidValue`]: crate::ln::msgs::DecodeError::InvalidValue
 	/// [`SIGHASH_SINGLE + update_fee Considered Harmful`]: https://lists.linuxfoundation.org/pipermail/lightning-dev/2020-September/002796.html
 	pub negotiate_anchors_zero_fee_htlc_tx: bool,
+	#[cfg(anchors)]
+	/// The on-chain funds, in satoshis, we require to be available for fee-bumping per anchor
+	/// channel before automatically accepting an inbound anchor channel.
+	///
+	/// Unlike other channels, the commitment and HTLC transactions of anchor channels pay little
+	/// to no fee and rely on us spending their anchor outputs with our own on-chain funds to get
+	/// them confirmed. If [`UserConfig::manually_accept_inbound_channels`] is not set and an
+	/// [`AnchorReserve`] was provided via [`ChannelManager::set_anchor_reserve`], we check that it
+	/// reports at least this amount per anchor channel we have, including the new one, and
+	/// otherwise fail the channel open with an error. Our counterparty may then downgrade the
+	/// channel, retrying without anchors, as LDK does.
+	///
+	/// Setting this to zero disables the check, accepting anchor channels irrespective of our
+	/// on-chain funds.
+	///
+	/// Default value: 10_000.
+	///
+	/// [`AnchorReserve`]: crate::events::bump_transaction::AnchorReserve
+	/// [`ChannelManager::set_anchor_reserve`]: crate::ln::channelmanager::ChannelManager::set_anchor_reserve
+	pub anchor_channel_reserve_sats: u64,
 
 	/// The maximum number of HTLCs in-flight from our counterparty towards us at the same time.
 	///
@@ -196,8 +235,11 @@ impl Default for ChannelHandshakeConfig {
 			announced_channel: false,
 			commit_upfront_shutdown_pubkey: true,
 			their_channel_reserve_proportional_millionths: 10_000,
+			their_channel_reserve_zero: false,
 			#[cfg(anchors)]
 			negotiate_anchors_zero_fee_htlc_tx: false,
+			#[cfg(anchors)]
+			anchor_channel_reserve_sats: 10_000,
 			our_max_accepted_htlcs: 50,
 		}
 	}
@@ -288,7 +330,23 @@ pub struct ChannelHandshakeLimits {
 	///
 	/// Default value: 2016, which we also enforce as a maximum value so you can tweak config to
 	/// reduce the loss of having useless locked funds (if your peer accepts)
-	pub their_to_self_delay: u16
+	pub their_to_self_delay: u16,
+	/// If this is set along with [`UserConfig::manually_accept_inbound_channels`], an inbound
+	/// channel which violates one of [`Self::min_funding_satoshis`],
+	/// [`Self::max_htlc_minimum_msat`], [`Self::min_max_htlc_value_in_flight_msat`],
+	/// [`Self::max_channel_reserve_satoshis`] or [`Self::min_max_accepted_htlcs`] is not rejected
+	/// outright. Instead, the violations are listed in [`Event::OpenChannelRequest::limit_violations`],
+	/// allowing you to accept the channel anyway, possibly with our own parameters adjusted via
+	/// [`ChannelManager::accept_inbound_channel_with_overrides`], or to reject it via
+	/// [`ChannelManager::reject_inbound_channel`], telling our counterparty which of its
+	/// parameters we found unacceptable.
+	///
+	/// Default value: false.
+	///
+	/// [`Event::OpenChannelRequest::limit_violations`]: crate::events::Event::OpenChannelRequest::limit_violations
+	/// [`ChannelManager::accept_inbound_channel_with_overrides`]: crate::ln::channelmanager::ChannelManager::accept_inbound_channel_with_overrides
+	/// [`ChannelManager::reject_inbound_channel`]: crate::ln::channelmanager::ChannelManager::reject_inbound_channel
+	pub negotiate_limit_violations: bool,
 }
 
 impl Default for ChannelHandshakeLimits {
@@ -304,10 +362,170 @@ impl Default for ChannelHandshakeLimits {
 			max_minimum_depth: 144,
 			force_announced_channel_preference: true,
 			their_to_self_delay: MAX_LOCAL_BREAKDOWN_TIMEOUT,
+			negotiate_limit_violations: false,
+		}
+	}
+}
+
+/// A parameter of an inbound `open_channel` which violates one of our [`ChannelHandshakeLimits`],
+/// as surfaced via [`Event::OpenChannelRequest::limit_violations`] if
+/// [`ChannelHandshakeLimits::negotiate_limit_violations`] is set.
+///
+/// Its [`Display`] implementation describes the violation in a form suitable to be sent to our
+/// counterparty when rejecting the channel.
+///
+/// [`Event::OpenChannelRequest::limit_violations`]: crate::events::Event::OpenChannelRequest::limit_violations
+/// [`Display`]: core::fmt::Display
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HandshakeLimitViolation {
+	/// The channel is funded with less than [`ChannelHandshakeLimits::min_funding_satoshis`].
+	FundingTooLow {
+		/// The funding amount our counterparty proposed.
+		funding_satoshis: u64,
+		/// Our configured [`ChannelHandshakeLimits::min_funding_satoshis`].
+		min_funding_satoshis: u64,
+	},
+	/// Our counterparty requires HTLCs we send to be larger than
+	/// [`ChannelHandshakeLimits::max_htlc_minimum_msat`].
+	HTLCMinimumTooHigh {
+		/// The `htlc_minimum_msat` our counterparty proposed.
+		htlc_minimum_msat: u64,
+		/// Our configured [`ChannelHandshakeLimits::max_htlc_minimum_msat`].
+		max_htlc_minimum_msat: u64,
+	},
+	/// Our counterparty limits the value of HTLCs pending to them to less than
+	/// [`ChannelHandshakeLimits::min_max_htlc_value_in_flight_msat`].
+	MaxHTLCValueInFlightTooLow {
+		/// The `max_htlc_value_in_flight_msat` our counterparty proposed.
+		max_htlc_value_in_flight_msat: u64,
+		/// Our configured [`ChannelHandshakeLimits::min_max_htlc_value_in_flight_msat`].
+		min_max_htlc_value_in_flight_msat: u64,
+	},
+	/// Our counterparty requires us to keep a channel reserve larger than
+	/// [`ChannelHandshakeLimits::max_channel_reserve_satoshis`].
+	ChannelReserveTooHigh {
+		/// The `channel_reserve_satoshis` our counterparty proposed.
+		channel_reserve_satoshis: u64,
+		/// Our configured [`ChannelHandshakeLimits::max_channel_reserve_satoshis`].
+		max_channel_reserve_satoshis: u64,
+	},
+	/// Our counterparty limits the number of HTLCs pending to them to less than
+	/// [`ChannelHandshakeLimits::min_max_accepted_htlcs`].
+	MaxAcceptedHTLCsTooLow {
+		/// The `max_accepted_htlcs` our counterparty proposed.
+		max_accepted_htlcs: u16,
+		/// Our configured [`ChannelHandshakeLimits::min_max_accepted_htlcs`].
+		min_max_accepted_htlcs: u16,
+	},
+}
+
+impl core::fmt::Display for HandshakeLimitViolation {
+	fn fmt(&self, f: &mut core::fmt::Formatter) -> Result<(), core::fmt::Error> {
+		match self {
+			HandshakeLimitViolation::FundingTooLow { funding_satoshis, min_funding_satoshis } =>
+				write!(f, "Funding satoshis ({}) is less than the user specified limit ({})", funding_satoshis, min_funding_satoshis),
+			HandshakeLimitViolation::HTLCMinimumTooHigh { htlc_minimum_msat, max_htlc_minimum_msat } =>
+				write!(f, "htlc_minimum_msat ({}) is higher than the user specified limit ({})", htlc_minimum_msat, max_htlc_minimum_msat),
+			HandshakeLimitViolation::MaxHTLCValueInFlightTooLow { max_htlc_value_in_flight_msat, min_max_htlc_value_in_flight_msat } =>
+				write!(f, "max_htlc_value_in_flight_msat ({}) is less than the user specified limit ({})", max_htlc_value_in_flight_msat, min_max_htlc_value_in_flight_msat),
+			HandshakeLimitViolation::ChannelReserveTooHigh { channel_reserve_satoshis, max_channel_reserve_satoshis } =>
+				write!(f, "channel_reserve_satoshis ({}) is higher than the user specified limit ({})", channel_reserve_satoshis, max_channel_reserve_satoshis),
+			HandshakeLimitViolation::MaxAcceptedHTLCsTooLow { max_accepted_htlcs, min_max_accepted_htlcs } =>
+				write!(f, "max_accepted_htlcs ({}) is less than the user specified limit ({})", max_accepted_htlcs, min_max_accepted_htlcs),
+		}
+	}
+}
+
+/// Overrides of the parameters we select for an inbound channel, which are otherwise derived
+/// from our [`ChannelHandshakeConfig`], applied when accepting it via
+/// [`ChannelManager::accept_inbound_channel_with_overrides`].
+///
+/// This allows answering an `open_channel` which we'd only find acceptable under other terms with
+/// a counter-proposal, e.g. requiring a larger channel reserve of our counterparty in turn for
+/// keeping the larger reserve it requires of us. Fields left as `None` are not overridden.
+///
+/// [`ChannelManager::accept_inbound_channel_with_overrides`]: crate::ln::channelmanager::ChannelManager::accept_inbound_channel_with_overrides
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct InboundChannelOverrides {
+	/// The channel reserve, in satoshis, our counterparty must keep, overriding
+	/// [`ChannelHandshakeConfig::their_channel_reserve_proportional_millionths`]. Must be at
+	/// least the dust limit of either side and below the channel value.
+	pub their_channel_reserve_satoshis: Option<u64>,
+	/// The smallest HTLC we accept, overriding [`ChannelHandshakeConfig::our_htlc_minimum_msat`].
+	pub our_htlc_minimum_msat: Option<u64>,
+	/// The maximum total value of HTLCs pending to us, overriding
+	/// [`ChannelHandshakeConfig::max_inbound_htlc_value_in_flight_percent_of_channel`]. This is
+	/// capped at the channel value.
+	pub max_inbound_htlc_value_in_flight_msat: Option<u64>,
+	/// The maximum number of HTLCs pending to us, overriding
+	/// [`ChannelHandshakeConfig::our_max_accepted_htlcs`]. Must be at least 1, and values above
+	/// the protocol maximum of 483 are capped.
+	pub our_max_accepted_htlcs: Option<u16>,
+}
+
+/// Selects how urgently the commitment transaction should confirm when a channel is force-closed,
+/// depending on why it was closed.
+///
+/// Only anchor channels are affected, as the commitment transaction of other channels pays the
+/// feerate negotiated with our counterparty and cannot be bumped. For anchor channels, the
+/// selected [`ConfirmationTarget`] is used to bump the commitment transaction via its anchor
+/// output with a `BumpTransactionEvent::ChannelClose`. The targets used to claim HTLC outputs once
+/// the commitment transaction confirms are not affected, as our funds are at risk then.
+///
+/// The selected target and the resulting feerates are surfaced in [`Event::ChannelClosed`].
+///
+/// [`Event::ChannelClosed`]: crate::events::Event::ChannelClosed
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ForceCloseFeePolicy {
+	/// The target used once one of the HTLCs in the commitment transaction is about to expire,
+	/// e.g., when we went on-chain to claim or time out an HTLC before our counterparty could.
+	///
+	/// Default value: [`ConfirmationTarget::OnChainSweep`]
+	pub htlc_expiry_target: ConfirmationTarget,
+	/// The target used when the commitment transaction contains HTLCs, none of which are about to
+	/// expire.
+	///
+	/// Default value: [`ConfirmationTarget::OnChainSweep`]
+	pub pending_htlcs_target: ConfirmationTarget,
+	/// The target used when the commitment transaction does not contain any HTLCs, e.g., when a
+	/// channel with an unresponsive peer is closed, in which case only our balance's timelock is
+	/// delayed by waiting.
+	///
+	/// Default value: [`ConfirmationTarget::AnchorChannelFee`]
+	pub no_pending_htlcs_target: ConfirmationTarget,
+}
+
+impl Default for ForceCloseFeePolicy {
+	fn default() -> Self {
+		Self {
+			htlc_expiry_target: ConfirmationTarget::OnChainSweep,
+			pending_htlcs_target: ConfirmationTarget::OnChainSweep,
+			no_pending_htlcs_target: ConfirmationTarget::AnchorChannelFee,
 		}
 	}
 }
 
+impl ForceCloseFeePolicy {
+	/// Returns the target for a commitment transaction containing HTLCs with the given CLTV
+	/// expiries at the given height.
+	pub(crate) fn confirmation_target<I: Iterator<Item = u32>>(&self, htlc_expiries: I, height: u32) -> ConfirmationTarget {
+		let mut has_htlcs = false;
+		for cltv_expiry in htlc_expiries {
+			if cltv_expiry <= height + CLTV_CLAIM_BUFFER {
+				return self.htlc_expiry_target;
+			}
+			has_htlcs = true;
+		}
+		if has_htlcs { self.pending_htlcs_target } else { self.no_pending_htlcs_target }
+	}
+}
+
+impl_writeable_tlv_based!(ForceCloseFeePolicy, {
+	(0, htlc_expiry_target, required),
+	(2, pending_htlcs_target, required),
+	(4, no_pending_htlcs_target, required),
+});
+
 /// Options which apply on a per-channel basis and may change at runtime or based on negotiation
 /// with our counterparty.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -382,21 +600,77 @@ pub struct ChannelConfig {
 	/// funder/initiator.
 	///
 	/// When we are the funder, because we have to pay the channel closing fee, we bound the
-	/// acceptable fee by our [`Background`] and [`Normal`] fees, with the upper bound increased by
+	/// acceptable fee by our [`ChannelCloseMinimum`] and [`Normal`] fees, with the upper bound increased by
 	/// this value. Because the on-chain fee we'd pay to force-close the channel is kept near our
 	/// [`Normal`] feerate during normal operation, this value represents the additional fee we're
 	/// willing to pay in order to avoid waiting for our counterparty's to_self_delay to reclaim our
 	/// funds.
 	///
 	/// When we are not the funder, we require the closing transaction fee pay at least our
-	/// [`Background`] fee estimate, but allow our counterparty to pay as much fee as they like.
+	/// [`ChannelCloseMinimum`] fee estimate, but allow our counterparty to pay as much fee as they like.
 	/// Thus, this value is ignored when we are not the funder.
 	///
 	/// Default value: 1000 satoshis.
 	///
 	/// [`Normal`]: crate::chain::chaininterface::ConfirmationTarget::Normal
-	/// [`Background`]: crate::chain::chaininterface::ConfirmationTarget::Background
+	/// [`ChannelCloseMinimum`]: crate::chain::chaininterface::ConfirmationTarget::ChannelCloseMinimum
 	pub force_close_avoidance_max_fee_satoshis: u64,
+	/// The minimum value, in millisatoshis, of HTLCs we will forward outbound over the channel.
+	///
+	/// This is advertised as the `htlc_minimum_msat` in our `channel_update` for the channel,
+	/// unless our counterparty requires a higher minimum for HTLCs we send it, in which case its
+	/// minimum is advertised and enforced instead. HTLCs we are asked to forward below this value
+	/// are failed back with `amount_below_minimum`.
+	///
+	/// Default value: 0, i.e. only our counterparty's minimum applies.
+	pub forwarding_htlc_minimum_msat: u64,
+	/// The maximum value, in millisatoshis, of HTLCs we will forward outbound over the channel.
+	///
+	/// This is advertised as the `htlc_maximum_msat` in our `channel_update` for the channel, if
+	/// it is below the maximum we would otherwise advertise based on the channel's capacity and our
+	/// counterparty's `max_htlc_value_in_flight_msat`. HTLCs we are asked to forward above this
+	/// value are failed back with `temporary_channel_failure`.
+	///
+	/// Default value: `u64::max_value()`, i.e. only the capacity-based maximum applies.
+	pub forwarding_htlc_maximum_msat: u64,
+	/// The multiple of the current commitment transaction fee we keep available, on channels where
+	/// we pay the fee, before sending or forwarding a new HTLC over the channel.
+	///
+	/// Keeping a multiple of the fee in reserve (the "fee-spike buffer") ensures we can still
+	/// afford the commitment transaction if feerates rise before the HTLC is resolved. Forwards
+	/// which are rejected only because of this buffer are surfaced as
+	/// [`Event::HTLCRejectedForFeeSpikeBuffer`]. Lowering this lets more of our balance be used
+	/// for HTLCs at the cost of a higher risk of being unable to keep up with a feerate increase.
+	///
+	/// Values below 1 are treated as 1, i.e. no buffer beyond the current fee.
+	///
+	/// Default value: 2.
+	///
+	/// [`Event::HTLCRejectedForFeeSpikeBuffer`]: crate::events::Event::HTLCRejectedForFeeSpikeBuffer
+	pub fee_spike_buffer_multiple: u64,
+	/// How urgently the commitment transaction should confirm if the channel is force-closed. See
+	/// [`ForceCloseFeePolicy`] for details.
+	///
+	/// Default value: [`ForceCloseFeePolicy::default`].
+	pub force_close_fee_policy: ForceCloseFeePolicy,
+	/// The base fee, in millisatoshis, charged for HTLCs we relay over the channel as an
+	/// intermediate hop within a blinded path, overriding [`Self::forwarding_fee_base_msat`] for
+	/// such HTLCs.
+	///
+	/// The fees of blinded hops are set by the recipient when building its path, usually from the
+	/// fees we advertise in our `channel_update`. HTLCs which do not pay the fee required here are
+	/// failed back with `invalid_onion_blinding`.
+	///
+	/// Default value: None, i.e. [`Self::forwarding_fee_base_msat`] applies.
+	pub blinded_forwarding_fee_base_msat: Option<u32>,
+	/// The proportional fee, in millionths of a satoshi, charged for HTLCs we relay over the
+	/// channel as an intermediate hop within a blinded path, overriding
+	/// [`Self::forwarding_fee_proportional_millionths`] for such HTLCs.
+	///
+	/// See [`Self::blinded_forwarding_fee_base_msat`] for more details.
+	///
+	/// Default value: None, i.e. [`Self::forwarding_fee_proportional_millionths`] applies.
+	pub blinded_forwarding_fee_proportional_millionths: Option<u32>,
 }
 
 impl ChannelConfig {
@@ -417,6 +691,24 @@ impl ChannelConfig {
 		if let Some(force_close_avoidance_max_fee_satoshis) = update.force_close_avoidance_max_fee_satoshis {
 			self.force_close_avoidance_max_fee_satoshis = force_close_avoidance_max_fee_satoshis;
 		}
+		if let Some(forwarding_htlc_minimum_msat) = update.forwarding_htlc_minimum_msat {
+			self.forwarding_htlc_minimum_msat = forwarding_htlc_minimum_msat;
+		}
+		if let Some(forwarding_htlc_maximum_msat) = update.forwarding_htlc_maximum_msat {
+			self.forwarding_htlc_maximum_msat = forwarding_htlc_maximum_msat;
+		}
+		if let Some(fee_spike_buffer_multiple) = update.fee_spike_buffer_multiple {
+			self.fee_spike_buffer_multiple = fee_spike_buffer_multiple;
+		}
+		if let Some(force_close_fee_policy) = update.force_close_fee_policy {
+			self.force_close_fee_policy = force_close_fee_policy;
+		}
+		if let Some(blinded_forwarding_fee_base_msat) = update.blinded_forwarding_fee_base_msat {
+			self.blinded_forwarding_fee_base_msat = blinded_forwarding_fee_base_msat;
+		}
+		if let Some(blinded_forwarding_fee_proportional_millionths) = update.blinded_forwarding_fee_proportional_millionths {
+			self.blinded_forwarding_fee_proportional_millionths = blinded_forwarding_fee_proportional_millionths;
+		}
 	}
 }
 
@@ -429,6 +721,12 @@ impl Default for ChannelConfig {
 			cltv_expiry_delta: 6 * 12, // 6 blocks/hour * 12 hours
 			max_dust_htlc_exposure_msat: 5_000_000,
 			force_close_avoidance_max_fee_satoshis: 1000,
+			forwarding_htlc_minimum_msat: 0,
+			forwarding_htlc_maximum_msat: u64::max_value(),
+			fee_spike_buffer_multiple: 2,
+			force_close_fee_policy: ForceCloseFeePolicy::default(),
+			blinded_forwarding_fee_base_msat: None,
+			blinded_forwarding_fee_proportional_millionths: None,
 		}
 	}
 }
@@ -442,6 +740,12 @@ impl_writeable_tlv_based!(ChannelConfig, {
 	// LegacyChannelConfig. To make sure that serialization is not compatible with this one, we use
 	// the next required type of 10, which if seen by the old serialization will always fail.
 	(10, force_close_avoidance_max_fee_satoshis, required),
+	(11, forwarding_htlc_minimum_msat, (default_value, 0u64)),
+	(13, forwarding_htlc_maximum_msat, (default_value, u64::max_value())),
+	(15, fee_spike_buffer_multiple, (default_value, 2u64)),
+	(17, force_close_fee_policy, (default_value, ForceCloseFeePolicy::default())),
+	(19, blinded_forwarding_fee_base_msat, option),
+	(21, blinded_forwarding_fee_proportional_millionths, option),
 });
 
 /// A parallel struct to [`ChannelConfig`] to define partial updates.
@@ -452,6 +756,14 @@ pub struct ChannelConfigUpdate {
 	pub cltv_expiry_delta: Option<u16>,
 	pub max_dust_htlc_exposure_msat: Option<u64>,
 	pub force_close_avoidance_max_fee_satoshis: Option<u64>,
+	pub forwarding_htlc_minimum_msat: Option<u64>,
+	pub forwarding_htlc_maximum_msat: Option<u64>,
+	pub fee_spike_buffer_multiple: Option<u64>,
+	pub force_close_fee_policy: Option<ForceCloseFeePolicy>,
+	/// Set to `Some(None)` to clear the override.
+	pub blinded_forwarding_fee_base_msat: Option<Option<u32>>,
+	/// Set to `Some(None)` to clear the override.
+	pub blinded_forwarding_fee_proportional_millionths: Option<Option<u32>>,
 }
 
 impl Default for ChannelConfigUpdate {
@@ -462,6 +774,12 @@ impl Default for ChannelConfigUpdate {
 			cltv_expiry_delta: None,
 			max_dust_htlc_exposure_msat: None,
 			force_close_avoidance_max_fee_satoshis: None,
+			forwarding_htlc_minimum_msat: None,
+			forwarding_htlc_maximum_msat: None,
+			fee_spike_buffer_multiple: None,
+			force_close_fee_policy: None,
+			blinded_forwarding_fee_base_msat: None,
+			blinded_forwarding_fee_proportional_millionths: None,
 		}
 	}
 }
@@ -474,6 +792,12 @@ impl From<ChannelConfig> for ChannelConfigUpdate {
 			cltv_expiry_delta: Some(config.cltv_expiry_delta),
 			max_dust_htlc_exposure_msat: Some(config.max_dust_htlc_exposure_msat),
 			force_close_avoidance_max_fee_satoshis: Some(config.force_close_avoidance_max_fee_satoshis),
+			forwarding_htlc_minimum_msat: Some(config.forwarding_htlc_minimum_msat),
+			forwarding_htlc_maximum_msat: Some(config.forwarding_htlc_maximum_msat),
+			fee_spike_buffer_multiple: Some(config.fee_spike_buffer_multiple),
+			force_close_fee_policy: Some(config.force_close_fee_policy),
+			blinded_forwarding_fee_base_msat: Some(config.blinded_forwarding_fee_base_msat),
+			blinded_forwarding_fee_proportional_millionths: Some(config.blinded_forwarding_fee_proportional_millionths),
 		}
 	}
 }
@@ -513,6 +837,12 @@ impl crate::util::ser::Writeable for LegacyChannelConfig {
 			(4, self.announced_channel, required),
 			(6, self.commit_upfront_shutdown_pubkey, required),
 			(8, self.options.forwarding_fee_base_msat, required),
+			(11, self.options.forwarding_htlc_minimum_msat, (default_value, 0u64)),
+			(13, self.options.forwarding_htlc_maximum_msat, (default_value, u64::max_value())),
+			(15, self.options.fee_spike_buffer_multiple, (default_value, 2u64)),
+			(17, self.options.force_close_fee_policy, (default_value, ForceCloseFeePolicy::default())),
+			(19, self.options.blinded_forwarding_fee_base_msat, option),
+			(21, self.options.blinded_forwarding_fee_proportional_millionths, option),
 		});
 		Ok(())
 	}
@@ -527,6 +857,12 @@ impl crate::util::ser::Readable for LegacyChannelConfig {
 		let mut announced_channel = false;
 		let mut commit_upfront_shutdown_pubkey = false;
 		let mut forwarding_fee_base_msat = 0;
+		let mut forwarding_htlc_minimum_msat = 0;
+		let mut forwarding_htlc_maximum_msat = u64::max_value();
+		let mut fee_spike_buffer_multiple = 2;
+		let mut force_close_fee_policy = ForceCloseFeePolicy::default();
+		let mut blinded_forwarding_fee_base_msat = None;
+		let mut blinded_forwarding_fee_proportional_millionths = None;
 		read_tlv_fields!(reader, {
 			(0, forwarding_fee_proportional_millionths, required),
 			(1, max_dust_htlc_exposure_msat, (default_value, 5_000_000u64)),
@@ -535,6 +871,12 @@ impl crate::util::ser::Readable for LegacyChannelConfig {
 			(4, announced_channel, required),
 			(6, commit_upfront_shutdown_pubkey, required),
 			(8, forwarding_fee_base_msat, required),
+			(11, forwarding_htlc_minimum_msat, (default_value, 0u64)),
+			(13, forwarding_htlc_maximum_msat, (default_value, u64::max_value())),
+			(15, fee_spike_buffer_multiple, (default_value, 2u64)),
+			(17, force_close_fee_policy, (default_value, ForceCloseFeePolicy::default())),
+			(19, blinded_forwarding_fee_base_msat, option),
+			(21, blinded_forwarding_fee_proportional_millionths, option),
 		});
 		Ok(Self {
 			options: ChannelConfig {
@@ -543,6 +885,12 @@ impl crate::util::ser::Readable for LegacyChannelConfig {
 				cltv_expiry_delta,
 				force_close_avoidance_max_fee_satoshis,
 				forwarding_fee_base_msat,
+				forwarding_htlc_minimum_msat,
+				forwarding_htlc_maximum_msat,
+				fee_spike_buffer_multiple,
+				force_close_fee_policy,
+				blinded_forwarding_fee_base_msat,
+				blinded_forwarding_fee_proportional_millionths,
 			},
 			announced_channel,
 			commit_upfront_shutdown_pubkey,
@@ -550,6 +898,719 @@ impl crate::util::ser::Readable for LegacyChannelConfig {
 	}
 }
 
+/// Limits on the resources our peers may consume, protecting against denial-of-service attacks.
+///
+/// This includes limits on the amount of memory LDK will use to buffer messages and HTLCs which
+/// are awaiting processing or delivery, on the number of HTLCs and unfunded channels any one peer
+/// may have pending with us, and on how long we wait for inbound channels to be funded. Operators
+/// of public routing nodes may want to tighten or relax these depending on their resources.
+///
+/// When a limit is hit, LDK degrades gracefully rather than continuing to grow its buffers:
+/// HTLCs are failed back, gossip broadcasts are skipped, onion messages are refused and new
+/// inbound channels or connections are rejected. The number
+/// of times each limit has been hit is exposed by the relevant object, e.g. via
+/// [`ChannelManager::pending_forward_limit_hits`], [`PeerManager::gossip_buffer_limit_hits`] and
+/// [`OnionMessenger::onion_message_buffer_limit_hits`].
+///
+/// [`ChannelManager::pending_forward_limit_hits`]: crate::ln::channelmanager::ChannelManager::pending_forward_limit_hits
+/// [`PeerManager::gossip_buffer_limit_hits`]: crate::ln::peer_handler::PeerManager::gossip_buffer_limit_hits
+/// [`OnionMessenger::onion_message_buffer_limit_hits`]: crate::onion_message::OnionMessenger::onion_message_buffer_limit_hits
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ResourceLimitsConfig {
+	/// The maximum number of HTLCs which may be buffered awaiting forwarding across all channels.
+	///
+	/// Once this many HTLCs are pending forwarding, further HTLCs which we are asked to forward
+	/// will be failed back with a `temporary_node_failure` until the buffer has been drained via
+	/// [`ChannelManager::process_pending_htlc_forwards`]. HTLCs which are to be received by us are
+	/// never subject to this limit.
+	///
+	/// Default value: 10,000
+	///
+	/// [`ChannelManager::process_pending_htlc_forwards`]: crate::ln::channelmanager::ChannelManager::process_pending_htlc_forwards
+	pub max_pending_forward_htlcs: usize,
+	/// The maximum number of messages which may be buffered awaiting delivery to a single peer
+	/// before we stop relaying gossip broadcasts to it.
+	///
+	/// Default value: 24
+	pub max_outbound_gossip_buffer_messages: usize,
+	/// The maximum total size, in bytes, of onion messages buffered awaiting delivery across all
+	/// peers.
+	///
+	/// Default value: 128 MiB
+	pub max_onion_message_buffer_bytes: usize,
+	/// The maximum size, in bytes, of onion messages buffered awaiting delivery to any one peer.
+	///
+	/// Default value: 256 KiB
+	pub max_onion_message_buffer_bytes_per_peer: usize,
+	/// The maximum number of inbound HTLCs which may be pending across all channels with any one
+	/// peer.
+	///
+	/// Once a peer has this many HTLCs pending with us, further HTLCs which it asks us to forward
+	/// will be failed back with a `temporary_channel_failure`. HTLCs which are to be received by us
+	/// are never subject to this limit. The number of HTLCs pending on each individual channel is
+	/// separately limited by [`ChannelHandshakeConfig::our_max_accepted_htlcs`].
+	///
+	/// Default value: 1,000
+	pub max_pending_inbound_htlcs_per_peer: usize,
+	/// The maximum total value, in millisatoshis, of the inbound HTLCs which may be pending across
+	/// all channels with any one peer.
+	///
+	/// HTLCs which a peer asks us to forward and which would take the value it has pending with us
+	/// over this limit will be failed back with a `temporary_channel_failure`, preventing a single
+	/// counterparty from locking up the liquidity of all of our channels at once. HTLCs which are
+	/// to be received by us are never subject to this limit.
+	///
+	/// Default value: `u64::max_value()` (no limit)
+	pub max_pending_inbound_htlc_value_msat_per_peer: u64,
+	/// The maximum number of forwards of [`HtlcPriority::Low`], i.e. forwards which our
+	/// [`ForwardingPolicy`] does not consider endorsed, which may be pending across all of our
+	/// channels and peers at once.
+	///
+	/// Once this many low-priority forwards are pending, further ones are failed back with a
+	/// `temporary_channel_failure`, keeping HTLC slots and liquidity available for high-priority
+	/// traffic even if many peers jam us at once. Forwards pending prior to a restart do not count
+	/// towards this limit.
+	///
+	/// Default value: `usize::max_value()` (no limit)
+	///
+	/// [`HtlcPriority::Low`]: crate::ln::channelmanager::HtlcPriority::Low
+	/// [`ForwardingPolicy`]: crate::ln::channelmanager::ForwardingPolicy
+	pub max_pending_low_priority_forwards: usize,
+	/// The maximum number of unfunded inbound channels we will allow any one peer to have open
+	/// with us at once before we start rejecting new ones.
+	///
+	/// Default value: 4
+	pub max_unfunded_channels_per_peer: usize,
+	/// The maximum number of peers from which we will allow pending unfunded channels. Once we
+	/// reach this many peers we reject new inbound channels from peers with which we don't have a
+	/// channel, unless [`UserConfig::manually_accept_inbound_channels`] is set.
+	///
+	/// Default value: 50
+	pub max_unfunded_channel_peers: usize,
+	/// The maximum number of connected peers with which we do not have a funded channel. Once we
+	/// reach this many peers we reject new inbound connections from peers with which we don't
+	/// have a funded channel.
+	///
+	/// Default value: 250
+	pub max_no_channel_peers: usize,
+	/// The number of blocks after which an inbound channel whose funding transaction has not
+	/// confirmed will be closed with [`ClosureReason::FundingTimedOut`].
+	///
+	/// Default value: 2016, as suggested by BOLT 2
+	///
+	/// [`ClosureReason::FundingTimedOut`]: crate::events::ClosureReason::FundingTimedOut
+	pub funding_confirmation_deadline_blocks: u32,
+}
+
+impl Default for ResourceLimitsConfig {
+	fn default() -> Self {
+		ResourceLimitsConfig {
+			max_pending_forward_htlcs: 10_000,
+			max_outbound_gossip_buffer_messages: crate::ln::peer_handler::OUTBOUND_BUFFER_LIMIT_DROP_GOSSIP,
+			max_onion_message_buffer_bytes: (1 << 20) * 128,
+			max_onion_message_buffer_bytes_per_peer: (1 << 10) * 256,
+			max_pending_inbound_htlcs_per_peer: 1_000,
+			max_pending_inbound_htlc_value_msat_per_peer: u64::max_value(),
+			max_pending_low_priority_forwards: usize::max_value(),
+			max_unfunded_channels_per_peer: crate::ln::channelmanager::MAX_UNFUNDED_CHANS_PER_PEER,
+			max_unfunded_channel_peers: crate::ln::channelmanager::MAX_UNFUNDED_CHANNEL_PEERS,
+			max_no_channel_peers: crate::ln::channelmanager::MAX_NO_CHANNEL_PEERS,
+			funding_confirmation_deadline_blocks: crate::ln::channel::FUNDING_CONF_DEADLINE_BLOCKS,
+		}
+	}
+}
+
+/// Settings for the pings [`PeerManager`] sends to detect stalled connections.
+///
+/// A ping is sent to each peer every [`Self::ping_interval_ticks`], with peers which do not
+/// respond in time being disconnected. Peers' latest ping round-trip times are exposed via
+/// [`PeerManager::peer_by_node_id`].
+///
+/// [`PeerManager`]: crate::ln::peer_handler::PeerManager
+/// [`PeerManager::peer_by_node_id`]: crate::ln::peer_handler::PeerManager::peer_by_node_id
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PeerKeepaliveConfig {
+	/// The number of calls to [`PeerManager::timer_tick_occurred`] between two pings sent to a
+	/// peer.
+	///
+	/// Default value: 1
+	///
+	/// [`PeerManager::timer_tick_occurred`]: crate::ln::peer_handler::PeerManager::timer_tick_occurred
+	pub ping_interval_ticks: u32,
+	/// The number of calls to [`PeerManager::timer_tick_occurred`], per connected peer, after
+	/// which a peer which has not yet responded to our ping is disconnected.
+	///
+	/// Note that a peer is disconnected after a single timer tick if it does not send us any
+	/// message at all while we are awaiting its response, as we're then likely disconnected.
+	///
+	/// Default value: 4
+	///
+	/// [`PeerManager::timer_tick_occurred`]: crate::ln::peer_handler::PeerManager::timer_tick_occurred
+	pub max_pong_wait_ticks_per_peer: u32,
+}
+
+impl Default for PeerKeepaliveConfig {
+	fn default() -> Self {
+		PeerKeepaliveConfig {
+			ping_interval_ticks: 1,
+			max_pong_wait_ticks_per_peer: crate::ln::peer_handler::MAX_BUFFER_DRAIN_TICK_INTERVALS_PER_PEER,
+		}
+	}
+}
+
+/// Limits on new inbound connections accepted by [`PeerManager`], allowing public nodes to
+/// withstand connection floods without an external firewall.
+///
+/// All windows and cooldowns are measured in calls to [`PeerManager::timer_tick_occurred`].
+/// Connections refused due to these limits are counted in
+/// [`PeerManager::inbound_connection_limit_hits`].
+///
+/// These limits are only applied once set via [`PeerManager::with_inbound_connection_limits`].
+///
+/// [`PeerManager`]: crate::ln::peer_handler::PeerManager
+/// [`PeerManager::timer_tick_occurred`]: crate::ln::peer_handler::PeerManager::timer_tick_occurred
+/// [`PeerManager::inbound_connection_limit_hits`]: crate::ln::peer_handler::PeerManager::inbound_connection_limit_hits
+/// [`PeerManager::with_inbound_connection_limits`]: crate::ln::peer_handler::PeerManager::with_inbound_connection_limits
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct InboundConnectionLimitConfig {
+	/// The maximum number of new inbound connections we accept from a single address (ignoring
+	/// the port) within each window of [`Self::handshake_window_ticks`].
+	///
+	/// Connections for which no remote address was provided are not subject to this limit.
+	///
+	/// Default value: 8
+	pub max_handshakes_per_address_per_window: u32,
+	/// The number of timer ticks over which [`Self::max_handshakes_per_address_per_window`] and
+	/// [`Self::max_failed_handshakes_per_address`] are counted.
+	///
+	/// Default value: 6 (one minute given the recommended ten second tick interval)
+	pub handshake_window_ticks: u32,
+	/// The maximum number of inbound connections which may be working through the noise
+	/// handshake at once, across all addresses.
+	///
+	/// Default value: 128
+	pub max_concurrent_inbound_handshakes: usize,
+	/// The number of inbound connections from a single address which may fail the noise
+	/// handshake within a window before we refuse further connections from it for
+	/// [`Self::failed_handshake_cooldown_ticks`].
+	///
+	/// A connection which is closed or times out before completing the handshake counts as a
+	/// failure.
+	///
+	/// Default value: 3
+	pub max_failed_handshakes_per_address: u32,
+	/// The number of timer ticks for which we refuse connections from an address once it has
+	/// failed [`Self::max_failed_handshakes_per_address`] handshakes.
+	///
+	/// Default value: 30 (five minutes given the recommended ten second tick interval)
+	pub failed_handshake_cooldown_ticks: u32,
+}
+
+impl Default for InboundConnectionLimitConfig {
+	fn default() -> Self {
+		InboundConnectionLimitConfig {
+			max_handshakes_per_address_per_window: 8,
+			handshake_window_ticks: 6,
+			max_concurrent_inbound_handshakes: 128,
+			max_failed_handshakes_per_address: 3,
+			failed_handshake_cooldown_ticks: 30,
+		}
+	}
+}
+
+/// Limits on the messages passed to a [`WireMessageObserver`], bounding the overhead of observing
+/// the messages exchanged with our peers.
+///
+/// [`WireMessageObserver`]: crate::ln::peer_handler::WireMessageObserver
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct WireMessageObserverConfig {
+	/// Only one in every `sample_interval` messages is observed. A value of zero is treated as
+	/// one.
+	///
+	/// Default value: 1, i.e., all messages are observed.
+	pub sample_interval: u32,
+	/// The maximum number of messages observed between two calls to
+	/// [`PeerManager::timer_tick_occurred`], after which further messages are skipped until the
+	/// next call.
+	///
+	/// Default value: 10_000
+	///
+	/// [`PeerManager::timer_tick_occurred`]: crate::ln::peer_handler::PeerManager::timer_tick_occurred
+	pub max_messages_per_tick: u32,
+	/// Whether gossip messages, which usually make up the vast majority of messages exchanged, are
+	/// observed.
+	///
+	/// Default value: false
+	pub observe_gossip: bool,
+}
+
+impl Default for WireMessageObserverConfig {
+	fn default() -> Self {
+		WireMessageObserverConfig {
+			sample_interval: 1,
+			max_messages_per_tick: 10_000,
+			observe_gossip: false,
+		}
+	}
+}
+
+/// A policy for detecting channels which have become stale, i.e. whose counterparty has been
+/// unreachable or which have not been used for a long time, and optionally force-closing them to
+/// reclaim the liquidity locked in them.
+///
+/// Staleness is evaluated on each call to [`ChannelManager::timer_tick_occurred`], with time
+/// measured using the timestamps of the blocks connected to the [`ChannelManager`]. When a channel
+/// first becomes stale an [`Event::ChannelStale`] is generated.
+///
+/// Note that a channel's last activity and connection times are only tracked from the time it
+/// finished opening or the [`ChannelManager`] was upgraded to a version which tracks them.
+///
+/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+/// [`ChannelManager::timer_tick_occurred`]: crate::ln::channelmanager::ChannelManager::timer_tick_occurred
+/// [`Event::ChannelStale`]: crate::events::Event::ChannelStale
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ChannelStalenessConfig {
+	/// The number of seconds after which a channel whose counterparty has not been connected to
+	/// us is considered stale, or `None` to never consider channels stale due to their peer being
+	/// unreachable.
+	///
+	/// Default value: `None`
+	pub peer_unreachable_threshold_secs: Option<u64>,
+	/// The number of seconds after which a channel which has not been used to send, receive or
+	/// forward any HTLCs is considered stale, or `None` to never consider channels stale due to
+	/// inactivity.
+	///
+	/// Default value: `None`
+	pub inactivity_threshold_secs: Option<u64>,
+	/// If this is set to true, channels which remain stale for
+	/// [`Self::force_close_grace_period_secs`] after the [`Event::ChannelStale`] for them was
+	/// generated are force-closed automatically, broadcasting our latest commitment transaction.
+	///
+	/// Channels whose counterparty is connected are never force-closed automatically, even if they
+	/// are stale due to inactivity, as they can be closed cooperatively instead. Their
+	/// force-closure is postponed until the counterparty disconnects, if it remains stale by then.
+	///
+	/// Default value: false
+	///
+	/// [`Event::ChannelStale`]: crate::events::Event::ChannelStale
+	pub auto_force_close: bool,
+	/// The number of seconds to wait after generating an [`Event::ChannelStale`] before
+	/// automatically force-closing the channel, giving the counterparty a last chance to come back
+	/// online and you a chance to close the channel cooperatively instead.
+	///
+	/// Default value: 604,800 (one week)
+	///
+	/// [`Event::ChannelStale`]: crate::events::Event::ChannelStale
+	pub force_close_grace_period_secs: u64,
+	/// The maximum [`ConfirmationTarget::Normal`] feerate, in satoshis per 1000 weight units, at
+	/// which we will automatically force-close a stale channel. While the feerate is higher,
+	/// automatic force-closes are postponed, avoiding paying high fees to claim our funds on-chain.
+	///
+	/// Default value: 2,500 (roughly 10 sat/vbyte)
+	///
+	/// [`ConfirmationTarget::Normal`]: crate::chain::chaininterface::ConfirmationTarget::Normal
+	pub max_force_close_feerate_sat_per_1000_weight: u32,
+}
+
+impl Default for ChannelStalenessConfig {
+	fn default() -> Self {
+		ChannelStalenessConfig {
+			peer_unreachable_threshold_secs: None,
+			inactivity_threshold_secs: None,
+			auto_force_close: false,
+			force_close_grace_period_secs: 60 * 60 * 24 * 7,
+			max_force_close_feerate_sat_per_1000_weight: 2_500,
+		}
+	}
+}
+
+/// A policy for warning when our view of the chain appears to have fallen far enough behind the
+/// actual chain tip to put our pending HTLCs at risk.
+///
+/// HTLCs are only failed back or claimed on-chain in time if we learn about new blocks promptly.
+/// If chain sync stalls, an HTLC may expire on-chain long before we notice, at which point our
+/// counterparty may claim it, while once sync resumes a batch of channels may be force-closed at
+/// once. On each call to [`ChannelManager::timer_tick_occurred`], we estimate the height of the
+/// actual chain tip from the number of timer ticks since our best block was last updated, or take
+/// the height given to [`ChannelManager::set_chain_tip_height`] if it is higher, and generate an
+/// [`Event::ChainSyncLagging`] if a pending HTLC expires within
+/// [`Self::htlc_expiry_warning_buffer_blocks`] of it.
+///
+/// As the time is only measured in timer ticks, which are assumed to occur once per minute, no
+/// wall clock is needed and this is available without the `std` feature.
+///
+/// Additionally, if the height of the actual chain tip is known, e.g. from the chain sync client,
+/// and given to [`ChannelManager::set_chain_tip_height`], operations which depend on an
+/// up-to-date view of the chain are deferred while we are more than
+/// [`Self::max_blocks_behind_tip`] blocks behind it.
+///
+/// [`ChannelManager::timer_tick_occurred`]: crate::ln::channelmanager::ChannelManager::timer_tick_occurred
+/// [`ChannelManager::set_chain_tip_height`]: crate::ln::channelmanager::ChannelManager::set_chain_tip_height
+/// [`Event::ChainSyncLagging`]: crate::events::Event::ChainSyncLagging
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ChainSyncSafetyConfig {
+	/// The number of seconds, counted as one minute per call to
+	/// [`ChannelManager::timer_tick_occurred`], we may go without our best block being updated
+	/// before we consider our chain sync to be lagging, or `None` to disable the check.
+	///
+	/// Note that there may naturally be an hour or more between blocks.
+	///
+	/// [`ChannelManager::timer_tick_occurred`]: crate::ln::channelmanager::ChannelManager::timer_tick_occurred
+	///
+	/// Default value: `None`
+	pub max_chain_tip_lag_secs: Option<u64>,
+	/// The number of blocks before a pending HTLC expires, at the estimated height of the chain
+	/// tip, at which we start warning.
+	///
+	/// Default value: 72 (12 hours)
+	pub htlc_expiry_warning_buffer_blocks: u32,
+	/// The number of blocks our best block may be behind the chain tip given to
+	/// [`ChannelManager::set_chain_tip_height`] before we defer sending payments and forwarding
+	/// HTLCs until chain sync has caught up, see [`ChannelManager::is_chain_sync_ready`].
+	///
+	/// Default value: 2
+	///
+	/// [`ChannelManager::set_chain_tip_height`]: crate::ln::channelmanager::ChannelManager::set_chain_tip_height
+	/// [`ChannelManager::is_chain_sync_ready`]: crate::ln::channelmanager::ChannelManager::is_chain_sync_ready
+	pub max_blocks_behind_tip: u32,
+	/// The number of consecutive calls to [`ChannelManager::timer_tick_occurred`] during which we
+	/// defer sending payments and forwarding HTLCs due to [`Self::max_blocks_behind_tip`], after
+	/// which we stop deferring until chain sync next catches up, e.g. in case our chain source has
+	/// stalled or the chain tip we were given is bogus.
+	///
+	/// Default value: 30 (approximately 30 minutes)
+	///
+	/// [`ChannelManager::timer_tick_occurred`]: crate::ln::channelmanager::ChannelManager::timer_tick_occurred
+	pub max_deferral_timer_ticks: u16,
+}
+
+impl Default for ChainSyncSafetyConfig {
+	fn default() -> Self {
+		ChainSyncSafetyConfig {
+			max_chain_tip_lag_secs: None,
+			htlc_expiry_warning_buffer_blocks: 72,
+			max_blocks_behind_tip: 2,
+			max_deferral_timer_ticks: 30,
+		}
+	}
+}
+
+/// A policy for pacing the broadcast of `channel_update`s for our own channels.
+///
+/// Updating many channels at once, e.g. changing the forwarding fees of all of them, would
+/// otherwise flood our peers with `channel_update`s, risking them rate-limiting or ignoring our
+/// gossip. Broadcasts are instead paced using a token bucket of up to [`Self::max_burst`] tokens,
+/// each broadcast consuming a token and [`Self::max_channel_updates_per_tick`] tokens being
+/// replenished on each call to [`ChannelManager::timer_tick_occurred`]. Broadcasts for which no
+/// token is available are queued, with a later update to a channel replacing any queued update to
+/// the same channel.
+///
+/// While pacing is enabled, the `channel_update` for any channel which was newly announced is also
+/// broadcast once more on the following timer tick, as peers which had yet to validate the
+/// `channel_announcement` will have dropped the one broadcast alongside it.
+///
+/// Note that queued broadcasts are not persisted.
+///
+/// [`ChannelManager::timer_tick_occurred`]: crate::ln::channelmanager::ChannelManager::timer_tick_occurred
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct GossipBroadcastPacingConfig {
+	/// The number of `channel_update` broadcasts we replenish the budget for on each timer tick,
+	/// or `None` to broadcast them without pacing.
+	///
+	/// Default value: `None`
+	pub max_channel_updates_per_tick: Option<u32>,
+	/// The maximum number of `channel_update`s we may broadcast in a burst, i.e. the size of the
+	/// token bucket. Values lower than [`Self::max_channel_updates_per_tick`] are treated as it.
+	///
+	/// Default value: 50
+	pub max_burst: u32,
+}
+
+impl Default for GossipBroadcastPacingConfig {
+	fn default() -> Self {
+		GossipBroadcastPacingConfig {
+			max_channel_updates_per_tick: None,
+			max_burst: 50,
+		}
+	}
+}
+
+/// A policy for marking our channels disabled in gossip while our counterparty is disconnected,
+/// such that the network stops routing payments through us to it, and enabled again once it
+/// reconnects.
+///
+/// To avoid spamming the network with `channel_update`s when a connection is flapping, a channel
+/// is only gossiped as disabled once it has not been live for [`Self::disable_after_ticks`] calls
+/// to [`ChannelManager::timer_tick_occurred`], and as enabled once it has been live again for
+/// [`Self::enable_after_ticks`] calls. Additionally, at least
+/// [`Self::min_ticks_between_status_changes`] calls must pass between two such changes to a channel.
+///
+/// [`ChannelManager::timer_tick_occurred`]: crate::ln::channelmanager::ChannelManager::timer_tick_occurred
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ChannelDisableConfig {
+	/// The number of timer ticks a channel must not have been live for before we gossip it as
+	/// disabled, or `None` to never gossip our channels as disabled because our counterparty
+	/// disconnected.
+	///
+	/// Default value: `Some(10)`, i.e. about ten minutes.
+	pub disable_after_ticks: Option<u8>,
+	/// The number of timer ticks a channel gossiped as disabled must have been live again for
+	/// before we gossip it as enabled.
+	///
+	/// Default value: 5
+	pub enable_after_ticks: u8,
+	/// The minimum number of timer ticks between gossiping a channel as disabled and as enabled
+	/// again, or vice versa, limiting the rate of `channel_update`s for channels whose counterparty
+	/// keeps reconnecting. Not persisted, i.e. the limit does not apply across restarts.
+	///
+	/// Default value: 0, i.e. only [`Self::disable_after_ticks`] and [`Self::enable_after_ticks`]
+	/// apply.
+	pub min_ticks_between_status_changes: u8,
+}
+
+impl Default for ChannelDisableConfig {
+	fn default() -> Self {
+		ChannelDisableConfig {
+			disable_after_ticks: Some(crate::ln::channelmanager::DISABLE_GOSSIP_TICKS),
+			enable_after_ticks: crate::ln::channelmanager::ENABLE_GOSSIP_TICKS,
+			min_ticks_between_status_changes: 0,
+		}
+	}
+}
+
+/// Limits on the value and number of HTLCs of the payments we send which may be in flight at
+/// once, both in total and per destination, e.g. as risk controls for custodial senders.
+///
+/// Only the HTLCs of outbound payments which have neither been fulfilled nor abandoned are
+/// counted. Payments to blinded paths have no known destination and are thus only subject to the
+/// total limits.
+///
+/// Payments sent via [`ChannelManager::send_payment`] (and similar methods which find routes and
+/// retry payments automatically) which would exceed any limit fail with
+/// [`RetryableSendFailure::ExposureLimitReached`]. If a retry of a payment would exceed any limit,
+/// it is deferred until the HTLCs of other payments have been resolved. Only if the retry would
+/// exceed a limit on its own is the payment abandoned, generating an [`Event::PaymentFailed`] with
+/// [`PaymentFailureReason::RouteNotFound`] once its remaining HTLCs have been resolved.
+///
+/// [`ChannelManager::send_payment`]: crate::ln::channelmanager::ChannelManager::send_payment
+/// [`RetryableSendFailure::ExposureLimitReached`]: crate::ln::outbound_payment::RetryableSendFailure::ExposureLimitReached
+/// [`Event::PaymentFailed`]: crate::events::Event::PaymentFailed
+/// [`PaymentFailureReason::RouteNotFound`]: crate::events::PaymentFailureReason::RouteNotFound
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct OutboundHtlcExposureConfig {
+	/// The maximum total value, in millisatoshis and including routing fees, of the HTLCs of all
+	/// our outbound payments in flight at once.
+	///
+	/// Default value: None, i.e. unlimited.
+	pub max_total_in_flight_msat: Option<u64>,
+	/// The maximum number of HTLCs of all our outbound payments in flight at once.
+	///
+	/// Default value: None, i.e. unlimited.
+	pub max_total_in_flight_htlcs: Option<u32>,
+	/// The maximum total value, in millisatoshis and including routing fees, of the HTLCs of our
+	/// outbound payments to any single destination in flight at once.
+	///
+	/// Default value: None, i.e. unlimited.
+	pub max_in_flight_msat_per_destination: Option<u64>,
+	/// The maximum number of HTLCs of our outbound payments to any single destination in flight at
+	/// once.
+	///
+	/// Default value: None, i.e. unlimited.
+	pub max_in_flight_htlcs_per_destination: Option<u32>,
+}
+
+impl Default for OutboundHtlcExposureConfig {
+	fn default() -> Self {
+		OutboundHtlcExposureConfig {
+			max_total_in_flight_msat: None,
+			max_total_in_flight_htlcs: None,
+			max_in_flight_msat_per_destination: None,
+			max_in_flight_htlcs_per_destination: None,
+		}
+	}
+}
+
+/// How the channel used to send a payment to a first-hop peer is chosen when we have several
+/// usable channels with that peer.
+///
+/// Unless set to [`FirstHopChannelSelection::Router`], the payments we send via
+/// [`ChannelManager::send_payment`] (and similar methods which find routes and retry payments
+/// automatically) only consider a single channel to each peer, which is selected anew for every
+/// route found, including for retries. Channels with no available outbound capacity are only
+/// selected if all of the peer's channels have none.
+///
+/// [`ChannelManager::send_payment`]: crate::ln::channelmanager::ChannelManager::send_payment
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FirstHopChannelSelection {
+	/// All usable channels to a peer are given to the [`Router`], which may split a payment across
+	/// them, see [`PaymentParameters::split_across_first_hop_channels`].
+	///
+	/// [`Router`]: crate::routing::router::Router
+	/// [`PaymentParameters::split_across_first_hop_channels`]: crate::routing::router::PaymentParameters::split_across_first_hop_channels
+	Router,
+	/// The channel with the most available outbound capacity, i.e. the highest
+	/// [`ChannelDetails::next_outbound_htlc_limit_msat`], is used.
+	///
+	/// [`ChannelDetails::next_outbound_htlc_limit_msat`]: crate::ln::channelmanager::ChannelDetails::next_outbound_htlc_limit_msat
+	LargestLiquidity,
+	/// The channel over which we charge the lowest fees to forward HTLCs, i.e. with the lowest
+	/// [`ChannelConfig::forwarding_fee_proportional_millionths`], then the lowest
+	/// [`ChannelConfig::forwarding_fee_base_msat`], is used, preserving the outbound capacity of the
+	/// channels which earn us the most when forwarding.
+	LowestFee,
+	/// The peer's channels are used in turn, ordered by channel id, spreading the HTLCs we send
+	/// across them.
+	RoundRobin,
+}
+
+impl Default for FirstHopChannelSelection {
+	fn default() -> Self {
+		FirstHopChannelSelection::Router
+	}
+}
+
+/// Grace handling for `update_fee`s from our counterparty with a feerate outside of the bounds we
+/// accept for channels it funded.
+///
+/// By default, we force-close a channel as soon as our counterparty proposes such a feerate. As
+/// fee estimators often diverge temporarily, e.g. while mempools are clearing, this may cause
+/// avoidable force-closes. Once a [`Self::grace_period_ticks`] is set, the feerate is instead
+/// accepted temporarily and an [`Event::CounterpartyFeerateDisagreement`] is generated. The
+/// channel is only force-closed if the feerate is still outside of our bounds after the grace
+/// period, or if the value of the HTLCs pending in the channel exceeds
+/// [`Self::max_pending_htlc_value_msat`] in the meantime.
+///
+/// Note that updates which would over-expose us to dust HTLCs are never tolerated.
+///
+/// [`Event::CounterpartyFeerateDisagreement`]: crate::events::Event::CounterpartyFeerateDisagreement
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FeerateDisagreementConfig {
+	/// The number of calls to [`ChannelManager::timer_tick_occurred`] for which we tolerate a
+	/// feerate outside of our bounds before force-closing the channel, or `None` to force-close it
+	/// immediately.
+	///
+	/// Default value: None
+	///
+	/// [`ChannelManager::timer_tick_occurred`]: crate::ln::channelmanager::ChannelManager::timer_tick_occurred
+	pub grace_period_ticks: Option<u8>,
+	/// The maximum total value of HTLCs, in millisatoshis, which may be pending in either
+	/// direction in a channel while we tolerate a feerate outside of our bounds on it. New HTLCs,
+	/// whether sent, forwarded or received, which would exceed it are failed. If it is exceeded
+	/// anyway, e.g. by HTLCs added before the disagreement started, the channel is force-closed on
+	/// the next timer tick rather than after the grace period, limiting how much value is exposed
+	/// to an unsuitable commitment transaction feerate.
+	///
+	/// Default value: 100_000_000, i.e. 100k sats.
+	pub max_pending_htlc_value_msat: u64,
+}
+
+impl Default for FeerateDisagreementConfig {
+	fn default() -> Self {
+		FeerateDisagreementConfig {
+			grace_period_ticks: None,
+			max_pending_htlc_value_msat: 100_000_000,
+		}
+	}
+}
+
+/// Configuration for reserving the HTLC slots of our channels for high-priority HTLCs when they
+/// are under load, see [`HtlcPriority`].
+///
+/// Our counterparty limits the number of HTLCs which may be pending towards it on a channel via
+/// its `max_accepted_htlcs`. Once a channel is close to this limit, forwards of
+/// [`HtlcPriority::Low`] are failed back with a `temporary_channel_failure` rather than using up
+/// its remaining slots, keeping them available for the payments we send ourselves and for
+/// forwards our [`ForwardingPolicy`] deems of [`HtlcPriority::High`], e.g. endorsed ones.
+///
+/// [`HtlcPriority`]: crate::ln::channelmanager::HtlcPriority
+/// [`HtlcPriority::Low`]: crate::ln::channelmanager::HtlcPriority::Low
+/// [`HtlcPriority::High`]: crate::ln::channelmanager::HtlcPriority::High
+/// [`ForwardingPolicy`]: crate::ln::channelmanager::ForwardingPolicy
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct HtlcPriorityConfig {
+	/// The percentage of our counterparty's `max_accepted_htlcs` on a channel beyond which we fail
+	/// back low-priority forwards over it. HTLCs pending towards our counterparty count towards
+	/// this limit regardless of their priority.
+	///
+	/// Default value: 100, i.e. low-priority forwards may use all of a channel's HTLC slots.
+	pub low_priority_slot_limit_percent: u8,
+}
+
+impl Default for HtlcPriorityConfig {
+	fn default() -> Self {
+		HtlcPriorityConfig {
+			low_priority_slot_limit_percent: 100,
+		}
+	}
+}
+
+/// Configuration for watching how close the HTLCs pending in our channels are to expiring.
+///
+/// Once a HTLC comes within [`Self::warning_blocks`] or [`Self::critical_blocks`] of its CLTV
+/// expiry, an [`Event::HTLCExpiryApproaching`] is generated for it. Without
+/// [`Self::preemptively_resolve_critical_htlcs`], expiring HTLCs are only acted upon at the last
+/// moment, e.g., by failing back payments we did not claim a few blocks before they expire or by
+/// force-closing a channel once a HTLC we offered has expired for `LATENCY_GRACE_PERIOD_BLOCKS`.
+///
+/// [`Event::HTLCExpiryApproaching`]: crate::events::Event::HTLCExpiryApproaching
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct HtlcExpiryWatchdogConfig {
+	/// The number of blocks ahead of a HTLC's expiry at which an
+	/// [`Event::HTLCExpiryApproaching`] with [`HTLCExpirySeverity::Warning`] is generated for it.
+	///
+	/// Default value: 0, i.e. no such events are generated.
+	///
+	/// [`Event::HTLCExpiryApproaching`]: crate::events::Event::HTLCExpiryApproaching
+	/// [`HTLCExpirySeverity::Warning`]: crate::events::HTLCExpirySeverity::Warning
+	pub warning_blocks: u32,
+	/// The number of blocks ahead of a HTLC's expiry at which an
+	/// [`Event::HTLCExpiryApproaching`] with [`HTLCExpirySeverity::Critical`] is generated for it.
+	///
+	/// If this is greater than a non-zero [`Self::warning_blocks`], it is treated as equal to
+	/// [`Self::warning_blocks`].
+	///
+	/// Default value: 0, i.e. no such events are generated.
+	///
+	/// [`Event::HTLCExpiryApproaching`]: crate::events::Event::HTLCExpiryApproaching
+	/// [`HTLCExpirySeverity::Critical`]: crate::events::HTLCExpirySeverity::Critical
+	pub critical_blocks: u32,
+	/// If this is set to true, HTLCs are resolved as soon as they come within
+	/// [`Self::critical_blocks`] of their expiry, rather than at the last moment:
+	///  * HTLCs of payments we received but have not yet claimed are failed back.
+	///  * Channels with HTLCs we offered which have not been resolved by our counterparty, or
+	///    with HTLCs we claimed but our counterparty has not yet removed, are force-closed with
+	///    [`ClosureReason::HTLCExpiryApproaching`], such that the HTLCs are resolved on-chain.
+	///
+	/// HTLCs we received and forwarded onwards are resolved once the HTLCs we forwarded them over
+	/// are.
+	///
+	/// Default value: false.
+	///
+	/// [`ClosureReason::HTLCExpiryApproaching`]: crate::events::ClosureReason::HTLCExpiryApproaching
+	pub preemptively_resolve_critical_htlcs: bool,
+}
+
+impl HtlcExpiryWatchdogConfig {
+	/// Returns this config with [`Self::critical_blocks`] clamped to a non-zero
+	/// [`Self::warning_blocks`].
+	pub(crate) fn sanitized(mut self) -> Self {
+		if self.warning_blocks != 0 && self.critical_blocks > self.warning_blocks {
+			self.critical_blocks = self.warning_blocks;
+		}
+		self
+	}
+}
+
+impl Default for HtlcExpiryWatchdogConfig {
+	fn default() -> Self {
+		HtlcExpiryWatchdogConfig {
+			warning_blocks: 0,
+			critical_blocks: 0,
+			preemptively_resolve_critical_htlcs: false,
+		}
+	}
+}
+
 /// Top-level config which holds ChannelHandshakeLimits and ChannelConfig.
 ///
 /// Default::default() provides sane defaults for most configurations
@@ -597,18 +1658,35 @@ pub struct UserConfig {
 	/// [`msgs::AcceptChannel`]: crate::ln::msgs::AcceptChannel
 	pub manually_accept_inbound_channels: bool,
 	///  If this is set to true, LDK will intercept HTLCs that are attempting to be forwarded over
-	///  fake short channel ids generated via [`ChannelManager::get_intercept_scid`]. Upon HTLC
-	///  intercept, LDK will generate an [`Event::HTLCIntercepted`] which MUST be handled by the user.
+	///  fake short channel ids generated via [`ChannelManager::get_intercept_scid`] or
+	///  [`ChannelManager::create_intercept_scid`]. Upon HTLC intercept, LDK will generate an
+	///  [`Event::HTLCIntercepted`] which MUST be handled by the user.
 	///
 	///  Setting this to true may break backwards compatibility with LDK versions < 0.0.113.
 	///
 	///  Default value: false.
 	///
 	/// [`ChannelManager::get_intercept_scid`]: crate::ln::channelmanager::ChannelManager::get_intercept_scid
+	/// [`ChannelManager::create_intercept_scid`]: crate::ln::channelmanager::ChannelManager::create_intercept_scid
 	/// [`Event::HTLCIntercepted`]: crate::events::Event::HTLCIntercepted
 	pub accept_intercept_htlcs: bool,
+	/// If this is set to false, we will fail back HTLCs we are asked to relay as an intermediate
+	/// hop within a blinded path, generating an [`Event::BlindedForwardRejected`] for each.
+	///
+	/// Blinded paths built by recipients, including by [`ChannelManager`] when receiving
+	/// payments for offers and refunds, may route through us, so disabling this breaks payments
+	/// to recipients relying on us as a hop in their paths.
+	///
+	/// Default value: true.
+	///
+	/// [`Event::BlindedForwardRejected`]: crate::events::Event::BlindedForwardRejected
+	/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+	pub accept_blinded_forwards: bool,
 	/// If this is set to false, when receiving a keysend payment we'll fail it if it has multiple
-	/// parts. If this is set to true, we'll accept the payment.
+	/// parts, unless its payment hash was registered as accepting them via
+	/// [`ChannelManager::register_keysend_payment_hash`]. If this is set to true, we'll accept the
+	/// payment. Which keysend payments are accepted at all can be restricted at runtime via
+	/// [`ChannelManager::set_keysend_policy`].
 	///
 	/// Setting this to true will break backwards compatibility upon downgrading to an LDK
 	/// version < 0.0.116 while receiving an MPP keysend. If we have already received an MPP
@@ -617,7 +1695,264 @@ pub struct UserConfig {
 	/// Default value: false.
 	///
 	/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+	/// [`ChannelManager::register_keysend_payment_hash`]: crate::ln::channelmanager::ChannelManager::register_keysend_payment_hash
+	/// [`ChannelManager::set_keysend_policy`]: crate::ln::channelmanager::ChannelManager::set_keysend_policy
 	pub accept_mpp_keysend: bool,
+	/// If this is set to true, the [`ChannelManager`] will generate an
+	/// [`Event::StaticBackupUpdated`] on startup and whenever the set of channels which may be
+	/// recovered from a static backup has changed.
+	///
+	/// Default value: false.
+	///
+	/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+	/// [`Event::StaticBackupUpdated`]: crate::events::Event::StaticBackupUpdated
+	pub generate_static_backup_events: bool,
+	/// If this is set to true, the [`ChannelManager`] will track the expiry of inbound payments
+	/// created via [`ChannelManager::create_inbound_payment`] and similar methods, generating an
+	/// [`Event::InboundPaymentExpired`] for any which expire without being paid.
+	///
+	/// Note that this requires storing a small amount of data per inbound payment until it is
+	/// paid or expires.
+	///
+	/// Default value: false.
+	///
+	/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+	/// [`ChannelManager::create_inbound_payment`]: crate::ln::channelmanager::ChannelManager::create_inbound_payment
+	/// [`Event::InboundPaymentExpired`]: crate::events::Event::InboundPaymentExpired
+	pub generate_inbound_payment_expired_events: bool,
+	/// If this is set to true, unknown even TLV records read from a [`ChannelManager`] or
+	/// [`ChannelMonitor`] written by a newer version of LDK are retained and re-written when the
+	/// object is next persisted, rather than failing to read with
+	/// [`DecodeError::UnknownRequiredFeature`]. This allows temporarily downgrading LDK without
+	/// losing data, but as the newer version required the data to be understood, it should only
+	/// be set when the release notes of the newer version state that downgrading is safe.
+	///
+	/// Unknown odd TLV records are always retained. Unknown records which cannot be re-written,
+	/// e.g., as their type lies between known types, are dropped if odd and fail to read if even.
+	///
+	/// Note that this is only used when reading a [`ChannelManager`] or [`ChannelMonitor`], see
+	/// [`ChannelManagerReadArgs::default_config`].
+	///
+	/// Default value: false.
+	///
+	/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+	/// [`ChannelMonitor`]: crate::chain::channelmonitor::ChannelMonitor
+	/// [`DecodeError::UnknownRequiredFeature`]: crate::ln::msgs::DecodeError::UnknownRequiredFeature
+	/// [`ChannelManagerReadArgs::default_config`]: crate::ln::channelmanager::ChannelManagerReadArgs::default_config
+	pub preserve_unknown_even_tlvs: bool,
+	/// Limits on the resources our peers may consume in the [`ChannelManager`].
+	///
+	/// Note that [`ResourceLimitsConfig::max_outbound_gossip_buffer_messages`],
+	/// [`ResourceLimitsConfig::max_onion_message_buffer_bytes`] and
+	/// [`ResourceLimitsConfig::max_onion_message_buffer_bytes_per_peer`] are not used by the
+	/// [`ChannelManager`], and should be provided to the [`PeerManager`] and [`OnionMessenger`]
+	/// directly.
+	///
+	/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+	/// [`PeerManager`]: crate::ln::peer_handler::PeerManager
+	/// [`OnionMessenger`]: crate::onion_message::OnionMessenger
+	pub resource_limits: ResourceLimitsConfig,
+	/// The number of resolved outbound payments whose [`PaymentId`]s the [`ChannelManager`] will
+	/// remember, and persist, after they have been fulfilled or have failed.
+	///
+	/// Once a payment is resolved and no longer tracked as pending, a later attempt to send a
+	/// payment with the same [`PaymentId`] is rejected as a duplicate as long as the payment is
+	/// among the most recent `resolved_payment_history_limit` resolved payments, even across
+	/// restarts. The terminal status of a remembered payment is available via
+	/// [`ChannelManager::resolved_payment_status`]. If set to zero, [`PaymentId`]s may be reused as
+	/// soon as their payment is no longer pending.
+	///
+	/// Setting this allows safely retrying a `send_payment` call whose outcome is unknown, e.g.
+	/// after a crash, using the same [`PaymentId`], without risking paying twice.
+	///
+	/// Default value: 0.
+	///
+	/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+	/// [`PaymentId`]: crate::ln::channelmanager::PaymentId
+	/// [`ChannelManager::resolved_payment_status`]: crate::ln::channelmanager::ChannelManager::resolved_payment_status
+	pub resolved_payment_history_limit: usize,
+	/// The number of calls to [`ChannelManager::timer_tick_occurred`] for which the preimages of
+	/// payments we have claimed or successfully sent are retained after the payment settled, and
+	/// are thus available via [`ChannelManager::payment_preimage`].
+	///
+	/// Retained preimages are persisted along with the [`ChannelManager`]. If set to zero,
+	/// preimages are not retained.
+	///
+	/// Default value: 1440 (roughly one day, assuming `timer_tick_occurred` is called once per
+	/// minute)
+	///
+	/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+	/// [`ChannelManager::timer_tick_occurred`]: crate::ln::channelmanager::ChannelManager::timer_tick_occurred
+	/// [`ChannelManager::payment_preimage`]: crate::ln::channelmanager::ChannelManager::payment_preimage
+	pub payment_preimage_retention_ticks: u32,
+	/// The number of calls to [`ChannelManager::timer_tick_occurred`] for which we hold the parts
+	/// of a partially-received multi-part payment while waiting for the remaining parts to arrive.
+	///
+	/// Once a part has been held for this many ticks without the full amount having been
+	/// received, all parts are failed back and an [`Event::PartialPaymentTimedOut`] is generated.
+	/// Values lower than 1 are treated as 1.
+	///
+	/// Default value: 3 (roughly three minutes, assuming `timer_tick_occurred` is called once per
+	/// minute)
+	///
+	/// [`ChannelManager::timer_tick_occurred`]: crate::ln::channelmanager::ChannelManager::timer_tick_occurred
+	/// [`Event::PartialPaymentTimedOut`]: crate::events::Event::PartialPaymentTimedOut
+	pub mpp_receive_timeout_ticks: u8,
+	/// If this is set to true, the per-channel forwarding statistics available via
+	/// [`ChannelManager::channel_stats`] are persisted along with the [`ChannelManager`], rather
+	/// than being reset on restart.
+	///
+	/// Default value: false.
+	///
+	/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+	/// [`ChannelManager::channel_stats`]: crate::ln::channelmanager::ChannelManager::channel_stats
+	pub persist_channel_stats: bool,
+	/// If this is set to true, the [`ChannelManager`] will send an encrypted copy of its static
+	/// backup to each connected peer with which we have a channel and which supports
+	/// `option_provide_storage`, on connection and whenever the backup changes. Peers return the
+	/// latest copy they hold on reconnection, which is surfaced as an
+	/// [`Event::StaticBackupRetrieved`] if it contains channels we do not know about, e.g. after
+	/// losing our local state.
+	///
+	/// Note that backups are only sent if they fit within a single `peer_storage` message.
+	///
+	/// Default value: false.
+	///
+	/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+	/// [`Event::StaticBackupRetrieved`]: crate::events::Event::StaticBackupRetrieved
+	pub store_static_backup_with_peers: bool,
+	/// The maximum size, in bytes, of the blob we will store on behalf of each peer with which we
+	/// have a channel, and return to it on reconnection, as a provider of
+	/// `option_provide_storage`. Stored blobs are persisted along with the [`ChannelManager`].
+	///
+	/// If set to zero, we will neither advertise the feature nor store blobs for our peers.
+	///
+	/// Default value: 0.
+	///
+	/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+	pub peer_storage_quota_bytes: usize,
+	/// The policy for detecting stale channels and optionally force-closing them.
+	///
+	/// Default value: all detection disabled, see [`ChannelStalenessConfig`].
+	pub channel_staleness: ChannelStalenessConfig,
+	/// The policy for warning when our chain sync lags behind far enough to put pending HTLCs at
+	/// risk.
+	///
+	/// Default value: disabled, see [`ChainSyncSafetyConfig`].
+	pub chain_sync_safety: ChainSyncSafetyConfig,
+	/// If set, BOLT 12 invoices passed to [`ChannelManager::send_payment_for_bolt12_invoice`] for
+	/// an amount above this many millisatoshis are not paid until explicitly approved via
+	/// [`ChannelManager::approve_bolt12_payment`]. The [`Event::Bolt12InvoiceReceived`] generated
+	/// for each such invoice indicates whether it is awaiting approval.
+	///
+	/// Default value: None, i.e. no approval is required.
+	///
+	/// [`ChannelManager::send_payment_for_bolt12_invoice`]: crate::ln::channelmanager::ChannelManager::send_payment_for_bolt12_invoice
+	/// [`ChannelManager::approve_bolt12_payment`]: crate::ln::channelmanager::ChannelManager::approve_bolt12_payment
+	/// [`Event::Bolt12InvoiceReceived`]: crate::events::Event::Bolt12InvoiceReceived
+	pub bolt12_payment_approval_threshold_msat: Option<u64>,
+	/// If this is set to true, we will signal support for `option_simple_close` and, with peers
+	/// which support it too, cooperatively close channels using the simplified closing protocol.
+	///
+	/// In that protocol, rather than negotiating a fee over several rounds of `closing_signed`,
+	/// the channel funder offers a replaceable closing transaction which pays the full fee out of
+	/// its own balance, and which its counterparty simply countersigns. With peers which do not
+	/// support it, we continue to use the legacy `closing_signed` negotiation.
+	///
+	/// Default value: false.
+	pub negotiate_simple_close: bool,
+	/// The maximum random delay, in milliseconds, we add before relaying the settlement of an HTLC
+	/// we forwarded back to the previous hop, once we learn its preimage from the next hop.
+	///
+	/// Settling forwarded HTLCs immediately lets an observer of several hops along a route
+	/// correlate them by timing. If this is set, settlements are instead held back until
+	/// [`ChannelManager::process_pending_htlc_forwards`] is next called, for which a randomized
+	/// [`Event::PendingHTLCsForwardable`] is generated. Settlements are never held back if the
+	/// inbound HTLC is close to its CLTV expiry, and all held settlements are released on the next
+	/// [`ChannelManager::timer_tick_occurred`] call at the latest. Values above
+	/// [`MAX_FORWARDED_HTLC_SETTLEMENT_JITTER_MILLIS`] are treated as that value.
+	///
+	/// Default value: 0, i.e. settlements are relayed without any additional delay.
+	///
+	/// [`ChannelManager::process_pending_htlc_forwards`]: crate::ln::channelmanager::ChannelManager::process_pending_htlc_forwards
+	/// [`ChannelManager::timer_tick_occurred`]: crate::ln::channelmanager::ChannelManager::timer_tick_occurred
+	/// [`Event::PendingHTLCsForwardable`]: crate::events::Event::PendingHTLCsForwardable
+	/// [`MAX_FORWARDED_HTLC_SETTLEMENT_JITTER_MILLIS`]: crate::ln::channelmanager::MAX_FORWARDED_HTLC_SETTLEMENT_JITTER_MILLIS
+	pub forwarded_htlc_settlement_jitter_max_ms: u64,
+	/// The policy for pacing the broadcast of `channel_update`s for our own channels.
+	///
+	/// Default value: disabled, see [`GossipBroadcastPacingConfig`].
+	pub gossip_broadcast_pacing: GossipBroadcastPacingConfig,
+	/// If set, HTLCs intercepted as per [`Self::accept_intercept_htlcs`] which have been neither
+	/// forwarded nor failed are failed back once the chain is within this many blocks of the
+	/// inbound HTLC's CLTV expiry.
+	///
+	/// Otherwise, unresolved intercepted HTLCs are only failed back shortly before the CLTV expiry
+	/// the sender intended for the next hop, which may leave too little time to fail the inbound
+	/// HTLC back off-chain if the intercepted HTLC's CLTV delta is large, risking a force-close by
+	/// our counterparty. Either way, the height at which each pending intercepted HTLC will be
+	/// failed back is listed by [`ChannelManager::list_intercepted_htlcs`].
+	///
+	/// Default value: None.
+	///
+	/// [`ChannelManager::list_intercepted_htlcs`]: crate::ln::channelmanager::ChannelManager::list_intercepted_htlcs
+	pub intercepted_htlc_auto_fail_cltv_delta: Option<u32>,
+	/// The number of blocks ahead of an intercepted HTLC being failed back automatically at which
+	/// an [`Event::HTLCInterceptExpiring`] is generated for it.
+	///
+	/// Default value: 0, i.e. no such events are generated.
+	///
+	/// [`Event::HTLCInterceptExpiring`]: crate::events::Event::HTLCInterceptExpiring
+	pub intercepted_htlc_expiry_warning_blocks: u32,
+	/// The policy for reserving the HTLC slots of our channels for high-priority HTLCs.
+	///
+	/// Default value: disabled, see [`HtlcPriorityConfig`].
+	pub htlc_priority: HtlcPriorityConfig,
+	/// If this is set to true, the [`ChannelManager`] will generate an [`Event::ChannelProgressed`]
+	/// whenever a channel takes a step through its lifecycle, e.g. its funding transaction gaining
+	/// a confirmation or a round of closing fee negotiation taking place.
+	///
+	/// Default value: false.
+	///
+	/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+	/// [`Event::ChannelProgressed`]: crate::events::Event::ChannelProgressed
+	pub generate_channel_progress_events: bool,
+	/// Configuration for watching how close the HTLCs pending in our channels are to expiring.
+	///
+	/// Default value: disabled, see [`HtlcExpiryWatchdogConfig`].
+	pub htlc_expiry_watchdog: HtlcExpiryWatchdogConfig,
+	/// The policy for marking our channels disabled in gossip while our counterparty is
+	/// disconnected.
+	///
+	/// Default value: see [`ChannelDisableConfig`].
+	pub channel_disable: ChannelDisableConfig,
+	/// Limits on the HTLCs of the payments we send which may be in flight at once.
+	///
+	/// Default value: no limits, see [`OutboundHtlcExposureConfig`].
+	pub outbound_htlc_exposure: OutboundHtlcExposureConfig,
+	/// How the channel used to send a payment to a first-hop peer is chosen when we have several
+	/// usable channels with that peer.
+	///
+	/// Default value: [`FirstHopChannelSelection::Router`].
+	pub first_hop_channel_selection: FirstHopChannelSelection,
+	/// Grace handling for `update_fee`s from our counterparty with a feerate outside of the bounds
+	/// we accept.
+	///
+	/// Default value: immediately force-close, see [`FeerateDisagreementConfig`].
+	pub feerate_disagreement: FeerateDisagreementConfig,
+	/// The number of outbound payments for which the [`ChannelManager`] keeps an in-memory
+	/// timeline of route attempts, invoice requests, and HTLC sends, failures, and claims,
+	/// available via [`ChannelManager::payment_trace`].
+	///
+	/// Timelines are not persisted, and the oldest timeline is dropped once timelines would be
+	/// kept for more payments than this, after which no further events are traced for it. Each
+	/// timeline keeps at most the latest 64 events. If set to zero, no timelines are kept.
+	///
+	/// Default value: 0.
+	///
+	/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+	/// [`ChannelManager::payment_trace`]: crate::ln::channelmanager::ChannelManager::payment_trace
+	pub payment_trace_limit: usize,
 }
 
 impl Default for UserConfig {
@@ -630,7 +1965,34 @@ impl Default for UserConfig {
 			accept_inbound_channels: true,
 			manually_accept_inbound_channels: false,
 			accept_intercept_htlcs: false,
+			accept_blinded_forwards: true,
 			accept_mpp_keysend: false,
+			generate_static_backup_events: false,
+			generate_inbound_payment_expired_events: false,
+			preserve_unknown_even_tlvs: false,
+			resource_limits: ResourceLimitsConfig::default(),
+			resolved_payment_history_limit: 0,
+			payment_preimage_retention_ticks: 60 * 24,
+			mpp_receive_timeout_ticks: crate::ln::channelmanager::MPP_TIMEOUT_TICKS,
+			persist_channel_stats: false,
+			store_static_backup_with_peers: false,
+			peer_storage_quota_bytes: 0,
+			channel_staleness: ChannelStalenessConfig::default(),
+			chain_sync_safety: ChainSyncSafetyConfig::default(),
+			bolt12_payment_approval_threshold_msat: None,
+			negotiate_simple_close: false,
+			forwarded_htlc_settlement_jitter_max_ms: 0,
+			gossip_broadcast_pacing: GossipBroadcastPacingConfig::default(),
+			intercepted_htlc_auto_fail_cltv_delta: None,
+			intercepted_htlc_expiry_warning_blocks: 0,
+			htlc_priority: HtlcPriorityConfig::default(),
+			generate_channel_progress_events: false,
+			htlc_expiry_watchdog: HtlcExpiryWatchdogConfig::default(),
+			channel_disable: ChannelDisableConfig::default(),
+			outbound_htlc_exposure: OutboundHtlcExposureConfig::default(),
+			first_hop_channel_selection: FirstHopChannelSelection::Router,
+			feerate_disagreement: FeerateDisagreementConfig::default(),
+			payment_trace_limit: 0,
 		}
 	}
 }
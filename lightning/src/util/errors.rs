@@ -9,9 +9,13 @@
 
 //! Error types live here.
 
+use crate::ln::PaymentHash;
 use crate::ln::script::ShutdownScript;
+use crate::util::logger::DebugBytes;
 
-use alloc::string::String;
+use bitcoin::secp256k1::PublicKey;
+
+use alloc::string::{String, ToString};
 use core::fmt;
 
 /// Indicates an error on the client's part (usually some variant of attempting to use too-low or
@@ -67,6 +71,25 @@ pub enum APIError {
 		/// The incompatible shutdown script.
 		script: ShutdownScript,
 	},
+	/// We were unable to send an HTLC over the first hop of a path, as we have no such channel or
+	/// it is unable to carry the HTLC at this time.
+	///
+	/// As with [`APIError::ChannelUnavailable`], the payment may succeed over other first hops.
+	ChannelCannotSendHtlc {
+		/// The reason the HTLC could not be sent.
+		err: SendHtlcError,
+	},
+}
+
+impl APIError {
+	/// Whether the channel required to complete the request is unavailable, i.e., for payments,
+	/// whether the first hop of the path could not be used.
+	pub(crate) fn is_channel_unavailable(&self) -> bool {
+		match self {
+			APIError::ChannelUnavailable { .. } | APIError::ChannelCannotSendHtlc { .. } => true,
+			_ => false,
+		}
+	}
 }
 
 impl fmt::Debug for APIError {
@@ -80,6 +103,7 @@ impl fmt::Debug for APIError {
 			APIError::IncompatibleShutdownScript { ref script } => {
 				write!(f, "Provided a scriptpubkey format not accepted by peer: {}", script)
 			},
+			APIError::ChannelCannotSendHtlc { ref err } => write!(f, "Channel unavailable: {}", err),
 		}
 	}
 }
@@ -94,12 +118,447 @@ impl_writeable_tlv_based_enum_upgradable!(APIError,
 	(6, ChannelUnavailable) => { (0, err, required), },
 	(8, MonitorUpdateInProgress) => {},
 	(10, IncompatibleShutdownScript) => { (0, script, required), },
+	(11, ChannelCannotSendHtlc) => { (0, err, required), },
+);
+
+/// The reason we were unable to send an HTLC over the first hop of a path, see
+/// [`APIError::ChannelCannotSendHtlc`].
+///
+/// Variants rejecting the amount of the HTLC carry both the amount and the limit it violated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SendHtlcError {
+	/// We have no channel with the short channel id of the first hop, e.g. as it was closed.
+	ChannelNotFound {
+		/// The short channel id of the first hop.
+		short_channel_id: u64,
+	},
+	/// We have no peer with the node id of the first hop.
+	PeerNotFound {
+		/// The node id of the first hop.
+		counterparty_node_id: PublicKey,
+	},
+	/// The counterparty of the first hop channel is disconnected.
+	PeerDisconnected {
+		/// The node id of the first hop.
+		counterparty_node_id: PublicKey,
+	},
+	/// The first hop channel is not yet fully established or is shutting down.
+	ChannelNotReady,
+	/// The amount of the HTLC exceeds the value of the whole channel.
+	AmountExceedsChannelValue {
+		/// The amount of the HTLC.
+		amount_msat: u64,
+		/// The value of the channel.
+		channel_value_msat: u64,
+	},
+	/// The amount of the HTLC is zero.
+	ZeroAmount,
+	/// The amount of the HTLC is below the smallest HTLC we can currently send over the channel.
+	AmountBelowHtlcMinimum {
+		/// The amount of the HTLC.
+		amount_msat: u64,
+		/// The smallest HTLC we can currently send, see
+		/// [`ChannelDetails::next_outbound_htlc_minimum_msat`].
+		///
+		/// [`ChannelDetails::next_outbound_htlc_minimum_msat`]: crate::ln::channelmanager::ChannelDetails::next_outbound_htlc_minimum_msat
+		next_outbound_htlc_minimum_msat: u64,
+	},
+	/// The amount of the HTLC exceeds the largest HTLC we can currently send over the channel.
+	AmountAboveHtlcLimit {
+		/// The amount of the HTLC.
+		amount_msat: u64,
+		/// The largest HTLC we can currently send, see
+		/// [`ChannelDetails::next_outbound_htlc_limit_msat`].
+		///
+		/// [`ChannelDetails::next_outbound_htlc_limit_msat`]: crate::ln::channelmanager::ChannelDetails::next_outbound_htlc_limit_msat
+		next_outbound_htlc_limit_msat: u64,
+	},
+}
+
+impl fmt::Display for SendHtlcError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			SendHtlcError::ChannelNotFound { .. } => f.write_str("No channel available with first hop!"),
+			SendHtlcError::PeerNotFound { .. } => f.write_str("No peer matching the path's first hop found!"),
+			SendHtlcError::PeerDisconnected { .. } => f.write_str("Peer for first hop currently disconnected"),
+			SendHtlcError::ChannelNotReady =>
+				f.write_str("Cannot send HTLC until channel is fully established and we haven't started shutting down"),
+			SendHtlcError::AmountExceedsChannelValue { amount_msat, channel_value_msat } =>
+				write!(f, "Cannot send amount {}, because it is more than the total value of the channel {}", amount_msat, channel_value_msat),
+			SendHtlcError::ZeroAmount => f.write_str("Cannot send 0-msat HTLC"),
+			SendHtlcError::AmountBelowHtlcMinimum { next_outbound_htlc_minimum_msat, .. } =>
+				write!(f, "Cannot send less than our next-HTLC minimum - {} msat", next_outbound_htlc_minimum_msat),
+			SendHtlcError::AmountAboveHtlcLimit { next_outbound_htlc_limit_msat, .. } =>
+				write!(f, "Cannot send more than our next-HTLC maximum - {} msat", next_outbound_htlc_limit_msat),
+		}
+	}
+}
+
+impl_writeable_tlv_based_enum!(SendHtlcError,
+	(0, ChannelNotFound) => { (0, short_channel_id, required), },
+	(2, PeerNotFound) => { (0, counterparty_node_id, required), },
+	(4, PeerDisconnected) => { (0, counterparty_node_id, required), },
+	(6, ChannelNotReady) => {},
+	(8, AmountExceedsChannelValue) => {
+		(0, amount_msat, required),
+		(2, channel_value_msat, required),
+	},
+	(10, ZeroAmount) => {},
+	(12, AmountBelowHtlcMinimum) => {
+		(0, amount_msat, required),
+		(2, next_outbound_htlc_minimum_msat, required),
+	},
+	(14, AmountAboveHtlcLimit) => {
+		(0, amount_msat, required),
+		(2, next_outbound_htlc_limit_msat, required),
+	};
 );
 
+/// An error when attempting to claim a payment using [`ChannelManager::try_claim_funds`].
+///
+/// In each case no HTLCs have been claimed.
+///
+/// [`ChannelManager::try_claim_funds`]: crate::ln::channelmanager::ChannelManager::try_claim_funds
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ClaimFundsError {
+	/// We have no claimable payment for the hash of the preimage, e.g. as it was already claimed
+	/// or failed back.
+	PaymentNotClaimable {
+		/// The hash of the preimage passed in.
+		payment_hash: PaymentHash,
+	},
+	/// Some of the payment's HTLCs were failed between the [`Event::PaymentClaimable`] and the
+	/// claim, so less than the expected amount is available to be claimed. The remaining HTLCs
+	/// will be failed back once they time out.
+	///
+	/// [`Event::PaymentClaimable`]: crate::events::Event::PaymentClaimable
+	IncompletePayment {
+		/// The hash of the preimage passed in.
+		payment_hash: PaymentHash,
+		/// The amount we told the user was claimable.
+		expected_amount_msat: u64,
+		/// The amount which was actually available to be claimed.
+		claimable_amount_msat: u64,
+	},
+	/// The parts of the payment disagreed on its total amount. The HTLCs have been failed back.
+	InvalidMpp {
+		/// The hash of the preimage passed in.
+		payment_hash: PaymentHash,
+	},
+}
+
+impl fmt::Display for ClaimFundsError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ClaimFundsError::PaymentNotClaimable { payment_hash } =>
+				write!(f, "No claimable payment with hash {}", DebugBytes(&payment_hash.0)),
+			ClaimFundsError::IncompletePayment { expected_amount_msat, claimable_amount_msat, .. } =>
+				write!(f, "Attempted to claim an incomplete payment, expected {} msat, had {} available to claim",
+					expected_amount_msat, claimable_amount_msat),
+			ClaimFundsError::InvalidMpp { .. } =>
+				f.write_str("MPP payment parts disagreed on the total amount"),
+		}
+	}
+}
+
+/// An error when attempting to open a channel using [`ChannelManager::create_channel`] or
+/// [`ChannelManager::create_channel_with_upfront_shutdown_script`].
+///
+/// Variants rejecting a requested value carry both the value and the limit it violated. Each
+/// converts into the [`APIError`] previously returned for it.
+///
+/// [`ChannelManager::create_channel`]: crate::ln::channelmanager::ChannelManager::create_channel
+/// [`ChannelManager::create_channel_with_upfront_shutdown_script`]: crate::ln::channelmanager::ChannelManager::create_channel_with_upfront_shutdown_script
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OpenChannelError {
+	/// The channel value is below the minimum we allow.
+	ChannelValueTooLow {
+		/// The requested channel value.
+		channel_value_satoshis: u64,
+		/// The smallest channel value we allow.
+		min_channel_value_satoshis: u64,
+	},
+	/// The channel value exceeds the maximum allowed, e.g. as our counterparty does not support
+	/// large channels.
+	ChannelValueTooHigh {
+		/// The requested channel value.
+		channel_value_satoshis: u64,
+		/// The largest channel value allowed with this counterparty.
+		max_channel_value_satoshis: u64,
+	},
+	/// The amount to push to our counterparty exceeds the channel value.
+	PushAmountTooHigh {
+		/// The requested amount to push.
+		push_msat: u64,
+		/// The largest amount we could push, i.e. the channel value.
+		max_push_msat: u64,
+	},
+	/// Our balance would not even pay the fee for the initial commitment transaction at the
+	/// current feerate.
+	InsufficientFundsForFees {
+		/// Our balance in the channel, i.e. the channel value less the amount pushed.
+		value_to_self_msat: u64,
+		/// The fee of the initial commitment transaction.
+		commitment_tx_fee_msat: u64,
+	},
+	/// Our configured [`ChannelHandshakeConfig::our_to_self_delay`] is too low to keep our funds
+	/// safe.
+	///
+	/// [`ChannelHandshakeConfig::our_to_self_delay`]: crate::util::config::ChannelHandshakeConfig::our_to_self_delay
+	ToSelfDelayTooLow {
+		/// The configured delay, in blocks.
+		our_to_self_delay: u16,
+		/// The smallest delay we allow, in blocks.
+		min_to_self_delay: u16,
+	},
+	/// The channel reserve we'd require from our counterparty would be below the dust limit, as
+	/// the channel value is too low.
+	ChannelReserveTooLow {
+		/// The channel reserve we'd require.
+		channel_reserve_satoshis: u64,
+		/// The smallest channel reserve we allow.
+		min_channel_reserve_satoshis: u64,
+	},
+	/// The shutdown script to commit to is incompatible with the features of our counterparty.
+	IncompatibleShutdownScript {
+		/// The incompatible shutdown script.
+		script: ShutdownScript,
+	},
+	/// [`SignerProvider::get_shutdown_scriptpubkey`] failed to provide the shutdown script to
+	/// commit to, as [`ChannelHandshakeConfig::commit_upfront_shutdown_pubkey`] is set.
+	///
+	/// [`SignerProvider::get_shutdown_scriptpubkey`]: crate::sign::SignerProvider::get_shutdown_scriptpubkey
+	/// [`ChannelHandshakeConfig::commit_upfront_shutdown_pubkey`]: crate::util::config::ChannelHandshakeConfig::commit_upfront_shutdown_pubkey
+	ShutdownScriptUnavailable,
+	/// [`SignerProvider::get_destination_script`] failed to provide the script our balance is
+	/// swept to once the channel closes.
+	///
+	/// [`SignerProvider::get_destination_script`]: crate::sign::SignerProvider::get_destination_script
+	DestinationScriptUnavailable,
+	/// We are not connected to the given counterparty.
+	PeerNotConnected {
+		/// The node id of the counterparty.
+		counterparty_node_id: PublicKey,
+	},
+	/// Our [`PeerHistoryPolicy`] does not allow opening a channel to the given counterparty.
+	///
+	/// [`PeerHistoryPolicy`]: crate::ln::channelmanager::PeerHistoryPolicy
+	RejectedByPeerHistoryPolicy {
+		/// The node id of the counterparty.
+		counterparty_node_id: PublicKey,
+	},
+	/// [`ChannelManager::begin_shutdown`] was called, after which no new channels are opened.
+	///
+	/// [`ChannelManager::begin_shutdown`]: crate::ln::channelmanager::ChannelManager::begin_shutdown
+	ShuttingDown,
+	/// The randomly generated temporary channel id collided with that of an existing channel,
+	/// which only ever happens if the [`EntropySource`] is broken, e.g. when fuzzing.
+	///
+	/// [`EntropySource`]: crate::sign::EntropySource
+	TemporaryChannelIdCollision,
+}
+
+impl From<OpenChannelError> for APIError {
+	fn from(err: OpenChannelError) -> Self {
+		match err {
+			OpenChannelError::ChannelValueTooLow { channel_value_satoshis, min_channel_value_satoshis } =>
+				APIError::APIMisuseError { err: format!("Channel value must be at least {} satoshis. It was {}", min_channel_value_satoshis, channel_value_satoshis) },
+			OpenChannelError::ChannelValueTooHigh { channel_value_satoshis, max_channel_value_satoshis } =>
+				APIError::APIMisuseError { err: format!("funding_value must not exceed {}, it was {}", max_channel_value_satoshis, channel_value_satoshis) },
+			OpenChannelError::PushAmountTooHigh { push_msat, max_push_msat } =>
+				APIError::APIMisuseError { err: format!("Push value ({}) was larger than channel_value ({})", push_msat, max_push_msat) },
+			OpenChannelError::InsufficientFundsForFees { value_to_self_msat, commitment_tx_fee_msat } =>
+				APIError::APIMisuseError { err: format!("Funding amount ({}) can't even pay fee for initial commitment transaction fee of {}.", value_to_self_msat / 1000, commitment_tx_fee_msat / 1000) },
+			OpenChannelError::ToSelfDelayTooLow { our_to_self_delay, .. } =>
+				APIError::APIMisuseError { err: format!("Configured with an unreasonable our_to_self_delay ({}) putting user funds at risks", our_to_self_delay) },
+			OpenChannelError::ChannelReserveTooLow { channel_reserve_satoshis, .. } =>
+				APIError::APIMisuseError { err: format!("Holder selected channel  reserve below implemention limit dust_limit_satoshis {}", channel_reserve_satoshis) },
+			OpenChannelError::IncompatibleShutdownScript { script } =>
+				APIError::IncompatibleShutdownScript { script },
+			OpenChannelError::ShutdownScriptUnavailable =>
+				APIError::ChannelUnavailable { err: "Failed to get shutdown scriptpubkey".to_string() },
+			OpenChannelError::DestinationScriptUnavailable =>
+				APIError::ChannelUnavailable { err: "Failed to get destination script".to_string() },
+			OpenChannelError::PeerNotConnected { counterparty_node_id } =>
+				APIError::APIMisuseError { err: format!("Not connected to node: {}", counterparty_node_id) },
+			OpenChannelError::RejectedByPeerHistoryPolicy { counterparty_node_id } =>
+				APIError::ChannelUnavailable { err: format!("Our PeerHistoryPolicy does not allow opening a channel to node {}", counterparty_node_id) },
+			OpenChannelError::ShuttingDown =>
+				APIError::APIMisuseError { err: "Cannot open new channels after begin_shutdown was called".to_string() },
+			OpenChannelError::TemporaryChannelIdCollision =>
+				APIError::APIMisuseError { err: "Fuzzy bad RNG".to_string() },
+		}
+	}
+}
+
+/// An error when attempting to cooperatively close a channel using
+/// [`ChannelManager::close_channel`] or [`ChannelManager::close_channel_with_feerate_and_script`].
+///
+/// Each variant converts into the [`APIError`] previously returned for it.
+///
+/// [`ChannelManager::close_channel`]: crate::ln::channelmanager::ChannelManager::close_channel
+/// [`ChannelManager::close_channel_with_feerate_and_script`]: crate::ln::channelmanager::ChannelManager::close_channel_with_feerate_and_script
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CloseChannelError {
+	/// We have no peer with the given node id.
+	PeerNotFound {
+		/// The node id of the counterparty.
+		counterparty_node_id: PublicKey,
+	},
+	/// We have no funded channel with the given id with the given counterparty.
+	ChannelNotFound {
+		/// The id of the channel.
+		channel_id: [u8; 32],
+		/// The node id of the counterparty.
+		counterparty_node_id: PublicKey,
+	},
+	/// We have no channel with the given id with any counterparty, see
+	/// [`ChannelManager::close_channel_by_id`].
+	///
+	/// [`ChannelManager::close_channel_by_id`]: crate::ln::channelmanager::ChannelManager::close_channel_by_id
+	UnknownChannel {
+		/// The id of the channel.
+		channel_id: [u8; 32],
+	},
+	/// HTLCs we've sent have yet to be committed to. Processing pending events first should
+	/// resolve this.
+	HtlcsPendingCommitment,
+	/// We've already begun closing the channel.
+	ShutdownInProgress,
+	/// Our counterparty has already begun closing the channel.
+	ShutdownInitiatedByCounterparty,
+	/// A shutdown script was given while the channel already committed to one.
+	ShutdownScriptAlreadySet,
+	/// Our counterparty is disconnected. A force-closure may be needed to close the channel.
+	PeerDisconnected,
+	/// A [`ChannelMonitorUpdate`] for the channel is still in progress.
+	///
+	/// [`ChannelMonitorUpdate`]: crate::chain::channelmonitor::ChannelMonitorUpdate
+	MonitorUpdateInProgress,
+	/// [`SignerProvider::get_shutdown_scriptpubkey`] failed to provide the script to close to. A
+	/// force-closure may be needed to close the channel.
+	///
+	/// [`SignerProvider::get_shutdown_scriptpubkey`]: crate::sign::SignerProvider::get_shutdown_scriptpubkey
+	ShutdownScriptUnavailable,
+	/// The shutdown script to close to is incompatible with the features of our counterparty.
+	IncompatibleShutdownScript {
+		/// The incompatible shutdown script.
+		script: ShutdownScript,
+	},
+}
+
+impl From<CloseChannelError> for APIError {
+	fn from(err: CloseChannelError) -> Self {
+		match err {
+			CloseChannelError::PeerNotFound { counterparty_node_id } =>
+				APIError::ChannelUnavailable { err: format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id) },
+			CloseChannelError::ChannelNotFound { channel_id, counterparty_node_id } =>
+				APIError::ChannelUnavailable { err: format!("Channel with id {} not found for the passed counterparty node_id {}", DebugBytes(&channel_id), counterparty_node_id) },
+			CloseChannelError::UnknownChannel { channel_id } =>
+				APIError::ChannelUnavailable { err: format!("Channel with id {} not found", DebugBytes(&channel_id)) },
+			CloseChannelError::HtlcsPendingCommitment =>
+				APIError::APIMisuseError { err: "Cannot begin shutdown with pending HTLCs. Process pending events first".to_string() },
+			CloseChannelError::ShutdownInProgress =>
+				APIError::APIMisuseError { err: "Shutdown already in progress".to_string() },
+			CloseChannelError::ShutdownInitiatedByCounterparty =>
+				APIError::ChannelUnavailable { err: "Shutdown already in progress by remote".to_string() },
+			CloseChannelError::ShutdownScriptAlreadySet =>
+				APIError::APIMisuseError { err: "Cannot override shutdown script for a channel with one already set".to_string() },
+			CloseChannelError::PeerDisconnected | CloseChannelError::MonitorUpdateInProgress =>
+				APIError::ChannelUnavailable { err: "Cannot begin shutdown while peer is disconnected or we're waiting on a monitor update, maybe force-close instead?".to_string() },
+			CloseChannelError::ShutdownScriptUnavailable =>
+				APIError::ChannelUnavailable { err: "Failed to get shutdown scriptpubkey".to_string() },
+			CloseChannelError::IncompatibleShutdownScript { script } =>
+				APIError::IncompatibleShutdownScript { script },
+		}
+	}
+}
+
+/// An error when attempting to update the [`ChannelConfig`] of channels using
+/// [`ChannelManager::update_partial_channel_config`] and related methods, in which case none of
+/// the updates were applied.
+///
+/// Variants rejecting a requested value carry both the value and the limit it violated. Each
+/// converts into the [`APIError`] previously returned for it.
+///
+/// [`ChannelConfig`]: crate::util::config::ChannelConfig
+/// [`ChannelManager::update_partial_channel_config`]: crate::ln::channelmanager::ChannelManager::update_partial_channel_config
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChannelConfigUpdateError {
+	/// The [`ChannelConfig::cltv_expiry_delta`] to apply is below the minimum we allow.
+	///
+	/// [`ChannelConfig::cltv_expiry_delta`]: crate::util::config::ChannelConfig::cltv_expiry_delta
+	CltvExpiryDeltaTooLow {
+		/// The requested CLTV expiry delta.
+		cltv_expiry_delta: u16,
+		/// The smallest CLTV expiry delta we allow, i.e. [`MIN_CLTV_EXPIRY_DELTA`].
+		///
+		/// [`MIN_CLTV_EXPIRY_DELTA`]: crate::ln::channelmanager::MIN_CLTV_EXPIRY_DELTA
+		min_cltv_expiry_delta: u16,
+	},
+	/// We have no peer with the given node id.
+	PeerNotFound {
+		/// The node id of the counterparty.
+		counterparty_node_id: PublicKey,
+	},
+	/// We have no funded channel with the given id with the given counterparty.
+	ChannelNotFound {
+		/// The id of the channel.
+		channel_id: [u8; 32],
+		/// The node id of the counterparty.
+		counterparty_node_id: PublicKey,
+	},
+	/// We have no channel with the given id with any counterparty, see
+	/// [`ChannelManager::update_partial_channel_config_by_id`].
+	///
+	/// [`ChannelManager::update_partial_channel_config_by_id`]: crate::ln::channelmanager::ChannelManager::update_partial_channel_config_by_id
+	UnknownChannel {
+		/// The id of the channel.
+		channel_id: [u8; 32],
+	},
+	/// The resulting HTLC minimum of the channel exceeds what it can carry after reserves.
+	HtlcMinimumExceedsCapacity {
+		/// The id of the channel.
+		channel_id: [u8; 32],
+		/// The HTLC minimum which would result from the update.
+		htlc_minimum_msat: u64,
+		/// The largest HTLC the channel can carry after reserves.
+		max_sendable_msat: u64,
+	},
+	/// The resulting HTLC maximum of the channel is below its HTLC minimum.
+	HtlcMaximumBelowMinimum {
+		/// The id of the channel.
+		channel_id: [u8; 32],
+		/// The HTLC maximum which would result from the update.
+		htlc_maximum_msat: u64,
+		/// The HTLC minimum which would result from the update.
+		htlc_minimum_msat: u64,
+	},
+}
+
+impl From<ChannelConfigUpdateError> for APIError {
+	fn from(err: ChannelConfigUpdateError) -> Self {
+		match err {
+			ChannelConfigUpdateError::CltvExpiryDeltaTooLow { min_cltv_expiry_delta, .. } =>
+				APIError::APIMisuseError { err: format!("The chosen CLTV expiry delta is below the minimum of {}", min_cltv_expiry_delta) },
+			ChannelConfigUpdateError::PeerNotFound { counterparty_node_id } =>
+				APIError::ChannelUnavailable { err: format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id) },
+			ChannelConfigUpdateError::ChannelNotFound { channel_id, counterparty_node_id } =>
+				APIError::ChannelUnavailable { err: format!("Channel with ID {} was not found for the passed counterparty_node_id {}", DebugBytes(&channel_id), counterparty_node_id) },
+			ChannelConfigUpdateError::UnknownChannel { channel_id } =>
+				APIError::ChannelUnavailable { err: format!("Channel with ID {} was not found", DebugBytes(&channel_id)) },
+			ChannelConfigUpdateError::HtlcMinimumExceedsCapacity { htlc_minimum_msat, max_sendable_msat, .. } =>
+				APIError::APIMisuseError { err: format!("The HTLC minimum of {} msat exceeds the {} msat the channel can carry after reserves", htlc_minimum_msat, max_sendable_msat) },
+			ChannelConfigUpdateError::HtlcMaximumBelowMinimum { htlc_maximum_msat, htlc_minimum_msat, .. } =>
+				APIError::APIMisuseError { err: format!("The HTLC maximum of {} msat is below the HTLC minimum of {} msat", htlc_maximum_msat, htlc_minimum_msat) },
+		}
+	}
+}
+
 #[inline]
 pub(crate) fn get_onion_debug_field(error_code: u16) -> (&'static str, usize) {
 	match error_code & 0xff {
-		4|5|6 => ("sha256_of_onion", 32),
+		4|5|6|24 => ("sha256_of_onion", 32),
 		11|12 => ("htlc_msat", 8),
 		13|18 => ("cltv_expiry", 4),
 		19 => ("incoming_htlc_msat", 8),
@@ -139,6 +598,7 @@ pub(crate) fn get_onion_error_description(error_code: u16) -> (&'static str, &'s
 		_c if _c == 21 => ("Node indicated the CLTV expiry in the HTLC is too far in the future", "expiry_too_far"),
 		_c if _c == PERM|22 => ("Node indicated that the decrypted onion per-hop payload was not understood by it or is incomplete", "invalid_onion_payload"),
 		_c if _c == 23 => ("The final node indicated the complete amount of the multi-part payment was not received within a reasonable time", "mpp_timeout"),
+		_c if _c == BADONION|PERM|24 => ("Node indicated the HTLC could not be processed within a blinded path", "invalid_onion_blinding"),
 		_ => ("Unknown", ""),
 	}
 }
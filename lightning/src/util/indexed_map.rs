@@ -1,12 +1,30 @@
 //! This module has a map which can be iterated in a deterministic order. See the [`IndexedMap`].
 
 use crate::prelude::{HashMap, hash_map};
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 use alloc::slice::Iter;
-use core::hash::Hash;
+use core::hash::{BuildHasher, Hash, Hasher};
 use core::cmp::Ord;
+use core::iter::Peekable;
 use core::ops::{Bound, RangeBounds};
 
+#[cfg(feature = "hashbrown")]
+type ShardHashBuilder = hash_map::DefaultHashBuilder;
+#[cfg(not(feature = "hashbrown"))]
+type ShardHashBuilder = hash_map::RandomState;
+
+/// The number of shards an [`IndexedMap`] is split into. Cloning a map only clones the references
+/// to its shards, and mutating it afterwards only copies the shards which are mutated.
+const SHARD_COUNT: usize = 32;
+
+#[derive(Clone, Debug)]
+struct Shard<K: Hash + Ord, V> {
+	map: HashMap<K, V>,
+	keys: Vec<K>,
+	keys_sorted: bool,
+}
+
 /// A map which can be iterated in a deterministic order.
 ///
 /// This would traditionally be accomplished by simply using a [`BTreeMap`], however B-Trees
@@ -21,47 +39,91 @@ use core::ops::{Bound, RangeBounds};
 /// actually backed by a [`HashMap`], with some additional tracking to ensure we can iterate over
 /// keys in the order defined by [`Ord`].
 ///
+/// The map is split into shards which are shared between clones of it and copied on write, so
+/// that cloning it is cheap and a mutation after a clone only copies the affected shard rather
+/// than the whole map. This lets the network graph hand out snapshots of itself while continuing
+/// to apply updates.
+///
 /// This is not exported to bindings users as bindings provide alternate accessors rather than exposing maps directly.
 ///
 /// [`BTreeMap`]: alloc::collections::BTreeMap
-#[derive(Clone, Debug, Eq)]
+#[derive(Clone, Debug)]
 pub struct IndexedMap<K: Hash + Ord, V> {
-	map: HashMap<K, V>,
-	keys: Vec<K>,
+	shards: Vec<Arc<Shard<K, V>>>,
+	hash_builder: ShardHashBuilder,
+}
+
+impl<K: Hash + Ord, V> IndexedMap<K, V> {
+	fn shard_idx(&self, key: &K) -> usize {
+		let mut hasher = self.hash_builder.build_hasher();
+		key.hash(&mut hasher);
+		(hasher.finish() % SHARD_COUNT as u64) as usize
+	}
 }
 
 impl<K: Clone + Hash + Ord, V> IndexedMap<K, V> {
 	/// Constructs a new, empty map
 	pub fn new() -> Self {
-		Self {
-			map: HashMap::new(),
-			keys: Vec::new(),
+		let mut shards = Vec::with_capacity(SHARD_COUNT);
+		for _ in 0..SHARD_COUNT {
+			shards.push(Arc::new(Shard { map: HashMap::new(), keys: Vec::new(), keys_sorted: true }));
 		}
+		Self { shards, hash_builder: ShardHashBuilder::default() }
 	}
 
 	#[inline(always)]
 	/// Fetches the element with the given `key`, if one exists.
 	pub fn get(&self, key: &K) -> Option<&V> {
-		self.map.get(key)
-	}
-
-	/// Fetches a mutable reference to the element with the given `key`, if one exists.
-	pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
-		self.map.get_mut(key)
+		self.shards[self.shard_idx(key)].map.get(key)
 	}
 
 	#[inline]
 	/// Returns true if an element with the given `key` exists in the map.
 	pub fn contains_key(&self, key: &K) -> bool {
-		self.map.contains_key(key)
+		self.shards[self.shard_idx(key)].map.contains_key(key)
+	}
+
+	/// Returns an iterator which iterates over the keys in the map, in a random order.
+	pub fn unordered_keys(&self) -> impl Iterator<Item = &K> {
+		self.shards.iter().flat_map(|shard| shard.map.keys())
+	}
+
+	/// Returns an iterator which iterates over the `key`/`value` pairs in a random order.
+	pub fn unordered_iter(&self) -> impl Iterator<Item = (&K, &V)> {
+		self.shards.iter().flat_map(|shard| shard.map.iter())
+	}
+
+	/// Returns the number of `key`/`value` pairs in the map
+	pub fn len(&self) -> usize {
+		self.shards.iter().map(|shard| shard.map.len()).sum()
+	}
+
+	/// Returns true if there are no elements in the map
+	pub fn is_empty(&self) -> bool {
+		self.shards.iter().all(|shard| shard.map.is_empty())
+	}
+}
+
+impl<K: Clone + Hash + Ord, V: Clone> IndexedMap<K, V> {
+	fn shard_mut(&mut self, key: &K) -> &mut Shard<K, V> {
+		let idx = self.shard_idx(key);
+		Arc::make_mut(&mut self.shards[idx])
+	}
+
+	/// Fetches a mutable reference to the element with the given `key`, if one exists.
+	pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+		if !self.contains_key(key) { return None; }
+		self.shard_mut(key).map.get_mut(key)
 	}
 
 	/// Removes the element with the given `key`, returning it, if one exists.
 	pub fn remove(&mut self, key: &K) -> Option<V> {
-		let ret = self.map.remove(key);
+		if !self.contains_key(key) { return None; }
+		let shard = self.shard_mut(key);
+		let ret = shard.map.remove(key);
 		if let Some(_) = ret {
-			let idx = self.keys.iter().position(|k| k == key).expect("map and keys must be consistent");
-			self.keys.remove(idx);
+			let idx = shard.keys.iter().position(|k| k == key).expect("map and keys must be consistent");
+			shard.keys.remove(idx);
 		}
 		ret
 	}
@@ -69,97 +131,110 @@ impl<K: Clone + Hash + Ord, V> IndexedMap<K, V> {
 	/// Inserts the given `key`/`value` pair into the map, returning the element that was
 	/// previously stored at the given `key`, if one exists.
 	pub fn insert(&mut self, key: K, value: V) -> Option<V> {
-		let ret = self.map.insert(key.clone(), value);
+		let shard = self.shard_mut(&key);
+		let ret = shard.map.insert(key.clone(), value);
 		if ret.is_none() {
-			self.keys.push(key);
+			shard.keys.push(key);
+			shard.keys_sorted = false;
 		}
 		ret
 	}
 
 	/// Returns an [`Entry`] for the given `key` in the map, allowing access to the value.
 	pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
-		match self.map.entry(key.clone()) {
+		let shard = self.shard_mut(&key);
+		match shard.map.entry(key.clone()) {
 			hash_map::Entry::Vacant(entry) => {
 				Entry::Vacant(VacantEntry {
 					underlying_entry: entry,
 					key,
-					keys: &mut self.keys,
+					keys: &mut shard.keys,
+					keys_sorted: &mut shard.keys_sorted,
 				})
 			},
 			hash_map::Entry::Occupied(entry) => {
 				Entry::Occupied(OccupiedEntry {
 					underlying_entry: entry,
-					keys: &mut self.keys,
+					keys: &mut shard.keys,
 				})
 			}
 		}
 	}
 
-	/// Returns an iterator which iterates over the keys in the map, in a random order.
-	pub fn unordered_keys(&self) -> impl Iterator<Item = &K> {
-		self.map.keys()
-	}
-
-	/// Returns an iterator which iterates over the `key`/`value` pairs in a random order.
-	pub fn unordered_iter(&self) -> impl Iterator<Item = (&K, &V)> {
-		self.map.iter()
-	}
-
 	/// Returns an iterator which iterates over the `key`s and mutable references to `value`s in a
 	/// random order.
+	///
+	/// Note that this copies every shard of the map which is shared with a clone of it.
 	pub fn unordered_iter_mut(&mut self) -> impl Iterator<Item = (&K, &mut V)> {
-		self.map.iter_mut()
+		self.shards.iter_mut().flat_map(|shard| Arc::make_mut(shard).map.iter_mut())
 	}
 
 	/// Returns an iterator which iterates over the `key`/`value` pairs in a given range.
 	pub fn range<R: RangeBounds<K>>(&mut self, range: R) -> Range<K, V> {
-		self.keys.sort_unstable();
-		let start = match range.start_bound() {
-			Bound::Unbounded => 0,
-			Bound::Included(key) => self.keys.binary_search(key).unwrap_or_else(|index| index),
-			Bound::Excluded(key) => self.keys.binary_search(key).and_then(|index| Ok(index + 1)).unwrap_or_else(|index| index),
-		};
-		let end = match range.end_bound() {
-			Bound::Unbounded => self.keys.len(),
-			Bound::Included(key) => self.keys.binary_search(key).and_then(|index| Ok(index + 1)).unwrap_or_else(|index| index),
-			Bound::Excluded(key) => self.keys.binary_search(key).unwrap_or_else(|index| index),
-		};
-
-		Range {
-			inner_range: self.keys[start..end].iter(),
-			map: &self.map,
+		for shard in self.shards.iter_mut() {
+			if !shard.keys_sorted {
+				let shard = Arc::make_mut(shard);
+				shard.keys.sort_unstable();
+				shard.keys_sorted = true;
+			}
 		}
-	}
 
-	/// Returns the number of `key`/`value` pairs in the map
-	pub fn len(&self) -> usize {
-		self.map.len()
-	}
+		let mut shard_ranges = Vec::with_capacity(self.shards.len());
+		for shard in self.shards.iter() {
+			let start = match range.start_bound() {
+				Bound::Unbounded => 0,
+				Bound::Included(key) => shard.keys.binary_search(key).unwrap_or_else(|index| index),
+				Bound::Excluded(key) => shard.keys.binary_search(key).and_then(|index| Ok(index + 1)).unwrap_or_else(|index| index),
+			};
+			let end = match range.end_bound() {
+				Bound::Unbounded => shard.keys.len(),
+				Bound::Included(key) => shard.keys.binary_search(key).and_then(|index| Ok(index + 1)).unwrap_or_else(|index| index),
+				Bound::Excluded(key) => shard.keys.binary_search(key).unwrap_or_else(|index| index),
+			};
+			shard_ranges.push((shard.keys[start..end].iter().peekable(), &shard.map));
+		}
 
-	/// Returns true if there are no elements in the map
-	pub fn is_empty(&self) -> bool {
-		self.map.is_empty()
+		Range { shard_ranges }
 	}
 }
 
 impl<K: Hash + Ord + PartialEq, V: PartialEq> PartialEq for IndexedMap<K, V> {
 	fn eq(&self, other: &Self) -> bool {
-		self.map == other.map
+		// Shards are selected with a per-map hasher, so compare entries rather than shards.
+		let len = self.shards.iter().map(|shard| shard.map.len()).sum::<usize>();
+		let other_len = other.shards.iter().map(|shard| shard.map.len()).sum::<usize>();
+		len == other_len && self.shards.iter().all(|shard| shard.map.iter().all(|(k, v)|
+			other.shards[other.shard_idx(k)].map.get(k) == Some(v)))
 	}
 }
 
+impl<K: Hash + Ord + Eq, V: Eq> Eq for IndexedMap<K, V> {}
+
 /// An iterator over a range of values in an [`IndexedMap`]
 ///
 /// This is not exported to bindings users as bindings provide alternate accessors rather than exposing maps directly.
 pub struct Range<'a, K: Hash + Ord, V> {
-	inner_range: Iter<'a, K>,
-	map: &'a HashMap<K, V>,
+	shard_ranges: Vec<(Peekable<Iter<'a, K>>, &'a HashMap<K, V>)>,
 }
 impl<'a, K: Hash + Ord, V: 'a> Iterator for Range<'a, K, V> {
 	type Item = (&'a K, &'a V);
 	fn next(&mut self) -> Option<(&'a K, &'a V)> {
-		self.inner_range.next().map(|k| {
-			(k, self.map.get(k).expect("map and keys must be consistent"))
+		// Each shard's keys are sorted, so the next key in the map is the least next key of any
+		// shard.
+		let mut next_shard = None;
+		let mut next_key: Option<&'a K> = None;
+		for (idx, (keys, _)) in self.shard_ranges.iter_mut().enumerate() {
+			if let Some(key) = keys.peek() {
+				if next_key.map_or(true, |next_key| *key < next_key) {
+					next_key = Some(*key);
+					next_shard = Some(idx);
+				}
+			}
+		}
+		next_shard.map(|idx| {
+			let (keys, map) = &mut self.shard_ranges[idx];
+			let k = keys.next().expect("We just peeked a key");
+			(k, map.get(k).expect("map and keys must be consistent"))
 		})
 	}
 }
@@ -174,6 +249,7 @@ pub struct VacantEntry<'a, K: Hash + Ord, V> {
 	underlying_entry: hash_map::VacantEntry<'a, K, V>,
 	key: K,
 	keys: &'a mut Vec<K>,
+	keys_sorted: &'a mut bool,
 }
 
 /// An [`Entry`] for an existing key-value pair
@@ -202,6 +278,7 @@ impl<'a, K: Hash + Ord, V> VacantEntry<'a, K, V> {
 	/// Insert a value into the position described by this entry.
 	pub fn insert(self, value: V) -> &'a mut V {
 		self.keys.push(self.key);
+		*self.keys_sorted = false;
 		self.underlying_entry.insert(value)
 	}
 }
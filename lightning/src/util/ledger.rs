@@ -0,0 +1,761 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Structured, double-entry-style accounting records built from the [`Event`] stream.
+//!
+//! A [`Ledger`] turns the events relevant for bookkeeping into [`LedgerEntry`]s, each moving an
+//! amount from one [`LedgerAccount`] to another, such that the balance of each account can be
+//! derived by summing its debits and credits. Feed each [`Event`] to [`Ledger::record_event`] as
+//! it is handled, persist the [`Ledger`] (it implements [`Writeable`]) and export its entries via
+//! [`Ledger::to_csv`] or [`Ledger::to_json`].
+//!
+//! Note that events may be provided more than once if the event handler does not complete before
+//! a restart, thus the [`Ledger`] should be persisted along with whatever the handler persists.
+//! Replayed events are detected and not recorded twice, except for [`Event::PaymentForwarded`],
+//! which carries nothing identifying a specific forward.
+//!
+//! LDK does not know the fee of on-chain transactions it does not build itself, such as funding
+//! transactions, transactions sweeping [`Event::SpendableOutputs`] or anchor fee-bumping
+//! transactions. Their fees should be provided via [`Ledger::record_on_chain_fee`].
+//!
+//! [`Writeable`]: crate::util::ser::Writeable
+
+use bitcoin::hash_types::Txid;
+use bitcoin::hashes::{Hash, HashEngine};
+use bitcoin::hashes::hex::ToHex;
+use bitcoin::hashes::sha256::Hash as Sha256;
+
+use crate::chain::transaction::OutPoint;
+use crate::events::{ChannelProgress, ClosureReason, Event};
+use crate::events::bump_transaction::BumpTransactionEvent;
+use crate::io;
+use crate::ln::PaymentHash;
+use crate::ln::msgs::DecodeError;
+use crate::sign::SpendableOutputDescriptor;
+use crate::util::ser::{Readable, Writeable, Writer};
+
+use core::fmt::Write;
+use crate::prelude::*;
+
+/// An account which a [`LedgerEntry`] debits or credits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LedgerAccount {
+	/// Our off-chain balance in open channels.
+	Lightning,
+	/// Our on-chain wallet.
+	OnChain,
+	/// Funds paid to, or received from, other parties.
+	External,
+	/// Routing fees we paid to intermediate nodes when sending payments.
+	RoutingFeesPaid,
+	/// Routing fees we earned forwarding payments.
+	RoutingFeesEarned,
+	/// Fees we paid to miners for on-chain transactions.
+	OnChainFeesPaid,
+}
+
+impl_writeable_tlv_based_enum!(LedgerAccount,
+	(0, Lightning) => {},
+	(2, OnChain) => {},
+	(4, External) => {},
+	(6, RoutingFeesPaid) => {},
+	(8, RoutingFeesEarned) => {},
+	(10, OnChainFeesPaid) => {};
+);
+
+impl LedgerAccount {
+	fn as_str(&self) -> &'static str {
+		match self {
+			LedgerAccount::Lightning => "lightning",
+			LedgerAccount::OnChain => "on_chain",
+			LedgerAccount::External => "external",
+			LedgerAccount::RoutingFeesPaid => "routing_fees_paid",
+			LedgerAccount::RoutingFeesEarned => "routing_fees_earned",
+			LedgerAccount::OnChainFeesPaid => "on_chain_fees_paid",
+		}
+	}
+}
+
+/// The kind of activity a [`LedgerEntry`] records.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LedgerEntryKind {
+	/// A path of an outbound payment succeeded, from [`Event::PaymentPathSuccessful`]. The amount
+	/// is the value received by the recipient over the path, excluding fees.
+	PaymentSent,
+	/// An inbound payment was claimed, from [`Event::PaymentClaimed`].
+	PaymentReceived,
+	/// The routing fees paid for an outbound payment, from [`Event::PaymentSent`].
+	FeePaid,
+	/// The routing fee earned forwarding a payment, from [`Event::PaymentForwarded`].
+	RoutingFeeEarned,
+	/// An output from a closed channel became spendable by our on-chain wallet, from
+	/// [`Event::SpendableOutputs`]. The `txid` is that of the transaction creating the output,
+	/// i.e. the closing transaction for our balance in a cooperatively closed channel.
+	OnChainSweep,
+	/// We funded an outbound channel, from [`Event::FundingGenerationReady`] followed by
+	/// [`Event::ChannelPending`]. The `txid` is that of the funding transaction.
+	ChannelFunded,
+	/// The funding transaction of a channel we funded will never be broadcast, from
+	/// [`Event::DiscardFunding`], reversing its [`LedgerEntryKind::ChannelFunded`] entry.
+	ChannelFundingDiscarded,
+	/// A channel was closed, from [`Event::ChannelClosed`]. No amount is moved, as our balance in
+	/// the channel is only known once it is swept, see [`LedgerEntryKind::OnChainSweep`].
+	ChannelClosed,
+	/// A fee paid for an on-chain transaction. This is recorded for the closing transaction of a
+	/// channel we funded and closed cooperatively (if [`ChannelProgress::ClosingFeeProposed`] was
+	/// provided), for our commitment transaction of an anchor channel we funded, from
+	/// [`BumpTransactionEvent::ChannelClose`], and for fees provided via
+	/// [`Ledger::record_on_chain_fee`].
+	OnChainFeePaid,
+}
+
+impl_writeable_tlv_based_enum!(LedgerEntryKind,
+	(0, PaymentSent) => {},
+	(2, PaymentReceived) => {},
+	(4, FeePaid) => {},
+	(6, RoutingFeeEarned) => {},
+	(8, OnChainSweep) => {},
+	(10, ChannelFunded) => {},
+	(12, ChannelFundingDiscarded) => {},
+	(14, ChannelClosed) => {},
+	(16, OnChainFeePaid) => {};
+);
+
+impl LedgerEntryKind {
+	fn as_str(&self) -> &'static str {
+		match self {
+			LedgerEntryKind::PaymentSent => "payment_sent",
+			LedgerEntryKind::PaymentReceived => "payment_received",
+			LedgerEntryKind::FeePaid => "fee_paid",
+			LedgerEntryKind::RoutingFeeEarned => "routing_fee_earned",
+			LedgerEntryKind::OnChainSweep => "on_chain_sweep",
+			LedgerEntryKind::ChannelFunded => "channel_funded",
+			LedgerEntryKind::ChannelFundingDiscarded => "channel_funding_discarded",
+			LedgerEntryKind::ChannelClosed => "channel_closed",
+			LedgerEntryKind::OnChainFeePaid => "on_chain_fee_paid",
+		}
+	}
+}
+
+/// A single ledger record, moving `amount_msat` from the `credit` account to the `debit` account.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LedgerEntry {
+	/// The time at which the entry was recorded, as provided to [`Ledger::record_event`].
+	pub timestamp: u64,
+	/// The kind of activity this entry records.
+	pub kind: LedgerEntryKind,
+	/// The account which received the amount.
+	pub debit: LedgerAccount,
+	/// The account the amount was taken from.
+	pub credit: LedgerAccount,
+	/// The amount moved, in millisatoshis.
+	///
+	/// This is `None` if the event did not include the amount, e.g. for fees of payments sent or
+	/// forwarded by versions of LDK which did not report them, or for
+	/// [`LedgerEntryKind::ChannelClosed`] entries. Such entries should be reconciled manually.
+	pub amount_msat: Option<u64>,
+	/// The payment hash of the payment this entry relates to, if any.
+	pub payment_hash: Option<PaymentHash>,
+	/// The channel over which a forwarded payment was received, if any.
+	pub prev_channel_id: Option<[u8; 32]>,
+	/// The channel over which a forwarded payment was sent, if any.
+	pub next_channel_id: Option<[u8; 32]>,
+	/// The on-chain transaction this entry relates to, if any.
+	pub txid: Option<Txid>,
+	/// The channel this entry relates to, for channel funding and closing entries.
+	pub channel_id: Option<[u8; 32]>,
+	/// Identifies the event this entry was recorded from, allowing replayed events to be ignored.
+	/// `None` for entries which cannot be told apart from a replay, i.e. forwards.
+	id: Option<[u8; 32]>,
+}
+
+impl_writeable_tlv_based!(LedgerEntry, {
+	(0, timestamp, required),
+	(2, kind, required),
+	(4, debit, required),
+	(6, credit, required),
+	(8, amount_msat, option),
+	(10, payment_hash, option),
+	(12, prev_channel_id, option),
+	(14, next_channel_id, option),
+	(16, txid, option),
+	(18, channel_id, option),
+	(20, id, option),
+});
+
+/// A channel we funded, tracked to attribute the fees of its closing transactions to us.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct FundedChannel {
+	funding_txo: OutPoint,
+	value_satoshis: u64,
+	/// The latest fee proposed in a `closing_signed` message, which is the fee of the closing
+	/// transaction once the channel has been closed cooperatively.
+	closing_fee_satoshis: Option<u64>,
+	/// The timestamp of the [`LedgerEntryKind::ChannelClosed`] entry, once closed.
+	closed_at: Option<u64>,
+}
+
+impl_writeable_tlv_based!(FundedChannel, {
+	(0, funding_txo, required),
+	(2, value_satoshis, required),
+	(4, closing_fee_satoshis, option),
+	(6, closed_at, option),
+});
+
+/// A list of [`LedgerEntry`]s built from the [`Event`] stream, see the [module-level
+/// documentation] for more.
+///
+/// [module-level documentation]: self
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Ledger {
+	entries: Vec<LedgerEntry>,
+	/// The [`LedgerEntry::id`]s of `entries`.
+	entry_ids: HashSet<[u8; 32]>,
+	/// The value of the outbound channels which we were asked to fund, by temporary channel id.
+	pending_fundings: HashMap<[u8; 32], u64>,
+	/// The channels we funded, by channel id.
+	funded_channels: HashMap<[u8; 32], FundedChannel>,
+}
+
+impl Writeable for Ledger {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
+		write_tlv_fields!(writer, {
+			(0, self.entries, vec_type),
+			(1, self.pending_fundings, required),
+			(3, self.funded_channels, required),
+		});
+		Ok(())
+	}
+}
+
+impl Readable for Ledger {
+	fn read<R: io::Read>(reader: &mut R) -> Result<Self, DecodeError> {
+		let mut entries = Vec::new();
+		let mut pending_fundings = None;
+		let mut funded_channels = None;
+		read_tlv_fields!(reader, {
+			(0, entries, vec_type),
+			(1, pending_fundings, option),
+			(3, funded_channels, option),
+		});
+		let entry_ids = entries.iter().filter_map(|entry: &LedgerEntry| entry.id).collect();
+		Ok(Self {
+			entries, entry_ids,
+			pending_fundings: pending_fundings.unwrap_or_else(HashMap::new),
+			funded_channels: funded_channels.unwrap_or_else(HashMap::new),
+		})
+	}
+}
+
+/// Computes the [`LedgerEntry::id`] of an entry of the given kind, identified by `data`.
+fn entry_id(kind: LedgerEntryKind, data: &[&[u8]]) -> [u8; 32] {
+	let mut engine = Sha256::engine();
+	engine.input(kind.as_str().as_bytes());
+	for item in data {
+		engine.input(item);
+	}
+	Sha256::from_engine(engine).into_inner()
+}
+
+impl Ledger {
+	/// Creates a new, empty, [`Ledger`].
+	pub fn new() -> Self {
+		Self {
+			entries: Vec::new(), entry_ids: HashSet::new(), pending_fundings: HashMap::new(),
+			funded_channels: HashMap::new(),
+		}
+	}
+
+	/// Records the [`LedgerEntry`]s for the given event, if any, at the given `timestamp`
+	/// (typically seconds since the UNIX epoch).
+	///
+	/// Events which do not move funds between accounts are ignored, as are events which were
+	/// already recorded.
+	pub fn record_event(&mut self, event: &Event, timestamp: u64) {
+		let entry = |kind, debit, credit, amount_msat| LedgerEntry {
+			timestamp, kind, debit, credit, amount_msat, payment_hash: None, prev_channel_id: None,
+			next_channel_id: None, txid: None, channel_id: None, id: None,
+		};
+		match event {
+			Event::PaymentPathSuccessful { payment_id, payment_hash, path } => {
+				let mut path_data = Vec::with_capacity(path.hops.len() * 16);
+				for hop in path.hops.iter() {
+					path_data.extend_from_slice(&hop.short_channel_id.to_be_bytes());
+					path_data.extend_from_slice(&hop.fee_msat.to_be_bytes());
+				}
+				self.push_entry(LedgerEntry {
+					payment_hash: *payment_hash,
+					id: Some(entry_id(LedgerEntryKind::PaymentSent, &[&payment_id.0[..], &path_data[..]])),
+					..entry(LedgerEntryKind::PaymentSent, LedgerAccount::External, LedgerAccount::Lightning, Some(path.final_value_msat()))
+				});
+			},
+			Event::PaymentSent { payment_hash, fee_paid_msat, .. } => {
+				if *fee_paid_msat != Some(0) {
+					self.push_entry(LedgerEntry {
+						payment_hash: Some(*payment_hash),
+						id: Some(entry_id(LedgerEntryKind::FeePaid, &[&payment_hash.0[..]])),
+						..entry(LedgerEntryKind::FeePaid, LedgerAccount::RoutingFeesPaid, LedgerAccount::Lightning, *fee_paid_msat)
+					});
+				}
+			},
+			Event::PaymentClaimed { payment_hash, amount_msat, .. } => {
+				self.push_entry(LedgerEntry {
+					payment_hash: Some(*payment_hash),
+					id: Some(entry_id(LedgerEntryKind::PaymentReceived, &[&payment_hash.0[..]])),
+					..entry(LedgerEntryKind::PaymentReceived, LedgerAccount::Lightning, LedgerAccount::External, Some(*amount_msat))
+				});
+			},
+			Event::PaymentForwarded { prev_channel_id, next_channel_id, fee_earned_msat, .. } => {
+				self.push_entry(LedgerEntry {
+					prev_channel_id: *prev_channel_id,
+					next_channel_id: *next_channel_id,
+					..entry(LedgerEntryKind::RoutingFeeEarned, LedgerAccount::Lightning, LedgerAccount::RoutingFeesEarned, *fee_earned_msat)
+				});
+			},
+			Event::SpendableOutputs { outputs } => {
+				for output in outputs {
+					let (outpoint, value) = match output {
+						SpendableOutputDescriptor::StaticOutput { outpoint, output } => (outpoint, output.value),
+						SpendableOutputDescriptor::DelayedPaymentOutput(descriptor) =>
+							(&descriptor.outpoint, descriptor.output.value),
+						SpendableOutputDescriptor::StaticPaymentOutput(descriptor) =>
+							(&descriptor.outpoint, descriptor.output.value),
+					};
+					self.push_entry(LedgerEntry {
+						txid: Some(outpoint.txid),
+						id: Some(entry_id(LedgerEntryKind::OnChainSweep, &[&outpoint.encode()[..]])),
+						..entry(LedgerEntryKind::OnChainSweep, LedgerAccount::OnChain, LedgerAccount::Lightning, Some(value * 1000))
+					});
+				}
+			},
+			Event::FundingGenerationReady { temporary_channel_id, channel_value_satoshis, .. } => {
+				self.pending_fundings.insert(*temporary_channel_id, *channel_value_satoshis);
+			},
+			Event::ChannelPending { channel_id, former_temporary_channel_id: Some(temporary_channel_id), funding_txo, .. } => {
+				if let Some(value_satoshis) = self.pending_fundings.remove(temporary_channel_id) {
+					self.funded_channels.entry(*channel_id).or_insert(FundedChannel {
+						funding_txo: *funding_txo, value_satoshis, closing_fee_satoshis: None, closed_at: None,
+					});
+					self.push_entry(LedgerEntry {
+						txid: Some(funding_txo.txid),
+						channel_id: Some(*channel_id),
+						id: Some(entry_id(LedgerEntryKind::ChannelFunded, &[&channel_id[..]])),
+						..entry(LedgerEntryKind::ChannelFunded, LedgerAccount::Lightning, LedgerAccount::OnChain, Some(value_satoshis * 1000))
+					});
+				}
+			},
+			Event::DiscardFunding { channel_id, transaction } => {
+				if let Some(funded_channel) = self.funded_channels.get(channel_id) {
+					let amount_msat = funded_channel.value_satoshis * 1000;
+					self.push_entry(LedgerEntry {
+						txid: Some(transaction.txid()),
+						channel_id: Some(*channel_id),
+						id: Some(entry_id(LedgerEntryKind::ChannelFundingDiscarded, &[&channel_id[..]])),
+						..entry(LedgerEntryKind::ChannelFundingDiscarded, LedgerAccount::OnChain, LedgerAccount::Lightning, Some(amount_msat))
+					});
+				}
+			},
+			Event::ChannelProgressed { channel_id, progress: ChannelProgress::ClosingFeeProposed { fee_satoshis, .. }, .. } => {
+				if let Some(funded_channel) = self.funded_channels.get_mut(channel_id) {
+					funded_channel.closing_fee_satoshis = Some(*fee_satoshis);
+				}
+			},
+			Event::ChannelClosed { channel_id, reason, .. } => {
+				self.push_entry(LedgerEntry {
+					channel_id: Some(*channel_id),
+					id: Some(entry_id(LedgerEntryKind::ChannelClosed, &[&channel_id[..]])),
+					..entry(LedgerEntryKind::ChannelClosed, LedgerAccount::OnChain, LedgerAccount::Lightning, None)
+				});
+				let closing_fee_satoshis = match self.funded_channels.get_mut(channel_id) {
+					Some(funded_channel) => {
+						if funded_channel.closed_at.is_none() {
+							funded_channel.closed_at = Some(timestamp);
+						}
+						funded_channel.closing_fee_satoshis
+					},
+					None => None,
+				};
+				if let (ClosureReason::CooperativeClosure, Some(fee_satoshis)) = (reason, closing_fee_satoshis) {
+					self.push_entry(LedgerEntry {
+						channel_id: Some(*channel_id),
+						id: Some(entry_id(LedgerEntryKind::OnChainFeePaid, &[&b"closing"[..], &channel_id[..]])),
+						..entry(LedgerEntryKind::OnChainFeePaid, LedgerAccount::OnChainFeesPaid, LedgerAccount::Lightning, Some(fee_satoshis * 1000))
+					});
+				}
+			},
+			Event::BumpTransaction(BumpTransactionEvent::ChannelClose { commitment_tx, commitment_tx_fee_satoshis, .. }) => {
+				// The commitment transaction fee is paid by the channel funder.
+				let funding_outpoint = commitment_tx.input.first().map(|input| input.previous_output);
+				let funded_channel_id = self.funded_channels.iter()
+					.find(|(_, funded_channel)| Some(funded_channel.funding_txo.into_bitcoin_outpoint()) == funding_outpoint)
+					.map(|(channel_id, _)| *channel_id);
+				if let Some(channel_id) = funded_channel_id {
+					let txid = commitment_tx.txid();
+					self.push_entry(LedgerEntry {
+						txid: Some(txid),
+						channel_id: Some(channel_id),
+						id: Some(entry_id(LedgerEntryKind::OnChainFeePaid, &[&txid[..]])),
+						..entry(LedgerEntryKind::OnChainFeePaid, LedgerAccount::OnChainFeesPaid, LedgerAccount::Lightning, Some(commitment_tx_fee_satoshis * 1000))
+					});
+				}
+			},
+			_ => {},
+		}
+	}
+
+	/// Records the fee our on-chain wallet paid for the given transaction at the given
+	/// `timestamp`, for transactions LDK does not know the fee of, such as funding transactions,
+	/// transactions sweeping [`Event::SpendableOutputs`], or anchor fee-bumping transactions.
+	///
+	/// Fees already recorded for the same transaction are ignored.
+	pub fn record_on_chain_fee(&mut self, txid: Txid, fee_satoshis: u64, timestamp: u64) {
+		self.push_entry(LedgerEntry {
+			timestamp, kind: LedgerEntryKind::OnChainFeePaid, debit: LedgerAccount::OnChainFeesPaid,
+			credit: LedgerAccount::OnChain, amount_msat: Some(fee_satoshis * 1000), payment_hash: None,
+			prev_channel_id: None, next_channel_id: None, txid: Some(txid), channel_id: None,
+			id: Some(entry_id(LedgerEntryKind::OnChainFeePaid, &[&txid[..]])),
+		});
+	}
+
+	/// Adds the given entry, unless an entry with the same [`LedgerEntry::id`] was recorded.
+	fn push_entry(&mut self, entry: LedgerEntry) {
+		if let Some(id) = entry.id {
+			if !self.entry_ids.insert(id) { return; }
+		}
+		self.entries.push(entry);
+	}
+
+	/// The entries recorded so far, in the order they were recorded.
+	pub fn entries(&self) -> &[LedgerEntry] {
+		&self.entries
+	}
+
+	/// Removes all entries recorded at or before the given `timestamp`, e.g. once they have been
+	/// exported.
+	///
+	/// Events replayed after their entries were pruned are recorded again, thus entries should only
+	/// be pruned once no such replay is possible anymore.
+	pub fn prune_entries(&mut self, timestamp: u64) {
+		let entry_ids = &mut self.entry_ids;
+		self.entries.retain(|entry| {
+			if entry.timestamp > timestamp { return true; }
+			if let Some(id) = entry.id { entry_ids.remove(&id); }
+			false
+		});
+		self.funded_channels.retain(|_, funded_channel| match funded_channel.closed_at {
+			Some(closed_at) => closed_at > timestamp,
+			None => true,
+		});
+	}
+
+	/// Exports the entries as CSV, with a header row. Optional fields are left empty if unset.
+	pub fn to_csv(&self) -> String {
+		let mut res = String::from("timestamp,kind,debit,credit,amount_msat,payment_hash,prev_channel_id,next_channel_id,txid,channel_id\n");
+		for entry in self.entries.iter() {
+			let fields = entry.optional_fields();
+			let _ = write!(res, "{},{},{},{}", entry.timestamp, entry.kind.as_str(),
+				entry.debit.as_str(), entry.credit.as_str());
+			for field in fields.iter() {
+				let _ = write!(res, ",{}", field.as_deref().unwrap_or(""));
+			}
+			res.push('\n');
+		}
+		res
+	}
+
+	/// Exports the entries as a JSON array of objects. Optional fields are `null` if unset.
+	pub fn to_json(&self) -> String {
+		let mut res = String::from("[");
+		for (idx, entry) in self.entries.iter().enumerate() {
+			if idx != 0 { res.push(','); }
+			let _ = write!(res, "{{\"timestamp\":{},\"kind\":\"{}\",\"debit\":\"{}\",\"credit\":\"{}\"",
+				entry.timestamp, entry.kind.as_str(), entry.debit.as_str(), entry.credit.as_str());
+			let names = ["amount_msat", "payment_hash", "prev_channel_id", "next_channel_id", "txid", "channel_id"];
+			for (field_idx, (name, field)) in names.iter().zip(entry.optional_fields().iter()).enumerate() {
+				match field {
+					// The amount is a number, the remaining fields hex strings.
+					Some(value) if field_idx == 0 => { let _ = write!(res, ",\"{}\":{}", name, value); },
+					Some(value) => { let _ = write!(res, ",\"{}\":\"{}\"", name, value); },
+					None => { let _ = write!(res, ",\"{}\":null", name); },
+				}
+			}
+			res.push('}');
+		}
+		res.push(']');
+		res
+	}
+}
+
+impl LedgerEntry {
+	/// The amount, hex-encoded payment hash, previous and next channel ids, txid, and channel id,
+	/// in that order.
+	fn optional_fields(&self) -> [Option<String>; 6] {
+		[
+			self.amount_msat.map(|amount_msat| amount_msat.to_string()),
+			self.payment_hash.map(|hash| hash.0.to_hex()),
+			self.prev_channel_id.map(|id| id.to_hex()),
+			self.next_channel_id.map(|id| id.to_hex()),
+			self.txid.map(|txid| txid.to_string()),
+			self.channel_id.map(|id| id.to_hex()),
+		]
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Ledger, LedgerAccount, LedgerEntryKind};
+
+	use bitcoin::blockdata::script::Script;
+	use bitcoin::blockdata::transaction::{PackedLockTime, Transaction, TxIn, TxOut};
+	use bitcoin::hash_types::Txid;
+	use bitcoin::hashes::Hash;
+	use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+
+	use crate::chain::transaction::OutPoint;
+	use crate::events::{ChannelProgress, ClosureReason, Event, PaymentPurpose};
+	use crate::events::bump_transaction::{AnchorDescriptor, BumpTransactionEvent};
+	use crate::ln::{PaymentHash, PaymentPreimage};
+	use crate::sign::SpendableOutputDescriptor;
+	use crate::util::ser::{Readable, Writeable};
+
+	use crate::io::Cursor;
+
+	fn dummy_ledger() -> Ledger {
+		let mut ledger = Ledger::new();
+		ledger.record_event(&Event::PaymentClaimed {
+			receiver_node_id: None,
+			payment_hash: PaymentHash([1; 32]),
+			amount_msat: 10_000,
+			purpose: PaymentPurpose::SpontaneousPayment(PaymentPreimage([2; 32])),
+		}, 1);
+		ledger.record_event(&Event::PaymentSent {
+			payment_id: None,
+			payment_preimage: PaymentPreimage([3; 32]),
+			payment_hash: PaymentHash([4; 32]),
+			fee_paid_msat: Some(100),
+			attempts: Some(1),
+		}, 2);
+		ledger.record_event(&Event::PaymentForwarded {
+			prev_channel_id: Some([5; 32]),
+			next_channel_id: Some([6; 32]),
+			fee_earned_msat: Some(1_000),
+			claim_from_onchain_tx: false,
+			outbound_amount_forwarded_msat: Some(100_000),
+		}, 3);
+		ledger.record_event(&Event::SpendableOutputs {
+			outputs: vec![SpendableOutputDescriptor::StaticOutput {
+				outpoint: OutPoint { txid: Txid::from_slice(&[7; 32]).unwrap(), index: 0 },
+				output: TxOut { value: 50_000, script_pubkey: Script::new() },
+			}],
+		}, 4);
+		ledger.record_event(&Event::PendingHTLCsForwardable { time_forwardable: core::time::Duration::from_secs(1) }, 5);
+		ledger
+	}
+
+	#[test]
+	fn records_events() {
+		let ledger = dummy_ledger();
+		let entries = ledger.entries();
+		assert_eq!(entries.len(), 4);
+
+		assert_eq!(entries[0].kind, LedgerEntryKind::PaymentReceived);
+		assert_eq!((entries[0].debit, entries[0].credit), (LedgerAccount::Lightning, LedgerAccount::External));
+		assert_eq!(entries[0].amount_msat, Some(10_000));
+		assert_eq!(entries[0].payment_hash, Some(PaymentHash([1; 32])));
+
+		assert_eq!(entries[1].kind, LedgerEntryKind::FeePaid);
+		assert_eq!((entries[1].debit, entries[1].credit), (LedgerAccount::RoutingFeesPaid, LedgerAccount::Lightning));
+		assert_eq!(entries[1].amount_msat, Some(100));
+
+		assert_eq!(entries[2].kind, LedgerEntryKind::RoutingFeeEarned);
+		assert_eq!((entries[2].debit, entries[2].credit), (LedgerAccount::Lightning, LedgerAccount::RoutingFeesEarned));
+		assert_eq!((entries[2].prev_channel_id, entries[2].next_channel_id), (Some([5; 32]), Some([6; 32])));
+
+		assert_eq!(entries[3].kind, LedgerEntryKind::OnChainSweep);
+		assert_eq!((entries[3].debit, entries[3].credit), (LedgerAccount::OnChain, LedgerAccount::Lightning));
+		assert_eq!(entries[3].amount_msat, Some(50_000_000));
+		assert_eq!(entries[3].txid, Some(Txid::from_slice(&[7; 32]).unwrap()));
+
+		let encoded_ledger = ledger.encode();
+		let read_ledger: Ledger = Readable::read(&mut Cursor::new(&encoded_ledger)).unwrap();
+		assert_eq!(read_ledger, ledger);
+
+		let mut pruned_ledger = ledger;
+		pruned_ledger.prune_entries(2);
+		assert_eq!(pruned_ledger.entries().len(), 2);
+	}
+
+	#[test]
+	fn ignores_replayed_events() {
+		let mut ledger = dummy_ledger();
+
+		// Events are replayed after a restart, possibly after the ledger was persisted.
+		let mut read_ledger: Ledger = Readable::read(&mut Cursor::new(&ledger.encode())).unwrap();
+		for replayed_ledger in [&mut ledger, &mut read_ledger].iter_mut() {
+			replayed_ledger.record_event(&Event::PaymentClaimed {
+				receiver_node_id: None,
+				payment_hash: PaymentHash([1; 32]),
+				amount_msat: 10_000,
+				purpose: PaymentPurpose::SpontaneousPayment(PaymentPreimage([2; 32])),
+			}, 10);
+			replayed_ledger.record_event(&Event::SpendableOutputs {
+				outputs: vec![SpendableOutputDescriptor::StaticOutput {
+					outpoint: OutPoint { txid: Txid::from_slice(&[7; 32]).unwrap(), index: 0 },
+					output: TxOut { value: 50_000, script_pubkey: Script::new() },
+				}],
+			}, 10);
+			assert_eq!(replayed_ledger.entries().len(), 4);
+		}
+
+		// Once pruned, the same events are recorded again.
+		ledger.prune_entries(1);
+		ledger.record_event(&Event::PaymentClaimed {
+			receiver_node_id: None,
+			payment_hash: PaymentHash([1; 32]),
+			amount_msat: 10_000,
+			purpose: PaymentPurpose::SpontaneousPayment(PaymentPreimage([2; 32])),
+		}, 10);
+		assert_eq!(ledger.entries().len(), 4);
+	}
+
+	#[test]
+	fn records_events_without_fees() {
+		let mut ledger = Ledger::new();
+		ledger.record_event(&Event::PaymentSent {
+			payment_id: None,
+			payment_preimage: PaymentPreimage([3; 32]),
+			payment_hash: PaymentHash([4; 32]),
+			fee_paid_msat: None,
+			attempts: None,
+		}, 1);
+		ledger.record_event(&Event::PaymentForwarded {
+			prev_channel_id: Some([5; 32]),
+			next_channel_id: Some([6; 32]),
+			fee_earned_msat: None,
+			claim_from_onchain_tx: false,
+			outbound_amount_forwarded_msat: None,
+		}, 2);
+		let entries = ledger.entries();
+		assert_eq!(entries.len(), 2);
+		assert_eq!((entries[0].kind, entries[0].amount_msat), (LedgerEntryKind::FeePaid, None));
+		assert_eq!((entries[1].kind, entries[1].amount_msat), (LedgerEntryKind::RoutingFeeEarned, None));
+		assert_eq!(ledger.to_csv().lines().nth(1), Some(format!(
+			"1,fee_paid,routing_fees_paid,lightning,,{},,,,", "04".repeat(32)).as_str()));
+	}
+
+	#[test]
+	fn records_channel_funding_and_closes() {
+		let secp_ctx = Secp256k1::new();
+		let counterparty_node_id = PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[42; 32]).unwrap());
+		let funding_txo = OutPoint { txid: Txid::from_slice(&[8; 32]).unwrap(), index: 1 };
+		let (temporary_channel_id, channel_id) = ([9; 32], [10; 32]);
+
+		let mut ledger = Ledger::new();
+		ledger.record_event(&Event::FundingGenerationReady {
+			temporary_channel_id, counterparty_node_id, channel_value_satoshis: 100_000,
+			output_script: Script::new(), user_channel_id: 0,
+		}, 1);
+		let channel_pending = Event::ChannelPending {
+			channel_id, user_channel_id: 0, former_temporary_channel_id: Some(temporary_channel_id),
+			counterparty_node_id, funding_txo,
+		};
+		ledger.record_event(&channel_pending, 2);
+		ledger.record_event(&channel_pending, 3);
+		ledger.record_on_chain_fee(funding_txo.txid, 500, 3);
+		ledger.record_on_chain_fee(funding_txo.txid, 500, 4);
+
+		// Channels we did not fund are not recorded as funded, but their closes are.
+		let inbound_channel_id = [11; 32];
+		ledger.record_event(&Event::ChannelPending {
+			channel_id: inbound_channel_id, user_channel_id: 0,
+			former_temporary_channel_id: Some([12; 32]), counterparty_node_id,
+			funding_txo: OutPoint { txid: Txid::from_slice(&[13; 32]).unwrap(), index: 0 },
+		}, 5);
+		ledger.record_event(&Event::ChannelClosed {
+			channel_id: inbound_channel_id, user_channel_id: 0,
+			reason: ClosureReason::CooperativeClosure, force_close_feerates: None,
+		}, 6);
+
+		for fee_satoshis in [1_000, 800].iter() {
+			ledger.record_event(&Event::ChannelProgressed {
+				channel_id, counterparty_node_id, user_channel_id: 0,
+				progress: ChannelProgress::ClosingFeeProposed { fee_satoshis: *fee_satoshis, proposed_by_counterparty: false },
+			}, 7);
+		}
+		ledger.record_event(&Event::ChannelClosed {
+			channel_id, user_channel_id: 0, reason: ClosureReason::CooperativeClosure,
+			force_close_feerates: None,
+		}, 8);
+
+		let entries = ledger.entries();
+		assert_eq!(entries.len(), 5);
+
+		assert_eq!(entries[0].kind, LedgerEntryKind::ChannelFunded);
+		assert_eq!((entries[0].debit, entries[0].credit), (LedgerAccount::Lightning, LedgerAccount::OnChain));
+		assert_eq!((entries[0].amount_msat, entries[0].txid), (Some(100_000_000), Some(funding_txo.txid)));
+		assert_eq!(entries[0].channel_id, Some(channel_id));
+
+		assert_eq!(entries[1].kind, LedgerEntryKind::OnChainFeePaid);
+		assert_eq!((entries[1].debit, entries[1].credit), (LedgerAccount::OnChainFeesPaid, LedgerAccount::OnChain));
+		assert_eq!((entries[1].amount_msat, entries[1].txid), (Some(500_000), Some(funding_txo.txid)));
+
+		assert_eq!((entries[2].kind, entries[2].channel_id), (LedgerEntryKind::ChannelClosed, Some(inbound_channel_id)));
+		assert_eq!(entries[2].amount_msat, None);
+		assert_eq!((entries[3].kind, entries[3].channel_id), (LedgerEntryKind::ChannelClosed, Some(channel_id)));
+
+		assert_eq!(entries[4].kind, LedgerEntryKind::OnChainFeePaid);
+		assert_eq!((entries[4].debit, entries[4].credit), (LedgerAccount::OnChainFeesPaid, LedgerAccount::Lightning));
+		assert_eq!((entries[4].amount_msat, entries[4].channel_id), (Some(800_000), Some(channel_id)));
+
+		// The funded channel is still tracked across a restart, attributing its commitment
+		// transaction fee to us.
+		let mut ledger: Ledger = Readable::read(&mut Cursor::new(&ledger.encode())).unwrap();
+		let commitment_tx = Transaction {
+			version: 2, lock_time: PackedLockTime::ZERO,
+			input: vec![TxIn { previous_output: funding_txo.into_bitcoin_outpoint(), ..Default::default() }],
+			output: vec![],
+		};
+		ledger.record_event(&Event::BumpTransaction(BumpTransactionEvent::ChannelClose {
+			package_target_feerate_sat_per_1000_weight: 253,
+			commitment_tx: commitment_tx.clone(),
+			commitment_tx_fee_satoshis: 300,
+			anchor_descriptor: AnchorDescriptor {
+				channel_keys_id: [0; 32], channel_value_satoshis: 100_000,
+				outpoint: bitcoin::OutPoint { txid: commitment_tx.txid(), vout: 0 },
+			},
+			pending_htlcs: vec![],
+		}), 9);
+		assert_eq!(ledger.entries().len(), 6);
+		assert_eq!(ledger.entries()[5].kind, LedgerEntryKind::OnChainFeePaid);
+		assert_eq!((ledger.entries()[5].amount_msat, ledger.entries()[5].txid), (Some(300_000), Some(commitment_tx.txid())));
+
+		// Once its close was pruned, the channel is no longer tracked.
+		ledger.prune_entries(8);
+		assert_eq!(ledger.entries().len(), 1);
+		assert!(ledger.funded_channels.is_empty());
+	}
+
+	#[test]
+	fn exports_entries() {
+		let mut ledger = dummy_ledger();
+		ledger.prune_entries(2);
+		let txid = Txid::from_slice(&[7; 32]).unwrap();
+		let channel_ids = ("05".repeat(32), "06".repeat(32));
+
+		assert_eq!(ledger.to_csv(), format!(
+			"timestamp,kind,debit,credit,amount_msat,payment_hash,prev_channel_id,next_channel_id,txid,channel_id\n\
+			3,routing_fee_earned,lightning,routing_fees_earned,1000,,{},{},,\n\
+			4,on_chain_sweep,on_chain,lightning,50000000,,,,{},\n", channel_ids.0, channel_ids.1, txid));
+
+		assert_eq!(ledger.to_json(), format!(
+			"[{{\"timestamp\":3,\"kind\":\"routing_fee_earned\",\"debit\":\"lightning\",\"credit\":\"routing_fees_earned\",\"amount_msat\":1000,\"payment_hash\":null,\"prev_channel_id\":\"{}\",\"next_channel_id\":\"{}\",\"txid\":null,\"channel_id\":null}},\
+			{{\"timestamp\":4,\"kind\":\"on_chain_sweep\",\"debit\":\"on_chain\",\"credit\":\"lightning\",\"amount_msat\":50000000,\"payment_hash\":null,\"prev_channel_id\":null,\"next_channel_id\":null,\"txid\":\"{}\",\"channel_id\":null}}]",
+			channel_ids.0, channel_ids.1, txid));
+	}
+}
@@ -10,17 +10,20 @@
 //! Log traits live here, which are called throughout the library to provide useful information for
 //! debugging purposes.
 //!
-//! There is currently 2 ways to filter log messages. First one, by using compilation features, e.g "max_level_off".
+//! There is currently 3 ways to filter log messages. First one, by using compilation features, e.g "max_level_off".
 //! The second one, client-side by implementing check against Record Level field.
+//! The third one, by wrapping a Logger in a [`ModuleLevelFilter`], which allows configuring a
+//! different level for each module.
 //! Each module may have its own Logger or share one.
 
 use bitcoin::secp256k1::PublicKey;
 
 use core::cmp;
 use core::fmt;
+use core::ops::Deref;
 
-#[cfg(c_bindings)]
-use crate::prelude::*; // Needed for String
+use crate::ln::PaymentHash;
+use crate::prelude::*; // Needed for String and Vec
 
 static LOG_LEVEL_NAMES: [&'static str; 6] = ["GOSSIP", "TRACE", "DEBUG", "INFO", "WARN", "ERROR"];
 
@@ -95,6 +98,17 @@ impl Level {
 pub struct Record<'a> {
 	/// The verbosity level of the message.
 	pub level: Level,
+	/// The node id of the peer pertaining to the logged record.
+	///
+	/// Note that in some cases a [`Self::channel_id`] may be filled in but this may still be
+	/// `None`, depending on if the peer information is readily available in LDK when the log is
+	/// generated.
+	pub peer_id: Option<PublicKey>,
+	/// The channel id of the channel pertaining to the logged record. May be a temporary id before
+	/// the channel has been funded.
+	pub channel_id: Option<[u8; 32]>,
+	/// The payment hash of the payment pertaining to the logged record, if any.
+	pub payment_hash: Option<PaymentHash>,
 	#[cfg(not(c_bindings))]
 	/// The message body.
 	pub args: fmt::Arguments<'a>,
@@ -119,9 +133,16 @@ impl<'a> Record<'a> {
 	///
 	/// This is not exported to bindings users as fmt can't be used in C
 	#[inline]
-	pub fn new(level: Level, args: fmt::Arguments<'a>, module_path: &'static str, file: &'static str, line: u32) -> Record<'a> {
+	pub fn new(
+		level: Level, peer_id: Option<PublicKey>, channel_id: Option<[u8; 32]>,
+		args: fmt::Arguments<'a>, module_path: &'static str, file: &'static str, line: u32,
+		payment_hash: Option<PaymentHash>,
+	) -> Record<'a> {
 		Record {
 			level,
+			peer_id,
+			channel_id,
+			payment_hash,
 			#[cfg(not(c_bindings))]
 			args,
 			#[cfg(c_bindings)]
@@ -141,6 +162,102 @@ pub trait Logger {
 	fn log(&self, record: &Record);
 }
 
+/// A [`Logger`] wrapper which adds the given context to each [`Record`] logged through it, unless
+/// the [`Record`] already has its own value for a given field.
+pub struct WithContext<'a, L: Deref> where L::Target: Logger {
+	/// The logger to delegate to after adding context to the record.
+	logger: &'a L,
+	/// The node id of the peer pertaining to the logged record.
+	peer_id: Option<PublicKey>,
+	/// The channel id of the channel pertaining to the logged record.
+	channel_id: Option<[u8; 32]>,
+	/// The payment hash of the payment pertaining to the logged record.
+	payment_hash: Option<PaymentHash>,
+}
+
+impl<'a, L: Deref> Logger for WithContext<'a, L> where L::Target: Logger {
+	fn log(&self, record: &Record) {
+		let mut record = record.clone();
+		if record.peer_id.is_none() {
+			record.peer_id = self.peer_id;
+		}
+		if record.channel_id.is_none() {
+			record.channel_id = self.channel_id;
+		}
+		if record.payment_hash.is_none() {
+			record.payment_hash = self.payment_hash;
+		}
+		self.logger.log(&record)
+	}
+}
+
+impl<'a, L: Deref> WithContext<'a, L> where L::Target: Logger {
+	/// Wraps the given logger, providing additional context to any logged records.
+	pub fn from(logger: &'a L, peer_id: Option<PublicKey>, channel_id: Option<[u8; 32]>, payment_hash: Option<PaymentHash>) -> Self {
+		WithContext { logger, peer_id, channel_id, payment_hash }
+	}
+}
+
+/// A [`Logger`] wrapper which drops any [`Record`] less severe than the [`Level`] configured for
+/// the module it was logged from.
+///
+/// This allows, e.g., suppressing high-volume gossip logging while keeping the channel state
+/// machine logs at [`Level::Debug`]:
+/// ```
+/// # use lightning::util::logger::{Level, Logger, ModuleLevelFilter, Record};
+/// # struct StdoutLogger;
+/// # impl Logger for StdoutLogger { fn log(&self, record: &Record) { println!("{}", record.args); } }
+/// let logger = ModuleLevelFilter::new(&StdoutLogger, Level::Debug)
+/// 	.with_module_level("lightning::routing::gossip", Level::Warn)
+/// 	.with_module_level("lightning::ln::peer_handler", Level::Info);
+/// ```
+///
+/// This is not exported to bindings users as it requires a `Deref`-able logger.
+pub struct ModuleLevelFilter<L: Deref> where L::Target: Logger {
+	logger: L,
+	default_level: Level,
+	/// Module path prefixes along with the minimum level logged for them, sorted by decreasing
+	/// prefix length such that the most specific prefix is found first.
+	module_levels: Vec<(String, Level)>,
+}
+
+impl<L: Deref> ModuleLevelFilter<L> where L::Target: Logger {
+	/// Wraps the given logger, only logging records at `default_level` or above for any module
+	/// without a more specific level set via [`Self::with_module_level`].
+	pub fn new(logger: L, default_level: Level) -> Self {
+		ModuleLevelFilter { logger, default_level, module_levels: Vec::new() }
+	}
+
+	/// Sets the minimum level logged for the module at `module_path` and all of its submodules,
+	/// e.g. `"lightning::routing"`, overriding any level set for a parent module.
+	pub fn with_module_level(mut self, module_path: &str, level: Level) -> Self {
+		self.module_levels.retain(|(path, _)| path != module_path);
+		self.module_levels.push((module_path.to_owned(), level));
+		self.module_levels.sort_unstable_by(|(a, _), (b, _)| b.len().cmp(&a.len()));
+		self
+	}
+
+	/// Gets the minimum level which will be logged for records from the given module.
+	pub fn level_for_module(&self, module_path: &str) -> Level {
+		for (path, level) in self.module_levels.iter() {
+			if module_path.starts_with(path.as_str()) &&
+				(module_path.len() == path.len() || module_path[path.len()..].starts_with("::"))
+			{
+				return *level;
+			}
+		}
+		self.default_level
+	}
+}
+
+impl<L: Deref> Logger for ModuleLevelFilter<L> where L::Target: Logger {
+	fn log(&self, record: &Record) {
+		if record.level >= self.level_for_module(record.module_path) {
+			self.logger.log(record)
+		}
+	}
+}
+
 /// Wrapper for logging a [`PublicKey`] in hex format.
 ///
 /// This is not exported to bindings users as fmt can't be used in C
@@ -171,9 +288,12 @@ impl<'a> core::fmt::Display for DebugBytes<'a> {
 
 #[cfg(test)]
 mod tests {
-	use crate::util::logger::{Logger, Level};
+	use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+	use crate::ln::PaymentHash;
+	use crate::util::logger::{Logger, Level, ModuleLevelFilter, Record, WithContext};
 	use crate::util::test_utils::TestLogger;
-	use crate::sync::Arc;
+	use crate::sync::{Arc, Mutex};
+	use crate::prelude::*;
 
 	#[test]
 	fn test_level_show() {
@@ -248,4 +368,54 @@ mod tests {
 		assert!(Level::Gossip <= Level::Trace);
 		assert!(Level::Gossip <= Level::Gossip);
 	}
+
+	/// A logger which stores the context and module path of each record it is handed.
+	struct ContextCapturingLogger {
+		records: Mutex<Vec<(Option<PublicKey>, Option<[u8; 32]>, Option<PaymentHash>, &'static str)>>,
+	}
+
+	impl Logger for ContextCapturingLogger {
+		fn log(&self, record: &Record) {
+			self.records.lock().unwrap().push((record.peer_id, record.channel_id, record.payment_hash, record.module_path));
+		}
+	}
+
+	#[test]
+	fn test_logging_with_context() {
+		let logger = &ContextCapturingLogger { records: Mutex::new(Vec::new()) };
+		let peer_id = PublicKey::from_secret_key(&Secp256k1::new(), &SecretKey::from_slice(&[42; 32]).unwrap());
+		let context_logger = WithContext::from(&logger, Some(peer_id), Some([1; 32]), None);
+		log_info!(context_logger, "This is an info");
+
+		// Fields already set on the record are not overridden.
+		context_logger.log(&Record::new(Level::Info, None, Some([2; 32]), format_args!("This is an info"),
+			"module", "file", 1, Some(PaymentHash([3; 32]))));
+
+		assert_eq!(*logger.records.lock().unwrap(), vec![
+			(Some(peer_id), Some([1; 32]), None, module_path!()),
+			(Some(peer_id), Some([2; 32]), Some(PaymentHash([3; 32])), "module"),
+		]);
+	}
+
+	#[test]
+	fn test_module_level_filter() {
+		let logger = &ContextCapturingLogger { records: Mutex::new(Vec::new()) };
+		let filter = ModuleLevelFilter::new(logger, Level::Debug)
+			.with_module_level("lightning::routing", Level::Warn)
+			.with_module_level("lightning::routing::scoring", Level::Trace);
+		assert_eq!(filter.level_for_module("lightning::ln::channel"), Level::Debug);
+		assert_eq!(filter.level_for_module("lightning::routing"), Level::Warn);
+		assert_eq!(filter.level_for_module("lightning::routing::gossip"), Level::Warn);
+		assert_eq!(filter.level_for_module("lightning::routing::scoring"), Level::Trace);
+		assert_eq!(filter.level_for_module("lightning::routing_other"), Level::Debug);
+
+		for (module_path, level) in [
+			("lightning::ln::channel", Level::Debug), ("lightning::ln::channel", Level::Trace),
+			("lightning::routing::gossip", Level::Gossip), ("lightning::routing::gossip", Level::Error),
+		].iter() {
+			filter.log(&Record::new(*level, None, None, format_args!("test"), *module_path, "file", 1, None));
+		}
+		let logged_modules: Vec<_> = logger.records.lock().unwrap().iter().map(|record| record.3).collect();
+		assert_eq!(logged_modules, vec!["lightning::ln::channel", "lightning::routing::gossip"]);
+	}
 }
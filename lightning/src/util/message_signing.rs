@@ -89,6 +89,7 @@ pub fn verify(msg: &[u8], sig: &str, pk: &PublicKey) -> bool {
 #[cfg(test)]
 mod test {
     use core::str::FromStr;
+    use crate::sign::{KeysManager, NodeSigner, Recipient};
     use crate::util::message_signing::{sign, recover_pk, verify};
     use bitcoin::secp256k1::ONE_KEY;
     use bitcoin::secp256k1::{PublicKey, Secp256k1};
@@ -119,6 +120,17 @@ mod test {
         assert!(verify(message.as_bytes(), &sig, &pk))
     }
 
+    #[test]
+    fn test_node_signer_sign_message() {
+        let message = "proof of node ownership";
+        let keys_manager = KeysManager::new(&[42; 32], 42, 42);
+        let sig = keys_manager.sign_message(message.as_bytes()).unwrap();
+        let node_id = keys_manager.get_node_id(Recipient::Node).unwrap();
+
+        assert!(verify(message.as_bytes(), &sig, &node_id));
+        assert!(!verify(b"some other message", &sig, &node_id));
+    }
+
     #[test]
     fn test_verify_ground_truth_ish() {
         // There are no standard tests vectors for Sign/Verify, using the same tests vectors as c-lightning to see if they are compatible.
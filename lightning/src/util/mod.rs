@@ -20,10 +20,13 @@ pub mod ser;
 pub mod message_signing;
 pub mod invoice;
 pub mod persist;
+pub mod ledger;
 pub mod string;
 pub mod wakers;
+pub mod clock;
 
 pub(crate) mod atomic_counter;
+pub(crate) mod base32;
 pub(crate) mod byte_utils;
 pub(crate) mod chacha20;
 #[cfg(fuzzing)]
@@ -7,30 +7,88 @@
 //! This module contains a simple key-value store trait KVStorePersister that
 //! allows one to implement the persistence for [`ChannelManager`], [`NetworkGraph`],
 //! and [`ChannelMonitor`] all in one place.
+//!
+//! It also contains [`export_node_state`] and [`import_node_state`], which allow moving all of
+//! the above between machines or storage backends as a single [`NodeStateBundle`].
+//!
+//! [`MigrationRegistry`] upgrades the objects stored via a [`KVStorePersister`] when the format in
+//! which they are persisted changes in a way newer versions of LDK cannot read directly.
+//!
+//! Finally, [`RecordingFilter`] persists the registrations made via a [`chain::Filter`] so that
+//! they can be replayed into a filtering backend which was restarted.
+//!
+//! [`FencedStore`] guards a [`KVStorePersister`] against being written to by more than one running
+//! instance of a node at once, while [`EncryptedStore`] allows storing objects with an untrusted
+//! remote backend.
 
+use core::cmp;
 use core::ops::Deref;
-use bitcoin::hashes::hex::ToHex;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use bitcoin::blockdata::script::Script;
+use bitcoin::hash_types::{BlockHash, Txid};
+use bitcoin::hashes::{Hash, HashEngine, Hmac, HmacEngine};
+use bitcoin::hashes::hex::{FromHex, ToHex};
+use bitcoin::hashes::sha256::Hash as Sha256;
 use crate::io;
+use crate::io::Cursor;
+use crate::prelude::*;
 use crate::routing::scoring::WriteableScore;
 
 use crate::chain;
+use crate::chain::{Filter, WatchedOutput};
 use crate::chain::chaininterface::{BroadcasterInterface, FeeEstimator};
 use crate::chain::chainmonitor::{Persist, MonitorUpdateId};
 use crate::sign::{EntropySource, NodeSigner, WriteableEcdsaChannelSigner, SignerProvider};
 use crate::chain::transaction::OutPoint;
 use crate::chain::channelmonitor::{ChannelMonitor, ChannelMonitorUpdate};
-use crate::ln::channelmanager::ChannelManager;
+use crate::ln::channelmanager::{ChannelManager, apply_channel_manager_delta};
 use crate::routing::router::Router;
 use crate::routing::gossip::NetworkGraph;
-use super::{logger::Logger, ser::Writeable};
+use crate::util::config::UserConfig;
+use crate::ln::msgs::DecodeError;
+use crate::sync::Mutex;
+use crate::util::chacha20poly1305rfc::ChaCha20Poly1305RFC;
+use super::{logger::Logger, ser::{Readable, ReadableArgs, Writeable, Writer, WithoutLength}};
 
 /// Trait for a key-value store for persisting some writeable object at some key
 /// Implementing `KVStorePersister` provides auto-implementations for [`Persister`]
-/// and [`Persist`] traits.  It uses "manager", "network_graph",
+/// and [`Persist`] traits.  It uses "manager", "manager_delta", "network_graph",
 /// and "monitors/{funding_txo_id}_{funding_txo_index}" for keys.
 pub trait KVStorePersister {
 	/// Persist the given writeable using the provided key
 	fn persist<W: Writeable>(&self, key: &str, object: &W) -> io::Result<()>;
+
+	/// Returns true if this store has detected that another instance of the node has taken over
+	/// writing to it, see [`FencedStore`].
+	///
+	/// If a [`ChannelMonitor`] fails to be persisted while this returns true, the auto-implemented
+	/// [`Persist`] returns [`ChannelMonitorUpdateStatus::InProgress`] rather than
+	/// [`ChannelMonitorUpdateStatus::PermanentFailure`], freezing the channel instead of
+	/// broadcasting a commitment transaction which may already have been revoked by the other
+	/// instance.
+	///
+	/// [`ChannelMonitorUpdateStatus::InProgress`]: chain::ChannelMonitorUpdateStatus::InProgress
+	/// [`ChannelMonitorUpdateStatus::PermanentFailure`]: chain::ChannelMonitorUpdateStatus::PermanentFailure
+	fn is_fenced(&self) -> bool { false }
+
+	/// Returns true if the auto-implemented [`Persister::persist_manager_delta`] should write only
+	/// the changes to the [`ChannelManager`] since it was last persisted in full to "manager_delta",
+	/// rather than persisting it in full.
+	///
+	/// Stores opting in must be read back via [`read_channel_manager`], which merges the last
+	/// delta into the last full snapshot, as the object stored at "manager" alone may be stale.
+	fn persists_manager_deltas(&self) -> bool { false }
+}
+
+/// Trait for a key-value store which can read back the objects written via its
+/// [`KVStorePersister`] implementation, as required by [`MigrationRegistry::run`].
+pub trait KVStoreReader {
+	/// Returns the bytes stored at the given key, or `None` if no object is stored at it.
+	fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>>;
+
+	/// Returns all keys starting with the given prefix, e.g., "monitors/" to list the keys of all
+	/// persisted [`ChannelMonitor`]s.
+	fn list(&self, prefix: &str) -> io::Result<Vec<String>>;
 }
 
 /// Trait that handles persisting a [`ChannelManager`], [`NetworkGraph`], and [`WriteableScore`] to disk.
@@ -47,6 +105,15 @@ pub trait Persister<'a, M: Deref, T: Deref, ES: Deref, NS: Deref, SP: Deref, F:
 	/// Persist the given ['ChannelManager'] to disk, returning an error if persistence failed.
 	fn persist_manager(&self, channel_manager: &ChannelManager<M, T, ES, NS, SP, F, R, L>) -> Result<(), io::Error>;
 
+	/// Persist the changes to the given [`ChannelManager`] since it was last persisted in full via
+	/// [`Self::persist_manager`], returning an error if persistence failed. See
+	/// [`ChannelManager::encode_delta`] for more.
+	///
+	/// By default, this persists the [`ChannelManager`] in full.
+	fn persist_manager_delta(&self, channel_manager: &ChannelManager<M, T, ES, NS, SP, F, R, L>) -> Result<(), io::Error> {
+		self.persist_manager(channel_manager)
+	}
+
 	/// Persist the given [`NetworkGraph`] to disk, returning an error if persistence failed.
 	fn persist_graph(&self, network_graph: &NetworkGraph<L>) -> Result<(), io::Error>;
 
@@ -66,7 +133,17 @@ impl<'a, A: KVStorePersister, M: Deref, T: Deref, ES: Deref, NS: Deref, SP: Dere
 {
 	/// Persist the given ['ChannelManager'] to disk with the name "manager", returning an error if persistence failed.
 	fn persist_manager(&self, channel_manager: &ChannelManager<M, T, ES, NS, SP, F, R, L>) -> Result<(), io::Error> {
-		self.persist("manager", channel_manager)
+		persist_channel_manager(self, channel_manager)
+	}
+
+	/// Persist the changes to the given [`ChannelManager`] since it was last persisted in full to
+	/// disk with the name "manager_delta", returning an error if persistence failed.
+	///
+	/// The [`ChannelManager`] is persisted in full instead unless
+	/// [`KVStorePersister::persists_manager_deltas`] returns true, or if it has not been persisted
+	/// in full since startup.
+	fn persist_manager_delta(&self, channel_manager: &ChannelManager<M, T, ES, NS, SP, F, R, L>) -> Result<(), io::Error> {
+		persist_channel_manager_delta(self, channel_manager)
 	}
 
 	/// Persist the given [`NetworkGraph`] to disk with the name "network_graph", returning an error if persistence failed.
@@ -80,6 +157,79 @@ impl<'a, A: KVStorePersister, M: Deref, T: Deref, ES: Deref, NS: Deref, SP: Dere
 	}
 }
 
+pub(crate) fn persist_channel_manager<K: KVStorePersister + ?Sized, M: Deref, T: Deref, ES: Deref, NS: Deref, SP: Deref, F: Deref, R: Deref, L: Deref>(
+	store: &K, channel_manager: &ChannelManager<M, T, ES, NS, SP, F, R, L>
+) -> Result<(), io::Error>
+where
+	M::Target: chain::Watch<<SP::Target as SignerProvider>::Signer>,
+	T::Target: BroadcasterInterface,
+	ES::Target: EntropySource,
+	NS::Target: NodeSigner,
+	SP::Target: SignerProvider,
+	F::Target: FeeEstimator,
+	R::Target: Router,
+	L::Target: Logger,
+{
+	if !store.persists_manager_deltas() {
+		return store.persist("manager", channel_manager);
+	}
+	let snapshot = channel_manager.snapshot();
+	store.persist("manager", &snapshot)?;
+	channel_manager.snapshot_persisted(snapshot);
+	Ok(())
+}
+
+pub(crate) fn persist_channel_manager_delta<K: KVStorePersister + ?Sized, M: Deref, T: Deref, ES: Deref, NS: Deref, SP: Deref, F: Deref, R: Deref, L: Deref>(
+	store: &K, channel_manager: &ChannelManager<M, T, ES, NS, SP, F, R, L>
+) -> Result<(), io::Error>
+where
+	M::Target: chain::Watch<<SP::Target as SignerProvider>::Signer>,
+	T::Target: BroadcasterInterface,
+	ES::Target: EntropySource,
+	NS::Target: NodeSigner,
+	SP::Target: SignerProvider,
+	F::Target: FeeEstimator,
+	R::Target: Router,
+	L::Target: Logger,
+{
+	if !store.persists_manager_deltas() {
+		return persist_channel_manager(store, channel_manager);
+	}
+	match channel_manager.encode_delta() {
+		Some(delta) => store.persist("manager_delta", &WithoutLength(&delta)),
+		None => persist_channel_manager(store, channel_manager),
+	}
+}
+
+/// Reads back the serialized [`ChannelManager`] persisted to the given store via the
+/// auto-implemented [`Persister`], returning `None` if none has been persisted yet.
+///
+/// If the store [`persists_manager_deltas`], the last delta persisted to "manager_delta" is merged
+/// into the full snapshot persisted to "manager" via [`apply_channel_manager_delta`]. The returned
+/// bytes can be deserialized as usual via [`ChannelManagerReadArgs`].
+///
+/// [`persists_manager_deltas`]: KVStorePersister::persists_manager_deltas
+/// [`apply_channel_manager_delta`]: crate::ln::channelmanager::apply_channel_manager_delta
+/// [`ChannelManagerReadArgs`]: crate::ln::channelmanager::ChannelManagerReadArgs
+pub fn read_channel_manager<K: KVStoreReader + ?Sized, ES: Deref, SP: Deref>(
+	store: &K, entropy_source: ES, signer_provider: SP, default_config: &UserConfig
+) -> io::Result<Option<Vec<u8>>>
+where
+	ES::Target: EntropySource,
+	SP::Target: SignerProvider,
+{
+	let snapshot = match store.read("manager")? {
+		Some(snapshot) => snapshot,
+		None => return Ok(None),
+	};
+	match store.read("manager_delta")? {
+		Some(delta) => apply_channel_manager_delta(&snapshot, &delta, entropy_source, signer_provider, default_config)
+			.map(Some)
+			.map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Failed to apply ChannelManager delta")),
+		None => Ok(Some(snapshot)),
+	}
+}
+
 impl<ChannelSigner: WriteableEcdsaChannelSigner, K: KVStorePersister> Persist<ChannelSigner> for K {
 	// TODO: We really need a way for the persister to inform the user that its time to crash/shut
 	// down once these start returning failure.
@@ -90,6 +240,7 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner, K: KVStorePersister> Persist<Ch
 		let key = format!("monitors/{}_{}", funding_txo.txid.to_hex(), funding_txo.index);
 		match self.persist(&key, monitor) {
 			Ok(()) => chain::ChannelMonitorUpdateStatus::Completed,
+			Err(_) if self.is_fenced() => chain::ChannelMonitorUpdateStatus::InProgress,
 			Err(_) => chain::ChannelMonitorUpdateStatus::PermanentFailure,
 		}
 	}
@@ -98,7 +249,827 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner, K: KVStorePersister> Persist<Ch
 		let key = format!("monitors/{}_{}", funding_txo.txid.to_hex(), funding_txo.index);
 		match self.persist(&key, monitor) {
 			Ok(()) => chain::ChannelMonitorUpdateStatus::Completed,
+			Err(_) if self.is_fenced() => chain::ChannelMonitorUpdateStatus::InProgress,
 			Err(_) => chain::ChannelMonitorUpdateStatus::PermanentFailure,
 		}
 	}
 }
+
+const SERIALIZATION_VERSION: u8 = 1;
+const MIN_SERIALIZATION_VERSION: u8 = 1;
+
+/// A snapshot of all the state required to restart a node, as returned by [`export_node_state`]
+/// and [`import_node_state`].
+///
+/// Each object is kept in its serialized form, such that it can be deserialized with the usual
+/// [`ReadableArgs`] implementations once all its dependencies are available. When serialized,
+/// each object is stored along with a checksum which is verified by [`import_node_state`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodeStateBundle {
+	/// The serialized [`ChannelManager`].
+	pub channel_manager: Vec<u8>,
+	/// The serialized [`ChannelMonitor`]s, along with the funding outpoint of each.
+	pub channel_monitors: Vec<(OutPoint, Vec<u8>)>,
+	/// The serialized [`NetworkGraph`].
+	pub network_graph: Vec<u8>,
+	/// The serialized scorer.
+	pub scorer: Vec<u8>,
+}
+
+fn checksum(bytes: &[u8]) -> [u8; 32] {
+	Sha256::hash(bytes).into_inner()
+}
+
+fn read_checksummed<R: io::Read>(reader: &mut R) -> Result<Vec<u8>, DecodeError> {
+	let bytes: Vec<u8> = Readable::read(reader)?;
+	let expected_checksum: [u8; 32] = Readable::read(reader)?;
+	if checksum(&bytes) != expected_checksum {
+		return Err(DecodeError::InvalidValue);
+	}
+	Ok(bytes)
+}
+
+impl NodeStateBundle {
+	/// Deserializes the [`ChannelMonitor`]s in the bundle, which must happen before the
+	/// [`ChannelManager`] can be deserialized using [`ChannelManagerReadArgs`].
+	///
+	/// [`ChannelManagerReadArgs`]: crate::ln::channelmanager::ChannelManagerReadArgs
+	pub fn read_channel_monitors<ES: Deref, SP: Deref>(&self, entropy_source: ES, signer_provider: SP)
+	-> Result<Vec<(BlockHash, ChannelMonitor<<SP::Target as SignerProvider>::Signer>)>, DecodeError>
+	where
+		ES::Target: EntropySource + Sized,
+		SP::Target: SignerProvider + Sized,
+	{
+		let mut res = Vec::with_capacity(self.channel_monitors.len());
+		for (funding_txo, monitor_bytes) in self.channel_monitors.iter() {
+			let (block_hash, monitor): (BlockHash, ChannelMonitor<<SP::Target as SignerProvider>::Signer>) =
+				ReadableArgs::read(&mut Cursor::new(&monitor_bytes[..]), (&*entropy_source, &*signer_provider))?;
+			if monitor.get_funding_txo().0 != *funding_txo {
+				return Err(DecodeError::InvalidValue);
+			}
+			res.push((block_hash, monitor));
+		}
+		Ok(res)
+	}
+
+	/// Writes each object in the bundle to the given [`KVStorePersister`] using the same keys as
+	/// its [`Persister`] and [`Persist`] implementations, allowing the node to be restarted from
+	/// the store as usual.
+	///
+	/// The [`ChannelMonitor`]s are written before the [`ChannelManager`], such that a failure
+	/// part-way through never leaves the store with a [`ChannelManager`] newer than its
+	/// [`ChannelMonitor`]s.
+	pub fn persist<K: KVStorePersister>(&self, persister: &K) -> io::Result<()> {
+		for (funding_txo, monitor_bytes) in self.channel_monitors.iter() {
+			let key = format!("monitors/{}_{}", funding_txo.txid.to_hex(), funding_txo.index);
+			persister.persist(&key, &WithoutLength(monitor_bytes))?;
+		}
+		persister.persist("network_graph", &WithoutLength(&self.network_graph))?;
+		persister.persist("scorer", &WithoutLength(&self.scorer))?;
+		persister.persist("manager", &WithoutLength(&self.channel_manager))
+	}
+}
+
+impl Writeable for NodeStateBundle {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
+		write_ver_prefix!(writer, SERIALIZATION_VERSION, MIN_SERIALIZATION_VERSION);
+
+		self.channel_manager.write(writer)?;
+		checksum(&self.channel_manager).write(writer)?;
+		(self.channel_monitors.len() as u64).write(writer)?;
+		for (funding_txo, monitor_bytes) in self.channel_monitors.iter() {
+			funding_txo.write(writer)?;
+			monitor_bytes.write(writer)?;
+			checksum(monitor_bytes).write(writer)?;
+		}
+		self.network_graph.write(writer)?;
+		checksum(&self.network_graph).write(writer)?;
+		self.scorer.write(writer)?;
+		checksum(&self.scorer).write(writer)?;
+
+		write_tlv_fields!(writer, {});
+		Ok(())
+	}
+}
+
+impl Readable for NodeStateBundle {
+	fn read<R: io::Read>(reader: &mut R) -> Result<Self, DecodeError> {
+		let _ver = read_ver_prefix!(reader, SERIALIZATION_VERSION);
+
+		let channel_manager = read_checksummed(reader)?;
+		let monitor_count: u64 = Readable::read(reader)?;
+		let mut channel_monitors = Vec::with_capacity(cmp::min(monitor_count as usize, 128));
+		for _ in 0..monitor_count {
+			let funding_txo: OutPoint = Readable::read(reader)?;
+			channel_monitors.push((funding_txo, read_checksummed(reader)?));
+		}
+		let network_graph = read_checksummed(reader)?;
+		let scorer = read_checksummed(reader)?;
+
+		read_tlv_fields!(reader, {});
+		Ok(Self { channel_manager, channel_monitors, network_graph, scorer })
+	}
+}
+
+/// Snapshots the given [`ChannelManager`], all [`ChannelMonitor`]s in the given [`ChainMonitor`],
+/// the given [`NetworkGraph`], and the given scorer into a single [`NodeStateBundle`], which can
+/// be serialized and later restored with [`import_node_state`], e.g., to migrate a node to a new
+/// machine.
+///
+/// The [`ChannelManager`] is serialized before the [`ChannelMonitor`]s, such that the
+/// [`ChannelMonitor`]s in the bundle are never older than the [`ChannelManager`], as is required
+/// when deserializing it. Still, the node should be shut down (or at least not processing
+/// messages) while its state is exported, as otherwise the bundle may be stale by the time it is
+/// restored.
+///
+/// [`ChainMonitor`]: crate::chain::chainmonitor::ChainMonitor
+pub fn export_node_state<CM: Writeable, ChannelSigner: WriteableEcdsaChannelSigner, C: Deref, T: Deref, F: Deref, L: Deref, P: Deref, GL: Deref, S: Writeable>(
+	channel_manager: &CM, chain_monitor: &crate::chain::chainmonitor::ChainMonitor<ChannelSigner, C, T, F, L, P>,
+	network_graph: &NetworkGraph<GL>, scorer: &S
+) -> NodeStateBundle
+where
+	C::Target: chain::Filter,
+	T::Target: BroadcasterInterface,
+	F::Target: FeeEstimator,
+	L::Target: Logger,
+	P::Target: Persist<ChannelSigner>,
+	GL::Target: Logger,
+{
+	let channel_manager = channel_manager.encode();
+	let mut channel_monitors = Vec::new();
+	for funding_txo in chain_monitor.list_monitors() {
+		if let Ok(monitor) = chain_monitor.get_monitor(funding_txo) {
+			channel_monitors.push((funding_txo, monitor.encode()));
+		}
+	}
+	NodeStateBundle {
+		channel_manager,
+		channel_monitors,
+		network_graph: network_graph.encode(),
+		scorer: scorer.encode(),
+	}
+}
+
+/// Reads a [`NodeStateBundle`] previously serialized from the result of [`export_node_state`].
+///
+/// Fails with [`DecodeError::InvalidValue`] if the checksum of any object in the bundle does not
+/// match, or [`DecodeError::UnknownVersion`] if the bundle was written by a newer version of LDK.
+pub fn import_node_state(bytes: &[u8]) -> Result<NodeStateBundle, DecodeError> {
+	let mut reader = Cursor::new(bytes);
+	let bundle: NodeStateBundle = Readable::read(&mut reader)?;
+	if reader.position() != bytes.len() as u64 {
+		return Err(DecodeError::InvalidValue);
+	}
+	Ok(bundle)
+}
+
+/// The key at which [`MigrationRegistry::run`] stores the schema version of the objects in a
+/// [`KVStorePersister`].
+pub const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+/// The schema version of the objects persisted by this version of LDK.
+///
+/// Stores which have never been migrated are considered to be at version 0, which is persisted
+/// identically to version 1.
+pub const CURRENT_SCHEMA_VERSION: u32 = 1;
+
+/// The kinds of objects persisted via a [`KVStorePersister`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PersistedObject {
+	/// The [`ChannelManager`], stored under "manager".
+	ChannelManager,
+	/// Each [`ChannelMonitor`], stored under "monitors/{funding_txo_id}_{funding_txo_index}".
+	ChannelMonitor,
+	/// The [`NetworkGraph`], stored under "network_graph".
+	NetworkGraph,
+	/// The scorer, stored under "scorer".
+	Scorer,
+}
+
+/// A function converting a serialized object from the format of one schema version to the next.
+///
+/// Should a migration be interrupted, it will be run again on the objects which were already
+/// migrated, thus it must leave objects already in the newer format unchanged.
+pub type MigrationFn = fn(&[u8]) -> Result<Vec<u8>, DecodeError>;
+
+struct Migration {
+	object: PersistedObject,
+	from_version: u32,
+	migrate: MigrationFn,
+}
+
+/// The set of migrations required to upgrade the objects stored via a [`KVStorePersister`] from
+/// any previous schema version to the [`CURRENT_SCHEMA_VERSION`].
+///
+/// [`Self::run`] should be called on startup, before any object is read from the store.
+pub struct MigrationRegistry {
+	target_version: u32,
+	migrations: Vec<Migration>,
+}
+
+impl MigrationRegistry {
+	/// Returns a registry containing all migrations shipped with this version of LDK.
+	pub fn new() -> Self {
+		Self { target_version: CURRENT_SCHEMA_VERSION, migrations: Vec::new() }
+	}
+
+	/// Registers a migration converting the given kind of object from `from_version` to
+	/// `from_version + 1`.
+	pub(crate) fn register(&mut self, object: PersistedObject, from_version: u32, migrate: MigrationFn) {
+		self.target_version = cmp::max(self.target_version, from_version + 1);
+		self.migrations.push(Migration { object, from_version, migrate });
+	}
+
+	/// Migrates all objects in the given store to the current schema version, one version at a
+	/// time, returning the schema version the store was at before.
+	///
+	/// The schema version stored at [`SCHEMA_VERSION_KEY`] is only updated once all objects have
+	/// been migrated to it. Fails if the store was written by a newer version of LDK, or if any
+	/// object fails to migrate, in which case the store is left at the last schema version all
+	/// objects were successfully migrated to.
+	pub fn run<K: KVStorePersister + KVStoreReader>(&self, store: &K) -> io::Result<u32> {
+		let stored_version = match store.read(SCHEMA_VERSION_KEY)? {
+			Some(bytes) => <u32 as Readable>::read(&mut &bytes[..])
+				.map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid schema version"))?,
+			None => 0,
+		};
+		if stored_version > self.target_version {
+			return Err(io::Error::new(io::ErrorKind::InvalidData,
+				"Persisted objects were written by a newer version of LDK"));
+		}
+
+		for version in stored_version..self.target_version {
+			for migration in self.migrations.iter().filter(|migration| migration.from_version == version) {
+				let keys = match migration.object {
+					PersistedObject::ChannelManager => vec!["manager".to_owned()],
+					PersistedObject::ChannelMonitor => store.list("monitors/")?,
+					PersistedObject::NetworkGraph => vec!["network_graph".to_owned()],
+					PersistedObject::Scorer => vec!["scorer".to_owned()],
+				};
+				for key in keys {
+					if let Some(bytes) = store.read(&key)? {
+						let migrated = (migration.migrate)(&bytes)
+							.map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Failed to migrate persisted object"))?;
+						store.persist(&key, &WithoutLength(&migrated))?;
+					}
+				}
+			}
+			store.persist(SCHEMA_VERSION_KEY, &(version + 1))?;
+		}
+		Ok(stored_version)
+	}
+}
+
+/// The key at which a [`RecordingFilter`] stores its [`FilterRegistrations`].
+pub const FILTER_REGISTRATIONS_KEY: &str = "filter_registrations";
+
+/// The set of transactions and outputs registered via a [`chain::Filter`], as recorded by a
+/// [`RecordingFilter`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FilterRegistrations {
+	/// The arguments of each [`Filter::register_tx`] call, in the order they were made.
+	pub txs: Vec<(Txid, Script)>,
+	/// The arguments of each [`Filter::register_output`] call, in the order they were made.
+	pub outputs: Vec<WatchedOutput>,
+}
+
+impl_writeable_tlv_based!(FilterRegistrations, {
+	(0, txs, vec_type),
+	(2, outputs, vec_type),
+});
+
+impl FilterRegistrations {
+	/// Reads the registrations stored at [`FILTER_REGISTRATIONS_KEY`], returning an empty set if
+	/// none were stored.
+	pub fn read_from_store<K: KVStoreReader>(store: &K) -> io::Result<Self> {
+		match store.read(FILTER_REGISTRATIONS_KEY)? {
+			Some(bytes) => <Self as Readable>::read(&mut &bytes[..])
+				.map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid filter registrations")),
+			None => Ok(Self::default()),
+		}
+	}
+
+	/// Registers all recorded transactions and outputs with the given [`chain::Filter`], e.g., a
+	/// freshly started Electrum or Esplora sync client.
+	pub fn replay<F: Deref>(&self, filter: F) where F::Target: Filter {
+		for (txid, script_pubkey) in self.txs.iter() {
+			filter.register_tx(txid, script_pubkey);
+		}
+		for output in self.outputs.iter() {
+			filter.register_output(output.clone());
+		}
+	}
+}
+
+/// A [`chain::Filter`] which forwards all registrations to an inner [`chain::Filter`] while
+/// recording them to a [`KVStorePersister`] at [`FILTER_REGISTRATIONS_KEY`].
+///
+/// Registrations are generally only made once, e.g., when a [`ChannelMonitor`] is first loaded or
+/// learns of a new output to watch. If the filtering backend loses its state, e.g. because it was
+/// restarted, any transactions relevant to us would be silently missed. Using this in place of the
+/// backend's [`chain::Filter`] allows recovering by calling [`FilterRegistrations::replay`] with
+/// the restarted backend.
+///
+/// Persistence happens on each new registration, and a registration which fails to be persisted
+/// is retried on the next one. [`Self::persist`] may be used to retry explicitly.
+pub struct RecordingFilter<F: Deref, K: Deref> where F::Target: Filter, K::Target: KVStorePersister {
+	filter: F,
+	store: K,
+	registrations: Mutex<FilterRegistrations>,
+}
+
+impl<F: Deref, K: Deref> RecordingFilter<F, K> where F::Target: Filter, K::Target: KVStorePersister {
+	/// Constructs a new [`RecordingFilter`] which wraps `filter` and records to `store`.
+	///
+	/// `registrations` should be the [`FilterRegistrations`] previously recorded to `store`, as
+	/// read via [`FilterRegistrations::read_from_store`], or empty if none were. These are *not*
+	/// replayed into `filter`.
+	pub fn new(filter: F, store: K, registrations: FilterRegistrations) -> Self {
+		Self { filter, store, registrations: Mutex::new(registrations) }
+	}
+
+	/// Returns all registrations recorded so far.
+	pub fn registrations(&self) -> FilterRegistrations {
+		self.registrations.lock().unwrap().clone()
+	}
+
+	/// Persists all registrations recorded so far.
+	pub fn persist(&self) -> io::Result<()> {
+		let registrations = self.registrations.lock().unwrap();
+		self.store.persist(FILTER_REGISTRATIONS_KEY, &*registrations)
+	}
+}
+
+impl<F: Deref, K: Deref> Filter for RecordingFilter<F, K> where F::Target: Filter, K::Target: KVStorePersister {
+	fn register_tx(&self, txid: &Txid, script_pubkey: &Script) {
+		self.filter.register_tx(txid, script_pubkey);
+		let mut registrations = self.registrations.lock().unwrap();
+		if !registrations.txs.iter().any(|(registered_txid, registered_script)|
+			registered_txid == txid && registered_script == script_pubkey)
+		{
+			registrations.txs.push((*txid, script_pubkey.clone()));
+			let _ = self.store.persist(FILTER_REGISTRATIONS_KEY, &*registrations);
+		}
+	}
+
+	fn register_output(&self, output: WatchedOutput) {
+		self.filter.register_output(output.clone());
+		let mut registrations = self.registrations.lock().unwrap();
+		if !registrations.outputs.contains(&output) {
+			registrations.outputs.push(output);
+			let _ = self.store.persist(FILTER_REGISTRATIONS_KEY, &*registrations);
+		}
+	}
+}
+
+/// The key at which a [`FencedStore`] stores the fencing token of the instance currently allowed
+/// to write to the store.
+pub const FENCING_TOKEN_KEY: &str = "fencing_token";
+
+fn read_fencing_token<K: KVStoreReader + ?Sized>(store: &K) -> io::Result<Option<u64>> {
+	match store.read(FENCING_TOKEN_KEY)? {
+		Some(bytes) => <u64 as Readable>::read(&mut &bytes[..]).map(Some)
+			.map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid fencing token")),
+		None => Ok(None),
+	}
+}
+
+/// A [`KVStorePersister`] which detects when another instance of the node starts writing to the
+/// same underlying store and refuses all further writes once it does.
+///
+/// If two processes accidentally run against the same store, e.g., because a restarted cloud
+/// deployment came up before the old one was shut down, their writes would interleave and leave
+/// the store with channel state neither of them agrees with. To help detect this, each instance
+/// acquires the store on startup via [`Self::acquire`], which increments a monotonic fencing token
+/// stored at [`FENCING_TOKEN_KEY`]. The stored token is compared against the one acquired before
+/// the first write and then before every `check_interval`-th write, as well as whenever
+/// [`Self::check_fenced`] is called. Once a newer instance is found to have acquired the store,
+/// all further writes fail and the store is considered fenced for good. The stale instance should
+/// then shut down.
+///
+/// While fenced, [`ChannelMonitor`] updates persisted via the auto-implemented [`Persist`] remain
+/// [`ChannelMonitorUpdateStatus::InProgress`], such that the stale instance stops making progress
+/// in its channels, and all other writes, e.g., of the [`ChannelManager`], return an error. Note
+/// that the stale instance's in-memory [`ChannelMonitor`]s still react to on-chain events, and may
+/// thus still broadcast transactions until it is shut down.
+///
+/// This does not prevent all interleaved writes: up to `check_interval - 1` writes of a stale
+/// instance may land after the newer instance acquired the store, and even when checking before
+/// every write, the check and the write which follows it are not atomic. Thus, this is a safety
+/// net against misconfiguration rather than a replacement for ensuring only one instance runs at
+/// a time.
+///
+/// [`ChannelMonitorUpdateStatus::InProgress`]: chain::ChannelMonitorUpdateStatus::InProgress
+pub struct FencedStore<K: Deref> where K::Target: KVStorePersister + KVStoreReader {
+	store: K,
+	token: u64,
+	check_interval: usize,
+	writes_since_check: AtomicUsize,
+	fenced: AtomicBool,
+}
+
+impl<K: Deref> FencedStore<K> where K::Target: KVStorePersister + KVStoreReader {
+	/// Acquires the given store for this instance, fencing off any instance which acquired it
+	/// before.
+	///
+	/// The fencing token is read back to check whether this instance was fenced off before every
+	/// `check_interval`-th write, trading off the latency of detecting a newer instance against
+	/// the cost of an additional read. A `check_interval` of 0 is treated as 1, i.e., checking
+	/// before every write.
+	pub fn acquire(store: K, check_interval: usize) -> io::Result<Self> {
+		let token = read_fencing_token(&*store)?.unwrap_or(0) + 1;
+		store.persist(FENCING_TOKEN_KEY, &token)?;
+		Ok(Self {
+			store, token, check_interval: cmp::max(check_interval, 1),
+			writes_since_check: AtomicUsize::new(0), fenced: AtomicBool::new(false),
+		})
+	}
+
+	/// Returns the fencing token acquired by this instance.
+	pub fn token(&self) -> u64 {
+		self.token
+	}
+
+	/// Checks whether another instance has acquired the store since this instance did, returning
+	/// whether this instance is fenced.
+	///
+	/// This may be called regularly, e.g., on a timer, to detect a newer instance independently
+	/// of the `check_interval` given to [`Self::acquire`].
+	pub fn check_fenced(&self) -> io::Result<bool> {
+		if !self.fenced.load(Ordering::Acquire) && read_fencing_token(&*self.store)? != Some(self.token) {
+			self.fenced.store(true, Ordering::Release);
+		}
+		Ok(self.fenced.load(Ordering::Acquire))
+	}
+}
+
+impl<K: Deref> KVStorePersister for FencedStore<K> where K::Target: KVStorePersister + KVStoreReader {
+	fn persist<W: Writeable>(&self, key: &str, object: &W) -> io::Result<()> {
+		let writes_since_check = self.writes_since_check.fetch_add(1, Ordering::AcqRel);
+		let fenced = if writes_since_check % self.check_interval == 0 {
+			self.check_fenced()?
+		} else {
+			self.fenced.load(Ordering::Acquire)
+		};
+		if fenced {
+			return Err(io::Error::new(io::ErrorKind::Other, "Store was acquired by another instance"));
+		}
+		self.store.persist(key, object)
+	}
+
+	fn is_fenced(&self) -> bool {
+		self.fenced.load(Ordering::Acquire)
+	}
+
+	fn persists_manager_deltas(&self) -> bool {
+		self.store.persists_manager_deltas()
+	}
+}
+
+impl<K: Deref> KVStoreReader for FencedStore<K> where K::Target: KVStorePersister + KVStoreReader {
+	fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+		self.store.read(key)
+	}
+
+	fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+		self.store.list(prefix)
+	}
+}
+
+fn derive_encryption_key(seed: &[u8; 32], info: &[u8]) -> [u8; 32] {
+	let mut engine = HmacEngine::<Sha256>::new(seed);
+	engine.input(info);
+	Hmac::from_engine(engine).into_inner()
+}
+
+/// Encrypts and authenticates `plaintext` with ChaCha20Poly1305 under the given `key`, returning
+/// the ciphertext followed by its 16-byte tag.
+///
+/// The `nonce` must never be reused for different plaintexts under the same `key`. The `aad` is
+/// authenticated but not encrypted.
+fn encrypt_object(key: &[u8; 32], nonce: [u8; 12], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+	let mut res = vec![0; plaintext.len() + 16];
+	let (ciphertext, tag) = res.split_at_mut(plaintext.len());
+	ChaCha20Poly1305RFC::new(key, &nonce, aad).encrypt(plaintext, ciphertext, tag);
+	res
+}
+
+/// Decrypts an object encrypted via [`encrypt_object`] with the same `key`, `nonce` and `aad`,
+/// failing if it was tampered with.
+fn decrypt_object(key: &[u8; 32], nonce: [u8; 12], aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, DecodeError> {
+	if ciphertext.len() < 16 {
+		return Err(DecodeError::ShortRead);
+	}
+	let (ciphertext, tag) = ciphertext.split_at(ciphertext.len() - 16);
+	let mut res = vec![0; ciphertext.len()];
+	if !ChaCha20Poly1305RFC::new(key, &nonce, aad).decrypt(ciphertext, &mut res, tag) {
+		return Err(DecodeError::InvalidValue);
+	}
+	Ok(res)
+}
+
+/// A [`KVStorePersister`] which encrypts all objects and obfuscates all keys before handing them
+/// to an underlying store, such that the underlying store may be an untrusted remote backend.
+///
+/// Objects are encrypted with ChaCha20Poly1305 under a key derived from the `seed` given on
+/// construction, using a fresh random nonce for each write, and are authenticated together with
+/// the key they are stored at, so that the backend cannot swap objects around undetected. Keys are
+/// encrypted deterministically under another key derived from the `seed`, such that the same key
+/// always maps to the same obfuscated key but can still be recovered when listing keys. Thus, the
+/// same `seed` must be used to read the stored objects back, and should be derived from the node's
+/// seed rather than generated independently.
+///
+/// As the underlying store cannot filter obfuscated keys by prefix, [`KVStoreReader::list`] lists
+/// all of its keys, skipping any which were not written with the same `seed`.
+pub struct EncryptedStore<K: Deref, ES: Deref>
+where K::Target: KVStorePersister + KVStoreReader, ES::Target: EntropySource {
+	store: K,
+	encryption_key: [u8; 32],
+	obfuscation_key: [u8; 32],
+	entropy_source: ES,
+}
+
+impl<K: Deref, ES: Deref> EncryptedStore<K, ES>
+where K::Target: KVStorePersister + KVStoreReader, ES::Target: EntropySource {
+	/// Constructs a new store encrypting its objects with keys derived from `seed` before writing
+	/// them to `store`.
+	///
+	/// The `entropy_source` is used to generate a fresh nonce for each object written.
+	pub fn new(store: K, seed: [u8; 32], entropy_source: ES) -> Self {
+		Self {
+			store,
+			encryption_key: derive_encryption_key(&seed, b"LDK store encryption key"),
+			obfuscation_key: derive_encryption_key(&seed, b"LDK store key obfuscation key"),
+			entropy_source,
+		}
+	}
+
+	/// Deterministically encrypts `key`, using a nonce derived from the key itself, such that
+	/// nonces are only ever reused for the same key.
+	fn obfuscate_key(&self, key: &str) -> String {
+		let mut engine = HmacEngine::<Sha256>::new(&self.obfuscation_key);
+		engine.input(key.as_bytes());
+		let mut nonce = [0; 12];
+		nonce.copy_from_slice(&Hmac::from_engine(engine).into_inner()[..12]);
+		let mut obfuscated = nonce.to_vec();
+		obfuscated.extend_from_slice(&encrypt_object(&self.obfuscation_key, nonce, &[], key.as_bytes()));
+		obfuscated.to_hex()
+	}
+
+	fn deobfuscate_key(&self, obfuscated: &str) -> Option<String> {
+		let obfuscated = Vec::<u8>::from_hex(obfuscated).ok()?;
+		if obfuscated.len() < 12 {
+			return None;
+		}
+		let mut nonce = [0; 12];
+		nonce.copy_from_slice(&obfuscated[..12]);
+		let key = decrypt_object(&self.obfuscation_key, nonce, &[], &obfuscated[12..]).ok()?;
+		String::from_utf8(key).ok()
+	}
+}
+
+impl<K: Deref, ES: Deref> KVStorePersister for EncryptedStore<K, ES>
+where K::Target: KVStorePersister + KVStoreReader, ES::Target: EntropySource {
+	fn persist<W: Writeable>(&self, key: &str, object: &W) -> io::Result<()> {
+		let mut nonce = [0; 12];
+		nonce.copy_from_slice(&self.entropy_source.get_secure_random_bytes()[..12]);
+		let mut value = nonce.to_vec();
+		value.extend_from_slice(&encrypt_object(&self.encryption_key, nonce, key.as_bytes(), &object.encode()));
+		self.store.persist(&self.obfuscate_key(key), &WithoutLength(&value))
+	}
+
+	fn is_fenced(&self) -> bool {
+		self.store.is_fenced()
+	}
+
+	fn persists_manager_deltas(&self) -> bool {
+		self.store.persists_manager_deltas()
+	}
+}
+
+impl<K: Deref, ES: Deref> KVStoreReader for EncryptedStore<K, ES>
+where K::Target: KVStorePersister + KVStoreReader, ES::Target: EntropySource {
+	fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+		let value = match self.store.read(&self.obfuscate_key(key))? {
+			Some(value) => value,
+			None => return Ok(None),
+		};
+		if value.len() < 12 {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid encrypted object"));
+		}
+		let mut nonce = [0; 12];
+		nonce.copy_from_slice(&value[..12]);
+		decrypt_object(&self.encryption_key, nonce, key.as_bytes(), &value[12..]).map(Some)
+			.map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Failed to decrypt object"))
+	}
+
+	fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+		Ok(self.store.list("")?.iter()
+			.filter_map(|obfuscated| self.deobfuscate_key(obfuscated))
+			.filter(|key| key.starts_with(prefix))
+			.collect())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{FENCING_TOKEN_KEY, EncryptedStore, FencedStore, decrypt_object, encrypt_object, FilterRegistrations, KVStorePersister, KVStoreReader, MigrationRegistry, PersistedObject, RecordingFilter, SCHEMA_VERSION_KEY};
+	use crate::chain::{Filter, WatchedOutput};
+	use crate::chain::transaction::OutPoint;
+	use crate::io;
+	use crate::ln::msgs::DecodeError;
+	use crate::prelude::*;
+	use crate::sync::Mutex;
+	use crate::util::ser::Writeable;
+	use crate::util::test_utils;
+
+	use bitcoin::blockdata::script::Script;
+	use bitcoin::hash_types::Txid;
+	use bitcoin::hashes::Hash;
+	use bitcoin::network::constants::Network;
+
+	struct TestStore {
+		objects: Mutex<HashMap<String, Vec<u8>>>,
+	}
+
+	impl KVStorePersister for TestStore {
+		fn persist<W: Writeable>(&self, key: &str, object: &W) -> io::Result<()> {
+			self.objects.lock().unwrap().insert(key.to_owned(), object.encode());
+			Ok(())
+		}
+	}
+
+	impl KVStoreReader for TestStore {
+		fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+			Ok(self.objects.lock().unwrap().get(key).cloned())
+		}
+
+		fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+			Ok(self.objects.lock().unwrap().keys().filter(|key| key.starts_with(prefix)).cloned().collect())
+		}
+	}
+
+	fn append_version(bytes: &[u8]) -> Result<Vec<u8>, DecodeError> {
+		let mut res = bytes.to_vec();
+		res.push(2);
+		Ok(res)
+	}
+
+	fn fail_migration(_bytes: &[u8]) -> Result<Vec<u8>, DecodeError> {
+		Err(DecodeError::InvalidValue)
+	}
+
+	#[test]
+	fn runs_pending_migrations() {
+		let store = TestStore { objects: Mutex::new(HashMap::new()) };
+		store.objects.lock().unwrap().insert("manager".to_owned(), vec![0]);
+		store.objects.lock().unwrap().insert("monitors/a_0".to_owned(), vec![1]);
+		store.objects.lock().unwrap().insert("monitors/b_1".to_owned(), vec![1]);
+		store.objects.lock().unwrap().insert("scorer".to_owned(), vec![3]);
+
+		// A store which has never been migrated is brought up to the current version.
+		assert_eq!(MigrationRegistry::new().run(&store).unwrap(), 0);
+		assert_eq!(store.read(SCHEMA_VERSION_KEY).unwrap(), Some(1u32.encode()));
+		assert_eq!(MigrationRegistry::new().run(&store).unwrap(), 1);
+
+		let mut registry = MigrationRegistry::new();
+		registry.register(PersistedObject::ChannelMonitor, 1, append_version);
+		registry.register(PersistedObject::NetworkGraph, 1, append_version);
+		assert_eq!(registry.run(&store).unwrap(), 1);
+		assert_eq!(store.read("monitors/a_0").unwrap(), Some(vec![1, 2]));
+		assert_eq!(store.read("monitors/b_1").unwrap(), Some(vec![1, 2]));
+		assert_eq!(store.read("manager").unwrap(), Some(vec![0]));
+		assert_eq!(store.read("scorer").unwrap(), Some(vec![3]));
+		assert_eq!(store.read("network_graph").unwrap(), None);
+		assert_eq!(store.read(SCHEMA_VERSION_KEY).unwrap(), Some(2u32.encode()));
+
+		// Migrations which already ran are not run again.
+		assert_eq!(registry.run(&store).unwrap(), 2);
+		assert_eq!(store.read("monitors/a_0").unwrap(), Some(vec![1, 2]));
+
+		// An older version of LDK refuses to read the migrated store.
+		assert!(MigrationRegistry::new().run(&store).is_err());
+
+		// A failed migration leaves the schema version unchanged.
+		registry.register(PersistedObject::Scorer, 2, fail_migration);
+		assert!(registry.run(&store).is_err());
+		assert_eq!(store.read(SCHEMA_VERSION_KEY).unwrap(), Some(2u32.encode()));
+	}
+
+	#[test]
+	fn replays_recorded_filter_registrations() {
+		let store = TestStore { objects: Mutex::new(HashMap::new()) };
+		let chain_source = test_utils::TestChainSource::new(Network::Testnet);
+		let filter = RecordingFilter::new(&chain_source, &store,
+			FilterRegistrations::read_from_store(&store).unwrap());
+
+		let txid = Txid::from_slice(&[42; 32]).unwrap();
+		let script_pubkey = Script::new_op_return(&[42]);
+		let output = WatchedOutput {
+			block_hash: None,
+			outpoint: OutPoint { txid, index: 1 },
+			script_pubkey: script_pubkey.clone(),
+		};
+		filter.register_tx(&txid, &script_pubkey);
+		filter.register_output(output.clone());
+		// Repeated registrations are only recorded once.
+		filter.register_tx(&txid, &script_pubkey);
+		filter.register_output(output.clone());
+		assert_eq!(chain_source.watched_txn.lock().unwrap().len(), 1);
+		assert_eq!(chain_source.watched_outputs.lock().unwrap().len(), 1);
+
+		let registrations = FilterRegistrations::read_from_store(&store).unwrap();
+		assert_eq!(registrations, filter.registrations());
+		assert_eq!(registrations.txs, vec![(txid, script_pubkey.clone())]);
+		assert_eq!(registrations.outputs, vec![output.clone()]);
+
+		// A restarted backend learns of everything which was registered before.
+		let restarted_chain_source = test_utils::TestChainSource::new(Network::Testnet);
+		registrations.replay(&restarted_chain_source);
+		assert_eq!(*restarted_chain_source.watched_txn.lock().unwrap(), *chain_source.watched_txn.lock().unwrap());
+		assert_eq!(*restarted_chain_source.watched_outputs.lock().unwrap(), *chain_source.watched_outputs.lock().unwrap());
+	}
+
+	#[test]
+	fn fences_off_stale_instances() {
+		let store = TestStore { objects: Mutex::new(HashMap::new()) };
+		let first = FencedStore::acquire(&store, 1).unwrap();
+		assert_eq!(first.token(), 1);
+		first.persist("manager", &vec![1u8]).unwrap();
+		assert!(!first.is_fenced());
+
+		// Once another instance acquires the store, the first one may no longer write to it.
+		let second = FencedStore::acquire(&store, 1).unwrap();
+		assert_eq!(second.token(), 2);
+		assert_eq!(store.read(FENCING_TOKEN_KEY).unwrap(), Some(2u64.encode()));
+		assert!(first.persist("manager", &vec![2u8]).is_err());
+		assert!(first.is_fenced());
+		second.persist("manager", &vec![3u8]).unwrap();
+		assert!(!second.is_fenced());
+		assert_eq!(store.read("manager").unwrap(), Some(vec![3u8].encode()));
+
+		// Reads are unaffected, but the first instance stays fenced for good.
+		assert_eq!(first.read("manager").unwrap(), Some(vec![3u8].encode()));
+		store.persist(FENCING_TOKEN_KEY, &1u64).unwrap();
+		assert!(first.persist("manager", &vec![4u8]).is_err());
+
+		// With a larger check interval, writes only read the token back every so often, though a
+		// newer instance can still be detected explicitly.
+		let third = FencedStore::acquire(&store, 3).unwrap();
+		assert_eq!(third.token(), 2);
+		third.persist("manager", &vec![5u8]).unwrap();
+		let fourth = FencedStore::acquire(&store, 3).unwrap();
+		third.persist("manager", &vec![6u8]).unwrap();
+		assert!(!third.is_fenced());
+		assert!(third.check_fenced().unwrap());
+		assert!(third.persist("manager", &vec![7u8]).is_err());
+		fourth.persist("manager", &vec![8u8]).unwrap();
+	}
+
+	#[test]
+	fn encrypts_objects_for_remote_storage() {
+		let key = [42; 32];
+		let ciphertext = encrypt_object(&key, [1; 12], b"manager", b"channel state");
+		assert_eq!(ciphertext.len(), b"channel state".len() + 16);
+		assert_eq!(decrypt_object(&key, [1; 12], b"manager", &ciphertext), Ok(b"channel state".to_vec()));
+
+		// The object only decrypts with the same key, nonce and associated data.
+		assert_eq!(decrypt_object(&[43; 32], [1; 12], b"manager", &ciphertext), Err(DecodeError::InvalidValue));
+		assert_eq!(decrypt_object(&key, [2; 12], b"manager", &ciphertext), Err(DecodeError::InvalidValue));
+		assert_eq!(decrypt_object(&key, [1; 12], b"scorer", &ciphertext), Err(DecodeError::InvalidValue));
+		let mut tampered = ciphertext.clone();
+		tampered[0] ^= 1;
+		assert_eq!(decrypt_object(&key, [1; 12], b"manager", &tampered), Err(DecodeError::InvalidValue));
+		assert_eq!(decrypt_object(&key, [1; 12], b"manager", &ciphertext[..15]), Err(DecodeError::ShortRead));
+	}
+
+	#[test]
+	fn encrypted_store_hides_keys_and_objects() {
+		let store = TestStore { objects: Mutex::new(HashMap::new()) };
+		let keys = test_utils::TestKeysInterface::new(&[0; 32], Network::Testnet);
+		let encrypted_store = EncryptedStore::new(&store, [1; 32], &keys);
+
+		assert_eq!(encrypted_store.read("manager").unwrap(), None);
+		encrypted_store.persist("manager", &vec![1u8; 10]).unwrap();
+		encrypted_store.persist("manager", &vec![2u8; 10]).unwrap();
+		encrypted_store.persist("monitors/a_0", &vec![3u8; 10]).unwrap();
+		encrypted_store.persist("monitors/b_1", &vec![4u8; 10]).unwrap();
+		assert_eq!(encrypted_store.read("manager").unwrap(), Some(vec![2u8; 10].encode()));
+
+		// Neither keys nor values are visible to the underlying store.
+		assert_eq!(store.objects.lock().unwrap().len(), 3);
+		for (key, value) in store.objects.lock().unwrap().iter() {
+			assert!(!key.contains("manager") && !key.contains("monitors"));
+			assert!(!value.windows(10).any(|window| window.iter().all(|byte| *byte == window[0])));
+		}
+
+		let mut monitors = encrypted_store.list("monitors/").unwrap();
+		monitors.sort();
+		assert_eq!(monitors, vec!["monitors/a_0".to_owned(), "monitors/b_1".to_owned()]);
+
+		// A store with a different seed can read none of it.
+		let other_store = EncryptedStore::new(&store, [2; 32], &keys);
+		assert_eq!(other_store.read("manager").unwrap(), None);
+		assert!(other_store.list("").unwrap().is_empty());
+	}
+}
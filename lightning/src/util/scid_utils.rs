@@ -93,7 +93,8 @@ pub(crate) mod fake_scid {
 	pub(crate) enum Namespace {
 		Phantom,
 		OutboundAlias,
-		Intercept
+		Intercept,
+		ManagedIntercept,
 	}
 
 	impl Namespace {
@@ -191,6 +192,10 @@ pub(crate) mod fake_scid {
 			let intercept_namespace = Namespace::Intercept;
 			assert!((intercept_namespace as u8) < MAX_NAMESPACES);
 			assert!((intercept_namespace as u8) <= NAMESPACE_ID_BITMASK);
+
+			let managed_intercept_namespace = Namespace::ManagedIntercept;
+			assert!((managed_intercept_namespace as u8) < MAX_NAMESPACES);
+			assert!((managed_intercept_namespace as u8) <= NAMESPACE_ID_BITMASK);
 		}
 
 		#[test]
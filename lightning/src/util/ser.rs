@@ -153,6 +153,61 @@ impl<R: Read> Read for FixedLengthReader<R> {
 	}
 }
 
+/// TLV records which were not understood when reading an object written by a newer version of
+/// LDK, retained such that they can be re-written when the object is next persisted.
+///
+/// Only records with a type above every type known to the reader are retained, as they can be
+/// re-written after all known records without breaking the ordering of the TLV stream. Unknown
+/// even records are only retained when explicitly allowed, see
+/// [`UserConfig::preserve_unknown_even_tlvs`].
+///
+/// [`UserConfig::preserve_unknown_even_tlvs`]: crate::util::config::UserConfig::preserve_unknown_even_tlvs
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct UnknownTlvRecords {
+	records: Vec<(u64, Vec<u8>)>,
+}
+
+impl UnknownTlvRecords {
+	pub(crate) fn new() -> Self {
+		Self { records: Vec::new() }
+	}
+
+	/// Returns whether any retained record has an even type, i.e., the newer version of LDK
+	/// which wrote it required it to be understood.
+	pub(crate) fn has_even_records(&self) -> bool {
+		self.records.iter().any(|(typ, _)| typ % 2 == 0)
+	}
+
+	/// Retains the record with the given type, returning `Ok(false)` if it cannot be retained, in
+	/// which case an unknown odd record is ignored and an unknown even record fails to read with
+	/// [`DecodeError::UnknownRequiredFeature`].
+	pub(crate) fn read_record<R: Read>(
+		&mut self, typ: u64, max_known_type: u64, allow_even: bool, reader: &mut FixedLengthReader<R>
+	) -> Result<bool, DecodeError> {
+		if typ <= max_known_type || (typ % 2 == 0 && !allow_even) {
+			return Ok(false);
+		}
+		let mut value = Vec::new();
+		copy(reader, &mut value)?;
+		if reader.bytes_remain() {
+			return Err(DecodeError::ShortRead);
+		}
+		self.records.push((typ, value));
+		Ok(true)
+	}
+}
+
+impl Writeable for UnknownTlvRecords {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
+		for (typ, value) in self.records.iter() {
+			BigSize(*typ).write(writer)?;
+			BigSize(value.len() as u64).write(writer)?;
+			writer.write_all(value)?;
+		}
+		Ok(())
+	}
+}
+
 impl<R: Read> LengthRead for FixedLengthReader<R> {
 	#[inline]
 	fn total_bytes(&self) -> u64 {
@@ -1285,7 +1340,7 @@ impl Readable for String {
 /// This serialization is used by [`BOLT 7`] hostnames.
 ///
 /// [`BOLT 7`]: https://github.com/lightning/bolts/blob/master/07-routing-gossip.md
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub struct Hostname(String);
 impl Hostname {
 	/// Returns the length of the hostname.
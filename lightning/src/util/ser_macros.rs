@@ -206,6 +206,21 @@ macro_rules! _encode_varint_length_prefixed_tlv {
 		};
 		BigSize(len as u64).write($stream)?;
 		$crate::encode_tlv_stream!($stream, { $(($type, $field, $fieldty)),* });
+	} };
+	($stream: expr, {$(($type: expr, $field: expr, $fieldty: tt)),*}, $unknown_tlvs: expr) => { {
+		use $crate::util::ser::BigSize;
+		let len = {
+			#[allow(unused_mut)]
+			let mut len = $crate::util::ser::LengthCalculatingWriter(0);
+			$(
+				$crate::_get_varint_length_prefixed_tlv_length!(len, $type, $field, $fieldty);
+			)*
+			len.0 + $unknown_tlvs.serialized_length()
+		};
+		BigSize(len as u64).write($stream)?;
+		$crate::encode_tlv_stream!($stream, { $(($type, $field, $fieldty)),* });
+		// Retained unknown records all have a type above every known type, so go last.
+		$unknown_tlvs.write($stream)?;
 	} }
 }
 
@@ -529,6 +544,10 @@ macro_rules! _decode_tlv_stream_range {
 ///
 /// Currently `$fieldty` may only be `option`, i.e., `$tlvfield` is optional field.
 ///
+/// If a `$custom_tlvs` field of type `Vec<(u64, Vec<u8>)>` is given after the TLV fields, the
+/// records it contains are written after all other TLVs, and any unknown odd TLVs of a type of at
+/// least [`MIN_CUSTOM_TLV_TYPE`] are read into it.
+///
 /// For example,
 /// ```
 /// # use lightning::impl_writeable_msg;
@@ -551,8 +570,42 @@ macro_rules! _decode_tlv_stream_range {
 /// [`Readable`]: crate::util::ser::Readable
 /// [`Writeable`]: crate::util::ser::Writeable
 /// [`CustomMessageReader`]: crate::ln::wire::CustomMessageReader
+/// [`MIN_CUSTOM_TLV_TYPE`]: crate::ln::msgs::MIN_CUSTOM_TLV_TYPE
 #[macro_export]
 macro_rules! impl_writeable_msg {
+	($st:ident, {$($field:ident),* $(,)*}, {$(($type: expr, $tlvfield: ident, $fieldty: tt)),* $(,)*}, $custom_tlvs: ident) => {
+		impl $crate::util::ser::Writeable for $st {
+			fn write<W: $crate::util::ser::Writer>(&self, w: &mut W) -> Result<(), $crate::io::Error> {
+				$( self.$field.write(w)?; )*
+				$crate::encode_tlv_stream!(w, {$(($type, self.$tlvfield.as_ref(), $fieldty)),*});
+				for (typ, value) in self.$custom_tlvs.iter() {
+					$crate::util::ser::Writeable::write(&$crate::util::ser::BigSize(*typ), w)?;
+					$crate::util::ser::Writeable::write(&$crate::util::ser::BigSize(value.len() as u64), w)?;
+					w.write_all(value)?;
+				}
+				Ok(())
+			}
+		}
+		impl $crate::util::ser::Readable for $st {
+			fn read<R: $crate::io::Read>(r: &mut R) -> Result<Self, $crate::ln::msgs::DecodeError> {
+				$(let $field = $crate::util::ser::Readable::read(r)?;)*
+				$($crate::_init_tlv_field_var!($tlvfield, $fieldty);)*
+				let mut $custom_tlvs = Vec::new();
+				let rewind = |_, _| { unreachable!() };
+				$crate::_decode_tlv_stream_range!(r, .., rewind, {$(($type, $tlvfield, $fieldty)),*}, |typ: u64, reader| -> Result<bool, $crate::ln::msgs::DecodeError> {
+					if typ < $crate::ln::msgs::MIN_CUSTOM_TLV_TYPE || typ % 2 == 0 { return Ok(false); }
+					let value: $crate::util::ser::WithoutLength<Vec<u8>> = $crate::util::ser::Readable::read(reader)?;
+					$custom_tlvs.push((typ, value.0));
+					Ok(true)
+				});
+				Ok(Self {
+					$($field,)*
+					$($tlvfield,)*
+					$custom_tlvs
+				})
+			}
+		}
+	};
 	($st:ident, {$($field:ident),* $(,)*}, {$(($type: expr, $tlvfield: ident, $fieldty: tt)),* $(,)*}) => {
 		impl $crate::util::ser::Writeable for $st {
 			fn write<W: $crate::util::ser::Writer>(&self, w: &mut W) -> Result<(), $crate::io::Error> {
@@ -637,7 +690,10 @@ macro_rules! write_ver_prefix {
 macro_rules! write_tlv_fields {
 	($stream: expr, {$(($type: expr, $field: expr, $fieldty: tt)),* $(,)*}) => {
 		$crate::_encode_varint_length_prefixed_tlv!($stream, {$(($type, $field, $fieldty)),*})
-	}
+	};
+	($stream: expr, {$(($type: expr, $field: expr, $fieldty: tt)),* $(,)*}, $unknown_tlvs: expr) => {
+		$crate::_encode_varint_length_prefixed_tlv!($stream, {$(($type, $field, $fieldty)),*}, $unknown_tlvs)
+	};
 }
 
 /// Reads a prefix added by [`write_ver_prefix`], above. Takes the current version of the
@@ -656,6 +712,10 @@ macro_rules! read_ver_prefix {
 
 /// Reads a suffix added by [`write_tlv_fields`].
 ///
+/// May also be given a set of unknown TLV records and whether unknown even records are allowed,
+/// in which case unknown records with a type above every known type are retained in the set
+/// rather than being ignored (or failing to read, for even records).
+///
 /// [`write_tlv_fields`]: crate::write_tlv_fields
 #[macro_export]
 macro_rules! read_tlv_fields {
@@ -664,7 +724,16 @@ macro_rules! read_tlv_fields {
 		let mut rd = $crate::util::ser::FixedLengthReader::new($stream, tlv_len.0);
 		$crate::decode_tlv_stream!(&mut rd, {$(($type, $field, $fieldty)),*});
 		rd.eat_remaining().map_err(|_| $crate::ln::msgs::DecodeError::ShortRead)?;
-	} }
+	} };
+	($stream: expr, {$(($type: expr, $field: ident, $fieldty: tt)),* $(,)*}, $unknown_tlvs: expr, $allow_unknown_even: expr) => { {
+		let tlv_len: $crate::util::ser::BigSize = $crate::util::ser::Readable::read($stream)?;
+		let mut rd = $crate::util::ser::FixedLengthReader::new($stream, tlv_len.0);
+		let max_known_type = 0u64 $(.max($type as u64))*;
+		let rewind = |_, _| { unreachable!() };
+		$crate::_decode_tlv_stream_range!(&mut rd, .., rewind, {$(($type, $field, $fieldty)),*},
+			|typ, reader| $unknown_tlvs.read_record(typ, max_known_type, $allow_unknown_even, reader));
+		rd.eat_remaining().map_err(|_| $crate::ln::msgs::DecodeError::ShortRead)?;
+	} };
 }
 
 /// Initializes the struct fields.
@@ -1050,7 +1119,7 @@ mod tests {
 	use crate::io::{self, Cursor};
 	use crate::prelude::*;
 	use crate::ln::msgs::DecodeError;
-	use crate::util::ser::{Writeable, HighZeroBytesDroppedBigSize, VecWriter};
+	use crate::util::ser::{Writeable, HighZeroBytesDroppedBigSize, UnknownTlvRecords, VecWriter};
 	use bitcoin::secp256k1::PublicKey;
 
 	// The BOLT TLV test cases don't include any tests which use our "required-value" logic since
@@ -1307,4 +1376,36 @@ mod tests {
 	fn simple_test_tlv_write() {
 		do_simple_test_tlv_write().unwrap();
 	}
+
+	fn unknown_tlv_rewriter(s: &[u8], allow_unknown_even: bool) -> Result<(Vec<u8>, bool), DecodeError> {
+		let mut s = Cursor::new(s);
+		let mut a: u64 = 0;
+		let mut b: Option<u32> = None;
+		let mut unknown_tlvs = UnknownTlvRecords::new();
+		read_tlv_fields!(&mut s, {(2, a, required), (5, b, option)}, unknown_tlvs, allow_unknown_even);
+
+		let mut stream = VecWriter(Vec::new());
+		write_tlv_fields!(&mut stream, {(2, a, required), (5, b, option)}, unknown_tlvs);
+		Ok((stream.0, unknown_tlvs.has_even_records()))
+	}
+
+	#[test]
+	fn unknown_tlvs_preserved() {
+		// Unknown odd records above the known types are always re-written.
+		let odd = ::hex::decode(concat!("13", "02080000000000000001", "0504deadbeef", "0701ab")).unwrap();
+		assert_eq!(unknown_tlv_rewriter(&odd, false).unwrap(), (odd.clone(), false));
+
+		// Unknown even records above the known types are only re-written if allowed.
+		let even = ::hex::decode(concat!("16", "02080000000000000001", "0504deadbeef", "0701ab", "0801cd")).unwrap();
+		assert_eq!(unknown_tlv_rewriter(&even, false), Err(DecodeError::UnknownRequiredFeature));
+		assert_eq!(unknown_tlv_rewriter(&even, true).unwrap(), (even.clone(), true));
+
+		// Unknown records between the known types cannot be re-written in order, thus odd ones are
+		// dropped and even ones fail to read even if allowed.
+		let odd_gap = ::hex::decode(concat!("13", "02080000000000000001", "0301ab", "0504deadbeef")).unwrap();
+		let known = ::hex::decode(concat!("10", "02080000000000000001", "0504deadbeef")).unwrap();
+		assert_eq!(unknown_tlv_rewriter(&odd_gap, true).unwrap(), (known, false));
+		let even_gap = ::hex::decode(concat!("13", "02080000000000000001", "0401cd", "0504deadbeef")).unwrap();
+		assert_eq!(unknown_tlv_rewriter(&even_gap, true), Err(DecodeError::UnknownRequiredFeature));
+	}
 }
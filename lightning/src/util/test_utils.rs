@@ -40,6 +40,8 @@ use bitcoin::blockdata::opcodes;
 use bitcoin::blockdata::block::Block;
 use bitcoin::network::constants::Network;
 use bitcoin::hash_types::{BlockHash, Txid};
+use bitcoin::hashes::Hash;
+use bitcoin::hashes::sha256d::Hash as Sha256dHash;
 
 use bitcoin::secp256k1::{SecretKey, PublicKey, Secp256k1, ecdsa::Signature, Scalar};
 use bitcoin::secp256k1::ecdh::SharedSecret;
@@ -55,7 +57,7 @@ use crate::sync::{Mutex, Arc};
 use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use core::mem;
 use bitcoin::bech32::u5;
-use crate::sign::{InMemorySigner, Recipient, EntropySource, NodeSigner, SignerProvider};
+use crate::sign::{InMemorySigner, Recipient, EntropySource, NodeSigner, NodeSignerStatus, SignerProvider, WatchOnlySigner, WatchOnlySignerSource};
 
 #[cfg(feature = "std")]
 use std::time::{SystemTime, UNIX_EPOCH};
@@ -240,7 +242,7 @@ impl<'a> chain::Watch<EnforcingSigner> for TestChainMonitor<'a> {
 		if let Some(exp) = self.expect_channel_force_closed.lock().unwrap().take() {
 			assert_eq!(funding_txo.to_channel_id(), exp.0);
 			assert_eq!(update.updates.len(), 1);
-			if let channelmonitor::ChannelMonitorUpdateStep::ChannelForceClosed { should_broadcast } = update.updates[0] {
+			if let channelmonitor::ChannelMonitorUpdateStep::ChannelForceClosed { should_broadcast, .. } = update.updates[0] {
 				assert_eq!(should_broadcast, exp.1);
 			} else { panic!(); }
 		}
@@ -429,6 +431,12 @@ impl msgs::ChannelMessageHandler for TestChannelMessageHandler {
 	fn handle_closing_signed(&self, _their_node_id: &PublicKey, msg: &msgs::ClosingSigned) {
 		self.received_msg(wire::Message::ClosingSigned(msg.clone()));
 	}
+	fn handle_closing_complete(&self, _their_node_id: &PublicKey, msg: &msgs::ClosingComplete) {
+		self.received_msg(wire::Message::ClosingComplete(msg.clone()));
+	}
+	fn handle_closing_sig(&self, _their_node_id: &PublicKey, msg: &msgs::ClosingSig) {
+		self.received_msg(wire::Message::ClosingSig(msg.clone()));
+	}
 	fn handle_update_add_htlc(&self, _their_node_id: &PublicKey, msg: &msgs::UpdateAddHTLC) {
 		self.received_msg(wire::Message::UpdateAddHTLC(msg.clone()));
 	}
@@ -525,6 +533,14 @@ impl msgs::ChannelMessageHandler for TestChannelMessageHandler {
 	fn handle_tx_abort(&self, _their_node_id: &PublicKey, msg: &msgs::TxAbort) {
 		self.received_msg(wire::Message::TxAbort(msg.clone()));
 	}
+
+	fn handle_peer_storage(&self, _their_node_id: &PublicKey, msg: &msgs::PeerStorage) {
+		self.received_msg(wire::Message::PeerStorage(msg.clone()));
+	}
+
+	fn handle_your_peer_storage(&self, _their_node_id: &PublicKey, msg: &msgs::YourPeerStorage) {
+		self.received_msg(wire::Message::YourPeerStorage(msg.clone()));
+	}
 }
 
 impl events::MessageSendEventsProvider for TestChannelMessageHandler {
@@ -612,6 +628,11 @@ impl msgs::RoutingMessageHandler for TestRoutingMessageHandler {
 		self.chan_anns_recvd.fetch_add(1, Ordering::AcqRel);
 		Err(msgs::LightningError { err: "".to_owned(), action: msgs::ErrorAction::IgnoreError })
 	}
+	#[cfg(taproot)]
+	fn handle_channel_announcement_2(&self, _msg: &msgs::ChannelAnnouncement2) -> Result<bool, msgs::LightningError> {
+		self.chan_anns_recvd.fetch_add(1, Ordering::AcqRel);
+		Err(msgs::LightningError { err: "".to_owned(), action: msgs::ErrorAction::IgnoreError })
+	}
 	fn handle_channel_update(&self, _msg: &msgs::ChannelUpdate) -> Result<bool, msgs::LightningError> {
 		self.chan_upds_recvd.fetch_add(1, Ordering::AcqRel);
 		Err(msgs::LightningError { err: "".to_owned(), action: msgs::ErrorAction::IgnoreError })
@@ -759,11 +780,13 @@ impl Logger for TestLogger {
 
 pub struct TestNodeSigner {
 	node_secret: SecretKey,
+	/// If set, [`NodeSigner::ecdh_async`] returns [`NodeSignerStatus::InProgress`].
+	pub ecdh_in_progress: Mutex<bool>,
 }
 
 impl TestNodeSigner {
 	pub fn new(node_secret: SecretKey) -> Self {
-		Self { node_secret }
+		Self { node_secret, ecdh_in_progress: Mutex::new(false) }
 	}
 }
 
@@ -791,11 +814,23 @@ impl NodeSigner for TestNodeSigner {
 		Ok(SharedSecret::new(other_key, &node_secret))
 	}
 
+	fn ecdh_async(&self, recipient: Recipient, other_key: &PublicKey, tweak: Option<&bitcoin::secp256k1::Scalar>) -> NodeSignerStatus<SharedSecret> {
+		if *self.ecdh_in_progress.lock().unwrap() {
+			return NodeSignerStatus::InProgress;
+		}
+		NodeSignerStatus::Complete(self.ecdh(recipient, other_key, tweak))
+	}
+
 	fn sign_invoice(&self, _: &[u8], _: &[bitcoin::bech32::u5], _: Recipient) -> Result<bitcoin::secp256k1::ecdsa::RecoverableSignature, ()> {
 		unreachable!()
 	}
 
-	fn sign_gossip_message(&self, _msg: msgs::UnsignedGossipMessage) -> Result<Signature, ()> {
+	fn sign_gossip_message(&self, msg: msgs::UnsignedGossipMessage) -> Result<Signature, ()> {
+		let msg_hash = hash_to_message!(&Sha256dHash::hash(&msg.encode()[..])[..]);
+		Ok(Secp256k1::signing_only().sign_ecdsa(&msg_hash, &self.node_secret))
+	}
+
+	fn sign_message(&self, _msg: &[u8]) -> Result<String, ()> {
 		unreachable!()
 	}
 }
@@ -806,6 +841,10 @@ pub struct TestKeysInterface {
 	pub disable_revocation_policy_check: bool,
 	enforcement_states: Mutex<HashMap<[u8;32], Arc<Mutex<EnforcementState>>>>,
 	expectations: Mutex<Option<VecDeque<OnGetShutdownScriptpubkey>>>,
+	/// If set, [`NodeSigner::ecdh_async`] returns [`NodeSignerStatus::InProgress`].
+	pub ecdh_in_progress: Mutex<bool>,
+	/// If set, [`NodeSigner::sign_invoice_async`] returns [`NodeSignerStatus::InProgress`].
+	pub sign_invoice_in_progress: Mutex<bool>,
 }
 
 impl EntropySource for TestKeysInterface {
@@ -827,6 +866,13 @@ impl NodeSigner for TestKeysInterface {
 		self.backing.ecdh(recipient, other_key, tweak)
 	}
 
+	fn ecdh_async(&self, recipient: Recipient, other_key: &PublicKey, tweak: Option<&Scalar>) -> NodeSignerStatus<SharedSecret> {
+		if *self.ecdh_in_progress.lock().unwrap() {
+			return NodeSignerStatus::InProgress;
+		}
+		NodeSignerStatus::Complete(self.ecdh(recipient, other_key, tweak))
+	}
+
 	fn get_inbound_payment_key_material(&self) -> sign::KeyMaterial {
 		self.backing.get_inbound_payment_key_material()
 	}
@@ -835,9 +881,20 @@ impl NodeSigner for TestKeysInterface {
 		self.backing.sign_invoice(hrp_bytes, invoice_data, recipient)
 	}
 
+	fn sign_invoice_async(&self, hrp_bytes: &[u8], invoice_data: &[u5], recipient: Recipient) -> NodeSignerStatus<RecoverableSignature> {
+		if *self.sign_invoice_in_progress.lock().unwrap() {
+			return NodeSignerStatus::InProgress;
+		}
+		NodeSignerStatus::Complete(self.sign_invoice(hrp_bytes, invoice_data, recipient))
+	}
+
 	fn sign_gossip_message(&self, msg: msgs::UnsignedGossipMessage) -> Result<Signature, ()> {
 		self.backing.sign_gossip_message(msg)
 	}
+
+	fn sign_message(&self, msg: &[u8]) -> Result<String, ()> {
+		self.backing.sign_message(msg)
+	}
 }
 
 impl SignerProvider for TestKeysInterface {
@@ -879,6 +936,12 @@ impl SignerProvider for TestKeysInterface {
 	}
 }
 
+impl WatchOnlySignerSource for TestKeysInterface {
+	fn get_watch_only_signer(&self, channel_value_satoshis: u64, channel_keys_id: [u8; 32]) -> WatchOnlySigner {
+		self.backing.get_watch_only_signer(channel_value_satoshis, channel_keys_id)
+	}
+}
+
 impl TestKeysInterface {
 	pub fn new(seed: &[u8; 32], network: Network) -> Self {
 		let now = Duration::from_secs(genesis_block(network).header.time as u64);
@@ -888,6 +951,8 @@ impl TestKeysInterface {
 			disable_revocation_policy_check: false,
 			enforcement_states: Mutex::new(HashMap::new()),
 			expectations: Mutex::new(None),
+			ecdh_in_progress: Mutex::new(false),
+			sign_invoice_in_progress: Mutex::new(false),
 		}
 	}
 